@@ -0,0 +1,166 @@
+//! # FADT (Fixed ACPI Description Table)
+//!
+//! Only the fields the kernel uses are parsed: where the CMOS RTC keeps the
+//! century and whether there is a CMOS RTC at all, where the DSDT is, and
+//! the registers needed to power off ([`PM1a_CNT`](Fadt::pm1a_cnt) and
+//! friends) or reset ([`reset`](Fadt::reset)) the machine.
+
+use crate::{u16_at, u32_at, u64_at};
+
+/// Signature of the FADT, for [`find`](crate::sdt::find).
+pub const SIGNATURE: [u8; 4] = *b"FACP";
+
+/// Offset of the 32-bit physical address of the DSDT.
+const DSDT: usize = 40;
+
+/// Offset of the port taking [`ACPI_ENABLE`].
+const SMI_CMD: usize = 48;
+
+/// Offset of the value that switches the firmware to ACPI mode.
+const ACPI_ENABLE: usize = 52;
+
+/// Offset of the port of the `PM1a` control register.
+const PM1A_CNT_BLK: usize = 64;
+
+/// Offset of the port of the `PM1b` control register.
+const PM1B_CNT_BLK: usize = 68;
+
+/// Offset of the CMOS index of the century register.
+const CENTURY: usize = 108;
+
+/// Offset of the IA-PC boot architecture flags (ACPI 2.0+).
+const IAPC_BOOT_ARCH: usize = 109;
+
+/// Offset of the feature flags.
+const FLAGS: usize = 112;
+
+/// Offset of the reset register (ACPI 2.0+).
+const RESET_REG: usize = 116;
+
+/// Offset of the value to write to the reset register.
+const RESET_VALUE: usize = 128;
+
+/// Offset of the 64-bit physical address of the DSDT (ACPI 2.0+).
+const X_DSDT: usize = 140;
+
+/// Offset of the extended `PM1a` control register (ACPI 2.0+).
+const X_PM1A_CNT_BLK: usize = 172;
+
+/// Offset of the extended `PM1b` control register (ACPI 2.0+).
+const X_PM1B_CNT_BLK: usize = 184;
+
+/// Boot architecture flag: there is no CMOS RTC (ACPI 5.0+).
+const CMOS_RTC_NOT_PRESENT: u16 = 1 << 5;
+
+/// Feature flag: the reset register is supported.
+const RESET_REG_SUP: u32 = 1 << 10;
+
+/// The FADT fields the kernel uses.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Fadt {
+    /// CMOS RAM index of the RTC's century register, if it has one.
+    pub century_register: Option<u8>,
+    /// Whether the CMOS RTC exists; only ACPI 5.0 firmware can say it does not.
+    pub cmos_rtc_present: bool,
+    /// Physical address of the DSDT.
+    pub dsdt: Option<u64>,
+    /// Port taking [`acpi_enable`](Self::acpi_enable), if the firmware starts
+    /// out in legacy mode.
+    pub smi_cmd: Option<u16>,
+    /// Value that switches the firmware to ACPI mode.
+    pub acpi_enable: u8,
+    /// Port of the `PM1a` control register.
+    pub pm1a_cnt: Option<u16>,
+    /// Port of the `PM1b` control register, if there is a second one.
+    pub pm1b_cnt: Option<u16>,
+    /// The reset register and the value to write to it, if supported.
+    pub reset: Option<(GenericAddress, u8)>,
+}
+
+/// A register in some address space (ACPI "Generic Address Structure").
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct GenericAddress {
+    /// One of the `SPACE_*` constants.
+    pub space_id: u8,
+    pub bit_width: u8,
+    pub bit_offset: u8,
+    pub access_size: u8,
+    pub address: u64,
+}
+
+impl GenericAddress {
+    /// Address space: physical memory.
+    pub const SPACE_MEMORY: u8 = 0;
+    /// Address space: I/O ports.
+    pub const SPACE_IO: u8 = 1;
+    /// Address space: PCI configuration space.
+    pub const SPACE_PCI_CONFIG: u8 = 2;
+
+    /// Parse the 12-byte structure at `offset`.
+    fn parse(table: &[u8], offset: usize) -> Option<Self> {
+        let bytes = table.get(offset..offset.checked_add(12)?)?;
+        Some(Self {
+            space_id: bytes[0],
+            bit_width: bytes[1],
+            bit_offset: bytes[2],
+            access_size: bytes[3],
+            address: u64_at(bytes, 4)?,
+        })
+    }
+
+    /// The I/O port, if the register is a non-zero one in I/O space.
+    #[must_use]
+    pub fn port(&self) -> Option<u16> {
+        if self.space_id != Self::SPACE_IO {
+            return None;
+        }
+        u16::try_from(self.address).ok().filter(|&port| port != 0)
+    }
+}
+
+impl Fadt {
+    /// Parse a table [`find`](crate::sdt::find) returned.
+    ///
+    /// Fields a shorter, older revision of the table lacks take their
+    /// defaults: no century register, a CMOS RTC, and no reset register.
+    /// The 64-bit DSDT address and PM1 control registers take precedence
+    /// over the 32-bit ones where both are set.
+    #[must_use]
+    pub fn parse(table: &[u8]) -> Option<Self> {
+        if table.get(..4)? != SIGNATURE {
+            return None;
+        }
+        let century_register = table.get(CENTURY).copied().filter(|&index| index != 0);
+        let boot_arch = u16_at(table, IAPC_BOOT_ARCH).unwrap_or(0);
+        let flags = u32_at(table, FLAGS).unwrap_or(0);
+
+        let dsdt = u64_at(table, X_DSDT)
+            .filter(|&addr| addr != 0)
+            .or_else(|| u32_at(table, DSDT).map(u64::from))
+            .filter(|&addr| addr != 0);
+        let port = |offset| {
+            u32_at(table, offset)
+                .and_then(|port| u16::try_from(port).ok())
+                .filter(|&port| port != 0)
+        };
+        let pm1_cnt = |extended, legacy| {
+            GenericAddress::parse(table, extended)
+                .and_then(|gas| gas.port())
+                .or_else(|| port(legacy))
+        };
+        let reset = GenericAddress::parse(table, RESET_REG)
+            .zip(table.get(RESET_VALUE).copied())
+            .filter(|(gas, _)| flags & RESET_REG_SUP != 0 && gas.address != 0);
+
+        Some(Self {
+            century_register,
+            cmos_rtc_present: boot_arch & CMOS_RTC_NOT_PRESENT == 0,
+            dsdt,
+            smi_cmd: port(SMI_CMD),
+            acpi_enable: table.get(ACPI_ENABLE).copied().unwrap_or(0),
+            pm1a_cnt: pm1_cnt(X_PM1A_CNT_BLK, PM1A_CNT_BLK),
+            pm1b_cnt: pm1_cnt(X_PM1B_CNT_BLK, PM1B_CNT_BLK),
+            reset,
+        })
+    }
+}
@@ -40,6 +40,16 @@
 //! * **Validation**: Checksum verification and signature validation
 //! * **Version Detection**: Automatic handling of ACPI 1.0 vs 2.0+ variants
 //!
+//! ### Table Discovery ([`sdt`])
+//! * **Header Validation**: Length and checksum of every mapped table
+//! * **Lookup**: [`sdt::find`] walks the XSDT (or RSDT) for a signature
+//!
+//! ### Table Parsers
+//! * [`fadt`]: the CMOS RTC century register and presence flag, the DSDT,
+//!   and the power-off and reset registers
+//! * [`madt`]: I/O APICs and interrupt source overrides
+//! * [`sleep`]: the S5 (soft off) sleep type, found in the DSDT's AML
+//!
 //! ## ACPI Version Support
 //!
 //! ### ACPI 1.0 Support
@@ -116,8 +126,7 @@
 //! ## Future Extensions
 //!
 //! This foundational crate enables future ACPI functionality:
-//! * **Table Enumeration**: Parse RSDT/XSDT to discover available tables
-//! * **Specific Parsers**: FADT, MADT, MCFG, and other standard tables
+//! * **More Parsers**: MCFG, HPET, and other standard tables
 //! * **AML Interpreter**: ACPI Machine Language execution engine
 //! * **Power Management**: ACPI power state and thermal management
 //! * **Device Enumeration**: PCI Express configuration and device discovery
@@ -133,7 +142,11 @@
 #![cfg_attr(not(any(test, doctest)), no_std)]
 #![allow(unsafe_code)]
 
+pub mod fadt;
+pub mod madt;
 pub mod rsdp;
+pub mod sdt;
+pub mod sleep;
 
 /// Map a physical region and return a *read-only* byte slice for its contents.
 /// You provide the implementation (identity map, kmap, etc.).
@@ -146,3 +159,21 @@ pub trait PhysMapRo {
 fn sum(bytes: &[u8]) -> u8 {
     bytes.iter().fold(0, |a, &b| a.wrapping_add(b))
 }
+
+/// The little-endian `u16` at `offset`, if `bytes` is long enough.
+fn u16_at(bytes: &[u8], offset: usize) -> Option<u16> {
+    let bytes = bytes.get(offset..offset.checked_add(2)?)?;
+    Some(u16::from_le_bytes(bytes.try_into().ok()?))
+}
+
+/// The little-endian `u32` at `offset`, if `bytes` is long enough.
+fn u32_at(bytes: &[u8], offset: usize) -> Option<u32> {
+    let bytes = bytes.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_le_bytes(bytes.try_into().ok()?))
+}
+
+/// The little-endian `u64` at `offset`, if `bytes` is long enough.
+fn u64_at(bytes: &[u8], offset: usize) -> Option<u64> {
+    let bytes = bytes.get(offset..offset.checked_add(8)?)?;
+    Some(u64::from_le_bytes(bytes.try_into().ok()?))
+}
@@ -0,0 +1,275 @@
+//! # MADT (Multiple APIC Description Table)
+//!
+//! Lists the interrupt controllers of the machine. The kernel reads the I/O
+//! APICs and the interrupt source overrides, which tell where an ISA IRQ
+//! arrives if not at the global system interrupt (GSI) of the same number,
+//! and with which polarity and trigger mode. It also reads the local APICs
+//! and the local APIC NMI entries, which tell which LINT pin of a
+//! processor's local APIC is wired to NMI.
+//!
+//! Processors appear in local APIC entries, or in local x2APIC entries if
+//! their APIC ID or processor UID does not fit a byte; both parse to a
+//! [`LocalApic`]. The same goes for [`LocalApicNmi`].
+
+use crate::sdt::HEADER_LEN;
+use crate::{u16_at, u32_at};
+
+/// Signature of the MADT, for [`find`](crate::sdt::find).
+pub const SIGNATURE: [u8; 4] = *b"APIC";
+
+/// Offset of the local APIC address.
+const LOCAL_APIC_ADDRESS: usize = HEADER_LEN;
+
+/// Offset of the first entry, after the local APIC address and flags.
+const ENTRIES: usize = HEADER_LEN + 8;
+
+/// Entry type of an I/O APIC.
+const TYPE_IO_APIC: u8 = 1;
+
+/// Entry type of an interrupt source override.
+const TYPE_INTERRUPT_OVERRIDE: u8 = 2;
+
+/// Entry type of a processor's local APIC.
+const TYPE_LOCAL_APIC: u8 = 0;
+
+/// Entry type of a local APIC NMI.
+const TYPE_LOCAL_APIC_NMI: u8 = 4;
+
+/// Entry type of a processor's local x2APIC.
+const TYPE_LOCAL_X2APIC: u8 = 9;
+
+/// Entry type of a local x2APIC NMI.
+const TYPE_LOCAL_X2APIC_NMI: u8 = 10;
+
+/// Processor UID of a local APIC NMI entry that applies to all processors.
+const ALL_PROCESSORS: u8 = 0xFF;
+
+/// Processor UID of a local x2APIC NMI entry that applies to all processors.
+const ALL_X2_PROCESSORS: u32 = 0xFFFF_FFFF;
+
+/// Local APIC flag: the processor is usable.
+const LOCAL_APIC_ENABLED: u32 = 1 << 0;
+
+/// A parsed MADT, borrowing the table.
+#[derive(Debug, Copy, Clone)]
+pub struct Madt<'a> {
+    table: &'a [u8],
+}
+
+/// An entry of the MADT.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Entry {
+    LocalApic(LocalApic),
+    IoApic(IoApic),
+    InterruptOverride(InterruptOverride),
+    LocalApicNmi(LocalApicNmi),
+    /// An entry of a type not parsed, such as a local APIC address override.
+    Other {
+        kind: u8,
+    },
+}
+
+/// A processor and its local APIC.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct LocalApic {
+    /// ACPI processor UID, as [`LocalApicNmi`] entries name it.
+    pub processor_uid: u32,
+    pub apic_id: u32,
+    /// Whether the processor is usable.
+    pub enabled: bool,
+}
+
+/// Input `lint` (0 or 1) of the local APIC of one or all processors is
+/// wired to NMI.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct LocalApicNmi {
+    /// ACPI processor UID, or `None` for all processors.
+    pub processor_uid: Option<u32>,
+    pub lint: u8,
+    pub polarity: Polarity,
+    pub trigger: Trigger,
+}
+
+/// An I/O APIC.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct IoApic {
+    pub id: u8,
+    /// Physical address of the register window.
+    pub address: u32,
+    /// GSI of the first input.
+    pub gsi_base: u32,
+}
+
+/// ISA IRQ `source` arrives at `gsi` instead of the GSI of the same number.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct InterruptOverride {
+    pub bus: u8,
+    pub source: u8,
+    pub gsi: u32,
+    pub polarity: Polarity,
+    pub trigger: Trigger,
+}
+
+/// Polarity of an interrupt input.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Polarity {
+    /// As the bus defines it; active high for ISA.
+    ConformsToBus,
+    ActiveHigh,
+    ActiveLow,
+}
+
+/// Trigger mode of an interrupt input.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Trigger {
+    /// As the bus defines it; edge for ISA.
+    ConformsToBus,
+    Edge,
+    Level,
+}
+
+impl<'a> Madt<'a> {
+    /// Parse a table [`find`](crate::sdt::find) returned.
+    #[must_use]
+    pub fn parse(table: &'a [u8]) -> Option<Self> {
+        (table.get(..4)? == SIGNATURE && table.len() >= ENTRIES).then_some(Self { table })
+    }
+
+    /// Physical address of the local APIC registers in xAPIC mode.
+    #[must_use]
+    pub fn local_apic_address(&self) -> u32 {
+        u32_at(self.table, LOCAL_APIC_ADDRESS).unwrap_or_default()
+    }
+
+    /// All entries, up to the first malformed one.
+    #[must_use]
+    pub fn entries(&self) -> Entries<'a> {
+        Entries {
+            rest: &self.table[ENTRIES..],
+        }
+    }
+
+    /// All I/O APICs.
+    pub fn io_apics(&self) -> impl Iterator<Item = IoApic> + 'a {
+        self.entries().filter_map(|entry| match entry {
+            Entry::IoApic(io_apic) => Some(io_apic),
+            _ => None,
+        })
+    }
+
+    /// All processors.
+    pub fn local_apics(&self) -> impl Iterator<Item = LocalApic> + 'a {
+        self.entries().filter_map(|entry| match entry {
+            Entry::LocalApic(local_apic) => Some(local_apic),
+            _ => None,
+        })
+    }
+
+    /// The local APIC NMI entries that apply to the processor whose local
+    /// APIC has `apic_id`; those for all processors if it is not listed.
+    pub fn local_apic_nmis(&self, apic_id: u32) -> impl Iterator<Item = LocalApicNmi> + 'a {
+        let uid = self
+            .local_apics()
+            .find(|local_apic| local_apic.apic_id == apic_id)
+            .map(|local_apic| local_apic.processor_uid);
+        self.entries().filter_map(move |entry| match entry {
+            Entry::LocalApicNmi(nmi) if nmi.processor_uid.is_none() || nmi.processor_uid == uid => {
+                Some(nmi)
+            }
+            _ => None,
+        })
+    }
+
+    /// The override of ISA IRQ `irq`, if there is one.
+    #[must_use]
+    pub fn interrupt_override(&self, irq: u8) -> Option<InterruptOverride> {
+        self.entries().find_map(|entry| match entry {
+            Entry::InterruptOverride(o) if o.bus == 0 && o.source == irq => Some(o),
+            _ => None,
+        })
+    }
+}
+
+/// Iterator over the [`Entry`]s of a [`Madt`].
+#[derive(Debug, Clone)]
+pub struct Entries<'a> {
+    rest: &'a [u8],
+}
+
+impl Iterator for Entries<'_> {
+    type Item = Entry;
+
+    fn next(&mut self) -> Option<Entry> {
+        let (&kind, len) = (self.rest.first()?, *self.rest.get(1)? as usize);
+        let Some(entry) = self.rest.get(..len).filter(|_| len >= 2) else {
+            self.rest = &[];
+            return None;
+        };
+        self.rest = &self.rest[len..];
+
+        let parsed = match kind {
+            TYPE_LOCAL_APIC if len >= 8 => Entry::LocalApic(LocalApic {
+                processor_uid: u32::from(entry[2]),
+                apic_id: u32::from(entry[3]),
+                enabled: u32_at(entry, 4)? & LOCAL_APIC_ENABLED != 0,
+            }),
+            TYPE_LOCAL_X2APIC if len >= 16 => Entry::LocalApic(LocalApic {
+                processor_uid: u32_at(entry, 12)?,
+                apic_id: u32_at(entry, 4)?,
+                enabled: u32_at(entry, 8)? & LOCAL_APIC_ENABLED != 0,
+            }),
+            TYPE_LOCAL_APIC_NMI if len >= 6 => {
+                let (polarity, trigger) = mode(u16_at(entry, 3)?);
+                Entry::LocalApicNmi(LocalApicNmi {
+                    processor_uid: Some(entry[2])
+                        .filter(|&uid| uid != ALL_PROCESSORS)
+                        .map(u32::from),
+                    lint: entry[5],
+                    polarity,
+                    trigger,
+                })
+            }
+            TYPE_LOCAL_X2APIC_NMI if len >= 12 => {
+                let (polarity, trigger) = mode(u16_at(entry, 2)?);
+                Entry::LocalApicNmi(LocalApicNmi {
+                    processor_uid: Some(u32_at(entry, 4)?).filter(|&uid| uid != ALL_X2_PROCESSORS),
+                    lint: entry[8],
+                    polarity,
+                    trigger,
+                })
+            }
+            TYPE_IO_APIC if len >= 12 => Entry::IoApic(IoApic {
+                id: entry[2],
+                address: u32_at(entry, 4)?,
+                gsi_base: u32_at(entry, 8)?,
+            }),
+            TYPE_INTERRUPT_OVERRIDE if len >= 10 => {
+                let (polarity, trigger) = mode(u16_at(entry, 8)?);
+                Entry::InterruptOverride(InterruptOverride {
+                    bus: entry[2],
+                    source: entry[3],
+                    gsi: u32_at(entry, 4)?,
+                    polarity,
+                    trigger,
+                })
+            }
+            _ => Entry::Other { kind },
+        };
+        Some(parsed)
+    }
+}
+
+/// Polarity and trigger mode from the MPS INTI flags of an entry.
+const fn mode(flags: u16) -> (Polarity, Trigger) {
+    let polarity = match flags & 0b11 {
+        0b01 => Polarity::ActiveHigh,
+        0b11 => Polarity::ActiveLow,
+        _ => Polarity::ConformsToBus,
+    };
+    let trigger = match (flags >> 2) & 0b11 {
+        0b01 => Trigger::Edge,
+        0b11 => Trigger::Level,
+        _ => Trigger::ConformsToBus,
+    };
+    (polarity, trigger)
+}
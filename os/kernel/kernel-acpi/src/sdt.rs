@@ -0,0 +1,68 @@
+//! # System Description Tables
+//!
+//! Every ACPI table but the RSDP starts with the same 36-byte header: a
+//! four-byte signature, the table length and a checksum over the whole
+//! table. The XSDT (or, before ACPI 2.0, the RSDT) lists the physical
+//! addresses of all other tables; [`find`] walks it for a signature.
+
+use crate::rsdp::AcpiRoots;
+use crate::{PhysMapRo, sum, u32_at, u64_at};
+
+/// Length of the common table header.
+pub const HEADER_LEN: usize = 36;
+
+/// Offset of the table length in the header.
+const LENGTH: usize = 4;
+
+/// Map the whole table at `addr`.
+///
+/// Returns `None` if the table does not fit its own header or its checksum
+/// does not add up.
+///
+/// # Safety
+/// `addr` must be the physical address of an ACPI table, or memory `map`
+/// can read without side effects.
+#[must_use]
+pub unsafe fn map_table<'a>(map: &impl PhysMapRo, addr: u64) -> Option<&'a [u8]> {
+    if addr == 0 {
+        return None;
+    }
+    let header = unsafe { map.map_ro(addr, HEADER_LEN) };
+    let len = usize::try_from(u32_at(header, LENGTH)?).ok()?;
+    if len < HEADER_LEN {
+        return None;
+    }
+    let table = unsafe { map.map_ro(addr, len) };
+    (table.len() == len && sum(table) == 0).then_some(table)
+}
+
+/// The first table with `signature` listed in the XSDT, or the RSDT if
+/// there is no XSDT.
+///
+/// # Safety
+/// `roots` must have been parsed through `map`.
+#[must_use]
+pub unsafe fn find<'a>(
+    map: &impl PhysMapRo,
+    roots: &AcpiRoots,
+    signature: [u8; 4],
+) -> Option<&'a [u8]> {
+    let (root, entry_len) = match (roots.xsdt_addr, roots.rsdt_addr) {
+        (Some(xsdt), _) => (xsdt, 8),
+        (None, Some(rsdt)) => (rsdt, 4),
+        (None, None) => return None,
+    };
+    let root: &[u8] = unsafe { map_table(map, root) }?;
+
+    root[HEADER_LEN..]
+        .chunks_exact(entry_len)
+        .filter_map(|entry| {
+            if entry_len == 8 {
+                u64_at(entry, 0)
+            } else {
+                u32_at(entry, 0).map(u64::from)
+            }
+        })
+        .filter_map(|addr| unsafe { map_table(map, addr) })
+        .find(|table| table[..4] == signature)
+}
@@ -0,0 +1,113 @@
+//! # Sleep States
+//!
+//! Entering sleep state S5 ("soft off") powers the machine down: the OS
+//! writes the state's `SLP_TYPa`/`SLP_TYPb` values together with `SLP_EN`
+//! to the `PM1a` and `PM1b` control registers named in the
+//! [FADT](crate::fadt). The values are not in a fixed table but in the
+//! DSDT's AML, as the package `_S5_`:
+//!
+//! ```text
+//! Name (_S5_, Package (0x04) { 0x05, 0x05, Zero, Zero })
+//!
+//! 08 5F 53 35 5F 12 08 04 0A 05 0A 05 00 00
+//! ```
+//!
+//! Instead of interpreting the AML, [`find_s5`] looks for the encoded
+//! `NameOp "_S5_" PackageOp` and decodes the package's first two integers,
+//! as most small kernels do. That misses a `_S5_` computed by a method, which
+//! real firmware does not do.
+
+/// `NameOp`: defines a named object.
+const NAME_OP: u8 = 0x08;
+/// `RootChar`: a name path starting at the namespace root.
+const ROOT_CHAR: u8 = b'\\';
+/// `PackageOp`: a package follows.
+const PACKAGE_OP: u8 = 0x12;
+/// `ZeroOp`, `OneOp` and `OnesOp`: the constants 0, 1 and all ones.
+const ZERO_OP: u8 = 0x00;
+const ONE_OP: u8 = 0x01;
+const ONES_OP: u8 = 0xFF;
+/// Prefixes of integers of one, two and four bytes.
+const BYTE_PREFIX: u8 = 0x0A;
+const WORD_PREFIX: u8 = 0x0B;
+const DWORD_PREFIX: u8 = 0x0C;
+
+/// `SLP_TYP` values of a sleep state, for the `PM1a` and `PM1b` control
+/// registers.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct SleepType {
+    pub a: u8,
+    pub b: u8,
+}
+
+impl SleepType {
+    /// Bit position of `SLP_TYP` in the PM1 control registers.
+    pub const SLP_TYP_SHIFT: u16 = 10;
+    /// `SLP_EN`: enter the sleep state in `SLP_TYP`.
+    pub const SLP_EN: u16 = 1 << 13;
+
+    /// The `PM1a` control register bits entering this state.
+    #[must_use]
+    pub fn pm1a_bits(self) -> u16 {
+        ((u16::from(self.a) & 0x7) << Self::SLP_TYP_SHIFT) | Self::SLP_EN
+    }
+
+    /// The `PM1b` control register bits entering this state.
+    #[must_use]
+    pub fn pm1b_bits(self) -> u16 {
+        ((u16::from(self.b) & 0x7) << Self::SLP_TYP_SHIFT) | Self::SLP_EN
+    }
+}
+
+/// The `SLP_TYP` values of S5 in `aml`, the DSDT (header included).
+#[must_use]
+pub fn find_s5(aml: &[u8]) -> Option<SleepType> {
+    aml.windows(4)
+        .enumerate()
+        .filter(|(_, window)| *window == b"_S5_")
+        .find_map(|(at, _)| {
+            let before = aml.get(..at)?;
+            if !matches!(before, [.., NAME_OP, ROOT_CHAR] | [.., NAME_OP]) {
+                return None;
+            }
+            parse_package(aml.get(at + 4..)?)
+        })
+}
+
+/// The first two integers of the package at the start of `bytes`.
+#[allow(clippy::cast_possible_truncation)]
+fn parse_package(bytes: &[u8]) -> Option<SleepType> {
+    let [PACKAGE_OP, lead, ..] = *bytes else {
+        return None;
+    };
+    // The top two bits of the lead byte count the `PkgLength` bytes after it;
+    // then comes the element count.
+    let elements = bytes.get(2 + usize::from(lead >> 6) + 1..)?;
+    let (a, len) = integer(elements)?;
+    let (b, _) = integer(elements.get(len..)?)?;
+    Some(SleepType {
+        a: a as u8,
+        b: b as u8,
+    })
+}
+
+/// The integer at the start of `bytes` and its encoded length.
+fn integer(bytes: &[u8]) -> Option<(u32, usize)> {
+    let le = |len: usize| {
+        let value = bytes
+            .get(1..=len)?
+            .iter()
+            .rev()
+            .fold(0u32, |acc, &b| (acc << 8) | u32::from(b));
+        Some((value, 1 + len))
+    };
+    match *bytes.first()? {
+        ZERO_OP => Some((0, 1)),
+        ONE_OP => Some((1, 1)),
+        ONES_OP => Some((u32::MAX, 1)),
+        BYTE_PREFIX => le(1),
+        WORD_PREFIX => le(2),
+        DWORD_PREFIX => le(4),
+        _ => None,
+    }
+}
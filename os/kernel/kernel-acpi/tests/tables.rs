@@ -0,0 +1,297 @@
+use kernel_acpi::fadt::GenericAddress;
+use kernel_acpi::madt::{
+    Entry, InterruptOverride, IoApic, LocalApic, LocalApicNmi, Madt, Polarity, Trigger,
+};
+use kernel_acpi::rsdp::AcpiRoots;
+use kernel_acpi::sleep::{self, SleepType};
+use kernel_acpi::{PhysMapRo, fadt, madt, sdt};
+
+const RSDP: usize = 0x40;
+const XSDT: usize = 0x100;
+const FADT: usize = 0x200;
+const MADT: usize = 0x400;
+
+/// Physical memory starting at address 0.
+struct Memory(&'static [u8]);
+
+impl PhysMapRo for Memory {
+    unsafe fn map_ro<'a>(&self, paddr: u64, len: usize) -> &'a [u8] {
+        let start = usize::try_from(paddr).unwrap();
+        self.0.get(start..start + len).unwrap_or(&[])
+    }
+}
+
+/// Set the checksum byte at `offset` so that `bytes` sums to zero.
+fn fix_checksum(bytes: &mut [u8], offset: usize) {
+    bytes[offset] = 0;
+    let sum = bytes.iter().fold(0u8, |a, &b| a.wrapping_add(b));
+    bytes[offset] = 0u8.wrapping_sub(sum);
+}
+
+/// A table with `signature` and `body` after the header.
+fn table(signature: [u8; 4], body: &[u8]) -> Vec<u8> {
+    let mut table = vec![0; sdt::HEADER_LEN];
+    table[..4].copy_from_slice(&signature);
+    table.extend_from_slice(body);
+    let len = u32::try_from(table.len()).unwrap();
+    table[4..8].copy_from_slice(&len.to_le_bytes());
+    fix_checksum(&mut table, 9);
+    table
+}
+
+fn fadt_body(century: u8, boot_arch: u16) -> Vec<u8> {
+    let mut body = vec![0; 116 - sdt::HEADER_LEN];
+    body[108 - sdt::HEADER_LEN] = century;
+    body[109 - sdt::HEADER_LEN..111 - sdt::HEADER_LEN].copy_from_slice(&boot_arch.to_le_bytes());
+    body
+}
+
+fn madt_body() -> Vec<u8> {
+    let mut body = Vec::new();
+    body.extend_from_slice(&0xFEE0_0000u32.to_le_bytes());
+    body.extend_from_slice(&1u32.to_le_bytes());
+    // Local APIC 0.
+    body.extend_from_slice(&[0, 8, 0, 0, 1, 0, 0, 0]);
+    // I/O APIC 1 at 0xFEC00000, GSI 0.
+    body.extend_from_slice(&[1, 12, 1, 0]);
+    body.extend_from_slice(&0xFEC0_0000u32.to_le_bytes());
+    body.extend_from_slice(&0u32.to_le_bytes());
+    // ISA IRQ 0 at GSI 2, as the bus says.
+    body.extend_from_slice(&[2, 10, 0, 0]);
+    body.extend_from_slice(&2u32.to_le_bytes());
+    body.extend_from_slice(&0u16.to_le_bytes());
+    // ISA IRQ 9 at GSI 9, active low, level-triggered.
+    body.extend_from_slice(&[2, 10, 0, 9]);
+    body.extend_from_slice(&9u32.to_le_bytes());
+    body.extend_from_slice(&0b1111u16.to_le_bytes());
+    // LINT1 of all processors is NMI, as the bus says.
+    body.extend_from_slice(&[4, 6, 0xFF, 0, 0, 1]);
+    // Local x2APIC 0x100 of processor 0x200, disabled.
+    body.extend_from_slice(&[9, 16, 0, 0]);
+    body.extend_from_slice(&0x100u32.to_le_bytes());
+    body.extend_from_slice(&0u32.to_le_bytes());
+    body.extend_from_slice(&0x200u32.to_le_bytes());
+    // LINT0 of processor 0x200 is NMI, active high and edge-triggered.
+    body.extend_from_slice(&[10, 12]);
+    body.extend_from_slice(&0b0101u16.to_le_bytes());
+    body.extend_from_slice(&0x200u32.to_le_bytes());
+    body.extend_from_slice(&[0, 0, 0, 0]);
+    body
+}
+
+/// An ACPI 2.0 RSDP and an XSDT listing `tables`.
+fn memory(tables: &[(usize, Vec<u8>)]) -> (Memory, AcpiRoots) {
+    let mut memory = vec![0u8; 0x1000];
+
+    let mut rsdp = [0u8; 36];
+    rsdp[..8].copy_from_slice(b"RSD PTR ");
+    rsdp[15] = 2;
+    rsdp[20..24].copy_from_slice(&36u32.to_le_bytes());
+    rsdp[24..32].copy_from_slice(&(XSDT as u64).to_le_bytes());
+    fix_checksum(&mut rsdp[..20], 8);
+    fix_checksum(&mut rsdp, 32);
+    memory[RSDP..RSDP + 36].copy_from_slice(&rsdp);
+
+    let entries: Vec<u8> = tables
+        .iter()
+        .flat_map(|(addr, _)| (*addr as u64).to_le_bytes())
+        .collect();
+    let xsdt = table(*b"XSDT", &entries);
+    memory[XSDT..XSDT + xsdt.len()].copy_from_slice(&xsdt);
+    for (addr, table) in tables {
+        memory[*addr..*addr + table.len()].copy_from_slice(table);
+    }
+
+    let memory = Memory(Vec::leak(memory));
+    let roots = unsafe { AcpiRoots::parse(&memory, RSDP as u64) }.expect("RSDP");
+    (memory, roots)
+}
+
+#[test]
+fn tables_are_found_by_signature() {
+    let (memory, roots) = memory(&[
+        (FADT, table(fadt::SIGNATURE, &fadt_body(0x32, 0))),
+        (MADT, table(madt::SIGNATURE, &madt_body())),
+    ]);
+
+    let madt = unsafe { sdt::find(&memory, &roots, madt::SIGNATURE) }.expect("MADT");
+    assert_eq!(madt.as_ptr(), memory.0[MADT..].as_ptr());
+    assert!(unsafe { sdt::find(&memory, &roots, *b"HPET") }.is_none());
+}
+
+#[test]
+fn tables_with_a_bad_checksum_are_skipped() {
+    let mut fadt = table(fadt::SIGNATURE, &fadt_body(0x32, 0));
+    fadt[20] ^= 1;
+    let (memory, roots) = memory(&[(FADT, fadt)]);
+
+    assert!(unsafe { sdt::find(&memory, &roots, fadt::SIGNATURE) }.is_none());
+}
+
+#[test]
+fn fadt_names_the_century_register() {
+    let fadt = fadt::Fadt::parse(&table(fadt::SIGNATURE, &fadt_body(0x32, 0))).unwrap();
+    assert_eq!(fadt.century_register, Some(0x32));
+    assert!(fadt.cmos_rtc_present);
+
+    let fadt = fadt::Fadt::parse(&table(fadt::SIGNATURE, &fadt_body(0, 1 << 5))).unwrap();
+    assert_eq!(fadt.century_register, None);
+    assert!(!fadt.cmos_rtc_present);
+}
+
+#[test]
+fn short_fadt_falls_back_to_defaults() {
+    let fadt = fadt::Fadt::parse(&table(fadt::SIGNATURE, &[0; 40])).unwrap();
+    assert_eq!(fadt.century_register, None);
+    assert!(fadt.cmos_rtc_present);
+}
+
+#[test]
+fn fadt_names_the_power_registers() {
+    let mut body = vec![0; 196 - sdt::HEADER_LEN];
+    let at = |offset: usize| offset - sdt::HEADER_LEN;
+    body[at(40)..at(44)].copy_from_slice(&0x7FE0_0000u32.to_le_bytes());
+    body[at(48)..at(52)].copy_from_slice(&0xB2u32.to_le_bytes());
+    body[at(52)] = 0xF1;
+    body[at(64)..at(68)].copy_from_slice(&0x604u32.to_le_bytes());
+    body[at(112)..at(116)].copy_from_slice(&(1u32 << 10).to_le_bytes());
+    // Reset register: port 0xCF9, byte access; value 6.
+    body[at(116)..at(120)].copy_from_slice(&[1, 8, 0, 1]);
+    body[at(120)..at(128)].copy_from_slice(&0xCF9u64.to_le_bytes());
+    body[at(128)] = 6;
+    // The extended PM1a register takes precedence.
+    body[at(172)..at(176)].copy_from_slice(&[1, 16, 0, 2]);
+    body[at(176)..at(184)].copy_from_slice(&0xB004u64.to_le_bytes());
+
+    let fadt = fadt::Fadt::parse(&table(fadt::SIGNATURE, &body)).unwrap();
+    assert_eq!(fadt.dsdt, Some(0x7FE0_0000));
+    assert_eq!(fadt.smi_cmd, Some(0xB2));
+    assert_eq!(fadt.acpi_enable, 0xF1);
+    assert_eq!(fadt.pm1a_cnt, Some(0xB004));
+    assert_eq!(fadt.pm1b_cnt, None);
+    let (reset, value) = fadt.reset.expect("reset register");
+    assert_eq!(reset.space_id, GenericAddress::SPACE_IO);
+    assert_eq!(reset.port(), Some(0xCF9));
+    assert_eq!(value, 6);
+
+    // Without the feature flag, the reset register is ignored.
+    body[at(112)..at(116)].copy_from_slice(&0u32.to_le_bytes());
+    let fadt = fadt::Fadt::parse(&table(fadt::SIGNATURE, &body)).unwrap();
+    assert_eq!(fadt.reset, None);
+}
+
+#[test]
+fn s5_is_found_in_the_aml() {
+    // QEMU: `Name (_S5, Package (0x04) { Zero, Zero, Zero, Zero })`.
+    let aml = [
+        0x08, 0x5F, 0x53, 0x35, 0x5F, 0x12, 0x06, 0x04, 0x00, 0x00, 0x00, 0x00,
+    ];
+    assert_eq!(sleep::find_s5(&aml), Some(SleepType { a: 0, b: 0 }));
+
+    // `Name (\_S5_, Package (0x04) { 0x05, 0x0007, Zero, Zero })`.
+    let aml = [
+        0x08, 0x5C, 0x5F, 0x53, 0x35, 0x5F, 0x12, 0x0A, 0x04, 0x0A, 0x05, 0x0B, 0x07, 0x00, 0x00,
+        0x00,
+    ];
+    let s5 = sleep::find_s5(&aml).expect("_S5_");
+    assert_eq!(s5, SleepType { a: 5, b: 7 });
+    assert_eq!(s5.pm1a_bits(), (5 << 10) | (1 << 13));
+}
+
+#[test]
+fn s5_references_are_not_definitions() {
+    let aml = [0x70, 0x5F, 0x53, 0x35, 0x5F, 0x12, 0x06, 0x04, 0x00, 0x00];
+    assert_eq!(sleep::find_s5(&aml), None);
+}
+
+#[test]
+fn madt_lists_io_apics_and_overrides() {
+    let table = table(madt::SIGNATURE, &madt_body());
+    let madt = Madt::parse(&table).unwrap();
+
+    assert_eq!(madt.local_apic_address(), 0xFEE0_0000);
+    assert_eq!(madt.entries().count(), 7);
+    assert_eq!(
+        madt.entries().next(),
+        Some(Entry::LocalApic(LocalApic {
+            processor_uid: 0,
+            apic_id: 0,
+            enabled: true
+        }))
+    );
+    assert_eq!(
+        madt.io_apics().collect::<Vec<_>>(),
+        [IoApic {
+            id: 1,
+            address: 0xFEC0_0000,
+            gsi_base: 0
+        }]
+    );
+    assert_eq!(
+        madt.interrupt_override(9),
+        Some(InterruptOverride {
+            bus: 0,
+            source: 9,
+            gsi: 9,
+            polarity: Polarity::ActiveLow,
+            trigger: Trigger::Level,
+        })
+    );
+    assert_eq!(madt.interrupt_override(0).map(|o| o.gsi), Some(2));
+    assert_eq!(madt.interrupt_override(8), None);
+}
+
+#[test]
+fn madt_lists_local_apics_and_nmis() {
+    let table = table(madt::SIGNATURE, &madt_body());
+    let madt = Madt::parse(&table).unwrap();
+
+    assert_eq!(
+        madt.local_apics().collect::<Vec<_>>(),
+        [
+            LocalApic {
+                processor_uid: 0,
+                apic_id: 0,
+                enabled: true
+            },
+            LocalApic {
+                processor_uid: 0x200,
+                apic_id: 0x100,
+                enabled: false
+            }
+        ]
+    );
+
+    let all = LocalApicNmi {
+        processor_uid: None,
+        lint: 1,
+        polarity: Polarity::ConformsToBus,
+        trigger: Trigger::ConformsToBus,
+    };
+    assert_eq!(madt.local_apic_nmis(0).collect::<Vec<_>>(), [all]);
+    assert_eq!(
+        madt.local_apic_nmis(0x100).collect::<Vec<_>>(),
+        [
+            all,
+            LocalApicNmi {
+                processor_uid: Some(0x200),
+                lint: 0,
+                polarity: Polarity::ActiveHigh,
+                trigger: Trigger::Edge,
+            }
+        ]
+    );
+    // Processors not listed only get the entries for all processors.
+    assert_eq!(madt.local_apic_nmis(7).collect::<Vec<_>>(), [all]);
+}
+
+#[test]
+fn madt_entries_stop_at_a_malformed_entry() {
+    let mut body = madt_body();
+    // The local APIC entry claims to be empty.
+    body[9] = 0;
+    let table = table(madt::SIGNATURE, &body);
+    let madt = Madt::parse(&table).unwrap();
+
+    assert_eq!(madt.entries().count(), 0);
+}
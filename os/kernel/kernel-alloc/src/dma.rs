@@ -0,0 +1,277 @@
+//! # DMA Buffers
+//!
+//! Devices read and write memory by physical address, bypassing the page
+//! tables, so memory shared with a device must be physically contiguous, lie
+//! where the device can address it, and its physical address must be known
+//! to program the device. [`DmaBuffer::alloc`] returns such a buffer:
+//!
+//! * **Contiguous** whole frames, zeroed.
+//! * **Constrained** by [`DmaConstraints`]: a highest [`Zone`], e.g.
+//!   [`Zone::Below4G`] for devices with 32-bit addressing, and an alignment
+//!   of at least [`CACHE_LINE_SIZE`]. As buffers take whole frames, no other
+//!   data shares a cache line with them.
+//! * **Addressable** from both sides: [`DmaBuffer::phys`] for the device,
+//!   [`DmaBuffer::virt`] (the direct map alias) or the slice the buffer
+//!   derefs to for the CPU.
+//! * **Released** when dropped. In debug builds, the memory is filled with
+//!   [`DMA_POISON_BYTE`] first, so a device or driver still using the buffer
+//!   reads something that stands out.
+//!
+//! DMA on x86 is cache coherent, so the write-back direct map needs no
+//! flushing; ordering against the device is up to the driver's fences.
+//!
+//! ## Frames
+//!
+//! The crate keeps no allocator of its own. The kernel implements
+//! [`DmaFrames`] over its frame allocator and names the buffer type:
+//!
+//! ```rust,ignore
+//! pub type DmaBuffer = kernel_alloc::dma::DmaBuffer<KernelDmaFrames>;
+//!
+//! let ring = DmaBuffer::alloc(4096, DmaConstraints::BELOW_4G)?;
+//! device.set_ring_address(ring.phys());
+//! ```
+
+use crate::frame_alloc::Zone;
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+use kernel_info::memory::HHDM_BASE;
+use kernel_memory_addresses::{PageSize, PhysicalAddress, PhysicalPage, Size4K, VirtualAddress};
+
+/// Size of a cache line, and the smallest alignment of a [`DmaBuffer`].
+pub const CACHE_LINE_SIZE: u64 = 64;
+
+/// The byte released buffers are filled with in debug builds.
+pub const DMA_POISON_BYTE: u8 = 0xd6;
+
+/// Where a [`DmaBuffer`] may be placed.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct DmaConstraints {
+    zone: Zone,
+    align: u64,
+}
+
+impl DmaConstraints {
+    /// Anywhere in physical memory.
+    pub const ANY: Self = Self::below(Zone::Normal);
+
+    /// Below 4 GiB, for devices with 32-bit addressing.
+    pub const BELOW_4G: Self = Self::below(Zone::Below4G);
+
+    /// Within `zone` or a lower one, cache-line aligned.
+    #[must_use]
+    pub const fn below(zone: Zone) -> Self {
+        Self {
+            zone,
+            align: CACHE_LINE_SIZE,
+        }
+    }
+
+    /// The same, with the buffer starting at a multiple of `align` bytes.
+    ///
+    /// # Panics
+    /// If `align` is not a power of two; alignments below
+    /// [`CACHE_LINE_SIZE`] are raised to it.
+    #[must_use]
+    pub const fn with_align(self, align: u64) -> Self {
+        assert!(align.is_power_of_two(), "alignment must be a power of two");
+        Self {
+            zone: self.zone,
+            align: if align < CACHE_LINE_SIZE {
+                CACHE_LINE_SIZE
+            } else {
+                align
+            },
+        }
+    }
+
+    /// The highest zone the buffer may lie in.
+    #[must_use]
+    pub const fn zone(self) -> Zone {
+        self.zone
+    }
+
+    /// Alignment of the buffer in bytes.
+    #[must_use]
+    pub const fn align(self) -> u64 {
+        self.align
+    }
+
+    /// Alignment of the buffer in frames.
+    #[allow(clippy::cast_possible_truncation)]
+    const fn align_frames(self) -> usize {
+        self.align.div_ceil(Size4K::SIZE) as usize
+    }
+}
+
+impl Default for DmaConstraints {
+    fn default() -> Self {
+        Self::ANY
+    }
+}
+
+/// Where [`DmaBuffer`]s take their frames from and return them to.
+pub trait DmaFrames {
+    /// Allocate `count` physically contiguous frames within `zone` or a
+    /// lower one, the first at a multiple of `align` frames.
+    fn alloc_frames(count: usize, zone: Zone, align: usize) -> Option<PhysicalPage<Size4K>>;
+
+    /// Return `count` frames from [`alloc_frames`](Self::alloc_frames),
+    /// starting at `first`.
+    fn free_frames(first: PhysicalPage<Size4K>, count: usize);
+}
+
+/// Why [`DmaBuffer::alloc`] failed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum DmaError {
+    #[error("empty DMA buffer")]
+    Empty,
+    #[error("no contiguous frames for a DMA buffer")]
+    OutOfMemory,
+}
+
+/// Physically contiguous memory shared with a device; see the
+/// [module docs](self).
+pub struct DmaBuffer<F: DmaFrames> {
+    first: PhysicalPage<Size4K>,
+    frames: usize,
+    len: usize,
+    _frames: PhantomData<fn() -> F>,
+}
+
+impl<F: DmaFrames> DmaBuffer<F> {
+    /// Allocate `len` zeroed bytes that satisfy `constraints`.
+    ///
+    /// # Errors
+    /// [`DmaError::Empty`] if `len` is zero, [`DmaError::OutOfMemory`] if no
+    /// run of frames satisfies the constraints.
+    pub fn alloc(len: usize, constraints: DmaConstraints) -> Result<Self, DmaError> {
+        if len == 0 {
+            return Err(DmaError::Empty);
+        }
+        let frames = (len as u64).div_ceil(Size4K::SIZE);
+        let frames = usize::try_from(frames).map_err(|_| DmaError::OutOfMemory)?;
+        let first = F::alloc_frames(frames, constraints.zone, constraints.align_frames())
+            .ok_or(DmaError::OutOfMemory)?;
+        debug_assert!(first.base().as_u64().is_multiple_of(constraints.align));
+
+        let buffer = Self {
+            first,
+            frames,
+            len,
+            _frames: PhantomData,
+        };
+        buffer.fill(0);
+        Ok(buffer)
+    }
+
+    /// Physical address of the first byte, for the device.
+    #[must_use]
+    pub const fn phys(&self) -> PhysicalAddress {
+        self.first.base()
+    }
+
+    /// Physical address of the byte at `offset`.
+    ///
+    /// # Panics
+    /// If `offset` is beyond the end of the buffer.
+    #[must_use]
+    pub fn phys_at(&self, offset: usize) -> PhysicalAddress {
+        assert!(
+            offset < self.len,
+            "offset {offset:#x} outside of DMA buffer"
+        );
+        self.phys() + offset as u64
+    }
+
+    /// Virtual address of the first byte, in the direct map.
+    #[must_use]
+    pub fn virt(&self) -> VirtualAddress {
+        HHDM_BASE + self.phys().as_u64()
+    }
+
+    /// Length in bytes, as requested.
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Pointer to the first byte, for volatile accesses to memory the device
+    /// may be writing.
+    #[must_use]
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.virt().as_u64() as *mut u8
+    }
+
+    /// Fill all frames of the buffer, including the slack after `len`.
+    #[allow(clippy::cast_possible_truncation)]
+    fn fill(&self, byte: u8) {
+        let bytes = self.frames * Size4K::SIZE as usize;
+        // Safety: the frames are ours and reachable through the direct map.
+        unsafe { core::ptr::write_bytes(self.as_ptr(), byte, bytes) };
+    }
+}
+
+impl<F: DmaFrames> Deref for DmaBuffer<F> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        // Safety: see `fill`; the driver keeps the CPU off bytes the device
+        // currently owns.
+        unsafe { core::slice::from_raw_parts(self.as_ptr(), self.len) }
+    }
+}
+
+impl<F: DmaFrames> DerefMut for DmaBuffer<F> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        // Safety: see `deref`.
+        unsafe { core::slice::from_raw_parts_mut(self.as_ptr(), self.len) }
+    }
+}
+
+impl<F: DmaFrames> Drop for DmaBuffer<F> {
+    fn drop(&mut self) {
+        if cfg!(debug_assertions) {
+            self.fill(DMA_POISON_BYTE);
+        }
+        F::free_frames(self.first, self.frames);
+    }
+}
+
+impl<F: DmaFrames> fmt::Debug for DmaBuffer<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DmaBuffer")
+            .field("phys", &self.phys())
+            .field("frames", &self.frames)
+            .field("len", &self.len)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alignment_is_at_least_a_cache_line() {
+        assert_eq!(DmaConstraints::ANY.align(), CACHE_LINE_SIZE);
+        assert_eq!(DmaConstraints::ANY.with_align(8).align(), CACHE_LINE_SIZE);
+        assert_eq!(DmaConstraints::ANY.with_align(8).align_frames(), 1);
+
+        let large = DmaConstraints::BELOW_4G.with_align(64 * 1024);
+        assert_eq!(large.zone(), Zone::Below4G);
+        assert_eq!(large.align_frames(), 16);
+    }
+
+    #[test]
+    #[should_panic(expected = "power of two")]
+    fn alignment_must_be_a_power_of_two() {
+        let _ = DmaConstraints::ANY.with_align(96);
+    }
+}
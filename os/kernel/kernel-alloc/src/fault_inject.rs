@@ -0,0 +1,518 @@
+//! # Allocation Fault Injection
+//!
+//! Deterministic allocation failures for exercising out-of-memory paths such
+//! as [`AddressSpaceError::OutOfMemory`](kernel_vmem::address_space::AddressSpaceError)
+//! that never trigger on a machine with enough memory. Only compiled with the
+//! `fault-inject` feature (and for this crate's tests).
+//!
+//! ## Injector
+//!
+//! A [`FaultInjector`] decides, for every allocation it sees, whether it
+//! fails. Its [`FaultMode`] is either
+//!
+//! * [`EveryNth(n)`](FaultMode::EveryNth): the `n`th, `2n`th, ... allocation
+//!   fails, or
+//! * [`AfterBudget(n)`](FaultMode::AfterBudget): the first `n` allocations
+//!   succeed and all later ones fail.
+//!
+//! The decision depends on nothing but the allocation count, so a failing
+//! sequence replays exactly. All state is atomic: an injector lives in a
+//! `static` and can be shared by several wrapped allocators.
+//!
+//! ## Wrappers
+//!
+//! * [`FaultyFrameAlloc`] wraps a [`PhysFrameAlloc`]; injected failures make
+//!   [`alloc_4k`](PhysFrameAlloc::alloc_4k) return `None`.
+//! * [`FaultyGlobalAlloc`] wraps a [`GlobalAlloc`]; injected failures return
+//!   a null pointer from `alloc`, `alloc_zeroed` and `realloc`.
+//!
+//! Frees always reach the wrapped allocator.
+//!
+//! ## Skip list
+//!
+//! Some allocations must not fail because nothing could handle it, e.g. the
+//! ones made during boot. [`FaultInjector::skip`] exempts a call site, given
+//! as a source file (matched as a path suffix) and an optional line. The site
+//! of an allocation is the caller of the wrapper's allocation method, as
+//! reported by [`Location::caller`]; for [`FaultyFrameAlloc`] used through
+//! `AddressSpace`, this is the `kernel-vmem` code that needs the frame.
+//! Exempted allocations do not advance the allocation count.
+//!
+//! ## Example
+//!
+//! ```rust
+//! use kernel_alloc::fault_inject::{FaultInjector, FaultMode, FaultyFrameAlloc};
+//! use kernel_alloc::frame_alloc::{BitmapFrameAlloc, DEFAULT_MANAGED};
+//! use kernel_vmem::PhysFrameAlloc;
+//!
+//! static FAULTS: FaultInjector = FaultInjector::new();
+//!
+//! let words = BitmapFrameAlloc::storage_words(DEFAULT_MANAGED);
+//! let storage = Box::leak(vec![0; words].into_boxed_slice());
+//! let mut pmm = FaultyFrameAlloc::new(BitmapFrameAlloc::new(storage), &FAULTS);
+//! FAULTS.set_mode(FaultMode::AfterBudget(1));
+//! assert!(pmm.alloc_4k().is_some());
+//! assert!(pmm.alloc_4k().is_none());
+//! assert_eq!(FAULTS.stats().injected, 1);
+//! ```
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::ops::{Deref, DerefMut};
+use core::panic::Location;
+use core::sync::atomic::{AtomicPtr, AtomicU8, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use kernel_memory_addresses::{PhysicalPage, Size4K};
+use kernel_vmem::PhysFrameAlloc;
+
+/// Maximum number of call sites on a [`FaultInjector`]'s skip list.
+pub const MAX_SKIP_SITES: usize = 8;
+
+/// When a [`FaultInjector`] fails allocations.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub enum FaultMode {
+    /// Never fail.
+    #[default]
+    Off,
+    /// Fail every `n`th allocation; `0` never fails.
+    EveryNth(u64),
+    /// Let `n` allocations succeed, then fail all later ones.
+    AfterBudget(u64),
+}
+
+impl FaultMode {
+    const OFF: u8 = 0;
+    const EVERY_NTH: u8 = 1;
+    const AFTER_BUDGET: u8 = 2;
+
+    const fn encode(self) -> (u8, u64) {
+        match self {
+            Self::Off => (Self::OFF, 0),
+            Self::EveryNth(n) => (Self::EVERY_NTH, n),
+            Self::AfterBudget(n) => (Self::AFTER_BUDGET, n),
+        }
+    }
+
+    const fn decode(kind: u8, n: u64) -> Self {
+        match kind {
+            Self::EVERY_NTH => Self::EveryNth(n),
+            Self::AFTER_BUDGET => Self::AfterBudget(n),
+            _ => Self::Off,
+        }
+    }
+
+    /// Whether the allocation with the 1-based number `nth` fails.
+    const fn fails(self, nth: u64) -> bool {
+        match self {
+            Self::Off | Self::EveryNth(0) => false,
+            Self::EveryNth(n) => nth.is_multiple_of(n),
+            Self::AfterBudget(n) => nth > n,
+        }
+    }
+}
+
+/// A snapshot of a [`FaultInjector`]'s counters.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct FaultStats {
+    /// Allocations counted towards the [`FaultMode`].
+    pub allocations: u64,
+    /// Allocations failed on purpose.
+    pub injected: u64,
+    /// Allocations from a skipped call site.
+    pub skipped: u64,
+}
+
+/// Error returned by [`FaultInjector::skip`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq, thiserror::Error)]
+#[error("too many skipped call sites (max {MAX_SKIP_SITES})")]
+pub struct TooManySkipSites;
+
+/// One skip list entry; `file` is null while the slot is free.
+struct SkipSlot {
+    file: AtomicPtr<u8>,
+    len: AtomicUsize,
+    /// `0` matches every line.
+    line: AtomicU32,
+}
+
+impl SkipSlot {
+    const fn new() -> Self {
+        Self {
+            file: AtomicPtr::new(core::ptr::null_mut()),
+            len: AtomicUsize::new(0),
+            line: AtomicU32::new(0),
+        }
+    }
+
+    fn matches(&self, site: &Location<'_>) -> bool {
+        let file = self.file.load(Ordering::Acquire);
+        if file.is_null() {
+            return false;
+        }
+        let len = self.len.load(Ordering::Relaxed);
+        // SAFETY: `skip` stored the parts of a `&'static str` before publishing `file`.
+        let file =
+            unsafe { core::str::from_utf8_unchecked(core::slice::from_raw_parts(file, len)) };
+        let line = self.line.load(Ordering::Relaxed);
+        site.file().ends_with(file) && (line == 0 || line == site.line())
+    }
+}
+
+/// Decides which allocations fail; see the [module docs](self).
+pub struct FaultInjector {
+    kind: AtomicU8,
+    n: AtomicU64,
+    allocations: AtomicU64,
+    injected: AtomicU64,
+    skipped: AtomicU64,
+    skip: [SkipSlot; MAX_SKIP_SITES],
+}
+
+impl Default for FaultInjector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FaultInjector {
+    /// An injector in [`FaultMode::Off`] with an empty skip list.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            kind: AtomicU8::new(FaultMode::OFF),
+            n: AtomicU64::new(0),
+            allocations: AtomicU64::new(0),
+            injected: AtomicU64::new(0),
+            skipped: AtomicU64::new(0),
+            skip: [const { SkipSlot::new() }; MAX_SKIP_SITES],
+        }
+    }
+
+    /// The current mode.
+    pub fn mode(&self) -> FaultMode {
+        FaultMode::decode(
+            self.kind.load(Ordering::Acquire),
+            self.n.load(Ordering::Relaxed),
+        )
+    }
+
+    /// Switch to `mode` and reset the counters, so that counting starts over.
+    pub fn set_mode(&self, mode: FaultMode) {
+        let (kind, n) = mode.encode();
+        self.kind.store(FaultMode::OFF, Ordering::Release);
+        self.reset();
+        self.n.store(n, Ordering::Relaxed);
+        self.kind.store(kind, Ordering::Release);
+    }
+
+    /// Zero the counters.
+    pub fn reset(&self) {
+        self.allocations.store(0, Ordering::Relaxed);
+        self.injected.store(0, Ordering::Relaxed);
+        self.skipped.store(0, Ordering::Relaxed);
+    }
+
+    /// A snapshot of the counters.
+    pub fn stats(&self) -> FaultStats {
+        FaultStats {
+            allocations: self.allocations.load(Ordering::Relaxed),
+            injected: self.injected.load(Ordering::Relaxed),
+            skipped: self.skipped.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Never fail allocations made in `file` (a path suffix such as
+    /// `"address_space.rs"`), at `line` or anywhere in the file.
+    ///
+    /// Not synchronized with concurrent calls to `skip` or
+    /// [`clear_skips`](Self::clear_skips); configure the skip list before
+    /// sharing the injector.
+    ///
+    /// # Errors
+    /// [`TooManySkipSites`] if the list already holds [`MAX_SKIP_SITES`] sites.
+    pub fn skip(&self, file: &'static str, line: Option<u32>) -> Result<(), TooManySkipSites> {
+        let slot = self
+            .skip
+            .iter()
+            .find(|slot| slot.file.load(Ordering::Acquire).is_null())
+            .ok_or(TooManySkipSites)?;
+        slot.len.store(file.len(), Ordering::Relaxed);
+        slot.line.store(line.unwrap_or(0), Ordering::Relaxed);
+        slot.file.store(file.as_ptr().cast_mut(), Ordering::Release);
+        Ok(())
+    }
+
+    /// Empty the skip list.
+    pub fn clear_skips(&self) {
+        for slot in &self.skip {
+            slot.file.store(core::ptr::null_mut(), Ordering::Release);
+        }
+    }
+
+    /// Count an allocation made at `site` and decide whether it fails.
+    pub fn should_fail(&self, site: &Location<'_>) -> bool {
+        let mode = self.mode();
+        if mode == FaultMode::Off {
+            return false;
+        }
+        if self.skip.iter().any(|slot| slot.matches(site)) {
+            self.skipped.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+
+        let nth = self.allocations.fetch_add(1, Ordering::Relaxed) + 1;
+        let fail = mode.fails(nth);
+        if fail {
+            self.injected.fetch_add(1, Ordering::Relaxed);
+        }
+        fail
+    }
+}
+
+/// A [`PhysFrameAlloc`] whose allocations fail as its [`FaultInjector`] decides.
+///
+/// Dereferences to the wrapped allocator for everything but
+/// [`PhysFrameAlloc`].
+pub struct FaultyFrameAlloc<'f, A> {
+    inner: A,
+    faults: &'f FaultInjector,
+}
+
+impl<'f, A> FaultyFrameAlloc<'f, A> {
+    /// Wrap `inner`, failing allocations as `faults` decides.
+    pub const fn new(inner: A, faults: &'f FaultInjector) -> Self {
+        Self { inner, faults }
+    }
+
+    /// The injector.
+    pub const fn faults(&self) -> &'f FaultInjector {
+        self.faults
+    }
+
+    /// Unwrap the allocator.
+    pub fn into_inner(self) -> A {
+        self.inner
+    }
+}
+
+impl<A> Deref for FaultyFrameAlloc<'_, A> {
+    type Target = A;
+
+    fn deref(&self) -> &A {
+        &self.inner
+    }
+}
+
+impl<A> DerefMut for FaultyFrameAlloc<'_, A> {
+    fn deref_mut(&mut self) -> &mut A {
+        &mut self.inner
+    }
+}
+
+impl<A: PhysFrameAlloc> PhysFrameAlloc for FaultyFrameAlloc<'_, A> {
+    #[track_caller]
+    fn alloc_4k(&mut self) -> Option<PhysicalPage<Size4K>> {
+        if self.faults.should_fail(Location::caller()) {
+            return None;
+        }
+        self.inner.alloc_4k()
+    }
+
+    fn free_4k(&mut self, pa: PhysicalPage<Size4K>) {
+        self.inner.free_4k(pa);
+    }
+}
+
+/// A [`GlobalAlloc`] whose allocations fail as its [`FaultInjector`] decides.
+///
+/// Allocation sites are inside the `alloc` crate, so only a skip list entry
+/// for its sources (e.g. `"raw_vec.rs"`) matches.
+pub struct FaultyGlobalAlloc<A> {
+    inner: A,
+    faults: &'static FaultInjector,
+}
+
+impl<A> FaultyGlobalAlloc<A> {
+    /// Wrap `inner`, failing allocations as `faults` decides.
+    pub const fn new(inner: A, faults: &'static FaultInjector) -> Self {
+        Self { inner, faults }
+    }
+
+    /// The wrapped allocator.
+    pub const fn inner(&self) -> &A {
+        &self.inner
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for FaultyGlobalAlloc<A> {
+    #[track_caller]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if self.faults.should_fail(Location::caller()) {
+            return core::ptr::null_mut();
+        }
+        unsafe { self.inner.alloc(layout) }
+    }
+
+    #[track_caller]
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        if self.faults.should_fail(Location::caller()) {
+            return core::ptr::null_mut();
+        }
+        unsafe { self.inner.alloc_zeroed(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { self.inner.dealloc(ptr, layout) }
+    }
+
+    #[track_caller]
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if self.faults.should_fail(Location::caller()) {
+            return core::ptr::null_mut();
+        }
+        unsafe { self.inner.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kernel_memory_addresses::{PhysicalAddress, VirtualAddress};
+    use kernel_vmem::VirtualMemoryPageBits;
+    use kernel_vmem::address_space::{
+        AddressSpace, AddressSpaceError, AddressSpaceMapOneError, MapSizeEnsureChainError,
+    };
+    use kernel_vmem_testutil::MockMemory;
+    use std::alloc::System;
+
+    #[test]
+    fn every_nth_fails_periodically() {
+        static FAULTS: FaultInjector = FaultInjector::new();
+        let mem = MockMemory::new(16);
+        let mut alloc = FaultyFrameAlloc::new(mem.allocator(), &FAULTS);
+        FAULTS.set_mode(FaultMode::EveryNth(3));
+
+        let pattern: Vec<bool> = (0..7).map(|_| alloc.alloc_4k().is_some()).collect();
+        assert_eq!(pattern, [true, true, false, true, true, false, true]);
+        assert_eq!(
+            FAULTS.stats(),
+            FaultStats {
+                allocations: 7,
+                injected: 2,
+                skipped: 0
+            }
+        );
+    }
+
+    #[test]
+    fn budget_and_mode_switch_reset_counting() {
+        static FAULTS: FaultInjector = FaultInjector::new();
+        let mem = MockMemory::new(16);
+        let mut alloc = FaultyFrameAlloc::new(mem.allocator(), &FAULTS);
+
+        FAULTS.set_mode(FaultMode::AfterBudget(2));
+        assert!(alloc.alloc_4k().is_some());
+        assert!(alloc.alloc_4k().is_some());
+        assert!(alloc.alloc_4k().is_none());
+        assert!(alloc.alloc_4k().is_none());
+
+        FAULTS.set_mode(FaultMode::AfterBudget(1));
+        assert_eq!(FAULTS.stats(), FaultStats::default());
+        assert!(alloc.alloc_4k().is_some());
+        assert!(alloc.alloc_4k().is_none());
+
+        FAULTS.set_mode(FaultMode::Off);
+        assert!(alloc.alloc_4k().is_some());
+        assert_eq!(FAULTS.stats().allocations, 0);
+    }
+
+    #[test]
+    fn skipped_sites_never_fail() {
+        static FAULTS: FaultInjector = FaultInjector::new();
+        let mem = MockMemory::new(16);
+        let mut alloc = FaultyFrameAlloc::new(mem.allocator(), &FAULTS);
+        FAULTS.set_mode(FaultMode::AfterBudget(0));
+
+        FAULTS.skip("fault_inject.rs", Some(line!() + 1)).unwrap();
+        assert!(alloc.alloc_4k().is_some());
+        assert!(alloc.alloc_4k().is_none());
+        assert_eq!(FAULTS.stats().skipped, 1);
+
+        FAULTS.clear_skips();
+        FAULTS.skip("fault_inject.rs", None).unwrap();
+        assert!(alloc.alloc_4k().is_some());
+
+        FAULTS.clear_skips();
+        for _ in 0..MAX_SKIP_SITES {
+            FAULTS.skip("elsewhere.rs", None).unwrap();
+        }
+        assert_eq!(FAULTS.skip("elsewhere.rs", None), Err(TooManySkipSites));
+    }
+
+    #[test]
+    fn new_address_space_reports_oom() {
+        static FAULTS: FaultInjector = FaultInjector::new();
+        let mem = MockMemory::new(16);
+        let mut alloc = FaultyFrameAlloc::new(mem.allocator(), &FAULTS);
+        FAULTS.set_mode(FaultMode::AfterBudget(0));
+
+        let result = AddressSpace::new(&mem, &mut alloc);
+        assert!(matches!(result, Err(AddressSpaceError::OutOfMemory)));
+        assert_eq!(FAULTS.stats().injected, 1);
+    }
+
+    #[test]
+    fn mapping_reports_the_table_that_ran_out() {
+        static FAULTS: FaultInjector = FaultInjector::new();
+        let mem = MockMemory::new(16);
+        let mut alloc = FaultyFrameAlloc::new(mem.allocator(), &FAULTS);
+        let root = alloc.alloc_4k().unwrap();
+        let aspace = AddressSpace::from_root(&mem, root);
+
+        let va = VirtualAddress::new(0x4000_0000);
+        let pa = PhysicalAddress::new(0x20_0000);
+        let nonleaf = VirtualMemoryPageBits::new()
+            .with_present(true)
+            .with_writable(true);
+        let leaf = nonleaf;
+
+        let expected = [
+            MapSizeEnsureChainError::OomPdpt,
+            MapSizeEnsureChainError::OomPd,
+            MapSizeEnsureChainError::OomPt,
+        ];
+        for (budget, oom) in (0..).zip(expected) {
+            let aspace = AddressSpace::from_root(&mem, alloc.alloc_4k().unwrap());
+            FAULTS.set_mode(FaultMode::AfterBudget(budget));
+            let result = aspace.map_one::<_, Size4K>(&mut alloc, va, pa, nonleaf, leaf);
+            assert_eq!(result, Err(AddressSpaceMapOneError::OutOfMemory(oom)));
+            FAULTS.set_mode(FaultMode::Off);
+        }
+
+        FAULTS.set_mode(FaultMode::AfterBudget(3));
+        aspace
+            .map_one::<_, Size4K>(&mut alloc, va, pa, nonleaf, leaf)
+            .unwrap();
+        assert_eq!(aspace.query(va), Some(pa));
+    }
+
+    #[test]
+    fn global_alloc_returns_null() {
+        static FAULTS: FaultInjector = FaultInjector::new();
+        let heap = FaultyGlobalAlloc::new(System, &FAULTS);
+        let layout = Layout::from_size_align(64, 8).unwrap();
+        FAULTS.set_mode(FaultMode::EveryNth(2));
+
+        unsafe {
+            let a = heap.alloc(layout);
+            assert!(!a.is_null());
+            assert!(heap.alloc_zeroed(layout).is_null());
+            let a = heap.realloc(a, layout, 128);
+            assert!(!a.is_null());
+            assert!(
+                heap.realloc(a, Layout::from_size_align(128, 8).unwrap(), 256)
+                    .is_null()
+            );
+            heap.dealloc(a, Layout::from_size_align(128, 8).unwrap());
+        }
+        assert_eq!(FAULTS.stats().injected, 2);
+    }
+}
@@ -1,19 +1,22 @@
 //! # Minimal Bitmap-based Physical Memory Manager (PMM)
 //!
 //! This module provides a minimal, no-heap physical memory manager for 4K frames,
-//! using a bitmap to track free/used frames in a fixed region. It is suitable for
-//! early kernel use or as a foundation for a more advanced PMM.
+//! using a bitmap to track free/used frames in a region starting at address 0.
+//! It is suitable for early kernel use or as a foundation for a more advanced PMM.
 //!
 //! ## Features
 //! - Tracks allocation and freeing of 4K frames using a bitmap.
-//! - No heap required; all state is stored inline.
-//! - Can be extended to initialize from a memory map.
+//! - No heap required; the bitmaps live in storage the caller provides, sized
+//!   with [`BitmapFrameAlloc::storage_words`] for the memory to manage.
+//! - Initialized from a memory map: only frames inside usable ranges are handed out.
+//! - Physical memory [`Zone`]s for callers with address constraints.
 //!
 //! ## Usage Example
 //! ```rust
-//! use kernel_alloc::frame_alloc::BitmapFrameAlloc;
+//! use kernel_alloc::frame_alloc::{BitmapFrameAlloc, DEFAULT_MANAGED};
 //! use kernel_vmem::PhysFrameAlloc;
-//! let mut pmm = BitmapFrameAlloc::new();
+//! let words = BitmapFrameAlloc::storage_words(DEFAULT_MANAGED);
+//! let mut pmm = BitmapFrameAlloc::new(Box::leak(vec![0; words].into_boxed_slice()));
 //! let frame = pmm.alloc_4k();
 //! if let Some(pa) = frame {
 //!     // Use the physical address...
@@ -21,30 +24,231 @@
 //! }
 //! ```
 //!
+//! ## Usable memory
+//! A fresh allocator considers every frame of its region from 1 MiB upwards
+//! usable; the region ends where its storage does, see
+//! [`manageable_size`](BitmapFrameAlloc::manageable_size). Once a memory map is available, call
+//! [`reserve_all`](BitmapFrameAlloc::reserve_all) and then
+//! [`add_usable_range`](BitmapFrameAlloc::add_usable_range) for each usable
+//! range; frames outside those ranges are never allocated. Frame 0 is never
+//! usable.
+//!
+//! ## Zones
+//! Some consumers need frames below an address limit: real-mode code such as
+//! the AP bootstrap trampoline below 1 MiB, ISA DMA below 16 MiB, 32-bit DMA
+//! below 4 GiB. [`BitmapFrameAlloc::alloc_4k_in`] returns a frame that
+//! satisfies the limit of the requested [`Zone`], trying the highest allowed
+//! zone first and falling back to the lower ones, so scarce low memory is
+//! only used once the rest is exhausted. The plain
+//! [`alloc_4k`](PhysFrameAlloc::alloc_4k) is a request for [`Zone::Normal`].
+//!
+//! ## Statistics
+//! The allocator counts used frames as they change. [`BitmapFrameAlloc::stats`]
+//! returns a [`FrameStats`] snapshot; to read them *without* access to the
+//! allocator (e.g. while it sits behind a lock), attach a shared
+//! [`FrameCounters`] block with [`BitmapFrameAlloc::with_counters`], which is
+//! kept up to date with atomic stores.
+//!
+//! ## Low-memory callbacks
+//! [`BitmapFrameAlloc::on_low_memory`] registers a callback that fires once
+//! whenever the number of free frames drops below a threshold; it is re-armed
+//! when the free count climbs back to the threshold. Callbacks run inside the
+//! allocation that crossed the threshold, so they must not allocate or free
+//! frames themselves — record the event or wake a worker that trims caches.
+//!
+//! ## Frame metadata
+//! [`BitmapFrameAlloc::attach_frame_table`] connects a [`FrameTable`] with one
+//! record per frame. The allocator keeps the records' [`FrameFlags`] in sync
+//! with the bitmap and, in debug builds, panics on double frees. The table's
+//! storage usually comes from [`BitmapFrameAlloc::alloc_contiguous_4k`]; see
+//! [`frame_info`](crate::frame_info).
+//!
 //! ## Safety
 //! - Only physical addresses within the managed region are tracked.
 //! - The user must ensure that reserved/used frames (e.g., kernel, bootloader) are marked as used before allocation.
 //! - No synchronization is provided; not thread-safe.
 
+use crate::frame_info::{FrameFlags, FrameTable};
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use kernel_memory_addresses::{PageSize, PhysicalAddress, PhysicalPage, Size4K};
 use kernel_vmem::PhysFrameAlloc;
 use log::trace;
 
-const PHYS_MEM_START: u64 = 0x0010_0000; // 1 MiB, example
-const PHYS_MEM_SIZE: u64 = 512 * 1024 * 1024; // 512 MiB, example
-const FRAME_SIZE: u64 = Size4K::SIZE;
-const NUM_FRAMES: usize = (PHYS_MEM_SIZE / FRAME_SIZE) as usize;
+pub(crate) const PHYS_MEM_START: u64 = 0;
+pub(crate) const FRAME_SIZE: u64 = Size4K::SIZE;
+
+/// Physical memory to manage when nothing tells how much there is.
+pub const DEFAULT_MANAGED: u64 = 512 * 1024 * 1024; // 512 MiB
+
+/// Without a memory map, memory below this address is left alone.
+const DEFAULT_USABLE_START: u64 = 0x0010_0000; // 1 MiB
+
+/// A physical memory zone, i.e. an address range for constrained allocations.
+///
+/// Zones are ordered from low to high addresses.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Zone {
+    /// Below 1 MiB; reachable from real mode (e.g. the AP trampoline).
+    Below1M,
+    /// 1 MiB to 16 MiB; reachable by legacy ISA DMA.
+    Below16M,
+    /// 16 MiB to 4 GiB; reachable by 32-bit DMA.
+    Below4G,
+    /// Everything above 4 GiB.
+    Normal,
+}
+
+impl Zone {
+    /// All zones, from low to high addresses.
+    pub const ALL: [Self; 4] = [Self::Below1M, Self::Below16M, Self::Below4G, Self::Normal];
+
+    /// First physical address of the zone.
+    #[must_use]
+    pub const fn start(self) -> u64 {
+        match self {
+            Self::Below1M => 0,
+            Self::Below16M => 0x0010_0000,
+            Self::Below4G => 0x0100_0000,
+            Self::Normal => 0x1_0000_0000,
+        }
+    }
+
+    /// Physical address right after the zone.
+    #[must_use]
+    pub const fn end(self) -> u64 {
+        match self {
+            Self::Below1M => 0x0010_0000,
+            Self::Below16M => 0x0100_0000,
+            Self::Below4G => 0x1_0000_0000,
+            Self::Normal => u64::MAX,
+        }
+    }
+
+    /// The zone containing physical address `pa`.
+    #[must_use]
+    pub const fn of(pa: u64) -> Self {
+        if pa < Self::Below1M.end() {
+            Self::Below1M
+        } else if pa < Self::Below16M.end() {
+            Self::Below16M
+        } else if pa < Self::Below4G.end() {
+            Self::Below4G
+        } else {
+            Self::Normal
+        }
+    }
+}
+
+/// Frame counts of one [`Zone`].
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct ZoneStats {
+    /// Usable frames in the zone.
+    pub total: usize,
+    /// Currently available frames in the zone.
+    pub free: usize,
+}
+
+/// Maximum number of low-memory callbacks per allocator.
+pub const MAX_LOW_MEMORY_WATCHES: usize = 4;
+
+/// A snapshot of frame allocator statistics (in 4 KiB frames).
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct FrameStats {
+    /// Usable frames managed by the allocator.
+    pub total: usize,
+    /// Frames currently allocated.
+    pub used: usize,
+    /// Frames currently available.
+    pub free: usize,
+    /// Lowest number of free frames seen so far.
+    pub min_free: usize,
+    /// Allocations that failed because no frame was free.
+    pub failed_allocs: u64,
+}
+
+/// Lock-free mirror of an allocator's statistics.
+///
+/// Attach with [`BitmapFrameAlloc::with_counters`], then read with
+/// [`snapshot`](Self::snapshot) from any context.
+pub struct FrameCounters {
+    total: AtomicUsize,
+    used: AtomicUsize,
+    min_free: AtomicUsize,
+    failed_allocs: AtomicU64,
+}
+
+impl Default for FrameCounters {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FrameCounters {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            total: AtomicUsize::new(0),
+            used: AtomicUsize::new(0),
+            min_free: AtomicUsize::new(0),
+            failed_allocs: AtomicU64::new(0),
+        }
+    }
+
+    /// The most recently published statistics.
+    ///
+    /// Each field is read individually, so a snapshot taken during an
+    /// allocation may be off by one frame.
+    #[must_use]
+    pub fn snapshot(&self) -> FrameStats {
+        let total = self.total.load(Ordering::Relaxed);
+        let used = self.used.load(Ordering::Relaxed).min(total);
+        FrameStats {
+            total,
+            used,
+            free: total - used,
+            min_free: self.min_free.load(Ordering::Relaxed),
+            failed_allocs: self.failed_allocs.load(Ordering::Relaxed),
+        }
+    }
+
+    fn publish(&self, stats: &FrameStats) {
+        self.total.store(stats.total, Ordering::Relaxed);
+        self.used.store(stats.used, Ordering::Relaxed);
+        self.min_free.store(stats.min_free, Ordering::Relaxed);
+        self.failed_allocs
+            .store(stats.failed_allocs, Ordering::Relaxed);
+    }
+}
+
+/// Called with the current statistics when free frames drop below a threshold.
+pub type LowMemoryCallback = fn(&FrameStats);
+
+/// Error returned by [`BitmapFrameAlloc::on_low_memory`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq, thiserror::Error)]
+#[error("too many low-memory callbacks (max {MAX_LOW_MEMORY_WATCHES})")]
+pub struct TooManyWatches;
+
+#[derive(Copy, Clone)]
+struct LowMemoryWatch {
+    /// Fire when fewer than this many frames are free.
+    threshold: usize,
+    callback: LowMemoryCallback,
+    /// Cleared after firing until the free count recovers.
+    armed: bool,
+}
 
 /// Minimal bitmap-based PMM for 4K frames in a fixed region.
 ///
 /// This type manages a fixed region of physical memory, tracking free/used 4K frames
-/// using a bitmap. It supports allocation and freeing, but does not require a heap.
+/// using a bitmap. It supports allocation and freeing, but does not require a heap:
+/// the bitmaps live in the storage passed to [`new`](Self::new).
 ///
 /// # Example
 /// ```rust
 /// use kernel_alloc::frame_alloc::BitmapFrameAlloc;
 /// use kernel_vmem::PhysFrameAlloc;
-/// let mut pmm = BitmapFrameAlloc::new();
+/// let words = BitmapFrameAlloc::storage_words(64 * 1024 * 1024);
+/// let mut pmm = BitmapFrameAlloc::new(Box::leak(vec![0; words].into_boxed_slice()));
 /// let frame = pmm.alloc_4k();
 /// if let Some(pa) = frame {
 ///     // Use the physical address...
@@ -57,41 +261,287 @@ const NUM_FRAMES: usize = (PHYS_MEM_SIZE / FRAME_SIZE) as usize;
 /// - The user must ensure that reserved/used frames (e.g., kernel, bootloader) are marked as used before allocation.
 /// - No synchronization is provided; not thread-safe.
 pub struct BitmapFrameAlloc {
-    bitmap: [u64; NUM_FRAMES.div_ceil(64)],
+    /// Set bits are allocated or unusable frames.
+    bitmap: &'static mut [u64],
+    /// Set bits are usable frames.
+    usable: &'static mut [u64],
+    /// Number of frames the bitmaps cover.
+    num_frames: usize,
     base: u64,
+    stats: FrameStats,
+    zones: [ZoneStats; Zone::ALL.len()],
+    counters: Option<&'static FrameCounters>,
+    frames: Option<&'static FrameTable>,
+    watches: [Option<LowMemoryWatch>; MAX_LOW_MEMORY_WATCHES],
 }
 
-impl Default for BitmapFrameAlloc {
-    fn default() -> Self {
-        Self::new()
+impl BitmapFrameAlloc {
+    /// Words of storage [`new`](Self::new) takes to manage the physical
+    /// memory below `end`.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn storage_words(end: u64) -> usize {
+        let frames = (end.saturating_sub(PHYS_MEM_START) / FRAME_SIZE) as usize;
+        2 * frames.div_ceil(64)
     }
-}
 
-impl BitmapFrameAlloc {
+    /// An allocator for the physical memory `storage` has room for (see
+    /// [`storage_words`](Self::storage_words)), keeping its bitmaps there.
+    ///
+    /// Every frame from 1 MiB upwards starts out usable.
+    ///
+    /// # Panics
+    /// If `storage` has an odd number of words.
     #[must_use]
-    pub const fn new() -> Self {
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn new(storage: &'static mut [u64]) -> Self {
+        assert!(storage.len().is_multiple_of(2), "odd frame bitmap storage");
+        let (bitmap, usable) = storage.split_at_mut(storage.len() / 2);
+        let num_frames = bitmap.len() * 64;
+        let first_usable =
+            (((DEFAULT_USABLE_START - PHYS_MEM_START) / FRAME_SIZE) as usize).min(num_frames);
+        bitmap.fill(0);
+        usable.fill(u64::MAX);
+        bitmap[..first_usable / 64].fill(u64::MAX);
+        usable[..first_usable / 64].fill(0);
+
+        let mut zones = [ZoneStats::default(); Zone::ALL.len()];
+        for (stats, &zone) in zones.iter_mut().zip(&Zone::ALL) {
+            let (lo, hi) = zone_frames(num_frames, zone);
+            let lo = lo.max(first_usable);
+            if hi > lo {
+                *stats = ZoneStats {
+                    total: hi - lo,
+                    free: hi - lo,
+                };
+            }
+        }
+
+        let total = num_frames - first_usable;
         Self {
-            bitmap: [0; NUM_FRAMES.div_ceil(64)],
+            bitmap,
+            usable,
+            num_frames,
             base: PHYS_MEM_START,
+            stats: FrameStats {
+                total,
+                used: 0,
+                free: total,
+                min_free: total,
+                failed_allocs: 0,
+            },
+            zones,
+            counters: None,
+            frames: None,
+            watches: [None; MAX_LOW_MEMORY_WATCHES],
         }
     }
 
-    #[must_use]
+    /// Mark every frame unusable, e.g. before adding the usable ranges of a
+    /// memory map. Must be called before any frame is allocated.
+    pub fn reserve_all(&mut self) {
+        self.bitmap.fill(u64::MAX);
+        self.usable.fill(0);
+        self.zones = [ZoneStats::default(); Zone::ALL.len()];
+        self.stats.total = 0;
+        self.stats.used = 0;
+        self.stats.free = 0;
+        self.stats.min_free = 0;
+        self.publish();
+        if let Some(table) = self.frames {
+            for idx in 0..self.num_frames {
+                table.set_state(idx, FrameFlags::RESERVED);
+            }
+        }
+    }
+
+    /// Make all frames fully inside `[start, end)` available for allocation.
+    ///
+    /// Parts of the range outside the managed region are ignored.
     #[allow(clippy::cast_possible_truncation)]
+    pub fn add_usable_range(&mut self, start: PhysicalAddress, end: PhysicalAddress) {
+        let region_end = PHYS_MEM_START + self.manageable_size();
+        let start = start.as_u64().max(PHYS_MEM_START + FRAME_SIZE); // never frame 0
+        let end = end.as_u64().min(region_end);
+        if start >= end {
+            return;
+        }
+
+        let first = (start - self.base).div_ceil(FRAME_SIZE) as usize;
+        let last = ((end - self.base) / FRAME_SIZE) as usize;
+        for idx in first..last {
+            let (word, bit) = (idx / 64, idx % 64);
+            if self.usable[word] & (1 << bit) != 0 {
+                continue;
+            }
+
+            self.usable[word] |= 1 << bit;
+            self.bitmap[word] &= !(1 << bit);
+            if let Some(table) = self.frames {
+                table.set_state(idx, FrameFlags::NONE);
+            }
+            let zone = &mut self.zones[self.zone_of(idx) as usize];
+            zone.total += 1;
+            zone.free += 1;
+            self.stats.total += 1;
+            self.stats.free += 1;
+            self.stats.min_free += 1;
+        }
+        self.publish();
+    }
+
+    /// Frame counts of `zone`.
+    #[must_use]
+    pub const fn zone_stats(&self, zone: Zone) -> ZoneStats {
+        self.zones[zone as usize]
+    }
+
+    /// Allocate a frame that lies within `zone` or a lower one.
+    ///
+    /// Zones are tried from `zone` downwards, so low memory is only handed out
+    /// once higher zones are exhausted.
+    pub fn alloc_4k_in(&mut self, zone: Zone) -> Option<PhysicalPage<Size4K>> {
+        let found = Zone::ALL[..=zone as usize].iter().rev().find_map(|&z| {
+            let (lo, hi) = zone_frames(self.num_frames, z);
+            self.find_free(lo, hi)
+        });
+        let Some(idx) = found else {
+            self.count_failure();
+            return None;
+        };
+
+        let (word, bit) = (idx / 64, idx % 64);
+        self.bitmap[word] |= 1 << bit;
+        self.count_used(idx);
+
+        let pa = self.base + (idx as u64) * FRAME_SIZE;
+        let pa = PhysicalAddress::new(pa);
+        trace!("Allocated 4K frame at {pa} ({zone:?} request)");
+        Some(PhysicalPage::from_addr(pa))
+    }
+
+    /// Allocate `count` physically contiguous frames and return the first.
+    ///
+    /// Like [`alloc_4k`](PhysFrameAlloc::alloc_4k), higher zones are tried
+    /// first. The run never straddles a zone boundary. Meant for boot-time
+    /// allocations such as the [`FrameTable`]; the search is linear.
+    pub fn alloc_contiguous_4k(&mut self, count: usize) -> Option<PhysicalPage<Size4K>> {
+        self.alloc_contiguous_4k_in(count, Zone::Normal, 1)
+    }
+
+    /// Allocate `count` physically contiguous frames within `zone` or a lower
+    /// one, the first of them at a multiple of `align` frames, and return the
+    /// first.
+    ///
+    /// Zones are tried like in [`alloc_4k_in`](Self::alloc_4k_in).
+    ///
+    /// # Panics
+    /// If `align` is not a power of two.
+    pub fn alloc_contiguous_4k_in(
+        &mut self,
+        count: usize,
+        zone: Zone,
+        align: usize,
+    ) -> Option<PhysicalPage<Size4K>> {
+        assert!(align.is_power_of_two(), "alignment must be a power of two");
+        let found = Zone::ALL[..=zone as usize].iter().rev().find_map(|&z| {
+            let (lo, hi) = zone_frames(self.num_frames, z);
+            self.find_free_run(lo, hi, count, align)
+        });
+        let Some(first) = found else {
+            self.count_failure();
+            return None;
+        };
+
+        for idx in first..first + count {
+            self.bitmap[idx / 64] |= 1 << (idx % 64);
+            self.count_used(idx);
+        }
+
+        let pa = PhysicalAddress::new(self.base + (first as u64) * FRAME_SIZE);
+        trace!("Allocated {count} contiguous 4K frames at {pa} ({zone:?} request)");
+        Some(PhysicalPage::from_addr(pa))
+    }
+
+    /// Keep the records of `table` in sync with the allocator from now on.
+    ///
+    /// Records the current state first: unusable frames become
+    /// [`FrameFlags::RESERVED`], allocated ones [`FrameFlags::ALLOCATED`].
+    pub fn attach_frame_table(&mut self, table: &'static FrameTable) {
+        for idx in 0..self.num_frames.min(table.len()) {
+            let (word, bit) = (idx / 64, idx % 64);
+            let flags = if self.usable[word] & (1 << bit) == 0 {
+                FrameFlags::RESERVED
+            } else if self.bitmap[word] & (1 << bit) != 0 {
+                FrameFlags::ALLOCATED
+            } else {
+                FrameFlags::NONE
+            };
+            table.set_state(idx, flags);
+        }
+        self.frames = Some(table);
+    }
+
+    /// Publish statistics to `counters` from now on.
+    #[must_use]
+    pub fn with_counters(mut self, counters: &'static FrameCounters) -> Self {
+        counters.publish(&self.stats);
+        self.counters = Some(counters);
+        self
+    }
+
+    /// A snapshot of the current statistics.
+    #[must_use]
+    pub const fn stats(&self) -> FrameStats {
+        self.stats
+    }
+
+    /// Register `callback` to run when fewer than `threshold` frames are free.
+    ///
+    /// If that is already the case, the callback fires on the next allocation.
+    ///
+    /// # Errors
+    /// If [`MAX_LOW_MEMORY_WATCHES`] callbacks are registered already.
+    pub fn on_low_memory(
+        &mut self,
+        threshold: usize,
+        callback: LowMemoryCallback,
+    ) -> Result<(), TooManyWatches> {
+        let slot = self
+            .watches
+            .iter_mut()
+            .find(|w| w.is_none())
+            .ok_or(TooManyWatches)?;
+        *slot = Some(LowMemoryWatch {
+            threshold,
+            callback,
+            armed: true,
+        });
+        Ok(())
+    }
+
+    /// Bytes of physical memory the allocator covers, from address 0.
+    #[must_use]
     pub const fn manageable_size(&self) -> u64 {
-        (NUM_FRAMES as u64) * FRAME_SIZE
+        (self.num_frames as u64) * FRAME_SIZE
     }
 
     /// Mark a frame as used (allocated).
-    pub const fn mark_used(&mut self, frame_idx: usize) {
+    pub fn mark_used(&mut self, frame_idx: usize) {
         let (word, bit) = (frame_idx / 64, frame_idx % 64);
-        self.bitmap[word] |= 1 << bit;
+        if self.bitmap[word] & (1 << bit) == 0 {
+            self.bitmap[word] |= 1 << bit;
+            self.count_used(frame_idx);
+        }
     }
 
-    /// Mark a frame as free.
-    pub const fn mark_free(&mut self, frame_idx: usize) {
+    /// Mark a frame as free. Unusable frames are left untouched.
+    pub fn mark_free(&mut self, frame_idx: usize) {
         let (word, bit) = (frame_idx / 64, frame_idx % 64);
-        self.bitmap[word] &= !(1 << bit);
+        if self.bitmap[word] & self.usable[word] & (1 << bit) != 0 {
+            self.bitmap[word] &= !(1 << bit);
+            self.count_freed(frame_idx);
+        }
     }
 
     /// Returns true if the frame is allocated.
@@ -100,50 +550,128 @@ impl BitmapFrameAlloc {
         let (word, bit) = (frame_idx / 64, frame_idx % 64);
         (self.bitmap[word] & (1 << bit)) != 0
     }
+
+    const fn zone_of(&self, idx: usize) -> Zone {
+        Zone::of(self.base + (idx as u64) * FRAME_SIZE)
+    }
+
+    /// Index of the first free frame in `[lo, hi)`, if any.
+    fn find_free(&self, lo: usize, hi: usize) -> Option<usize> {
+        if lo >= hi {
+            return None;
+        }
+
+        for word in lo / 64..=(hi - 1) / 64 {
+            let mut candidates = !self.bitmap[word];
+            if word == lo / 64 {
+                candidates &= u64::MAX << (lo % 64);
+            }
+            if word == (hi - 1) / 64 && !hi.is_multiple_of(64) {
+                candidates &= (1 << (hi % 64)) - 1;
+            }
+            if candidates != 0 {
+                return Some(word * 64 + candidates.trailing_zeros() as usize);
+            }
+        }
+        None
+    }
+
+    /// Index of the first frame of `count` free ones in `[lo, hi)` whose
+    /// physical frame number is a multiple of `align`, if any.
+    fn find_free_run(&self, lo: usize, hi: usize, count: usize, align: usize) -> Option<usize> {
+        let base_frame = (self.base / FRAME_SIZE) as usize;
+        let mut run = 0;
+        for idx in lo..hi {
+            if self.is_used(idx) || (run == 0 && !(base_frame + idx).is_multiple_of(align)) {
+                run = 0;
+                continue;
+            }
+            run += 1;
+            if run == count {
+                return Some(idx + 1 - count);
+            }
+        }
+        None
+    }
+
+    fn count_used(&mut self, idx: usize) {
+        if let Some(table) = self.frames {
+            table.set_state(idx, FrameFlags::ALLOCATED);
+        }
+        self.zones[self.zone_of(idx) as usize].free -= 1;
+        self.stats.used += 1;
+        self.stats.free -= 1;
+        self.stats.min_free = self.stats.min_free.min(self.stats.free);
+        self.publish();
+
+        let stats = self.stats;
+        for watch in self.watches.iter_mut().flatten() {
+            if watch.armed && stats.free < watch.threshold {
+                watch.armed = false;
+                (watch.callback)(&stats);
+            }
+        }
+    }
+
+    fn count_freed(&mut self, idx: usize) {
+        if let Some(table) = self.frames {
+            table.set_state(idx, FrameFlags::NONE);
+        }
+        self.zones[self.zone_of(idx) as usize].free += 1;
+        self.stats.used -= 1;
+        self.stats.free += 1;
+        self.publish();
+
+        let free = self.stats.free;
+        for watch in self.watches.iter_mut().flatten() {
+            watch.armed |= free >= watch.threshold;
+        }
+    }
+
+    fn count_failure(&mut self) {
+        self.stats.failed_allocs += 1;
+        self.publish();
+    }
+
+    fn publish(&self) {
+        if let Some(counters) = self.counters {
+            counters.publish(&self.stats);
+        }
+    }
+}
+
+/// Frame index range `[lo, hi)` of `zone` within a region of `num_frames`
+/// frames.
+#[allow(clippy::cast_possible_truncation)]
+fn zone_frames(num_frames: usize, zone: Zone) -> (usize, usize) {
+    let clamp = |pa: u64| {
+        let idx = pa.saturating_sub(PHYS_MEM_START) / FRAME_SIZE;
+        if idx >= num_frames as u64 {
+            num_frames
+        } else {
+            idx as usize
+        }
+    };
+    (clamp(zone.start()), clamp(zone.end()))
 }
 
 impl PhysFrameAlloc for BitmapFrameAlloc {
-    /// Allocates a single 4 KiB physical frame.
+    /// Allocates a single 4 KiB physical frame from any zone.
     ///
-    /// This method iterates over the bitmap looking for the first free bit.
-    /// When it finds one, it:
-    /// 1. Marks the bit as used.
-    /// 2. Computes the corresponding physical address.
-    /// 3. Returns a [`PhysicalPage<Size4K>`] representing that frame.
+    /// Equivalent to [`alloc_4k_in(Zone::Normal)`](BitmapFrameAlloc::alloc_4k_in).
     ///
     /// # Returns
     /// - `Some(PhysicalPage<Size4K>)` if a free frame was found.
     /// - `None` if all frames are already allocated.
     fn alloc_4k(&mut self) -> Option<PhysicalPage<Size4K>> {
-        for (i, word) in self.bitmap.iter_mut().enumerate() {
-            if *word == u64::MAX {
-                continue;
-            }
-
-            for bit in 0..64 {
-                let idx = i * 64 + bit;
-                if idx >= NUM_FRAMES {
-                    break;
-                }
-
-                if (*word & (1 << bit)) != 0 {
-                    continue;
-                }
-
-                *word |= 1 << bit;
-                let pa = self.base + (idx as u64) * FRAME_SIZE;
-                let pa = PhysicalAddress::new(pa);
-                trace!("Allocated 4K frame at {pa}");
-                return Some(PhysicalPage::from_addr(pa));
-            }
-        }
-        None
+        self.alloc_4k_in(Zone::Normal)
     }
 
     /// Frees a 4 KiB physical frame.
     ///
     /// This method clears the corresponding bit in the bitmap,
     /// marking the frame as free and available for future allocations.
+    /// With a [`FrameTable`] attached, debug builds panic on double frees.
     ///
     /// # Arguments
     /// * `pa` - The physical page to free.
@@ -154,7 +682,304 @@ impl PhysFrameAlloc for BitmapFrameAlloc {
     /// - The frame is not in active use.
     fn free_4k(&mut self, pa: PhysicalPage<Size4K>) {
         trace!("Freeing 4K frame at {pa}");
+        if let Some(table) = self.frames {
+            table.check_free(pa);
+        }
         let idx = ((pa.base().as_u64() - self.base) / FRAME_SIZE) as usize;
         self.mark_free(idx);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::sync::atomic::AtomicUsize;
+
+    /// Frames of an allocator from [`pmm`].
+    #[allow(clippy::cast_possible_truncation)]
+    const FRAMES: usize = (DEFAULT_MANAGED / FRAME_SIZE) as usize;
+
+    /// An allocator for the first [`DEFAULT_MANAGED`] bytes.
+    fn pmm() -> BitmapFrameAlloc {
+        pmm_below(DEFAULT_MANAGED)
+    }
+
+    fn pmm_below(end: u64) -> BitmapFrameAlloc {
+        let words = BitmapFrameAlloc::storage_words(end);
+        BitmapFrameAlloc::new(Box::leak(vec![0; words].into_boxed_slice()))
+    }
+
+    #[test]
+    fn stats_track_alloc_and_free() {
+        let mut pmm = pmm();
+        let total = pmm.stats().total;
+        assert_eq!(pmm.stats().free, total);
+
+        let a = pmm.alloc_4k().unwrap();
+        let b = pmm.alloc_4k().unwrap();
+        assert_ne!(a, b);
+        assert_eq!(pmm.stats().used, 2);
+        assert_eq!(pmm.stats().free, total - 2);
+
+        pmm.free_4k(a);
+        let stats = pmm.stats();
+        assert_eq!(stats.used, 1);
+        assert_eq!(stats.min_free, total - 2);
+
+        // Double marks do not skew the counters.
+        let idx = 300;
+        pmm.mark_free(idx);
+        pmm.mark_free(idx);
+        pmm.mark_used(idx);
+        pmm.mark_used(idx);
+        assert_eq!(pmm.stats().used, 2);
+    }
+
+    #[test]
+    fn counters_mirror_stats() {
+        static COUNTERS: FrameCounters = FrameCounters::new();
+        let mut pmm = pmm().with_counters(&COUNTERS);
+        assert_eq!(COUNTERS.snapshot(), pmm.stats());
+
+        let frame = pmm.alloc_4k().unwrap();
+        assert_eq!(COUNTERS.snapshot().used, 1);
+        pmm.free_4k(frame);
+        assert_eq!(COUNTERS.snapshot(), pmm.stats());
+    }
+
+    #[test]
+    fn low_memory_callback_fires_once_per_crossing() {
+        static FIRED: AtomicUsize = AtomicUsize::new(0);
+        fn on_low(stats: &FrameStats) {
+            assert!(stats.free < stats.total - 2);
+            FIRED.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let mut pmm = pmm();
+        let total = pmm.stats().total;
+        pmm.on_low_memory(total - 2, on_low).unwrap();
+
+        let a = pmm.alloc_4k().unwrap();
+        let b = pmm.alloc_4k().unwrap();
+        assert_eq!(FIRED.load(Ordering::Relaxed), 0);
+
+        let c = pmm.alloc_4k().unwrap();
+        let d = pmm.alloc_4k().unwrap();
+        assert_eq!(FIRED.load(Ordering::Relaxed), 1, "fires only on crossing");
+
+        pmm.free_4k(d);
+        pmm.free_4k(c);
+        let _c = pmm.alloc_4k().unwrap();
+        assert_eq!(FIRED.load(Ordering::Relaxed), 2, "re-armed after recovery");
+
+        pmm.free_4k(a);
+        pmm.free_4k(b);
+    }
+
+    #[test]
+    fn watch_table_is_bounded() {
+        fn noop(_: &FrameStats) {}
+        let mut pmm = pmm();
+        for _ in 0..MAX_LOW_MEMORY_WATCHES {
+            pmm.on_low_memory(1, noop).unwrap();
+        }
+        assert_eq!(pmm.on_low_memory(1, noop), Err(TooManyWatches));
+    }
+
+    #[test]
+    fn default_region_skips_low_memory() {
+        let mut pmm = pmm();
+        assert_eq!(pmm.zone_stats(Zone::Below1M), ZoneStats::default());
+        assert!(pmm.alloc_4k_in(Zone::Below1M).is_none());
+
+        let frame = pmm.alloc_4k().unwrap();
+        assert!(frame.base().as_u64() >= 0x10_0000);
+    }
+
+    #[test]
+    fn memory_map_limits_usable_frames() {
+        let mut pmm = pmm();
+        pmm.reserve_all();
+        assert!(pmm.alloc_4k().is_none());
+
+        // Unaligned edges shrink inwards; frame 0 stays reserved.
+        pmm.add_usable_range(PhysicalAddress::new(0), PhysicalAddress::new(0x2800));
+        pmm.add_usable_range(
+            PhysicalAddress::new(0x20_0800),
+            PhysicalAddress::new(0x20_3000),
+        );
+        let stats = pmm.stats();
+        assert_eq!(stats.total, 1 + 2);
+        assert_eq!(stats.free, 3);
+        assert_eq!(pmm.zone_stats(Zone::Below1M).total, 1);
+        assert_eq!(pmm.zone_stats(Zone::Below16M).total, 2);
+
+        // Freeing an unusable frame has no effect.
+        pmm.mark_free(10);
+        assert_eq!(pmm.stats().free, 3);
+
+        let frames: Vec<_> = (0..3).map(|_| pmm.alloc_4k().unwrap()).collect();
+        assert!(pmm.alloc_4k().is_none());
+        for frame in &frames {
+            let pa = frame.base().as_u64();
+            assert!(
+                pa == 0x1000 || (0x20_1000..0x20_3000).contains(&pa),
+                "{pa:#x}"
+            );
+        }
+    }
+
+    #[test]
+    fn zone_requests_prefer_high_and_respect_limits() {
+        let mut pmm = pmm();
+        pmm.reserve_all();
+        pmm.add_usable_range(PhysicalAddress::new(0x8000), PhysicalAddress::new(0xA000));
+        pmm.add_usable_range(
+            PhysicalAddress::new(0x0200_0000),
+            PhysicalAddress::new(0x0200_1000),
+        );
+
+        // A normal request takes the frame above 16 MiB first ...
+        let high = pmm.alloc_4k().unwrap();
+        assert_eq!(high.base().as_u64(), 0x0200_0000);
+        assert_eq!(pmm.zone_stats(Zone::Below4G).free, 0);
+
+        // ... and only then falls back to low memory.
+        let low = pmm.alloc_4k().unwrap();
+        assert!(low.base().as_u64() < Zone::Below1M.end());
+
+        // A constrained request never goes above its limit.
+        pmm.free_4k(high);
+        let dma = pmm.alloc_4k_in(Zone::Below16M).unwrap();
+        assert!(dma.base().as_u64() < Zone::Below16M.end());
+        assert!(pmm.alloc_4k_in(Zone::Below16M).is_none());
+        assert_eq!(pmm.zone_stats(Zone::Below1M).free, 0);
+    }
+
+    fn attached_table() -> &'static FrameTable {
+        Box::leak(Box::new(FrameTable::new(Box::leak(Box::new_uninit_slice(
+            FRAMES,
+        )))))
+    }
+
+    #[test]
+    fn frame_table_follows_the_bitmap() {
+        let mut pmm = pmm();
+        pmm.reserve_all();
+        pmm.add_usable_range(
+            PhysicalAddress::new(0x20_0000),
+            PhysicalAddress::new(0x20_4000),
+        );
+        let early = pmm.alloc_4k().unwrap();
+
+        let table = attached_table();
+        pmm.attach_frame_table(table);
+        assert_eq!(table.flags(early), FrameFlags::ALLOCATED);
+        let reserved = PhysicalPage::from_addr(PhysicalAddress::new(0x1000));
+        assert_eq!(table.flags(reserved), FrameFlags::RESERVED);
+
+        let late = pmm.alloc_4k().unwrap();
+        assert_eq!(table.flags(late), FrameFlags::ALLOCATED);
+        pmm.free_4k(late);
+        pmm.free_4k(early);
+        assert_eq!(table.flags(late), FrameFlags::NONE);
+        assert_eq!(table.flags(early), FrameFlags::NONE);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "double free")]
+    fn frame_table_catches_double_free() {
+        let mut pmm = pmm();
+        pmm.attach_frame_table(attached_table());
+        let frame = pmm.alloc_4k().unwrap();
+        pmm.free_4k(frame);
+        pmm.free_4k(frame);
+    }
+
+    #[test]
+    fn contiguous_runs_skip_used_frames() {
+        let mut pmm = pmm();
+        pmm.reserve_all();
+        pmm.add_usable_range(
+            PhysicalAddress::new(0x20_0000),
+            PhysicalAddress::new(0x20_2000),
+        );
+        pmm.add_usable_range(
+            PhysicalAddress::new(0x30_0000),
+            PhysicalAddress::new(0x30_3000),
+        );
+
+        let run = pmm.alloc_contiguous_4k(3).unwrap();
+        assert_eq!(run.base().as_u64(), 0x30_0000);
+        assert_eq!(pmm.stats().used, 3);
+        assert!(pmm.alloc_contiguous_4k(3).is_none());
+        assert_eq!(pmm.stats().failed_allocs, 1);
+        assert_eq!(
+            pmm.alloc_contiguous_4k(2).unwrap().base().as_u64(),
+            0x20_0000
+        );
+    }
+
+    #[test]
+    fn contiguous_runs_respect_zone_and_alignment() {
+        let mut pmm = pmm();
+        pmm.reserve_all();
+        pmm.add_usable_range(
+            PhysicalAddress::new(0x20_1000),
+            PhysicalAddress::new(0x20_6000),
+        );
+        pmm.add_usable_range(
+            PhysicalAddress::new(0x100_0000),
+            PhysicalAddress::new(0x100_4000),
+        );
+
+        // The highest zone with room comes first, unless the request says
+        // otherwise.
+        let high = pmm.alloc_contiguous_4k_in(2, Zone::Below4G, 1).unwrap();
+        assert_eq!(high.base().as_u64(), 0x100_0000);
+        let low = pmm.alloc_contiguous_4k_in(2, Zone::Below16M, 1).unwrap();
+        assert_eq!(low.base().as_u64(), 0x20_1000);
+
+        // 0x20_3000 is free but not 16 KiB aligned; 0x20_4000 is.
+        let aligned = pmm.alloc_contiguous_4k_in(1, Zone::Below16M, 4).unwrap();
+        assert_eq!(aligned.base().as_u64(), 0x20_4000);
+        assert!(pmm.alloc_contiguous_4k_in(2, Zone::Below16M, 4).is_none());
+    }
+
+    #[test]
+    fn exhaustion_counts_failures() {
+        let mut pmm = pmm();
+        for idx in 0..FRAMES {
+            pmm.mark_used(idx);
+        }
+        assert!(pmm.alloc_4k().is_none());
+        let stats = pmm.stats();
+        assert_eq!(stats.free, 0);
+        assert_eq!(stats.failed_allocs, 1);
+    }
+
+    #[test]
+    fn storage_sizes_the_region() {
+        let mut pmm = pmm_below(6 << 30);
+        assert_eq!(pmm.manageable_size(), 6 << 30);
+        pmm.reserve_all();
+        pmm.add_usable_range(
+            PhysicalAddress::new(0x20_0000),
+            PhysicalAddress::new(0x20_1000),
+        );
+        // Reaches past the region, whose end cuts it short.
+        pmm.add_usable_range(
+            PhysicalAddress::new((6 << 30) - 0x1000),
+            PhysicalAddress::new(7 << 30),
+        );
+        assert_eq!(pmm.stats().total, 2);
+        assert_eq!(pmm.zone_stats(Zone::Normal).total, 1);
+
+        // Plain requests take memory above 4 GiB first.
+        let high = pmm.alloc_4k().unwrap();
+        assert_eq!(high.base().as_u64(), (6 << 30) - 0x1000);
+        let low = pmm.alloc_4k().unwrap();
+        assert_eq!(low.base().as_u64(), 0x20_0000);
+    }
+}
@@ -0,0 +1,413 @@
+//! # Per-Frame Metadata
+//!
+//! [`FrameTable`] keeps one [`FrameInfo`] record per physical 4 KiB frame,
+//! indexed by frame number. It is the kernel's equivalent of Linux's
+//! `struct page`: everything that has to be known about a frame beyond
+//! "free or used" lives here.
+//!
+//! ## Contents
+//!
+//! Each record holds
+//!
+//! * a **reference count** for frames shared between owners, e.g.
+//!   copy-on-write pages after a fork (see [Counting](#counting)),
+//! * [`FrameFlags`] maintained by the frame allocator: whether the frame is
+//!   allocated, or reserved by the memory map,
+//! * a [`FrameOwner`] tag saying what the frame was allocated for. The
+//!   allocator resets it to [`FrameOwner::Unknown`]; the user of the frame
+//!   may refine it with [`FrameTable::set_owner`].
+//!
+//! Records are four bytes of atomics, so the table can be read and updated
+//! without the allocator lock.
+//!
+//! ## Placement
+//!
+//! The table is sized from the memory map: [`FrameTable::entries_for`] gives
+//! the number of records needed to cover physical memory up to the highest
+//! usable address, [`FrameTable::bytes_for`] their size. The kernel carves
+//! that many bytes out of physical memory with
+//! [`BitmapFrameAlloc::alloc_contiguous_4k`] and hands the storage to
+//! [`FrameTable::new`] through the direct map.
+//!
+//! ## Maintenance
+//!
+//! [`BitmapFrameAlloc::attach_frame_table`] records the allocator's current
+//! state in the table and keeps the flags up to date from then on. In debug
+//! builds, freeing a frame that is not allocated (a double free) or that
+//! still has other owners panics.
+//!
+//! ## Counting
+//!
+//! A frame normally has exactly one owner: the address space (or kernel
+//! structure) it was allocated for. Copy-on-write fork breaks that rule, as
+//! parent and child map the same frame until one of them writes to it.
+//!
+//! The table stores the number of **additional** owners per frame, so a
+//! fresh record means "a single owner" and frames that are never shared need
+//! no bookkeeping:
+//!
+//! * [`share`](FrameTable::share) adds an owner, e.g. when a fork maps the
+//!   frame into the child.
+//! * [`release`](FrameTable::release) drops an owner and says whether it was
+//!   the last one, i.e. whether the caller must free the frame.
+//! * [`is_shared`](FrameTable::is_shared) tells a copy-on-write fault whether
+//!   it has to copy the frame or may simply take it over.
+//!
+//! Counts are atomic, so owners in different address spaces may race to
+//! release a frame; exactly one of them sees the last reference go.
+//!
+//! Frames beyond the end of the table cannot be shared and are always
+//! reported as singly owned.
+
+#[cfg(doc)]
+use crate::frame_alloc::BitmapFrameAlloc;
+use crate::frame_alloc::{FRAME_SIZE, PHYS_MEM_START};
+use core::fmt;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicU8, AtomicU16, Ordering};
+use kernel_memory_addresses::{PhysicalAddress, PhysicalPage, Size4K};
+
+/// State bits of a frame, maintained by the frame allocator.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct FrameFlags(u8);
+
+impl FrameFlags {
+    /// No flags: a free, usable frame.
+    pub const NONE: Self = Self(0);
+    /// The frame is handed out by the allocator.
+    pub const ALLOCATED: Self = Self(1 << 0);
+    /// The frame is not usable memory according to the memory map.
+    pub const RESERVED: Self = Self(1 << 1);
+
+    /// The raw bits.
+    #[must_use]
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    /// Whether all flags of `other` are set.
+    #[must_use]
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl fmt::Display for FrameFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = if self.contains(Self::RESERVED) {
+            "reserved"
+        } else if self.contains(Self::ALLOCATED) {
+            "allocated"
+        } else {
+            "free"
+        };
+        f.write_str(state)
+    }
+}
+
+/// What a frame was allocated for.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+#[repr(u8)]
+pub enum FrameOwner {
+    /// Not recorded (yet).
+    #[default]
+    Unknown = 0,
+    /// Kernel data, e.g. a heap or stack page.
+    Kernel = 1,
+    /// A page table of some address space.
+    PageTable = 2,
+    /// A page mapped into user space.
+    User = 3,
+    /// Storage of the [`FrameTable`] itself.
+    FrameTable = 4,
+    /// A page of file or block device contents in the page cache.
+    PageCache = 5,
+}
+
+impl FrameOwner {
+    const fn from_bits(bits: u8) -> Self {
+        match bits {
+            1 => Self::Kernel,
+            2 => Self::PageTable,
+            3 => Self::User,
+            4 => Self::FrameTable,
+            5 => Self::PageCache,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// Metadata of one physical frame; see the [module docs](self).
+pub struct FrameInfo {
+    /// Number of owners beyond the first.
+    extra: AtomicU16,
+    flags: AtomicU8,
+    owner: AtomicU8,
+}
+
+impl FrameInfo {
+    /// The record of a free, unshared frame.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            extra: AtomicU16::new(0),
+            flags: AtomicU8::new(0),
+            owner: AtomicU8::new(FrameOwner::Unknown as u8),
+        }
+    }
+
+    /// Number of owners (at least 1).
+    #[must_use]
+    pub fn owners(&self) -> usize {
+        usize::from(self.extra.load(Ordering::Acquire)) + 1
+    }
+
+    /// Allocator state of the frame.
+    #[must_use]
+    pub fn flags(&self) -> FrameFlags {
+        FrameFlags(self.flags.load(Ordering::Acquire))
+    }
+
+    /// What the frame was allocated for.
+    #[must_use]
+    pub fn owner(&self) -> FrameOwner {
+        FrameOwner::from_bits(self.owner.load(Ordering::Acquire))
+    }
+}
+
+impl Default for FrameInfo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for FrameInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{flags}, owner {owner:?}, {owners} owner(s)",
+            flags = self.flags(),
+            owner = self.owner(),
+            owners = self.owners()
+        )
+    }
+}
+
+/// Physically indexed array of [`FrameInfo`] records; see the [module docs](self).
+pub struct FrameTable {
+    frames: &'static [FrameInfo],
+}
+
+impl FrameTable {
+    /// A table without records; every lookup misses.
+    #[must_use]
+    pub const fn empty() -> Self {
+        Self { frames: &[] }
+    }
+
+    /// Initialize `storage` to records of free, unshared frames.
+    ///
+    /// Record `i` describes the frame at physical address `i * 4096`.
+    #[must_use]
+    pub fn new(storage: &'static mut [MaybeUninit<FrameInfo>]) -> Self {
+        for slot in storage.iter_mut() {
+            slot.write(FrameInfo::new());
+        }
+        // Safety: every element was initialized above.
+        let frames = unsafe { &*(core::ptr::from_mut(storage) as *const [FrameInfo]) };
+        Self { frames }
+    }
+
+    /// Number of records needed to describe physical memory below `end`.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn entries_for(end: PhysicalAddress) -> usize {
+        (end.as_u64().saturating_sub(PHYS_MEM_START)).div_ceil(FRAME_SIZE) as usize
+    }
+
+    /// Size in bytes of a table with `entries` records.
+    #[must_use]
+    pub const fn bytes_for(entries: usize) -> u64 {
+        (entries * size_of::<FrameInfo>()) as u64
+    }
+
+    /// Number of frames described by the table.
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Whether the table describes no frames.
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// The record of `frame`, if the table covers it.
+    #[must_use]
+    pub fn get(&self, frame: PhysicalPage<Size4K>) -> Option<&FrameInfo> {
+        let offset = frame.base().as_u64().checked_sub(PHYS_MEM_START)?;
+        let idx = usize::try_from(offset / FRAME_SIZE).ok()?;
+        self.frames.get(idx)
+    }
+
+    /// Number of owners of `frame` (at least 1).
+    #[must_use]
+    pub fn owners(&self, frame: PhysicalPage<Size4K>) -> usize {
+        self.get(frame).map_or(1, FrameInfo::owners)
+    }
+
+    /// Whether `frame` has more than one owner.
+    #[must_use]
+    pub fn is_shared(&self, frame: PhysicalPage<Size4K>) -> bool {
+        self.owners(frame) > 1
+    }
+
+    /// Add an owner to `frame`.
+    ///
+    /// # Panics
+    /// If the table does not cover `frame`, or it has `u16::MAX + 1` owners.
+    pub fn share(&self, frame: PhysicalPage<Size4K>) {
+        let info = self.get(frame).expect("sharing an untracked frame");
+        let prev = info.extra.fetch_add(1, Ordering::AcqRel);
+        assert_ne!(prev, u16::MAX, "too many owners of frame {frame}");
+    }
+
+    /// Drop an owner of `frame`. Returns `true` if it was the last one and the
+    /// frame should be freed.
+    #[must_use]
+    pub fn release(&self, frame: PhysicalPage<Size4K>) -> bool {
+        let Some(info) = self.get(frame) else {
+            return true;
+        };
+        info.extra
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |extra| {
+                extra.checked_sub(1)
+            })
+            .is_err()
+    }
+
+    /// Allocator state of `frame`; frames beyond the table are reported as
+    /// [`FrameFlags::RESERVED`].
+    #[must_use]
+    pub fn flags(&self, frame: PhysicalPage<Size4K>) -> FrameFlags {
+        self.get(frame)
+            .map_or(FrameFlags::RESERVED, FrameInfo::flags)
+    }
+
+    /// What `frame` was allocated for.
+    #[must_use]
+    pub fn owner(&self, frame: PhysicalPage<Size4K>) -> FrameOwner {
+        self.get(frame)
+            .map_or(FrameOwner::Unknown, FrameInfo::owner)
+    }
+
+    /// Record what `frame` is used for. Ignored for frames beyond the table.
+    pub fn set_owner(&self, frame: PhysicalPage<Size4K>, owner: FrameOwner) {
+        if let Some(info) = self.get(frame) {
+            info.owner.store(owner as u8, Ordering::Release);
+        }
+    }
+
+    /// Set the allocator state of frame number `idx`, resetting its owner.
+    pub(crate) fn set_state(&self, idx: usize, flags: FrameFlags) {
+        if let Some(info) = self.frames.get(idx) {
+            info.owner
+                .store(FrameOwner::Unknown as u8, Ordering::Relaxed);
+            info.flags.store(flags.bits(), Ordering::Release);
+        }
+    }
+
+    /// Check that `frame` may be returned to the allocator.
+    ///
+    /// # Panics
+    /// In debug builds, if `frame` is not allocated or still shared.
+    pub(crate) fn check_free(&self, frame: PhysicalPage<Size4K>) {
+        if let Some(info) = self.get(frame) {
+            debug_assert!(
+                info.flags().contains(FrameFlags::ALLOCATED),
+                "double free of frame {frame} ({info})"
+            );
+            debug_assert_eq!(info.owners(), 1, "freeing shared frame {frame}");
+        }
+    }
+}
+
+impl Default for FrameTable {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(entries: usize) -> FrameTable {
+        FrameTable::new(Box::leak(Box::new_uninit_slice(entries)))
+    }
+
+    fn frame(addr: u64) -> PhysicalPage<Size4K> {
+        PhysicalPage::from_addr(PhysicalAddress::new(addr))
+    }
+
+    #[test]
+    fn sized_from_the_highest_address() {
+        assert_eq!(FrameTable::entries_for(PhysicalAddress::new(0)), 0);
+        assert_eq!(FrameTable::entries_for(PhysicalAddress::new(0x1001)), 2);
+        assert_eq!(FrameTable::bytes_for(1024), 4096);
+    }
+
+    #[test]
+    fn unshared_frames_have_one_owner() {
+        let table = table(0x400);
+        let f = frame(0x10_0000);
+        assert_eq!(table.owners(f), 1);
+        assert!(!table.is_shared(f));
+        assert!(table.release(f));
+        assert_eq!(table.owners(f), 1);
+    }
+
+    #[test]
+    fn last_release_frees() {
+        let table = table(0x400);
+        let f = frame(0x20_0000);
+        table.share(f);
+        table.share(f);
+        assert_eq!(table.owners(f), 3);
+        assert!(table.is_shared(f));
+
+        assert!(!table.release(f));
+        assert!(!table.release(f));
+        assert!(!table.is_shared(f));
+        assert!(table.release(f));
+    }
+
+    #[test]
+    fn frames_outside_the_table_are_never_shared() {
+        let table = table(0x10);
+        let f = frame(0x10_0000);
+        assert_eq!(table.owners(f), 1);
+        assert!(table.release(f));
+        assert_eq!(table.flags(f), FrameFlags::RESERVED);
+        table.set_owner(f, FrameOwner::User);
+        assert_eq!(table.owner(f), FrameOwner::Unknown);
+    }
+
+    #[test]
+    fn state_changes_reset_the_owner() {
+        let table = table(0x10);
+        let f = frame(0x3000);
+        table.set_state(3, FrameFlags::ALLOCATED);
+        table.set_owner(f, FrameOwner::PageTable);
+        assert_eq!(table.owner(f), FrameOwner::PageTable);
+        assert_eq!(
+            table.get(f).unwrap().to_string(),
+            "allocated, owner PageTable, 1 owner(s)"
+        );
+
+        table.set_state(3, FrameFlags::NONE);
+        assert_eq!(table.owner(f), FrameOwner::Unknown);
+        assert_eq!(table.flags(f), FrameFlags::NONE);
+    }
+}
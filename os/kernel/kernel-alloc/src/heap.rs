@@ -0,0 +1,858 @@
+//! # Slab Heap
+//!
+//! A general-purpose heap with segregated size classes. Small allocations
+//! come from per-class free lists, so that allocating and freeing take
+//! constant time no matter how many blocks are live; large ones go straight
+//! to the [`HeapBackend`].
+//!
+//! ## Size classes
+//!
+//! A request of up to [`MAX_CLASS_SIZE`] bytes is served from the smallest of
+//! the [`CLASS_SIZES`] that holds both its size and its alignment. Classes are
+//! powers of two carved out of page-aligned slabs of [`SLAB_SIZE`] bytes, so
+//! every block is aligned to its class size. Larger requests, and requests
+//! aligned to more than a page, are [large](HeapBackend::alloc_large).
+//!
+//! Slabs are never returned to the backend: a freed block goes back on the
+//! free list of its class and only ever serves that class again.
+//!
+//! ## Per-CPU caches
+//!
+//! Every CPU keeps up to [`CACHE_BLOCKS`] free blocks per class in a front-end
+//! cache that only it uses, so that most allocations and frees take an
+//! uncontended lock. An empty cache takes [`CACHE_BATCH`] blocks from the
+//! shared free list of the class, carving a new slab if that is empty too; a
+//! full cache gives [`CACHE_BATCH`] blocks back. A CPU index at or above the
+//! heap's `CPUS` bypasses the caches.
+//!
+//! The heap does not disable interrupts. If an interrupt handler may
+//! allocate, the caller must keep interrupts off around every heap call, or
+//! a handler interrupting the same CPU spins on its cache forever.
+//!
+//! ## Call-site accounting
+//!
+//! With the `heap-accounting` feature (and for this crate's tests), the heap
+//! counts the live bytes and blocks of up to [`MAX_SITES`] call sites; see
+//! [`SlabHeap::sites`]. The site of an allocation is the caller of
+//! [`SlabHeap::alloc`], as reported by [`Location::caller`]; through the
+//! [`GlobalAlloc`] implementation this is code in the `alloc` crate, such as
+//! `raw_vec.rs`, unless every caller up to the interesting one carries
+//! `#[track_caller]`. Every allocation then carries a small header naming its
+//! site, so the feature costs memory as well as time.
+//!
+//! ## Example
+//!
+//! ```rust
+//! use core::alloc::Layout;
+//! use core::ptr::NonNull;
+//! use kernel_alloc::heap::{HeapBackend, SLAB_SIZE, SlabHeap};
+//! use std::alloc::System;
+//! # use std::alloc::GlobalAlloc;
+//!
+//! struct Host;
+//!
+//! unsafe impl HeapBackend for Host {
+//!     fn alloc_slab(&self) -> Option<NonNull<u8>> {
+//!         let layout = Layout::from_size_align(SLAB_SIZE, 4096).unwrap();
+//!         NonNull::new(unsafe { System.alloc(layout) })
+//!     }
+//!     fn alloc_large(&self, layout: Layout) -> Option<NonNull<u8>> {
+//!         NonNull::new(unsafe { System.alloc(layout) })
+//!     }
+//!     unsafe fn free_large(&self, ptr: NonNull<u8>, layout: Layout) {
+//!         unsafe { System.dealloc(ptr.as_ptr(), layout) }
+//!     }
+//!     fn cpu(&self) -> usize {
+//!         0
+//!     }
+//! }
+//!
+//! let heap: SlabHeap<Host, 1> = SlabHeap::new(Host);
+//! let layout = Layout::from_size_align(24, 8).unwrap();
+//! let block = heap.alloc(layout);
+//! unsafe { heap.dealloc(block, layout) };
+//! assert_eq!(heap.alloc(layout), block);
+//! ```
+
+use core::alloc::{GlobalAlloc, Layout};
+#[cfg(any(test, feature = "heap-accounting"))]
+use core::panic::Location;
+use core::ptr::{self, NonNull};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use kernel_sync::SpinMutex;
+
+/// Number of size classes.
+pub const CLASSES: usize = 9;
+
+/// Block sizes of the size classes, in bytes.
+pub const CLASS_SIZES: [usize; CLASSES] = [16, 32, 64, 128, 256, 512, 1024, 2048, 4096];
+
+/// Largest request served from a size class.
+pub const MAX_CLASS_SIZE: usize = CLASS_SIZES[CLASSES - 1];
+
+/// Bytes the heap takes from its backend at a time for the size classes.
+pub const SLAB_SIZE: usize = 16 * 1024;
+
+/// Alignment of a slab.
+pub const SLAB_ALIGN: usize = 4096;
+
+/// Most free blocks a per-CPU cache keeps per class.
+pub const CACHE_BLOCKS: usize = 32;
+
+/// Blocks moved between a per-CPU cache and the shared free lists at once.
+pub const CACHE_BATCH: usize = CACHE_BLOCKS / 2;
+
+const _: () = assert!(MAX_CLASS_SIZE <= SLAB_ALIGN && SLAB_SIZE.is_multiple_of(SLAB_ALIGN));
+
+/// Where a [`SlabHeap`] gets its memory.
+///
+/// # Safety
+/// Returned memory must be valid for reads and writes, belong to nobody
+/// else until it is freed, and be aligned as documented on each method.
+pub unsafe trait HeapBackend {
+    /// [`SLAB_SIZE`] bytes aligned to [`SLAB_ALIGN`], kept for good.
+    fn alloc_slab(&self) -> Option<NonNull<u8>>;
+
+    /// Memory for a request too large for the size classes.
+    fn alloc_large(&self, layout: Layout) -> Option<NonNull<u8>>;
+
+    /// Free memory from [`alloc_large`](Self::alloc_large).
+    ///
+    /// # Safety
+    /// `ptr` came from `alloc_large(layout)` and is not used afterwards.
+    unsafe fn free_large(&self, ptr: NonNull<u8>, layout: Layout);
+
+    /// Index of the CPU making the call, for its per-CPU cache.
+    fn cpu(&self) -> usize;
+}
+
+/// The size class serving `layout`, or `None` if it is large.
+#[must_use]
+pub const fn class_of(layout: Layout) -> Option<usize> {
+    let size = if layout.size() > layout.align() {
+        layout.size()
+    } else {
+        layout.align()
+    };
+    if size > MAX_CLASS_SIZE {
+        return None;
+    }
+    let size = if size < CLASS_SIZES[0] {
+        CLASS_SIZES[0]
+    } else {
+        size.next_power_of_two()
+    };
+    Some((size.trailing_zeros() - CLASS_SIZES[0].trailing_zeros()) as usize)
+}
+
+/// Heap counters; see [`SlabHeap::stats`].
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct HeapStats {
+    /// Bytes of class blocks handed out, counted at their class size.
+    pub small_bytes: usize,
+    /// Bytes of live large allocations, as requested.
+    pub large_bytes: usize,
+    /// Bytes taken from the backend as slabs.
+    pub slab_bytes: usize,
+    /// Successful allocations.
+    pub allocations: usize,
+    /// Frees.
+    pub frees: usize,
+    /// Allocations that failed for lack of memory.
+    pub failures: usize,
+    /// Batches a per-CPU cache took from the shared free lists.
+    pub refills: usize,
+    /// Batches a per-CPU cache gave back to the shared free lists.
+    pub flushes: usize,
+}
+
+struct Counters {
+    small_bytes: AtomicUsize,
+    large_bytes: AtomicUsize,
+    slab_bytes: AtomicUsize,
+    allocations: AtomicUsize,
+    frees: AtomicUsize,
+    failures: AtomicUsize,
+    refills: AtomicUsize,
+    flushes: AtomicUsize,
+}
+
+impl Counters {
+    const fn new() -> Self {
+        Self {
+            small_bytes: AtomicUsize::new(0),
+            large_bytes: AtomicUsize::new(0),
+            slab_bytes: AtomicUsize::new(0),
+            allocations: AtomicUsize::new(0),
+            frees: AtomicUsize::new(0),
+            failures: AtomicUsize::new(0),
+            refills: AtomicUsize::new(0),
+            flushes: AtomicUsize::new(0),
+        }
+    }
+
+    fn add(counter: &AtomicUsize, n: usize) {
+        counter.fetch_add(n, Ordering::Relaxed);
+    }
+
+    fn sub(counter: &AtomicUsize, n: usize) {
+        counter.fetch_sub(n, Ordering::Relaxed);
+    }
+}
+
+/// A free block, holding the link to the next one.
+struct FreeBlock {
+    next: *mut Self,
+}
+
+/// An intrusive singly linked list of free blocks of one class.
+struct FreeList {
+    head: *mut FreeBlock,
+    len: usize,
+}
+
+// SAFETY: The blocks belong to the heap; the list is only reached through
+// the lock around it.
+unsafe impl Send for FreeList {}
+
+impl FreeList {
+    const EMPTY: Self = Self {
+        head: ptr::null_mut(),
+        len: 0,
+    };
+
+    /// Add a block.
+    ///
+    /// # Safety
+    /// `block` is a free block of this list's class.
+    const unsafe fn push(&mut self, block: NonNull<u8>) {
+        #[allow(clippy::cast_ptr_alignment)]
+        let block = block.as_ptr().cast::<FreeBlock>();
+        // SAFETY: Free blocks are at least 16 bytes, aligned to their size.
+        unsafe { block.write(FreeBlock { next: self.head }) };
+        self.head = block;
+        self.len += 1;
+    }
+
+    fn pop(&mut self) -> Option<NonNull<u8>> {
+        let block = NonNull::new(self.head)?;
+        // SAFETY: Blocks on the list were written by `push`.
+        self.head = unsafe { block.as_ref().next };
+        self.len -= 1;
+        Some(block.cast())
+    }
+
+    /// Move up to `n` blocks to `other`.
+    fn move_to(&mut self, other: &mut Self, n: usize) -> usize {
+        let mut moved = 0;
+        while moved < n
+            && let Some(block) = self.pop()
+        {
+            // SAFETY: Both lists hold blocks of the same class.
+            unsafe { other.push(block) };
+            moved += 1;
+        }
+        moved
+    }
+}
+
+/// One CPU's front-end cache.
+struct CpuCache {
+    lists: [FreeList; CLASSES],
+}
+
+/// A heap of size-class slabs over a [`HeapBackend`], with front-end caches
+/// for `CPUS` CPUs; see the [module docs](self).
+pub struct SlabHeap<B, const CPUS: usize> {
+    backend: B,
+    shared: [SpinMutex<FreeList>; CLASSES],
+    caches: [SpinMutex<CpuCache>; CPUS],
+    counters: Counters,
+    #[cfg(any(test, feature = "heap-accounting"))]
+    sites: accounting::Sites,
+}
+
+impl<B, const CPUS: usize> SlabHeap<B, CPUS> {
+    /// An empty heap taking its memory from `backend`.
+    pub const fn new(backend: B) -> Self {
+        Self {
+            backend,
+            shared: [const { SpinMutex::new(FreeList::EMPTY) }; CLASSES],
+            caches: [const {
+                SpinMutex::new(CpuCache {
+                    lists: [FreeList::EMPTY; CLASSES],
+                })
+            }; CPUS],
+            counters: Counters::new(),
+            #[cfg(any(test, feature = "heap-accounting"))]
+            sites: accounting::Sites::new(),
+        }
+    }
+
+    /// The backend.
+    pub const fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// A snapshot of the counters.
+    pub fn stats(&self) -> HeapStats {
+        let c = &self.counters;
+        HeapStats {
+            small_bytes: c.small_bytes.load(Ordering::Relaxed),
+            large_bytes: c.large_bytes.load(Ordering::Relaxed),
+            slab_bytes: c.slab_bytes.load(Ordering::Relaxed),
+            allocations: c.allocations.load(Ordering::Relaxed),
+            frees: c.frees.load(Ordering::Relaxed),
+            failures: c.failures.load(Ordering::Relaxed),
+            refills: c.refills.load(Ordering::Relaxed),
+            flushes: c.flushes.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl<B: HeapBackend, const CPUS: usize> SlabHeap<B, CPUS> {
+    /// Allocate memory for `layout`; null if there is none.
+    #[track_caller]
+    pub fn alloc(&self, layout: Layout) -> *mut u8 {
+        #[cfg(any(test, feature = "heap-accounting"))]
+        return self.alloc_tracked(layout, Location::caller());
+        #[cfg(not(any(test, feature = "heap-accounting")))]
+        self.alloc_untracked(layout)
+    }
+
+    /// Free memory from [`alloc`](Self::alloc).
+    ///
+    /// # Safety
+    /// `ptr` came from `alloc(layout)` on this heap and is not used afterwards.
+    pub unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // SAFETY: As required of the caller.
+        #[cfg(any(test, feature = "heap-accounting"))]
+        unsafe {
+            self.dealloc_tracked(ptr, layout);
+        }
+        // SAFETY: As required of the caller.
+        #[cfg(not(any(test, feature = "heap-accounting")))]
+        unsafe {
+            self.dealloc_untracked(ptr, layout);
+        }
+    }
+
+    fn alloc_untracked(&self, layout: Layout) -> *mut u8 {
+        let block = class_of(layout).map_or_else(
+            || {
+                let block = self.backend.alloc_large(layout)?;
+                Counters::add(&self.counters.large_bytes, layout.size());
+                Some(block)
+            },
+            |class| self.alloc_small(class),
+        );
+        let Some(block) = block else {
+            Counters::add(&self.counters.failures, 1);
+            return ptr::null_mut();
+        };
+        Counters::add(&self.counters.allocations, 1);
+        block.as_ptr()
+    }
+
+    /// # Safety
+    /// As for [`dealloc`](Self::dealloc).
+    unsafe fn dealloc_untracked(&self, ptr: *mut u8, layout: Layout) {
+        let Some(block) = NonNull::new(ptr) else {
+            return;
+        };
+        Counters::add(&self.counters.frees, 1);
+        if let Some(class) = class_of(layout) {
+            // SAFETY: `block` is a block of `class`, handed out by `alloc_small`.
+            unsafe { self.free_small(class, block) };
+        } else {
+            Counters::sub(&self.counters.large_bytes, layout.size());
+            // SAFETY: Large blocks come from the backend.
+            unsafe { self.backend.free_large(block, layout) };
+        }
+    }
+
+    fn alloc_small(&self, class: usize) -> Option<NonNull<u8>> {
+        let block = if let Some(cache) = self.caches.get(self.backend.cpu()) {
+            let mut cache = cache.lock();
+            let list = &mut cache.lists[class];
+            if list.len == 0 {
+                self.refill(class, list);
+            }
+            list.pop()
+        } else {
+            let mut shared = self.shared[class].lock();
+            if shared.len == 0 {
+                self.carve_slab(class, &mut shared);
+            }
+            shared.pop()
+        }?;
+        Counters::add(&self.counters.small_bytes, CLASS_SIZES[class]);
+        Some(block)
+    }
+
+    /// # Safety
+    /// `block` is a block of `class` from [`alloc_small`](Self::alloc_small).
+    unsafe fn free_small(&self, class: usize, block: NonNull<u8>) {
+        Counters::sub(&self.counters.small_bytes, CLASS_SIZES[class]);
+        if let Some(cache) = self.caches.get(self.backend.cpu()) {
+            let mut cache = cache.lock();
+            let list = &mut cache.lists[class];
+            // SAFETY: As required of the caller.
+            unsafe { list.push(block) };
+            if list.len > CACHE_BLOCKS {
+                list.move_to(&mut self.shared[class].lock(), CACHE_BATCH);
+                Counters::add(&self.counters.flushes, 1);
+            }
+        } else {
+            // SAFETY: As required of the caller.
+            unsafe { self.shared[class].lock().push(block) };
+        }
+    }
+
+    /// Move a batch of free blocks of `class` into an empty per-CPU `list`.
+    fn refill(&self, class: usize, list: &mut FreeList) {
+        let mut shared = self.shared[class].lock();
+        if shared.len < CACHE_BATCH {
+            self.carve_slab(class, &mut shared);
+        }
+        if shared.move_to(list, CACHE_BATCH) > 0 {
+            Counters::add(&self.counters.refills, 1);
+        }
+    }
+
+    /// Cut a new slab into blocks of `class` and put them on `list`.
+    fn carve_slab(&self, class: usize, list: &mut FreeList) {
+        let Some(slab) = self.backend.alloc_slab() else {
+            return;
+        };
+        Counters::add(&self.counters.slab_bytes, SLAB_SIZE);
+        let size = CLASS_SIZES[class];
+        // Pushed from the end, so that blocks are handed out in address order.
+        for offset in (0..SLAB_SIZE).step_by(size).rev() {
+            // SAFETY: The slab is ours and `offset` stays inside it; blocks
+            // are aligned to `size` as the slab is aligned to a page.
+            unsafe { list.push(slab.add(offset)) };
+        }
+    }
+}
+
+// SAFETY: Memory comes from `SlabHeap`, which hands every block out once.
+unsafe impl<B: HeapBackend, const CPUS: usize> GlobalAlloc for SlabHeap<B, CPUS> {
+    #[track_caller]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        Self::alloc(self, layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // SAFETY: As required of the caller.
+        unsafe { Self::dealloc(self, ptr, layout) }
+    }
+}
+
+#[cfg(any(test, feature = "heap-accounting"))]
+pub use accounting::{MAX_SITES, SiteStats};
+
+#[cfg(any(test, feature = "heap-accounting"))]
+mod accounting {
+    use super::{HeapBackend, SlabHeap};
+    use core::alloc::Layout;
+    use core::panic::Location;
+    use core::sync::atomic::{AtomicPtr, AtomicU32, AtomicUsize, Ordering};
+    use kernel_sync::SpinMutex;
+
+    /// Number of call sites the heap tells apart.
+    pub const MAX_SITES: usize = 64;
+
+    /// Bytes in front of every allocation; the site index sits in its last
+    /// word.
+    const HEADER: usize = 16;
+
+    /// Site index of allocations from sites beyond [`MAX_SITES`].
+    const UNTRACKED: usize = usize::MAX;
+
+    /// Live allocations of one call site.
+    #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+    pub struct SiteStats {
+        pub file: &'static str,
+        pub line: u32,
+        /// Requested bytes still allocated.
+        pub live_bytes: usize,
+        /// Allocations not yet freed.
+        pub live_blocks: usize,
+    }
+
+    /// One site; `file` is null while the slot is free.
+    struct Site {
+        file: AtomicPtr<u8>,
+        len: AtomicUsize,
+        line: AtomicU32,
+        live_bytes: AtomicUsize,
+        live_blocks: AtomicUsize,
+    }
+
+    impl Site {
+        const fn new() -> Self {
+            Self {
+                file: AtomicPtr::new(core::ptr::null_mut()),
+                len: AtomicUsize::new(0),
+                line: AtomicU32::new(0),
+                live_bytes: AtomicUsize::new(0),
+                live_blocks: AtomicUsize::new(0),
+            }
+        }
+
+        fn file(&self) -> Option<&'static str> {
+            let file = self.file.load(Ordering::Acquire);
+            if file.is_null() {
+                return None;
+            }
+            let len = self.len.load(Ordering::Relaxed);
+            // SAFETY: `index` stored the parts of a `&'static str` before
+            // publishing `file`.
+            Some(unsafe { core::str::from_utf8_unchecked(core::slice::from_raw_parts(file, len)) })
+        }
+
+        fn is(&self, site: &Location<'static>) -> bool {
+            self.file().is_some_and(|file| file == site.file())
+                && self.line.load(Ordering::Relaxed) == site.line()
+        }
+    }
+
+    pub struct Sites {
+        sites: [Site; MAX_SITES],
+        /// Serializes claiming free slots.
+        claim: SpinMutex<()>,
+    }
+
+    impl Sites {
+        pub const fn new() -> Self {
+            Self {
+                sites: [const { Site::new() }; MAX_SITES],
+                claim: SpinMutex::new(()),
+            }
+        }
+
+        /// The slot of `site`, claiming a free one if needed.
+        fn index(&self, site: &Location<'static>) -> usize {
+            if let Some(index) = self.sites.iter().position(|s| s.is(site)) {
+                return index;
+            }
+            let _claim = self.claim.lock();
+            for (index, slot) in self.sites.iter().enumerate() {
+                if slot.is(site) {
+                    return index;
+                }
+                if slot.file().is_none() {
+                    slot.len.store(site.file().len(), Ordering::Relaxed);
+                    slot.line.store(site.line(), Ordering::Relaxed);
+                    slot.file
+                        .store(site.file().as_ptr().cast_mut(), Ordering::Release);
+                    return index;
+                }
+            }
+            UNTRACKED
+        }
+
+        fn account(&self, index: usize, bytes: usize, alloc: bool) {
+            let Some(site) = self.sites.get(index) else {
+                return;
+            };
+            if alloc {
+                site.live_bytes.fetch_add(bytes, Ordering::Relaxed);
+                site.live_blocks.fetch_add(1, Ordering::Relaxed);
+            } else {
+                site.live_bytes.fetch_sub(bytes, Ordering::Relaxed);
+                site.live_blocks.fetch_sub(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// The layout of an allocation with its header, and the offset of the
+    /// memory handed out.
+    fn with_header(layout: Layout) -> Option<(Layout, usize)> {
+        let align = layout.align().max(align_of::<usize>());
+        let offset = align.max(HEADER);
+        let size = layout.size().checked_add(offset)?;
+        Some((Layout::from_size_align(size, align).ok()?, offset))
+    }
+
+    impl<B, const CPUS: usize> SlabHeap<B, CPUS> {
+        /// Hand the live allocations of every call site seen so far to `f`.
+        pub fn sites(&self, mut f: impl FnMut(SiteStats)) {
+            for site in &self.sites.sites {
+                let Some(file) = site.file() else {
+                    break;
+                };
+                f(SiteStats {
+                    file,
+                    line: site.line.load(Ordering::Relaxed),
+                    live_bytes: site.live_bytes.load(Ordering::Relaxed),
+                    live_blocks: site.live_blocks.load(Ordering::Relaxed),
+                });
+            }
+        }
+    }
+
+    impl<B: HeapBackend, const CPUS: usize> SlabHeap<B, CPUS> {
+        pub(super) fn alloc_tracked(&self, layout: Layout, site: &Location<'static>) -> *mut u8 {
+            let Some((inner, offset)) = with_header(layout) else {
+                return core::ptr::null_mut();
+            };
+            let block = self.alloc_untracked(inner);
+            if block.is_null() {
+                return block;
+            }
+            let index = self.sites.index(site);
+            self.sites.account(index, layout.size(), true);
+            // SAFETY: The header lies in front of the memory handed out, in
+            // the same block, and is aligned to a word.
+            #[allow(clippy::cast_ptr_alignment)]
+            unsafe {
+                let user = block.add(offset);
+                user.cast::<usize>().sub(1).write(index);
+                user
+            }
+        }
+
+        /// # Safety
+        /// As for [`dealloc`](SlabHeap::dealloc).
+        pub(super) unsafe fn dealloc_tracked(&self, ptr: *mut u8, layout: Layout) {
+            if ptr.is_null() {
+                return;
+            }
+            let Some((inner, offset)) = with_header(layout) else {
+                return;
+            };
+            // SAFETY: `alloc_tracked` wrote the header in front of `ptr`.
+            #[allow(clippy::cast_ptr_alignment)]
+            let (block, index) = unsafe { (ptr.sub(offset), ptr.cast::<usize>().sub(1).read()) };
+            self.sites.account(index, layout.size(), false);
+            // SAFETY: `block` came from `alloc_untracked(inner)`.
+            unsafe { self.dealloc_untracked(block, inner) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::alloc::System;
+    use std::cell::Cell;
+
+    std::thread_local! {
+        static CPU: Cell<usize> = const { Cell::new(0) };
+    }
+
+    /// Host memory; the CPU index is per thread.
+    #[derive(Default)]
+    struct Host {
+        slabs: AtomicUsize,
+        large: AtomicUsize,
+    }
+
+    unsafe impl HeapBackend for Host {
+        fn alloc_slab(&self) -> Option<NonNull<u8>> {
+            self.slabs.fetch_add(1, Ordering::Relaxed);
+            let layout = Layout::from_size_align(SLAB_SIZE, SLAB_ALIGN).unwrap();
+            NonNull::new(unsafe { System.alloc(layout) })
+        }
+
+        fn alloc_large(&self, layout: Layout) -> Option<NonNull<u8>> {
+            self.large.fetch_add(1, Ordering::Relaxed);
+            NonNull::new(unsafe { System.alloc(layout) })
+        }
+
+        unsafe fn free_large(&self, ptr: NonNull<u8>, layout: Layout) {
+            self.large.fetch_sub(1, Ordering::Relaxed);
+            unsafe { System.dealloc(ptr.as_ptr(), layout) }
+        }
+
+        fn cpu(&self) -> usize {
+            CPU.get()
+        }
+    }
+
+    fn layout(size: usize, align: usize) -> Layout {
+        Layout::from_size_align(size, align).unwrap()
+    }
+
+    #[test]
+    fn requests_map_to_the_smallest_fitting_class() {
+        assert_eq!(class_of(layout(1, 1)), Some(0));
+        assert_eq!(class_of(layout(16, 8)), Some(0));
+        assert_eq!(class_of(layout(17, 8)), Some(1));
+        assert_eq!(class_of(layout(8, 64)), Some(2));
+        assert_eq!(class_of(layout(0, 1)), Some(0));
+        assert_eq!(class_of(layout(4096, 4096)), Some(CLASSES - 1));
+        assert_eq!(class_of(layout(4097, 8)), None);
+        assert_eq!(class_of(layout(8, 8192)), None);
+        for (class, &size) in CLASS_SIZES.iter().enumerate() {
+            assert_eq!(class_of(layout(size, 1)), Some(class));
+        }
+    }
+
+    #[test]
+    fn freed_blocks_are_reused() {
+        let heap: SlabHeap<Host, 2> = SlabHeap::new(Host::default());
+        let layout = layout(100, 8);
+        let a = heap.alloc(layout);
+        let b = heap.alloc(layout);
+        assert_ne!(a, b);
+        unsafe { heap.dealloc(a, layout) };
+        assert_eq!(heap.alloc(layout), a);
+        unsafe {
+            heap.dealloc(a, layout);
+            heap.dealloc(b, layout);
+        }
+        assert_eq!(heap.backend().slabs.load(Ordering::Relaxed), 1);
+
+        let stats = heap.stats();
+        assert_eq!(stats.small_bytes, 0);
+        assert_eq!(stats.allocations, 3);
+        assert_eq!(stats.frees, 3);
+        assert_eq!(stats.slab_bytes, SLAB_SIZE);
+    }
+
+    #[test]
+    fn blocks_are_aligned_and_disjoint() {
+        let heap: SlabHeap<Host, 1> = SlabHeap::new(Host::default());
+        let mut blocks = Vec::new();
+        for i in 0..2000_usize {
+            let layout = layout(1 + i * 7 % 3000, 1 << (i % 7));
+            let block = heap.alloc(layout);
+            assert!(!block.is_null());
+            assert!(block.addr().is_multiple_of(layout.align()));
+            #[allow(clippy::cast_possible_truncation)]
+            unsafe {
+                block.write_bytes(i as u8, layout.size());
+            }
+            blocks.push((block, layout, i));
+        }
+        for &(block, layout, i) in &blocks {
+            let bytes = unsafe { core::slice::from_raw_parts(block, layout.size()) };
+            #[allow(clippy::cast_possible_truncation)]
+            let expected = i as u8;
+            assert!(
+                bytes.iter().all(|&b| b == expected),
+                "block {i} overwritten"
+            );
+            unsafe { heap.dealloc(block, layout) };
+        }
+        assert_eq!(heap.stats().small_bytes, 0);
+    }
+
+    #[test]
+    fn large_requests_go_to_the_backend() {
+        let heap: SlabHeap<Host, 1> = SlabHeap::new(Host::default());
+        let large = layout(MAX_CLASS_SIZE + 1, 8);
+        let block = heap.alloc(large);
+        assert!(!block.is_null());
+        assert_eq!(heap.backend().large.load(Ordering::Relaxed), 1);
+        assert_eq!(heap.backend().slabs.load(Ordering::Relaxed), 0);
+        // Plus the accounting header.
+        assert!(heap.stats().large_bytes > MAX_CLASS_SIZE);
+
+        unsafe { heap.dealloc(block, large) };
+        assert_eq!(heap.backend().large.load(Ordering::Relaxed), 0);
+        assert_eq!(heap.stats().large_bytes, 0);
+    }
+
+    #[test]
+    fn caches_trade_batches_with_the_shared_lists() {
+        let heap: SlabHeap<Host, 2> = SlabHeap::new(Host::default());
+        let layout = layout(256, 8);
+        let blocks: Vec<_> = (0..3 * CACHE_BLOCKS).map(|_| heap.alloc(layout)).collect();
+        for &block in &blocks {
+            unsafe { heap.dealloc(block, layout) };
+        }
+        let stats = heap.stats();
+        assert!(stats.refills >= 3 * CACHE_BLOCKS / CACHE_BATCH);
+        assert!(stats.flushes > 0);
+
+        // Another CPU picks up what the first one gave back.
+        let slabs = heap.backend().slabs.load(Ordering::Relaxed);
+        CPU.set(1);
+        let again: Vec<_> = (0..CACHE_BLOCKS).map(|_| heap.alloc(layout)).collect();
+        assert_eq!(heap.backend().slabs.load(Ordering::Relaxed), slabs);
+        for block in again {
+            unsafe { heap.dealloc(block, layout) };
+        }
+
+        // CPUs without a cache use the shared lists.
+        CPU.set(7);
+        let block = heap.alloc(layout);
+        assert!(!block.is_null());
+        unsafe { heap.dealloc(block, layout) };
+        CPU.set(0);
+    }
+
+    #[test]
+    fn live_bytes_are_counted_per_call_site() {
+        let heap: SlabHeap<Host, 1> = SlabHeap::new(Host::default());
+        let small = layout(40, 8);
+        let big = layout(10_000, 64);
+        let a = heap.alloc(small);
+        let b = heap.alloc(small);
+        let c = heap.alloc(big);
+        assert!(c.addr().is_multiple_of(64));
+
+        let mut sites = Vec::new();
+        heap.sites(|site| sites.push(site));
+        assert_eq!(sites.len(), 3);
+        assert!(sites.iter().all(|site| site.file.ends_with("heap.rs")));
+        assert_eq!(sites[0].live_bytes, 40);
+        assert_eq!(sites[2].live_bytes, 10_000);
+
+        unsafe {
+            heap.dealloc(a, small);
+            heap.dealloc(b, small);
+            heap.dealloc(c, big);
+        }
+        sites.clear();
+        heap.sites(|site| sites.push(site));
+        assert!(
+            sites
+                .iter()
+                .all(|site| site.live_bytes == 0 && site.live_blocks == 0)
+        );
+
+        // The same site in a loop is one entry.
+        for _ in 0..3 {
+            let block = heap.alloc(small);
+            unsafe { heap.dealloc(block, small) };
+        }
+        let mut count = 0;
+        heap.sites(|_| count += 1);
+        assert_eq!(count, 4);
+    }
+
+    #[test]
+    fn concurrent_use_keeps_blocks_apart() {
+        static HEAP: SlabHeap<Host, 4> = SlabHeap::new(Host {
+            slabs: AtomicUsize::new(0),
+            large: AtomicUsize::new(0),
+        });
+        let threads: Vec<_> = (0..4)
+            .map(|cpu| {
+                std::thread::spawn(move || {
+                    CPU.set(cpu);
+                    let layout = layout(48, 16);
+                    for round in 0..200_u8 {
+                        let blocks: Vec<_> = (0..50).map(|_| HEAP.alloc(layout)).collect();
+                        for &block in &blocks {
+                            unsafe { block.write_bytes(round, layout.size()) };
+                        }
+                        for &block in &blocks {
+                            assert_eq!(unsafe { block.read() }, round);
+                            unsafe { HEAP.dealloc(block, layout) };
+                        }
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(HEAP.stats().small_bytes, 0);
+    }
+}
@@ -0,0 +1,29 @@
+//! Tests run inside the kernel (`kernel-test` feature), against the live
+//! page tables and the direct map.
+
+use crate::phys_mapper::HhdmPhysMapper;
+use kernel_info::memory::{HHDM_BASE, KERNEL_BASE};
+use kernel_memory_addresses::Size4K;
+use kernel_test::kernel_test;
+use kernel_vmem::page_table::pml4::PageMapLevel4;
+use kernel_vmem::{AddressSpace, PhysMapperExt, read_cr3_phys};
+
+#[kernel_test]
+fn hhdm_shows_active_root() {
+    let root = unsafe { read_cr3_phys() };
+    let pml4 = HhdmPhysMapper.pml4_mut(root.page::<Size4K>());
+    for va in [KERNEL_BASE, HHDM_BASE] {
+        let entry = pml4.get(PageMapLevel4::index_of(va));
+        assert!(entry.next_table().is_some(), "{va} not mapped in the root");
+    }
+}
+
+#[kernel_test]
+fn hhdm_translation_round_trips() {
+    let root = unsafe { read_cr3_phys() };
+    let space = unsafe { AddressSpace::from_current(&HhdmPhysMapper) };
+    for offset in [0, 0x1000, 0x12_3456] {
+        let pa = root + offset;
+        assert_eq!(space.query(HHDM_BASE + pa.as_u64()), Some(pa));
+    }
+}
@@ -41,6 +41,7 @@
 //! * **No-Heap Design**: Self-contained implementation requiring no dynamic allocation
 //! * **Fixed Region**: Manages a predefined region of physical memory (currently 512 MiB)
 //! * **Early Boot Support**: Suitable for use before full memory management is available
+//! * **Statistics**: Used/free counters, a lock-free mirror and low-memory callbacks
 //!
 //! Key features:
 //! - O(1) allocation when frames are available
@@ -48,6 +49,14 @@
 //! - Configurable memory region boundaries
 //! - Integration with kernel memory layout
 //!
+//! ### Frame Metadata ([`frame_info`])
+//!
+//! One record per physical frame, kept in sync by the frame allocator:
+//! * **Reference Counts**: Owners of frames shared between address spaces,
+//!   e.g. copy-on-write pages after a fork
+//! * **Flags**: Allocated and reserved frames; debug builds catch double frees
+//! * **Owner Tags**: What a frame was allocated for
+//!
 //! ### Physical Mapper ([`phys_mapper`])
 //!
 //! Provides safe conversion between physical addresses and virtual pointers:
@@ -77,6 +86,36 @@
 //! - TLB management and invalidation
 //! - User/kernel space isolation
 //!
+//! ### Fault Injection (`fault_inject`)
+//!
+//! With the `fault-inject` feature, wrappers around a frame allocator or a
+//! global allocator that fail allocations deterministically, for testing
+//! out-of-memory paths.
+//!
+//! ### Poisoning (`poison`)
+//!
+//! With the `poison` feature, wrappers around a frame allocator or a global
+//! allocator that fill freed memory with a pattern and check it before the
+//! memory is reused, to catch writes after a free.
+//!
+//! ### Slab Heap ([`heap`])
+//!
+//! A general-purpose heap with segregated size classes from 16 B to 4 KiB,
+//! carved out of slabs, with per-CPU front-end caches; larger requests go to
+//! a backend such as the VMM. With the `heap-accounting` feature, it counts
+//! live bytes per call site.
+//!
+//! ### DMA Buffers ([`dma`])
+//!
+//! Zeroed, physically contiguous buffers for devices, with their physical
+//! and direct map addresses, placed below an address limit and aligned as
+//! requested, and released when dropped.
+//!
+//! ### MMIO Regions ([`mmio`])
+//!
+//! Uncached mappings of device registers with bounds-checked volatile
+//! accessors, created by [`Vmm::map_mmio`](vmm::Vmm::map_mmio).
+//!
 //! ## Memory Layout Integration
 //!
 //! The crate integrates with the kernel's memory layout defined in `kernel-info`:
@@ -121,10 +160,11 @@
 //!
 //! ### Basic Physical Allocation
 //! ```rust
-//! use kernel_alloc::frame_alloc::BitmapFrameAlloc;
+//! use kernel_alloc::frame_alloc::{BitmapFrameAlloc, DEFAULT_MANAGED};
 //! use kernel_vmem::PhysFrameAlloc;
 //!
-//! let mut allocator = BitmapFrameAlloc::new();
+//! let words = BitmapFrameAlloc::storage_words(DEFAULT_MANAGED);
+//! let mut allocator = BitmapFrameAlloc::new(Box::leak(vec![0; words].into_boxed_slice()));
 //! if let Some(frame) = allocator.alloc_4k() {
 //!     // Use the physical frame
 //!     allocator.free_4k(frame);
@@ -134,10 +174,11 @@
 //! ### Virtual Memory Management
 //! ```rust,no_run
 //! use kernel_alloc::{phys_mapper::HhdmPhysMapper, vmm::Vmm};
-//! use kernel_alloc::frame_alloc::BitmapFrameAlloc;
+//! use kernel_alloc::frame_alloc::{BitmapFrameAlloc, DEFAULT_MANAGED};
 //!
 //! let mapper = HhdmPhysMapper;
-//! let mut allocator = BitmapFrameAlloc::new();
+//! let words = BitmapFrameAlloc::storage_words(DEFAULT_MANAGED);
+//! let mut allocator = BitmapFrameAlloc::new(Box::leak(vec![0; words].into_boxed_slice()));
 //! let mut vmm = unsafe { Vmm::from_current(&mapper, &mut allocator) };
 //!
 //! // Map virtual memory regions, manage page tables, etc.
@@ -163,6 +204,16 @@
 
 #![cfg_attr(not(any(test, doctest)), no_std)]
 
+pub mod dma;
+#[cfg(any(test, feature = "fault-inject"))]
+pub mod fault_inject;
 pub mod frame_alloc;
+pub mod frame_info;
+pub mod heap;
+#[cfg(feature = "kernel-test")]
+mod ktests;
+pub mod mmio;
 pub mod phys_mapper;
+#[cfg(any(test, feature = "poison"))]
+pub mod poison;
 pub mod vmm;
@@ -0,0 +1,224 @@
+//! # Memory-Mapped I/O Regions
+//!
+//! Device registers live at fixed physical addresses and must be accessed
+//! uncached and with volatile loads and stores. [`Vmm::map_mmio`](crate::vmm::Vmm::map_mmio)
+//! maps such a range and returns an [`MmioRegion`]: a bounds-checked window
+//! with typed accessors, so drivers do not hand-roll pointer arithmetic on
+//! register blocks.
+//!
+//! ## Mapping
+//!
+//! Regions are mapped with 4 KiB pages that are
+//! - **uncached** ([`CacheMode::Uncached`]),
+//! - **writable**, **non-executable** and **global**.
+//!
+//! Neither the physical start nor the length need to be page aligned; the
+//! region covers exactly the requested bytes, its mapping the surrounding
+//! pages (see [`mapped_len`]).
+//!
+//! ## Accessors
+//!
+//! `readN`/`writeN` take a byte offset into the region. Accesses must be
+//! naturally aligned and lie entirely inside the region; violations panic
+//! instead of touching a neighboring device.
+
+use kernel_memory_addresses::{PageSize, PhysicalAddress, Size4K, VirtualAddress};
+use kernel_vmem::{CacheMode, VirtualMemoryPageBits};
+
+/// Page flags of MMIO mappings.
+pub const MMIO_FLAGS: VirtualMemoryPageBits = VirtualMemoryPageBits::new()
+    .with_present(true)
+    .with_writable(true)
+    .with_cache_mode(CacheMode::Uncached)
+    .with_global(true)
+    .with_no_execute(true);
+
+/// Bytes of virtual address space needed to map `len` bytes at `pa`.
+#[must_use]
+pub const fn mapped_len(pa: PhysicalAddress, len: u64) -> u64 {
+    let offset = pa.as_u64() & (Size4K::SIZE - 1);
+    (offset + len).div_ceil(Size4K::SIZE) * Size4K::SIZE
+}
+
+/// A mapped MMIO range; see the [module docs](self).
+#[derive(Debug)]
+pub struct MmioRegion {
+    /// Virtual address of the first requested byte.
+    base: VirtualAddress,
+    /// Physical address of the first requested byte.
+    phys: PhysicalAddress,
+    len: u64,
+}
+
+impl MmioRegion {
+    /// Wrap an existing mapping of `len` bytes at `base`.
+    ///
+    /// # Safety
+    /// `base..base + len` must be mapped to the device memory at `phys`
+    /// (uncached, writable) for as long as the region is used.
+    #[must_use]
+    pub const unsafe fn from_raw(base: VirtualAddress, phys: PhysicalAddress, len: u64) -> Self {
+        Self { base, phys, len }
+    }
+
+    /// Virtual address of the first byte.
+    #[must_use]
+    pub const fn base(&self) -> VirtualAddress {
+        self.base
+    }
+
+    /// Physical address of the first byte.
+    #[must_use]
+    pub const fn phys(&self) -> PhysicalAddress {
+        self.phys
+    }
+
+    /// Length of the region in bytes.
+    #[must_use]
+    pub const fn len(&self) -> u64 {
+        self.len
+    }
+
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Pointer to the `T` at `offset`.
+    ///
+    /// # Panics
+    /// If the access is out of bounds or misaligned.
+    fn ptr<T>(&self, offset: u64) -> *mut T {
+        let size = size_of::<T>() as u64;
+        assert!(
+            offset.checked_add(size).is_some_and(|end| end <= self.len),
+            "MMIO access at {offset:#x} (+{size}) outside of {len:#x}-byte region at {phys}",
+            len = self.len,
+            phys = self.phys,
+        );
+        let addr = self.base.as_u64() + offset;
+        assert!(
+            addr.is_multiple_of(size),
+            "misaligned {size}-byte MMIO access at {offset:#x}"
+        );
+        addr as *mut T
+    }
+
+    /// Read the byte at `offset`.
+    ///
+    /// # Panics
+    /// If the access is out of bounds.
+    #[must_use]
+    pub fn read8(&self, offset: u64) -> u8 {
+        unsafe { self.ptr::<u8>(offset).read_volatile() }
+    }
+
+    /// Read the 16-bit register at `offset`.
+    ///
+    /// # Panics
+    /// If the access is out of bounds or misaligned.
+    #[must_use]
+    pub fn read16(&self, offset: u64) -> u16 {
+        unsafe { self.ptr::<u16>(offset).read_volatile() }
+    }
+
+    /// Read the 32-bit register at `offset`.
+    ///
+    /// # Panics
+    /// If the access is out of bounds or misaligned.
+    #[must_use]
+    pub fn read32(&self, offset: u64) -> u32 {
+        unsafe { self.ptr::<u32>(offset).read_volatile() }
+    }
+
+    /// Read the 64-bit register at `offset`.
+    ///
+    /// # Panics
+    /// If the access is out of bounds or misaligned.
+    #[must_use]
+    pub fn read64(&self, offset: u64) -> u64 {
+        unsafe { self.ptr::<u64>(offset).read_volatile() }
+    }
+
+    /// Write the byte at `offset`.
+    ///
+    /// # Panics
+    /// If the access is out of bounds.
+    pub fn write8(&self, offset: u64, value: u8) {
+        unsafe { self.ptr::<u8>(offset).write_volatile(value) }
+    }
+
+    /// Write the 16-bit register at `offset`.
+    ///
+    /// # Panics
+    /// If the access is out of bounds or misaligned.
+    pub fn write16(&self, offset: u64, value: u16) {
+        unsafe { self.ptr::<u16>(offset).write_volatile(value) }
+    }
+
+    /// Write the 32-bit register at `offset`.
+    ///
+    /// # Panics
+    /// If the access is out of bounds or misaligned.
+    pub fn write32(&self, offset: u64, value: u32) {
+        unsafe { self.ptr::<u32>(offset).write_volatile(value) }
+    }
+
+    /// Write the 64-bit register at `offset`.
+    ///
+    /// # Panics
+    /// If the access is out of bounds or misaligned.
+    pub fn write64(&self, offset: u64, value: u64) {
+        unsafe { self.ptr::<u64>(offset).write_volatile(value) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A region over ordinary memory standing in for device registers.
+    fn region(regs: &mut [u64]) -> MmioRegion {
+        let base = VirtualAddress::new(regs.as_mut_ptr() as u64);
+        let len = size_of_val(regs) as u64;
+        unsafe { MmioRegion::from_raw(base, PhysicalAddress::new(0xFEE0_0000), len) }
+    }
+
+    #[test]
+    fn accessors_hit_the_right_offsets() {
+        let mut regs = [0u64; 4];
+        let mmio = region(&mut regs);
+
+        mmio.write32(0x8, 0xDEAD_BEEF);
+        mmio.write64(0x18, u64::MAX);
+        mmio.write8(0x1, 0x42);
+        assert_eq!(mmio.read32(0x8), 0xDEAD_BEEF);
+        assert_eq!(mmio.read16(0x0), 0x4200);
+        assert_eq!(mmio.read64(0x18), u64::MAX);
+        assert_eq!(regs, [0x4200, 0xDEAD_BEEF, 0, u64::MAX]);
+    }
+
+    #[test]
+    #[should_panic(expected = "outside of")]
+    fn out_of_bounds_access_panics() {
+        let mut regs = [0u64; 2];
+        let _ = region(&mut regs).read32(0x10);
+    }
+
+    #[test]
+    #[should_panic(expected = "misaligned")]
+    fn misaligned_access_panics() {
+        let mut regs = [0u64; 2];
+        region(&mut regs).write32(0x2, 1);
+    }
+
+    #[test]
+    fn mapped_len_covers_partial_pages() {
+        assert_eq!(mapped_len(PhysicalAddress::new(0xFEC0_0000), 0x20), 0x1000);
+        assert_eq!(mapped_len(PhysicalAddress::new(0xFED0_0FF0), 0x20), 0x2000);
+        assert_eq!(
+            mapped_len(PhysicalAddress::new(0xE000_0000), 0x10_0000),
+            0x10_0000
+        );
+    }
+}
@@ -0,0 +1,731 @@
+//! # Use-After-Free Poisoning
+//!
+//! A write through a dangling pointer into freed memory corrupts whatever is
+//! allocated there next, and the crash it eventually causes points anywhere
+//! but at the culprit. With the `poison` feature (and for this crate's tests)
+//! the kernel's allocators can be wrapped to catch such writes:
+//!
+//! * Freed memory is filled with [`POISON_BYTE`]. A pointer loaded from it is
+//!   non-canonical and faults on first use.
+//! * Before freed memory is handed out again, the pattern is checked. A
+//!   byte that changed means something wrote to the memory after it was
+//!   freed; the check panics with a [`PoisonViolation`] naming the region
+//!   and the offset of the first changed byte.
+//! * Optionally, a sample of freed frames additionally loses its alias in the
+//!   direct map, so that a stray write through it faults right away, with the
+//!   writer still on the stack. Such frames sit in a quarantine of
+//!   [`QUARANTINE_FRAMES`] frames before they are checked and really freed;
+//!   [`FramePoison::describe`] tells a page fault handler whether an address
+//!   hit one of them.
+//!
+//! ## Wrappers
+//!
+//! * [`PoisonFrameAlloc`] wraps a [`PhysFrameAlloc`]. Frames are poisoned in
+//!   [`free_4k`](PhysFrameAlloc::free_4k) and checked in
+//!   [`alloc_4k`](PhysFrameAlloc::alloc_4k). A frame the allocator has never
+//!   seen freed holds arbitrary data, so a frame only counts as poisoned if
+//!   most of it still holds the pattern; a frame with a few changed bytes is
+//!   reported. Frames handed out through other methods of the wrapped
+//!   allocator, such as contiguous runs, are not checked.
+//! * [`PoisonGlobalAlloc`] wraps a [`GlobalAlloc`]. Freed blocks of at least
+//!   [`MIN_QUARANTINED_BLOCK`] bytes wait in a quarantine of
+//!   [`QUARANTINE_BLOCKS`] blocks and are checked when they leave it, right
+//!   before the wrapped allocator may reuse them. Smaller blocks are only
+//!   poisoned.
+//!
+//! State and counters live in a [`FramePoison`] or [`HeapPoison`] that can
+//! sit in a `static`, readable without the allocator's lock.
+//!
+//! ## Direct map aliases
+//!
+//! [`PoisonFrameAlloc::unmap_aliases`] enables unmapping for one in `n`
+//! freed frames. The alias is unmapped in the page tables of an
+//! [`AliasUnmap::root`] sharing the kernel half; huge leaves of the direct map
+//! are [split](kernel_vmem::AddressSpace::split_to_4k) on demand, with tables
+//! from the wrapped allocator. Only the local TLB is flushed: another CPU may
+//! still write through a stale entry without faulting, which the check on
+//! leaving the quarantine then reports instead.
+//!
+//! ## Example
+//!
+//! ```rust
+//! use kernel_alloc::poison::{HeapPoison, PoisonGlobalAlloc};
+//! use std::alloc::{GlobalAlloc, Layout, System};
+//!
+//! static POISON: HeapPoison = HeapPoison::new();
+//!
+//! let heap = PoisonGlobalAlloc::new(System, &POISON);
+//! let layout = Layout::from_size_align(64, 8).unwrap();
+//! unsafe {
+//!     let block = heap.alloc(layout);
+//!     heap.dealloc(block, layout);
+//!     heap.drain();
+//! }
+//! assert_eq!(POISON.stats().checked, 1);
+//! ```
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicPtr, AtomicU64, AtomicUsize, Ordering};
+use kernel_memory_addresses::{PhysicalAddress, PhysicalPage, Size4K, VirtualAddress};
+use kernel_vmem::{AddressSpace, PhysFrameAlloc, PhysMapper, VirtualMemoryPageBits};
+
+/// The byte freed memory is filled with.
+///
+/// Eight of them make a non-canonical address, so a pointer read from freed
+/// memory faults when it is followed.
+pub const POISON_BYTE: u8 = 0x6b;
+
+const POISON_WORD: u64 = u64::from_ne_bytes([POISON_BYTE; 8]);
+
+const FRAME_WORDS: usize = 4096 / size_of::<u64>();
+
+/// Number of frames a [`FramePoison`] keeps unmapped.
+pub const QUARANTINE_FRAMES: usize = 64;
+
+/// Number of blocks a [`HeapPoison`] holds back from reuse.
+pub const QUARANTINE_BLOCKS: usize = 256;
+
+/// Smallest block a [`PoisonGlobalAlloc`] quarantines; it must have room
+/// for the block's layout.
+pub const MIN_QUARANTINED_BLOCK: usize = 2 * size_of::<usize>();
+
+/// Bytes of freed memory that changed before it was allocated again.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct PoisonViolation {
+    /// Start of the freed block or frame: a virtual address for heap
+    /// blocks, a physical one for frames.
+    pub start: u64,
+    /// Offset of the first changed byte.
+    pub offset: usize,
+    /// Number of changed bytes.
+    pub changed: usize,
+    /// Value of the first changed byte.
+    pub found: u8,
+}
+
+impl PoisonViolation {
+    /// Compare `bytes`, freed memory starting at `start`, with the pattern.
+    #[must_use]
+    pub fn check(start: u64, bytes: &[u8]) -> Option<Self> {
+        let offset = bytes.iter().position(|&b| b != POISON_BYTE)?;
+        Some(Self {
+            start,
+            offset,
+            changed: bytes[offset..]
+                .iter()
+                .filter(|&&b| b != POISON_BYTE)
+                .count(),
+            found: bytes[offset],
+        })
+    }
+}
+
+impl fmt::Display for PoisonViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{changed} byte(s) of freed memory at {start:#x} changed after the free, \
+             first at {first:#x} (offset {offset:#x}, now {found:#04x})",
+            changed = self.changed,
+            start = self.start,
+            first = self.start + self.offset as u64,
+            offset = self.offset,
+            found = self.found
+        )
+    }
+}
+
+/// A snapshot of a [`FramePoison`]'s or [`HeapPoison`]'s counters.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct PoisonStats {
+    /// Frees that filled memory with the pattern.
+    pub poisoned: u64,
+    /// Checks that found the pattern intact.
+    pub checked: u64,
+    /// Checks that found changed bytes.
+    pub violations: u64,
+    /// Frames whose direct map alias was unmapped.
+    pub unmapped: u64,
+}
+
+struct Counters {
+    poisoned: AtomicU64,
+    checked: AtomicU64,
+    violations: AtomicU64,
+    unmapped: AtomicU64,
+}
+
+impl Counters {
+    const fn new() -> Self {
+        Self {
+            poisoned: AtomicU64::new(0),
+            checked: AtomicU64::new(0),
+            violations: AtomicU64::new(0),
+            unmapped: AtomicU64::new(0),
+        }
+    }
+
+    fn count(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> PoisonStats {
+        PoisonStats {
+            poisoned: self.poisoned.load(Ordering::Relaxed),
+            checked: self.checked.load(Ordering::Relaxed),
+            violations: self.violations.load(Ordering::Relaxed),
+            unmapped: self.unmapped.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Count the outcome of a check and panic on a violation.
+    fn verdict(&self, violation: Option<PoisonViolation>) {
+        match violation {
+            None => Self::count(&self.checked),
+            Some(violation) => {
+                Self::count(&self.violations);
+                panic!("use after free: {violation}");
+            }
+        }
+    }
+}
+
+/// Counters and quarantine of a [`PoisonFrameAlloc`]; see the
+/// [module docs](self).
+pub struct FramePoison {
+    counters: Counters,
+    /// Physical addresses of unmapped frames; `0` marks a free slot.
+    quarantine: [AtomicU64; QUARANTINE_FRAMES],
+    next: AtomicUsize,
+}
+
+impl Default for FramePoison {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FramePoison {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            counters: Counters::new(),
+            quarantine: [const { AtomicU64::new(0) }; QUARANTINE_FRAMES],
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// A snapshot of the counters.
+    pub fn stats(&self) -> PoisonStats {
+        self.counters.snapshot()
+    }
+
+    /// The quarantined frame whose direct map alias contains `va`, if any.
+    ///
+    /// Lock-free, so a page fault handler may call it.
+    pub fn describe(
+        &self,
+        direct_map: VirtualAddress,
+        va: VirtualAddress,
+    ) -> Option<PhysicalPage<Size4K>> {
+        let pa = va.as_u64().checked_sub(direct_map.as_u64())? & !0xFFF;
+        let quarantined = |slot: &AtomicU64| slot.load(Ordering::Acquire) == pa;
+        (pa != 0 && self.quarantine.iter().any(quarantined))
+            .then(|| PhysicalPage::from_addr(PhysicalAddress::new(pa)))
+    }
+
+    /// Put `frame` into the quarantine; returns the frame it displaces.
+    fn enqueue(&self, frame: PhysicalPage<Size4K>) -> Option<PhysicalPage<Size4K>> {
+        let slot = self.next.fetch_add(1, Ordering::Relaxed) % QUARANTINE_FRAMES;
+        let old = self.quarantine[slot].swap(frame.base().as_u64(), Ordering::AcqRel);
+        (old != 0).then(|| PhysicalPage::from_addr(PhysicalAddress::new(old)))
+    }
+}
+
+/// How a [`PoisonFrameAlloc`] unmaps direct map aliases of freed frames.
+#[derive(Debug, Copy, Clone)]
+pub struct AliasUnmap {
+    /// Root of an address space whose kernel half holds the direct map.
+    pub root: PhysicalPage<Size4K>,
+    /// Virtual address of physical address `0` in the direct map.
+    pub direct_map: VirtualAddress,
+    /// Flags of the direct map's leaves, restored when an alias is mapped again.
+    pub leaf_flags: VirtualMemoryPageBits,
+    /// Unmap the alias of one in `sample` freed frames; `0` never does.
+    pub sample: u64,
+    /// Invalidate the local TLB entry of a page.
+    pub flush: fn(VirtualAddress),
+}
+
+impl AliasUnmap {
+    /// Flags of the tables created when splitting the direct map.
+    const LINK_FLAGS: VirtualMemoryPageBits = VirtualMemoryPageBits::new()
+        .with_present(true)
+        .with_writable(true);
+
+    const fn alias(&self, frame: PhysicalPage<Size4K>) -> VirtualAddress {
+        VirtualAddress::new(self.direct_map.as_u64() + frame.base().as_u64())
+    }
+}
+
+/// A [`PhysFrameAlloc`] that poisons freed frames; see the [module docs](self).
+///
+/// Dereferences to the wrapped allocator for everything but
+/// [`PhysFrameAlloc`].
+pub struct PoisonFrameAlloc<'p, A, M> {
+    inner: A,
+    mapper: &'p M,
+    poison: &'p FramePoison,
+    unmap: Option<AliasUnmap>,
+    freed: u64,
+}
+
+impl<'p, A, M: PhysMapper> PoisonFrameAlloc<'p, A, M> {
+    /// Wrap `inner`, reaching frame contents through `mapper`.
+    pub const fn new(inner: A, mapper: &'p M, poison: &'p FramePoison) -> Self {
+        Self {
+            inner,
+            mapper,
+            poison,
+            unmap: None,
+            freed: 0,
+        }
+    }
+
+    /// The counters and quarantine.
+    pub const fn poison(&self) -> &'p FramePoison {
+        self.poison
+    }
+
+    /// Unmap the direct map alias of some freed frames as `unmap` says.
+    pub const fn unmap_aliases(&mut self, unmap: AliasUnmap) {
+        self.unmap = Some(unmap);
+    }
+
+    /// Unwrap the allocator.
+    pub fn into_inner(self) -> A {
+        self.inner
+    }
+
+    fn fill(&self, frame: PhysicalPage<Size4K>) {
+        // Safety: the frame is allocated and mapped, and freed by its owner.
+        let words = unsafe { self.mapper.phys_to_mut::<[u64; FRAME_WORDS]>(frame.base()) };
+        words.fill(POISON_WORD);
+        Counters::count(&self.poison.counters.poisoned);
+    }
+
+    /// Check a frame that may or may not have been poisoned.
+    fn check(&self, frame: PhysicalPage<Size4K>) {
+        // Safety: the frame is mapped and not handed out yet.
+        let words: &[u64; FRAME_WORDS] = unsafe { self.mapper.phys_to_mut(frame.base()) };
+        let intact = words.iter().filter(|&&w| w == POISON_WORD).count();
+        if intact <= FRAME_WORDS / 2 {
+            // Not poisoned by us: fresh memory with arbitrary contents.
+            return;
+        }
+        // Safety: `[u64; 512]` and `[u8; 4096]` have the same size.
+        let bytes = unsafe { &*core::ptr::from_ref(words).cast::<[u8; 4096]>() };
+        let violation = PoisonViolation::check(frame.base().as_u64(), bytes);
+        self.poison.counters.verdict(violation);
+    }
+}
+
+impl<A: PhysFrameAlloc, M: PhysMapper> PoisonFrameAlloc<'_, A, M> {
+    /// Unmap the alias of `frame`; `false` if that was not possible.
+    fn hide(&mut self, unmap: &AliasUnmap, frame: PhysicalPage<Size4K>) -> bool {
+        let va = unmap.alias(frame);
+        let space = AddressSpace::from_root(self.mapper, unmap.root);
+        if space
+            .split_to_4k(&mut self.inner, va, AliasUnmap::LINK_FLAGS)
+            .is_err()
+            || space.unmap_one(va).is_err()
+        {
+            return false;
+        }
+        (unmap.flush)(va);
+        Counters::count(&self.poison.counters.unmapped);
+        true
+    }
+
+    /// Map the alias of `frame` again.
+    fn reveal(&mut self, unmap: &AliasUnmap, frame: PhysicalPage<Size4K>) {
+        let va = unmap.alias(frame);
+        let space = AddressSpace::from_root(self.mapper, unmap.root);
+        space
+            .map_one::<_, Size4K>(
+                &mut self.inner,
+                va,
+                frame.base(),
+                AliasUnmap::LINK_FLAGS,
+                unmap.leaf_flags,
+            )
+            .expect("the page table of an unmapped alias is still there");
+        (unmap.flush)(va);
+    }
+}
+
+impl<A, M> Deref for PoisonFrameAlloc<'_, A, M> {
+    type Target = A;
+
+    fn deref(&self) -> &A {
+        &self.inner
+    }
+}
+
+impl<A, M> DerefMut for PoisonFrameAlloc<'_, A, M> {
+    fn deref_mut(&mut self) -> &mut A {
+        &mut self.inner
+    }
+}
+
+impl<A: PhysFrameAlloc, M: PhysMapper> PhysFrameAlloc for PoisonFrameAlloc<'_, A, M> {
+    fn alloc_4k(&mut self) -> Option<PhysicalPage<Size4K>> {
+        let frame = self.inner.alloc_4k()?;
+        self.check(frame);
+        Some(frame)
+    }
+
+    fn free_4k(&mut self, frame: PhysicalPage<Size4K>) {
+        self.fill(frame);
+        self.freed += 1;
+
+        if let Some(unmap) = self.unmap
+            && unmap.sample != 0
+            && self.freed.is_multiple_of(unmap.sample)
+            && frame.base().as_u64() != 0
+            && self.hide(&unmap, frame)
+        {
+            // The quarantine holds on to the frame; free the one it lets go.
+            let Some(evicted) = self.poison.enqueue(frame) else {
+                return;
+            };
+            self.reveal(&unmap, evicted);
+            self.check(evicted);
+            self.inner.free_4k(evicted);
+            return;
+        }
+        self.inner.free_4k(frame);
+    }
+}
+
+/// Counters and quarantine of a [`PoisonGlobalAlloc`]; see the
+/// [module docs](self).
+pub struct HeapPoison {
+    counters: Counters,
+    /// Quarantined blocks, each starting with its size and alignment.
+    quarantine: [AtomicPtr<u8>; QUARANTINE_BLOCKS],
+    next: AtomicUsize,
+}
+
+impl Default for HeapPoison {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HeapPoison {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            counters: Counters::new(),
+            quarantine: [const { AtomicPtr::new(core::ptr::null_mut()) }; QUARANTINE_BLOCKS],
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// A snapshot of the counters.
+    pub fn stats(&self) -> PoisonStats {
+        self.counters.snapshot()
+    }
+}
+
+/// A [`GlobalAlloc`] that poisons freed blocks; see the [module docs](self).
+pub struct PoisonGlobalAlloc<A> {
+    inner: A,
+    poison: &'static HeapPoison,
+}
+
+impl<A> PoisonGlobalAlloc<A> {
+    /// Wrap `inner`.
+    pub const fn new(inner: A, poison: &'static HeapPoison) -> Self {
+        Self { inner, poison }
+    }
+
+    /// The wrapped allocator.
+    pub const fn inner(&self) -> &A {
+        &self.inner
+    }
+}
+
+impl<A: GlobalAlloc> PoisonGlobalAlloc<A> {
+    /// Check and free every quarantined block.
+    ///
+    /// # Safety
+    /// No block may be freed concurrently.
+    pub unsafe fn drain(&self) {
+        for slot in &self.poison.quarantine {
+            let block = slot.swap(core::ptr::null_mut(), Ordering::AcqRel);
+            if !block.is_null() {
+                unsafe { self.release(block) };
+            }
+        }
+    }
+
+    /// Check a block leaving the quarantine and hand it to the wrapped allocator.
+    unsafe fn release(&self, block: *mut u8) {
+        // Safety: `dealloc` stored the layout at the start of the block,
+        // which is aligned for `usize`.
+        #[allow(clippy::cast_ptr_alignment)]
+        let (size, align) = unsafe {
+            let header = block.cast::<usize>();
+            (header.read(), header.add(1).read())
+        };
+        let poisoned = unsafe {
+            core::slice::from_raw_parts(
+                block.add(MIN_QUARANTINED_BLOCK),
+                size - MIN_QUARANTINED_BLOCK,
+            )
+        };
+        let start = (block.addr() + MIN_QUARANTINED_BLOCK) as u64;
+        self.poison
+            .counters
+            .verdict(PoisonViolation::check(start, poisoned));
+
+        // Safety: the layout is the one the block was allocated with.
+        unsafe {
+            self.inner
+                .dealloc(block, Layout::from_size_align_unchecked(size, align));
+        }
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for PoisonGlobalAlloc<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        unsafe { self.inner.alloc(layout) }
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        unsafe { self.inner.alloc_zeroed(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // Safety: the block is ours until it is handed back.
+        unsafe { ptr.write_bytes(POISON_BYTE, layout.size()) };
+        Counters::count(&self.poison.counters.poisoned);
+
+        if layout.size() < MIN_QUARANTINED_BLOCK || layout.align() < align_of::<usize>() {
+            unsafe { self.inner.dealloc(ptr, layout) };
+            return;
+        }
+
+        // Safety: the block is large enough and aligned for the header.
+        #[allow(clippy::cast_ptr_alignment)]
+        unsafe {
+            let header = ptr.cast::<usize>();
+            header.write(layout.size());
+            header.add(1).write(layout.align());
+        }
+        let slot = self.poison.next.fetch_add(1, Ordering::Relaxed) % QUARANTINE_BLOCKS;
+        let evicted = self.poison.quarantine[slot].swap(ptr, Ordering::AcqRel);
+        if !evicted.is_null() {
+            unsafe { self.release(evicted) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kernel_memory_addresses::Size1G;
+    use kernel_vmem::address_space::AddressSpaceMapOneError;
+    use kernel_vmem_testutil::{MockFrameAlloc, MockMemory};
+    use std::alloc::System;
+    use std::panic::{AssertUnwindSafe, catch_unwind};
+
+    /// Frames of a [`MockMemory`], handed out again last in first out with
+    /// their contents untouched, like the kernel's allocator does. They stay
+    /// allocated in the pool, so freed frames can still be inspected.
+    struct Recycler<'m> {
+        pool: MockFrameAlloc<'m>,
+        free: Vec<PhysicalPage<Size4K>>,
+    }
+
+    impl<'m> Recycler<'m> {
+        fn new(mem: &'m MockMemory) -> Self {
+            Self {
+                pool: mem.allocator(),
+                free: Vec::new(),
+            }
+        }
+    }
+
+    impl PhysFrameAlloc for Recycler<'_> {
+        fn alloc_4k(&mut self) -> Option<PhysicalPage<Size4K>> {
+            self.free.pop().or_else(|| self.pool.alloc_4k())
+        }
+
+        fn free_4k(&mut self, frame: PhysicalPage<Size4K>) {
+            self.free.push(frame);
+        }
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    fn bytes(mem: &MockMemory, frame: PhysicalPage<Size4K>) -> &mut [u8; 4096] {
+        unsafe { mem.phys_to_mut(frame.base()) }
+    }
+
+    #[test]
+    fn violation_names_the_first_changed_byte() {
+        let mut bytes = [POISON_BYTE; 64];
+        assert_eq!(PoisonViolation::check(0x1000, &bytes), None);
+
+        bytes[10] = 0;
+        bytes[20] = 1;
+        let violation = PoisonViolation::check(0x1000, &bytes).unwrap();
+        assert_eq!(
+            violation,
+            PoisonViolation {
+                start: 0x1000,
+                offset: 10,
+                changed: 2,
+                found: 0
+            }
+        );
+        assert_eq!(
+            violation.to_string(),
+            "2 byte(s) of freed memory at 0x1000 changed after the free, \
+             first at 0x100a (offset 0xa, now 0x00)"
+        );
+    }
+
+    #[test]
+    fn freed_frames_are_poisoned_and_checked() {
+        static POISON: FramePoison = FramePoison::new();
+        let mem = MockMemory::new(8);
+        let mut alloc = PoisonFrameAlloc::new(Recycler::new(&mem), &mem, &POISON);
+
+        // Fresh frames are not checked.
+        let frame = alloc.alloc_4k().unwrap();
+        bytes(&mem, frame).fill(0);
+        alloc.free_4k(frame);
+        assert!(bytes(&mem, frame).iter().all(|&b| b == POISON_BYTE));
+
+        assert_eq!(alloc.alloc_4k(), Some(frame));
+        assert_eq!(
+            POISON.stats(),
+            PoisonStats {
+                poisoned: 1,
+                checked: 1,
+                violations: 0,
+                unmapped: 0
+            }
+        );
+    }
+
+    #[test]
+    fn write_after_free_is_reported() {
+        static POISON: FramePoison = FramePoison::new();
+        let mem = MockMemory::new(8);
+        let mut alloc = PoisonFrameAlloc::new(Recycler::new(&mem), &mem, &POISON);
+        let frame = alloc.alloc_4k().unwrap();
+        alloc.free_4k(frame);
+
+        // The stray write.
+        bytes(&mem, frame)[0x80..0x88].fill(0);
+
+        let result = catch_unwind(AssertUnwindSafe(|| alloc.alloc_4k()));
+        let message = *result.unwrap_err().downcast::<String>().unwrap();
+        assert!(message.contains("8 byte(s)"), "{message}");
+        assert!(message.contains("(offset 0x80"), "{message}");
+        assert_eq!(POISON.stats().violations, 1);
+    }
+
+    #[test]
+    fn sampled_frames_lose_their_alias_until_they_leave_the_quarantine() {
+        static POISON: FramePoison = FramePoison::new();
+        let mem = MockMemory::new(128);
+        let mut alloc = PoisonFrameAlloc::new(Recycler::new(&mem), &mem, &POISON);
+
+        // An identity "direct map" of the first GiB, where the pool lies.
+        let root = alloc.alloc_4k().unwrap();
+        let space = AddressSpace::from_root(&mem, root);
+        let frames: Vec<_> = (0..=QUARANTINE_FRAMES)
+            .map(|_| alloc.alloc_4k().unwrap())
+            .collect();
+        let leaf = AliasUnmap::LINK_FLAGS.with_no_execute(true);
+        let result: Result<(), AddressSpaceMapOneError> = space.map_one::<_, Size1G>(
+            &mut alloc,
+            VirtualAddress::new(0),
+            PhysicalAddress::new(0),
+            AliasUnmap::LINK_FLAGS,
+            leaf,
+        );
+        result.unwrap();
+
+        alloc.unmap_aliases(AliasUnmap {
+            root,
+            direct_map: VirtualAddress::new(0),
+            leaf_flags: leaf,
+            sample: 1,
+            flush: |_| {},
+        });
+        let alias = |f: PhysicalPage<Size4K>| VirtualAddress::new(f.base().as_u64());
+
+        alloc.free_4k(frames[0]);
+        assert_eq!(space.query(alias(frames[0])), None);
+        assert_eq!(
+            POISON.describe(VirtualAddress::new(0), alias(frames[0]) + 8),
+            Some(frames[0])
+        );
+        assert_eq!(space.query(alias(frames[1])), Some(frames[1].base()));
+
+        // Filling the quarantine pushes the first frame out, mapped again.
+        for &frame in &frames[1..] {
+            alloc.free_4k(frame);
+        }
+        assert_eq!(space.query(alias(frames[0])), Some(frames[0].base()));
+        assert_eq!(space.query_flags(alias(frames[0])), Some(leaf));
+        assert_eq!(
+            POISON.describe(VirtualAddress::new(0), alias(frames[0])),
+            None
+        );
+        assert_eq!(POISON.stats().unmapped, frames.len() as u64);
+        assert_eq!(POISON.stats().checked, 1);
+    }
+
+    #[test]
+    fn heap_blocks_are_checked_when_they_leave_the_quarantine() {
+        static POISON: HeapPoison = HeapPoison::new();
+        let heap = PoisonGlobalAlloc::new(System, &POISON);
+        let layout = Layout::from_size_align(64, 8).unwrap();
+
+        unsafe {
+            let block = heap.alloc(layout);
+            heap.dealloc(block, layout);
+            heap.drain();
+
+            let block = heap.alloc(layout);
+            heap.dealloc(block, layout);
+            // The stray write.
+            block.add(40).write(0);
+            let result = catch_unwind(AssertUnwindSafe(|| heap.drain()));
+            assert!(result.is_err());
+
+            // Too small to quarantine, but still poisoned.
+            let tiny = Layout::from_size_align(4, 4).unwrap();
+            let block = heap.alloc(tiny);
+            heap.dealloc(block, tiny);
+        }
+        assert_eq!(
+            POISON.stats(),
+            PoisonStats {
+                poisoned: 3,
+                checked: 1,
+                violations: 1,
+                unmapped: 0
+            }
+        );
+    }
+}
@@ -7,18 +7,23 @@
 //! # Example
 //! ```ignore
 //! use kernel_alloc::{frame_alloc::BitmapFrameAlloc, phys_mapper::HhdmPhysMapper, vmm::Vmm};
-//! let mut pmm = BitmapFrameAlloc::new();
+//! let mut pmm = BitmapFrameAlloc::new(storage);
 //! let mapper = HhdmPhysMapper;
 //! let mut vmm = Vmm::new(&mapper, &mut pmm);
 //! // Map, unmap, query...
 //! ```
 
+use crate::mmio::{MMIO_FLAGS, MmioRegion, mapped_len};
 use core::ptr::copy_nonoverlapping;
 use kernel_info::memory::{LAST_USERSPACE_ADDRESS, USERSPACE_END};
-use kernel_memory_addresses::{PageSize, PhysicalAddress, Size4K, VirtualAddress, VirtualPage};
+use kernel_memory_addresses::{
+    PageSize, PhysicalAddress, PhysicalPage, Size4K, VirtualAddress, VirtualPage,
+};
 use kernel_registers::cr3::Cr3;
 use kernel_registers::{LoadRegisterUnsafe, StoreRegisterUnsafe};
-use kernel_vmem::address_space::{AddressSpaceMapOneError, AddressSpaceMapRegionError, MapSize};
+use kernel_vmem::address_space::{
+    AddressSpaceMapOneError, AddressSpaceMapRegionError, MapSize, PromotionStats,
+};
 use kernel_vmem::{AddressSpace, PhysFrameAlloc, PhysMapper};
 use kernel_vmem::{VirtualMemoryPageBits, invalidate_tlb_page};
 
@@ -84,6 +89,12 @@ impl<'m, M: PhysMapper, A: PhysFrameAlloc> Vmm<'m, M, A> {
         self.ptables.query(va)
     }
 
+    /// Leaf flags of the mapping at `va`, if mapped.
+    #[must_use]
+    pub fn query_flags(&self, va: VirtualAddress) -> Option<VirtualMemoryPageBits> {
+        self.ptables.query_flags(va)
+    }
+
     /// Map **one** page of size `S` with `leaf_flags`, creating parents with `nonleaf_flags`.
     #[allow(clippy::missing_errors_doc, clippy::missing_panics_doc)]
     pub fn map_one<S: MapSize>(
@@ -129,6 +140,76 @@ impl<'m, M: PhysMapper, A: PhysFrameAlloc> Vmm<'m, M, A> {
         self.ptables.unmap_region(va, len);
     }
 
+    /// Merge runs of small leaves in `[va .. va+len)` into large ones and free
+    /// the emptied tables; see [`AddressSpace::promote_large_pages`].
+    ///
+    /// The caller must flush the TLB, global entries included, before the
+    /// freed frames are allocated again.
+    pub fn promote_large_pages(&mut self, va: VirtualAddress, len: u64) -> PromotionStats {
+        self.ptables.promote_large_pages(self.alloc, va, len)
+    }
+
+    /// Map the device memory `[pa .. pa+len)` uncached at the page-aligned
+    /// kernel address `va`; see [`mmio`](crate::mmio).
+    ///
+    /// The mapping occupies [`mapped_len(pa, len)`](mapped_len) bytes from `va`.
+    ///
+    /// # Errors
+    /// - [`VmmError::Unaligned`] if `va` is not page aligned.
+    /// - [`VmmError::InvalidRange`] if `len` is zero.
+    /// - [`VmmError::OutOfMemory`] if page tables could not be allocated.
+    ///
+    /// # Panics
+    /// If `va` is not a kernel address.
+    pub fn map_mmio(
+        &mut self,
+        va: VirtualAddress,
+        pa: PhysicalAddress,
+        len: u64,
+    ) -> Result<MmioRegion, VmmError> {
+        assert!(AllocationTarget::Kernel.matches(va));
+        if !va.as_u64().is_multiple_of(Size4K::SIZE) {
+            return Err(VmmError::Unaligned);
+        }
+        if len == 0 {
+            return Err(VmmError::InvalidRange);
+        }
+
+        let nonleaf = VirtualMemoryPageBits::new()
+            .with_present(true)
+            .with_writable(true);
+        let page_pa = pa_aligned_4k(pa);
+        let span = mapped_len(pa, len);
+        for off in (0..span / Size4K::SIZE).map(|i| i * Size4K::SIZE) {
+            self.ptables.map_one::<A, Size4K>(
+                self.alloc,
+                va + off,
+                page_pa + off,
+                nonleaf,
+                MMIO_FLAGS,
+            )?;
+        }
+
+        let offset = pa.as_u64() - page_pa.as_u64();
+        Ok(unsafe { MmioRegion::from_raw(va + offset, pa, len) })
+    }
+
+    /// Remove the mapping of `region` and invalidate it in the local TLB.
+    ///
+    /// # Errors
+    /// [`VmmError::UnmapFailed`] if a page of the region was not mapped.
+    #[allow(clippy::needless_pass_by_value)] // the region is unusable afterwards
+    pub fn unmap_mmio(&mut self, region: MmioRegion) -> Result<(), VmmError> {
+        let page_va = region.base().as_u64() & !(Size4K::SIZE - 1);
+        let span = mapped_len(region.phys(), region.len());
+        for off in (0..span / Size4K::SIZE).map(|i| i * Size4K::SIZE) {
+            let va = VirtualAddress::new(page_va + off);
+            self.ptables.unmap_one(va).map_err(VmmError::UnmapFailed)?;
+            self.invlpg(VirtualPage::<Size4K>::containing_address(va));
+        }
+        Ok(())
+    }
+
     /// Convenience: map a **per-page** region using freshly allocated 4K frames (no PA contiguity).
     ///
     /// Leaves `guard` bytes at the beginning **unmapped** (for stacks).
@@ -156,13 +237,45 @@ impl<'m, M: PhysMapper, A: PhysFrameAlloc> Vmm<'m, M, A> {
             };
 
             let pa = pp.base();
-            self.ptables
-                .map_one::<A, Size4K>(self.alloc, va, pa, nonleaf, leaf)?;
+            if let Err(e) = self
+                .ptables
+                .map_one::<A, Size4K>(self.alloc, va, pa, nonleaf, leaf)
+            {
+                self.alloc.free_4k(pp);
+                return Err(e.into());
+            }
         }
 
         Ok(())
     }
 
+    /// Unmap the 4 KiB pages in `[va .. va+len)` and free every frame for
+    /// which `release(frame)` holds. Unmapped pages are skipped.
+    ///
+    /// Each page is invalidated in the local TLB before its frame is freed,
+    /// global or not, so this CPU can not reach a reused frame through a
+    /// stale entry. Other CPUs must still be told.
+    pub fn unmap_4k_pages_release(
+        &mut self,
+        va: VirtualAddress,
+        len: u64,
+        mut release: impl FnMut(PhysicalPage<Size4K>) -> bool,
+    ) {
+        for i in 0..len.div_ceil(Size4K::SIZE) {
+            let va = va + i * Size4K::SIZE;
+            let Some(pa) = self.ptables.query(va) else {
+                continue;
+            };
+            if self.ptables.unmap_one(va).is_ok() {
+                self.invlpg(VirtualPage::<Size4K>::containing_address(va));
+                let frame = pa.page::<Size4K>();
+                if release(frame) {
+                    self.alloc.free_4k(frame);
+                }
+            }
+        }
+    }
+
     /// Copy a kernel slice into an already **mapped** user region.
     ///
     /// # Safety
@@ -202,6 +315,46 @@ impl<'m, M: PhysMapper, A: PhysFrameAlloc> Vmm<'m, M, A> {
         Ok(())
     }
 
+    /// Set the writable and no-execute bits of every leaf in `[va_start .. va_start+len)`,
+    /// keeping the physical pages and all other attributes.
+    ///
+    /// Large (2 MiB / 1 GiB) leaves are changed as a whole, so the region should
+    /// not split one; leaves reaching past its end are changed entirely.
+    ///
+    /// # Errors
+    /// [`VmmError::Unaligned`] if `va_start` is not 4 KiB aligned,
+    /// [`VmmError::Unmapped`] at the first hole in the region; leaves before it
+    /// have already been changed.
+    pub fn protect_region(
+        &mut self,
+        va_start: VirtualAddress,
+        len: u64,
+        writable: bool,
+        no_execute: bool,
+    ) -> Result<(), VmmError> {
+        if va_start.as_u64() & (Size4K::SIZE - 1) != 0 {
+            return Err(VmmError::Unaligned);
+        }
+
+        let end = va_start
+            .as_u64()
+            .checked_add(len)
+            .ok_or(VmmError::InvalidRange)?;
+        let mut va = va_start;
+        while va.as_u64() < end {
+            let leaf = self
+                .ptables
+                .protect_one(va, writable, no_execute)
+                .map_err(|_| VmmError::Unmapped)?;
+            self.invlpg(VirtualPage::<Size4K>::containing_address(va));
+
+            // Continue at the start of the next leaf.
+            let next = (va.as_u64() & !(leaf - 1)) + leaf;
+            va = VirtualAddress::new(next);
+        }
+        Ok(())
+    }
+
     /// Change per-page protection from RW to RX by unmapping & remapping with the same PA.
     /// Works for 4K pages created by `map_anon_4k_pages`.v
     #[allow(clippy::missing_errors_doc)]
@@ -328,7 +481,7 @@ const fn pa_aligned_4k(pa: PhysicalAddress) -> PhysicalAddress {
     pa.page::<Size4K>().base()
 }
 
-#[derive(Debug, thiserror::Error)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, thiserror::Error)]
 pub enum VmmError {
     #[error("out of memory")]
     OutOfMemory,
@@ -1,4 +1,21 @@
 //! # Kerrnel Boot Information
+//!
+//! ## Versioning
+//!
+//! Loader and kernel are built separately, and a kernel that reads a
+//! [`KernelBootInfo`] of another layout fails in confusing ways. Every boot
+//! info therefore starts with a [`BootInfoHeader`] whose layout never
+//! changes: a magic value, the [`BOOT_INFO_VERSION`] and size the loader was
+//! built with, and the [`BootCapabilities`] naming the optional sections it
+//! filled in. The kernel [checks](BootInfoHeader::check) the header before it
+//! reads anything else and refuses to boot on a mismatch.
+//!
+//! Any change to [`KernelBootInfo`] or to a type it contains must bump
+//! [`BOOT_INFO_VERSION`]; the size assertion next to it is a reminder.
+//! Optional sections are read through the accessors of [`KernelBootInfo`],
+//! which return nothing unless the capability says the section is valid.
+
+use core::fmt;
 
 /// Kernel function pointer.
 ///
@@ -7,22 +24,378 @@
 /// (PE/COFF) application.
 pub type KernelEntryFn = extern "win64" fn(*const KernelBootInfo) -> !;
 
+/// `"OSBOOTIF"` in little-endian; the first bytes of every [`KernelBootInfo`].
+pub const BOOT_INFO_MAGIC: u64 = u64::from_le_bytes(*b"OSBOOTIF");
+
+/// Layout version of [`KernelBootInfo`].
+pub const BOOT_INFO_VERSION: u32 = 3;
+
+// Changed the layout? Bump BOOT_INFO_VERSION, then update the size here.
+const _: () = assert!(size_of::<KernelBootInfo>() == 896);
+
 /// Information the kernel needs right after `ExitBootServices`.
 /// Keep this `#[repr(C)]` and prefer fixed-size integers over `u64` at the ABI boundary.
 #[repr(C)]
 #[derive(Clone)]
 pub struct KernelBootInfo {
+    /// Identifies the layout of the rest; see the [module docs](self).
+    pub header: BootInfoHeader,
+
     /// Memory map information.
     pub mmap: UefiMemoryMapInfo,
 
     /// RSDP (ACPI 2.0+) physical address, or 0 if not provided.
+    ///
+    /// Valid with [`BootCapabilities::RSDP`].
     pub rsdp_addr: u64,
 
     /// Framebuffer information, passed from UEFI GOP.
     pub fb: FramebufferInfo,
 
-    /// Userland binaries
+    /// Userland binaries.
+    ///
+    /// Valid with [`BootCapabilities::USERLAND`].
     pub userland: UserBundleInfo,
+
+    /// Physical address of the kernel command line (UTF-8, not NUL-terminated),
+    /// or 0 if there is none. Lies below [`HHDM_SIZE`](crate::memory::HHDM_SIZE),
+    /// so the kernel can read it through the HHDM.
+    ///
+    /// Valid with [`BootCapabilities::CMDLINE`].
+    pub cmdline_ptr: u64,
+
+    /// Length of the kernel command line in bytes.
+    pub cmdline_len: u64,
+
+    /// Additional files the loader placed in memory (fonts, microcode, ...).
+    ///
+    /// Valid with [`BootCapabilities::MODULES`].
+    pub modules: BootModules,
+
+    /// Where the loader mapped the kernel's `PT_LOAD` segments.
+    pub kernel_segments: KernelSegments,
+
+    /// Memory for allocations before the kernel's frame allocator exists.
+    ///
+    /// Valid with [`BootCapabilities::ARENA`].
+    pub arena: BootArenaInfo,
+
+    /// How far the loader moved the kernel from its link address, in bytes.
+    ///
+    /// Valid with [`BootCapabilities::KASLR_SLIDE`].
+    pub kaslr_slide: u64,
+
+    /// Random bytes from the firmware, to seed the kernel's random number
+    /// generator.
+    ///
+    /// Valid with [`BootCapabilities::SEED`].
+    pub seed: [u8; 32],
+
+    /// Memory kept across warm reboots, for the kernel's panic records.
+    ///
+    /// Valid with [`BootCapabilities::PSTORE`].
+    pub pstore: PstoreInfo,
+}
+
+impl KernelBootInfo {
+    /// Physical address and length of the kernel command line, if there is one.
+    #[must_use]
+    pub const fn cmdline(&self) -> Option<(u64, u64)> {
+        if self.has(BootCapabilities::CMDLINE) && self.cmdline_ptr != 0 && self.cmdline_len != 0 {
+            Some((self.cmdline_ptr, self.cmdline_len))
+        } else {
+            None
+        }
+    }
+
+    /// The boot modules; empty if the loader passed none.
+    #[must_use]
+    pub fn modules(&self) -> &[BootModule] {
+        if self.has(BootCapabilities::MODULES) {
+            self.modules.as_slice()
+        } else {
+            &[]
+        }
+    }
+
+    /// Physical address of the ACPI RSDP, if the loader found one.
+    #[must_use]
+    pub const fn rsdp(&self) -> Option<u64> {
+        if self.has(BootCapabilities::RSDP) && self.rsdp_addr != 0 {
+            Some(self.rsdp_addr)
+        } else {
+            None
+        }
+    }
+
+    /// The userland bundle, unless a boot module replaces it.
+    #[must_use]
+    pub const fn userland(&self) -> Option<&UserBundleInfo> {
+        if self.has(BootCapabilities::USERLAND) && self.userland.length != 0 {
+            Some(&self.userland)
+        } else {
+            None
+        }
+    }
+
+    /// The early boot arena, if the loader set one aside.
+    #[must_use]
+    pub const fn arena(&self) -> Option<&BootArenaInfo> {
+        if self.has(BootCapabilities::ARENA) {
+            Some(&self.arena)
+        } else {
+            None
+        }
+    }
+
+    /// How far the kernel was moved from its link address, if it was.
+    #[must_use]
+    pub const fn kaslr_slide(&self) -> Option<u64> {
+        if self.has(BootCapabilities::KASLR_SLIDE) {
+            Some(self.kaslr_slide)
+        } else {
+            None
+        }
+    }
+
+    /// Random bytes from the firmware, if it provided any.
+    #[must_use]
+    pub const fn seed(&self) -> Option<&[u8; 32]> {
+        if self.has(BootCapabilities::SEED) {
+            Some(&self.seed)
+        } else {
+            None
+        }
+    }
+
+    /// The persistent store, if the loader could reserve it.
+    #[must_use]
+    pub const fn pstore(&self) -> Option<&PstoreInfo> {
+        if self.has(BootCapabilities::PSTORE) {
+            Some(&self.pstore)
+        } else {
+            None
+        }
+    }
+
+    const fn has(&self, capability: BootCapabilities) -> bool {
+        self.header.capabilities.contains(capability)
+    }
+}
+
+/// The start of every [`KernelBootInfo`]; its layout never changes.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct BootInfoHeader {
+    /// Always [`BOOT_INFO_MAGIC`].
+    pub magic: u64,
+    /// The [`BOOT_INFO_VERSION`] the loader was built with.
+    pub version: u32,
+    /// Size of the whole [`KernelBootInfo`] in bytes, header included.
+    pub size: u32,
+    /// The optional sections the loader filled in.
+    pub capabilities: BootCapabilities,
+}
+
+impl BootInfoHeader {
+    /// The header of a [`KernelBootInfo`] of this build.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn new(capabilities: BootCapabilities) -> Self {
+        Self {
+            magic: BOOT_INFO_MAGIC,
+            version: BOOT_INFO_VERSION,
+            size: size_of::<KernelBootInfo>() as u32,
+            capabilities,
+        }
+    }
+
+    /// Whether the boot info was written by a loader of this build's layout.
+    ///
+    /// # Errors
+    /// The first field that does not match; see [`BootInfoError`].
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn check(&self) -> Result<(), BootInfoError> {
+        if self.magic != BOOT_INFO_MAGIC {
+            return Err(BootInfoError::BadMagic(self.magic));
+        }
+        if self.version != BOOT_INFO_VERSION {
+            return Err(BootInfoError::Version {
+                found: self.version,
+                expected: BOOT_INFO_VERSION,
+            });
+        }
+        if self.size != size_of::<KernelBootInfo>() as u32 {
+            return Err(BootInfoError::Size {
+                found: self.size,
+                expected: size_of::<KernelBootInfo>() as u32,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Why a boot info cannot be used; see [`BootInfoHeader::check`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum BootInfoError {
+    /// The loader passed a null pointer.
+    Null,
+    /// The boot info does not start with [`BOOT_INFO_MAGIC`].
+    BadMagic(u64),
+    /// Loader and kernel were built with different layouts.
+    Version { found: u32, expected: u32 },
+    /// Same version, yet a different size: the layout changed without a
+    /// version bump.
+    Size { found: u32, expected: u32 },
+}
+
+impl fmt::Display for BootInfoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Null => f.write_str("the loader passed no boot info"),
+            Self::BadMagic(magic) => write!(f, "bad magic {magic:#018x}; not a boot info"),
+            Self::Version { found, expected } => write!(
+                f,
+                "boot info version {found}, but the kernel expects {expected}; rebuild the loader and the kernel together"
+            ),
+            Self::Size { found, expected } => write!(
+                f,
+                "boot info is {found} bytes, but the kernel expects {expected}"
+            ),
+        }
+    }
+}
+
+/// Optional sections of a [`KernelBootInfo`] the loader filled in.
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct BootCapabilities(u64);
+
+impl BootCapabilities {
+    /// No optional sections.
+    pub const NONE: Self = Self(0);
+    /// [`KernelBootInfo::cmdline_ptr`] and `cmdline_len`.
+    pub const CMDLINE: Self = Self(1 << 0);
+    /// [`KernelBootInfo::modules`].
+    pub const MODULES: Self = Self(1 << 1);
+    /// [`KernelBootInfo::rsdp_addr`].
+    pub const RSDP: Self = Self(1 << 2);
+    /// [`KernelBootInfo::userland`].
+    pub const USERLAND: Self = Self(1 << 3);
+    /// [`KernelBootInfo::arena`].
+    pub const ARENA: Self = Self(1 << 4);
+    /// [`KernelBootInfo::kaslr_slide`].
+    pub const KASLR_SLIDE: Self = Self(1 << 5);
+    /// [`KernelBootInfo::seed`].
+    pub const SEED: Self = Self(1 << 6);
+    /// [`KernelBootInfo::pstore`].
+    pub const PSTORE: Self = Self(1 << 7);
+
+    const NAMES: [(Self, &str); 8] = [
+        (Self::CMDLINE, "cmdline"),
+        (Self::MODULES, "modules"),
+        (Self::RSDP, "rsdp"),
+        (Self::USERLAND, "userland"),
+        (Self::ARENA, "arena"),
+        (Self::KASLR_SLIDE, "kaslr_slide"),
+        (Self::SEED, "seed"),
+        (Self::PSTORE, "pstore"),
+    ];
+
+    #[must_use]
+    pub const fn from_bits(bits: u64) -> Self {
+        Self(bits)
+    }
+
+    #[must_use]
+    pub const fn bits(self) -> u64 {
+        self.0
+    }
+
+    /// Both sets of capabilities.
+    #[must_use]
+    pub const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    /// `self`, plus `other` if `condition` holds.
+    #[must_use]
+    pub const fn union_if(self, other: Self, condition: bool) -> Self {
+        if condition { self.union(other) } else { self }
+    }
+
+    /// Whether all of `other` is set.
+    #[must_use]
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+/// Space-separated names, e.g. `cmdline arena`, or `-` for none.
+impl fmt::Display for BootCapabilities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0 == 0 {
+            return f.write_str("-");
+        }
+
+        let mut separator = "";
+        let mut known = 0;
+        for (capability, name) in Self::NAMES {
+            known |= capability.0;
+            if self.contains(capability) {
+                write!(f, "{separator}{name}")?;
+                separator = " ";
+            }
+        }
+
+        let unknown = self.0 & !known;
+        if unknown != 0 {
+            write!(f, "{separator}{unknown:#x}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Size of the [`BootArenaInfo`] region the loader reserves, in bytes.
+pub const BOOT_ARENA_SIZE: u64 = 128 * 1024;
+
+/// A region of free RAM the loader set aside for the kernel's early boot
+/// allocations, such as the frame allocator's bitmaps.
+///
+/// The region is page aligned, lies below [`HHDM_SIZE`](crate::memory::HHDM_SIZE)
+/// and is marked as loader data in the memory map, so the kernel does not
+/// mistake it for free memory. Once the kernel's frame allocator is up, the
+/// kernel hands the part it did not use to that allocator.
+#[repr(C)]
+#[derive(Clone)]
+pub struct BootArenaInfo {
+    /// Physical address of the first byte, or 0 if there is no arena.
+    pub phys_start: u64,
+    /// Length in bytes (a multiple of 4 KiB).
+    pub length: u64,
+}
+
+/// Physical address of the [`PstoreInfo`] region; fixed, so that every boot
+/// finds what the one before left there.
+pub const PSTORE_PHYS: u64 = 0x0380_0000;
+
+/// Size of the [`PstoreInfo`] region, in bytes.
+pub const PSTORE_SIZE: u64 = 16 * 1024;
+
+/// A region of RAM that survives a warm reboot.
+///
+/// The loader reserves it at [`PSTORE_PHYS`] on every boot and never clears
+/// it; the kernel keeps the report of its last panic in it. The region is
+/// page aligned, lies below [`HHDM_SIZE`](crate::memory::HHDM_SIZE)
+/// and is marked as loader data in the memory map. Firmware may still reuse
+/// or clear it, so its contents must be checked before use.
+#[repr(C)]
+#[derive(Clone)]
+pub struct PstoreInfo {
+    /// Physical address of the first byte, or 0 if there is no region.
+    pub phys_start: u64,
+    /// Length in bytes (a multiple of 4 KiB).
+    pub length: u64,
 }
 
 #[repr(C)]
@@ -65,6 +438,24 @@ pub struct FramebufferInfo {
 
     /// Pixel bit masks (only meaningful when `framebuffer_format == Bitmask`).
     pub framebuffer_masks: BootPixelMasks,
+
+    /// Number of GOP modes the firmware offered.
+    pub framebuffer_mode_count: u32,
+
+    /// How the loader chose this mode.
+    pub framebuffer_selection: BootModeSelection,
+}
+
+/// How the loader chose the GOP mode.
+#[repr(u32)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum BootModeSelection {
+    /// No mode qualified (or switching failed); the firmware's active mode was kept.
+    Firmware = 0,
+    /// The mode matches the configured preferred resolution.
+    Preferred = 1,
+    /// The preferred resolution was unavailable; this is the largest 32-bit linear mode.
+    Largest = 2,
 }
 
 /// Pixel format tag compatible with UEFI GOP.
@@ -105,3 +496,240 @@ pub struct UserBundleInfo {
     /// The number of bytes in memory.
     pub length: u64,
 }
+
+/// Maximum number of [`BootModule`]s the loader passes on.
+pub const MAX_BOOT_MODULES: usize = 8;
+
+/// Maximum length of a [`BootModule`] name in bytes.
+pub const BOOT_MODULE_NAME_LEN: usize = 32;
+
+/// A file the loader read from the ESP and left in memory for the kernel.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct BootModule {
+    /// Name from `boot.cfg` (UTF-8), padded with NUL bytes.
+    pub name: [u8; BOOT_MODULE_NAME_LEN],
+    /// Physical address of the first byte.
+    pub bytes_ptr: u64,
+    /// The number of bytes in memory.
+    pub length: u64,
+}
+
+impl BootModule {
+    /// An unused slot.
+    pub const EMPTY: Self = Self {
+        name: [0; BOOT_MODULE_NAME_LEN],
+        bytes_ptr: 0,
+        length: 0,
+    };
+
+    /// The module's name, without padding.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        let len = self
+            .name
+            .iter()
+            .position(|&b| b == 0)
+            .unwrap_or(BOOT_MODULE_NAME_LEN);
+        core::str::from_utf8(&self.name[..len]).unwrap_or_default()
+    }
+}
+
+/// The boot modules, in the order `boot.cfg` lists them.
+#[repr(C)]
+#[derive(Clone)]
+pub struct BootModules {
+    /// Number of used entries at the start of `modules`.
+    pub count: u64,
+    pub modules: [BootModule; MAX_BOOT_MODULES],
+}
+
+impl BootModules {
+    /// No modules.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            count: 0,
+            modules: [BootModule::EMPTY; MAX_BOOT_MODULES],
+        }
+    }
+
+    /// Append a module.
+    ///
+    /// Returns `false` if all slots are taken or `name` is empty or longer
+    /// than [`BOOT_MODULE_NAME_LEN`] bytes.
+    pub fn push(&mut self, name: &str, bytes_ptr: u64, length: u64) -> bool {
+        let count = self.as_slice().len();
+        if count == MAX_BOOT_MODULES || name.is_empty() || name.len() > BOOT_MODULE_NAME_LEN {
+            return false;
+        }
+
+        let module = &mut self.modules[count];
+        module.name = [0; BOOT_MODULE_NAME_LEN];
+        module.name[..name.len()].copy_from_slice(name.as_bytes());
+        module.bytes_ptr = bytes_ptr;
+        module.length = length;
+        self.count += 1;
+        true
+    }
+
+    /// The used entries.
+    #[must_use]
+    pub fn as_slice(&self) -> &[BootModule] {
+        let count =
+            usize::try_from(self.count).map_or(MAX_BOOT_MODULES, |n| n.min(MAX_BOOT_MODULES));
+        &self.modules[..count]
+    }
+}
+
+impl Default for BootModules {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Maximum number of [`KernelSegment`]s the loader passes on.
+pub const MAX_KERNEL_SEGMENTS: usize = 8;
+
+/// One `PT_LOAD` segment of the kernel image, as mapped by the loader.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct KernelSegment {
+    /// First virtual address (4 KiB aligned).
+    pub virt_start: u64,
+    /// Physical address backing `virt_start`.
+    pub phys_start: u64,
+    /// Mapped length in bytes (a multiple of 4 KiB).
+    pub length: u64,
+    /// ELF `p_flags`: [`PF_X`](Self::PF_X), [`PF_W`](Self::PF_W), [`PF_R`](Self::PF_R).
+    pub flags: u32,
+}
+
+impl KernelSegment {
+    /// Executable.
+    pub const PF_X: u32 = 1 << 0;
+    /// Writable.
+    pub const PF_W: u32 = 1 << 1;
+    /// Readable.
+    pub const PF_R: u32 = 1 << 2;
+
+    /// An unused slot.
+    pub const EMPTY: Self = Self {
+        virt_start: 0,
+        phys_start: 0,
+        length: 0,
+        flags: 0,
+    };
+
+    #[must_use]
+    pub const fn is_executable(&self) -> bool {
+        self.flags & Self::PF_X != 0
+    }
+
+    #[must_use]
+    pub const fn is_writable(&self) -> bool {
+        self.flags & Self::PF_W != 0
+    }
+
+    /// One past the last mapped virtual address.
+    #[must_use]
+    pub const fn virt_end(&self) -> u64 {
+        self.virt_start.saturating_add(self.length)
+    }
+
+    /// Whether `addr` lies inside the segment.
+    #[must_use]
+    pub const fn contains(&self, addr: u64) -> bool {
+        addr >= self.virt_start && addr < self.virt_end()
+    }
+}
+
+/// The kernel's segments, in program header order.
+#[repr(C)]
+#[derive(Clone)]
+pub struct KernelSegments {
+    /// Number of used entries at the start of `segments`.
+    pub count: u64,
+    pub segments: [KernelSegment; MAX_KERNEL_SEGMENTS],
+}
+
+impl KernelSegments {
+    /// No segments.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            count: 0,
+            segments: [KernelSegment::EMPTY; MAX_KERNEL_SEGMENTS],
+        }
+    }
+
+    /// Append a segment.
+    ///
+    /// Returns `false` if all slots are taken.
+    pub fn push(&mut self, segment: KernelSegment) -> bool {
+        let count = self.as_slice().len();
+        if count == MAX_KERNEL_SEGMENTS {
+            return false;
+        }
+        self.segments[count] = segment;
+        self.count += 1;
+        true
+    }
+
+    /// The used entries.
+    #[must_use]
+    pub fn as_slice(&self) -> &[KernelSegment] {
+        let count =
+            usize::try_from(self.count).map_or(MAX_KERNEL_SEGMENTS, |n| n.min(MAX_KERNEL_SEGMENTS));
+        &self.segments[..count]
+    }
+}
+
+impl Default for KernelSegments {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_of_this_build_passes() {
+        assert_eq!(BootInfoHeader::new(BootCapabilities::NONE).check(), Ok(()));
+    }
+
+    #[test]
+    fn mismatches_are_reported() {
+        let good = BootInfoHeader::new(BootCapabilities::NONE);
+
+        let header = BootInfoHeader { magic: 0, ..good };
+        assert_eq!(header.check(), Err(BootInfoError::BadMagic(0)));
+
+        let header = BootInfoHeader {
+            version: BOOT_INFO_VERSION + 1,
+            ..good
+        };
+        assert!(matches!(header.check(), Err(BootInfoError::Version { .. })));
+
+        let header = BootInfoHeader {
+            size: good.size - 8,
+            ..good
+        };
+        assert!(matches!(header.check(), Err(BootInfoError::Size { .. })));
+    }
+
+    #[test]
+    fn capabilities_display_their_names() {
+        let caps = BootCapabilities::CMDLINE.union(BootCapabilities::ARENA);
+        assert!(caps.contains(BootCapabilities::ARENA));
+        assert!(!caps.contains(BootCapabilities::RSDP));
+        assert_eq!(caps.to_string(), "cmdline arena");
+        assert_eq!(BootCapabilities::NONE.to_string(), "-");
+        assert_eq!(
+            BootCapabilities::from_bits(1 << 63).to_string(),
+            "0x8000000000000000"
+        );
+    }
+}
@@ -21,6 +21,7 @@
 //! Defines the bootloader-to-kernel handoff interface:
 //! * **Kernel Entry Point**: Function signature and calling convention
 //! * **Boot Data Structures**: Memory map, ACPI information, framebuffer details
+//! * **Versioning**: A header the kernel checks before trusting the rest
 //! * **ABI Stability**: C-compatible structures for cross-component communication
 //! * **UEFI Integration**: Direct compatibility with UEFI GOP and memory services
 //!
@@ -148,21 +149,33 @@
 //!
 //! ### Build Script Integration
 //! ```rust
-//! // In build.rs
+//! // In build.rs, while generating the linker script
 //! use kernel_info::memory::{KERNEL_BASE, PHYS_LOAD};
 //!
-//! println!("cargo:rustc-link-arg=--defsym=KERNEL_BASE={:#x}", KERNEL_BASE.as_u64());
-//! println!("cargo:rustc-link-arg=--defsym=PHYS_LOAD={:#x}", PHYS_LOAD.as_u64());
+//! let script = format!(
+//!     "KBASE = {:#x};\nPLOAD = {:#x};\n",
+//!     KERNEL_BASE.as_u64(),
+//!     PHYS_LOAD.as_u64()
+//! );
 //! ```
 //!
 //! ### Bootloader Integration
 //! ```rust,ignore
-//! use kernel_info::boot::{KernelBootInfo, KernelEntryFn};
+//! use kernel_info::boot::{BootCapabilities, BootInfoHeader, KernelBootInfo, KernelEntryFn};
 //!
 //! let boot_info = KernelBootInfo {
+//!     header: BootInfoHeader::new(BootCapabilities::ARENA /* | sections filled in */),
 //!     mmap: /* memory map info */,
 //!     rsdp_addr: /* ACPI root */,
 //!     fb: /* framebuffer info */,
+//!     userland: /* userland bundle */,
+//!     cmdline_ptr: /* kernel command line */,
+//!     cmdline_len: /* command line length */,
+//!     modules: /* files listed in boot.cfg */,
+//!     kernel_segments: /* PT_LOAD ranges of the kernel image */,
+//!     arena: /* memory for early kernel allocations */,
+//!     kaslr_slide: 0,
+//!     seed: /* random bytes from the firmware */,
 //! };
 //!
 //! let kernel_entry: KernelEntryFn = /* kernel entry point */;
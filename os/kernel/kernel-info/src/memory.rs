@@ -13,18 +13,19 @@ pub const LAST_USERSPACE_ADDRESS: VirtualAddress = VirtualAddress::new(0x0000_ff
 /// access physical memory via a fixed offset.
 pub const HHDM_BASE: VirtualAddress = VirtualAddress::new(0xffff_8880_0000_0000);
 
-/// Where the kernel executes (VMA), matches your linker script.
+/// Amount of physical memory the loader maps at [`HHDM_BASE`] (one 1 GiB page).
+pub const HHDM_SIZE: u64 = 1 << 30;
+
+/// Where the kernel executes (VMA).
 ///
 /// # Kernel Build
-/// This information is sourced in the kernel's `build.rs` to configure
-/// the linker.
+/// The kernel's `build.rs` generates its linker script from this.
 pub const KERNEL_BASE: VirtualAddress = VirtualAddress::new(0xffff_ffff_8000_0000);
 
 /// Where you place the bytes in *physical* memory (LMA) before paging.
 ///
 /// # Kernel Build
-/// This information is sourced in the kernel's `build.rs` to configure
-/// the linker.
+/// The kernel's `build.rs` generates its linker script from this.
 pub const PHYS_LOAD: PhysicalAddress = PhysicalAddress::new(0x0010_0000); // 1 MiB
 
 /// Keep a tiny identity map so the paging switch code remains executable
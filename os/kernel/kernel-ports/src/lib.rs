@@ -0,0 +1,372 @@
+//! # `kernel_ports` — typed x86 I/O port access
+//!
+//! Access to the x86/x86-64 I/O port address space through typed handles
+//! around the `in` and `out` instructions, for legacy devices and system
+//! controllers that use port-mapped I/O rather than memory-mapped I/O (MMIO).
+//!
+//! ## Overview
+//!
+//! The x86 architecture provides two distinct address spaces for device communication:
+//! - **I/O Port Space**: Legacy 16-bit address space (0x0000-0xFFFF) accessed via
+//!   special `in`/`out` instructions
+//! - **Memory Space**: Normal memory addresses accessed via standard load/store
+//!   instructions (MMIO)
+//!
+//! This crate handles the I/O port space, which is primarily used by legacy
+//! devices and some system controllers that maintain backward compatibility.
+//!
+//! ### Common Port Ranges
+//! ```text
+//! 0x0000-0x001F   DMA Controllers
+//! 0x0020-0x0021   Programmable Interrupt Controller (PIC) #1
+//! 0x0040-0x0043   Programmable Interval Timer (PIT)
+//! 0x0060-0x0064   Keyboard Controller
+//! 0x0070-0x0071   CMOS/RTC
+//! 0x00A0-0x00A1   PIC #2
+//! 0x00F0-0x00FF   Math Coprocessor
+//! 0x0170-0x0177   Secondary IDE Controller
+//! 0x01F0-0x01F7   Primary IDE Controller
+//! 0x0278-0x027A   Parallel Port #2
+//! 0x02E8-0x02EF   Serial Port #4
+//! 0x02F8-0x02FF   Serial Port #2
+//! 0x0378-0x037A   Parallel Port #1
+//! 0x03E8-0x03EF   Serial Port #3
+//! 0x03F0-0x03F7   Floppy Disk Controller
+//! 0x03F8-0x03FF   Serial Port #1
+//! 0x0402          QEMU debug console
+//! 0x0CF8-0x0CFF   PCI configuration mechanism #1
+//! ```
+//!
+//! ## Types
+//!
+//! * [`Port<T>`]: a register that is read and written, `T` being [`u8`],
+//!   [`u16`] or [`u32`]
+//! * [`PortReadOnly<T>`], [`PortWriteOnly<T>`]: registers that only go one
+//!   way, such as a status register or a command register
+//! * [`PortRange`]: the register block of a device, handing out the ports at
+//!   an offset; debug builds check that the access stays inside the block
+//!
+//! Handles are plain port numbers and `Copy`; they are meant to be `const`s
+//! next to the driver that owns the device.
+//!
+//! ```no_run
+//! use kernel_ports::{PortRange, PortReadOnly, PortWriteOnly};
+//!
+//! const PS2_STATUS: PortReadOnly<u8> = PortReadOnly::new(0x64);
+//! const DEBUGCON: PortWriteOnly<u8> = PortWriteOnly::new(0x402);
+//!
+//! let status = unsafe { PS2_STATUS.read() };
+//! unsafe { DEBUGCON.write(b'H') };
+//!
+//! let com1 = PortRange::new(0x3F8, 8);
+//! let line_status = unsafe { com1.read_only::<u8>(5).read() };
+//! ```
+//!
+//! ## Safety Requirements
+//!
+//! Creating a handle is safe; reading and writing through it is not. Callers
+//! must ensure:
+//!
+//! ### Privilege Requirements
+//! * **Ring 0 Execution**: Most secure when running in kernel mode (CPL 0)
+//! * **I/O Permission**: If not in ring 0, IOPL bits or I/O permission bitmap
+//!   must allow access to the specific port
+//!
+//! ### Hardware Safety
+//! * **Correct Port**: Target the intended device register, not arbitrary addresses
+//! * **Device Presence**: Ensure the device exists and is properly initialized
+//! * **Protocol Compliance**: Follow device-specific communication protocols
+//! * **Timing Requirements**: Respect device timing constraints and handshakes
+//!
+//! ### Concurrency Safety
+//! * **Mutual Exclusion**: Coordinate with interrupt handlers and other threads
+//! * **Atomic Sequences**: Protect multi-step device interactions from interruption
+//! * **Driver Coordination**: Ensure only one driver controls each device
+//!
+//! ### Memory Ordering
+//! * **I/O Ordering**: `in`/`out` instructions are ordered relative to each other
+//! * **Memory Barriers**: Insert appropriate fences when coordinating with MMIO
+//! * **Compiler Barriers**: Prevent unwanted optimization of I/O sequences
+//!
+//! ## Future Extensions
+//!
+//! Additional I/O operations may be added as needed:
+//! * String operations (`insb`/`outsb`) for bulk transfers
+//! * I/O delay helpers for timing-sensitive devices
+
+#![cfg_attr(not(any(test, doctest)), no_std)]
+#![allow(unsafe_code)]
+
+use core::fmt;
+use core::marker::PhantomData;
+
+mod sealed {
+    pub trait Sealed {}
+    impl Sealed for u8 {}
+    impl Sealed for u16 {}
+    impl Sealed for u32 {}
+}
+
+/// A value the CPU moves through an I/O port in one instruction: [`u8`],
+/// [`u16`] or [`u32`].
+pub trait PortValue: sealed::Sealed + Copy {
+    /// Bytes the access covers, starting at the port.
+    #[allow(clippy::cast_possible_truncation)]
+    const WIDTH: u16 = size_of::<Self>() as u16;
+
+    /// Read from `port`.
+    ///
+    /// # Safety
+    /// You must uphold **all** of the following:
+    /// - **Privilege:** Execute at CPL0 **or** have I/O permission (IOPL/IO bitmap)
+    ///   that allows access to `port`; otherwise the CPU raises `#GP`.
+    /// - **Correct port:** `port` must be a readable register of the intended
+    ///   device; reading from the wrong port can yield undefined garbage or stall
+    ///   the device’s protocol.
+    /// - **Device presence:** The target device must exist and be decoding the
+    ///   address. Accesses to nonexistent ports may fault or hang on some systems.
+    /// - **Concurrency:** Coordinate with interrupt handlers/other CPUs that
+    ///   manipulate the same device/port to avoid tearing multi-step handshakes.
+    /// - **Ordering:** `in` orders with other I/O instructions but is **not** a
+    ///   general memory fence. If you must order this read with normal memory
+    ///   operations (e.g., reading a status port then consuming an MMIO buffer),
+    ///   insert the appropriate compiler/CPU fence.
+    unsafe fn read_from(port: u16) -> Self;
+
+    /// Write `self` to `port`.
+    ///
+    /// # Safety
+    /// You must uphold **all** of the following:
+    /// - **Privilege:** Execute at CPL0 **or** have I/O permission (IOPL/IO bitmap)
+    ///   that allows access to `port`. Otherwise the CPU raises `#GP`.
+    /// - **Correct port:** `port` must belong to the intended device and be in a
+    ///   valid state for this write. Writing the wrong port or wrong value can wedge
+    ///   the device or the system (e.g., disabling the PIC, reprogramming timers).
+    /// - **Device presence:** The target device must exist and be decoded on the
+    ///   bus. Some platforms hang on accesses to nonexistent ports.
+    /// - **Concurrency:** Coordinate with interrupt handlers and other CPUs/threads
+    ///   that touch the same device/port. Use your driver’s locking/serialization
+    ///   so register-level protocols aren’t violated.
+    /// - **Ordering:** `out` orders with respect to other I/O instructions to the
+    ///   same device but is **not** a general memory fence. If you need ordering
+    ///   with normal memory (e.g., MMIO buffers or shared memory), add an
+    ///   appropriate compiler/CPU fence around calls.
+    unsafe fn write_to(self, port: u16);
+}
+
+impl PortValue for u8 {
+    #[inline]
+    unsafe fn read_from(port: u16) -> Self {
+        let v: Self;
+        unsafe {
+            core::arch::asm!("in al, dx", in("dx") port, out("al") v, options(nomem, nostack, preserves_flags));
+        }
+        v
+    }
+
+    #[inline]
+    unsafe fn write_to(self, port: u16) {
+        unsafe {
+            core::arch::asm!("out dx, al", in("dx") port, in("al") self, options(nomem, nostack, preserves_flags));
+        }
+    }
+}
+
+impl PortValue for u16 {
+    #[inline]
+    unsafe fn read_from(port: u16) -> Self {
+        let v: Self;
+        unsafe {
+            core::arch::asm!("in ax, dx", in("dx") port, out("ax") v, options(nomem, nostack, preserves_flags));
+        }
+        v
+    }
+
+    #[inline]
+    unsafe fn write_to(self, port: u16) {
+        unsafe {
+            core::arch::asm!("out dx, ax", in("dx") port, in("ax") self, options(nomem, nostack, preserves_flags));
+        }
+    }
+}
+
+impl PortValue for u32 {
+    #[inline]
+    unsafe fn read_from(port: u16) -> Self {
+        let v: Self;
+        unsafe {
+            core::arch::asm!("in eax, dx", in("dx") port, out("eax") v, options(nomem, nostack, preserves_flags));
+        }
+        v
+    }
+
+    #[inline]
+    unsafe fn write_to(self, port: u16) {
+        unsafe {
+            core::arch::asm!("out dx, eax", in("dx") port, in("eax") self, options(nomem, nostack, preserves_flags));
+        }
+    }
+}
+
+macro_rules! port_type {
+    ($(#[$meta:meta])* $name:ident) => {
+        $(#[$meta])*
+        #[derive(Copy, Clone, Eq, PartialEq)]
+        pub struct $name<T: PortValue> {
+            port: u16,
+            _value: PhantomData<T>,
+        }
+
+        impl<T: PortValue> $name<T> {
+            #[must_use]
+            pub const fn new(port: u16) -> Self {
+                Self {
+                    port,
+                    _value: PhantomData,
+                }
+            }
+
+            /// The port number.
+            #[must_use]
+            pub const fn port(self) -> u16 {
+                self.port
+            }
+        }
+
+        impl<T: PortValue> fmt::Debug for $name<T> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "{}<u{}>({:#06x})", stringify!($name), T::WIDTH * 8, self.port)
+            }
+        }
+    };
+}
+
+port_type!(
+    /// An I/O port that is read and written.
+    Port
+);
+
+port_type!(
+    /// An I/O port that is only read, e.g. a status register.
+    PortReadOnly
+);
+
+port_type!(
+    /// An I/O port that is only written, e.g. a command register.
+    PortWriteOnly
+);
+
+impl<T: PortValue> Port<T> {
+    /// Read the port.
+    ///
+    /// # Safety
+    /// See [`PortValue::read_from`].
+    #[inline]
+    #[must_use]
+    pub unsafe fn read(self) -> T {
+        unsafe { T::read_from(self.port) }
+    }
+
+    /// Write `value` to the port.
+    ///
+    /// # Safety
+    /// See [`PortValue::write_to`].
+    #[inline]
+    pub unsafe fn write(self, value: T) {
+        unsafe { value.write_to(self.port) }
+    }
+}
+
+impl<T: PortValue> PortReadOnly<T> {
+    /// Read the port.
+    ///
+    /// # Safety
+    /// See [`PortValue::read_from`].
+    #[inline]
+    #[must_use]
+    pub unsafe fn read(self) -> T {
+        unsafe { T::read_from(self.port) }
+    }
+}
+
+impl<T: PortValue> PortWriteOnly<T> {
+    /// Write `value` to the port.
+    ///
+    /// # Safety
+    /// See [`PortValue::write_to`].
+    #[inline]
+    pub unsafe fn write(self, value: T) {
+        unsafe { value.write_to(self.port) }
+    }
+}
+
+/// A block of `len` consecutive ports starting at `base`, such as the
+/// registers of one device.
+///
+/// Registers are addressed by their offset into the block. In debug builds,
+/// an access that does not fit into the block panics.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct PortRange {
+    base: u16,
+    len: u16,
+}
+
+impl PortRange {
+    /// # Panics
+    /// In debug builds, if the range runs past port `0xFFFF`.
+    #[must_use]
+    pub const fn new(base: u16, len: u16) -> Self {
+        debug_assert!(
+            base as u32 + len as u32 <= 0x1_0000,
+            "port range past the end of the I/O space"
+        );
+        Self { base, len }
+    }
+
+    /// The first port.
+    #[must_use]
+    pub const fn base(self) -> u16 {
+        self.base
+    }
+
+    /// Number of ports.
+    #[must_use]
+    pub const fn len(self) -> u16 {
+        self.len
+    }
+
+    #[must_use]
+    pub const fn is_empty(self) -> bool {
+        self.len == 0
+    }
+
+    /// The port at `offset`.
+    ///
+    /// # Panics
+    /// In debug builds, if a `T` at `offset` does not fit into the range.
+    #[must_use]
+    pub const fn port<T: PortValue>(self, offset: u16) -> Port<T> {
+        Port::new(self.at::<T>(offset))
+    }
+
+    /// The read-only port at `offset`; panics like [`Self::port`].
+    #[must_use]
+    pub const fn read_only<T: PortValue>(self, offset: u16) -> PortReadOnly<T> {
+        PortReadOnly::new(self.at::<T>(offset))
+    }
+
+    /// The write-only port at `offset`; panics like [`Self::port`].
+    #[must_use]
+    pub const fn write_only<T: PortValue>(self, offset: u16) -> PortWriteOnly<T> {
+        PortWriteOnly::new(self.at::<T>(offset))
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    const fn at<T: PortValue>(self, offset: u16) -> u16 {
+        debug_assert!(
+            offset as u32 + T::WIDTH as u32 <= self.len as u32,
+            "port access outside of the range"
+        );
+        (self.base as u32 + offset as u32) as u16
+    }
+}
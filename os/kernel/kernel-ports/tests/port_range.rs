@@ -0,0 +1,46 @@
+use kernel_ports::{PortRange, PortValue};
+
+const COM1: PortRange = PortRange::new(0x3F8, 8);
+
+#[test]
+fn ports_are_offsets_into_the_range() {
+    assert_eq!(COM1.port::<u8>(0).port(), 0x3F8);
+    assert_eq!(COM1.read_only::<u8>(5).port(), 0x3FD);
+    assert_eq!(COM1.write_only::<u16>(6).port(), 0x3FE);
+    assert_eq!(COM1.port::<u32>(4).port(), 0x3FC);
+}
+
+#[test]
+fn range_may_end_at_the_last_port() {
+    let top = PortRange::new(0xFFF8, 8);
+    assert_eq!(top.port::<u32>(4).port(), 0xFFFC);
+}
+
+#[test]
+fn widths_match_the_value() {
+    assert_eq!(u8::WIDTH, 1);
+    assert_eq!(u16::WIDTH, 2);
+    assert_eq!(u32::WIDTH, 4);
+}
+
+#[test]
+fn handles_print_width_and_port() {
+    assert_eq!(
+        format!("{:?}", COM1.read_only::<u8>(5)),
+        "PortReadOnly<u8>(0x03fd)"
+    );
+}
+
+#[test]
+#[should_panic(expected = "port access outside of the range")]
+#[cfg(debug_assertions)]
+fn access_past_the_end_panics() {
+    let _ = COM1.port::<u16>(7);
+}
+
+#[test]
+#[should_panic(expected = "port range past the end of the I/O space")]
+#[cfg(debug_assertions)]
+fn range_past_the_io_space_panics() {
+    let _ = PortRange::new(0xFFFC, 8);
+}
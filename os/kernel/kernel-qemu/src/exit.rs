@@ -0,0 +1,50 @@
+//! QEMU `isa-debug-exit` device.
+//!
+//! Started with `-device isa-debug-exit,iobase=0xf4,iosize=0x04`, QEMU
+//! terminates as soon as the guest writes a value to the device's port; the
+//! host process exits with status `(value << 1) | 1`.
+
+use kernel_ports::PortWriteOnly;
+
+/// I/O port of the `isa-debug-exit` device.
+pub const ISA_DEBUG_EXIT_PORT: u16 = 0xF4;
+
+/// Values written to the exit device.
+///
+/// They avoid `0`, which QEMU would turn into the ordinary exit status `1`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(u32)]
+pub enum QemuExitCode {
+    /// QEMU exits with status `33`.
+    Success = 0x10,
+    /// QEMU exits with status `35`.
+    Failed = 0x11,
+}
+
+impl QemuExitCode {
+    /// Exit status of the QEMU process after writing this code.
+    #[must_use]
+    pub const fn host_status(self) -> i32 {
+        ((self as i32) << 1) | 1
+    }
+}
+
+/// Ask QEMU to terminate with `code`.
+///
+/// Returns if there is no exit device, which ignores the write; the caller
+/// can then shut the machine down by other means.
+pub fn request_exit(code: QemuExitCode) {
+    unsafe { PortWriteOnly::new(ISA_DEBUG_EXIT_PORT).write(code as u32) };
+}
+
+/// Terminate QEMU with `code`.
+///
+/// Without the exit device, the write is ignored and the CPU halts for good.
+pub fn exit_qemu(code: QemuExitCode) -> ! {
+    request_exit(code);
+    loop {
+        unsafe {
+            core::arch::asm!("cli", "hlt", options(nomem, nostack));
+        }
+    }
+}
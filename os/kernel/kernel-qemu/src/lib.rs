@@ -66,6 +66,14 @@
 //! * **UTF-8 Encoding**: Proper Unicode character decomposition
 //! * **Error Resilience**: Best-effort output with graceful degradation
 //!
+//! ### Exit Device ([`exit_qemu`])
+//! Ends the emulation with a status code through QEMU's `isa-debug-exit`
+//! device (port `0xf4`), e.g. after an automated test run:
+//! * **Status Mapping**: Writing `code` makes QEMU exit with `(code << 1) | 1`
+//! * **Fallback**: [`request_exit`] returns without the device, so the
+//!   caller can power off instead of halting
+//! * **Always Available**: Not affected by the `enabled` feature
+//!
 //! ## Feature System
 //!
 //! The crate uses Cargo features to control compilation and runtime behavior:
@@ -180,37 +188,26 @@
 #![cfg_attr(not(any(test, doctest)), no_std)]
 #![allow(unsafe_code)]
 
+mod exit;
 mod logger;
 
+pub use exit::{ISA_DEBUG_EXIT_PORT, QemuExitCode, exit_qemu, request_exit};
 pub use logger::QemuLogger;
 
 #[cfg(feature = "enabled")]
 #[doc(hidden)]
 pub mod qemu_fmt {
     use core::fmt::{self, Write};
+    use kernel_ports::PortWriteOnly;
 
-    /// The port number for QEMU's debug port.
-    const QEMU_DEBUG_PORT: u16 = 0x402;
+    /// QEMU's debug port.
+    const QEMU_DEBUG_PORT: PortWriteOnly<u8> = PortWriteOnly::new(0x402);
 
     /// Write a single character to QEMU's debug port.
     #[allow(clippy::inline_always)]
     #[inline(always)]
     pub fn dbg_putc(c: u8) {
-        unsafe { outb(QEMU_DEBUG_PORT, c) }
-    }
-
-    /// Write to QEMU's port.
-    #[allow(clippy::inline_always)]
-    #[inline(always)]
-    unsafe fn outb(port: u16, val: u8) {
-        unsafe {
-            core::arch::asm!(
-            "out dx, al",
-            in("dx") port,
-            in("al") val,
-            options(nomem, preserves_flags)
-            );
-        }
+        unsafe { QEMU_DEBUG_PORT.write(c) }
     }
 
     // TODO: Model this as an actual sink for arbitrary port write
@@ -0,0 +1,122 @@
+//! # Descriptor Table Registers
+//!
+//! `GDTR` and `IDTR` hold the linear base address and limit of the Global and
+//! Interrupt Descriptor Tables. Both are read and written through the same
+//! 10-byte in-memory operand, [`DescriptorTablePointer`].
+//!
+//! Loading (`lgdt`/`lidt`) is privileged and makes the CPU use the table from
+//! then on, so the table must stay mapped for as long as it is active.
+//! Storing (`sgdt`/`sidt`) faults in user mode if `CR4.UMIP` is set.
+
+#[cfg(feature = "asm")]
+use crate::{LoadRegisterUnsafe, StoreRegisterUnsafe};
+use kernel_memory_addresses::VirtualAddress;
+
+/// Operand of `lgdt`/`lidt` and `sgdt`/`sidt`.
+///
+/// The CPU reads exactly `limit + 1` bytes of the table starting at `base`.
+#[repr(C, packed)]
+#[derive(Copy, Clone)]
+pub struct DescriptorTablePointer {
+    /// Size of the table **minus one** in bytes.
+    limit: u16,
+    /// Base **linear (virtual) address** of the table.
+    base: VirtualAddress,
+}
+
+impl DescriptorTablePointer {
+    #[must_use]
+    pub const fn new(base: VirtualAddress, limit: u16) -> Self {
+        Self { limit, base }
+    }
+
+    /// Describe `table`, using its size for the limit.
+    ///
+    /// # Panics
+    /// If `T` is larger than 64 KiB.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn for_table<T>(table: &T) -> Self {
+        assert!(size_of::<T>() > 0 && size_of::<T>() <= 1 << 16);
+        Self::new(
+            VirtualAddress::from_ptr(core::ptr::from_ref(table)),
+            (size_of::<T>() - 1) as u16,
+        )
+    }
+
+    /// Size of the table minus one, in bytes.
+    #[must_use]
+    pub const fn limit(&self) -> u16 {
+        self.limit
+    }
+
+    /// Linear base address of the table.
+    #[must_use]
+    pub const fn base(&self) -> VirtualAddress {
+        self.base
+    }
+
+    /// Whether this describes exactly `table`.
+    #[must_use]
+    pub fn is_table<T>(&self, table: &T) -> bool {
+        self.base() == VirtualAddress::from_ptr(core::ptr::from_ref(table))
+            && usize::from(self.limit()) + 1 == size_of::<T>()
+    }
+}
+
+impl core::fmt::Debug for DescriptorTablePointer {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("DescriptorTablePointer")
+            .field("limit", &self.limit())
+            .field("base", &self.base())
+            .finish()
+    }
+}
+
+/// GDTR — Global Descriptor Table Register.
+#[repr(transparent)]
+#[derive(Debug, Copy, Clone)]
+pub struct Gdtr(pub DescriptorTablePointer);
+
+/// IDTR — Interrupt Descriptor Table Register.
+#[repr(transparent)]
+#[derive(Debug, Copy, Clone)]
+pub struct Idtr(pub DescriptorTablePointer);
+
+/// `sxdt`/`lxdt` through a [`DescriptorTablePointer`] operand.
+macro_rules! descriptor_table_register {
+    ($ty:ident, $store:literal, $load:literal) => {
+        #[cfg(feature = "asm")]
+        impl LoadRegisterUnsafe for $ty {
+            #[inline]
+            unsafe fn load_unsafe() -> Self {
+                let mut ptr = DescriptorTablePointer::new(VirtualAddress::zero(), 0);
+                unsafe {
+                    core::arch::asm!(
+                        concat!($store, " [{}]"),
+                        in(reg) &raw mut ptr,
+                        options(nostack, preserves_flags)
+                    );
+                }
+                Self(ptr)
+            }
+        }
+
+        #[cfg(feature = "asm")]
+        impl StoreRegisterUnsafe for $ty {
+            #[inline]
+            unsafe fn store_unsafe(self) {
+                unsafe {
+                    core::arch::asm!(
+                        concat!($load, " [{}]"),
+                        in(reg) &raw const self.0,
+                        options(readonly, nostack, preserves_flags)
+                    );
+                }
+            }
+        }
+    };
+}
+
+descriptor_table_register!(Gdtr, "sgdt", "lgdt");
+descriptor_table_register!(Idtr, "sidt", "lidt");
@@ -0,0 +1,94 @@
+//! # Exception Table
+//!
+//! Instructions that may fault in a way the kernel expects, such as `rdmsr`
+//! on an MSR the CPU does not implement, are listed in the `.extable`
+//! section together with the address execution continues at instead. A
+//! fault handler looks up the faulting RIP with [`search`] and, on a hit,
+//! resumes at the recovery address rather than treating the fault as fatal.
+//!
+//! ## Recovery
+//!
+//! Before resuming, the handler stores the vector of the fault in `rax`:
+//! [`GP_FAULT`] or [`PAGE_FAULT`], neither of them zero. Code around a listed
+//! instruction therefore takes `rax` as an output, clears it on the path that
+//! did not fault, and places the recovery label after that:
+//!
+//! ```ignore
+//! core::arch::asm!(
+//!     "2: mov {value}, [{addr}]",
+//!     "xor eax, eax",
+//!     "3:",
+//!     kernel_registers::extable_entry!("2b", "3b"),
+//!     addr = in(reg) addr,
+//!     value = out(reg) value,
+//!     out("rax") fault,
+//! );
+//! ```
+//!
+//! Only the `#GP` and `#PF` handlers consult the table.
+//!
+//! Entries hold 32-bit offsets relative to themselves, so the table needs no
+//! relocation, as long as the code is within ±2 GiB of it. The kernel's
+//! linker script keeps the section and brackets it with symbols.
+
+/// Value of `rax` after recovering from a general protection fault.
+pub const GP_FAULT: u64 = 13;
+
+/// Value of `rax` after recovering from a page fault.
+pub const PAGE_FAULT: u64 = 14;
+
+/// One entry of the `.extable` section.
+#[repr(C)]
+#[derive(Debug)]
+pub struct ExceptionTableEntry {
+    /// Offset from this field to the instruction that may fault.
+    fault: i32,
+    /// Offset from this field to where execution continues after a fault.
+    recovery: i32,
+}
+
+impl ExceptionTableEntry {
+    /// Address of the instruction that may fault.
+    #[must_use]
+    pub fn fault_rip(&self) -> u64 {
+        Self::resolve(&self.fault)
+    }
+
+    /// Address execution continues at after a fault.
+    #[must_use]
+    pub fn recovery_rip(&self) -> u64 {
+        Self::resolve(&self.recovery)
+    }
+
+    fn resolve(field: &i32) -> u64 {
+        (core::ptr::from_ref(field) as u64).wrapping_add_signed(i64::from(*field))
+    }
+}
+
+/// The recovery address for a fault at `rip`, if `table` lists it.
+#[must_use]
+pub fn search(table: &[ExceptionTableEntry], rip: u64) -> Option<u64> {
+    table
+        .iter()
+        .find(|entry| entry.fault_rip() == rip)
+        .map(ExceptionTableEntry::recovery_rip)
+}
+
+/// Assembly adding an [`ExceptionTableEntry`] for the local labels `fault`
+/// and `recovery`, for use in an `asm!` template; see [Recovery](self#recovery).
+#[macro_export]
+macro_rules! extable_entry {
+    ($fault:literal, $recovery:literal) => {
+        concat!(
+            ".pushsection .extable, \"a\"\n",
+            ".balign 4\n",
+            ".long ",
+            $fault,
+            " - .\n",
+            ".long ",
+            $recovery,
+            " - .\n",
+            ".popsection",
+        )
+    };
+}
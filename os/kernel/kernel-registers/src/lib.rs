@@ -12,15 +12,29 @@ pub mod cr3;
 #[cfg(feature = "cr4")]
 pub mod cr4;
 
+#[cfg(feature = "dtr")]
+pub mod dtr;
+
 #[cfg(feature = "efer")]
 pub mod efer;
 
+pub mod extable;
+
 #[cfg(feature = "msr")]
 pub mod msr;
 
 #[cfg(feature = "rflags")]
 pub mod rflags;
 
+#[cfg(feature = "segment")]
+pub mod segment;
+
+#[cfg(feature = "tr")]
+pub mod tr;
+
+#[cfg(feature = "xcr0")]
+pub mod xcr0;
+
 pub trait LoadRegisterUnsafe {
     /// # Safety
     /// The caller must uphold the implementation-specific safety requirements.
@@ -10,6 +10,7 @@
 //! the privileged `RDMSR` and `WRMSR` instructions.
 //!
 //! Commonly used MSRs include:
+//! - `IA32_FS_BASE` (`0xC000_0100)`: current FS base address used by `mov %fs:...`
 //! - `IA32_GS_BASE` (`0xC000_0101)`: current GS base address used by `mov %gs:...`
 //! - `IA32_KERNEL_GS_BASE` (`0xC000_0102)`: swap value used when executing `swapgs`
 //!
@@ -20,22 +21,36 @@
 //! executes `swapgs`, which exchanges the contents of `IA32_GS_BASE` and
 //! `IA32_KERNEL_GS_BASE`, giving the kernel immediate access to its per-CPU data.
 //!
+//! ## Probing
+//! Accessing an MSR the CPU does not implement, or writing a value it rejects,
+//! raises `#GP`. [`try_rdmsr`] and [`try_wrmsr`] list their `rdmsr`/`wrmsr` in
+//! the [exception table](crate::extable), so a kernel whose `#GP` handler
+//! consults it gets an [`MsrFault`] back instead. Use them to probe MSRs that
+//! may be missing under a hypervisor or on older hardware.
+//!
 //! ## References
 //! - Intel SDM Vol. 3, §2.5.4 “FS and GS Base Address Registers”
 //! - AMD64 Architecture Programmer’s Manual Vol. 2, §4.8.3 “MSRs for FS/GS Base”
 
 mod ia32_fmask;
+mod ia32_fs_base;
 mod ia32_gs_base;
 mod ia32_kernel_gs_base;
 mod ia32_lstar;
+mod ia32_pat;
 mod ia32_star;
 
 pub use ia32_fmask::Ia32Fmask;
+pub use ia32_fs_base::Ia32FsBaseMsr;
 pub use ia32_gs_base::Ia32GsBaseMsr;
 pub use ia32_kernel_gs_base::Ia32KernelGsBaseMsr;
 pub use ia32_lstar::Ia32LStar;
+pub use ia32_pat::{Ia32Pat, PatMemoryType};
 pub use ia32_star::Ia32Star;
 
+use crate::extable_entry;
+use core::fmt;
+
 /// Identifies a **Model-Specific Register (MSR)** by its architectural index.
 ///
 /// MSR indices are 32-bit identifiers used by the `rdmsr` and `wrmsr`
@@ -120,6 +135,86 @@ impl Msr {
     }
 }
 
+/// An MSR access raised `#GP`: the MSR does not exist, or rejected the value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MsrFault(pub Msr);
+
+impl fmt::Display for MsrFault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#GP accessing MSR {:#x}", self.0.raw())
+    }
+}
+
+/// Read `msr` like [`Msr::load_raw`], but return an error if it raises `#GP`.
+///
+/// # Errors
+/// [`MsrFault`] if the CPU does not implement `msr`.
+///
+/// # Safety
+/// - Must run at **CPL=0**.
+/// - The `#GP` handler must resume at the [exception table](crate::extable)
+///   recovery address as described there; otherwise a fault is as fatal as with `load_raw`.
+/// - Reading the MSR must have no side effects the caller is not prepared for.
+#[inline]
+#[doc(alias = "rdmsr_safe")]
+pub unsafe fn try_rdmsr(msr: Msr) -> Result<u64, MsrFault> {
+    let lo: u32;
+    let hi: u32;
+    let fault: u64;
+    unsafe {
+        core::arch::asm!(
+            "2:",
+            "rdmsr",
+            "mov {lo:e}, eax",
+            "xor eax, eax",
+            "3:",
+            extable_entry!("2b", "3b"),
+            lo = out(reg) lo,
+            in("ecx") msr.raw(),
+            out("rax") fault,
+            out("edx") hi,
+            options(nostack)
+        );
+    }
+    if fault != 0 {
+        return Err(MsrFault(msr));
+    }
+    Ok((u64::from(hi) << 32) | u64::from(lo))
+}
+
+/// Write `val` to `msr` like [`Msr::store_raw`], but return an error if it
+/// raises `#GP`.
+///
+/// # Errors
+/// [`MsrFault`] if the CPU does not implement `msr` or rejects `val`.
+///
+/// # Safety
+/// Same as [`try_rdmsr`], and the write itself must be sound, as with
+/// [`Msr::store_raw`].
+#[inline]
+#[allow(clippy::cast_possible_truncation)]
+#[doc(alias = "wrmsr_safe")]
+pub unsafe fn try_wrmsr(msr: Msr, val: u64) -> Result<(), MsrFault> {
+    let fault: u64;
+    unsafe {
+        core::arch::asm!(
+            "2:",
+            "wrmsr",
+            "xor eax, eax",
+            "3:",
+            extable_entry!("2b", "3b"),
+            in("ecx") msr.raw(),
+            inout("rax") val & 0xFFFF_FFFF => fault,
+            in("edx") (val >> 32) as u32,
+            options(nostack)
+        );
+    }
+    if fault != 0 {
+        return Err(MsrFault(msr));
+    }
+    Ok(())
+}
+
 #[inline(always)]
 #[allow(clippy::inline_always)]
 #[must_use]
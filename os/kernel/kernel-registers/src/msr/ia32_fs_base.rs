@@ -0,0 +1,43 @@
+//! Provides the [`Ia32FsBaseMsr`] type.
+
+use crate::msr::Msr;
+use crate::{LoadRegisterUnsafe, StoreRegisterUnsafe};
+use bitfield_struct::bitfield;
+
+/// Model-Specific Register: current **FS base address**.
+///
+/// The CPU uses this value when resolving memory references through the FS
+/// segment register (`mov %fs:offset, ...`). User programs keep their
+/// thread pointer here; the kernel itself does not use FS.
+///
+/// In 64-bit mode, this value is 64 bits wide and read/writable through
+/// `RDMSR`/`WRMSR` at index `0xC000_0100`.
+#[bitfield(u64, order = Lsb)]
+pub struct Ia32FsBaseMsr {
+    #[bits(64)]
+    pub ptr: u64,
+}
+
+impl Ia32FsBaseMsr {
+    pub const IA32_FS_BASE: u32 = 0xC000_0100;
+    pub const MSR: Msr = Msr::new(Self::IA32_FS_BASE);
+}
+
+#[cfg(feature = "asm")]
+impl LoadRegisterUnsafe for Ia32FsBaseMsr {
+    #[inline(always)]
+    #[allow(clippy::inline_always)]
+    unsafe fn load_unsafe() -> Self {
+        let msr = unsafe { Self::MSR.load_raw() };
+        Self::from_bits(msr)
+    }
+}
+
+#[cfg(feature = "asm")]
+impl StoreRegisterUnsafe for Ia32FsBaseMsr {
+    #[inline(always)]
+    #[allow(clippy::inline_always)]
+    unsafe fn store_unsafe(self) {
+        unsafe { Self::MSR.store_raw(self.into_bits()) }
+    }
+}
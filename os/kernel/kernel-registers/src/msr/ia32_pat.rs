@@ -0,0 +1,107 @@
+use crate::msr::Msr;
+use crate::{LoadRegisterUnsafe, StoreRegisterUnsafe};
+
+/// Memory type of one [`Ia32Pat`] entry.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(u8)]
+pub enum PatMemoryType {
+    /// UC — uncacheable, strongly ordered.
+    Uncacheable = 0x00,
+    /// WC — uncached, writes are combined in write-combining buffers.
+    WriteCombining = 0x01,
+    /// WT — reads cached, writes go through to memory.
+    WriteThrough = 0x04,
+    /// WP — reads cached, writes invalidate cache lines.
+    WriteProtected = 0x05,
+    /// WB — fully cached.
+    WriteBack = 0x06,
+    /// UC- — uncacheable, but may be overridden to WC by the MTRRs.
+    UncachedMinus = 0x07,
+}
+
+impl PatMemoryType {
+    /// Decode an entry; `None` for reserved encodings.
+    #[must_use]
+    pub const fn from_bits(bits: u8) -> Option<Self> {
+        match bits {
+            0x00 => Some(Self::Uncacheable),
+            0x01 => Some(Self::WriteCombining),
+            0x04 => Some(Self::WriteThrough),
+            0x05 => Some(Self::WriteProtected),
+            0x06 => Some(Self::WriteBack),
+            0x07 => Some(Self::UncachedMinus),
+            _ => None,
+        }
+    }
+}
+
+/// `IA32_PAT` — Page Attribute Table (MSR `0x277`).
+///
+/// Eight entries `PA0..PA7`, one byte each (bits 0–2 used). A page's
+/// `PAT:PCD:PWT` bits select the entry that defines its memory type.
+///
+/// The power-on value is `WB, WT, UC-, UC, WB, WT, UC-, UC`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(transparent)]
+pub struct Ia32Pat(u64);
+
+impl Ia32Pat {
+    /// MSR index for `IA32_PAT`.
+    pub const IA32_PAT: u32 = 0x277;
+
+    /// The MSR.
+    pub const MSR: Msr = Msr::new(Self::IA32_PAT);
+
+    /// The power-on default layout.
+    pub const POWER_ON: Self = Self(0x0007_0406_0007_0406);
+
+    #[must_use]
+    pub const fn from_bits(bits: u64) -> Self {
+        Self(bits)
+    }
+
+    #[must_use]
+    pub const fn into_bits(self) -> u64 {
+        self.0
+    }
+
+    /// Memory type of entry `index` (0..=7); `None` for reserved encodings.
+    ///
+    /// # Panics
+    /// If `index` is larger than 7.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn entry(self, index: u8) -> Option<PatMemoryType> {
+        assert!(index < 8, "PAT index out of range");
+        PatMemoryType::from_bits((self.0 >> (index * 8)) as u8 & 0x7)
+    }
+
+    /// Set entry `index` (0..=7) to `ty`.
+    ///
+    /// # Panics
+    /// If `index` is larger than 7.
+    #[must_use]
+    pub const fn with_entry(self, index: u8, ty: PatMemoryType) -> Self {
+        assert!(index < 8, "PAT index out of range");
+        let shift = index * 8;
+        Self((self.0 & !(0xFF << shift)) | ((ty as u64) << shift))
+    }
+}
+
+#[cfg(feature = "asm")]
+impl LoadRegisterUnsafe for Ia32Pat {
+    #[inline(always)]
+    #[allow(clippy::inline_always)]
+    unsafe fn load_unsafe() -> Self {
+        Self(unsafe { Self::MSR.load_raw() })
+    }
+}
+
+#[cfg(feature = "asm")]
+impl StoreRegisterUnsafe for Ia32Pat {
+    #[inline(always)]
+    #[allow(clippy::inline_always)]
+    unsafe fn store_unsafe(self) {
+        unsafe { Self::MSR.store_raw(self.0) }
+    }
+}
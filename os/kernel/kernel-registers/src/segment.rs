@@ -0,0 +1,143 @@
+//! # Segment Registers
+//!
+//! The selector registers `CS`, `DS`, `ES`, `SS`, `FS` and `GS`. In long mode
+//! only the selectors of `CS` and `SS` matter beyond their privilege level;
+//! the `FS`/`GS` bases live in MSRs (see [`msr`](crate::msr)).
+//!
+//! Reading a selector is unprivileged. Writing one makes the CPU load the
+//! descriptor it refers to, which must exist in the active GDT; hence the
+//! stores are `unsafe`. Note that loading `FS` or `GS` clears the
+//! corresponding base.
+
+#[cfg(feature = "asm")]
+use crate::{LoadRegister, StoreRegisterUnsafe};
+use bitfield_struct::bitfield;
+
+/// A 16-bit segment selector (index, table indicator and RPL).
+///
+/// ```text
+///  15            3 2  1  0
+/// +----------------+--+----+
+/// |   Index[12:0]  |TI| RPL|
+/// +----------------+--+----+
+/// ```
+#[bitfield(u16)]
+#[derive(Eq, PartialEq)]
+pub struct Selector {
+    /// Bits 0–1 — Requested Privilege Level.
+    #[bits(2)]
+    pub rpl: u8,
+
+    /// Bit 2 — Table Indicator: selects the LDT instead of the GDT.
+    pub ldt: bool,
+
+    /// Bits 3–15 — Descriptor index.
+    #[bits(13)]
+    pub index: u16,
+}
+
+/// CS — Code Segment register.
+#[repr(transparent)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Cs(pub Selector);
+
+/// DS — Data Segment register.
+#[repr(transparent)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Ds(pub Selector);
+
+/// ES — Extra Segment register.
+#[repr(transparent)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Es(pub Selector);
+
+/// SS — Stack Segment register.
+#[repr(transparent)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Ss(pub Selector);
+
+/// FS — general-purpose segment register (base in `IA32_FS_BASE`).
+#[repr(transparent)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Fs(pub Selector);
+
+/// GS — general-purpose segment register (base in `IA32_GS_BASE`).
+#[repr(transparent)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Gs(pub Selector);
+
+/// `mov` to and from a data segment register.
+macro_rules! data_segment_register {
+    ($ty:ident, $reg:literal) => {
+        #[cfg(feature = "asm")]
+        impl LoadRegister for $ty {
+            #[inline]
+            fn load() -> Self {
+                let sel: u16;
+                unsafe {
+                    core::arch::asm!(
+                        concat!("mov {0:x}, ", $reg),
+                        out(reg) sel,
+                        options(nomem, nostack, preserves_flags)
+                    );
+                }
+                Self(Selector::from_bits(sel))
+            }
+        }
+
+        #[cfg(feature = "asm")]
+        impl StoreRegisterUnsafe for $ty {
+            #[inline]
+            unsafe fn store_unsafe(self) {
+                let sel = self.0.into_bits();
+                unsafe {
+                    core::arch::asm!(
+                        concat!("mov ", $reg, ", {0:x}"),
+                        in(reg) sel,
+                        options(nostack, preserves_flags)
+                    );
+                }
+            }
+        }
+    };
+}
+
+data_segment_register!(Ds, "ds");
+data_segment_register!(Es, "es");
+data_segment_register!(Ss, "ss");
+data_segment_register!(Fs, "fs");
+data_segment_register!(Gs, "gs");
+
+#[cfg(feature = "asm")]
+impl LoadRegister for Cs {
+    #[inline]
+    fn load() -> Self {
+        let sel: u16;
+        unsafe {
+            core::arch::asm!("mov {0:x}, cs", out(reg) sel, options(nomem, nostack, preserves_flags));
+        }
+        Self(Selector::from_bits(sel))
+    }
+}
+
+#[cfg(feature = "asm")]
+impl StoreRegisterUnsafe for Cs {
+    /// `CS` cannot be the target of `mov`; this performs a far return to the
+    /// next instruction with the new selector.
+    #[inline]
+    unsafe fn store_unsafe(self) {
+        let sel = u64::from(self.0.into_bits());
+        unsafe {
+            core::arch::asm!(
+                "push {cs}",
+                "lea {tmp}, [rip + 2f]",
+                "push {tmp}",
+                "retfq",
+                "2:",
+                cs = in(reg) sel,
+                tmp = lateout(reg) _,
+                options(preserves_flags)
+            );
+        }
+    }
+}
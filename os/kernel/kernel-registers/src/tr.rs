@@ -0,0 +1,40 @@
+//! # Task Register
+//!
+//! `TR` selects the 64-bit TSS descriptor in the GDT; the CPU takes `RSP0`
+//! and the IST stacks from that TSS. `ltr` marks the descriptor busy, so a
+//! selector can only be loaded once per TSS.
+
+use crate::segment::Selector;
+#[cfg(feature = "asm")]
+use crate::{LoadRegisterUnsafe, StoreRegisterUnsafe};
+
+/// TR — Task Register.
+#[repr(transparent)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct TaskRegister(pub Selector);
+
+#[cfg(feature = "asm")]
+impl LoadRegisterUnsafe for TaskRegister {
+    /// `str`; faults in user mode if `CR4.UMIP` is set.
+    #[inline]
+    unsafe fn load_unsafe() -> Self {
+        let sel: u16;
+        unsafe {
+            core::arch::asm!("str {0:x}", out(reg) sel, options(nomem, nostack, preserves_flags));
+        }
+        Self(Selector::from_bits(sel))
+    }
+}
+
+#[cfg(feature = "asm")]
+impl StoreRegisterUnsafe for TaskRegister {
+    /// `ltr`; the selector must refer to an available 64-bit TSS descriptor
+    /// in the active GDT, and the TSS must stay mapped.
+    #[inline]
+    unsafe fn store_unsafe(self) {
+        let sel = self.0.into_bits();
+        unsafe {
+            core::arch::asm!("ltr {0:x}", in(reg) sel, options(nostack, preserves_flags));
+        }
+    }
+}
@@ -0,0 +1,85 @@
+#[cfg(feature = "asm")]
+use crate::{LoadRegisterUnsafe, StoreRegisterUnsafe};
+use bitfield_struct::bitfield;
+
+/// XCR0 — Extended Control Register 0 (`XFEATURE_ENABLED_MASK`).
+///
+/// Selects the state components managed by `XSAVE`/`XRSTOR` and which
+/// instruction set extensions may be used. Only accessible after
+/// `CR4.OSXSAVE` is set. Bit 0 must always be set, and AVX requires SSE.
+#[bitfield(u64)]
+pub struct Xcr0 {
+    /// Bit 0 — x87 FPU/MMX state (must be 1).
+    pub x87: bool,
+
+    /// Bit 1 — SSE state: XMM0–15 and MXCSR.
+    pub sse: bool,
+
+    /// Bit 2 — AVX state: upper halves of YMM0–15.
+    pub avx: bool,
+
+    /// Bit 3 — MPX bound registers `BND0`–`BND3`.
+    pub bndreg: bool,
+
+    /// Bit 4 — MPX `BNDCFGU` and `BNDSTATUS`.
+    pub bndcsr: bool,
+
+    /// Bit 5 — AVX-512 opmask registers `k0`–`k7`.
+    pub opmask: bool,
+
+    /// Bit 6 — AVX-512 upper halves of ZMM0–15.
+    pub zmm_hi256: bool,
+
+    /// Bit 7 — AVX-512 ZMM16–31.
+    pub hi16_zmm: bool,
+
+    /// Bit 8 — Reserved (supervisor state, PT).
+    #[bits(access = RO)]
+    pub reserved0: bool,
+
+    /// Bit 9 — PKRU register.
+    pub pkru: bool,
+
+    /// Bits 10–63 — Other components (AMX, ...); not modeled.
+    #[bits(54)]
+    pub other: u64,
+}
+
+impl Xcr0 {
+    /// The `XCR` index passed in `ECX` to `xgetbv`/`xsetbv`.
+    pub const INDEX: u32 = 0;
+}
+
+#[cfg(feature = "asm")]
+impl LoadRegisterUnsafe for Xcr0 {
+    unsafe fn load_unsafe() -> Self {
+        let (lo, hi): (u32, u32);
+        unsafe {
+            core::arch::asm!(
+                "xgetbv",
+                in("ecx") Self::INDEX,
+                out("eax") lo,
+                out("edx") hi,
+                options(nomem, nostack, preserves_flags)
+            );
+        }
+        Self::from_bits(u64::from(hi) << 32 | u64::from(lo))
+    }
+}
+
+#[cfg(feature = "asm")]
+impl StoreRegisterUnsafe for Xcr0 {
+    #[allow(clippy::cast_possible_truncation)]
+    unsafe fn store_unsafe(self) {
+        let bits = self.into_bits();
+        unsafe {
+            core::arch::asm!(
+                "xsetbv",
+                in("ecx") Self::INDEX,
+                in("eax") bits as u32,
+                in("edx") (bits >> 32) as u32,
+                options(nomem, nostack, preserves_flags)
+            );
+        }
+    }
+}
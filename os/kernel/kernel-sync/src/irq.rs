@@ -1,17 +1,21 @@
+use crate::preempt::PreemptGuard;
 use crate::{Mutex, MutexGuard, RawLock, RawUnlock};
+use core::ops::{Deref, DerefMut};
 
-/// A mutex guard that also disables interrupts while held.
+/// A mutex guard that also disables interrupts and preemption while held.
 ///
-/// `IrqMutex` combines an interrupt guard with a regular [`MutexGuard`].
-/// When created via [`Mutex::lock_irq`], it:
+/// `IrqMutex` combines an interrupt guard and a [`PreemptGuard`] with a
+/// regular [`MutexGuard`]. When created via [`Mutex::lock_irq`], it:
 ///
-/// 1. saves the current interrupt state and disables interrupts, and
-/// 2. acquires the underlying mutex,
+/// 1. saves the current interrupt state and disables interrupts,
+/// 2. disables preemption, and
+/// 3. acquires the underlying mutex,
 ///
 /// releasing them in reverse order on drop.
 ///
 /// This prevents interrupt handlers from preempting the critical section
-/// and re-entering code that uses the same lock.
+/// and re-entering code that uses the same lock, and the scheduler from
+/// switching away while it is held.
 ///
 /// # Platform
 ///
@@ -33,23 +37,26 @@ use crate::{Mutex, MutexGuard, RawLock, RawUnlock};
 ///
 /// // Disable interrupts and lock for the duration of the scope.
 /// {
-///     let _ig = M.lock_irq();
-///     // critical section guarded from both threads and interrupts
+///     let mut value = M.lock_irq();
+///     *value += 1; // critical section guarded from threads, interrupts and preemption
 /// }
 /// // interrupts and mutex are released here
 /// ```
 pub struct IrqMutex<'a, T, R: RawLock + RawUnlock> {
+    // Fields drop in declaration order: unlock first, restore interrupts last.
+    guard: MutexGuard<'a, T, R>,
+    _preempt: PreemptGuard,
     _irq: IrqGuard,
-    _g: MutexGuard<'a, T, R>,
 }
 
 impl<T, R: RawLock + RawUnlock> Mutex<T, R> {
-    /// Acquires the mutex with interrupts disabled for the guard’s lifetime.
+    /// Acquires the mutex with interrupts and preemption disabled for the
+    /// guard’s lifetime.
     ///
-    /// This constructs an [`IrqGuard`] to save/disable interrupts, then
-    /// acquires the mutex and returns a paired [`IrqMutex`] guard. Dropping
-    /// the guard releases the mutex and restores interrupts if they were
-    /// previously enabled.
+    /// This constructs an [`IrqGuard`] to save/disable interrupts and a
+    /// [`PreemptGuard`], then acquires the mutex and returns the combined
+    /// [`IrqMutex`] guard. Dropping the guard releases the mutex, enables
+    /// preemption and restores interrupts if they were previously enabled.
     ///
     /// # Platform / Privilege
     ///
@@ -58,8 +65,27 @@ impl<T, R: RawLock + RawUnlock> Mutex<T, R> {
     #[inline]
     pub fn lock_irq(&self) -> IrqMutex<'_, T, R> {
         let ig = IrqGuard::new();
+        let pg = PreemptGuard::new();
         let g = self.lock();
-        IrqMutex { _irq: ig, _g: g }
+        IrqMutex {
+            guard: g,
+            _preempt: pg,
+            _irq: ig,
+        }
+    }
+}
+
+impl<T, R: RawLock + RawUnlock> Deref for IrqMutex<'_, T, R> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T, R: RawLock + RawUnlock> DerefMut for IrqMutex<'_, T, R> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
     }
 }
 
@@ -93,6 +119,27 @@ pub fn sti_enable_interrupts() {
     unsafe { core::arch::asm!("sti", options(nomem, nostack, preserves_flags)) }
 }
 
+/// Enables interrupts, halts until the next one arrives, then disables
+/// interrupts again (`sti; hlt; cli`).
+///
+/// Because `sti` only takes effect after the following instruction, no
+/// interrupt can slip in between enabling and halting. Check the wake-up
+/// condition with interrupts disabled, then call this to sleep without
+/// missing the interrupt that would change it.
+///
+/// # Platform
+///
+/// `x86/x86_64`.
+///
+/// # Safety & Privilege
+///
+/// Must only be called in contexts where `sti`/`hlt` are permitted and
+/// interrupts are expected to be disabled on return.
+#[inline]
+pub fn wait_for_interrupt() {
+    unsafe { core::arch::asm!("sti", "hlt", "cli", options(nomem, nostack)) }
+}
+
 /// Returns the current `RFLAGS` value (via `pushfq/pop`).
 ///
 /// Bit 9 (`IF`) indicates whether interrupts are enabled.
@@ -160,6 +207,13 @@ impl IrqGuard {
             were_enabled: enabled,
         }
     }
+
+    /// Whether interrupts were enabled when the guard was created.
+    #[inline]
+    #[must_use]
+    pub const fn were_enabled(&self) -> bool {
+        self.were_enabled
+    }
 }
 
 impl Drop for IrqGuard {
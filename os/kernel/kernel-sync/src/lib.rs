@@ -11,7 +11,13 @@
 //! - [`SpinMutex<T>`], [`TicketMutex<T>`]: convenient mutex aliases.
 //! - [`IrqGuard`], [`IrqMutex`]: scope-based interrupt disable + mutex guard
 //!   (`x86/x86_64`, privileged mode).
+//! - [`PreemptGuard`]: scope-based preemption disable, see [`preempt`].
+//! - [`RwSpinLock<T>`]: writer-preferring reader/writer spinlock.
+//! - [`SeqLock<T>`]: sequence lock with lock-free, retrying readers for `Copy` data.
+//! - [`rcu::Rcu`]: epoch-based deferred reclamation for lock-free readers.
+//! - [`ring::MpscRing`]: lock-free bounded queue, e.g. from interrupt handlers to threads.
 //! - [`SyncOnceCell<T>`]: single-writer, multi-reader, spin-based once-cell.
+//! - [`lockdep`]: optional lock-order checking (`lockdep` feature).
 //!
 //! ## Concurrency model
 //! These primitives rely on acquire/release atomics and CPU-local spinning.
@@ -32,16 +38,25 @@
 #![allow(unsafe_code)]
 
 pub mod irq;
+pub mod lockdep;
 mod mutex;
+pub mod preempt;
 mod raw_spin;
 mod raw_ticket;
+pub mod rcu;
+pub mod ring;
+mod rw_spin;
+mod seq_lock;
 mod spin_lock;
 mod sync_once_cell;
 
 pub use irq::{IrqGuard, IrqMutex};
 pub use mutex::{Mutex, MutexGuard};
+pub use preempt::PreemptGuard;
 pub use raw_spin::RawSpin;
 pub use raw_ticket::RawTicket;
+pub use rw_spin::{RwSpinLock, RwSpinReadGuard, RwSpinWriteGuard};
+pub use seq_lock::{SeqLock, SeqLockWriteGuard};
 pub use spin_lock::{SpinLock, SpinLockGuard};
 pub use sync_once_cell::SyncOnceCell;
 
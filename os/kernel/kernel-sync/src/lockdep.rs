@@ -0,0 +1,215 @@
+//! # Lock dependency checking (`lockdep` feature)
+//!
+//! A small, allocation-free take on Linux' *lockdep*: every lock instance is
+//! a **class**, and whenever a class `B` is acquired while `A` is held on the
+//! same CPU, the edge `A → B` is recorded in a global order graph.
+//!
+//! Acquiring a lock panics if
+//! - the same lock is already held on this CPU (**double acquisition**), or
+//! - the new edge would close a cycle, i.e. the locks were previously taken
+//!   in the opposite order (**order inversion**), which can deadlock on SMP
+//!   or with interrupts.
+//!
+//! `try_lock`-style acquisitions are recorded as held but never checked,
+//! since failing to acquire cannot deadlock.
+//!
+//! Tracking covers [`Mutex`](crate::Mutex) (and thus [`SpinMutex`](crate::SpinMutex),
+//! [`TicketMutex`](crate::TicketMutex) and [`IrqMutex`](crate::IrqMutex)) as well
+//! as [`RwSpinLock`](crate::RwSpinLock). Without the feature, all hooks
+//! compile to nothing.
+//!
+//! ## Limits
+//! At most [`MAX_CLASSES`] locks are tracked; locks beyond that are silently
+//! ignored. Each CPU may hold at most [`MAX_HELD`] tracked locks at once.
+//!
+//! ## CPU identity
+//! Held locks are recorded per CPU. Checking starts once a CPU index
+//! provider was installed with [`set_cpu_id_source`], typically as soon as
+//! per-CPU data is available; locks taken before that are not tracked.
+//!
+//! Read locks of a [`RwSpinLock`](crate::RwSpinLock) are treated like write
+//! locks: with a writer queued in between, even recursive reading deadlocks.
+
+/// Maximum number of distinct locks tracked.
+pub const MAX_CLASSES: usize = 64;
+
+/// Maximum number of CPUs with their own held-lock stack.
+pub const MAX_CPUS: usize = 64;
+
+/// Maximum nesting depth of tracked locks per CPU.
+pub const MAX_HELD: usize = 16;
+
+#[cfg(feature = "lockdep")]
+mod imp {
+    use super::{MAX_CLASSES, MAX_CPUS, MAX_HELD};
+    use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+    /// Lock key (address) per class; `0` marks a free slot.
+    static CLASSES: [AtomicUsize; MAX_CLASSES] = [const { AtomicUsize::new(0) }; MAX_CLASSES];
+
+    /// `AFTER[a]` has bit `b` set if class `b` was acquired while holding `a`.
+    static AFTER: [AtomicU64; MAX_CLASSES] = [const { AtomicU64::new(0) }; MAX_CLASSES];
+
+    /// Classes held per CPU, in acquisition order.
+    static HELD: [[AtomicUsize; MAX_HELD]; MAX_CPUS] =
+        [const { [const { AtomicUsize::new(0) }; MAX_HELD] }; MAX_CPUS];
+
+    /// Number of valid entries in `HELD` per CPU.
+    static DEPTH: [AtomicUsize; MAX_CPUS] = [const { AtomicUsize::new(0) }; MAX_CPUS];
+
+    /// `fn() -> usize` returning the current CPU index, or `0` if unset.
+    static CPU_ID_SOURCE: AtomicUsize = AtomicUsize::new(0);
+
+    pub fn set_cpu_id_source(source: fn() -> usize) {
+        CPU_ID_SOURCE.store(source as usize, Ordering::Release);
+    }
+
+    /// The current CPU, or `None` while tracking is not enabled yet.
+    fn cpu() -> Option<usize> {
+        let source = CPU_ID_SOURCE.load(Ordering::Acquire);
+        if source == 0 {
+            return None;
+        }
+
+        // Safety: only ever set from a valid `fn() -> usize` above.
+        let source: fn() -> usize = unsafe { core::mem::transmute(source) };
+        Some(source() % MAX_CPUS)
+    }
+
+    /// Find or register the class for `key`.
+    fn class_of(key: usize) -> Option<usize> {
+        for (i, slot) in CLASSES.iter().enumerate() {
+            match slot.load(Ordering::Acquire) {
+                k if k == key => return Some(i),
+                0 => match slot.compare_exchange(0, key, Ordering::AcqRel, Ordering::Acquire) {
+                    Ok(_) => return Some(i),
+                    Err(k) if k == key => return Some(i),
+                    Err(_) => {}
+                },
+                _ => {}
+            }
+        }
+        None
+    }
+
+    /// Whether `to` can be reached from `from` in the order graph.
+    fn reachable(from: usize, to: usize) -> bool {
+        let mut visited = 1u64 << from;
+        let mut frontier = visited;
+        while frontier != 0 {
+            let mut next = 0;
+            for (i, after) in AFTER.iter().enumerate() {
+                if frontier & (1 << i) != 0 {
+                    next |= after.load(Ordering::Relaxed);
+                }
+            }
+            if next & (1 << to) != 0 {
+                return true;
+            }
+            frontier = next & !visited;
+            visited |= next;
+        }
+        false
+    }
+
+    fn held(cpu: usize) -> impl DoubleEndedIterator<Item = usize> + ExactSizeIterator {
+        let depth = DEPTH[cpu].load(Ordering::Relaxed);
+        HELD[cpu][..depth].iter().map(|c| c.load(Ordering::Relaxed))
+    }
+
+    fn push(cpu: usize, class: usize) {
+        let depth = DEPTH[cpu].load(Ordering::Relaxed);
+        assert!(
+            depth < MAX_HELD,
+            "lockdep: more than {MAX_HELD} locks held on CPU {cpu}"
+        );
+        HELD[cpu][depth].store(class, Ordering::Relaxed);
+        DEPTH[cpu].store(depth + 1, Ordering::Relaxed);
+    }
+
+    pub fn acquire(key: usize) {
+        let Some(cpu) = cpu() else {
+            return;
+        };
+        let Some(class) = class_of(key) else {
+            return;
+        };
+
+        for held in held(cpu) {
+            let held_key = CLASSES[held].load(Ordering::Relaxed);
+            assert!(
+                held != class,
+                "lockdep: lock {key:#x} acquired twice on CPU {cpu}"
+            );
+            assert!(
+                !reachable(class, held),
+                "lockdep: lock order inversion on CPU {cpu}: {key:#x} acquired while holding {held_key:#x}, but previously taken before it"
+            );
+            AFTER[held].fetch_or(1 << class, Ordering::Relaxed);
+        }
+
+        push(cpu, class);
+    }
+
+    pub fn acquired_try(key: usize) {
+        if let Some(cpu) = cpu()
+            && let Some(class) = class_of(key)
+        {
+            push(cpu, class);
+        }
+    }
+
+    pub fn release(key: usize) {
+        let Some(cpu) = cpu() else {
+            return;
+        };
+        let Some(class) = class_of(key) else {
+            return;
+        };
+
+        // Locks need not be released in LIFO order; drop the newest matching entry.
+        let depth = DEPTH[cpu].load(Ordering::Relaxed);
+        let Some(pos) = held(cpu).rposition(|held| held == class) else {
+            return;
+        };
+        for i in pos..depth - 1 {
+            let next = HELD[cpu][i + 1].load(Ordering::Relaxed);
+            HELD[cpu][i].store(next, Ordering::Relaxed);
+        }
+        DEPTH[cpu].store(depth - 1, Ordering::Relaxed);
+    }
+}
+
+/// Install the function used to determine the current CPU index.
+///
+/// Has no effect unless the `lockdep` feature is enabled.
+#[inline]
+#[allow(unused_variables)]
+pub fn set_cpu_id_source(source: fn() -> usize) {
+    #[cfg(feature = "lockdep")]
+    imp::set_cpu_id_source(source);
+}
+
+/// Record a blocking acquisition of the lock identified by `key`.
+#[inline(always)]
+#[allow(unused_variables, clippy::inline_always, clippy::missing_const_for_fn)]
+pub(crate) fn acquire(key: usize) {
+    #[cfg(feature = "lockdep")]
+    imp::acquire(key);
+}
+
+/// Record a successful non-blocking acquisition of the lock `key`.
+#[inline(always)]
+#[allow(unused_variables, clippy::inline_always, clippy::missing_const_for_fn)]
+pub(crate) fn acquired_try(key: usize) {
+    #[cfg(feature = "lockdep")]
+    imp::acquired_try(key);
+}
+
+/// Record the release of the lock `key`.
+#[inline(always)]
+#[allow(unused_variables, clippy::inline_always, clippy::missing_const_for_fn)]
+pub(crate) fn release(key: usize) {
+    #[cfg(feature = "lockdep")]
+    imp::release(key);
+}
@@ -1,4 +1,4 @@
-use crate::{RawLock, RawUnlock};
+use crate::{RawLock, RawUnlock, lockdep};
 use core::cell::UnsafeCell;
 use core::marker::PhantomData;
 use core::ops::{Deref, DerefMut};
@@ -105,6 +105,11 @@ impl<T, R> Mutex<T, R> {
     pub const fn get_mut(&mut self) -> &mut T {
         self.cell.get_mut()
     }
+
+    /// Identity of this lock for [`lockdep`](crate::lockdep).
+    fn lockdep_key(&self) -> usize {
+        core::ptr::from_ref(self).addr()
+    }
 }
 
 /// A guard that releases a [`Mutex`] when dropped.
@@ -147,6 +152,7 @@ where
     fn drop(&mut self) {
         // Unlock on scope exit.
         unsafe { self.m.raw.raw_unlock() }
+        lockdep::release(self.m.lockdep_key());
     }
 }
 
@@ -169,6 +175,7 @@ where
     /// ```
     #[inline]
     pub fn lock(&self) -> MutexGuard<'_, T, R> {
+        lockdep::acquire(self.lockdep_key());
         self.raw.raw_lock();
         MutexGuard { m: self }
     }
@@ -190,6 +197,7 @@ where
     #[inline]
     pub fn try_lock(&self) -> Option<MutexGuard<'_, T, R>> {
         if self.raw.raw_try_lock() {
+            lockdep::acquired_try(self.lockdep_key());
             Some(MutexGuard { m: self })
         } else {
             None
@@ -0,0 +1,94 @@
+//! # Preemption control
+//!
+//! Code that must not be switched away from in the middle, e.g. while
+//! holding a lock another task might spin on, brackets itself with
+//! [`preempt_disable`] and [`preempt_enable`], or holds a [`PreemptGuard`].
+//! Sections nest: preemption is allowed again once the outermost one ends.
+//!
+//! The counter itself lives with the kernel (typically in its per-CPU data),
+//! which installs the two operations with [`set_preempt_hooks`]. Until then,
+//! and in hosted tests, disabling and enabling preemption does nothing.
+//!
+//! [`IrqMutex`](crate::IrqMutex) disables preemption for as long as it is
+//! held.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// `fn()` raising the preemption counter, or `0` if unset.
+static DISABLE_HOOK: AtomicUsize = AtomicUsize::new(0);
+
+/// `fn()` lowering the preemption counter, or `0` if unset.
+static ENABLE_HOOK: AtomicUsize = AtomicUsize::new(0);
+
+/// Install the functions that raise (`disable`) and lower (`enable`) the
+/// current CPU's preemption counter.
+///
+/// `enable` may reschedule when the counter drops to zero; it must cope with
+/// being called with interrupts disabled.
+pub fn set_preempt_hooks(disable: fn(), enable: fn()) {
+    DISABLE_HOOK.store(disable as usize, Ordering::Release);
+    ENABLE_HOOK.store(enable as usize, Ordering::Release);
+}
+
+fn call_hook(hook: &AtomicUsize) {
+    let hook = hook.load(Ordering::Acquire);
+    if hook != 0 {
+        // Safety: only ever set from a valid `fn()` above.
+        let hook: fn() = unsafe { core::mem::transmute(hook) };
+        hook();
+    }
+}
+
+/// Disable preemption on the current CPU until the matching
+/// [`preempt_enable`].
+#[inline]
+pub fn preempt_disable() {
+    call_hook(&DISABLE_HOOK);
+}
+
+/// End a section started with [`preempt_disable`].
+///
+/// Leaving the outermost section may switch to another task if one became
+/// due in the meantime.
+#[inline]
+pub fn preempt_enable() {
+    call_hook(&ENABLE_HOOK);
+}
+
+/// RAII guard that disables preemption on creation and re-enables it on drop.
+///
+/// # Examples
+///
+/// ```
+/// use kernel_sync::preempt::PreemptGuard;
+///
+/// {
+///     let _g = PreemptGuard::new();
+///     // not switched away from here
+/// }
+/// ```
+pub struct PreemptGuard {
+    _private: (),
+}
+
+impl Default for PreemptGuard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PreemptGuard {
+    /// Disables preemption until the guard is dropped.
+    #[inline]
+    #[must_use]
+    pub fn new() -> Self {
+        preempt_disable();
+        Self { _private: () }
+    }
+}
+
+impl Drop for PreemptGuard {
+    fn drop(&mut self) {
+        preempt_enable();
+    }
+}
@@ -0,0 +1,417 @@
+//! # RCU-lite: epoch-based deferred reclamation
+//!
+//! Read-mostly structures (process lists, lookup caches, …) want readers
+//! that never take a lock. Writers then must not free an object that was
+//! just unlinked, because a concurrent reader may still be looking at it.
+//! An [`Rcu`] domain tracks who might still be looking and defers the free
+//! until a **grace period** has passed.
+//!
+//! ## Readers
+//! [`Rcu::read`] pins the calling CPU to the current global epoch and returns
+//! an [`RcuReadGuard`]; dropping the guard leaves the read-side critical
+//! section. Both are a handful of atomic operations, never wait, nest, and
+//! may be used from interrupt handlers. Shared pointers are published through
+//! [`RcuPointer`], whose [`load`](RcuPointer::load) borrows the guard so the
+//! reference cannot outlive the critical section.
+//!
+//! ## Writers
+//! A writer unlinks an object (e.g. with [`RcuPointer::swap`]) and hands it to
+//! [`Rcu::retire`] together with a free callback. Retired objects wait on a
+//! per-CPU list, stamped with the epoch they were retired in.
+//!
+//! ## Grace periods
+//! The global epoch advances only once every CPU inside a read-side critical
+//! section has observed the current epoch. An object retired in epoch `e` is
+//! therefore unreachable for all readers once the epoch reached `e + 2`.
+//! [`Rcu::reclaim`] (cheap, never waits; e.g. from the idle loop) frees what
+//! is ready, [`Rcu::synchronize`] spins until a full grace period elapsed.
+//!
+//! ## Embedding
+//! Reclamation is intrusive and allocation-free: objects embed an [`RcuHead`]
+//! and the free callback recovers the containing object from it, e.g. with
+//! [`core::mem::offset_of!`].
+
+use core::cell::UnsafeCell;
+use core::hint::spin_loop;
+use core::marker::PhantomData;
+use core::ptr::{self, NonNull};
+use core::sync::atomic::{AtomicPtr, AtomicU64, AtomicUsize, Ordering, fence};
+
+/// Default number of CPUs an [`Rcu`] domain supports.
+pub const MAX_CPUS: usize = 64;
+
+/// Mask of the nesting depth in a CPU's state word; the epoch lives above it.
+const NEST_MASK: u64 = 0xffff_ffff;
+
+/// Epochs between retirement and reclamation.
+const GRACE_EPOCHS: u64 = 2;
+
+/// Callback freeing the object that embeds a retired [`RcuHead`].
+pub type RcuFree = unsafe fn(NonNull<RcuHead>);
+
+/// Reclamation bookkeeping embedded in every object that can be retired.
+pub struct RcuHead {
+    next: AtomicPtr<Self>,
+    epoch: UnsafeCell<u64>,
+    free: UnsafeCell<Option<RcuFree>>,
+}
+
+// Safety: the cells are only written by the retiring CPU before the head is
+// published on a retire list, and only read by the reclaimer after taking it off.
+unsafe impl Send for RcuHead {}
+unsafe impl Sync for RcuHead {}
+
+impl RcuHead {
+    /// Creates an unused head.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            next: AtomicPtr::new(ptr::null_mut()),
+            epoch: UnsafeCell::new(0),
+            free: UnsafeCell::new(None),
+        }
+    }
+}
+
+impl Default for RcuHead {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Per-CPU reader state and retire list.
+struct RcuCpu {
+    /// `epoch << 32 | nesting depth`; the CPU is inside a read-side critical
+    /// section while the depth is non-zero.
+    state: AtomicU64,
+    /// Treiber stack of retired objects.
+    retired: AtomicPtr<RcuHead>,
+    pending: AtomicUsize,
+}
+
+impl RcuCpu {
+    const fn new() -> Self {
+        Self {
+            state: AtomicU64::new(0),
+            retired: AtomicPtr::new(ptr::null_mut()),
+            pending: AtomicUsize::new(0),
+        }
+    }
+}
+
+/// An epoch-based reclamation domain for up to `CPUS` CPUs.
+///
+/// CPUs are identified by their index (`0..CPUS`), passed explicitly by the
+/// caller. Indices out of range panic.
+///
+/// # Examples
+///
+/// ```
+/// use core::ptr::NonNull;
+/// use kernel_sync::rcu::{Rcu, RcuHead, RcuPointer};
+///
+/// #[repr(C)]
+/// struct Node {
+///     head: RcuHead,
+///     value: u32,
+/// }
+///
+/// unsafe fn free_node(head: NonNull<RcuHead>) {
+///     // `head` is the first field of a `#[repr(C)]` node.
+///     drop(unsafe { Box::from_raw(head.as_ptr().cast::<Node>()) });
+/// }
+///
+/// static RCU: Rcu<4> = Rcu::new();
+/// let first = Box::into_raw(Box::new(Node { head: RcuHead::new(), value: 1 }));
+/// let current = unsafe { RcuPointer::new(first) };
+///
+/// {
+///     let guard = RCU.read(0);
+///     assert_eq!(current.load(&guard).unwrap().value, 1);
+/// }
+///
+/// let second = Box::into_raw(Box::new(Node { head: RcuHead::new(), value: 2 }));
+/// let old = unsafe { current.swap(second) };
+/// unsafe { RCU.retire(0, NonNull::new(old).unwrap().cast(), free_node) };
+///
+/// RCU.synchronize();
+/// assert_eq!(RCU.reclaim(0), 1);
+/// # unsafe { free_node(NonNull::new(current.swap(core::ptr::null_mut())).unwrap().cast()) };
+/// ```
+pub struct Rcu<const CPUS: usize = MAX_CPUS> {
+    epoch: AtomicU64,
+    cpus: [RcuCpu; CPUS],
+}
+
+impl<const CPUS: usize> Default for Rcu<CPUS> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const CPUS: usize> Rcu<CPUS> {
+    /// Creates a domain in epoch zero with no readers and nothing retired.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            epoch: AtomicU64::new(0),
+            cpus: [const { RcuCpu::new() }; CPUS],
+        }
+    }
+
+    /// The current global epoch.
+    #[must_use]
+    pub fn epoch(&self) -> u64 {
+        self.epoch.load(Ordering::Acquire)
+    }
+
+    /// Enters a read-side critical section on `cpu`.
+    ///
+    /// Critical sections nest; the CPU stays pinned until the outermost guard
+    /// is dropped. They must not be held across a context switch that could
+    /// resume the code on another CPU.
+    #[inline]
+    pub fn read(&self, cpu: usize) -> RcuReadGuard<'_, CPUS> {
+        let state = &self.cpus[cpu].state;
+
+        // A CAS instead of load/store: an interrupt handler entering and
+        // leaving its own critical section in between must not be lost.
+        let mut s = state.load(Ordering::Relaxed);
+        loop {
+            let next = if s & NEST_MASK == 0 {
+                (self.epoch.load(Ordering::Relaxed) << 32) | 1
+            } else {
+                s + 1
+            };
+            match state.compare_exchange_weak(s, next, Ordering::Relaxed, Ordering::Relaxed) {
+                Ok(_) => break,
+                Err(current) => s = current,
+            }
+        }
+
+        // Publish the pin before any protected pointer is loaded.
+        fence(Ordering::SeqCst);
+
+        RcuReadGuard {
+            rcu: self,
+            cpu,
+            _not_send: PhantomData,
+        }
+    }
+
+    /// Whether `cpu` is currently inside a read-side critical section.
+    #[must_use]
+    pub fn is_reading(&self, cpu: usize) -> bool {
+        self.cpus[cpu].state.load(Ordering::Relaxed) & NEST_MASK != 0
+    }
+
+    /// Queues the object embedding `head` to be freed by `free` once all
+    /// readers that might still see it have left their critical sections.
+    ///
+    /// # Safety
+    /// - The object must already be unreachable for new readers.
+    /// - `head` must stay valid until `free` is called and must not be
+    ///   retired again before that.
+    /// - `free` must be sound to call on `head` from whichever CPU runs
+    ///   [`reclaim`](Self::reclaim).
+    pub unsafe fn retire(&self, cpu: usize, head: NonNull<RcuHead>, free: RcuFree) {
+        fence(Ordering::SeqCst);
+        let epoch = self.epoch.load(Ordering::Relaxed);
+
+        // Safety: the caller hands us exclusive ownership of `head`.
+        unsafe {
+            *head.as_ref().epoch.get() = epoch;
+            *head.as_ref().free.get() = Some(free);
+        }
+
+        let cpu = &self.cpus[cpu];
+        cpu.pending.fetch_add(1, Ordering::Relaxed);
+        Self::push(cpu, head);
+    }
+
+    /// Number of objects retired on `cpu` that were not yet freed.
+    #[must_use]
+    pub fn pending(&self, cpu: usize) -> usize {
+        self.cpus[cpu].pending.load(Ordering::Relaxed)
+    }
+
+    /// Advances the global epoch if every reading CPU has observed it.
+    ///
+    /// Returns whether the epoch was advanced (by this or another caller).
+    pub fn try_advance(&self) -> bool {
+        let epoch = self.epoch.load(Ordering::Relaxed);
+        fence(Ordering::SeqCst);
+
+        let lagging = self.cpus.iter().any(|cpu| {
+            let s = cpu.state.load(Ordering::Relaxed);
+            // Only the low 32 bits of the epoch are stored per CPU.
+            s & NEST_MASK != 0 && s >> 32 != epoch & NEST_MASK
+        });
+        if lagging {
+            return false;
+        }
+
+        fence(Ordering::Acquire);
+        match self.epoch.compare_exchange(
+            epoch,
+            epoch.wrapping_add(1),
+            Ordering::Release,
+            Ordering::Relaxed,
+        ) {
+            Ok(_) => true,
+            Err(current) => current != epoch,
+        }
+    }
+
+    /// Frees the objects retired on `cpu` whose grace period has elapsed.
+    ///
+    /// Never waits for readers, so it is cheap enough for the idle loop or a
+    /// timer tick. Returns the number of freed objects.
+    pub fn reclaim(&self, cpu: usize) -> usize {
+        self.try_advance();
+        let epoch = self.epoch.load(Ordering::Acquire);
+
+        let cpu = &self.cpus[cpu];
+        let mut node = cpu.retired.swap(ptr::null_mut(), Ordering::Acquire);
+        let mut freed = 0;
+
+        while let Some(head) = NonNull::new(node) {
+            // Safety: taken off the retire list, so we own `head` until it is
+            // freed or pushed back.
+            let (retired_in, free) = unsafe {
+                let h = head.as_ref();
+                node = h.next.load(Ordering::Relaxed);
+                (*h.epoch.get(), *h.free.get())
+            };
+
+            if epoch.wrapping_sub(retired_in) >= GRACE_EPOCHS
+                && let Some(free) = free
+            {
+                // Safety: no reader can still hold a reference (see module docs).
+                unsafe { free(head) };
+                freed += 1;
+            } else {
+                Self::push(cpu, head);
+            }
+        }
+
+        cpu.pending.fetch_sub(freed, Ordering::Relaxed);
+        freed
+    }
+
+    /// Spins until a full grace period has elapsed, i.e. until every read-side
+    /// critical section that was active on entry has ended.
+    ///
+    /// Must not be called from inside a read-side critical section; that
+    /// would never return.
+    pub fn synchronize(&self) {
+        let target = self
+            .epoch
+            .load(Ordering::Acquire)
+            .wrapping_add(GRACE_EPOCHS);
+        while self
+            .epoch
+            .load(Ordering::Acquire)
+            .wrapping_sub(target)
+            .cast_signed()
+            < 0
+        {
+            if !self.try_advance() {
+                spin_loop();
+            }
+        }
+    }
+
+    fn push(cpu: &RcuCpu, head: NonNull<RcuHead>) {
+        // Safety: we own `head` until it is visible on the list.
+        let next = unsafe { &head.as_ref().next };
+        let mut top = cpu.retired.load(Ordering::Relaxed);
+        loop {
+            next.store(top, Ordering::Relaxed);
+            match cpu.retired.compare_exchange_weak(
+                top,
+                head.as_ptr(),
+                Ordering::Release,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return,
+                Err(current) => top = current,
+            }
+        }
+    }
+}
+
+/// A read-side critical section of an [`Rcu`] domain; leaves it on drop.
+///
+/// Bound to the CPU it was entered on and therefore neither `Send` nor `Sync`.
+pub struct RcuReadGuard<'a, const CPUS: usize = MAX_CPUS> {
+    rcu: &'a Rcu<CPUS>,
+    cpu: usize,
+    _not_send: PhantomData<*mut ()>,
+}
+
+impl<const CPUS: usize> RcuReadGuard<'_, CPUS> {
+    /// The CPU this critical section runs on.
+    #[must_use]
+    pub const fn cpu(&self) -> usize {
+        self.cpu
+    }
+}
+
+impl<const CPUS: usize> Drop for RcuReadGuard<'_, CPUS> {
+    fn drop(&mut self) {
+        self.rcu.cpus[self.cpu]
+            .state
+            .fetch_sub(1, Ordering::Release);
+    }
+}
+
+/// An atomically replaceable pointer to an RCU-protected object.
+pub struct RcuPointer<T> {
+    ptr: AtomicPtr<T>,
+}
+
+// Safety: readers share `&T` across CPUs, writers hand objects between them.
+unsafe impl<T: Send + Sync> Sync for RcuPointer<T> {}
+unsafe impl<T: Send + Sync> Send for RcuPointer<T> {}
+
+impl<T> RcuPointer<T> {
+    /// Creates a pointer to `ptr` (which may be null).
+    ///
+    /// # Safety
+    /// A non-null `ptr` must stay valid until it was replaced and the
+    /// replaced object was freed through [`Rcu::retire`] of the domain whose
+    /// guards are used to [`load`](Self::load) it.
+    pub const unsafe fn new(ptr: *mut T) -> Self {
+        Self {
+            ptr: AtomicPtr::new(ptr),
+        }
+    }
+
+    /// Creates a null pointer.
+    #[must_use]
+    pub const fn null() -> Self {
+        Self {
+            ptr: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    /// Reads the current object; the reference lives as long as the guard.
+    #[must_use]
+    pub fn load<'g, const CPUS: usize>(&self, _guard: &'g RcuReadGuard<'_, CPUS>) -> Option<&'g T> {
+        let ptr = self.ptr.load(Ordering::Acquire);
+        // Safety: per the contract of `new`/`swap`, the object is only freed
+        // after a grace period, which cannot end while `_guard` is alive.
+        unsafe { ptr.as_ref() }
+    }
+
+    /// Publishes `new` and returns the previous pointer.
+    ///
+    /// The caller owns the returned object and typically retires it.
+    ///
+    /// # Safety
+    /// Same contract for `new` as for [`new`](Self::new).
+    pub unsafe fn swap(&self, new: *mut T) -> *mut T {
+        self.ptr.swap(new, Ordering::AcqRel)
+    }
+}
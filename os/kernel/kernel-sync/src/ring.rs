@@ -0,0 +1,289 @@
+//! # Lock-free bounded ring buffers
+//!
+//! [`MpscRing`] is a fixed-capacity queue for handing items from any number
+//! of producers — including **interrupt handlers** — to a consumer, without
+//! locks and without allocation.
+//!
+//! ## Algorithm
+//! Each slot carries a sequence number next to its value (D. Vyukov's
+//! bounded queue). Producers claim a position by advancing `tail` with a CAS
+//! and publish the value by bumping the slot's sequence; the consumer reads
+//! slots whose sequence says "full" and marks them free for the next lap.
+//! No operation ever waits for another thread to make progress, so pushing
+//! from an interrupt that preempted a half-finished push cannot deadlock.
+//!
+//! ## Overflow
+//! A full ring rejects new items and counts them in [`RingStats::overflows`];
+//! producers decide whether to drop the item or to make room with
+//! [`MpscRing::force_push`] or [`MpscRing::push_evicting`].
+
+use core::cell::UnsafeCell;
+use core::cmp::Ordering as Cmp;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+/// Counters describing the traffic through an [`MpscRing`].
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct RingStats {
+    /// Items successfully pushed.
+    pub pushed: u64,
+    /// Items popped by the consumer.
+    pub popped: u64,
+    /// Pushes rejected (or older items evicted) because the ring was full.
+    pub overflows: u64,
+}
+
+struct Slot<T> {
+    /// Sequence number, stored relative to the slot index so that an all-zero
+    /// initializer is valid (see [`MpscRing::new`]).
+    seq: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+impl<T> Slot<T> {
+    const fn new() -> Self {
+        Self {
+            seq: AtomicUsize::new(0),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+}
+
+/// A fixed-capacity, lock-free multi-producer queue of `N` items.
+///
+/// `N` must be a power of two (checked at compile time).
+///
+/// Although intended for a single consumer, popping is safe from multiple
+/// contexts as well.
+///
+/// # Examples
+///
+/// ```
+/// use kernel_sync::ring::MpscRing;
+///
+/// static EVENTS: MpscRing<u8, 4> = MpscRing::new();
+///
+/// // e.g. from an interrupt handler
+/// EVENTS.push(0x1c).unwrap();
+///
+/// // from the consumer thread
+/// assert_eq!(EVENTS.pop(), Some(0x1c));
+/// assert_eq!(EVENTS.pop(), None);
+/// ```
+pub struct MpscRing<T, const N: usize> {
+    slots: [Slot<T>; N],
+    /// Next position to push to.
+    tail: AtomicUsize,
+    /// Next position to pop from.
+    head: AtomicUsize,
+    pushed: AtomicU64,
+    popped: AtomicU64,
+    overflows: AtomicU64,
+}
+
+// Safety: values move between contexts (`T: Send`); slot access is arbitrated by the sequence numbers.
+unsafe impl<T: Send, const N: usize> Sync for MpscRing<T, N> {}
+unsafe impl<T: Send, const N: usize> Send for MpscRing<T, N> {}
+
+impl<T, const N: usize> Default for MpscRing<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> MpscRing<T, N> {
+    const CAPACITY_IS_POWER_OF_TWO: () = assert!(
+        N.is_power_of_two(),
+        "MpscRing capacity must be a power of two"
+    );
+
+    /// Creates an empty ring.
+    #[must_use]
+    pub const fn new() -> Self {
+        let () = Self::CAPACITY_IS_POWER_OF_TWO;
+        Self {
+            slots: [const { Slot::new() }; N],
+            tail: AtomicUsize::new(0),
+            head: AtomicUsize::new(0),
+            pushed: AtomicU64::new(0),
+            popped: AtomicU64::new(0),
+            overflows: AtomicU64::new(0),
+        }
+    }
+
+    /// The number of items the ring can hold.
+    #[must_use]
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// An approximate number of queued items.
+    ///
+    /// Exact only while no push or pop is in flight.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        let tail = self.tail.load(Ordering::Acquire);
+        let head = self.head.load(Ordering::Acquire);
+        tail.wrapping_sub(head).min(N)
+    }
+
+    /// Whether the ring is (approximately) empty; see [`len`](Self::len).
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Traffic counters since creation.
+    #[must_use]
+    pub fn stats(&self) -> RingStats {
+        RingStats {
+            pushed: self.pushed.load(Ordering::Relaxed),
+            popped: self.popped.load(Ordering::Relaxed),
+            overflows: self.overflows.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Appends `value`, or returns it if the ring is full.
+    ///
+    /// Never blocks; safe to call from interrupt context.
+    ///
+    /// # Errors
+    /// Returns `Err(value)` if the ring is full. The overflow is counted.
+    pub fn push(&self, value: T) -> Result<(), T> {
+        let result = self.try_push(value);
+        if result.is_err() {
+            self.overflows.fetch_add(1, Ordering::Relaxed);
+        }
+        result
+    }
+
+    /// Appends `value`, evicting the oldest items while the ring is full.
+    ///
+    /// Evictions are counted as overflows. Useful for history buffers where
+    /// the newest data matters most.
+    ///
+    /// Retries until it succeeds: if the ring is full and its oldest item is
+    /// a push still in progress, e.g. one interrupted on the same CPU, this
+    /// spins until that push completes. Use [`push_evicting`](Self::push_evicting)
+    /// where that push might never complete.
+    pub fn force_push(&self, mut value: T) {
+        loop {
+            match self.try_push(value) {
+                Ok(()) => return,
+                Err(v) => {
+                    value = v;
+                    self.evict();
+                }
+            }
+        }
+    }
+
+    /// Appends `value`, evicting the oldest item once if the ring is full.
+    ///
+    /// Like [`force_push`](Self::force_push), but gives up instead of
+    /// retrying, so it returns in bounded time from any context.
+    ///
+    /// # Errors
+    /// Returns `Err(value)` if the ring is still full after the eviction.
+    /// Both the eviction and the rejected push are counted as overflows.
+    pub fn push_evicting(&self, value: T) -> Result<(), T> {
+        match self.try_push(value) {
+            Ok(()) => Ok(()),
+            Err(value) => {
+                self.evict();
+                self.push(value)
+            }
+        }
+    }
+
+    /// Drops the oldest item, if it is published, as an overflow.
+    fn evict(&self) {
+        if self.pop().is_some() {
+            // `pop` counted it as consumed; reclassify as dropped.
+            self.popped.fetch_sub(1, Ordering::Relaxed);
+            self.overflows.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Removes the oldest item, if any.
+    pub fn pop(&self) -> Option<T> {
+        let mut pos = self.head.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[pos & (N - 1)];
+            let seq = Self::load_seq(slot, pos);
+            let diff = seq.wrapping_sub(pos.wrapping_add(1)).cast_signed();
+
+            match diff.cmp(&0) {
+                Cmp::Equal => match self.head.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        // Safety: the sequence says the slot is full and we own position `pos`.
+                        let value = unsafe { (*slot.value.get()).assume_init_read() };
+                        Self::store_seq(slot, pos, pos.wrapping_add(N));
+                        self.popped.fetch_add(1, Ordering::Relaxed);
+                        return Some(value);
+                    }
+                    Err(current) => pos = current,
+                },
+                Cmp::Less => {
+                    // Not yet published (empty, or a producer is mid-push).
+                    return None;
+                }
+                Cmp::Greater => pos = self.head.load(Ordering::Relaxed),
+            }
+        }
+    }
+
+    fn try_push(&self, value: T) -> Result<(), T> {
+        let mut pos = self.tail.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[pos & (N - 1)];
+            let seq = Self::load_seq(slot, pos);
+            let diff = seq.wrapping_sub(pos).cast_signed();
+
+            match diff.cmp(&0) {
+                Cmp::Equal => match self.tail.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        // Safety: the sequence says the slot is free and we own position `pos`.
+                        unsafe { (*slot.value.get()).write(value) };
+                        Self::store_seq(slot, pos, pos.wrapping_add(1));
+                        self.pushed.fetch_add(1, Ordering::Relaxed);
+                        return Ok(());
+                    }
+                    Err(current) => pos = current,
+                },
+                Cmp::Less => {
+                    // The slot still holds an item from the previous lap: full.
+                    return Err(value);
+                }
+                Cmp::Greater => pos = self.tail.load(Ordering::Relaxed),
+            }
+        }
+    }
+
+    /// Absolute sequence number of `slot` (for a position on the same slot).
+    fn load_seq(slot: &Slot<T>, pos: usize) -> usize {
+        let index = pos & (N - 1);
+        slot.seq.load(Ordering::Acquire).wrapping_add(index)
+    }
+
+    fn store_seq(slot: &Slot<T>, pos: usize, seq: usize) {
+        let index = pos & (N - 1);
+        slot.seq.store(seq.wrapping_sub(index), Ordering::Release);
+    }
+}
+
+impl<T, const N: usize> Drop for MpscRing<T, N> {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
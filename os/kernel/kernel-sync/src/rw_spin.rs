@@ -0,0 +1,209 @@
+use crate::lockdep;
+use core::cell::UnsafeCell;
+use core::hint::spin_loop;
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// Set while a writer holds the lock.
+const WRITER: u32 = 1 << 31;
+
+/// Set while at least one writer waits; blocks new readers.
+const WRITER_WAITING: u32 = 1 << 30;
+
+/// Mask of the active reader count.
+const READERS: u32 = WRITER_WAITING - 1;
+
+/// A writer-preferring reader/writer spinlock.
+///
+/// Any number of readers may hold the lock at the same time, while a writer
+/// holds it exclusively. As soon as a writer starts waiting, **new readers
+/// are held back** until it got its turn, so a steady stream of readers can
+/// never starve writers. This suits mostly-read data (e.g. mount tables)
+/// that is updated occasionally.
+///
+/// # Caveats
+///
+/// - Not reentrant: taking a read lock while already holding one can
+///   deadlock if a writer queued up in between.
+/// - Writers are not ordered among themselves.
+///
+/// # Examples
+///
+/// ```
+/// use kernel_sync::RwSpinLock;
+///
+/// let table = RwSpinLock::new([0u32; 4]);
+/// {
+///     let r1 = table.read();
+///     let r2 = table.read(); // readers share the lock
+///     assert_eq!(r1[0], r2[0]);
+/// }
+/// table.write()[0] = 7;
+/// assert_eq!(table.read()[0], 7);
+/// ```
+pub struct RwSpinLock<T> {
+    state: AtomicU32,
+    cell: UnsafeCell<T>,
+    /// Prevent default auto-`Send`/`Sync`; we add them with bounds below.
+    _no_send_sync: PhantomData<*mut ()>,
+}
+
+// Safety: readers share `&T` across threads (`T: Sync`), writers move `&mut T` (`T: Send`).
+unsafe impl<T: Send + Sync> Sync for RwSpinLock<T> {}
+unsafe impl<T: Send> Send for RwSpinLock<T> {}
+
+impl<T> RwSpinLock<T> {
+    /// Creates a new unlocked lock containing `value`.
+    pub const fn new(value: T) -> Self {
+        Self {
+            state: AtomicU32::new(0),
+            cell: UnsafeCell::new(value),
+            _no_send_sync: PhantomData,
+        }
+    }
+
+    /// Returns a mutable reference to the inner value.
+    ///
+    /// Because you hold `&mut self`, no locking is needed.
+    #[inline]
+    pub const fn get_mut(&mut self) -> &mut T {
+        self.cell.get_mut()
+    }
+
+    /// Consumes the lock and returns the inner value.
+    #[inline]
+    pub fn into_inner(self) -> T {
+        self.cell.into_inner()
+    }
+
+    /// Acquires a shared read lock, spinning while a writer holds or waits
+    /// for the lock.
+    #[inline]
+    pub fn read(&self) -> RwSpinReadGuard<'_, T> {
+        lockdep::acquire(self.lockdep_key());
+        while !self.try_acquire_read() {
+            spin_loop();
+        }
+        RwSpinReadGuard { lock: self }
+    }
+
+    /// Attempts to acquire a shared read lock without spinning.
+    #[inline]
+    pub fn try_read(&self) -> Option<RwSpinReadGuard<'_, T>> {
+        if self.try_acquire_read() {
+            lockdep::acquired_try(self.lockdep_key());
+            Some(RwSpinReadGuard { lock: self })
+        } else {
+            None
+        }
+    }
+
+    /// Acquires the exclusive write lock, spinning until all readers left.
+    #[inline]
+    pub fn write(&self) -> RwSpinWriteGuard<'_, T> {
+        lockdep::acquire(self.lockdep_key());
+        loop {
+            let s = self.state.load(Ordering::Relaxed);
+            if s & (WRITER | READERS) == 0 {
+                // Clears our (or a shared) waiting flag; other waiting
+                // writers set it again while spinning.
+                if self
+                    .state
+                    .compare_exchange_weak(s, WRITER, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+                {
+                    return RwSpinWriteGuard { lock: self };
+                }
+            } else if s & WRITER_WAITING == 0 {
+                self.state.fetch_or(WRITER_WAITING, Ordering::Relaxed);
+            }
+            spin_loop();
+        }
+    }
+
+    /// Attempts to acquire the exclusive write lock without spinning.
+    #[inline]
+    pub fn try_write(&self) -> Option<RwSpinWriteGuard<'_, T>> {
+        let s = self.state.load(Ordering::Relaxed);
+        if s & (WRITER | READERS) != 0 {
+            return None;
+        }
+
+        self.state
+            .compare_exchange(s, WRITER, Ordering::Acquire, Ordering::Relaxed)
+            .ok()?;
+        lockdep::acquired_try(self.lockdep_key());
+        Some(RwSpinWriteGuard { lock: self })
+    }
+
+    fn try_acquire_read(&self) -> bool {
+        let s = self.state.load(Ordering::Relaxed);
+        if s & (WRITER | WRITER_WAITING) != 0 || s & READERS == READERS {
+            return false;
+        }
+
+        self.state
+            .compare_exchange_weak(s, s + 1, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    fn lockdep_key(&self) -> usize {
+        core::ptr::from_ref(self).addr()
+    }
+}
+
+impl<T: Default> Default for RwSpinLock<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+/// Shared access to a [`RwSpinLock`]; releases the read lock on drop.
+pub struct RwSpinReadGuard<'a, T> {
+    lock: &'a RwSpinLock<T>,
+}
+
+impl<T> Deref for RwSpinReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // Safety: no writer can hold the lock while we hold a read lock.
+        unsafe { &*self.lock.cell.get() }
+    }
+}
+
+impl<T> Drop for RwSpinReadGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.state.fetch_sub(1, Ordering::Release);
+        lockdep::release(self.lock.lockdep_key());
+    }
+}
+
+/// Exclusive access to a [`RwSpinLock`]; releases the write lock on drop.
+pub struct RwSpinWriteGuard<'a, T> {
+    lock: &'a RwSpinLock<T>,
+}
+
+impl<T> Deref for RwSpinWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // Safety: the guard holds the lock exclusively.
+        unsafe { &*self.lock.cell.get() }
+    }
+}
+
+impl<T> DerefMut for RwSpinWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // Safety: the guard holds the lock exclusively.
+        unsafe { &mut *self.lock.cell.get() }
+    }
+}
+
+impl<T> Drop for RwSpinWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.state.fetch_and(!WRITER, Ordering::Release);
+        lockdep::release(self.lock.lockdep_key());
+    }
+}
@@ -0,0 +1,184 @@
+use crate::irq::IrqGuard;
+use crate::{RawSpin, lockdep};
+use core::cell::UnsafeCell;
+use core::hint::spin_loop;
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicUsize, Ordering, fence};
+
+/// A sequence lock for small, frequently read and rarely written values.
+///
+/// Writers are serialized by an internal spinlock and bump a sequence
+/// counter before and after updating the value. Readers take **no lock at
+/// all**: they copy the value and retry if the counter shows that a write
+/// overlapped the copy.
+///
+/// # Readers never block
+///
+/// This is enforced by the API rather than by convention:
+/// - [`read`](Self::read) takes `&self` and returns a copy; there is no read
+///   guard, so a reader cannot hold anything a writer would wait for.
+/// - `T: Copy` guarantees a torn copy can simply be discarded: it has no
+///   destructor and owns no resources.
+///
+/// A reader only retries while a write is in progress, so writers must keep
+/// their critical sections short. If a value may be read from interrupt
+/// context, update it with [`write_irq`](Self::write_irq) so the interrupted
+/// writer cannot livelock a reader on the same CPU.
+///
+/// # Examples
+///
+/// ```
+/// use kernel_sync::SeqLock;
+///
+/// #[derive(Copy, Clone)]
+/// struct Params { mult: u64, shift: u32 }
+///
+/// let params = SeqLock::new(Params { mult: 1, shift: 0 });
+/// params.write().mult = 3;
+/// assert_eq!(params.read().mult, 3);
+/// ```
+pub struct SeqLock<T: Copy> {
+    /// Even while stable, odd while a write is in progress.
+    seq: AtomicUsize,
+    writer: RawSpin,
+    cell: UnsafeCell<T>,
+    /// Prevent default auto-`Send`/`Sync`; we add them with bounds below.
+    _no_send_sync: PhantomData<*mut ()>,
+}
+
+// Safety: writers are serialized; readers only ever obtain validated copies.
+unsafe impl<T: Copy + Send> Sync for SeqLock<T> {}
+unsafe impl<T: Copy + Send> Send for SeqLock<T> {}
+
+impl<T: Copy> SeqLock<T> {
+    /// Creates a new sequence lock holding `value`.
+    pub const fn new(value: T) -> Self {
+        Self {
+            seq: AtomicUsize::new(0),
+            writer: RawSpin::new(),
+            cell: UnsafeCell::new(value),
+            _no_send_sync: PhantomData,
+        }
+    }
+
+    /// Returns a consistent copy of the value, retrying while a write overlaps.
+    #[inline]
+    pub fn read(&self) -> T {
+        loop {
+            let before = self.seq.load(Ordering::Acquire);
+            if before & 1 != 0 {
+                spin_loop();
+                continue;
+            }
+
+            // Safety: the copy may be torn by a concurrent writer; it is only
+            // returned if the sequence shows no write overlapped it.
+            let value = unsafe { core::ptr::read_volatile(self.cell.get()) };
+
+            fence(Ordering::Acquire);
+            if self.seq.load(Ordering::Relaxed) == before {
+                return value;
+            }
+        }
+    }
+
+    /// Returns the sequence number of the current value.
+    ///
+    /// It changes with every completed write and can be used to cheaply detect
+    /// updates between two reads.
+    #[inline]
+    pub fn sequence(&self) -> usize {
+        self.seq.load(Ordering::Acquire) & !1
+    }
+
+    /// Begins a write; readers retry until the returned guard is dropped.
+    #[inline]
+    pub fn write(&self) -> SeqLockWriteGuard<'_, T> {
+        self.begin_write(None)
+    }
+
+    /// Like [`write`](Self::write), but with interrupts disabled while the
+    /// guard is held.
+    ///
+    /// # Platform / Privilege
+    ///
+    /// Requires `x86/x86_64` and a privileged execution context where
+    /// `cli/sti` are permitted.
+    #[inline]
+    pub fn write_irq(&self) -> SeqLockWriteGuard<'_, T> {
+        self.begin_write(Some(IrqGuard::new()))
+    }
+
+    fn begin_write(&self, irq: Option<IrqGuard>) -> SeqLockWriteGuard<'_, T> {
+        lockdep::acquire(self.lockdep_key());
+        self.writer.lock();
+
+        let seq = self.seq.load(Ordering::Relaxed);
+        self.seq.store(seq.wrapping_add(1), Ordering::Relaxed);
+        fence(Ordering::Release);
+
+        SeqLockWriteGuard {
+            lock: self,
+            _irq: irq,
+        }
+    }
+
+    /// Replaces the value.
+    #[inline]
+    pub fn set(&self, value: T) {
+        *self.write() = value;
+    }
+
+    /// Returns a mutable reference to the inner value.
+    ///
+    /// Because you hold `&mut self`, no locking is needed.
+    #[inline]
+    pub const fn get_mut(&mut self) -> &mut T {
+        self.cell.get_mut()
+    }
+
+    fn lockdep_key(&self) -> usize {
+        core::ptr::from_ref(self).addr()
+    }
+}
+
+impl<T: Copy + Default> Default for SeqLock<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+/// Exclusive write access to a [`SeqLock`]; publishes the update on drop.
+pub struct SeqLockWriteGuard<'a, T: Copy> {
+    lock: &'a SeqLock<T>,
+    /// Dropped after the write is published (fields drop in declaration order).
+    _irq: Option<IrqGuard>,
+}
+
+impl<T: Copy> Deref for SeqLockWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // Safety: writers are serialized; we are the only one.
+        unsafe { &*self.lock.cell.get() }
+    }
+}
+
+impl<T: Copy> DerefMut for SeqLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // Safety: writers are serialized; readers never create references.
+        unsafe { &mut *self.lock.cell.get() }
+    }
+}
+
+impl<T: Copy> Drop for SeqLockWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        let seq = self.lock.seq.load(Ordering::Relaxed);
+        self.lock.seq.store(seq.wrapping_add(1), Ordering::Release);
+
+        // Safety: acquired in `SeqLock::write`.
+        unsafe { self.lock.writer.unlock() };
+        lockdep::release(self.lock.lockdep_key());
+    }
+}
@@ -0,0 +1,75 @@
+#![cfg(feature = "lockdep")]
+
+use kernel_sync::{RwSpinLock, SpinMutex, lockdep};
+use std::panic;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Give every test thread its own "CPU" so parallel tests don't share held-lock stacks.
+fn install_cpu_ids() {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    thread_local! {
+        static ID: usize = NEXT.fetch_add(1, Ordering::Relaxed);
+    }
+    lockdep::set_cpu_id_source(|| ID.with(|id| *id));
+}
+
+#[test]
+fn consistent_order_is_accepted() {
+    install_cpu_ids();
+    let a = SpinMutex::new(());
+    let b = SpinMutex::new(());
+
+    for _ in 0..3 {
+        let _ga = a.lock();
+        let _gb = b.lock();
+    }
+}
+
+#[test]
+fn order_inversion_panics() {
+    install_cpu_ids();
+    let a = Box::leak(Box::new(SpinMutex::new(())));
+    let b = Box::leak(Box::new(SpinMutex::new(())));
+
+    {
+        let _ga = a.lock();
+        let _gb = b.lock();
+    }
+
+    let res = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        let _gb = b.lock();
+        let _ga = a.lock();
+    }));
+    assert!(res.is_err(), "expected lockdep to report the inversion");
+}
+
+#[test]
+fn double_acquisition_panics() {
+    install_cpu_ids();
+    let a = Box::leak(Box::new(SpinMutex::new(())));
+
+    let res = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        let _g1 = a.lock();
+        let _g2 = a.lock();
+    }));
+    assert!(
+        res.is_err(),
+        "expected lockdep to report the double acquisition"
+    );
+}
+
+#[test]
+fn try_lock_is_not_checked() {
+    install_cpu_ids();
+    let a = SpinMutex::new(());
+    let b = RwSpinLock::new(());
+
+    {
+        let _ga = a.lock();
+        let _gb = b.write();
+    }
+
+    // Reverse order, but non-blocking: cannot deadlock.
+    let _gb = b.read();
+    assert!(a.try_lock().is_some());
+}
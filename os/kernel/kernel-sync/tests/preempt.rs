@@ -0,0 +1,42 @@
+use kernel_sync::PreemptGuard;
+use kernel_sync::preempt::{self, preempt_disable, preempt_enable};
+use std::cell::Cell;
+
+thread_local! {
+    /// Per-thread stand-in for the kernel's per-CPU preemption counter.
+    static COUNT: Cell<u32> = const { Cell::new(0) };
+}
+
+fn install_hooks() {
+    preempt::set_preempt_hooks(
+        || COUNT.with(|c| c.set(c.get() + 1)),
+        || COUNT.with(|c| c.set(c.get() - 1)),
+    );
+}
+
+fn count() -> u32 {
+    COUNT.with(Cell::get)
+}
+
+#[test]
+fn guard_disables_until_dropped() {
+    install_hooks();
+    {
+        let _g = PreemptGuard::new();
+        assert_eq!(count(), 1);
+    }
+    assert_eq!(count(), 0);
+}
+
+#[test]
+fn sections_nest() {
+    install_hooks();
+    preempt_disable();
+    {
+        let _g = PreemptGuard::new();
+        assert_eq!(count(), 2);
+    }
+    assert_eq!(count(), 1);
+    preempt_enable();
+    assert_eq!(count(), 0);
+}
@@ -0,0 +1,176 @@
+use core::ptr::NonNull;
+use kernel_sync::rcu::{Rcu, RcuHead, RcuPointer};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Barrier};
+use std::thread;
+
+#[repr(C)]
+struct Node {
+    head: RcuHead,
+    value: usize,
+    freed: AtomicBool,
+}
+
+fn node(value: usize) -> *mut Node {
+    Box::into_raw(Box::new(Node {
+        head: RcuHead::new(),
+        value,
+        freed: AtomicBool::new(false),
+    }))
+}
+
+/// Marks the node as freed instead of deallocating it, so tests can detect
+/// use after "free" without invoking undefined behavior.
+unsafe fn mark_freed(head: NonNull<RcuHead>) {
+    let node = unsafe { head.cast::<Node>().as_ref() };
+    assert!(!node.freed.swap(true, Ordering::SeqCst), "freed twice");
+}
+
+unsafe fn dealloc(ptr: *mut Node) {
+    drop(unsafe { Box::from_raw(ptr) });
+}
+
+fn retire<const CPUS: usize>(rcu: &Rcu<CPUS>, cpu: usize, ptr: *mut Node) {
+    unsafe { rcu.retire(cpu, NonNull::new(ptr).unwrap().cast(), mark_freed) };
+}
+
+fn is_freed(ptr: *mut Node) -> bool {
+    unsafe { &*ptr }.freed.load(Ordering::SeqCst)
+}
+
+#[test]
+fn reclaim_waits_for_active_reader() {
+    let rcu: Rcu<2> = Rcu::new();
+    let n = node(1);
+
+    let guard = rcu.read(1);
+    retire(&rcu, 0, n);
+    assert_eq!(rcu.pending(0), 1);
+
+    for _ in 0..10 {
+        assert_eq!(rcu.reclaim(0), 0);
+    }
+    assert!(!is_freed(n));
+
+    drop(guard);
+    rcu.synchronize();
+    assert_eq!(rcu.reclaim(0), 1);
+    assert_eq!(rcu.pending(0), 0);
+    assert!(is_freed(n));
+
+    unsafe { dealloc(n) };
+}
+
+#[test]
+fn nested_sections_stay_pinned_until_outermost_ends() {
+    let rcu: Rcu<1> = Rcu::new();
+    let outer = rcu.read(0);
+    let inner = rcu.read(0);
+    drop(inner);
+    assert!(rcu.is_reading(0));
+
+    let epoch = rcu.epoch();
+    assert!(rcu.try_advance());
+    assert!(!rcu.try_advance(), "pinned reader lags behind");
+    assert_eq!(rcu.epoch(), epoch + 1);
+
+    drop(outer);
+    assert!(!rcu.is_reading(0));
+    assert!(rcu.try_advance());
+}
+
+#[test]
+fn synchronize_allows_reclaiming_everything_retired_before() {
+    let rcu: Rcu<2> = Rcu::new();
+    let nodes: Vec<_> = (0..8).map(node).collect();
+    for (i, &n) in nodes.iter().enumerate() {
+        retire(&rcu, i % 2, n);
+    }
+
+    rcu.synchronize();
+    assert_eq!(rcu.reclaim(0) + rcu.reclaim(1), nodes.len());
+    for n in nodes {
+        assert!(is_freed(n));
+        unsafe { dealloc(n) };
+    }
+}
+
+#[test]
+fn pointer_load_sees_latest_swap() {
+    let rcu: Rcu<1> = Rcu::new();
+    let ptr = RcuPointer::null();
+    assert!(ptr.load(&rcu.read(0)).is_none());
+
+    let a = node(1);
+    let old = unsafe { ptr.swap(a) };
+    assert!(old.is_null());
+    assert_eq!(ptr.load(&rcu.read(0)).unwrap().value, 1);
+
+    let b = node(2);
+    assert_eq!(unsafe { ptr.swap(b) }, a);
+    assert_eq!(ptr.load(&rcu.read(0)).unwrap().value, 2);
+
+    unsafe {
+        dealloc(a);
+        dealloc(b);
+    }
+}
+
+#[test]
+fn concurrent_readers_never_see_freed_objects() {
+    const READERS: usize = 3;
+    const UPDATES: usize = 2_000;
+
+    let rcu = Arc::new(Rcu::<{ READERS + 1 }>::new());
+    let current = Arc::new(unsafe { RcuPointer::new(node(0)) });
+    let done = Arc::new(AtomicBool::new(false));
+    let reads = Arc::new(AtomicUsize::new(0));
+    let start = Arc::new(Barrier::new(READERS + 1));
+
+    let readers: Vec<_> = (0..READERS)
+        .map(|cpu| {
+            let (rcu, current, done, reads, start) = (
+                rcu.clone(),
+                current.clone(),
+                done.clone(),
+                reads.clone(),
+                start.clone(),
+            );
+            thread::spawn(move || {
+                start.wait();
+                while !done.load(Ordering::Relaxed) {
+                    let guard = rcu.read(cpu);
+                    let n = current.load(&guard).unwrap();
+                    assert!(!n.freed.load(Ordering::SeqCst), "read a reclaimed object");
+                    reads.fetch_add(1, Ordering::Relaxed);
+                }
+            })
+        })
+        .collect();
+
+    let writer_cpu = READERS;
+    let mut retired = Vec::new();
+    start.wait();
+    for i in 1..=UPDATES {
+        let old = unsafe { current.swap(node(i)) };
+        retire(&rcu, writer_cpu, old);
+        retired.push(old);
+        rcu.reclaim(writer_cpu);
+    }
+
+    done.store(true, Ordering::Relaxed);
+    for r in readers {
+        r.join().unwrap();
+    }
+
+    rcu.synchronize();
+    rcu.reclaim(writer_cpu);
+    assert_eq!(rcu.pending(writer_cpu), 0);
+    assert!(reads.load(Ordering::Relaxed) > 0);
+
+    for n in retired {
+        assert!(is_freed(n));
+        unsafe { dealloc(n) };
+    }
+    unsafe { dealloc(current.swap(core::ptr::null_mut())) };
+}
@@ -0,0 +1,144 @@
+use kernel_sync::ring::{MpscRing, RingStats};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Barrier};
+use std::thread;
+
+#[test]
+fn fifo_order() {
+    let r: MpscRing<u32, 4> = MpscRing::new();
+    assert!(r.is_empty());
+    for i in 0..4 {
+        r.push(i).unwrap();
+    }
+    assert_eq!(r.len(), 4);
+    for i in 0..4 {
+        assert_eq!(r.pop(), Some(i));
+    }
+    assert_eq!(r.pop(), None);
+}
+
+#[test]
+fn wraps_around_many_laps() {
+    let r: MpscRing<usize, 2> = MpscRing::new();
+    for i in 0..100 {
+        r.push(i).unwrap();
+        assert_eq!(r.pop(), Some(i));
+    }
+}
+
+#[test]
+fn full_ring_rejects_and_counts_overflow() {
+    let r: MpscRing<u8, 2> = MpscRing::new();
+    r.push(1).unwrap();
+    r.push(2).unwrap();
+    assert_eq!(r.push(3), Err(3));
+
+    assert_eq!(r.pop(), Some(1));
+    assert_eq!(
+        r.stats(),
+        RingStats {
+            pushed: 2,
+            popped: 1,
+            overflows: 1
+        }
+    );
+}
+
+#[test]
+fn force_push_evicts_oldest() {
+    let r: MpscRing<u8, 2> = MpscRing::new();
+    r.force_push(1);
+    r.force_push(2);
+    r.force_push(3);
+
+    assert_eq!(r.pop(), Some(2));
+    assert_eq!(r.pop(), Some(3));
+    assert_eq!(r.stats().overflows, 1);
+    assert_eq!(r.stats().popped, 2);
+}
+
+#[test]
+fn push_evicting_evicts_once() {
+    let r: MpscRing<u8, 2> = MpscRing::new();
+    r.push_evicting(1).unwrap();
+    r.push_evicting(2).unwrap();
+    r.push_evicting(3).unwrap();
+
+    assert_eq!(r.pop(), Some(2));
+    assert_eq!(r.pop(), Some(3));
+    assert_eq!(
+        r.stats(),
+        RingStats {
+            pushed: 3,
+            popped: 2,
+            overflows: 1
+        }
+    );
+}
+
+#[test]
+fn drop_releases_queued_items() {
+    let item = Arc::new(());
+    {
+        let r: MpscRing<Arc<()>, 4> = MpscRing::new();
+        r.push(Arc::clone(&item)).unwrap();
+        r.push(Arc::clone(&item)).unwrap();
+        assert_eq!(Arc::strong_count(&item), 3);
+    }
+    assert_eq!(Arc::strong_count(&item), 1);
+}
+
+#[test]
+fn concurrent_producers_single_consumer() {
+    const PRODUCERS: usize = 4;
+    const PER_PRODUCER: usize = 10_000;
+
+    let ring = Arc::new(MpscRing::<(usize, usize), 64>::new());
+    let start = Arc::new(Barrier::new(PRODUCERS + 1));
+    let done = Arc::new(AtomicBool::new(false));
+
+    let producers: Vec<_> = (0..PRODUCERS)
+        .map(|p| {
+            let ring = Arc::clone(&ring);
+            let start = Arc::clone(&start);
+            thread::spawn(move || {
+                start.wait();
+                for i in 0..PER_PRODUCER {
+                    while ring.push((p, i)).is_err() {
+                        thread::yield_now();
+                    }
+                }
+            })
+        })
+        .collect();
+
+    let consumer = {
+        let ring = Arc::clone(&ring);
+        let done = Arc::clone(&done);
+        thread::spawn(move || {
+            let mut next = [0usize; PRODUCERS];
+            loop {
+                match ring.pop() {
+                    Some((p, i)) => {
+                        // Per-producer order is preserved.
+                        assert_eq!(i, next[p]);
+                        next[p] += 1;
+                    }
+                    None if done.load(Ordering::Acquire) && ring.is_empty() => break,
+                    None => thread::yield_now(),
+                }
+            }
+            next
+        })
+    };
+
+    start.wait();
+    for p in producers {
+        p.join().unwrap();
+    }
+    done.store(true, Ordering::Release);
+
+    let counts = consumer.join().unwrap();
+    assert!(counts.iter().all(|&n| n == PER_PRODUCER));
+    assert_eq!(ring.stats().pushed, (PRODUCERS * PER_PRODUCER) as u64);
+}
@@ -0,0 +1,104 @@
+use kernel_sync::RwSpinLock;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Barrier};
+use std::thread;
+
+#[test]
+fn readers_share_the_lock() {
+    let l = RwSpinLock::new(5u32);
+
+    let r1 = l.read();
+    let r2 = l.read();
+    assert_eq!(*r1 + *r2, 10);
+
+    // no writer while readers are active
+    assert!(l.try_write().is_none());
+
+    drop(r1);
+    assert!(l.try_write().is_none());
+    drop(r2);
+    assert!(l.try_write().is_some());
+}
+
+#[test]
+fn writer_is_exclusive() {
+    let l = RwSpinLock::new(String::from("a"));
+
+    {
+        let mut w = l.write();
+        w.push('b');
+        assert!(l.try_read().is_none());
+        assert!(l.try_write().is_none());
+    }
+
+    assert_eq!(l.read().as_str(), "ab");
+}
+
+#[test]
+fn get_mut_and_into_inner() {
+    let mut l = RwSpinLock::new(vec![1, 2]);
+    l.get_mut().push(3);
+    assert_eq!(l.into_inner(), vec![1, 2, 3]);
+}
+
+#[test]
+fn waiting_writer_holds_back_new_readers() {
+    let l = Arc::new(RwSpinLock::new(0u32));
+    let reader = l.read();
+
+    let writer = {
+        let l = Arc::clone(&l);
+        thread::spawn(move || {
+            *l.write() = 1;
+        })
+    };
+
+    // Once the writer is queued, new readers must not get in.
+    while l.try_read().is_some() {
+        thread::yield_now();
+    }
+
+    drop(reader);
+    writer.join().unwrap();
+    assert_eq!(*l.read(), 1);
+}
+
+#[test]
+fn contended_readers_and_writers() {
+    let threads = 8;
+    let iters = 2_000;
+
+    let lock = Arc::new(RwSpinLock::new(0usize));
+    let writers_in = Arc::new(AtomicUsize::new(0));
+    let start = Arc::new(Barrier::new(threads));
+
+    let handles: Vec<_> = (0..threads)
+        .map(|t| {
+            let lock = Arc::clone(&lock);
+            let writers_in = Arc::clone(&writers_in);
+            let start = Arc::clone(&start);
+            thread::spawn(move || {
+                start.wait();
+                for _ in 0..iters {
+                    if t % 2 == 0 {
+                        let mut w = lock.write();
+                        let prev = writers_in.fetch_add(1, Ordering::SeqCst);
+                        assert_eq!(prev, 0, "writer exclusion violated");
+                        *w += 1;
+                        writers_in.fetch_sub(1, Ordering::SeqCst);
+                    } else {
+                        let _r = lock.read();
+                        assert_eq!(writers_in.load(Ordering::SeqCst), 0, "reader saw a writer");
+                    }
+                    thread::yield_now();
+                }
+            })
+        })
+        .collect();
+
+    for h in handles {
+        h.join().unwrap();
+    }
+
+    assert_eq!(*lock.read(), threads / 2 * iters);
+}
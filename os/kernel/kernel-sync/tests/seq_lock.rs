@@ -0,0 +1,83 @@
+use kernel_sync::SeqLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Barrier};
+use std::thread;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+struct Pair {
+    a: u64,
+    b: u64,
+}
+
+#[test]
+fn read_returns_latest_write() {
+    let l = SeqLock::new(Pair { a: 1, b: 2 });
+    assert_eq!(l.read(), Pair { a: 1, b: 2 });
+
+    l.set(Pair { a: 3, b: 4 });
+    assert_eq!(l.read(), Pair { a: 3, b: 4 });
+
+    l.write().a = 5;
+    assert_eq!(l.read(), Pair { a: 5, b: 4 });
+}
+
+#[test]
+fn sequence_advances_per_write() {
+    let l = SeqLock::new(0u32);
+    let s0 = l.sequence();
+    l.set(1);
+    let s1 = l.sequence();
+    assert_ne!(s0, s1);
+    assert_eq!(s1 % 2, 0, "sequence is even while no write is in progress");
+}
+
+#[test]
+fn get_mut_allows_direct_mutation() {
+    let mut l = SeqLock::new(1u8);
+    *l.get_mut() = 2;
+    assert_eq!(l.read(), 2);
+}
+
+#[test]
+fn readers_never_observe_torn_values() {
+    let readers = 4;
+    let writes = 20_000;
+
+    let lock = Arc::new(SeqLock::new(Pair::default()));
+    let done = Arc::new(AtomicBool::new(false));
+    let start = Arc::new(Barrier::new(readers + 1));
+
+    let handles: Vec<_> = (0..readers)
+        .map(|_| {
+            let lock = Arc::clone(&lock);
+            let done = Arc::clone(&done);
+            let start = Arc::clone(&start);
+            thread::spawn(move || {
+                start.wait();
+                while !done.load(Ordering::Acquire) {
+                    let p = lock.read();
+                    assert_eq!(p.b, p.a * 2, "torn read: {p:?}");
+                }
+            })
+        })
+        .collect();
+
+    start.wait();
+    for i in 1..=writes {
+        let mut w = lock.write();
+        w.a = i;
+        w.b = i * 2;
+    }
+    done.store(true, Ordering::Release);
+
+    for h in handles {
+        h.join().unwrap();
+    }
+    assert_eq!(
+        lock.read(),
+        Pair {
+            a: writes,
+            b: writes * 2
+        }
+    );
+}
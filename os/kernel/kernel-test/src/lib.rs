@@ -0,0 +1,63 @@
+//! # Kernel Tests
+//!
+//! Shared pieces of the in-kernel test facility: tests that run inside the
+//! booted kernel because they depend on hardware state the host cannot
+//! provide, such as the active page tables, `CR3` switches or `invlpg`.
+//!
+//! ## Declaring tests
+//!
+//! Any crate linked into the kernel can declare tests with
+//! [`#[kernel_test]`](kernel_test), usually in a module behind a
+//! `kernel-test` feature so host builds stay unaffected:
+//!
+//! ```ignore
+//! #[cfg(feature = "kernel-test")]
+//! mod ktests {
+//!     use kernel_test::kernel_test;
+//!
+//!     #[kernel_test]
+//!     fn cr3_is_page_aligned() {
+//!         assert_eq!(unsafe { kernel_vmem::read_cr3_phys() }.as_u64() % 4096, 0);
+//!     }
+//!
+//!     #[kernel_test(should_panic)]
+//!     fn out_of_range_index_panics() {
+//!         let _ = L4Index::new(512);
+//!     }
+//! }
+//! ```
+//!
+//! ## Registration
+//!
+//! The attribute places a [`KernelTest`] descriptor into the [`SECTION`]
+//! linker section. The kernel's linker script collects the section from all
+//! crates, and the kernel's `ktest` feature runs every descriptor at boot and
+//! reports the results over the QEMU debug console.
+
+#![cfg_attr(not(test), no_std)]
+
+use core::fmt;
+
+pub use utils_kernel_test_macros::kernel_test;
+
+/// Name of the linker section holding the [`KernelTest`] descriptors.
+pub const SECTION: &str = ".ktests";
+
+/// A registered test; emitted by [`#[kernel_test]`](kernel_test).
+#[derive(Debug)]
+pub struct KernelTest {
+    /// Name of the test function.
+    pub name: &'static str,
+    /// Module path of the test function, starting with the crate name.
+    pub module: &'static str,
+    /// The test function; it fails by panicking.
+    pub run: fn(),
+    /// Whether the test passes only if it panics.
+    pub should_panic: bool,
+}
+
+impl fmt::Display for KernelTest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}::{}", self.module, self.name)
+    }
+}
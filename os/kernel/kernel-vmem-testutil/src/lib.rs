@@ -0,0 +1,50 @@
+//! # Test Harness for `kernel-vmem`
+//!
+//! Host-side stand-ins for what [`kernel_vmem`] expects from the kernel, so
+//! that page table code can be exercised by `cargo test` without paging
+//! hardware:
+//!
+//! - [`MockMemory`]: a pool of heap-backed 4 KiB frames at made-up physical
+//!   addresses, acting as both the [`PhysMapper`](kernel_vmem::PhysMapper)
+//!   and, through [`MockMemory::allocator`], the
+//!   [`PhysFrameAlloc`](kernel_vmem::PhysFrameAlloc). It tracks which frames
+//!   are live and panics on double frees and on accesses to freed frames.
+//! - [`walk_tables`]: every table reachable from a root, with the number of
+//!   present entries in it, to check the shape of a tree after an operation.
+//! - [`Rng`] and [`run_cases`]: seeded randomness for property-style tests
+//!   that name the failing seed.
+//!
+//! Address spaces under test are built with
+//! [`AddressSpace::from_root`](kernel_vmem::AddressSpace::from_root) on a
+//! frame of the pool; everything that reads CR3 is out of reach on the host.
+//!
+//! ## Example
+//!
+//! ```rust
+//! use kernel_memory_addresses::{PhysicalAddress, Size4K, VirtualAddress};
+//! use kernel_vmem::{AddressSpace, PhysFrameAlloc, VirtualMemoryPageBits};
+//! use kernel_vmem_testutil::MockMemory;
+//!
+//! let mem = MockMemory::new(16);
+//! let mut alloc = mem.allocator();
+//! let space = AddressSpace::from_root(&mem, alloc.alloc_4k().unwrap());
+//!
+//! let flags = VirtualMemoryPageBits::new()
+//!     .with_present(true)
+//!     .with_writable(true);
+//! let (va, pa) = (VirtualAddress::new(0x40_0000), PhysicalAddress::new(0x8000));
+//! space
+//!     .map_one::<_, Size4K>(&mut alloc, va, pa, flags, flags)
+//!     .unwrap();
+//!
+//! assert_eq!(space.query(va), Some(pa));
+//! assert_eq!(mem.live_count(), 4); // PML4, PDPT, PD and PT
+//! ```
+
+mod memory;
+mod rng;
+mod walk;
+
+pub use crate::memory::{MockFrameAlloc, MockMemory};
+pub use crate::rng::{Rng, run_cases};
+pub use crate::walk::{TableInfo, walk_tables};
@@ -0,0 +1,154 @@
+//! Mock physical memory.
+
+use kernel_memory_addresses::{PhysicalAddress, PhysicalPage, Size4K};
+use kernel_vmem::{PhysFrameAlloc, PhysMapper};
+use std::cell::{RefCell, UnsafeCell};
+use std::collections::BTreeSet;
+
+const FRAME_SIZE: usize = 4096;
+
+/// One 4 KiB frame of backing storage.
+#[repr(C, align(4096))]
+struct Frame([u8; FRAME_SIZE]);
+
+/// A pool of 4 KiB frames standing in for physical memory.
+///
+/// Frame `i` lives at physical address `BASE + i * 4096`; see
+/// [`MockMemory::BASE`].
+/// Only frames handed out by the [allocator](MockMemory::allocator) may be
+/// accessed through the [`PhysMapper`]; anything else panics. That turns a
+/// table that is still linked after it was freed into a test failure rather
+/// than silent corruption.
+pub struct MockMemory {
+    frames: Box<[UnsafeCell<Frame>]>,
+    state: RefCell<State>,
+}
+
+struct State {
+    /// Indices of free frames; the last one is handed out next.
+    free: Vec<usize>,
+    /// Indices of allocated frames.
+    live: BTreeSet<usize>,
+}
+
+impl MockMemory {
+    /// Physical address of the first frame.
+    ///
+    /// Not zero, so that a zeroed entry never points at a valid frame.
+    pub const BASE: u64 = 0x10_0000;
+
+    /// A pool of `frames` frames, all free.
+    #[must_use]
+    pub fn new(frames: usize) -> Self {
+        Self {
+            frames: (0..frames)
+                .map(|_| UnsafeCell::new(Frame([0; FRAME_SIZE])))
+                .collect(),
+            state: RefCell::new(State {
+                free: (0..frames).rev().collect(),
+                live: BTreeSet::new(),
+            }),
+        }
+    }
+
+    /// A [`PhysFrameAlloc`] handing out frames of this pool.
+    #[must_use]
+    pub const fn allocator(&self) -> MockFrameAlloc<'_> {
+        MockFrameAlloc { memory: self }
+    }
+
+    /// Whether `page` is an allocated frame of this pool.
+    #[must_use]
+    pub fn is_live(&self, page: PhysicalPage<Size4K>) -> bool {
+        self.index_of(page.base())
+            .is_some_and(|i| self.state.borrow().live.contains(&i))
+    }
+
+    /// Number of allocated frames.
+    #[must_use]
+    pub fn live_count(&self) -> usize {
+        self.state.borrow().live.len()
+    }
+
+    /// All allocated frames, in ascending order.
+    #[must_use]
+    pub fn live_frames(&self) -> BTreeSet<PhysicalPage<Size4K>> {
+        self.state
+            .borrow()
+            .live
+            .iter()
+            .map(|&i| Self::page_of(i))
+            .collect()
+    }
+
+    fn alloc(&self) -> Option<PhysicalPage<Size4K>> {
+        let mut state = self.state.borrow_mut();
+        let i = state.free.pop()?;
+        state.live.insert(i);
+
+        // Safety: the frame was free, so nothing references it.
+        unsafe { (*self.frames[i].get()).0.fill(0) };
+        Some(Self::page_of(i))
+    }
+
+    fn free(&self, page: PhysicalPage<Size4K>) {
+        let i = self
+            .index_of(page.base())
+            .unwrap_or_else(|| panic!("freed frame {page:?} is not part of the pool"));
+        let mut state = self.state.borrow_mut();
+        assert!(state.live.remove(&i), "double free of frame {page:?}");
+        state.free.push(i);
+    }
+
+    fn index_of(&self, at: PhysicalAddress) -> Option<usize> {
+        let offset = at.as_u64().checked_sub(Self::BASE)?;
+        let i = usize::try_from(offset).ok()? / FRAME_SIZE;
+        (i < self.frames.len()).then_some(i)
+    }
+
+    const fn page_of(i: usize) -> PhysicalPage<Size4K> {
+        PhysicalPage::from_addr(PhysicalAddress::new(Self::BASE + (i * FRAME_SIZE) as u64))
+    }
+}
+
+impl PhysMapper for MockMemory {
+    unsafe fn phys_to_mut<T>(&self, at: PhysicalAddress) -> &mut T {
+        let i = self
+            .index_of(at)
+            .unwrap_or_else(|| panic!("{at:?} is not part of the pool"));
+        assert!(
+            self.state.borrow().live.contains(&i),
+            "access to frame at {at:?}, which is not allocated"
+        );
+
+        #[allow(clippy::cast_possible_truncation)]
+        let offset = (at.as_u64() - Self::BASE) as usize % FRAME_SIZE;
+        assert!(
+            offset + size_of::<T>() <= FRAME_SIZE,
+            "access at {at:?} crosses a frame boundary"
+        );
+
+        // Safety: in bounds of a live frame; aliasing is the caller's
+        // problem, as with the kernel's direct map.
+        unsafe { &mut *self.frames[i].get().cast::<u8>().add(offset).cast::<T>() }
+    }
+}
+
+/// The [`PhysFrameAlloc`] of a [`MockMemory`]; see [`MockMemory::allocator`].
+///
+/// Frames are zeroed when handed out. Freeing a frame that is not allocated
+/// panics.
+#[derive(Copy, Clone)]
+pub struct MockFrameAlloc<'m> {
+    memory: &'m MockMemory,
+}
+
+impl PhysFrameAlloc for MockFrameAlloc<'_> {
+    fn alloc_4k(&mut self) -> Option<PhysicalPage<Size4K>> {
+        self.memory.alloc()
+    }
+
+    fn free_4k(&mut self, pa: PhysicalPage<Size4K>) {
+        self.memory.free(pa);
+    }
+}
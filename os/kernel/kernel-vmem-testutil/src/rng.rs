@@ -0,0 +1,73 @@
+//! Seeded randomness for property-style tests.
+
+use std::panic::{self, AssertUnwindSafe};
+
+/// A small, deterministic pseudo-random number generator (`SplitMix64`).
+///
+/// Not for anything but tests: the same seed always gives the same sequence,
+/// so a failing case can be replayed from its seed.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    #[must_use]
+    pub const fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// The next 64 random bits.
+    pub const fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..bound`.
+    ///
+    /// # Panics
+    /// If `bound` is zero.
+    pub fn below(&mut self, bound: u64) -> u64 {
+        assert_ne!(bound, 0, "empty range");
+        self.next_u64() % bound
+    }
+
+    /// `true` with a probability of `1 / n`.
+    pub fn one_in(&mut self, n: u64) -> bool {
+        self.below(n) == 0
+    }
+
+    /// A random element of `items`.
+    ///
+    /// # Panics
+    /// If `items` is empty.
+    pub fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        #[allow(clippy::cast_possible_truncation)]
+        &items[self.below(items.len() as u64) as usize]
+    }
+}
+
+/// Run `case` once for each of `cases` seeds, with an [`Rng`] of that seed.
+///
+/// The seeds are `0..cases`, or only the one in the `VMEM_TEST_SEED`
+/// environment variable if it is set, to replay a failure.
+///
+/// # Panics
+/// If a case panics; the message names its seed.
+pub fn run_cases(cases: u64, mut case: impl FnMut(&mut Rng)) {
+    let seeds = std::env::var("VMEM_TEST_SEED").map_or(0..cases, |seed| {
+        let seed = seed.parse().expect("VMEM_TEST_SEED is not a number");
+        seed..seed + 1
+    });
+
+    for seed in seeds {
+        let mut rng = Rng::new(seed);
+        if let Err(panic) = panic::catch_unwind(AssertUnwindSafe(|| case(&mut rng))) {
+            eprintln!("case failed with seed {seed}; replay with VMEM_TEST_SEED={seed}");
+            panic::resume_unwind(panic);
+        }
+    }
+}
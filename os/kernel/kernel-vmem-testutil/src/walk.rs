@@ -0,0 +1,74 @@
+//! Inspecting the shape of a page table tree.
+
+use kernel_memory_addresses::{PhysicalPage, Size4K};
+use kernel_vmem::PhysMapperExt;
+use kernel_vmem::page_table::pd::{L2Index, PdEntryKind};
+use kernel_vmem::page_table::pdpt::{L3Index, PdptEntryKind};
+use kernel_vmem::page_table::pml4::L4Index;
+use kernel_vmem::page_table::pt::L1Index;
+
+/// A table found by [`walk_tables`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct TableInfo {
+    /// The frame holding the table.
+    pub frame: PhysicalPage<Size4K>,
+    /// `4` for the PML4 down to `1` for a PT.
+    pub level: u8,
+    /// Number of present entries, leaves and links alike.
+    pub present: usize,
+}
+
+/// Every table reachable from the PML4 in `root`, parents before children.
+#[allow(clippy::similar_names)]
+pub fn walk_tables<M: PhysMapperExt>(mapper: &M, root: PhysicalPage<Size4K>) -> Vec<TableInfo> {
+    let mut tables = Vec::new();
+    let pml4 = mapper.pml4_mut(root);
+    let pdpts: Vec<_> = (0..512)
+        .filter_map(|i4| pml4.get(L4Index::new(i4)).next_table())
+        .collect();
+    tables.push(TableInfo {
+        frame: root,
+        level: 4,
+        present: (0..512)
+            .filter(|&i4| pml4.get(L4Index::new(i4)).present())
+            .count(),
+    });
+
+    for pdpt_page in pdpts {
+        let pdpt = mapper.pdpt_mut(pdpt_page);
+        let entries: Vec<_> = (0..512).map(|i3| pdpt.get(L3Index::new(i3))).collect();
+        tables.push(TableInfo {
+            frame: pdpt_page,
+            level: 3,
+            present: entries.iter().filter(|e| e.present()).count(),
+        });
+
+        for e3 in entries {
+            let Some(PdptEntryKind::NextPageDirectory(pd_page, _)) = e3.kind() else {
+                continue;
+            };
+            let pd = mapper.pd_mut(pd_page);
+            let entries: Vec<_> = (0..512).map(|i2| pd.get(L2Index::new(i2))).collect();
+            tables.push(TableInfo {
+                frame: pd_page,
+                level: 2,
+                present: entries.iter().filter(|e| e.present()).count(),
+            });
+
+            for e2 in entries {
+                let Some(PdEntryKind::NextPageTable(pt_page, _)) = e2.kind() else {
+                    continue;
+                };
+                let pt = mapper.pt_mut(pt_page);
+                tables.push(TableInfo {
+                    frame: pt_page,
+                    level: 1,
+                    present: (0..512)
+                        .filter(|&i1| pt.get(L1Index::new(i1)).present())
+                        .count(),
+                });
+            }
+        }
+    }
+    tables
+}
@@ -8,8 +8,19 @@
 //!
 //! - [`AddressSpace::map_one`] to install one mapping (4 KiB / 2 MiB / 1 GiB).
 //! - [`AddressSpace::unmap_one`] to clear a single 4 KiB PTE.
+//! - [`AddressSpace::split_to_4k`] to break the huge leaf over a page into 4 KiB leaves.
+//! - [`AddressSpace::promote_large_pages`] to merge runs of small leaves back into
+//!   2 MiB and 1 GiB leaves.
 //! - [`AddressSpace::query`] to translate a VA to PA (handles huge pages).
+//! - [`AddressSpace::for_each_leaf`] to visit every mapping with its effective flags.
+//! - [`AddressSpace::trace`] to visit the entries the walk to one VA passes.
 //! - [`AddressSpace::activate`] to load CR3 with this space’s root.
+//! - [`AddressSpace::destroy`] to free the user half and the PML4 of a dead space.
+//! - [`AddressSpace::sync_upper_half_from`] to pick up kernel-half PML4 slots filled
+//!   after creation; see [`RootRegistry`].
+//! - [`AddressSpace::cow_clone_into`], [`AddressSpace::cow_page`] and
+//!   [`AddressSpace::break_cow`] to fork the user half copy-on-write and to
+//!   resolve the resulting write faults.
 //!
 //! ## Design
 //!
@@ -28,9 +39,10 @@
 //! - The provided `PhysMapper` must yield **writable** references to table frames.
 
 mod map_size;
+mod roots;
 
-pub use crate::address_space::map_size::MapSize;
-use crate::address_space::map_size::MapSizeEnsureChainError;
+pub use crate::address_space::map_size::{MapSize, MapSizeEnsureChainError};
+pub use crate::address_space::roots::{RegistryFull, RootRegistry, kernel_pml4_generation};
 use crate::bits::VirtualMemoryPageBits;
 use crate::page_table::pd::{L2Index, PageDirectory, PdEntry, PdEntryKind};
 use crate::page_table::pdpt::{L3Index, PageDirectoryPointerTable, PdptEntry, PdptEntryKind};
@@ -52,6 +64,8 @@ pub struct AddressSpace<'m, M: PhysMapper> {
 pub enum AddressSpaceError {
     #[error("Failed to create a new address space due to OOM in the allocator")]
     OutOfMemory,
+    #[error("Failed to create a new address space because too many are live")]
+    TooMany,
 }
 
 /// The PML4 root page for an [`AddressSpace`].
@@ -145,6 +159,33 @@ impl<'m, M: PhysMapper> AddressSpace<'m, M> {
         }
     }
 
+    /// The flags of the leaf that maps `va`, if mapped.
+    ///
+    /// Only the leaf entry is inspected; permissions of the non-leaf links
+    /// above it are not intersected in.
+    #[must_use]
+    pub fn query_flags(&self, va: VirtualAddress) -> Option<VirtualMemoryPageBits> {
+        match self.walk(va) {
+            WalkResult::Leaf1G { pdpt, i3, .. } => match pdpt.get(i3).kind() {
+                Some(PdptEntryKind::Leaf1GiB(_, entry)) => {
+                    Some(VirtualMemoryPageBits::from_pdpte_1g(&entry))
+                }
+                _ => None,
+            },
+            WalkResult::Leaf2M { pd, i2, .. } => match pd.get(i2).kind() {
+                Some(PdEntryKind::Leaf2MiB(_, entry)) => {
+                    Some(VirtualMemoryPageBits::from_pde_2m(&entry))
+                }
+                _ => None,
+            },
+            WalkResult::L1 { pte, .. } => {
+                let (_, entry) = pte.page_4k()?;
+                Some(VirtualMemoryPageBits::from_pte_4k(&entry))
+            }
+            WalkResult::Missing => None,
+        }
+    }
+
     /// Map **one** page at `va → pa` with size `S` and `leaf_flags`.
     ///
     /// - Non-leaf links are created with `nonleaf_flags` (e.g., present+writable).
@@ -192,6 +233,103 @@ impl<'m, M: PhysMapper> AddressSpace<'m, M> {
         }
     }
 
+    /// Set the writable and no-execute bits of the leaf that maps `va`, in place.
+    ///
+    /// All other bits (cache mode, global, user) are kept. Works for leaves of
+    /// any size and changes the **whole** leaf; returns its size in bytes so
+    /// that callers can step over a region without knowing how it is tiled.
+    ///
+    /// The caller must invalidate the TLB for the leaf.
+    ///
+    /// # Errors
+    /// - `va` is not mapped.
+    // TODO: Refactor to error type
+    pub fn protect_one(
+        &self,
+        va: VirtualAddress,
+        writable: bool,
+        no_execute: bool,
+    ) -> Result<u64, &'static str> {
+        match self.walk(va) {
+            WalkResult::Leaf1G { base, pdpt, i3 } => {
+                let Some(PdptEntryKind::Leaf1GiB(_, entry)) = pdpt.get(i3).kind() else {
+                    return Err("missing: pdpte");
+                };
+                let flags = VirtualMemoryPageBits::from_pdpte_1g(&entry)
+                    .with_writable(writable)
+                    .with_no_execute(no_execute);
+                pdpt.set(i3, PdptEntry::present_leaf_with(flags, base));
+                Ok(Size1G::SIZE)
+            }
+            WalkResult::Leaf2M { base, pd, i2 } => {
+                let Some(PdEntryKind::Leaf2MiB(_, entry)) = pd.get(i2).kind() else {
+                    return Err("missing: pde");
+                };
+                let flags = VirtualMemoryPageBits::from_pde_2m(&entry)
+                    .with_writable(writable)
+                    .with_no_execute(no_execute);
+                pd.set(i2, PdEntry::present_leaf_with(flags, base));
+                Ok(Size2M::SIZE)
+            }
+            WalkResult::L1 { pt, i1, pte } => {
+                let Some((page, entry)) = pte.page_4k() else {
+                    return Err("missing: pte");
+                };
+                let flags = VirtualMemoryPageBits::from_pte_4k(&entry)
+                    .with_writable(writable)
+                    .with_no_execute(no_execute);
+                pt.set(i1, PtEntry4k::present_with(flags, page));
+                Ok(Size4K::SIZE)
+            }
+            WalkResult::Missing => Err("missing: chain"),
+        }
+    }
+
+    /// Break the huge leaf that maps `va` down until `va` is mapped by a
+    /// 4 KiB leaf, e.g. to unmap or protect a single page of it.
+    ///
+    /// A 1 GiB leaf becomes a PD of 2 MiB leaves, the one covering `va`
+    /// becomes a PT of 4 KiB leaves. Every address keeps its translation and
+    /// flags, so the caller need not flush the TLB. New links use
+    /// `nonleaf_flags`. Does nothing if `va` already is a 4 KiB page or not
+    /// mapped at all.
+    ///
+    /// # Errors
+    /// - Out of memory for the new PD or PT; the leaf is then left as it was.
+    pub fn split_to_4k<A: PhysFrameAlloc>(
+        &self,
+        alloc: &mut A,
+        va: VirtualAddress,
+        nonleaf_flags: VirtualMemoryPageBits,
+    ) -> Result<(), MapSizeEnsureChainError> {
+        if let WalkResult::Leaf1G { base, pdpt, i3 } = self.walk(va)
+            && let Some(PdptEntryKind::Leaf1GiB(_, entry)) = pdpt.get(i3).kind()
+        {
+            let flags = VirtualMemoryPageBits::from_pdpte_1g(&entry);
+            let f = alloc.alloc_4k().ok_or(MapSizeEnsureChainError::OomPd)?;
+            let pd = self.pd_mut(f);
+            for i in 0..512 {
+                let page = PhysicalPage::from_addr(base.base() + u64::from(i) * Size2M::SIZE);
+                pd.set(L2Index::new(i), PdEntry::present_leaf_with(flags, page));
+            }
+            pdpt.set(i3, PdptEntry::present_next_with(nonleaf_flags, f));
+        }
+
+        if let WalkResult::Leaf2M { base, pd, i2 } = self.walk(va)
+            && let Some(PdEntryKind::Leaf2MiB(_, entry)) = pd.get(i2).kind()
+        {
+            let flags = VirtualMemoryPageBits::from_pde_2m(&entry);
+            let f = alloc.alloc_4k().ok_or(MapSizeEnsureChainError::OomPt)?;
+            let pt = self.pt_mut(f);
+            for i in 0..512 {
+                let page = PhysicalPage::from_addr(base.base() + u64::from(i) * Size4K::SIZE);
+                pt.set(L1Index::new(i), PtEntry4k::present_with(flags, page));
+            }
+            pd.set(i2, PdEntry::present_next_with(nonleaf_flags, f));
+        }
+        Ok(())
+    }
+
     /// Greedy region mapping: tiles `[virt_start .. virt_start+len)` onto
     /// `[phys_start .. phys_start+len)` using 1G / 2M / 4K pages as alignment permits.
     ///
@@ -351,6 +489,468 @@ impl<'m, M: PhysMapper> AddressSpace<'m, M> {
         }
     }
 
+    /// Replace runs of small leaves in `[virt_start .. virt_start+len)` with
+    /// large ones, freeing the tables that held them.
+    ///
+    /// A page table becomes a single 2 MiB leaf if its 512 entries are all
+    /// present and map 2 MiB of physically contiguous, 2 MiB aligned memory
+    /// with the same attributes (accessed and dirty aside). Then, likewise, a
+    /// page directory of 512 such 2 MiB leaves becomes a 1 GiB leaf. Only
+    /// tables that lie wholly inside the range are considered, and
+    /// [copy-on-write](VirtualMemoryPageBits::copy_on_write) leaves are never
+    /// merged.
+    ///
+    /// The new leaf grants no more than the walk through the replaced link
+    /// did: it is writable or user-accessible only if both link and leaves
+    /// were, and not executable if either forbade it.
+    ///
+    /// Translations do not change, but the caller must flush the TLB of
+    /// every CPU that may use this space, global entries included, before
+    /// the freed frames are used again: the paging-structure caches may
+    /// still point into them.
+    #[allow(clippy::similar_names)]
+    pub fn promote_large_pages<F: PhysFrameAlloc>(
+        &self,
+        free: &mut F,
+        virt_start: VirtualAddress,
+        len: u64,
+    ) -> PromotionStats {
+        let start = virt_start.as_u64();
+        let end = start.saturating_add(len);
+        let mut stats = PromotionStats::default();
+
+        let mut va = start.next_multiple_of(Size2M::SIZE);
+        while va.checked_add(Size2M::SIZE).is_some_and(|next| next <= end) {
+            if let Some((pd, i2)) = self.walk_to_pd(VirtualAddress::new(va))
+                && let Some(pt_page) = self.promote_pt(pd, i2)
+            {
+                free.free_4k(pt_page);
+                stats.to_2m += 1;
+            }
+            va += Size2M::SIZE;
+        }
+
+        let mut va = start.next_multiple_of(Size1G::SIZE);
+        while va.checked_add(Size1G::SIZE).is_some_and(|next| next <= end) {
+            if let Some((pdpt, i3)) = self.walk_to_pdpt(VirtualAddress::new(va))
+                && let Some(pd_page) = self.promote_pd(pdpt, i3)
+            {
+                free.free_4k(pd_page);
+                stats.to_1g += 1;
+            }
+            va += Size1G::SIZE;
+        }
+        stats
+    }
+
+    /// Replace the page table linked at `pd[i2]` by a 2 MiB leaf if it can
+    /// be; returns the table's frame if so.
+    fn promote_pt(&self, pd: &mut PageDirectory, i2: L2Index) -> Option<PhysicalPage<Size4K>> {
+        let Some(PdEntryKind::NextPageTable(pt_page, link)) = pd.get(i2).kind() else {
+            return None;
+        };
+        let pt = self.pt_mut(pt_page);
+        let (first, entry) = pt.get(L1Index::new(0)).page_4k()?;
+        let base = first.base();
+        if base.offset::<Size2M>().as_u64() != 0 {
+            return None;
+        }
+
+        let mut flags = VirtualMemoryPageBits::from_pte_4k(&entry);
+        for i in 1..512 {
+            let (page, entry) = pt.get(L1Index::new(i)).page_4k()?;
+            if page.base().as_u64() != base.as_u64() + u64::from(i) * Size4K::SIZE {
+                return None;
+            }
+            flags = merge_leaf_flags(flags, VirtualMemoryPageBits::from_pte_4k(&entry))?;
+        }
+        if flags.copy_on_write() {
+            return None;
+        }
+
+        let flags = restrict_to_link(flags, VirtualMemoryPageBits::from_pde(&link));
+        pd.set(
+            i2,
+            PdEntry::present_leaf_with(flags, PhysicalPage::from_addr(base)),
+        );
+        trace!("Promoted PT {pt_page:?} to a 2 MiB leaf at PA={base}");
+        Some(pt_page)
+    }
+
+    /// Replace the page directory linked at `pdpt[i3]` by a 1 GiB leaf if
+    /// it can be; returns the directory's frame if so.
+    fn promote_pd(
+        &self,
+        pdpt: &mut PageDirectoryPointerTable,
+        i3: L3Index,
+    ) -> Option<PhysicalPage<Size4K>> {
+        let Some(PdptEntryKind::NextPageDirectory(pd_page, link)) = pdpt.get(i3).kind() else {
+            return None;
+        };
+        let pd = self.pd_mut(pd_page);
+        let Some(PdEntryKind::Leaf2MiB(first, entry)) = pd.get(L2Index::new(0)).kind() else {
+            return None;
+        };
+        let base = first.base();
+        if base.offset::<Size1G>().as_u64() != 0 {
+            return None;
+        }
+
+        let mut flags = VirtualMemoryPageBits::from_pde_2m(&entry);
+        for i in 1..512 {
+            let Some(PdEntryKind::Leaf2MiB(page, entry)) = pd.get(L2Index::new(i)).kind() else {
+                return None;
+            };
+            if page.base().as_u64() != base.as_u64() + u64::from(i) * Size2M::SIZE {
+                return None;
+            }
+            flags = merge_leaf_flags(flags, VirtualMemoryPageBits::from_pde_2m(&entry))?;
+        }
+        if flags.copy_on_write() {
+            return None;
+        }
+
+        let flags = restrict_to_link(flags, VirtualMemoryPageBits::from_pdpte(&link));
+        pdpt.set(
+            i3,
+            PdptEntry::present_leaf_with(flags, PhysicalPage::from_addr(base)),
+        );
+        trace!("Promoted PD {pd_page:?} to a 1 GiB leaf at PA={base}");
+        Some(pd_page)
+    }
+
+    /// Call `f` for every present leaf, in address order.
+    ///
+    /// The [`Leaf::flags`] are the ones the walk grants, see there.
+    #[allow(clippy::similar_names)]
+    pub fn for_each_leaf(&self, mut f: impl FnMut(&Leaf)) {
+        let pml4 = self.pml4_mut();
+        for i4 in 0..512 {
+            let e4 = pml4.get(L4Index::new(i4));
+            let Some(pdpt_page) = e4.next_table() else {
+                continue;
+            };
+            let l4 = VirtualMemoryPageBits::from_pml4e(&e4);
+            let pdpt = self.pdpt_mut(pdpt_page);
+            for i3 in 0..512 {
+                let (pd_page, l3) = match pdpt.get(L3Index::new(i3)).kind() {
+                    Some(PdptEntryKind::NextPageDirectory(pd_page, entry)) => {
+                        (pd_page, VirtualMemoryPageBits::from_pdpte(&entry))
+                    }
+                    Some(PdptEntryKind::Leaf1GiB(base, entry)) => {
+                        let flags = VirtualMemoryPageBits::from_pdpte_1g(&entry);
+                        f(&Leaf::new(
+                            canonical_va(i4, i3, 0, 0),
+                            base.base(),
+                            3,
+                            [l4],
+                            flags,
+                        ));
+                        continue;
+                    }
+                    None => continue,
+                };
+                let pd = self.pd_mut(pd_page);
+                for i2 in 0..512 {
+                    let (pt_page, l2) = match pd.get(L2Index::new(i2)).kind() {
+                        Some(PdEntryKind::NextPageTable(pt_page, entry)) => {
+                            (pt_page, VirtualMemoryPageBits::from_pde(&entry))
+                        }
+                        Some(PdEntryKind::Leaf2MiB(base, entry)) => {
+                            let flags = VirtualMemoryPageBits::from_pde_2m(&entry);
+                            let va = canonical_va(i4, i3, i2, 0);
+                            f(&Leaf::new(va, base.base(), 2, [l4, l3], flags));
+                            continue;
+                        }
+                        None => continue,
+                    };
+                    let pt = self.pt_mut(pt_page);
+                    for i1 in 0..512 {
+                        let Some((page, entry)) = pt.get(L1Index::new(i1)).page_4k() else {
+                            continue;
+                        };
+                        let flags = VirtualMemoryPageBits::from_pte_4k(&entry);
+                        let va = canonical_va(i4, i3, i2, i1);
+                        f(&Leaf::new(va, page.base(), 1, [l4, l3, l2], flags));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Call `f` for each entry the walk to `va` passes, from the PML4 down to
+    /// the leaf or the first entry that is not present.
+    #[allow(clippy::similar_names)]
+    pub fn trace(&self, va: VirtualAddress, mut f: impl FnMut(&Step)) {
+        let (i4, i3, i2, i1) = crate::page_table::split_indices(va);
+
+        let e4 = self.pml4_mut().get(i4);
+        let next = e4.next_table();
+        let flags = VirtualMemoryPageBits::from_pml4e(&e4);
+        f(&Step::link(4, i4.as_usize(), self.root, next, flags));
+        let Some(pdpt_page) = next else {
+            return;
+        };
+
+        let pd_page = match self.pdpt_mut(pdpt_page).get(i3).kind() {
+            Some(PdptEntryKind::NextPageDirectory(pd_page, entry)) => {
+                let flags = VirtualMemoryPageBits::from_pdpte(&entry);
+                f(&Step::link(
+                    3,
+                    i3.as_usize(),
+                    pdpt_page,
+                    Some(pd_page),
+                    flags,
+                ));
+                pd_page
+            }
+            Some(PdptEntryKind::Leaf1GiB(base, entry)) => {
+                let flags = VirtualMemoryPageBits::from_pdpte_1g(&entry);
+                f(&Step::leaf(3, i3.as_usize(), pdpt_page, base.base(), flags));
+                return;
+            }
+            None => {
+                f(&Step::missing(3, i3.as_usize(), pdpt_page));
+                return;
+            }
+        };
+
+        let pt_page = match self.pd_mut(pd_page).get(i2).kind() {
+            Some(PdEntryKind::NextPageTable(pt_page, entry)) => {
+                let flags = VirtualMemoryPageBits::from_pde(&entry);
+                f(&Step::link(2, i2.as_usize(), pd_page, Some(pt_page), flags));
+                pt_page
+            }
+            Some(PdEntryKind::Leaf2MiB(base, entry)) => {
+                let flags = VirtualMemoryPageBits::from_pde_2m(&entry);
+                f(&Step::leaf(2, i2.as_usize(), pd_page, base.base(), flags));
+                return;
+            }
+            None => {
+                f(&Step::missing(2, i2.as_usize(), pd_page));
+                return;
+            }
+        };
+
+        match self.pt_mut(pt_page).get(i1).page_4k() {
+            Some((page, entry)) => {
+                let flags = VirtualMemoryPageBits::from_pte_4k(&entry);
+                f(&Step::leaf(1, i1.as_usize(), pt_page, page.base(), flags));
+            }
+            None => f(&Step::missing(1, i1.as_usize(), pt_page)),
+        }
+    }
+
+    /// Tear down the user (lower) half and free every frame it owns,
+    /// including the PML4 itself.
+    ///
+    /// All PDPT, PD and PT frames below PML4 slots `0..256` are freed. The frame
+    /// behind a 4 KiB leaf is freed only if `release(va, frame)` says this
+    /// address space held the last reference to it (typically because the
+    /// address space [owns](crate::vma::VmaSet::owns_frame) the page and no
+    /// fork shares it anymore); other leaves, and all 2 MiB and 1 GiB leaves,
+    /// may map shared or device memory and are just dropped. The kernel half (slots `256..512`) aliases
+    /// the kernel's own tables and is never touched.
+    ///
+    /// # Safety
+    /// The address space must not be active on any CPU, and no TLB may still
+    /// cache its user translations under a PCID that can be loaded again.
+    ///
+    /// # Panics
+    /// If a kernel-half PML4 entry is user-accessible, i.e. the upper half is
+    /// not the kernel's.
+    #[allow(clippy::similar_names)]
+    pub unsafe fn destroy<A: PhysFrameAlloc>(
+        self,
+        alloc: &mut A,
+        mut release: impl FnMut(VirtualAddress, PhysicalPage<Size4K>) -> bool,
+    ) -> TeardownStats {
+        let pml4 = self.pml4_mut();
+        assert!(
+            (256..512).all(|i4| !pml4.get(L4Index::new(i4)).user()),
+            "kernel half of the address space is user-accessible"
+        );
+
+        let mut stats = TeardownStats::default();
+        for i4 in 0..256 {
+            let Some(pdpt_page) = pml4.get(L4Index::new(i4)).next_table() else {
+                continue;
+            };
+            let pdpt = self.pdpt_mut(pdpt_page);
+            for i3 in 0..512 {
+                let Some(PdptEntryKind::NextPageDirectory(pd_page, _)) =
+                    pdpt.get(L3Index::new(i3)).kind()
+                else {
+                    continue;
+                };
+                let pd = self.pd_mut(pd_page);
+                for i2 in 0..512 {
+                    let Some(PdEntryKind::NextPageTable(pt_page, _)) =
+                        pd.get(L2Index::new(i2)).kind()
+                    else {
+                        continue;
+                    };
+                    let pt = self.pt_mut(pt_page);
+                    for i1 in 0..512 {
+                        let Some((page, _)) = pt.get(L1Index::new(i1)).page_4k() else {
+                            continue;
+                        };
+                        if release(user_va(i4, i3, i2, i1), page) {
+                            alloc.free_4k(page);
+                            stats.pages += 1;
+                        }
+                    }
+                    alloc.free_4k(pt_page);
+                    stats.tables += 1;
+                }
+                alloc.free_4k(pd_page);
+                stats.tables += 1;
+            }
+            pml4.set(L4Index::new(i4), Pml4Entry::zero());
+            alloc.free_4k(pdpt_page);
+            stats.tables += 1;
+        }
+
+        alloc.free_4k(self.root);
+        stats.tables += 1;
+        stats
+    }
+
+    /// Share the user half of this address space with `child`, copy-on-write.
+    ///
+    /// Every present 4 KiB user leaf is mapped at the same address in `child`,
+    /// backed by the same frame, as `policy(va)` says (see
+    /// [`VmaSet::clone_policy`](crate::vma::VmaSet::clone_policy)):
+    ///
+    /// * [`ClonePolicy::CopyOnWrite`]: `share(frame)` is called to account for
+    ///   the second reference and, if the leaf is writable, it is made
+    ///   read-only and marked [copy-on-write](VirtualMemoryPageBits::copy_on_write)
+    ///   in **both** spaces.
+    /// * [`ClonePolicy::Shared`]: `share(frame)` is called; the leaf stays as it is.
+    /// * [`ClonePolicy::Borrowed`]: the leaf is mapped as it is.
+    ///
+    /// 2 MiB and 1 GiB leaves are always borrowed.
+    ///
+    /// Returns the number of pages for which `share` was called. The caller
+    /// must flush the TLB of this address space afterwards.
+    ///
+    /// # Errors
+    /// - Out of memory while building `child`'s tables. Pages shared up to
+    ///   that point stay shared; `child` should be [destroyed](Self::destroy).
+    #[allow(clippy::similar_names)]
+    pub fn cow_clone_into<A: PhysFrameAlloc>(
+        &self,
+        child: &Self,
+        alloc: &mut A,
+        nonleaf_flags: VirtualMemoryPageBits,
+        mut policy: impl FnMut(VirtualAddress) -> ClonePolicy,
+        mut share: impl FnMut(PhysicalPage<Size4K>),
+    ) -> Result<usize, AddressSpaceMapOneError> {
+        let pml4 = self.pml4_mut();
+        let mut shared = 0;
+        for i4 in 0..256 {
+            let Some(pdpt_page) = pml4.get(L4Index::new(i4)).next_table() else {
+                continue;
+            };
+            let pdpt = self.pdpt_mut(pdpt_page);
+            for i3 in 0..512 {
+                let pd_page = match pdpt.get(L3Index::new(i3)).kind() {
+                    Some(PdptEntryKind::NextPageDirectory(pd_page, _)) => pd_page,
+                    Some(PdptEntryKind::Leaf1GiB(base, entry)) => {
+                        let flags = VirtualMemoryPageBits::from_pdpte_1g(&entry);
+                        let va = user_va(i4, i3, 0, 0);
+                        child.map_one::<A, Size1G>(alloc, va, base.base(), nonleaf_flags, flags)?;
+                        continue;
+                    }
+                    None => continue,
+                };
+                let pd = self.pd_mut(pd_page);
+                for i2 in 0..512 {
+                    let pt_page = match pd.get(L2Index::new(i2)).kind() {
+                        Some(PdEntryKind::NextPageTable(pt_page, _)) => pt_page,
+                        Some(PdEntryKind::Leaf2MiB(base, entry)) => {
+                            let flags = VirtualMemoryPageBits::from_pde_2m(&entry);
+                            let va = user_va(i4, i3, i2, 0);
+                            child.map_one::<A, Size2M>(
+                                alloc,
+                                va,
+                                base.base(),
+                                nonleaf_flags,
+                                flags,
+                            )?;
+                            continue;
+                        }
+                        None => continue,
+                    };
+                    let pt = self.pt_mut(pt_page);
+                    for i1 in 0..512 {
+                        let idx = L1Index::new(i1);
+                        let Some((page, entry)) = pt.get(idx).page_4k() else {
+                            continue;
+                        };
+                        let va = user_va(i4, i3, i2, i1);
+                        let mut flags = VirtualMemoryPageBits::from_pte_4k(&entry);
+                        let policy = policy(va);
+                        if policy == ClonePolicy::CopyOnWrite && flags.writable {
+                            flags = flags.with_writable(false).with_copy_on_write(true);
+                            pt.set(idx, flags.to_pte_4k(page));
+                        }
+                        if policy != ClonePolicy::Borrowed {
+                            share(page);
+                            shared += 1;
+                        }
+                        child.map_one::<A, Size4K>(alloc, va, page.base(), nonleaf_flags, flags)?;
+                    }
+                }
+            }
+        }
+        Ok(shared)
+    }
+
+    /// The frame behind `va` if it is a [copy-on-write](VirtualMemoryPageBits::copy_on_write)
+    /// 4 KiB leaf.
+    #[must_use]
+    pub fn cow_page(&self, va: VirtualAddress) -> Option<PhysicalPage<Size4K>> {
+        match self.walk(va) {
+            WalkResult::L1 { pte, .. } => {
+                let (page, entry) = pte.page_4k()?;
+                VirtualMemoryPageBits::from_pte_4k(&entry)
+                    .copy_on_write()
+                    .then_some(page)
+            }
+            _ => None,
+        }
+    }
+
+    /// Replace the copy-on-write leaf at `va` with a writable mapping of `page`.
+    ///
+    /// `page` may be the old frame (if this address space is its last user) or
+    /// a private copy. All other leaf bits are kept. The caller must invalidate
+    /// the TLB entry for `va`.
+    ///
+    /// # Errors
+    /// - `va` is not a copy-on-write 4 KiB leaf.
+    // TODO: Refactor to error type
+    pub fn break_cow(
+        &self,
+        va: VirtualAddress,
+        page: PhysicalPage<Size4K>,
+    ) -> Result<(), &'static str> {
+        let WalkResult::L1 { pt, i1, pte } = self.walk(va) else {
+            return Err("missing: pte");
+        };
+        let Some((_, entry)) = pte.page_4k() else {
+            return Err("missing: pte");
+        };
+        let flags = VirtualMemoryPageBits::from_pte_4k(&entry);
+        if !flags.copy_on_write() {
+            return Err("not copy-on-write");
+        }
+        let flags = flags.with_copy_on_write(false).with_writable(true);
+        pt.set(i1, flags.to_pte_4k(page));
+        Ok(())
+    }
+
     /// Internal walker: resolves VA to the point it terminates.
     #[allow(clippy::similar_names)]
     fn walk(&self, va: VirtualAddress) -> WalkResult<'_> {
@@ -384,6 +984,26 @@ impl<'m, M: PhysMapper> AddressSpace<'m, M> {
         }
     }
 
+    /// The PDPT entry the walk to `va` passes, if the PML4 links a PDPT.
+    fn walk_to_pdpt(
+        &self,
+        va: VirtualAddress,
+    ) -> Option<(&mut PageDirectoryPointerTable, L3Index)> {
+        let (i4, i3, _, _) = crate::page_table::split_indices(va);
+        let pdpt_page = self.pml4_mut().get(i4).next_table()?;
+        Some((self.pdpt_mut(pdpt_page), i3))
+    }
+
+    /// The PD entry the walk to `va` passes, if the PDPT links a PD.
+    fn walk_to_pd(&self, va: VirtualAddress) -> Option<(&mut PageDirectory, L2Index)> {
+        let (pdpt, i3) = self.walk_to_pdpt(va)?;
+        let Some(PdptEntryKind::NextPageDirectory(pd_page, _)) = pdpt.get(i3).kind() else {
+            return None;
+        };
+        let (_, _, i2, _) = crate::page_table::split_indices(va);
+        Some((self.pd_mut(pd_page), i2))
+    }
+
     /// Borrow the [`PageMapLevel4`] (PML4) as a typed table.
     ///
     /// Convenience wrapper for [`PhysMapperExt::pml4_mut`] at the [`root_page`](Self::root_page).
@@ -452,6 +1072,28 @@ impl<'m, M: PhysMapper> AddressSpace<'m, M> {
         }
     }
 
+    /// Copy the kernel-half PML4 entries `src` has and `self` lacks, such as
+    /// a PDPT added to the kernel half after `self` was created. Returns the
+    /// number of entries copied.
+    pub fn sync_upper_half_from(&self, src: &Self) -> usize {
+        if self.root == src.root {
+            return 0;
+        }
+        let dst_l4 = self.pml4_mut();
+        let src_l4 = src.pml4_mut();
+
+        let mut copied = 0;
+        for i in (256..512).map(L4Index::new) {
+            let e = src_l4.get(i);
+            if e.present() && !dst_l4.get(i).present() {
+                debug_assert!(!e.user(), "kernel PML4E must have US=0");
+                dst_l4.set(i, e);
+                copied += 1;
+            }
+        }
+        copied
+    }
+
     /// Post-bringup clearing.
     pub fn clear_lower_half(&mut self) {
         let root = self.root_page();
@@ -466,6 +1108,191 @@ impl<'m, M: PhysMapper> AddressSpace<'m, M> {
     }
 }
 
+/// The lower-half virtual address selected by the given table indices.
+const fn user_va(i4: u16, i3: u16, i2: u16, i1: u16) -> VirtualAddress {
+    VirtualAddress::new(
+        (i4 as u64) << 39 | (i3 as u64) << 30 | (i2 as u64) << 21 | (i1 as u64) << 12,
+    )
+}
+
+/// The virtual address selected by the given table indices, sign-extended
+/// into the upper half for PML4 slots `256..512`.
+const fn canonical_va(i4: u16, i3: u16, i2: u16, i1: u16) -> VirtualAddress {
+    let va = user_va(i4, i3, i2, i1).as_u64();
+    VirtualAddress::new(if i4 >= 256 {
+        va | 0xFFFF_0000_0000_0000
+    } else {
+        va
+    })
+}
+
+/// `leaf` with the access bits the `link` above it grants: writable and user
+/// only if both allow it, no-execute if either forbids execution.
+const fn restrict_to_link(
+    leaf: VirtualMemoryPageBits,
+    link: VirtualMemoryPageBits,
+) -> VirtualMemoryPageBits {
+    leaf.with_writable(leaf.writable && link.writable)
+        .with_user(leaf.user && link.user)
+        .with_no_execute(leaf.no_execute || link.no_execute)
+}
+
+/// The flags of a leaf covering two leaves with flags `a` and `b`, if they
+/// differ in the accessed and dirty bits at most.
+fn merge_leaf_flags(
+    a: VirtualMemoryPageBits,
+    b: VirtualMemoryPageBits,
+) -> Option<VirtualMemoryPageBits> {
+    let same = a.with_accessed(false).with_dirty(false) == b.with_accessed(false).with_dirty(false);
+    same.then(|| {
+        a.with_accessed(a.accessed || b.accessed)
+            .with_dirty(a.dirty || b.dirty)
+    })
+}
+
+/// A present leaf, as seen by [`AddressSpace::for_each_leaf`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Leaf {
+    pub va: VirtualAddress,
+    pub pa: PhysicalAddress,
+    /// Level of the entry: 1 for a 4 KiB, 2 for a 2 MiB, 3 for a 1 GiB page.
+    pub level: u8,
+    /// The leaf's flags, with the access bits as the whole walk grants them:
+    /// writable and user only if every level allows it, no-execute if any
+    /// level forbids execution.
+    pub flags: VirtualMemoryPageBits,
+}
+
+impl Leaf {
+    fn new<const N: usize>(
+        va: VirtualAddress,
+        pa: PhysicalAddress,
+        level: u8,
+        links: [VirtualMemoryPageBits; N],
+        leaf: VirtualMemoryPageBits,
+    ) -> Self {
+        let flags = links
+            .iter()
+            .fold(leaf, |flags, link| restrict_to_link(flags, *link));
+        Self {
+            va,
+            pa,
+            level,
+            flags,
+        }
+    }
+
+    /// Size of the page in bytes.
+    #[must_use]
+    pub const fn size(&self) -> u64 {
+        match self.level {
+            3 => Size1G::SIZE,
+            2 => Size2M::SIZE,
+            _ => Size4K::SIZE,
+        }
+    }
+}
+
+/// An entry on the walk to an address, as seen by [`AddressSpace::trace`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Step {
+    /// Level of the table: 4 for the PML4 down to 1 for a page table.
+    pub level: u8,
+    /// Index of the entry in its table.
+    pub index: usize,
+    /// The table holding the entry.
+    pub table: PhysicalPage<Size4K>,
+    /// The next table or, for a leaf, the page the entry points to, with the
+    /// entry's own flags; `None` if the entry is not present.
+    pub target: Option<(PhysicalAddress, VirtualMemoryPageBits)>,
+    /// Whether the entry maps a page rather than linking a table.
+    pub leaf: bool,
+}
+
+impl Step {
+    const fn link(
+        level: u8,
+        index: usize,
+        table: PhysicalPage<Size4K>,
+        next: Option<PhysicalPage<Size4K>>,
+        flags: VirtualMemoryPageBits,
+    ) -> Self {
+        Self {
+            level,
+            index,
+            table,
+            target: match next {
+                Some(next) => Some((next.base(), flags)),
+                None => None,
+            },
+            leaf: false,
+        }
+    }
+
+    const fn leaf(
+        level: u8,
+        index: usize,
+        table: PhysicalPage<Size4K>,
+        page: PhysicalAddress,
+        flags: VirtualMemoryPageBits,
+    ) -> Self {
+        Self {
+            level,
+            index,
+            table,
+            target: Some((page, flags)),
+            leaf: true,
+        }
+    }
+
+    const fn missing(level: u8, index: usize, table: PhysicalPage<Size4K>) -> Self {
+        Self {
+            level,
+            index,
+            table,
+            target: None,
+            leaf: false,
+        }
+    }
+}
+
+/// How [`AddressSpace::cow_clone_into`] maps a page into the child.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ClonePolicy {
+    /// Not owned by the address space (e.g. device memory); mapped as is.
+    Borrowed,
+    /// Private to the address space; shared read-only until written to.
+    CopyOnWrite,
+    /// Shared memory; both spaces keep writing to the same frame.
+    Shared,
+}
+
+/// Frames released by [`AddressSpace::destroy`].
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct TeardownStats {
+    /// Leaf frames (user memory).
+    pub pages: usize,
+    /// Page-table frames, including the PML4.
+    pub tables: usize,
+}
+
+/// Leaves merged by [`AddressSpace::promote_large_pages`].
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct PromotionStats {
+    /// Page tables replaced by a 2 MiB leaf.
+    pub to_2m: usize,
+    /// Page directories replaced by a 1 GiB leaf.
+    pub to_1g: usize,
+}
+
+impl PromotionStats {
+    /// Table frames freed; one per merged table.
+    #[must_use]
+    pub const fn tables_freed(&self) -> usize {
+        self.to_2m + self.to_1g
+    }
+}
+
 /// A mapping error.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, thiserror::Error)]
 pub enum AddressSpaceMapOneError {
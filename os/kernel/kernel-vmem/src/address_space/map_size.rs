@@ -9,6 +9,7 @@
 //! - `ensure_chain_for`: Given a virtual address, ensure that the non-leaf
 //!   chain for that address down to the table that holds the leaf for the
 
+use crate::address_space::roots::kernel_pml4_filled;
 use crate::bits::VirtualMemoryPageBits;
 use crate::page_table::pd::{L2Index, PdEntry, PdEntryKind};
 use crate::page_table::pdpt::{L3Index, PdptEntry, PdptEntryKind};
@@ -119,6 +120,14 @@ pub enum MapSizeEnsureChainError {
     OomPt,
 }
 
+/// Count a PML4 slot `i4` that just gained a PDPT, if it is in the kernel
+/// half; see [`RootRegistry`](crate::address_space::RootRegistry).
+fn note_new_pml4_entry(i4: L4Index) {
+    if i4.as_usize() >= 256 {
+        kernel_pml4_filled();
+    }
+}
+
 impl MapSize for Size1G {
     fn ensure_chain_for<A: PhysFrameAlloc, M: PhysMapper>(
         aspace: &AddressSpace<M>,
@@ -138,6 +147,7 @@ impl MapSize for Size1G {
         let f = alloc.alloc_4k().ok_or(MapSizeEnsureChainError::OomPdpt)?;
         aspace.zero_pdpt(f);
         pml4.set(i4, Pml4Entry::present_with(nonleaf_flags, f));
+        note_new_pml4_entry(i4);
         Ok(f)
     }
 
@@ -176,6 +186,7 @@ impl MapSize for Size2M {
             let f = alloc.alloc_4k().ok_or(MapSizeEnsureChainError::OomPdpt)?;
             aspace.zero_pdpt(f);
             pml4.set(i4, Pml4Entry::present_with(nonleaf_flags, f));
+            note_new_pml4_entry(i4);
             f
         };
 
@@ -228,6 +239,7 @@ impl MapSize for Size4K {
             let f = alloc.alloc_4k().ok_or(MapSizeEnsureChainError::OomPdpt)?;
             aspace.zero_pdpt(f);
             pml4.set(i4, Pml4Entry::present_with(nonleaf_flags, f));
+            note_new_pml4_entry(i4);
             f
         };
 
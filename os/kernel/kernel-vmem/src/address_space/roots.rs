@@ -0,0 +1,159 @@
+//! # Registry of Live Address Spaces
+//!
+//! Every address space shares the kernel half by copying the kernel's PML4
+//! slots `256..512` when it is created (see [`AddressSpace::new`]). The PDPTs
+//! behind those slots are shared, so later mappings below an existing slot
+//! show up everywhere. A slot that was **empty** when an address space was
+//! created, and only gains a PDPT afterwards, does not: the new entry lands
+//! in the PML4 that was active at the time and nowhere else.
+//!
+//! Two pieces close that gap:
+//!
+//! - [`kernel_pml4_generation`] advances whenever a mapping call fills an
+//!   empty kernel-half PML4 slot, so callers can tell cheaply whether one did.
+//! - A [`RootRegistry`] lists the PML4 of every live address space, for the
+//!   caller to bring each one up to date with
+//!   [`AddressSpace::sync_upper_half_from`].
+//!
+//! The kernel never empties a kernel-half PML4 slot again, so copying the filled
+//! ones is all it takes.
+
+use crate::address_space::RootPage;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// Bumped every time an empty kernel-half PML4 slot is filled.
+static KERNEL_PML4_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// How many empty kernel-half PML4 slots have been filled so far.
+#[must_use]
+pub fn kernel_pml4_generation() -> u64 {
+    KERNEL_PML4_GENERATION.load(Ordering::Acquire)
+}
+
+/// Record that an empty kernel-half PML4 slot was filled.
+pub fn kernel_pml4_filled() {
+    KERNEL_PML4_GENERATION.fetch_add(1, Ordering::AcqRel);
+}
+
+/// The registry has no room for another address space.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("the address space registry is full")]
+pub struct RegistryFull;
+
+/// The roots of up to `N` live address spaces.
+///
+/// The registry does no locking of its own; the caller keeps it behind the
+/// same lock that serializes kernel-half mapping calls, so that no address
+/// space is created between a mapping and the sync that follows it.
+#[derive(Debug)]
+pub struct RootRegistry<const N: usize> {
+    roots: [Option<RootPage>; N],
+}
+
+impl<const N: usize> RootRegistry<N> {
+    /// An empty registry.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self { roots: [None; N] }
+    }
+
+    /// Add `root`; adding it twice does nothing.
+    ///
+    /// # Errors
+    /// [`RegistryFull`] if `N` roots are registered already.
+    pub fn register(&mut self, root: RootPage) -> Result<(), RegistryFull> {
+        if self.contains(root) {
+            return Ok(());
+        }
+        let slot = self
+            .roots
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(RegistryFull)?;
+        *slot = Some(root);
+        Ok(())
+    }
+
+    /// Remove `root`; returns whether it was registered.
+    pub fn unregister(&mut self, root: RootPage) -> bool {
+        self.roots
+            .iter_mut()
+            .find(|slot| **slot == Some(root))
+            .map(Option::take)
+            .is_some()
+    }
+
+    /// Whether `root` is registered.
+    #[must_use]
+    pub fn contains(&self, root: RootPage) -> bool {
+        self.roots.contains(&Some(root))
+    }
+
+    /// Number of registered roots.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.roots.iter().flatten().count()
+    }
+
+    /// Whether no root is registered.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The registered roots, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = RootPage> + '_ {
+        self.roots.iter().flatten().copied()
+    }
+}
+
+impl<const N: usize> Default for RootRegistry<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kernel_memory_addresses::{PhysicalAddress, PhysicalPage};
+
+    fn root(n: u64) -> RootPage {
+        PhysicalPage::from_addr(PhysicalAddress::new(n * 4096))
+    }
+
+    #[test]
+    fn registers_each_root_once() {
+        let mut registry = RootRegistry::<2>::new();
+        assert!(registry.is_empty());
+        registry.register(root(1)).unwrap();
+        registry.register(root(1)).unwrap();
+        assert_eq!(registry.len(), 1);
+        assert!(registry.contains(root(1)));
+    }
+
+    #[test]
+    fn refuses_roots_beyond_capacity() {
+        let mut registry = RootRegistry::<2>::new();
+        registry.register(root(1)).unwrap();
+        registry.register(root(2)).unwrap();
+        assert_eq!(registry.register(root(3)), Err(RegistryFull));
+    }
+
+    #[test]
+    fn unregistering_frees_the_slot() {
+        let mut registry = RootRegistry::<2>::new();
+        registry.register(root(1)).unwrap();
+        registry.register(root(2)).unwrap();
+        assert!(registry.unregister(root(1)));
+        assert!(!registry.unregister(root(1)));
+        registry.register(root(3)).unwrap();
+
+        let mut roots: [_; 2] = core::array::from_fn(|_| root(0));
+        for (slot, root) in roots.iter_mut().zip(registry.iter()) {
+            *slot = root;
+        }
+        roots.sort_by_key(|root| root.base().as_u64());
+        assert_eq!(roots, [root(2), root(3)]);
+    }
+}
@@ -0,0 +1,116 @@
+//! # Page Table Audit
+//!
+//! [`audit`] walks an address space and checks every leaf against the
+//! invariants the kernel's tables keep once boot is done:
+//!
+//! * nothing above [`LAST_USERSPACE_ADDRESS`] is user-accessible,
+//! * nothing in the kernel half is executable but the kernel's own code, so
+//!   the HHDM and all kernel data are no-execute,
+//! * nothing anywhere is both writable and executable,
+//! * only kernel-half pages are global.
+//!
+//! A leaf is judged by the access the whole walk grants (see [`Leaf::flags`]),
+//! so a user bit on a leaf below a supervisor-only link is not a violation.
+//! Each violation is reported as a [`Finding`] naming the leaf's address,
+//! level and flags.
+
+use crate::address_space::Leaf;
+use crate::{AddressSpace, PhysMapper};
+use core::fmt;
+use kernel_info::memory::LAST_USERSPACE_ADDRESS;
+use kernel_memory_addresses::VirtualAddress;
+
+/// An invariant a leaf breaks; see the [module docs](self).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Violation {
+    /// A kernel-half page is user-accessible.
+    UserKernelPage,
+    /// A kernel-half page outside the kernel's code is executable.
+    ExecutableData,
+    /// A page is writable and executable.
+    WritableExecutable,
+    /// A user-half page is global.
+    GlobalUserPage,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::UserKernelPage => "user-accessible kernel page",
+            Self::ExecutableData => "executable kernel data",
+            Self::WritableExecutable => "writable and executable page",
+            Self::GlobalUserPage => "global user page",
+        })
+    }
+}
+
+/// A leaf breaking an invariant.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub violation: Violation,
+    pub leaf: Leaf,
+}
+
+/// `<violation> at <va> -> <pa> (L<level>, rwx U G <cache mode>)`
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Leaf {
+            va,
+            pa,
+            level,
+            flags,
+        } = self.leaf;
+        write!(
+            f,
+            "{} at {va} -> {pa} (L{level}, r{}{} {} {} {:?})",
+            self.violation,
+            if flags.writable { 'w' } else { '-' },
+            if flags.no_execute { '-' } else { 'x' },
+            if flags.user { 'U' } else { 'S' },
+            if flags.global { 'G' } else { '-' },
+            flags.cache_mode()
+        )
+    }
+}
+
+/// The invariants `leaf` breaks; `is_kernel_code` tells whether a kernel-half
+/// address belongs to the kernel's code.
+pub fn check_leaf(
+    leaf: &Leaf,
+    is_kernel_code: impl Fn(VirtualAddress) -> bool,
+) -> impl Iterator<Item = Violation> {
+    let flags = leaf.flags;
+    let kernel = leaf.va > LAST_USERSPACE_ADDRESS;
+    let executable = !flags.no_execute;
+    [
+        (kernel && flags.user, Violation::UserKernelPage),
+        (
+            kernel && executable && !is_kernel_code(leaf.va),
+            Violation::ExecutableData,
+        ),
+        (flags.writable && executable, Violation::WritableExecutable),
+        (!kernel && flags.global, Violation::GlobalUserPage),
+    ]
+    .into_iter()
+    .filter_map(|(broken, violation)| broken.then_some(violation))
+}
+
+/// Check every leaf of `space` and pass each violation to `report`; returns
+/// the number of violations.
+pub fn audit<M: PhysMapper>(
+    space: &AddressSpace<M>,
+    is_kernel_code: impl Fn(VirtualAddress) -> bool,
+    mut report: impl FnMut(&Finding),
+) -> usize {
+    let mut violations = 0;
+    space.for_each_leaf(|leaf| {
+        for violation in check_leaf(leaf, &is_kernel_code) {
+            violations += 1;
+            report(&Finding {
+                violation,
+                leaf: *leaf,
+            });
+        }
+    });
+    violations
+}
@@ -5,6 +5,73 @@ use crate::page_table::pt::PtEntry4k;
 use kernel_memory_addresses::{PhysicalPage, Size1G, Size2M, Size4K};
 use utils_accessors_derive::Setters;
 
+/// Memory type of a mapping, selected through the Page Attribute Table.
+///
+/// A leaf's `PAT:PCD:PWT` bits form a 3-bit index into the `IA32_PAT` MSR.
+/// [`pat_index`](Self::pat_index) assumes the kernel's PAT layout:
+///
+/// | Index | `PAT` | `PCD` | `PWT` | Type |
+/// |:-----:|:-----:|:-----:|:-----:|:-----|
+/// | 0     | 0     | 0     | 0     | WB   |
+/// | 1     | 0     | 0     | 1     | WT   |
+/// | 2     | 0     | 1     | 0     | UC-  |
+/// | 3     | 0     | 1     | 1     | UC   |
+/// | 4     | 1     | 0     | 0     | WB   |
+/// | 5     | 1     | 0     | 1     | WT   |
+/// | 6     | 1     | 1     | 0     | **WC** |
+/// | 7     | 1     | 1     | 1     | UC   |
+///
+/// Entries 0–3 match the power-on defaults, so mappings made before the PAT
+/// is programmed (or on CPUs without PAT) keep their meaning. Only index 6
+/// differs (UC- by default), so WC degrades to uncached without a PAT.
+///
+/// The PAT bit sits at a different position per page size (bit 7 in a 4 KiB
+/// PTE, bit 12 in 2 MiB / 1 GiB leaves); the `to_*` conversions place it.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum CacheMode {
+    /// Write-back; normal memory.
+    WriteBack,
+    /// Write-through.
+    WriteThrough,
+    /// Uncacheable; device registers.
+    Uncached,
+    /// Write-combining; frame buffers.
+    WriteCombining,
+}
+
+impl CacheMode {
+    /// All cache modes.
+    pub const ALL: [Self; 4] = [
+        Self::WriteBack,
+        Self::WriteThrough,
+        Self::Uncached,
+        Self::WriteCombining,
+    ];
+
+    /// The PAT index (`PAT:PCD:PWT`) selecting this mode.
+    #[must_use]
+    pub const fn pat_index(self) -> u8 {
+        match self {
+            Self::WriteBack => 0,
+            Self::WriteThrough => 1,
+            Self::Uncached => 3,
+            Self::WriteCombining => 6,
+        }
+    }
+
+    /// The mode selected by PAT index `index` (0..=7).
+    #[must_use]
+    pub const fn from_pat_index(index: u8) -> Self {
+        match index & 0b111 {
+            0 | 4 => Self::WriteBack,
+            1 | 5 => Self::WriteThrough,
+            6 => Self::WriteCombining,
+            // UC- (2) only differs from UC through MTRR overrides.
+            _ => Self::Uncached,
+        }
+    }
+}
+
 /// Unified, ergonomic view over x86-64 paging entries (all levels / forms).
 ///
 /// This type deliberately does **not** use bit-packing. Instead, it models the
@@ -84,6 +151,9 @@ impl Default for VirtualMemoryPageBits {
 }
 
 impl VirtualMemoryPageBits {
+    /// [`os_available_low`](Self::os_available_low) bit marking copy-on-write leaves.
+    const OS_COPY_ON_WRITE: u8 = 0b001;
+
     #[must_use]
     pub const fn new() -> Self {
         Self {
@@ -187,13 +257,50 @@ impl VirtualMemoryPageBits {
         Self::user_table_wb_exec()
     }
 
+    /// Whether the leaf is **copy-on-write**: shared read-only after a fork
+    /// and to be copied on the first write (OS-available bit 9).
+    #[inline]
+    #[must_use]
+    pub const fn copy_on_write(&self) -> bool {
+        self.os_available_low & Self::OS_COPY_ON_WRITE != 0
+    }
+
+    /// Set or clear the [copy-on-write](Self::copy_on_write) marker.
+    #[inline]
+    #[must_use]
+    pub const fn with_copy_on_write(mut self, cow: bool) -> Self {
+        if cow {
+            self.os_available_low |= Self::OS_COPY_ON_WRITE;
+        } else {
+            self.os_available_low &= !Self::OS_COPY_ON_WRITE;
+        }
+        self
+    }
+
     /// Enable write-combining (WC) via PAT registers.
     #[inline]
     #[must_use]
     pub const fn with_write_combining(self) -> Self {
-        self.with_write_through(false)
-            .with_cache_disable(true)
-            .with_pat_bit2(true)
+        self.with_cache_mode(CacheMode::WriteCombining)
+    }
+
+    /// Select the memory type of a leaf mapping; see [`CacheMode`].
+    #[inline]
+    #[must_use]
+    pub const fn with_cache_mode(self, mode: CacheMode) -> Self {
+        let index = mode.pat_index();
+        self.with_write_through(index & 0b001 != 0)
+            .with_cache_disable(index & 0b010 != 0)
+            .with_pat_bit2(index & 0b100 != 0)
+    }
+
+    /// The memory type of a leaf mapping; see [`CacheMode`].
+    #[inline]
+    #[must_use]
+    pub const fn cache_mode(&self) -> CacheMode {
+        let index =
+            (self.pat_bit2 as u8) << 2 | (self.cache_disable as u8) << 1 | self.write_through as u8;
+        CacheMode::from_pat_index(index)
     }
 
     /// Populate from an L4 [`Pml4Entry`] (non-leaf).
@@ -482,3 +589,55 @@ impl From<PtEntry4k> for VirtualMemoryPageBits {
         Self::from_pte_4k(&e)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kernel_memory_addresses::PhysicalAddress;
+
+    #[test]
+    fn cache_mode_round_trips() {
+        for mode in CacheMode::ALL {
+            let bits = VirtualMemoryPageBits::new().with_cache_mode(mode);
+            assert_eq!(bits.cache_mode(), mode);
+        }
+    }
+
+    #[test]
+    fn copy_on_write_survives_pte_round_trip() {
+        let page = PhysicalPage::from_addr(PhysicalAddress::new(0x1000));
+        let cow = VirtualMemoryPageBits::user_leaf_data_wb()
+            .with_writable(false)
+            .with_copy_on_write(true);
+
+        let pte = cow.to_pte_4k(page);
+        assert_eq!(pte.into_bits() & (1 << 9), 1 << 9);
+        let back = VirtualMemoryPageBits::from_pte_4k(&pte);
+        assert!(back.copy_on_write());
+        assert!(!back.with_copy_on_write(false).copy_on_write());
+        assert!(!VirtualMemoryPageBits::user_leaf_data_wb().copy_on_write());
+    }
+
+    #[test]
+    fn pat_bit_position_depends_on_page_size() {
+        let wc = VirtualMemoryPageBits::new()
+            .with_present(true)
+            .with_cache_mode(CacheMode::WriteCombining);
+
+        let pte = wc.to_pte_4k(PhysicalPage::from_addr(PhysicalAddress::new(0x1000)));
+        assert_eq!(
+            pte.into_bits() & (1 << 7 | 1 << 4 | 1 << 3),
+            1 << 7 | 1 << 4
+        );
+
+        let pde = wc.to_pde_2m(PhysicalPage::from_addr(PhysicalAddress::new(0x20_0000)));
+        assert_eq!(
+            pde.into_bits() & (1 << 12 | 1 << 4 | 1 << 3),
+            1 << 12 | 1 << 4
+        );
+        assert_eq!(
+            VirtualMemoryPageBits::from_pde_2m(&pde).cache_mode(),
+            CacheMode::WriteCombining
+        );
+    }
+}
@@ -0,0 +1,55 @@
+//! Tests run inside the kernel (`kernel-test` feature), against the live
+//! page tables.
+
+use crate::page_table::pml4::L4Index;
+use crate::{AddressSpace, PhysMapper, invalidate_tlb_page, read_cr3_phys};
+use kernel_info::memory::HHDM_BASE;
+use kernel_memory_addresses::{PhysicalAddress, Size4K, VirtualAddress};
+use kernel_test::kernel_test;
+
+/// Views physical memory through the kernel's direct map.
+struct Hhdm;
+
+impl PhysMapper for Hhdm {
+    unsafe fn phys_to_mut<T>(&self, at: PhysicalAddress) -> &mut T {
+        unsafe { &mut *((HHDM_BASE.as_u64() + at.as_u64()) as *mut T) }
+    }
+}
+
+static MARKER: u64 = 0x5EED_5EED_5EED_5EED;
+
+#[kernel_test]
+fn cr3_root_is_page_aligned() {
+    let root = unsafe { read_cr3_phys() };
+    assert_ne!(root.as_u64(), 0);
+    assert!(root.as_u64().is_multiple_of(4096));
+}
+
+#[kernel_test]
+fn active_tables_translate_kernel_and_hhdm() {
+    let root = unsafe { read_cr3_phys() };
+    let space = unsafe { AddressSpace::from_current(&Hhdm) };
+
+    assert_eq!(space.query(HHDM_BASE + root.as_u64()), Some(root));
+    let marker = space
+        .query(VirtualAddress::from_ptr(&raw const MARKER))
+        .expect("kernel data not mapped");
+    let through_hhdm = unsafe { *Hhdm.phys_to_mut::<u64>(marker) };
+    assert_eq!(through_hhdm, MARKER);
+}
+
+#[kernel_test]
+fn invlpg_keeps_mapping_usable() {
+    let va = VirtualAddress::from_ptr(&raw const MARKER);
+    unsafe { invalidate_tlb_page(va.page::<Size4K>()) };
+    assert_eq!(
+        unsafe { core::ptr::read_volatile(&raw const MARKER) },
+        MARKER
+    );
+}
+
+#[cfg(debug_assertions)]
+#[kernel_test(should_panic)]
+fn l4_index_out_of_range_panics() {
+    let _ = L4Index::new(core::hint::black_box(512));
+}
@@ -7,8 +7,12 @@
 //! - Tiny [`PhysicalAddress`]/[`VirtualAddress`](kernel_memory_addresses::VirtualAddress) newtypes (u64) to avoid mixing address kinds.
 //! - A [`PageSize`](kernel_memory_addresses::PageSize) enum for 4 KiB / 2 MiB / 1 GiB mappings.
 //! - x86-64 page-table [`VirtualMemoryPageBits`] with practical explanations.
+//! - A [`CacheMode`] per mapping (WB/WT/UC/WC), encoded through the PAT.
 //! - A 4 KiB-aligned [`PageTable`] wrapper and index helpers.
 //! - A tiny allocator/mapper interface ([`PhysFrameAlloc`], [`PhysMapper`]).
+//! - A generation-based [`PCID`](pcid) allocator for flush-free address space switches.
+//! - A per-address-space record of [virtual memory areas](vma) (what is mapped where, and why).
+//! - An [`audit`] of a finished address space against the kernel's W^X and isolation rules.
 //!
 //! ## x86-64 Virtual Address → Physical Address Walk
 //!
@@ -71,11 +75,16 @@
 #![allow(unsafe_code, clippy::inline_always)]
 
 pub mod address_space;
+pub mod audit;
 mod bits;
+#[cfg(feature = "kernel-test")]
+mod ktests;
 pub mod page_table;
+pub mod pcid;
+pub mod vma;
 
 pub use crate::address_space::AddressSpace;
-pub use crate::bits::VirtualMemoryPageBits;
+pub use crate::bits::{CacheMode, VirtualMemoryPageBits};
 use crate::page_table::pd::PageDirectory;
 use crate::page_table::pdpt::PageDirectoryPointerTable;
 use crate::page_table::pml4::PageMapLevel4;
@@ -0,0 +1,301 @@
+//! # Process-Context Identifiers (PCID)
+//!
+//! With `CR4.PCIDE` set, every TLB entry is tagged with the 12-bit PCID that
+//! was in `CR3[11:0]` when it was cached. Switching address spaces then no
+//! longer has to throw away the whole TLB: loading `CR3` with bit 63
+//! ("no flush") set keeps the entries of all PCIDs, and a process that runs
+//! again finds its translations still cached.
+//!
+//! ## Allocation
+//!
+//! There are only 4095 usable PCIDs (0 is reserved, see [`Pcid::NONE`]), so
+//! they are recycled. [`PcidAllocator`] hands them out in order and tags every
+//! assignment with a **generation**. When the PCIDs of a generation run out,
+//! the generation is bumped, all PCIDs are flushed from the TLB at once and
+//! numbering starts over. An address space keeps its PCID for as long as its
+//! [`PcidTag`] matches the current generation; afterwards it simply gets a
+//! new one on its next activation.
+//!
+//! Because a PCID is never handed out twice within a generation, and the TLB
+//! is flushed between generations, an address space never sees entries
+//! cached for another one. Freeing a PCID when an address space dies is not
+//! necessary.
+//!
+//! ## Scope
+//!
+//! TLBs are per CPU, and so are PCIDs: each CPU needs its own allocator and
+//! each address space one tag per CPU it runs on.
+//!
+//! ## Invalidation
+//!
+//! `invlpg` only affects the current PCID (and global pages).
+//! [`invpcid`] invalidates entries of any PCID, if the CPU supports it.
+
+use crate::address_space::RootPage;
+
+/// CR3 bit 63: keep the TLB entries of the loaded PCID.
+const CR3_NOFLUSH: u64 = 1 << 63;
+
+/// A process-context identifier (`CR3[11:0]`).
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct Pcid(u16);
+
+impl Pcid {
+    /// PCID 0, used for CR3 loads outside of the allocator's control.
+    ///
+    /// It is never assigned to an address space and must always be loaded
+    /// with a flush.
+    pub const NONE: Self = Self(0);
+
+    /// The largest PCID.
+    pub const MAX: u16 = 0xFFF;
+
+    /// The PCID `raw`, if it fits into 12 bits.
+    #[must_use]
+    pub const fn new(raw: u16) -> Option<Self> {
+        if raw <= Self::MAX {
+            Some(Self(raw))
+        } else {
+            None
+        }
+    }
+
+    #[must_use]
+    pub const fn as_u16(self) -> u16 {
+        self.0
+    }
+}
+
+/// The PCID assignment of one address space (on one CPU).
+///
+/// The default tag has never been assigned and gets a PCID on first use.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct PcidTag {
+    /// Generation the PCID belongs to; 0 means unassigned.
+    generation: u64,
+    pcid: u16,
+}
+
+impl PcidTag {
+    /// An unassigned tag.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            generation: 0,
+            pcid: 0,
+        }
+    }
+}
+
+/// Result of [`PcidAllocator::assign`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PcidAssignment {
+    /// The address space still owns this PCID; its cached entries are valid.
+    Current(Pcid),
+    /// A PCID not used since the last flush was assigned.
+    New(Pcid),
+    /// The PCIDs ran out: a new generation started with this PCID, and the
+    /// caller **must flush all PCIDs** before using it.
+    Rollover(Pcid),
+}
+
+impl PcidAssignment {
+    /// The assigned PCID.
+    #[must_use]
+    pub const fn pcid(self) -> Pcid {
+        match self {
+            Self::Current(pcid) | Self::New(pcid) | Self::Rollover(pcid) => pcid,
+        }
+    }
+}
+
+/// Generation-based PCID allocator for one CPU; see the [module docs](self).
+#[derive(Debug)]
+pub struct PcidAllocator {
+    generation: u64,
+    /// Next PCID to hand out in the current generation.
+    next: u16,
+    /// Last PCID to hand out before rolling over.
+    last: u16,
+    rollovers: u64,
+}
+
+impl Default for PcidAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PcidAllocator {
+    /// An allocator using all PCIDs.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self::with_limit(Pcid::MAX)
+    }
+
+    /// An allocator that only hands out PCIDs `1..=last`.
+    ///
+    /// # Panics
+    /// If `last` is 0 or not a valid PCID.
+    #[must_use]
+    pub const fn with_limit(last: u16) -> Self {
+        assert!(last >= 1 && last <= Pcid::MAX, "invalid PCID limit");
+        Self {
+            generation: 1,
+            next: 1,
+            last,
+            rollovers: 0,
+        }
+    }
+
+    /// The current generation.
+    #[must_use]
+    pub const fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// How often the PCIDs ran out so far.
+    #[must_use]
+    pub const fn rollovers(&self) -> u64 {
+        self.rollovers
+    }
+
+    /// Return the PCID of the address space owning `tag`, assigning a new one
+    /// if its old one belongs to a previous generation.
+    pub const fn assign(&mut self, tag: &mut PcidTag) -> PcidAssignment {
+        if tag.generation == self.generation {
+            return PcidAssignment::Current(Pcid(tag.pcid));
+        }
+
+        let rollover = self.next > self.last;
+        if rollover {
+            self.generation += 1;
+            self.next = 1;
+            self.rollovers += 1;
+        }
+
+        let pcid = Pcid(self.next);
+        self.next += 1;
+        *tag = PcidTag {
+            generation: self.generation,
+            pcid: pcid.0,
+        };
+
+        if rollover {
+            PcidAssignment::Rollover(pcid)
+        } else {
+            PcidAssignment::New(pcid)
+        }
+    }
+}
+
+/// The CR3 value selecting `root` with `pcid`.
+///
+/// With `noflush`, the TLB entries cached for `pcid` are kept.
+#[must_use]
+pub const fn cr3_value(root: RootPage, pcid: Pcid, noflush: bool) -> u64 {
+    let flag = if noflush { CR3_NOFLUSH } else { 0 };
+    root.base().as_u64() | pcid.0 as u64 | flag
+}
+
+/// Load CR3 with `root` and `pcid`.
+///
+/// # Safety
+/// - `CR4.PCIDE` must be set (unless `pcid` is [`Pcid::NONE`] and `noflush` is false).
+/// - `root` must be a valid PML4 that maps the executing code and stack.
+/// - With `noflush`, the entries cached for `pcid` must belong to `root`.
+#[inline]
+pub unsafe fn load_cr3(root: RootPage, pcid: Pcid, noflush: bool) {
+    let cr3 = cr3_value(root, pcid, noflush);
+    unsafe {
+        core::arch::asm!("mov cr3, {}", in(reg) cr3, options(nostack, preserves_flags));
+    }
+}
+
+/// `INVPCID` invalidation types.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(u64)]
+pub enum InvpcidKind {
+    /// One address (non-global) of one PCID.
+    Address = 0,
+    /// All non-global entries of one PCID.
+    SingleContext = 1,
+    /// All entries of all PCIDs, including global ones.
+    AllIncludingGlobal = 2,
+    /// All non-global entries of all PCIDs.
+    AllContexts = 3,
+}
+
+/// Invalidate TLB entries with `INVPCID`.
+///
+/// `pcid` and `va` are ignored where `kind` does not use them.
+///
+/// # Safety
+/// Requires CPL0 and `INVPCID` support (CPUID.07H:EBX bit 10).
+#[inline]
+pub unsafe fn invpcid(kind: InvpcidKind, pcid: Pcid, va: u64) {
+    let descriptor: [u64; 2] = [u64::from(pcid.0), va];
+    unsafe {
+        core::arch::asm!(
+            "invpcid {kind}, [{desc}]",
+            kind = in(reg) kind as u64,
+            desc = in(reg) &raw const descriptor,
+            options(nostack, preserves_flags, readonly),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kernel_memory_addresses::{PhysicalAddress, PhysicalPage};
+
+    #[test]
+    fn tags_keep_their_pcid_within_a_generation() {
+        let mut alloc = PcidAllocator::new();
+        let (mut a, mut b) = (PcidTag::new(), PcidTag::new());
+
+        let pa = alloc.assign(&mut a);
+        let pb = alloc.assign(&mut b);
+        assert!(matches!(pa, PcidAssignment::New(_)));
+        assert_ne!(pa.pcid(), pb.pcid());
+        assert_ne!(pa.pcid(), Pcid::NONE);
+
+        assert_eq!(alloc.assign(&mut a), PcidAssignment::Current(pa.pcid()));
+        assert_eq!(alloc.assign(&mut b), PcidAssignment::Current(pb.pcid()));
+    }
+
+    #[test]
+    fn exhaustion_starts_a_new_generation() {
+        let mut alloc = PcidAllocator::with_limit(2);
+        let mut tags = [PcidTag::new(); 3];
+
+        assert!(matches!(alloc.assign(&mut tags[0]), PcidAssignment::New(_)));
+        assert!(matches!(alloc.assign(&mut tags[1]), PcidAssignment::New(_)));
+        let third = alloc.assign(&mut tags[2]);
+        assert!(matches!(third, PcidAssignment::Rollover(_)));
+        assert_eq!(third.pcid().as_u16(), 1);
+        assert_eq!(alloc.generation(), 2);
+        assert_eq!(alloc.rollovers(), 1);
+
+        // Stale tags get fresh PCIDs of the new generation.
+        assert_eq!(alloc.assign(&mut tags[0]), PcidAssignment::New(Pcid(2)));
+        assert!(matches!(
+            alloc.assign(&mut tags[1]),
+            PcidAssignment::Rollover(_)
+        ));
+        assert!(matches!(
+            alloc.assign(&mut tags[1]),
+            PcidAssignment::Current(_)
+        ));
+    }
+
+    #[test]
+    fn cr3_value_encodes_pcid_and_noflush() {
+        let root = PhysicalPage::from_addr(PhysicalAddress::new(0x0012_3000));
+        let pcid = Pcid::new(0x2A).unwrap();
+        assert_eq!(cr3_value(root, pcid, false), 0x0012_302A);
+        assert_eq!(cr3_value(root, pcid, true), 0x8000_0000_0012_302A);
+        assert!(Pcid::new(0x1000).is_none());
+    }
+}
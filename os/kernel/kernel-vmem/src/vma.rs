@@ -0,0 +1,730 @@
+//! # Virtual Memory Areas
+//!
+//! Page tables say what is mapped, but not *why*. A [`VmaSet`] keeps that
+//! record for a user address space: a sorted list of non-overlapping,
+//! page-aligned [`Vma`]s, each with a [`VmaKind`] (ELF image, stack, guard,
+//! anonymous or shared memory, file contents, device memory, thread-local
+//! storage, the clock page) and the [`VmaPerms`] its pages are mapped with.
+//!
+//! ## Maintenance
+//!
+//! Whoever maps, re-protects or unmaps user pages updates the set alongside
+//! the page tables: [`VmaSet::insert`] for new mappings,
+//! [`VmaSet::protect`] for permission changes and [`VmaSet::remove`] for
+//! unmaps. The latter two split areas that straddle the edges of the range;
+//! the upper part of a split [file](VmaKind::File) area starts that much
+//! further into the file.
+//!
+//! ## Queries
+//!
+//! * [`VmaSet::find`] returns the area containing an address, e.g. to classify
+//!   a page fault.
+//! * [`VmaSet::iter`] walks the areas in address order, e.g. for a
+//!   `/proc/<pid>/maps` style listing (see the [`Display`](core::fmt::Display)
+//!   impl of [`Vma`]).
+//! * [`VmaSet::find_gap`] picks the lowest free range of a given size within
+//!   bounds, for placing new mappings; [`VmaSet::find_gap_from`] prefers
+//!   ranges at or above a (randomized) start address.
+//! * [`VmaSet::owns_frame`] and [`VmaSet::clone_policy`] say how the frame at
+//!   an address is accounted for on teardown and fork.
+//!
+//! ## Capacity
+//!
+//! The set has a fixed capacity `N` and never allocates. Operations that
+//! would exceed it fail with [`VmaError::Full`] *before* modifying the set.
+
+use crate::address_space::ClonePolicy;
+use core::fmt;
+use kernel_memory_addresses::{PageSize, Size4K, VirtualAddress};
+
+/// What a [`Vma`] is used for.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum VmaKind {
+    /// A loadable segment of the program image.
+    Image,
+    /// The user stack.
+    Stack,
+    /// Reserved, unmapped pages that catch overruns (e.g. below the stack).
+    Guard,
+    /// Anonymous memory mapped on request.
+    Anonymous,
+    /// Memory of a shared memory object, mapped by several address spaces.
+    Shared,
+    /// Pages of the file `inode`, private to the address space; the area
+    /// starts at byte `offset` of the file (page aligned).
+    File { inode: u64, offset: u64 },
+    /// Memory of a device, such as a framebuffer's back buffer, which the
+    /// device keeps.
+    Device,
+    /// The thread-local storage block and thread control block.
+    Tls,
+    /// The read-only clock page the kernel shares with every process.
+    ClockPage,
+}
+
+impl VmaKind {
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Image => "image",
+            Self::Stack => "stack",
+            Self::Guard => "guard",
+            Self::Anonymous => "anon",
+            Self::Shared => "shm",
+            Self::File { .. } => "file",
+            Self::Device => "dev",
+            Self::Tls => "tls",
+            Self::ClockPage => "clock",
+        }
+    }
+
+    /// Whether pages mapped in such an area hold a reference on their frames,
+    /// which is dropped with the address space. Frames of private areas only
+    /// have one; [`Shared`](Self::Shared) frames and those of
+    /// [`File`](Self::File) areas, which the page cache may hold as well,
+    /// are freed with their last. [`Device`](Self::Device) memory stays
+    /// with the device.
+    #[must_use]
+    pub const fn owns_frames(self) -> bool {
+        match self {
+            Self::Image
+            | Self::Stack
+            | Self::Anonymous
+            | Self::Shared
+            | Self::File { .. }
+            | Self::Tls
+            | Self::ClockPage => true,
+            Self::Guard | Self::Device => false,
+        }
+    }
+
+    /// How a fork treats pages mapped in such an area.
+    #[must_use]
+    pub const fn clone_policy(self) -> ClonePolicy {
+        match self {
+            Self::Image | Self::Stack | Self::Anonymous | Self::File { .. } | Self::Tls => {
+                ClonePolicy::CopyOnWrite
+            }
+            Self::Shared | Self::ClockPage => ClonePolicy::Shared,
+            Self::Guard | Self::Device => ClonePolicy::Borrowed,
+        }
+    }
+
+    /// The kind of the part of an area of this kind that starts `by` bytes
+    /// into it.
+    const fn advanced(self, by: u64) -> Self {
+        match self {
+            Self::File { inode, offset } => Self::File {
+                inode,
+                offset: offset + by,
+            },
+            kind => kind,
+        }
+    }
+}
+
+/// Access permissions of a [`Vma`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct VmaPerms {
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+}
+
+impl VmaPerms {
+    /// No access (guard areas).
+    pub const NONE: Self = Self::new(false, false, false);
+    /// Read-only data.
+    pub const RO: Self = Self::new(true, false, false);
+    /// Read-write data.
+    pub const RW: Self = Self::new(true, true, false);
+    /// Code.
+    pub const RX: Self = Self::new(true, false, true);
+
+    #[must_use]
+    pub const fn new(read: bool, write: bool, execute: bool) -> Self {
+        Self {
+            read,
+            write,
+            execute,
+        }
+    }
+}
+
+impl fmt::Display for VmaPerms {
+    /// `rwx`-style, with `-` for missing permissions.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let flag = |set: bool, c: char| if set { c } else { '-' };
+        write!(
+            f,
+            "{}{}{}",
+            flag(self.read, 'r'),
+            flag(self.write, 'w'),
+            flag(self.execute, 'x')
+        )
+    }
+}
+
+/// One virtual memory area `[start, end)`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Vma {
+    /// First byte; page aligned.
+    pub start: VirtualAddress,
+    /// One past the last byte; page aligned.
+    pub end: VirtualAddress,
+    pub kind: VmaKind,
+    pub perms: VmaPerms,
+}
+
+impl Vma {
+    const EMPTY: Self = Self {
+        start: VirtualAddress::zero(),
+        end: VirtualAddress::zero(),
+        kind: VmaKind::Anonymous,
+        perms: VmaPerms::NONE,
+    };
+
+    #[must_use]
+    pub const fn new(
+        start: VirtualAddress,
+        end: VirtualAddress,
+        kind: VmaKind,
+        perms: VmaPerms,
+    ) -> Self {
+        Self {
+            start,
+            end,
+            kind,
+            perms,
+        }
+    }
+
+    /// Size in bytes.
+    #[must_use]
+    pub const fn len(&self) -> u64 {
+        self.end.as_u64() - self.start.as_u64()
+    }
+
+    /// Whether the area is empty.
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether `addr` lies in the area.
+    #[must_use]
+    pub const fn contains(&self, addr: VirtualAddress) -> bool {
+        self.start.as_u64() <= addr.as_u64() && addr.as_u64() < self.end.as_u64()
+    }
+
+    /// For a [file](VmaKind::File) area containing `addr`, the file's inode
+    /// and the offset into it that `addr` maps.
+    #[must_use]
+    pub const fn file_offset(&self, addr: VirtualAddress) -> Option<(u64, u64)> {
+        match self.kind {
+            VmaKind::File { inode, offset } if self.contains(addr) => {
+                Some((inode, offset + (addr.as_u64() - self.start.as_u64())))
+            }
+            _ => None,
+        }
+    }
+
+    const fn overlaps(&self, start: u64, end: u64) -> bool {
+        self.start.as_u64() < end && start < self.end.as_u64()
+    }
+}
+
+impl fmt::Display for Vma {
+    /// `start-end perms kind`, like a line of `/proc/<pid>/maps`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:012x}-{:012x} {} {}",
+            self.start.as_u64(),
+            self.end.as_u64(),
+            self.perms,
+            self.kind.name()
+        )
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, thiserror::Error)]
+pub enum VmaError {
+    #[error("range {start:#x}..{end:#x} is empty or not page aligned")]
+    InvalidRange { start: u64, end: u64 },
+    #[error("range overlaps the existing area {existing}")]
+    Overlap { existing: Vma },
+    #[error("no room for more areas")]
+    Full,
+}
+
+/// The areas of one address space, sorted by address; see the
+/// [module docs](self).
+#[derive(Clone)]
+pub struct VmaSet<const N: usize> {
+    vmas: [Vma; N],
+    len: usize,
+}
+
+impl<const N: usize> Default for VmaSet<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> VmaSet<N> {
+    /// An empty set.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            vmas: [Vma::EMPTY; N],
+            len: 0,
+        }
+    }
+
+    /// Number of areas.
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The areas in address order.
+    pub fn iter(&self) -> impl Iterator<Item = &Vma> {
+        self.vmas[..self.len].iter()
+    }
+
+    /// The area containing `addr`, if any.
+    #[must_use]
+    pub fn find(&self, addr: VirtualAddress) -> Option<&Vma> {
+        let i = self.index_after(addr.as_u64()).checked_sub(1)?;
+        Some(&self.vmas[i]).filter(|vma| vma.contains(addr))
+    }
+
+    /// Whether the frame mapped at `addr` holds a reference of this address
+    /// space; see [`VmaKind::owns_frames`].
+    #[must_use]
+    pub fn owns_frame(&self, addr: VirtualAddress) -> bool {
+        self.find(addr).is_some_and(|vma| vma.kind.owns_frames())
+    }
+
+    /// How a fork treats the page at `addr`; see [`VmaKind::clone_policy`].
+    /// Pages outside any area are [borrowed](ClonePolicy::Borrowed).
+    #[must_use]
+    pub fn clone_policy(&self, addr: VirtualAddress) -> ClonePolicy {
+        self.find(addr)
+            .map_or(ClonePolicy::Borrowed, |vma| vma.kind.clone_policy())
+    }
+
+    /// Record a new area.
+    ///
+    /// # Errors
+    /// - [`VmaError::InvalidRange`] if the area is empty or not page aligned.
+    /// - [`VmaError::Overlap`] if it overlaps an existing area.
+    /// - [`VmaError::Full`] if the set is at capacity.
+    pub fn insert(&mut self, vma: Vma) -> Result<(), VmaError> {
+        let (start, end) = check_range(vma.start, vma.end)?;
+        if let Some(existing) = self.iter().find(|v| v.overlaps(start, end)) {
+            return Err(VmaError::Overlap {
+                existing: *existing,
+            });
+        }
+        if self.len == N {
+            return Err(VmaError::Full);
+        }
+
+        let at = self.index_after(start);
+        self.vmas.copy_within(at..self.len, at + 1);
+        self.vmas[at] = vma;
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Change the permissions of `[start, end)` wherever it is covered by an
+    /// area, splitting areas at the range's edges.
+    ///
+    /// # Errors
+    /// - [`VmaError::InvalidRange`] if the range is empty or not page aligned.
+    /// - [`VmaError::Full`] if splitting needs more room than is left.
+    pub fn protect(
+        &mut self,
+        start: VirtualAddress,
+        end: VirtualAddress,
+        perms: VmaPerms,
+    ) -> Result<(), VmaError> {
+        let (start, end) = check_range(start, end)?;
+        self.split_edges(start, end)?;
+        for vma in &mut self.vmas[..self.len] {
+            if vma.overlaps(start, end) {
+                vma.perms = perms;
+            }
+        }
+        Ok(())
+    }
+
+    /// Forget `[start, end)`, trimming or splitting areas at its edges.
+    ///
+    /// # Errors
+    /// - [`VmaError::InvalidRange`] if the range is empty or not page aligned.
+    /// - [`VmaError::Full`] if splitting needs more room than is left.
+    pub fn remove(&mut self, start: VirtualAddress, end: VirtualAddress) -> Result<(), VmaError> {
+        let (start, end) = check_range(start, end)?;
+        self.split_edges(start, end)?;
+
+        let mut kept = 0;
+        for i in 0..self.len {
+            if !self.vmas[i].overlaps(start, end) {
+                self.vmas[kept] = self.vmas[i];
+                kept += 1;
+            }
+        }
+        self.len = kept;
+        Ok(())
+    }
+
+    /// Forget all areas.
+    pub const fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// The lowest page-aligned address `a` in `[lo, hi)` such that
+    /// `[a, a + len)` fits below `hi` without touching any area.
+    #[must_use]
+    pub fn find_gap(
+        &self,
+        len: u64,
+        lo: VirtualAddress,
+        hi: VirtualAddress,
+    ) -> Option<VirtualAddress> {
+        if len == 0 {
+            return None;
+        }
+        let len = len.checked_next_multiple_of(Size4K::SIZE)?;
+        let mut candidate = lo.as_u64().checked_next_multiple_of(Size4K::SIZE)?;
+        for vma in self.iter() {
+            if vma.end.as_u64() <= candidate {
+                continue;
+            }
+            if candidate.checked_add(len)? <= vma.start.as_u64() {
+                break;
+            }
+            candidate = vma.end.as_u64();
+        }
+        (candidate.checked_add(len)? <= hi.as_u64()).then(|| VirtualAddress::new(candidate))
+    }
+
+    /// Like [`find_gap`](Self::find_gap), but prefer ranges at or above
+    /// `start`: the lowest fitting range in `[start, hi)`, or else the lowest
+    /// in `[lo, hi)`.
+    #[must_use]
+    pub fn find_gap_from(
+        &self,
+        len: u64,
+        start: VirtualAddress,
+        lo: VirtualAddress,
+        hi: VirtualAddress,
+    ) -> Option<VirtualAddress> {
+        let start = start.max(lo);
+        self.find_gap(len, start, hi)
+            .or_else(|| self.find_gap(len, lo, hi))
+    }
+
+    /// Index of the first area starting after `addr`.
+    fn index_after(&self, addr: u64) -> usize {
+        self.vmas[..self.len].partition_point(|v| v.start.as_u64() <= addr)
+    }
+
+    /// Split the areas straddling `start` and `end` so that both are area
+    /// boundaries. Checks capacity first, so nothing changes on error.
+    fn split_edges(&mut self, start: u64, end: u64) -> Result<(), VmaError> {
+        let straddles = |at: u64| {
+            self.iter()
+                .any(|v| v.start.as_u64() < at && at < v.end.as_u64())
+        };
+        let needed = usize::from(straddles(start)) + usize::from(straddles(end));
+        if self.len + needed > N {
+            return Err(VmaError::Full);
+        }
+        self.split_at(start);
+        self.split_at(end);
+        Ok(())
+    }
+
+    /// Split the area strictly containing `at`, if any, into two.
+    fn split_at(&mut self, at: u64) {
+        let Some(i) = self.index_after(at).checked_sub(1) else {
+            return;
+        };
+        let vma = self.vmas[i];
+        if !(vma.start.as_u64() < at && at < vma.end.as_u64()) {
+            return;
+        }
+
+        self.vmas.copy_within(i + 1..self.len, i + 2);
+        self.vmas[i].end = VirtualAddress::new(at);
+        self.vmas[i + 1] = Vma {
+            start: VirtualAddress::new(at),
+            kind: vma.kind.advanced(at - vma.start.as_u64()),
+            ..vma
+        };
+        self.len += 1;
+    }
+}
+
+/// Validate a page-aligned, non-empty range.
+const fn check_range(start: VirtualAddress, end: VirtualAddress) -> Result<(u64, u64), VmaError> {
+    let (start, end) = (start.as_u64(), end.as_u64());
+    if start >= end || !start.is_multiple_of(Size4K::SIZE) || !end.is_multiple_of(Size4K::SIZE) {
+        return Err(VmaError::InvalidRange { start, end });
+    }
+    Ok((start, end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const fn va(addr: u64) -> VirtualAddress {
+        VirtualAddress::new(addr)
+    }
+
+    fn vma(start: u64, end: u64, kind: VmaKind, perms: VmaPerms) -> Vma {
+        Vma::new(va(start), va(end), kind, perms)
+    }
+
+    fn ranges<const N: usize>(set: &VmaSet<N>) -> Vec<(u64, u64, VmaPerms)> {
+        set.iter()
+            .map(|v| (v.start.as_u64(), v.end.as_u64(), v.perms))
+            .collect()
+    }
+
+    #[test]
+    fn insert_keeps_areas_sorted_and_disjoint() {
+        let mut set = VmaSet::<4>::new();
+        set.insert(vma(0x5000, 0x6000, VmaKind::Stack, VmaPerms::RW))
+            .unwrap();
+        set.insert(vma(0x1000, 0x3000, VmaKind::Image, VmaPerms::RX))
+            .unwrap();
+        set.insert(vma(0x3000, 0x4000, VmaKind::Image, VmaPerms::RW))
+            .unwrap();
+
+        assert_eq!(
+            ranges(&set),
+            [
+                (0x1000, 0x3000, VmaPerms::RX),
+                (0x3000, 0x4000, VmaPerms::RW),
+                (0x5000, 0x6000, VmaPerms::RW),
+            ]
+        );
+
+        let err = set
+            .insert(vma(0x2000, 0x5000, VmaKind::Anonymous, VmaPerms::RW))
+            .unwrap_err();
+        assert!(matches!(err, VmaError::Overlap { existing } if existing.start == va(0x1000)));
+        assert!(matches!(
+            set.insert(vma(0x7000, 0x7800, VmaKind::Anonymous, VmaPerms::RW)),
+            Err(VmaError::InvalidRange { .. })
+        ));
+
+        set.insert(vma(0x8000, 0x9000, VmaKind::Anonymous, VmaPerms::RW))
+            .unwrap();
+        assert_eq!(
+            set.insert(vma(0x9000, 0xA000, VmaKind::Anonymous, VmaPerms::RW)),
+            Err(VmaError::Full)
+        );
+    }
+
+    #[test]
+    fn find_returns_the_containing_area() {
+        let mut set = VmaSet::<4>::new();
+        set.insert(vma(0x1000, 0x3000, VmaKind::Image, VmaPerms::RX))
+            .unwrap();
+        set.insert(vma(0x4000, 0x5000, VmaKind::Stack, VmaPerms::RW))
+            .unwrap();
+
+        assert!(set.find(va(0x0fff)).is_none());
+        assert_eq!(set.find(va(0x1000)).unwrap().kind, VmaKind::Image);
+        assert_eq!(set.find(va(0x2fff)).unwrap().kind, VmaKind::Image);
+        assert!(set.find(va(0x3000)).is_none());
+        assert_eq!(set.find(va(0x4abc)).unwrap().kind, VmaKind::Stack);
+        assert!(set.find(va(0x5000)).is_none());
+    }
+
+    #[test]
+    fn guard_areas_own_no_frames() {
+        let mut set = VmaSet::<4>::new();
+        set.insert(vma(0x1000, 0x2000, VmaKind::Guard, VmaPerms::NONE))
+            .unwrap();
+        set.insert(vma(0x2000, 0x4000, VmaKind::Stack, VmaPerms::RW))
+            .unwrap();
+
+        assert!(!set.owns_frame(va(0x1000)));
+        assert!(set.owns_frame(va(0x3000)));
+        assert!(!set.owns_frame(va(0x4000)));
+    }
+
+    #[test]
+    fn device_areas_are_borrowed() {
+        let mut set = VmaSet::<4>::new();
+        set.insert(vma(0x1000, 0x3000, VmaKind::Device, VmaPerms::RW))
+            .unwrap();
+
+        assert!(!set.owns_frame(va(0x1000)));
+        assert_eq!(set.clone_policy(va(0x2000)), ClonePolicy::Borrowed);
+    }
+
+    #[test]
+    fn shared_areas_are_shared_on_fork() {
+        let mut set = VmaSet::<4>::new();
+        set.insert(vma(0x1000, 0x2000, VmaKind::Shared, VmaPerms::RW))
+            .unwrap();
+        set.insert(vma(0x2000, 0x3000, VmaKind::Anonymous, VmaPerms::RW))
+            .unwrap();
+
+        assert!(set.owns_frame(va(0x1000)));
+        assert_eq!(set.clone_policy(va(0x1000)), ClonePolicy::Shared);
+        assert_eq!(set.clone_policy(va(0x2000)), ClonePolicy::CopyOnWrite);
+        assert_eq!(set.clone_policy(va(0x3000)), ClonePolicy::Borrowed);
+    }
+
+    #[test]
+    fn split_file_areas_keep_their_offsets() {
+        let file = VmaKind::File {
+            inode: 7,
+            offset: 0x2000,
+        };
+        let mut set = VmaSet::<4>::new();
+        set.insert(vma(0x1000, 0x5000, file, VmaPerms::RX)).unwrap();
+        set.protect(va(0x2000), va(0x3000), VmaPerms::RO).unwrap();
+
+        assert_eq!(set.clone_policy(va(0x1000)), ClonePolicy::CopyOnWrite);
+        for addr in [0x1000, 0x2000, 0x3800, 0x4FFF] {
+            let vma = set.find(va(addr)).unwrap();
+            assert_eq!(vma.file_offset(va(addr)), Some((7, addr + 0x1000)));
+        }
+        let anon = vma(0x1000, 0x2000, VmaKind::Anonymous, VmaPerms::RW);
+        assert_eq!(anon.file_offset(va(0x1000)), None);
+    }
+
+    #[test]
+    fn protect_splits_at_the_edges() {
+        let mut set = VmaSet::<4>::new();
+        set.insert(vma(0x1000, 0x5000, VmaKind::Image, VmaPerms::RW))
+            .unwrap();
+        set.protect(va(0x2000), va(0x3000), VmaPerms::RX).unwrap();
+
+        assert_eq!(
+            ranges(&set),
+            [
+                (0x1000, 0x2000, VmaPerms::RW),
+                (0x2000, 0x3000, VmaPerms::RX),
+                (0x3000, 0x5000, VmaPerms::RW),
+            ]
+        );
+        assert!(set.iter().all(|v| v.kind == VmaKind::Image));
+    }
+
+    #[test]
+    fn remove_trims_and_punches_holes() {
+        let mut set = VmaSet::<4>::new();
+        set.insert(vma(0x1000, 0x4000, VmaKind::Anonymous, VmaPerms::RW))
+            .unwrap();
+        set.insert(vma(0x5000, 0x7000, VmaKind::Anonymous, VmaPerms::RO))
+            .unwrap();
+
+        set.remove(va(0x2000), va(0x3000)).unwrap();
+        set.remove(va(0x6000), va(0x8000)).unwrap();
+        assert_eq!(
+            ranges(&set),
+            [
+                (0x1000, 0x2000, VmaPerms::RW),
+                (0x3000, 0x4000, VmaPerms::RW),
+                (0x5000, 0x6000, VmaPerms::RO),
+            ]
+        );
+
+        set.remove(va(0x0000), va(0x10000)).unwrap();
+        assert!(set.is_empty());
+    }
+
+    #[test]
+    fn splitting_beyond_capacity_changes_nothing() {
+        let mut set = VmaSet::<1>::new();
+        set.insert(vma(0x1000, 0x4000, VmaKind::Anonymous, VmaPerms::RW))
+            .unwrap();
+        assert_eq!(
+            set.protect(va(0x2000), va(0x3000), VmaPerms::RO),
+            Err(VmaError::Full)
+        );
+        assert_eq!(ranges(&set), [(0x1000, 0x4000, VmaPerms::RW)]);
+
+        // Covering the whole area needs no split.
+        set.protect(va(0x1000), va(0x4000), VmaPerms::RO).unwrap();
+        assert_eq!(ranges(&set), [(0x1000, 0x4000, VmaPerms::RO)]);
+    }
+
+    #[test]
+    fn find_gap_picks_the_lowest_fitting_range() {
+        let mut set = VmaSet::<4>::new();
+        set.insert(vma(0x2000, 0x3000, VmaKind::Image, VmaPerms::RX))
+            .unwrap();
+        set.insert(vma(0x4000, 0x6000, VmaKind::Anonymous, VmaPerms::RW))
+            .unwrap();
+
+        assert_eq!(
+            set.find_gap(0x1000, va(0x1000), va(0x10000)),
+            Some(va(0x1000))
+        );
+        assert_eq!(
+            set.find_gap(0x1800, va(0x1000), va(0x10000)),
+            Some(va(0x6000))
+        );
+        assert_eq!(
+            set.find_gap(0x1000, va(0x2000), va(0x10000)),
+            Some(va(0x3000))
+        );
+        assert_eq!(
+            set.find_gap(0x1000, va(0x5000), va(0x7000)),
+            Some(va(0x6000))
+        );
+        assert_eq!(set.find_gap(0x2000, va(0x5000), va(0x7000)), None);
+        assert_eq!(set.find_gap(0, va(0x1000), va(0x10000)), None);
+    }
+
+    #[test]
+    fn find_gap_from_wraps_around_to_lo() {
+        let mut set = VmaSet::<4>::new();
+        set.insert(vma(0x2000, 0x3000, VmaKind::Image, VmaPerms::RX))
+            .unwrap();
+        set.insert(vma(0x4000, 0x6000, VmaKind::Anonymous, VmaPerms::RW))
+            .unwrap();
+
+        assert_eq!(
+            set.find_gap_from(0x1000, va(0x4000), va(0x1000), va(0x10000)),
+            Some(va(0x6000))
+        );
+        assert_eq!(
+            set.find_gap_from(0x1000, va(0x4000), va(0x1000), va(0x6000)),
+            Some(va(0x1000))
+        );
+        assert_eq!(
+            set.find_gap_from(0x1000, va(0x0000), va(0x3000), va(0x6000)),
+            Some(va(0x3000))
+        );
+        assert_eq!(
+            set.find_gap_from(0x2000, va(0x4000), va(0x1000), va(0x6000)),
+            None
+        );
+    }
+
+    #[test]
+    fn display_matches_maps_format() {
+        let v = vma(0x40_0000, 0x40_2000, VmaKind::Image, VmaPerms::RX);
+        assert_eq!(v.to_string(), "000000400000-000000402000 r-x image");
+    }
+}
@@ -0,0 +1,499 @@
+//! Property-style tests of [`AddressSpace`] against mock physical memory.
+//!
+//! Each case maps a random set of non-overlapping 4 KiB, 2 MiB and 1 GiB
+//! pages and checks the tree against a plain list of what it should contain.
+
+use kernel_memory_addresses::{
+    PageSize, PhysicalAddress, PhysicalPage, Size1G, Size2M, Size4K, VirtualAddress,
+};
+use kernel_vmem::{AddressSpace, CacheMode, PhysFrameAlloc, VirtualMemoryPageBits};
+use kernel_vmem_testutil::{MockFrameAlloc, MockMemory, Rng, run_cases, walk_tables};
+use std::collections::BTreeSet;
+use std::panic::AssertUnwindSafe;
+
+const CASES: u64 = 64;
+
+/// Frames in the pool; enough for the worst case of [`MAPPINGS`] mappings
+/// that share no tables.
+const FRAMES: usize = 1 + 3 * MAPPINGS;
+
+const MAPPINGS: usize = 48;
+
+/// PML4 slots mappings are placed in; few, so that mappings share tables.
+const L4_SLOTS: [u64; 4] = [0, 1, 128, 255];
+
+/// Window at the start of each GiB that 2 MiB and 4 KiB pages are placed in.
+const SMALL_WINDOW: u64 = 16 * Size2M::SIZE;
+
+#[derive(Debug, Copy, Clone)]
+struct Mapping {
+    va: u64,
+    pa: u64,
+    size: u64,
+    flags: VirtualMemoryPageBits,
+}
+
+impl Mapping {
+    const fn end(&self) -> u64 {
+        self.va + self.size
+    }
+
+    const fn overlaps(&self, other: &Self) -> bool {
+        self.va < other.end() && other.va < self.end()
+    }
+
+    /// A random address inside the mapping and where it should translate to.
+    fn probe(&self, rng: &mut Rng) -> (VirtualAddress, PhysicalAddress) {
+        let off = rng.below(self.size);
+        (
+            VirtualAddress::new(self.va + off),
+            PhysicalAddress::new(self.pa + off),
+        )
+    }
+}
+
+/// Link flags; permissive, so that only the leaves decide.
+const fn nonleaf_flags() -> VirtualMemoryPageBits {
+    VirtualMemoryPageBits::new()
+        .with_present(true)
+        .with_writable(true)
+        .with_user(true)
+}
+
+fn random_flags(rng: &mut Rng) -> VirtualMemoryPageBits {
+    let mode = *rng.pick(&[
+        CacheMode::WriteBack,
+        CacheMode::WriteThrough,
+        CacheMode::Uncached,
+        CacheMode::WriteCombining,
+    ]);
+    VirtualMemoryPageBits::new()
+        .with_present(true)
+        .with_writable(rng.one_in(2))
+        .with_user(rng.one_in(2))
+        .with_global(rng.one_in(4))
+        .with_no_execute(rng.one_in(2))
+        .with_cache_mode(mode)
+}
+
+fn random_mapping(rng: &mut Rng) -> Mapping {
+    let size = match rng.below(16) {
+        0 => Size1G::SIZE,
+        1..=4 => Size2M::SIZE,
+        _ => Size4K::SIZE,
+    };
+    let gib = (rng.pick(&L4_SLOTS) << 39) | (rng.below(4) << 30);
+    let va = if size == Size1G::SIZE {
+        gib
+    } else {
+        gib + rng.below(SMALL_WINDOW / size) * size
+    };
+    Mapping {
+        va,
+        pa: rng.below((64 * Size1G::SIZE) / size) * size,
+        size,
+        flags: random_flags(rng),
+    }
+}
+
+/// Up to [`MAPPINGS`] random mappings, none overlapping another.
+fn random_mappings(rng: &mut Rng) -> Vec<Mapping> {
+    let mut mappings: Vec<Mapping> = Vec::new();
+    for _ in 0..MAPPINGS {
+        let m = random_mapping(rng);
+        if mappings.iter().all(|other| !m.overlaps(other)) {
+            mappings.push(m);
+        }
+    }
+    mappings
+}
+
+fn map(space: &AddressSpace<MockMemory>, alloc: &mut MockFrameAlloc, m: &Mapping) {
+    let (va, pa) = (VirtualAddress::new(m.va), PhysicalAddress::new(m.pa));
+    let result = match m.size {
+        Size1G::SIZE => space.map_one::<_, Size1G>(alloc, va, pa, nonleaf_flags(), m.flags),
+        Size2M::SIZE => space.map_one::<_, Size2M>(alloc, va, pa, nonleaf_flags(), m.flags),
+        _ => space.map_one::<_, Size4K>(alloc, va, pa, nonleaf_flags(), m.flags),
+    };
+    result.unwrap_or_else(|e| panic!("mapping {m:?} failed: {e:?}"));
+}
+
+fn unmap(space: &AddressSpace<MockMemory>, m: &Mapping) {
+    if m.size == Size4K::SIZE {
+        space
+            .unmap_one(VirtualAddress::new(m.va))
+            .unwrap_or_else(|e| panic!("unmapping {m:?} failed: {e}"));
+    } else {
+        space.unmap_region(VirtualAddress::new(m.va), m.size);
+    }
+}
+
+fn assert_mapped(space: &AddressSpace<MockMemory>, m: &Mapping, rng: &mut Rng) {
+    for (va, pa) in [
+        (VirtualAddress::new(m.va), PhysicalAddress::new(m.pa)),
+        (
+            VirtualAddress::new(m.end() - 1),
+            PhysicalAddress::new(m.pa + m.size - 1),
+        ),
+        m.probe(rng),
+    ] {
+        assert_eq!(space.query(va), Some(pa), "wrong translation in {m:?}");
+        assert_eq!(space.query_flags(va), Some(m.flags), "wrong flags in {m:?}");
+    }
+}
+
+fn assert_unmapped(space: &AddressSpace<MockMemory>, m: &Mapping, rng: &mut Rng) {
+    for va in [m.va, m.end() - 1, m.probe(rng).0.as_u64()] {
+        let va = VirtualAddress::new(va);
+        assert_eq!(space.query(va), None, "{va:?} of {m:?} is still mapped");
+        assert_eq!(space.query_flags(va), None);
+    }
+}
+
+/// The tables reachable from the root are exactly the frames allocated.
+fn assert_no_leaks(mem: &MockMemory, root: PhysicalPage<Size4K>) {
+    let tables: BTreeSet<_> = walk_tables(mem, root).iter().map(|t| t.frame).collect();
+    assert_eq!(tables, mem.live_frames());
+}
+
+#[test]
+fn query_round_trips_random_mappings() {
+    run_cases(CASES, |rng| {
+        let mem = MockMemory::new(FRAMES);
+        let mut alloc = mem.allocator();
+        let space = AddressSpace::from_root(&mem, alloc.alloc_4k().unwrap());
+
+        let mappings = random_mappings(rng);
+        for m in &mappings {
+            map(&space, &mut alloc, m);
+        }
+
+        for m in &mappings {
+            assert_mapped(&space, m, rng);
+        }
+        for _ in 0..MAPPINGS {
+            let hole = random_mapping(rng);
+            if mappings.iter().all(|m| !hole.overlaps(m)) {
+                assert_unmapped(&space, &hole, rng);
+            }
+        }
+        assert_no_leaks(&mem, space.root_page());
+    });
+}
+
+#[test]
+fn unmap_removes_exactly_the_target() {
+    run_cases(CASES, |rng| {
+        let mem = MockMemory::new(FRAMES);
+        let mut alloc = mem.allocator();
+        let space = AddressSpace::from_root(&mem, alloc.alloc_4k().unwrap());
+
+        let mappings = random_mappings(rng);
+        for m in &mappings {
+            map(&space, &mut alloc, m);
+        }
+
+        let (gone, kept): (Vec<_>, Vec<_>) = mappings.iter().partition(|_| rng.one_in(2));
+        for m in &gone {
+            unmap(&space, m);
+        }
+
+        for m in &gone {
+            assert_unmapped(&space, m, rng);
+        }
+        for m in &kept {
+            assert_mapped(&space, m, rng);
+        }
+
+        // Huge leaves cannot be unmapped as 4 KiB pages.
+        for m in kept.iter().filter(|m| m.size != Size4K::SIZE) {
+            assert!(space.unmap_one(VirtualAddress::new(m.va)).is_err());
+            assert_mapped(&space, m, rng);
+        }
+
+        // Unmapping never frees tables.
+        assert_no_leaks(&mem, space.root_page());
+    });
+}
+
+#[test]
+fn collapse_frees_only_empty_tables() {
+    run_cases(CASES, |rng| {
+        let mem = MockMemory::new(FRAMES);
+        let mut alloc = mem.allocator();
+        let space = AddressSpace::from_root(&mem, alloc.alloc_4k().unwrap());
+
+        let mappings = random_mappings(rng);
+        for m in &mappings {
+            map(&space, &mut alloc, m);
+        }
+        let (gone, kept): (Vec<_>, Vec<_>) = mappings.iter().partition(|_| rng.one_in(2));
+        for m in &gone {
+            unmap(&space, m);
+        }
+
+        // A freed table that is still in use trips the mock on the next
+        // access, or shows up as a leak.
+        space.collapse_empty_tables(&mut alloc);
+        for m in &kept {
+            assert_mapped(&space, m, rng);
+        }
+        for m in &gone {
+            assert_unmapped(&space, m, rng);
+        }
+        assert_no_leaks(&mem, space.root_page());
+
+        // Every table but the root holds something.
+        for table in walk_tables(&mem, space.root_page()) {
+            assert!(table.level == 4 || table.present > 0, "empty {table:?}");
+        }
+
+        // Once everything is gone, only the root is left.
+        for m in &kept {
+            unmap(&space, m);
+        }
+        space.collapse_empty_tables(&mut alloc);
+        assert_eq!(mem.live_frames(), BTreeSet::from([space.root_page()]));
+    });
+}
+
+#[test]
+fn split_keeps_every_translation() {
+    run_cases(CASES, |rng| {
+        let mem = MockMemory::new(FRAMES + 2 * MAPPINGS);
+        let mut alloc = mem.allocator();
+        let space = AddressSpace::from_root(&mem, alloc.alloc_4k().unwrap());
+
+        let mappings = random_mappings(rng);
+        for m in &mappings {
+            map(&space, &mut alloc, m);
+        }
+
+        for m in mappings.iter().filter(|m| m.size != Size4K::SIZE) {
+            let (va, _) = m.probe(rng);
+            space.split_to_4k(&mut alloc, va, nonleaf_flags()).unwrap();
+
+            // Now a single 4 KiB page can go without taking the rest along.
+            let page = VirtualAddress::new(va.as_u64() & !(Size4K::SIZE - 1));
+            let pa = space.query(page).unwrap();
+            space.unmap_one(page).unwrap();
+            assert_eq!(space.query(page), None);
+            space
+                .map_one::<_, Size4K>(&mut alloc, page, pa, nonleaf_flags(), m.flags)
+                .unwrap();
+        }
+
+        for m in &mappings {
+            assert_mapped(&space, m, rng);
+        }
+        assert_no_leaks(&mem, space.root_page());
+    });
+}
+
+#[test]
+fn promotion_merges_contiguous_runs() {
+    run_cases(CASES, |rng| {
+        let mem = MockMemory::new(16);
+        let mut alloc = mem.allocator();
+        let space = AddressSpace::from_root(&mem, alloc.alloc_4k().unwrap());
+
+        // A GiB of contiguous memory, mapped with 2 MiB leaves except for a
+        // few chunks split into 4 KiB pages.
+        let flags = random_flags(rng);
+        let gib = Mapping {
+            va: (rng.pick(&L4_SLOTS) << 39) | (rng.below(4) << 30),
+            pa: rng.below(64) * Size1G::SIZE,
+            size: Size1G::SIZE,
+            flags,
+        };
+        let split: BTreeSet<u64> = (0..8).map(|_| rng.below(512)).collect();
+        // One page of a split chunk may differ in its flags.
+        let spoiled = rng.one_in(2).then(|| *split.first().unwrap());
+        for chunk in 0..512 {
+            let va = gib.va + chunk * Size2M::SIZE;
+            let pa = gib.pa + chunk * Size2M::SIZE;
+            if !split.contains(&chunk) {
+                map(
+                    &space,
+                    &mut alloc,
+                    &Mapping {
+                        va,
+                        pa,
+                        size: Size2M::SIZE,
+                        flags,
+                    },
+                );
+                continue;
+            }
+            for page in 0..512 {
+                let off = page * Size4K::SIZE;
+                let flags = if spoiled == Some(chunk) && page == 511 {
+                    flags.with_no_execute(!flags.no_execute)
+                } else {
+                    flags
+                };
+                let m = Mapping {
+                    va: va + off,
+                    pa: pa + off,
+                    size: Size4K::SIZE,
+                    flags,
+                };
+                map(&space, &mut alloc, &m);
+            }
+        }
+
+        // Leaving out the first chunk keeps it, and the GiB, as they are.
+        let skipped = rng.one_in(4).then_some(0);
+        let start = gib.va + skipped.map_or(0, |_| Size2M::SIZE);
+        let stats =
+            space.promote_large_pages(&mut alloc, VirtualAddress::new(start), gib.end() - start);
+
+        let kept = split
+            .iter()
+            .filter(|&&c| Some(c) == spoiled || Some(c) == skipped);
+        assert_eq!(stats.to_2m, split.len() - kept.count());
+        let whole = spoiled.is_none() && skipped.is_none();
+        assert_eq!(stats.to_1g, usize::from(whole));
+        assert_no_leaks(&mem, space.root_page());
+        let pts = walk_tables(&mem, space.root_page())
+            .iter()
+            .filter(|t| t.level == 1)
+            .count();
+        assert_eq!(pts, split.len() - stats.to_2m);
+
+        for chunk in 0..512 {
+            let va = gib.va + chunk * Size2M::SIZE;
+            let pa = gib.pa + chunk * Size2M::SIZE;
+            let m = Mapping {
+                va,
+                pa,
+                size: Size2M::SIZE - Size4K::SIZE,
+                flags,
+            };
+            assert_mapped(&space, &m, rng);
+        }
+        let last = Mapping {
+            va: gib.end() - Size4K::SIZE,
+            pa: gib.pa + gib.size - Size4K::SIZE,
+            size: Size4K::SIZE,
+            flags,
+        };
+        assert_mapped(&space, &last, rng);
+        if let Some(chunk) = spoiled {
+            let va = VirtualAddress::new(gib.va + (chunk + 1) * Size2M::SIZE - Size4K::SIZE);
+            assert_eq!(space.query_flags(va).unwrap().no_execute, !flags.no_execute);
+        }
+    });
+}
+
+#[test]
+fn promotion_keeps_the_link_permissions() {
+    let mem = MockMemory::new(8);
+    let mut alloc = mem.allocator();
+    let space = AddressSpace::from_root(&mem, alloc.alloc_4k().unwrap());
+    let link = nonleaf_flags().with_writable(false).with_no_execute(true);
+    let leaf = VirtualMemoryPageBits::new()
+        .with_present(true)
+        .with_writable(true);
+    for page in 0..512 {
+        let off = page * Size4K::SIZE;
+        space
+            .map_one::<_, Size4K>(
+                &mut alloc,
+                VirtualAddress::new(Size1G::SIZE + off),
+                PhysicalAddress::new(Size2M::SIZE + off),
+                link,
+                leaf,
+            )
+            .unwrap();
+    }
+
+    let stats = space.promote_large_pages(&mut alloc, VirtualAddress::new(0), 2 * Size1G::SIZE);
+    assert_eq!((stats.to_2m, stats.to_1g), (1, 0));
+    let flags = space
+        .query_flags(VirtualAddress::new(Size1G::SIZE))
+        .unwrap();
+    assert!(!flags.writable && flags.no_execute);
+    assert_eq!(
+        space.query(VirtualAddress::new(Size1G::SIZE + 0x1234)),
+        Some(PhysicalAddress::new(Size2M::SIZE + 0x1234))
+    );
+    assert_no_leaks(&mem, space.root_page());
+}
+
+#[test]
+fn trace_ends_at_the_leaf_query_uses() {
+    run_cases(CASES, |rng| {
+        let mem = MockMemory::new(FRAMES);
+        let mut alloc = mem.allocator();
+        let space = AddressSpace::from_root(&mem, alloc.alloc_4k().unwrap());
+
+        let mappings = random_mappings(rng);
+        for m in &mappings {
+            map(&space, &mut alloc, m);
+        }
+
+        for m in &mappings {
+            let (va, pa) = m.probe(rng);
+            let mut steps = Vec::new();
+            space.trace(va, |step| steps.push(*step));
+
+            let levels: Vec<u8> = steps.iter().map(|s| s.level).collect();
+            let expected: Vec<u8> = (leaf_level(m.size)..=4).rev().collect();
+            assert_eq!(levels, expected, "trace of {va}");
+            assert_eq!(steps[0].table, space.root_page());
+            for pair in steps.windows(2) {
+                let (next, _) = pair[0].target.expect("link not present");
+                assert!(!pair[0].leaf);
+                assert_eq!(pair[1].table.base(), next);
+            }
+
+            let last = steps.last().unwrap();
+            let (page, flags) = last.target.expect("leaf not present");
+            assert!(last.leaf);
+            assert_eq!(page.as_u64(), pa.as_u64() / m.size * m.size);
+            assert_eq!(flags.writable, m.flags.writable);
+        }
+
+        // Stops at the first entry that is not present; PML4 slot 2 is
+        // never used.
+        let mut steps = Vec::new();
+        space.trace(VirtualAddress::new(2 << 39), |step| steps.push(*step));
+        assert_eq!(steps.len(), 1);
+        assert!(steps[0].target.is_none() && !steps[0].leaf);
+    });
+}
+
+/// The level of the leaf mapping a page of `size`.
+const fn leaf_level(size: u64) -> u8 {
+    match size {
+        Size1G::SIZE => 3,
+        Size2M::SIZE => 2,
+        _ => 1,
+    }
+}
+
+#[test]
+fn mock_memory_catches_use_after_free() {
+    let mem = MockMemory::new(4);
+    let mut alloc = mem.allocator();
+    let space = AddressSpace::from_root(&mem, alloc.alloc_4k().unwrap());
+    let m = Mapping {
+        va: 0x20_0000,
+        pa: 0x1000,
+        size: Size4K::SIZE,
+        flags: nonleaf_flags(),
+    };
+    map(&space, &mut alloc, &m);
+
+    // Free the PT behind the back of the PD that links it.
+    let pt = walk_tables(&mem, space.root_page())
+        .into_iter()
+        .find(|t| t.level == 1)
+        .unwrap();
+    alloc.free_4k(pt.frame);
+
+    let result =
+        std::panic::catch_unwind(AssertUnwindSafe(|| space.query(VirtualAddress::new(m.va))));
+    assert!(result.is_err());
+}
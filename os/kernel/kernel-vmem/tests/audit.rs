@@ -0,0 +1,135 @@
+//! Tests of the page table [`audit`](kernel_vmem::audit) against mock physical memory.
+
+use kernel_memory_addresses::{PageSize, PhysicalAddress, Size1G, Size2M, Size4K, VirtualAddress};
+use kernel_vmem::audit::{Violation, audit};
+use kernel_vmem::info::HHDM_BASE;
+use kernel_vmem::{AddressSpace, PhysFrameAlloc, VirtualMemoryPageBits};
+use kernel_vmem_testutil::{MockFrameAlloc, MockMemory};
+
+const TEXT: VirtualAddress = VirtualAddress::new(0xffff_ffff_8000_0000);
+const DATA: VirtualAddress = VirtualAddress::new(0xffff_ffff_8020_0000);
+const USER: VirtualAddress = VirtualAddress::new(0x40_0000);
+
+const fn kernel_links() -> VirtualMemoryPageBits {
+    VirtualMemoryPageBits::new()
+        .with_present(true)
+        .with_writable(true)
+}
+
+const fn user_links() -> VirtualMemoryPageBits {
+    kernel_links().with_user(true)
+}
+
+const fn kernel_data() -> VirtualMemoryPageBits {
+    kernel_links().with_global(true).with_no_execute(true)
+}
+
+const fn kernel_text() -> VirtualMemoryPageBits {
+    VirtualMemoryPageBits::new()
+        .with_present(true)
+        .with_global(true)
+}
+
+fn is_text(va: VirtualAddress) -> bool {
+    (TEXT.as_u64()..TEXT.as_u64() + Size2M::SIZE).contains(&va.as_u64())
+}
+
+/// An address space laid out like the kernel's, with a user program.
+fn clean_space(mem: &MockMemory) -> (AddressSpace<'_, MockMemory>, MockFrameAlloc<'_>) {
+    let mut alloc = mem.allocator();
+    let space = AddressSpace::from_root(mem, alloc.alloc_4k().unwrap());
+    let pa = PhysicalAddress::new(0x20_0000);
+    space
+        .map_one::<_, Size1G>(
+            &mut alloc,
+            HHDM_BASE,
+            PhysicalAddress::zero(),
+            kernel_links(),
+            kernel_data(),
+        )
+        .unwrap();
+    space
+        .map_one::<_, Size4K>(&mut alloc, TEXT, pa, kernel_links(), kernel_text())
+        .unwrap();
+    space
+        .map_one::<_, Size2M>(&mut alloc, DATA, pa, kernel_links(), kernel_data())
+        .unwrap();
+    let user_code = VirtualMemoryPageBits::user_leaf_code_wb().with_no_execute(false);
+    space
+        .map_one::<_, Size4K>(&mut alloc, USER, pa, user_links(), user_code)
+        .unwrap();
+    (space, alloc)
+}
+
+fn findings(space: &AddressSpace<'_, MockMemory>) -> Vec<(Violation, VirtualAddress, u8)> {
+    let mut found = Vec::new();
+    let count = audit(space, is_text, |f| {
+        found.push((f.violation, f.leaf.va, f.leaf.level));
+    });
+    assert_eq!(count, found.len());
+    found
+}
+
+#[test]
+fn kernel_layout_passes() {
+    let mem = MockMemory::new(16);
+    let (space, _alloc) = clean_space(&mem);
+    assert_eq!(findings(&space), []);
+}
+
+#[test]
+fn every_invariant_is_checked() {
+    let mem = MockMemory::new(32);
+    let (space, mut alloc) = clean_space(&mem);
+    let pa = PhysicalAddress::new(0x1000);
+
+    let user_in_kernel = VirtualAddress::new(0xffff_c000_0000_0000);
+    let leaf = kernel_data().with_user(true);
+    space
+        .map_one::<_, Size4K>(&mut alloc, user_in_kernel, pa, user_links(), leaf)
+        .unwrap();
+
+    let executable_data = DATA + Size2M::SIZE;
+    let leaf = kernel_text().with_writable(false);
+    space
+        .map_one::<_, Size4K>(&mut alloc, executable_data, pa, kernel_links(), leaf)
+        .unwrap();
+
+    let writable_text = TEXT + Size4K::SIZE;
+    let leaf = kernel_text().with_writable(true);
+    space
+        .map_one::<_, Size4K>(&mut alloc, writable_text, pa, kernel_links(), leaf)
+        .unwrap();
+
+    let global_user = USER + Size4K::SIZE;
+    let leaf = VirtualMemoryPageBits::user_leaf_data_wb().with_global(true);
+    space
+        .map_one::<_, Size4K>(&mut alloc, global_user, pa, user_links(), leaf)
+        .unwrap();
+
+    assert_eq!(
+        findings(&space),
+        [
+            (Violation::GlobalUserPage, global_user, 1),
+            (Violation::UserKernelPage, user_in_kernel, 1),
+            (Violation::WritableExecutable, writable_text, 1),
+            (Violation::ExecutableData, executable_data, 1),
+        ]
+    );
+}
+
+#[test]
+fn links_restrict_the_leaf() {
+    let mem = MockMemory::new(16);
+    let (space, mut alloc) = clean_space(&mem);
+
+    // A user, writable, executable leaf behind supervisor-only, no-execute links.
+    let va = VirtualAddress::new(0xffff_c000_0000_0000);
+    let links = kernel_links().with_no_execute(true);
+    let leaf = user_links();
+    space
+        .map_one::<_, Size4K>(&mut alloc, va, PhysicalAddress::new(0x1000), links, leaf)
+        .unwrap();
+
+    assert_eq!(findings(&space), []);
+}
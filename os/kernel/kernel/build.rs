@@ -1,39 +1,174 @@
-use kernel_info::memory;
-use std::{env, path::PathBuf};
+//! Generates the kernel's linker script, `kernel.ld`, from the memory layout
+//! in [`kernel_info::memory`], so the two can not drift apart.
+//!
+//! The script gives `.text`, `.rodata` and `.data`/`.bss` a `PT_LOAD`
+//! segment each (see `kimage.rs`), keeps the [tables](KEPT_TABLES) other
+//! crates place into sections of their own, and brackets them with
+//! `__<name>_start` / `__<name>_end`. The layout is checked twice: the
+//! constants when this script is compiled, the linked image by `ASSERT`s in
+//! the generated script.
 
-fn main() {
-    // Point to the linker script
-    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
-    let ld = manifest_dir.join("kernel.ld");
-
-    // Sanity checks (fail fast during build)
-    let kernel_base = memory::KERNEL_BASE;
-    let phys_load = memory::PHYS_LOAD;
-    assert_eq!(
-        kernel_base.as_u64() & ((1u64 << 21) - 1),
-        0,
-        "KERNEL_BASE must be 2 MiB aligned (got {kernel_base})"
+use kernel_info::memory::{HHDM_BASE, HHDM_SIZE, KERNEL_BASE, PHYS_LOAD};
+use std::fmt::Write as _;
+use std::{env, fs, path::PathBuf};
+
+/// Alignment of every output section, so each starts on a page of its own
+/// and can be mapped with its own permissions.
+const PAGE_SIZE: u64 = 4096;
+
+/// Alignment of [`KERNEL_BASE`]: the loader maps the kernel with 2 MiB pages.
+const LARGE_PAGE_SIZE: u64 = 2 * 1024 * 1024;
+
+/// With `code-model=kernel`, all kernel symbols must lie in the top 2 GiB of
+/// the address space.
+const CODE_MODEL_SIZE: u64 = 2 * 1024 * 1024 * 1024;
+
+const _: () = {
+    let kernel_base = KERNEL_BASE.as_u64();
+    let phys_load = PHYS_LOAD.as_u64();
+    assert!(
+        kernel_base.is_multiple_of(LARGE_PAGE_SIZE),
+        "KERNEL_BASE must be 2 MiB aligned"
+    );
+    assert!(
+        phys_load.is_multiple_of(PAGE_SIZE),
+        "PHYS_LOAD must be 4 KiB aligned"
+    );
+    assert!(
+        kernel_base > u64::MAX - CODE_MODEL_SIZE,
+        "KERNEL_BASE must lie in the top 2 GiB"
     );
-    assert_eq!(
-        phys_load.as_u64() & 0xfff,
-        0,
-        "PHYS_LOAD must be 4 KiB aligned (got {phys_load})"
+    assert!(
+        phys_load < CODE_MODEL_SIZE,
+        "the kernel image must start below KERNEL_BASE + 2 GiB"
     );
+    assert!(
+        HHDM_BASE.as_u64() + HHDM_SIZE <= kernel_base,
+        "the direct map must end below KERNEL_BASE"
+    );
+};
+
+/// A table collected from input sections of the same name into `.rodata`.
+struct KeptTable {
+    /// Input section, and the name of the bracketing symbols.
+    name: &'static str,
+    /// Alignment of the table's entries.
+    align: u64,
+    /// Where the table is described.
+    doc: &'static str,
+}
 
-    // Rebuild when inputs change
-    println!("cargo:rerun-if-changed={}", ld.display());
+/// Tables placed in linker sections by other code.
+///
+/// Tracepoints and symbols need no entry: events are declared in one static
+/// table, and the symbol table is a boot module.
+const KEPT_TABLES: &[KeptTable] = &[
+    KeptTable {
+        name: "ktests",
+        align: 8,
+        doc: "In-kernel tests (`ktest` feature); see ktest.rs",
+    },
+    KeptTable {
+        name: "extable",
+        align: 4,
+        doc: "Expected faults and where to resume; see kernel_registers::extable",
+    },
+];
 
-    // Linker script
+fn main() {
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let ld = out_dir.join("kernel.ld");
+    fs::write(&ld, linker_script()).expect("failed to write the linker script");
+
+    // The layout comes from kernel-info, which cargo already tracks.
+    println!("cargo:rerun-if-changed=build.rs");
     println!("cargo:rustc-link-arg-bins=-T{}", ld.display());
+}
 
-    // Provide symbols to the linker script
-    // (cargo:rustc-link-arg-bins passes args directly to the linker)
-    println!(
-        "cargo:rustc-link-arg-bins=--defsym=KERNEL_BASE={kernel_base:#x}",
-        kernel_base = kernel_base.as_u64()
-    );
-    println!(
-        "cargo:rustc-link-arg-bins=--defsym=PHYS_LOAD={phys_load:#x}",
-        phys_load = phys_load.as_u64()
-    );
+/// The complete linker script.
+fn linker_script() -> String {
+    let kernel_base = KERNEL_BASE.as_u64();
+    let phys_load = PHYS_LOAD.as_u64();
+
+    let mut tables = String::new();
+    for table in KEPT_TABLES {
+        let KeptTable { name, align, doc } = table;
+        writeln!(
+            tables,
+            "
+    /* {doc} */
+    . = ALIGN({align});
+    __{name}_start = .;
+    KEEP(*(.{name}))
+    __{name}_end = .;"
+        )
+        .unwrap();
+    }
+
+    format!(
+        "/* kernel.ld — higher-half kernel; generated by build.rs, do not edit */
+OUTPUT_FORMAT(elf64-x86-64)
+OUTPUT_ARCH(i386:x86-64)
+ENTRY(_start_kernel)
+
+KBASE = {kernel_base:#x};  /* VMA base, kernel_info::memory::KERNEL_BASE */
+PLOAD = {phys_load:#x};  /* LMA base, kernel_info::memory::PHYS_LOAD */
+
+PHDRS {{
+  text   PT_LOAD FLAGS(5);   /* R+X */
+  rodata PT_LOAD FLAGS(4);   /* R   */
+  data   PT_LOAD FLAGS(6);   /* R+W */
+}}
+
+SECTIONS
+{{
+  /* All section VMAs live in higher half */
+  . = KBASE + PLOAD;
+
+  /* Text (VMA = KBASE+..., LMA = PLOAD+offset) */
+  . = ALIGN({PAGE_SIZE});
+  .text : AT(ADDR(.text) - KBASE) {{
+    *(.text .text.*)
+  }} :text
+
+  /* Read-only data */
+  . = ALIGN({PAGE_SIZE});
+  .rodata : AT(ADDR(.rodata) - KBASE) {{
+    *(.rodata .rodata.*)
+{tables}  }} :rodata
+
+  /* Writable data */
+  . = ALIGN({PAGE_SIZE});
+  .data : AT(ADDR(.data) - KBASE) {{
+    *(.data .data.*)
+  }} :data
+
+  /* BSS: no file bytes, just virtual space; includes the boot stack */
+  . = ALIGN({PAGE_SIZE});
+  __bss_start = .;
+  .bss (NOLOAD) : {{
+    *(.bss .bss.* COMMON)
+    *(.bss.boot)
+  }} :data
+  __bss_end = .;
+
+  /* Useful symbols */
+  __kernel_base     = KBASE;
+  __phys_load_base  = PLOAD;
+  __virt_start      = ADDR(.text);
+  __phys_start      = LOADADDR(.text);
+  __virt_end        = .;
+  __phys_end        = LOADADDR(.data) + SIZEOF(.data);
+
+  /* Drop unwind info */
+  /DISCARD/ : {{ *(.eh_frame) *(.eh_frame_hdr) }}
+}}
+
+ASSERT(__virt_start == KBASE + PLOAD, \"kernel image does not start at KERNEL_BASE + PHYS_LOAD\");
+ASSERT(__virt_end - KBASE <= {CODE_MODEL_SIZE:#x}, \"kernel image exceeds the top 2 GiB\");
+ASSERT(ADDR(.rodata) >= ADDR(.text) + SIZEOF(.text), \".text overlaps .rodata\");
+ASSERT(ADDR(.data) >= ADDR(.rodata) + SIZEOF(.rodata), \".rodata overlaps .data\");
+ASSERT(ADDR(.bss) >= ADDR(.data) + SIZEOF(.data), \".data overlaps .bss\");
+"
+    )
 }
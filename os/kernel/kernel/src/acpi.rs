@@ -0,0 +1,92 @@
+//! # ACPI Tables
+//!
+//! The firmware's ACPI tables, read in place through the HHDM. [`init`]
+//! validates the RSDP the loader passed along; [`fadt`], [`madt`] and
+//! [`dsdt`] then look up and parse their tables on every call, which is
+//! cheap enough for the few boot-time and shutdown users.
+//!
+//! The HHDM covers the first [`HHDM_SIZE`] bytes of physical memory only;
+//! tables above it are treated as missing. The memory holding the tables is
+//! never handed to the frame allocator.
+
+use kernel_acpi::fadt::{self, Fadt};
+use kernel_acpi::madt::{self, Madt};
+use kernel_acpi::rsdp::AcpiRoots;
+use kernel_acpi::{PhysMapRo, sdt};
+use kernel_info::boot::KernelBootInfo;
+use kernel_info::memory::{HHDM_BASE, HHDM_SIZE};
+use kernel_sync::SyncOnceCell;
+use log::{info, warn};
+
+/// Length of the ACPI 2.0 RSDP, the most the RSDP parser reads unchecked.
+const RSDP_LEN: u64 = 36;
+
+static ROOTS: SyncOnceCell<Option<AcpiRoots>> = SyncOnceCell::new();
+
+/// Maps physical memory through the HHDM; regions outside of it map to an
+/// empty slice.
+struct HhdmMap;
+
+impl PhysMapRo for HhdmMap {
+    unsafe fn map_ro<'a>(&self, paddr: u64, len: usize) -> &'a [u8] {
+        let inside = paddr
+            .checked_add(len as u64)
+            .is_some_and(|end| paddr != 0 && end <= HHDM_SIZE);
+        if !inside {
+            return &[];
+        }
+        // Safety: the HHDM maps all of this range, and firmware tables are
+        // never written.
+        unsafe { core::slice::from_raw_parts((HHDM_BASE.as_u64() + paddr) as *const u8, len) }
+    }
+}
+
+/// Find the ACPI root tables. Calling it again does nothing.
+pub fn init(bi: &KernelBootInfo) {
+    let roots = ROOTS.get_or_init(|| {
+        let rsdp = bi.rsdp()?;
+        if rsdp.saturating_add(RSDP_LEN) > HHDM_SIZE {
+            warn!("ACPI RSDP at {rsdp:#x} lies outside the HHDM; ignoring it");
+            return None;
+        }
+        // Safety: the loader got the address from the firmware.
+        unsafe { AcpiRoots::parse(&HhdmMap, rsdp) }
+    });
+
+    match roots {
+        Some(AcpiRoots {
+            xsdt_addr: Some(xsdt),
+            ..
+        }) => info!("ACPI 2.0+, XSDT at {xsdt:#x}"),
+        Some(AcpiRoots {
+            rsdt_addr: Some(rsdt),
+            ..
+        }) => info!("ACPI 1.0, RSDT at {rsdt:#x}"),
+        _ => warn!("No usable ACPI tables"),
+    }
+}
+
+/// The table with `signature`, if [`init`] found the root tables.
+fn find(signature: [u8; 4]) -> Option<&'static [u8]> {
+    let roots = ROOTS.get()?.as_ref()?;
+    // Safety: the roots were parsed through the same mapping.
+    unsafe { sdt::find(&HhdmMap, roots, signature) }
+}
+
+/// The Fixed ACPI Description Table.
+pub fn fadt() -> Option<Fadt> {
+    find(fadt::SIGNATURE).and_then(Fadt::parse)
+}
+
+/// The Differentiated System Description Table, the AML the FADT points
+/// to.
+pub fn dsdt() -> Option<&'static [u8]> {
+    let addr = fadt()?.dsdt?;
+    // Safety: the FADT names the DSDT's address; the table is checksummed.
+    unsafe { sdt::map_table(&HhdmMap, addr) }.filter(|table| table.starts_with(b"DSDT"))
+}
+
+/// The Multiple APIC Description Table.
+pub fn madt() -> Option<Madt<'static>> {
+    find(madt::SIGNATURE).and_then(Madt::parse)
+}
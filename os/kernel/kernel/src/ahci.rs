@@ -0,0 +1,585 @@
+//! # AHCI
+//!
+//! Driver of AHCI host bus adapters, through which older machines and most
+//! firmware talk to SATA disks. On `-machine q35`, QEMU's ICH9 controller
+//! carries the drives given with `-drive` and no interface.
+//!
+//! ## Bring-up
+//!
+//! [`init`] takes the first function with the AHCI class code and maps its
+//! registers from BAR 5 (`ABAR`). If the firmware still owns the
+//! controller, it is asked to hand it over; then the controller is reset
+//! and switched to AHCI mode, with its interrupts off. Each implemented
+//! [port](port) is powered up, and one whose link comes up with an ATA
+//! signature is identified. A disk that supports 48-bit LBAs becomes the
+//! [block device](crate::block) `sda`, `sdb` and so on, in port order.
+//!
+//! ## Commands
+//!
+//! Each disk runs one command at a time, from command slot 0 of its port.
+//! The issuer claims the disk, issues and waits on the disk's
+//! [`WaitQueue`]; the wait condition checks the port itself, as with the
+//! [NVMe driver](crate::nvme). There is no MSI here: the timer tick runs the
+//! top half, which checks whether a command finished, and the bottom half
+//! wakes the waiters.
+//!
+//! Reads and writes use *READ DMA EXT* and *WRITE DMA EXT* through a bounce
+//! buffer of [`BOUNCE_LEN`] bytes per disk, described by a single physical
+//! region; larger transfers are split. A write ends with *FLUSH CACHE EXT*,
+//! so the data is on the medium when it returns.
+//!
+//! ## Limitations
+//!
+//! * One controller, no port multipliers, no ATAPI drives.
+//! * No native command queuing; one command in flight per disk.
+//! * A command that times out fails its disk for good; there is no port
+//!   reset.
+
+mod port;
+
+use crate::alloc::dma::{DmaBuffer, DmaConstraints, DmaError};
+use crate::alloc::mmio::{Mmio, map_mmio};
+use crate::block::{self, BlockDevice, BlockError};
+use crate::irq_thread::{self, IrqReturn, ThreadedIrq};
+use crate::pci::{self, Bar, COMMAND_BUS_MASTER, COMMAND_MEMORY_SPACE, PciFunction};
+use crate::sched::WaitQueue;
+use crate::timer;
+use core::fmt;
+use core::sync::atomic::{AtomicBool, Ordering};
+use kernel_alloc::mmio::MmioRegion;
+use kernel_alloc::vmm::VmmError;
+use kernel_memory_addresses::{PageSize, Size4K};
+use kernel_sync::{IrqGuard, SpinMutex, SyncOnceCell};
+use log::{debug, info, warn};
+use port::{AtaCommand, Port, SIGNATURE_ATA, TaskFile, spin_until};
+
+/// PCI class, subclass and programming interface of AHCI controllers.
+const CLASS: (u8, u8, u8) = (0x01, 0x06, 0x01);
+
+/// BAR holding the registers, `ABAR`.
+const ABAR: u8 = 5;
+
+/// Register: host capabilities.
+const CAP: u64 = 0x00;
+/// Register: global host control.
+const GHC: u64 = 0x04;
+/// Register: ports implemented.
+const PI: u64 = 0x0C;
+/// Register: version.
+const VS: u64 = 0x10;
+/// Register: extended host capabilities.
+const CAP2: u64 = 0x24;
+/// Register: BIOS/OS handoff control and status.
+const BOHC: u64 = 0x28;
+/// Offset of the registers of port 0.
+const PORTS: u64 = 0x100;
+/// Distance between the registers of two ports.
+const PORT_STRIDE: u64 = 0x80;
+/// Registers the driver maps: the ones above and those of all 32 ports.
+const REGISTERS_LEN: u64 = PORTS + 32 * PORT_STRIDE;
+
+/// `CAP`: 64-bit addressing.
+const CAP_S64A: u32 = 1 << 31;
+
+/// `GHC`: HBA reset.
+const GHC_HR: u32 = 1 << 0;
+/// `GHC`: AHCI enable, as opposed to legacy IDE emulation.
+const GHC_AE: u32 = 1 << 31;
+
+/// `CAP2`: BIOS/OS handoff supported.
+const CAP2_BOH: u32 = 1 << 0;
+
+/// `BOHC`: the firmware owns the controller.
+const BOHC_BOS: u32 = 1 << 0;
+/// `BOHC`: the operating system asks for the controller.
+const BOHC_OOS: u32 = 1 << 1;
+
+/// ATA command: identify device.
+const ATA_IDENTIFY: u8 = 0xEC;
+/// ATA command: read with 48-bit LBA.
+const ATA_READ_DMA_EXT: u8 = 0x25;
+/// ATA command: write with 48-bit LBA.
+const ATA_WRITE_DMA_EXT: u8 = 0x35;
+/// ATA command: flush the write cache, 48-bit variant.
+const ATA_FLUSH_CACHE_EXT: u8 = 0xEA;
+
+/// Size of the identify data.
+const IDENTIFY_LEN: usize = 512;
+
+/// How long the firmware may take to hand the controller over.
+const HANDOFF_TIMEOUT_MS: u64 = 2_000;
+/// How long an HBA reset may take.
+const RESET_TIMEOUT_MS: u64 = 1_000;
+/// How long a link may take to come up; the specification allows 10 ms.
+const LINK_TIMEOUT_MS: u64 = 50;
+/// How long a device may take to spin up and become ready.
+const READY_TIMEOUT_MS: u64 = 5_000;
+/// How long a port's command engine may take to stop.
+const ENGINE_TIMEOUT_MS: u64 = 500;
+/// How long a command may take.
+const COMMAND_TIMEOUT_MS: u64 = 5_000;
+
+/// Size of each disk's bounce buffer, and of the largest transfer.
+#[allow(clippy::cast_possible_truncation)]
+pub const BOUNCE_LEN: usize = 16 * Size4K::SIZE as usize;
+
+/// Largest sector size supported, as a power of two: a page.
+const MAX_SECTOR_SHIFT: u32 = 12;
+
+/// Most disks registered as block devices.
+const MAX_DISKS: usize = 4;
+
+/// Block device names of the disks, in port order.
+const DISK_NAMES: [&str; MAX_DISKS] = ["sda", "sdb", "sdc", "sdd"];
+
+static CONTROLLER: SyncOnceCell<Controller> = SyncOnceCell::new();
+
+static DISKS: [SyncOnceCell<Disk>; MAX_DISKS] = [const { SyncOnceCell::new() }; MAX_DISKS];
+
+/// Commands finished on any disk; run from the timer tick.
+pub static IRQ: ThreadedIrq = ThreadedIrq::new("irq-ahci0", check_completions, wake_issuers);
+
+/// Why the controller or a disk could not be set up, or a command failed.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum AhciError {
+    /// BAR 5 is not a memory range.
+    NoRegisters(PciFunction),
+    /// The registers could not be mapped.
+    Map(VmmError),
+    /// The disk lacks 48-bit LBAs or has an unsupported sector size.
+    Unsupported,
+    /// No contiguous frames for command memory or buffers.
+    OutOfMemory,
+    /// The controller, a port or a command did not finish in time.
+    Timeout,
+    /// An earlier command timed out; the disk is unusable.
+    Failed,
+    /// The device failed a command.
+    Device(TaskFile),
+}
+
+impl fmt::Display for AhciError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoRegisters(function) => write!(f, "{function} has no memory BAR 5"),
+            Self::Map(e) => write!(f, "failed to map the registers: {e}"),
+            Self::Unsupported => f.write_str("no 48-bit LBAs or unsupported sector size"),
+            Self::OutOfMemory => f.write_str("out of memory"),
+            Self::Timeout => f.write_str("timed out"),
+            Self::Failed => f.write_str("disk failed after a timeout"),
+            Self::Device(task_file) => write!(f, "command failed: {task_file}"),
+        }
+    }
+}
+
+impl From<DmaError> for AhciError {
+    fn from(_: DmaError) -> Self {
+        Self::OutOfMemory
+    }
+}
+
+/// A controller after bring-up.
+struct Controller {
+    function: PciFunction,
+    regs: Mmio,
+    /// Where command memory and buffers may live.
+    constraints: DmaConstraints,
+}
+
+impl Controller {
+    /// Take the controller from the firmware and reset it.
+    fn bring_up(function: PciFunction) -> Result<Self, AhciError> {
+        let Some(Bar::Memory(base)) = function.bar(ABAR) else {
+            return Err(AhciError::NoRegisters(function));
+        };
+        function.enable(COMMAND_MEMORY_SPACE | COMMAND_BUS_MASTER);
+        let regs = map_mmio(base, REGISTERS_LEN).map_err(AhciError::Map)?;
+
+        take_ownership(function, &regs);
+        regs.write32(GHC, GHC_AE);
+        regs.write32(GHC, GHC_AE | GHC_HR);
+        spin_until(RESET_TIMEOUT_MS, || regs.read32(GHC) & GHC_HR == 0)?;
+        // The reset may clear `AE`; interrupts stay off.
+        regs.write32(GHC, GHC_AE);
+
+        let cap = regs.read32(CAP);
+        let constraints = if cap & CAP_S64A != 0 {
+            DmaConstraints::ANY
+        } else {
+            DmaConstraints::BELOW_4G
+        };
+        let version = regs.read32(VS);
+        info!(
+            "AHCI controller at {function}: AHCI {}.{}, {} ports, ports implemented {:#010x}",
+            version >> 16,
+            (version >> 8) & 0xFF,
+            (cap & 0x1F) + 1,
+            regs.read32(PI)
+        );
+        Ok(Self {
+            function,
+            regs,
+            constraints,
+        })
+    }
+
+    /// Probe the implemented ports and register their disks as block
+    /// devices.
+    fn register_disks(&self) {
+        let implemented = self.regs.read32(PI);
+        let mut slots = DISKS.iter().zip(DISK_NAMES).peekable();
+        for index in (0..32).filter(|index| implemented & (1 << index) != 0) {
+            let port = match Port::new(PORTS + PORT_STRIDE * index, self.constraints) {
+                Ok(port) => port,
+                Err(e) => {
+                    warn!(
+                        "AHCI controller at {}: port {index} not usable: {e}",
+                        self.function
+                    );
+                    continue;
+                }
+            };
+            if !port.power_up(&self.regs, LINK_TIMEOUT_MS) {
+                continue;
+            }
+            let Some(&(slot, name)) = slots.peek() else {
+                warn!(
+                    "AHCI controller at {}: disk on port {index} ignored",
+                    self.function
+                );
+                continue;
+            };
+            let disk = match Disk::attach(&self.regs, port, name, self.constraints) {
+                Ok(Some(disk)) => {
+                    slots.next();
+                    slot.get_or_init(|| disk)
+                }
+                Ok(None) => {
+                    debug!(
+                        "AHCI controller at {}: port {index} has no ATA disk",
+                        self.function
+                    );
+                    continue;
+                }
+                Err(e) => {
+                    warn!(
+                        "AHCI controller at {}: port {index} not usable: {e}",
+                        self.function
+                    );
+                    continue;
+                }
+            };
+            if let Err(e) = block::register(disk) {
+                warn!("{name} not registered: {e}");
+            }
+        }
+    }
+}
+
+/// A SATA disk behind a port, as a block device.
+struct Disk {
+    name: &'static str,
+    port: SpinMutex<Port>,
+    bounce: DmaBuffer,
+    /// Claimed by an issuer.
+    busy: AtomicBool,
+    /// A command timed out.
+    failed: AtomicBool,
+    /// Where issuers wait for the disk and for their command.
+    done: WaitQueue,
+    sector_size: usize,
+    sectors: u64,
+}
+
+impl Disk {
+    /// Start `port` and identify its disk; `None` if it is not an ATA disk.
+    fn attach(
+        regs: &MmioRegion,
+        port: Port,
+        name: &'static str,
+        constraints: DmaConstraints,
+    ) -> Result<Option<Self>, AhciError> {
+        let signature = match port.start(regs, READY_TIMEOUT_MS) {
+            Ok(signature) => signature,
+            Err(e) => {
+                let _ = port.stop(regs, ENGINE_TIMEOUT_MS);
+                return Err(e);
+            }
+        };
+        if signature != SIGNATURE_ATA {
+            let _ = port.stop(regs, ENGINE_TIMEOUT_MS);
+            return Ok(None);
+        }
+        let mut disk = Self {
+            name,
+            port: SpinMutex::new(port),
+            bounce: DmaBuffer::alloc(BOUNCE_LEN, constraints.with_align(Size4K::SIZE))?,
+            busy: AtomicBool::new(false),
+            failed: AtomicBool::new(false),
+            done: WaitQueue::new(),
+            sector_size: 0,
+            sectors: 0,
+        };
+        if let Err(e) = disk.identify(regs) {
+            // Stop the port before its memory is freed.
+            let _ = disk.with_port(|port| port.stop(regs, ENGINE_TIMEOUT_MS));
+            return Err(e);
+        }
+        Ok(Some(disk))
+    }
+
+    /// Identify the disk and take its geometry.
+    fn identify(&mut self, regs: &MmioRegion) -> Result<(), AhciError> {
+        let mut claim = self.claim()?;
+        claim.execute(regs, AtaCommand::new(ATA_IDENTIFY), IDENTIFY_LEN)?;
+        let identify = claim.bounce();
+        let (sectors, sector_size) = geometry(identify).ok_or(AhciError::Unsupported)?;
+        let (mut model, mut serial) = ([0; 40], [0; 20]);
+        info!(
+            "{}: {}, serial {}, {sectors} sectors of {sector_size} bytes",
+            self.name,
+            ata_text(&identify[54..94], &mut model),
+            ata_text(&identify[20..40], &mut serial)
+        );
+        drop(claim);
+        self.sectors = sectors;
+        self.sector_size = sector_size;
+        Ok(())
+    }
+
+    fn with_port<R>(&self, f: impl FnOnce(&mut Port) -> R) -> R {
+        let _irq = IrqGuard::new();
+        f(&mut self.port.lock())
+    }
+
+    /// Wait until the disk is free and take it.
+    fn claim(&self) -> Result<Claim<'_>, AhciError> {
+        let claimed = self.done.wait_until_timeout(
+            || {
+                self.failed.load(Ordering::Acquire)
+                    || self
+                        .busy
+                        .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+                        .is_ok()
+            },
+            timer::ms_to_ticks(COMMAND_TIMEOUT_MS),
+        );
+        if self.failed.load(Ordering::Acquire) {
+            if claimed {
+                // We may have taken it before noticing.
+                self.busy.store(false, Ordering::Release);
+            }
+            return Err(AhciError::Failed);
+        }
+        if !claimed {
+            return Err(AhciError::Timeout);
+        }
+        Ok(Claim { disk: self })
+    }
+
+    /// Run `command` on the `count` sectors starting at `lba`, with their
+    /// data in the bounce buffer.
+    #[allow(clippy::cast_possible_truncation)]
+    fn transfer(
+        &self,
+        claim: &Claim<'_>,
+        regs: &MmioRegion,
+        command: AtaCommand,
+        lba: u64,
+        count: usize,
+    ) -> Result<(), AhciError> {
+        // At most `BOUNCE_LEN / 512` sectors, far below the limit of 65536.
+        let command = command.sectors(lba, count as u16);
+        claim.execute(regs, command, count * self.sector_size)
+    }
+
+    fn controller() -> Result<&'static Controller, BlockError> {
+        CONTROLLER.get().ok_or(BlockError::Io)
+    }
+
+    fn failed(&self, what: &str, lba: u64, e: AhciError) -> BlockError {
+        warn!("{}: {what} at sector {lba}: {e}", self.name);
+        BlockError::Io
+    }
+}
+
+impl BlockDevice for Disk {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn block_size(&self) -> usize {
+        self.sector_size
+    }
+
+    fn blocks(&self) -> u64 {
+        self.sectors
+    }
+
+    fn read(&self, lba: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        self.check_range(lba, buf.len())?;
+        let regs = &Self::controller()?.regs;
+        let mut claim = self.claim().map_err(|e| self.failed("read", lba, e))?;
+        let mut sector = lba;
+        for chunk in buf.chunks_mut(BOUNCE_LEN) {
+            let count = chunk.len() / self.sector_size;
+            let command = AtaCommand::new(ATA_READ_DMA_EXT);
+            self.transfer(&claim, regs, command, sector, count)
+                .map_err(|e| self.failed("read", sector, e))?;
+            chunk.copy_from_slice(&claim.bounce()[..chunk.len()]);
+            sector += count as u64;
+        }
+        Ok(())
+    }
+
+    fn write(&self, lba: u64, buf: &[u8]) -> Result<(), BlockError> {
+        self.check_range(lba, buf.len())?;
+        let regs = &Self::controller()?.regs;
+        let mut claim = self.claim().map_err(|e| self.failed("write", lba, e))?;
+        let mut sector = lba;
+        for chunk in buf.chunks(BOUNCE_LEN) {
+            let count = chunk.len() / self.sector_size;
+            claim.bounce()[..chunk.len()].copy_from_slice(chunk);
+            let command = AtaCommand::new(ATA_WRITE_DMA_EXT).write();
+            self.transfer(&claim, regs, command, sector, count)
+                .map_err(|e| self.failed("write", sector, e))?;
+            sector += count as u64;
+        }
+        claim
+            .execute(regs, AtaCommand::new(ATA_FLUSH_CACHE_EXT), 0)
+            .map_err(|e| self.failed("flush", lba, e))
+    }
+}
+
+/// Exclusive use of a [`Disk`]; released when dropped.
+struct Claim<'a> {
+    disk: &'a Disk,
+}
+
+impl Claim<'_> {
+    /// The bounce buffer.
+    fn bounce(&mut self) -> &mut [u8] {
+        // Safety: the claim makes us the only user of the buffer, and no
+        // command using it is in flight while we hold the slice.
+        unsafe { core::slice::from_raw_parts_mut(self.disk.bounce.as_ptr(), BOUNCE_LEN) }
+    }
+
+    /// Issue `command`, moving `len` bytes of the bounce buffer, and wait
+    /// for it to finish.
+    fn execute(&self, regs: &MmioRegion, command: AtaCommand, len: usize) -> Result<(), AhciError> {
+        let disk = self.disk;
+        disk.with_port(|port| port.issue(regs, command, disk.bounce.phys(), len));
+        let mut result = None;
+        disk.done.wait_until_timeout(
+            || {
+                result = disk.with_port(|port| port.reap(regs, ENGINE_TIMEOUT_MS));
+                result.is_some()
+            },
+            timer::ms_to_ticks(COMMAND_TIMEOUT_MS),
+        );
+        let Some(result) = result else {
+            // The port may still write the bounce buffer.
+            disk.failed.store(true, Ordering::Release);
+            return Err(AhciError::Timeout);
+        };
+        result.map_err(AhciError::Device)
+    }
+}
+
+impl Drop for Claim<'_> {
+    fn drop(&mut self) {
+        self.disk.busy.store(false, Ordering::Release);
+        self.disk.done.wake_all();
+    }
+}
+
+/// Find and set up the first controller, and register its disks.
+pub fn init() {
+    let Some(function) = pci::find_class(CLASS.0, CLASS.1, CLASS.2) else {
+        debug!("No AHCI controller");
+        return;
+    };
+    let controller = match Controller::bring_up(function) {
+        Ok(controller) => CONTROLLER.get_or_init(|| controller),
+        Err(e) => {
+            warn!("AHCI controller at {function} not usable: {e}");
+            return;
+        }
+    };
+    if let Err(e) = irq_thread::register(&IRQ) {
+        warn!("AHCI issuers will not be woken: {e}");
+    }
+    controller.register_disks();
+}
+
+/// Have issuers woken if a command finished.
+///
+/// Called from interrupt context.
+pub fn poll() {
+    IRQ.handle();
+}
+
+/// Ask the firmware to hand the controller over, if it supports that.
+fn take_ownership(function: PciFunction, regs: &MmioRegion) {
+    if regs.read32(CAP2) & CAP2_BOH == 0 {
+        return;
+    }
+    regs.write32(BOHC, regs.read32(BOHC) | BOHC_OOS);
+    if spin_until(HANDOFF_TIMEOUT_MS, || regs.read32(BOHC) & BOHC_BOS == 0).is_err() {
+        warn!("AHCI controller at {function}: firmware did not hand it over");
+    }
+}
+
+/// Sector count and logical sector size from identify data, if the disk
+/// supports 48-bit LBAs and sectors of 512 bytes up to a page.
+fn geometry(identify: &[u8]) -> Option<(u64, usize)> {
+    let word = |i: usize| u16::from_le_bytes([identify[2 * i], identify[2 * i + 1]]);
+    let lba48 = word(83) & (1 << 10) != 0;
+    let sectors = (100..104)
+        .rev()
+        .fold(0, |n, i| n << 16 | u64::from(word(i)));
+    // Word 106 is valid if bit 14 is set and bit 15 clear; bit 12 says the
+    // logical sector size in words is in words 117 and 118.
+    let sizes = word(106);
+    let sector_size = if sizes & 0xD000 == 0x5000 {
+        2 * (usize::from(word(118)) << 16 | usize::from(word(117)))
+    } else {
+        512
+    };
+    let supported =
+        sector_size.is_power_of_two() && (512..=1 << MAX_SECTOR_SHIFT).contains(&sector_size);
+    (lba48 && supported && sectors != 0).then_some((sectors, sector_size))
+}
+
+/// An ATA string of identify data, without its padding. ATA strings hold
+/// two characters per word, the first in the high byte.
+fn ata_text<'a>(field: &[u8], buf: &'a mut [u8]) -> &'a str {
+    for (chars, word) in buf.chunks_exact_mut(2).zip(field.chunks_exact(2)) {
+        chars[0] = word[1];
+        chars[1] = word[0];
+    }
+    core::str::from_utf8(&buf[..field.len()]).map_or("?", str::trim)
+}
+
+/// Top half: whether a command finished on any disk.
+fn check_completions() -> IrqReturn {
+    let Some(controller) = CONTROLLER.get() else {
+        return IrqReturn::None;
+    };
+    let completed = DISKS
+        .iter()
+        .filter_map(SyncOnceCell::get)
+        .any(|disk| disk.with_port(|port| port.has_completion(&controller.regs)));
+    if completed {
+        IrqReturn::WakeThread
+    } else {
+        IrqReturn::None
+    }
+}
+
+/// Bottom half: wake the issuers, which reap their commands themselves.
+fn wake_issuers() {
+    for disk in DISKS.iter().filter_map(SyncOnceCell::get) {
+        disk.done.wake_all();
+    }
+}
@@ -0,0 +1,332 @@
+//! # Ports
+//!
+//! Each port of an AHCI controller drives one SATA link. The port finds its
+//! commands in a command list of 32 headers, each pointing at a command
+//! table: the command FIS (frame information structure) to send, and a
+//! physical region descriptor table (PRDT) saying where the data goes.
+//! FISes the device sends back land in the port's received FIS area.
+//!
+//! A [`Port`] keeps all three in one page of DMA memory and uses command
+//! slot 0 only, so it runs one command at a time; serializing commands is
+//! up to the [driver](super). Issuing sets the slot's bit in `PxCI`, and
+//! the port clears it when the command is done. A failed command sets the
+//! task file error status instead, which stops the command engine until it
+//! is restarted.
+
+use super::AhciError;
+use crate::alloc::dma::{DmaBuffer, DmaConstraints, DmaError};
+use crate::clock;
+use crate::tsc::rdtsc;
+use core::fmt;
+use core::hint::spin_loop;
+use core::sync::atomic::{Ordering, fence};
+use kernel_alloc::mmio::MmioRegion;
+use kernel_memory_addresses::{PageSize, PhysicalAddress, Size4K};
+
+/// Port register: command list base (two halves).
+const PXCLB: u64 = 0x00;
+/// Port register: received FIS base (two halves).
+const PXFB: u64 = 0x08;
+/// Port register: interrupt status.
+const PXIS: u64 = 0x10;
+/// Port register: command and status.
+const PXCMD: u64 = 0x18;
+/// Port register: task file data, the device's status and error registers.
+const PXTFD: u64 = 0x20;
+/// Port register: signature of the attached device.
+const PXSIG: u64 = 0x24;
+/// Port register: SATA status.
+const PXSSTS: u64 = 0x28;
+/// Port register: SATA error.
+const PXSERR: u64 = 0x30;
+/// Port register: commands issued.
+const PXCI: u64 = 0x38;
+
+/// `PxIS`: task file error status.
+const IS_TFES: u32 = 1 << 30;
+
+/// `PxCMD`: start the command engine.
+const CMD_ST: u32 = 1 << 0;
+/// `PxCMD`: spin up the device.
+const CMD_SUD: u32 = 1 << 1;
+/// `PxCMD`: power on the device.
+const CMD_POD: u32 = 1 << 2;
+/// `PxCMD`: receive FISes.
+const CMD_FRE: u32 = 1 << 4;
+/// `PxCMD`: FIS receive running.
+const CMD_FR: u32 = 1 << 14;
+/// `PxCMD`: command list running.
+const CMD_CR: u32 = 1 << 15;
+
+/// `PxTFD` status: busy.
+const TFD_BSY: u32 = 1 << 7;
+/// `PxTFD` status: data request.
+const TFD_DRQ: u32 = 1 << 3;
+
+/// `PxSSTS.DET`: device present, PHY communication established.
+const SSTS_DET_PRESENT: u32 = 3;
+
+/// Signature of an ATA disk, as opposed to ATAPI drives or port
+/// multipliers.
+pub const SIGNATURE_ATA: u32 = 0x0000_0101;
+
+/// Offset of the command list in the port's memory.
+const COMMAND_LIST: usize = 0x000;
+/// Offset of the received FIS area: after the 32 command headers.
+const RECEIVED_FIS: usize = 0x400;
+/// Offset of the command table of slot 0, 128-byte aligned.
+const COMMAND_TABLE: usize = 0x500;
+/// Offset of the PRDT within a command table.
+const PRDT: usize = 0x80;
+
+/// FIS type: register, host to device.
+const FIS_REGISTER_H2D: u8 = 0x27;
+/// Register FIS flag: the FIS carries a command.
+const FIS_COMMAND: u8 = 1 << 7;
+/// Length of a register FIS, in double words.
+const FIS_DWORDS: u32 = 5;
+/// Device register: LBA addressing.
+const DEVICE_LBA: u8 = 1 << 6;
+
+/// Command header flag: the data goes to the device.
+const HEADER_WRITE: u32 = 1 << 6;
+
+/// An ATA command, before it becomes a register FIS.
+#[derive(Debug, Copy, Clone)]
+pub struct AtaCommand {
+    command: u8,
+    lba: u64,
+    count: u16,
+    write: bool,
+}
+
+impl AtaCommand {
+    pub const fn new(command: u8) -> Self {
+        Self {
+            command,
+            lba: 0,
+            count: 0,
+            write: false,
+        }
+    }
+
+    /// `count` sectors starting at `lba`, for 48-bit commands; a count of 0
+    /// means 65536.
+    pub const fn sectors(mut self, lba: u64, count: u16) -> Self {
+        self.lba = lba;
+        self.count = count;
+        self
+    }
+
+    /// The data goes to the device.
+    pub const fn write(mut self) -> Self {
+        self.write = true;
+        self
+    }
+
+    /// The register FIS carrying the command.
+    #[allow(clippy::cast_possible_truncation)]
+    const fn fis(&self) -> [u8; FIS_DWORDS as usize * 4] {
+        let lba = self.lba.to_le_bytes();
+        let count = self.count.to_le_bytes();
+        let mut fis = [0; FIS_DWORDS as usize * 4];
+        fis[0] = FIS_REGISTER_H2D;
+        fis[1] = FIS_COMMAND;
+        fis[2] = self.command;
+        fis[4] = lba[0];
+        fis[5] = lba[1];
+        fis[6] = lba[2];
+        fis[7] = DEVICE_LBA;
+        fis[8] = lba[3];
+        fis[9] = lba[4];
+        fis[10] = lba[5];
+        fis[12] = count[0];
+        fis[13] = count[1];
+        fis
+    }
+}
+
+/// The status and error registers of a failed command.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct TaskFile {
+    pub status: u8,
+    pub error: u8,
+}
+
+impl fmt::Display for TaskFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "status {:#04x}, error {:#04x}", self.status, self.error)
+    }
+}
+
+/// A port and its command memory; see the [module docs](self).
+#[derive(Debug)]
+pub struct Port {
+    /// Offset of the port's registers.
+    base: u64,
+    memory: DmaBuffer,
+    /// A command is in slot 0.
+    issued: bool,
+}
+
+impl Port {
+    pub fn new(base: u64, constraints: DmaConstraints) -> Result<Self, DmaError> {
+        #[allow(clippy::cast_possible_truncation)]
+        let len = Size4K::SIZE as usize;
+        Ok(Self {
+            base,
+            memory: DmaBuffer::alloc(len, constraints.with_align(Size4K::SIZE))?,
+            issued: false,
+        })
+    }
+
+    /// Power and spin up the device; returns whether its link came up
+    /// within `timeout_ms`.
+    pub fn power_up(&self, regs: &MmioRegion, timeout_ms: u64) -> bool {
+        self.write(regs, PXCMD, self.read(regs, PXCMD) | CMD_SUD | CMD_POD);
+        spin_until(timeout_ms, || {
+            self.read(regs, PXSSTS) & 0xF == SSTS_DET_PRESENT
+        })
+        .is_ok()
+    }
+
+    /// Hand the port its memory and start the command engine once the
+    /// device is ready; returns the device's signature.
+    pub fn start(&self, regs: &MmioRegion, timeout_ms: u64) -> Result<u32, AhciError> {
+        self.stop(regs, timeout_ms)?;
+        let list = self.memory.phys_at(COMMAND_LIST).as_u64();
+        let fis = self.memory.phys_at(RECEIVED_FIS).as_u64();
+        #[allow(clippy::cast_possible_truncation)]
+        for (register, address) in [(PXCLB, list), (PXFB, fis)] {
+            self.write(regs, register, address as u32);
+            self.write(regs, register + 4, (address >> 32) as u32);
+        }
+        self.write(regs, PXSERR, u32::MAX);
+        self.write(regs, PXIS, u32::MAX);
+
+        self.write(regs, PXCMD, self.read(regs, PXCMD) | CMD_FRE);
+        spin_until(timeout_ms, || {
+            self.read(regs, PXTFD) & (TFD_BSY | TFD_DRQ) == 0
+        })?;
+        let signature = self.read(regs, PXSIG);
+        self.write(regs, PXCMD, self.read(regs, PXCMD) | CMD_ST);
+        Ok(signature)
+    }
+
+    /// Stop the command engine and FIS reception, so the port no longer
+    /// touches its memory.
+    pub fn stop(&self, regs: &MmioRegion, timeout_ms: u64) -> Result<(), AhciError> {
+        self.write(regs, PXCMD, self.read(regs, PXCMD) & !CMD_ST);
+        spin_until(timeout_ms, || self.read(regs, PXCMD) & CMD_CR == 0)?;
+        self.write(regs, PXCMD, self.read(regs, PXCMD) & !CMD_FRE);
+        spin_until(timeout_ms, || self.read(regs, PXCMD) & CMD_FR == 0)
+    }
+
+    /// Issue `command` in slot 0, moving `len` bytes at `data`.
+    ///
+    /// # Panics
+    /// If a command is in flight already.
+    pub fn issue(
+        &mut self,
+        regs: &MmioRegion,
+        command: AtaCommand,
+        data: PhysicalAddress,
+        len: usize,
+    ) {
+        assert!(
+            !self.issued,
+            "AHCI port busy; command {:#04x} issued",
+            command.command
+        );
+        let table = self.memory.phys_at(COMMAND_TABLE).as_u64();
+        let fis = command.fis();
+        self.memory[COMMAND_TABLE..][..fis.len()].copy_from_slice(&fis);
+
+        #[allow(clippy::cast_possible_truncation)]
+        let prd = [
+            data.as_u64() as u32,
+            (data.as_u64() >> 32) as u32,
+            0,
+            // Byte count, minus one.
+            len.saturating_sub(1) as u32,
+        ];
+        self.set_dwords(COMMAND_TABLE + PRDT, &prd);
+
+        let regions = u32::from(len > 0);
+        let write = if command.write { HEADER_WRITE } else { 0 };
+        #[allow(clippy::cast_possible_truncation)]
+        let header = [
+            FIS_DWORDS | write | regions << 16,
+            // Bytes transferred, counted by the port.
+            0,
+            table as u32,
+            (table >> 32) as u32,
+        ];
+        self.set_dwords(COMMAND_LIST, &header);
+
+        fence(Ordering::SeqCst);
+        self.write(regs, PXCI, 1);
+        self.issued = true;
+    }
+
+    /// Whether the command in flight finished, one way or the other.
+    pub fn has_completion(&self, regs: &MmioRegion) -> bool {
+        self.issued && (self.read(regs, PXCI) & 1 == 0 || self.read(regs, PXIS) & IS_TFES != 0)
+    }
+
+    /// Take the outcome of the command in flight, if it finished. After an
+    /// error, the command engine is restarted.
+    pub fn reap(&mut self, regs: &MmioRegion, timeout_ms: u64) -> Option<Result<(), TaskFile>> {
+        if !self.has_completion(regs) {
+            return None;
+        }
+        self.issued = false;
+        let status = self.read(regs, PXIS);
+        self.write(regs, PXIS, status);
+        if status & IS_TFES == 0 {
+            fence(Ordering::Acquire);
+            return Some(Ok(()));
+        }
+
+        let task_file = self.read(regs, PXTFD);
+        // Clearing `ST` drops the failed command; a port that does not stop
+        // fails the next command by timing out.
+        self.write(regs, PXCMD, self.read(regs, PXCMD) & !CMD_ST);
+        if spin_until(timeout_ms, || self.read(regs, PXCMD) & CMD_CR == 0).is_ok() {
+            self.write(regs, PXSERR, u32::MAX);
+            self.write(regs, PXCMD, self.read(regs, PXCMD) | CMD_ST);
+        }
+        #[allow(clippy::cast_possible_truncation)]
+        Some(Err(TaskFile {
+            status: task_file as u8,
+            error: (task_file >> 8) as u8,
+        }))
+    }
+
+    fn set_dwords(&mut self, offset: usize, dwords: &[u32]) {
+        for (i, dword) in dwords.iter().enumerate() {
+            self.memory[offset + 4 * i..][..4].copy_from_slice(&dword.to_le_bytes());
+        }
+    }
+
+    fn read(&self, regs: &MmioRegion, register: u64) -> u32 {
+        regs.read32(self.base + register)
+    }
+
+    fn write(&self, regs: &MmioRegion, register: u64, value: u32) {
+        regs.write32(self.base + register, value);
+    }
+}
+
+/// Spin until `done` holds, for at most `timeout_ms`. Ports are touched
+/// with their lock held, so this counts TSC cycles rather than sleeping.
+pub fn spin_until(timeout_ms: u64, mut done: impl FnMut() -> bool) -> Result<(), AhciError> {
+    let deadline = rdtsc() + clock::tsc_hz() / 1000 * timeout_ms;
+    while !done() {
+        if rdtsc() >= deadline {
+            return Err(AhciError::Timeout);
+        }
+        spin_loop();
+    }
+    Ok(())
+}
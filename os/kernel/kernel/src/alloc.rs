@@ -28,7 +28,11 @@
 //! Memory management is initialized in two phases:
 //!
 //! 1. **Physical Allocator Setup**: [`init_physical_memory_allocator_once`] creates
-//!    the bitmap allocator in a dedicated BSS section (`.bss.pmm`)
+//!    the bitmap allocator in the [early boot allocator](crate::boot_alloc)'s arena,
+//!    retires the arena and restricts the allocator to the usable ranges of the
+//!    [`MemoryMap`], plus the unused rest of the arena. It then carves the per-frame
+//!    metadata ([`frame_table`]) out of physical memory, sized to the highest
+//!    usable address
 //! 2. **VMM Initialization**: [`init_kernel_vmm`] combines the allocator and mapper
 //!    into a globally accessible kernel VMM instance
 //!
@@ -39,6 +43,47 @@
 //! * [`with_kernel_vmm`] - Execute operations with automatic VMM lifecycle management
 //! * [`try_with_kernel_vmm`] - Execute fallible operations with configurable TLB flushing
 //!
+//! Frame allocator statistics are readable without the allocator lock through
+//! [`frame_stats`]; [`on_low_memory`] registers callbacks for low free-frame
+//! counts.
+//!
+//! Both operate on the **currently active** address space. To populate a different
+//! one (e.g. a freshly created process), create it with [`create_address_space`] and
+//! run the VMM calls inside [`with_address_space`]. Once it is no longer needed,
+//! [`destroy_address_space`] returns its frames to the allocator.
+//!
+//! ## Kernel half
+//!
+//! Every address space is registered from creation to destruction (see
+//! [`RootRegistry`]). When a kernel-half mapping fills a PML4 slot that was
+//! empty, [`with_kernel_vmm`] and [`try_with_kernel_vmm`] copy the new entry
+//! into every registered address space before they return, so a mapping made
+//! while one process is active is visible from all others.
+//! [`sync_kernel_mappings`] does the same on demand.
+//!
+//! Once the boot-time kernel mappings are in place,
+//! [`promote_kernel_mappings`] merges runs of small pages among them into
+//! large ones to take pressure off the TLB.
+//!
+//! ## Copy-on-write
+//!
+//! [`fork_address_space`] shares the user pages of the current address space
+//! with a new one. Frames mapped by more than one address space are counted in
+//! the [`frame_table`]; [`resolve_cow_fault`] gives the writer its own copy, and
+//! [`destroy_address_space`] only frees a frame with its last owner.
+//!
+//! ## Address space switches
+//!
+//! If the CPU supports PCIDs, [`init_pcid`] enables them and
+//! [`switch_address_space`] tags every address space with its own PCID (see
+//! [`kernel_vmem::pcid`]). Switching then keeps the TLB entries of all address
+//! spaces instead of flushing them on every CR3 load. Without PCID support it
+//! falls back to a plain CR3 load.
+//!
+//! The kept entries include the kernel half, so a flush after changing it
+//! must reach every PCID: [`try_with_kernel_vmm`] uses `INVPCID` where the
+//! CPU has it and toggles `CR4.PGE` otherwise.
+//!
 //! ## Safety
 //!
 //! This module contains extensive unsafe code for:
@@ -50,50 +95,386 @@
 //! All unsafe operations are carefully isolated behind safe abstractions and
 //! documented for their safety requirements.
 //!
+//! ## Device memory
+//!
+//! The [`mmio`] submodule maps device register blocks uncached and hands out
+//! bounds-checked [`MmioRegion`](kernel_alloc::mmio::MmioRegion) handles that
+//! unmap themselves on drop.
+//!
+//! ## Heap
+//!
+//! The [`heap`] submodule installs the kernel's global allocator, a slab
+//! heap with per-CPU caches whose large allocations are mapped through the
+//! kernel VMM.
+//!
+//! ## Fault injection
+//!
+//! With the `fault-inject` feature, the frame allocator fails allocations on
+//! request; see the [`fault_inject`] submodule.
+//!
+//! ## Poisoning
+//!
+//! With the `poison` feature, freed frames are filled with a pattern that is
+//! checked when they are handed out again, and a sample of them loses its
+//! direct map alias for a while; see the [`poison`] submodule.
+//!
 //! ## Debugging
 //!
 //! The [`debug`] submodule provides utilities for inspecting page table state,
 //! walking virtual address translations, and debugging memory management issues.
+//! The [`audit`] submodule checks the finished kernel page tables for W^X and
+//! user/kernel isolation at boot.
+//!
+//! ## Device memory
+//!
+//! [`mmio`] maps device registers; [`dma`] allocates buffers that devices
+//! read and write.
 
+pub mod audit;
 pub mod debug;
+pub mod dma;
+#[cfg(feature = "fault-inject")]
+pub mod fault_inject;
+pub mod heap;
+pub mod mmio;
+#[cfg(feature = "poison")]
+pub mod poison;
 
+use crate::boot_alloc::{BootAlloc, BootAllocError};
+use crate::cpuid::{CpuidRanges, Leaf01h, Leaf07h};
+use crate::hhdm;
+use crate::memmap::{MemoryMap, PhysRange};
+use crate::per_cpu::PerCpu;
+use crate::process::MAX_PROCESSES;
 use core::mem::MaybeUninit;
-use kernel_alloc::frame_alloc::BitmapFrameAlloc;
+use core::sync::atomic::{AtomicU64, Ordering};
+#[cfg(feature = "fault-inject")]
+use kernel_alloc::fault_inject::FaultyFrameAlloc;
+use kernel_alloc::frame_alloc::{
+    BitmapFrameAlloc, DEFAULT_MANAGED, FrameCounters, FrameStats, LowMemoryCallback, TooManyWatches,
+};
+use kernel_alloc::frame_info::{FrameInfo, FrameOwner, FrameTable};
 use kernel_alloc::phys_mapper::HhdmPhysMapper;
-use kernel_alloc::vmm::Vmm;
-use kernel_sync::{RawSpin, SpinMutex, SyncOnceCell};
-use kernel_vmem::{PhysFrameAlloc, PhysMapper};
+#[cfg(feature = "poison")]
+use kernel_alloc::poison::PoisonFrameAlloc;
+use kernel_alloc::vmm::{AllocationTarget, Vmm};
+use kernel_info::memory::{HHDM_BASE, HHDM_SIZE};
+use kernel_memory_addresses::{PageSize, PhysicalAddress, PhysicalPage, Size4K, VirtualAddress};
+use kernel_registers::cr3::Cr3;
+use kernel_registers::cr4::Cr4;
+use kernel_registers::{LoadRegisterUnsafe, StoreRegisterUnsafe};
+use kernel_sync::{IrqGuard, RawSpin, SpinMutex, SyncOnceCell};
+use kernel_vmem::address_space::{
+    AddressSpaceError, AddressSpaceMapOneError, ClonePolicy, PromotionStats, RootPage,
+    RootRegistry, TeardownStats, kernel_pml4_generation,
+};
+use kernel_vmem::pcid::{InvpcidKind, Pcid, PcidAssignment, PcidTag, invpcid, load_cr3};
+use kernel_vmem::{
+    AddressSpace, PhysFrameAlloc, PhysMapper, VirtualMemoryPageBits, invalidate_tlb_page,
+    read_cr3_phys,
+};
+use log::{debug, warn};
+
+/// The bitmap allocator, poisoning freed frames if enabled.
+#[cfg(not(feature = "poison"))]
+pub type PoisonLayer = BitmapFrameAlloc;
+
+/// The bitmap allocator, poisoning freed frames as [`poison::POISON`] tracks.
+#[cfg(feature = "poison")]
+pub type PoisonLayer = PoisonFrameAlloc<'static, BitmapFrameAlloc, HhdmPhysMapper>;
 
-pub type KernelVmm<'alloc> = Vmm<'alloc, HhdmPhysMapper, BitmapFrameAlloc>;
+/// The kernel's physical frame allocator.
+#[cfg(not(feature = "fault-inject"))]
+pub type KernelFrameAlloc = PoisonLayer;
+
+/// The kernel's physical frame allocator, failing as [`fault_inject::FAULTS`] decides.
+#[cfg(feature = "fault-inject")]
+pub type KernelFrameAlloc = FaultyFrameAlloc<'static, PoisonLayer>;
+
+pub type KernelVmm<'alloc> = Vmm<'alloc, HhdmPhysMapper, KernelFrameAlloc>;
 
 pub struct KernelVm<M: PhysMapper, A: PhysFrameAlloc + 'static> {
     pub mapper: M,
     pub alloc: SpinMutex<&'static mut A>,
 }
 
-#[unsafe(link_section = ".bss.pmm")]
-static mut PMM: MaybeUninit<BitmapFrameAlloc> = MaybeUninit::uninit();
+/// Lock-free mirror of the frame allocator statistics.
+static FRAME_COUNTERS: FrameCounters = FrameCounters::new();
 
+/// Words of bitmap storage for the first [`DEFAULT_MANAGED`] bytes.
+const DEFAULT_STORAGE_WORDS: usize = BitmapFrameAlloc::storage_words(DEFAULT_MANAGED);
+
+/// Create the physical frame allocator in `arena`, then retire the arena.
+///
+/// With a memory map, the allocator covers every usable range the HHDM can
+/// reach, and its bitmaps are carved out of one of them; only the ranges the
+/// loader's HHDM maps are handed out yet, [`hhdm::init`] adds the others
+/// once it mapped them. Without a map, the allocator covers the first
+/// [`DEFAULT_MANAGED`] bytes and keeps its bitmaps in the arena. Either way,
+/// the part of the arena nothing was allocated from becomes usable as well.
+///
+/// # Errors
+/// If the allocator does not fit into the arena.
 #[doc(alias = "init_pmm_once")]
-#[allow(static_mut_refs)]
-pub unsafe fn init_physical_memory_allocator_once() -> &'static mut BitmapFrameAlloc {
-    // Construct in place; allowed because we're in early single-core init.
-    unsafe {
-        PMM.write(BitmapFrameAlloc::new());
-        &mut *PMM.as_mut_ptr()
+#[allow(clippy::cast_possible_truncation)]
+pub unsafe fn init_physical_memory_allocator_once(
+    map: Option<&MemoryMap>,
+    mut arena: BootAlloc,
+) -> Result<&'static mut KernelFrameAlloc, BootAllocError> {
+    let (storage, bitmaps) = if let Some((storage, bitmaps)) = map.and_then(bitmap_storage) {
+        (storage, Some(bitmaps))
+    } else {
+        let storage = arena.alloc_uninit::<[u64; DEFAULT_STORAGE_WORDS]>()?;
+        // Safety: zeroes are valid words; they are too large for the stack.
+        let storage = unsafe {
+            storage.as_mut_ptr().write_bytes(0, 1);
+            storage.assume_init_mut()
+        };
+        (&mut storage[..], None)
+    };
+    let pmm = BitmapFrameAlloc::new(storage).with_counters(&FRAME_COUNTERS);
+    #[cfg(feature = "poison")]
+    let pmm = PoisonFrameAlloc::new(pmm, &HhdmPhysMapper, &poison::POISON);
+    #[cfg(feature = "fault-inject")]
+    let pmm = FaultyFrameAlloc::new(pmm, &fault_inject::FAULTS);
+    let pmm = arena.alloc(pmm)?;
+
+    if let Some(map) = map {
+        pmm.reserve_all();
+        for range in map.ranges() {
+            pmm.add_usable_range(
+                PhysicalAddress::new(range.start),
+                PhysicalAddress::new(range.end.min(HHDM_SIZE)),
+            );
+        }
+        if let Some(bitmaps) = bitmaps {
+            for frame in bitmaps.start / Size4K::SIZE..bitmaps.end / Size4K::SIZE {
+                pmm.mark_used(frame as usize);
+            }
+            debug!("Frame bitmaps: {} KiB at {bitmaps}", bitmaps.len() / 1024);
+        }
+
+        let managed = pmm.manageable_size();
+        let ignored: u64 = map
+            .ranges()
+            .iter()
+            .map(|range| range.end.saturating_sub(range.start.max(managed)))
+            .sum();
+        if ignored > 0 {
+            warn!(
+                "Ignoring {} MiB of RAM above {managed:#x}, which the HHDM cannot reach",
+                ignored / 1024 / 1024
+            );
+        }
+    }
+
+    let (used, unused) = arena.retire();
+    // Without a memory map, the arena counts as usable; keep the allocator.
+    let used_end = used.end.min(pmm.manageable_size());
+    for frame in used.start / Size4K::SIZE..used_end / Size4K::SIZE {
+        pmm.mark_used(frame as usize);
     }
+    pmm.add_usable_range(
+        PhysicalAddress::new(unused.start),
+        PhysicalAddress::new(unused.end),
+    );
+    debug!(
+        "Boot arena: {} KiB used at {used}, {} KiB handed to the frame allocator",
+        used.len() / 1024,
+        unused.len() / 1024
+    );
+
+    init_frame_table(pmm, map);
+    Ok(pmm)
+}
+
+/// Storage for the bitmaps of a frame allocator covering the usable ranges
+/// of `map` below [`hhdm::WINDOW`], taken from the top of the highest usable
+/// range in the loader's HHDM with room for it; also returns where it is.
+#[allow(clippy::cast_possible_truncation)]
+fn bitmap_storage(map: &MemoryMap) -> Option<(&'static mut [u64], PhysRange)> {
+    let end = map.ranges().iter().map(|range| range.end).max()?;
+    let words = BitmapFrameAlloc::storage_words(end.min(hhdm::WINDOW));
+    let bytes = (words as u64 * 8).next_multiple_of(Size4K::SIZE);
+    let bitmaps = map.ranges().iter().rev().find_map(|range| {
+        let top = range.end.min(HHDM_SIZE) & !(Size4K::SIZE - 1);
+        let start = top.checked_sub(bytes)?;
+        (start >= range.start).then_some(PhysRange {
+            start,
+            end: start + bytes,
+        })
+    })?;
+    // Safety: usable RAM nothing refers to yet, inside the loader's HHDM.
+    let storage = unsafe {
+        core::slice::from_raw_parts_mut((HHDM_BASE + bitmaps.start).as_u64() as *mut u64, words)
+    };
+    Some((storage, bitmaps))
 }
 
-static KVM: SyncOnceCell<KernelVm<HhdmPhysMapper, BitmapFrameAlloc>> = SyncOnceCell::new();
+/// Per-frame metadata, kept in sync by the frame allocator.
+static FRAME_TABLE: SyncOnceCell<FrameTable> = SyncOnceCell::new();
+
+/// Size the frame table from `map`, carve it out of `pmm` and attach it.
+#[allow(clippy::cast_possible_truncation)]
+fn init_frame_table(pmm: &mut BitmapFrameAlloc, map: Option<&MemoryMap>) {
+    let managed = PhysicalAddress::new(pmm.manageable_size());
+    let end = map
+        .and_then(|map| map.ranges().iter().map(|range| range.end).max())
+        .map_or(managed, |end| PhysicalAddress::new(end).min(managed));
+    let entries = FrameTable::entries_for(end);
+    let frames = FrameTable::bytes_for(entries).div_ceil(Size4K::SIZE) as usize;
+    let storage = pmm
+        .alloc_contiguous_4k(frames)
+        .expect("no room for the frame table");
+
+    let table = FRAME_TABLE.get_or_init(|| {
+        // Safety: the frames are ours and reachable through the HHDM.
+        let records = unsafe {
+            let first = HhdmPhysMapper.phys_to_mut::<MaybeUninit<FrameInfo>>(storage.base());
+            core::slice::from_raw_parts_mut(first, entries)
+        };
+        FrameTable::new(records)
+    });
+    pmm.attach_frame_table(table);
+    for i in 0..frames as u64 {
+        let frame = PhysicalPage::from_addr(storage.base() + i * Size4K::SIZE);
+        table.set_owner(frame, FrameOwner::FrameTable);
+    }
+    debug!("Frame table: {entries} records in {frames} frames at {storage}");
+}
+
+/// Per-frame metadata: allocator state, owners and reference counts.
+///
+/// # Panics
+/// Before [`init_physical_memory_allocator_once`].
+pub fn frame_table() -> &'static FrameTable {
+    FRAME_TABLE.get().expect("frame table not initialized")
+}
+
+/// The kernel VMM. Its frame allocator is only locked with interrupts
+/// disabled, since the [heap](heap) refills from interrupt handlers too.
+static KVM: SyncOnceCell<KernelVm<HhdmPhysMapper, KernelFrameAlloc>> = SyncOnceCell::new();
+
+/// Live address spaces: the boot one, one per process, and a replacement
+/// being built by `exec` for each.
+const MAX_ADDRESS_SPACES: usize = 1 + 2 * MAX_PROCESSES;
+
+/// Every live address space. Only locked with the frame allocator held, which
+/// also serializes kernel-half mappings against creating address spaces.
+static ADDRESS_SPACES: SpinMutex<RootRegistry<MAX_ADDRESS_SPACES>> =
+    SpinMutex::new(RootRegistry::new());
+
+/// The [`kernel_pml4_generation`] all registered address spaces are in sync
+/// with.
+static SYNCED_GENERATION: AtomicU64 = AtomicU64::new(0);
 
 /// Call once in very early boot.
-pub unsafe fn init_kernel_vmm(mapper: HhdmPhysMapper, alloc: &'static mut BitmapFrameAlloc) {
+pub unsafe fn init_kernel_vmm(mapper: HhdmPhysMapper, alloc: &'static mut KernelFrameAlloc) {
     let _ = KVM.get_or_init(|| KernelVm {
         mapper,
         alloc: SpinMutex::from_raw(RawSpin::new(), alloc),
     });
+
+    // The boot address space is the one all others copy their kernel half
+    // from, and the one kernel threads run in.
+    let boot = PhysicalPage::from_addr(unsafe { read_cr3_phys() });
+    let _irq = IrqGuard::new();
+    let kvm = KVM.get().expect("Kernel VM not initialized");
+    let _alloc = kvm.alloc.lock();
+    ADDRESS_SPACES
+        .lock()
+        .register(boot)
+        .expect("the address space registry has room for the boot one");
+    SYNCED_GENERATION.store(kernel_pml4_generation(), Ordering::Release);
+}
+
+/// Copy kernel-half PML4 entries from the current address space into every
+/// registered one, if a mapping filled an empty slot since the last sync.
+/// Returns the number of entries copied. The caller holds the frame
+/// allocator lock.
+fn sync_registered_roots(mapper: &HhdmPhysMapper) -> usize {
+    let generation = kernel_pml4_generation();
+    if SYNCED_GENERATION.load(Ordering::Acquire) == generation {
+        return 0;
+    }
+
+    // Safety: CR3 points to a valid PML4; the kernel half of the current
+    // address space is the one the mapping went into.
+    let current = unsafe { AddressSpace::from_current(mapper) };
+    let copied = ADDRESS_SPACES
+        .lock()
+        .iter()
+        .map(|root| AddressSpace::from_root(mapper, root).sync_upper_half_from(&current))
+        .sum();
+    SYNCED_GENERATION.store(generation, Ordering::Release);
+    debug!("Synced {copied} kernel PML4 entries into all address spaces");
+    copied
+}
+
+/// Bring the kernel half of every address space up to date with the current
+/// one; returns the number of PML4 entries copied.
+///
+/// The VMM entry points do this after every mapping that needs it, so this
+/// only matters for mappings made around them.
+#[allow(dead_code)]
+pub fn sync_kernel_mappings() -> usize {
+    let _irq = IrqGuard::new();
+    let kvm = KVM.get().expect("Kernel VM not initialized");
+    let _alloc = kvm.alloc.lock();
+    sync_registered_roots(&kvm.mapper)
+}
+
+/// Merge runs of small kernel pages in `[va .. va+len)` into 2 MiB and 1 GiB
+/// pages where memory and flags allow it, freeing the tables they leave
+/// behind; see [`AddressSpace::promote_large_pages`].
+///
+/// Flushes this CPU's TLB, global entries included, before the allocator
+/// lock is released, so the freed tables cannot be reused while the
+/// paging-structure caches still point into them. Other CPUs are not told;
+/// call this before they start.
+pub fn promote_kernel_mappings(va: VirtualAddress, len: u64) -> PromotionStats {
+    assert!(AllocationTarget::Kernel.matches(va));
+    let _irq = IrqGuard::new();
+    let kvm = KVM.get().expect("Kernel VM not initialized");
+    let mut alloc = kvm.alloc.lock();
+
+    // Safety: CR3 points to a valid PML4; mapper is valid for kernel lifetime.
+    let mut vmm = unsafe { Vmm::from_current(&kvm.mapper, *alloc) };
+    let stats = vmm.promote_large_pages(va, len);
+    if stats.tables_freed() > 0 {
+        unsafe { flush_tlb_global() };
+    }
+    stats
+}
+
+/// Physical frame statistics; does not take the allocator lock.
+pub fn frame_stats() -> FrameStats {
+    FRAME_COUNTERS.snapshot()
 }
 
+/// Run `callback` whenever fewer than `threshold` frames are free.
+///
+/// The callback runs with the allocator locked and must not allocate or free
+/// frames.
+pub fn on_low_memory(threshold: usize, callback: LowMemoryCallback) -> Result<(), TooManyWatches> {
+    let _irq = IrqGuard::new();
+    let kvm = KVM.get().expect("Kernel VM not initialized");
+    kvm.alloc.lock().on_low_memory(threshold, callback)
+}
+
+/// Run `f` with the frame allocator locked, e.g. to allocate frames that are
+/// not mapped through the VMM.
+pub fn with_kernel_frame_alloc<R>(f: impl FnOnce(&mut KernelFrameAlloc) -> R) -> R {
+    let _irq = IrqGuard::new();
+    let kvm = KVM.get().expect("Kernel VM not initialized");
+    let mut alloc = kvm.alloc.lock();
+    f(*alloc)
+}
+
+/// When [`try_with_kernel_vmm`] flushes this CPU's TLB.
+///
+/// The kernel half is shared by every address space, so with PCIDs the
+/// flush covers the entries of all of them, not just the loaded one's.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
 #[allow(dead_code)]
 pub enum FlushTlb {
@@ -104,12 +485,14 @@ pub enum FlushTlb {
 
 #[inline]
 pub fn with_kernel_vmm(f: impl FnOnce(&mut KernelVmm)) {
+    let _irq = IrqGuard::new();
     let kvm = KVM.get().expect("Kernel VM not initialized");
     let mut alloc = kvm.alloc.lock();
 
     // Safety: CR3 points to a valid PML4; mapper is valid for kernel lifetime.
     let mut vmm = unsafe { Vmm::from_current(&kvm.mapper, *alloc) };
     f(&mut vmm);
+    sync_registered_roots(&kvm.mapper);
 }
 
 #[inline]
@@ -117,27 +500,284 @@ pub fn try_with_kernel_vmm<R, E>(
     flush: FlushTlb,
     f: impl FnOnce(&mut KernelVmm) -> Result<R, E>,
 ) -> Result<R, E> {
+    let _irq = IrqGuard::new();
     let kvm = KVM.get().expect("Kernel VM not initialized");
     let mut alloc = kvm.alloc.lock();
 
     // Safety: CR3 points to a valid PML4; mapper is valid for kernel lifetime.
     let mut vmm = unsafe { Vmm::from_current(&kvm.mapper, *alloc) };
-    match f(&mut vmm) {
+    let result = f(&mut vmm);
+    sync_registered_roots(&kvm.mapper);
+    match result {
         Ok(r) => {
             if matches!(flush, FlushTlb::Always | FlushTlb::OnSuccess) {
-                unsafe {
-                    vmm.local_tlb_flush_all();
-                }
+                unsafe { flush_kernel_tlb(&vmm) };
             }
             Ok(r)
         }
         Err(e) => {
             if matches!(flush, FlushTlb::Always) {
-                unsafe {
-                    vmm.local_tlb_flush_all();
-                }
+                unsafe { flush_kernel_tlb(&vmm) };
             }
             Err(e)
         }
     }
 }
+
+/// Flush this CPU's TLB after a change to the kernel half.
+///
+/// Reloading CR3 only drops the loaded PCID's entries; the ones cached for
+/// other address spaces would survive until their PCIDs roll over.
+///
+/// # Safety
+/// Must run at CPL0.
+unsafe fn flush_kernel_tlb(vmm: &KernelVmm) {
+    match PCID_MODE.get() {
+        Some(PcidMode::Enabled { invpcid: true }) => unsafe {
+            invpcid(InvpcidKind::AllContexts, Pcid::NONE, 0);
+        },
+        Some(PcidMode::Enabled { invpcid: false }) => unsafe { flush_tlb_global() },
+        Some(PcidMode::Disabled) | None => unsafe { vmm.local_tlb_flush_all() },
+    }
+}
+
+/// Create a new address space that shares the kernel half of the current one.
+///
+/// The lower (user) half starts out empty.
+pub fn create_address_space() -> Result<RootPage, AddressSpaceError> {
+    let _irq = IrqGuard::new();
+    let kvm = KVM.get().expect("Kernel VM not initialized");
+    let mut alloc = kvm.alloc.lock();
+    let aspace = AddressSpace::new(&kvm.mapper, *alloc)?;
+    let root = aspace.root_page();
+    if ADDRESS_SPACES.lock().register(root).is_err() {
+        warn!("More than {MAX_ADDRESS_SPACES} live address spaces");
+        alloc.free_4k(root);
+        return Err(AddressSpaceError::TooMany);
+    }
+    Ok(root)
+}
+
+/// Free the address space `root` created by [`create_address_space`]: its
+/// page tables, the PML4 and every user frame `owns_frame` claims and no
+/// other address space still shares (see [`AddressSpace::destroy`] and
+/// the [`frame_table`]).
+///
+/// # Safety
+/// `root` must not be active on any CPU and must not be used again.
+pub unsafe fn destroy_address_space(
+    root: RootPage,
+    mut owns_frame: impl FnMut(VirtualAddress) -> bool,
+) -> TeardownStats {
+    let kvm = KVM.get().expect("Kernel VM not initialized");
+    debug_assert_ne!(
+        unsafe { read_cr3_phys() },
+        root.base(),
+        "destroying the active address space"
+    );
+    let _irq = IrqGuard::new();
+    let mut alloc = kvm.alloc.lock();
+    ADDRESS_SPACES.lock().unregister(root);
+    unsafe {
+        AddressSpace::from_root(&kvm.mapper, root).destroy(*alloc, |va, frame| {
+            owns_frame(va) && frame_table().release(frame)
+        })
+    }
+}
+
+/// Share the user half of the **current** address space with `child`,
+/// copy-on-write (see [`AddressSpace::cow_clone_into`]).
+///
+/// Pages `policy` calls [copy-on-write](ClonePolicy::CopyOnWrite) become
+/// read-only in both address spaces and gain an owner in the [`frame_table`];
+/// the first write to one of them is resolved by [`resolve_cow_fault`].
+/// [Shared](ClonePolicy::Shared) pages gain an owner and stay writable.
+/// Returns the number of pages that gained an owner.
+///
+/// # Errors
+/// Out of memory while building `child`'s page tables; `child` must then be
+/// released with [`destroy_address_space`].
+pub fn fork_address_space(
+    child: RootPage,
+    policy: impl FnMut(VirtualAddress) -> ClonePolicy,
+) -> Result<usize, AddressSpaceMapOneError> {
+    let _irq = IrqGuard::new();
+    let kvm = KVM.get().expect("Kernel VM not initialized");
+    let mut alloc = kvm.alloc.lock();
+
+    // Safety: CR3 points to a valid PML4; mapper is valid for kernel lifetime.
+    let parent = unsafe { AddressSpace::from_current(&kvm.mapper) };
+    let child = AddressSpace::from_root(&kvm.mapper, child);
+    let shared = parent.cow_clone_into(
+        &child,
+        *alloc,
+        VirtualMemoryPageBits::user_table_wb_exec(),
+        policy,
+        |frame| frame_table().share(frame),
+    );
+
+    // Writable parent pages just became read-only.
+    unsafe { Cr3::load_unsafe().store_unsafe() };
+    shared
+}
+
+/// Resolve a write fault at `va` on a copy-on-write page of the current
+/// address space.
+///
+/// A frame that is still shared is copied into a fresh private frame; the
+/// last owner simply takes it over. Either way `va` is writable afterwards.
+/// Returns `false` if `va` is not a copy-on-write page, or if the fault can
+/// not be resolved right now (out of memory, or the allocator is locked by
+/// the faulting context).
+pub fn resolve_cow_fault(va: VirtualAddress) -> bool {
+    let Some(kvm) = KVM.get() else {
+        return false;
+    };
+    let Some(mut alloc) = kvm.alloc.try_lock() else {
+        return false;
+    };
+
+    // Safety: CR3 points to a valid PML4; mapper is valid for kernel lifetime.
+    let space = unsafe { AddressSpace::from_current(&kvm.mapper) };
+    let Some(old) = space.cow_page(va) else {
+        return false;
+    };
+
+    let frame = if frame_table().is_shared(old) {
+        let Some(new) = alloc.alloc_4k() else {
+            return false;
+        };
+        unsafe {
+            let src = kvm.mapper.phys_to_mut::<[u8; 4096]>(old.base());
+            let dst = kvm.mapper.phys_to_mut::<[u8; 4096]>(new.base());
+            dst.copy_from_slice(src);
+        }
+        // Another owner may have copied the frame concurrently and left it to us.
+        if frame_table().release(old) {
+            alloc.free_4k(old);
+        }
+        frame_table().set_owner(new, FrameOwner::User);
+        new
+    } else {
+        old
+    };
+
+    let resolved = space.break_cow(va, frame).is_ok();
+    unsafe { invalidate_tlb_page(va.page::<Size4K>()) };
+    resolved
+}
+
+/// Load CR3 with the given address space.
+///
+/// # Safety
+/// `root` must be a valid PML4 whose kernel half mirrors the current one
+/// (e.g. created by [`create_address_space`]).
+pub unsafe fn activate_address_space(root: RootPage) {
+    let kvm = KVM.get().expect("Kernel VM not initialized");
+    unsafe { AddressSpace::from_root(&kvm.mapper, root).activate() }
+}
+
+/// Temporarily switch to the address space `root`, run `f` and switch back.
+///
+/// Interrupts are disabled for the duration of the call. Both switches flush
+/// the TLB entries of the loaded PCID, so no stale entries survive.
+///
+/// # Safety
+/// Same as [`activate_address_space`].
+pub unsafe fn with_address_space<R>(root: RootPage, f: impl FnOnce() -> R) -> R {
+    let _irq = IrqGuard::new();
+    let prev = unsafe { Cr3::load_unsafe() };
+    unsafe { activate_address_space(root) };
+    let result = f();
+    unsafe { prev.store_unsafe() };
+    result
+}
+
+/// How this system switches address spaces.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PcidMode {
+    /// No PCID support: every CR3 load flushes the TLB.
+    Disabled,
+    /// PCIDs are enabled; `invpcid` says whether `INVPCID` is available.
+    Enabled { invpcid: bool },
+}
+
+static PCID_MODE: SyncOnceCell<PcidMode> = SyncOnceCell::new();
+
+/// Enable PCIDs on this CPU if supported.
+///
+/// # Safety
+/// Must run at CPL0 with paging enabled, before [`switch_address_space`].
+pub unsafe fn init_pcid() -> PcidMode {
+    let ranges = unsafe { CpuidRanges::read() };
+    let has_pcid = unsafe { Leaf01h::read(&ranges) }.is_some_and(|l| l.has_pcid());
+    let has_invpcid = unsafe { Leaf07h::read(&ranges) }.is_some_and(|l| l.has_invpcid());
+
+    let mode = *PCID_MODE.get_or_init(|| {
+        if has_pcid {
+            PcidMode::Enabled {
+                invpcid: has_invpcid,
+            }
+        } else {
+            PcidMode::Disabled
+        }
+    });
+
+    if matches!(mode, PcidMode::Enabled { .. }) {
+        // Setting CR4.PCIDE requires CR3[11:0] to be 0.
+        let _irq = IrqGuard::new();
+        unsafe {
+            activate_address_space(read_cr3_phys().page());
+            Cr4::load_unsafe().with_pcide(true).store_unsafe();
+        }
+    }
+    mode
+}
+
+/// Switch this CPU to the address space `root`, using its PCID (`tag`) if
+/// PCIDs are enabled.
+///
+/// # Safety
+/// - Interrupts must be disabled.
+/// - Same as [`activate_address_space`]; `tag` must belong to `root` and not be
+///   used for any other address space.
+pub unsafe fn switch_address_space(root: RootPage, tag: &mut PcidTag) {
+    let Some(&PcidMode::Enabled {
+        invpcid: has_invpcid,
+    }) = PCID_MODE.get()
+    else {
+        unsafe { activate_address_space(root) };
+        return;
+    };
+
+    let cpu = unsafe { PerCpu::current() };
+    let assignment = cpu.pcids.lock().assign(tag);
+    match assignment {
+        PcidAssignment::Current(pcid) => unsafe { load_cr3(root, pcid, true) },
+        PcidAssignment::New(pcid) => unsafe { load_cr3(root, pcid, false) },
+        PcidAssignment::Rollover(pcid) => {
+            debug!("PCIDs exhausted, flushing the TLB of all address spaces");
+            unsafe {
+                if has_invpcid {
+                    invpcid(InvpcidKind::AllContexts, Pcid::NONE, 0);
+                } else {
+                    flush_tlb_global();
+                }
+                load_cr3(root, pcid, false);
+            }
+        }
+    }
+}
+
+/// Flush this CPU's TLB for all PCIDs, global entries included.
+///
+/// # Safety
+/// Must run at CPL0 with `CR4.PGE` set.
+unsafe fn flush_tlb_global() {
+    // Toggling CR4.PGE flushes all PCIDs, including global entries.
+    unsafe {
+        let cr4 = Cr4::load_unsafe();
+        cr4.with_pge(false).store_unsafe();
+        cr4.store_unsafe();
+    }
+}
@@ -0,0 +1,34 @@
+//! # Page Table Audit
+//!
+//! Once early init has dropped the loader's identity mappings, [`run`] walks
+//! the kernel's page tables and checks them with
+//! [`kernel_vmem::audit`]: no user-accessible kernel pages, no executable
+//! pages in the kernel half but the [kernel's text](crate::kimage), no
+//! writable and executable pages anywhere and no global user pages.
+//!
+//! Every violation is logged with its address, level and flags. Debug builds
+//! panic if there are any; release builds carry on.
+
+use crate::kimage;
+use kernel_alloc::phys_mapper::HhdmPhysMapper;
+use kernel_vmem::AddressSpace;
+use kernel_vmem::audit::audit;
+use log::{error, info};
+
+/// Audit the active page tables; returns the number of violations.
+///
+/// # Panics
+/// In debug builds, if there is a violation.
+pub fn run() -> usize {
+    // Safety: CR3 points to a valid PML4; the mapper is valid for the kernel's lifetime.
+    let space = unsafe { AddressSpace::from_current(&HhdmPhysMapper) };
+    let violations = audit(&space, kimage::is_kernel_text, |finding| {
+        error!("Page table audit: {finding}");
+    });
+
+    if violations == 0 {
+        info!("Page table audit passed");
+    }
+    debug_assert_eq!(violations, 0, "page table audit failed");
+    violations
+}
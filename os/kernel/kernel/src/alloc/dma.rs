@@ -0,0 +1,36 @@
+//! # DMA Buffers
+//!
+//! [`DmaBuffer`] is [`kernel_alloc::dma::DmaBuffer`] with its frames from
+//! the kernel frame allocator; see there for the guarantees.
+//!
+//! Users so far: the [virtio](crate::virtio) queues and the buffers of the
+//! [virtio console](crate::virtio::console).
+
+use crate::alloc::with_kernel_frame_alloc;
+use kernel_alloc::dma::DmaFrames;
+use kernel_alloc::frame_alloc::Zone;
+use kernel_memory_addresses::{PageSize, PhysicalPage, Size4K};
+use kernel_vmem::PhysFrameAlloc;
+
+pub use kernel_alloc::dma::{DmaConstraints, DmaError};
+
+/// A DMA buffer from the kernel frame allocator.
+pub type DmaBuffer = kernel_alloc::dma::DmaBuffer<KernelDmaFrames>;
+
+/// The kernel frame allocator, as a source of [`DmaBuffer`] frames.
+#[derive(Debug)]
+pub struct KernelDmaFrames;
+
+impl DmaFrames for KernelDmaFrames {
+    fn alloc_frames(count: usize, zone: Zone, align: usize) -> Option<PhysicalPage<Size4K>> {
+        with_kernel_frame_alloc(|alloc| alloc.alloc_contiguous_4k_in(count, zone, align))
+    }
+
+    fn free_frames(first: PhysicalPage<Size4K>, count: usize) {
+        with_kernel_frame_alloc(|alloc| {
+            for i in 0..count as u64 {
+                alloc.free_4k(PhysicalPage::from_addr(first.base() + i * Size4K::SIZE));
+            }
+        });
+    }
+}
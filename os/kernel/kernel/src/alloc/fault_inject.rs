@@ -0,0 +1,96 @@
+//! # Frame Allocation Fault Injection
+//!
+//! With the `fault-inject` feature, the kernel's frame allocator is wrapped
+//! in a [`FaultyFrameAlloc`](kernel_alloc::fault_inject::FaultyFrameAlloc)
+//! driven by [`FAULTS`], so out-of-memory paths such as a failing
+//! `AddressSpace::new` during process spawn can be exercised on purpose.
+//!
+//! ## Configuration
+//!
+//! Injection stays off during boot. [`init`] arms it at the end of early init
+//! from the [kernel command line](crate::cmdline):
+//!
+//! * `failalloc=every:<n>` fails every `n`th frame allocation,
+//! * `failalloc=after:<n>` lets `n` allocations succeed and fails all later ones,
+//! * `failalloc_skip=<file>[:<line>],...` never fails allocations made at
+//!   the listed call sites (see [`FaultInjector::skip`]).
+//!
+//! Tests can also switch modes directly through [`FAULTS`]. [`stats`] returns
+//! the injector's counters.
+//!
+//! ## Heap
+//!
+//! The kernel heap's [global allocator](crate::alloc::heap::GLOBAL) is
+//! wrapped in a [`FaultyGlobalAlloc`](kernel_alloc::fault_inject::FaultyGlobalAlloc)
+//! driven by [`HEAP_FAULTS`], which nothing arms at boot: the kernel treats
+//! an exhausted heap as fatal, so only tests switch it on.
+
+use crate::cmdline::{self, Param, ParamKind};
+use kernel_alloc::fault_inject::{FaultInjector, FaultMode, FaultStats};
+use log::{info, warn};
+
+pub static FAILALLOC_PARAM: Param = Param {
+    name: "failalloc",
+    kind: ParamKind::Str,
+    help: "fail frame allocations: every:<n> or after:<n>",
+};
+
+pub static FAILALLOC_SKIP_PARAM: Param = Param {
+    name: "failalloc_skip",
+    kind: ParamKind::Str,
+    help: "comma-separated <file>[:<line>] call sites exempt from failalloc",
+};
+
+/// The injector of the kernel's frame allocator.
+pub static FAULTS: FaultInjector = FaultInjector::new();
+
+/// The injector of the kernel heap.
+pub static HEAP_FAULTS: FaultInjector = FaultInjector::new();
+
+/// Arm the injector as configured on the command line.
+pub fn init() {
+    if let Some(sites) = cmdline::get_str(FAILALLOC_SKIP_PARAM.name) {
+        for site in sites.split(',').filter(|s| !s.is_empty()) {
+            let Some((file, line)) = parse_site(site) else {
+                warn!("failalloc_skip: ignoring {site:?}, invalid line number");
+                continue;
+            };
+            if let Err(e) = FAULTS.skip(file, line) {
+                warn!("failalloc_skip: ignoring {site:?}: {e}");
+            }
+        }
+    }
+
+    let Some(value) = cmdline::get_str(FAILALLOC_PARAM.name) else {
+        return;
+    };
+    let Some(mode) = parse_mode(value) else {
+        warn!("failalloc: expected every:<n> or after:<n>, got {value:?}");
+        return;
+    };
+    info!("Injecting frame allocation failures: {mode:?}");
+    FAULTS.set_mode(mode);
+}
+
+/// The injector's counters.
+#[allow(dead_code)]
+pub fn stats() -> FaultStats {
+    FAULTS.stats()
+}
+
+fn parse_site(site: &'static str) -> Option<(&'static str, Option<u32>)> {
+    match site.rsplit_once(':') {
+        Some((file, line)) => Some((file, Some(line.parse().ok()?))),
+        None => Some((site, None)),
+    }
+}
+
+fn parse_mode(value: &str) -> Option<FaultMode> {
+    let (kind, n) = value.split_once(':')?;
+    let n = n.parse().ok()?;
+    match kind {
+        "every" => Some(FaultMode::EveryNth(n)),
+        "after" => Some(FaultMode::AfterBudget(n)),
+        _ => None,
+    }
+}
@@ -0,0 +1,270 @@
+//! # Kernel Heap
+//!
+//! The kernel's `#[global_allocator]`: a [`SlabHeap`] with a front-end cache
+//! for each of the [`MAX_CPUS`] CPUs; see [`kernel_alloc::heap`] for how it
+//! works. Its memory comes from [`HeapPages`]:
+//!
+//! * Slabs are physically contiguous frames from the kernel frame allocator,
+//!   used through the HHDM and tagged [`FrameOwner::Kernel`].
+//! * Large allocations are mapped page by page into the window at
+//!   [`HEAP_BASE`], at the start of a run of [`LARGE_SLOT`] slots that ends
+//!   with at least one unmapped guard page. Freeing one unmaps its pages and
+//!   returns their frames.
+//!
+//! The pages are global, so a CR3 reload does not drop them from the TLB;
+//! freeing invalidates each page with `invlpg` before its frame is returned.
+//! That covers the local TLB only; like elsewhere in the kernel, other CPUs
+//! keep stale translations of the window until they flush theirs.
+//!
+//! Every heap call runs with interrupts disabled, and so does everything
+//! that locks the frame allocator or the kernel VMM, so interrupt handlers
+//! may allocate; NMI and machine check handlers must not, since they also
+//! interrupt those. The heap takes the frame allocator lock while holding
+//! its own, so nothing may allocate from the heap with the frame allocator
+//! or the kernel VMM locked. The heap is available once the kernel VMM is.
+//!
+//! The `#[global_allocator]` is [`GLOBAL`], the heap behind two optional
+//! wrappers: with the `poison` feature, freed blocks are poisoned and
+//! quarantined (`alloc::poison::HEAP_POISON`); with `fault-inject`,
+//! allocations fail as `alloc::fault_inject::HEAP_FAULTS` decides. Calls on
+//! [`HEAP`] itself bypass both.
+//!
+//! [`write_stats`] reports the counters, and with the `heap-accounting`
+//! feature the live bytes per call site; the `heap` command of
+//! [kdb](crate::kdb) shows them.
+
+#[cfg(feature = "fault-inject")]
+use crate::alloc::fault_inject::HEAP_FAULTS;
+#[cfg(feature = "poison")]
+use crate::alloc::poison::HEAP_POISON;
+use crate::alloc::{FlushTlb, frame_table, try_with_kernel_vmm, with_kernel_frame_alloc};
+use crate::msr::Ia32GsBaseMsrExt;
+use crate::per_cpu::MAX_CPUS;
+use core::alloc::{GlobalAlloc, Layout};
+use core::fmt::{self, Write};
+use core::ptr::NonNull;
+#[cfg(feature = "fault-inject")]
+use kernel_alloc::fault_inject::FaultyGlobalAlloc;
+use kernel_alloc::frame_info::FrameOwner;
+use kernel_alloc::heap::{HeapBackend, HeapStats, SLAB_SIZE, SlabHeap};
+#[cfg(feature = "poison")]
+use kernel_alloc::poison::PoisonGlobalAlloc;
+use kernel_alloc::vmm::AllocationTarget;
+use kernel_info::memory::HHDM_BASE;
+use kernel_memory_addresses::{PageSize, PhysicalPage, Size4K, VirtualAddress};
+use kernel_registers::msr::Ia32GsBaseMsr;
+use kernel_sync::{IrqGuard, SpinMutex};
+use kernel_vmem::VirtualMemoryPageBits;
+
+/// Start of the window large allocations are mapped in.
+pub const HEAP_BASE: u64 = 0xffff_ff30_0000_0000;
+
+/// Every large allocation starts on a slot of this many bytes.
+pub const LARGE_SLOT: u64 = 64 * 1024;
+
+/// Number of slots in the window; 256 MiB in all.
+const LARGE_SLOTS: usize = 4096;
+
+/// Frames per slab.
+#[allow(clippy::cast_possible_truncation)]
+const SLAB_FRAMES: usize = SLAB_SIZE / Size4K::SIZE as usize;
+
+/// The kernel heap.
+pub static HEAP: KernelHeap = KernelHeap(SlabHeap::new(HeapPages));
+
+#[cfg(feature = "poison")]
+type Poisoned = PoisonGlobalAlloc<&'static KernelHeap>;
+#[cfg(not(feature = "poison"))]
+type Poisoned = &'static KernelHeap;
+
+#[cfg(feature = "poison")]
+const POISONED: Poisoned = PoisonGlobalAlloc::new(&HEAP, &HEAP_POISON);
+#[cfg(not(feature = "poison"))]
+const POISONED: Poisoned = &HEAP;
+
+#[cfg(feature = "fault-inject")]
+type Global = FaultyGlobalAlloc<Poisoned>;
+#[cfg(not(feature = "fault-inject"))]
+type Global = Poisoned;
+
+/// [`HEAP`] behind the wrappers of the `poison` and `fault-inject` features.
+#[global_allocator]
+#[cfg(feature = "fault-inject")]
+pub static GLOBAL: Global = FaultyGlobalAlloc::new(POISONED, &HEAP_FAULTS);
+/// [`HEAP`] behind the wrappers of the `poison` and `fault-inject` features.
+#[global_allocator]
+#[cfg(not(feature = "fault-inject"))]
+pub static GLOBAL: Global = POISONED;
+
+/// Slots of the window in use, one bit each.
+static LARGE: SpinMutex<[u64; LARGE_SLOTS / 64]> = SpinMutex::new([0; LARGE_SLOTS / 64]);
+
+/// The [`SlabHeap`] behind [`HEAP`], with interrupts disabled around every
+/// call.
+pub struct KernelHeap(SlabHeap<HeapPages, MAX_CPUS>);
+
+impl KernelHeap {
+    /// A snapshot of the counters.
+    pub fn stats(&self) -> HeapStats {
+        self.0.stats()
+    }
+}
+
+// SAFETY: Forwards to `KernelHeap`.
+unsafe impl GlobalAlloc for &KernelHeap {
+    #[track_caller]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        // SAFETY: As required of the caller.
+        unsafe { (**self).alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // SAFETY: As required of the caller.
+        unsafe { (**self).dealloc(ptr, layout) }
+    }
+}
+
+// SAFETY: Forwards to `SlabHeap`.
+unsafe impl GlobalAlloc for KernelHeap {
+    #[track_caller]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _irq = IrqGuard::new();
+        self.0.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let _irq = IrqGuard::new();
+        // SAFETY: As required of the caller.
+        unsafe { self.0.dealloc(ptr, layout) }
+    }
+}
+
+/// Memory for the [`HEAP`]; see the [module docs](self).
+pub struct HeapPages;
+
+// SAFETY: Slabs are frames nobody else owns; large allocations are mapped
+// into slots nobody else uses.
+unsafe impl HeapBackend for HeapPages {
+    fn alloc_slab(&self) -> Option<NonNull<u8>> {
+        let first = with_kernel_frame_alloc(|alloc| alloc.alloc_contiguous_4k(SLAB_FRAMES))?;
+        for i in 0..SLAB_FRAMES as u64 {
+            let frame = PhysicalPage::from_addr(first.base() + i * Size4K::SIZE);
+            frame_table().set_owner(frame, FrameOwner::Kernel);
+        }
+        NonNull::new((HHDM_BASE + first.base().as_u64()).as_u64() as *mut u8)
+    }
+
+    fn alloc_large(&self, layout: Layout) -> Option<NonNull<u8>> {
+        if layout.align() as u64 > LARGE_SLOT {
+            return None;
+        }
+        let bytes = (layout.size() as u64).next_multiple_of(Size4K::SIZE);
+        let slots = slots_for(bytes);
+        let first = claim_slots(&mut LARGE.lock(), slots)?;
+        let va = slot_address(first);
+
+        let nonleaf = VirtualMemoryPageBits::new()
+            .with_present(true)
+            .with_writable(true);
+        let leaf = VirtualMemoryPageBits::new()
+            .with_present(true)
+            .with_writable(true)
+            .with_no_execute(true)
+            .with_global(true);
+        let mapped = try_with_kernel_vmm(FlushTlb::Never, |vmm| {
+            vmm.map_anon_4k_pages(AllocationTarget::Kernel, va, 0, bytes, nonleaf, leaf)
+                .inspect_err(|_| vmm.unmap_4k_pages_release(va, bytes, |_| true))
+        });
+        if mapped.is_err() {
+            release_slots(&mut LARGE.lock(), first, slots);
+            return None;
+        }
+        NonNull::new(va.as_u64() as *mut u8)
+    }
+
+    unsafe fn free_large(&self, ptr: NonNull<u8>, layout: Layout) {
+        let va = VirtualAddress::new(ptr.as_ptr() as u64);
+        let bytes = (layout.size() as u64).next_multiple_of(Size4K::SIZE);
+        let _ = try_with_kernel_vmm(FlushTlb::Never, |vmm| {
+            vmm.unmap_4k_pages_release(va, bytes, |_| true);
+            Ok::<_, ()>(())
+        });
+        let first = usize::try_from((va.as_u64() - HEAP_BASE) / LARGE_SLOT).unwrap_or(usize::MAX);
+        release_slots(&mut LARGE.lock(), first, slots_for(bytes));
+    }
+
+    fn cpu(&self) -> usize {
+        // SAFETY: Reading GS base has no side effects.
+        let cpu = unsafe { <Ia32GsBaseMsr as Ia32GsBaseMsrExt>::read_ptr() };
+        if cpu.is_null() {
+            // Before this CPU's per-CPU data: no cache.
+            return usize::MAX;
+        }
+        // SAFETY: A non-null GS base points to this CPU's `PerCpu`.
+        unsafe { (*cpu).cpu_id as usize }
+    }
+}
+
+/// Slots for `bytes` mapped bytes and a guard page behind them.
+#[allow(clippy::cast_possible_truncation)]
+const fn slots_for(bytes: u64) -> usize {
+    (bytes + Size4K::SIZE).div_ceil(LARGE_SLOT) as usize
+}
+
+const fn slot_address(slot: usize) -> VirtualAddress {
+    VirtualAddress::new(HEAP_BASE + slot as u64 * LARGE_SLOT)
+}
+
+/// Mark the first run of `n` free slots as used and return its first slot.
+fn claim_slots(used: &mut [u64; LARGE_SLOTS / 64], n: usize) -> Option<usize> {
+    let mut start = 0;
+    while start + n <= LARGE_SLOTS {
+        if let Some(taken) =
+            (start..start + n).find(|&slot| used[slot / 64] & (1 << (slot % 64)) != 0)
+        {
+            start = taken + 1;
+        } else {
+            for slot in start..start + n {
+                used[slot / 64] |= 1 << (slot % 64);
+            }
+            return Some(start);
+        }
+    }
+    None
+}
+
+fn release_slots(used: &mut [u64; LARGE_SLOTS / 64], first: usize, n: usize) {
+    for slot in first..first.saturating_add(n).min(LARGE_SLOTS) {
+        used[slot / 64] &= !(1 << (slot % 64));
+    }
+}
+
+/// Write the heap counters, and the live allocations per call site if they
+/// are counted, to `out`.
+pub fn write_stats(out: &mut impl Write) -> fmt::Result {
+    let stats = HEAP.stats();
+    writeln!(out, "slabs:       {:>10} kB", stats.slab_bytes / 1024)?;
+    writeln!(out, "small live:  {:>10} kB", stats.small_bytes / 1024)?;
+    writeln!(out, "large live:  {:>10} kB", stats.large_bytes / 1024)?;
+    writeln!(out, "allocations: {:>10}", stats.allocations)?;
+    writeln!(out, "frees:       {:>10}", stats.frees)?;
+    writeln!(out, "failures:    {:>10}", stats.failures)?;
+    writeln!(out, "refills:     {:>10}", stats.refills)?;
+    writeln!(out, "flushes:     {:>10}", stats.flushes)?;
+
+    #[cfg(feature = "heap-accounting")]
+    {
+        let mut result = Ok(());
+        HEAP.0.sites(|site| {
+            if result.is_ok() && site.live_blocks > 0 {
+                result = writeln!(
+                    out,
+                    "{:>10} B in {:>6} at {}:{}",
+                    site.live_bytes, site.live_blocks, site.file, site.line
+                );
+            }
+        });
+        result?;
+    }
+    Ok(())
+}
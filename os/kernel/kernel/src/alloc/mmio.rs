@@ -0,0 +1,82 @@
+//! # Device Memory Mappings
+//!
+//! [`map_mmio`] maps a device's register block uncached into a dedicated
+//! window of the kernel address space and returns an [`Mmio`] handle. The
+//! handle derefs to a bounds-checked [`MmioRegion`] and removes the mapping
+//! when dropped.
+//!
+//! Virtual addresses in the window are handed out by a bump allocator and
+//! never reused; at 1 TiB, the window outlasts any realistic number of
+//! device mappings.
+
+use crate::alloc::{FlushTlb, try_with_kernel_vmm, with_kernel_vmm};
+use core::ops::Deref;
+use kernel_alloc::mmio::{MmioRegion, mapped_len};
+use kernel_alloc::vmm::VmmError;
+use kernel_info::memory::HHDM_BASE;
+use kernel_memory_addresses::{PhysicalAddress, VirtualAddress};
+use kernel_sync::SpinMutex;
+use log::{trace, warn};
+
+/// Virtual offset inside the HHDM range where MMIO regions are mapped.
+pub const MMIO_WINDOW_OFFSET: u64 = 3u64 << 40; // 3 TiB inside HHDM range
+
+/// Size of the MMIO window.
+const MMIO_WINDOW_SIZE: u64 = 1u64 << 40;
+
+/// Next free address in the MMIO window, relative to its start.
+static NEXT: SpinMutex<u64> = SpinMutex::new(0);
+
+/// A mapped device register block; unmapped on drop.
+#[derive(Debug)]
+pub struct Mmio {
+    region: Option<MmioRegion>,
+}
+
+impl Deref for Mmio {
+    type Target = MmioRegion;
+
+    fn deref(&self) -> &MmioRegion {
+        self.region.as_ref().expect("MMIO region already unmapped")
+    }
+}
+
+impl Drop for Mmio {
+    fn drop(&mut self) {
+        let Some(region) = self.region.take() else {
+            return;
+        };
+
+        let phys = region.phys();
+        with_kernel_vmm(|vmm| {
+            if let Err(e) = vmm.unmap_mmio(region) {
+                warn!("Failed to unmap MMIO region at {phys}: {e}");
+            }
+        });
+    }
+}
+
+/// Map `len` bytes of device memory at `pa`.
+///
+/// # Errors
+/// - [`VmmError::InvalidRange`] if `len` is zero or the MMIO window is exhausted.
+/// - [`VmmError::OutOfMemory`] if page tables could not be allocated.
+pub fn map_mmio(pa: PhysicalAddress, len: u64) -> Result<Mmio, VmmError> {
+    let span = mapped_len(pa, len);
+    let offset = {
+        let mut next = NEXT.lock();
+        let offset = *next;
+        if span == 0 || offset + span > MMIO_WINDOW_SIZE {
+            return Err(VmmError::InvalidRange);
+        }
+        *next += span;
+        offset
+    };
+
+    let va = VirtualAddress::new(HHDM_BASE.as_u64() + MMIO_WINDOW_OFFSET + offset);
+    let region = try_with_kernel_vmm(FlushTlb::Never, |vmm| vmm.map_mmio(va, pa, len))?;
+    trace!("Mapped {len:#x} bytes of MMIO at {pa} to {}", region.base());
+    Ok(Mmio {
+        region: Some(region),
+    })
+}
@@ -0,0 +1,83 @@
+//! # Frame Poisoning
+//!
+//! With the `poison` feature, the kernel's frame allocator is wrapped in a
+//! [`PoisonFrameAlloc`](kernel_alloc::poison::PoisonFrameAlloc) tracked by
+//! [`POISON`]: freed frames are filled with a pattern, and a frame whose
+//! pattern changed before it is handed out again panics with the address of
+//! the first changed byte.
+//!
+//! ## Configuration
+//!
+//! Checking is always on. [`init`] additionally enables unmapping of direct
+//! map aliases at the end of early init, as configured on the
+//! [kernel command line](crate::cmdline):
+//!
+//! * `poison_unmap=<n>` unmaps the HHDM alias of one in `n` freed frames
+//!   until it leaves the quarantine; `0` (the default) never does.
+//!
+//! A kernel page fault on such an alias is reported as a use after free by
+//! the page fault handler, through [`describe`]. [`stats`] returns the
+//! counters.
+//!
+//! ## Heap
+//!
+//! The kernel heap's [global allocator](crate::alloc::heap::GLOBAL) is
+//! wrapped in a [`PoisonGlobalAlloc`](kernel_alloc::poison::PoisonGlobalAlloc)
+//! as well, tracked by [`HEAP_POISON`]: freed blocks are filled with the
+//! pattern, and larger ones are checked when they leave the quarantine.
+
+use crate::alloc::with_kernel_frame_alloc;
+use crate::cmdline::{self, Param, ParamKind};
+use kernel_alloc::poison::{AliasUnmap, FramePoison, HeapPoison, PoisonStats};
+use kernel_info::memory::HHDM_BASE;
+use kernel_memory_addresses::{PhysicalPage, Size4K, VirtualAddress};
+use kernel_vmem::{VirtualMemoryPageBits, invalidate_tlb_page, read_cr3_phys};
+use log::info;
+
+pub static POISON_UNMAP_PARAM: Param = Param {
+    name: "poison_unmap",
+    kind: ParamKind::U64,
+    help: "unmap the direct map alias of one in <n> freed frames, 0 disables",
+};
+
+/// Counters and quarantine of the kernel's frame allocator.
+pub static POISON: FramePoison = FramePoison::new();
+
+/// Counters and quarantine of the kernel heap.
+pub static HEAP_POISON: HeapPoison = HeapPoison::new();
+
+/// Enable unmapping of direct map aliases as configured on the command line.
+pub fn init() {
+    let sample = cmdline::get_u64(POISON_UNMAP_PARAM.name).unwrap_or(0);
+    if sample == 0 {
+        return;
+    }
+
+    // The loader maps the HHDM like this; split leaves keep the same flags.
+    let leaf_flags = VirtualMemoryPageBits::new()
+        .with_present(true)
+        .with_writable(true)
+        .with_global(true)
+        .with_no_execute(true);
+    let unmap = AliasUnmap {
+        // Safety: paging is enabled; the kernel half is shared by all roots.
+        root: unsafe { read_cr3_phys() }.page(),
+        direct_map: HHDM_BASE,
+        leaf_flags,
+        sample,
+        flush: |va| unsafe { invalidate_tlb_page(va.page()) },
+    };
+    info!("Unmapping the direct map alias of one in {sample} freed frames");
+    with_kernel_frame_alloc(|alloc| alloc.unmap_aliases(unmap));
+}
+
+/// The freed frame whose unmapped HHDM alias contains `va`, if any.
+pub fn describe(va: VirtualAddress) -> Option<PhysicalPage<Size4K>> {
+    POISON.describe(HHDM_BASE, va)
+}
+
+/// The poisoning counters.
+#[allow(dead_code)]
+pub fn stats() -> PoisonStats {
+    POISON.stats()
+}
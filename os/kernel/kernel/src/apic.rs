@@ -15,7 +15,7 @@
 //! * **x2APIC Mode**: Enables and configures x2APIC mode for improved performance
 //! * **Timer Management**: Configures periodic LAPIC timer for kernel scheduling
 //! * **Interrupt Handling**: Manages spurious interrupts and End-of-Interrupt (EOI) signaling
-//! * **Calibration**: TSC-based timer frequency calibration for accurate timing
+//! * **Calibration**: PIT-based timer frequency calibration for accurate timing
 //!
 //! ## Architecture
 //!
@@ -29,10 +29,14 @@
 //! - [`enable_and_read_id_x2apic`] - Initializes x2APIC mode and reads APIC ID
 //! - [`write_svr_x2apic`] - Configures Spurious Interrupt Vector Register
 //! - [`eoi_x2apic`] - Signals End-of-Interrupt for completed interrupt processing
+//! - [`send_ipi_x2apic`] - Sends an inter-processor interrupt to another CPU
+//! - [`set_task_priority_x2apic`] - Holds back interrupts below a priority class
+//! - [`lvt`] - Typed access to the error, thermal, performance counter and
+//!   LINT0/LINT1 entries of the Local Vector Table
 //!
 //! ### Timer Subsystem
 //! - [`program_timer_periodic_x2apic`] - Configures LAPIC timer in periodic mode
-//! - [`calibrate_lapic_hz_via_tsc`] - Calibrates timer frequency against TSC
+//! - [`calibrate_lapic_hz`] - Calibrates timer frequency against the [PIT](crate::pit)
 //! - [`lapic_div`] - Timer divider constants for frequency scaling
 //!
 //! ## Initialization Sequence
@@ -41,12 +45,18 @@
 //! 2. **Mode Enable**: Set `APIC_EN` and `APIC_EXTD` bits in `IA32_APIC_BASE` MSR
 //! 3. **ID Assignment**: Read and store Local APIC ID in per-CPU structure
 //! 4. **Spurious Vector**: Configure spurious interrupt handling
-//! 5. **Timer Setup**: Calibrate and configure periodic timer operation
+//! 5. **Local Interrupts**: Route LINT0/LINT1 as the MADT's local APIC NMI
+//!    entries say, masking the pins without one, and unmask the
+//!    [error](crate::interrupts::apic_error) and
+//!    [thermal](crate::interrupts::thermal) interrupts
+//! 6. **Timer Setup**: Calibrate and configure periodic timer operation
 //!
 //! ## Timer Operation
 //!
-//! The LAPIC timer operates in periodic mode at 1 kHz frequency:
-//! - Uses TSC-based calibration for accurate frequency measurement
+//! The LAPIC timer operates in periodic mode at [`DEFAULT_TICK_HZ`] (1 kHz),
+//! or at the rate given as `tick_hz=` on the [kernel command line](crate::cmdline):
+//! - Uses PIT-based calibration for accurate frequency measurement, with the
+//!   TSC as a fallback
 //! - Supports configurable clock dividers (1, 2, 4, 8, 16, 32, 64, 128)
 //! - Generates timer interrupts for kernel tick processing
 //!
@@ -60,12 +70,33 @@
 //! All unsafe operations are necessary for hardware control and are carefully
 //! isolated with documented safety requirements.
 
+use crate::acpi;
+use crate::apic::lvt::{DeliveryMode, Lvt, LvtEntry};
+use crate::clock::Calibration;
+use crate::cmdline::{self, Param, ParamKind};
 use crate::cpuid::Leaf01h;
+use crate::interrupts;
 use crate::interrupts::spurious::SPURIOUS_INTERRUPT_VECTOR;
 use crate::interrupts::timer::LAPIC_TIMER_VECTOR;
 use crate::per_cpu::PerCpu;
+use crate::pit;
 use crate::tsc::rdtsc;
-use log::info;
+use kernel_acpi::madt::Polarity;
+use log::{info, warn};
+
+pub mod lvt;
+
+/// Timer interrupts per second unless `tick_hz` says otherwise.
+pub const DEFAULT_TICK_HZ: u64 = 1_000;
+
+/// Accepted range of `tick_hz`.
+const TICK_HZ_RANGE: core::ops::RangeInclusive<u64> = 10..=10_000;
+
+pub static TICK_HZ_PARAM: Param = Param {
+    name: "tick_hz",
+    kind: ParamKind::U64,
+    help: "Timer interrupts per second (10-10000)",
+};
 
 // IA32_APIC_BASE MSR and bits
 pub const IA32_APIC_BASE: u32 = 0x1B;
@@ -74,8 +105,10 @@ const APIC_EXTD: u64 = 1 << 10; // x2APIC mode
 
 // x2APIC MSRs
 const IA32_X2APIC_ID: u32 = 0x802;
+const IA32_X2APIC_TPR: u32 = 0x808;
 const IA32_X2APIC_EOI: u32 = 0x80B;
 const IA32_X2APIC_SVR: u32 = 0x80F;
+const IA32_X2APIC_ICR: u32 = 0x830;
 const IA32_X2APIC_LVT_TIMER: u32 = 0x832;
 const IA32_X2APIC_INITCNT: u32 = 0x838;
 const IA32_X2APIC_DIVCONF: u32 = 0x83E;
@@ -173,6 +206,15 @@ pub unsafe fn program_timer_periodic_x2apic(vector: u8, divider: u32, initial: u
     }
 }
 
+/// Deliver performance counter overflows as NMIs (x2APIC).
+///
+/// The LAPIC masks this entry whenever it delivers a counter overflow, so the
+/// NMI handler calls this again to re-arm it.
+pub unsafe fn program_pmi_nmi_x2apic() {
+    let entry = LvtEntry::new().with_delivery_mode(DeliveryMode::Nmi);
+    unsafe { Lvt::PerfCounter.write(entry) };
+}
+
 #[allow(clippy::cast_possible_truncation)]
 pub unsafe fn mask_timer_x2apic(mask: bool) {
     const IA32_X2APIC_LVT_TIMER: u32 = 0x832;
@@ -185,6 +227,20 @@ pub unsafe fn mask_timer_x2apic(mask: bool) {
     unsafe { wrmsr(IA32_X2APIC_LVT_TIMER, u64::from(lvt)) };
 }
 
+/// Send a fixed interrupt with `vector` to the CPU with `apic_id`.
+pub unsafe fn send_ipi_x2apic(apic_id: u32, vector: u8) {
+    // Fixed delivery, physical destination, edge triggered: only the vector
+    // and the destination are set.
+    let icr = (u64::from(apic_id) << 32) | u64::from(vector);
+    unsafe { wrmsr(IA32_X2APIC_ICR, icr) };
+}
+
+/// Set the task priority: interrupts with a vector priority class (bits
+/// 7:4) at or below `class` are held back. `0` accepts all.
+pub unsafe fn set_task_priority_x2apic(class: u8) {
+    unsafe { wrmsr(IA32_X2APIC_TPR, u64::from(class & 0xF) << 4) };
+}
+
 /// Bring up x2APIC on the BSP and record the APIC ID in `PerCpu`.
 pub fn init_lapic_and_set_cpu_id(percpu: &mut PerCpu) {
     info!("Initializing LAPIC (x2APIC)…");
@@ -192,9 +248,44 @@ pub fn init_lapic_and_set_cpu_id(percpu: &mut PerCpu) {
     percpu.apic_id = apic_id;
 
     lapic_enable_spurious_vector();
+    route_lint_pins(apic_id);
+    interrupts::apic_error::enable();
+    interrupts::thermal::enable();
     info!("x2APIC enabled; APIC ID = {apic_id:#x}");
 }
 
+/// Deliver the LINT pins the MADT lists local APIC NMI entries for as NMIs
+/// and mask the others; the 8259 PICs are not used, so nothing needs
+/// `ExtINT` on LINT0. Without a MADT, the firmware's setup stays.
+fn route_lint_pins(apic_id: u32) {
+    let Some(madt) = acpi::madt() else {
+        warn!(
+            "No MADT; leaving LINT0/LINT1 as the firmware set them up: {:?}, {:?}",
+            Lvt::Lint0.read(),
+            Lvt::Lint1.read()
+        );
+        return;
+    };
+    for (lint, pin) in [(0, Lvt::Lint0), (1, Lvt::Lint1)] {
+        let nmi = madt.local_apic_nmis(apic_id).find(|nmi| nmi.lint == lint);
+        // NMIs are always edge-triggered.
+        let entry = nmi.map_or_else(
+            || LvtEntry::new().with_masked(true),
+            |nmi| {
+                LvtEntry::new()
+                    .with_delivery_mode(DeliveryMode::Nmi)
+                    .with_active_low(nmi.polarity == Polarity::ActiveLow)
+            },
+        );
+        // SAFETY: LINT0/LINT1 always exist; NMI delivery uses no vector.
+        unsafe { pin.write(entry) };
+        info!(
+            "LINT{lint}: {}",
+            if nmi.is_some() { "NMI" } else { "masked" }
+        );
+    }
+}
+
 fn lapic_enable_spurious_vector() {
     // Choose a spurious vector (>= 0x10, unused).
     unsafe { write_svr_x2apic(SPURIOUS_INTERRUPT_VECTOR) };
@@ -212,32 +303,61 @@ pub mod lapic_div {
     pub const DIV_128: u32 = 0b1010;
 }
 
+/// The configured timer rate; see [`TICK_HZ_PARAM`].
+pub fn tick_hz() -> u64 {
+    match cmdline::get_u64(TICK_HZ_PARAM.name) {
+        Some(hz) if TICK_HZ_RANGE.contains(&hz) => hz,
+        Some(hz) => {
+            warn!("tick_hz={hz} is outside {TICK_HZ_RANGE:?}; using {DEFAULT_TICK_HZ} Hz");
+            DEFAULT_TICK_HZ
+        }
+        None => DEFAULT_TICK_HZ,
+    }
+}
+
 /// Quick helper to start a periodic timer (coarse values; calibrate later).
+///
+/// Returns `false`, leaving the timer masked, if it does not count fast
+/// enough for the tick rate.
 #[allow(clippy::cast_possible_truncation)]
-pub fn start_lapic_timer(tsc_hz: u64) {
+pub fn start_lapic_timer(tsc_hz: u64) -> bool {
     // Make sure: SVR enabled, TPR=0, IF=1, IDT has the gate.
     unsafe {
         // Calibrate once (cache result).
-        info!("Calibrating LAPIC timer via TSC ...");
-        let lapic_hz = calibrate_lapic_hz_via_tsc(tsc_hz, 100_000, lapic_div::DIV_16); // 50ms, /16
+        info!("Calibrating LAPIC timer against the PIT ...");
+        let lapic = calibrate_lapic_hz(tsc_hz, lapic_div::DIV_16);
+        info!("LAPIC timer frequency = {lapic}");
+        let lapic_hz = lapic.hz;
 
         // Choose rate & compute initial
-        let target_hz = 1_000u64; // 1 kHz
+        let target_hz = tick_hz();
         let div = lapic_div::DIV_16;
         let dec_rate = lapic_hz / 16;
         let initial = (dec_rate / target_hz) as u32;
+        if initial == 0 {
+            warn!("LAPIC timer counts at {lapic_hz} Hz; too slow for {target_hz} Hz ticks");
+            return false;
+        }
 
         // Arm periodic
+        info!("Programming the LAPIC timer for {target_hz} Hz ...");
         program_timer_periodic_x2apic(LAPIC_TIMER_VECTOR, div, initial);
     }
+    true
 }
 
+/// PIT time the LAPIC timer is counted over.
+const CALIBRATION_MS: u64 = 50;
+
+/// Measure the LAPIC timer input frequency against the PIT, or, if the PIT
+/// does not count, against the TSC.
 #[allow(clippy::cast_possible_truncation)]
-unsafe fn calibrate_lapic_hz_via_tsc(tsc_hz: u64, window_us: u64, div: u32) -> u64 {
+unsafe fn calibrate_lapic_hz(tsc_hz: u64, div: u32) -> Calibration {
     // Program LAPIC masked at chosen divider
     const LVT: u32 = 0x832;
     const DIV: u32 = 0x83E;
     const INIT: u32 = 0x838;
+    const CURRENT: u32 = 0x839;
     unsafe {
         wrmsr(DIV, u64::from(div));
     }
@@ -253,17 +373,23 @@ unsafe fn calibrate_lapic_hz_via_tsc(tsc_hz: u64, window_us: u64, div: u32) -> u
         wrmsr(INIT, 0xFFFF_FFFF);
     }
 
-    // Busy-wait for window_us using TSC
-    let start = rdtsc();
-    let target = start + (tsc_hz / 1_000_000) * window_us;
-    while rdtsc() < target {}
+    // Ticks counted down so far, at (lapic_hz/div)
+    let elapsed = || 0xFFFF_FFFFu64 - u64::from(unsafe { rdmsr(CURRENT) as u32 });
+    let ticks = pit::measure(CALIBRATION_MS, elapsed).unwrap_or_else(|| {
+        warn!("The PIT does not count; calibrating the LAPIC timer against the TSC");
+        let window_us = CALIBRATION_MS * 1_000;
 
-    // Read CURRCNT and compute elapsed ticks
-    let cur = unsafe { rdmsr(0x839) as u32 };
-    let elapsed = 0xFFFF_FFFFu64 - u64::from(cur); // ticks at (lapic_hz/div)
+        // Busy-wait for window_us using TSC
+        let start = rdtsc();
+        let target = start + (tsc_hz / 1_000_000) * window_us;
+        while rdtsc() < target {}
 
-    // Convert to Hz: elapsed ticks happened in window_us
-    let ticks_per_sec = elapsed * 1_000_000 / window_us;
+        // Convert to Hz: elapsed ticks happened in window_us
+        Calibration {
+            hz: elapsed() * 1_000_000 / window_us,
+            error_hz: None,
+        }
+    });
 
     // That equals (lapic_hz / div). Multiply back:
     #[allow(clippy::match_same_arms)]
@@ -279,5 +405,8 @@ unsafe fn calibrate_lapic_hz_via_tsc(tsc_hz: u64, window_us: u64, div: u32) -> u
         _ => 16, // default
     };
 
-    ticks_per_sec * multiplier
+    Calibration {
+        hz: ticks.hz * multiplier,
+        error_hz: ticks.error_hz.map(|error_hz| error_hz * multiplier),
+    }
 }
@@ -0,0 +1,184 @@
+//! # Local Vector Table
+//!
+//! The LVT entries route the local APIC's own interrupt sources: its error
+//! detection, the thermal sensor, the performance counters and the two
+//! local interrupt pins, LINT0 and LINT1. [`Lvt`] names an entry and
+//! [`LvtEntry`] is its value; the timer entry is programmed by
+//! [`program_timer_periodic_x2apic`](super::program_timer_periodic_x2apic)
+//! instead.
+//!
+//! The error status register ([`read_esr`]) tells which [`ApicErrors`] the
+//! error interrupt was raised for.
+//!
+//! Reference: Intel SDM Vol. 3A, §11.5 "Handling Local Interrupts".
+
+use super::{rdmsr, wrmsr};
+use bitfield_struct::bitfield;
+use core::fmt;
+
+const IA32_X2APIC_VERSION: u32 = 0x803;
+const IA32_X2APIC_ESR: u32 = 0x828;
+
+/// An LVT entry other than the timer.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Lvt {
+    Thermal,
+    PerfCounter,
+    Lint0,
+    Lint1,
+    Error,
+}
+
+impl Lvt {
+    const fn msr(self) -> u32 {
+        match self {
+            Self::Thermal => 0x833,
+            Self::PerfCounter => 0x834,
+            Self::Lint0 => 0x835,
+            Self::Lint1 => 0x836,
+            Self::Error => 0x837,
+        }
+    }
+
+    /// Whether this local APIC has the entry; older ones lack the thermal
+    /// and performance counter entries.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn exists(self) -> bool {
+        // SAFETY: Reading the version register has no side effects.
+        let version = unsafe { rdmsr(IA32_X2APIC_VERSION) } as u32;
+        let max_lvt = (version >> 16) & 0xFF;
+        max_lvt
+            >= match self {
+                Self::Thermal => 5,
+                Self::PerfCounter => 4,
+                Self::Lint0 | Self::Lint1 | Self::Error => 3,
+            }
+    }
+
+    /// The programmed entry.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn read(self) -> LvtEntry {
+        // SAFETY: Reading an LVT entry has no side effects.
+        LvtEntry::from_bits(unsafe { rdmsr(self.msr()) } as u32)
+    }
+
+    /// Program the entry.
+    ///
+    /// # Safety
+    /// The entry must [exist](Self::exists). Unless it is masked, its vector
+    /// must have a handler, or its delivery mode must not need one.
+    pub unsafe fn write(self, entry: LvtEntry) {
+        unsafe { wrmsr(self.msr(), u64::from(entry.into_bits())) };
+    }
+}
+
+/// How an LVT entry delivers its interrupt.
+///
+/// The reserved encodings read as [`Fixed`](Self::Fixed).
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(u8)]
+pub enum DeliveryMode {
+    /// The entry's vector.
+    Fixed = 0b000,
+    Smi = 0b010,
+    /// An NMI; the vector is ignored.
+    Nmi = 0b100,
+    Init = 0b101,
+    /// The vector an external 8259 PIC supplies; LINT pins only.
+    ExtInt = 0b111,
+}
+
+impl DeliveryMode {
+    pub const fn from_bits(bits: u8) -> Self {
+        match bits {
+            0b010 => Self::Smi,
+            0b100 => Self::Nmi,
+            0b101 => Self::Init,
+            0b111 => Self::ExtInt,
+            _ => Self::Fixed,
+        }
+    }
+
+    pub const fn into_bits(self) -> u8 {
+        self as u8
+    }
+}
+
+/// The value of an LVT entry.
+#[bitfield(u32)]
+#[derive(Eq, PartialEq)]
+pub struct LvtEntry {
+    /// Vector of [fixed](DeliveryMode::Fixed) delivery.
+    pub vector: u8,
+    #[bits(3)]
+    pub delivery_mode: DeliveryMode,
+    __: bool,
+    /// An interrupt was raised but not accepted by the core yet.
+    #[bits(access = RO)]
+    pub send_pending: bool,
+    /// The pin is active low; LINT pins only.
+    pub active_low: bool,
+    /// A level-triggered interrupt is being serviced; LINT pins only.
+    #[bits(access = RO)]
+    pub remote_irr: bool,
+    /// The pin is level-triggered; LINT pins with fixed delivery only.
+    pub level_triggered: bool,
+    pub masked: bool,
+    #[bits(15)]
+    __: u16,
+}
+
+/// Errors the local APIC detected, from the error status register.
+#[bitfield(u32)]
+#[derive(Eq, PartialEq)]
+pub struct ApicErrors {
+    pub send_checksum: bool,
+    pub receive_checksum: bool,
+    pub send_accept: bool,
+    pub receive_accept: bool,
+    pub redirectable_ipi: bool,
+    pub send_illegal_vector: bool,
+    pub receive_illegal_vector: bool,
+    pub illegal_register_address: bool,
+    #[bits(24)]
+    __: u32,
+}
+
+impl fmt::Display for ApicErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names = [
+            (self.send_checksum(), "send checksum"),
+            (self.receive_checksum(), "receive checksum"),
+            (self.send_accept(), "send accept"),
+            (self.receive_accept(), "receive accept"),
+            (self.redirectable_ipi(), "redirectable IPI"),
+            (self.send_illegal_vector(), "send illegal vector"),
+            (self.receive_illegal_vector(), "receive illegal vector"),
+            (self.illegal_register_address(), "illegal register address"),
+        ];
+        let mut any = false;
+        for (_, name) in names.iter().filter(|(set, _)| *set) {
+            if any {
+                f.write_str(", ")?;
+            }
+            f.write_str(name)?;
+            any = true;
+        }
+        if !any {
+            f.write_str("none")?;
+        }
+        Ok(())
+    }
+}
+
+/// The errors detected since the last call, which it clears.
+#[must_use]
+#[allow(clippy::cast_possible_truncation)]
+pub fn read_esr() -> ApicErrors {
+    // SAFETY: A write moves the errors detected so far into the ESR and
+    // clears the internal ones; it has no other effect.
+    unsafe {
+        wrmsr(IA32_X2APIC_ESR, 0);
+        ApicErrors::from_bits(rdmsr(IA32_X2APIC_ESR) as u32)
+    }
+}
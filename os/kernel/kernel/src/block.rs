@@ -0,0 +1,126 @@
+//! # Block Devices
+//!
+//! A [`BlockDevice`] is a driver of storage addressed in fixed-size blocks,
+//! such as a namespace of an [NVMe controller](crate::nvme) or a disk behind
+//! an [AHCI controller](crate::ahci). Drivers [`register`] their devices
+//! once they are up; kernel code finds them by name with [`lookup`].
+//!
+//! ## Transfers
+//!
+//! Transfers are synchronous: [`BlockDevice::read`] and
+//! [`BlockDevice::write`] return once the device is done, and the caller
+//! may sleep meanwhile. Buffers are whole blocks, starting at a logical
+//! block address (LBA); the driver copies them to and from memory the
+//! device can reach, so any kernel buffer will do.
+//!
+//! ## Limitations
+//!
+//! * Block devices are not files; nothing under `/dev` opens them yet.
+//! * Transfers bypass the [page cache](crate::page_cache); readers that want
+//!   caching go through [`BlockPages`](crate::page_cache::BlockPages).
+//! * Devices are never unregistered.
+
+use core::fmt;
+use kernel_sync::{IrqGuard, SpinMutex};
+use log::info;
+
+/// Maximum number of block devices.
+pub const MAX_BLOCK_DEVICES: usize = 8;
+
+/// Why a transfer failed.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum BlockError {
+    /// The blocks lie beyond the end of the device.
+    OutOfRange,
+    /// The buffer is not a whole number of blocks.
+    Misaligned,
+    /// The device reported an error or did not answer.
+    Io,
+}
+
+impl fmt::Display for BlockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OutOfRange => f.write_str("block out of range"),
+            Self::Misaligned => f.write_str("buffer is not a whole number of blocks"),
+            Self::Io => f.write_str("I/O error"),
+        }
+    }
+}
+
+/// A driver of block-addressed storage.
+pub trait BlockDevice: Sync {
+    /// The device's name, e.g. `nvme0n1`.
+    fn name(&self) -> &'static str;
+
+    /// Size of a block in bytes.
+    fn block_size(&self) -> usize;
+
+    /// Number of blocks.
+    fn blocks(&self) -> u64;
+
+    /// Fill `buf` with the blocks starting at `lba`.
+    fn read(&self, lba: u64, buf: &mut [u8]) -> Result<(), BlockError>;
+
+    /// Write `buf` to the blocks starting at `lba`.
+    #[allow(dead_code)]
+    fn write(&self, lba: u64, buf: &[u8]) -> Result<(), BlockError>;
+
+    /// The number of blocks `len` bytes at `lba` span, if they lie on the
+    /// device; for drivers to validate a transfer with.
+    fn check_range(&self, lba: u64, len: usize) -> Result<u64, BlockError> {
+        if !len.is_multiple_of(self.block_size()) {
+            return Err(BlockError::Misaligned);
+        }
+        let count = (len / self.block_size()) as u64;
+        match lba.checked_add(count) {
+            Some(end) if end <= self.blocks() => Ok(count),
+            _ => Err(BlockError::OutOfRange),
+        }
+    }
+}
+
+/// All [`MAX_BLOCK_DEVICES`] slots are taken.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct TooManyDevices;
+
+impl fmt::Display for TooManyDevices {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("too many block devices")
+    }
+}
+
+static DEVICES: SpinMutex<[Option<&'static dyn BlockDevice>; MAX_BLOCK_DEVICES]> =
+    SpinMutex::new([None; MAX_BLOCK_DEVICES]);
+
+/// Make `device` available under its name.
+pub fn register(device: &'static dyn BlockDevice) -> Result<(), TooManyDevices> {
+    {
+        let _irq = IrqGuard::new();
+        let mut devices = DEVICES.lock();
+        let slot = devices
+            .iter()
+            .position(Option::is_none)
+            .ok_or(TooManyDevices)?;
+        devices[slot] = Some(device);
+    }
+    info!(
+        "Registered block device {}: {} blocks of {} bytes",
+        device.name(),
+        device.blocks(),
+        device.block_size()
+    );
+    Ok(())
+}
+
+/// The device named `name`, e.g. `nvme0n1`.
+#[allow(dead_code)]
+pub fn lookup(name: &str) -> Option<&'static dyn BlockDevice> {
+    let _irq = IrqGuard::new();
+    DEVICES
+        .lock()
+        .iter()
+        .flatten()
+        .find(|device| device.name() == name)
+        .copied()
+}
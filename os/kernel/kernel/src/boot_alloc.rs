@@ -0,0 +1,137 @@
+//! # Early Boot Allocator
+//!
+//! Between [`_start_kernel`](crate::init::_start_kernel) and the frame
+//! allocator there is no way to allocate memory except statics, yet building
+//! the frame allocator needs memory of its own. [`BootAlloc`] bridges the gap:
+//! a bump allocator over the arena the loader set aside for this purpose (see
+//! [`BootArenaInfo`]).
+//!
+//! ## Lifetime
+//!
+//! 1. [`BootAlloc::new`] takes over the arena right after the logger is up.
+//! 2. Memory management places the normalized [memory map](crate::memmap)
+//!    and the frame allocator itself into it with [`BootAlloc::alloc`].
+//! 3. [`BootAlloc::retire`] ends it. Allocations stay valid forever; the
+//!    frames behind the last one are handed to the frame allocator.
+//!
+//! Allocations are never freed individually, and the arena never grows: it
+//! only holds what early boot needs, and [`BOOT_ARENA_SIZE`] is sized for
+//! that.
+//!
+//! [`BOOT_ARENA_SIZE`]: kernel_info::boot::BOOT_ARENA_SIZE
+
+use crate::memmap::PhysRange;
+use core::fmt;
+use core::mem::MaybeUninit;
+use kernel_info::boot::BootArenaInfo;
+use kernel_info::memory::{HHDM_BASE, HHDM_SIZE};
+
+const PAGE_SIZE: u64 = 4096;
+
+/// Why the loader's arena could not be used, or an allocation failed.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum BootAllocError {
+    /// The loader did not pass an arena.
+    Missing,
+    /// The arena lies outside the HHDM and cannot be accessed.
+    NotMapped,
+    /// The arena is not page aligned.
+    Misaligned,
+    /// The arena has no room for an allocation of this many bytes.
+    OutOfMemory(usize),
+}
+
+impl fmt::Display for BootAllocError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Missing => f.write_str("the loader passed no boot arena"),
+            Self::NotMapped => f.write_str("the boot arena lies outside the HHDM"),
+            Self::Misaligned => f.write_str("the boot arena is not page aligned"),
+            Self::OutOfMemory(size) => write!(f, "no room for {size} bytes in the boot arena"),
+        }
+    }
+}
+
+/// Bump allocator over the loader's arena; see the [module docs](self).
+pub struct BootAlloc {
+    /// The whole arena.
+    arena: PhysRange,
+    /// Physical address of the next free byte.
+    next: u64,
+}
+
+impl BootAlloc {
+    /// Take over the arena described by `info`.
+    ///
+    /// # Errors
+    /// See [`BootAllocError`].
+    ///
+    /// # Safety
+    /// `info` must describe memory nothing else uses, and this must be the
+    /// only allocator over it.
+    pub unsafe fn new(info: &BootArenaInfo) -> Result<Self, BootAllocError> {
+        if info.phys_start == 0 || info.length == 0 {
+            return Err(BootAllocError::Missing);
+        }
+        if !info.phys_start.is_multiple_of(PAGE_SIZE) || !info.length.is_multiple_of(PAGE_SIZE) {
+            return Err(BootAllocError::Misaligned);
+        }
+        let end = info
+            .phys_start
+            .checked_add(info.length)
+            .filter(|&end| end <= HHDM_SIZE)
+            .ok_or(BootAllocError::NotMapped)?;
+
+        Ok(Self {
+            arena: PhysRange {
+                start: info.phys_start,
+                end,
+            },
+            next: info.phys_start,
+        })
+    }
+
+    /// Move `value` into the arena.
+    ///
+    /// # Errors
+    /// [`BootAllocError::OutOfMemory`] if it does not fit.
+    pub fn alloc<T>(&mut self, value: T) -> Result<&'static mut T, BootAllocError> {
+        Ok(self.alloc_uninit::<T>()?.write(value))
+    }
+
+    /// Room for a `T` in the arena.
+    ///
+    /// # Errors
+    /// [`BootAllocError::OutOfMemory`] if it does not fit.
+    pub fn alloc_uninit<T>(&mut self) -> Result<&'static mut MaybeUninit<T>, BootAllocError> {
+        let size = size_of::<T>();
+        let start = self.next.next_multiple_of(align_of::<T>() as u64);
+        let end = start
+            .checked_add(size as u64)
+            .filter(|&end| end <= self.arena.end)
+            .ok_or(BootAllocError::OutOfMemory(size))?;
+        self.next = end;
+
+        // Safety: inside the arena, which only we hand out and which is
+        // mapped through the HHDM.
+        Ok(unsafe { &mut *((HHDM_BASE.as_u64() + start) as *mut MaybeUninit<T>) })
+    }
+
+    /// Stop allocating.
+    ///
+    /// Returns the frames holding allocations, which stay where they are, and
+    /// the frames nothing was allocated from, for the frame allocator.
+    pub const fn retire(self) -> (PhysRange, PhysRange) {
+        let split = self.next.next_multiple_of(PAGE_SIZE);
+        (
+            PhysRange {
+                start: self.arena.start,
+                end: split,
+            },
+            PhysRange {
+                start: split,
+                end: self.arena.end,
+            },
+        )
+    }
+}
@@ -0,0 +1,127 @@
+//! # Boot Modules
+//!
+//! Files the UEFI loader read from the ESP on behalf of the kernel (see the
+//! `module` option of `boot.cfg`), such as the userland bundle, fonts or CPU
+//! microcode. The loader leaves them in `LOADER_DATA` memory, which the kernel
+//! never hands out, and lists them in [`KernelBootInfo::modules`].
+//!
+//! [`init`] maps every module read-only into a window at
+//! [`BOOT_MODULES_OFFSET`] inside the HHDM range; [`find`] then returns a
+//! module's bytes by name.
+
+use crate::alloc::{FlushTlb, try_with_kernel_vmm};
+use crate::init::USERLAND_BOOTSTRAP_BUNDLE;
+use kernel_alloc::vmm::AllocationTarget;
+use kernel_info::boot::{BootModule, KernelBootInfo, MAX_BOOT_MODULES};
+use kernel_info::memory::HHDM_BASE;
+use kernel_memory_addresses::{PageSize, PhysicalAddress, Size4K};
+use kernel_sync::SyncOnceCell;
+use kernel_vmem::VirtualMemoryPageBits;
+use log::{info, warn};
+
+/// Virtual offset inside the HHDM where boot modules are mapped, one after another.
+pub const BOOT_MODULES_OFFSET: u64 = USERLAND_BOOTSTRAP_BUNDLE + (1u64 << 39); // 2.5 TiB inside HHDM range
+
+/// Size of the boot module window; it ends where the MMIO window starts.
+const BOOT_MODULES_WINDOW: u64 = 1u64 << 39;
+
+static MODULES: SyncOnceCell<Modules> = SyncOnceCell::new();
+
+/// A mapped boot module.
+#[derive(Copy, Clone)]
+pub struct Module {
+    info: BootModule,
+    bytes: &'static [u8],
+}
+
+impl Module {
+    /// The name from `boot.cfg`.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        self.info.name()
+    }
+
+    /// The module's contents.
+    #[must_use]
+    pub const fn bytes(&self) -> &'static [u8] {
+        self.bytes
+    }
+}
+
+struct Modules {
+    entries: [Option<Module>; MAX_BOOT_MODULES],
+}
+
+/// Map the boot modules listed in `bi`. Calling this again does nothing.
+///
+/// Modules that cannot be mapped are skipped with a warning.
+///
+/// # Panics
+/// If a module's size does not fit into the address space.
+pub fn init(bi: &KernelBootInfo) {
+    MODULES.get_or_init(|| {
+        let mut modules = Modules {
+            entries: [None; MAX_BOOT_MODULES],
+        };
+        let mut offset = 0;
+
+        for (slot, module) in modules.entries.iter_mut().zip(bi.modules()) {
+            let page_offset = module.bytes_ptr & (Size4K::SIZE - 1);
+            let pa = PhysicalAddress::new(module.bytes_ptr - page_offset);
+            let len = (page_offset + module.length).next_multiple_of(Size4K::SIZE);
+            if module.length == 0 || offset + len > BOOT_MODULES_WINDOW {
+                warn!(
+                    "Skipping boot module {} ({} bytes)",
+                    module.name(),
+                    module.length
+                );
+                continue;
+            }
+
+            let va = HHDM_BASE + BOOT_MODULES_OFFSET + offset;
+            let flags = VirtualMemoryPageBits::default()
+                .with_writable(false)
+                .with_global(true)
+                .with_no_execute(true);
+            let mapped = try_with_kernel_vmm(FlushTlb::OnSuccess, |vmm| {
+                vmm.map_region(AllocationTarget::Kernel, va, pa, len, flags, flags)
+            });
+            if let Err(e) = mapped {
+                warn!("Failed to map boot module {}: {e}", module.name());
+                continue;
+            }
+            offset += len;
+
+            let bytes = unsafe {
+                core::slice::from_raw_parts(
+                    (va.as_u64() + page_offset) as *const u8,
+                    usize::try_from(module.length).expect("boot module too large"),
+                )
+            };
+            info!(
+                "Mapped boot module {} ({} bytes) to {va}",
+                module.name(),
+                module.length
+            );
+            *slot = Some(Module {
+                info: *module,
+                bytes,
+            });
+        }
+        modules
+    });
+}
+
+/// All mapped boot modules.
+pub fn iter() -> impl Iterator<Item = &'static Module> {
+    MODULES
+        .get()
+        .into_iter()
+        .flat_map(|m| m.entries.iter().flatten())
+}
+
+/// The contents of the boot module called `name`, if it was mapped.
+#[must_use]
+pub fn find(name: &str) -> Option<&'static [u8]> {
+    iter().find(|m| m.name() == name).map(Module::bytes)
+}
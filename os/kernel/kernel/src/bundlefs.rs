@@ -0,0 +1,103 @@
+//! # Bundle File System
+//!
+//! A read-only, flat namespace over the userland bundle handed to the kernel
+//! by the UEFI loader. Until a proper VFS exists, this is the only place the
+//! kernel can load programs from.
+//!
+//! ## Path resolution
+//!
+//! Bundle entries are plain file names (e.g. `init`). Paths are resolved by
+//! stripping any leading `/`, so `/init` and `init` name the same entry.
+//! Directories do not exist; the entries are listed with [`len`] and
+//! [`entry`].
+//!
+//! ## Lifetime
+//!
+//! The bundle is mapped once during early boot (see
+//! [`remap_userland_memory`](crate::init), or [`boot_modules`](crate::boot_modules)
+//! for a `userland` module) and never unmapped, so all returned slices are
+//! `'static`.
+//!
+//! ## Pages
+//!
+//! Mapping an entry goes through the [page cache](crate::page_cache), so
+//! processes mapping the same entry share its frames: [`EntryPages`] is the
+//! page source of an entry, and the entry's index serves as its inode
+//! number.
+
+use crate::page_cache::{CacheError, PAGE_LEN, PageKey, PageSource};
+use kernel_sync::SyncOnceCell;
+use log::{debug, info};
+use packer_abi::unbundle::Bundle;
+
+static BUNDLE: SyncOnceCell<Bundle<'static>> = SyncOnceCell::new();
+
+/// Make the (already mapped) userland bundle available for lookups.
+///
+/// # Panics
+/// If the bundle cannot be parsed.
+pub fn mount(slice: &'static [u8]) {
+    let bundle =
+        BUNDLE.get_or_init(|| Bundle::parse(slice).expect("failed to parse userland bundle"));
+    info!("Userland bundle has {num} entries", num = bundle.len());
+
+    for (name, bytes) in (0..bundle.len()).filter_map(|i| bundle.get(i).ok()) {
+        debug!("  {name} ({len} bytes)", len = bytes.len());
+    }
+}
+
+/// Resolve `path` to the contents of a bundle entry.
+///
+/// Returns `None` if the bundle is not mounted or has no such entry.
+pub fn lookup(path: &str) -> Option<&'static [u8]> {
+    find(path).and_then(entry).map(|(_name, bytes)| bytes)
+}
+
+/// Resolve `path` to the index of a bundle entry.
+pub fn find(path: &str) -> Option<usize> {
+    let name = path.trim_start_matches('/');
+    (0..len()).find(|&i| entry(i).is_some_and(|(entry, _bytes)| entry == name))
+}
+
+/// The name and contents of the entry at `index`.
+pub fn entry(index: usize) -> Option<(&'static str, &'static [u8])> {
+    BUNDLE.get()?.get(index).ok()
+}
+
+/// Number of entries; `0` if the bundle is not mounted.
+pub fn len() -> usize {
+    BUNDLE.get().map_or(0, Bundle::len)
+}
+
+/// The pages of the entry at the wrapped index.
+#[derive(Debug, Copy, Clone)]
+pub struct EntryPages(pub usize);
+
+impl EntryPages {
+    fn bytes(self) -> &'static [u8] {
+        entry(self.0).map_or(&[], |(_name, bytes)| bytes)
+    }
+}
+
+impl PageSource for EntryPages {
+    fn key(&self, page: u64) -> PageKey {
+        PageKey::File {
+            inode: self.0 as u64,
+            page,
+        }
+    }
+
+    fn len(&self) -> u64 {
+        self.bytes().len() as u64
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn fill(&self, page: u64, frame: &mut [u8; PAGE_LEN]) -> Result<(), CacheError> {
+        let offset = page as usize * PAGE_LEN;
+        let bytes = self.bytes().get(offset..).ok_or(CacheError::OutOfRange)?;
+        let len = bytes.len().min(PAGE_LEN);
+        frame[..len].copy_from_slice(&bytes[..len]);
+        frame[len..].fill(0);
+        Ok(())
+    }
+}
@@ -0,0 +1,222 @@
+//! # Character Devices
+//!
+//! A [`CharDevice`] is a driver moving a stream of bytes in and out of the
+//! kernel, such as the [virtio console](crate::virtio::console). Drivers
+//! [`register`] their device once it is up; it then shows up as
+//! `/dev/<name>` to [`lookup`] and can be opened through the
+//! [handle table](crate::process::handle).
+//!
+//! ## Blocking
+//!
+//! Devices never block: [`CharDevice::read`] returns what is buffered and
+//! [`CharDevice::write`] what the device took. [`read`] builds the blocking
+//! read on top, waiting on the device's [`CharDevice::readable`] queue, which
+//! the driver wakes when data arrives. Kernel code that must not sleep, like
+//! a debug shell, calls the device directly. A [`CharDevId`] is
+//! [`Pollable`] through the same queue: ready for `POLLIN` while the device
+//! [has input](CharDevice::has_input), and always for `POLLOUT`.
+//!
+//! ## Access
+//!
+//! Opening a device takes the [capabilities](crate::process::caps) it
+//! names in [`CharDevice::capabilities`], such as `CAP_DEVICE_CONSOLE` for
+//! consoles. Descriptors opened before are not affected, so a process
+//! without the capability can still use a console it inherited.
+//!
+//! ## Memory
+//!
+//! A device with memory of its own, like the
+//! [framebuffer](crate::framebuffer::device), hands out its frames through
+//! [`CharDevice::memory_page`], and processes map them with `mmap`.
+//!
+//! ## Lifetime
+//!
+//! Devices are never unregistered, so a [`CharDevId`] stays valid forever.
+
+use crate::poll::Pollable;
+use crate::process::Pid;
+use crate::sched::WaitQueue;
+use core::fmt;
+use kernel_memory_addresses::PhysicalAddress;
+use kernel_sync::{IrqGuard, SpinMutex};
+use log::info;
+use stdlib::syscall_abi::poll::{POLLIN, POLLOUT};
+
+/// Where devices are found by path.
+pub const MOUNT_POINT: &str = "/dev";
+
+/// Maximum number of character devices.
+pub const MAX_CHAR_DEVICES: usize = 8;
+
+/// A driver of a byte stream.
+pub trait CharDevice: Sync {
+    /// The device's name under [`MOUNT_POINT`].
+    fn name(&self) -> &'static str;
+
+    /// Move up to `buf.len()` received bytes into `buf`; returns how many,
+    /// `0` if none are buffered.
+    fn read(&self, buf: &mut [u8]) -> usize;
+
+    /// Send a prefix of `bytes`; returns its length, `0` if the device is
+    /// busy.
+    fn write(&self, bytes: &[u8]) -> usize;
+
+    /// Whether received bytes are buffered, so [`read`](Self::read) would
+    /// return some.
+    fn has_input(&self) -> bool;
+
+    /// Woken by the driver when data arrives.
+    fn readable(&self) -> &WaitQueue;
+
+    /// The [capabilities](crate::process::caps) a process needs to open the
+    /// device; none by default.
+    fn capabilities(&self) -> u64 {
+        0
+    }
+
+    /// Apply the device-specific `request` with `arg`; returns its result,
+    /// `None` if the device does not support it.
+    fn control(&self, request: u64, arg: u64) -> Option<u64> {
+        let _ = (request, arg);
+        None
+    }
+
+    /// The frame holding byte `offset` of the device's memory, for
+    /// [mapping](crate::process::mmap) it into a process; `None` past its
+    /// end or if the device has no memory to map.
+    fn memory_page(&self, offset: u64) -> Option<PhysicalAddress> {
+        let _ = offset;
+        None
+    }
+}
+
+/// A registered character device, identified by its table slot.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct CharDevId(usize);
+
+impl fmt::Display for CharDevId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", MOUNT_POINT, get(*self).name())
+    }
+}
+
+impl Pollable for CharDevId {
+    /// `POLLIN` while the device has input; `POLLOUT` always, as writes
+    /// never block.
+    fn ready(&self, events: u16) -> u16 {
+        let input = events & POLLIN != 0 && get(*self).has_input();
+        (if input { POLLIN } else { 0 }) | (events & POLLOUT)
+    }
+
+    fn register(&self, events: u16, pid: Pid) {
+        if events & POLLIN != 0 {
+            get(*self).readable().add_waiter(pid);
+        }
+    }
+
+    fn unregister(&self, events: u16, pid: Pid) {
+        if events & POLLIN != 0 {
+            get(*self).readable().remove_waiter(pid);
+        }
+    }
+}
+
+/// All [`MAX_CHAR_DEVICES`] slots are taken.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct TooManyDevices;
+
+impl fmt::Display for TooManyDevices {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("too many character devices")
+    }
+}
+
+static DEVICES: SpinMutex<[Option<&'static dyn CharDevice>; MAX_CHAR_DEVICES]> =
+    SpinMutex::new([None; MAX_CHAR_DEVICES]);
+
+/// Make `device` available under its name.
+pub fn register(device: &'static dyn CharDevice) -> Result<CharDevId, TooManyDevices> {
+    let id = {
+        let _irq = IrqGuard::new();
+        let mut devices = DEVICES.lock();
+        let slot = devices
+            .iter()
+            .position(Option::is_none)
+            .ok_or(TooManyDevices)?;
+        devices[slot] = Some(device);
+        CharDevId(slot)
+    };
+    info!("Registered character device {id}");
+    Ok(id)
+}
+
+/// The device at `path`, e.g. `/dev/hvc0`.
+pub fn lookup(path: &str) -> Option<CharDevId> {
+    let name = path.strip_prefix(MOUNT_POINT)?.strip_prefix('/')?;
+    let _irq = IrqGuard::new();
+    DEVICES
+        .lock()
+        .iter()
+        .position(|device| device.is_some_and(|device| device.name() == name))
+        .map(CharDevId)
+}
+
+/// The device registered as `id`.
+///
+/// # Panics
+/// If `id` was not handed out by [`register`].
+pub fn get(id: CharDevId) -> &'static dyn CharDevice {
+    let _irq = IrqGuard::new();
+    DEVICES.lock()[id.0].expect("character device not registered")
+}
+
+/// The device in table slot `index`, if one is registered there; devices
+/// take the lowest free slot, so the first `None` ends the list.
+pub fn nth(index: usize) -> Option<&'static dyn CharDevice> {
+    let _irq = IrqGuard::new();
+    DEVICES.lock().get(index).copied().flatten()
+}
+
+/// Read up to `buf.len()` bytes from `id`, blocking until some arrive.
+///
+/// Returns `0` only if `buf` is empty.
+pub fn read(id: CharDevId, buf: &mut [u8]) -> usize {
+    if buf.is_empty() {
+        return 0;
+    }
+
+    let device = get(id);
+    let mut read = 0;
+    device.readable().wait_until(|| {
+        read = device.read(buf);
+        read > 0
+    });
+    read
+}
+
+/// Apply the device-specific `request` with `arg` to `id`; see
+/// [`CharDevice::control`].
+pub fn control(id: CharDevId, request: u64, arg: u64) -> Option<u64> {
+    get(id).control(request, arg)
+}
+
+/// The frame holding byte `offset` of the memory of `id`; see
+/// [`CharDevice::memory_page`].
+pub fn memory_page(id: CharDevId, offset: u64) -> Option<PhysicalAddress> {
+    get(id).memory_page(offset)
+}
+
+/// Write `bytes` to `id`; returns how many the device took, fewer than
+/// `bytes.len()` only if it stayed busy.
+pub fn write(id: CharDevId, bytes: &[u8]) -> usize {
+    let device = get(id);
+    let mut written = 0;
+    while written < bytes.len() {
+        let n = device.write(&bytes[written..]);
+        if n == 0 {
+            break;
+        }
+        written += n;
+    }
+    written
+}
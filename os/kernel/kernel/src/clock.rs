@@ -0,0 +1,126 @@
+//! # Clock
+//!
+//! Conversion parameters shared by everything that turns raw counters into
+//! time: the TSC frequency, the rate of the timer tick, and the wall clock
+//! time at some TSC value, which the [RTC](crate::rtc) provides at boot.
+//!
+//! The TSC frequency comes with its [source](TscSource) and an error bound
+//! where the source has one: a [measurement against the PIT](crate::pit::measure)
+//! knows how far off it may be, CPUID leaf 15H is exact, and CPUID leaf 16H
+//! only names a nominal frequency.
+//!
+//! The parameters are written once or twice during boot (and possibly again
+//! by a future recalibration) but read on every clock query, so they live in
+//! a [`SeqLock`]: readers never take a lock and only retry if they raced an
+//! update. Userland reads a copy of them from the [clock page](crate::clock_page).
+
+use crate::clock_page;
+use crate::tsc::{TscSource, rdtsc};
+use core::fmt;
+use kernel_sync::SeqLock;
+
+/// A frequency and how far off it may be.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Calibration {
+    pub hz: u64,
+    /// Largest difference to the true frequency; `None` if unknown.
+    pub error_hz: Option<u64>,
+}
+
+impl fmt::Display for Calibration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.error_hz {
+            Some(error_hz) => write!(f, "{} Hz ± {error_hz} Hz", self.hz),
+            None => write!(f, "{} Hz (unverified)", self.hz),
+        }
+    }
+}
+
+/// Clock conversion parameters.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct ClockParams {
+    /// TSC frequency in Hz; `0` until calibrated.
+    pub tsc_hz: u64,
+    /// Error bound of [`Self::tsc_hz`]; `None` if unknown.
+    pub tsc_error_hz: Option<u64>,
+    /// Where [`Self::tsc_hz`] came from; `None` until calibrated.
+    pub tsc_source: Option<TscSource>,
+    /// Timer interrupts per second; `0` until measured.
+    pub timer_hz: u64,
+    /// Seconds since the Unix epoch at [`Self::wall_tsc`]; `0` until set.
+    pub wall_secs: u64,
+    /// TSC value at [`Self::wall_secs`].
+    pub wall_tsc: u64,
+}
+
+static PARAMS: SeqLock<ClockParams> = SeqLock::new(ClockParams {
+    tsc_hz: 0,
+    tsc_error_hz: None,
+    tsc_source: None,
+    timer_hz: 0,
+    wall_secs: 0,
+    wall_tsc: 0,
+});
+
+/// A consistent snapshot of all clock parameters.
+pub fn params() -> ClockParams {
+    PARAMS.read()
+}
+
+/// TSC frequency in Hz, or `0` if not calibrated yet.
+pub fn tsc_hz() -> u64 {
+    params().tsc_hz
+}
+
+/// Timer ticks per second, or `0` if not measured yet.
+pub fn timer_hz() -> u64 {
+    params().timer_hz
+}
+
+/// The calibrated TSC frequency, its error bound and where it came from;
+/// `None` if not calibrated yet.
+pub fn tsc_calibration() -> Option<(TscSource, Calibration)> {
+    let params = params();
+    let tsc = Calibration {
+        hz: params.tsc_hz,
+        error_hz: params.tsc_error_hz,
+    };
+    params.tsc_source.map(|source| (source, tsc))
+}
+
+/// Record the calibrated TSC frequency and where it came from.
+pub fn set_tsc_calibration(source: TscSource, tsc: Calibration) {
+    let mut params = PARAMS.write_irq();
+    params.tsc_hz = tsc.hz;
+    params.tsc_error_hz = tsc.error_hz;
+    params.tsc_source = Some(source);
+    clock_page::publish(&params);
+}
+
+/// Record the measured timer tick rate.
+pub fn set_timer_hz(hz: u64) {
+    PARAMS.write_irq().timer_hz = hz;
+}
+
+/// Record that it was `unix_secs` seconds after the Unix epoch when the TSC
+/// read `tsc`.
+pub fn set_wall_clock(unix_secs: u64, tsc: u64) {
+    let mut params = PARAMS.write_irq();
+    params.wall_secs = unix_secs;
+    params.wall_tsc = tsc;
+    clock_page::publish(&params);
+}
+
+/// Seconds since the Unix epoch, or `None` if the wall clock was never set.
+#[allow(dead_code)]
+pub fn unix_time() -> Option<u64> {
+    let params = params();
+    if params.wall_secs == 0 {
+        return None;
+    }
+    let elapsed = match params.tsc_hz {
+        0 => 0,
+        hz => rdtsc().saturating_sub(params.wall_tsc) / hz,
+    };
+    Some(params.wall_secs + elapsed)
+}
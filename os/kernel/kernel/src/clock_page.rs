@@ -0,0 +1,109 @@
+//! # Clock Page
+//!
+//! The data half of a vDSO: one frame holding a [`ClockPage`], which the
+//! kernel keeps in sync with the [clock parameters](crate::clock) and maps
+//! read-only into every process at [`CLOCK_PAGE_ADDR`]. Userland reads the
+//! TSC and converts it with these parameters, so reading a clock takes no
+//! system call. The conversion itself lives in the standard library
+//! (`stdlib::time`), which every program links anyway, so there is no code
+//! in the page and nothing user-executable the kernel has to provide.
+//!
+//! ## Updates
+//!
+//! [`publish`] writes the page through the HHDM as a sequence lock (see
+//! [`ClockPage`]); [`clock`](crate::clock) calls it while holding its own
+//! write lock, which keeps writers apart. Parameters set before [`init`],
+//! which runs at boot before any process exists, are published by it.
+//!
+//! ## Lifetime
+//!
+//! The frame is allocated once and never freed: the kernel keeps a reference
+//! in the [`frame_table`], and every mapping adds one, which is dropped with
+//! the address space. A fork shares the mapping as it is.
+
+use crate::alloc::{KernelVmm, frame_table, with_kernel_frame_alloc};
+use crate::clock::{self, ClockParams};
+use core::sync::atomic::{Ordering, fence};
+use kernel_alloc::frame_info::FrameOwner;
+use kernel_alloc::phys_mapper::HhdmPhysMapper;
+use kernel_alloc::vmm::AllocationTarget;
+use kernel_memory_addresses::{PageSize, PhysicalPage, Size4K, VirtualAddress};
+use kernel_sync::SyncOnceCell;
+use kernel_vmem::vma::{Vma, VmaKind, VmaPerms, VmaSet};
+use kernel_vmem::{PhysFrameAlloc, PhysMapper, VirtualMemoryPageBits};
+use log::info;
+use stdlib::syscall_abi::{CLOCK_PAGE_ADDR, ClockPage};
+
+static FRAME: SyncOnceCell<PhysicalPage<Size4K>> = SyncOnceCell::new();
+
+/// Allocate the clock page and publish the current clock parameters.
+#[allow(clippy::cast_possible_truncation)]
+pub fn init() {
+    let frame =
+        with_kernel_frame_alloc(PhysFrameAlloc::alloc_4k).expect("no frame for the clock page");
+    unsafe {
+        HhdmPhysMapper
+            .phys_to_mut::<[u8; Size4K::SIZE as usize]>(frame.base())
+            .fill(0);
+    }
+    frame_table().set_owner(frame, FrameOwner::User);
+    FRAME.get_or_init(|| frame);
+
+    publish(&clock::params());
+    info!("Clock page ready");
+}
+
+/// The clock page, once [`init`] allocated it.
+pub fn page() -> Option<&'static ClockPage> {
+    let frame = FRAME.get()?;
+    Some(unsafe { HhdmPhysMapper.phys_to_mut::<ClockPage>(frame.base()) })
+}
+
+/// Copy `params` to the clock page. Callers must keep each other out, e.g.
+/// by holding the write lock of the parameters.
+pub fn publish(params: &ClockParams) {
+    let Some(page) = page() else {
+        return;
+    };
+    let sequence = page.sequence.load(Ordering::Relaxed);
+    page.sequence.store(sequence + 1, Ordering::Relaxed);
+    fence(Ordering::Release);
+
+    page.tsc_hz.store(params.tsc_hz, Ordering::Relaxed);
+    page.wall_secs.store(params.wall_secs, Ordering::Relaxed);
+    page.wall_tsc.store(params.wall_tsc, Ordering::Relaxed);
+
+    page.sequence.store(sequence + 2, Ordering::Release);
+}
+
+/// Map the clock page read-only into the **currently active** address space
+/// and record it in `vmas`.
+///
+/// # Errors
+/// If the memory map is full or the page tables cannot be extended.
+pub fn map<const N: usize>(vmm: &mut KernelVmm, vmas: &mut VmaSet<N>) -> Result<(), ()> {
+    let &frame = FRAME.get().expect("clock page not initialized");
+    let start = VirtualAddress::new(CLOCK_PAGE_ADDR);
+    vmas.insert(Vma::new(
+        start,
+        start + Size4K::SIZE,
+        VmaKind::ClockPage,
+        VmaPerms::RO,
+    ))
+    .map_err(|_| ())?;
+
+    frame_table().share(frame);
+    let mapped = vmm.map_one::<Size4K>(
+        AllocationTarget::User,
+        start,
+        frame.base(),
+        VirtualMemoryPageBits::user_table_wb_exec(),
+        VirtualMemoryPageBits::user_leaf_data_wb().with_writable(false),
+    );
+    if mapped.is_err() {
+        // The kernel's own reference keeps the frame alive.
+        let _ = frame_table().release(frame);
+        return Err(());
+    }
+    Ok(())
+}
@@ -0,0 +1,329 @@
+//! # Kernel Command Line
+//!
+//! The UEFI loader passes the `cmdline` option of its `boot.cfg` through
+//! [`KernelBootInfo::cmdline_ptr`]. [`init`] copies it into kernel memory
+//! early during boot, so it stays available after the loader's memory is
+//! reclaimed; [`get_str`] and friends then look up options by name.
+//!
+//! Under QEMU, the host can add options without touching the ESP image by
+//! passing a [`fw_cfg`] file `opt/os/cmdline`; its contents are appended, so
+//! they override the loader's options.
+//!
+//! ## Syntax
+//!
+//! The command line is a whitespace-separated list of options, each either
+//! `key=value` or a bare `flag`. Values containing whitespace are enclosed in
+//! double quotes (`greeting="hello world"`). If a key appears more than once,
+//! the last occurrence wins.
+//!
+//! ## Registered options
+//!
+//! Subsystems describe the options they read as a [`Param`] next to the code
+//! that consumes them, and list it in [`PARAMS`]. At boot, [`init`] checks
+//! every option on the command line against this list and warns about
+//! unknown keys and values of the wrong type; the lookups themselves never
+//! fail on bad input but fall back to the subsystem's default.
+//!
+//! | Key               | Type   | Consumer                                             |
+//! |-------------------|--------|------------------------------------------------------|
+//! | `loglevel`        | string | [`klog`](crate::klog): name or number `0..=5`        |
+//! | `console`         | string | [`klog`](crate::klog): log sinks                     |
+//! | `nosmp`           | flag   | [`per_cpu`](crate::per_cpu): BSP only                |
+//! | `tick_hz`         | number | [`apic`](crate::apic): timer tick rate               |
+//! | `pit_tick`        | flag   | [`pit`](crate::pit): timer tick from the PIT         |
+//! | `rtc_tick`        | flag   | [`rtc`](crate::rtc): timer tick from the RTC         |
+//! | `watchdog_thresh` | number | [`watchdog`](crate::watchdog): seconds, `0` disables |
+//! | `profile`         | number | [`profiler`](crate::profiler): samples per second    |
+//! | `trace`           | string | [`tracepoint`](crate::tracepoint): event classes     |
+//! | `strace_init`     | flag   | [`strace`](crate::strace): trace init's syscalls     |
+//! | `splash`          | string | [`splash`](crate::framebuffer::splash): boot image   |
+//! | `font`            | string | [`font`](crate::framebuffer::font): console font     |
+//! | `keymap`          | string | [`keyboard`](crate::keyboard): keyboard layout       |
+//! | `stack_limit`     | number | `process::stack_growth`: user stack size in KiB      |
+//! | `aslr`            | switch | `process::layout`: randomize user layouts            |
+//! | `failalloc`       | string | `alloc::fault_inject`: failure mode                  |
+//! | `failalloc_skip`  | string | `alloc::fault_inject`: exempt call sites             |
+//! | `poison_unmap`    | number | `alloc::poison`: unmap one in `n` freed frames       |
+//!
+//! The `failalloc` options only exist with the `fault-inject` feature,
+//! `poison_unmap` only with the `poison` feature.
+
+use crate::{apic, fw_cfg, klog, per_cpu, pit, profiler, rtc, strace, tracepoint, watchdog};
+use kernel_info::boot::KernelBootInfo;
+use kernel_info::memory::{HHDM_BASE, HHDM_SIZE};
+use kernel_sync::SyncOnceCell;
+use log::{info, warn};
+
+/// Longest command line kept; the rest is cut off.
+pub const MAX_CMDLINE_LEN: usize = 4096;
+
+/// The [`fw_cfg`] file whose options are appended.
+const FW_CFG_FILE: &str = "opt/os/cmdline";
+
+static CMDLINE: SyncOnceCell<Cmdline> = SyncOnceCell::new();
+
+/// Every option the kernel understands.
+pub static PARAMS: &[&Param] = &[
+    &klog::LOGLEVEL_PARAM,
+    &klog::CONSOLE_PARAM,
+    &per_cpu::NOSMP_PARAM,
+    &apic::TICK_HZ_PARAM,
+    &pit::PIT_TICK_PARAM,
+    &rtc::RTC_TICK_PARAM,
+    &watchdog::WATCHDOG_THRESH_PARAM,
+    &profiler::PROFILE_PARAM,
+    &tracepoint::TRACE_PARAM,
+    &strace::STRACE_INIT_PARAM,
+    &crate::framebuffer::splash::SPLASH_PARAM,
+    &crate::framebuffer::font::FONT_PARAM,
+    &crate::keyboard::KEYMAP_PARAM,
+    &crate::process::stack_growth::STACK_LIMIT_PARAM,
+    &crate::process::layout::ASLR_PARAM,
+    #[cfg(feature = "fault-inject")]
+    &crate::alloc::fault_inject::FAILALLOC_PARAM,
+    #[cfg(feature = "fault-inject")]
+    &crate::alloc::fault_inject::FAILALLOC_SKIP_PARAM,
+    #[cfg(feature = "poison")]
+    &crate::alloc::poison::POISON_UNMAP_PARAM,
+];
+
+/// The type of a registered option's value.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ParamKind {
+    /// A bare `key`, read with [`flag`].
+    Flag,
+    /// A switch (`key`, `key=on`, `key=off`, ...), read with [`get_bool`].
+    Bool,
+    /// An unsigned number, read with [`get_u64`].
+    U64,
+    /// Any text, read with [`get_str`].
+    Str,
+}
+
+/// A command line option a subsystem reads; see the [module docs](self).
+#[derive(Debug)]
+pub struct Param {
+    pub name: &'static str,
+    pub kind: ParamKind,
+    /// One-line description.
+    #[allow(dead_code)]
+    pub help: &'static str,
+}
+
+/// A copy of the boot command line.
+struct Cmdline {
+    bytes: [u8; MAX_CMDLINE_LEN],
+    len: usize,
+}
+
+impl Cmdline {
+    fn as_str(&self) -> &str {
+        // Cutting off at MAX_CMDLINE_LEN may split a character.
+        let bytes = &self.bytes[..self.len];
+        core::str::from_utf8(bytes).unwrap_or_else(|e| {
+            // SAFETY: The prefix up to `valid_up_to` was just validated.
+            unsafe { core::str::from_utf8_unchecked(&bytes[..e.valid_up_to()]) }
+        })
+    }
+}
+
+/// Copy the command line out of the loader's memory, append the host's
+/// options from [`fw_cfg`] and check them all against [`PARAMS`].
+///
+/// Must be called while the loader's HHDM mapping is still present. Calling
+/// it again does nothing.
+pub fn init(bi: &KernelBootInfo) {
+    let cmdline = CMDLINE.get_or_init(|| {
+        let mut cmdline = from_loader(bi);
+        append_fw_cfg(&mut cmdline);
+        cmdline
+    });
+    info!("Kernel command line: {:?}", cmdline.as_str());
+
+    for (key, value) in options() {
+        let Some(param) = PARAMS.iter().find(|p| p.name == key) else {
+            warn!("Unknown kernel command line option `{key}`");
+            continue;
+        };
+        let valid = match (param.kind, value) {
+            (ParamKind::Flag, value) => value.is_none(),
+            (ParamKind::Bool, None) => true,
+            (ParamKind::Bool, Some(value)) => parse_bool(value).is_some(),
+            (ParamKind::U64, value) => value.and_then(parse_u64).is_some(),
+            (ParamKind::Str, value) => value.is_some(),
+        };
+        if !valid {
+            warn!(
+                "Invalid value {value:?} for kernel command line option `{key}` ({:?})",
+                param.kind
+            );
+        }
+    }
+}
+
+/// The command line the loader passed in `bi`.
+fn from_loader(bi: &KernelBootInfo) -> Cmdline {
+    let mut cmdline = Cmdline {
+        bytes: [0; MAX_CMDLINE_LEN],
+        len: 0,
+    };
+    let Some((ptr, len)) = bi.cmdline() else {
+        return cmdline;
+    };
+    let end = ptr.saturating_add(len);
+    let len = usize::try_from(len).unwrap_or(usize::MAX);
+    if end > HHDM_SIZE {
+        warn!("Kernel command line lies outside the HHDM; ignoring it");
+        return cmdline;
+    }
+    if len > MAX_CMDLINE_LEN {
+        warn!("Kernel command line exceeds {MAX_CMDLINE_LEN} bytes; truncating");
+    }
+
+    cmdline.len = len.min(MAX_CMDLINE_LEN);
+    let src = (HHDM_BASE + ptr).as_u64() as *const u8;
+    // SAFETY: The loader maps all memory below HHDM_SIZE into the HHDM.
+    unsafe {
+        core::ptr::copy_nonoverlapping(src, cmdline.bytes.as_mut_ptr(), cmdline.len);
+    }
+    cmdline
+}
+
+/// Append the options of the [`FW_CFG_FILE`], if the host passed one.
+fn append_fw_cfg(cmdline: &mut Cmdline) {
+    let Some(file) = fw_cfg::find(FW_CFG_FILE) else {
+        return;
+    };
+    // One separating space, unless there is nothing to separate.
+    let start = if cmdline.len == 0 { 0 } else { cmdline.len + 1 };
+    if start >= MAX_CMDLINE_LEN {
+        warn!("Kernel command line is full; ignoring {FW_CFG_FILE}");
+        return;
+    }
+    if start > 0 {
+        cmdline.bytes[cmdline.len] = b' ';
+    }
+    let rest = &mut cmdline.bytes[start..];
+    let read = fw_cfg::read(file, rest);
+    if read < file.size() as usize {
+        warn!("Kernel command line exceeds {MAX_CMDLINE_LEN} bytes; truncating {FW_CFG_FILE}");
+    }
+    // Strings passed with `string=` may end in a NUL.
+    let added = rest[..read]
+        .iter()
+        .rposition(|&b| b != 0)
+        .map_or(0, |i| i + 1);
+    cmdline.len = start + added;
+    info!("Appended {added} bytes of kernel command line from {FW_CFG_FILE}");
+}
+
+/// The whole command line; empty before [`init`] or if none was passed.
+#[must_use]
+#[allow(dead_code)]
+pub fn as_str() -> &'static str {
+    CMDLINE.get().map_or("", Cmdline::as_str)
+}
+
+/// All options in order, as `(key, value)` pairs; flags have no value.
+pub fn options() -> impl Iterator<Item = (&'static str, Option<&'static str>)> {
+    Options {
+        rest: CMDLINE.get().map_or("", Cmdline::as_str),
+    }
+}
+
+/// The value of `key`, or `None` if it is absent or a bare flag.
+#[must_use]
+pub fn get_str(key: &str) -> Option<&'static str> {
+    options()
+        .filter(|(k, _)| *k == key)
+        .last()
+        .and_then(|(_, v)| v)
+}
+
+/// Whether `key` is present, either as a flag or with any value.
+#[must_use]
+pub fn flag(key: &str) -> bool {
+    options().any(|(k, _)| k == key)
+}
+
+/// The value of `key` as a switch: `on`/`off`, `true`/`false`, `yes`/`no`
+/// or `1`/`0`. A bare flag counts as `true`.
+///
+/// Returns `None` if `key` is absent or its value is no switch.
+#[must_use]
+pub fn get_bool(key: &str) -> Option<bool> {
+    let (_, value) = options().filter(|(k, _)| *k == key).last()?;
+    value.map_or(Some(true), parse_bool)
+}
+
+/// The value of `key` as an unsigned number, either decimal or `0x`-prefixed
+/// hexadecimal.
+///
+/// Returns `None` if `key` is absent or its value is no number.
+#[must_use]
+pub fn get_u64(key: &str) -> Option<u64> {
+    get_str(key).and_then(parse_u64)
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    if ["on", "true", "yes", "1"]
+        .iter()
+        .any(|s| value.eq_ignore_ascii_case(s))
+    {
+        Some(true)
+    } else if ["off", "false", "no", "0"]
+        .iter()
+        .any(|s| value.eq_ignore_ascii_case(s))
+    {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+fn parse_u64(value: &str) -> Option<u64> {
+    value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+        .map_or_else(
+            || value.parse().ok(),
+            |hex| u64::from_str_radix(hex, 16).ok(),
+        )
+}
+
+/// Iterator over the options of a command line.
+struct Options<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Iterator for Options<'a> {
+    type Item = (&'a str, Option<&'a str>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let rest = self.rest.trim_start();
+        if rest.is_empty() {
+            self.rest = rest;
+            return None;
+        }
+
+        let key_end = rest
+            .find(|c: char| c == '=' || c.is_whitespace())
+            .unwrap_or(rest.len());
+        let key = &rest[..key_end];
+        let Some(after) = rest[key_end..].strip_prefix('=') else {
+            self.rest = &rest[key_end..];
+            return Some((key, None));
+        };
+
+        let (value, rest) = after.strip_prefix('"').map_or_else(
+            || after.split_at(after.find(char::is_whitespace).unwrap_or(after.len())),
+            |quoted| {
+                // An unterminated quote runs to the end of the line.
+                let end = quoted.find('"').unwrap_or(quoted.len());
+                (&quoted[..end], quoted.get(end + 1..).unwrap_or(""))
+            },
+        );
+        self.rest = rest;
+        Some((key, Some(value)))
+    }
+}
@@ -20,10 +20,17 @@
 //!   and maximum supported leaf numbers for both basic and extended functions
 //! * **Leaf 01H** ([`Leaf01h`]): Core feature flags, family/model/stepping info,
 //!   and processor capabilities (SSE, AVX, x2APIC, etc.)
+//! * **Leaf 05H** ([`Leaf05h`]): MONITOR/MWAIT line sizes and supported C-states
+//! * **Leaf 07H** ([`Leaf07h`]): Structured extended feature flags (e.g. `INVPCID`)
+//! * **Leaf 0AH** ([`Leaf0Ah`]): Architectural performance counters and events
+//! * **Leaf 0DH** ([`Leaf0Dh`]): `XSAVE` state components and save area sizes
 //! * **Leaf 15H** ([`Leaf15h`]): TSC (Time Stamp Counter) frequency information
 //!   with crystal oscillator frequency and ratio calculations
 //! * **Leaf 16H** ([`Leaf16`]): Processor frequency information including base,
 //!   maximum, and bus frequencies (Intel advisory data)
+//! * **Leaf 40000000H** ([`Hypervisor`]): Hypervisor vendor and leaf range,
+//!   with the timing leaf 40000010H ([`HypervisorTiming`]) and the Hyper-V
+//!   frequency MSR feature bits
 //!
 //! ## Key Features
 //!
@@ -79,15 +86,25 @@
 
 #![allow(dead_code)]
 
+mod hypervisor;
 mod leaf01h;
+mod leaf05h;
+mod leaf07h;
+mod leaf0ah;
+mod leaf0dh;
 mod leaf15h;
 mod leaf16h;
 mod ranges;
 
+pub use hypervisor::{Hypervisor, HypervisorTiming, HypervisorVendor};
+pub use leaf0ah::Leaf0Ah;
+pub use leaf0dh::Leaf0Dh;
 pub use leaf01h::Leaf01h;
+pub use leaf05h::Leaf05h;
+pub use leaf07h::Leaf07h;
 pub use leaf15h::Leaf15h;
 pub use leaf16h::Leaf16;
-pub use ranges::CpuidRanges;
+pub use ranges::{CpuVendor, CpuidRanges};
 
 /// Execute CPUID with the given leaf and subleaf.
 ///
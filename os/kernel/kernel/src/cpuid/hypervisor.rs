@@ -0,0 +1,153 @@
+use crate::cpuid::{CpuidResult, Leaf01h, cpuid};
+
+/// Hypervisor vendor signature and highest hypervisor leaf.
+pub const LEAF_4000_0000H: u32 = 0x4000_0000;
+
+/// Hyper-V feature identification.
+pub const LEAF_4000_0003H: u32 = 0x4000_0003;
+
+/// Timing information: TSC and LAPIC bus frequencies.
+pub const LEAF_4000_0010H: u32 = 0x4000_0010;
+
+/// Who runs the kernel, by the signature of leaf `0x40000000`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum HypervisorVendor {
+    Kvm,
+    /// QEMU without acceleration.
+    Tcg,
+    HyperV,
+    VMware,
+    VirtualBox,
+    Xen,
+    Other,
+}
+
+impl HypervisorVendor {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Kvm => "KVM",
+            Self::Tcg => "TCG",
+            Self::HyperV => "Hyper-V",
+            Self::VMware => "VMware",
+            Self::VirtualBox => "VirtualBox",
+            Self::Xen => "Xen",
+            Self::Other => "Other",
+        }
+    }
+}
+
+/// CPUID.40000000H — Hypervisor identification.
+///
+/// The range `0x40000000..=0x400000FF` is reserved for hypervisors and
+/// only meaningful if CPUID.01H:ECX.hypervisor is set.
+///
+/// Reference: Linux `Documentation/virt/kvm/x86/cpuid.rst`; Microsoft
+/// Hypervisor Top Level Functional Specification, "Feature Discovery".
+#[derive(Copy, Clone, Debug)]
+pub struct Hypervisor {
+    pub vendor: HypervisorVendor,
+    /// Highest hypervisor leaf (EAX).
+    pub max_leaf: u32,
+}
+
+impl Hypervisor {
+    /// Query CPUID.40000000H; None on bare metal.
+    pub unsafe fn read(leaf1: &Leaf01h) -> Option<Self> {
+        if !leaf1.has_hypervisor() {
+            return None;
+        }
+
+        unsafe {
+            let r = cpuid(LEAF_4000_0000H, 0);
+            Some(Self::from(r))
+        }
+    }
+
+    /// # Safety
+    /// The caller must ensure that the passed [`CpuidResult`] belongs to a valid leaf `0x40000000` entry.
+    pub unsafe fn from(r: CpuidResult) -> Self {
+        let mut signature = [0u8; 12];
+        signature[..4].copy_from_slice(&r.ebx.to_le_bytes());
+        signature[4..8].copy_from_slice(&r.ecx.to_le_bytes());
+        signature[8..].copy_from_slice(&r.edx.to_le_bytes());
+
+        let vendor = match &signature {
+            b"KVMKVMKVM\0\0\0" => HypervisorVendor::Kvm,
+            b"TCGTCGTCGTCG" => HypervisorVendor::Tcg,
+            b"Microsoft Hv" => HypervisorVendor::HyperV,
+            b"VMwareVMware" => HypervisorVendor::VMware,
+            b"VBoxVBoxVBox" => HypervisorVendor::VirtualBox,
+            b"XenVMMXenVMM" => HypervisorVendor::Xen,
+            _ => HypervisorVendor::Other,
+        };
+
+        // Early KVM reports 0, meaning 0x40000001.
+        let max_leaf = r.eax.max(LEAF_4000_0000H + 1);
+        Self { vendor, max_leaf }
+    }
+
+    #[inline]
+    pub const fn has_leaf(self, leaf: u32) -> bool {
+        leaf >= LEAF_4000_0000H && leaf <= self.max_leaf
+    }
+
+    /// Query CPUID.40000010H if the hypervisor has it.
+    pub unsafe fn timing(self) -> Option<HypervisorTiming> {
+        if !self.has_leaf(LEAF_4000_0010H) {
+            return None;
+        }
+
+        unsafe {
+            let r = cpuid(LEAF_4000_0010H, 0);
+            Some(HypervisorTiming::from(r))
+        }
+    }
+
+    /// Hyper-V lets the guest read the TSC and LAPIC frequencies from MSRs
+    /// `0x40000022` and `0x40000023`: CPUID.40000003H EAX bit 11 grants
+    /// access, EDX bit 8 says they hold values.
+    pub unsafe fn has_hyperv_frequency_msrs(self) -> bool {
+        if self.vendor != HypervisorVendor::HyperV || !self.has_leaf(LEAF_4000_0003H) {
+            return false;
+        }
+
+        let r = unsafe { cpuid(LEAF_4000_0003H, 0) };
+        r.eax & (1 << 11) != 0 && r.edx & (1 << 8) != 0
+    }
+}
+
+/// CPUID.40000010H — Timing information, as defined by VMware and also
+/// offered by KVM/QEMU (`vmware-cpuid-freq`, with an invariant TSC).
+///
+/// - EAX = TSC frequency in kHz
+/// - EBX = LAPIC bus frequency in kHz
+#[derive(Copy, Clone, Debug)]
+pub struct HypervisorTiming {
+    /// TSC frequency in kHz (EAX). 0 means "not reported".
+    pub tsc_khz: u32,
+    /// LAPIC bus frequency in kHz (EBX). 0 means "not reported".
+    pub apic_bus_khz: u32,
+}
+
+impl HypervisorTiming {
+    /// # Safety
+    /// The caller must ensure that the passed [`CpuidResult`] belongs to a valid leaf `0x40000010` entry.
+    pub const unsafe fn from(r: CpuidResult) -> Self {
+        Self {
+            tsc_khz: r.eax,
+            apic_bus_khz: r.ebx,
+        }
+    }
+
+    /// TSC frequency in Hz (if EAX was non-zero).
+    #[inline]
+    pub fn tsc_hz(self) -> Option<u64> {
+        (self.tsc_khz != 0).then(|| u64::from(self.tsc_khz) * 1_000)
+    }
+
+    /// LAPIC bus frequency in Hz (if EBX was non-zero).
+    #[inline]
+    pub fn apic_bus_hz(self) -> Option<u64> {
+        (self.apic_bus_khz != 0).then(|| u64::from(self.apic_bus_khz) * 1_000)
+    }
+}
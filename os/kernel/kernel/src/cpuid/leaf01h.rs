@@ -57,6 +57,58 @@ impl Leaf01h {
         self.ecx.x2apic()
     }
 
+    #[inline]
+    pub const fn has_monitor(&self) -> bool {
+        self.ecx.monitor()
+    }
+
+    #[inline]
+    pub const fn has_pcid(&self) -> bool {
+        self.ecx.pcid()
+    }
+
+    #[inline]
+    pub const fn has_mce(&self) -> bool {
+        self.edx.mce()
+    }
+
+    /// Thermal monitor MSRs and the thermal sensor interrupt.
+    #[inline]
+    pub const fn has_thermal_monitor(&self) -> bool {
+        self.edx.acpi()
+    }
+
+    #[inline]
+    pub const fn has_pat(&self) -> bool {
+        self.edx.pat()
+    }
+
+    #[inline]
+    pub const fn has_fxsr(&self) -> bool {
+        self.edx.fxsr()
+    }
+
+    #[inline]
+    pub const fn has_xsave(&self) -> bool {
+        self.ecx.xsave()
+    }
+
+    #[inline]
+    pub const fn has_avx(&self) -> bool {
+        self.ecx.avx()
+    }
+
+    #[inline]
+    pub const fn has_rdrand(&self) -> bool {
+        self.ecx.rdrand()
+    }
+
+    /// Running under a hypervisor; see [`Hypervisor`](crate::cpuid::Hypervisor).
+    #[inline]
+    pub const fn has_hypervisor(&self) -> bool {
+        self.ecx.hypervisor()
+    }
+
     #[inline]
     pub const fn avx_usable(&self) -> bool {
         self.ecx.avx() && self.ecx.xsave() && self.ecx.osxsave()
@@ -0,0 +1,74 @@
+use crate::cpuid::{CpuidRanges, CpuidResult, cpuid};
+
+pub const LEAF_05H: u32 = 0x05;
+
+/// CPUID.05H — MONITOR/MWAIT parameters.
+///
+/// Reports the monitor line sizes, whether MWAIT extensions are available and
+/// how many sub-states each C-state supports when entered via MWAIT.
+///
+/// Reference: Intel SDM Vol. 2A, CPUID leaf 05H.
+#[derive(Copy, Clone, Debug)]
+pub struct Leaf05h {
+    pub smallest_line: u16, // EAX[15:0]
+    pub largest_line: u16,  // EBX[15:0]
+    pub ecx: u32,
+    /// Number of MWAIT sub-states of C0..C7, one nibble each.
+    pub edx: u32,
+}
+
+impl Leaf05h {
+    /// Query CPUID.05H if available; None if leaf unsupported.
+    pub unsafe fn read(ranges: &CpuidRanges) -> Option<Self> {
+        if !ranges.has_basic(LEAF_05H) {
+            return None;
+        }
+
+        unsafe {
+            let r = cpuid(LEAF_05H, 0);
+            Some(Self::from(r))
+        }
+    }
+
+    /// # Safety
+    /// The caller must ensure that the passed [`CpuidResult`] belongs to a valid leaf `0x05` entry.
+    #[allow(clippy::cast_possible_truncation)]
+    pub const unsafe fn from(r: CpuidResult) -> Self {
+        Self {
+            smallest_line: r.eax as u16,
+            largest_line: r.ebx as u16,
+            ecx: r.ecx,
+            edx: r.edx,
+        }
+    }
+
+    /// Whether the sub-state enumeration in EDX is valid (ECX bit 0).
+    #[inline]
+    pub const fn has_extensions(&self) -> bool {
+        self.ecx & 1 != 0
+    }
+
+    /// Whether interrupts break MWAIT even with `IF=0` (ECX bit 1).
+    #[inline]
+    pub const fn interrupts_break(&self) -> bool {
+        self.ecx & 2 != 0
+    }
+
+    /// Number of MWAIT sub-states supported for C-state `cstate` (0..=7).
+    #[inline]
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn sub_states(&self, cstate: u8) -> u8 {
+        if cstate > 7 {
+            return 0;
+        }
+        ((self.edx >> (cstate * 4)) & 0xF) as u8
+    }
+
+    /// The deepest C-state (1..=7) with at least one MWAIT sub-state.
+    pub fn deepest_cstate(&self) -> Option<u8> {
+        if !self.has_extensions() {
+            return None;
+        }
+        (1..=7).rev().find(|&c| self.sub_states(c) > 0)
+    }
+}
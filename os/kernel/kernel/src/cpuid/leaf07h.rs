@@ -0,0 +1,51 @@
+use crate::cpuid::{CpuidRanges, CpuidResult, cpuid};
+
+pub const LEAF_07H: u32 = 0x07;
+
+/// CPUID.07H.0 — Structured Extended Feature Flags.
+///
+/// Only the raw registers are kept; accessors cover the bits the kernel uses.
+///
+/// Reference: Intel SDM Vol. 2A, CPUID leaf 07H.
+#[derive(Copy, Clone, Debug)]
+pub struct Leaf07h {
+    pub ebx: u32,
+    pub ecx: u32,
+    pub edx: u32,
+}
+
+impl Leaf07h {
+    /// Query CPUID.07H (sub-leaf 0) if available; None if leaf unsupported.
+    pub unsafe fn read(ranges: &CpuidRanges) -> Option<Self> {
+        if !ranges.has_basic(LEAF_07H) {
+            return None;
+        }
+
+        unsafe {
+            let r = cpuid(LEAF_07H, 0);
+            Some(Self::from(r))
+        }
+    }
+
+    /// # Safety
+    /// The caller must ensure that the passed [`CpuidResult`] belongs to a valid leaf `0x07` entry.
+    pub const unsafe fn from(r: CpuidResult) -> Self {
+        Self {
+            ebx: r.ebx,
+            ecx: r.ecx,
+            edx: r.edx,
+        }
+    }
+
+    /// `INVPCID` is supported (EBX bit 10).
+    #[inline]
+    pub const fn has_invpcid(&self) -> bool {
+        self.ebx & (1 << 10) != 0
+    }
+
+    /// `RDSEED` is supported (EBX bit 18).
+    #[inline]
+    pub const fn has_rdseed(&self) -> bool {
+        self.ebx & (1 << 18) != 0
+    }
+}
@@ -0,0 +1,69 @@
+use crate::cpuid::{CpuidRanges, CpuidResult, cpuid};
+
+pub const LEAF_0AH: u32 = 0x0A;
+
+/// CPUID.0AH — Architectural Performance Monitoring.
+///
+/// Describes the general-purpose performance counters and which
+/// architectural events they can count.
+///
+/// Reference: Intel SDM Vol. 2A, CPUID leaf 0AH; Vol. 3B, §20.2.
+#[derive(Copy, Clone, Debug)]
+pub struct Leaf0Ah {
+    pub eax: u32,
+    /// Architectural events that are *not* available, one bit each.
+    pub ebx: u32,
+    pub edx: u32,
+}
+
+impl Leaf0Ah {
+    /// Query CPUID.0AH if available; None if leaf unsupported.
+    pub unsafe fn read(ranges: &CpuidRanges) -> Option<Self> {
+        if !ranges.has_basic(LEAF_0AH) {
+            return None;
+        }
+
+        unsafe {
+            let r = cpuid(LEAF_0AH, 0);
+            Some(Self::from(r))
+        }
+    }
+
+    /// # Safety
+    /// The caller must ensure that the passed [`CpuidResult`] belongs to a valid leaf `0x0A` entry.
+    pub const unsafe fn from(r: CpuidResult) -> Self {
+        Self {
+            eax: r.eax,
+            ebx: r.ebx,
+            edx: r.edx,
+        }
+    }
+
+    /// Architectural performance monitoring version (EAX[7:0]); `0` if absent.
+    #[inline]
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn version(&self) -> u8 {
+        self.eax as u8
+    }
+
+    /// Number of general-purpose counters per logical processor (EAX[15:8]).
+    #[inline]
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn counters(&self) -> u8 {
+        (self.eax >> 8) as u8
+    }
+
+    /// Bit width of the general-purpose counters (EAX[23:16]).
+    #[inline]
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn counter_width(&self) -> u8 {
+        (self.eax >> 16) as u8
+    }
+
+    /// Whether the "unhalted core cycles" event can be counted (EBX bit 0 clear).
+    #[inline]
+    pub const fn has_core_cycles(&self) -> bool {
+        let enumerated = (self.eax >> 24) & 0xFF;
+        enumerated > 0 && self.ebx & 1 == 0
+    }
+}
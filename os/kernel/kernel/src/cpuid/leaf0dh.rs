@@ -0,0 +1,44 @@
+use crate::cpuid::{CpuidRanges, CpuidResult, cpuid};
+
+pub const LEAF_0DH: u32 = 0x0D;
+
+/// CPUID.0DH.0 — Processor Extended State Enumeration (main sub-leaf).
+///
+/// Reports which state components `XSAVE` can manage and how large the save
+/// area is. `enabled_size` depends on the current value of `XCR0`, so read the
+/// leaf again after changing it.
+///
+/// Reference: Intel SDM Vol. 2A, CPUID leaf 0DH.
+#[derive(Copy, Clone, Debug)]
+pub struct Leaf0Dh {
+    /// Bitmap of the `XCR0` components the CPU supports (EDX:EAX).
+    pub supported_xcr0: u64,
+    /// Save area size in bytes for the components enabled in `XCR0` (EBX).
+    pub enabled_size: u32,
+    /// Save area size in bytes for all supported components (ECX).
+    pub max_size: u32,
+}
+
+impl Leaf0Dh {
+    /// Query CPUID.0DH (sub-leaf 0) if available; None if leaf unsupported.
+    pub unsafe fn read(ranges: &CpuidRanges) -> Option<Self> {
+        if !ranges.has_basic(LEAF_0DH) {
+            return None;
+        }
+
+        unsafe {
+            let r = cpuid(LEAF_0DH, 0);
+            Some(Self::from(r))
+        }
+    }
+
+    /// # Safety
+    /// The caller must ensure that the passed [`CpuidResult`] belongs to a valid leaf `0x0D` entry.
+    pub const unsafe fn from(r: CpuidResult) -> Self {
+        Self {
+            supported_xcr0: (r.edx as u64) << 32 | r.eax as u64,
+            enabled_size: r.ebx,
+            max_size: r.ecx,
+        }
+    }
+}
@@ -0,0 +1,73 @@
+//! # Directories
+//!
+//! There is no directory tree yet; the few directories there are follow from
+//! where the file systems are mounted:
+//!
+//! | Path          | Entries                                                 |
+//! |---------------|---------------------------------------------------------|
+//! | `/`           | The [bundle](crate::bundlefs) entries, `dev` and `proc` |
+//! | `/dev`        | The registered [character devices](crate::chardev)      |
+//! | `/proc`       | The [`procfs`] files and `self`                         |
+//! | `/proc/<pid>` | The files of a process, e.g. `maps`                     |
+//!
+//! [`read`] describes one entry at a time, addressed by its position in the
+//! listing; the listing changes only as devices are registered.
+
+use crate::{bundlefs, chardev, procfs};
+use stdlib::syscall_abi::DirEntry;
+use stdlib::syscall_abi::dirent::{KIND_DEVICE, KIND_DIR, KIND_FILE};
+
+/// The directories listed after the bundle entries in `/`.
+const ROOT_DIRS: [&str; 2] = ["dev", "proc"];
+
+/// Entry `index` of the directory at `path`, and the index of the next
+/// entry; `None` past the last entry or if there is no such directory.
+pub fn read(path: &str, index: usize) -> Option<(DirEntry, usize)> {
+    let path = path.trim_end_matches('/');
+    let entry = if path.is_empty() {
+        root(index)?
+    } else if path == chardev::MOUNT_POINT {
+        entry(KIND_DEVICE, chardev::nth(index)?.name(), 0)
+    } else if path == procfs::MOUNT_POINT {
+        match procfs::FILES.get(index) {
+            Some(name) => entry(KIND_FILE, name, 0),
+            None if index == procfs::FILES.len() => entry(KIND_DIR, "self", 0),
+            None => return None,
+        }
+    } else if is_process_dir(path) {
+        entry(KIND_FILE, procfs::PROCESS_FILES.get(index)?, 0)
+    } else {
+        return None;
+    };
+    Some((entry, index + 1))
+}
+
+/// Entry `index` of `/`.
+fn root(index: usize) -> Option<DirEntry> {
+    let bundled = bundlefs::len();
+    if index < bundled {
+        let (name, bytes) = bundlefs::entry(index)?;
+        Some(entry(KIND_FILE, name, bytes.len()))
+    } else {
+        Some(entry(KIND_DIR, ROOT_DIRS.get(index - bundled)?, 0))
+    }
+}
+
+/// Whether `path` is `/proc/self` or `/proc/<pid>`.
+fn is_process_dir(path: &str) -> bool {
+    path.strip_prefix(procfs::MOUNT_POINT)
+        .and_then(|rest| rest.strip_prefix('/'))
+        .is_some_and(|name| name == "self" || name.parse::<u64>().is_ok())
+}
+
+/// An entry of `kind` called `name`, cut to the length the record holds.
+fn entry(kind: u32, name: &str, size: usize) -> DirEntry {
+    let mut entry = DirEntry {
+        kind,
+        size: size as u64,
+        ..DirEntry::default()
+    };
+    let len = name.len().min(entry.name.len());
+    entry.name[..len].copy_from_slice(&name.as_bytes()[..len]);
+    entry
+}
@@ -13,6 +13,14 @@ pub enum ElfErr {
     Oob,
     BadPh,
     MapFail,
+    /// The program asks for a dynamic loader (`PT_INTERP`).
+    NeedsInterp,
+    /// The program needs shared objects (`DT_NEEDED`).
+    NeedsLibs,
+    /// The dynamic section or a relocation table is out of bounds.
+    BadDyn,
+    /// A relocation other than a relative one.
+    BadReloc,
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -63,6 +71,28 @@ const ET_EXEC: u16 = 2;
 const ET_DYN: u16 = 3;
 const EM_X86_64: u16 = 62;
 const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+const PT_INTERP: u32 = 3;
+const PT_PHDR: u32 = 6;
+const PT_TLS: u32 = 7;
+
+/// Size of a program header.
+pub const PHENT_SIZE: u64 = 56;
+
+const DT_NULL: u64 = 0;
+const DT_NEEDED: u64 = 1;
+const DT_RELA: u64 = 7;
+const DT_RELASZ: u64 = 8;
+const DT_RELAENT: u64 = 9;
+const DT_RELSZ: u64 = 18;
+const DT_RELRSZ: u64 = 35;
+const DYN_LEN: usize = 16;
+const RELA_LEN: usize = 24;
+
+/// Relocation type: nothing to do.
+pub const R_X86_64_NONE: u32 = 0;
+/// Relocation type: store the load bias plus the addend.
+pub const R_X86_64_RELATIVE: u32 = 8;
 
 #[inline]
 fn le16(x: &[u8]) -> u16 {
@@ -231,6 +261,96 @@ impl ElfView<'_> {
         self.iter_ph().filter(|ph| ph.p_type == PT_LOAD)
     }
 
+    /// The `PT_TLS` header, the initial image of the thread-local storage.
+    pub fn tls(&self) -> Option<Ph64> {
+        self.iter_ph().find(|ph| ph.p_type == PT_TLS)
+    }
+
+    /// The `PT_INTERP` header, naming the dynamic loader the program asks
+    /// for.
+    pub fn interp(&self) -> Option<Ph64> {
+        self.iter_ph().find(|ph| ph.p_type == PT_INTERP)
+    }
+
+    /// Number of program headers.
+    pub const fn phnum(&self) -> u16 {
+        self.eh.e_phnum
+    }
+
+    /// The unbiased address of the program headers: that of `PT_PHDR`, or
+    /// where the `PT_LOAD` segment covering them in the file puts them.
+    /// `None` if they are not loaded.
+    pub fn phdr_vaddr(&self) -> Option<u64> {
+        if let Some(ph) = self.iter_ph().find(|ph| ph.p_type == PT_PHDR) {
+            return Some(ph.p_vaddr.as_u64());
+        }
+        let phoff = self.eh.e_phoff;
+        let end = phoff.saturating_add(PHENT_SIZE * u64::from(self.eh.e_phnum));
+        self.iter_pt_load()
+            .find(|ph| phoff >= ph.p_offset && end <= ph.p_offset.saturating_add(ph.p_filesz))
+            .map(|ph| ph.p_vaddr.as_u64() + (phoff - ph.p_offset))
+    }
+
+    /// The `Elf64_Rela` relocations of the dynamic section, none without one.
+    ///
+    /// # Errors
+    /// If the program needs shared objects, uses a relocation table format
+    /// other than `DT_RELA`, or a table is out of bounds.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn relocations(&self) -> Result<RelaIter<'_>, ElfErr> {
+        let mut rela = RelaIter { b: &[] };
+        let Some(dynamic) = self.iter_ph().find(|ph| ph.p_type == PT_DYNAMIC) else {
+            return Ok(rela);
+        };
+        let dynamic =
+            helpers::segment_file_bytes(self.bytes, &dynamic).map_err(|_| ElfErr::BadDyn)?;
+
+        let (mut addr, mut size, mut entsize) = (0, 0, RELA_LEN as u64);
+        for entry in dynamic.chunks_exact(DYN_LEN) {
+            let (tag, value) = (le64(&entry[0..8]), le64(&entry[8..16]));
+            match tag {
+                DT_NULL => break,
+                DT_NEEDED => return Err(ElfErr::NeedsLibs),
+                DT_RELA => addr = value,
+                DT_RELASZ => size = value,
+                DT_RELAENT => entsize = value,
+                DT_RELSZ | DT_RELRSZ if value != 0 => return Err(ElfErr::BadReloc),
+                _ => {}
+            }
+        }
+        if size == 0 {
+            return Ok(rela);
+        }
+        if entsize != RELA_LEN as u64 || size % entsize != 0 {
+            return Err(ElfErr::BadDyn);
+        }
+
+        // The table is addressed in memory; find it in the file.
+        let end = addr.checked_add(size).ok_or(ElfErr::BadDyn)?;
+        let seg = self
+            .iter_pt_load()
+            .find(|ph| {
+                let start = ph.p_vaddr.as_u64();
+                addr >= start && end <= start.saturating_add(ph.p_filesz)
+            })
+            .ok_or(ElfErr::BadDyn)?;
+        let off = (seg.p_offset + (addr - seg.p_vaddr.as_u64())) as usize;
+        rela.b = self
+            .bytes
+            .get(off..off + size as usize)
+            .ok_or(ElfErr::BadDyn)?;
+        Ok(rela)
+    }
+
+    /// Whether `[vaddr, vaddr + len)` lies within the memory of a `PT_LOAD`
+    /// segment, before biasing.
+    pub fn is_loaded(&self, vaddr: u64, len: u64) -> bool {
+        self.iter_pt_load().any(|ph| {
+            let start = ph.p_vaddr.as_u64();
+            vaddr >= start && vaddr.saturating_add(len) <= start.saturating_add(ph.p_memsz)
+        })
+    }
+
     /// True for PIE (`ET_DYN`), false for fixed `ET_EXEC`.
     pub const fn is_pie(&self) -> bool {
         self.eh.e_type == ET_DYN
@@ -240,3 +360,36 @@ impl ElfView<'_> {
         self.eh.e_entry
     }
 }
+
+/// An `Elf64_Rela` relocation.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[allow(clippy::struct_field_names)]
+pub struct Rela {
+    /// Where to apply it, before biasing.
+    pub r_offset: u64,
+    pub r_type: u32,
+    pub r_sym: u32,
+    pub r_addend: i64,
+}
+
+/// The entries of a `DT_RELA` table.
+pub struct RelaIter<'a> {
+    b: &'a [u8],
+}
+
+impl Iterator for RelaIter<'_> {
+    type Item = Rela;
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+    fn next(&mut self) -> Option<Self::Item> {
+        let (s, rest) = self.b.split_at_checked(RELA_LEN)?;
+        self.b = rest;
+        let info = le64(&s[8..16]);
+        Some(Rela {
+            r_offset: le64(&s[0..8]),
+            r_type: info as u32,
+            r_sym: (info >> 32) as u32,
+            r_addend: le64(&s[16..24]) as i64,
+        })
+    }
+}
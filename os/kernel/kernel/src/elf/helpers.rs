@@ -1,8 +1,11 @@
 use crate::elf::{ElfErr, ElfView, PFlags, Ph64};
 use bitfield_struct::bitfield;
 
-/// Compute a load bias for `ET_DYN`: min(vaddr of `PT_LOAD`), aligned to `max(p_align`, 0x1000).
-pub fn pie_bias(view: &ElfView<'_>) -> Option<u64> {
+/// Compute the load bias for `ET_DYN`: what moves the lowest `PT_LOAD`, aligned down to
+/// `max(p_align, 0x1000)`, to `base` (rounded up to that alignment). `0` for `ET_EXEC`.
+///
+/// `None` without `PT_LOAD` segments, or if they start above `base`.
+pub fn pie_bias(view: &ElfView<'_>, base: u64) -> Option<u64> {
     if !view.is_pie() {
         return Some(0);
     }
@@ -17,10 +20,11 @@ pub fn pie_bias(view: &ElfView<'_>) -> Option<u64> {
             max_align = ph.p_align;
         }
     }
-    if min == u64::MAX {
+    if min == u64::MAX || !max_align.is_power_of_two() {
         None
     } else {
-        Some(min & !(max_align - 1))
+        base.checked_next_multiple_of(max_align)?
+            .checked_sub(min & !(max_align - 1))
     }
 }
 
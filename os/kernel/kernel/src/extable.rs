@@ -0,0 +1,71 @@
+//! # Exception Table
+//!
+//! The kernel's half of [`kernel_registers::extable`]: the table `kernel.ld`
+//! collects from the `.extable` sections of all linked code, and the lookup
+//! the `#GP` and `#PF` handlers use to resume after an expected fault in
+//! kernel mode.
+//!
+//! Instructions become expected to fault by listing them with
+//! [`extable_entry!`](kernel_registers::extable_entry). Users so far:
+//!
+//! * [`try_rdmsr`](kernel_registers::msr::try_rdmsr) and
+//!   [`try_wrmsr`](kernel_registers::msr::try_wrmsr), for MSRs that may be
+//!   missing.
+//! * The [user copies](crate::uaccess), for pages unmapped between checking
+//!   the range and copying it.
+//! * [`probe_read_u32`], for memory, such as MMIO, that may not answer.
+
+use crate::interrupts::ExceptionFrame;
+use kernel_registers::extable::{self, ExceptionTableEntry};
+use kernel_registers::extable_entry;
+
+// Declared as `u32` for the alignment `kernel.ld` gives the section.
+unsafe extern "C" {
+    static __extable_start: u32;
+    static __extable_end: u32;
+}
+
+/// Every exception table entry linked into the kernel.
+fn table() -> &'static [ExceptionTableEntry] {
+    let start = (&raw const __extable_start).cast::<ExceptionTableEntry>();
+    let end = &raw const __extable_end;
+    let len = (end as usize - start as usize) / size_of::<ExceptionTableEntry>();
+    // SAFETY: The linker script places only `ExceptionTableEntry`s between the markers.
+    unsafe { core::slice::from_raw_parts(start, len) }
+}
+
+/// If the kernel-mode fault `vector` in `frame` was expected, redirect the
+/// frame to its recovery address with `vector` in `rax`, and return `true`.
+pub fn fixup(frame: &mut ExceptionFrame, vector: usize) -> bool {
+    let Some(recovery) = extable::search(table(), frame.rip) else {
+        return false;
+    };
+    frame.rip = recovery;
+    frame.rax = vector as u64;
+    true
+}
+
+/// Read the `u32` at `addr`, or `None` if that faults.
+///
+/// # Safety
+/// The read must have no side effects the caller is not prepared for, as
+/// reads of some device registers do.
+#[allow(dead_code)]
+pub unsafe fn probe_read_u32(addr: u64) -> Option<u32> {
+    let value: u32;
+    let fault: u64;
+    unsafe {
+        core::arch::asm!(
+            "2:",
+            "mov {value:e}, [{addr}]",
+            "xor eax, eax",
+            "3:",
+            extable_entry!("2b", "3b"),
+            addr = in(reg) addr,
+            value = out(reg) value,
+            out("rax") fault,
+            options(nostack, readonly)
+        );
+    }
+    (fault == 0).then_some(value)
+}
@@ -0,0 +1,278 @@
+//! # FPU and SIMD State
+//!
+//! Enables the x87 FPU, SSE and (where available) AVX for user mode and keeps
+//! a separate copy of that register state for every process.
+//!
+//! ## Setup
+//!
+//! [`init`] runs once per CPU:
+//!
+//! * `CR0`: FPU errors are reported natively (`NE`), `WAIT` honours `TS`
+//!   (`MP`), and neither emulation (`EM`) nor `TS` traps are left on.
+//! * `CR4`: `OSFXSR` and `OSXMMEXCPT` enable SSE and its exceptions. With
+//!   `XSAVE` support, `OSXSAVE` is set and `XCR0` enables the x87, SSE and AVX
+//!   components the CPU offers. Larger components (AVX-512, AMX) stay off.
+//! * The x87 control word and `MXCSR` are reset to their defaults (all
+//!   exceptions masked, round to nearest).
+//!
+//! The resulting [`FpuMode`] says whether state is switched with
+//! `XSAVE`/`XRSTOR` or `FXSAVE`/`FXRSTOR`; it is the same on all CPUs.
+//!
+//! ## Context switches
+//!
+//! Every process owns an [`FpuState`] save area. The kernel is built for a
+//! soft-float target and never touches FP/SIMD registers, so whatever they
+//! hold on entry to the kernel belongs to the interrupted process. The
+//! [scheduler](crate::sched) saves it **eagerly** when switching away from a
+//! process and restores the next process' state before switching to it. A
+//! fresh [`FpuState`] restores the architectural init state.
+//!
+//! ## Untrusted state
+//!
+//! [Signal delivery](crate::signal) hands a copy of the state to user mode
+//! and takes it back on return. [`FpuState::from_untrusted`] only accepts
+//! areas that `FXRSTOR`/`XRSTOR` load without faulting: no reserved `MXCSR`
+//! bits, and an `XSAVE` header in standard form naming only enabled
+//! components.
+
+use crate::cpuid::{CpuidRanges, Leaf0Dh, Leaf01h};
+use kernel_registers::cr0::Cr0;
+use kernel_registers::cr4::Cr4;
+use kernel_registers::xcr0::Xcr0;
+use kernel_registers::{LoadRegisterUnsafe, StoreRegisterUnsafe};
+use kernel_sync::SyncOnceCell;
+
+/// Capacity of an [`FpuState`] save area in bytes.
+///
+/// Enough for the legacy region (512), the `XSAVE` header (64) and the AVX
+/// component (256).
+pub const AREA_SIZE: usize = 1024;
+
+/// Default x87 control word: all exceptions masked, 64-bit precision, round
+/// to nearest.
+pub const DEFAULT_FCW: u16 = 0x037F;
+
+/// Default `MXCSR`: all exceptions masked, round to nearest.
+pub const DEFAULT_MXCSR: u32 = 0x1F80;
+
+/// Offset of the x87 control word in the legacy save region.
+const FCW_OFFSET: usize = 0;
+
+/// Offset of `MXCSR` in the legacy save region.
+const MXCSR_OFFSET: usize = 24;
+
+/// `MXCSR` bits that may be set; the rest are reserved and fault on restore.
+const MXCSR_VALID: u32 = 0xFFFF;
+
+/// Offset of the `XSAVE` header (`XSTATE_BV`, `XCOMP_BV`, reserved).
+const XSAVE_HEADER_OFFSET: usize = 512;
+
+/// Size of the `XSAVE` header.
+const XSAVE_HEADER_LEN: usize = 64;
+
+/// How FP/SIMD state is saved and restored.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum FpuMode {
+    /// `FXSAVE`/`FXRSTOR`: x87 and SSE only.
+    Fxsr,
+    /// `XSAVE`/`XRSTOR` of the components in `xcr0`, needing `size` bytes.
+    Xsave { xcr0: u64, size: u32 },
+}
+
+static FPU_MODE: SyncOnceCell<FpuMode> = SyncOnceCell::new();
+
+/// Saved FP/SIMD register state of one process.
+///
+/// Aligned for `XSAVE` (64 bytes), which also satisfies `FXSAVE` (16 bytes).
+#[derive(Clone)]
+#[repr(C, align(64))]
+pub struct FpuState {
+    area: [u8; AREA_SIZE],
+}
+
+const _: () = assert!(align_of::<FpuState>() == 64);
+
+impl FpuState {
+    /// The architectural init state, with default control words.
+    ///
+    /// The `XSAVE` header is all zero, so `XRSTOR` puts every component into
+    /// its init state; it still loads `MXCSR` from the legacy region.
+    pub const fn new() -> Self {
+        let mut area = [0; AREA_SIZE];
+        let fcw = DEFAULT_FCW.to_le_bytes();
+        area[FCW_OFFSET] = fcw[0];
+        area[FCW_OFFSET + 1] = fcw[1];
+        let mxcsr = DEFAULT_MXCSR.to_le_bytes();
+        let mut i = 0;
+        while i < mxcsr.len() {
+            area[MXCSR_OFFSET + i] = mxcsr[i];
+            i += 1;
+        }
+        Self { area }
+    }
+
+    /// Take over a save area that came from user mode, if it is safe to
+    /// restore (see [Untrusted state](self#untrusted-state)).
+    pub fn from_untrusted(area: &[u8; AREA_SIZE]) -> Option<Self> {
+        let state = Self { area: *area };
+        if state.mxcsr() & !MXCSR_VALID != 0 {
+            return None;
+        }
+
+        if let Some(FpuMode::Xsave { xcr0, .. }) = mode() {
+            let header = &state.area[XSAVE_HEADER_OFFSET..XSAVE_HEADER_OFFSET + XSAVE_HEADER_LEN];
+            let mut xstate_bv = [0; 8];
+            xstate_bv.copy_from_slice(&header[..8]);
+            if u64::from_le_bytes(xstate_bv) & !xcr0 != 0 || header[8..].iter().any(|&b| b != 0) {
+                return None;
+            }
+        }
+        Some(state)
+    }
+
+    /// The raw save area.
+    pub const fn as_bytes(&self) -> &[u8; AREA_SIZE] {
+        &self.area
+    }
+
+    /// The saved `MXCSR`.
+    pub fn mxcsr(&self) -> u32 {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(&self.area[MXCSR_OFFSET..MXCSR_OFFSET + 4]);
+        u32::from_le_bytes(bytes)
+    }
+}
+
+impl Default for FpuState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Enable FP/SIMD instructions on this CPU and reset their control state.
+///
+/// # Safety
+/// Must run at CPL0 on every CPU before it runs user code.
+///
+/// # Panics
+/// If the CPU lacks `FXSAVE`, which every x86-64 CPU has.
+pub unsafe fn init() -> FpuMode {
+    let ranges = unsafe { CpuidRanges::read() };
+    let leaf1 = unsafe { Leaf01h::read(&ranges) };
+    assert!(
+        leaf1.is_some_and(|l| l.has_fxsr()),
+        "CPU does not support FXSAVE"
+    );
+
+    unsafe {
+        Cr0::load_unsafe()
+            .with_em_emulation(false)
+            .with_ts_task_switched(false)
+            .with_mp_monitor_coprocessor(true)
+            .with_ne_numeric_error(true)
+            .store_unsafe();
+    }
+
+    let xsave = unsafe { Leaf0Dh::read(&ranges) }.filter(|_| leaf1.is_some_and(|l| l.has_xsave()));
+    let mut cr4 = unsafe { Cr4::load_unsafe() }
+        .with_osfxsr(true)
+        .with_osxmmexcpt(true);
+    if xsave.is_some() {
+        cr4 = cr4.with_osxsave(true);
+    }
+    unsafe { cr4.store_unsafe() };
+
+    let avx = leaf1.is_some_and(|l| l.has_avx());
+    let mode = xsave.map_or(FpuMode::Fxsr, |leaf| unsafe {
+        enable_xsave(&ranges, leaf, avx)
+    });
+    let mode = *FPU_MODE.get_or_init(|| mode);
+
+    let mxcsr = DEFAULT_MXCSR;
+    unsafe {
+        core::arch::asm!("fninit", options(nomem, nostack));
+        core::arch::asm!("ldmxcsr [{}]", in(reg) &raw const mxcsr, options(nostack, readonly));
+    }
+    mode
+}
+
+/// Program `XCR0` with x87, SSE and, if `avx`, AVX.
+unsafe fn enable_xsave(ranges: &CpuidRanges, leaf: Leaf0Dh, avx: bool) -> FpuMode {
+    let supported = Xcr0::from_bits(leaf.supported_xcr0);
+    let xcr0 = Xcr0::new()
+        .with_x87(true)
+        .with_sse(true)
+        .with_avx(avx && supported.avx());
+    unsafe { xcr0.store_unsafe() };
+
+    // The area size depends on the enabled components.
+    let size = unsafe { Leaf0Dh::read(ranges) }.map_or(leaf.enabled_size, |l| l.enabled_size);
+    assert!(
+        size as usize <= AREA_SIZE,
+        "XSAVE area of {size} bytes exceeds {AREA_SIZE}"
+    );
+    FpuMode::Xsave {
+        xcr0: xcr0.into_bits(),
+        size,
+    }
+}
+
+/// The switching mode chosen by [`init`].
+pub fn mode() -> Option<FpuMode> {
+    FPU_MODE.get().copied()
+}
+
+/// Save this CPU's FP/SIMD registers into `state`.
+///
+/// # Safety
+/// [`init`] must have run on this CPU.
+#[allow(clippy::cast_possible_truncation)]
+pub unsafe fn save(state: &mut FpuState) {
+    match FPU_MODE.get() {
+        Some(&FpuMode::Xsave { xcr0, .. }) => unsafe {
+            core::arch::asm!(
+                "xsave64 [{}]",
+                in(reg) state.area.as_mut_ptr(),
+                in("eax") xcr0 as u32,
+                in("edx") (xcr0 >> 32) as u32,
+                options(nostack, preserves_flags)
+            );
+        },
+        Some(FpuMode::Fxsr) => unsafe {
+            core::arch::asm!(
+                "fxsave64 [{}]",
+                in(reg) state.area.as_mut_ptr(),
+                options(nostack, preserves_flags)
+            );
+        },
+        None => {}
+    }
+}
+
+/// Load this CPU's FP/SIMD registers from `state`.
+///
+/// # Safety
+/// [`init`] must have run on this CPU, and `state` must come from
+/// [`FpuState::new`] or [`save`].
+#[allow(clippy::cast_possible_truncation)]
+pub unsafe fn restore(state: &FpuState) {
+    match FPU_MODE.get() {
+        Some(&FpuMode::Xsave { xcr0, .. }) => unsafe {
+            core::arch::asm!(
+                "xrstor64 [{}]",
+                in(reg) state.area.as_ptr(),
+                in("eax") xcr0 as u32,
+                in("edx") (xcr0 >> 32) as u32,
+                options(nostack, preserves_flags, readonly)
+            );
+        },
+        Some(FpuMode::Fxsr) => unsafe {
+            core::arch::asm!(
+                "fxrstor64 [{}]",
+                in(reg) state.area.as_ptr(),
+                options(nostack, preserves_flags, readonly)
+            );
+        },
+        None => {}
+    }
+}
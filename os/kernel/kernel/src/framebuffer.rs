@@ -1,34 +1,187 @@
 //! # Kernel Framebuffer helpers
+//!
+//! The UEFI GOP framebuffer is mapped write-combining at [`VGA_LIKE_OFFSET`].
+//! Writes to it are cheap, reads are not, and drawing into it directly tears.
+//!
+//! ## Drawing
+//!
+//! * [`compositor`] keeps a back buffer in ordinary RAM, collects dirty
+//!   rectangles and copies only those to the framebuffer on
+//!   [`present`](compositor::Compositor::present).
+//! * [`surface`] implements the drawing primitives on any pixel buffer in
+//!   memory, the back buffer as well as off-screen images.
+//! * [`pixel`] converts between [`Color`]s and the framebuffer's RGB, BGR or
+//!   bitmask pixel layout.
+//! * [`font`] picks the console font used by
+//!   [`draw_text`](surface::Surface::draw_text): a [`psf2`] font loaded at
+//!   boot, or the builtin 8x16 one.
+//! * [`psf2`] parses PC Screen Font files.
+//! * [`image`] decodes BMP and QOI images for
+//!   [`draw_image`](surface::Surface::draw_image).
+//! * [`console`] writes text to the screen line by line, scrolling as it
+//!   fills up.
+//! * [`splash`] shows a boot image with a progress bar while init runs.
+//! * [`device`] exposes the back buffer to userland as `/dev/fb0`.
+//! * [`fill_solid`] paints straight into the framebuffer, for use before (or
+//!   without) a compositor.
 
-use kernel_info::boot::{BootPixelFormat, FramebufferInfo};
+pub mod compositor;
+pub mod console;
+pub mod device;
+pub mod font;
+pub mod image;
+pub mod pixel;
+pub mod psf2;
+pub mod splash;
+pub mod surface;
+
+use crate::framebuffer::pixel::{PixelFormat, write_pixel};
+use kernel_info::boot::FramebufferInfo;
 
 /// Virtual offset inside the HHDM where we map the framebuffer.
 ///
 /// This reduces the risk of having to split a 1 MiB page into 4 KiB pages.
 pub const VGA_LIKE_OFFSET: u64 = 1u64 << 40; // 1 TiB inside HHDM range
 
-#[allow(clippy::missing_safety_doc, clippy::many_single_char_names)]
-pub unsafe fn fill_solid(fb: &FramebufferInfo, r: u8, g: u8, b: u8) {
-    // Nothing to draw into
-    if matches!(fb.framebuffer_format, BootPixelFormat::BltOnly) {
-        return;
+/// Virtual offset inside the HHDM where the compositor's back buffer lives.
+pub const BACK_BUFFER_OFFSET: u64 = VGA_LIKE_OFFSET + (1u64 << 39); // 1.5 TiB inside HHDM range
+
+/// An RGB color with an alpha channel for blending (`0` transparent, `255` opaque).
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Color {
+    pub const BLACK: Self = Self::new(0, 0, 0);
+    pub const WHITE: Self = Self::new(0xFF, 0xFF, 0xFF);
+    #[allow(dead_code)]
+    pub const TRANSPARENT: Self = Self::rgba(0, 0, 0, 0);
+
+    /// An opaque color.
+    #[must_use]
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self::rgba(r, g, b, 0xFF)
+    }
+
+    #[must_use]
+    pub const fn rgba(r: u8, g: u8, b: u8, a: u8) -> Self {
+        Self { r, g, b, a }
     }
 
-    // Precompute the packed pixel once (little-endian):
-    // - RGB format => bytes [R, G, B, 0xFF] -> value 0xFF_BB_GG_RR
-    // - BGR format => bytes [B, G, R, 0xFF] -> value 0xFF_RR_GG_BB
-    let px: u32 = match fb.framebuffer_format {
-        BootPixelFormat::Rgb => {
-            (0xFFu32 << 24) | (u32::from(b) << 16) | (u32::from(g) << 8) | u32::from(r)
+    /// `self` drawn over the opaque color `dst`.
+    #[must_use]
+    #[allow(dead_code, clippy::cast_possible_truncation)]
+    pub const fn over(self, dst: Self) -> Self {
+        const fn mix(src: u8, dst: u8, a: u32) -> u8 {
+            ((src as u32 * a + dst as u32 * (255 - a) + 127) / 255) as u8
         }
-        BootPixelFormat::Bgr => {
-            (0xFFu32 << 24) | (u32::from(r) << 16) | (u32::from(g) << 8) | u32::from(b)
+
+        let a = self.a as u32;
+        Self::new(
+            mix(self.r, dst.r, a),
+            mix(self.g, dst.g, a),
+            mix(self.b, dst.b, a),
+        )
+    }
+}
+
+/// An axis-aligned rectangle in pixels; `x..x + width` by `y..y + height`.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    #[must_use]
+    pub const fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
         }
-        BootPixelFormat::Bitmask | BootPixelFormat::BltOnly => return,
+    }
+
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    /// One past the rightmost column.
+    #[must_use]
+    pub const fn right(&self) -> u32 {
+        self.x.saturating_add(self.width)
+    }
+
+    /// One past the bottom row.
+    #[must_use]
+    pub const fn bottom(&self) -> u32 {
+        self.y.saturating_add(self.height)
+    }
+
+    /// The part of `self` inside a `width` x `height` area at the origin.
+    #[must_use]
+    pub fn clip(&self, width: u32, height: u32) -> Self {
+        let x = self.x.min(width);
+        let y = self.y.min(height);
+        Self::new(
+            x,
+            y,
+            self.right().min(width) - x,
+            self.bottom().min(height) - y,
+        )
+    }
+
+    /// Whether the rectangles overlap or share an edge.
+    #[must_use]
+    pub const fn touches(&self, other: &Self) -> bool {
+        self.x <= other.right()
+            && other.x <= self.right()
+            && self.y <= other.bottom()
+            && other.y <= self.bottom()
+    }
+
+    /// The smallest rectangle containing both.
+    #[must_use]
+    pub fn union(&self, other: &Self) -> Self {
+        if self.is_empty() {
+            return *other;
+        }
+        if other.is_empty() {
+            return *self;
+        }
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        Self::new(
+            x,
+            y,
+            self.right().max(other.right()) - x,
+            self.bottom().max(other.bottom()) - y,
+        )
+    }
+}
+
+/// Fill the center quarter of the screen with the color `(r, g, b)`.
+///
+/// Works for every linear pixel layout; `BltOnly` framebuffers are left alone.
+#[allow(clippy::missing_safety_doc, clippy::many_single_char_names)]
+pub unsafe fn fill_solid(fb: &FramebufferInfo, r: u8, g: u8, b: u8) {
+    // Precompute the packed pixel once (little-endian)
+    let Some(format) = PixelFormat::from_boot(fb.framebuffer_format, &fb.framebuffer_masks) else {
+        return;
     };
+    let px = format.pack(Color::new(r, g, b));
 
-    // 32-bit pixels
-    let base = fb.framebuffer_ptr as *mut u32;
+    // Pixels are 1 to 4 bytes, depending on the format
+    let base = fb.framebuffer_ptr as *mut u8;
+    let bpp = format.bytes_per_pixel();
 
     // pixels per row (GOP "PixelsPerScanLine")
     let stride = usize::try_from(fb.framebuffer_stride).unwrap_or_default();
@@ -46,13 +199,13 @@ pub unsafe fn fill_solid(fb: &FramebufferInfo, r: u8, g: u8, b: u8) {
 
     for y in start_y..end_y {
         // Pointer to first pixel in this row at start_x
-        let mut p = unsafe { base.add(y * stride + start_x) };
+        let mut p = unsafe { base.add((y * stride + start_x) * bpp) };
 
         // Fill [start_x, end_x)
         for _ in start_x..end_x {
             unsafe {
-                p.write_volatile(px);
-                p = p.add(1);
+                write_pixel(p, px, format);
+                p = p.add(bpp);
             }
         }
     }
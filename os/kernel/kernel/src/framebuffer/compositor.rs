@@ -0,0 +1,349 @@
+//! # Double-Buffered Compositor
+//!
+//! Drawing straight into the framebuffer shows every intermediate state on
+//! screen and touches slow device memory for every pixel, including those
+//! that end up unchanged. The [`Compositor`] instead draws into a back buffer
+//! in ordinary RAM and copies it to the framebuffer only on
+//! [`present`](Compositor::present) — and only the parts that changed.
+//!
+//! ## Back buffer
+//!
+//! The back buffer has the screen's width and height, no row padding, and
+//! holds pixels already packed in the framebuffer's format, so presenting
+//! 32-bit layouts is a plain row-by-row copy. Narrower (16 or 24 bpp) layouts
+//! are written pixel by pixel. It is mapped from fresh 4 KiB frames at
+//! [`BACK_BUFFER_OFFSET`] and never freed.
+//!
+//! ## Dirty rectangles
+//!
+//! Every primitive records the (clipped) area it drew to. Touching or
+//! overlapping rectangles are merged on insertion; once more than
+//! [`MAX_DIRTY`] disjoint ones pile up, all of them collapse into their
+//! bounding box. Copying a few unchanged pixels is cheaper than tracking
+//! every stroke.
+//!
+//! Drawing itself happens on the back buffer's [`Surface`]; the compositor's
+//! [`draw`](Compositor::draw) records whatever area a surface primitive
+//! reports as changed.
+//!
+//! ## Access
+//!
+//! [`init`] creates the kernel's compositor for the boot framebuffer;
+//! [`with_compositor`] runs a closure on it under its lock.
+//! [`back_buffer_frame`] finds the frames of its back buffer, which
+//! [`/dev/fb0`](super::device) maps into processes.
+
+use crate::alloc::{FlushTlb, try_with_kernel_vmm};
+use crate::framebuffer::pixel::{PixelFormat, write_pixel};
+use crate::framebuffer::surface::Surface;
+use crate::framebuffer::{BACK_BUFFER_OFFSET, Color, Rect};
+use core::fmt;
+use kernel_alloc::vmm::{AllocationTarget, VmmError};
+use kernel_info::boot::FramebufferInfo;
+use kernel_info::memory::HHDM_BASE;
+use kernel_memory_addresses::{PageSize, PhysicalAddress, Size4K};
+use kernel_sync::{SpinMutex, SyncOnceCell};
+use kernel_vmem::VirtualMemoryPageBits;
+
+/// Disjoint dirty rectangles tracked before collapsing them into one.
+pub const MAX_DIRTY: usize = 16;
+
+/// Largest back buffer in bytes; the window ends where the userland bundle starts.
+const BACK_BUFFER_MAX: u64 = 1u64 << 39;
+
+/// The kernel's compositor for the boot framebuffer.
+static COMPOSITOR: SyncOnceCell<SpinMutex<Compositor>> = SyncOnceCell::new();
+
+/// Why no compositor could be created.
+#[derive(Debug)]
+pub enum CompositorError {
+    /// The framebuffer has no linear pixel layout (`BltOnly`).
+    UnsupportedFormat,
+    /// The framebuffer reports a zero or oversized dimension.
+    InvalidGeometry,
+    /// The back buffer could not be mapped.
+    BackBuffer(VmmError),
+}
+
+impl fmt::Display for CompositorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnsupportedFormat => f.write_str("unsupported pixel format"),
+            Self::InvalidGeometry => f.write_str("invalid framebuffer geometry"),
+            Self::BackBuffer(e) => write!(f, "failed to map back buffer: {e}"),
+        }
+    }
+}
+
+/// Areas of the back buffer changed since the last present.
+#[derive(Debug)]
+struct DirtyRects {
+    rects: [Rect; MAX_DIRTY],
+    len: usize,
+}
+
+impl DirtyRects {
+    const fn new() -> Self {
+        Self {
+            rects: [Rect::new(0, 0, 0, 0); MAX_DIRTY],
+            len: 0,
+        }
+    }
+
+    fn add(&mut self, rect: Rect) {
+        if rect.is_empty() {
+            return;
+        }
+
+        // Absorb everything the new rectangle touches. A merge grows it, so
+        // start over until nothing touches anymore.
+        let mut rect = rect;
+        let mut i = 0;
+        while i < self.len {
+            if self.rects[i].touches(&rect) {
+                rect = rect.union(&self.rects[i]);
+                self.len -= 1;
+                self.rects[i] = self.rects[self.len];
+                i = 0;
+            } else {
+                i += 1;
+            }
+        }
+
+        if self.len == MAX_DIRTY {
+            rect = self.rects.iter().fold(rect, |acc, r| acc.union(r));
+            self.len = 0;
+        }
+
+        self.rects[self.len] = rect;
+        self.len += 1;
+    }
+
+    fn as_slice(&self) -> &[Rect] {
+        &self.rects[..self.len]
+    }
+
+    const fn clear(&mut self) {
+        self.len = 0;
+    }
+}
+
+/// A back buffer for a linear framebuffer; see the [module docs](self).
+pub struct Compositor {
+    /// First pixel of the (write-combining) framebuffer.
+    front: *mut u8,
+    /// Bytes per framebuffer scanline.
+    front_pitch: usize,
+    back: Surface<'static>,
+    dirty: DirtyRects,
+}
+
+// SAFETY: The framebuffer pointer refers to a global kernel mapping and is only
+// dereferenced by the owner of the compositor.
+unsafe impl Send for Compositor {}
+
+impl Compositor {
+    /// Create a compositor presenting `back` to the framebuffer `fb`.
+    ///
+    /// The back buffer is cleared to black; the screen is left untouched
+    /// until the first [`present`](Self::present).
+    ///
+    /// # Errors
+    /// If the framebuffer's format or geometry is unsupported, or `back`
+    /// holds fewer than `width * height` pixels.
+    ///
+    /// # Safety
+    /// `fb.framebuffer_ptr` must be the virtual address of the mapped
+    /// framebuffer, which must stay mapped for the compositor's lifetime.
+    pub unsafe fn new(
+        fb: &FramebufferInfo,
+        back: &'static mut [u32],
+    ) -> Result<Self, CompositorError> {
+        let format = PixelFormat::from_boot(fb.framebuffer_format, &fb.framebuffer_masks)
+            .ok_or(CompositorError::UnsupportedFormat)?;
+
+        let width = u32::try_from(fb.framebuffer_width).unwrap_or_default();
+        let height = u32::try_from(fb.framebuffer_height).unwrap_or_default();
+        let stride = usize::try_from(fb.framebuffer_stride).unwrap_or_default();
+        if width == 0 || height == 0 || stride < width as usize {
+            return Err(CompositorError::InvalidGeometry);
+        }
+
+        let mut back =
+            Surface::new(back, width, height, format).ok_or(CompositorError::InvalidGeometry)?;
+        back.clear(Color::BLACK);
+
+        Ok(Self {
+            front: fb.framebuffer_ptr as *mut u8,
+            front_pitch: stride * format.bytes_per_pixel(),
+            back,
+            dirty: DirtyRects::new(),
+        })
+    }
+
+    #[must_use]
+    pub const fn width(&self) -> u32 {
+        self.back.width()
+    }
+
+    #[must_use]
+    pub const fn height(&self) -> u32 {
+        self.back.height()
+    }
+
+    /// The whole screen.
+    #[must_use]
+    pub const fn bounds(&self) -> Rect {
+        self.back.bounds()
+    }
+
+    /// The layout of the back buffer's pixels.
+    #[must_use]
+    pub const fn format(&self) -> PixelFormat {
+        self.back.format()
+    }
+
+    /// Bytes mapped for the back buffer, a multiple of the page size.
+    #[must_use]
+    pub const fn back_buffer_len(&self) -> u64 {
+        back_buffer_len(self.width() as u64 * self.height() as u64)
+    }
+
+    /// Mark `rect` for copying on the next [`present`](Self::present).
+    pub fn mark_dirty(&mut self, rect: Rect) {
+        self.dirty.add(rect.clip(self.width(), self.height()));
+    }
+
+    /// Mark the whole screen for copying on the next [`present`](Self::present).
+    #[allow(dead_code)]
+    pub fn invalidate(&mut self) {
+        self.mark_dirty(self.bounds());
+    }
+
+    /// Run `f` on the back buffer and mark the rectangle it returns as dirty.
+    pub fn draw(&mut self, f: impl FnOnce(&mut Surface<'static>) -> Rect) -> Rect {
+        let rect = f(&mut self.back);
+        self.mark_dirty(rect);
+        rect
+    }
+
+    /// Fill `rect` (clipped to the screen) with `color`.
+    pub fn fill_rect(&mut self, rect: Rect, color: Color) -> Rect {
+        self.draw(|s| s.fill_rect(rect, color))
+    }
+
+    /// Copy `src_rect` of `src` so that its top-left corner lands at `(x, y)`.
+    #[allow(dead_code)]
+    pub fn blit(&mut self, x: u32, y: u32, src: &Surface<'_>, src_rect: Rect) -> Rect {
+        self.draw(|s| s.blit(x, y, src, src_rect))
+    }
+
+    /// Draw a single line of `text` in the [builtin font](crate::framebuffer::font) with its
+    /// top-left corner at `(x, y)`; see [`Surface::draw_text`].
+    pub fn draw_text(&mut self, x: u32, y: u32, text: &str, fg: Color, bg: Option<Color>) -> Rect {
+        self.draw(|s| s.draw_text(x, y, text, fg, bg))
+    }
+
+    /// Copy all dirty regions to the framebuffer.
+    ///
+    /// Returns the number of pixels copied.
+    pub fn present(&mut self) -> u64 {
+        let format = self.back.format();
+        let bpp = format.bytes_per_pixel();
+        let mut copied = 0;
+        for rect in self.dirty.as_slice() {
+            for y in rect.y..rect.bottom() {
+                let src = self.back.span(rect.x, y, rect.width);
+                let dst = unsafe {
+                    self.front
+                        .add(y as usize * self.front_pitch + rect.x as usize * bpp)
+                };
+                if bpp == size_of::<u32>() {
+                    unsafe {
+                        core::ptr::copy_nonoverlapping(src.as_ptr().cast(), dst, size_of_val(src));
+                    }
+                } else {
+                    for (i, px) in src.iter().enumerate() {
+                        unsafe { write_pixel(dst.add(i * bpp), *px, format) };
+                    }
+                }
+            }
+            copied += u64::from(rect.width) * u64::from(rect.height);
+        }
+        self.dirty.clear();
+
+        // Drain the write-combining buffers so the frame is fully visible.
+        unsafe {
+            core::arch::asm!("sfence", options(nostack, preserves_flags));
+        }
+        copied
+    }
+}
+
+/// Create the kernel's compositor for the (already mapped) framebuffer `fb`.
+///
+/// Maps a back buffer at [`BACK_BUFFER_OFFSET`]. Calling this again after a
+/// successful initialization does nothing.
+///
+/// # Errors
+/// See [`CompositorError`].
+///
+/// # Safety
+/// `fb.framebuffer_ptr` must be the virtual address of the mapped framebuffer,
+/// which must stay mapped forever.
+pub unsafe fn init(fb: &FramebufferInfo) -> Result<(), CompositorError> {
+    if COMPOSITOR.get().is_some() {
+        return Ok(());
+    }
+    if PixelFormat::from_boot(fb.framebuffer_format, &fb.framebuffer_masks).is_none() {
+        return Err(CompositorError::UnsupportedFormat);
+    }
+
+    let pixels = usize::try_from(fb.framebuffer_width.saturating_mul(fb.framebuffer_height))
+        .map_err(|_| CompositorError::InvalidGeometry)?;
+    let bytes = back_buffer_len(pixels as u64);
+    if bytes == 0 || bytes > BACK_BUFFER_MAX {
+        return Err(CompositorError::InvalidGeometry);
+    }
+
+    let va = HHDM_BASE + BACK_BUFFER_OFFSET;
+    let nonleaf = VirtualMemoryPageBits::new()
+        .with_present(true)
+        .with_writable(true);
+    let leaf = VirtualMemoryPageBits::new()
+        .with_present(true)
+        .with_writable(true)
+        .with_no_execute(true)
+        .with_global(true);
+    try_with_kernel_vmm(FlushTlb::Never, |vmm| {
+        vmm.map_anon_4k_pages(AllocationTarget::Kernel, va, 0, bytes, nonleaf, leaf)
+    })
+    .map_err(CompositorError::BackBuffer)?;
+
+    let back = unsafe { core::slice::from_raw_parts_mut(va.as_u64() as *mut u32, pixels) };
+    let compositor = unsafe { Compositor::new(fb, back)? };
+    COMPOSITOR.get_or_init(|| SpinMutex::new(compositor));
+    Ok(())
+}
+
+/// Bytes mapped for a back buffer of `pixels` pixels.
+const fn back_buffer_len(pixels: u64) -> u64 {
+    (pixels * 4).next_multiple_of(Size4K::SIZE)
+}
+
+/// The frame holding byte `offset` of the kernel compositor's back buffer,
+/// to map it elsewhere; `None` past its end or without a compositor.
+pub fn back_buffer_frame(offset: u64) -> Option<PhysicalAddress> {
+    if offset >= with_compositor(|c| c.back_buffer_len())? {
+        return None;
+    }
+    let va = HHDM_BASE + BACK_BUFFER_OFFSET + (offset & !(Size4K::SIZE - 1));
+    try_with_kernel_vmm(FlushTlb::Never, |vmm| vmm.query(va).ok_or(())).ok()
+}
+
+/// Run `f` on the kernel's compositor.
+///
+/// Returns `None` if [`init`] has not succeeded.
+pub fn with_compositor<R>(f: impl FnOnce(&mut Compositor) -> R) -> Option<R> {
+    COMPOSITOR.get().map(|c| f(&mut c.lock()))
+}
@@ -0,0 +1,164 @@
+//! # Framebuffer Console
+//!
+//! A text screen on the [compositor](crate::framebuffer::compositor), in
+//! the [console font](crate::framebuffer::font): [`write`] draws UTF-8 text
+//! at a cursor that moves on by one character cell per character. It is the
+//! output side of the [terminal](crate::tty).
+//!
+//! The first write clears the screen and fits as many cells as the font
+//! allows onto it. From then on, text wraps at the right edge and the screen
+//! scrolls up by a line once the cursor moves past the bottom.
+//!
+//! ## Control characters
+//!
+//! | Byte   | Effect                                   |
+//! |--------|------------------------------------------|
+//! | `\n`   | Move to the start of the next line       |
+//! | `\r`   | Move to the start of the line            |
+//! | `\x08` | Move one cell back, without erasing      |
+//! | `\t`   | Move to the next multiple of eight cells |
+//!
+//! Other control characters are ignored; bytes that are not valid UTF-8
+//! show as `?`.
+
+use crate::framebuffer::compositor::{Compositor, with_compositor};
+use crate::framebuffer::font::{self, Font};
+use crate::framebuffer::{Color, Rect};
+use kernel_sync::SpinMutex;
+
+const FG: Color = Color::new(0xC0, 0xC0, 0xC0);
+const BG: Color = Color::BLACK;
+
+/// Cells per tab stop.
+const TAB_WIDTH: u32 = 8;
+
+/// The cursor, once the console was first written to.
+static CONSOLE: SpinMutex<Option<Cursor>> = SpinMutex::new(None);
+
+#[derive(Debug)]
+struct Cursor {
+    font: Font<'static>,
+    col: u32,
+    row: u32,
+    cols: u32,
+    rows: u32,
+    /// The start of a UTF-8 sequence not completed yet.
+    partial: [u8; 4],
+    partial_len: usize,
+}
+
+/// Draw `bytes` at the cursor; see the [module docs](self).
+///
+/// Without a compositor, the text is dropped.
+pub fn write(bytes: &[u8]) {
+    let mut cursor = CONSOLE.lock();
+    with_compositor(|c| {
+        let cursor = cursor.get_or_insert_with(|| Cursor::new(c));
+        for &byte in bytes {
+            cursor.put_byte(c, byte);
+        }
+        c.present();
+    });
+}
+
+impl Cursor {
+    /// Clear the screen and home the cursor.
+    fn new(c: &mut Compositor) -> Self {
+        let font = font::current();
+        c.fill_rect(c.bounds(), BG);
+        Self {
+            font,
+            col: 0,
+            row: 0,
+            cols: (c.width() / font.width()).max(1),
+            rows: (c.height() / font.height()).max(1),
+            partial: [0; 4],
+            partial_len: 0,
+        }
+    }
+
+    fn put_byte(&mut self, c: &mut Compositor, byte: u8) {
+        if self.partial_len == 0 {
+            match byte {
+                b'\n' => self.newline(c),
+                b'\r' => self.col = 0,
+                0x08 => self.back(),
+                b'\t' => {
+                    let stop = (self.col / TAB_WIDTH + 1) * TAB_WIDTH;
+                    while self.col < stop.min(self.cols) {
+                        self.put_char(c, ' ');
+                    }
+                }
+                0x20..=0x7E => self.put_char(c, char::from(byte)),
+                0xC0..=0xF7 => {
+                    self.partial[0] = byte;
+                    self.partial_len = 1;
+                }
+                0x80.. => self.put_char(c, '?'),
+                _ => {}
+            }
+            return;
+        }
+
+        if byte & 0xC0 != 0x80 {
+            // The sequence broke off; start over with this byte.
+            self.partial_len = 0;
+            self.put_char(c, '?');
+            self.put_byte(c, byte);
+            return;
+        }
+        self.partial[self.partial_len] = byte;
+        self.partial_len += 1;
+        if self.partial_len == sequence_len(self.partial[0]) {
+            let decoded = core::str::from_utf8(&self.partial[..self.partial_len])
+                .ok()
+                .and_then(|s| s.chars().next());
+            self.partial_len = 0;
+            self.put_char(c, decoded.unwrap_or('?'));
+        }
+    }
+
+    /// Draw `ch` at the cursor and advance it, wrapping first if the line
+    /// is full.
+    fn put_char(&mut self, c: &mut Compositor, ch: char) {
+        if self.col >= self.cols {
+            self.newline(c);
+        }
+        let (x, y) = (self.col * self.font.width(), self.row * self.font.height());
+        let mut utf8 = [0; 4];
+        let text = ch.encode_utf8(&mut utf8);
+        let font = self.font;
+        c.draw(|s| s.draw_text_in(font, x, y, text, FG, Some(BG)));
+        self.col += 1;
+    }
+
+    /// Move one cell left, to the end of the previous line from its start.
+    const fn back(&mut self) {
+        if self.col > 0 {
+            self.col -= 1;
+        } else if self.row > 0 {
+            self.row -= 1;
+            self.col = self.cols - 1;
+        }
+    }
+
+    fn newline(&mut self, c: &mut Compositor) {
+        self.col = 0;
+        if self.row + 1 < self.rows {
+            self.row += 1;
+            return;
+        }
+        let (w, h) = (self.font.width(), self.font.height());
+        let text = Rect::new(0, 0, self.cols * w, self.rows * h);
+        c.draw(|s| s.scroll_up(text, h, BG));
+    }
+}
+
+/// Length of the UTF-8 sequence starting with `first`.
+const fn sequence_len(first: u8) -> usize {
+    match first {
+        0xC0..=0xDF => 2,
+        0xE0..=0xEF => 3,
+        _ => 4,
+    }
+}
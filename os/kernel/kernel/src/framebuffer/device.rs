@@ -0,0 +1,134 @@
+//! # Framebuffer Device
+//!
+//! `/dev/fb0`: the [compositor](super::compositor)'s back buffer as a
+//! [character device](crate::chardev), [`FB0`], so userland can draw to the
+//! screen. A process maps the back buffer with `mmap` and
+//! [`MAP_DEVICE`](stdlib::syscall_abi::MAP_DEVICE), draws into it and has
+//! the changed rectangles copied to the screen with the `ioctl` requests of
+//! [`syscall_abi::fb`](stdlib::syscall_abi::fb):
+//!
+//! * `FB_GET_INFO` stores the buffer's [`FbInfo`]: its size, stride and
+//!   pixel layout.
+//! * `FB_PRESENT` marks an [`FbRect`] (or the whole screen) dirty and
+//!   [presents](super::compositor::Compositor::present).
+//!
+//! Opening the device takes `CAP_DEVICE_FB`. It moves no bytes: writes are
+//! dropped, and there is never anything to read.
+//!
+//! ## Limitations
+//!
+//! * Every process mapping the device draws into the same buffer as the
+//!   [framebuffer console](super::console); whoever draws last wins.
+//! * The write-combining framebuffer itself cannot be mapped.
+
+use crate::chardev::{self, CharDevice};
+use crate::framebuffer::Rect;
+use crate::framebuffer::compositor::{back_buffer_frame, with_compositor};
+use crate::sched::WaitQueue;
+use crate::uaccess::{UserPtr, UserSlice};
+use kernel_memory_addresses::PhysicalAddress;
+use log::{info, warn};
+use stdlib::syscall_abi::caps::CAP_DEVICE_FB;
+use stdlib::syscall_abi::fb::{FB_GET_INFO, FB_PRESENT};
+use stdlib::syscall_abi::{FbInfo, FbRect};
+
+/// The framebuffer; see the [module docs](self).
+pub static FB0: FramebufferDevice = FramebufferDevice {
+    readable: WaitQueue::new(),
+};
+
+pub struct FramebufferDevice {
+    /// Never woken; there is nothing to read.
+    readable: WaitQueue,
+}
+
+/// Register `/dev/fb0`, if there is a compositor to expose.
+pub fn init() {
+    if with_compositor(|_| ()).is_none() {
+        return;
+    }
+    match chardev::register(&FB0) {
+        Ok(_) => info!("Framebuffer device ready"),
+        Err(e) => warn!("Framebuffer device not registered: {e}"),
+    }
+}
+
+/// The back buffer's layout, or `None` without a compositor.
+fn info() -> Option<FbInfo> {
+    with_compositor(|c| {
+        let format = c.format();
+        let [red_mask, green_mask, blue_mask] = format.masks();
+        FbInfo {
+            width: c.width(),
+            height: c.height(),
+            stride: c.width(),
+            red_mask,
+            green_mask,
+            blue_mask,
+            opaque: format.opaque(),
+            reserved: 0,
+            len: c.back_buffer_len(),
+        }
+    })
+}
+
+/// Present `rect` of the back buffer, or all of it for `None`; returns
+/// the number of pixels copied.
+fn present(rect: Option<Rect>) -> Option<u64> {
+    with_compositor(|c| {
+        c.mark_dirty(rect.unwrap_or_else(|| c.bounds()));
+        c.present()
+    })
+}
+
+impl CharDevice for FramebufferDevice {
+    fn name(&self) -> &'static str {
+        "fb0"
+    }
+
+    fn read(&self, _buf: &mut [u8]) -> usize {
+        0
+    }
+
+    fn write(&self, bytes: &[u8]) -> usize {
+        bytes.len()
+    }
+
+    fn has_input(&self) -> bool {
+        false
+    }
+
+    fn readable(&self) -> &WaitQueue {
+        &self.readable
+    }
+
+    fn capabilities(&self) -> u64 {
+        CAP_DEVICE_FB
+    }
+
+    fn control(&self, request: u64, arg: u64) -> Option<u64> {
+        match request {
+            FB_GET_INFO => {
+                let info = info()?;
+                // SAFETY: `FbInfo` is `repr(C)` without padding.
+                let bytes = unsafe {
+                    core::slice::from_raw_parts((&raw const info).cast::<u8>(), size_of::<FbInfo>())
+                };
+                UserSlice::new(arg, bytes.len())
+                    .and_then(|dst| dst.write(bytes))
+                    .ok()
+                    .map(|()| 0)
+            }
+            FB_PRESENT if arg == 0 => present(None),
+            FB_PRESENT => {
+                let rect = UserPtr::<FbRect>::new(arg).and_then(UserPtr::read).ok()?;
+                present(Some(Rect::new(rect.x, rect.y, rect.width, rect.height)))
+            }
+            _ => None,
+        }
+    }
+
+    fn memory_page(&self, offset: u64) -> Option<PhysicalAddress> {
+        back_buffer_frame(offset)
+    }
+}
@@ -0,0 +1,280 @@
+//! # Console Fonts
+//!
+//! Text is drawn in the [current](current) [`Font`]: a [PSF2 font](psf2)
+//! loaded by [`init`], or the builtin one.
+//!
+//! ## Loading
+//!
+//! [`init`] loads the font named by the `font` option of the
+//! [command line](crate::cmdline), `font.psf` if not given, from the
+//! [boot module](crate::boot_modules) of that name or, failing that, the
+//! [userland bundle](crate::bundlefs). Without such a font, or if it cannot
+//! be parsed, the builtin font stays in use.
+//!
+//! ## Builtin font
+//!
+//! A fixed 8x8 font covering printable ASCII (`0x20..=0x7E`), derived from the
+//! public-domain `font8x8_basic` set (itself based on the IBM PC BIOS font).
+//! Glyphs are drawn at double height, giving 8x16 character cells like the
+//! classic VGA text mode.
+//!
+//! Each glyph is eight rows, top to bottom; bit 0 of a row is its **leftmost**
+//! pixel. Characters without a glyph render as `?`, in either font.
+
+use crate::cmdline::{self, Param, ParamKind};
+use crate::framebuffer::psf2::Psf2Font;
+use crate::{boot_modules, bundlefs};
+use kernel_sync::SyncOnceCell;
+use log::{debug, info, warn};
+
+pub static FONT_PARAM: Param = Param {
+    name: "font",
+    kind: ParamKind::Str,
+    help: "boot module or bundle entry with a PSF2 console font",
+};
+
+/// Font looked up if the command line names none.
+const DEFAULT_FONT: &str = "font.psf";
+
+/// The loaded PSF2 font, `None` if the builtin one is used.
+static LOADED: SyncOnceCell<Option<Psf2Font<'static>>> = SyncOnceCell::new();
+
+/// Width of a character cell of the builtin font in pixels.
+pub const GLYPH_WIDTH: u32 = 8;
+
+/// Height of a character cell of the builtin font in pixels.
+pub const GLYPH_HEIGHT: u32 = 16;
+
+/// Number of bitmap rows per builtin glyph.
+const ROWS: usize = 8;
+
+/// Pixel rows each builtin bitmap row is drawn to.
+const ROW_HEIGHT: u32 = 2;
+
+/// First character with a glyph.
+const FIRST: u32 = 0x20;
+
+/// Glyphs for `0x20..=0x7E`.
+#[rustfmt::skip]
+static GLYPHS: [[u8; ROWS]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x18, 0x3C, 0x3C, 0x18, 0x18, 0x00, 0x18, 0x00], // '!'
+    [0x36, 0x36, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '"'
+    [0x36, 0x36, 0x7F, 0x36, 0x7F, 0x36, 0x36, 0x00], // '#'
+    [0x0C, 0x3E, 0x03, 0x1E, 0x30, 0x1F, 0x0C, 0x00], // '$'
+    [0x00, 0x63, 0x33, 0x18, 0x0C, 0x66, 0x63, 0x00], // '%'
+    [0x1C, 0x36, 0x1C, 0x6E, 0x3B, 0x33, 0x6E, 0x00], // '&'
+    [0x06, 0x06, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00], // '''
+    [0x18, 0x0C, 0x06, 0x06, 0x06, 0x0C, 0x18, 0x00], // '('
+    [0x06, 0x0C, 0x18, 0x18, 0x18, 0x0C, 0x06, 0x00], // ')'
+    [0x00, 0x66, 0x3C, 0xFF, 0x3C, 0x66, 0x00, 0x00], // '*'
+    [0x00, 0x0C, 0x0C, 0x3F, 0x0C, 0x0C, 0x00, 0x00], // '+'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C, 0x06], // ','
+    [0x00, 0x00, 0x00, 0x3F, 0x00, 0x00, 0x00, 0x00], // '-'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C, 0x00], // '.'
+    [0x60, 0x30, 0x18, 0x0C, 0x06, 0x03, 0x01, 0x00], // '/'
+    [0x3E, 0x63, 0x73, 0x7B, 0x6F, 0x67, 0x3E, 0x00], // '0'
+    [0x0C, 0x0E, 0x0C, 0x0C, 0x0C, 0x0C, 0x3F, 0x00], // '1'
+    [0x1E, 0x33, 0x30, 0x1C, 0x06, 0x33, 0x3F, 0x00], // '2'
+    [0x1E, 0x33, 0x30, 0x1C, 0x30, 0x33, 0x1E, 0x00], // '3'
+    [0x38, 0x3C, 0x36, 0x33, 0x7F, 0x30, 0x78, 0x00], // '4'
+    [0x3F, 0x03, 0x1F, 0x30, 0x30, 0x33, 0x1E, 0x00], // '5'
+    [0x1C, 0x06, 0x03, 0x1F, 0x33, 0x33, 0x1E, 0x00], // '6'
+    [0x3F, 0x33, 0x30, 0x18, 0x0C, 0x0C, 0x0C, 0x00], // '7'
+    [0x1E, 0x33, 0x33, 0x1E, 0x33, 0x33, 0x1E, 0x00], // '8'
+    [0x1E, 0x33, 0x33, 0x3E, 0x30, 0x18, 0x0E, 0x00], // '9'
+    [0x00, 0x0C, 0x0C, 0x00, 0x00, 0x0C, 0x0C, 0x00], // ':'
+    [0x00, 0x0C, 0x0C, 0x00, 0x00, 0x0C, 0x0C, 0x06], // ';'
+    [0x18, 0x0C, 0x06, 0x03, 0x06, 0x0C, 0x18, 0x00], // '<'
+    [0x00, 0x00, 0x3F, 0x00, 0x00, 0x3F, 0x00, 0x00], // '='
+    [0x06, 0x0C, 0x18, 0x30, 0x18, 0x0C, 0x06, 0x00], // '>'
+    [0x1E, 0x33, 0x30, 0x18, 0x0C, 0x00, 0x0C, 0x00], // '?'
+    [0x3E, 0x63, 0x7B, 0x7B, 0x7B, 0x03, 0x1E, 0x00], // '@'
+    [0x0C, 0x1E, 0x33, 0x33, 0x3F, 0x33, 0x33, 0x00], // 'A'
+    [0x3F, 0x66, 0x66, 0x3E, 0x66, 0x66, 0x3F, 0x00], // 'B'
+    [0x3C, 0x66, 0x03, 0x03, 0x03, 0x66, 0x3C, 0x00], // 'C'
+    [0x1F, 0x36, 0x66, 0x66, 0x66, 0x36, 0x1F, 0x00], // 'D'
+    [0x7F, 0x46, 0x16, 0x1E, 0x16, 0x46, 0x7F, 0x00], // 'E'
+    [0x7F, 0x46, 0x16, 0x1E, 0x16, 0x06, 0x0F, 0x00], // 'F'
+    [0x3C, 0x66, 0x03, 0x03, 0x73, 0x66, 0x7C, 0x00], // 'G'
+    [0x33, 0x33, 0x33, 0x3F, 0x33, 0x33, 0x33, 0x00], // 'H'
+    [0x1E, 0x0C, 0x0C, 0x0C, 0x0C, 0x0C, 0x1E, 0x00], // 'I'
+    [0x78, 0x30, 0x30, 0x30, 0x33, 0x33, 0x1E, 0x00], // 'J'
+    [0x67, 0x66, 0x36, 0x1E, 0x36, 0x66, 0x67, 0x00], // 'K'
+    [0x0F, 0x06, 0x06, 0x06, 0x46, 0x66, 0x7F, 0x00], // 'L'
+    [0x63, 0x77, 0x7F, 0x7F, 0x6B, 0x63, 0x63, 0x00], // 'M'
+    [0x63, 0x67, 0x6F, 0x7B, 0x73, 0x63, 0x63, 0x00], // 'N'
+    [0x1C, 0x36, 0x63, 0x63, 0x63, 0x36, 0x1C, 0x00], // 'O'
+    [0x3F, 0x66, 0x66, 0x3E, 0x06, 0x06, 0x0F, 0x00], // 'P'
+    [0x1E, 0x33, 0x33, 0x33, 0x3B, 0x1E, 0x38, 0x00], // 'Q'
+    [0x3F, 0x66, 0x66, 0x3E, 0x36, 0x66, 0x67, 0x00], // 'R'
+    [0x1E, 0x33, 0x07, 0x0E, 0x38, 0x33, 0x1E, 0x00], // 'S'
+    [0x3F, 0x2D, 0x0C, 0x0C, 0x0C, 0x0C, 0x1E, 0x00], // 'T'
+    [0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x3F, 0x00], // 'U'
+    [0x33, 0x33, 0x33, 0x33, 0x33, 0x1E, 0x0C, 0x00], // 'V'
+    [0x63, 0x63, 0x63, 0x6B, 0x7F, 0x77, 0x63, 0x00], // 'W'
+    [0x63, 0x63, 0x36, 0x1C, 0x1C, 0x36, 0x63, 0x00], // 'X'
+    [0x33, 0x33, 0x33, 0x1E, 0x0C, 0x0C, 0x1E, 0x00], // 'Y'
+    [0x7F, 0x63, 0x31, 0x18, 0x4C, 0x66, 0x7F, 0x00], // 'Z'
+    [0x1E, 0x06, 0x06, 0x06, 0x06, 0x06, 0x1E, 0x00], // '['
+    [0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x40, 0x00], // '\'
+    [0x1E, 0x18, 0x18, 0x18, 0x18, 0x18, 0x1E, 0x00], // ']'
+    [0x08, 0x1C, 0x36, 0x63, 0x00, 0x00, 0x00, 0x00], // '^'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF], // '_'
+    [0x0C, 0x0C, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00], // '`'
+    [0x00, 0x00, 0x1E, 0x30, 0x3E, 0x33, 0x6E, 0x00], // 'a'
+    [0x07, 0x06, 0x06, 0x3E, 0x66, 0x66, 0x3B, 0x00], // 'b'
+    [0x00, 0x00, 0x1E, 0x33, 0x03, 0x33, 0x1E, 0x00], // 'c'
+    [0x38, 0x30, 0x30, 0x3E, 0x33, 0x33, 0x6E, 0x00], // 'd'
+    [0x00, 0x00, 0x1E, 0x33, 0x3F, 0x03, 0x1E, 0x00], // 'e'
+    [0x1C, 0x36, 0x06, 0x0F, 0x06, 0x06, 0x0F, 0x00], // 'f'
+    [0x00, 0x00, 0x6E, 0x33, 0x33, 0x3E, 0x30, 0x1F], // 'g'
+    [0x07, 0x06, 0x36, 0x6E, 0x66, 0x66, 0x67, 0x00], // 'h'
+    [0x0C, 0x00, 0x0E, 0x0C, 0x0C, 0x0C, 0x1E, 0x00], // 'i'
+    [0x30, 0x00, 0x30, 0x30, 0x30, 0x33, 0x33, 0x1E], // 'j'
+    [0x07, 0x06, 0x66, 0x36, 0x1E, 0x36, 0x67, 0x00], // 'k'
+    [0x0E, 0x0C, 0x0C, 0x0C, 0x0C, 0x0C, 0x1E, 0x00], // 'l'
+    [0x00, 0x00, 0x33, 0x7F, 0x7F, 0x6B, 0x63, 0x00], // 'm'
+    [0x00, 0x00, 0x1F, 0x33, 0x33, 0x33, 0x33, 0x00], // 'n'
+    [0x00, 0x00, 0x1E, 0x33, 0x33, 0x33, 0x1E, 0x00], // 'o'
+    [0x00, 0x00, 0x3B, 0x66, 0x66, 0x3E, 0x06, 0x0F], // 'p'
+    [0x00, 0x00, 0x6E, 0x33, 0x33, 0x3E, 0x30, 0x78], // 'q'
+    [0x00, 0x00, 0x3B, 0x6E, 0x66, 0x06, 0x0F, 0x00], // 'r'
+    [0x00, 0x00, 0x3E, 0x03, 0x1E, 0x30, 0x1F, 0x00], // 's'
+    [0x08, 0x0C, 0x3E, 0x0C, 0x0C, 0x2C, 0x18, 0x00], // 't'
+    [0x00, 0x00, 0x33, 0x33, 0x33, 0x33, 0x6E, 0x00], // 'u'
+    [0x00, 0x00, 0x33, 0x33, 0x33, 0x1E, 0x0C, 0x00], // 'v'
+    [0x00, 0x00, 0x63, 0x6B, 0x7F, 0x7F, 0x36, 0x00], // 'w'
+    [0x00, 0x00, 0x63, 0x36, 0x1C, 0x36, 0x63, 0x00], // 'x'
+    [0x00, 0x00, 0x33, 0x33, 0x33, 0x3E, 0x30, 0x1F], // 'y'
+    [0x00, 0x00, 0x3F, 0x19, 0x0C, 0x26, 0x3F, 0x00], // 'z'
+    [0x38, 0x0C, 0x0C, 0x07, 0x0C, 0x0C, 0x38, 0x00], // '{'
+    [0x18, 0x18, 0x18, 0x00, 0x18, 0x18, 0x18, 0x00], // '|'
+    [0x07, 0x0C, 0x0C, 0x38, 0x0C, 0x0C, 0x07, 0x00], // '}'
+    [0x6E, 0x3B, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '~'
+];
+
+/// Load the console font; see the [module docs](self). Must run after
+/// [`boot_modules::init`] and [`bundlefs::mount`]; calling it again does
+/// nothing.
+pub fn init() {
+    LOADED.get_or_init(|| {
+        let name = cmdline::get_str(FONT_PARAM.name).unwrap_or(DEFAULT_FONT);
+        let Some(bytes) = boot_modules::find(name).or_else(|| bundlefs::lookup(name)) else {
+            debug!("No console font {name}; using the builtin font");
+            return None;
+        };
+        match Psf2Font::parse(bytes) {
+            Ok(font) => {
+                info!(
+                    "Loaded console font {name} ({}x{})",
+                    font.width(),
+                    font.height()
+                );
+                Some(font)
+            }
+            Err(e) => {
+                warn!("Cannot load console font {name}: {e}; using the builtin font");
+                None
+            }
+        }
+    });
+}
+
+/// The font text is drawn in.
+#[must_use]
+pub fn current() -> Font<'static> {
+    match LOADED.get() {
+        Some(Some(font)) => Font::Psf2(font),
+        _ => Font::Builtin,
+    }
+}
+
+/// A font to draw text in.
+#[derive(Debug, Copy, Clone)]
+pub enum Font<'a> {
+    /// The builtin 8x16 font.
+    Builtin,
+    Psf2(&'a Psf2Font<'a>),
+}
+
+impl<'a> Font<'a> {
+    /// Width of a character cell in pixels.
+    #[must_use]
+    pub const fn width(self) -> u32 {
+        match self {
+            Self::Builtin => GLYPH_WIDTH,
+            Self::Psf2(font) => font.width(),
+        }
+    }
+
+    /// Height of a character cell in pixels.
+    #[must_use]
+    pub const fn height(self) -> u32 {
+        match self {
+            Self::Builtin => GLYPH_HEIGHT,
+            Self::Psf2(font) => font.height(),
+        }
+    }
+
+    /// Width of `text` drawn on a single line, in pixels.
+    #[must_use]
+    pub fn text_width(self, text: &str) -> u32 {
+        u32::try_from(text.chars().count())
+            .unwrap_or(u32::MAX)
+            .saturating_mul(self.width())
+    }
+
+    /// The glyph of `c`, or that of `?` if the font has none.
+    #[must_use]
+    pub fn glyph(self, c: char) -> Glyph<'a> {
+        match self {
+            Self::Builtin => Glyph::Builtin(builtin_glyph(c)),
+            Self::Psf2(font) => {
+                let bitmap = font
+                    .glyph(c)
+                    .or_else(|| font.glyph('?'))
+                    .unwrap_or_default();
+                Glyph::Psf2 {
+                    bitmap,
+                    stride: font.stride(),
+                }
+            }
+        }
+    }
+}
+
+/// The bitmap of a single character.
+#[derive(Debug, Copy, Clone)]
+pub enum Glyph<'a> {
+    Builtin(&'static [u8; ROWS]),
+    /// Rows of `stride` bytes; empty if the font has neither the character
+    /// nor `?`.
+    Psf2 {
+        bitmap: &'a [u8],
+        stride: usize,
+    },
+}
+
+impl Glyph<'_> {
+    /// Whether the pixel at `(x, y)` of the character cell is set.
+    #[must_use]
+    pub fn is_set(&self, x: u32, y: u32) -> bool {
+        match *self {
+            Self::Builtin(rows) => rows
+                .get((y / ROW_HEIGHT) as usize)
+                .is_some_and(|row| row & (1 << x) != 0),
+            Self::Psf2 { bitmap, stride } => bitmap
+                .get(y as usize * stride + x as usize / 8)
+                .is_some_and(|byte| byte & (0x80 >> (x % 8)) != 0),
+        }
+    }
+}
+
+/// The builtin bitmap of `c`, or that of `?` if there is none.
+fn builtin_glyph(c: char) -> &'static [u8; ROWS] {
+    let index = u32::from(c).wrapping_sub(FIRST) as usize;
+    GLYPHS
+        .get(index)
+        .unwrap_or(&GLYPHS[('?' as u32 - FIRST) as usize])
+}
@@ -0,0 +1,332 @@
+//! # Image Decoding
+//!
+//! [`Image::parse`] reads the two formats small enough to decode in the
+//! kernel without a heap:
+//!
+//! * **BMP**: uncompressed 24- and 32-bit bitmaps, bottom-up or top-down,
+//!   including 32-bit ones whose channels are given by bit masks
+//!   (`BI_BITFIELDS`, `BI_ALPHABITFIELDS`). 32-bit bitmaps without an alpha
+//!   mask are opaque. Palette and RLE images are refused.
+//! * **QOI**: the [Quite OK Image](https://qoiformat.org/) format, with 3 or
+//!   4 channels.
+//!
+//! Images are decoded on the fly from the bytes they were parsed from:
+//! [`for_each_pixel`](Image::for_each_pixel) hands out every pixel as an RGBA
+//! [`Color`], and [`Surface::draw_image`](crate::framebuffer::surface::Surface::draw_image)
+//! blends them onto a surface in its pixel format. Parsing checks the whole
+//! image, so decoding cannot fail halfway.
+
+use crate::framebuffer::Color;
+use core::fmt;
+
+/// Widest and tallest image accepted.
+pub const MAX_DIMENSION: u32 = 4096;
+
+/// Why an image could not be parsed.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ImageError {
+    /// Neither a BMP nor a QOI file.
+    UnknownFormat,
+    /// A BMP variant that is not decoded, e.g. with a palette or compression.
+    Unsupported,
+    /// Zero width or height, or more than [`MAX_DIMENSION`].
+    InvalidSize,
+    /// The file ends before the image does.
+    Truncated,
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownFormat => f.write_str("not a BMP or QOI image"),
+            Self::Unsupported => f.write_str("unsupported BMP variant"),
+            Self::InvalidSize => write!(f, "size is zero or exceeds {MAX_DIMENSION} pixels"),
+            Self::Truncated => f.write_str("image data is truncated"),
+        }
+    }
+}
+
+/// A parsed image; see the [module docs](self).
+#[derive(Debug, Copy, Clone)]
+pub struct Image<'a> {
+    width: u32,
+    height: u32,
+    data: Data<'a>,
+}
+
+#[derive(Debug, Copy, Clone)]
+enum Data<'a> {
+    Bmp(Bmp<'a>),
+    /// The QOI chunks, without header and end marker.
+    Qoi(&'a [u8]),
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Bmp<'a> {
+    /// Rows as stored, each `stride` bytes.
+    rows: &'a [u8],
+    stride: usize,
+    /// Bytes per pixel, 3 or 4.
+    bytes: usize,
+    /// Whether the first stored row is the top one.
+    top_down: bool,
+    /// Red, green, blue and alpha masks of 32-bit pixels.
+    masks: [u32; 4],
+}
+
+impl<'a> Image<'a> {
+    /// Parse a BMP or QOI file.
+    ///
+    /// # Errors
+    /// See [`ImageError`].
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, ImageError> {
+        if bytes.starts_with(b"BM") {
+            parse_bmp(bytes)
+        } else if bytes.starts_with(b"qoif") {
+            parse_qoi(bytes)
+        } else {
+            Err(ImageError::UnknownFormat)
+        }
+    }
+
+    #[must_use]
+    pub const fn width(&self) -> u32 {
+        self.width
+    }
+
+    #[must_use]
+    pub const fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Call `f` with the position and color of every pixel.
+    ///
+    /// Pixels come in the order they are stored, which for bottom-up BMPs
+    /// is not top to bottom.
+    pub fn for_each_pixel(&self, mut f: impl FnMut(u32, u32, Color)) {
+        match self.data {
+            Data::Bmp(bmp) => {
+                for (row, bytes) in (0..self.height).zip(bmp.rows.chunks_exact(bmp.stride)) {
+                    let y = if bmp.top_down {
+                        row
+                    } else {
+                        self.height - 1 - row
+                    };
+                    for (x, px) in (0..self.width).zip(bytes.chunks_exact(bmp.bytes)) {
+                        f(x, y, bmp.color(px));
+                    }
+                }
+            }
+            Data::Qoi(chunks) => {
+                let width = self.width;
+                let mut i = 0;
+                // Checked by `parse_qoi`.
+                let _ = decode_qoi(chunks, self.pixels(), |color| {
+                    f(i % width, i / width, color);
+                    i += 1;
+                });
+            }
+        }
+    }
+
+    const fn pixels(&self) -> u32 {
+        self.width * self.height
+    }
+}
+
+impl Bmp<'_> {
+    fn color(&self, px: &[u8]) -> Color {
+        if px.len() == 3 {
+            return Color::new(px[2], px[1], px[0]);
+        }
+        let px = u32::from_le_bytes([px[0], px[1], px[2], px[3]]);
+        let [r, g, b, a] = self.masks;
+        Color::rgba(
+            channel(px, r, 0),
+            channel(px, g, 0),
+            channel(px, b, 0),
+            channel(px, a, 0xFF),
+        )
+    }
+}
+
+/// The channel of `px` selected by `mask`, scaled to 8 bits; `default` if
+/// the mask is empty.
+#[allow(clippy::cast_possible_truncation)]
+const fn channel(px: u32, mask: u32, default: u8) -> u8 {
+    if mask == 0 {
+        return default;
+    }
+    let shift = mask.trailing_zeros();
+    let max = mask >> shift;
+    let value = (px & mask) >> shift;
+    ((value as u64 * 255 + max as u64 / 2) / max as u64) as u8
+}
+
+fn u16_le(bytes: &[u8], at: usize) -> Result<u16, ImageError> {
+    let b = bytes.get(at..at + 2).ok_or(ImageError::Truncated)?;
+    Ok(u16::from_le_bytes([b[0], b[1]]))
+}
+
+fn u32_le(bytes: &[u8], at: usize) -> Result<u32, ImageError> {
+    let b = bytes.get(at..at + 4).ok_or(ImageError::Truncated)?;
+    Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn u32_be(bytes: &[u8], at: usize) -> Result<u32, ImageError> {
+    let b = bytes.get(at..at + 4).ok_or(ImageError::Truncated)?;
+    Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+const fn check_size(width: u32, height: u32) -> Result<(), ImageError> {
+    if width == 0 || height == 0 || width > MAX_DIMENSION || height > MAX_DIMENSION {
+        Err(ImageError::InvalidSize)
+    } else {
+        Ok(())
+    }
+}
+
+/// `BI_RGB`: no compression.
+const BI_RGB: u32 = 0;
+/// `BI_BITFIELDS`: red, green and blue masks follow the 40-byte header.
+const BI_BITFIELDS: u32 = 3;
+/// `BI_ALPHABITFIELDS`: an alpha mask follows as well.
+const BI_ALPHABITFIELDS: u32 = 6;
+
+#[allow(clippy::cast_sign_loss)]
+fn parse_bmp(bytes: &[u8]) -> Result<Image<'_>, ImageError> {
+    let data_offset = u32_le(bytes, 10)? as usize;
+    let header_size = u32_le(bytes, 14)?;
+    if header_size < 40 {
+        return Err(ImageError::Unsupported);
+    }
+    let width = u32_le(bytes, 18)?.cast_signed();
+    let height = u32_le(bytes, 22)?.cast_signed();
+    let bits = u16_le(bytes, 28)?;
+    let compression = u32_le(bytes, 30)?;
+    if width <= 0 || height == 0 || height == i32::MIN {
+        return Err(ImageError::InvalidSize);
+    }
+    let (width, top_down) = (width as u32, height < 0);
+    let height = height.unsigned_abs();
+    check_size(width, height)?;
+
+    let masks = match (bits, compression) {
+        (24, BI_RGB) => [0; 4],
+        (32, BI_RGB) => [0x00FF_0000, 0x0000_FF00, 0x0000_00FF, 0],
+        (32, BI_BITFIELDS | BI_ALPHABITFIELDS) => {
+            // The alpha mask is part of V3 and later headers, or follows
+            // the other masks.
+            let alpha = header_size >= 56 || compression == BI_ALPHABITFIELDS;
+            [
+                u32_le(bytes, 54)?,
+                u32_le(bytes, 58)?,
+                u32_le(bytes, 62)?,
+                if alpha { u32_le(bytes, 66)? } else { 0 },
+            ]
+        }
+        _ => return Err(ImageError::Unsupported),
+    };
+
+    let bytes_per_pixel = usize::from(bits / 8);
+    let stride = (width as usize * bytes_per_pixel).next_multiple_of(4);
+    let rows = bytes
+        .get(data_offset..)
+        .and_then(|rows| rows.get(..stride * height as usize))
+        .ok_or(ImageError::Truncated)?;
+    Ok(Image {
+        width,
+        height,
+        data: Data::Bmp(Bmp {
+            rows,
+            stride,
+            bytes: bytes_per_pixel,
+            top_down,
+            masks,
+        }),
+    })
+}
+
+/// Size of the QOI header.
+const QOI_HEADER: usize = 14;
+/// The seven zero bytes and the one that end a QOI file.
+const QOI_END: [u8; 8] = [0, 0, 0, 0, 0, 0, 0, 1];
+
+fn parse_qoi(bytes: &[u8]) -> Result<Image<'_>, ImageError> {
+    let width = u32_be(bytes, 4)?;
+    let height = u32_be(bytes, 8)?;
+    let channels = *bytes.get(12).ok_or(ImageError::Truncated)?;
+    if !matches!(channels, 3 | 4) {
+        return Err(ImageError::Unsupported);
+    }
+    check_size(width, height)?;
+    if bytes.len() < QOI_HEADER + QOI_END.len() || !bytes.ends_with(&QOI_END) {
+        return Err(ImageError::Truncated);
+    }
+
+    let chunks = &bytes[QOI_HEADER..bytes.len() - QOI_END.len()];
+    let image = Image {
+        width,
+        height,
+        data: Data::Qoi(chunks),
+    };
+    decode_qoi(chunks, image.pixels(), |_| {})?;
+    Ok(image)
+}
+
+/// Decode `pixels` pixels from the QOI `chunks`, handing each to `f`.
+#[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+fn decode_qoi(chunks: &[u8], pixels: u32, mut f: impl FnMut(Color)) -> Result<(), ImageError> {
+    let mut seen = [Color::rgba(0, 0, 0, 0); 64];
+    let mut px = Color::rgba(0, 0, 0, 0xFF);
+    let mut bytes = chunks.iter().copied();
+    let mut next = || bytes.next().ok_or(ImageError::Truncated);
+
+    let mut run = 0;
+    for _ in 0..pixels {
+        if run > 0 {
+            run -= 1;
+            f(px);
+            continue;
+        }
+
+        let op = next()?;
+        match op {
+            0xFE => {
+                px = Color::rgba(next()?, next()?, next()?, px.a);
+            }
+            0xFF => {
+                px = Color::rgba(next()?, next()?, next()?, next()?);
+            }
+            _ => match op >> 6 {
+                // QOI_OP_INDEX
+                0b00 => px = seen[usize::from(op & 0x3F)],
+                // QOI_OP_DIFF
+                0b01 => {
+                    px.r = px.r.wrapping_add((op >> 4 & 3).wrapping_sub(2));
+                    px.g = px.g.wrapping_add((op >> 2 & 3).wrapping_sub(2));
+                    px.b = px.b.wrapping_add((op & 3).wrapping_sub(2));
+                }
+                // QOI_OP_LUMA
+                0b10 => {
+                    let dg = (op & 0x3F) as i8 - 32;
+                    let rb = next()?;
+                    let dr = dg + (rb >> 4) as i8 - 8;
+                    let db = dg + (rb & 0xF) as i8 - 8;
+                    px.r = px.r.wrapping_add(dr as u8);
+                    px.g = px.g.wrapping_add(dg as u8);
+                    px.b = px.b.wrapping_add(db as u8);
+                }
+                // QOI_OP_RUN; this pixel and `run` more.
+                _ => run = op & 0x3F,
+            },
+        }
+        seen[qoi_hash(px)] = px;
+        f(px);
+    }
+    Ok(())
+}
+
+const fn qoi_hash(px: Color) -> usize {
+    (px.r as usize * 3 + px.g as usize * 5 + px.b as usize * 7 + px.a as usize * 11) % 64
+}
@@ -0,0 +1,194 @@
+//! # Pixel Formats
+//!
+//! GOP reports a framebuffer's layout either as one of two fixed 8:8:8
+//! orders (`Rgb`, `Bgr`) or as per-channel bit masks (`Bitmask`).
+//! [`PixelFormat`] reduces all of them to masks, so drawing code converts
+//! between [`Color`] and raw pixels the same way for every layout.
+//!
+//! Channels narrower than 8 bits keep the most significant bits of a color
+//! component; when unpacked, they are scaled back to the full `0..=255` range.
+//! Bits not covered by the red, green or blue mask (the alpha channel or
+//! reserved padding) are set in packed pixels.
+//!
+//! ## Pixel size
+//!
+//! Pixels are handled as `u32` values in memory. In the framebuffer, a pixel
+//! occupies as many bytes as its highest mask bit requires
+//! ([`bytes_per_pixel`](PixelFormat::bytes_per_pixel)): 4 for `Rgb`/`Bgr` and
+//! 8:8:8:8 bitmasks, 3 for packed 8:8:8 and 2 for 5:6:5 or 5:5:5 layouts.
+//! [`write_pixel`] stores the low bytes of a `u32` pixel accordingly.
+
+use crate::framebuffer::Color;
+use kernel_info::boot::{BootPixelFormat, BootPixelMasks};
+
+/// One color channel inside a packed pixel.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+struct Channel {
+    /// Position of the channel's least significant bit.
+    shift: u32,
+    /// Width in bits, at most 8.
+    bits: u32,
+}
+
+impl Channel {
+    /// The channel selected by `mask`; of wider channels, only the top 8 bits are used.
+    const fn from_mask(mask: u32) -> Self {
+        let shift = mask.trailing_zeros() % 32;
+        let bits = (mask >> shift).trailing_ones();
+        if bits > 8 {
+            Self {
+                shift: shift + bits - 8,
+                bits: 8,
+            }
+        } else {
+            Self { shift, bits }
+        }
+    }
+
+    /// Place the 8-bit component `value` into this channel.
+    const fn pack(self, value: u8) -> u32 {
+        if self.bits == 0 {
+            return 0;
+        }
+        ((value as u32) >> (8 - self.bits)) << self.shift
+    }
+
+    /// The bits of this channel.
+    const fn mask(self) -> u32 {
+        ((1u32 << self.bits) - 1) << self.shift
+    }
+
+    /// Extract this channel from `pixel`, scaled to 8 bits.
+    #[allow(clippy::cast_possible_truncation)]
+    const fn unpack(self, pixel: u32) -> u8 {
+        if self.bits == 0 {
+            return 0;
+        }
+        let max = (1u32 << self.bits) - 1;
+        let value = (pixel >> self.shift) & max;
+        ((value * 255 + max / 2) / max) as u8
+    }
+}
+
+/// Layout of a 32-bit pixel; see the [module docs](self).
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct PixelFormat {
+    red: Channel,
+    green: Channel,
+    blue: Channel,
+    /// Bits set in every packed pixel.
+    opaque: u32,
+    /// Bytes per pixel in the framebuffer, 1 to 4.
+    bytes: u8,
+}
+
+impl PixelFormat {
+    /// Bytes `[R, G, B, X]` in memory; value `0xXX_BB_GG_RR`.
+    pub const RGB: Self = Self::from_masks(0x0000_00FF, 0x0000_FF00, 0x00FF_0000, 0xFF00_0000);
+
+    /// Bytes `[B, G, R, X]` in memory; value `0xXX_RR_GG_BB`.
+    pub const BGR: Self = Self::from_masks(0x00FF_0000, 0x0000_FF00, 0x0000_00FF, 0xFF00_0000);
+
+    /// A format with the given channel masks.
+    ///
+    /// Each color mask must be a contiguous run of bits. The pixel size
+    /// follows from the highest bit of any mask, including `reserved` (alpha
+    /// or padding); bits of the pixel outside the color masks are set in
+    /// packed pixels.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn from_masks(red: u32, green: u32, blue: u32, reserved: u32) -> Self {
+        let all = red | green | blue | reserved;
+        let bits = 32 - all.leading_zeros();
+        let bytes = if bits == 0 { 1 } else { bits.div_ceil(8) };
+        let pixel_mask = if bytes == 4 {
+            u32::MAX
+        } else {
+            (1 << (bytes * 8)) - 1
+        };
+        Self {
+            red: Channel::from_mask(red),
+            green: Channel::from_mask(green),
+            blue: Channel::from_mask(blue),
+            opaque: !(red | green | blue) & pixel_mask,
+            bytes: bytes as u8,
+        }
+    }
+
+    /// The format GOP reported, or `None` for `BltOnly` framebuffers and
+    /// bitmasks without any color bits.
+    #[must_use]
+    pub const fn from_boot(format: BootPixelFormat, masks: &BootPixelMasks) -> Option<Self> {
+        match format {
+            BootPixelFormat::Rgb => Some(Self::RGB),
+            BootPixelFormat::Bgr => Some(Self::BGR),
+            BootPixelFormat::Bitmask => {
+                if masks.red_mask | masks.green_mask | masks.blue_mask == 0 {
+                    None
+                } else {
+                    Some(Self::from_masks(
+                        masks.red_mask,
+                        masks.green_mask,
+                        masks.blue_mask,
+                        masks.alpha_mask,
+                    ))
+                }
+            }
+            BootPixelFormat::BltOnly => None,
+        }
+    }
+
+    /// Bytes one pixel occupies in the framebuffer.
+    #[must_use]
+    pub const fn bytes_per_pixel(self) -> usize {
+        self.bytes as usize
+    }
+
+    /// The red, green and blue bits that [`pack`](Self::pack) fills; of
+    /// channels wider than 8 bits, only the top 8.
+    #[must_use]
+    pub const fn masks(self) -> [u32; 3] {
+        [self.red.mask(), self.green.mask(), self.blue.mask()]
+    }
+
+    /// The bits set in every packed pixel.
+    #[must_use]
+    pub const fn opaque(self) -> u32 {
+        self.opaque
+    }
+
+    /// The pixel value of `color`; its alpha is ignored.
+    #[must_use]
+    pub const fn pack(self, color: Color) -> u32 {
+        self.opaque | self.red.pack(color.r) | self.green.pack(color.g) | self.blue.pack(color.b)
+    }
+
+    /// The (opaque) color of `pixel`.
+    #[must_use]
+    pub const fn unpack(self, pixel: u32) -> Color {
+        Color::new(
+            self.red.unpack(pixel),
+            self.green.unpack(pixel),
+            self.blue.unpack(pixel),
+        )
+    }
+}
+
+/// Store the low [`bytes_per_pixel`](PixelFormat::bytes_per_pixel) bytes of
+/// `pixel` at `dst`, little-endian.
+///
+/// # Safety
+/// `dst` must be valid for writes of `format.bytes_per_pixel()` bytes.
+#[inline]
+pub const unsafe fn write_pixel(dst: *mut u8, pixel: u32, format: PixelFormat) {
+    let bytes = pixel.to_le_bytes();
+    unsafe {
+        match format.bytes {
+            4 => dst.cast::<u32>().write_unaligned(pixel),
+            2 => dst
+                .cast::<u16>()
+                .write_unaligned(u16::from_le_bytes([bytes[0], bytes[1]])),
+            n => core::ptr::copy_nonoverlapping(bytes.as_ptr(), dst, n as usize),
+        }
+    }
+}
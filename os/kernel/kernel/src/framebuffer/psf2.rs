@@ -0,0 +1,240 @@
+//! # PC Screen Fonts
+//!
+//! [`Psf2Font::parse`] reads version 2 PC Screen Font files, the console
+//! fonts most distributions ship in `/usr/share/consolefonts` (decompress the
+//! `.psf.gz` files first). A file is a 32-byte header, the glyph bitmaps and,
+//! if the header flags it, a Unicode table mapping characters to glyphs.
+//!
+//! ## Bitmaps
+//!
+//! Every glyph is `height` rows of `ceil(width / 8)` bytes; the most
+//! significant bit of a row's first byte is its **leftmost** pixel.
+//!
+//! ## Unicode table
+//!
+//! For each glyph in turn, the table lists the characters it renders as
+//! UTF-8, optionally followed by `0xFE`-prefixed sequences of combining
+//! characters (which are skipped here), and ends with `0xFF`. Fonts without a
+//! table map character `n` to glyph `n`.
+//!
+//! Parsing checks the whole table and indexes the Latin-1 range, so looking
+//! up the common characters is a single array access; any others are found
+//! by scanning the table.
+
+use core::fmt;
+
+/// The magic number a PSF2 file starts with.
+const MAGIC: [u8; 4] = [0x72, 0xB5, 0x4A, 0x86];
+
+/// Size of the header in the files this code knows about.
+const HEADER_SIZE: usize = 32;
+
+/// Header flag: a Unicode table follows the glyphs.
+const HAS_UNICODE_TABLE: u32 = 1;
+
+/// Ends the entry of a glyph in the Unicode table.
+const ENTRY_END: u8 = 0xFF;
+
+/// Starts a sequence of combining characters in the Unicode table.
+const SEQUENCE_START: u8 = 0xFE;
+
+/// Widest and tallest glyph accepted.
+pub const MAX_GLYPH_SIZE: u32 = 64;
+
+/// Characters indexed when parsing, `U+0000..=U+00FF`.
+const INDEXED: usize = 256;
+
+/// Marks a character without a glyph in the index.
+const NO_GLYPH: u32 = u32::MAX;
+
+/// Why a font could not be parsed.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum FontError {
+    /// Not a PSF2 file.
+    BadMagic,
+    /// An unknown version, no glyphs, or glyphs of zero or more than
+    /// [`MAX_GLYPH_SIZE`] pixels.
+    Unsupported,
+    /// The file ends before the glyphs or the Unicode table do.
+    Truncated,
+    /// The Unicode table is not valid UTF-8.
+    InvalidTable,
+}
+
+impl fmt::Display for FontError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadMagic => f.write_str("not a PSF2 font"),
+            Self::Unsupported => f.write_str("unsupported PSF2 version or glyph size"),
+            Self::Truncated => f.write_str("font data is truncated"),
+            Self::InvalidTable => f.write_str("invalid Unicode table"),
+        }
+    }
+}
+
+/// A parsed PSF2 font; see the [module docs](self).
+#[derive(Debug, Clone)]
+pub struct Psf2Font<'a> {
+    width: u32,
+    height: u32,
+    /// Bytes per glyph row.
+    stride: usize,
+    /// The bitmaps, `height * stride` bytes each.
+    glyphs: &'a [u8],
+    /// The Unicode table, if the font has one.
+    table: Option<&'a [u8]>,
+    /// Glyph of each Latin-1 character, or [`NO_GLYPH`].
+    latin1: [u32; INDEXED],
+}
+
+impl<'a> Psf2Font<'a> {
+    /// Parse a PSF2 file.
+    ///
+    /// # Errors
+    /// See [`FontError`].
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, FontError> {
+        if !bytes.starts_with(&MAGIC) {
+            return Err(FontError::BadMagic);
+        }
+        let version = u32_le(bytes, 4)?;
+        let header_size = u32_le(bytes, 8)? as usize;
+        let flags = u32_le(bytes, 12)?;
+        let count = u32_le(bytes, 16)?;
+        let glyph_size = u32_le(bytes, 20)? as usize;
+        let height = u32_le(bytes, 24)?;
+        let width = u32_le(bytes, 28)?;
+        if version != 0 || header_size < HEADER_SIZE || count == 0 {
+            return Err(FontError::Unsupported);
+        }
+        if !(1..=MAX_GLYPH_SIZE).contains(&width) || !(1..=MAX_GLYPH_SIZE).contains(&height) {
+            return Err(FontError::Unsupported);
+        }
+        let stride = width.div_ceil(8) as usize;
+        if glyph_size != stride * height as usize {
+            return Err(FontError::Unsupported);
+        }
+
+        let end = (count as usize)
+            .checked_mul(glyph_size)
+            .and_then(|len| len.checked_add(header_size))
+            .ok_or(FontError::Truncated)?;
+        let glyphs = bytes.get(header_size..end).ok_or(FontError::Truncated)?;
+        let table = (flags & HAS_UNICODE_TABLE != 0).then(|| &bytes[end..]);
+
+        let mut font = Self {
+            width,
+            height,
+            stride,
+            glyphs,
+            table,
+            latin1: [NO_GLYPH; INDEXED],
+        };
+        font.index(count)?;
+        Ok(font)
+    }
+
+    /// Check the Unicode table of a font with `count` glyphs and fill in
+    /// the Latin-1 index.
+    fn index(&mut self, count: u32) -> Result<(), FontError> {
+        let Some(table) = self.table else {
+            for (c, glyph) in (0..count).zip(self.latin1.iter_mut()) {
+                *glyph = c;
+            }
+            return Ok(());
+        };
+
+        let mut entries = Entries { rest: table };
+        for glyph in 0..count {
+            let entry = entries.next().ok_or(FontError::Truncated)?;
+            for c in entry.chars()? {
+                if let Some(slot) = self.latin1.get_mut(c as usize)
+                    && *slot == NO_GLYPH
+                {
+                    *slot = glyph;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Width of a glyph in pixels.
+    #[must_use]
+    pub const fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Height of a glyph in pixels.
+    #[must_use]
+    pub const fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Bytes per glyph row.
+    #[must_use]
+    pub const fn stride(&self) -> usize {
+        self.stride
+    }
+
+    /// The bitmap of the glyph for `c`, if the font has one.
+    #[must_use]
+    pub fn glyph(&self, c: char) -> Option<&'a [u8]> {
+        let index = match self.latin1.get(c as usize) {
+            Some(&NO_GLYPH) => return None,
+            Some(&index) => index,
+            None => self.find(c)?,
+        };
+        let size = self.stride * self.height as usize;
+        let start = index as usize * size;
+        self.glyphs.get(start..start + size)
+    }
+
+    /// Search the Unicode table for `c`; without a table, `c` is its own
+    /// glyph.
+    fn find(&self, c: char) -> Option<u32> {
+        let Some(table) = self.table else {
+            return Some(c as u32);
+        };
+        (0..)
+            .zip(Entries { rest: table })
+            .find(|(_, entry)| entry.chars().is_ok_and(|mut chars| chars.any(|e| e == c)))
+            .map(|(index, _)| index)
+    }
+}
+
+/// The entry of one glyph in the Unicode table.
+struct Entry<'a>(&'a [u8]);
+
+impl<'a> Entry<'a> {
+    /// The single characters the glyph renders, without sequences.
+    fn chars(&self) -> Result<core::str::Chars<'a>, FontError> {
+        let singles = self
+            .0
+            .split(|&b| b == SEQUENCE_START)
+            .next()
+            .unwrap_or_default();
+        core::str::from_utf8(singles)
+            .map(str::chars)
+            .map_err(|_| FontError::InvalidTable)
+    }
+}
+
+/// The entries of a Unicode table, in glyph order.
+struct Entries<'a> {
+    rest: &'a [u8],
+}
+
+impl<'a> Iterator for Entries<'a> {
+    type Item = Entry<'a>;
+
+    fn next(&mut self) -> Option<Entry<'a>> {
+        let end = self.rest.iter().position(|&b| b == ENTRY_END)?;
+        let entry = &self.rest[..end];
+        self.rest = &self.rest[end + 1..];
+        Some(Entry(entry))
+    }
+}
+
+fn u32_le(bytes: &[u8], at: usize) -> Result<u32, FontError> {
+    let b = bytes.get(at..at + 4).ok_or(FontError::Truncated)?;
+    Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
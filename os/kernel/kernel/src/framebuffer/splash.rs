@@ -0,0 +1,172 @@
+//! # Boot Splash
+//!
+//! While the kernel boots, [`show`] draws an image centered on the screen,
+//! with a progress bar and the name of the current step below it.
+//! [`advance`] moves the bar on as init reaches each [`Stage`]; [`finish`]
+//! clears the screen again before the kernel main loop takes it over.
+//!
+//! ## Image
+//!
+//! The image is a [BMP or QOI file](crate::framebuffer::image) named by the
+//! `splash` option of the [command line](crate::cmdline), `splash` if not
+//! given. It is taken from the [boot module](crate::boot_modules) of that
+//! name or, failing that, from the [userland bundle](crate::bundlefs).
+//! `splash=off` turns the splash off.
+//!
+//! Without an image or a [compositor](crate::framebuffer::compositor),
+//! nothing is drawn and the calls here do nothing.
+
+use crate::cmdline::{self, Param, ParamKind};
+use crate::framebuffer::compositor::with_compositor;
+use crate::framebuffer::font;
+use crate::framebuffer::image::Image;
+use crate::framebuffer::{Color, Rect};
+use crate::{boot_modules, bundlefs};
+use kernel_sync::SpinMutex;
+use log::{debug, info, warn};
+
+pub static SPLASH_PARAM: Param = Param {
+    name: "splash",
+    kind: ParamKind::Str,
+    help: "boot module or bundle entry with the boot splash image, or off",
+};
+
+/// Image looked up if the command line names none.
+const DEFAULT_IMAGE: &str = "splash";
+
+/// Space between the image and the progress bar, in pixels.
+const GAP: u32 = 16;
+
+/// Height of the progress bar, outline included.
+const BAR_HEIGHT: u32 = 10;
+
+const BAR_COLOR: Color = Color::WHITE;
+const LABEL_COLOR: Color = Color::new(0xA0, 0xA0, 0xA0);
+
+/// Where the progress bar and label go; `None` while no splash is shown.
+static LAYOUT: SpinMutex<Option<Layout>> = SpinMutex::new(None);
+
+#[derive(Debug, Copy, Clone)]
+struct Layout {
+    /// The progress bar, outline included.
+    bar: Rect,
+    /// Top row of the step label.
+    label_y: u32,
+}
+
+/// The steps of init the progress bar counts, in boot order.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Stage {
+    Interrupts,
+    Clocks,
+    Firmware,
+    Timer,
+    AddressSpace,
+}
+
+impl Stage {
+    pub const ALL: [Self; 5] = [
+        Self::Interrupts,
+        Self::Clocks,
+        Self::Firmware,
+        Self::Timer,
+        Self::AddressSpace,
+    ];
+
+    /// The label shown below the progress bar.
+    pub const fn label(self) -> &'static str {
+        match self {
+            Self::Interrupts => "Installing interrupt handlers",
+            Self::Clocks => "Calibrating clocks",
+            Self::Firmware => "Reading ACPI tables",
+            Self::Timer => "Starting the timer tick",
+            Self::AddressSpace => "Securing the address space",
+        }
+    }
+
+    /// Position in [`ALL`](Self::ALL).
+    const fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Draw the splash image, if there is one, and an empty progress bar.
+pub fn show() {
+    let name = cmdline::get_str(SPLASH_PARAM.name).unwrap_or(DEFAULT_IMAGE);
+    if name == "off" {
+        return;
+    }
+    let Some(bytes) = boot_modules::find(name).or_else(|| bundlefs::lookup(name)) else {
+        debug!("No splash image {name}");
+        return;
+    };
+    let image = match Image::parse(bytes) {
+        Ok(image) => image,
+        Err(e) => {
+            warn!("Cannot show splash image {name}: {e}");
+            return;
+        }
+    };
+
+    let mut layout = LAYOUT.lock();
+    let drawn = with_compositor(|c| {
+        let (w, h) = (c.width(), c.height());
+        let x = w.saturating_sub(image.width()) / 2;
+        let y = h.saturating_sub(image.height()) / 2;
+        c.draw(|s| s.draw_image(x, y, &image));
+
+        let bar_y = y + image.height() + GAP;
+        let bar = Rect::new(w / 3, bar_y, w / 3, BAR_HEIGHT);
+        c.draw(|s| s.draw_rect(bar, BAR_COLOR));
+        c.present();
+        Layout {
+            bar,
+            label_y: bar.bottom() + GAP / 2,
+        }
+    });
+    if drawn.is_some() {
+        info!(
+            "Showing splash image {name} ({}x{})",
+            image.width(),
+            image.height()
+        );
+    }
+    *layout = drawn;
+}
+
+/// Fill the progress bar up to `stage` and show its label.
+pub fn advance(stage: Stage) {
+    let layout = LAYOUT.lock();
+    let Some(layout) = *layout else {
+        return;
+    };
+    let done = stage.index() as u64 + 1;
+    let steps = Stage::ALL.len() as u64 + 1;
+
+    with_compositor(|c| {
+        let inner = layout.bar.width.saturating_sub(4);
+        let filled = u32::try_from(u64::from(inner) * done / steps).unwrap_or(inner);
+        let fill = Rect::new(layout.bar.x + 2, layout.bar.y + 2, filled, BAR_HEIGHT - 4);
+        c.fill_rect(fill, BAR_COLOR);
+
+        let label = stage.label();
+        let font = font::current();
+        let width = font.text_width(label);
+        let row = Rect::new(0, layout.label_y, c.width(), font.height());
+        c.fill_rect(row, Color::BLACK);
+        let x = c.width().saturating_sub(width) / 2;
+        c.draw_text(x, layout.label_y, label, LABEL_COLOR, None);
+        c.present();
+    });
+}
+
+/// Take the splash down and clear the screen.
+pub fn finish() {
+    if LAYOUT.lock().take().is_none() {
+        return;
+    }
+    with_compositor(|c| {
+        c.fill_rect(c.bounds(), Color::BLACK);
+        c.present();
+    });
+}
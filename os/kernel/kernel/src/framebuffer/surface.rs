@@ -0,0 +1,369 @@
+//! # Drawing Surfaces
+//!
+//! A [`Surface`] is a rectangle of 32-bit pixels in ordinary memory together
+//! with its [`PixelFormat`]. The compositor's back buffer is one; off-screen
+//! surfaces (glyph caches, sprites, decoded images) wrap any other pixel
+//! buffer, so the same drawing code serves both.
+//!
+//! ## Primitives
+//!
+//! * [`fill_rect`](Surface::fill_rect), [`draw_rect`](Surface::draw_rect) and
+//!   [`draw_line`](Surface::draw_line) for solid shapes,
+//! * [`blit`](Surface::blit) and [`blit_scaled`](Surface::blit_scaled) to copy
+//!   between surfaces, converting pixel formats where they differ,
+//! * [`blend`](Surface::blend) to alpha-composite an RGBA image,
+//! * [`scroll_up`](Surface::scroll_up) to move a region's contents up,
+//! * [`draw_image`](Surface::draw_image) to alpha-composite a decoded
+//!   [BMP or QOI image](crate::framebuffer::image),
+//! * [`draw_text`](Surface::draw_text) in the [console font](font).
+//!
+//! All of them clip to the surface and return the (possibly empty) [`Rect`]
+//! they changed, which callers feed into their dirty tracking.
+
+use crate::framebuffer::font::{self, Font};
+use crate::framebuffer::image::Image;
+use crate::framebuffer::pixel::PixelFormat;
+use crate::framebuffer::{Color, Rect};
+
+/// Pixels in memory; see the [module docs](self).
+#[derive(Debug)]
+pub struct Surface<'a> {
+    pixels: &'a mut [u32],
+    width: u32,
+    height: u32,
+    /// Pixels per row.
+    stride: u32,
+    format: PixelFormat,
+}
+
+#[allow(dead_code)]
+impl<'a> Surface<'a> {
+    /// A `width` x `height` surface over `pixels`, rows packed back to back.
+    ///
+    /// Returns `None` if `pixels` is too small.
+    #[must_use]
+    pub fn new(
+        pixels: &'a mut [u32],
+        width: u32,
+        height: u32,
+        format: PixelFormat,
+    ) -> Option<Self> {
+        Self::with_stride(pixels, width, height, width, format)
+    }
+
+    /// A `width` x `height` surface over `pixels` with `stride` pixels per row.
+    ///
+    /// Returns `None` if `stride` is less than `width` or `pixels` is too small.
+    #[must_use]
+    pub fn with_stride(
+        pixels: &'a mut [u32],
+        width: u32,
+        height: u32,
+        stride: u32,
+        format: PixelFormat,
+    ) -> Option<Self> {
+        let needed = (stride as usize).checked_mul(height as usize)?;
+        if stride < width || pixels.len() < needed {
+            return None;
+        }
+        Some(Self {
+            pixels,
+            width,
+            height,
+            stride,
+            format,
+        })
+    }
+
+    #[must_use]
+    pub const fn width(&self) -> u32 {
+        self.width
+    }
+
+    #[must_use]
+    pub const fn height(&self) -> u32 {
+        self.height
+    }
+
+    #[must_use]
+    pub const fn format(&self) -> PixelFormat {
+        self.format
+    }
+
+    /// The whole surface.
+    #[must_use]
+    pub const fn bounds(&self) -> Rect {
+        Rect::new(0, 0, self.width, self.height)
+    }
+
+    /// The raw pixels of row `y`, columns `x..x + width`.
+    ///
+    /// # Panics
+    /// If the span lies outside the surface.
+    #[must_use]
+    pub fn span(&self, x: u32, y: u32, width: u32) -> &[u32] {
+        let start = self.index(x, y);
+        &self.pixels[start..start + width as usize]
+    }
+
+    fn span_mut(&mut self, x: u32, y: u32, width: u32) -> &mut [u32] {
+        let start = self.index(x, y);
+        &mut self.pixels[start..start + width as usize]
+    }
+
+    const fn index(&self, x: u32, y: u32) -> usize {
+        y as usize * self.stride as usize + x as usize
+    }
+
+    /// The color at `(x, y)`, if inside the surface.
+    #[must_use]
+    pub fn get(&self, x: u32, y: u32) -> Option<Color> {
+        (x < self.width && y < self.height)
+            .then(|| self.format.unpack(self.pixels[self.index(x, y)]))
+    }
+
+    /// Set the pixel at `(x, y)`, if inside the surface.
+    pub fn put(&mut self, x: u32, y: u32, color: Color) {
+        if x < self.width && y < self.height {
+            let index = self.index(x, y);
+            self.pixels[index] = self.format.pack(color);
+        }
+    }
+
+    /// Fill the whole surface with `color`.
+    pub fn clear(&mut self, color: Color) -> Rect {
+        self.fill_rect(self.bounds(), color)
+    }
+
+    /// Fill `rect` with `color`.
+    pub fn fill_rect(&mut self, rect: Rect, color: Color) -> Rect {
+        let rect = rect.clip(self.width, self.height);
+        let px = self.format.pack(color);
+        for y in rect.y..rect.bottom() {
+            self.span_mut(rect.x, y, rect.width).fill(px);
+        }
+        rect
+    }
+
+    /// Draw the one pixel wide outline of `rect`.
+    pub fn draw_rect(&mut self, rect: Rect, color: Color) -> Rect {
+        if rect.is_empty() {
+            return Rect::default();
+        }
+        let (right, bottom) = (rect.right() - 1, rect.bottom() - 1);
+        self.fill_rect(Rect::new(rect.x, rect.y, rect.width, 1), color);
+        self.fill_rect(Rect::new(rect.x, bottom, rect.width, 1), color);
+        self.fill_rect(Rect::new(rect.x, rect.y, 1, rect.height), color);
+        self.fill_rect(Rect::new(right, rect.y, 1, rect.height), color);
+        rect.clip(self.width, self.height)
+    }
+
+    /// Draw a line from `(x0, y0)` to `(x1, y1)`, both ends inclusive.
+    ///
+    /// Endpoints may lie outside the surface; only the visible part is drawn.
+    #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
+    pub fn draw_line(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, color: Color) -> Rect {
+        // Bresenham, in i64 so that extreme endpoints cannot overflow.
+        let (mut x, mut y) = (i64::from(x0), i64::from(y0));
+        let (x1, y1) = (i64::from(x1), i64::from(y1));
+        let dx = (x1 - x).abs();
+        let dy = -(y1 - y).abs();
+        let sx = if x < x1 { 1 } else { -1 };
+        let sy = if y < y1 { 1 } else { -1 };
+        let mut err = dx + dy;
+
+        let px = self.format.pack(color);
+        let (w, h) = (i64::from(self.width), i64::from(self.height));
+        let mut dirty = Rect::default();
+        loop {
+            if (0..w).contains(&x) && (0..h).contains(&y) {
+                let (px_x, px_y) = (x as u32, y as u32);
+                let index = self.index(px_x, px_y);
+                self.pixels[index] = px;
+                dirty = dirty.union(&Rect::new(px_x, px_y, 1, 1));
+            }
+            if x == x1 && y == y1 {
+                break;
+            }
+            let e2 = 2 * err;
+            if e2 >= dy {
+                err += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                err += dx;
+                y += sy;
+            }
+        }
+        dirty
+    }
+
+    /// Copy `src_rect` of `src` so that its top-left corner lands at `(x, y)`.
+    ///
+    /// Pixels are converted if the surfaces have different formats.
+    pub fn blit(&mut self, x: u32, y: u32, src: &Surface<'_>, src_rect: Rect) -> Rect {
+        let src_rect = src_rect.clip(src.width, src.height);
+        let dst = Rect::new(x, y, src_rect.width, src_rect.height).clip(self.width, self.height);
+        let same_format = self.format == src.format;
+
+        for row in 0..dst.height {
+            let from = src.span(src_rect.x, src_rect.y + row, dst.width);
+            let format = self.format;
+            let to = self.span_mut(dst.x, dst.y + row, dst.width);
+            if same_format {
+                to.copy_from_slice(from);
+            } else {
+                for (to, from) in to.iter_mut().zip(from) {
+                    *to = format.pack(src.format.unpack(*from));
+                }
+            }
+        }
+        dst
+    }
+
+    /// Copy `src_rect` of `src` stretched (nearest neighbor) to fill `dst`.
+    pub fn blit_scaled(&mut self, dst: Rect, src: &Surface<'_>, src_rect: Rect) -> Rect {
+        let src_rect = src_rect.clip(src.width, src.height);
+        if dst.is_empty() || src_rect.is_empty() {
+            return Rect::default();
+        }
+        let clipped = dst.clip(self.width, self.height);
+
+        // Map each destination pixel's center back into the source.
+        let scale = |d: u32, d_len: u32, s_len: u32| -> u32 {
+            let s = (u64::from(d) * 2 + 1) * u64::from(s_len) / (u64::from(d_len) * 2);
+            u32::try_from(s).unwrap_or(u32::MAX).min(s_len - 1)
+        };
+
+        let format = self.format;
+        for y in clipped.y..clipped.bottom() {
+            let sy = src_rect.y + scale(y - dst.y, dst.height, src_rect.height);
+            for x in clipped.x..clipped.right() {
+                let sx = src_rect.x + scale(x - dst.x, dst.width, src_rect.width);
+                let pixel = src.pixels[src.index(sx, sy)];
+                let index = self.index(x, y);
+                self.pixels[index] = if format == src.format {
+                    pixel
+                } else {
+                    format.pack(src.format.unpack(pixel))
+                };
+            }
+        }
+        clipped
+    }
+
+    /// Alpha-blend a `width` pixels wide RGBA image with its top-left corner
+    /// at `(x, y)`.
+    ///
+    /// `pixels` holds the image row by row; a trailing partial row is ignored.
+    pub fn blend(&mut self, x: u32, y: u32, width: u32, pixels: &[Color]) -> Rect {
+        if width == 0 {
+            return Rect::default();
+        }
+        let rows = u32::try_from(pixels.len() / width as usize).unwrap_or(u32::MAX);
+        let dst = Rect::new(x, y, width, rows).clip(self.width, self.height);
+
+        let format = self.format;
+        for (src_row, y) in pixels.chunks_exact(width as usize).zip(dst.y..dst.bottom()) {
+            let to = self.span_mut(dst.x, y, dst.width);
+            for (to, src) in to.iter_mut().zip(src_row) {
+                *to = match src.a {
+                    0 => continue,
+                    0xFF => format.pack(*src),
+                    _ => format.pack(src.over(format.unpack(*to))),
+                };
+            }
+        }
+        dst
+    }
+
+    /// Alpha-blend `image` with its top-left corner at `(x, y)`, converting
+    /// its pixels to the surface's format.
+    pub fn draw_image(&mut self, x: u32, y: u32, image: &Image<'_>) -> Rect {
+        let dst = Rect::new(x, y, image.width(), image.height()).clip(self.width, self.height);
+        if dst.is_empty() {
+            return dst;
+        }
+        image.for_each_pixel(|px, py, color| {
+            let (Some(sx), Some(sy)) = (x.checked_add(px), y.checked_add(py)) else {
+                return;
+            };
+            if sx >= self.width || sy >= self.height {
+                return;
+            }
+            let index = self.index(sx, sy);
+            let to = &mut self.pixels[index];
+            *to = match color.a {
+                0 => return,
+                0xFF => self.format.pack(color),
+                _ => self.format.pack(color.over(self.format.unpack(*to))),
+            };
+        });
+        dst
+    }
+
+    /// Move the contents of `rect` up by `dy` rows, filling the rows that
+    /// become free at its bottom with `color`.
+    pub fn scroll_up(&mut self, rect: Rect, dy: u32, color: Color) -> Rect {
+        let rect = rect.clip(self.width, self.height);
+        let dy = dy.min(rect.height);
+        let width = rect.width as usize;
+        for y in rect.y..rect.bottom() - dy {
+            let from = self.index(rect.x, y + dy);
+            let to = self.index(rect.x, y);
+            self.pixels.copy_within(from..from + width, to);
+        }
+        let freed = Rect::new(rect.x, rect.bottom() - dy, rect.width, dy);
+        self.fill_rect(freed, color);
+        rect
+    }
+
+    /// Draw a single line of `text` in the [current font](font::current)
+    /// with its top-left corner at `(x, y)`; see [`draw_text_in`](Self::draw_text_in).
+    pub fn draw_text(&mut self, x: u32, y: u32, text: &str, fg: Color, bg: Option<Color>) -> Rect {
+        self.draw_text_in(font::current(), x, y, text, fg, bg)
+    }
+
+    /// Draw a single line of `text` in `font` with its top-left corner at
+    /// `(x, y)`.
+    ///
+    /// Glyph pixels are drawn in `fg`, the rest of each character cell in
+    /// `bg`, or left untouched if `bg` is `None`. Every character advances by
+    /// the [width](Font::width) of the font.
+    pub fn draw_text_in(
+        &mut self,
+        font: Font<'_>,
+        x: u32,
+        y: u32,
+        text: &str,
+        fg: Color,
+        bg: Option<Color>,
+    ) -> Rect {
+        let fg_px = self.format.pack(fg);
+        let bg_px = bg.map(|bg| self.format.pack(bg));
+        let (width, height) = (font.width(), font.height());
+        let mut cursor = x;
+
+        for c in text.chars() {
+            let cell = Rect::new(cursor, y, width, height).clip(self.width, self.height);
+            if cell.is_empty() {
+                break;
+            }
+
+            let glyph = font.glyph(c);
+            for py in cell.y..cell.bottom() {
+                let row = self.span_mut(cell.x, py, cell.width);
+                for (col, px) in (0..).zip(row.iter_mut()) {
+                    if glyph.is_set(col, py - y) {
+                        *px = fg_px;
+                    } else if let Some(bg_px) = bg_px {
+                        *px = bg_px;
+                    }
+                }
+            }
+            cursor += width;
+        }
+
+        Rect::new(x, y, cursor - x, height).clip(self.width, self.height)
+    }
+}
@@ -0,0 +1,335 @@
+//! # QEMU Firmware Configuration
+//!
+//! QEMU's `fw_cfg` device hands configuration items and whole files from the
+//! host to the guest, which makes it a way to change a test run without
+//! rebuilding the ESP image:
+//!
+//! ```text
+//! task qemu PROFILE=debug -- \
+//!     -fw_cfg name=opt/os/cmdline,string="loglevel=debug strace_init" \
+//!     -fw_cfg name=opt/os/userland,file=target/userland.bundle
+//! ```
+//!
+//! | File              | Consumer                                                    |
+//! |-------------------|-------------------------------------------------------------|
+//! | `opt/os/cmdline`  | [`cmdline`](crate::cmdline): appended to the loader's line  |
+//! | `opt/os/userland` | [`bundlefs`](crate::bundlefs): replaces the userland bundle |
+//!
+//! ## Interface
+//!
+//! Items are addressed by a 16-bit selector written to [`SELECTOR`]; their
+//! bytes are then read one at a time from [`DATA`]. Item `0x19` is the file
+//! directory: a big-endian count followed by one [`File`] entry (size,
+//! selector, 56-byte name) per file. [`files`] lists it, [`find`] looks a
+//! file up by name and [`read`] copies its start into a buffer.
+//!
+//! If the device offers DMA, [`load`] has it copy a whole file into fresh
+//! frames instead: the physical address of a descriptor naming selector,
+//! length and destination goes to [`DMA_ADDRESS`], and the transfer is done
+//! by the time the write returns.
+//!
+//! ## Detection
+//!
+//! The device is only probed under KVM or plain QEMU (by the CPUID
+//! hypervisor signature), and only used if item `0` reads `QEMU`. Elsewhere
+//! there are no files. The directory is read once, at the first lookup;
+//! that needs no memory management, so the command line can use it.
+
+use crate::alloc::with_kernel_frame_alloc;
+use crate::cpuid::{CpuidRanges, Hypervisor, HypervisorVendor, Leaf01h};
+use core::fmt;
+use core::sync::atomic::{Ordering, fence};
+use kernel_info::memory::HHDM_BASE;
+use kernel_memory_addresses::{PageSize, PhysicalAddress, PhysicalPage, Size4K};
+use kernel_ports::{PortReadOnly, PortWriteOnly};
+use kernel_sync::{IrqGuard, SpinMutex, SyncOnceCell};
+use kernel_vmem::PhysFrameAlloc;
+use log::{debug, info, warn};
+
+/// Port taking the selector of the item to read.
+const SELECTOR: PortWriteOnly<u16> = PortWriteOnly::new(0x510);
+
+/// Port reading the selected item, one byte at a time.
+const DATA: PortReadOnly<u8> = PortReadOnly::new(0x511);
+
+/// Ports taking the big-endian physical address of a DMA descriptor, high
+/// half first; writing the low half starts the transfer.
+const DMA_ADDRESS: (PortWriteOnly<u32>, PortWriteOnly<u32>) =
+    (PortWriteOnly::new(0x514), PortWriteOnly::new(0x518));
+
+/// Item holding the signature `QEMU`.
+const SIGNATURE_ITEM: u16 = 0x00;
+
+/// Item holding the little-endian feature bits.
+const ID_ITEM: u16 = 0x01;
+
+/// Item holding the file directory.
+const FILE_DIR_ITEM: u16 = 0x19;
+
+/// Feature bit: the DMA interface is available.
+const FEATURE_DMA: u32 = 1 << 1;
+
+/// DMA control bits; the selector goes into the upper half.
+const DMA_ERROR: u32 = 1 << 0;
+const DMA_READ: u32 = 1 << 1;
+const DMA_SELECT: u32 = 1 << 3;
+
+/// Longest file name, including the terminating NUL.
+pub const FILE_NAME_LEN: usize = 56;
+
+/// Size of a file directory entry.
+const FILE_ENTRY_LEN: usize = 8 + FILE_NAME_LEN;
+
+/// Most files remembered from the directory; QEMU lists a few dozen.
+const MAX_FILES: usize = 64;
+
+static DEVICE: SyncOnceCell<Option<Device>> = SyncOnceCell::new();
+
+/// Serializes selecting an item and reading it.
+static ACCESS: SpinMutex<()> = SpinMutex::new(());
+
+/// An entry of the file directory.
+#[derive(Debug, Copy, Clone)]
+pub struct File {
+    name: [u8; FILE_NAME_LEN],
+    size: u32,
+    select: u16,
+}
+
+impl File {
+    /// The file name, e.g. `etc/e820`.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        let len = self
+            .name
+            .iter()
+            .position(|&b| b == 0)
+            .unwrap_or(FILE_NAME_LEN);
+        core::str::from_utf8(&self.name[..len]).unwrap_or("")
+    }
+
+    /// Size in bytes.
+    #[must_use]
+    pub const fn size(&self) -> u32 {
+        self.size
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum FwCfgError {
+    /// No frames left for the file.
+    OutOfMemory,
+    /// The device reported an error during a DMA transfer.
+    Dma,
+}
+
+impl fmt::Display for FwCfgError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OutOfMemory => f.write_str("out of memory"),
+            Self::Dma => f.write_str("DMA transfer failed"),
+        }
+    }
+}
+
+/// The probed device and its file directory.
+struct Device {
+    dma: bool,
+    files: [File; MAX_FILES],
+    len: usize,
+}
+
+/// A DMA descriptor; all fields are big-endian.
+#[repr(C)]
+struct DmaAccess {
+    control: u32,
+    length: u32,
+    address: u64,
+}
+
+/// Whether there is a `fw_cfg` device.
+#[must_use]
+#[allow(dead_code)]
+pub fn available() -> bool {
+    device().is_some()
+}
+
+/// The files the host passed; none without a device.
+pub fn files() -> impl Iterator<Item = &'static File> {
+    device()
+        .into_iter()
+        .flat_map(|device| device.files[..device.len].iter())
+}
+
+/// The file called `name`.
+#[must_use]
+pub fn find(name: &str) -> Option<&'static File> {
+    files().find(|file| file.name() == name)
+}
+
+/// Copy the start of `file` into `buf` through the data port and return the
+/// number of bytes copied.
+pub fn read(file: &File, buf: &mut [u8]) -> usize {
+    let len = buf.len().min(file.size as usize);
+    let _irq = IrqGuard::new();
+    let _access = ACCESS.lock();
+    select(file.select);
+    for byte in &mut buf[..len] {
+        *byte = read_byte();
+    }
+    len
+}
+
+/// Copy all of `file` into fresh frames and return its bytes, through DMA if
+/// the device has it. The frames are never freed.
+///
+/// # Errors
+/// See [`FwCfgError`].
+pub fn load(file: &File) -> Result<&'static [u8], FwCfgError> {
+    let dma = device().is_some_and(|device| device.dma);
+    let len = file.size as usize;
+    // The DMA descriptor goes right behind the file.
+    let descriptor_at = len.next_multiple_of(8);
+    let total = (descriptor_at + size_of::<DmaAccess>()) as u64;
+    let frames = usize::try_from(total.div_ceil(Size4K::SIZE)).expect("file size fits usize");
+    let first = with_kernel_frame_alloc(|alloc| alloc.alloc_contiguous_4k(frames))
+        .ok_or(FwCfgError::OutOfMemory)?;
+    let base = HHDM_BASE.as_u64() + first.base().as_u64();
+
+    // Safety: the frames are ours and reachable through the HHDM.
+    let bytes = unsafe { core::slice::from_raw_parts_mut(base as *mut u8, len) };
+    if dma {
+        let descriptor = first.base() + descriptor_at as u64;
+        // Safety: as above; the descriptor is 8-byte aligned and in bounds.
+        if let Err(e) = unsafe { dma_read(file, first.base(), descriptor) } {
+            with_kernel_frame_alloc(|alloc| {
+                for i in 0..frames as u64 {
+                    alloc.free_4k(PhysicalPage::from_addr(first.base() + i * Size4K::SIZE));
+                }
+            });
+            return Err(e);
+        }
+    } else {
+        read(file, bytes);
+    }
+    info!(
+        "Loaded fw_cfg file {} ({len} bytes{})",
+        file.name(),
+        if dma { ", DMA" } else { "" }
+    );
+    Ok(bytes)
+}
+
+/// Have the device copy `file` to `dest`, using a descriptor at
+/// `descriptor`.
+///
+/// # Safety
+/// Both ranges must be unused memory reachable through the HHDM.
+#[allow(clippy::cast_possible_truncation)]
+unsafe fn dma_read(
+    file: &File,
+    dest: PhysicalAddress,
+    descriptor: PhysicalAddress,
+) -> Result<(), FwCfgError> {
+    let access = (HHDM_BASE.as_u64() + descriptor.as_u64()) as *mut DmaAccess;
+    let control = (u32::from(file.select) << 16) | DMA_SELECT | DMA_READ;
+    let _irq = IrqGuard::new();
+    let _access = ACCESS.lock();
+    unsafe {
+        (&raw mut (*access).control).write_volatile(control.to_be());
+        (&raw mut (*access).length).write_volatile(file.size.to_be());
+        (&raw mut (*access).address).write_volatile(dest.as_u64().to_be());
+        fence(Ordering::SeqCst);
+        let address = descriptor.as_u64();
+        DMA_ADDRESS.0.write(((address >> 32) as u32).to_be());
+        DMA_ADDRESS.1.write((address as u32).to_be());
+    }
+
+    // QEMU completes the transfer during the port write; the device clears
+    // every bit but the error bit when done.
+    loop {
+        let control = u32::from_be(unsafe { (&raw const (*access).control).read_volatile() });
+        if control & DMA_ERROR != 0 {
+            return Err(FwCfgError::Dma);
+        }
+        if control == 0 {
+            fence(Ordering::SeqCst);
+            return Ok(());
+        }
+        core::hint::spin_loop();
+    }
+}
+
+fn device() -> Option<&'static Device> {
+    DEVICE.get_or_init(probe).as_ref()
+}
+
+/// Look for the device and read its file directory.
+fn probe() -> Option<Device> {
+    let ranges = unsafe { CpuidRanges::read() };
+    let leaf1 = unsafe { Leaf01h::read(&ranges) }?;
+    let hypervisor = unsafe { Hypervisor::read(&leaf1) }?;
+    if !matches!(
+        hypervisor.vendor,
+        HypervisorVendor::Kvm | HypervisorVendor::Tcg
+    ) {
+        return None;
+    }
+
+    let _irq = IrqGuard::new();
+    let _access = ACCESS.lock();
+    select(SIGNATURE_ITEM);
+    if read_array() != *b"QEMU" {
+        return None;
+    }
+    select(ID_ITEM);
+    let features = u32::from_le_bytes(read_array());
+
+    let mut device = Device {
+        dma: features & FEATURE_DMA != 0,
+        files: [File {
+            name: [0; FILE_NAME_LEN],
+            size: 0,
+            select: 0,
+        }; MAX_FILES],
+        len: 0,
+    };
+    select(FILE_DIR_ITEM);
+    let count = u32::from_be_bytes(read_array()) as usize;
+    if count > MAX_FILES {
+        warn!("fw_cfg lists {count} files; ignoring all but {MAX_FILES}");
+    }
+    for file in device.files.iter_mut().take(count) {
+        let entry: [u8; FILE_ENTRY_LEN] = read_array();
+        file.size = u32::from_be_bytes([entry[0], entry[1], entry[2], entry[3]]);
+        file.select = u16::from_be_bytes([entry[4], entry[5]]);
+        file.name.copy_from_slice(&entry[8..]);
+        debug!("  fw_cfg {} ({} bytes)", file.name(), file.size);
+        device.len += 1;
+    }
+    info!(
+        "QEMU fw_cfg with {} files{}",
+        device.len,
+        if device.dma { ", DMA" } else { "" }
+    );
+    Some(device)
+}
+
+/// Select `item` and rewind to its start. Callers hold [`ACCESS`].
+fn select(item: u16) {
+    unsafe { SELECTOR.write(item) };
+}
+
+/// The next byte of the selected item. Callers hold [`ACCESS`].
+fn read_byte() -> u8 {
+    unsafe { DATA.read() }
+}
+
+/// The next `N` bytes of the selected item. Callers hold [`ACCESS`].
+fn read_array<const N: usize>() -> [u8; N] {
+    let mut bytes = [0; N];
+    for byte in &mut bytes {
+        *byte = read_byte();
+    }
+    bytes
+}
@@ -51,6 +51,10 @@ use crate::privilege::{Dpl, Rpl};
 use crate::tss::{Tss64, init_tss};
 use core::mem::size_of;
 use kernel_memory_addresses::VirtualAddress;
+use kernel_registers::StoreRegisterUnsafe;
+use kernel_registers::dtr::{DescriptorTablePointer, Gdtr};
+use kernel_registers::segment::{Cs, Ds, Es, Ss};
+use kernel_registers::tr::TaskRegister;
 
 #[allow(dead_code)]
 pub struct Selectors {
@@ -125,22 +129,6 @@ const _: () = {
     assert!(TSS_SEL == TSS_SYS_SEL.encode());
 };
 
-/// Virtual address used in descriptor-table pointers (with paging on).
-///
-/// In long mode `lgdt` expects a **linear (virtual) address** when paging is enabled.
-pub type LinearAddress = VirtualAddress;
-
-/// Pointer format required by `lgdt`.
-///
-/// The CPU reads exactly `limit+1` bytes starting at `base` to load the GDT.
-#[repr(C, packed)]
-struct DescTablePtr {
-    /// Size of the table **minus one** in bytes.
-    limit: u16,
-    /// Base **linear (virtual) address** of the table in memory.
-    base: LinearAddress,
-}
-
 /// The complete GDT for the bootstrap CPU.
 ///
 /// Layout matches the table described in this module-level doc. The TSS occupies
@@ -193,19 +181,9 @@ impl Gdt {
 ///   remain **mapped and readable** for the lifetime of the CPU.
 /// - Callers must ensure no interrupts or faults observe a half-installed state.
 #[inline]
-#[allow(clippy::cast_possible_truncation)]
 unsafe fn load_gdt(gdt: &Gdt) {
-    let ptr = DescTablePtr {
-        limit: (size_of::<Gdt>() - 1) as u16,
-        base: LinearAddress::from_ptr(&raw const *gdt),
-    };
-
     unsafe {
-        core::arch::asm!(
-            "lgdt [{}]",
-            in(reg) &raw const ptr,
-            options(readonly, nostack, preserves_flags)
-        );
+        Gdtr(DescriptorTablePointer::for_table(gdt)).store_unsafe();
     }
 }
 
@@ -220,19 +198,15 @@ unsafe fn load_gdt(gdt: &Gdt) {
 ///   privilege changes.
 #[inline]
 unsafe fn load_task_register(sel: SegmentSelector<TssSel>) {
-    let sel = sel.encode();
     unsafe {
-        core::arch::asm!(
-            "ltr {0:x}",
-            in(reg) sel,
-            options(nostack, preserves_flags)
-        );
+        TaskRegister(sel.into()).store_unsafe();
     }
 }
 
 /// Initialize and load **GDT + TSS** for the bootstrap CPU.
 ///
-/// - Programs the TSS with `rsp0` (kernel entry stack) and optional `IST1`.
+/// - Programs the TSS with `rsp0` (kernel entry stack); IST stacks are set
+///   afterwards with [`set_ist`](crate::tss::set_ist).
 /// - Builds a GDT with kernel/user code+data descriptors and a 64-bit TSS descriptor.
 /// - Executes `lgdt`, refreshes data segments (DS/ES/SS), and executes `ltr`.
 ///
@@ -240,7 +214,6 @@ unsafe fn load_task_register(sel: SegmentSelector<TssSel>) {
 ///
 /// ### Parameters
 /// - `kernel_stack_top`: top of the Ring-0 stack (used on CPL change to 0).
-/// - `ist1_top`: optional top of an **IST1** stack for critical handlers.
 ///
 /// ### Safety / Ordering
 /// - Run with interrupts disabled.
@@ -250,16 +223,12 @@ unsafe fn load_task_register(sel: SegmentSelector<TssSel>) {
 /// ### Example
 /// ```ignore
 /// // During boot on BSP:
-/// init_gdt_and_tss(kernel_stack_top, Some(double_fault_stack_top));
+/// init_gdt_and_tss(cpu, kernel_stack_top);
 /// ```
 #[allow(clippy::cast_possible_truncation)]
-pub fn init_gdt_and_tss(
-    p: &mut PerCpu,
-    kernel_stack_top: VirtualAddress,
-    ist1_top: VirtualAddress,
-) {
+pub fn init_gdt_and_tss(p: &mut PerCpu, kernel_stack_top: VirtualAddress) {
     // Initialize TSS contents first.
-    init_tss(p, kernel_stack_top, ist1_top);
+    init_tss(p, kernel_stack_top);
     let tss_base = p.tss_base();
     let tss_limit = (size_of::<Tss64>() - 1) as u32;
 
@@ -273,28 +242,13 @@ pub fn init_gdt_and_tss(
         load_gdt(&p.gdt);
 
         // Refresh data segments to kernel data
-        let kdata_sel = p.selectors.kernel_ds.encode();
-        core::arch::asm!(
-            "mov ds, {0:x}",
-            "mov es, {0:x}",
-            "mov ss, {0:x}",
-            in(reg) kdata_sel,
-            options(nostack, preserves_flags)
-        );
+        let kdata_sel = p.selectors.kernel_ds.into();
+        Ds(kdata_sel).store_unsafe();
+        Es(kdata_sel).store_unsafe();
+        Ss(kdata_sel).store_unsafe();
 
-        // Far reload of CS = 0x08 (kernel code). Use retfq trick in long mode.
-        let kcs: u16 = p.selectors.kernel_cs.encode(); // 0x08
-        core::arch::asm!(
-            // push target CS and RIP, then far return
-            "push {cs}",
-            "lea rax, [rip + 2f]",
-            "push rax",
-            "retfq",
-            "2:",
-            cs = in(reg) u64::from(kcs),
-            out("rax") _,
-            options(nostack)
-        );
+        // Far reload of CS = 0x08 (kernel code).
+        Cs(p.selectors.kernel_cs.into()).store_unsafe();
 
         // Load TR with the TSS selector (0x28).
         load_task_register(p.selectors.tss);
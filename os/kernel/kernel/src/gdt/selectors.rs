@@ -14,11 +14,16 @@
 //! This module adds a thin type layer so you can’t accidentally put a data
 //! selector into CS or a random value into `ltr`. It also exposes the **raw**
 //! encoding when you need to write `u16` values into an iret frame or asm.
+//!
+//! Typed selectors convert into the untyped
+//! [`Selector`](kernel_registers::segment::Selector) that the segment and
+//! task registers of `kernel_registers` are loaded with.
 
 use crate::privilege::Rpl;
 use bitfield_struct::bitfield;
 use core::fmt::Debug;
 use core::ops::Deref;
+use kernel_registers::segment::Selector;
 
 /// Which descriptor table a selector addresses.
 ///
@@ -132,6 +137,12 @@ where
     }
 }
 
+impl<K: SelectorKind> From<SegmentSelector<K>> for Selector {
+    fn from(sel: SegmentSelector<K>) -> Self {
+        Self::from_bits(sel.encode())
+    }
+}
+
 impl SegmentSelector<CodeSel> {
     /// Create a **code** selector from a GDT index and desired RPL.
     ///
@@ -0,0 +1,231 @@
+//! # Higher-Half Direct Map
+//!
+//! The HHDM maps physical memory at [`HHDM_BASE`] plus its physical address.
+//! The loader maps the first [`HHDM_SIZE`] bytes with a single 1 GiB page;
+//! everything else is brought online here, one range at a time:
+//!
+//! * [`init`] adds the usable ranges of the [memory map](crate::memmap) that
+//!   lie above the loader's mapping.
+//! * [`add_range`] adds a range discovered later, such as hot-plugged memory.
+//!
+//! Each range is mapped with the largest pages its alignment allows, as
+//! global, writable and not executable, and then handed to the frame
+//! allocator. A range that fills a PML4 slot of its own reaches the other
+//! address spaces through [`alloc`](crate::alloc)'s kernel-half sync.
+//!
+//! Ranges that abut each other, or start or end off a large-page boundary,
+//! leave runs of 4 KiB pages that could have been large ones. Once boot has
+//! brought its ranges online, [`promote_large_pages`] merges them.
+//!
+//! ## Limitations
+//!
+//! * The frame allocator manages the
+//!   [`manageable_size`](kernel_alloc::frame_alloc::BitmapFrameAlloc::manageable_size)
+//!   bytes up to the end of the last usable range the memory map lists at
+//!   boot; memory hot-plugged above is mapped but not allocated from.
+//! * The HHDM ends where the [framebuffer window](VGA_LIKE_OFFSET) begins,
+//!   which limits it to 1 TiB of physical address space.
+//! * Ranges are never taken offline again.
+
+use crate::alloc::{
+    FlushTlb, frame_stats, promote_kernel_mappings, try_with_kernel_vmm, with_kernel_frame_alloc,
+};
+use crate::framebuffer::VGA_LIKE_OFFSET;
+use crate::memmap::{MemoryMap, PhysRange};
+use core::fmt;
+use kernel_alloc::vmm::{AllocationTarget, VmmError};
+use kernel_info::memory::{HHDM_BASE, HHDM_SIZE};
+use kernel_memory_addresses::{PageSize, PhysicalAddress, Size4K};
+use kernel_sync::{IrqGuard, SpinMutex};
+use kernel_vmem::VirtualMemoryPageBits;
+use log::{info, warn};
+
+/// Most ranges that can be online, the loader's included.
+pub const MAX_RANGES: usize = 32;
+
+/// Physical addresses the HHDM can reach.
+pub const WINDOW: u64 = VGA_LIKE_OFFSET;
+
+/// The online ranges, sorted by address; the first is the loader's.
+static ONLINE: SpinMutex<OnlineRanges> = SpinMutex::new(OnlineRanges {
+    ranges: [PhysRange {
+        start: 0,
+        end: HHDM_SIZE,
+    }; MAX_RANGES],
+    len: 1,
+});
+
+#[derive(Copy, Clone)]
+struct OnlineRanges {
+    ranges: [PhysRange; MAX_RANGES],
+    len: usize,
+}
+
+impl OnlineRanges {
+    fn as_slice(&self) -> &[PhysRange] {
+        &self.ranges[..self.len]
+    }
+
+    fn insert(&mut self, range: PhysRange) -> Result<(), HhdmError> {
+        if self.len == MAX_RANGES {
+            return Err(HhdmError::TooManyRanges);
+        }
+        let at = self
+            .as_slice()
+            .iter()
+            .position(|online| online.start > range.start)
+            .unwrap_or(self.len);
+        self.ranges.copy_within(at..self.len, at + 1);
+        self.ranges[at] = range;
+        self.len += 1;
+        Ok(())
+    }
+}
+
+/// Why a range could not be brought online.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum HhdmError {
+    /// The range holds no whole page.
+    Empty,
+    /// The range reaches beyond what the HHDM can map.
+    OutOfWindow,
+    /// The range overlaps one that is online already.
+    Overlaps(PhysRange),
+    /// [`MAX_RANGES`] ranges are online already.
+    TooManyRanges,
+    /// Mapping the range failed.
+    Map(VmmError),
+}
+
+impl fmt::Display for HhdmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => f.write_str("the range holds no whole page"),
+            Self::OutOfWindow => write!(f, "the range reaches beyond {WINDOW:#x}"),
+            Self::Overlaps(online) => write!(f, "the range overlaps {online}, which is online"),
+            Self::TooManyRanges => write!(f, "{MAX_RANGES} ranges are online already"),
+            Self::Map(e) => write!(f, "mapping the range failed: {e}"),
+        }
+    }
+}
+
+/// Bring the usable ranges of `map` above the loader's mapping online.
+pub fn init(map: Option<&MemoryMap>) {
+    let Some(map) = map else {
+        return;
+    };
+    for range in map.ranges().iter().filter(|range| range.end > HHDM_SIZE) {
+        let start = range.start.max(HHDM_SIZE);
+        match add_range(PhysicalAddress::new(start), range.end - start) {
+            Ok(frames) => info!(
+                "HHDM: {} MiB at {start:#x} online, {frames} frames usable",
+                (range.end - start) / 1024 / 1024
+            ),
+            Err(e) => warn!("HHDM: cannot bring {range} online: {e}"),
+        }
+    }
+}
+
+/// Map the `len` bytes of RAM at `pa` into the HHDM and hand them to the
+/// frame allocator. The range is shrunk inwards to whole pages. Returns the
+/// number of frames that became allocatable.
+///
+/// # Errors
+/// See [`HhdmError`]; on error, nothing was brought online.
+pub fn add_range(pa: PhysicalAddress, len: u64) -> Result<usize, HhdmError> {
+    let start = pa.as_u64().next_multiple_of(Size4K::SIZE);
+    let end = pa.as_u64().saturating_add(len) & !(Size4K::SIZE - 1);
+    if start >= end {
+        return Err(HhdmError::Empty);
+    }
+    if end > WINDOW {
+        return Err(HhdmError::OutOfWindow);
+    }
+    let range = PhysRange { start, end };
+
+    let _irq = IrqGuard::new();
+    let mut online = ONLINE.lock();
+    if let Some(&overlap) = online
+        .as_slice()
+        .iter()
+        .find(|online| online.start < end && start < online.end)
+    {
+        return Err(HhdmError::Overlaps(overlap));
+    }
+    if online.len == MAX_RANGES {
+        return Err(HhdmError::TooManyRanges);
+    }
+
+    let nonleaf = VirtualMemoryPageBits::new()
+        .with_present(true)
+        .with_writable(true);
+    let leaf = VirtualMemoryPageBits::new()
+        .with_present(true)
+        .with_writable(true)
+        .with_global(true)
+        .with_no_execute(true);
+    // Nothing was mapped there, so no TLB holds a stale entry.
+    try_with_kernel_vmm(FlushTlb::Never, |vmm| {
+        vmm.map_region(
+            AllocationTarget::Kernel,
+            HHDM_BASE + start,
+            PhysicalAddress::new(start),
+            end - start,
+            nonleaf,
+            leaf,
+        )
+    })
+    .map_err(HhdmError::Map)?;
+    online.insert(range)?;
+
+    let total = frame_stats().total;
+    with_kernel_frame_alloc(|alloc| {
+        alloc.add_usable_range(PhysicalAddress::new(start), PhysicalAddress::new(end));
+    });
+    Ok(frame_stats().total - total)
+}
+
+/// Merge the 4 KiB and 2 MiB pages of the HHDM into larger ones where the
+/// online ranges allow it; see [`promote_kernel_mappings`]. Must run before
+/// the other CPUs start.
+pub fn promote_large_pages() {
+    let end = {
+        let _irq = IrqGuard::new();
+        let online = ONLINE.lock();
+        online.as_slice().last().map_or(0, |range| range.end)
+    };
+    let stats = promote_kernel_mappings(HHDM_BASE, end);
+    if stats.tables_freed() > 0 {
+        info!(
+            "HHDM: merged {} page tables into 2 MiB and {} page directories into 1 GiB pages",
+            stats.to_2m, stats.to_1g
+        );
+    }
+}
+
+/// Whether the HHDM maps all of the `len` bytes at `pa`.
+#[allow(dead_code)]
+pub fn contains(pa: PhysicalAddress, len: u64) -> bool {
+    let Some(end) = pa.as_u64().checked_add(len) else {
+        return false;
+    };
+    let _irq = IrqGuard::new();
+    let online = ONLINE.lock();
+    let mut covered = pa.as_u64();
+    for range in online.as_slice() {
+        if range.start <= covered && covered < range.end {
+            covered = range.end;
+        }
+    }
+    covered >= end
+}
+
+/// Call `f` with every online range, in address order.
+#[allow(dead_code)]
+pub fn for_each_range(f: impl FnMut(PhysRange)) {
+    let online = {
+        let _irq = IrqGuard::new();
+        *ONLINE.lock()
+    };
+    online.as_slice().iter().copied().for_each(f);
+}
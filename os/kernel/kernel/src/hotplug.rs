@@ -0,0 +1,201 @@
+//! # CPU Hotplug
+//!
+//! Takes application processors out of service and back without resetting
+//! them, for debugging and power experiments.
+//!
+//! ## States
+//!
+//! Each CPU's [`CpuHotplug`] is in one of three [`CpuState`]s:
+//!
+//! * **Online**: the CPU runs processes as usual.
+//! * **Parking**: [`offline`] was requested. The CPU no longer picks up
+//!   processes: the running one is [preempted](crate::preempt) at the next
+//!   tick, and the scheduler switches to the idle loop instead of another
+//!   process. The idle loop then parks the CPU.
+//! * **Offline**: the CPU is parked.
+//!
+//! Processes live in the shared process table, so a parked CPU leaves none
+//! behind: the preempted process is back in the run queue for the others.
+//!
+//! ## Parking
+//!
+//! [`park_if_requested`] runs the CPU's pending [work](crate::workqueue),
+//! masks its LAPIC timer and raises its task priority so that only the
+//! [wake IPI](WAKE_IPI_VECTOR) gets through. The CPU then sleeps in a `hlt`
+//! loop until [`online`] marks it online again and sends the IPI. On the way
+//! out, the timer and task priority are restored and the
+//! [`watchdog`](crate::watchdog), which does not check parked CPUs, starts
+//! over for this CPU.
+//!
+//! ## Limitations
+//!
+//! * The bootstrap processor drives the clock and the [timers](crate::timer)
+//!   and is never taken offline.
+//! * Application processors are not started yet, so there is nothing
+//!   [`offline`] could park so far.
+
+use crate::apic;
+use crate::interrupts::wake::WAKE_IPI_VECTOR;
+use crate::per_cpu::{self, PerCpu};
+use crate::{watchdog, workqueue};
+use core::fmt;
+use core::sync::atomic::{AtomicU8, AtomicU64, Ordering};
+use kernel_sync::IrqGuard;
+use log::info;
+
+/// Logical index of the bootstrap processor.
+pub const BOOT_CPU: u32 = 0;
+
+/// Whether a CPU takes part in scheduling; see the [module docs](self).
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(u8)]
+pub enum CpuState {
+    Online = 0,
+    Parking = 1,
+    Offline = 2,
+}
+
+impl CpuState {
+    const fn from_raw(raw: u8) -> Self {
+        match raw {
+            1 => Self::Parking,
+            2 => Self::Offline,
+            _ => Self::Online,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum HotplugError {
+    /// No CPU with this index is registered.
+    NoSuchCpu,
+    /// The bootstrap processor can not be taken offline.
+    BootCpu,
+    /// The CPU is online (or parking) already.
+    AlreadyOnline,
+    /// The CPU is offline (or parking) already.
+    AlreadyOffline,
+}
+
+impl fmt::Display for HotplugError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoSuchCpu => f.write_str("no such CPU"),
+            Self::BootCpu => f.write_str("the boot CPU can not be taken offline"),
+            Self::AlreadyOnline => f.write_str("CPU is online"),
+            Self::AlreadyOffline => f.write_str("CPU is offline"),
+        }
+    }
+}
+
+/// Hotplug state of one CPU, embedded in [`PerCpu`].
+pub struct CpuHotplug {
+    state: AtomicU8,
+    /// Times the CPU was parked.
+    parks: AtomicU64,
+}
+
+impl CpuHotplug {
+    pub const fn new() -> Self {
+        Self {
+            state: AtomicU8::new(CpuState::Online as u8),
+            parks: AtomicU64::new(0),
+        }
+    }
+
+    pub fn state(&self) -> CpuState {
+        CpuState::from_raw(self.state.load(Ordering::Acquire))
+    }
+
+    /// Whether the CPU may pick up processes.
+    pub fn is_online(&self) -> bool {
+        self.state() == CpuState::Online
+    }
+
+    /// Times the CPU was parked.
+    #[allow(dead_code)]
+    pub fn parks(&self) -> u64 {
+        self.parks.load(Ordering::Relaxed)
+    }
+
+    fn transition(&self, from: CpuState, to: CpuState) -> bool {
+        self.state
+            .compare_exchange(from as u8, to as u8, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+    }
+}
+
+impl Default for CpuHotplug {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Take the CPU `cpu_id` out of scheduling and park it.
+///
+/// Returns once the request is made; the CPU parks the next time it is idle.
+pub fn offline(cpu_id: u32) -> Result<(), HotplugError> {
+    let cpu = per_cpu::by_id(cpu_id).ok_or(HotplugError::NoSuchCpu)?;
+    if cpu_id == BOOT_CPU {
+        return Err(HotplugError::BootCpu);
+    }
+    if !cpu.hotplug.transition(CpuState::Online, CpuState::Parking) {
+        return Err(HotplugError::AlreadyOffline);
+    }
+
+    info!("CPU {cpu_id}: going offline");
+    // Cut an idle CPU's sleep short so it parks right away.
+    unsafe { apic::send_ipi_x2apic(cpu.apic_id, WAKE_IPI_VECTOR) };
+    Ok(())
+}
+
+/// Bring the CPU `cpu_id` back into scheduling.
+///
+/// A CPU that is still parking simply stays online.
+pub fn online(cpu_id: u32) -> Result<(), HotplugError> {
+    let cpu = per_cpu::by_id(cpu_id).ok_or(HotplugError::NoSuchCpu)?;
+    let hotplug = &cpu.hotplug;
+    if !hotplug.transition(CpuState::Offline, CpuState::Online)
+        && !hotplug.transition(CpuState::Parking, CpuState::Online)
+    {
+        return Err(HotplugError::AlreadyOnline);
+    }
+
+    unsafe { apic::send_ipi_x2apic(cpu.apic_id, WAKE_IPI_VECTOR) };
+    Ok(())
+}
+
+/// Park the current CPU if [`offline`] asked for it; returns once it is
+/// online again. Called from the idle loop.
+pub fn park_if_requested() {
+    let cpu = unsafe { PerCpu::current() };
+    if cpu.hotplug.state() != CpuState::Parking {
+        return;
+    }
+
+    while workqueue::run_pending() > 0 {}
+    if !cpu.hotplug.transition(CpuState::Parking, CpuState::Offline) {
+        // Brought back online meanwhile.
+        return;
+    }
+
+    let _irq = IrqGuard::new();
+    unsafe {
+        apic::mask_timer_x2apic(true);
+        apic::set_task_priority_x2apic((WAKE_IPI_VECTOR >> 4) - 1);
+    }
+    cpu.hotplug.parks.fetch_add(1, Ordering::Relaxed);
+    info!("CPU {}: offline", cpu.cpu_id);
+
+    while cpu.hotplug.state() == CpuState::Offline {
+        // `sti; hlt` as one unit: the IPI can not slip in between.
+        unsafe { core::arch::asm!("sti; hlt; cli", options(nostack)) };
+    }
+
+    unsafe {
+        apic::set_task_priority_x2apic(0);
+        apic::mask_timer_x2apic(false);
+    }
+    watchdog::resume();
+    info!("CPU {}: online", cpu.cpu_id);
+}
@@ -0,0 +1,125 @@
+//! # CPU Idle
+//!
+//! What a CPU does when the scheduler has nothing to run: it sleeps until the
+//! next interrupt instead of spinning.
+//!
+//! ## Idle methods
+//!
+//! * **`MWAIT`**, if CPUID reports MONITOR/MWAIT with C-state enumeration
+//!   (leaf 05H). The CPU monitors its own tick counter, which the timer
+//!   interrupt bumps, and sleeps in the requested C-state.
+//! * **`HLT`** everywhere else. This is what QEMU usually exposes.
+//!
+//! Both are entered as `sti; hlt` / `sti; mwait`: the one-instruction
+//! interrupt shadow of `sti` guarantees that an interrupt arriving right
+//! before the sleep still wakes the CPU.
+//!
+//! ## C-state selection
+//!
+//! Deeper C-states save more power but take longer to wake up from. A CPU
+//! that just became idle sleeps in C1; once it stayed idle for
+//! [`DEEP_IDLE_STREAK`] consecutive wake-ups without running anything, it
+//! goes to the deepest C-state the CPU reports.
+//!
+//! ## Accounting
+//!
+//! Every idle period is measured in TSC cycles and added to the per-CPU
+//! [`idle_tsc`](PerCpu::idle_tsc) counter; see [`sched::load`](crate::sched::load).
+
+use crate::cpuid::{CpuidRanges, Leaf01h, Leaf05h};
+use crate::per_cpu::PerCpu;
+use crate::tsc::rdtsc;
+use core::sync::atomic::Ordering;
+use kernel_sync::{IrqGuard, SyncOnceCell, irq};
+use log::info;
+
+/// Consecutive idle wake-ups after which the deepest C-state is used.
+pub const DEEP_IDLE_STREAK: u32 = 8;
+
+/// How this CPU sleeps while idle.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum IdleMethod {
+    /// `HLT` (C1).
+    Hlt,
+    /// `MWAIT`, with the hint for the deepest supported C-state.
+    Mwait { deep_hint: u32 },
+}
+
+static METHOD: SyncOnceCell<IdleMethod> = SyncOnceCell::new();
+
+/// The idle method of this system, detected on first use.
+pub fn method() -> IdleMethod {
+    *METHOD.get_or_init(|| {
+        let method = detect();
+        info!("Idle method: {method:?}");
+        method
+    })
+}
+
+fn detect() -> IdleMethod {
+    let ranges = unsafe { CpuidRanges::read() };
+    let has_monitor = unsafe { Leaf01h::read(&ranges) }.is_some_and(|l| l.has_monitor());
+    if !has_monitor {
+        return IdleMethod::Hlt;
+    }
+
+    // EAX[7:4] is the target C-state minus one; sub-state 0.
+    unsafe { Leaf05h::read(&ranges) }
+        .and_then(|l| l.deepest_cstate())
+        .map_or(IdleMethod::Hlt, |cstate| IdleMethod::Mwait {
+            deep_hint: u32::from(cstate - 1) << 4,
+        })
+}
+
+/// Sleep until the next interrupt.
+///
+/// `streak` is the number of consecutive idle periods without any work in
+/// between; it selects the C-state (see the [module docs](self)).
+/// Interrupts are enabled while sleeping and restored to their previous
+/// state before returning.
+pub fn enter(streak: u32) {
+    let _irq = IrqGuard::new();
+    let cpu = unsafe { PerCpu::current() };
+
+    let start = rdtsc();
+    match method() {
+        IdleMethod::Mwait { deep_hint } => {
+            let hint = if streak >= DEEP_IDLE_STREAK {
+                deep_hint
+            } else {
+                0 // C1
+            };
+            unsafe { mwait(cpu.ticks.as_ptr().cast_const(), hint) };
+        }
+        IdleMethod::Hlt => irq::wait_for_interrupt(),
+    }
+    let slept = rdtsc().wrapping_sub(start);
+
+    cpu.idle_tsc.fetch_add(slept, Ordering::Relaxed);
+    cpu.idle_entries.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Arm the monitor on `addr` and sleep with `hint` until it is written or an
+/// interrupt arrives. Returns with interrupts disabled.
+///
+/// # Safety
+/// Requires CPL0, MONITOR/MWAIT support and interrupts disabled on entry.
+unsafe fn mwait(addr: *const u64, hint: u32) {
+    unsafe {
+        core::arch::asm!(
+            "monitor",
+            in("rax") addr,
+            in("ecx") 0,
+            in("edx") 0,
+            options(nostack, preserves_flags),
+        );
+        core::arch::asm!(
+            "sti",
+            "mwait",
+            "cli",
+            in("eax") hint,
+            in("ecx") 0,
+            options(nomem, nostack),
+        );
+    }
+}
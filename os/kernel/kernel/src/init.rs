@@ -33,7 +33,7 @@
 //! ### Subsystem Initialization
 //! * [`initialize_memory_management`] - Sets up physical and virtual memory management
 //! * [`initialize_kernel_stack`] - Allocates and maps per-CPU kernel stack
-//! * [`allocate_ist1_stack`] - Creates IST1 stack for exception handling
+//! * [`allocate_ist_stacks`] - Creates the IST stacks for critical exceptions
 //! * [`remap_framebuffer_memory`] - Maps UEFI GOP framebuffer into kernel space
 //!
 //! ## Architecture Details
@@ -77,39 +77,64 @@
 //! ## Error Handling
 //!
 //! Early boot failures are generally unrecoverable and result in kernel panic.
-//! The initialization sequence uses `.expect()` patterns to provide meaningful
-//! error messages for debugging boot failures.
+//! The fallible steps return a [`BootError`] naming the stage, the address
+//! involved and the cause; [`OrHalt::or_halt`] logs it before panicking.
+
+mod error;
+
+pub use error::{BootError, BootStage, OrHalt};
 
 use crate::idt::{idt_update_in_place, init_idt_once};
+use crate::interrupts::Idt;
 use crate::interrupts::syscall::SyscallInterrupt;
-use crate::interrupts::{Idt, Ist};
 use crate::tracing::trace_boot_info;
-use crate::{gdt, interrupts, kernel_main};
-use kernel_info::boot::{FramebufferInfo, KernelBootInfo, UserBundleInfo};
-use kernel_qemu::QemuLogger;
-use log::{LevelFilter, info};
+use crate::{
+    acpi, boot_modules, bundlefs, clock, clock_page, cmdline, fpu, fw_cfg, gdt, hhdm, interrupts,
+    ioapic, kernel_main, keyboard, kimage, klog, ksyms, pat, per_cpu, pit, preempt, profiler,
+    pstore, random, rtc, tracepoint, tss, tty, watchdog,
+};
+use kernel_info::boot::{
+    BootInfoError, BootInfoHeader, FramebufferInfo, KernelBootInfo, UserBundleInfo,
+};
+use log::{debug, error, info, warn};
 
 use crate::alloc::{
-    FlushTlb, init_kernel_vmm, init_physical_memory_allocator_once, try_with_kernel_vmm,
-    with_kernel_vmm,
+    FlushTlb, frame_stats, init_kernel_vmm, init_pcid, init_physical_memory_allocator_once,
+    on_low_memory, try_with_kernel_vmm, with_kernel_vmm,
 };
-use crate::apic::{init_lapic_and_set_cpu_id, start_lapic_timer};
-use crate::cpuid::CpuidRanges;
+use crate::apic::{init_lapic_and_set_cpu_id, start_lapic_timer, tick_hz};
+use crate::boot_alloc::{BootAlloc, BootAllocError};
+use crate::clock::Calibration;
+use crate::cpuid::{CpuidRanges, Leaf01h};
 use crate::framebuffer::VGA_LIKE_OFFSET;
+use crate::framebuffer::compositor;
+use crate::framebuffer::font;
+use crate::framebuffer::splash::{self, Stage};
+use crate::interrupts::apic_error::ApicErrorInterrupt;
 use crate::interrupts::bp::BreakpointInterrupt;
 use crate::interrupts::df::DfInterrupt;
 use crate::interrupts::gp::GeneralProtectionFaultInterrupt;
+use crate::interrupts::mc::MachineCheckInterrupt;
+use crate::interrupts::msi::MsiInterrupt;
+use crate::interrupts::nmi::NmiInterrupt;
 use crate::interrupts::page_fault::PageFaultInterrupt;
+use crate::interrupts::resched::ReschedInterrupt;
 use crate::interrupts::spurious::SpuriousInterrupt;
 use crate::interrupts::ss::SegmentFaultInterrupt;
+use crate::interrupts::thermal::ThermalInterrupt;
 use crate::interrupts::timer::TimerInterrupt;
+use crate::interrupts::wake::WakeInterrupt;
+use crate::memmap::MemoryMap;
 use crate::msr::{Ia32StarExt, init_gs_bases};
 use crate::per_cpu::PerCpu;
-use crate::per_cpu::ist_stacks::{IST1_SIZE, ist_slot_for_cpu};
+use crate::per_cpu::ist_stacks::{
+    CPU_IST_STACKS, DOUBLE_FAULT_IST, IstStack, NMI_MCE_IST, PAGE_FAULT_IST, ist_slot_for_cpu,
+};
 use crate::per_cpu::kernel_stacks::kstack_slot_for_cpu;
-use crate::per_cpu::stack::{CpuStack, map_ist_stack, map_kernel_stack};
+use crate::per_cpu::stack::{self, CpuStack, StackKind, map_ist_stack, map_kernel_stack};
 use crate::syscall::entry::syscall_entry_stub;
-use crate::tsc::estimate_tsc_hz;
+use crate::tsc::{TscSource, estimate_tsc_hz};
+use kernel_alloc::frame_alloc::{FrameStats, Zone};
 use kernel_alloc::phys_mapper::HhdmPhysMapper;
 use kernel_alloc::vmm::AllocationTarget;
 use kernel_info::memory::{HHDM_BASE, KERNEL_STACK_SIZE};
@@ -119,7 +144,7 @@ use kernel_registers::efer::Efer;
 use kernel_registers::msr::{Ia32Fmask, Ia32LStar, Ia32Star};
 use kernel_registers::{LoadRegisterUnsafe, StoreRegisterUnsafe};
 use kernel_sync::irq::sti_enable_interrupts;
-use kernel_vmem::VirtualMemoryPageBits;
+use kernel_vmem::{CacheMode, VirtualMemoryPageBits};
 
 /// Earliest boot stack size. This stack is used only when handing over from UEFI
 /// to the Kernel, and then immediately changed for a properly allocated stack.
@@ -234,21 +259,28 @@ pub extern "C" fn _start_kernel(_boot_info: *const KernelBootInfo) {
 /// * The [`_start_kernel`] function keeps `boot_info` in `RDI`, matching C ABI expectations.
 #[unsafe(no_mangle)]
 pub extern "C" fn kernel_entry_on_boot_stack(boot_info: *const KernelBootInfo) -> ! {
-    let logger = QemuLogger::new(LevelFilter::Debug);
-    logger.init().expect("logger init");
+    klog::init()
+        .map_err(|e| BootError::new(BootStage::Logger, e))
+        .or_halt();
 
     info!("Kernel reporting to QEMU! Initializing bootstrap processor now.");
     let info = unsafe { CpuidRanges::read() };
     info!("Running on {}", info.vendor.as_str());
 
-    let bi = unsafe { &*boot_info };
+    let bi = validate_boot_info(boot_info).or_halt();
     trace_boot_info(bi);
+    cmdline::init(bi);
+    klog::configure();
+    pstore::init(bi);
 
     info!("Initializing Virtual Memory Manager ...");
-    initialize_memory_management();
+    initialize_memory_management(bi).or_halt();
+
+    info!("Enforcing W^X on the kernel image ...");
+    kimage::init(bi);
 
     info!("Initializing Kernel stack ...");
-    let kstack_top = initialize_kernel_stack();
+    let kstack_top = initialize_kernel_stack().or_halt();
 
     // Switch to the new stack (align already handled in map_kernel_stack)
     info!("Switching to boostrap processor kernel stack ...");
@@ -261,33 +293,107 @@ pub extern "C" fn kernel_entry_on_boot_stack(boot_info: *const KernelBootInfo) -
     }
 }
 
-fn initialize_memory_management() {
+/// Check the header of the loader's boot info before reading anything else.
+///
+/// A loader built against another [`KernelBootInfo`] layout would otherwise
+/// hand over fields the kernel misreads; see [`kernel_info::boot`].
+fn validate_boot_info(
+    boot_info: *const KernelBootInfo,
+) -> Result<&'static KernelBootInfo, BootError> {
+    let boot_error = |e: BootInfoError| BootError::new(BootStage::BootInfo, e);
+    if boot_info.is_null() {
+        return Err(boot_error(BootInfoError::Null));
+    }
+
+    // Safety: every layout starts with the header, so it can be read even
+    // from a boot info of another version.
+    let header = unsafe { boot_info.cast::<BootInfoHeader>().read() };
+    header
+        .check()
+        .map_err(|e| boot_error(e).at(VirtualAddress::from_ptr(boot_info)))?;
+
+    // Safety: the header matches, so the loader wrote a boot info of this
+    // layout, and it stays in loader data the kernel never reuses.
+    Ok(unsafe { &*boot_info })
+}
+
+fn initialize_memory_management(bi: &KernelBootInfo) -> Result<(), BootError> {
+    let boot_error = |e: BootAllocError| BootError::new(BootStage::MemoryManagement, e);
+
+    let arena = bi
+        .arena()
+        .ok_or(BootAllocError::Missing)
+        .map_err(boot_error)?;
+    // Safety: the loader reserved the arena for us alone.
+    let mut arena = unsafe { BootAlloc::new(arena) }.map_err(boot_error)?;
+
+    // Safety: the loader keeps the memory map copy in reserved loader data.
+    let map = match unsafe { MemoryMap::from_uefi(&bi.mmap) } {
+        Ok(map) => {
+            for range in map.ranges() {
+                debug!("Usable RAM: {range} ({} KiB)", range.len() / 1024);
+            }
+            if map.dropped > 0 {
+                warn!(
+                    "Memory map too long, ignoring {} usable ranges",
+                    map.dropped
+                );
+            }
+            info!("Usable RAM: {} MiB", map.usable_bytes() / 1024 / 1024);
+            Some(&*arena.alloc(map).map_err(boot_error)?)
+        }
+        Err(e) => {
+            warn!("Cannot use the UEFI memory map ({e:?}), falling back to defaults");
+            None
+        }
+    };
+
     unsafe {
-        // Initialize the bitmap allocator on the heap.
-        // TODO: Restrict allocator to actual available RAM size.
-        let alloc = init_physical_memory_allocator_once();
+        // Initialize the bitmap allocator in the arena.
+        let alloc = init_physical_memory_allocator_once(map, arena).map_err(boot_error)?;
         info!(
             "Supporting {} MiB of physical RAM",
             alloc.manageable_size() / 1024 / 1024
         );
+        for zone in Zone::ALL {
+            let stats = alloc.zone_stats(zone);
+            info!("  {zone:?}: {} of {} frames free", stats.free, stats.total);
+        }
 
         // Initialize the VMM with the allocator.
         init_kernel_vmm(HhdmPhysMapper, alloc);
     }
+    hhdm::init(map);
+    hhdm::promote_large_pages();
+
+    let total = frame_stats().total;
+    on_low_memory(total / LOW_MEMORY_DIVISOR, warn_low_memory)
+        .map_err(|e| BootError::new(BootStage::MemoryManagement, e))
+}
+
+/// Warn once free frames drop below `1 / LOW_MEMORY_DIVISOR` of all frames.
+const LOW_MEMORY_DIVISOR: usize = 16;
+
+fn warn_low_memory(stats: &FrameStats) {
+    warn!(
+        "Low on physical memory: {} of {} frames free ({} allocations failed)",
+        stats.free, stats.total, stats.failed_allocs
+    );
 }
 
-fn initialize_kernel_stack() -> KernelStackTop {
+fn initialize_kernel_stack() -> Result<KernelStackTop, BootError> {
     let kstack_cpu_slot = kstack_slot_for_cpu(0);
     info!("Designated CPU-specific stack base at {kstack_cpu_slot}.");
     info!("Allocating bootstrap processor kernel stack ...");
     let CpuStack {
-        base: _base,
+        base,
         top: kstack_top,
-        len: _len,
+        len,
     } = try_with_kernel_vmm(FlushTlb::OnSuccess, |vmm| {
         map_kernel_stack(vmm, kstack_cpu_slot, KERNEL_STACK_SIZE as u64)
     })
-    .expect("map per-CPU kernel stack");
+    .map_err(|e| BootError::new(BootStage::KernelStack, e).at(kstack_cpu_slot.base()))?;
+    stack::watch(StackKind::Cpu(0), base, len);
 
     info!("Probing new kernel stack at {kstack_top} ...");
     let probe = (kstack_top.as_u64() - 8) as *mut u64;
@@ -296,7 +402,7 @@ fn initialize_kernel_stack() -> KernelStackTop {
         let _ = core::ptr::read_volatile(probe);
     }
 
-    kstack_top
+    Ok(kstack_top)
 }
 
 /// Naked jump pad: set RSP and jump; no Rust frame, no locals.
@@ -368,46 +474,81 @@ unsafe extern "C" fn stage_one_switch_to_stack_and_enter(
 /// per-CPU stack offsets.
 static mut PER_CPU0: PerCpu = PerCpu::new();
 
+#[allow(clippy::too_many_lines)]
 extern "C" fn stage_two_init_bootstrap_processor(
     boot_info: *const KernelBootInfo,
     kstack_top: KernelStackTop,
 ) -> ! {
     info!("Trampolined onto the kernel stack. Observing kernel stack top at {kstack_top}.");
+    // Safety: validated on entry.
     let bi = unsafe { &*boot_info };
     trace_boot_info(bi);
 
-    info!("Allocating IST1 stack ..");
-    let ist1_top = allocate_ist1_stack();
-
     // Initialize per-CPU configuration
-    let cpu = initialize_percpu_config_for_bsp(kstack_top, ist1_top);
+    let cpu = initialize_percpu_config_for_bsp(kstack_top);
 
     info!("Initializing GDT and TSS ...");
-    gdt::init_gdt_and_tss(cpu, kstack_top, ist1_top);
+    gdt::init_gdt_and_tss(cpu, kstack_top);
+
+    info!("Allocating IST stacks ...");
+    allocate_ist_stacks(cpu).or_halt();
 
     // Point GS.base to &PerCpu for fast access
     unsafe {
         init_gs_bases(cpu);
     }
 
+    // Lock debugging can tell CPUs apart from here on.
+    kernel_sync::lockdep::set_cpu_id_source(|| unsafe { PerCpu::current() }.cpu_id as usize);
+
+    // So can preemption-disabled sections.
+    preempt::init();
+
     // Enable syscall
     unsafe {
         init_syscall(cpu);
     }
 
+    let fpu = unsafe { fpu::init() };
+    info!("FP/SIMD state switching: {fpu:?}");
+
+    if unsafe { pat::init() } {
+        info!(
+            "Programmed the Page Attribute Table: {:#018x}",
+            pat::KERNEL_PAT.into_bits()
+        );
+    } else {
+        warn!("No PAT support; write-combining mappings fall back to uncached");
+    }
+
     info!(
         "Remapping UEFI GOP framebuffer ({size} bytes) ...",
         size = bi.fb.framebuffer_size
     );
-    let fb = remap_framebuffer_memory(bi);
+    let fb = remap_framebuffer_memory(bi).or_halt();
 
     info!(
         "Remapping userland bundle ({size} bytes) ...",
-        size = bi.userland.bytes_ptr
+        size = bi.userland().map_or(0, |u| u.length)
     );
-    let user = remap_userland_memory(bi);
+    let user = remap_userland_memory(bi).or_halt();
+
+    info!(
+        "Mapping {count} boot modules ...",
+        count = bi.modules().len()
+    );
+    boot_modules::init(bi);
+    ksyms::init();
+    mount_userland_bundle(&user);
+    font::init();
+
+    if let Err(e) = unsafe { compositor::init(&fb) } {
+        warn!("No compositor, drawing to the framebuffer directly: {e}");
+    }
+    splash::show();
 
     // Initialize the IDT once.
+    splash::advance(Stage::Interrupts);
     info!("Initializing IDT ...");
 
     unsafe {
@@ -418,35 +559,139 @@ extern "C" fn stage_two_init_bootstrap_processor(
     // when the function returns.
     info!("Installing interrupt handlers ...");
     idt_update_in_place(|idt| {
-        idt.init_df_gate_ist(interrupts::df::double_fault_handler, Ist::Ist1); // TODO: Use a different IST from PF
+        idt.init_df_gate_ist(interrupts::df::double_fault_handler, DOUBLE_FAULT_IST);
         idt.init_breakpoint_gate(interrupts::bp::bp_handler);
         idt.init_syscall_gate();
         idt.init_ss_fault_gate(interrupts::ss::ss_fault_handler);
         idt.init_gp_fault_gate(interrupts::gp::gp_fault_handler);
-        idt.init_page_fault_gate_ist(interrupts::page_fault::page_fault_handler, Ist::Ist1);
+        idt.init_page_fault_gate_ist(interrupts::page_fault::page_fault_handler, PAGE_FAULT_IST);
         idt.init_timer_gate(interrupts::timer::lapic_timer_handler);
+        idt.init_nmi_gate_ist(interrupts::nmi::nmi_handler, NMI_MCE_IST);
+        idt.init_mc_gate_ist(interrupts::mc::machine_check_handler, NMI_MCE_IST);
         idt.init_spurious_interrupt_gate();
+        idt.init_wake_gate();
+        idt.init_resched_gate();
+        idt.init_msi_gate();
+        idt.init_apic_error_gate();
+        idt.init_thermal_gate();
     });
+    enable_machine_checks();
 
+    // From here on, the debugger is reachable through breakpoints.
+    crate::virtio::console::init();
+
+    splash::advance(Stage::Clocks);
     info!("Estimating TSC frequency ...");
-    let tsc_hz = unsafe { estimate_tsc_hz() };
-    trace_tsc_frequency(tsc_hz);
+    let (source, tsc) = unsafe { estimate_tsc_hz() }.expect("no source for the TSC frequency");
+    trace_tsc_frequency(source, tsc);
+    clock::set_tsc_calibration(source, tsc);
+    clock_page::init();
+    random::init(bi);
+    let tsc_hz = tsc.hz;
+
+    splash::advance(Stage::Firmware);
+    info!("Reading ACPI tables ...");
+    acpi::init(bi);
+    ioapic::init();
+    rtc::init();
+
+    if !per_cpu::smp_enabled() {
+        info!("SMP disabled on the command line; staying on the bootstrap processor");
+    }
 
     // Init LAPIC, store LAPIC ID into per-CPU struct, then arm timer.
+    splash::advance(Stage::Timer);
     init_lapic_and_set_cpu_id(cpu);
-    start_lapic_timer(tsc_hz);
+    start_timer_tick(tsc_hz);
+    per_cpu::register(unsafe { PerCpu::current() });
+    tracepoint::init();
+    keyboard::init();
+    tty::init();
+    crate::framebuffer::device::init();
 
     info!("Enabling interrupts ...");
     sti_enable_interrupts();
+    watchdog::enable();
+    profiler::init();
+    crate::page_cache::init();
 
+    // Commands complete by interrupt or timer tick, so after `sti`.
+    crate::nvme::init();
+    crate::ahci::init();
+    crate::xhci::init();
+
+    splash::advance(Stage::AddressSpace);
     info!("Clearing UEFI pages ...");
     with_kernel_vmm(|vmm| unsafe { vmm.clear_lower_half() });
 
     info!("Enabling Supervisor Mode Execution and Access Prevention (SMEP/SMAP)");
     enable_supervisor_protections();
 
+    info!("Auditing the kernel page tables ...");
+    crate::alloc::audit::run();
+
+    let pcid = unsafe { init_pcid() };
+    info!("Address space switching: {pcid:?}");
+
+    #[cfg(feature = "poison")]
+    crate::alloc::poison::init();
+
+    #[cfg(feature = "fault-inject")]
+    crate::alloc::fault_inject::init();
+
+    #[cfg(feature = "ktest")]
+    crate::ktest::run();
+
+    splash::finish();
     info!("Kernel early init is done, jumping into kernel main loop ...");
-    kernel_main(&fb, &user)
+    kernel_main(&fb)
+}
+
+/// Make the userland bundle available to [`bundlefs`]. A bundle the QEMU host
+/// passed as the [`fw_cfg`] file `opt/os/userland` comes first, then a
+/// `userland` boot module, then the loader's default bundle.
+#[allow(clippy::cast_possible_truncation)]
+fn mount_userland_bundle(user: &UserBundleInfo) {
+    let from_host = fw_cfg::find("opt/os/userland").and_then(|file| {
+        fw_cfg::load(file)
+            .inspect_err(|e| warn!("Failed to load {}: {e}", file.name()))
+            .ok()
+    });
+    let bundle = from_host
+        .or_else(|| boot_modules::find("userland"))
+        .unwrap_or_else(|| unsafe {
+            core::slice::from_raw_parts(user.bytes_ptr as *const u8, user.length as usize)
+        });
+    bundlefs::mount(bundle);
+}
+
+/// Arm the LAPIC timer, or, if it does not count or `pit_tick` or `rtc_tick`
+/// asks for it, have the PIT or the RTC drive the tick.
+fn start_timer_tick(tsc_hz: u64) {
+    let rtc = rtc::tick_requested();
+    if !rtc && !pit::tick_requested() && start_lapic_timer(tsc_hz) {
+        return;
+    }
+    if !rtc {
+        info!("Driving the timer tick from the PIT ...");
+        match pit::start_tick(tick_hz()) {
+            Ok(()) => return,
+            Err(e) => warn!("No PIT tick: {e}"),
+        }
+    }
+    info!("Driving the timer tick from the RTC ...");
+    if let Err(e) = rtc::start_tick() {
+        error!("No timer tick: {e}");
+    }
+}
+
+/// Deliver machine checks to their handler instead of shutting the CPU down.
+fn enable_machine_checks() {
+    let ranges = unsafe { CpuidRanges::read() };
+    if unsafe { Leaf01h::read(&ranges) }.is_some_and(|l| l.has_mce()) {
+        info!("Enabling machine check exceptions");
+        unsafe { Cr4::load_unsafe().with_mce(true).store_unsafe() };
+    }
 }
 
 fn enable_supervisor_protections() {
@@ -480,39 +725,44 @@ unsafe fn init_syscall(cpu: &PerCpu) {
     unsafe { Efer::load_unsafe().with_sce(true).store_unsafe() }
 }
 
-type Ist1StackTop = VirtualAddress;
 type KernelStackTop = VirtualAddress;
 
-fn allocate_ist1_stack() -> Ist1StackTop {
-    let (ist1_base, ist1_top) = try_with_kernel_vmm(FlushTlb::OnSuccess, |vmm| {
-        let slot = ist_slot_for_cpu(0, Ist::Ist1);
-        map_ist_stack(vmm, slot, IST1_SIZE)
+/// Map the [IST stacks](CPU_IST_STACKS) of `cpu` and point its TSS at them.
+fn allocate_ist_stacks(cpu: &mut PerCpu) -> Result<(), BootError> {
+    for stack in CPU_IST_STACKS {
+        let top = allocate_ist_stack(cpu.cpu_id, stack)?;
+        tss::set_ist(cpu, stack.ist, top);
+    }
+    Ok(())
+}
+
+fn allocate_ist_stack(cpu: u32, stack: IstStack) -> Result<VirtualAddress, BootError> {
+    let slot = ist_slot_for_cpu(u64::from(cpu), stack.ist);
+    let (base, top) = try_with_kernel_vmm(FlushTlb::OnSuccess, |vmm| {
+        map_ist_stack(vmm, slot, stack.size)
     })
-    .expect("map IST1");
-    info!("IST1 mapped: base={ist1_base}, top={ist1_top}");
-    ist1_top
+    .map_err(|e| BootError::new(BootStage::IstStack, e).at(slot.base()))?;
+    let n = stack.ist.gate_index();
+    info!("IST{n} ({}) mapped: base={base}, top={top}", stack.purpose);
+    stack::watch(StackKind::Ist { cpu, ist: n }, base, stack.size);
+    Ok(top)
 }
 
-fn initialize_percpu_config_for_bsp(
-    kstack_top: KernelStackTop,
-    ist1_top: Ist1StackTop,
-) -> &'static mut PerCpu {
+fn initialize_percpu_config_for_bsp(kstack_top: KernelStackTop) -> &'static mut PerCpu {
     #[allow(static_mut_refs)]
     let p = unsafe { &mut PER_CPU0 };
     p.cpu_id = 0;
     p.apic_id = 0; // will be set below by the APIC initialization.
     p.kstack_top = kstack_top;
-    if let Some(idx) = Ist::Ist1.tss_index() {
-        p.ist_stacks[idx] = ist1_top;
-    }
     p
 }
 
 #[allow(clippy::cast_precision_loss)]
-fn trace_tsc_frequency(tsc_hz: u64) {
+fn trace_tsc_frequency(source: TscSource, tsc: Calibration) {
     info!(
-        "TSC frequency = {tsc_hz} Hz ({ghz:0.2} GHz)",
-        ghz = (tsc_hz as f32) / 1000.0 / 1000.0 / 1000.0
+        "TSC frequency = {tsc} ({ghz:0.2} GHz) from {source}, {confidence}",
+        ghz = (tsc.hz as f32) / 1000.0 / 1000.0 / 1000.0,
+        confidence = source.confidence()
     );
 }
 
@@ -522,14 +772,14 @@ fn trace_tsc_frequency(tsc_hz: u64) {
 /// include it in the memory mapping table. This means the kernel must manually map the
 /// framebuffer into its own virtual address space to access it. This function sets up the
 /// necessary mapping so the framebuffer can be used by the kernel.
-fn remap_framebuffer_memory(bi: &KernelBootInfo) -> FramebufferInfo {
+fn remap_framebuffer_memory(bi: &KernelBootInfo) -> Result<FramebufferInfo, BootError> {
     // Map framebuffer
     let fb_pa = PhysicalAddress::new(bi.fb.framebuffer_ptr);
     let fb_len = bi.fb.framebuffer_size;
     let va_base = HHDM_BASE + VGA_LIKE_OFFSET;
     let fb_flags = VirtualMemoryPageBits::default()
         .with_writable(true)
-        .with_write_combining()
+        .with_cache_mode(CacheMode::WriteCombining)
         .with_global(true)
         .with_no_execute(true);
 
@@ -543,13 +793,13 @@ fn remap_framebuffer_memory(bi: &KernelBootInfo) -> FramebufferInfo {
             fb_flags,
         )
     })
-    .expect("Framebuffer mapping failed");
+    .map_err(|e| BootError::new(BootStage::Framebuffer, e).at(va_base))?;
 
     // Return updated FramebufferInfo with new virtual address
     let mut fb_virt = bi.fb.clone();
     fb_virt.framebuffer_ptr = (va_base + (fb_pa.as_u64() & 0xFFF)).as_u64(); // preserve offset within page
     info!("Remapped frame buffer to {va_base}");
-    fb_virt
+    Ok(fb_virt)
 }
 
 /// Virtual offset inside the HHDM where we map the userland bootstrap data.
@@ -561,10 +811,17 @@ pub const USERLAND_BOOTSTRAP_BUNDLE: u64 = 1u64 << 41; // 2 TiB inside HHDM rang
 /// include it in the memory mapping table. This means the kernel must manually map the
 /// framebuffer into its own virtual address space to access it. This function sets up the
 /// necessary mapping so the framebuffer can be used by the kernel.
-fn remap_userland_memory(bi: &KernelBootInfo) -> UserBundleInfo {
-    // Map framebuffer
-    let pa = PhysicalAddress::new(bi.userland.bytes_ptr);
-    let len = bi.userland.length;
+fn remap_userland_memory(bi: &KernelBootInfo) -> Result<UserBundleInfo, BootError> {
+    // The loader skips the default bundle if a `userland` boot module replaces it.
+    let Some(userland) = bi.userland() else {
+        return Ok(UserBundleInfo {
+            bytes_ptr: 0,
+            length: 0,
+        });
+    };
+
+    let pa = PhysicalAddress::new(userland.bytes_ptr);
+    let len = userland.length;
     let va_base = HHDM_BASE + USERLAND_BOOTSTRAP_BUNDLE;
 
     let user_flags = VirtualMemoryPageBits::default()
@@ -582,11 +839,11 @@ fn remap_userland_memory(bi: &KernelBootInfo) -> UserBundleInfo {
             user_flags,
         )
     })
-    .expect("Userland mapping failed");
+    .map_err(|e| BootError::new(BootStage::UserBundle, e).at(va_base))?;
 
     // Return updated FramebufferInfo with new virtual address
-    let mut virt = bi.userland.clone();
+    let mut virt = userland.clone();
     virt.bytes_ptr = (va_base + (pa.as_u64() & 0xFFF)).as_u64(); // preserve offset within page
     info!("Remapped userland bundle to {va_base}");
-    virt
+    Ok(virt)
 }
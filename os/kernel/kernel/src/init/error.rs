@@ -0,0 +1,160 @@
+//! Errors of the early init stages.
+//!
+//! The fallible init steps return a [`BootError`] naming the [`BootStage`],
+//! the address involved and the cause. Early boot cannot recover from any of
+//! them: callers unwrap with [`OrHalt::or_halt`], which logs the error and
+//! panics, so the panic handler adds a backtrace and stops the CPU.
+
+use crate::boot_alloc::BootAllocError;
+use core::fmt;
+use kernel_alloc::frame_alloc::TooManyWatches;
+use kernel_alloc::vmm::VmmError;
+use kernel_info::boot::BootInfoError;
+use kernel_memory_addresses::VirtualAddress;
+use log::{SetLoggerError, error};
+
+/// A step of the early init sequence.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum BootStage {
+    Logger,
+    BootInfo,
+    MemoryManagement,
+    KernelStack,
+    IstStack,
+    Framebuffer,
+    UserBundle,
+}
+
+impl BootStage {
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Logger => "setting up the logger",
+            Self::BootInfo => "validating the boot info",
+            Self::MemoryManagement => "initializing memory management",
+            Self::KernelStack => "mapping the kernel stack",
+            Self::IstStack => "mapping an IST stack",
+            Self::Framebuffer => "remapping the framebuffer",
+            Self::UserBundle => "remapping the userland bundle",
+        }
+    }
+}
+
+impl fmt::Display for BootStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// What went wrong in a [`BootStage`].
+#[derive(Debug)]
+pub enum BootErrorCause {
+    /// Another logger was installed first.
+    Logger(SetLoggerError),
+    /// Loader and kernel disagree on the boot info layout.
+    BootInfo(BootInfoError),
+    /// A mapping failed.
+    Vmm(VmmError),
+    /// No room for another low-memory callback.
+    LowMemoryWatch(TooManyWatches),
+    /// The early boot allocator failed.
+    BootAlloc(BootAllocError),
+}
+
+impl fmt::Display for BootErrorCause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Logger(e) => write!(f, "logger: {e}"),
+            Self::BootInfo(e) => write!(f, "boot info: {e}"),
+            Self::Vmm(e) => write!(f, "virtual memory: {e}"),
+            Self::LowMemoryWatch(e) => write!(f, "low-memory watch: {e}"),
+            Self::BootAlloc(e) => write!(f, "boot allocator: {e}"),
+        }
+    }
+}
+
+impl From<SetLoggerError> for BootErrorCause {
+    fn from(e: SetLoggerError) -> Self {
+        Self::Logger(e)
+    }
+}
+
+impl From<BootInfoError> for BootErrorCause {
+    fn from(e: BootInfoError) -> Self {
+        Self::BootInfo(e)
+    }
+}
+
+impl From<VmmError> for BootErrorCause {
+    fn from(e: VmmError) -> Self {
+        Self::Vmm(e)
+    }
+}
+
+impl From<TooManyWatches> for BootErrorCause {
+    fn from(e: TooManyWatches) -> Self {
+        Self::LowMemoryWatch(e)
+    }
+}
+
+impl From<BootAllocError> for BootErrorCause {
+    fn from(e: BootAllocError) -> Self {
+        Self::BootAlloc(e)
+    }
+}
+
+/// A failed [`BootStage`]; see the [module docs](self).
+#[derive(Debug)]
+pub struct BootError {
+    pub stage: BootStage,
+    /// The address the failure is about, if any.
+    pub address: Option<VirtualAddress>,
+    pub cause: BootErrorCause,
+}
+
+impl BootError {
+    pub fn new(stage: BootStage, cause: impl Into<BootErrorCause>) -> Self {
+        Self {
+            stage,
+            address: None,
+            cause: cause.into(),
+        }
+    }
+
+    /// Attach the address the failure is about.
+    #[must_use]
+    pub const fn at(mut self, address: VirtualAddress) -> Self {
+        self.address = Some(address);
+        self
+    }
+
+    /// Log the error and stop.
+    pub fn halt(self) -> ! {
+        match self.address {
+            Some(address) => error!("Kernel init failed while {} at {address}", self.stage),
+            None => error!("Kernel init failed while {}", self.stage),
+        }
+        error!("  cause: {}", self.cause);
+        panic!("{self}");
+    }
+}
+
+impl fmt::Display for BootError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} failed: {}", self.stage, self.cause)
+    }
+}
+
+/// Halt on a failed init step.
+pub trait OrHalt<T> {
+    /// The success value, or [`BootError::halt`].
+    fn or_halt(self) -> T;
+}
+
+impl<T> OrHalt<T> for Result<T, BootError> {
+    fn or_halt(self) -> T {
+        match self {
+            Ok(value) => value,
+            Err(e) => e.halt(),
+        }
+    }
+}
@@ -46,24 +46,32 @@
 //!   for most ISRs and for a simple `int 0x80` syscall path.
 //! - **Trap gates** leave IF unchanged. Useful for debugging and certain faults.
 
+pub mod apic_error;
 pub mod bp;
 pub mod df;
 pub mod gp;
 mod ist;
+pub mod mc;
+pub mod msi;
+pub mod nmi;
 pub mod page_fault;
+pub mod resched;
 pub mod spurious;
 pub mod ss;
 pub mod syscall;
+pub mod thermal;
 pub mod timer;
+pub mod wake;
 
 use crate::gdt::selectors::{SegmentSelector, SegmentSelectorRaw, SelectorKind};
 use crate::privilege::Ring;
 use bitfield_struct::bitfield;
-use core::arch::asm;
 use core::mem::size_of;
 use core::ops::{Index, IndexMut};
 pub use ist::Ist;
-use kernel_memory_addresses::VirtualAddress;
+use kernel_registers::dtr::{DescriptorTablePointer, Idtr};
+use kernel_registers::segment::Cs;
+use kernel_registers::{LoadRegister, LoadRegisterUnsafe, StoreRegisterUnsafe};
 
 // Compile-time layout sanity checks for the architecture.
 //
@@ -161,14 +169,9 @@ impl Idt {
     /// - If any entry is callable from user mode (DPL=3), ensure your **TSS**
     ///   (especially `rsp0`) is configured for safe privilege transitions.
     #[inline]
-    #[allow(clippy::cast_possible_truncation)]
     pub unsafe fn load(&'static self) {
-        let idtr = Idtr {
-            limit: (size_of::<Self>() - 1) as u16,
-            base: VirtualAddress::from_ptr(core::ptr::from_ref(self)),
-        };
         unsafe {
-            asm!("lidt [{}]", in(reg) &raw const idtr, options(nostack, preserves_flags, readonly));
+            Idtr(DescriptorTablePointer::for_table(self)).store_unsafe();
         }
     }
 
@@ -194,19 +197,8 @@ impl Idt {
     ///   - the limit (size − 1) field, which should equal `size_of::<Idt>() - 1`.
     #[inline]
     pub unsafe fn is_loaded(&'static self) -> bool {
-        let mut idtr = Idtr {
-            limit: 0,
-            base: VirtualAddress::zero(),
-        };
-        unsafe {
-            asm!("sidt [{}]", in(reg) &raw mut idtr, options(nostack, preserves_flags));
-        }
-
-        // Compare base to our IDT buffer
-        #[allow(static_mut_refs)]
-        let our_base = VirtualAddress::from_ptr(self);
-        let base = unsafe { core::ptr::addr_of!(idtr.base).read_unaligned() };
-        base == our_base && idtr.limit as usize + 1 == size_of::<Self>()
+        let idtr = unsafe { Idtr::load_unsafe() };
+        idtr.0.is_table(self)
     }
 }
 
@@ -223,13 +215,6 @@ impl IndexMut<usize> for Idt {
     }
 }
 
-/// Operand format used by `lidt` (limit + base).
-#[repr(C, packed)]
-pub struct Idtr {
-    limit: u16,
-    base: VirtualAddress,
-}
-
 /// One **16-byte** x86-64 IDT gate descriptor.
 ///
 /// Layout summary (Intel SDM, “Interrupt Descriptor Table”):
@@ -426,12 +411,81 @@ impl IdtEntryBuilder<'_> {
     }
 }
 
+/// General-purpose registers pushed by an interrupt entry stub, followed by
+/// the frame the CPU pushed on entry.
+///
+/// Stubs push `rax` first and `r15` last, so `r15` ends up at the lowest
+/// address; passing `rsp` right after the pushes yields a pointer to this.
+#[repr(C)]
+#[allow(dead_code)]
+pub struct InterruptFrame {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rbp: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rax: u64,
+    pub rip: u64,
+    pub cs: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
+}
+
+impl InterruptFrame {
+    /// Whether the interrupt arrived while running in user mode.
+    #[inline]
+    pub const fn is_from_user(&self) -> bool {
+        self.cs & 3 == 3
+    }
+}
+
+/// Like [`InterruptFrame`], for exceptions where the CPU pushes an error code
+/// below the return frame (e.g. page and general protection faults).
+#[repr(C)]
+pub struct ExceptionFrame {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rbp: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rax: u64,
+    pub error_code: u64,
+    pub rip: u64,
+    pub cs: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
+}
+
+impl ExceptionFrame {
+    /// Whether the exception was raised in user mode.
+    #[inline]
+    pub const fn is_from_user(&self) -> bool {
+        self.cs & 3 == 3
+    }
+}
+
 /// Read the current **CS** selector (used as a sensible default for entries).
 #[inline]
 fn current_cs() -> SegmentSelectorRaw {
-    let cs: u16;
-    unsafe {
-        asm!("mov {0:x}, cs", out(reg) cs, options(nomem, nostack, preserves_flags));
-    }
-    SegmentSelectorRaw::from_bits(cs)
+    SegmentSelectorRaw::from_bits(Cs::load().0.into_bits())
 }
@@ -0,0 +1,73 @@
+//! Local APIC error interrupt.
+//!
+//! The local APIC raises it when it fails to send or accept an interrupt,
+//! sees an illegal vector, or is accessed at a register it does not have;
+//! see [`ApicErrors`](crate::apic::lvt::ApicErrors). The handler reads and
+//! clears the error status register and logs the errors, which would
+//! otherwise go unnoticed.
+
+use crate::apic;
+use crate::apic::lvt::{self, Lvt, LvtEntry};
+use crate::gdt::KERNEL_CS_SEL;
+use crate::interrupts::{GateType, Idt};
+use crate::irq_stats;
+use log::warn;
+
+/// Vector of the local APIC error interrupt.
+pub const APIC_ERROR_VECTOR: u8 = 0xFE;
+
+pub trait ApicErrorInterrupt {
+    /// Install the handler of the [`APIC_ERROR_VECTOR`].
+    fn init_apic_error_gate(&mut self) -> &mut Self;
+}
+
+impl ApicErrorInterrupt for Idt {
+    fn init_apic_error_gate(&mut self) -> &mut Self {
+        self[usize::from(APIC_ERROR_VECTOR)]
+            .set_handler(apic_error_handler)
+            .selector(KERNEL_CS_SEL)
+            .present(true)
+            .kernel_only()
+            .gate_type(GateType::InterruptGate);
+        self
+    }
+}
+
+/// Discard the errors detected so far and unmask the error entry of the
+/// current CPU's local APIC.
+pub fn enable() {
+    let _ = lvt::read_esr();
+    // SAFETY: The entry always exists, and the gate is installed.
+    unsafe { Lvt::Error.write(LvtEntry::new().with_vector(APIC_ERROR_VECTOR)) };
+}
+
+#[unsafe(naked)]
+extern "C" fn apic_error_handler() {
+    core::arch::naked_asm!(
+        "cld",
+        // Save the caller-saved GPRs, and RBX for the stack pointer.
+        "push rax","push rbx","push rcx","push rdx","push rsi","push rdi",
+        "push r8","push r9","push r10","push r11",
+
+        "mov rbx, rsp",
+        "and rsp, -16",
+        "call {rust_handler}",
+        "mov rsp, rbx",
+
+        "pop r11","pop r10","pop r9","pop r8",
+        "pop rdi","pop rsi","pop rdx","pop rcx","pop rbx","pop rax",
+        "iretq",
+
+        rust_handler = sym apic_error_handler_rust,
+    )
+}
+
+extern "C" fn apic_error_handler_rust() {
+    irq_stats::count(usize::from(APIC_ERROR_VECTOR));
+    let errors = lvt::read_esr();
+    warn!(
+        "Local APIC error on APIC {:#x}: {errors}",
+        apic::x2apic_id()
+    );
+    unsafe { apic::eoi_x2apic() };
+}
@@ -1,6 +1,16 @@
+//! # Breakpoint (`#BP`)
+//!
+//! `int3` in kernel mode enters the [debugger](crate::kdb) if it is
+//! available; otherwise, and for `int3` in user mode, the breakpoint is
+//! logged and execution continues after it.
+
 use crate::gdt::KERNEL_CS_SEL;
 use crate::interrupts::{GateType, Idt};
+use crate::kdb::Reason;
+use crate::ksyms::Symbolized;
+use crate::{irq_stats, kdb};
 use core::arch::naked_asm;
+use kernel_memory_addresses::VirtualAddress;
 use log::warn;
 
 pub const BP_VECTOR: usize = 0x03;
@@ -36,7 +46,11 @@ pub extern "C" fn bp_handler() {
         "1:",
 
         "mov rdi, [rsp]      ", // cr3 as arg0 (just to print something)
+        "mov rsi, [rsp + 16]",  // RIP after the int3 as arg1
+        "mov rdx, [rsp + 24]",  // CS as arg2
+        "sub rsp, 8",           // align the stack for the call
         "call {rust}",
+        "add rsp, 8",
 
          // EXIT: swapgs back if returning to CPL3 (same offset; stack unchanged)
         "mov rax, [rsp + 24]",
@@ -52,6 +66,12 @@ pub extern "C" fn bp_handler() {
     );
 }
 
-extern "C" fn bp_rust(cr3: u64) {
-    warn!("Breakpoint from user, CR3={cr3:#x}");
+extern "C" fn bp_rust(cr3: u64, rip: u64, cs: u64) {
+    irq_stats::count(BP_VECTOR);
+    let rip = VirtualAddress::new(rip);
+    if cs & 3 != 0 {
+        warn!("Breakpoint from user at {rip}, CR3={cr3:#x}");
+    } else if !kdb::enter(Reason::Breakpoint(rip)) {
+        warn!("Breakpoint at {}, CR3={cr3:#x}", Symbolized(rip));
+    }
 }
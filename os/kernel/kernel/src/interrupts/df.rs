@@ -1,6 +1,9 @@
 use crate::gdt::KERNEL_CS_SEL;
 use crate::interrupts::{GateType, Idt, Ist};
-use log::error;
+use crate::irq_stats;
+use crate::klog::emergency;
+use crate::per_cpu::ist_stacks;
+use kernel_memory_addresses::VirtualAddress;
 
 pub const DF_VECTOR: usize = 0x08;
 
@@ -36,5 +39,13 @@ pub extern "C" fn double_fault_handler() {
 }
 
 extern "C" fn df_rust(cr2: u64) {
-    error!("#DF cr2={cr2:#x}");
+    irq_stats::count(DF_VECTOR);
+    emergency!("#DF cr2={cr2:#x}");
+    if let Some((cpu, stack)) = ist_stacks::guard_owner(VirtualAddress::new(cr2)) {
+        emergency!(
+            "CR2 lies in the guard page of CPU {cpu}'s IST{} ({}) stack: it overflowed",
+            stack.ist.gate_index(),
+            stack.purpose
+        );
+    }
 }
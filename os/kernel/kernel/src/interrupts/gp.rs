@@ -1,9 +1,12 @@
 use crate::gdt::KERNEL_CS_SEL;
-use crate::interrupts::{GateType, Idt};
+use crate::interrupts::{ExceptionFrame, GateType, Idt};
+use crate::klog::emergency;
+use crate::{extable, irq_stats, kimage, ksyms, signal};
 use core::arch::naked_asm;
 use core::hint::spin_loop;
 use kernel_memory_addresses::VirtualAddress;
-use log::error;
+use log::info;
+use stdlib::syscall_abi::signal::SIGSEGV;
 
 pub const GP_FAULT_VECTOR: usize = 0x0D; // 13
 
@@ -23,40 +26,75 @@ impl GeneralProtectionFaultInterrupt for Idt {
     }
 }
 
+/// Interrupt-gate #GP handler.
+///
+/// Faults in user mode raise `SIGSEGV` for the process (see [`signal`]); in
+/// kernel mode they resume at the [exception table](extable) recovery
+/// address if expected, and are logged and park the CPU otherwise.
 #[unsafe(naked)]
 pub extern "C" fn gp_fault_handler() {
     naked_asm!(
-        "cli",
-        // Save a minimal set of caller-saved regs we’ll use (SysV: rax, rdi, rsi)
-        "push rax",
-        "push rdi",
-        "push rsi",
-
-        // ENTRY swapgs if from CPL3: CS at [rsp + 40]
-        "mov rax, [rsp + 40]",
+        // Save all GPRs; with the error code and the CPU frame above them,
+        // they form an `ExceptionFrame` at rsp.
+        "push rax","push rbx","push rcx","push rdx","push rsi","push rdi","push rbp",
+        "push r8","push r9","push r10","push r11","push r12","push r13","push r14","push r15",
+
+        // ENTRY swapgs if from CPL3: CS at [rsp + 136]
+        "mov rax, [rsp + 136]",
         "test al, 3",
         "jz 1f",
         "swapgs",
         "1:",
 
-        // rdi := RIP (first arg)
-        "mov rdi, [rsp + 32]",   // 3 pushes * 8 + 8 = 24 + 8 = 32
-        // rsi := error code (second arg)
-        "mov rsi, [rsp + 24]",   // 3 pushes * 8 = 24
+        // rdi := frame (first arg)
+        "mov rdi, rsp",
 
-        "call {log_gp}",
+        // Align the stack for the call; rbx is callee-saved.
+        "mov rbx, rsp",
+        "and rsp, -16",
+        "call {handle_gp}",
+        "mov rsp, rbx",
 
-        // Park the CPU
-        "1: hlt",
-        "jmp 1b",
+        // User faults and expected kernel faults return; swapgs back to
+        // user GS only for the former.
+        "mov rax, [rsp + 136]",
+        "test al, 3",
+        "jz 2f",
+        "swapgs",
+        "2:",
+        "pop r15","pop r14","pop r13","pop r12","pop r11","pop r10","pop r9","pop r8",
+        "pop rbp","pop rdi","pop rsi","pop rdx","pop rcx","pop rbx","pop rax",
+        "add rsp, 8",            // drop the error code
+        "iretq",
 
-        log_gp = sym log_gp_fault
+        handle_gp = sym handle_gp_fault
     )
 }
 
-extern "C" fn log_gp_fault(rip: VirtualAddress, selector: u64) {
+/// Raise `SIGSEGV` for a fault in user mode; resume after expected kernel
+/// faults, log others and park.
+extern "C" fn handle_gp_fault(frame: &mut ExceptionFrame) {
+    irq_stats::count(GP_FAULT_VECTOR);
+    if frame.is_from_user() {
+        info!(
+            "User general protection fault at rip={rip:#x} (error code {err:#x})",
+            rip = frame.rip,
+            err = frame.error_code
+        );
+        signal::raise_fault(frame, SIGSEGV);
+        return;
+    }
+
+    if extable::fixup(frame, GP_FAULT_VECTOR) {
+        return;
+    }
+
+    log_gp_fault(VirtualAddress::new(frame.rip), frame.error_code, frame.rbp);
+}
+
+fn log_gp_fault(rip: VirtualAddress, selector: u64, rbp: u64) -> ! {
     let info = decode_gp_error(selector);
-    error!(
+    emergency!(
         "general protection fault general protection fault page
         ⠀⠀⠀⠀⠀⠀⠀⠙⣿⣷⣄⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀
         ⠀⠀⠀⠀⠀⠀⠀⠀⢺⣿⣿⡆⠀⠀⠀⠀⠀⠀⡀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀
@@ -82,11 +120,23 @@ extern "C" fn log_gp_fault(rip: VirtualAddress, selector: u64) {
         ⠠⢸⣿⣿⣿⣿⣿⠀⠀⠀⠀⠀⠀⠀⢸⣿⣿⣿⣿⣿⣿⢀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠘⣿⣿⣿⣿⣿⣿⣿⣿
         ⠀⠛⣿⣿⣿⡿⠏⠀⠀⠀⠀⠀⠀⢳⣾⣿⣿⣿⣿⣿⣿⡶⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⣿⣿⣿⣿⣿⣿⣿
         ⠀⢨⠀⠉⠉⠀⠀⠀⠀⠀⠀⠀⠀⠙⣿⣿⡿⡿⠿⠛⠙⠁⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠹⠏⠉⠻⠿⠟⠁\n\
-        GENERAL PROTECTION FAULT: rip=0x{rip} selector={selector:#x}\
+        GENERAL PROTECTION FAULT: rip={sym} selector={selector:#x}\
           selector_idx={} ti={} ext={}\n",
-        info.selector_index, info.ti_ldt, info.external
+        info.selector_index,
+        info.ti_ldt,
+        info.external,
+        sym = ksyms::Symbolized(rip)
     );
 
+    if !kimage::is_kernel_text(rip) {
+        emergency!(
+            "RIP lies outside the kernel text: {:?}",
+            kimage::describe(rip)
+        );
+    }
+
+    ksyms::log_backtrace_from(rbp);
+
     loop {
         spin_loop();
     }
@@ -0,0 +1,52 @@
+//! Machine check exception (vector 18).
+//!
+//! Raised for uncorrected hardware errors, once `CR4.MCE` is set. Like an NMI
+//! it may interrupt anything, so it runs on the IST stack it shares with NMIs
+//! ([`NMI_MCE_IST`](crate::per_cpu::ist_stacks::NMI_MCE_IST)). The state of
+//! the machine is unknown afterwards: the handler logs where it hit and halts.
+
+use crate::gdt::KERNEL_CS_SEL;
+use crate::interrupts::{GateType, Idt, Ist};
+use crate::irq_stats;
+use crate::klog::emergency;
+use crate::ksyms::Symbolized;
+use kernel_memory_addresses::VirtualAddress;
+
+pub const MC_VECTOR: usize = 0x12; // 18
+
+pub trait MachineCheckInterrupt {
+    fn init_mc_gate_ist(&mut self, handler: extern "C" fn(), ist: Ist) -> &mut Self;
+}
+
+impl MachineCheckInterrupt for Idt {
+    fn init_mc_gate_ist(&mut self, handler: extern "C" fn(), ist: Ist) -> &mut Self {
+        self[MC_VECTOR]
+            .set_handler(handler)
+            .selector(KERNEL_CS_SEL)
+            .present(true)
+            .kernel_only()
+            .ist(ist)
+            .gate_type(GateType::InterruptGate);
+        self
+    }
+}
+
+#[unsafe(naked)]
+pub extern "C" fn machine_check_handler() {
+    core::arch::naked_asm!(
+        "cli",
+        "mov rdi, [rsp]",           // rip as arg0; no error code
+        "and rsp, -16",
+        "call {rust}",
+        "1: hlt; jmp 1b",
+        rust = sym mc_rust
+    );
+}
+
+extern "C" fn mc_rust(rip: u64) {
+    irq_stats::count(MC_VECTOR);
+    emergency!(
+        "#MC machine check at {}",
+        Symbolized(VirtualAddress::new(rip))
+    );
+}
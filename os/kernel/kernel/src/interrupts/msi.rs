@@ -0,0 +1,102 @@
+use crate::apic;
+use crate::gdt::KERNEL_CS_SEL;
+use crate::interrupts::{GateType, Idt, InterruptFrame};
+use crate::irq_thread::{MAX_THREADED_IRQS, ThreadedIrq, TooManyIrqs};
+use crate::{irq_stats, preempt, signal};
+use kernel_sync::{IrqGuard, SpinMutex};
+
+/// Vector all message signaled device interrupts are delivered on; see
+/// [`msix`](crate::pci::msix).
+///
+/// Devices share it: the handler runs the top half of every
+/// [`register`]ed interrupt, and each returns [`IrqReturn::None`](crate::irq_thread::IrqReturn::None) unless its
+/// device has work. The lowest priority class keeps it behind the timer and
+/// IPIs.
+pub const MSI_VECTOR: u8 = 0x50;
+
+const _: () = assert!(MSI_VECTOR >> 4 == 0x5);
+
+/// Interrupts whose top halves run on the [`MSI_VECTOR`].
+static HANDLERS: SpinMutex<[Option<&'static ThreadedIrq>; MAX_THREADED_IRQS]> =
+    SpinMutex::new([None; MAX_THREADED_IRQS]);
+
+/// Run the top half of `irq` on every [`MSI_VECTOR`] interrupt; returns the
+/// vector to program into the device. Registering it again does nothing.
+pub fn register(irq: &'static ThreadedIrq) -> Result<u8, TooManyIrqs> {
+    let _irq = IrqGuard::new();
+    let mut handlers = HANDLERS.lock();
+    if !handlers
+        .iter()
+        .flatten()
+        .any(|&registered| core::ptr::eq(registered, irq))
+    {
+        let slot = handlers
+            .iter()
+            .position(Option::is_none)
+            .ok_or(TooManyIrqs)?;
+        handlers[slot] = Some(irq);
+    }
+    Ok(MSI_VECTOR)
+}
+
+pub trait MsiInterrupt {
+    /// Install the handler of the [`MSI_VECTOR`].
+    fn init_msi_gate(&mut self) -> &mut Self;
+}
+
+impl MsiInterrupt for Idt {
+    fn init_msi_gate(&mut self) -> &mut Self {
+        self[usize::from(MSI_VECTOR)]
+            .set_handler(msi_handler)
+            .selector(KERNEL_CS_SEL)
+            .present(true)
+            .kernel_only()
+            .gate_type(GateType::InterruptGate);
+        self
+    }
+}
+
+/// Saves all GPRs like the timer handler, as the Rust part may switch to
+/// another process.
+#[unsafe(naked)]
+extern "C" fn msi_handler() {
+    core::arch::naked_asm!(
+        "cld",
+        "push rax","push rbx","push rcx","push rdx","push rsi","push rdi","push rbp",
+        "push r8","push r9","push r10","push r11","push r12","push r13","push r14","push r15",
+
+        // rdi := saved registers and interrupt frame; RBX keeps the
+        // unaligned stack pointer across a switch.
+        "mov rdi, rsp",
+        "mov rbx, rsp",
+        "and rsp, -16",
+        "call {rust_handler}",
+        "mov rsp, rbx",
+
+        "pop r15","pop r14","pop r13","pop r12","pop r11","pop r10","pop r9","pop r8",
+        "pop rbp","pop rdi","pop rsi","pop rdx","pop rcx","pop rbx","pop rax",
+        "iretq",
+
+        rust_handler = sym msi_handler_rust,
+    )
+}
+
+/// Run the top halves, then, if user mode was interrupted, let a woken
+/// bottom half run right away.
+extern "C" fn msi_handler_rust(frame: &mut InterruptFrame) {
+    irq_stats::count(usize::from(MSI_VECTOR));
+
+    // Handlers take the lowest free slot, so the first `None` ends the list.
+    for index in 0..MAX_THREADED_IRQS {
+        let Some(irq) = HANDLERS.lock().get(index).copied().flatten() else {
+            break;
+        };
+        irq.handle();
+    }
+    unsafe { apic::eoi_x2apic() };
+
+    if frame.is_from_user() {
+        preempt::preempt_point();
+        signal::deliver(frame);
+    }
+}
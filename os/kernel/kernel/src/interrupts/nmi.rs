@@ -0,0 +1,91 @@
+//! Non-maskable interrupt (vector 2).
+//!
+//! NMIs arrive from the LAPIC when a [profiler](crate::profiler) counter
+//! overflows, and from the platform on hardware errors. They can interrupt
+//! any code, including other interrupt handlers and the instructions between
+//! a `swapgs` and the matching return to user mode, so the handler
+//!
+//! * runs on its own IST stack ([`NMI_MCE_IST`](crate::per_cpu::ist_stacks::NMI_MCE_IST)), shared only with machine
+//!   checks, and
+//! * decides whether to `swapgs` by looking at `IA32_GS_BASE` itself instead
+//!   of trusting the interrupted `CS`.
+//!
+//! Every NMI also runs the [watchdog](crate::watchdog)'s hard lockup check.
+
+use crate::gdt::KERNEL_CS_SEL;
+use crate::interrupts::{GateType, Idt, InterruptFrame, Ist};
+use crate::klog::emergency;
+use crate::ksyms::Symbolized;
+use crate::tracepoint::trace_event;
+use crate::{irq_stats, profiler, watchdog};
+use kernel_memory_addresses::VirtualAddress;
+
+pub const NMI_VECTOR: usize = 0x02;
+
+pub trait NmiInterrupt {
+    fn init_nmi_gate_ist(&mut self, handler: extern "C" fn(), ist: Ist) -> &mut Self;
+}
+
+impl NmiInterrupt for Idt {
+    fn init_nmi_gate_ist(&mut self, handler: extern "C" fn(), ist: Ist) -> &mut Self {
+        self[NMI_VECTOR]
+            .set_handler(handler)
+            .selector(KERNEL_CS_SEL)
+            .present(true)
+            .kernel_only()
+            .ist(ist)
+            .gate_type(GateType::InterruptGate);
+        self
+    }
+}
+
+#[unsafe(naked)]
+pub extern "C" fn nmi_handler() {
+    core::arch::naked_asm!(
+        "cld",
+        "push rax","push rbx","push rcx","push rdx","push rsi","push rdi","push rbp",
+        "push r8","push r9","push r10","push r11","push r12","push r13","push r14","push r15",
+
+        // rdi := saved registers and interrupt frame (first arg)
+        "mov rdi, rsp",
+
+        // Kernel GS bases live in the upper half; swap if the current one does not.
+        // r12 (callee-saved) remembers whether to swap back.
+        "mov ecx, 0xC0000101",   // IA32_GS_BASE
+        "rdmsr",
+        "xor r12d, r12d",
+        "test edx, edx",
+        "js 1f",
+        "swapgs",
+        "mov r12d, 1",
+        "1:",
+
+        // The CPU aligned the IST stack before pushing 5 words; 15 pushes
+        // later RSP is 16-byte aligned again.
+        "call {rust_handler}",
+
+        "test r12d, r12d",
+        "jz 2f",
+        "swapgs",
+        "2:",
+
+        "pop r15","pop r14","pop r13","pop r12","pop r11","pop r10","pop r9","pop r8",
+        "pop rbp","pop rdi","pop rsi","pop rdx","pop rcx","pop rbx","pop rax",
+        "iretq",
+
+        rust_handler = sym nmi_handler_rust,
+    )
+}
+
+extern "C" fn nmi_handler_rust(frame: &InterruptFrame) {
+    irq_stats::count(NMI_VECTOR);
+    let rip = VirtualAddress::new(frame.rip);
+    trace_event!(irq_nmi, rip);
+    let from_profiler = profiler::on_nmi(rip, frame.is_from_user());
+
+    watchdog::check_all();
+
+    if !from_profiler {
+        emergency!("Unexpected NMI at {}", Symbolized(rip));
+    }
+}
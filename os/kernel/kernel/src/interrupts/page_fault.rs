@@ -1,13 +1,15 @@
-use crate::alloc;
 use crate::gdt::KERNEL_CS_SEL;
-use crate::interrupts::{GateType, Idt, Ist};
+use crate::interrupts::{ExceptionFrame, GateType, Idt, Ist};
+use crate::klog::emergency;
 use crate::tracing::log_ctrl_bits;
+use crate::{alloc, extable, irq_stats, kimage, ksyms, process, sched, signal};
 use bitfield_struct::bitfield;
 use core::arch::naked_asm;
-use core::hint::spin_loop;
 use kernel_alloc::phys_mapper::HhdmPhysMapper;
+use kernel_info::memory::LAST_USERSPACE_ADDRESS;
 use kernel_memory_addresses::VirtualAddress;
-use log::{error, info};
+use log::info;
+use stdlib::syscall_abi::signal::SIGSEGV;
 
 pub const PAGE_FAULT_VECTOR: usize = 0x0E; // 14
 
@@ -28,43 +30,104 @@ impl PageFaultInterrupt for Idt {
     }
 }
 
-/// Interrupt-gate PF handler: reads CR2 and the pushed error code, then halts.
+/// Interrupt-gate PF handler.
 ///
-/// # Safety
-/// Early-bringup only. Does not attempt to resume execution.
+/// Write faults on copy-on-write user pages are resolved and the faulting
+/// instruction is retried, as are user faults below the mapped part of the
+/// [stack](process::stack_growth) and on unmapped pages of
+/// [file areas](process::file_pages). Other faults in user mode raise `SIGSEGV` for the
+/// process (see [`signal`]); in kernel mode they resume at the
+/// [exception table](extable) recovery address if expected, and are logged
+/// and halt the CPU otherwise.
 #[unsafe(naked)]
 pub extern "C" fn page_fault_handler() {
     naked_asm!(
-        "cli",
-        // Save a minimal set of caller-saved regs we’ll use (SysV: rax, rcx, rdx, rsi, rdi, r8-r11).
-        "push rax",
-        "push rdi",
-        "push rsi",
-
-        // ENTRY swapgs if from CPL3: CS at [rsp + 40]
-        "mov rax, [rsp + 40]",
+        // Save all GPRs; with the error code and the CPU frame above them,
+        // they form an `ExceptionFrame` at rsp.
+        "push rax","push rbx","push rcx","push rdx","push rsi","push rdi","push rbp",
+        "push r8","push r9","push r10","push r11","push r12","push r13","push r14","push r15",
+
+        // ENTRY swapgs if from CPL3: CS at [rsp + 136]
+        "mov rax, [rsp + 136]",
         "test al, 3",
         "jz 1f",
         "swapgs",
         "1:",
 
-        // rdi := cr2 (first arg)
-        "mov rdi, cr2",
-        // The CPU pushed an error code before entering the handler.
-        // We just pushed 3 regs → error code is now at [rsp + 3*8].
-        "mov rsi, [rsp + 24]",   // rsi := error code (second arg)
-        "call {log_pf}",         // log_page_fault(cr2, err)
-
-        // Stop here; don't try to return in early bringup.
-        "1: hlt",
-        "jmp 1b",
-        log_pf = sym log_page_fault
+        // rdi := frame (first arg), rsi := cr2 (second arg)
+        "mov rdi, rsp",
+        "mov rsi, cr2",
+
+        // Align the stack for the call; rbx is callee-saved.
+        "mov rbx, rsp",
+        "and rsp, -16",
+        "call {handle_pf}",      // handle_page_fault(frame, cr2)
+        "mov rsp, rbx",
+        "test al, al",
+        "jz 3f",
+
+        // EXIT swapgs if returning to CPL3 (the frame may have been redirected
+        // into a signal handler, but never out of user mode).
+        "mov rax, [rsp + 136]",
+        "test al, 3",
+        "jz 2f",
+        "swapgs",
+        "2:",
+
+        "pop r15","pop r14","pop r13","pop r12","pop r11","pop r10","pop r9","pop r8",
+        "pop rbp","pop rdi","pop rsi","pop rdx","pop rcx","pop rbx","pop rax",
+        "add rsp, 8",            // drop the error code
+        "iretq",
+
+        // Unresolved: the fault has been logged, stop here.
+        "3: hlt",
+        "jmp 3b",
+        handle_pf = sym handle_page_fault
     )
 }
 
+/// Try to resolve the fault, or turn it into `SIGSEGV` if it was raised in
+/// user mode, or recover from it through the exception table. Returns `false`
+/// after logging an unresolved kernel fault.
 #[unsafe(no_mangle)]
-extern "C" fn log_page_fault(cr2: VirtualAddress, err: PageFaultError) {
-    error!(
+extern "C" fn handle_page_fault(frame: &mut ExceptionFrame, cr2: VirtualAddress) -> bool {
+    irq_stats::count(PAGE_FAULT_VECTOR);
+    let err = PageFaultError::from_bits(frame.error_code);
+    if err.present()
+        && err.write()
+        && cr2 <= LAST_USERSPACE_ADDRESS
+        && alloc::resolve_cow_fault(cr2)
+    {
+        return true;
+    }
+
+    if frame.is_from_user() {
+        if !err.present()
+            && cr2 <= LAST_USERSPACE_ADDRESS
+            && (process::stack_growth::handle_user_fault(cr2)
+                || process::file_pages::handle_user_fault(cr2))
+        {
+            return true;
+        }
+        info!(
+            "User page fault at {cr2} (rip={rip:#x}): {explained}",
+            rip = frame.rip,
+            explained = err.explain()
+        );
+        signal::raise_fault(frame, SIGSEGV);
+        return true;
+    }
+
+    if extable::fixup(frame, PAGE_FAULT_VECTOR) {
+        return true;
+    }
+
+    log_page_fault(cr2, err, VirtualAddress::new(frame.rip), frame.rbp);
+    false
+}
+
+fn log_page_fault(cr2: VirtualAddress, err: PageFaultError, rip: VirtualAddress, rbp: u64) {
+    emergency!(
         "page fault page fault page fault
         ⠀⠀⠀⠀⠀⠀⠀⠙⣿⣷⣄⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀
         ⠀⠀⠀⠀⠀⠀⠀⠀⢺⣿⣿⡆⠀⠀⠀⠀⠀⠀⡀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀
@@ -90,23 +153,39 @@ extern "C" fn log_page_fault(cr2: VirtualAddress, err: PageFaultError) {
         ⠠⢸⣿⣿⣿⣿⣿⠀⠀⠀⠀⠀⠀⠀⢸⣿⣿⣿⣿⣿⣿⢀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠘⣿⣿⣿⣿⣿⣿⣿⣿
         ⠀⠛⣿⣿⣿⡿⠏⠀⠀⠀⠀⠀⠀⢳⣾⣿⣿⣿⣿⣿⣿⡶⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⣿⣿⣿⣿⣿⣿⣿
         ⠀⢨⠀⠉⠉⠀⠀⠀⠀⠀⠀⠀⠀⠙⣿⣿⡿⡿⠿⠛⠙⠁⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠹⠏⠉⠻⠿⠟⠁\n\
-        PAGE FAULT: address/cr2={cr2} err={raw:#x}\n\
+        PAGE FAULT: address/cr2={cr2} err={raw:#x} rip={rip}\n\
         {explained}\n\
         {err:#?}",
         raw = err.into_bits(),
+        rip = ksyms::Symbolized(rip),
         explained = err.explain()
     );
 
-    info!("Control bits:");
+    emergency!("Control bits:");
     log_ctrl_bits();
 
-    info!("Table walk at CR2:");
-    alloc::debug::dump_walk(&HhdmPhysMapper, cr2);
+    if let Some(kind) = kimage::describe(cr2) {
+        emergency!("CR2 lies in the {kind} segment");
+    }
 
-    loop {
-        spin_loop();
+    #[cfg(feature = "poison")]
+    if let Some(frame) = alloc::poison::describe(cr2) {
+        emergency!(
+            "CR2 lies in the unmapped direct map alias of freed frame {frame} (use after free)"
+        );
     }
-    // TODO: Whenever returning, fix the swapgs in the asm handler above.
+
+    if let Some(pid) = sched::current_pid().filter(|_| cr2 <= LAST_USERSPACE_ADDRESS) {
+        match process::find_vma(pid, cr2) {
+            Some(vma) => emergency!("CR2 lies in VMA {vma} of process {pid}"),
+            None => emergency!("CR2 lies in no VMA of process {pid}"),
+        }
+    }
+
+    emergency!("Table walk at CR2:");
+    alloc::debug::dump_walk(&HhdmPhysMapper, cr2);
+
+    ksyms::log_backtrace_from(rbp);
 }
 
 /// Page-fault error code layout (x86-64).
@@ -0,0 +1,68 @@
+use crate::apic;
+use crate::gdt::KERNEL_CS_SEL;
+use crate::interrupts::{GateType, Idt, InterruptFrame};
+use crate::{irq_stats, preempt, signal};
+
+/// Inter-processor interrupt asking a CPU to reschedule; see
+/// [remote wakeups](crate::sched#remote-wakeups).
+///
+/// It shares the priority class of the timer, so a parked CPU, which only
+/// lets the [wake IPI](super::wake::WAKE_IPI_VECTOR) through, ignores it.
+pub const RESCHED_IPI_VECTOR: u8 = 0xE1;
+
+const _: () = assert!(RESCHED_IPI_VECTOR >> 4 == 0xE);
+
+pub trait ReschedInterrupt {
+    /// Install the handler of the [`RESCHED_IPI_VECTOR`].
+    fn init_resched_gate(&mut self) -> &mut Self;
+}
+
+impl ReschedInterrupt for Idt {
+    fn init_resched_gate(&mut self) -> &mut Self {
+        self[usize::from(RESCHED_IPI_VECTOR)]
+            .set_handler(resched_handler)
+            .selector(KERNEL_CS_SEL)
+            .present(true)
+            .kernel_only()
+            .gate_type(GateType::InterruptGate);
+        self
+    }
+}
+
+/// Saves all GPRs like the timer handler, as the Rust part may switch to
+/// another process.
+#[unsafe(naked)]
+extern "C" fn resched_handler() {
+    core::arch::naked_asm!(
+        "cld",
+        "push rax","push rbx","push rcx","push rdx","push rsi","push rdi","push rbp",
+        "push r8","push r9","push r10","push r11","push r12","push r13","push r14","push r15",
+
+        // rdi := saved registers and interrupt frame; RBX keeps the
+        // unaligned stack pointer across a switch.
+        "mov rdi, rsp",
+        "mov rbx, rsp",
+        "and rsp, -16",
+        "call {rust_handler}",
+        "mov rsp, rbx",
+
+        "pop r15","pop r14","pop r13","pop r12","pop r11","pop r10","pop r9","pop r8",
+        "pop rbp","pop rdi","pop rsi","pop rdx","pop rcx","pop rbx","pop rax",
+        "iretq",
+
+        rust_handler = sym resched_handler_rust,
+    )
+}
+
+/// Flag the reschedule and, if user mode was interrupted, switch right away.
+/// An idle CPU only needs its sleep ended; the idle loop schedules next.
+extern "C" fn resched_handler_rust(frame: &mut InterruptFrame) {
+    irq_stats::count(usize::from(RESCHED_IPI_VECTOR));
+    unsafe { apic::eoi_x2apic() };
+
+    preempt::request_resched();
+    if frame.is_from_user() {
+        preempt::preempt_point();
+        signal::deliver(frame);
+    }
+}
@@ -1,5 +1,6 @@
 use crate::gdt::KERNEL_CS_SEL;
 use crate::interrupts::{GateType, Idt};
+use crate::irq_stats;
 
 /// Spurious interrupt vector for APIC.
 pub const SPURIOUS_INTERRUPT_VECTOR: u8 = 0xFF;
@@ -23,6 +24,28 @@ impl SpuriousInterrupt for Idt {
     }
 }
 
-const extern "C" fn spurious_handler() {
-    // No EOI for spurious; just return
+/// Count the interrupt and return; spurious interrupts take no EOI.
+#[unsafe(naked)]
+extern "C" fn spurious_handler() {
+    core::arch::naked_asm!(
+        "cld",
+        // Save the caller-saved GPRs, and RBX for the stack pointer.
+        "push rax","push rbx","push rcx","push rdx","push rsi","push rdi",
+        "push r8","push r9","push r10","push r11",
+
+        "mov rbx, rsp",
+        "and rsp, -16",
+        "call {rust_handler}",
+        "mov rsp, rbx",
+
+        "pop r11","pop r10","pop r9","pop r8",
+        "pop rdi","pop rsi","pop rdx","pop rcx","pop rbx","pop rax",
+        "iretq",
+
+        rust_handler = sym spurious_handler_rust,
+    )
+}
+
+extern "C" fn spurious_handler_rust() {
+    irq_stats::count(usize::from(SPURIOUS_INTERRUPT_VECTOR));
 }
@@ -1,8 +1,9 @@
 use crate::gdt::KERNEL_CS_SEL;
 use crate::interrupts::{GateType, Idt};
+use crate::irq_stats;
+use crate::klog::emergency;
 use core::arch::naked_asm;
 use core::hint::spin_loop;
-use log::error;
 
 pub const SS_FAULT_VECTOR: usize = 0x0C; // 12
 
@@ -36,7 +37,8 @@ pub extern "C" fn ss_fault_handler() {
 
 #[unsafe(no_mangle)]
 extern "C" fn log_ss_fault(err: u64) {
-    error!(
+    irq_stats::count(SS_FAULT_VECTOR);
+    emergency!(
         "segment fault segment fault segment fault
        ⠀⠀ ⠀⠀⠀⠀⠀⠙⣿⣷⣄⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀
         ⠀⠀⠀⠀⠀⠀⠀⠀⢺⣿⣿⡆⠀⠀⠀⠀⠀⠀⡀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀
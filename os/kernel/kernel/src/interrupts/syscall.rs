@@ -42,6 +42,7 @@
 
 use crate::gdt::KERNEL_CS_SEL;
 use crate::interrupts::{GateType, Idt};
+use crate::irq_stats;
 use crate::syscall::{SyscallSource, syscall};
 use kernel_registers::rflags::Rflags;
 
@@ -178,6 +179,8 @@ extern "C" fn syscall_int80_handler() {
 /// - Must not assume interrupts are enabled; they are not.
 #[allow(clippy::no_effect_underscore_binding)]
 extern "C" fn syscall_int80_rust(tf: &mut TrapFrame) {
+    irq_stats::count(SYSCALL_VECTOR);
+
     let sysno = tf.rax;
     let a0 = tf.rdi;
     let a1 = tf.rsi;
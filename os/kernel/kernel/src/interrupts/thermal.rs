@@ -0,0 +1,178 @@
+//! Thermal sensor interrupt.
+//!
+//! On CPUs with the thermal monitor MSRs (CPUID.01H:EDX bit 22), [`enable`]
+//! has the sensor interrupt when the core gets too hot or critically hot,
+//! or when PROCHOT# is asserted, and routes it through the local APIC's
+//! thermal entry. The handler logs the [`ThermStatus`] and clears its log
+//! bits, so the next event interrupts again.
+//!
+//! Hypervisors rarely emulate the sensor; without it, nothing is enabled.
+
+use crate::apic;
+use crate::apic::lvt::{Lvt, LvtEntry};
+use crate::cpuid::{CpuidRanges, Leaf01h};
+use crate::gdt::KERNEL_CS_SEL;
+use crate::interrupts::{GateType, Idt};
+use crate::irq_stats;
+use bitfield_struct::bitfield;
+use core::fmt;
+use kernel_registers::msr::{Msr, try_rdmsr, try_wrmsr};
+use log::{info, warn};
+
+/// Vector of the thermal sensor interrupt.
+pub const THERMAL_VECTOR: u8 = 0xFD;
+
+const IA32_THERM_INTERRUPT: Msr = Msr(0x19B);
+const IA32_THERM_STATUS: Msr = Msr(0x19C);
+
+/// `IA32_THERM_INTERRUPT` bits enabling the high temperature, PROCHOT# and
+/// critical temperature interrupts.
+const INTERRUPT_ENABLE: u64 = (1 << 0) | (1 << 2) | (1 << 4);
+
+/// The sticky log bits of [`ThermStatus`]; writing 0 clears them.
+const LOG_BITS: u64 = 0b1010_1010_1010;
+
+/// `IA32_THERM_STATUS`: the thermal conditions of the core, and for each
+/// whether it occurred since its log bit was last cleared.
+#[bitfield(u64)]
+pub struct ThermStatus {
+    /// The core is at or above its high temperature.
+    pub thermal: bool,
+    pub thermal_log: bool,
+    /// PROCHOT# is asserted.
+    pub prochot: bool,
+    pub prochot_log: bool,
+    /// The core is at or above its critical temperature.
+    pub critical: bool,
+    pub critical_log: bool,
+    pub threshold1: bool,
+    pub threshold1_log: bool,
+    pub threshold2: bool,
+    pub threshold2_log: bool,
+    /// The core runs below the requested frequency to stay in power limits.
+    pub power_limit: bool,
+    pub power_limit_log: bool,
+    #[bits(4)]
+    __: u8,
+    /// Degrees Celsius below the core's maximum temperature, if
+    /// [`reading_valid`](Self::reading_valid).
+    #[bits(7)]
+    pub readout: u8,
+    #[bits(4)]
+    __: u8,
+    /// Accuracy of the readout in degrees Celsius.
+    #[bits(4)]
+    pub resolution: u8,
+    pub reading_valid: bool,
+    __: u32,
+}
+
+impl fmt::Display for ThermStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let conditions = [
+            (self.thermal(), self.thermal_log(), "high temperature"),
+            (self.prochot(), self.prochot_log(), "PROCHOT#"),
+            (self.critical(), self.critical_log(), "critical temperature"),
+            (self.threshold1(), self.threshold1_log(), "threshold 1"),
+            (self.threshold2(), self.threshold2_log(), "threshold 2"),
+            (self.power_limit(), self.power_limit_log(), "power limit"),
+        ];
+        let mut any = false;
+        for (now, seen, name) in conditions {
+            if !now && !seen {
+                continue;
+            }
+            if any {
+                f.write_str(", ")?;
+            }
+            f.write_str(name)?;
+            if !now {
+                f.write_str(" (over)")?;
+            }
+            any = true;
+        }
+        if !any {
+            f.write_str("no condition")?;
+        }
+        if self.reading_valid() {
+            write!(f, "; {} degrees below the maximum", self.readout())?;
+        }
+        Ok(())
+    }
+}
+
+pub trait ThermalInterrupt {
+    /// Install the handler of the [`THERMAL_VECTOR`].
+    fn init_thermal_gate(&mut self) -> &mut Self;
+}
+
+impl ThermalInterrupt for Idt {
+    fn init_thermal_gate(&mut self) -> &mut Self {
+        self[usize::from(THERMAL_VECTOR)]
+            .set_handler(thermal_handler)
+            .selector(KERNEL_CS_SEL)
+            .present(true)
+            .kernel_only()
+            .gate_type(GateType::InterruptGate);
+        self
+    }
+}
+
+/// Enable the thermal sensor interrupt of the current CPU, if it has one.
+pub fn enable() {
+    let ranges = unsafe { CpuidRanges::read() };
+    let monitor = unsafe { Leaf01h::read(&ranges) }.is_some_and(|l| l.has_thermal_monitor());
+    if !monitor || !Lvt::Thermal.exists() {
+        info!("No thermal sensor interrupt on this CPU");
+        return;
+    }
+
+    // SAFETY: The thermal monitor has these MSRs, and the `#GP` handler
+    // recovers through the exception table if it does not after all.
+    let enabled = unsafe {
+        try_rdmsr(IA32_THERM_INTERRUPT)
+            .and_then(|bits| try_wrmsr(IA32_THERM_INTERRUPT, bits | INTERRUPT_ENABLE))
+            .and_then(|()| try_wrmsr(IA32_THERM_STATUS, 0))
+    };
+    if let Err(e) = enabled {
+        warn!("Not enabling thermal interrupts: {e}");
+        return;
+    }
+    // SAFETY: The entry exists, and the gate is installed.
+    unsafe { Lvt::Thermal.write(LvtEntry::new().with_vector(THERMAL_VECTOR)) };
+    info!("Thermal sensor interrupt enabled");
+}
+
+#[unsafe(naked)]
+extern "C" fn thermal_handler() {
+    core::arch::naked_asm!(
+        "cld",
+        // Save the caller-saved GPRs, and RBX for the stack pointer.
+        "push rax","push rbx","push rcx","push rdx","push rsi","push rdi",
+        "push r8","push r9","push r10","push r11",
+
+        "mov rbx, rsp",
+        "and rsp, -16",
+        "call {rust_handler}",
+        "mov rsp, rbx",
+
+        "pop r11","pop r10","pop r9","pop r8",
+        "pop rdi","pop rsi","pop rdx","pop rcx","pop rbx","pop rax",
+        "iretq",
+
+        rust_handler = sym thermal_handler_rust,
+    )
+}
+
+extern "C" fn thermal_handler_rust() {
+    irq_stats::count(usize::from(THERMAL_VECTOR));
+    // SAFETY: `enable` checked that the MSR exists.
+    if let Ok(bits) = unsafe { try_rdmsr(IA32_THERM_STATUS) } {
+        let status = ThermStatus::from_bits(bits);
+        warn!("Thermal event on APIC {:#x}: {status}", apic::x2apic_id());
+        // Writing 0 clears the log bits seen; writing 1 leaves the others.
+        // SAFETY: As above.
+        let _ = unsafe { try_wrmsr(IA32_THERM_STATUS, LOG_BITS & !bits) };
+    }
+    unsafe { apic::eoi_x2apic() };
+}
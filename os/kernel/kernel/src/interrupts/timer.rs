@@ -2,8 +2,12 @@
 
 use crate::apic;
 use crate::gdt::KERNEL_CS_SEL;
-use crate::interrupts::{GateType, Idt};
+use crate::interrupts::{GateType, Idt, InterruptFrame};
 use crate::per_cpu::PerCpu;
+use crate::tracepoint::trace_event;
+use crate::{ahci, irq_stats, kdb, keyboard, nvme, rtc, virtio};
+use crate::{preempt, profiler, signal, timer, watchdog, workqueue, xhci};
+use kernel_memory_addresses::VirtualAddress;
 
 pub const LAPIC_TIMER_VECTOR: u8 = 0xE0; // 224
 
@@ -37,28 +41,20 @@ pub extern "C" fn lapic_timer_handler() {
         "push rax","push rbx","push rcx","push rdx","push rsi","push rdi","push rbp",
         "push r8","push r9","push r10","push r11","push r12","push r13","push r14","push r15",
 
-        // Ensure SysV stack alignment for the CALL.
-        // SysV requires RSP % 16 == 8 BEFORE `call` so that inside the callee it's 16-aligned.
-        // We don't know the pre-interrupt alignment, so compute and fix it.
-        "mov r11, rsp",
-        "and r11, 15",
-        "cmp r11, 8",
-        "je 2f",              // already correct parity → no adjust
-        "sub rsp, 8",         // make it so
-        "mov r11, 1",         // remember we adjusted
-        "jmp 3f",
-        "2:",
-        "xor r11, r11",       // no adjust
-        "3:",
+        // rdi := saved registers and interrupt frame (first arg)
+        "mov rdi, rsp",
+
+        // Ensure SysV stack alignment for the CALL. We don't know the
+        // pre-interrupt alignment, so align down and remember the original
+        // stack pointer in callee-saved RBX: the handler may switch to
+        // another process and clobber any caller-saved register.
+        "mov rbx, rsp",
+        "and rsp, -16",
 
         // Call the Rust handler (does EOI/masking etc.)
         "call {rust_handler}",
 
-        // Undo temporary alignment if we subtracted 8
-        "test r11, r11",
-        "jz 4f",
-        "add rsp, 8",
-        "4:",
+        "mov rsp, rbx",
 
         // Restore GPRs and return from interrupt
         "pop r15","pop r14","pop r13","pop r12","pop r11","pop r10","pop r9","pop r8",
@@ -69,12 +65,36 @@ pub extern "C" fn lapic_timer_handler() {
     )
 }
 
-extern "C" fn lapic_timer_handler_rust() {
+extern "C" fn lapic_timer_handler_rust(frame: &mut InterruptFrame) {
+    irq_stats::count(usize::from(LAPIC_TIMER_VECTOR));
+
     // EOI first to reduce chance of nesting storms
     unsafe {
         apic::eoi_x2apic();
     }
+    rtc::acknowledge_tick();
 
     let p = unsafe { PerCpu::current() };
-    p.ticks.fetch_add(1, core::sync::atomic::Ordering::Relaxed);
+    let tick = p.ticks.fetch_add(1, core::sync::atomic::Ordering::Relaxed) + 1;
+
+    let rip = VirtualAddress::new(frame.rip);
+    trace_event!(irq_timer, tick, rip);
+    watchdog::on_tick(rip, frame.rbp, frame.is_from_user());
+    profiler::on_tick(p, rip, frame.is_from_user());
+
+    keyboard::poll();
+    virtio::console::poll();
+    nvme::poll();
+    ahci::poll();
+    xhci::poll();
+    kdb::poll();
+    timer::on_tick(tick);
+    workqueue::wake_worker();
+
+    // May switch to another process; this one resumes here later.
+    preempt::on_tick(frame.is_from_user());
+
+    if frame.is_from_user() {
+        signal::deliver(frame);
+    }
 }
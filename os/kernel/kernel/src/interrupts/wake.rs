@@ -0,0 +1,57 @@
+use crate::apic;
+use crate::gdt::KERNEL_CS_SEL;
+use crate::interrupts::{GateType, Idt};
+use crate::irq_stats;
+
+/// Inter-processor interrupt that wakes a parked CPU; see
+/// [`hotplug`](crate::hotplug).
+///
+/// It is in the highest priority class, so it still arrives while a parked
+/// CPU holds back everything else with its task priority.
+pub const WAKE_IPI_VECTOR: u8 = 0xF0;
+
+const _: () = assert!(WAKE_IPI_VECTOR >> 4 == 0xF);
+
+pub trait WakeInterrupt {
+    /// Install the handler of the [`WAKE_IPI_VECTOR`].
+    fn init_wake_gate(&mut self) -> &mut Self;
+}
+
+impl WakeInterrupt for Idt {
+    fn init_wake_gate(&mut self) -> &mut Self {
+        self[usize::from(WAKE_IPI_VECTOR)]
+            .set_handler(wake_handler)
+            .selector(KERNEL_CS_SEL)
+            .present(true)
+            .kernel_only()
+            .gate_type(GateType::InterruptGate);
+        self
+    }
+}
+
+/// The IPI only has to end the `hlt`; count and acknowledge it and return.
+#[unsafe(naked)]
+extern "C" fn wake_handler() {
+    core::arch::naked_asm!(
+        "cld",
+        // Save the caller-saved GPRs, and RBX for the stack pointer.
+        "push rax","push rbx","push rcx","push rdx","push rsi","push rdi",
+        "push r8","push r9","push r10","push r11",
+
+        "mov rbx, rsp",
+        "and rsp, -16",
+        "call {rust_handler}",
+        "mov rsp, rbx",
+
+        "pop r11","pop r10","pop r9","pop r8",
+        "pop rdi","pop rsi","pop rdx","pop rcx","pop rbx","pop rax",
+        "iretq",
+
+        rust_handler = sym wake_handler_rust,
+    )
+}
+
+extern "C" fn wake_handler_rust() {
+    irq_stats::count(usize::from(WAKE_IPI_VECTOR));
+    unsafe { apic::eoi_x2apic() };
+}
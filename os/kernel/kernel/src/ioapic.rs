@@ -0,0 +1,174 @@
+//! # I/O APIC
+//!
+//! Routes the interrupts of chipset devices, such as the [RTC](crate::rtc),
+//! to local APIC vectors. [`init`] finds the first I/O APIC in the
+//! [MADT](crate::acpi::madt) and masks all of its inputs, as well as the
+//! legacy 8259 PICs, which would otherwise deliver the same IRQs a second
+//! time. [`route_isa_irq`] then unmasks a single input.
+//!
+//! ISA IRQs arrive at the global system interrupt (GSI) of the same number,
+//! edge-triggered and active high, unless the MADT has an interrupt source
+//! override for them.
+//!
+//! ## Limitations
+//!
+//! * Only the first I/O APIC is used; QEMU has one, covering GSIs 0 to 23.
+//! * Destinations are physical APIC IDs up to 255; there is no interrupt
+//!   remapping.
+
+use crate::acpi;
+use crate::alloc::mmio::{Mmio, map_mmio};
+use core::fmt;
+use kernel_acpi::madt::{Polarity, Trigger};
+use kernel_memory_addresses::PhysicalAddress;
+use kernel_ports::PortWriteOnly;
+use kernel_sync::{IrqGuard, SpinMutex};
+use log::{info, warn};
+
+/// Offset of the register select register.
+const IOREGSEL: u64 = 0x00;
+
+/// Offset of the data window onto the selected register.
+const IOWIN: u64 = 0x10;
+
+/// Bytes of the register window.
+const REGISTERS_LEN: u64 = 0x20;
+
+/// Register: version, and the number of redirection entries.
+const REG_VERSION: u32 = 0x01;
+
+/// Register: low half of the first redirection entry; each takes two.
+const REG_REDIRECTION: u32 = 0x10;
+
+/// Redirection entry bit: the input is masked.
+const MASKED: u32 = 1 << 16;
+
+/// Redirection entry bit: level-triggered rather than edge-triggered.
+const LEVEL_TRIGGERED: u32 = 1 << 15;
+
+/// Redirection entry bit: active low rather than active high.
+const ACTIVE_LOW: u32 = 1 << 13;
+
+/// Interrupt mask registers of the master and slave 8259 PIC.
+const PIC_MASKS: [PortWriteOnly<u8>; 2] = [PortWriteOnly::new(0x21), PortWriteOnly::new(0xA1)];
+
+static IO_APIC: SpinMutex<Option<IoApic>> = SpinMutex::new(None);
+
+struct IoApic {
+    registers: Mmio,
+    /// GSI of the first input.
+    gsi_base: u32,
+    /// Number of inputs.
+    inputs: u32,
+}
+
+/// Why an IRQ could not be routed.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum IoApicError {
+    /// [`init`] found no I/O APIC.
+    NotFound,
+    /// The I/O APIC has no input for the GSI.
+    NoInput(u32),
+    /// The APIC ID does not fit a physical destination.
+    Destination(u32),
+}
+
+impl fmt::Display for IoApicError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound => f.write_str("no I/O APIC"),
+            Self::NoInput(gsi) => write!(f, "no I/O APIC input for GSI {gsi}"),
+            Self::Destination(apic_id) => {
+                write!(f, "APIC ID {apic_id} is not addressable from the I/O APIC")
+            }
+        }
+    }
+}
+
+impl IoApic {
+    fn read(&self, register: u32) -> u32 {
+        self.registers.write32(IOREGSEL, register);
+        self.registers.read32(IOWIN)
+    }
+
+    fn write(&self, register: u32, value: u32) {
+        self.registers.write32(IOREGSEL, register);
+        self.registers.write32(IOWIN, value);
+    }
+
+    /// Program the redirection entry of `input`; the low half, which holds
+    /// the mask bit, goes last.
+    fn set_entry(&self, input: u32, low: u32, high: u32) {
+        self.write(REG_REDIRECTION + 2 * input + 1, high);
+        self.write(REG_REDIRECTION + 2 * input, low);
+    }
+}
+
+/// Mask the 8259 PICs, and find and mask the I/O APIC.
+pub fn init() {
+    for mask in PIC_MASKS {
+        // Safety: the PICs are always decoded; nothing else programs them.
+        unsafe { mask.write(0xFF) };
+    }
+
+    let Some(entry) = acpi::madt().and_then(|madt| madt.io_apics().next()) else {
+        warn!("No I/O APIC in the MADT; device IRQs are not routed");
+        return;
+    };
+    let registers = match map_mmio(
+        PhysicalAddress::new(u64::from(entry.address)),
+        REGISTERS_LEN,
+    ) {
+        Ok(registers) => registers,
+        Err(e) => {
+            warn!("Failed to map the I/O APIC at {:#x}: {e}", entry.address);
+            return;
+        }
+    };
+
+    let mut io_apic = IoApic {
+        registers,
+        gsi_base: entry.gsi_base,
+        inputs: 0,
+    };
+    io_apic.inputs = ((io_apic.read(REG_VERSION) >> 16) & 0xFF) + 1;
+    for input in 0..io_apic.inputs {
+        io_apic.set_entry(input, MASKED, 0);
+    }
+    info!(
+        "I/O APIC {} at {:#x}: GSIs {}..{}",
+        entry.id,
+        entry.address,
+        io_apic.gsi_base,
+        io_apic.gsi_base + io_apic.inputs
+    );
+
+    let _irq = IrqGuard::new();
+    *IO_APIC.lock() = Some(io_apic);
+}
+
+/// Deliver ISA IRQ `irq` as `vector` to the CPU with `apic_id`; returns the
+/// GSI it arrives at.
+pub fn route_isa_irq(irq: u8, vector: u8, apic_id: u32) -> Result<u32, IoApicError> {
+    let over = acpi::madt().and_then(|madt| madt.interrupt_override(irq));
+    let gsi = over.map_or_else(|| u32::from(irq), |over| over.gsi);
+    let destination = u8::try_from(apic_id).map_err(|_| IoApicError::Destination(apic_id))?;
+
+    let mut low = u32::from(vector);
+    if over.is_some_and(|over| over.polarity == Polarity::ActiveLow) {
+        low |= ACTIVE_LOW;
+    }
+    if over.is_some_and(|over| over.trigger == Trigger::Level) {
+        low |= LEVEL_TRIGGERED;
+    }
+
+    let _irq = IrqGuard::new();
+    let io_apic = IO_APIC.lock();
+    let io_apic = io_apic.as_ref().ok_or(IoApicError::NotFound)?;
+    let input = gsi
+        .checked_sub(io_apic.gsi_base)
+        .filter(|&input| input < io_apic.inputs)
+        .ok_or(IoApicError::NoInput(gsi))?;
+    io_apic.set_entry(input, low, u32::from(destination) << 24);
+    Ok(gsi)
+}
@@ -0,0 +1,145 @@
+//! # Interrupt Statistics
+//!
+//! Counts every interrupt and exception per CPU and vector, to spot
+//! interrupt storms, missing EOIs and unexpected vectors.
+//!
+//! ## Counting
+//!
+//! Each CPU's [`CpuIrqStats`] holds one counter per IDT vector. Every
+//! [handler](crate::interrupts) calls [`count`] with its vector on entry,
+//! before it does anything else, so faults that never return are counted
+//! too. Counting is a single relaxed atomic add on the current CPU's
+//! counters; it neither locks nor allocates and is safe in NMI context.
+//!
+//! ## Reading
+//!
+//! * [`CpuIrqStats::snapshot`] copies the counters of one CPU.
+//! * [`reset`] zeroes the counters of all CPUs.
+//! * [`log_report`] logs the non-zero counters of all CPUs. Pressing F11
+//!   runs it from the [keyboard](crate::keyboard) handler.
+//! * `/proc/interrupts` lists them by vector; see [`procfs`](crate::procfs).
+//!
+//! Counters keep running while they are read or reset, so a snapshot of
+//! several CPUs is not taken at a single point in time.
+
+use crate::interrupts::apic_error::APIC_ERROR_VECTOR;
+use crate::interrupts::bp::BP_VECTOR;
+use crate::interrupts::df::DF_VECTOR;
+use crate::interrupts::gp::GP_FAULT_VECTOR;
+use crate::interrupts::mc::MC_VECTOR;
+use crate::interrupts::msi::MSI_VECTOR;
+use crate::interrupts::nmi::NMI_VECTOR;
+use crate::interrupts::page_fault::PAGE_FAULT_VECTOR;
+use crate::interrupts::resched::RESCHED_IPI_VECTOR;
+use crate::interrupts::spurious::SPURIOUS_INTERRUPT_VECTOR;
+use crate::interrupts::ss::SS_FAULT_VECTOR;
+use crate::interrupts::syscall::SYSCALL_VECTOR;
+use crate::interrupts::thermal::THERMAL_VECTOR;
+use crate::interrupts::timer::LAPIC_TIMER_VECTOR;
+use crate::interrupts::wake::WAKE_IPI_VECTOR;
+use crate::per_cpu::{self, PerCpu};
+use core::sync::atomic::{AtomicU64, Ordering};
+use log::info;
+
+/// Number of IDT vectors.
+pub const VECTORS: usize = 256;
+
+/// Interrupt counters of one CPU, embedded in [`PerCpu`].
+pub struct CpuIrqStats {
+    counts: [AtomicU64; VECTORS],
+}
+
+impl CpuIrqStats {
+    pub const fn new() -> Self {
+        Self {
+            counts: [const { AtomicU64::new(0) }; VECTORS],
+        }
+    }
+
+    /// Interrupts seen on `vector`.
+    pub fn get(&self, vector: usize) -> u64 {
+        self.counts[vector].load(Ordering::Relaxed)
+    }
+
+    /// The counters of all vectors, indexed by vector.
+    pub fn snapshot(&self) -> [u64; VECTORS] {
+        core::array::from_fn(|vector| self.counts[vector].load(Ordering::Relaxed))
+    }
+
+    /// Zero all counters.
+    pub fn reset(&self) {
+        for count in &self.counts {
+            count.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Default for CpuIrqStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Count an interrupt on `vector` for the current CPU.
+///
+/// Called first thing by every interrupt and exception handler.
+#[inline]
+pub fn count(vector: usize) {
+    let cpu = unsafe { PerCpu::current() };
+    cpu.irq_stats.counts[vector].fetch_add(1, Ordering::Relaxed);
+}
+
+/// Zero the counters of all CPUs.
+#[allow(dead_code)]
+pub fn reset() {
+    for cpu in per_cpu::cpus() {
+        cpu.irq_stats.reset();
+    }
+}
+
+/// Whether any CPU saw an interrupt on `vector`.
+pub fn seen(vector: usize) -> bool {
+    per_cpu::cpus().any(|cpu| cpu.irq_stats.get(vector) != 0)
+}
+
+/// Vectors the kernel installs handlers for, and what raises them.
+const NAMES: [(usize, &str); 15] = [
+    (NMI_VECTOR, "Non-maskable interrupt"),
+    (BP_VECTOR, "Breakpoint"),
+    (DF_VECTOR, "Double fault"),
+    (SS_FAULT_VECTOR, "Stack-segment fault"),
+    (GP_FAULT_VECTOR, "General protection fault"),
+    (PAGE_FAULT_VECTOR, "Page fault"),
+    (MC_VECTOR, "Machine check"),
+    (SYSCALL_VECTOR, "System call (int 0x80)"),
+    (MSI_VECTOR as usize, "Device interrupt (MSI)"),
+    (LAPIC_TIMER_VECTOR as usize, "Local APIC timer"),
+    (RESCHED_IPI_VECTOR as usize, "Reschedule IPI"),
+    (WAKE_IPI_VECTOR as usize, "Wake IPI"),
+    (THERMAL_VECTOR as usize, "Thermal sensor"),
+    (APIC_ERROR_VECTOR as usize, "Local APIC error"),
+    (SPURIOUS_INTERRUPT_VECTOR as usize, "Spurious interrupt"),
+];
+
+/// What raises `vector`, or `?` if the kernel installs no handler for it.
+pub fn vector_name(vector: usize) -> &'static str {
+    NAMES
+        .iter()
+        .find(|(v, _)| *v == vector)
+        .map_or("?", |(_, name)| name)
+}
+
+/// Log the non-zero counters of all CPUs.
+pub fn log_report() {
+    for cpu in per_cpu::cpus() {
+        let stats = cpu.irq_stats.snapshot();
+        info!(
+            "CPU {}: {} interrupts",
+            cpu.cpu_id,
+            stats.iter().sum::<u64>()
+        );
+        for (vector, &n) in stats.iter().enumerate().filter(|(_, n)| **n != 0) {
+            info!("  {vector:#04x} {n:>10}  {}", vector_name(vector));
+        }
+    }
+}
@@ -0,0 +1,201 @@
+//! # Threaded Interrupts
+//!
+//! A device interrupt is split in two halves, so that as little as possible
+//! runs with interrupts disabled:
+//!
+//! * The **top half** runs where the interrupt is taken. It acknowledges
+//!   the device and captures what can not wait, e.g. bytes the device would
+//!   overwrite, and says whether there is more to do by returning
+//!   [`IrqReturn::WakeThread`].
+//! * The **bottom half** runs in a kernel thread of its own, at
+//!   [`Priority::MAX`], which sleeps on a [`WaitQueue`] until the top half
+//!   wakes it. It runs with interrupts enabled and may take locks, log and
+//!   wake other processes. Like all kernel code, it is only switched away
+//!   from when it sleeps or leaves a [preemption-disabled](crate::preempt)
+//!   section with a switch due, so it should not run for long.
+//!
+//! A driver declares both halves as a static [`ThreadedIrq`], calls
+//! [`ThreadedIrq::handle`] from its interrupt path and [`register`]s it once
+//! the device is up. [`start_all`] starts the threads when the scheduler is
+//! about to run; until then, wake-ups are remembered and the bottom half
+//! runs right after its thread starts.
+//!
+//! Wake-ups coalesce: however often the top half asks before the thread
+//! gets to run, the bottom half runs once, and must do all the work there
+//! is.
+//!
+//! ## Limitations
+//!
+//! * Only message signaled interrupts are routed, to a
+//!   [shared vector](crate::interrupts::msi). The top halves of the
+//!   [keyboard](crate::keyboard), the [virtio console](crate::virtio::console)
+//!   and the [USB keyboard](crate::xhci) are only polled, from the LAPIC
+//!   timer interrupt, so their bottom halves run a tick late at best.
+//! * Threads are never stopped; a registered interrupt keeps its table slot.
+
+use crate::process::{self, Pid, SpawnError};
+use crate::sched::priority::Priority;
+use crate::sched::{self, WaitQueue};
+use core::fmt;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use kernel_sync::{IrqGuard, SpinMutex};
+use log::{info, warn};
+
+/// Maximum number of threaded interrupts.
+pub const MAX_THREADED_IRQS: usize = 8;
+
+/// What a top half found.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum IrqReturn {
+    /// The device had nothing for us.
+    None,
+    /// Handled completely in the top half.
+    Handled,
+    /// The bottom half has work to do.
+    WakeThread,
+}
+
+/// The two halves of a device interrupt; see the [module docs](self).
+pub struct ThreadedIrq {
+    /// Name of the thread, as listed by [`tasks`](crate::tasks).
+    name: &'static str,
+    top: fn() -> IrqReturn,
+    bottom: fn(),
+    /// Set by the top half, taken by the thread before each bottom half.
+    pending: AtomicBool,
+    /// Where the thread sleeps.
+    queue: WaitQueue,
+    /// PID of the thread; `0` until it is started.
+    thread: AtomicU32,
+    top_runs: AtomicU64,
+    bottom_runs: AtomicU64,
+}
+
+/// Counters of a [`ThreadedIrq`].
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+#[allow(dead_code)]
+pub struct IrqThreadStats {
+    /// Times the top half ran.
+    pub top_runs: u64,
+    /// Times the bottom half ran.
+    pub bottom_runs: u64,
+}
+
+impl ThreadedIrq {
+    pub const fn new(name: &'static str, top: fn() -> IrqReturn, bottom: fn()) -> Self {
+        Self {
+            name,
+            top,
+            bottom,
+            pending: AtomicBool::new(false),
+            queue: WaitQueue::new(),
+            thread: AtomicU32::new(0),
+            top_runs: AtomicU64::new(0),
+            bottom_runs: AtomicU64::new(0),
+        }
+    }
+
+    /// Run the top half, and wake the thread if it asks for it.
+    ///
+    /// Called from interrupt context.
+    pub fn handle(&self) -> IrqReturn {
+        self.top_runs.fetch_add(1, Ordering::Relaxed);
+        let ret = (self.top)();
+        if ret == IrqReturn::WakeThread {
+            self.wake();
+        }
+        ret
+    }
+
+    /// Have the bottom half run (again) soon.
+    pub fn wake(&self) {
+        self.pending.store(true, Ordering::Release);
+        self.queue.wake_one();
+    }
+
+    /// PID of the thread, once started.
+    #[allow(dead_code)]
+    pub fn thread(&self) -> Option<Pid> {
+        Pid::from_raw(u64::from(self.thread.load(Ordering::Acquire)))
+    }
+
+    #[allow(dead_code)]
+    pub fn stats(&self) -> IrqThreadStats {
+        IrqThreadStats {
+            top_runs: self.top_runs.load(Ordering::Relaxed),
+            bottom_runs: self.bottom_runs.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Spawn the thread running the bottom half, unless it runs already.
+    pub fn start(&'static self) -> Result<Pid, SpawnError> {
+        if let Some(pid) = self.thread() {
+            return Ok(pid);
+        }
+        let arg = core::ptr::from_ref(self) as usize;
+        let pid = process::spawn_kernel_thread(self.name, run, arg)?;
+        sched::set_priority(pid, Priority::MAX);
+        self.thread.store(pid.as_u32(), Ordering::Release);
+        Ok(pid)
+    }
+}
+
+/// All [`MAX_THREADED_IRQS`] slots are taken.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct TooManyIrqs;
+
+impl fmt::Display for TooManyIrqs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("too many threaded interrupts")
+    }
+}
+
+static IRQS: SpinMutex<[Option<&'static ThreadedIrq>; MAX_THREADED_IRQS]> =
+    SpinMutex::new([None; MAX_THREADED_IRQS]);
+
+/// Have [`start_all`] start the thread of `irq`; registering it again does
+/// nothing.
+pub fn register(irq: &'static ThreadedIrq) -> Result<(), TooManyIrqs> {
+    let _irq = IrqGuard::new();
+    let mut irqs = IRQS.lock();
+    if irqs
+        .iter()
+        .flatten()
+        .any(|&registered| core::ptr::eq(registered, irq))
+    {
+        return Ok(());
+    }
+    let slot = irqs.iter().position(Option::is_none).ok_or(TooManyIrqs)?;
+    irqs[slot] = Some(irq);
+    Ok(())
+}
+
+/// Start the threads of all registered interrupts.
+pub fn start_all() {
+    for irq in (0..MAX_THREADED_IRQS).map_while(nth) {
+        match irq.start() {
+            Ok(pid) => info!("Threaded IRQ {}: thread {pid}", irq.name),
+            Err(e) => warn!("Threaded IRQ {}: no thread: {e}", irq.name),
+        }
+    }
+}
+
+/// The interrupt in slot `index`; interrupts take the lowest free slot, so
+/// the first `None` ends the list.
+fn nth(index: usize) -> Option<&'static ThreadedIrq> {
+    let _irq = IrqGuard::new();
+    IRQS.lock().get(index).copied().flatten()
+}
+
+/// Kernel thread: run the bottom half of the [`ThreadedIrq`] at `arg` each
+/// time its top half asks for it.
+fn run(arg: usize) {
+    // Safety: `start` passes a `&'static ThreadedIrq`.
+    let irq = unsafe { &*(arg as *const ThreadedIrq) };
+    loop {
+        irq.queue
+            .wait_until(|| irq.pending.swap(false, Ordering::AcqRel));
+        irq.bottom_runs.fetch_add(1, Ordering::Relaxed);
+        (irq.bottom)();
+    }
+}
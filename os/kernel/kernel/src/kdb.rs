@@ -0,0 +1,373 @@
+//! # Kernel Debugger
+//!
+//! `kdb-lite`, a small interactive monitor on the
+//! [virtio console](crate::virtio::console) for looking into a kernel that
+//! misbehaves, without a debugger attached.
+//!
+//! ## Entering
+//!
+//! * Press [`BREAK_KEY`] (Ctrl-]) on the console. The timer interrupt looks
+//!   for it through [`poll`]; console input typed before it is discarded.
+//! * Execute `int3` in kernel mode, e.g. an `asm!("int3")` placed while
+//!   debugging.
+//! * Call [`enter`].
+//!
+//! ## Commands
+//!
+//! | Command          | Effect                                                    |
+//! |------------------|-----------------------------------------------------------|
+//! | `md <addr> [n]`  | Hex dump `n` bytes (default 64) at `addr`                 |
+//! | `pt <va>`        | Page table entries the walk to `va` passes                |
+//! | `tasks`          | The task table                                            |
+//! | `irq`            | Interrupt counts per CPU and vector                       |
+//! | `heap`           | Heap counters, and live bytes per call site if counted    |
+//! | `panic`          | Panic, to try out the panic path                          |
+//! | `c`, `continue`  | Leave the debugger                                        |
+//! | `help`           | List the commands                                         |
+//!
+//! Numbers are decimal, or hexadecimal with a `0x` prefix. `md` reads at
+//! most [`MAX_DUMP_LEN`] bytes and stops at the first unmapped page; it
+//! reads device memory like any other, side effects included.
+//!
+//! ## Environment
+//!
+//! The debugger runs with interrupts disabled on the CPU that entered it and
+//! polls the console; it never sleeps or schedules. It works from interrupt
+//! handlers and during early init, as soon as the console is up. Other CPUs
+//! keep running. `tasks` takes the process table lock and hangs if the
+//! debugger was entered while holding it.
+
+use crate::alloc::heap;
+use crate::chardev::CharDevice;
+use crate::ksyms::Symbolized;
+use crate::per_cpu::PerCpu;
+use crate::procfs::{self, ProcFile};
+use crate::smap::SmapGuard;
+use crate::tasks;
+use crate::virtio::console::CONSOLE;
+use crate::watchdog;
+use core::fmt::{self, Write};
+use core::hint::spin_loop;
+use core::sync::atomic::{AtomicBool, Ordering};
+use kernel_alloc::phys_mapper::HhdmPhysMapper;
+use kernel_info::memory::LAST_USERSPACE_ADDRESS;
+use kernel_memory_addresses::VirtualAddress;
+use kernel_sync::IrqGuard;
+use kernel_vmem::AddressSpace;
+use kernel_vmem::address_space::Step;
+use log::{info, warn};
+
+/// Console input that enters the debugger: Ctrl-].
+pub const BREAK_KEY: u8 = 0x1D;
+
+/// Bytes `md` dumps when not told.
+pub const DEFAULT_DUMP_LEN: u64 = 64;
+
+/// Most bytes one `md` dumps.
+pub const MAX_DUMP_LEN: u64 = 4096;
+
+/// Longest command line.
+const LINE_LEN: usize = 80;
+
+/// Set while a CPU is in the debugger.
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Why the debugger was entered.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Reason {
+    /// [`BREAK_KEY`] on the console.
+    BreakKey,
+    /// An `int3`; the address is the instruction after it.
+    Breakpoint(VirtualAddress),
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BreakKey => f.write_str("break key"),
+            Self::Breakpoint(rip) => write!(f, "breakpoint at {}", Symbolized(*rip)),
+        }
+    }
+}
+
+/// What to do after a command.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Flow {
+    /// Prompt for the next command.
+    Stay,
+    /// Leave the debugger.
+    Continue,
+}
+
+/// Enter the debugger if [`BREAK_KEY`] arrived on the console.
+///
+/// Called from the timer interrupt.
+pub fn poll() {
+    if CONSOLE.contains(BREAK_KEY) {
+        enter(Reason::BreakKey);
+    }
+}
+
+/// Run the debugger on the console until `continue`.
+///
+/// Returns `false` without doing anything if there is no console, or if a
+/// CPU already is in the debugger.
+pub fn enter(reason: Reason) -> bool {
+    if !CONSOLE.is_up() || ACTIVE.swap(true, Ordering::AcqRel) {
+        return false;
+    }
+    let _irq = IrqGuard::new();
+    warn!("Entering kdb: {reason}");
+
+    if reason == Reason::BreakKey {
+        discard_input_until(BREAK_KEY);
+    }
+    let cpu = unsafe { PerCpu::current() }.cpu_id;
+    let mut out = ConsoleOut;
+    let _ = writeln!(out, "\nkdb: {reason} on CPU {cpu}; `help` lists commands");
+
+    let mut line = [0u8; LINE_LEN];
+    loop {
+        let _ = out.write_str("kdb> ");
+        let len = read_line(&mut out, &mut line);
+        // Only printable ASCII makes it into the line.
+        let line = core::str::from_utf8(&line[..len]).unwrap_or_default();
+        if run(line, &mut out) == Flow::Continue {
+            break;
+        }
+    }
+
+    // This CPU took no timer interrupts while it was in here.
+    watchdog::resume();
+    ACTIVE.store(false, Ordering::Release);
+    info!("Left kdb");
+    true
+}
+
+/// Run the command `line`, writing its output to `out`.
+pub fn run(line: &str, out: &mut impl Write) -> Flow {
+    let mut words = line.split_whitespace();
+    let Some(command) = words.next() else {
+        return Flow::Stay;
+    };
+
+    // Output that does not make it to the console is lost either way.
+    let _ = match command {
+        "md" => match (
+            words.next().map(parse_number),
+            words.next().map(parse_number),
+        ) {
+            (Some(Some(addr)), None) => dump(out, addr, DEFAULT_DUMP_LEN),
+            (Some(Some(addr)), Some(Some(len))) => dump(out, addr, len),
+            _ => writeln!(out, "usage: md <addr> [len]"),
+        },
+        "pt" => match words.next().and_then(parse_number) {
+            Some(va) => page_walk(out, va),
+            None => writeln!(out, "usage: pt <va>"),
+        },
+        "tasks" => tasks::write_table(out),
+        "irq" => procfs::generate(ProcFile::Interrupts, out),
+        "heap" => heap::write_stats(out),
+        "panic" => panic!("test panic from kdb"),
+        "c" | "continue" => return Flow::Continue,
+        "help" => help(out),
+        _ => writeln!(out, "unknown command `{command}`; try `help`"),
+    };
+    Flow::Stay
+}
+
+fn help(out: &mut impl Write) -> fmt::Result {
+    out.write_str(
+        "md <addr> [len]  hex dump memory\n\
+         pt <va>          walk the page tables for an address\n\
+         tasks            list tasks\n\
+         irq              interrupt counts\n\
+         heap             heap usage\n\
+         panic            trigger a test panic\n\
+         c, continue      leave kdb\n",
+    )
+}
+
+/// `n` or `0xn`.
+fn parse_number(word: &str) -> Option<u64> {
+    word.strip_prefix("0x").map_or_else(
+        || word.parse().ok(),
+        |hex| u64::from_str_radix(hex, 16).ok(),
+    )
+}
+
+/// Whether `addr` is canonical, i.e. bits 63..47 are all equal.
+#[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+const fn is_canonical(addr: u64) -> bool {
+    ((addr << 16) as i64 >> 16) as u64 == addr
+}
+
+/// The active address space, without taking the VMM lock.
+fn current_space() -> AddressSpace<'static, HhdmPhysMapper> {
+    // Safety: CR3 points to a valid PML4; the mapper is valid for the kernel's lifetime.
+    unsafe { AddressSpace::from_current(&HhdmPhysMapper) }
+}
+
+/// Hex dump `len` bytes at `addr`, 16 per line.
+fn dump(out: &mut impl Write, addr: u64, len: u64) -> fmt::Result {
+    let space = current_space();
+    let end = addr.saturating_add(len.min(MAX_DUMP_LEN));
+    let mut line = addr;
+    while line < end {
+        let mut bytes = [0u8; 16];
+        let n = (end - line).min(16);
+        for (i, byte) in (line..line + n).zip(&mut bytes) {
+            if !is_canonical(i) || space.query(VirtualAddress::new(i)).is_none() {
+                return writeln!(out, "{i:#018x}: not mapped");
+            }
+            *byte = read_byte(i);
+        }
+
+        write!(out, "{line:016x}:")?;
+        for (i, byte) in bytes.iter().enumerate() {
+            if (i as u64) < n {
+                write!(out, " {byte:02x}")?;
+            } else {
+                out.write_str("   ")?;
+            }
+        }
+        out.write_str("  ")?;
+        for &byte in &bytes[..n as usize] {
+            let c = if byte.is_ascii_graphic() || byte == b' ' {
+                byte as char
+            } else {
+                '.'
+            };
+            out.write_char(c)?;
+        }
+        out.write_char('\n')?;
+        line += n;
+    }
+    Ok(())
+}
+
+/// The byte at the mapped address `addr`.
+fn read_byte(addr: u64) -> u8 {
+    let _smap = (addr <= LAST_USERSPACE_ADDRESS.as_u64()).then(SmapGuard::enter);
+    // Safety: the caller checked that the page is mapped.
+    unsafe { core::ptr::read_volatile(addr as *const u8) }
+}
+
+/// The entries the walk to `va` passes, and where it leads.
+fn page_walk(out: &mut impl Write, va: u64) -> fmt::Result {
+    if !is_canonical(va) {
+        return writeln!(out, "{va:#x} is not canonical");
+    }
+    let space = current_space();
+    let va = VirtualAddress::new(va);
+
+    let mut result = Ok(());
+    space.trace(va, |step| {
+        if result.is_ok() {
+            result = write_step(out, step);
+        }
+    });
+    result?;
+
+    match space.query(va) {
+        Some(pa) => writeln!(out, "{va} -> {pa}"),
+        None => writeln!(out, "{va} is not mapped"),
+    }
+}
+
+/// `<table>[<index>] @ <pa>: <table|page> <pa> rwx U G <cache mode>`
+fn write_step(out: &mut impl Write, step: &Step) -> fmt::Result {
+    let table = match step.level {
+        4 => "PML4",
+        3 => "PDPT",
+        2 => "PD",
+        _ => "PT",
+    };
+    write!(
+        out,
+        "{table:>4}[{:>3}] @ {}: ",
+        step.index,
+        step.table.base()
+    )?;
+    let Some((target, flags)) = step.target else {
+        return writeln!(out, "not present");
+    };
+    writeln!(
+        out,
+        "{} {target} r{}{} {} {} {:?}",
+        if step.leaf { "page " } else { "table" },
+        if flags.writable { 'w' } else { '-' },
+        if flags.no_execute { '-' } else { 'x' },
+        if flags.user { 'U' } else { 'S' },
+        if flags.global { 'G' } else { '-' },
+        flags.cache_mode()
+    )
+}
+
+/// Drop console input up to and including `byte`.
+fn discard_input_until(byte: u8) {
+    let mut buf = [0u8; 1];
+    while CONSOLE.read(&mut buf) == 1 && buf[0] != byte {}
+}
+
+/// Read a line of printable ASCII into `line`, echoing it; returns its
+/// length.
+fn read_line(out: &mut ConsoleOut, line: &mut [u8]) -> usize {
+    let mut len = 0;
+    let mut buf = [0u8; 1];
+    loop {
+        if CONSOLE.read(&mut buf) == 0 {
+            // Interrupts are off; keep the watchdog from calling this a lockup.
+            watchdog::resume();
+            spin_loop();
+            continue;
+        }
+
+        match buf[0] {
+            b'\r' | b'\n' => {
+                let _ = out.write_char('\n');
+                return len;
+            }
+            // Backspace or delete.
+            0x08 | 0x7F if len > 0 => {
+                len -= 1;
+                let _ = out.write_str("\x08 \x08");
+            }
+            byte @ b' '..=b'~' if len < line.len() => {
+                line[len] = byte;
+                len += 1;
+                let _ = out.write_char(byte as char);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Writes to the console, with `\n` sent as `\r\n`.
+struct ConsoleOut;
+
+impl ConsoleOut {
+    fn send(bytes: &[u8]) {
+        let mut sent = 0;
+        while sent < bytes.len() {
+            let n = CONSOLE.write(&bytes[sent..]);
+            if n == 0 {
+                // Nobody is listening; drop the rest.
+                return;
+            }
+            sent += n;
+        }
+    }
+}
+
+impl Write for ConsoleOut {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for (i, part) in s.split('\n').enumerate() {
+            if i > 0 {
+                Self::send(b"\r\n");
+            }
+            Self::send(part.as_bytes());
+        }
+        Ok(())
+    }
+}
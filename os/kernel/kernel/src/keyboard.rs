@@ -0,0 +1,168 @@
+//! # Keyboard
+//!
+//! Raw scancodes from the PS/2 controller are handed from interrupt context
+//! to a [decoder](decoder) through a lock-free [`MpscRing`], so the producer
+//! side never takes a lock and never waits. USB keyboards on an
+//! [xHCI controller](crate::xhci) feed the same queue with
+//! [`queue_scancode`], their [boot reports](hid) translated to scancodes.
+//! The decoder turns them into [`KeyEvent`]s with raw key codes and the
+//! Unicode text typed. The text goes to the [terminal](crate::tty); the
+//! events are queued for other consumers in [`read_event`].
+//!
+//! ## Polling
+//!
+//! IRQ 1 is not routed yet, so the controller is polled from the LAPIC timer
+//! interrupt via [`poll`], which runs the top half of the [threaded
+//! interrupt](crate::irq_thread) [`IRQ`]: it drains the controller into the
+//! scancode queue. Bytes flagged as mouse (auxiliary port) data are
+//! discarded.
+//!
+//! ## Decoding
+//!
+//! Decoding and logging are too slow for the interrupt, so they are the
+//! bottom half: the `irq-kbd` kernel thread drains the scancode queue
+//! whenever the top half queued something. Scancodes are translated on the
+//! [keymap](keymap) chosen by the `keymap` option of the
+//! [command line](crate::cmdline), `us` if not given; see [`init`].
+//!
+//! Pressing F11 additionally [logs the interrupt counts](crate::irq_stats::log_report),
+//! F12 [dumps the task table](crate::tasks::dump).
+//!
+//! ## Overflow
+//!
+//! If consumers fall behind, new scancodes and events are dropped and
+//! counted; see [`MpscRing::stats`].
+
+pub mod decoder;
+pub mod hid;
+pub mod keymap;
+
+use crate::cmdline::{self, Param, ParamKind};
+use crate::irq_thread::{self, IrqReturn, ThreadedIrq};
+use crate::keyboard::decoder::{Decoder, KeyEvent};
+use crate::keyboard::keymap::KeyCode;
+use crate::{irq_stats, tasks, tty};
+use kernel_ports::PortReadOnly;
+use kernel_sync::SpinMutex;
+use kernel_sync::ring::MpscRing;
+use log::{debug, info, warn};
+
+pub static KEYMAP_PARAM: Param = Param {
+    name: "keymap",
+    kind: ParamKind::Str,
+    help: "Keyboard layout: us, uk or de",
+};
+
+/// PS/2 controller data port.
+const DATA_PORT: PortReadOnly<u8> = PortReadOnly::new(0x60);
+
+/// PS/2 controller status port.
+const STATUS_PORT: PortReadOnly<u8> = PortReadOnly::new(0x64);
+
+/// Status bit: the output buffer holds a byte for us.
+const STATUS_OUTPUT_FULL: u8 = 1 << 0;
+
+/// Status bit: the byte in the output buffer came from the auxiliary (mouse) port.
+const STATUS_AUX_DATA: u8 = 1 << 5;
+
+/// Upper bound of bytes drained per [`poll`], to keep the interrupt short.
+const MAX_BYTES_PER_POLL: usize = 16;
+
+static SCANCODES: MpscRing<u8, 128> = MpscRing::new();
+
+static EVENTS: MpscRing<KeyEvent, 64> = MpscRing::new();
+
+static DECODER: SpinMutex<Decoder> = SpinMutex::new(Decoder::new(&keymap::US));
+
+/// Reads the controller in the interrupt, decodes in a thread.
+pub static IRQ: ThreadedIrq = ThreadedIrq::new("irq-kbd", drain_controller, decode_scancodes);
+
+/// Register the [`IRQ`] thread and select the keymap named on the command
+/// line.
+pub fn init() {
+    if let Err(e) = irq_thread::register(&IRQ) {
+        warn!("Keyboard input will not be decoded: {e}");
+    }
+    let Some(name) = cmdline::get_str(KEYMAP_PARAM.name) else {
+        return;
+    };
+    match keymap::find(name) {
+        Some(keymap) => {
+            DECODER.lock().set_keymap(keymap);
+            info!("Using the {name} keymap");
+        }
+        None => warn!("Unknown keymap {name}; using the us keymap"),
+    }
+}
+
+/// Move pending bytes from the PS/2 controller into the scancode queue, and
+/// have them decoded.
+///
+/// Called from interrupt context.
+pub fn poll() {
+    IRQ.handle();
+}
+
+/// Queue a set 1 scancode from another keyboard, such as a USB one, and
+/// have it decoded.
+pub fn queue_scancode(scancode: u8) {
+    // Dropped scancodes are accounted for in the ring statistics.
+    SCANCODES.push(scancode).ok();
+    IRQ.wake();
+}
+
+/// Top half: move pending bytes from the controller into the scancode queue.
+fn drain_controller() -> IrqReturn {
+    let mut ret = IrqReturn::None;
+    for _ in 0..MAX_BYTES_PER_POLL {
+        let status = unsafe { STATUS_PORT.read() };
+        if status & STATUS_OUTPUT_FULL == 0 {
+            break;
+        }
+
+        let byte = unsafe { DATA_PORT.read() };
+        ret = IrqReturn::Handled;
+        if status & STATUS_AUX_DATA == 0 {
+            // Dropped scancodes are accounted for in the ring statistics.
+            SCANCODES.push(byte).ok();
+        }
+    }
+
+    if SCANCODES.is_empty() {
+        ret
+    } else {
+        IrqReturn::WakeThread
+    }
+}
+
+/// Bottom half: decode the queued scancodes into events.
+fn decode_scancodes() {
+    let mut decoder = DECODER.lock();
+    while let Some(scancode) = SCANCODES.pop() {
+        let Some(event) = decoder.decode(scancode) else {
+            continue;
+        };
+        debug!(
+            "Key {:#04x} {}, modifiers {:#04x}",
+            event.code.0,
+            if event.pressed { "pressed" } else { "released" },
+            event.modifiers.into_bits()
+        );
+        if event.pressed {
+            match event.code {
+                KeyCode::F11 => irq_stats::log_report(),
+                KeyCode::F12 => tasks::dump(),
+                _ => {}
+            }
+        }
+        tty::TTY.receive(event.text());
+        // Dropped events are accounted for in the ring statistics.
+        EVENTS.push(event).ok();
+    }
+}
+
+/// Take the oldest key event, if any.
+#[allow(dead_code)]
+pub fn read_event() -> Option<KeyEvent> {
+    EVENTS.pop()
+}
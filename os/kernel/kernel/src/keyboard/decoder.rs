@@ -0,0 +1,202 @@
+//! # Scancode Decoding
+//!
+//! A [`Decoder`] turns the set 1 scancodes of a PS/2 keyboard into
+//! [`KeyEvent`]s: which key went down or up, the modifiers in effect, and
+//! the text it typed on the decoder's [`Keymap`].
+//!
+//! ## Modifiers
+//!
+//! Shift, Ctrl, Alt and `AltGr` (right Alt) count while held; Caps Lock
+//! toggles on every press. Caps Lock only shifts [letter
+//! keys](Key::follows_caps_lock), and Shift undoes it. With Ctrl held,
+//! letters type the matching control character (`Ctrl-C` is `0x03`).
+//!
+//! ## Dead keys
+//!
+//! A dead key types nothing but remembers its [`Accent`]; the next key that
+//! types something gets the accent if it [can carry it](Accent::compose).
+//! Space, or the dead key again, types the accent on its own. Anything else
+//! types the accent followed by the character, which is why an event can
+//! carry [two characters](KeyEvent::text).
+
+use crate::keyboard::keymap::{Accent, Key, KeyCode, Keymap, Sym};
+use bitfield_struct::bitfield;
+
+/// Prefix of the scancodes of extended keys.
+const EXTENDED: u8 = 0xE0;
+
+/// Prefix of the Pause key's six-byte sequence.
+const PAUSE: u8 = 0xE1;
+
+/// Bytes of the Pause sequence after [`PAUSE`].
+const PAUSE_LEN: u8 = 5;
+
+/// Scancode bit: the key was released.
+const RELEASED: u8 = 0x80;
+
+/// Modifiers in effect when a key event happened.
+#[bitfield(u8)]
+#[derive(Eq, PartialEq)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub altgr: bool,
+    pub caps_lock: bool,
+    #[bits(3)]
+    __: u8,
+}
+
+/// A key going down or up.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct KeyEvent {
+    pub code: KeyCode,
+    pub pressed: bool,
+    /// The modifiers, after this event changed them.
+    pub modifiers: Modifiers,
+    text: [Option<char>; 2],
+}
+
+impl KeyEvent {
+    /// The characters the key typed: none for releases, modifiers and
+    /// dead keys, two for an accent that did not combine.
+    pub fn text(&self) -> impl Iterator<Item = char> + use<> {
+        self.text.into_iter().flatten()
+    }
+}
+
+/// Decoder state; see the [module docs](self).
+#[derive(Debug)]
+pub struct Decoder {
+    keymap: &'static Keymap,
+    modifiers: Modifiers,
+    /// Set after an [`EXTENDED`] prefix.
+    extended: bool,
+    /// Bytes of the Pause sequence still to skip.
+    skip: u8,
+    /// The accent of a dead key waiting for the next character.
+    dead: Option<Accent>,
+}
+
+impl Decoder {
+    #[must_use]
+    pub const fn new(keymap: &'static Keymap) -> Self {
+        Self {
+            keymap,
+            modifiers: Modifiers::new(),
+            extended: false,
+            skip: 0,
+            dead: None,
+        }
+    }
+
+    /// Switch to `keymap`, dropping a pending dead key.
+    pub const fn set_keymap(&mut self, keymap: &'static Keymap) {
+        self.keymap = keymap;
+        self.dead = None;
+    }
+
+    /// Feed the next scancode byte; returns the event once a key's
+    /// scancode is complete.
+    pub fn decode(&mut self, byte: u8) -> Option<KeyEvent> {
+        if self.skip > 0 {
+            self.skip -= 1;
+            return None;
+        }
+        match byte {
+            EXTENDED => {
+                self.extended = true;
+                return None;
+            }
+            PAUSE => {
+                self.skip = PAUSE_LEN;
+                return None;
+            }
+            _ => {}
+        }
+
+        let prefix = if core::mem::take(&mut self.extended) {
+            0x80
+        } else {
+            0
+        };
+        let code = KeyCode(prefix | byte & !RELEASED);
+        let pressed = byte & RELEASED == 0;
+        // Print Screen and the navigation keys wrap themselves in fake
+        // shift presses and releases.
+        if code == KeyCode(0x80 | KeyCode::LEFT_SHIFT.0)
+            || code == KeyCode(0x80 | KeyCode::RIGHT_SHIFT.0)
+        {
+            return None;
+        }
+
+        self.update_modifiers(code, pressed);
+        let text = if pressed {
+            self.type_key(self.keymap.key(code))
+        } else {
+            [None; 2]
+        };
+        Some(KeyEvent {
+            code,
+            pressed,
+            modifiers: self.modifiers,
+            text,
+        })
+    }
+
+    const fn update_modifiers(&mut self, code: KeyCode, pressed: bool) {
+        let m = &mut self.modifiers;
+        match code {
+            KeyCode::LEFT_SHIFT | KeyCode::RIGHT_SHIFT => m.set_shift(pressed),
+            KeyCode::LEFT_CTRL | KeyCode::RIGHT_CTRL => m.set_ctrl(pressed),
+            KeyCode::LEFT_ALT => m.set_alt(pressed),
+            KeyCode::RIGHT_ALT => m.set_altgr(pressed),
+            KeyCode::CAPS_LOCK if pressed => m.set_caps_lock(!m.caps_lock()),
+            _ => {}
+        }
+    }
+
+    /// The text typed by pressing `key`.
+    fn type_key(&mut self, key: Key) -> [Option<char>; 2] {
+        let m = self.modifiers;
+        let sym = if m.altgr() {
+            key.altgr
+        } else if m.shift() != (m.caps_lock() && key.follows_caps_lock()) {
+            key.shift
+        } else {
+            key.plain
+        };
+
+        match (sym, self.dead) {
+            (Sym::None, _) => [None; 2],
+            (Sym::Dead(accent), None) => {
+                self.dead = Some(accent);
+                [None; 2]
+            }
+            (Sym::Dead(accent), Some(pending)) => {
+                self.dead = (accent != pending).then_some(accent);
+                [Some(pending.spacing()), None]
+            }
+            (Sym::Char(c), None) => [Some(self.control(c)), None],
+            (Sym::Char(c), Some(pending)) => {
+                self.dead = None;
+                match pending.compose(c) {
+                    Some(accented) => [Some(accented), None],
+                    None if c == ' ' => [Some(pending.spacing()), None],
+                    None => [Some(pending.spacing()), Some(self.control(c))],
+                }
+            }
+        }
+    }
+
+    /// `c` as typed with the current modifiers: the control character for
+    /// letters with Ctrl held.
+    #[allow(clippy::cast_possible_truncation)]
+    const fn control(&self, c: char) -> char {
+        if self.modifiers.ctrl() && c.is_ascii_alphabetic() {
+            (c as u8 & 0x1F) as char
+        } else {
+            c
+        }
+    }
+}
@@ -0,0 +1,119 @@
+//! # USB Boot Keyboards
+//!
+//! A USB keyboard speaking the HID boot protocol sends an 8-byte report
+//! whenever its state changes: a bitmap of the eight modifier keys, a
+//! reserved byte, and the usage IDs of up to six other keys held down.
+//! Reports carry state rather than events, so [`BootReports`] compares each
+//! report with the previous one and turns the differences into set 1
+//! scancodes, which the [decoder](super::decoder) takes like those of a
+//! PS/2 keyboard.
+//!
+//! While more keys are held than fit, the keyboard reports
+//! [`ERROR_ROLL_OVER`] in every key slot; such reports are ignored, and the
+//! keys stay as they were.
+
+use crate::keyboard::keymap::KeyCode;
+
+/// Size of a boot protocol keyboard report.
+pub const REPORT_LEN: usize = 8;
+
+/// Usage ID reported in every key slot while too many keys are held.
+const ERROR_ROLL_OVER: u8 = 0x01;
+
+/// First usage ID in [`USAGES`].
+const FIRST_USAGE: u8 = 0x04;
+
+/// Prefix of the scancodes of extended keys.
+const EXTENDED: u8 = 0xE0;
+
+/// Scancode bit: the key was released.
+const RELEASED: u8 = 0x80;
+
+/// Key codes of the modifier bits of the first report byte, lowest first.
+const MODIFIERS: [KeyCode; 8] = [
+    KeyCode::LEFT_CTRL,
+    KeyCode::LEFT_SHIFT,
+    KeyCode::LEFT_ALT,
+    KeyCode(0x80 | 0x5B), // Left GUI
+    KeyCode::RIGHT_CTRL,
+    KeyCode::RIGHT_SHIFT,
+    KeyCode::RIGHT_ALT,
+    KeyCode(0x80 | 0x5C), // Right GUI
+];
+
+/// [`KeyCode`]s of the keyboard usage page from [`FIRST_USAGE`] on; `0`
+/// for keys without a plain set 1 scancode, such as Pause.
+#[rustfmt::skip]
+const USAGES: [u8; 98] = [
+    0x1E, 0x30, 0x2E, 0x20, 0x12, 0x21, 0x22, 0x23, // 0x04: a-h
+    0x17, 0x24, 0x25, 0x26, 0x32, 0x31, 0x18, 0x19, // 0x0c: i-p
+    0x10, 0x13, 0x1F, 0x14, 0x16, 0x2F, 0x11, 0x2D, // 0x14: q-x
+    0x15, 0x2C, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, // 0x1c: y, z, 1-6
+    0x08, 0x09, 0x0A, 0x0B, 0x1C, 0x01, 0x0E, 0x0F, // 0x24: 7-0, Enter, Esc, Backspace, Tab
+    0x39, 0x0C, 0x0D, 0x1A, 0x1B, 0x2B, 0x2B, 0x27, // 0x2c: Space, - = [ ] \ # ;
+    0x28, 0x29, 0x33, 0x34, 0x35, 0x3A, 0x3B, 0x3C, // 0x34: ' ` , . /, Caps Lock, F1, F2
+    0x3D, 0x3E, 0x3F, 0x40, 0x41, 0x42, 0x43, 0x44, // 0x3c: F3-F10
+    0x57, 0x58, 0xB7, 0x46, 0x00, 0xD2, 0xC7, 0xC9, // 0x44: F11, F12, Print, Scroll Lock, Pause, Insert, Home, Page Up
+    0xD3, 0xCF, 0xD1, 0xCD, 0xCB, 0xD0, 0xC8, 0x45, // 0x4c: Delete, End, Page Down, arrows, Num Lock
+    0xB5, 0x37, 0x4A, 0x4E, 0x9C, 0x4F, 0x50, 0x51, // 0x54: keypad / * - +, Enter, 1-3
+    0x4B, 0x4C, 0x4D, 0x47, 0x48, 0x49, 0x52, 0x53, // 0x5c: keypad 4-9, 0, .
+    0x56, 0xDD,                                     // 0x64: non-US \, Application
+];
+
+/// The key with usage ID `usage` on the keyboard usage page.
+#[must_use]
+pub fn key_code(usage: u8) -> Option<KeyCode> {
+    let index = usage.checked_sub(FIRST_USAGE)?;
+    USAGES
+        .get(usize::from(index))
+        .filter(|&&code| code != 0)
+        .map(|&code| KeyCode(code))
+}
+
+/// The last report of a keyboard; see the [module docs](self).
+#[derive(Debug, Default)]
+pub struct BootReports {
+    last: [u8; REPORT_LEN],
+}
+
+impl BootReports {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            last: [0; REPORT_LEN],
+        }
+    }
+
+    /// Take the next `report` and pass the scancodes of the keys it
+    /// released, then of those it pressed, to `emit`.
+    pub fn update(&mut self, report: [u8; REPORT_LEN], mut emit: impl FnMut(u8)) {
+        if report[2..].contains(&ERROR_ROLL_OVER) {
+            return;
+        }
+        let last = core::mem::replace(&mut self.last, report);
+        for (pressed, now, before) in [(false, last, report), (true, report, last)] {
+            // Keys in `now` but not in `before`.
+            for (bit, &code) in MODIFIERS.iter().enumerate() {
+                if now[0] & !before[0] & (1 << bit) != 0 {
+                    scancode(code, pressed, &mut emit);
+                }
+            }
+            let added = now[2..]
+                .iter()
+                .filter(|usage| !before[2..].contains(usage))
+                .filter_map(|&usage| key_code(usage));
+            for code in added {
+                scancode(code, pressed, &mut emit);
+            }
+        }
+    }
+}
+
+/// Emit the set 1 scancode of `code` going down or up.
+fn scancode(code: KeyCode, pressed: bool, emit: &mut impl FnMut(u8)) {
+    if code.0 & 0x80 != 0 {
+        emit(EXTENDED);
+    }
+    let released = if pressed { 0 } else { RELEASED };
+    emit(code.0 & !0x80 | released);
+}
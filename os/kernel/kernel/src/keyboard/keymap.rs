@@ -0,0 +1,282 @@
+//! # Keyboard Layouts
+//!
+//! A [`Keymap`] assigns every [`KeyCode`] a [`Key`]: what it types alone,
+//! with Shift, and with `AltGr`. The builtin layouts list only the keys that
+//! differ from the US layout; any key they leave out types what it does on a
+//! US keyboard.
+//!
+//! | Name | Layout                  | Dead keys                 |
+//! |------|-------------------------|---------------------------|
+//! | `us` | US (ANSI)               | none                      |
+//! | `uk` | United Kingdom          | none                      |
+//! | `de` | German (QWERTZ, T1)     | `^`, `´` and `` ` ``      |
+//!
+//! Dead keys type nothing themselves; they put their [`Accent`] on the next
+//! character (see [`Accent::compose`]).
+
+/// A key, as the set 1 make code of the PS/2 scancode that reports it, with
+/// bit 7 set for keys reported with an `0xE0` prefix.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct KeyCode(pub u8);
+
+impl KeyCode {
+    pub const LEFT_CTRL: Self = Self(0x1D);
+    pub const LEFT_SHIFT: Self = Self(0x2A);
+    pub const RIGHT_SHIFT: Self = Self(0x36);
+    pub const LEFT_ALT: Self = Self(0x38);
+    pub const CAPS_LOCK: Self = Self(0x3A);
+    pub const F11: Self = Self(0x57);
+    pub const F12: Self = Self(0x58);
+    pub const RIGHT_CTRL: Self = Self(0x80 | 0x1D);
+    /// Right Alt, `AltGr` on most layouts outside the US.
+    pub const RIGHT_ALT: Self = Self(0x80 | 0x38);
+}
+
+/// An accent typed by a dead key.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Accent {
+    Grave,
+    Acute,
+    Circumflex,
+}
+
+impl Accent {
+    /// The accent on its own, as typed by the dead key followed by Space.
+    #[must_use]
+    pub const fn spacing(self) -> char {
+        match self {
+            Self::Grave => '`',
+            Self::Acute => '´',
+            Self::Circumflex => '^',
+        }
+    }
+
+    /// `c` with this accent, if there is such a character.
+    #[must_use]
+    pub fn compose(self, c: char) -> Option<char> {
+        let (bases, accented) = match self {
+            Self::Grave => ("aeiouAEIOU", "àèìòùÀÈÌÒÙ"),
+            Self::Acute => ("aeiouyAEIOUY", "áéíóúýÁÉÍÓÚÝ"),
+            Self::Circumflex => ("aeiouAEIOU", "âêîôûÂÊÎÔÛ"),
+        };
+        bases
+            .chars()
+            .zip(accented.chars())
+            .find_map(|(base, accented)| (base == c).then_some(accented))
+    }
+}
+
+/// What a key types in one shift state.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Sym {
+    /// Nothing, e.g. for modifiers and function keys.
+    None,
+    Char(char),
+    /// A dead key.
+    Dead(Accent),
+}
+
+/// What a key types alone, with Shift, and with `AltGr`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Key {
+    pub plain: Sym,
+    pub shift: Sym,
+    pub altgr: Sym,
+}
+
+impl Key {
+    /// A key that types nothing.
+    pub const NONE: Self = Self::new(Sym::None, Sym::None, Sym::None);
+
+    #[must_use]
+    pub const fn new(plain: Sym, shift: Sym, altgr: Sym) -> Self {
+        Self {
+            plain,
+            shift,
+            altgr,
+        }
+    }
+
+    /// Whether Caps Lock acts as Shift on this key, i.e. it types a lower
+    /// case letter alone and the upper case one with Shift.
+    #[must_use]
+    pub const fn follows_caps_lock(&self) -> bool {
+        matches!((self.plain, self.shift), (Sym::Char(plain), Sym::Char(shift))
+            if plain.is_lowercase() && shift.is_uppercase())
+    }
+}
+
+/// A key typing `plain` alone and `shift` with Shift.
+const fn key(plain: char, shift: char) -> Key {
+    Key::new(Sym::Char(plain), Sym::Char(shift), Sym::None)
+}
+
+/// A key that also types `altgr` with `AltGr`.
+const fn key3(plain: char, shift: char, altgr: char) -> Key {
+    Key::new(Sym::Char(plain), Sym::Char(shift), Sym::Char(altgr))
+}
+
+/// A key typing `c` whether Shift is held or not.
+const fn same(c: char) -> Key {
+    key(c, c)
+}
+
+/// A keyboard layout; see the [module docs](self).
+#[derive(Debug)]
+pub struct Keymap {
+    pub name: &'static str,
+    /// Keys that differ from the US layout.
+    overrides: &'static [(KeyCode, Key)],
+}
+
+impl Keymap {
+    /// What `code` types on this layout.
+    #[must_use]
+    pub fn key(&self, code: KeyCode) -> Key {
+        self.overrides
+            .iter()
+            .find(|(c, _)| *c == code)
+            .map_or_else(|| us_key(code), |&(_, key)| key)
+    }
+}
+
+/// The builtin layouts.
+pub static KEYMAPS: [&Keymap; 3] = [&US, &UK, &DE];
+
+/// The builtin layout called `name`.
+#[must_use]
+pub fn find(name: &str) -> Option<&'static Keymap> {
+    KEYMAPS.iter().copied().find(|m| m.name == name)
+}
+
+pub static US: Keymap = Keymap {
+    name: "us",
+    overrides: &[],
+};
+
+pub static UK: Keymap = Keymap {
+    name: "uk",
+    overrides: &[
+        (KeyCode(0x03), key('2', '"')),
+        (KeyCode(0x04), key('3', '£')),
+        (KeyCode(0x05), key3('4', '$', '€')),
+        (KeyCode(0x28), key('\'', '@')),
+        (KeyCode(0x29), key3('`', '¬', '¦')),
+        (KeyCode(0x2B), key('#', '~')),
+    ],
+};
+
+pub static DE: Keymap = Keymap {
+    name: "de",
+    overrides: &[
+        (KeyCode(0x03), key3('2', '"', '²')),
+        (KeyCode(0x04), key3('3', '§', '³')),
+        (KeyCode(0x07), key('6', '&')),
+        (KeyCode(0x08), key3('7', '/', '{')),
+        (KeyCode(0x09), key3('8', '(', '[')),
+        (KeyCode(0x0A), key3('9', ')', ']')),
+        (KeyCode(0x0B), key3('0', '=', '}')),
+        (KeyCode(0x0C), key3('ß', '?', '\\')),
+        (
+            KeyCode(0x0D),
+            Key::new(
+                Sym::Dead(Accent::Acute),
+                Sym::Dead(Accent::Grave),
+                Sym::None,
+            ),
+        ),
+        (KeyCode(0x10), key3('q', 'Q', '@')),
+        (KeyCode(0x12), key3('e', 'E', '€')),
+        (KeyCode(0x15), key('z', 'Z')),
+        (KeyCode(0x1A), key('ü', 'Ü')),
+        (KeyCode(0x1B), key3('+', '*', '~')),
+        (KeyCode(0x27), key('ö', 'Ö')),
+        (KeyCode(0x28), key('ä', 'Ä')),
+        (
+            KeyCode(0x29),
+            Key::new(Sym::Dead(Accent::Circumflex), Sym::Char('°'), Sym::None),
+        ),
+        (KeyCode(0x2B), key('#', '\'')),
+        (KeyCode(0x2C), key('y', 'Y')),
+        (KeyCode(0x32), key3('m', 'M', 'µ')),
+        (KeyCode(0x33), key(',', ';')),
+        (KeyCode(0x34), key('.', ':')),
+        (KeyCode(0x35), key('-', '_')),
+        (KeyCode(0x56), key3('<', '>', '|')),
+    ],
+};
+
+/// What `code` types on a US keyboard.
+const fn us_key(code: KeyCode) -> Key {
+    match code.0 {
+        0x01 => same('\u{1B}'),
+        0x02 => key('1', '!'),
+        0x03 => key('2', '@'),
+        0x04 => key('3', '#'),
+        0x05 => key('4', '$'),
+        0x06 => key('5', '%'),
+        0x07 => key('6', '^'),
+        0x08 => key('7', '&'),
+        0x09 => key('8', '*'),
+        0x0A => key('9', '('),
+        0x0B => key('0', ')'),
+        0x0C => key('-', '_'),
+        0x0D => key('=', '+'),
+        0x0E => same('\u{8}'),
+        0x0F => same('\t'),
+        0x10 => key('q', 'Q'),
+        0x11 => key('w', 'W'),
+        0x12 => key('e', 'E'),
+        0x13 => key('r', 'R'),
+        0x14 => key('t', 'T'),
+        0x15 => key('y', 'Y'),
+        0x16 => key('u', 'U'),
+        0x17 => key('i', 'I'),
+        0x18 => key('o', 'O'),
+        0x19 => key('p', 'P'),
+        0x1A => key('[', '{'),
+        0x1B => key(']', '}'),
+        0x1C | 0x9C => same('\n'),
+        0x1E => key('a', 'A'),
+        0x1F => key('s', 'S'),
+        0x20 => key('d', 'D'),
+        0x21 => key('f', 'F'),
+        0x22 => key('g', 'G'),
+        0x23 => key('h', 'H'),
+        0x24 => key('j', 'J'),
+        0x25 => key('k', 'K'),
+        0x26 => key('l', 'L'),
+        0x27 => key(';', ':'),
+        0x28 => key('\'', '"'),
+        0x29 => key('`', '~'),
+        0x2B | 0x56 => key('\\', '|'),
+        0x2C => key('z', 'Z'),
+        0x2D => key('x', 'X'),
+        0x2E => key('c', 'C'),
+        0x2F => key('v', 'V'),
+        0x30 => key('b', 'B'),
+        0x31 => key('n', 'N'),
+        0x32 => key('m', 'M'),
+        0x33 => key(',', '<'),
+        0x34 => key('.', '>'),
+        0x35 => key('/', '?'),
+        0x37 => same('*'),
+        0x39 => same(' '),
+        // Keypad, as with Num Lock on.
+        0x47 => same('7'),
+        0x48 => same('8'),
+        0x49 => same('9'),
+        0x4A => same('-'),
+        0x4B => same('4'),
+        0x4C => same('5'),
+        0x4D => same('6'),
+        0x4E => same('+'),
+        0x4F => same('1'),
+        0x50 => same('2'),
+        0x51 => same('3'),
+        0x52 => same('0'),
+        0x53 => same('.'),
+        0xB5 => same('/'),
+        _ => Key::NONE,
+    }
+}
@@ -0,0 +1,96 @@
+//! # Kernel Image Layout
+//!
+//! The UEFI loader maps the kernel's `PT_LOAD` segments and records each
+//! segment's virtual range and ELF flags in
+//! [`KernelBootInfo::kernel_segments`]. The linker script gives `.text`,
+//! `.rodata` and `.data`/`.bss` a segment each, so these ranges tell the kernel
+//! where its code, constants and variables live.
+//!
+//! ## W^X
+//!
+//! [`init`] re-applies the segment permissions to the live page tables:
+//! code is mapped read-only and executable, everything else non-executable,
+//! and only writable segments stay writable. A segment asking to be both
+//! writable and executable is mapped writable but not executable.
+//!
+//! ## Introspection
+//!
+//! [`is_kernel_text`] and [`segment_of`] classify addresses, e.g. return
+//! addresses during backtraces or faulting addresses in exception handlers.
+
+use crate::alloc::{FlushTlb, try_with_kernel_vmm};
+use kernel_info::boot::{KernelBootInfo, KernelSegment, KernelSegments};
+use kernel_memory_addresses::VirtualAddress;
+use kernel_sync::SyncOnceCell;
+use log::{info, warn};
+
+static SEGMENTS: SyncOnceCell<KernelSegments> = SyncOnceCell::new();
+
+/// Record the kernel's segments and enforce W^X on them. Calling this again
+/// does nothing.
+///
+/// # Panics
+/// If a segment is not mapped.
+pub fn init(bi: &KernelBootInfo) {
+    if SEGMENTS.get().is_some() {
+        return;
+    }
+    let segments = SEGMENTS.get_or_init(|| bi.kernel_segments.clone());
+
+    for segment in segments.as_slice() {
+        let writable = segment.is_writable();
+        let no_execute = writable || !segment.is_executable();
+        if writable && segment.is_executable() {
+            warn!(
+                "Kernel segment {:#x}..{:#x} is writable and executable; dropping execute",
+                segment.virt_start,
+                segment.virt_end()
+            );
+        }
+
+        try_with_kernel_vmm(FlushTlb::Never, |vmm| {
+            vmm.protect_region(
+                VirtualAddress::new(segment.virt_start),
+                segment.length,
+                writable,
+                no_execute,
+            )
+        })
+        .expect("failed to protect kernel segment");
+
+        info!(
+            "Kernel segment {:#x}..{:#x}: {}{}",
+            segment.virt_start,
+            segment.virt_end(),
+            if writable { "RW" } else { "R" },
+            if no_execute { "" } else { "X" }
+        );
+    }
+}
+
+/// The kernel segment containing `addr`, if any.
+#[must_use]
+pub fn segment_of(addr: VirtualAddress) -> Option<KernelSegment> {
+    SEGMENTS
+        .get()?
+        .as_slice()
+        .iter()
+        .find(|s| s.contains(addr.as_u64()))
+        .copied()
+}
+
+/// Whether `addr` points into the kernel's executable code.
+#[must_use]
+pub fn is_kernel_text(addr: VirtualAddress) -> bool {
+    segment_of(addr).is_some_and(|s| s.is_executable() && !s.is_writable())
+}
+
+/// A short name for the kind of kernel segment containing `addr`.
+#[must_use]
+pub fn describe(addr: VirtualAddress) -> Option<&'static str> {
+    segment_of(addr).map(|s| match (s.is_writable(), s.is_executable()) {
+        (false, true) => "kernel text",
+        (false, false) => "kernel rodata",
+        (true, _) => "kernel data",
+    })
+}
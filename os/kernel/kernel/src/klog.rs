@@ -0,0 +1,360 @@
+//! # Kernel Log Ring
+//!
+//! The kernel's [`log`] backend. Every record is written to the QEMU debug
+//! port (via [`QemuLogger`]) exactly as before, and additionally kept in an
+//! in-memory history of the most recent lines.
+//!
+//! ## Log ring
+//!
+//! Log calls happen in any context, including interrupt handlers, so the
+//! history is a lock-free [`MpscRing`]. When it is full, the oldest line is
+//! evicted (and counted as an overflow) to make room for the newest one.
+//! Lines longer than [`LINE_LEN`] bytes are truncated.
+//!
+//! Userland reads the ring through the `log_read` syscall (see
+//! [`syscall::log`](crate::syscall::log)); lines are removed as they are read.
+//!
+//! ## Command line
+//!
+//! [`configure`] applies two [kernel command line](crate::cmdline) options:
+//!
+//! * `loglevel=` sets the most verbose level logged, by name (`off`, `error`,
+//!   `warn`, `info`, `debug`, `trace`) or as a number from `0` (off) to `5`
+//!   (trace).
+//! * `console=` selects the sinks as a comma-separated list of `qemu` (the
+//!   debug port) and `ring` (the log ring), or `none`. Both are enabled by
+//!   default.
+//!
+//! ## Emergency path
+//!
+//! Panic and fault handlers log through [`emergency!`] (or [`emergency_fmt`]),
+//! which ignores the log level and writes to the enabled sinks without
+//! allocating, locking or waiting for another CPU: the QEMU port takes bytes
+//! as they come, and lines go into the ring with
+//! [`push_evicting`](MpscRing::push_evicting), which evicts at most one line
+//! to make room and drops the new line if that is not enough.
+//!
+//! Every logger call, emergency or not, runs with interrupts disabled and is
+//! counted on its CPU, so NMIs, machine checks and faults are the only ways
+//! to nest them. Each nesting level has its own per-CPU staging line that
+//! the ring line is formatted into, which also keeps it off the small
+//! interrupt stacks. A fault inside the logger is logged one level deeper;
+//! past [`MAX_DEPTH`] levels lines are dropped instead, which ends a fault
+//! loop within the logger. Lines logged while the CPU cannot be told, i.e.
+//! while GS does not point to kernel per-CPU data, only go to the QEMU port.
+
+use crate::cmdline::{self, Param, ParamKind};
+use crate::msr::Ia32GsBaseMsrExt;
+use crate::per_cpu::MAX_CPUS;
+use core::cell::UnsafeCell;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicU8, AtomicU64, AtomicUsize, Ordering};
+use kernel_info::memory::LAST_USERSPACE_ADDRESS;
+use kernel_memory_addresses::VirtualAddress;
+use kernel_qemu::{QemuLogger, qemu_trace};
+use kernel_registers::msr::Ia32GsBaseMsr;
+use kernel_sync::IrqGuard;
+use kernel_sync::ring::{MpscRing, RingStats};
+use log::{Level, LevelFilter, Log, Metadata, Record, SetLoggerError};
+
+/// Maximum number of bytes kept per log line.
+pub const LINE_LEN: usize = 120;
+
+/// Number of lines kept in the log ring.
+const RING_LINES: usize = 64;
+
+/// Most logger calls nested on one CPU; deeper ones are dropped.
+pub const MAX_DEPTH: usize = 3;
+
+pub static LOGLEVEL_PARAM: Param = Param {
+    name: "loglevel",
+    kind: ParamKind::Str,
+    help: "most verbose log level: off, error, warn, info, debug, trace or 0-5",
+};
+
+pub static CONSOLE_PARAM: Param = Param {
+    name: "console",
+    kind: ParamKind::Str,
+    help: "comma-separated log sinks: qemu, ring or none",
+};
+
+/// Sink bit: the QEMU debug port.
+const SINK_QEMU: u8 = 1 << 0;
+
+/// Sink bit: the in-memory log ring.
+const SINK_RING: u8 = 1 << 1;
+
+/// Enabled sinks.
+static SINKS: AtomicU8 = AtomicU8::new(SINK_QEMU | SINK_RING);
+
+/// One formatted log line (`target: message`).
+#[derive(Copy, Clone)]
+pub struct LogLine {
+    pub level: Level,
+    len: u8,
+    text: [u8; LINE_LEN],
+}
+
+impl LogLine {
+    const fn new(level: Level) -> Self {
+        Self {
+            level,
+            len: 0,
+            text: [0; LINE_LEN],
+        }
+    }
+
+    /// The (possibly truncated) message text.
+    pub fn text(&self) -> &str {
+        let bytes = &self.text[..usize::from(self.len)];
+        match core::str::from_utf8(bytes) {
+            Ok(s) => s,
+            // Truncation may have split a character; drop the partial tail.
+            Err(e) => core::str::from_utf8(&bytes[..e.valid_up_to()]).unwrap_or_default(),
+        }
+    }
+}
+
+impl Write for LogLine {
+    #[allow(clippy::cast_possible_truncation)]
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let start = usize::from(self.len);
+        let n = s.len().min(LINE_LEN - start);
+        self.text[start..start + n].copy_from_slice(&s.as_bytes()[..n]);
+        self.len = (start + n) as u8;
+        Ok(())
+    }
+}
+
+struct KernelLogger {
+    qemu: QemuLogger,
+}
+
+static LOGGER: KernelLogger = KernelLogger {
+    qemu: QemuLogger::new(LevelFilter::Debug),
+};
+
+static LOG_RING: MpscRing<LogLine, RING_LINES> = MpscRing::new();
+
+/// Logger state of one CPU.
+struct CpuLog {
+    /// Logger calls in progress on this CPU.
+    depth: AtomicUsize,
+    /// One staging line per nesting level.
+    staging: [UnsafeCell<LogLine>; MAX_DEPTH],
+}
+
+// SAFETY: A staging line is only used by the `Entry` holding its nesting
+// level on its CPU.
+unsafe impl Sync for CpuLog {}
+
+impl CpuLog {
+    const fn new() -> Self {
+        Self {
+            depth: AtomicUsize::new(0),
+            staging: [const { UnsafeCell::new(LogLine::new(Level::Error)) }; MAX_DEPTH],
+        }
+    }
+}
+
+static CPU_LOGS: [CpuLog; MAX_CPUS] = [const { CpuLog::new() }; MAX_CPUS];
+
+/// Lines dropped because logger calls nested more than [`MAX_DEPTH`] deep.
+static NESTED_DROPS: AtomicU64 = AtomicU64::new(0);
+
+/// A logger call in progress on the current CPU.
+struct Entry {
+    /// This CPU's state and the nesting level of the call in it, if the CPU
+    /// is known.
+    slot: Option<(&'static CpuLog, usize)>,
+    _irq: IrqGuard,
+}
+
+impl Entry {
+    /// Enter the logger, or `None` if calls already nest too deep.
+    fn enter() -> Option<Self> {
+        let irq = IrqGuard::new();
+        let Some(cpu) = current_cpu_log() else {
+            return Some(Self {
+                slot: None,
+                _irq: irq,
+            });
+        };
+        let level = cpu.depth.fetch_add(1, Ordering::Acquire);
+        if level >= MAX_DEPTH {
+            cpu.depth.fetch_sub(1, Ordering::Release);
+            NESTED_DROPS.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        Some(Self {
+            slot: Some((cpu, level)),
+            _irq: irq,
+        })
+    }
+
+    /// Format a line into this call's staging line and push it to the ring.
+    fn push_line(&self, level: Level, target: &str, args: &fmt::Arguments) {
+        let Some((cpu, depth)) = self.slot else {
+            return;
+        };
+        // SAFETY: Only this call uses the staging line of its level, and it
+        // does not hand out references to it.
+        let line = unsafe { &mut *cpu.staging[depth].get() };
+        *line = LogLine::new(level);
+        write!(line, "{target}: {args}").ok();
+        // A full ring drops the line.
+        let _ = LOG_RING.push_evicting(*line);
+    }
+}
+
+impl Drop for Entry {
+    fn drop(&mut self) {
+        if let Some((cpu, _)) = self.slot {
+            cpu.depth.fetch_sub(1, Ordering::Release);
+        }
+    }
+}
+
+/// The logger state of the current CPU, or `None` if GS does not point to
+/// per-CPU data of the kernel, e.g. on entry from user mode before `swapgs`.
+fn current_cpu_log() -> Option<&'static CpuLog> {
+    // SAFETY: Reading GS base has no side effects.
+    let cpu = unsafe { <Ia32GsBaseMsr as Ia32GsBaseMsrExt>::read_ptr() };
+    if cpu.is_null() {
+        // Before the per-CPU data is set up, only the BSP runs.
+        return CPU_LOGS.first();
+    }
+    if VirtualAddress::from_ptr(cpu) <= LAST_USERSPACE_ADDRESS {
+        return None;
+    }
+    // SAFETY: A GS base in the kernel half points to this CPU's `PerCpu`.
+    let id = unsafe { (*cpu).cpu_id };
+    CPU_LOGS.get(usize::try_from(id).ok()?)
+}
+
+/// Install the kernel logger. Call once during early boot.
+///
+/// # Errors
+/// If a logger was already installed.
+pub fn init() -> Result<(), SetLoggerError> {
+    log::set_logger(&LOGGER)?;
+    log::set_max_level(LevelFilter::Trace);
+    Ok(())
+}
+
+/// Apply the `loglevel` and `console` command line options.
+///
+/// Call after [`cmdline::init`].
+pub fn configure() {
+    if let Some(value) = cmdline::get_str("loglevel") {
+        let level = cmdline::get_u64("loglevel").map_or_else(
+            || value.parse().ok(),
+            |n| {
+                usize::try_from(n)
+                    .ok()
+                    .and_then(|n| LevelFilter::iter().nth(n))
+            },
+        );
+        match level {
+            Some(level) => log::set_max_level(level),
+            None => warn_invalid("loglevel"),
+        }
+    }
+
+    if let Some(console) = cmdline::get_str("console") {
+        let mut sinks = 0;
+        for sink in console.split(',') {
+            match sink {
+                "qemu" => sinks |= SINK_QEMU,
+                "ring" => sinks |= SINK_RING,
+                "none" => {}
+                _ => warn_invalid("console"),
+            }
+        }
+        SINKS.store(sinks, Ordering::Relaxed);
+    }
+}
+
+fn warn_invalid(key: &str) {
+    log::warn!("Ignoring invalid `{key}` on the kernel command line");
+}
+
+/// Remove the oldest line from the log ring.
+pub fn pop() -> Option<LogLine> {
+    LOG_RING.pop()
+}
+
+/// Hand every line currently in the log ring to `f`, oldest first, and
+/// remove it from the ring.
+#[allow(dead_code)]
+pub fn drain(mut f: impl FnMut(&LogLine)) {
+    while let Some(line) = LOG_RING.pop() {
+        f(&line);
+    }
+}
+
+/// Traffic counters of the log ring.
+#[allow(dead_code)]
+pub fn stats() -> RingStats {
+    LOG_RING.stats()
+}
+
+/// Number of lines dropped because logger calls nested too deep on a CPU.
+#[allow(dead_code)]
+pub fn nested_drops() -> u64 {
+    NESTED_DROPS.load(Ordering::Relaxed)
+}
+
+/// Log `args` as an error from a panic or fault handler; see the
+/// [module docs](self#emergency-path).
+///
+/// Ignores the log level. Does not allocate, take locks or wait for other
+/// CPUs.
+pub fn emergency_fmt(args: fmt::Arguments) {
+    let Some(entry) = Entry::enter() else {
+        return;
+    };
+    let sinks = SINKS.load(Ordering::Relaxed);
+    if sinks & SINK_QEMU != 0 {
+        qemu_trace!("[{}] emergency: {args}\n", Level::Error);
+    }
+    if sinks & SINK_RING != 0 {
+        entry.push_line(Level::Error, "emergency", &args);
+    }
+}
+
+/// Log a message through [`emergency_fmt`], with the arguments of [`format_args!`].
+macro_rules! emergency {
+    ($($arg:tt)*) => {
+        $crate::klog::emergency_fmt(core::format_args!($($arg)*))
+    };
+}
+
+pub(crate) use emergency;
+
+impl Log for KernelLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.qemu.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let Some(entry) = Entry::enter() else {
+            return;
+        };
+
+        let sinks = SINKS.load(Ordering::Relaxed);
+        if sinks & SINK_QEMU != 0 {
+            self.qemu.log(record);
+        }
+        if sinks & SINK_RING != 0 {
+            entry.push_line(record.level(), record.target(), record.args());
+        }
+    }
+
+    fn flush(&self) {
+        self.qemu.flush();
+    }
+}
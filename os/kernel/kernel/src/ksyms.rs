@@ -0,0 +1,119 @@
+//! # Kernel Symbols
+//!
+//! Runtime symbolization of kernel addresses. `packer symbols` extracts the
+//! function and data symbols of the kernel ELF into a sorted
+//! [`packer_abi::symbols`] table, which the loader passes on as the `ksyms`
+//! boot module (see [`boot_modules`](crate::boot_modules)).
+//!
+//! [`init`] picks the table up once the boot modules are mapped; from then on
+//! [`resolve`] turns an address into `(symbol, offset)`. Without the module,
+//! every lookup simply returns `None` and addresses are printed raw.
+//!
+//! ## Backtraces
+//!
+//! The kernel is built with frame pointers, so [`log_backtrace`] can follow
+//! the chain of saved `RBP` values and symbolize each return address. The walk
+//! stops at the first frame that looks implausible, so a corrupted stack
+//! yields a short trace rather than a nested fault.
+
+use crate::klog::emergency;
+use crate::{boot_modules, kimage};
+use core::fmt;
+use kernel_memory_addresses::VirtualAddress;
+use kernel_sync::SyncOnceCell;
+use log::{info, warn};
+use packer_abi::symbols::SymbolTable;
+
+/// Name of the boot module holding the symbol table.
+pub const KSYMS_MODULE: &str = "ksyms";
+
+/// Most frames printed by [`log_backtrace`].
+const MAX_FRAMES: usize = 32;
+
+/// Largest distance between two consecutive frame pointers still trusted.
+const MAX_FRAME_SIZE: u64 = 1 << 20;
+
+static SYMBOLS: SyncOnceCell<Option<SymbolTable<'static>>> = SyncOnceCell::new();
+
+/// Load the symbol table from the `ksyms` boot module. Must run after
+/// [`boot_modules::init`]; calling it again does nothing.
+pub fn init() {
+    SYMBOLS.get_or_init(|| {
+        let Some(bytes) = boot_modules::find(KSYMS_MODULE) else {
+            info!("No kernel symbol table; addresses will not be symbolized");
+            return None;
+        };
+        match SymbolTable::parse(bytes) {
+            Ok(table) => {
+                info!("Loaded {} kernel symbols", table.len());
+                Some(table)
+            }
+            Err(e) => {
+                warn!("Invalid kernel symbol table: {e:?}");
+                None
+            }
+        }
+    });
+}
+
+/// The symbol containing `addr` and the offset of `addr` into it.
+#[must_use]
+pub fn resolve(addr: VirtualAddress) -> Option<(&'static str, u64)> {
+    SYMBOLS.get()?.as_ref()?.resolve(addr.as_u64())
+}
+
+/// Formats an address as `symbol+0xoffset (0xaddress)`, or just the address
+/// if it cannot be resolved.
+#[derive(Copy, Clone)]
+pub struct Symbolized(pub VirtualAddress);
+
+impl fmt::Display for Symbolized {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match resolve(self.0) {
+            Some((name, offset)) => write!(f, "{name}+{offset:#x} ({:#x})", self.0.as_u64()),
+            None => write!(f, "{:#x}", self.0.as_u64()),
+        }
+    }
+}
+
+/// Log the call chain leading up to this call, starting with the caller.
+pub fn log_backtrace() {
+    let rbp: u64;
+    // SAFETY: Reading RBP has no side effects.
+    unsafe {
+        core::arch::asm!("mov {}, rbp", out(reg) rbp, options(nomem, nostack, preserves_flags));
+    }
+    log_backtrace_from(rbp);
+}
+
+/// Log the call chain starting at the frame pointed to by `rbp`.
+pub fn log_backtrace_from(mut rbp: u64) {
+    emergency!("Backtrace:");
+    for frame in 0..MAX_FRAMES {
+        if !plausible_frame(rbp) {
+            break;
+        }
+
+        // SAFETY: `rbp` is an aligned higher-half address; frames hold the
+        // caller's RBP followed by the return address.
+        let (next, ret) = unsafe {
+            let p = rbp as *const u64;
+            (p.read(), p.add(1).read())
+        };
+        let ret = VirtualAddress::new(ret);
+        if !kimage::is_kernel_text(ret) {
+            break;
+        }
+        emergency!("  #{frame:<2} {}", Symbolized(ret));
+
+        if next <= rbp || next - rbp > MAX_FRAME_SIZE {
+            break;
+        }
+        rbp = next;
+    }
+}
+
+/// Whether `rbp` may point at a stack frame in kernel memory.
+const fn plausible_frame(rbp: u64) -> bool {
+    rbp >= 0xFFFF_8000_0000_0000 && rbp.is_multiple_of(8)
+}
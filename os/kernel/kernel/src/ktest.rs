@@ -0,0 +1,197 @@
+//! # In-Kernel Tests
+//!
+//! Integration tests that run inside the booted kernel, for code paths that
+//! only exist on real (or emulated) hardware: paging, the frame allocator,
+//! system calls and the like. Only compiled with the `ktest` feature.
+//!
+//! ## Declaring tests
+//!
+//! Tests are plain functions marked with
+//! [`#[kernel_test]`](kernel_test::kernel_test); a test fails by panicking,
+//! or by not panicking if it is marked `should_panic`:
+//!
+//! ```ignore
+//! #[kernel_test]
+//! fn frames_are_counted() {
+//!     assert!(frame_stats().total > 0);
+//! }
+//! ```
+//!
+//! Each test is a [`KernelTest`] placed in the `.ktests` linker section,
+//! which `kernel.ld` keeps and brackets with `__ktests_start` /
+//! `__ktests_end`. Tests can therefore live next to the code they cover, in
+//! this crate or in a library crate linked into the kernel: the `ktest`
+//! feature also enables the `kernel-test` features of `kernel-vmem` and
+//! `kernel-alloc`.
+//!
+//! ## Running
+//!
+//! With the feature enabled, early init calls [`run`] instead of entering the
+//! kernel main loop. It runs every test in link order and reports over the
+//! QEMU debug console:
+//!
+//! ```text
+//! ktest: running 9 tests
+//! ktest: kernel::ktest::paging::map_write_unmap ... ok
+//! ktest:   panicked at kernel-vmem/src/ktests.rs:40:9: ...
+//! ktest: kernel_vmem::ktests::some_test ... FAILED
+//! ktest: result: FAILED. 8 passed; 1 failed
+//! ```
+//!
+//! A panic during a test does not end the run: before each test, the runner
+//! saves its callee-saved registers, stack pointer and flags, and the panic
+//! handler calls [`on_panic`], which jumps back there. The panicking test's
+//! stack frames are abandoned without running destructors, so locks it held
+//! stay locked; tests should not panic while holding shared locks.
+//!
+//! Afterwards the runner terminates QEMU through the `isa-debug-exit` device
+//! with [`QemuExitCode::Success`] or [`QemuExitCode::Failed`]. A panic outside
+//! a test exits with [`QemuExitCode::Failed`] as well. `task qemu:test` builds
+//! the kernel with the feature and maps the exit status back to `0` or `1`.
+//!
+//! Without the exit device, the runner [shuts down](crate::power) instead:
+//! orderly after the last test, straight to ACPI power-off after a panic,
+//! when locks may be held. Either way the run ends rather than hanging.
+
+mod ahci;
+mod apic;
+mod boot_alloc;
+mod caps;
+mod chardev;
+mod clock_page;
+mod dir;
+mod dma;
+mod elf;
+mod extable;
+mod fb_device;
+mod file_pages;
+mod font;
+mod fpu;
+mod fw_cfg;
+mod handle;
+mod heap;
+mod hhdm;
+mod hotplug;
+mod image;
+mod irq_stats;
+mod irq_thread;
+mod kdb;
+mod keyboard;
+mod klog;
+mod layout;
+mod nvme;
+mod page_cache;
+mod paging;
+mod pipe;
+mod pit;
+mod poll;
+mod power;
+mod preempt;
+mod process;
+mod procfs;
+mod pstore;
+mod random;
+mod rtc;
+mod run_queue;
+mod runner;
+mod signal;
+mod stack_growth;
+mod syscall;
+mod timer;
+mod tsc;
+mod tty;
+mod uaccess;
+mod workqueue;
+
+use crate::power::{PowerAction, halt, power_off, shutdown};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicPtr, Ordering};
+use kernel_qemu::{QemuExitCode, qemu_trace, request_exit};
+use kernel_test::{KernelTest, kernel_test};
+use runner::Recovery;
+
+// Declared as `u64` for the alignment `kernel.ld` gives the section.
+unsafe extern "C" {
+    static __ktests_start: u64;
+    static __ktests_end: u64;
+}
+
+/// Where [`on_panic`] resumes while a test is running.
+static RECOVERY: AtomicPtr<Recovery> = AtomicPtr::new(core::ptr::null_mut());
+
+/// Every test linked into the kernel.
+fn tests() -> &'static [KernelTest] {
+    let start = (&raw const __ktests_start).cast::<KernelTest>();
+    let end = &raw const __ktests_end;
+    let len = (end as usize - start as usize) / size_of::<KernelTest>();
+    // SAFETY: The linker script places only `KernelTest` statics between the markers.
+    unsafe { core::slice::from_raw_parts(start, len) }
+}
+
+/// Run all tests and exit QEMU with the result; does not return.
+///
+/// Declared as returning `()` so the regular boot path after the call still
+/// type-checks without the feature.
+pub fn run() {
+    let tests = tests();
+    qemu_trace!("ktest: running {} tests\n", tests.len());
+
+    let mut failed = 0;
+    for test in tests {
+        let mut recovery = Recovery::new();
+        RECOVERY.store(&raw mut recovery, Ordering::Release);
+        let panicked = unsafe { runner::call_guarded(&raw mut recovery, test) };
+        RECOVERY.store(core::ptr::null_mut(), Ordering::Release);
+
+        let verdict = match (panicked, test.should_panic) {
+            (false, false) | (true, true) => "ok",
+            (true, false) => "FAILED",
+            (false, true) => "FAILED (did not panic)",
+        };
+        if verdict != "ok" {
+            failed += 1;
+        }
+        qemu_trace!("ktest: {test} ... {verdict}\n");
+    }
+
+    if failed == 0 {
+        qemu_trace!("ktest: result: ok. {} passed\n", tests.len());
+        finish(QemuExitCode::Success)
+    } else {
+        qemu_trace!(
+            "ktest: result: FAILED. {} passed; {failed} failed\n",
+            tests.len() - failed
+        );
+        finish(QemuExitCode::Failed)
+    }
+}
+
+/// Exit QEMU with `code`, or power off without the exit device.
+fn finish(code: QemuExitCode) -> ! {
+    request_exit(code);
+    shutdown(PowerAction::PowerOff)
+}
+
+/// Called first by the panic handler: resumes the test runner if a test is
+/// running, and returns otherwise.
+pub fn on_panic(info: &PanicInfo) {
+    let recovery = RECOVERY.swap(core::ptr::null_mut(), Ordering::AcqRel);
+    if !recovery.is_null() {
+        qemu_trace!("ktest:   {info}\n");
+        unsafe { runner::resume(recovery) }
+    }
+}
+
+/// Exit QEMU after a panic outside a test; called last by the panic handler
+/// and does not return.
+pub fn abort() {
+    qemu_trace!("ktest: result: FAILED before all tests ran\n");
+    request_exit(QemuExitCode::Failed);
+    power_off();
+    halt()
+}
+
+#[kernel_test(should_panic)]
+fn panics_are_caught() {
+    panic!("expected panic");
+}
@@ -0,0 +1,55 @@
+//! SATA disks through the block device interface; on q35 the ESP drive is
+//! `sda`, and it is only read from.
+
+use crate::ahci::BOUNCE_LEN;
+use crate::alloc::dma::{DmaBuffer, DmaConstraints};
+use crate::block::{self, BlockError};
+use kernel_test::kernel_test;
+
+/// More than a bounce buffer, so transfers are split.
+const LEN: usize = BOUNCE_LEN + 4096;
+
+/// Scratch memory; there is no heap.
+fn scratch() -> DmaBuffer {
+    DmaBuffer::alloc(LEN, DmaConstraints::ANY).expect("no scratch buffer")
+}
+
+#[kernel_test]
+fn first_sector_holds_the_partition_table() {
+    let Some(disk) = block::lookup("sda") else {
+        return;
+    };
+    let mut buf = scratch();
+    disk.read(0, &mut buf[..disk.block_size()])
+        .expect("read failed");
+    assert_eq!(buf[510..512], [0x55, 0xAA], "no boot signature");
+}
+
+#[kernel_test]
+fn split_reads_match_single_reads() {
+    let Some(disk) = block::lookup("sda") else {
+        return;
+    };
+    let bs = disk.block_size();
+    let mut whole = scratch();
+    disk.read(0, &mut whole).expect("read failed");
+    let mut single = scratch();
+    for (i, block) in whole.chunks_exact(bs).enumerate().step_by(17) {
+        disk.read(i as u64, &mut single[..bs]).expect("read failed");
+        assert_eq!(&single[..bs], block, "block {i} differs");
+    }
+}
+
+#[kernel_test]
+fn transfers_off_the_disk_are_refused() {
+    let Some(disk) = block::lookup("sda") else {
+        return;
+    };
+    let mut buf = scratch();
+    let bs = disk.block_size();
+    assert_eq!(
+        disk.read(disk.blocks(), &mut buf[..bs]),
+        Err(BlockError::OutOfRange)
+    );
+    assert_eq!(disk.write(0, &buf[..bs - 1]), Err(BlockError::Misaligned));
+}
@@ -0,0 +1,19 @@
+//! The local vector table and the error status register.
+
+use crate::apic::lvt::{self, ApicErrors, DeliveryMode, Lvt};
+use crate::interrupts::apic_error::APIC_ERROR_VECTOR;
+use kernel_test::kernel_test;
+
+#[kernel_test]
+fn error_entry_is_unmasked() {
+    let entry = Lvt::Error.read();
+    assert_eq!(entry.vector(), APIC_ERROR_VECTOR);
+    assert_eq!(entry.delivery_mode(), DeliveryMode::Fixed);
+    assert!(!entry.masked());
+}
+
+#[kernel_test]
+fn reading_the_esr_clears_it() {
+    let _ = lvt::read_esr();
+    assert_eq!(lvt::read_esr(), ApicErrors::new());
+}
@@ -0,0 +1,65 @@
+//! Bump allocation and retirement of the early boot allocator.
+
+use crate::alloc::with_kernel_frame_alloc;
+use crate::boot_alloc::{BootAlloc, BootAllocError};
+use kernel_info::boot::BootArenaInfo;
+use kernel_memory_addresses::{PageSize, PhysicalPage, Size4K};
+use kernel_test::kernel_test;
+use kernel_vmem::PhysFrameAlloc;
+
+#[kernel_test]
+fn allocations_are_aligned_and_retired() {
+    const FRAMES: usize = 2;
+    /// More than the arena holds.
+    const TOO_LARGE: usize = FRAMES * 4096;
+
+    let first = with_kernel_frame_alloc(|pmm| pmm.alloc_contiguous_4k(FRAMES))
+        .expect("no frames for the arena");
+    let info = BootArenaInfo {
+        phys_start: first.base().as_u64(),
+        length: FRAMES as u64 * Size4K::SIZE,
+    };
+
+    let mut arena = unsafe { BootAlloc::new(&info) }.expect("arena rejected");
+    let byte = arena.alloc(0xA5u8).expect("no room for a byte");
+    let word = arena.alloc(0x1234_5678_u64).expect("no room for a word");
+    assert_eq!(*byte, 0xA5);
+    assert_eq!(*word, 0x1234_5678);
+    assert!(core::ptr::from_ref(word).is_aligned());
+    assert_eq!(
+        arena.alloc([0u8; TOO_LARGE]).err(),
+        Some(BootAllocError::OutOfMemory(TOO_LARGE))
+    );
+
+    let (used, unused) = arena.retire();
+    assert_eq!(used.start, info.phys_start);
+    assert_eq!(used.len(), Size4K::SIZE);
+    assert_eq!(unused.start, used.end);
+    assert_eq!(unused.end, info.phys_start + info.length);
+
+    with_kernel_frame_alloc(|pmm| {
+        for i in 0..FRAMES as u64 {
+            pmm.free_4k(PhysicalPage::from_addr(first.base() + i * Size4K::SIZE));
+        }
+    });
+}
+
+#[kernel_test]
+fn bad_arenas_are_rejected() {
+    let missing = BootArenaInfo {
+        phys_start: 0,
+        length: 0,
+    };
+    let misaligned = BootArenaInfo {
+        phys_start: 0x1000_0800,
+        length: Size4K::SIZE,
+    };
+    assert_eq!(
+        unsafe { BootAlloc::new(&missing) }.err(),
+        Some(BootAllocError::Missing)
+    );
+    assert_eq!(
+        unsafe { BootAlloc::new(&misaligned) }.err(),
+        Some(BootAllocError::Misaligned)
+    );
+}
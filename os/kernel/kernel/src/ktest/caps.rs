@@ -0,0 +1,72 @@
+//! Capability sets: inheritance, dropping, and the system calls outside of
+//! a process.
+
+use crate::process::Pid;
+use crate::process::caps::{self, Capabilities};
+use crate::syscall::{SyscallSource, syscall};
+use kernel_test::kernel_test;
+use stdlib::syscall_abi::caps::{CAP_DEVICE_CONSOLE, CAP_NET, CAP_REBOOT, EFFECTIVE, INHERITABLE};
+use stdlib::syscall_abi::{SYSCALL_ERROR, Sysno};
+
+#[kernel_test]
+fn children_get_the_inheritable_set() {
+    let parent = Capabilities::ALL
+        .with(INHERITABLE, CAP_DEVICE_CONSOLE)
+        .expect("subset refused");
+    let child = parent.spawned();
+    assert!(child.has(CAP_DEVICE_CONSOLE));
+    assert!(!child.has(CAP_REBOOT));
+    assert_eq!(child.inheritable(), CAP_DEVICE_CONSOLE);
+}
+
+#[kernel_test]
+fn dropped_capabilities_cannot_be_regained() {
+    let caps = Capabilities::ALL
+        .with(EFFECTIVE, CAP_REBOOT | CAP_NET)
+        .expect("subset refused");
+    assert_eq!(caps.inheritable(), CAP_REBOOT | CAP_NET);
+    assert_eq!(caps.with(EFFECTIVE, CAP_DEVICE_CONSOLE), None);
+    assert_eq!(caps.with(INHERITABLE, CAP_DEVICE_CONSOLE), None);
+    assert_eq!(caps.with(2, CAP_NET), None);
+    assert!(Capabilities::NONE.with(EFFECTIVE, 0).is_some());
+}
+
+#[kernel_test]
+fn kernel_code_holds_no_capabilities() {
+    let get = Sysno::CapGet as u64;
+    let set = Sysno::CapSet as u64;
+    assert_eq!(
+        syscall(get, EFFECTIVE, 0, 0, 0, 0, 0, SyscallSource::Syscall),
+        0
+    );
+    assert_eq!(
+        syscall(get, 2, 0, 0, 0, 0, 0, SyscallSource::Syscall),
+        SYSCALL_ERROR
+    );
+    assert_eq!(
+        syscall(set, EFFECTIVE, 0, 0, 0, 0, 0, SyscallSource::Syscall),
+        SYSCALL_ERROR
+    );
+}
+
+#[kernel_test]
+fn other_processes_need_the_process_capability() {
+    // Kernel code is no process's parent and holds no capabilities.
+    assert!(!caps::may_control(Pid::INIT, "ktest"));
+    assert!(!caps::may_signal(Pid::INIT));
+
+    let init = Pid::INIT.as_u64();
+    for (sysno, arg) in [
+        (Sysno::Kill, 0),
+        (Sysno::SetPriority, 1),
+        (Sysno::SchedSetAffinity, 1),
+        (Sysno::Trace, 0),
+    ] {
+        assert_eq!(
+            syscall(sysno as u64, init, arg, 0, 0, 0, 0, SyscallSource::Syscall),
+            SYSCALL_ERROR,
+            "{} refused",
+            sysno.name()
+        );
+    }
+}
@@ -0,0 +1,73 @@
+//! Character device registration, lookup and transfers.
+
+use crate::chardev::{self, CharDevice};
+use crate::sched::WaitQueue;
+use kernel_sync::{IrqGuard, SpinMutex};
+use kernel_test::kernel_test;
+
+/// Hands back what was written to it, at most [`Loopback::CAPACITY`] bytes
+/// per write.
+struct Loopback {
+    buffer: SpinMutex<([u8; 8], usize)>,
+    readable: WaitQueue,
+}
+
+impl Loopback {
+    const CAPACITY: usize = 8;
+}
+
+impl CharDevice for Loopback {
+    fn name(&self) -> &'static str {
+        "ktest-loop"
+    }
+
+    fn read(&self, buf: &mut [u8]) -> usize {
+        let _irq = IrqGuard::new();
+        let (data, len) = &mut *self.buffer.lock();
+        let n = (*len).min(buf.len());
+        buf[..n].copy_from_slice(&data[..n]);
+        data.copy_within(n..*len, 0);
+        *len -= n;
+        n
+    }
+
+    fn write(&self, bytes: &[u8]) -> usize {
+        let _irq = IrqGuard::new();
+        let (data, len) = &mut *self.buffer.lock();
+        let n = (Self::CAPACITY - *len).min(bytes.len());
+        data[*len..*len + n].copy_from_slice(&bytes[..n]);
+        *len += n;
+        n
+    }
+
+    fn has_input(&self) -> bool {
+        let _irq = IrqGuard::new();
+        self.buffer.lock().1 > 0
+    }
+
+    fn readable(&self) -> &WaitQueue {
+        &self.readable
+    }
+}
+
+static LOOPBACK: Loopback = Loopback {
+    buffer: SpinMutex::new(([0; 8], 0)),
+    readable: WaitQueue::new(),
+};
+
+#[kernel_test]
+fn devices_are_found_under_dev() {
+    let id = chardev::register(&LOOPBACK).expect("registering the device failed");
+    assert_eq!(chardev::lookup("/dev/ktest-loop"), Some(id));
+    assert_eq!(chardev::lookup("/dev/ktest-loo"), None);
+    assert_eq!(chardev::lookup("ktest-loop"), None);
+    assert_eq!(chardev::lookup("/proc/ktest-loop"), None);
+
+    // Writes stop short once the device stays busy.
+    assert_eq!(chardev::write(id, b"0123456789"), 8);
+    let mut buf = [0u8; 5];
+    assert_eq!(chardev::read(id, &mut buf), 5);
+    assert_eq!(&buf, b"01234");
+    assert_eq!(chardev::read(id, &mut buf), 3);
+    assert_eq!(&buf[..3], b"567");
+}
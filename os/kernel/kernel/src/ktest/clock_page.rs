@@ -0,0 +1,23 @@
+//! The clock page userland reads its clocks from.
+
+use crate::{clock, clock_page};
+use core::sync::atomic::Ordering;
+use kernel_test::kernel_test;
+
+#[kernel_test]
+fn page_mirrors_the_clock_parameters() {
+    let page = clock_page::page().unwrap();
+    let params = clock::params();
+    assert_eq!(page.sequence.load(Ordering::Acquire) % 2, 0);
+    assert_eq!(page.tsc_hz.load(Ordering::Relaxed), params.tsc_hz);
+    assert_eq!(page.wall_secs.load(Ordering::Relaxed), params.wall_secs);
+    assert_eq!(page.wall_tsc.load(Ordering::Relaxed), params.wall_tsc);
+}
+
+#[kernel_test]
+fn publishing_advances_the_sequence() {
+    let page = clock_page::page().unwrap();
+    let before = page.sequence.load(Ordering::Acquire);
+    clock_page::publish(&clock::params());
+    assert_eq!(page.sequence.load(Ordering::Acquire), before + 2);
+}
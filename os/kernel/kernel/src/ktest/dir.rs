@@ -0,0 +1,45 @@
+//! Listings of the fixed directories.
+
+use crate::dir;
+use kernel_test::kernel_test;
+use stdlib::syscall_abi::DirEntry;
+use stdlib::syscall_abi::dirent::{KIND_DEVICE, KIND_DIR, KIND_FILE};
+
+#[kernel_test]
+fn proc_lists_files_then_self() {
+    let (first, next) = dir::read("/proc", 0).unwrap();
+    assert_eq!((first.name(), first.kind, next), ("meminfo", KIND_FILE, 1));
+
+    let (last, next) = dir::read("/proc/", 4).unwrap();
+    assert_eq!((last.name(), last.kind), ("self", KIND_DIR));
+    assert!(dir::read("/proc", next).is_none());
+
+    let (maps, _) = dir::read("/proc/self", 0).unwrap();
+    assert_eq!(maps.name(), "maps");
+    assert!(dir::read("/proc/nope", 0).is_none());
+    assert!(dir::read("/nope", 0).is_none());
+}
+
+#[kernel_test]
+fn root_ends_with_dev_and_proc() {
+    let mut last = [DirEntry::default(); 2];
+    let mut cursor = 0;
+    while let Some((entry, next)) = dir::read("/", cursor) {
+        last = [last[1], entry];
+        cursor = next;
+    }
+    assert_eq!([last[0].name(), last[1].name()], ["dev", "proc"]);
+    assert_eq!(last.map(|entry| entry.kind), [KIND_DIR; 2]);
+}
+
+#[kernel_test]
+fn dev_lists_the_console() {
+    let mut cursor = 0;
+    let mut found = false;
+    while let Some((entry, next)) = dir::read("/dev", cursor) {
+        assert_eq!(entry.kind, KIND_DEVICE);
+        found |= entry.name() == "console";
+        cursor = next;
+    }
+    assert!(found);
+}
@@ -0,0 +1,43 @@
+//! DMA buffers from the kernel frame allocator.
+
+use crate::alloc::dma::{DmaBuffer, DmaConstraints, DmaError};
+use crate::alloc::{frame_stats, with_kernel_vmm};
+use kernel_alloc::frame_alloc::Zone;
+use kernel_info::memory::HHDM_BASE;
+use kernel_test::kernel_test;
+
+#[kernel_test]
+fn buffers_are_zeroed_and_placed_as_asked() {
+    let constraints = DmaConstraints::BELOW_4G.with_align(0x4000);
+    let mut buffer = DmaBuffer::alloc(5000, constraints).expect("no DMA buffer");
+    let pa = buffer.phys().as_u64();
+    assert!(pa + 5000 <= Zone::Below4G.end(), "buffer at {pa:#x}");
+    assert!(pa.is_multiple_of(0x4000), "buffer at {pa:#x}");
+    assert_eq!(buffer.len(), 5000);
+    assert!(buffer.iter().all(|&b| b == 0));
+
+    // The CPU and the device see the same bytes.
+    assert_eq!(buffer.virt(), HHDM_BASE + pa);
+    let mut mapped = None;
+    with_kernel_vmm(|vmm| mapped = vmm.query(buffer.virt() + 4096));
+    assert_eq!(mapped, Some(buffer.phys_at(4096)));
+    buffer[4999] = 0xAB;
+    assert_eq!(unsafe { buffer.as_ptr().add(4999).read_volatile() }, 0xAB);
+}
+
+#[kernel_test]
+fn dropping_a_buffer_frees_its_frames() {
+    let used = frame_stats().used;
+    let buffer = DmaBuffer::alloc(3 * 4096, DmaConstraints::ANY).expect("no DMA buffer");
+    assert_eq!(frame_stats().used, used + 3);
+    drop(buffer);
+    assert_eq!(frame_stats().used, used);
+}
+
+#[kernel_test]
+fn empty_buffers_are_refused() {
+    assert_eq!(
+        DmaBuffer::alloc(0, DmaConstraints::ANY).err(),
+        Some(DmaError::Empty)
+    );
+}
@@ -0,0 +1,85 @@
+//! Reading the relocations of position-independent programs.
+
+use crate::elf::helpers::pie_bias;
+use crate::elf::{ElfErr, R_X86_64_RELATIVE, Rela, elf64_view};
+use kernel_test::kernel_test;
+
+const DT_NEEDED: u64 = 1;
+const DT_RELA: u64 = 7;
+const DT_RELASZ: u64 = 8;
+
+/// A minimal `ET_DYN` image: one `PT_LOAD` segment covering the whole file
+/// at address 0 and a `PT_DYNAMIC` holding `dynamic` at `0x100`, followed by
+/// one relative relocation at `0x180`.
+fn image(dynamic: &[(u64, u64)]) -> [u8; 0x200] {
+    let mut b = [0u8; 0x200];
+    let mut put = |at: usize, bytes: &[u8]| b[at..at + bytes.len()].copy_from_slice(bytes);
+
+    put(0, b"\x7FELF\x02\x01\x01");
+    put(16, &3u16.to_le_bytes()); // ET_DYN
+    put(18, &62u16.to_le_bytes()); // EM_X86_64
+    put(20, &1u32.to_le_bytes());
+    put(24, &0x40u64.to_le_bytes()); // entry
+    put(32, &64u64.to_le_bytes()); // phoff
+    put(52, &64u16.to_le_bytes());
+    put(54, &56u16.to_le_bytes());
+    put(56, &2u16.to_le_bytes());
+
+    // type, flags, offset, vaddr, paddr, filesz, memsz, align
+    let phdrs: [[u64; 7]; 2] = [
+        [1 | (6 << 32), 0, 0, 0, 0x200, 0x1000, 0x1000], // PT_LOAD, RW
+        [2 | (6 << 32), 0x100, 0x100, 0x100, 0x80, 0x80, 8], // PT_DYNAMIC
+    ];
+    for (i, ph) in phdrs.iter().enumerate() {
+        for (j, field) in ph.iter().enumerate() {
+            put(64 + i * 56 + j * 8, &field.to_le_bytes());
+        }
+    }
+
+    for (i, (tag, value)) in dynamic.iter().enumerate() {
+        put(0x100 + i * 16, &tag.to_le_bytes());
+        put(0x108 + i * 16, &value.to_le_bytes());
+    }
+
+    put(0x180, &0x1F8u64.to_le_bytes());
+    put(0x188, &u64::from(R_X86_64_RELATIVE).to_le_bytes());
+    put(0x190, &0x40i64.to_le_bytes());
+    b
+}
+
+#[kernel_test]
+fn pie_relocations_are_read() {
+    let bytes = image(&[(DT_RELA, 0x180), (DT_RELASZ, 24)]);
+    let view = elf64_view(&bytes).unwrap();
+    assert!(view.is_pie());
+    assert_eq!(view.phdr_vaddr(), Some(64));
+
+    let mut relocations = view.relocations().unwrap();
+    assert_eq!(
+        relocations.next(),
+        Some(Rela {
+            r_offset: 0x1F8,
+            r_type: R_X86_64_RELATIVE,
+            r_sym: 0,
+            r_addend: 0x40,
+        })
+    );
+    assert_eq!(relocations.next(), None);
+    assert!(view.is_loaded(0x1F8, 8));
+    assert!(!view.is_loaded(0xFFC, 8));
+}
+
+#[kernel_test]
+fn pie_is_moved_to_the_aligned_base() {
+    let bytes = image(&[]);
+    let view = elf64_view(&bytes).unwrap();
+    assert_eq!(pie_bias(&view, 0x5555_0000), Some(0x5555_0000));
+    assert_eq!(pie_bias(&view, 0x5555_0123), Some(0x5555_1000));
+}
+
+#[kernel_test]
+fn shared_objects_are_refused() {
+    let bytes = image(&[(DT_NEEDED, 1), (DT_RELA, 0x180), (DT_RELASZ, 24)]);
+    let view = elf64_view(&bytes).unwrap();
+    assert!(matches!(view.relocations(), Err(ElfErr::NeedsLibs)));
+}
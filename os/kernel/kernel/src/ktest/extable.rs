@@ -0,0 +1,50 @@
+//! Resuming after expected faults.
+
+use crate::extable::probe_read_u32;
+use kernel_info::memory::HHDM_BASE;
+use kernel_registers::msr::{Msr, MsrFault, try_rdmsr, try_wrmsr};
+use kernel_test::kernel_test;
+
+/// `IA32_APIC_BASE`, present on every x86-64 CPU.
+const IA32_APIC_BASE: Msr = Msr(0x1B);
+
+/// Outside of every architectural and vendor MSR range.
+const MISSING: Msr = Msr(0xDEAD_0000);
+
+/// Unmapped kernel address, as in the paging tests.
+const UNMAPPED: u64 = HHDM_BASE.as_u64() + (5u64 << 40);
+
+/// Outside of the canonical address ranges.
+const NON_CANONICAL: u64 = 0x8000_0000_0000_0000;
+
+#[kernel_test]
+fn present_msrs_read_like_rdmsr() {
+    let expected = unsafe { IA32_APIC_BASE.load_raw() };
+    assert_eq!(unsafe { try_rdmsr(IA32_APIC_BASE) }, Ok(expected));
+}
+
+#[kernel_test]
+fn missing_msrs_return_an_error() {
+    assert_eq!(unsafe { try_rdmsr(MISSING) }, Err(MsrFault(MISSING)));
+    assert_eq!(unsafe { try_wrmsr(MISSING, 0) }, Err(MsrFault(MISSING)));
+
+    // The kernel keeps running, and so do later probes.
+    assert!(unsafe { try_rdmsr(IA32_APIC_BASE) }.is_ok());
+}
+
+#[kernel_test]
+fn probes_read_mapped_memory() {
+    let value = 0x1234_5678u32;
+    let addr = core::ptr::from_ref(&value) as u64;
+    assert_eq!(unsafe { probe_read_u32(addr) }, Some(value));
+}
+
+#[kernel_test]
+fn probes_recover_from_page_faults() {
+    assert_eq!(unsafe { probe_read_u32(UNMAPPED) }, None);
+}
+
+#[kernel_test]
+fn probes_recover_from_general_protection_faults() {
+    assert_eq!(unsafe { probe_read_u32(NON_CANONICAL) }, None);
+}
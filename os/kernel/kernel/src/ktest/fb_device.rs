@@ -0,0 +1,41 @@
+//! The framebuffer device's pixel layout and back buffer pages.
+
+use crate::chardev::CharDevice;
+use crate::framebuffer::Color;
+use crate::framebuffer::compositor::with_compositor;
+use crate::framebuffer::device::FB0;
+use crate::framebuffer::pixel::PixelFormat;
+use kernel_memory_addresses::{PageSize, Size4K};
+use kernel_test::kernel_test;
+use stdlib::syscall_abi::FbInfo;
+
+#[kernel_test]
+fn userland_packs_like_the_kernel() {
+    // 5:6:5, and a bitmask with a 10-bit channel.
+    for format in [
+        PixelFormat::BGR,
+        PixelFormat::from_masks(0xF800, 0x07E0, 0x001F, 0),
+        PixelFormat::from_masks(0x3FF0_0000, 0x000F_FC00, 0x0000_03FF, 0xC000_0000),
+    ] {
+        let [red_mask, green_mask, blue_mask] = format.masks();
+        let info = FbInfo {
+            red_mask,
+            green_mask,
+            blue_mask,
+            opaque: format.opaque(),
+            ..FbInfo::default()
+        };
+        let color = Color::new(0x12, 0xAB, 0xF0);
+        assert_eq!(info.pack(color.r, color.g, color.b), format.pack(color));
+    }
+}
+
+#[kernel_test]
+fn back_buffer_pages_end_with_the_buffer() {
+    let Some(len) = with_compositor(|c| c.back_buffer_len()) else {
+        return;
+    };
+    assert!(FB0.memory_page(0).is_some(), "first page not mappable");
+    assert!(FB0.memory_page(len - Size4K::SIZE).is_some());
+    assert_eq!(FB0.memory_page(len), None);
+}
@@ -0,0 +1,36 @@
+//! File-backed pages: bundle entries through the page cache.
+
+use crate::bundlefs::{self, EntryPages};
+use crate::page_cache::{self, CacheError, PAGE_LEN, PageSource};
+use crate::process::Pid;
+use crate::process::file_pages::{FaultError, fault_in};
+use kernel_memory_addresses::VirtualAddress;
+use kernel_test::kernel_test;
+
+#[kernel_test]
+fn entry_pages_match_the_bundle() {
+    let Some((_name, bytes)) = bundlefs::entry(0) else {
+        return;
+    };
+    let source = EntryPages(0);
+    assert_eq!(source.len(), bytes.len() as u64);
+
+    let page = page_cache::get(&source, 0).expect("get failed");
+    let len = bytes.len().min(PAGE_LEN);
+    assert_eq!(&page.bytes()[..len], &bytes[..len]);
+    assert!(
+        page.bytes()[len..].iter().all(|&b| b == 0),
+        "tail not zeroed"
+    );
+    assert_eq!(
+        page_cache::get(&source, source.pages()).err(),
+        Some(CacheError::OutOfRange)
+    );
+}
+
+#[kernel_test]
+fn unknown_processes_map_nothing() {
+    let pid = Pid::from_raw(0xFFFF_FFFF).unwrap();
+    let addr = VirtualAddress::new(0x40_0000);
+    assert_eq!(fault_in(pid, addr, false), Err(FaultError::NotFile));
+}
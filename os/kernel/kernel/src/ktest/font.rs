@@ -0,0 +1,102 @@
+//! Loading PSF2 fonts and drawing text in them.
+
+use crate::framebuffer::Color;
+use crate::framebuffer::font::{Font, GLYPH_HEIGHT, GLYPH_WIDTH};
+use crate::framebuffer::pixel::PixelFormat;
+use crate::framebuffer::psf2::{FontError, Psf2Font};
+use crate::framebuffer::surface::Surface;
+use kernel_test::kernel_test;
+
+/// A 4x3 PSF2 font of two glyphs: `?` as a bar on top, and a diagonal for
+/// `A`, `Ä` and `Ω`, plus the sequence `A` + `U+0308`.
+#[rustfmt::skip]
+const FONT: [u8; 50] = [
+    0x72, 0xB5, 0x4A, 0x86, // magic
+    0, 0, 0, 0,             // version
+    32, 0, 0, 0,            // header size
+    1, 0, 0, 0,             // flags: Unicode table
+    2, 0, 0, 0,             // glyphs
+    3, 0, 0, 0,             // bytes per glyph
+    3, 0, 0, 0,             // height
+    4, 0, 0, 0,             // width
+    0xF0, 0x00, 0x00,       // glyph 0
+    0x80, 0x40, 0x20,       // glyph 1
+    b'?', 0xFF,             // entry 0
+    b'A', 0xC3, 0x84, 0xCE, 0xA9, 0xFE, b'A', 0xCC, 0x88, 0xFF, // entry 1
+];
+
+#[kernel_test]
+fn psf2_glyphs_are_found_through_the_unicode_table() {
+    let font = Psf2Font::parse(&FONT).unwrap();
+    assert_eq!((font.width(), font.height()), (4, 3));
+
+    let diagonal: &[u8] = &[0x80, 0x40, 0x20];
+    assert_eq!(font.glyph('A'), Some(diagonal));
+    assert_eq!(font.glyph('Ä'), Some(diagonal));
+    assert_eq!(font.glyph('Ω'), Some(diagonal));
+    assert_eq!(font.glyph('?'), Some(&[0xF0, 0, 0][..]));
+
+    // Neither listed nor standalone in a sequence.
+    assert_eq!(font.glyph('x'), None);
+    assert_eq!(font.glyph('\u{308}'), None);
+}
+
+#[kernel_test]
+fn broken_fonts_are_refused() {
+    assert_eq!(
+        Psf2Font::parse(b"\x36\x04\x02\x10").unwrap_err(),
+        FontError::BadMagic
+    );
+    assert_eq!(
+        Psf2Font::parse(&FONT[..36]).unwrap_err(),
+        FontError::Truncated
+    );
+    // The last entry is not terminated.
+    assert_eq!(
+        Psf2Font::parse(&FONT[..49]).unwrap_err(),
+        FontError::Truncated
+    );
+
+    let mut zero_width = FONT;
+    zero_width[28] = 0;
+    assert_eq!(
+        Psf2Font::parse(&zero_width).unwrap_err(),
+        FontError::Unsupported
+    );
+
+    let mut bad_utf8 = FONT;
+    bad_utf8[38] = 0xC3;
+    assert_eq!(
+        Psf2Font::parse(&bad_utf8).unwrap_err(),
+        FontError::InvalidTable
+    );
+}
+
+#[kernel_test]
+fn text_is_drawn_in_the_given_font() {
+    let font = Psf2Font::parse(&FONT).unwrap();
+    let mut pixels = [0; 8 * 3];
+    let mut surface = Surface::new(&mut pixels, 8, 3, PixelFormat::BGR).unwrap();
+
+    // `x` has no glyph and is drawn as `?`.
+    let drawn = surface.draw_text_in(
+        Font::Psf2(&font),
+        0,
+        0,
+        "Ax",
+        Color::WHITE,
+        Some(Color::BLACK),
+    );
+    assert_eq!((drawn.width, drawn.height), (8, 3));
+    for (x, y) in [(0, 0), (1, 1), (2, 2), (4, 0), (7, 0)] {
+        assert_eq!(surface.get(x, y), Some(Color::WHITE), "({x}, {y})");
+    }
+    for (x, y) in [(1, 0), (3, 2), (4, 1), (7, 2)] {
+        assert_eq!(surface.get(x, y), Some(Color::BLACK), "({x}, {y})");
+    }
+
+    assert_eq!(
+        (Font::Builtin.width(), Font::Builtin.height()),
+        (GLYPH_WIDTH, GLYPH_HEIGHT)
+    );
+}
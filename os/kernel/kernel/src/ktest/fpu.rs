@@ -0,0 +1,45 @@
+//! FP/SIMD state save and restore.
+
+use crate::fpu::{self, DEFAULT_MXCSR, FpuState};
+use kernel_sync::IrqGuard;
+use kernel_test::kernel_test;
+
+fn read_mxcsr() -> u32 {
+    let mut mxcsr = 0u32;
+    unsafe {
+        core::arch::asm!("stmxcsr [{}]", in(reg) &raw mut mxcsr, options(nostack));
+    }
+    mxcsr
+}
+
+fn write_mxcsr(mxcsr: u32) {
+    unsafe {
+        core::arch::asm!("ldmxcsr [{}]", in(reg) &raw const mxcsr, options(nostack, readonly));
+    }
+}
+
+#[kernel_test]
+fn fresh_state_has_default_mxcsr() {
+    assert_eq!(FpuState::new().mxcsr(), DEFAULT_MXCSR);
+}
+
+#[kernel_test]
+fn save_restore_round_trips_mxcsr() {
+    let _irq = IrqGuard::new();
+    let original = read_mxcsr();
+
+    // Round toward zero, all exceptions masked.
+    let custom = DEFAULT_MXCSR | (0b11 << 13);
+    write_mxcsr(custom);
+    let mut saved = FpuState::new();
+    unsafe { fpu::save(&mut saved) };
+    assert_eq!(saved.mxcsr(), custom);
+
+    unsafe { fpu::restore(&FpuState::new()) };
+    assert_eq!(read_mxcsr(), DEFAULT_MXCSR);
+
+    unsafe { fpu::restore(&saved) };
+    assert_eq!(read_mxcsr(), custom);
+
+    write_mxcsr(original);
+}
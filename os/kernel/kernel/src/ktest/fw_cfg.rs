@@ -0,0 +1,29 @@
+//! Reading files from QEMU's `fw_cfg` device.
+
+use crate::fw_cfg;
+use kernel_test::kernel_test;
+
+/// Size of an `etc/e820` entry: base, length and type.
+const E820_ENTRY_LEN: u32 = 20;
+
+#[kernel_test]
+fn directory_lists_the_memory_map() {
+    assert!(fw_cfg::available());
+    let e820 = fw_cfg::find("etc/e820").expect("QEMU always passes etc/e820");
+    assert_eq!(e820.name(), "etc/e820");
+    assert!(e820.size() >= E820_ENTRY_LEN);
+    assert_eq!(e820.size() % E820_ENTRY_LEN, 0);
+    assert!(fw_cfg::find("etc/no-such-file").is_none());
+}
+
+#[kernel_test]
+fn load_matches_read() {
+    let e820 = fw_cfg::find("etc/e820").unwrap();
+    let mut head = [0u8; E820_ENTRY_LEN as usize];
+    assert_eq!(fw_cfg::read(e820, &mut head), head.len());
+
+    // Keeps a frame allocated for good.
+    let loaded = fw_cfg::load(e820).unwrap();
+    assert_eq!(loaded.len(), e820.size() as usize);
+    assert_eq!(loaded[..head.len()], head);
+}
@@ -0,0 +1,57 @@
+//! Handle tables: slot allocation, rights and capacity.
+
+use crate::process::handle::{HandleError, HandleTable, MAX_HANDLES, Object};
+use crate::procfs::ProcFile;
+use kernel_test::kernel_test;
+use stdlib::syscall_abi::handle::{ALL_RIGHTS, RIGHT_MAP, RIGHT_READ, RIGHT_WRITE};
+
+const BUNDLE: Object = Object::Bundle {
+    entry: 0,
+    offset: 0,
+};
+
+#[kernel_test]
+fn new_handles_take_the_lowest_free_slot() {
+    let mut table = HandleTable::new();
+    assert_eq!(table.insert(BUNDLE, ALL_RIGHTS), Ok(0));
+    assert_eq!(table.insert(BUNDLE, RIGHT_READ), Ok(1));
+    assert_eq!(table.insert(BUNDLE, RIGHT_READ), Ok(2));
+    assert_eq!(table.take(1), Some(BUNDLE));
+    assert_eq!(table.take(1), None);
+    assert_eq!(table.insert(BUNDLE, RIGHT_READ), Ok(1));
+    assert_eq!(table.iter().count(), 3);
+}
+
+#[kernel_test]
+fn rights_are_limited_to_the_object() {
+    let proc = Object::Proc {
+        file: ProcFile::Uptime,
+        offset: 0,
+    };
+    assert_eq!(proc.rights(), RIGHT_READ);
+    assert_eq!(BUNDLE.rights(), RIGHT_READ | RIGHT_MAP);
+
+    let mut table = HandleTable::new();
+    let all = table.insert(BUNDLE, ALL_RIGHTS).expect("table full");
+    let read = table
+        .insert(BUNDLE, RIGHT_READ | RIGHT_WRITE)
+        .expect("table full");
+    assert_eq!(
+        table.get(all).map(|e| e.rights),
+        Some(RIGHT_READ | RIGHT_MAP)
+    );
+    assert_eq!(table.get(read).map(|e| e.rights), Some(RIGHT_READ));
+}
+
+#[kernel_test]
+fn a_full_table_refuses_more() {
+    let mut table = HandleTable::new();
+    for handle in 0..MAX_HANDLES {
+        assert_eq!(table.insert(BUNDLE, RIGHT_READ), Ok(handle));
+    }
+    assert_eq!(
+        table.insert(BUNDLE, RIGHT_READ),
+        Err(HandleError::TooManyHandles)
+    );
+    assert_eq!(table.get(MAX_HANDLES), None);
+}
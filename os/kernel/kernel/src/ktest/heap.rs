@@ -0,0 +1,86 @@
+//! The kernel heap.
+
+#[cfg(feature = "fault-inject")]
+use crate::alloc::fault_inject::HEAP_FAULTS;
+#[cfg(any(feature = "poison", feature = "fault-inject"))]
+use crate::alloc::heap::GLOBAL;
+use crate::alloc::heap::{HEAP, HEAP_BASE};
+#[cfg(feature = "poison")]
+use crate::alloc::poison::HEAP_POISON;
+use core::alloc::{GlobalAlloc, Layout};
+#[cfg(feature = "fault-inject")]
+use kernel_alloc::fault_inject::FaultMode;
+#[cfg(feature = "fault-inject")]
+use kernel_sync::IrqGuard;
+use kernel_test::kernel_test;
+
+#[kernel_test]
+fn small_blocks_are_reused() {
+    let layout = Layout::from_size_align(48, 8).unwrap();
+    // SAFETY: `layout` has a non-zero size.
+    let block = unsafe { HEAP.alloc(layout) };
+    assert!(!block.is_null());
+    assert!(block.addr().is_multiple_of(8));
+    // SAFETY: The block is 48 bytes long and ours.
+    unsafe {
+        block.write_bytes(0x5A, 48);
+        HEAP.dealloc(block, layout);
+    }
+
+    // The freed block is on top of this CPU's cache.
+    // SAFETY: As above.
+    let again = unsafe { HEAP.alloc(layout) };
+    assert_eq!(again, block);
+    // SAFETY: Allocated above.
+    unsafe { HEAP.dealloc(again, layout) };
+}
+
+#[kernel_test]
+fn large_allocations_are_mapped() {
+    let layout = Layout::from_size_align(40 * 1024, 4096).unwrap();
+    let before = HEAP.stats();
+    // SAFETY: `layout` has a non-zero size.
+    let block = unsafe { HEAP.alloc(layout) };
+    assert!(!block.is_null());
+    assert!(block.addr() as u64 >= HEAP_BASE);
+    assert!(HEAP.stats().large_bytes >= before.large_bytes + layout.size());
+
+    // SAFETY: The block is `layout.size()` bytes long and ours.
+    unsafe {
+        block.write_bytes(0xC3, layout.size());
+        assert_eq!(block.add(layout.size() - 1).read(), 0xC3);
+        HEAP.dealloc(block, layout);
+    }
+    assert!(HEAP.stats().frees > before.frees);
+}
+
+#[cfg(feature = "poison")]
+#[kernel_test]
+fn global_allocator_poisons_freed_blocks() {
+    let layout = Layout::from_size_align(64, 8).unwrap();
+    let before = HEAP_POISON.stats();
+    // SAFETY: `layout` has a non-zero size.
+    let block = unsafe { GLOBAL.alloc(layout) };
+    assert!(!block.is_null());
+    // SAFETY: Allocated above.
+    unsafe { GLOBAL.dealloc(block, layout) };
+    assert_eq!(HEAP_POISON.stats().poisoned, before.poisoned + 1);
+}
+
+#[cfg(feature = "fault-inject")]
+#[kernel_test]
+fn global_allocator_fails_on_demand() {
+    let layout = Layout::from_size_align(64, 8).unwrap();
+    let (block, stats) = {
+        // Interrupt handlers may allocate, and must not see the failures.
+        let _irq = IrqGuard::new();
+        HEAP_FAULTS.set_mode(FaultMode::EveryNth(1));
+        // SAFETY: `layout` has a non-zero size.
+        let block = unsafe { GLOBAL.alloc(layout) };
+        let stats = HEAP_FAULTS.stats();
+        HEAP_FAULTS.set_mode(FaultMode::Off);
+        (block, stats)
+    };
+    assert!(block.is_null());
+    assert_eq!(stats.injected, 1);
+}
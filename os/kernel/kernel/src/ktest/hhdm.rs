@@ -0,0 +1,64 @@
+//! Bringing physical memory online in the HHDM.
+
+use crate::alloc::with_kernel_vmm;
+use crate::hhdm::{self, HhdmError};
+use crate::memmap::PhysRange;
+use kernel_info::memory::{HHDM_BASE, HHDM_SIZE};
+use kernel_memory_addresses::PhysicalAddress;
+use kernel_test::kernel_test;
+
+#[kernel_test]
+fn the_loader_mapping_is_online() {
+    assert!(hhdm::contains(PhysicalAddress::zero(), HHDM_SIZE));
+    assert!(!hhdm::contains(PhysicalAddress::zero(), u64::MAX));
+
+    let mut first = None;
+    hhdm::for_each_range(|range| {
+        first.get_or_insert(range);
+    });
+    assert_eq!(
+        first,
+        Some(PhysRange {
+            start: 0,
+            end: HHDM_SIZE
+        })
+    );
+}
+
+#[kernel_test]
+fn online_memory_is_not_added_twice() {
+    assert_eq!(
+        hhdm::add_range(PhysicalAddress::new(0x10_0000), 0x1000),
+        Err(HhdmError::Overlaps(PhysRange {
+            start: 0,
+            end: HHDM_SIZE
+        }))
+    );
+}
+
+#[kernel_test]
+fn ranges_must_hold_a_page_within_the_window() {
+    assert_eq!(
+        hhdm::add_range(PhysicalAddress::new(HHDM_SIZE + 1), 0x1000),
+        Err(HhdmError::Empty)
+    );
+    assert_eq!(
+        hhdm::add_range(PhysicalAddress::new(1 << 45), 0x1000),
+        Err(HhdmError::OutOfWindow)
+    );
+}
+
+#[kernel_test]
+fn promotion_keeps_every_translation() {
+    fn translates(pa: u64) -> bool {
+        let mut found = None;
+        with_kernel_vmm(|vmm| found = vmm.query(HHDM_BASE + pa));
+        found == Some(PhysicalAddress::new(pa))
+    }
+
+    hhdm::promote_large_pages();
+    hhdm::for_each_range(|range| {
+        assert!(translates(range.start), "{range} lost its start");
+        assert!(translates(range.end - 1), "{range} lost its end");
+    });
+}
@@ -0,0 +1,23 @@
+//! CPU hotplug requests that must be refused.
+
+use crate::hotplug::{self, BOOT_CPU, HotplugError};
+use crate::per_cpu::{self, MAX_CPUS, PerCpu};
+use kernel_test::kernel_test;
+
+#[kernel_test]
+fn boot_cpu_stays_online() {
+    assert_eq!(hotplug::offline(BOOT_CPU), Err(HotplugError::BootCpu));
+    assert_eq!(hotplug::online(BOOT_CPU), Err(HotplugError::AlreadyOnline));
+
+    let cpu = per_cpu::by_id(BOOT_CPU).expect("boot CPU registered");
+    assert!(cpu.hotplug.is_online());
+    assert!(core::ptr::eq(cpu, unsafe { PerCpu::current() }));
+}
+
+#[kernel_test]
+fn unknown_cpu_is_rejected() {
+    #[allow(clippy::cast_possible_truncation)]
+    let cpu_id = MAX_CPUS as u32;
+    assert_eq!(hotplug::offline(cpu_id), Err(HotplugError::NoSuchCpu));
+    assert_eq!(hotplug::online(cpu_id), Err(HotplugError::NoSuchCpu));
+}
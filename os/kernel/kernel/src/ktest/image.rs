@@ -0,0 +1,119 @@
+//! Decoding BMP and QOI images and drawing them onto a surface.
+
+use crate::framebuffer::Color;
+use crate::framebuffer::image::{Image, ImageError};
+use crate::framebuffer::pixel::PixelFormat;
+use crate::framebuffer::surface::Surface;
+use kernel_test::kernel_test;
+
+const RED: Color = Color::new(0xFF, 0, 0);
+const GREEN: Color = Color::new(0, 0xFF, 0);
+const BLUE: Color = Color::new(0, 0, 0xFF);
+
+/// A 2x2 24-bit bottom-up BMP: red, white on top; blue, green below.
+const BMP_2X2: [u8; 70] = {
+    let mut bmp = [0; 70];
+    let header: [u8; 30] = [
+        b'B', b'M', 70, 0, 0, 0, 0, 0, 0, 0, 54, 0, 0, 0, // file header
+        40, 0, 0, 0, 2, 0, 0, 0, 2, 0, 0, 0, 1, 0, 24, 0, // info header ...
+    ];
+    let mut i = 0;
+    while i < header.len() {
+        bmp[i] = header[i];
+        i += 1;
+    }
+    // Rows are padded to 8 bytes, stored bottom-up, pixels in BGR order.
+    let rows: [u8; 16] = [
+        0xFF, 0, 0, 0, 0xFF, 0, 0, 0, // blue, green
+        0, 0, 0xFF, 0xFF, 0xFF, 0xFF, 0, 0, // red, white
+    ];
+    let mut i = 0;
+    while i < rows.len() {
+        bmp[54 + i] = rows[i];
+        i += 1;
+    }
+    bmp
+};
+
+/// A 2x2 QOI: red, green on top; red, red below.
+const QOI_2X2: [u8; 33] = [
+    b'q', b'o', b'i', b'f', 0, 0, 0, 2, 0, 0, 0, 2, 4, 0, // header
+    0xFF, 0xFF, 0, 0, 0xFF, // QOI_OP_RGBA red
+    0xFE, 0, 0xFF, 0,    // QOI_OP_RGB green
+    0x32, // QOI_OP_INDEX of red
+    0xC0, // QOI_OP_RUN of one
+    0, 0, 0, 0, 0, 0, 0, 1, // end marker
+];
+
+/// A 2x1 QOI of a `QOI_OP_DIFF` and a `QOI_OP_LUMA`, starting from opaque black.
+const QOI_DIFFS: [u8; 25] = [
+    b'q', b'o', b'i', b'f', 0, 0, 0, 2, 0, 0, 0, 1, 3, 0,    // header
+    0x79, // QOI_OP_DIFF +1, 0, -1
+    0xA5, 0x5A, // QOI_OP_LUMA +2 (-3), +5, +7 (+2)
+    0, 0, 0, 0, 0, 0, 0, 1, // end marker
+];
+
+/// The pixels of `image`, row by row.
+fn pixels<const N: usize>(image: &Image<'_>) -> [Color; N] {
+    let mut pixels = [Color::TRANSPARENT; N];
+    image.for_each_pixel(|x, y, color| {
+        pixels[(y * image.width() + x) as usize] = color;
+    });
+    pixels
+}
+
+#[kernel_test]
+fn bottom_up_bmps_are_flipped() {
+    let image = Image::parse(&BMP_2X2).unwrap();
+    assert_eq!((image.width(), image.height()), (2, 2));
+    assert_eq!(pixels::<4>(&image), [RED, Color::WHITE, BLUE, GREEN]);
+}
+
+#[kernel_test]
+fn qoi_ops_are_decoded() {
+    let image = Image::parse(&QOI_2X2).unwrap();
+    assert_eq!(pixels::<4>(&image), [RED, GREEN, RED, RED]);
+
+    let image = Image::parse(&QOI_DIFFS).unwrap();
+    assert_eq!(
+        pixels::<2>(&image),
+        [Color::new(1, 0, 0xFF), Color::new(3, 5, 6)]
+    );
+}
+
+#[kernel_test]
+fn broken_images_are_refused() {
+    assert_eq!(
+        Image::parse(b"GIF89a").unwrap_err(),
+        ImageError::UnknownFormat
+    );
+    assert_eq!(
+        Image::parse(&BMP_2X2[..60]).unwrap_err(),
+        ImageError::Truncated
+    );
+
+    // One pixel of four.
+    let short = [
+        b'q', b'o', b'i', b'f', 0, 0, 0, 2, 0, 0, 0, 2, 4, 0, // header
+        0xFF, 0xFF, 0, 0, 0xFF, // QOI_OP_RGBA red
+        0, 0, 0, 0, 0, 0, 0, 1, // end marker
+    ];
+    assert_eq!(Image::parse(&short).unwrap_err(), ImageError::Truncated);
+
+    let mut palette = BMP_2X2;
+    palette[28] = 8;
+    assert_eq!(Image::parse(&palette).unwrap_err(), ImageError::Unsupported);
+}
+
+#[kernel_test]
+fn images_are_blended_and_clipped() {
+    let mut pixels = [0; 9];
+    let mut surface = Surface::new(&mut pixels, 3, 3, PixelFormat::BGR).unwrap();
+    surface.clear(Color::BLACK);
+
+    let image = Image::parse(&QOI_2X2).unwrap();
+    let drawn = surface.draw_image(2, 2, &image);
+    assert_eq!((drawn.x, drawn.y, drawn.width, drawn.height), (2, 2, 1, 1));
+    assert_eq!(surface.get(2, 2), Some(RED));
+    assert_eq!(surface.get(1, 1), Some(Color::BLACK));
+}
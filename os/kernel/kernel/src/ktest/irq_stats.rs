@@ -0,0 +1,42 @@
+//! Interrupt counting, snapshots and resets, and their `/proc/interrupts` listing.
+
+use crate::interrupts::spurious::SPURIOUS_INTERRUPT_VECTOR;
+use crate::irq_stats;
+use crate::per_cpu::PerCpu;
+use crate::procfs::{self, ProcFile};
+use kernel_test::kernel_test;
+
+const VECTOR: usize = SPURIOUS_INTERRUPT_VECTOR as usize;
+
+#[kernel_test]
+fn software_interrupts_are_counted() {
+    let stats = unsafe { &PerCpu::current().irq_stats };
+    let before = stats.snapshot();
+
+    unsafe { core::arch::asm!("int 0xFF") };
+    unsafe { core::arch::asm!("int 0xFF") };
+
+    let after = stats.snapshot();
+    assert_eq!(after[VECTOR], before[VECTOR] + 2);
+    assert!(irq_stats::seen(VECTOR));
+    assert_eq!(irq_stats::vector_name(VECTOR), "Spurious interrupt");
+
+    irq_stats::reset();
+    assert_eq!(stats.get(VECTOR), 0);
+    assert!(!irq_stats::seen(VECTOR));
+}
+
+#[kernel_test]
+fn proc_interrupts_lists_seen_vectors() {
+    unsafe { core::arch::asm!("int 0xFF") };
+
+    let mut buf = [0u8; 1024];
+    let len = procfs::read(ProcFile::Interrupts, 0, &mut buf);
+    let text = core::str::from_utf8(&buf[..len]).expect("valid UTF-8");
+    assert!(text.starts_with("           CPU0\n"), "{text}");
+    assert!(
+        text.lines()
+            .any(|line| line.starts_with("255:") && line.ends_with("  Spurious interrupt")),
+        "{text}"
+    );
+}
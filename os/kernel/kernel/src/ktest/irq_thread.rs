@@ -0,0 +1,56 @@
+//! Threaded interrupts, before and after their thread runs.
+
+use crate::irq_thread::{IrqReturn, ThreadedIrq};
+use crate::sched;
+use core::sync::atomic::{AtomicU64, Ordering};
+use kernel_sync::irq::rflags;
+use kernel_test::kernel_test;
+
+const fn nothing() -> IrqReturn {
+    IrqReturn::None
+}
+
+const fn more_work() -> IrqReturn {
+    IrqReturn::WakeThread
+}
+
+const fn bottom() {}
+
+/// `RFLAGS` as seen by the last bottom half of [`WOKEN`].
+static FLAGS: AtomicU64 = AtomicU64::new(0);
+
+fn record_flags() {
+    FLAGS.store(rflags(), Ordering::Release);
+}
+
+static QUIET: ThreadedIrq = ThreadedIrq::new("irq-quiet", nothing, bottom);
+static BUSY: ThreadedIrq = ThreadedIrq::new("irq-busy", more_work, bottom);
+static IDLE: ThreadedIrq = ThreadedIrq::new("irq-idle", more_work, bottom);
+static WOKEN: ThreadedIrq = ThreadedIrq::new("irq-woken", more_work, record_flags);
+
+#[kernel_test]
+fn top_half_runs_in_place() {
+    assert_eq!(QUIET.handle(), IrqReturn::None);
+    assert_eq!(BUSY.handle(), IrqReturn::WakeThread);
+    assert_eq!(BUSY.handle(), IrqReturn::WakeThread);
+
+    assert_eq!(QUIET.stats().top_runs, 1);
+    assert_eq!(BUSY.stats().top_runs, 2);
+}
+
+#[kernel_test]
+fn bottom_half_waits_for_the_thread() {
+    IDLE.handle();
+    assert_eq!(IDLE.thread(), None);
+    assert_eq!(IDLE.stats().bottom_runs, 0);
+}
+
+#[kernel_test]
+fn woken_thread_runs_the_bottom_half() {
+    WOKEN.start().unwrap();
+    assert_eq!(WOKEN.handle(), IrqReturn::WakeThread);
+    sched::run_until(|| FLAGS.load(Ordering::Acquire) != 0);
+
+    assert_eq!(WOKEN.stats().bottom_runs, 1);
+    assert_ne!(FLAGS.load(Ordering::Acquire) & (1 << 9), 0, "IF clear");
+}
@@ -0,0 +1,92 @@
+//! Debugger commands, run against a buffer instead of the console.
+
+use crate::kdb::{Flow, run};
+use core::fmt::{self, Write};
+use kernel_test::kernel_test;
+
+/// Collects command output.
+struct Buf {
+    bytes: [u8; 1024],
+    len: usize,
+}
+
+impl Buf {
+    const fn new() -> Self {
+        Self {
+            bytes: [0; 1024],
+            len: 0,
+        }
+    }
+
+    fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.bytes[..self.len]).expect("output is not UTF-8")
+    }
+
+    /// Run `command` and return its output.
+    fn run(command: fmt::Arguments<'_>) -> (Flow, Self) {
+        let mut line = Self::new();
+        line.write_fmt(command).expect("command too long");
+        let mut out = Self::new();
+        let flow = run(line.as_str(), &mut out);
+        (flow, out)
+    }
+}
+
+impl Write for Buf {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        self.bytes
+            .get_mut(self.len..end)
+            .ok_or(fmt::Error)?
+            .copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+static PATTERN: [u8; 4] = *b"kdb!";
+
+#[kernel_test]
+fn md_dumps_mapped_memory_only() {
+    let addr = PATTERN.as_ptr() as u64;
+    let (flow, out) = Buf::run(format_args!("md {addr:#x} 4"));
+    assert_eq!(flow, Flow::Stay);
+    assert!(out.as_str().contains(" 6b 64 62 21"), "{}", out.as_str());
+    assert!(
+        out.as_str().trim_end().ends_with("kdb!"),
+        "{}",
+        out.as_str()
+    );
+
+    // The lower half is gone after early init.
+    let (_, out) = Buf::run(format_args!("md 0 16"));
+    assert!(out.as_str().contains("not mapped"), "{}", out.as_str());
+}
+
+#[kernel_test]
+fn pt_walks_down_to_the_page() {
+    let addr = PATTERN.as_ptr() as u64;
+    let (_, out) = Buf::run(format_args!("pt {addr:#x}"));
+    let out = out.as_str();
+    assert!(out.starts_with("PML4["), "{out}");
+    assert!(out.contains("page "), "{out}");
+    assert!(out.contains(" -> "), "{out}");
+
+    let (_, out) = Buf::run(format_args!("pt 0x800000000000"));
+    assert!(out.as_str().contains("not canonical"), "{}", out.as_str());
+}
+
+#[kernel_test]
+fn commands_are_parsed() {
+    assert_eq!(Buf::run(format_args!("")).0, Flow::Stay);
+    assert_eq!(Buf::run(format_args!("  c ")).0, Flow::Continue);
+    assert_eq!(Buf::run(format_args!("continue")).0, Flow::Continue);
+
+    let (flow, out) = Buf::run(format_args!("frobnicate"));
+    assert_eq!(flow, Flow::Stay);
+    assert!(out.as_str().starts_with("unknown command"));
+    let (_, out) = Buf::run(format_args!("md nowhere"));
+    assert!(out.as_str().starts_with("usage"));
+    let (_, out) = Buf::run(format_args!("tasks"));
+    assert!(out.as_str().contains("PID"));
+}
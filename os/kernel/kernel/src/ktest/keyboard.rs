@@ -0,0 +1,114 @@
+//! Scancode decoding, modifiers and dead keys on the builtin keymaps, and
+//! USB boot reports.
+
+use crate::keyboard::decoder::{Decoder, KeyEvent};
+use crate::keyboard::hid::{self, BootReports};
+use crate::keyboard::keymap::{self, Accent, KeyCode};
+use kernel_test::kernel_test;
+
+/// Feed `scancodes` and collect the text typed, up to `N` characters.
+fn type_text<const N: usize>(decoder: &mut Decoder, scancodes: &[u8]) -> ([char; N], usize) {
+    let mut text = ['\0'; N];
+    let mut len = 0;
+    for event in scancodes.iter().filter_map(|&b| decoder.decode(b)) {
+        for c in event.text() {
+            text[len] = c;
+            len += 1;
+        }
+    }
+    (text, len)
+}
+
+#[kernel_test]
+fn scancodes_become_key_events() {
+    let mut decoder = Decoder::new(&keymap::US);
+    let event = decoder.decode(0x1E).unwrap();
+    assert_eq!(event.code, KeyCode(0x1E));
+    assert!(event.pressed);
+    assert_eq!(event.text().next(), Some('a'));
+
+    let event = decoder.decode(0x9E).unwrap();
+    assert!(!event.pressed);
+    assert_eq!(event.text().next(), None);
+
+    // Right Ctrl is extended, and makes `c` a control character.
+    assert_eq!(decoder.decode(0xE0), None);
+    let event: KeyEvent = decoder.decode(0x1D).unwrap();
+    assert_eq!(event.code, KeyCode::RIGHT_CTRL);
+    assert!(event.modifiers.ctrl());
+    assert_eq!(type_text::<1>(&mut decoder, &[0x2E]), (['\u{3}'], 1));
+}
+
+#[kernel_test]
+fn shift_and_caps_lock_select_the_upper_case() {
+    let mut decoder = Decoder::new(&keymap::US);
+    // Shift-a, 1 with Shift; Caps Lock, a, 1, Shift-a.
+    let (text, len) = type_text::<6>(
+        &mut decoder,
+        &[0x2A, 0x1E, 0x02, 0xAA, 0x3A, 0xBA, 0x1E, 0x02, 0x2A, 0x1E],
+    );
+    assert_eq!(&text[..len], ['A', '!', 'A', '1', 'a']);
+}
+
+#[kernel_test]
+fn keymaps_are_selected_by_name() {
+    assert_eq!(keymap::find("de").map(|m| m.name), Some("de"));
+    assert!(keymap::find("dvorak").is_none());
+
+    let mut decoder = Decoder::new(&keymap::DE);
+    // The US z and y keys, AltGr-q, Shift-ä, and a key left as on US.
+    let (text, len) = type_text::<5>(
+        &mut decoder,
+        &[
+            0x2C, 0x15, 0xE0, 0x38, 0x10, 0xE0, 0xB8, 0x2A, 0x28, 0xAA, 0x1F,
+        ],
+    );
+    assert_eq!(&text[..len], ['y', 'z', '@', 'Ä', 's']);
+}
+
+#[kernel_test]
+fn dead_keys_compose_with_the_next_character() {
+    let mut decoder = Decoder::new(&keymap::DE);
+    // ´ e, Shift-´ (`) Shift-a, ^ Space, ^ x.
+    let (text, len) = type_text::<5>(
+        &mut decoder,
+        &[0x0D, 0x12, 0x2A, 0x0D, 0x1E, 0xAA, 0x29, 0x39, 0x29, 0x2D],
+    );
+    assert_eq!(&text[..len], ['é', 'À', '^', '^', 'x']);
+
+    assert_eq!(Accent::Circumflex.compose('o'), Some('ô'));
+    assert_eq!(Accent::Acute.compose('q'), None);
+}
+
+#[kernel_test]
+fn boot_reports_become_scancodes() {
+    let mut reports = BootReports::new();
+    let mut scancodes = [0; 8];
+    let mut len = 0;
+    let mut update = |report| {
+        len = 0;
+        reports.update(report, |b| {
+            scancodes[len] = b;
+            len += 1;
+        });
+        (scancodes, len)
+    };
+
+    // Left Shift and a; Shift released; Up added.
+    let (codes, len) = update([0x02, 0, 0x04, 0, 0, 0, 0, 0]);
+    assert_eq!(&codes[..len], [0x2A, 0x1E]);
+    let (codes, len) = update([0, 0, 0x04, 0, 0, 0, 0, 0]);
+    assert_eq!(&codes[..len], [0xAA]);
+    let (codes, len) = update([0, 0, 0x04, 0x52, 0, 0, 0, 0]);
+    assert_eq!(&codes[..len], [0xE0, 0x48]);
+
+    // Roll-over changes nothing; then every key goes up.
+    let (_, len) = update([0, 0, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01]);
+    assert_eq!(len, 0);
+    let (codes, len) = update([0; hid::REPORT_LEN]);
+    assert_eq!(&codes[..len], [0x9E, 0xE0, 0xC8]);
+
+    assert_eq!(hid::key_code(0x29), Some(KeyCode(0x01)));
+    assert_eq!(hid::key_code(0x48), None);
+    assert_eq!(hid::key_code(0xE0), None);
+}
@@ -0,0 +1,45 @@
+//! The emergency path of the kernel log.
+
+use crate::klog::{self, MAX_DEPTH, emergency};
+use core::fmt;
+use kernel_test::kernel_test;
+use log::Level;
+
+/// Logs through the emergency path while being formatted, nesting that
+/// many more levels deep.
+struct Nested(usize);
+
+impl fmt::Display for Nested {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0 > 0 {
+            emergency!("{}", Self(self.0 - 1));
+        }
+        f.write_str("nested")
+    }
+}
+
+fn ring_holds(text: &str) -> bool {
+    let mut found = false;
+    klog::drain(|line| found |= line.level == Level::Error && line.text() == text);
+    found
+}
+
+#[kernel_test]
+fn emergency_lines_reach_the_ring() {
+    emergency!("ktest emergency line {}", 42);
+    assert!(ring_holds("emergency: ktest emergency line 42"));
+}
+
+#[kernel_test]
+fn deep_nesting_is_dropped() {
+    let before = klog::nested_drops();
+    emergency!("{}", Nested(MAX_DEPTH - 1));
+    assert_eq!(klog::nested_drops(), before);
+
+    emergency!("{}", Nested(MAX_DEPTH));
+    assert!(klog::nested_drops() > before);
+
+    // The nesting count recovered.
+    emergency!("ktest after nesting");
+    assert!(ring_holds("emergency: ktest after nesting"));
+}
@@ -0,0 +1,33 @@
+//! Randomizing user address space layouts.
+
+use crate::process::USER_STACK_TOP;
+use crate::process::layout::{Layout, MMAP_ENTROPY_BITS, PIE_ENTROPY_BITS, STACK_ENTROPY_BITS};
+use crate::process::mmap::{MMAP_BASE, MMAP_END};
+use crate::userland::PIE_BASE;
+use kernel_memory_addresses::{PageSize, Size4K};
+use kernel_test::kernel_test;
+
+#[kernel_test]
+fn random_layouts_stay_within_bounds() {
+    for _ in 0..64 {
+        let layout = Layout::random();
+        for addr in [layout.stack_top, layout.mmap_base, layout.pie_base] {
+            assert_eq!(addr.as_u64() % Size4K::SIZE, 0);
+        }
+
+        let stack_moved = USER_STACK_TOP.as_u64() - layout.stack_top.as_u64();
+        let mmap_moved = layout.mmap_base.as_u64() - MMAP_BASE.as_u64();
+        let pie_moved = layout.pie_base.as_u64() - PIE_BASE.as_u64();
+        assert!(stack_moved >> STACK_ENTROPY_BITS < Size4K::SIZE);
+        assert!(mmap_moved >> MMAP_ENTROPY_BITS < Size4K::SIZE);
+        assert!(pie_moved >> PIE_ENTROPY_BITS < Size4K::SIZE);
+        assert!(layout.mmap_base < MMAP_END);
+        assert!(layout.pie_base < layout.stack_top);
+    }
+}
+
+#[kernel_test]
+fn layouts_differ() {
+    // Equal by chance with a probability of 2^-60.
+    assert_ne!(Layout::random(), Layout::random());
+}
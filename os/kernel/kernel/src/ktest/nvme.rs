@@ -0,0 +1,55 @@
+//! NVMe namespaces through the block device interface; skipped without
+//! `-device nvme`.
+
+use crate::alloc::dma::{DmaBuffer, DmaConstraints};
+use crate::block::{self, BlockError};
+use crate::nvme::BOUNCE_LEN;
+use kernel_test::kernel_test;
+
+/// More than a bounce buffer, so transfers are split.
+const LEN: usize = BOUNCE_LEN + 4096;
+
+/// Scratch memory; there is no heap.
+fn scratch() -> DmaBuffer {
+    DmaBuffer::alloc(LEN, DmaConstraints::ANY).expect("no scratch buffer")
+}
+
+#[kernel_test]
+fn written_blocks_read_back() {
+    let Some(disk) = block::lookup("nvme0n1") else {
+        return;
+    };
+    let lba = disk.blocks() - (LEN / disk.block_size()) as u64;
+    let mut saved = scratch();
+    disk.read(lba, &mut saved).expect("read failed");
+
+    let mut pattern = scratch();
+    for (byte, value) in pattern.iter_mut().zip((0..251).cycle()) {
+        *byte = value;
+    }
+    disk.write(lba, &pattern).expect("write failed");
+    let mut read = scratch();
+    disk.read(lba, &mut read).expect("read back failed");
+    let same = *read == *pattern;
+
+    disk.write(lba, &saved).expect("restore failed");
+    assert!(same, "blocks {lba}.. read back differently");
+}
+
+#[kernel_test]
+fn transfers_off_the_namespace_are_refused() {
+    let Some(disk) = block::lookup("nvme0n1") else {
+        return;
+    };
+    let mut buf = scratch();
+    let bs = disk.block_size();
+    assert_eq!(
+        disk.read(disk.blocks(), &mut buf[..bs]),
+        Err(BlockError::OutOfRange)
+    );
+    assert_eq!(
+        disk.read(u64::MAX, &mut buf[..bs]),
+        Err(BlockError::OutOfRange)
+    );
+    assert_eq!(disk.write(0, &buf[..bs - 1]), Err(BlockError::Misaligned));
+}
@@ -0,0 +1,122 @@
+//! The page cache, over a synthetic source that counts its fills.
+
+use crate::alloc::frame_table;
+use crate::page_cache::{self, CacheError, PAGE_LEN, PageKey, PageSource, READ_AHEAD_PAGES};
+use core::sync::atomic::{AtomicU64, Ordering};
+use kernel_test::kernel_test;
+
+/// Pages whose bytes are their offset plus the page index, truncated.
+struct Pattern {
+    inode: u64,
+    len: u64,
+    fills: AtomicU64,
+}
+
+impl Pattern {
+    const fn new(inode: u64, len: u64) -> Self {
+        Self {
+            inode,
+            len,
+            fills: AtomicU64::new(0),
+        }
+    }
+
+    fn fills(&self) -> u64 {
+        self.fills.load(Ordering::Relaxed)
+    }
+
+    /// Forget all pages, so the next test starts cold.
+    fn drop_pages(&self) {
+        page_cache::invalidate(self, 0, self.pages());
+    }
+}
+
+impl PageSource for Pattern {
+    fn key(&self, page: u64) -> PageKey {
+        PageKey::File {
+            inode: self.inode,
+            page,
+        }
+    }
+
+    fn len(&self) -> u64 {
+        self.len
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn fill(&self, page: u64, frame: &mut [u8; PAGE_LEN]) -> Result<(), CacheError> {
+        self.fills.fetch_add(1, Ordering::Relaxed);
+        for (i, byte) in frame.iter_mut().enumerate() {
+            *byte = (i as u64 + page) as u8;
+        }
+        Ok(())
+    }
+}
+
+#[kernel_test]
+fn repeated_reads_fill_once() {
+    let source = Pattern::new(0x7e57_0001, 2 * PAGE_LEN as u64);
+    let mut buf = [0; 16];
+    page_cache::read(&source, PAGE_LEN as u64 + 3, &mut buf).expect("read failed");
+    let fills = source.fills();
+    page_cache::read(&source, PAGE_LEN as u64 + 3, &mut buf).expect("read failed");
+    assert_eq!(source.fills(), fills, "cached page filled again");
+    assert_eq!(buf[0], 4);
+    source.drop_pages();
+}
+
+#[kernel_test]
+fn misses_read_ahead() {
+    let source = Pattern::new(0x7e57_0002, 16 * PAGE_LEN as u64);
+    let mut buf = [0; 16];
+    page_cache::read(&source, 0, &mut buf).expect("read failed");
+    assert_eq!(source.fills(), 1 + READ_AHEAD_PAGES);
+
+    // The pages read ahead are hits.
+    page_cache::read(&source, READ_AHEAD_PAGES * PAGE_LEN as u64, &mut buf).expect("read failed");
+    assert_eq!(source.fills(), 1 + READ_AHEAD_PAGES);
+    source.drop_pages();
+}
+
+#[kernel_test]
+fn reads_stop_at_the_end() {
+    let source = Pattern::new(0x7e57_0003, PAGE_LEN as u64 + 100);
+    let mut buf = [0; PAGE_LEN];
+    let n = page_cache::read(&source, PAGE_LEN as u64, &mut buf).expect("read failed");
+    assert_eq!(n, 100);
+    assert_eq!(
+        page_cache::read(&source, 2 * PAGE_LEN as u64, &mut buf),
+        Ok(0)
+    );
+    assert_eq!(
+        page_cache::get(&source, 2).err(),
+        Some(CacheError::OutOfRange)
+    );
+    source.drop_pages();
+}
+
+#[kernel_test]
+fn held_frames_survive_shrinking() {
+    let source = Pattern::new(0x7e57_0004, PAGE_LEN as u64);
+    let frame = page_cache::get(&source, 0)
+        .expect("get failed")
+        .into_frame();
+    assert!(frame_table().is_shared(frame), "cache reference missing");
+
+    page_cache::shrink(usize::MAX);
+    assert!(
+        !frame_table().is_shared(frame),
+        "held page not dropped by the cache"
+    );
+    let page = page_cache::get(&source, 0).expect("get failed");
+    assert_eq!(source.fills(), 2, "evicted page not filled again");
+    assert_eq!(page.bytes()[7], 7);
+
+    assert!(frame_table().release(frame), "frame has other owners");
+    crate::alloc::with_kernel_frame_alloc(|alloc| {
+        use kernel_vmem::PhysFrameAlloc;
+        alloc.free_4k(frame);
+    });
+    drop(page);
+    source.drop_pages();
+}
@@ -0,0 +1,295 @@
+//! Mapping kernel pages and the frame allocator.
+
+use crate::alloc::{FlushTlb, frame_stats, try_with_kernel_vmm, with_kernel_vmm};
+use kernel_alloc::vmm::AllocationTarget;
+use kernel_info::memory::HHDM_BASE;
+use kernel_memory_addresses::{PageSize, Size4K, VirtualAddress};
+use kernel_test::kernel_test;
+use kernel_vmem::VirtualMemoryPageBits;
+
+/// Unused kernel window for test mappings.
+const SCRATCH_OFFSET: u64 = 5u64 << 40; // 5 TiB inside HHDM range
+
+/// Unused kernel window in a PML4 slot of its own, for the sync test.
+const SYNC_OFFSET: u64 = 6u64 << 40; // 6 TiB inside HHDM range
+
+const PATTERN: u64 = 0xA5A5_A5A5_A5A5_A5A5;
+
+#[kernel_test]
+fn frames_are_counted() {
+    let stats = frame_stats();
+    assert!(stats.total > 0, "no usable frames");
+    assert_eq!(stats.used + stats.free, stats.total);
+}
+
+#[kernel_test]
+fn frame_table_tracks_allocations() {
+    use crate::alloc::{create_address_space, destroy_address_space, frame_table};
+    use kernel_alloc::frame_info::{FrameFlags, FrameOwner};
+
+    let table = frame_table();
+    assert!(!table.is_empty(), "frame table not attached");
+
+    let root = create_address_space().expect("creating the address space failed");
+    assert_eq!(table.flags(root), FrameFlags::ALLOCATED);
+    assert_eq!(table.owner(root), FrameOwner::Unknown);
+    table.set_owner(root, FrameOwner::PageTable);
+    assert_eq!(table.owner(root), FrameOwner::PageTable);
+
+    unsafe { destroy_address_space(root, |_| false) };
+    assert_eq!(table.flags(root), FrameFlags::NONE);
+    assert_eq!(table.owner(root), FrameOwner::Unknown);
+}
+
+#[kernel_test]
+fn shm_frames_outlive_the_last_handle() {
+    use crate::alloc::{frame_table, with_kernel_frame_alloc};
+    use crate::shm;
+    use kernel_memory_addresses::{PhysicalAddress, PhysicalPage};
+    use kernel_vmem::PhysFrameAlloc;
+
+    let used = frame_stats().used;
+    let id = shm::open(b"/ktest", 2 * Size4K::SIZE).expect("creating the object failed");
+    assert_eq!(frame_stats().used, used + 2);
+    assert_eq!(shm::open(b"/ktest", Size4K::SIZE), Ok(id));
+
+    // A mapping holds references of its own.
+    let mut frames = [PhysicalPage::from_addr(PhysicalAddress::new(0)); 2];
+    shm::share_frames(id, 0, &mut frames).expect("sharing the frames failed");
+    assert!(frames.iter().all(|&f| frame_table().owners(f) == 2));
+    assert_eq!(
+        shm::share_frames(id, 1, &mut frames),
+        Err(shm::ShmError::OutOfRange)
+    );
+
+    shm::close(id).expect("closing the first handle failed");
+    shm::close(id).expect("closing the last handle failed");
+    assert_eq!(shm::close(id), Err(shm::ShmError::BadHandle));
+    assert_eq!(frame_stats().used, used + 2, "mapped frames were freed");
+
+    with_kernel_frame_alloc(|alloc| {
+        for frame in frames {
+            assert!(frame_table().release(frame), "frame still shared");
+            alloc.free_4k(frame);
+        }
+    });
+    assert_eq!(frame_stats().used, used);
+}
+
+#[kernel_test]
+fn map_write_unmap() {
+    let va = HHDM_BASE + SCRATCH_OFFSET;
+    let nonleaf = VirtualMemoryPageBits::new()
+        .with_present(true)
+        .with_writable(true);
+    let leaf = VirtualMemoryPageBits::new()
+        .with_present(true)
+        .with_writable(true)
+        .with_no_execute(true);
+    let used = frame_stats().used;
+
+    assert!(!is_mapped(va), "scratch window already in use");
+    try_with_kernel_vmm(FlushTlb::OnSuccess, |vmm| {
+        vmm.map_anon_4k_pages(AllocationTarget::Kernel, va, 0, Size4K::SIZE, nonleaf, leaf)
+    })
+    .expect("mapping the scratch page failed");
+    assert!(is_mapped(va));
+    assert!(frame_stats().used > used, "mapping took no frame");
+
+    let words = usize::try_from(Size4K::SIZE).unwrap_or_default() / size_of::<u64>();
+    let page = unsafe { core::slice::from_raw_parts_mut(va.as_u64() as *mut u64, words) };
+    for (i, word) in (0u64..).zip(page.iter_mut()) {
+        *word = i ^ PATTERN;
+    }
+    assert!(
+        (0u64..)
+            .zip(page.iter())
+            .all(|(i, &word)| word == i ^ PATTERN)
+    );
+
+    with_kernel_vmm(|vmm| {
+        vmm.unmap_region(va, Size4K::SIZE);
+        unsafe { vmm.local_tlb_flush_all() };
+    });
+    assert!(!is_mapped(va), "scratch page still mapped");
+}
+
+#[kernel_test]
+fn new_kernel_pml4_entries_reach_existing_address_spaces() {
+    use crate::alloc::{create_address_space, destroy_address_space, with_address_space};
+    use kernel_vmem::address_space::kernel_pml4_generation;
+
+    let va = HHDM_BASE + SYNC_OFFSET;
+    let leaf = VirtualMemoryPageBits::new()
+        .with_present(true)
+        .with_writable(true)
+        .with_no_execute(true);
+    let root = create_address_space().expect("creating the address space failed");
+
+    assert!(!is_mapped(va), "sync window already in use");
+    let generation = kernel_pml4_generation();
+    try_with_kernel_vmm(FlushTlb::OnSuccess, |vmm| {
+        vmm.map_anon_4k_pages(
+            AllocationTarget::Kernel,
+            va,
+            0,
+            Size4K::SIZE,
+            VirtualMemoryPageBits::new()
+                .with_present(true)
+                .with_writable(true),
+            leaf,
+        )
+    })
+    .expect("mapping the sync page failed");
+    assert!(
+        kernel_pml4_generation() > generation,
+        "the PML4 slot was not empty"
+    );
+
+    let seen = unsafe { with_address_space(root, || is_mapped(va)) };
+    unsafe { destroy_address_space(root, |_| false) };
+    with_kernel_vmm(|vmm| {
+        vmm.unmap_region(va, Size4K::SIZE);
+        unsafe { vmm.local_tlb_flush_all() };
+    });
+    assert!(
+        seen,
+        "the new mapping is missing from the older address space"
+    );
+}
+
+fn is_mapped(va: VirtualAddress) -> bool {
+    let mut mapped = false;
+    with_kernel_vmm(|vmm| mapped = vmm.query(va).is_some());
+    mapped
+}
+
+#[cfg(feature = "fault-inject")]
+#[kernel_test]
+fn address_space_creation_reports_oom() {
+    use crate::alloc::{create_address_space, fault_inject::FAULTS};
+    use kernel_alloc::fault_inject::FaultMode;
+    use kernel_vmem::address_space::AddressSpaceError;
+
+    let prev = FAULTS.mode();
+    FAULTS.set_mode(FaultMode::AfterBudget(0));
+    let result = create_address_space();
+    let injected = FAULTS.stats().injected;
+    FAULTS.set_mode(prev);
+
+    assert!(matches!(result, Err(AddressSpaceError::OutOfMemory)));
+    assert_eq!(injected, 1);
+}
+
+#[kernel_test]
+fn destroyed_address_space_returns_its_frames() {
+    use crate::alloc::{create_address_space, destroy_address_space, with_address_space};
+    use kernel_vmem::vma::{Vma, VmaKind, VmaPerms, VmaSet};
+
+    let va = VirtualAddress::new(0x4000_0000);
+    let len = 4 * Size4K::SIZE;
+    let mut vmas = VmaSet::<2>::new();
+    vmas.insert(Vma::new(va, va + len, VmaKind::Anonymous, VmaPerms::RW))
+        .expect("recording the area failed");
+    let used = frame_stats().used;
+
+    let root = create_address_space().expect("creating the address space failed");
+    unsafe {
+        with_address_space(root, || {
+            try_with_kernel_vmm(FlushTlb::OnSuccess, |vmm| {
+                vmm.map_anon_4k_pages(
+                    AllocationTarget::User,
+                    va,
+                    0,
+                    len,
+                    VirtualMemoryPageBits::user_table_wb_exec(),
+                    VirtualMemoryPageBits::user_leaf_data_wb(),
+                )
+            })
+        })
+    }
+    .expect("mapping user pages failed");
+    assert!(frame_stats().used > used, "mapping took no frame");
+
+    let stats = unsafe { destroy_address_space(root, |page| vmas.owns_frame(page)) };
+    assert_eq!(stats.pages, 4);
+    // PML4, PDPT, PD and PT.
+    assert_eq!(stats.tables, 4);
+    assert_eq!(frame_stats().used, used, "frames leaked");
+}
+
+#[kernel_test]
+fn forked_address_space_copies_on_write() {
+    use crate::alloc::{
+        create_address_space, destroy_address_space, fork_address_space, frame_table,
+        with_address_space,
+    };
+    use crate::smap::SmapGuard;
+    use kernel_vmem::vma::{Vma, VmaKind, VmaPerms, VmaSet};
+
+    let va = VirtualAddress::new(0x4000_0000);
+    let len = 2 * Size4K::SIZE;
+    let mut vmas = VmaSet::<2>::new();
+    vmas.insert(Vma::new(va, va + len, VmaKind::Anonymous, VmaPerms::RW))
+        .expect("recording the area failed");
+    let used = frame_stats().used;
+    let ptr = va.as_u64() as *mut u64;
+
+    let parent = create_address_space().expect("creating the parent failed");
+    let child = create_address_space().expect("creating the child failed");
+    let frame = unsafe {
+        with_address_space(parent, || {
+            try_with_kernel_vmm(FlushTlb::OnSuccess, |vmm| {
+                vmm.map_anon_4k_pages(
+                    AllocationTarget::User,
+                    va,
+                    0,
+                    len,
+                    VirtualMemoryPageBits::user_table_wb_exec(),
+                    VirtualMemoryPageBits::user_leaf_data_wb(),
+                )
+            })
+            .expect("mapping user pages failed");
+
+            let _smap = SmapGuard::enter();
+            ptr.write_volatile(PATTERN);
+            let shared = fork_address_space(child, |page| vmas.clone_policy(page))
+                .expect("forking the address space failed");
+            assert_eq!(shared, 2);
+
+            let mut frame = None;
+            with_kernel_vmm(|vmm| frame = vmm.query(va));
+            let frame = frame.expect("page vanished");
+            assert_eq!(frame_table().owners(frame.page::<Size4K>()), 2);
+
+            // Faults, copies the page and leaves the child's copy alone.
+            ptr.write_volatile(!PATTERN);
+            assert_eq!(ptr.read_volatile(), !PATTERN);
+            frame
+        })
+    };
+    assert_eq!(
+        frame_table().owners(frame.page::<Size4K>()),
+        1,
+        "copy kept the reference"
+    );
+
+    let seen = unsafe {
+        with_address_space(child, || {
+            let _smap = SmapGuard::enter();
+            ptr.read_volatile()
+        })
+    };
+    assert_eq!(seen, PATTERN, "child saw the parent's write");
+
+    unsafe {
+        destroy_address_space(parent, |page| vmas.owns_frame(page));
+        destroy_address_space(child, |page| vmas.owns_frame(page));
+    }
+    assert_eq!(frame_stats().used, used, "frames leaked");
+}
+
+#[kernel_test]
+fn live_tables_pass_the_audit() {
+    assert_eq!(crate::alloc::audit::run(), 0);
+}
@@ -0,0 +1,54 @@
+//! Pipe buffering and end-of-file handling.
+
+use crate::pipe::{self, PIPE_CAPACITY, PipeEnd, PipeError};
+use kernel_test::kernel_test;
+
+#[kernel_test]
+fn pipe_round_trip_and_eof() {
+    let id = pipe::create().expect("creating the pipe failed");
+    assert_eq!(pipe::write(id, b"hello, pipe"), Ok(11));
+
+    let mut buf = [0u8; 5];
+    assert_eq!(pipe::read(id, &mut buf), 5);
+    assert_eq!(&buf, b"hello");
+
+    // Buffered data survives the writer; then the reader sees end of file.
+    pipe::close(id, PipeEnd::Write);
+    let mut buf = [0u8; 16];
+    assert_eq!(pipe::read(id, &mut buf), 6);
+    assert_eq!(&buf[..6], b", pipe");
+    assert_eq!(pipe::read(id, &mut buf), 0);
+    pipe::close(id, PipeEnd::Read);
+}
+
+#[kernel_test]
+fn pipe_wraps_around() {
+    let id = pipe::create().expect("creating the pipe failed");
+    let mut buf = [0u8; 64];
+
+    // Move the head close to the end of the ring, then write across it.
+    let fill = [0xA5; PIPE_CAPACITY - 8];
+    assert_eq!(pipe::write(id, &fill), Ok(fill.len()));
+    let mut drained = 0;
+    while drained < fill.len() {
+        drained += pipe::read(id, &mut buf);
+    }
+    assert_eq!(pipe::write(id, b"0123456789abcdef"), Ok(16));
+    assert_eq!(pipe::read(id, &mut buf), 16);
+    assert_eq!(&buf[..16], b"0123456789abcdef");
+
+    pipe::close(id, PipeEnd::Read);
+    pipe::close(id, PipeEnd::Write);
+}
+
+#[kernel_test]
+fn pipe_without_reader_is_broken() {
+    let id = pipe::create().expect("creating the pipe failed");
+    pipe::dup(id, PipeEnd::Read);
+    pipe::close(id, PipeEnd::Read);
+    assert_eq!(pipe::write(id, b"still read"), Ok(10));
+
+    pipe::close(id, PipeEnd::Read);
+    assert_eq!(pipe::write(id, b"lost"), Err(PipeError::BrokenPipe));
+    pipe::close(id, PipeEnd::Write);
+}
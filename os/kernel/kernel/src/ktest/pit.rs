@@ -0,0 +1,47 @@
+//! Calibrating against the PIT.
+
+use crate::clock;
+use crate::pit::{measure, pit_wait_ms};
+use crate::tsc::rdtsc;
+use kernel_test::kernel_test;
+
+#[kernel_test]
+fn waiting_takes_at_least_the_requested_time() {
+    let tsc_hz = clock::tsc_hz();
+    let start = rdtsc();
+    assert!(pit_wait_ms(20), "the PIT does not count");
+    let elapsed_ms = (rdtsc() - start) * 1_000 / tsc_hz;
+
+    // Interrupts may stretch the wait, but not shorten it.
+    assert!((19..200).contains(&elapsed_ms), "waited {elapsed_ms} ms");
+}
+
+#[kernel_test]
+fn waits_longer_than_one_countdown_are_chained() {
+    let tsc_hz = clock::tsc_hz();
+    let start = rdtsc();
+    assert!(pit_wait_ms(120));
+    let elapsed_ms = (rdtsc() - start) * 1_000 / tsc_hz;
+
+    assert!((119..500).contains(&elapsed_ms), "waited {elapsed_ms} ms");
+}
+
+#[kernel_test]
+fn tsc_measurement_agrees_with_the_calibration() {
+    let tsc = measure(20, rdtsc).expect("the PIT does not count");
+    let error_hz = tsc.error_hz.expect("PIT measurements have an error bound");
+
+    // Interrupts during the measurement widen the bound, so allow 1% on top.
+    let tsc_hz = clock::tsc_hz();
+    assert!(
+        tsc.hz.abs_diff(tsc_hz) <= error_hz + tsc_hz / 100,
+        "measured {tsc}, calibrated {tsc_hz} Hz"
+    );
+}
+
+#[kernel_test]
+fn a_stopped_counter_measures_zero() {
+    let stopped = measure(5, || 42).expect("the PIT does not count");
+    assert_eq!(stopped.hz, 0);
+    assert_eq!(stopped.error_hz, Some(0));
+}
@@ -0,0 +1,60 @@
+//! Readiness of pollable objects.
+
+use crate::pipe::{self, PIPE_CAPACITY, PipeEnd};
+use crate::poll::{self, Pollable};
+use crate::process::handle::Object;
+use kernel_test::kernel_test;
+use stdlib::syscall_abi::poll::{POLLERR, POLLHUP, POLLIN, POLLOUT};
+
+#[kernel_test]
+fn pipe_readiness_follows_its_buffer_and_ends() {
+    let id = pipe::create().expect("creating the pipe failed");
+    assert_eq!(id.ready(POLLIN | POLLOUT), POLLOUT);
+
+    assert_eq!(pipe::write(id, b"x"), Ok(1));
+    assert_eq!(id.ready(POLLIN | POLLOUT), POLLIN | POLLOUT);
+    assert_eq!(id.ready(POLLIN), POLLIN);
+
+    let fill = [0; PIPE_CAPACITY - 1];
+    assert_eq!(pipe::write(id, &fill), Ok(fill.len()));
+    assert_eq!(id.ready(POLLOUT), 0);
+
+    // Data stays readable after the writer is gone.
+    pipe::close(id, PipeEnd::Write);
+    assert_eq!(id.ready(POLLIN), POLLIN | POLLHUP);
+    let mut buf = [0u8; 256];
+    while pipe::read(id, &mut buf) > 0 {}
+    assert_eq!(id.ready(POLLIN), POLLHUP);
+    pipe::close(id, PipeEnd::Read);
+
+    let id = pipe::create().expect("creating the pipe failed");
+    pipe::close(id, PipeEnd::Read);
+    assert_eq!(id.ready(POLLOUT), POLLERR);
+    pipe::close(id, PipeEnd::Write);
+}
+
+#[kernel_test]
+fn scan_counts_ready_objects() {
+    let id = pipe::create().expect("creating the pipe failed");
+    let bundle = Object::Bundle {
+        entry: 0,
+        offset: 0,
+    };
+    let objects = [
+        (Some(Object::PipeRead(id)), POLLIN),
+        (Some(Object::PipeWrite(id)), POLLIN | POLLOUT),
+        (Some(bundle), POLLIN),
+        (None, POLLIN),
+    ];
+    let mut revents = [0xFFFF; 4];
+    assert_eq!(poll::scan(&objects, &mut revents), 2);
+    // Only the events of the end's direction count.
+    assert_eq!(revents, [0, POLLOUT, POLLIN, 0]);
+
+    assert_eq!(pipe::write(id, b"x"), Ok(1));
+    assert_eq!(poll::scan(&objects, &mut revents), 3);
+    assert_eq!(revents[0], POLLIN);
+
+    pipe::close(id, PipeEnd::Write);
+    pipe::close(id, PipeEnd::Read);
+}
@@ -0,0 +1,17 @@
+//! The ACPI registers and sleep type a power-off needs.
+
+use crate::acpi;
+use kernel_acpi::sleep;
+use kernel_test::kernel_test;
+
+#[kernel_test]
+fn fadt_names_pm1a_control() {
+    let fadt = acpi::fadt().expect("FADT");
+    assert!(fadt.pm1a_cnt.is_some());
+}
+
+#[kernel_test]
+fn dsdt_defines_s5() {
+    let dsdt = acpi::dsdt().expect("DSDT");
+    assert!(sleep::find_s5(dsdt).is_some());
+}
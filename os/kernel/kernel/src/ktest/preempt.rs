@@ -0,0 +1,31 @@
+//! Preemption-disabled sections and their per-CPU counter.
+
+use crate::preempt::preemptible;
+use kernel_sync::preempt::{preempt_disable, preempt_enable};
+use kernel_sync::{PreemptGuard, SpinMutex};
+use kernel_test::kernel_test;
+
+#[kernel_test]
+fn sections_nest() {
+    assert!(preemptible());
+    preempt_disable();
+    {
+        let _g = PreemptGuard::new();
+        assert!(!preemptible());
+    }
+    assert!(!preemptible());
+    preempt_enable();
+    assert!(preemptible());
+}
+
+#[kernel_test]
+fn irq_lock_disables_preemption() {
+    let lock = SpinMutex::new(0u32);
+    {
+        let mut value = lock.lock_irq();
+        *value += 1;
+        assert!(!preemptible());
+    }
+    assert!(preemptible());
+    assert_eq!(*lock.lock(), 1);
+}
@@ -0,0 +1,21 @@
+//! Kernel threads.
+
+use crate::process::spawn_kernel_thread;
+use crate::sched;
+use core::sync::atomic::{AtomicU64, Ordering};
+use kernel_sync::irq::rflags;
+use kernel_test::kernel_test;
+
+/// `RFLAGS` as seen by the thread; never zero once it ran.
+static FLAGS: AtomicU64 = AtomicU64::new(0);
+
+fn record_flags(_: usize) {
+    FLAGS.store(rflags(), Ordering::Release);
+}
+
+#[kernel_test]
+fn kernel_thread_runs_with_interrupts_enabled() {
+    spawn_kernel_thread("ktest-flags", record_flags, 0).unwrap();
+    sched::run_until(|| FLAGS.load(Ordering::Acquire) != 0);
+    assert_ne!(FLAGS.load(Ordering::Acquire) & (1 << 9), 0, "IF clear");
+}
@@ -0,0 +1,44 @@
+//! Path lookup and offset reads of the process file system.
+
+use crate::process::Pid;
+use crate::procfs::{self, ProcFile};
+use kernel_test::kernel_test;
+
+#[kernel_test]
+fn lookup_resolves_paths() {
+    assert_eq!(procfs::lookup("/proc/meminfo"), Some(ProcFile::MemInfo));
+    assert_eq!(
+        procfs::lookup("/proc/interrupts"),
+        Some(ProcFile::Interrupts)
+    );
+    assert_eq!(
+        procfs::lookup("/proc/7/maps"),
+        Pid::from_raw(7).map(ProcFile::Maps)
+    );
+
+    assert_eq!(procfs::lookup("/proc/nope"), None);
+    assert_eq!(procfs::lookup("/proc/0/maps"), None);
+    assert_eq!(procfs::lookup("/procmeminfo"), None);
+    assert_eq!(procfs::lookup("/meminfo"), None);
+    // No process is running the tests.
+    assert_eq!(procfs::lookup("/proc/self/maps"), None);
+}
+
+#[kernel_test]
+fn reads_continue_at_offset() {
+    let mut whole = [0u8; 1024];
+    let len = procfs::read(ProcFile::CpuInfo, 0, &mut whole);
+    assert!(len > 0 && len < whole.len(), "cpuinfo has {len} bytes");
+    assert!(whole[..len].starts_with(b"processor : 0\n"));
+
+    let mut pieces = [0u8; 1024];
+    let mut offset = 0;
+    loop {
+        let n = procfs::read(ProcFile::CpuInfo, offset, &mut pieces[offset..offset + 7]);
+        if n == 0 {
+            break;
+        }
+        offset += n;
+    }
+    assert_eq!(&pieces[..offset], &whole[..len]);
+}
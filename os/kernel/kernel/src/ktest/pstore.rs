@@ -0,0 +1,49 @@
+//! Panic records in the persistent store.
+
+use crate::pstore::{self, HEADER_LEN, RecordWriter};
+use core::fmt::Write;
+use kernel_test::kernel_test;
+
+#[kernel_test]
+fn records_round_trip() {
+    let mut region = [0xAAu8; 64];
+    assert_eq!(pstore::read(&region), None);
+
+    let mut record = RecordWriter::new(&mut region).expect("the region is large enough");
+    write!(record, "123456789").unwrap();
+    record.commit();
+    assert_eq!(pstore::read(&region), Some("123456789"));
+    // The CRC-32 check value.
+    assert_eq!(region[16..20], 0xCBF4_3926u32.to_le_bytes());
+
+    pstore::clear(&mut region);
+    assert_eq!(pstore::read(&region), None);
+}
+
+#[kernel_test]
+fn damaged_records_are_rejected() {
+    let mut region = [0u8; 64];
+    let mut record = RecordWriter::new(&mut region).unwrap();
+    write!(record, "panicked at x.rs:1:1").unwrap();
+    record.commit();
+    assert!(pstore::read(&region).is_some());
+
+    region[HEADER_LEN] ^= 1;
+    assert_eq!(pstore::read(&region), None);
+    region[HEADER_LEN] ^= 1;
+
+    // A length beyond the region.
+    region[12] = 0xFF;
+    assert_eq!(pstore::read(&region), None);
+
+    assert!(RecordWriter::new(&mut [0; HEADER_LEN]).is_none());
+}
+
+#[kernel_test]
+fn long_text_is_cut_at_a_character_boundary() {
+    let mut region = [0u8; HEADER_LEN + 4];
+    let mut record = RecordWriter::new(&mut region).unwrap();
+    assert!(write!(record, "ab€").is_err());
+    record.commit();
+    assert_eq!(pstore::read(&region), Some("ab"));
+}
@@ -0,0 +1,46 @@
+//! The `ChaCha20` random number generator.
+
+use crate::random::{self, chacha20_block};
+use kernel_test::kernel_test;
+
+/// RFC 8439, section 2.3.2.
+#[kernel_test]
+fn chacha20_block_matches_the_rfc() {
+    let key = [
+        0x0302_0100,
+        0x0706_0504,
+        0x0B0A_0908,
+        0x0F0E_0D0C,
+        0x1312_1110,
+        0x1716_1514,
+        0x1B1A_1918,
+        0x1F1E_1D1C,
+    ];
+    let nonce = [0x0900_0000, 0x4A00_0000, 0];
+    let block = chacha20_block(&key, 1, &nonce);
+    assert_eq!(
+        block[..16],
+        [
+            0x10, 0xF1, 0xE7, 0xE4, 0xD1, 0x3B, 0x59, 0x15, 0x50, 0x0F, 0xDD, 0x1F, 0xA3, 0x20,
+            0x71, 0xC4,
+        ]
+    );
+    assert_eq!(
+        block[48..],
+        [
+            0xB5, 0x12, 0x9C, 0xD1, 0xDE, 0x16, 0x4E, 0xB9, 0xCB, 0xD0, 0x83, 0xE8, 0xA2, 0x50,
+            0x3C, 0x4E,
+        ]
+    );
+}
+
+#[kernel_test]
+fn requests_never_repeat() {
+    let mut first = [0u8; 100];
+    let mut second = [0u8; 100];
+    random::rand_bytes(&mut first);
+    random::rand_bytes(&mut second);
+    assert_ne!(first, second);
+    // Output spans blocks without repeating one.
+    assert_ne!(first[..36], first[64..]);
+}
@@ -0,0 +1,109 @@
+//! Decoding the CMOS real-time clock.
+
+use crate::clock;
+use crate::rtc::{self, BINARY, DateTime, HOURS_24, RawTime};
+use kernel_test::kernel_test;
+
+/// 2024-02-29 23:59:59 in BCD and 12-hour format.
+const LEAP_DAY_BCD_12H: RawTime = RawTime {
+    second: 0x59,
+    minute: 0x59,
+    hour: 0x80 | 0x11,
+    day: 0x29,
+    month: 0x02,
+    year: 0x24,
+    century: Some(0x20),
+};
+
+const LEAP_DAY: DateTime = DateTime {
+    year: 2024,
+    month: 2,
+    day: 29,
+    hour: 23,
+    minute: 59,
+    second: 59,
+};
+
+#[kernel_test]
+fn bcd_and_binary_registers_decode_alike() {
+    assert_eq!(LEAP_DAY_BCD_12H.decode(0), Some(LEAP_DAY));
+
+    let binary = RawTime {
+        second: 59,
+        minute: 59,
+        hour: 23,
+        day: 29,
+        month: 2,
+        year: 24,
+        century: None,
+    };
+    assert_eq!(binary.decode(BINARY | HOURS_24), Some(LEAP_DAY));
+}
+
+#[kernel_test]
+fn twelve_am_is_midnight() {
+    let midnight = RawTime {
+        hour: 0x12,
+        ..LEAP_DAY_BCD_12H
+    };
+    assert_eq!(midnight.decode(0).map(|t| t.hour), Some(0));
+
+    let noon = RawTime {
+        hour: 0x80 | 0x12,
+        ..LEAP_DAY_BCD_12H
+    };
+    assert_eq!(noon.decode(0).map(|t| t.hour), Some(12));
+}
+
+#[kernel_test]
+fn invalid_dates_are_rejected() {
+    let not_leap = RawTime {
+        year: 0x23,
+        ..LEAP_DAY_BCD_12H
+    };
+    assert_eq!(not_leap.decode(0), None);
+
+    let not_bcd = RawTime {
+        minute: 0x5A,
+        ..LEAP_DAY_BCD_12H
+    };
+    assert_eq!(not_bcd.decode(0), None);
+
+    let hour_zero = RawTime {
+        hour: 0,
+        ..LEAP_DAY_BCD_12H
+    };
+    assert_eq!(hour_zero.decode(0), None);
+}
+
+#[kernel_test]
+fn dates_convert_to_unix_time() {
+    let epoch = DateTime {
+        year: 1970,
+        month: 1,
+        day: 1,
+        hour: 0,
+        minute: 0,
+        second: 0,
+    };
+    assert_eq!(epoch.unix_seconds(), 0);
+    assert_eq!(
+        DateTime {
+            year: 2000,
+            month: 3,
+            ..epoch
+        }
+        .unix_seconds(),
+        951_868_800
+    );
+    assert_eq!(LEAP_DAY.unix_seconds(), 1_709_251_199);
+}
+
+#[kernel_test]
+fn wall_clock_follows_the_rtc() {
+    let now = rtc::read().expect("RTC");
+    assert!(now.year >= 2024, "RTC says {now}");
+
+    let wall = clock::unix_time().expect("wall clock set at boot");
+    assert!(wall.abs_diff(now.unix_seconds()) <= 2);
+}
@@ -0,0 +1,91 @@
+//! Priority run queues and aging.
+
+use crate::sched::RunQueue;
+use crate::sched::priority::{Priority, SchedInfo, WAKE_BOOST};
+use kernel_test::kernel_test;
+
+const fn prio(level: u64) -> Priority {
+    Priority::new(level).unwrap()
+}
+
+#[kernel_test]
+fn highest_level_first_then_fifo() {
+    let mut queue = RunQueue::new();
+    queue.push_back(Priority::DEFAULT, 1);
+    queue.push_back(prio(0), 2);
+    queue.push_back(Priority::MAX, 3);
+    queue.push_back(Priority::DEFAULT, 4);
+
+    assert_eq!(queue.highest(), Some(Priority::MAX));
+    assert_eq!(queue.pop_front(), Some(3));
+    assert_eq!(queue.pop_front(), Some(1));
+    assert_eq!(queue.pop_front(), Some(4));
+    assert_eq!(queue.pop_front(), Some(2));
+    assert_eq!(queue.pop_front(), None);
+    assert_eq!(queue.highest(), None);
+}
+
+#[kernel_test]
+fn remove_requeues_at_new_level() {
+    let mut queue = RunQueue::new();
+    queue.push_back(prio(3), 5);
+    queue.push_back(prio(7), 6);
+
+    assert!(queue.remove(5));
+    assert!(!queue.remove(5));
+    queue.push_back(prio(9), 5);
+    assert_eq!(queue.pop_front(), Some(5));
+    assert_eq!(queue.pop_front(), Some(6));
+}
+
+#[kernel_test]
+fn filtered_pop_skips_ineligible_slots() {
+    let mut queue = RunQueue::new();
+    queue.push_back(Priority::MAX, 1);
+    queue.push_back(Priority::DEFAULT, 2);
+    queue.push_back(Priority::DEFAULT, 3);
+
+    let odd = |slot: usize| slot % 2 == 1;
+    assert_eq!(
+        queue.first_where(|slot| slot != 1),
+        Some((Priority::DEFAULT, 2))
+    );
+    assert_eq!(queue.pop_front_where(|slot| !odd(slot)), Some(2));
+    assert_eq!(queue.pop_front_where(|slot| slot > 5), None);
+    assert_eq!(queue.pop_front_where(odd), Some(1));
+    assert_eq!(queue.pop_front(), Some(3));
+    assert_eq!(queue.highest(), None);
+}
+
+#[kernel_test]
+fn rejects_out_of_range_priority() {
+    assert_eq!(Priority::new(32), None);
+    assert_eq!(Priority::MAX.raised(1), Priority::MAX);
+}
+
+#[kernel_test]
+fn waiting_process_ages_and_drops_boost_when_run() {
+    let mut info = SchedInfo::new(prio(0), 0);
+    assert!(!info.age(9, 10));
+    assert!(info.age(10, 10));
+    assert!(!info.age(19, 10));
+    assert!(info.age(20, 10));
+    assert_eq!(info.priority(), prio(2));
+
+    info.started(25);
+    assert_eq!(info.priority(), prio(0));
+    assert_eq!(info.wait_ticks, 25);
+}
+
+#[kernel_test]
+fn wakeup_boosts_and_accounts_sleep() {
+    let mut info = SchedInfo::new(Priority::DEFAULT, 0);
+    info.started(0);
+    info.stopped(4);
+    info.woken(10);
+
+    assert_eq!(info.run_ticks, 4);
+    assert_eq!(info.sleep_ticks, 6);
+    assert_eq!(info.wakeups, 1);
+    assert_eq!(info.priority(), Priority::DEFAULT.raised(WAKE_BOOST));
+}
@@ -0,0 +1,94 @@
+//! Running a test so that a panic returns to the runner.
+
+use core::arch::naked_asm;
+use kernel_test::KernelTest;
+
+/// Callee-saved registers, stack pointer and flags of a [`call_guarded`] call.
+#[repr(C)]
+pub struct Recovery {
+    rbx: u64,
+    rbp: u64,
+    r12: u64,
+    r13: u64,
+    r14: u64,
+    r15: u64,
+    /// Points at the return address of the [`call_guarded`] call.
+    rsp: u64,
+    rflags: u64,
+}
+
+impl Recovery {
+    pub const fn new() -> Self {
+        Self {
+            rbx: 0,
+            rbp: 0,
+            r12: 0,
+            r13: 0,
+            r14: 0,
+            r15: 0,
+            rsp: 0,
+            rflags: 0,
+        }
+    }
+}
+
+/// Run `test`; returns `true` if it panicked and [`resume`] returned here.
+///
+/// # Safety
+/// `recovery` must stay valid until this call returns.
+pub unsafe fn call_guarded(recovery: *mut Recovery, test: &'static KernelTest) -> bool {
+    unsafe { save_and_call(recovery, test, run_test) != 0 }
+}
+
+extern "C" fn run_test(test: &'static KernelTest) {
+    (test.run)();
+}
+
+/// Save the context into `*recovery`, then call `f(test)`. Returns `0`, or
+/// `1` when [`resume`] restores the context.
+#[unsafe(naked)]
+unsafe extern "C" fn save_and_call(
+    recovery: *mut Recovery,
+    test: &'static KernelTest,
+    f: extern "C" fn(&'static KernelTest),
+) -> u64 {
+    naked_asm!(
+        "mov [rdi], rbx",
+        "mov [rdi + 8], rbp",
+        "mov [rdi + 16], r12",
+        "mov [rdi + 24], r13",
+        "mov [rdi + 32], r14",
+        "mov [rdi + 40], r15",
+        "mov [rdi + 48], rsp",
+        "pushfq",
+        "pop qword ptr [rdi + 56]",
+        // Entered with RSP ≡ 8 (mod 16); realign for the call.
+        "sub rsp, 8",
+        "mov rdi, rsi",
+        "call rdx",
+        "add rsp, 8",
+        "xor eax, eax",
+        "ret",
+    )
+}
+
+/// Return from the [`save_and_call`] that filled `recovery`, with result `1`.
+///
+/// # Safety
+/// The `save_and_call` must still be running, i.e. its caller's frame is intact.
+#[unsafe(naked)]
+pub unsafe extern "C" fn resume(recovery: *const Recovery) -> ! {
+    naked_asm!(
+        "mov rbx, [rdi]",
+        "mov rbp, [rdi + 8]",
+        "mov r12, [rdi + 16]",
+        "mov r13, [rdi + 24]",
+        "mov r14, [rdi + 32]",
+        "mov r15, [rdi + 40]",
+        "mov rsp, [rdi + 48]",
+        "push qword ptr [rdi + 56]",
+        "popfq",
+        "mov eax, 1",
+        "ret",
+    )
+}
@@ -0,0 +1,47 @@
+//! Pending signal bookkeeping and validation of user-supplied FP/SIMD state.
+
+use crate::fpu::{self, AREA_SIZE, FpuState};
+use crate::signal::{Disposition, SignalState};
+use kernel_sync::IrqGuard;
+use kernel_test::kernel_test;
+use stdlib::syscall_abi::signal::{SIGCHLD, SIGTERM, SIGUSR1};
+
+#[kernel_test]
+fn pending_signals_are_taken_lowest_first() {
+    let mut state = SignalState::new();
+    state.raise(SIGCHLD);
+    state.raise(SIGUSR1);
+    state.raise(SIGUSR1);
+
+    assert_eq!(state.take_pending(), Some((SIGUSR1, Disposition::Default)));
+    assert_eq!(state.take_pending(), Some((SIGCHLD, Disposition::Default)));
+    assert_eq!(state.take_pending(), None);
+}
+
+#[kernel_test]
+fn forked_state_has_nothing_pending() {
+    let mut state = SignalState::new();
+    state.raise(SIGTERM);
+    assert_eq!(state.fork().take_pending(), None);
+}
+
+#[kernel_test]
+fn saved_fpu_state_is_accepted_back() {
+    let _irq = IrqGuard::new();
+    let mut state = FpuState::new();
+    unsafe { fpu::save(&mut state) };
+
+    let restored = FpuState::from_untrusted(state.as_bytes()).expect("own state was rejected");
+    assert_eq!(restored.mxcsr(), state.mxcsr());
+}
+
+#[kernel_test]
+fn reserved_mxcsr_bits_are_rejected() {
+    let _irq = IrqGuard::new();
+    let mut state = FpuState::new();
+    unsafe { fpu::save(&mut state) };
+
+    let mut area: [u8; AREA_SIZE] = *state.as_bytes();
+    area[24 + 2] = 0x01; // MXCSR bit 16
+    assert!(FpuState::from_untrusted(&area).is_none());
+}
@@ -0,0 +1,20 @@
+//! Growing user stacks on demand.
+
+use crate::process::stack_growth::{GrowError, grow, limit};
+use crate::process::{Pid, USER_STACK_INITIAL_PAGES, USER_STACK_PAGES, USER_STACK_TOP};
+use kernel_memory_addresses::{PageSize, Size4K, VirtualAddress};
+use kernel_test::kernel_test;
+
+#[kernel_test]
+fn limit_lies_within_the_reservation() {
+    let limit = limit();
+    assert!(limit >= USER_STACK_INITIAL_PAGES.get() * Size4K::SIZE);
+    assert!(limit <= USER_STACK_PAGES.get() * Size4K::SIZE);
+}
+
+#[kernel_test]
+fn unknown_processes_do_not_grow() {
+    let pid = Pid::from_raw(0xFFFF_FFFF).unwrap();
+    let below = VirtualAddress::new(USER_STACK_TOP.as_u64() - 1024 * Size4K::SIZE);
+    assert_eq!(grow(pid, below), Err(GrowError::NotStack));
+}
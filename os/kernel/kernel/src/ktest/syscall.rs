@@ -0,0 +1,111 @@
+//! The system call dispatcher.
+
+use crate::process::Pid;
+use crate::sched;
+use crate::syscall::{SyscallSource, syscall};
+use kernel_test::kernel_test;
+use stdlib::syscall_abi::{LOG_RECORD_LEN, SYSCALL_ERROR, Sysno};
+
+#[kernel_test]
+fn dispatch_reports_source() {
+    let bogus = Sysno::Bogus as u64;
+    assert_eq!(
+        syscall(bogus, 0, 0, 0, 0, 0, 0, SyscallSource::Syscall),
+        0xb007_c4fe
+    );
+    assert_eq!(
+        syscall(bogus, 0, 0, 0, 0, 0, 0, SyscallSource::Int80h),
+        0xd34d_c0d3
+    );
+}
+
+#[kernel_test]
+fn unknown_syscall_fails() {
+    assert_eq!(
+        syscall(u64::MAX, 0, 0, 0, 0, 0, 0, SyscallSource::Syscall),
+        u64::MAX
+    );
+}
+
+#[kernel_test]
+fn log_rejects_invalid_level() {
+    assert_eq!(
+        syscall(Sysno::Log as u64, 0, 0, 0, 0, 0, 0, SyscallSource::Syscall),
+        SYSCALL_ERROR
+    );
+}
+
+#[kernel_test]
+fn log_read_rejects_kernel_buffer() {
+    let mut buf = [0u8; LOG_RECORD_LEN];
+    let ptr = buf.as_mut_ptr() as u64;
+    assert_eq!(
+        syscall(
+            Sysno::LogRead as u64,
+            ptr,
+            buf.len() as u64,
+            0,
+            0,
+            0,
+            0,
+            SyscallSource::Syscall
+        ),
+        SYSCALL_ERROR
+    );
+}
+
+#[kernel_test]
+fn syscall_numbers_decode_to_their_names() {
+    for sysno in Sysno::ALL {
+        assert_eq!(Sysno::from_raw(sysno as u64), Some(sysno));
+    }
+    assert_eq!(Sysno::from_raw(0), None);
+    assert_eq!(Sysno::Open.name(), "open");
+}
+
+#[kernel_test]
+fn trace_rejects_unknown_processes_and_switches() {
+    let trace = Sysno::Trace as u64;
+    assert_eq!(
+        syscall(trace, 0xFFFF_FFFF, 1, 0, 0, 0, 0, SyscallSource::Syscall),
+        SYSCALL_ERROR
+    );
+    assert_eq!(
+        syscall(trace, 1, 2, 0, 0, 0, 0, SyscallSource::Syscall),
+        SYSCALL_ERROR
+    );
+}
+
+#[kernel_test]
+fn reboot_needs_the_capability() {
+    let reboot = Sysno::Reboot as u64;
+    assert_eq!(
+        syscall(reboot, 1, 0, 0, 0, 0, 0, SyscallSource::Syscall),
+        SYSCALL_ERROR
+    );
+    assert_eq!(
+        syscall(reboot, 0, 0, 0, 0, 0, 0, SyscallSource::Syscall),
+        SYSCALL_ERROR
+    );
+}
+
+#[kernel_test]
+fn sched_setaffinity_needs_an_online_cpu_and_a_process() {
+    let setaffinity = Sysno::SchedSetAffinity as u64;
+    assert_ne!(sched::online_cpus() & 1, 0, "boot CPU online");
+    assert_eq!(
+        syscall(
+            setaffinity,
+            0xFFFF_FFFF,
+            1,
+            0,
+            0,
+            0,
+            0,
+            SyscallSource::Syscall
+        ),
+        SYSCALL_ERROR
+    );
+    assert_eq!(sched::set_affinity(Pid::INIT, 0), None);
+    assert_eq!(sched::set_affinity(Pid::INIT, !sched::online_cpus()), None);
+}
@@ -0,0 +1,67 @@
+//! Software timers on the timer wheel.
+
+use crate::sched::now_ticks;
+use crate::timer::{self, cancel, schedule, schedule_periodic};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use kernel_test::kernel_test;
+
+static FIRED: AtomicUsize = AtomicUsize::new(0);
+
+fn count(arg: usize) {
+    FIRED.fetch_add(arg, Ordering::Relaxed);
+}
+
+#[kernel_test]
+fn one_shot_fires_once_at_deadline() {
+    let before = FIRED.load(Ordering::Relaxed);
+    let now = now_ticks();
+    let handle = schedule(now + 3, count, 1).unwrap();
+
+    timer::on_tick(now + 2);
+    assert_eq!(FIRED.load(Ordering::Relaxed), before);
+    timer::on_tick(now + 3);
+    assert_eq!(FIRED.load(Ordering::Relaxed), before + 1);
+    timer::on_tick(now + 10);
+    assert_eq!(FIRED.load(Ordering::Relaxed), before + 1);
+
+    assert!(!cancel(handle), "fired timer cancelled");
+}
+
+#[kernel_test]
+fn cancelled_timer_does_not_fire() {
+    let before = FIRED.load(Ordering::Relaxed);
+    let now = now_ticks();
+    let handle = schedule(now + 2, count, 100).unwrap();
+
+    assert!(cancel(handle));
+    assert!(!cancel(handle));
+    timer::on_tick(now + 5);
+    assert_eq!(FIRED.load(Ordering::Relaxed), before);
+}
+
+#[kernel_test]
+fn far_timer_cascades_down() {
+    let before = FIRED.load(Ordering::Relaxed);
+    let now = now_ticks();
+    let handle = schedule(now + 200, count, 1).unwrap();
+
+    timer::on_tick(now + 199);
+    assert_eq!(FIRED.load(Ordering::Relaxed), before);
+    timer::on_tick(now + 200);
+    assert_eq!(FIRED.load(Ordering::Relaxed), before + 1);
+    assert!(!cancel(handle));
+}
+
+#[kernel_test]
+fn periodic_fires_until_cancelled() {
+    let before = FIRED.load(Ordering::Relaxed);
+    let now = now_ticks();
+    let handle = schedule_periodic(now + 1, 2, count, 1).unwrap();
+
+    timer::on_tick(now + 5);
+    assert_eq!(FIRED.load(Ordering::Relaxed), before + 3);
+
+    assert!(cancel(handle));
+    timer::on_tick(now + 9);
+    assert_eq!(FIRED.load(Ordering::Relaxed), before + 3);
+}
@@ -0,0 +1,60 @@
+//! Finding the TSC frequency.
+
+use crate::clock;
+use crate::cpuid::{CpuidResult, Hypervisor};
+use crate::pit;
+use crate::tsc::{Confidence, TscSource, rdtsc};
+use kernel_test::kernel_test;
+
+#[kernel_test]
+fn calibration_is_recorded_with_its_source() {
+    let (source, tsc) = clock::tsc_calibration().expect("TSC calibrated at boot");
+    assert_eq!(tsc.hz, clock::tsc_hz());
+    assert_eq!(
+        tsc.error_hz.is_none(),
+        source.confidence() == Confidence::Nominal,
+        "{tsc} from {source}"
+    );
+}
+
+#[kernel_test]
+fn deterministic_sources_agree_with_the_pit() {
+    let (source, tsc) = clock::tsc_calibration().expect("TSC calibrated at boot");
+    if source == TscSource::Pit {
+        return;
+    }
+
+    let measured = pit::measure(20, rdtsc).expect("the PIT does not count");
+    let slack = measured.error_hz.unwrap_or(0) + tsc.error_hz.unwrap_or(0) + tsc.hz / 50;
+    assert!(
+        measured.hz.abs_diff(tsc.hz) <= slack,
+        "{source} says {tsc}, the PIT {measured}"
+    );
+}
+
+#[kernel_test]
+fn hypervisor_signatures_are_recognized() {
+    let leaf = |signature: &[u8; 12], eax| {
+        let reg = |i: usize| u32::from_le_bytes(signature[i..i + 4].try_into().unwrap());
+        unsafe {
+            Hypervisor::from(CpuidResult {
+                eax,
+                ebx: reg(0),
+                ecx: reg(4),
+                edx: reg(8),
+            })
+        }
+    };
+
+    let kvm = leaf(b"KVMKVMKVM\0\0\0", 0);
+    assert_eq!(kvm.vendor.as_str(), "KVM");
+    assert_eq!(kvm.max_leaf, 0x4000_0001);
+    assert!(!kvm.has_leaf(0x4000_0010));
+
+    let hyperv = leaf(b"Microsoft Hv", 0x4000_000B);
+    assert_eq!(hyperv.vendor.as_str(), "Hyper-V");
+    assert!(hyperv.has_leaf(0x4000_0003));
+
+    assert_eq!(leaf(b"TCGTCGTCGTCG", 0x4000_0001).vendor.as_str(), "TCG");
+    assert_eq!(leaf(b"NotAHypervsr", 0x4000_0001).vendor.as_str(), "Other");
+}
@@ -0,0 +1,52 @@
+//! Line editing and raw mode of the terminal.
+
+use crate::chardev::CharDevice;
+use crate::tty::TTY;
+use kernel_test::kernel_test;
+use stdlib::syscall_abi::tty::{MODE_CANONICAL, MODE_DEFAULT, TTY_GET_MODE, TTY_SET_MODE};
+
+/// Read once from the terminal into `buf`; returns what was read.
+fn read(buf: &mut [u8]) -> &[u8] {
+    let len = TTY.read(buf);
+    &buf[..len]
+}
+
+#[kernel_test]
+fn canonical_mode_hands_out_edited_lines() {
+    let mut buf = [0; 16];
+    assert_eq!(
+        TTY.control(TTY_SET_MODE, MODE_CANONICAL),
+        Some(MODE_DEFAULT)
+    );
+    TTY.receive("ab\u{8}c".chars());
+    assert_eq!(TTY.read(&mut buf), 0);
+
+    // One line per read; Ctrl-U erases the line typed so far.
+    TTY.receive("c\nxy\u{15}d\n".chars());
+    assert_eq!(read(&mut buf), b"acc\n");
+    assert_eq!(read(&mut buf), b"d\n");
+
+    assert_eq!(
+        TTY.control(TTY_SET_MODE, MODE_DEFAULT),
+        Some(MODE_CANONICAL)
+    );
+}
+
+#[kernel_test]
+fn raw_mode_hands_out_every_character() {
+    let mut buf = [0; 16];
+    assert_eq!(
+        TTY.control(TTY_SET_MODE, MODE_CANONICAL),
+        Some(MODE_DEFAULT)
+    );
+    TTY.receive("ab".chars());
+
+    // Switching hands out the pending line; control characters pass.
+    TTY.control(TTY_SET_MODE, 0);
+    TTY.receive("\u{3}é".chars());
+    assert_eq!(read(&mut buf), "ab\u{3}é".as_bytes());
+    assert_eq!(TTY.control(TTY_GET_MODE, 0), Some(0));
+    assert_eq!(TTY.control(TTY_SET_MODE, 1 << 7), None);
+
+    TTY.control(TTY_SET_MODE, MODE_DEFAULT);
+}
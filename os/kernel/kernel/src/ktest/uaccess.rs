@@ -0,0 +1,74 @@
+//! Validation of syscall pointer arguments.
+
+use crate::uaccess::{UserAccessError, UserIoVec, UserPtr, UserSlice, rejected_accesses};
+use kernel_info::memory::{HHDM_BASE, LAST_USERSPACE_ADDRESS};
+use kernel_test::kernel_test;
+use stdlib::syscall_abi::MAX_IOV;
+
+/// Page aligned user address; nothing needs to be mapped there.
+const USER: u64 = 0x40_0000;
+
+#[kernel_test]
+fn kernel_addresses_are_refused() {
+    let before = rejected_accesses();
+    assert_eq!(
+        UserPtr::<u64>::new(HHDM_BASE.as_u64()).err(),
+        Some(UserAccessError::NotUserMemory)
+    );
+    assert_eq!(
+        UserSlice::new(LAST_USERSPACE_ADDRESS.as_u64(), 2).err(),
+        Some(UserAccessError::NotUserMemory)
+    );
+    assert_eq!(rejected_accesses(), before + 2);
+}
+
+#[kernel_test]
+fn wrapping_ranges_are_refused() {
+    assert_eq!(
+        UserSlice::new(u64::MAX, 2).err(),
+        Some(UserAccessError::NotUserMemory)
+    );
+}
+
+#[kernel_test]
+fn the_null_page_is_refused() {
+    assert_eq!(UserPtr::<u32>::new(0).err(), Some(UserAccessError::Guard));
+    assert_eq!(
+        UserSlice::new(0xFF0, 0x20).err(),
+        Some(UserAccessError::Guard)
+    );
+}
+
+#[kernel_test]
+fn empty_slices_are_accepted_anywhere() {
+    assert!(UserSlice::new(0, 0).is_ok());
+}
+
+#[kernel_test]
+fn reads_refuse_more_than_the_kernel_takes() {
+    let slice = UserSlice::new(USER, 32).unwrap();
+    assert_eq!(slice.read_vec::<16>().err(), Some(UserAccessError::TooLong));
+    assert_eq!(slice.read_into(&mut [0; 64]), Err(UserAccessError::TooLong));
+}
+
+#[kernel_test]
+fn sub_slices_stay_inside() {
+    let slice = UserSlice::new(USER, 32).unwrap();
+    let tail = slice.sub(24, 16);
+    assert_eq!((tail.addr(), tail.len()), (USER + 24, 8));
+    assert!(slice.sub(40, 8).is_empty());
+}
+
+#[kernel_test]
+fn io_vectors_are_limited_and_clamped() {
+    assert_eq!(
+        UserIoVec::new(USER, MAX_IOV + 1).err(),
+        Some(UserAccessError::TooLong)
+    );
+
+    let iov = UserIoVec::from(UserSlice::new(USER, 32).unwrap());
+    assert_eq!(iov.len(), 32);
+    assert_eq!(iov.sub(24, 16).len(), 8);
+    assert_eq!(iov.sub(40, 8).len(), 0);
+    assert_eq!(iov.read_into(&mut [0; 64]), Err(UserAccessError::TooLong));
+}
@@ -0,0 +1,43 @@
+//! Deferred and delayed work items.
+
+use crate::sched::now_ticks;
+use crate::timer;
+use crate::workqueue::{self, schedule_delayed_work, schedule_work};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use kernel_test::kernel_test;
+
+static SUM: AtomicUsize = AtomicUsize::new(0);
+
+fn add(arg: usize) {
+    SUM.fetch_add(arg, Ordering::Relaxed);
+}
+
+#[kernel_test]
+fn queued_work_runs_in_order() {
+    static ORDER: AtomicUsize = AtomicUsize::new(0);
+    fn append(digit: usize) {
+        let _ = ORDER.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |v| {
+            Some(v * 10 + digit)
+        });
+    }
+
+    schedule_work(append, 1).unwrap();
+    schedule_work(append, 2).unwrap();
+    while workqueue::run_pending() > 0 {}
+    assert_eq!(ORDER.load(Ordering::Relaxed), 12);
+}
+
+#[kernel_test]
+fn delayed_work_waits_for_deadline() {
+    let before = SUM.load(Ordering::Relaxed);
+    let now = now_ticks();
+    schedule_delayed_work(add, 5, 1).unwrap();
+
+    timer::on_tick(now);
+    while workqueue::run_pending() > 0 {}
+    assert_eq!(SUM.load(Ordering::Relaxed), before);
+
+    timer::on_tick(now + timer::ms_to_ticks(1));
+    while workqueue::run_pending() > 0 {}
+    assert_eq!(SUM.load(Ordering::Relaxed), before + 5);
+}
@@ -32,19 +32,68 @@
 //! The kernel follows a monolithic design with modular components:
 //!
 //! * `alloc`: Memory allocation and virtual memory management
+//! * `memmap`: Usable physical memory from the UEFI memory map
+//! * `hhdm`: Higher-half direct map, extended past the loader's mapping at runtime
+//! * `boot_alloc`: Bump allocator for early boot, before the frame allocator exists
+//! * `pat`: Page Attribute Table setup (write-combining)
+//! * `fpu`: FPU/SSE/AVX enablement and per-process `XSAVE` state
 //! * `interrupts`: Exception and interrupt handling subsystem
+//! * `irq_stats`: Interrupt counts per CPU and vector
+//! * `irq_thread`: Device interrupts split into a top half and a bottom-half kernel thread
+//! * `extable`: Expected kernel faults, such as MSR probes, and where to resume
 //! * `apic`: Advanced Programmable Interrupt Controller support
+//! * `acpi`: Lookup of the firmware's ACPI tables
+//! * `ioapic`: I/O APIC routing of chipset IRQs
+//! * `pit`: 8254 PIT, the reference for clock calibration and a fallback timer tick
+//! * `rtc`: CMOS real-time clock, the wall clock at boot and a last-resort timer tick
+//! * `clock`/`tsc`: TSC calibration and clock conversion parameters
+//! * `clock_page`: The clock parameters, mapped read-only into every process
+//! * `random`: Entropy pool and `ChaCha20` random number generator
+//! * `cmdline`: Kernel command line passed by the loader, with typed lookups
 //! * `gdt`/`tss`: Global Descriptor Table and Task State Segment
 //! * `userland`: User mode task creation and privilege switching
-//! * `framebuffer`: Graphics and display management
+//! * `process`/`sched`: Process table, spawn/wait/exit, kernel threads and a priority scheduler
+//! * `tasks`: Task listing over the console and for the `task_info` system call
+//! * `procfs`: Synthetic files under `/proc` exposing kernel state
+//! * `preempt`: Time slices and preemption-disabled sections
+//! * `timer`: Timer wheel of one-shot and periodic software timers
+//! * `signal`: Pending signals, dispositions and user signal handler frames
+//! * `idle`: HLT/MWAIT idle loop with C-state selection
+//! * `hotplug`: Parking CPUs and bringing them back online
+//! * `power`: Orderly power-off, reboot and halt through ACPI and reset registers
+//! * `boot_modules`: Files the loader passed along (`module` in `boot.cfg`)
+//! * `bundlefs`: Read-only lookup of programs in the userland bundle
+//! * `fw_cfg`: Files passed by the QEMU host through the `fw_cfg` device
+//! * `dir`: Listings of `/`, `/dev` and `/proc` for the `readdir` system call
+//! * `keyboard`: Polled PS/2 keyboard, keymaps and key events
+//! * `pci`: PCI configuration space access and device lookup
+//! * `virtio`: Legacy virtio transport and the virtio console
+//! * `nvme`: NVM Express controllers, their namespaces as block devices
+//! * `ahci`: AHCI host bus adapters, their SATA disks as block devices
+//! * `xhci`: USB host controllers, for a boot protocol keyboard
+//! * `chardev`: Character devices under `/dev`, such as the virtio console
+//! * `block`: Block devices, such as NVMe namespaces and SATA disks
+//! * `page_cache`: Recently read pages of block devices and files, with LRU eviction
+//! * `tty`: `/dev/console`, keyboard input and framebuffer output with line editing
+//! * `watchdog`: Per-CPU heartbeats and soft/hard lockup detection
+//! * `workqueue`: Work deferred from interrupt handlers, optionally delayed
+//! * `profiler`: Sampling profiler driven by performance counter NMIs or the timer
+//! * `tracepoint`: Static tracepoints recording events into per-CPU rings
+//! * `strace`: Per-process system call tracing, logged and recorded as tracepoints
+//! * `kimage`: Kernel segment ranges, W^X enforcement and address classification
+//! * `klog`: Kernel logger with an in-memory log ring
+//! * `ksyms`: Kernel symbol table for symbolizing addresses and backtraces
+//! * `kdb`: Interactive debug monitor on the virtio console
+//! * `ktest`: In-kernel integration tests, run at boot with the `ktest` feature
+//! * `framebuffer`: Graphics and display management, with a double-buffered compositor
 //!
 //! ## Main Loop Behavior
 //!
 //! The kernel's main loop demonstrates a breathing LED effect by:
 //! - Measuring timer frequency against TSC
 //! - Calculating sinusoidal brightness values over 2-second periods
-//! - Updating framebuffer with computed brightness
-//! - Transitioning to userland after 2 seconds
+//! - Drawing the computed brightness into the compositor's back buffer and presenting it
+//! - Spawning `init` and handing the CPU to the scheduler after 2 seconds
 //!
 //! ## Safety
 //!
@@ -56,42 +105,95 @@
 #![no_main]
 #![allow(unsafe_code)]
 
+mod acpi;
+mod ahci;
 mod alloc;
 mod apic;
+mod block;
+mod boot_alloc;
+mod boot_modules;
+mod bundlefs;
+mod chardev;
+mod clock;
+mod clock_page;
+mod cmdline;
 mod cpuid;
+mod dir;
 mod elf;
+mod extable;
+mod fpu;
 mod framebuffer;
+mod fw_cfg;
 mod gdt;
+mod hhdm;
+mod hotplug;
+mod idle;
 mod idt;
 mod init;
 mod interrupts;
+mod ioapic;
+mod irq_stats;
+mod irq_thread;
+mod kdb;
+mod keyboard;
+mod kimage;
+mod klog;
+mod ksyms;
+#[cfg(feature = "ktest")]
+mod ktest;
+mod memmap;
 mod msr;
+mod nvme;
+mod page_cache;
 mod panik;
+mod pat;
+mod pci;
 mod per_cpu;
-mod ports;
+mod pipe;
+mod pit;
+mod poll;
+mod power;
+mod preempt;
 mod privilege;
+mod process;
+mod procfs;
+mod profiler;
+mod pstore;
+mod random;
+mod rtc;
+mod sched;
+mod shm;
+mod signal;
 mod smap;
+mod strace;
 mod syscall;
 mod task;
+mod tasks;
+mod timer;
+mod tracepoint;
 mod tracing;
 mod tsc;
 mod tss;
+mod tty;
+mod uaccess;
 mod userland;
+mod virtio;
+mod watchdog;
+mod workqueue;
+mod xhci;
 
-use crate::alloc::{FlushTlb, try_with_kernel_vmm};
-use crate::framebuffer::fill_solid;
+use crate::framebuffer::compositor::with_compositor;
+use crate::framebuffer::font;
+use crate::framebuffer::{Color, Rect, fill_solid};
 use crate::per_cpu::PerCpu;
-use crate::smap::SmapGuard;
+use crate::process::ArgBuf;
+use crate::sched::WaitQueue;
 use crate::tracing::log_ctrl_bits;
-use crate::tsc::{estimate_tsc_hz, rdtsc};
-use crate::userland::{enter_user_mode, parse_userland_bundle};
+use crate::tsc::rdtsc;
 use core::f32::consts::{PI, TAU};
 use core::hint::spin_loop;
-use core::num::NonZeroU64;
-use core::sync::atomic::{AtomicU64, Ordering};
-use kernel_alloc::phys_mapper::HhdmPhysMapper;
-use kernel_info::boot::{FramebufferInfo, UserBundleInfo};
-use kernel_memory_addresses::VirtualAddress;
+use core::sync::atomic::Ordering;
+use kernel_info::boot::FramebufferInfo;
 use log::info;
 
 /// Main kernel loop, running with all memory (including framebuffer) properly mapped.
@@ -112,30 +214,40 @@ use log::info;
     clippy::cast_sign_loss,
     clippy::cast_precision_loss
 )]
-fn kernel_main(fb_virt: &FramebufferInfo, user: &UserBundleInfo) -> ! {
+fn kernel_main(fb_virt: &FramebufferInfo) -> ! {
     info!("Kernel doing kernel things now ...");
 
-    let ustack_top = VirtualAddress::new(0x0000_7fff_f000);
-    let num_stack_pages = unsafe { NonZeroU64::new_unchecked(2048) }; // 8 MiB
-    let (va, ustack_top) = try_with_kernel_vmm(FlushTlb::OnSuccess, |vmm| {
-        let _guard = SmapGuard::enter();
-        parse_userland_bundle(user, vmm, ustack_top, num_stack_pages)
-    })
-    .expect("Failed to parse userland bundle");
+    with_compositor(|c| {
+        let (x, y) = (c.width() / 4, c.height() / 4);
+        c.draw_text(
+            x,
+            y.saturating_sub(2 * font::current().height()),
+            "Kernel doing kernel things now ...",
+            Color::WHITE,
+            None,
+        );
+        let frame = Rect::new(
+            x.saturating_sub(2),
+            y.saturating_sub(2),
+            c.width() / 2 + 4,
+            c.height() / 2 + 4,
+        );
+        c.draw(|s| s.draw_rect(frame, Color::WHITE));
+    });
 
     let cpu = unsafe { PerCpu::current() };
     let start = cpu.ticks.load(Ordering::Acquire);
     let mut prev = 0;
 
-    if TIMER_HZ.load(Ordering::Acquire) == 0 {
-        let hz = unsafe { measure_timer_hz(cpu) };
-        TIMER_HZ.store(hz.max(1), Ordering::Release);
+    if clock::timer_hz() == 0 {
+        let hz = measure_timer_hz(cpu);
+        clock::set_timer_hz(hz.max(1));
         info!("Observed timer rate ≈ {hz} Hz");
     }
 
     loop {
         let ticks = cpu.ticks.load(Ordering::Acquire);
-        let hz = TIMER_HZ.load(Ordering::Acquire);
+        let hz = clock::timer_hz();
 
         // Phase from integer modulo: 2-second period
         let period_ticks = 2 * hz; // 2 s
@@ -163,16 +275,34 @@ fn kernel_main(fb_virt: &FramebufferInfo, user: &UserBundleInfo) -> ! {
             info!("Kernel cycle: {prev} s");
         }
 
-        unsafe { fill_solid(fb_virt, 72, 0, brightness) };
+        let color = Color::new(72, 0, brightness);
+        let presented = with_compositor(|c| {
+            let (w, h) = (c.width(), c.height());
+            c.fill_rect(Rect::new(w / 4, h / 4, w / 2, h / 2), color);
+            c.present();
+        });
+        if presented.is_none() {
+            unsafe { fill_solid(fb_virt, color.r, color.g, color.b) };
+        }
         spin_loop();
+        watchdog::feed();
 
         if prev == 2 {
             info!("About to enter user mode ...");
             log_ctrl_bits();
-            alloc::debug::dump_walk(&HhdmPhysMapper, va);
+
+            let mut args = ArgBuf::new();
+            args.push(b"/init").expect("init arguments exceed capacity");
+            let pid =
+                process::spawn("/init", &args, &ArgBuf::new(), None).expect("Failed to spawn init");
+            debug_assert_eq!(pid, process::Pid::INIT);
+            process::spawn_kernel_thread("kprofiled", sched::report_profile, 0)
+                .expect("Failed to spawn the profiler thread");
+            irq_thread::start_all();
+            workqueue::start_worker().expect("Failed to spawn the work queue worker");
 
             info!("Jumping into userland code - will not refresh screen anymore");
-            unsafe { enter_user_mode(va, ustack_top) }
+            sched::run_idle()
         }
     }
 }
@@ -189,22 +319,14 @@ fn fast_sin(x: f32) -> f32 {
     y * (0.775 + 0.225 * (y.abs() - 1.0))
 }
 
-pub static TIMER_HZ: AtomicU64 = AtomicU64::new(0);
-
-unsafe fn measure_timer_hz(cpu: &PerCpu) -> u64 {
-    let tsc_hz = unsafe { estimate_tsc_hz() };
-    let window_tsc = tsc_hz / 10; // ~100 ms
+fn measure_timer_hz(cpu: &PerCpu) -> u64 {
+    let window_tsc = clock::tsc_hz() / 10; // ~100 ms
 
     let t0_tsc = rdtsc();
     let t0_ticks = cpu.ticks.load(Ordering::Acquire);
 
-    // Busy-wait until ~100 ms have elapsed.
-    loop {
-        if rdtsc().wrapping_sub(t0_tsc) >= window_tsc {
-            break;
-        }
-        spin_loop();
-    }
+    // Sleep until ~100 ms have elapsed; every timer tick wakes us up to re-check.
+    WaitQueue::new().wait_until(|| rdtsc().wrapping_sub(t0_tsc) >= window_tsc);
 
     let t1_ticks = cpu.ticks.load(Ordering::Acquire);
     let dt_ticks = t1_ticks.saturating_sub(t0_ticks);
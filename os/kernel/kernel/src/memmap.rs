@@ -0,0 +1,192 @@
+//! # Physical Memory Map
+//!
+//! Turns the raw UEFI memory map handed over by the loader into a short,
+//! sorted list of usable physical ranges for the frame allocator.
+//!
+//! ## Usable memory
+//!
+//! After `ExitBootServices`, boot services code and data as well as
+//! conventional memory are free for the kernel to use. Everything else stays
+//! reserved — notably `LoaderCode`/`LoaderData`, which hold the kernel image,
+//! the initial page tables, the boot stack, the [boot arena](crate::boot_alloc)
+//! and this very memory map.
+//!
+//! ## Normalization
+//!
+//! Ranges are shrunk inwards to 4 KiB boundaries, sorted and merged with
+//! their neighbors. Firmware maps can be long; ranges that do not fit into
+//! [`MAX_RANGES`] are counted in [`MemoryMap::dropped`] and their memory is
+//! simply not used.
+
+use core::fmt;
+use kernel_info::boot::UefiMemoryMapInfo;
+use kernel_info::memory::{HHDM_BASE, HHDM_SIZE};
+
+/// Maximum number of usable ranges kept.
+pub const MAX_RANGES: usize = 64;
+
+const PAGE_SIZE: u64 = 4096;
+
+/// `EFI_BOOT_SERVICES_CODE`
+const EFI_BOOT_SERVICES_CODE: u32 = 3;
+/// `EFI_BOOT_SERVICES_DATA`
+const EFI_BOOT_SERVICES_DATA: u32 = 4;
+/// `EFI_CONVENTIONAL_MEMORY`
+const EFI_CONVENTIONAL_MEMORY: u32 = 7;
+
+/// Byte offsets within an `EFI_MEMORY_DESCRIPTOR`.
+const DESC_TYPE: usize = 0;
+const DESC_PHYS_START: usize = 8;
+const DESC_NUM_PAGES: usize = 24;
+const DESC_MIN_SIZE: u64 = 40;
+
+/// A page-aligned physical address range `[start, end)`.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct PhysRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl PhysRange {
+    pub const fn len(&self) -> u64 {
+        self.end - self.start
+    }
+}
+
+impl fmt::Display for PhysRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#014x}..{:#014x}", self.start, self.end)
+    }
+}
+
+/// Why the loader's memory map could not be used.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum MemoryMapError {
+    /// The loader did not pass a memory map.
+    Missing,
+    /// The map lies outside the HHDM and cannot be read.
+    NotMapped,
+    /// The descriptor size is smaller than an `EFI_MEMORY_DESCRIPTOR`.
+    BadDescriptorSize(u64),
+}
+
+/// Usable physical memory, sorted by address.
+pub struct MemoryMap {
+    ranges: [PhysRange; MAX_RANGES],
+    len: usize,
+    /// Usable ranges that did not fit.
+    pub dropped: usize,
+}
+
+impl MemoryMap {
+    /// Parse the UEFI memory map described by `info`.
+    ///
+    /// # Errors
+    /// See [`MemoryMapError`].
+    ///
+    /// # Safety
+    /// `info` must describe a valid UEFI memory map that is still intact.
+    #[allow(clippy::cast_possible_truncation)]
+    pub unsafe fn from_uefi(info: &UefiMemoryMapInfo) -> Result<Self, MemoryMapError> {
+        if info.mmap_ptr == 0 || info.mmap_len == 0 {
+            return Err(MemoryMapError::Missing);
+        }
+        if info.mmap_desc_size < DESC_MIN_SIZE {
+            return Err(MemoryMapError::BadDescriptorSize(info.mmap_desc_size));
+        }
+        let in_hhdm = info
+            .mmap_ptr
+            .checked_add(info.mmap_len)
+            .is_some_and(|end| end <= HHDM_SIZE);
+        if !in_hhdm {
+            return Err(MemoryMapError::NotMapped);
+        }
+
+        let mut map = Self {
+            ranges: [PhysRange::default(); MAX_RANGES],
+            len: 0,
+            dropped: 0,
+        };
+
+        let base = (HHDM_BASE.as_u64() + info.mmap_ptr) as *const u8;
+        let count = info.mmap_len / info.mmap_desc_size;
+        for i in 0..count {
+            // Safety: inside the map buffer, which is mapped through the HHDM.
+            let (ty, start, pages) = unsafe {
+                let desc = base.add((i * info.mmap_desc_size) as usize);
+                (
+                    desc.add(DESC_TYPE).cast::<u32>().read_unaligned(),
+                    desc.add(DESC_PHYS_START).cast::<u64>().read_unaligned(),
+                    desc.add(DESC_NUM_PAGES).cast::<u64>().read_unaligned(),
+                )
+            };
+            if matches!(
+                ty,
+                EFI_BOOT_SERVICES_CODE | EFI_BOOT_SERVICES_DATA | EFI_CONVENTIONAL_MEMORY
+            ) {
+                let end = pages
+                    .checked_mul(PAGE_SIZE)
+                    .and_then(|len| start.checked_add(len))
+                    .unwrap_or(u64::MAX);
+                map.insert(start, end);
+            }
+        }
+
+        Ok(map)
+    }
+
+    /// The usable ranges, sorted by address.
+    pub fn ranges(&self) -> &[PhysRange] {
+        &self.ranges[..self.len]
+    }
+
+    /// Total usable bytes.
+    pub fn usable_bytes(&self) -> u64 {
+        self.ranges().iter().map(PhysRange::len).sum()
+    }
+
+    /// Insert `[start, end)`, keeping the list sorted and merged.
+    fn insert(&mut self, start: u64, end: u64) {
+        let start = start.div_ceil(PAGE_SIZE).saturating_mul(PAGE_SIZE);
+        let end = end & !(PAGE_SIZE - 1);
+        if start >= end {
+            return;
+        }
+
+        let pos = self.ranges().partition_point(|r| r.start < start);
+
+        // Extend the predecessor if the new range touches it ...
+        if pos > 0 && self.ranges[pos - 1].end >= start {
+            self.absorb_following(pos - 1, end);
+            return;
+        }
+
+        if self.len == MAX_RANGES {
+            self.dropped += 1;
+            return;
+        }
+
+        self.ranges.copy_within(pos..self.len, pos + 1);
+        self.ranges[pos] = PhysRange { start, end };
+        self.len += 1;
+        self.absorb_following(pos, end);
+    }
+
+    /// Extend the range at `idx` to at least `end` and merge any successors it
+    /// now overlaps or touches.
+    fn absorb_following(&mut self, idx: usize, end: u64) {
+        let mut end = end.max(self.ranges[idx].end);
+        let mut next = idx + 1;
+        while next < self.len && self.ranges[next].start <= end {
+            end = end.max(self.ranges[next].end);
+            next += 1;
+        }
+
+        self.ranges[idx].end = end;
+        let removed = next - idx - 1;
+        if removed > 0 {
+            self.ranges.copy_within(next..self.len, idx + 1);
+            self.len -= removed;
+        }
+    }
+}
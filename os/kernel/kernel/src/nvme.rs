@@ -0,0 +1,693 @@
+//! # NVMe
+//!
+//! Driver of NVM Express controllers, the interface of current SSDs, on PCI
+//! Express; QEMU emulates one with `-device nvme`.
+//!
+//! ## Bring-up
+//!
+//! [`init`] takes the first function with the NVMe class code, maps its
+//! registers from BAR 0 and disables the controller. The admin
+//! [queue pair](queue) goes into `AQA`/`ASQ`/`ACQ`, then the controller is
+//! enabled and identified: model, serial number and firmware revision.
+//! One I/O queue pair is requested with *Set Features* and created. Each
+//! active namespace with a supported LBA format whose first block reads
+//! back becomes the [block device](crate::block) `nvme0n<nsid>`.
+//!
+//! ## Commands
+//!
+//! Each queue pair runs one command at a time. The issuer claims the pair,
+//! submits and waits on the pair's [`WaitQueue`] for the completion. The wait
+//! condition reaps the completion queue itself, so a command completes even
+//! if no interrupt arrives: while there are no processes, the waiter
+//! re-checks after each interrupt anyway.
+//!
+//! Both completion queues raise MSI-X entry 0, routed to the
+//! [shared device vector](crate::interrupts::msi). The top half only checks
+//! for a new phase tag; the bottom half wakes the waiters. The timer tick
+//! runs the top half too, which keeps commands going on controllers without
+//! MSI-X.
+//!
+//! Data moves through a bounce buffer of [`BOUNCE_LEN`] bytes per queue
+//! pair: two pages, described by PRP1 and PRP2 without a PRP list, and the
+//! smallest `MDTS` a controller may have. Larger transfers are split.
+//!
+//! ## Limitations
+//!
+//! * One controller, one I/O queue pair, one command in flight.
+//! * A command that times out fails its queue for good; there is no abort
+//!   or controller reset.
+//! * Only the NVM command set, and LBA formats without metadata.
+
+mod queue;
+
+use crate::alloc::dma::{DmaBuffer, DmaConstraints, DmaError};
+use crate::alloc::mmio::{Mmio, map_mmio};
+use crate::block::{self, BlockDevice, BlockError};
+use crate::interrupts::msi;
+use crate::irq_thread::{self, IrqReturn, ThreadedIrq};
+use crate::pci::msix::MsiX;
+use crate::pci::{self, Bar, COMMAND_BUS_MASTER, COMMAND_MEMORY_SPACE, PciFunction};
+use crate::sched::{self, WaitQueue, now_ticks};
+use crate::tsc::rdtsc;
+use crate::{apic, clock, timer};
+use core::fmt;
+use core::hint::spin_loop;
+use core::sync::atomic::{AtomicBool, Ordering};
+use kernel_alloc::mmio::MmioRegion;
+use kernel_alloc::vmm::VmmError;
+use kernel_memory_addresses::{PageSize, PhysicalAddress, Size4K};
+use kernel_sync::{IrqGuard, SpinMutex, SyncOnceCell};
+use log::{debug, info, warn};
+use queue::{Command, CommandStatus, QueuePair};
+
+/// PCI class, subclass and programming interface of NVMe controllers.
+const CLASS: (u8, u8, u8) = (0x01, 0x08, 0x02);
+
+/// Register: controller capabilities (64 bits).
+const CAP: u64 = 0x00;
+/// Register: version (32 bits).
+const VS: u64 = 0x08;
+/// Register: controller configuration (32 bits).
+const CC: u64 = 0x14;
+/// Register: controller status (32 bits).
+const CSTS: u64 = 0x1C;
+/// Register: admin queue sizes (32 bits).
+const AQA: u64 = 0x24;
+/// Register: admin submission queue base (64 bits).
+const ASQ: u64 = 0x28;
+/// Register: admin completion queue base (64 bits).
+const ACQ: u64 = 0x30;
+/// Registers the driver maps: the ones above and the doorbells of two
+/// queue pairs at the largest stride.
+const REGISTERS_LEN: u64 = 0x2000;
+
+/// `CC`: enable.
+const CC_EN: u32 = 1 << 0;
+/// `CC`: I/O submission and completion queue entry sizes, as powers of two.
+const CC_ENTRY_SIZES: u32 = 6 << 16 | 4 << 20;
+/// `CC`: normal shutdown notification.
+const CC_SHN_NORMAL: u32 = 0b01 << 14;
+/// `CC`: shutdown notification field.
+const CC_SHN_MASK: u32 = 0b11 << 14;
+
+/// `CSTS`: ready.
+const CSTS_RDY: u32 = 1 << 0;
+/// `CSTS`: controller fatal status.
+const CSTS_CFS: u32 = 1 << 1;
+/// `CSTS`: shutdown status field.
+const CSTS_SHST_MASK: u32 = 0b11 << 2;
+/// `CSTS`: shutdown complete.
+const CSTS_SHST_COMPLETE: u32 = 0b10 << 2;
+
+/// Admin command: create I/O submission queue.
+const ADMIN_CREATE_SQ: u8 = 0x01;
+/// Admin command: create I/O completion queue.
+const ADMIN_CREATE_CQ: u8 = 0x05;
+/// Admin command: identify.
+const ADMIN_IDENTIFY: u8 = 0x06;
+/// Admin command: set features.
+const ADMIN_SET_FEATURES: u8 = 0x09;
+
+/// Identify: namespace data structure.
+const CNS_NAMESPACE: u32 = 0x00;
+/// Identify: controller data structure.
+const CNS_CONTROLLER: u32 = 0x01;
+/// Identify: active namespace ID list.
+const CNS_ACTIVE_NAMESPACES: u32 = 0x02;
+
+/// Feature: number of queues.
+const FEATURE_QUEUES: u32 = 0x07;
+
+/// I/O command: write.
+#[allow(dead_code)]
+const IO_WRITE: u8 = 0x01;
+/// I/O command: read.
+const IO_READ: u8 = 0x02;
+
+/// Entries per queue, if the controller allows that many.
+const QUEUE_LEN: u16 = 64;
+
+/// Queue pair ID of the admin queues.
+const ADMIN_QUEUE: u16 = 0;
+
+/// Queue pair ID of the I/O queues.
+const IO_QUEUE: u16 = 1;
+
+/// Size of each queue pair's bounce buffer, and of the largest transfer.
+/// With 4 KiB pages, no controller limits transfers to less.
+#[allow(clippy::cast_possible_truncation)]
+pub const BOUNCE_LEN: usize = 2 * Size4K::SIZE as usize;
+
+/// How long a command may take.
+const COMMAND_TIMEOUT_MS: u64 = 5_000;
+
+/// Largest block size supported, as a power of two: a page.
+const MAX_BLOCK_SHIFT: u32 = 12;
+
+/// Highest namespace ID registered as a block device.
+const MAX_NAMESPACES: usize = 4;
+
+/// Block device names of namespaces 1 to [`MAX_NAMESPACES`].
+const NAMESPACE_NAMES: [&str; MAX_NAMESPACES] = ["nvme0n1", "nvme0n2", "nvme0n3", "nvme0n4"];
+
+static CONTROLLER: SyncOnceCell<Controller> = SyncOnceCell::new();
+
+static NAMESPACES: [SyncOnceCell<Namespace>; MAX_NAMESPACES] =
+    [const { SyncOnceCell::new() }; MAX_NAMESPACES];
+
+/// Completions of both queue pairs.
+pub static IRQ: ThreadedIrq = ThreadedIrq::new("irq-nvme0", check_completions, wake_issuers);
+
+/// Why the controller could not be set up, or a command failed.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum NvmeError {
+    /// BAR 0 is not a memory range.
+    NoRegisters(PciFunction),
+    /// The registers could not be mapped.
+    Map(VmmError),
+    /// The controller does not support 4 KiB pages or the NVM command set.
+    Unsupported,
+    /// No contiguous frames for queues or buffers.
+    OutOfMemory,
+    /// The controller reported a fatal error.
+    Fatal,
+    /// The controller or a command did not finish in time.
+    Timeout,
+    /// An earlier command timed out; the queue is unusable.
+    Failed,
+    /// The controller completed a command with an error.
+    Command(CommandStatus),
+}
+
+impl fmt::Display for NvmeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoRegisters(function) => write!(f, "{function} has no memory BAR 0"),
+            Self::Map(e) => write!(f, "failed to map the registers: {e}"),
+            Self::Unsupported => f.write_str("no 4 KiB pages or NVM command set"),
+            Self::OutOfMemory => f.write_str("out of memory"),
+            Self::Fatal => f.write_str("controller fatal status"),
+            Self::Timeout => f.write_str("timed out"),
+            Self::Failed => f.write_str("queue failed after a timeout"),
+            Self::Command(status) => write!(f, "command failed: {status}"),
+        }
+    }
+}
+
+impl From<DmaError> for NvmeError {
+    fn from(_: DmaError) -> Self {
+        Self::OutOfMemory
+    }
+}
+
+/// A queue pair, the bounce buffer of its commands and the issuers waiting
+/// for it.
+struct Queue {
+    pair: SpinMutex<QueuePair>,
+    bounce: DmaBuffer,
+    /// Claimed by an issuer.
+    busy: AtomicBool,
+    /// A command timed out.
+    failed: AtomicBool,
+    /// Where issuers wait for the pair and for their completion.
+    done: WaitQueue,
+}
+
+impl Queue {
+    fn new(id: u16, len: u16, doorbell_stride: u64) -> Result<Self, NvmeError> {
+        Ok(Self {
+            pair: SpinMutex::new(QueuePair::new(id, len, doorbell_stride)?),
+            bounce: DmaBuffer::alloc(BOUNCE_LEN, DmaConstraints::ANY.with_align(Size4K::SIZE))?,
+            busy: AtomicBool::new(false),
+            failed: AtomicBool::new(false),
+            done: WaitQueue::new(),
+        })
+    }
+
+    fn with_pair<R>(&self, f: impl FnOnce(&mut QueuePair) -> R) -> R {
+        let _irq = IrqGuard::new();
+        f(&mut self.pair.lock())
+    }
+
+    /// Wait until the pair is free and take it.
+    fn claim(&self) -> Result<Claim<'_>, NvmeError> {
+        let claimed = self.done.wait_until_timeout(
+            || {
+                self.failed.load(Ordering::Acquire)
+                    || self
+                        .busy
+                        .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+                        .is_ok()
+            },
+            timer::ms_to_ticks(COMMAND_TIMEOUT_MS),
+        );
+        if self.failed.load(Ordering::Acquire) {
+            if claimed {
+                // We may have taken it before noticing.
+                self.busy.store(false, Ordering::Release);
+            }
+            return Err(NvmeError::Failed);
+        }
+        if !claimed {
+            return Err(NvmeError::Timeout);
+        }
+        Ok(Claim { queue: self })
+    }
+}
+
+/// Exclusive use of a [`Queue`]; released when dropped.
+struct Claim<'a> {
+    queue: &'a Queue,
+}
+
+impl Claim<'_> {
+    /// The bounce buffer.
+    fn bounce(&mut self) -> &mut [u8] {
+        // Safety: the claim makes us the only user of the buffer, and no
+        // command using it is in flight while we hold the slice.
+        unsafe { core::slice::from_raw_parts_mut(self.queue.bounce.as_ptr(), BOUNCE_LEN) }
+    }
+
+    /// `command` with the bounce buffer as its data.
+    fn with_bounce(&self, command: Command) -> Command {
+        let bounce = &self.queue.bounce;
+        command.data(bounce.phys(), bounce.phys() + Size4K::SIZE)
+    }
+
+    /// Submit `command` and wait for its completion; returns its result
+    /// double word.
+    fn execute(&self, regs: &MmioRegion, command: Command) -> Result<u32, NvmeError> {
+        let queue = self.queue;
+        queue.with_pair(|pair| pair.submit(regs, command));
+        let mut result = None;
+        queue.done.wait_until_timeout(
+            || {
+                result = queue.with_pair(|pair| pair.reap(regs));
+                result.is_some()
+            },
+            timer::ms_to_ticks(COMMAND_TIMEOUT_MS),
+        );
+        let Some(result) = result else {
+            // The controller may still write the bounce buffer.
+            queue.failed.store(true, Ordering::Release);
+            return Err(NvmeError::Timeout);
+        };
+        result.map_err(NvmeError::Command)
+    }
+}
+
+impl Drop for Claim<'_> {
+    fn drop(&mut self) {
+        self.queue.busy.store(false, Ordering::Release);
+        self.queue.done.wake_all();
+    }
+}
+
+/// A controller after bring-up.
+struct Controller {
+    function: PciFunction,
+    regs: Mmio,
+    admin: Queue,
+    io: Queue,
+    /// Kept mapped while MSI-X is in use.
+    _msix: Option<MsiX>,
+    /// Worst case time to become ready or shut down, from `CAP.TO`.
+    ready_timeout_ms: u64,
+}
+
+impl Controller {
+    /// Reset the controller, identify it and create the I/O queue pair.
+    fn bring_up(function: PciFunction) -> Result<Self, NvmeError> {
+        let Some(Bar::Memory(base)) = function.bar(0) else {
+            return Err(NvmeError::NoRegisters(function));
+        };
+        function.enable(COMMAND_MEMORY_SPACE | COMMAND_BUS_MASTER);
+        let regs = map_mmio(base, REGISTERS_LEN).map_err(NvmeError::Map)?;
+
+        let cap = regs.read64(CAP);
+        let max_entries = u16::try_from((cap & 0xFFFF) + 1).unwrap_or(u16::MAX);
+        let doorbell_stride = 4 << ((cap >> 32) & 0xF);
+        let ready_timeout_ms = ((cap >> 24) & 0xFF) * 500;
+        let nvm_command_set = (cap >> 37) & 1 != 0;
+        let min_page_shift = 12 + ((cap >> 48) & 0xF);
+        if !nvm_command_set || min_page_shift != 12 || 4 * doorbell_stride > REGISTERS_LEN - 0x1000
+        {
+            return Err(NvmeError::Unsupported);
+        }
+
+        regs.write32(CC, regs.read32(CC) & !CC_EN);
+        wait_ready(&regs, false, ready_timeout_ms)?;
+
+        let len = QUEUE_LEN.min(max_entries);
+        let admin = Queue::new(ADMIN_QUEUE, len, doorbell_stride)?;
+        let io = Queue::new(IO_QUEUE, len, doorbell_stride)?;
+        let msix = match start(function, &regs, &admin, &io, ready_timeout_ms) {
+            Ok(msix) => msix,
+            Err(e) => {
+                // Stop the controller before its queues are freed.
+                regs.write32(CC, regs.read32(CC) & !CC_EN);
+                return Err(e);
+            }
+        };
+
+        Ok(Self {
+            function,
+            regs,
+            admin,
+            io,
+            _msix: msix,
+            ready_timeout_ms,
+        })
+    }
+
+    /// Identify the active namespaces and register them as block devices.
+    fn register_namespaces(&self) -> Result<(), NvmeError> {
+        let mut claim = self.admin.claim()?;
+        let command =
+            claim.with_bounce(Command::new(ADMIN_IDENTIFY).args(CNS_ACTIVE_NAMESPACES, 0, 0));
+        claim.execute(&self.regs, command)?;
+
+        // The list is sorted and ends with a zero; IDs up to
+        // `MAX_NAMESPACES` are among its first entries.
+        let mut ids = [0u32; MAX_NAMESPACES];
+        for (id, bytes) in ids.iter_mut().zip(claim.bounce().chunks_exact(4)) {
+            *id = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+
+        for id in ids.into_iter().take_while(|&id| id != 0) {
+            let Some(slot) = NAMESPACES.get(id as usize - 1) else {
+                warn!("NVMe namespace {id} ignored");
+                continue;
+            };
+            let command = Command::new(ADMIN_IDENTIFY)
+                .nsid(id)
+                .args(CNS_NAMESPACE, 0, 0);
+            claim.execute(&self.regs, claim.with_bounce(command))?;
+            let Some(namespace) = Namespace::parse(id, claim.bounce()) else {
+                warn!("NVMe namespace {id}: unsupported LBA format");
+                continue;
+            };
+            let namespace = slot.get_or_init(|| namespace);
+
+            // Read the first block, so a broken I/O queue shows at boot.
+            let mut first = [0; 1 << MAX_BLOCK_SHIFT];
+            if let Err(e) = namespace.read(0, &mut first[..namespace.block_size]) {
+                warn!("NVMe namespace {id} not readable: {e}");
+                continue;
+            }
+            if let Err(e) = block::register(namespace) {
+                warn!("NVMe namespace {id} not registered: {e}");
+            }
+        }
+        Ok(())
+    }
+
+    /// Run `opcode` on the blocks starting at `lba` of `namespace`, with
+    /// `blocks` blocks of data in the bounce buffer.
+    #[allow(clippy::cast_possible_truncation)]
+    fn io(
+        &self,
+        claim: &Claim<'_>,
+        namespace: &Namespace,
+        opcode: u8,
+        lba: u64,
+        blocks: usize,
+    ) -> Result<(), NvmeError> {
+        // The block count is 0-based.
+        let command = Command::new(opcode).nsid(namespace.id).args(
+            lba as u32,
+            (lba >> 32) as u32,
+            blocks as u32 - 1,
+        );
+        claim.execute(&self.regs, claim.with_bounce(command))?;
+        Ok(())
+    }
+}
+
+/// A namespace of the controller, as a block device.
+#[derive(Debug)]
+struct Namespace {
+    id: u32,
+    name: &'static str,
+    block_size: usize,
+    blocks: u64,
+}
+
+impl Namespace {
+    /// The namespace `id` from its identify data, if its LBA format has no
+    /// metadata and blocks of 512 bytes up to a page.
+    fn parse(id: u32, identify: &[u8]) -> Option<Self> {
+        let name = NAMESPACE_NAMES.get((id as usize).checked_sub(1)?)?;
+        let blocks = u64::from_le_bytes(identify[0..8].try_into().ok()?);
+        let format = 128 + 4 * usize::from(identify[26] & 0xF);
+        let format = u32::from_le_bytes(identify[format..format + 4].try_into().ok()?);
+        let metadata = format & 0xFFFF;
+        let shift = (format >> 16) & 0xFF;
+        if metadata != 0 || !(9..=MAX_BLOCK_SHIFT).contains(&shift) || blocks == 0 {
+            return None;
+        }
+        Some(Self {
+            id,
+            name,
+            block_size: 1 << shift,
+            blocks,
+        })
+    }
+
+    fn controller() -> Result<&'static Controller, BlockError> {
+        CONTROLLER.get().ok_or(BlockError::Io)
+    }
+
+    fn failed(&self, what: &str, lba: u64, e: NvmeError) -> BlockError {
+        warn!("{}: {what} at block {lba}: {e}", self.name);
+        BlockError::Io
+    }
+}
+
+impl BlockDevice for Namespace {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    fn blocks(&self) -> u64 {
+        self.blocks
+    }
+
+    fn read(&self, lba: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        self.check_range(lba, buf.len())?;
+        let controller = Self::controller()?;
+        let mut claim = controller
+            .io
+            .claim()
+            .map_err(|e| self.failed("read", lba, e))?;
+        let mut block = lba;
+        for chunk in buf.chunks_mut(BOUNCE_LEN) {
+            let blocks = chunk.len() / self.block_size;
+            controller
+                .io(&claim, self, IO_READ, block, blocks)
+                .map_err(|e| self.failed("read", block, e))?;
+            chunk.copy_from_slice(&claim.bounce()[..chunk.len()]);
+            block += blocks as u64;
+        }
+        Ok(())
+    }
+
+    fn write(&self, lba: u64, buf: &[u8]) -> Result<(), BlockError> {
+        self.check_range(lba, buf.len())?;
+        let controller = Self::controller()?;
+        let mut claim = controller
+            .io
+            .claim()
+            .map_err(|e| self.failed("write", lba, e))?;
+        let mut block = lba;
+        for chunk in buf.chunks(BOUNCE_LEN) {
+            let blocks = chunk.len() / self.block_size;
+            claim.bounce()[..chunk.len()].copy_from_slice(chunk);
+            controller
+                .io(&claim, self, IO_WRITE, block, blocks)
+                .map_err(|e| self.failed("write", block, e))?;
+            block += blocks as u64;
+        }
+        Ok(())
+    }
+}
+
+/// Find and set up the first controller, and register its namespaces.
+pub fn init() {
+    let Some(function) = pci::find_class(CLASS.0, CLASS.1, CLASS.2) else {
+        debug!("No NVMe controller");
+        return;
+    };
+    let controller = match Controller::bring_up(function) {
+        Ok(controller) => CONTROLLER.get_or_init(|| controller),
+        Err(e) => {
+            warn!("NVMe controller at {function} not usable: {e}");
+            return;
+        }
+    };
+    if let Err(e) = irq_thread::register(&IRQ) {
+        warn!("NVMe issuers will not be woken: {e}");
+    }
+    if let Err(e) = controller.register_namespaces() {
+        warn!("NVMe controller at {function}: no namespaces: {e}");
+    }
+}
+
+/// Have the controller flush its caches and stop; see
+/// [`power`](crate::power).
+pub fn shutdown() {
+    let Some(controller) = CONTROLLER.get() else {
+        return;
+    };
+    let regs = &controller.regs;
+    regs.write32(CC, (regs.read32(CC) & !CC_SHN_MASK) | CC_SHN_NORMAL);
+
+    // Interrupts may be off; count TSC cycles rather than ticks.
+    let deadline = rdtsc() + clock::tsc_hz() / 1000 * controller.ready_timeout_ms;
+    while regs.read32(CSTS) & CSTS_SHST_MASK != CSTS_SHST_COMPLETE {
+        if rdtsc() >= deadline {
+            warn!(
+                "NVMe controller at {}: shutdown timed out",
+                controller.function
+            );
+            return;
+        }
+        spin_loop();
+    }
+    debug!("NVMe controller at {} shut down", controller.function);
+}
+
+/// Have issuers woken if a command completed.
+///
+/// Called from interrupt context.
+pub fn poll() {
+    IRQ.handle();
+}
+
+/// Enable the controller with the `admin` queues, identify it and create
+/// the `io` queues; returns the MSI-X table, if completions interrupt.
+fn start(
+    function: PciFunction,
+    regs: &MmioRegion,
+    admin: &Queue,
+    io: &Queue,
+    ready_timeout_ms: u64,
+) -> Result<Option<MsiX>, NvmeError> {
+    let (sq, cq, len) = admin.with_pair(|pair| (pair.sq_phys(), pair.cq_phys(), pair.len()));
+    let sizes = u32::from(len - 1);
+    regs.write32(AQA, sizes << 16 | sizes);
+    regs.write64(ASQ, sq.as_u64());
+    regs.write64(ACQ, cq.as_u64());
+    // NVM command set, 4 KiB pages, round robin arbitration.
+    regs.write32(CC, CC_ENTRY_SIZES | CC_EN);
+    wait_ready(regs, true, ready_timeout_ms)?;
+
+    let msix = route_interrupts(function);
+
+    let mut claim = admin.claim()?;
+    let command = claim.with_bounce(Command::new(ADMIN_IDENTIFY).args(CNS_CONTROLLER, 0, 0));
+    claim.execute(regs, command)?;
+    let identify = claim.bounce();
+    let version = regs.read32(VS);
+    info!(
+        "NVMe controller at {function}: {}, serial {}, firmware {}, NVMe {}.{}",
+        text(&identify[24..64]),
+        text(&identify[4..24]),
+        text(&identify[64..72]),
+        version >> 16,
+        (version >> 8) & 0xFF
+    );
+
+    // One I/O submission and one completion queue; both counts 0-based.
+    claim.execute(
+        regs,
+        Command::new(ADMIN_SET_FEATURES).args(FEATURE_QUEUES, 0, 0),
+    )?;
+    let (sq, cq, id) = io.with_pair(|pair| (pair.sq_phys(), pair.cq_phys(), pair.id()));
+    let size = sizes << 16 | u32::from(id);
+    // Physically contiguous, interrupts on MSI-X entry 0.
+    let completions = Command::new(ADMIN_CREATE_CQ)
+        .data(cq, PhysicalAddress::zero())
+        .args(size, 0b11, 0);
+    claim.execute(regs, completions)?;
+    // Physically contiguous, completing to the queue just created.
+    let submissions = Command::new(ADMIN_CREATE_SQ)
+        .data(sq, PhysicalAddress::zero())
+        .args(size, u32::from(id) << 16 | 1, 0);
+    claim.execute(regs, submissions)?;
+    Ok(msix)
+}
+
+/// Route MSI-X entry 0 to the shared device vector on this CPU; `None` if
+/// completions have to be polled.
+fn route_interrupts(function: PciFunction) -> Option<MsiX> {
+    let vector = match msi::register(&IRQ) {
+        Ok(vector) => vector,
+        Err(e) => {
+            warn!("NVMe controller at {function}: polling for completions: {e}");
+            return None;
+        }
+    };
+    let routed = MsiX::enable(function)
+        .and_then(|msix| msix.route(0, vector, apic::x2apic_id()).map(|()| msix));
+    match routed {
+        Ok(msix) => {
+            debug!("NVMe controller at {function}: MSI-X entry 0 on vector {vector:#04x}");
+            Some(msix)
+        }
+        Err(e) => {
+            warn!("NVMe controller at {function}: polling for completions: {e}");
+            None
+        }
+    }
+}
+
+/// Wait until `CSTS.RDY` is `ready`.
+fn wait_ready(regs: &MmioRegion, ready: bool, timeout_ms: u64) -> Result<(), NvmeError> {
+    let deadline = now_ticks() + timer::ms_to_ticks(timeout_ms);
+    loop {
+        let status = regs.read32(CSTS);
+        if ready && status & CSTS_CFS != 0 {
+            return Err(NvmeError::Fatal);
+        }
+        if (status & CSTS_RDY != 0) == ready {
+            return Ok(());
+        }
+        if now_ticks() >= deadline {
+            return Err(NvmeError::Timeout);
+        }
+        sched::sleep(1);
+    }
+}
+
+/// An ASCII field of identify data, without its padding.
+fn text(field: &[u8]) -> &str {
+    core::str::from_utf8(field).map_or("?", str::trim)
+}
+
+/// Top half: whether either queue pair has a completion to reap.
+fn check_completions() -> IrqReturn {
+    let Some(controller) = CONTROLLER.get() else {
+        return IrqReturn::None;
+    };
+    let completed = [&controller.admin, &controller.io]
+        .iter()
+        .any(|queue| queue.with_pair(|pair| pair.has_completion()));
+    if completed {
+        IrqReturn::WakeThread
+    } else {
+        IrqReturn::None
+    }
+}
+
+/// Bottom half: wake the issuers, which reap their completions themselves.
+fn wake_issuers() {
+    if let Some(controller) = CONTROLLER.get() {
+        controller.admin.done.wake_all();
+        controller.io.done.wake_all();
+    }
+}
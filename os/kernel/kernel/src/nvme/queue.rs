@@ -0,0 +1,269 @@
+//! # Queue Pairs
+//!
+//! A submission queue (SQ) and the completion queue (CQ) the controller
+//! posts its results to, each a ring in a [`DmaBuffer`]. The driver writes a
+//! command at the SQ tail and tells the controller by writing the new tail
+//! to the SQ's doorbell. The controller writes completions at the CQ head,
+//! each with a phase bit that flips on every pass through the ring, so new
+//! entries can be told from old ones without reading a register; the driver
+//! hands consumed entries back by writing the new head to the CQ's doorbell.
+//!
+//! A [`QueuePair`] tracks one command in flight; serializing commands is up
+//! to the [driver](super).
+
+use crate::alloc::dma::{DmaBuffer, DmaConstraints, DmaError};
+use core::fmt;
+use core::sync::atomic::{Ordering, fence};
+use kernel_alloc::mmio::MmioRegion;
+use kernel_memory_addresses::{PageSize, PhysicalAddress, Size4K};
+
+/// Size of a submission queue entry, as `CC.IOSQES` announces it.
+pub const SQ_ENTRY_SIZE: usize = 64;
+
+/// Size of a completion queue entry, as `CC.IOCQES` announces it.
+pub const CQ_ENTRY_SIZE: usize = 16;
+
+/// Offset of the first doorbell register.
+const DOORBELLS: u64 = 0x1000;
+
+/// Queues are page aligned, as PRP1 of the queue creation commands and
+/// `ASQ`/`ACQ` require.
+const QUEUE_CONSTRAINTS: DmaConstraints = DmaConstraints::ANY.with_align(Size4K::SIZE);
+
+/// A submission queue entry, before it gets its command identifier.
+#[derive(Debug, Copy, Clone)]
+pub struct Command {
+    opcode: u8,
+    nsid: u32,
+    prp1: u64,
+    prp2: u64,
+    cdw10: u32,
+    cdw11: u32,
+    cdw12: u32,
+}
+
+impl Command {
+    pub const fn new(opcode: u8) -> Self {
+        Self {
+            opcode,
+            nsid: 0,
+            prp1: 0,
+            prp2: 0,
+            cdw10: 0,
+            cdw11: 0,
+            cdw12: 0,
+        }
+    }
+
+    /// The namespace the command applies to.
+    pub const fn nsid(mut self, nsid: u32) -> Self {
+        self.nsid = nsid;
+        self
+    }
+
+    /// Data in the page at `prp1` and, if it continues, the page at `prp2`.
+    pub const fn data(mut self, prp1: PhysicalAddress, prp2: PhysicalAddress) -> Self {
+        self.prp1 = prp1.as_u64();
+        self.prp2 = prp2.as_u64();
+        self
+    }
+
+    /// Command specific double words 10 to 12.
+    pub const fn args(mut self, cdw10: u32, cdw11: u32, cdw12: u32) -> Self {
+        self.cdw10 = cdw10;
+        self.cdw11 = cdw11;
+        self.cdw12 = cdw12;
+        self
+    }
+
+    /// The entry as the controller reads it, with identifier `cid`.
+    #[allow(clippy::cast_possible_truncation)]
+    const fn dwords(&self, cid: u16) -> [u32; SQ_ENTRY_SIZE / 4] {
+        let mut dwords = [0; SQ_ENTRY_SIZE / 4];
+        dwords[0] = self.opcode as u32 | (cid as u32) << 16;
+        dwords[1] = self.nsid;
+        dwords[6] = self.prp1 as u32;
+        dwords[7] = (self.prp1 >> 32) as u32;
+        dwords[8] = self.prp2 as u32;
+        dwords[9] = (self.prp2 >> 32) as u32;
+        dwords[10] = self.cdw10;
+        dwords[11] = self.cdw11;
+        dwords[12] = self.cdw12;
+        dwords
+    }
+}
+
+/// Completion entry double word: command specific result.
+const CQE_RESULT: usize = 0;
+/// Completion entry double word: command identifier in bits 15:0, phase tag
+/// in bit 16, status field in bits 31:17.
+const CQE_STATUS: usize = 3;
+
+/// The status field of a failed command.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct CommandStatus(u16);
+
+impl CommandStatus {
+    /// Status code type: generic, command specific, media errors, ...
+    pub const fn code_type(self) -> u8 {
+        ((self.0 >> 8) & 0b111) as u8
+    }
+
+    /// Status code, within its type.
+    pub const fn code(self) -> u8 {
+        (self.0 & 0xFF) as u8
+    }
+}
+
+impl fmt::Display for CommandStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "status code type {}, status code {:#04x}",
+            self.code_type(),
+            self.code()
+        )
+    }
+}
+
+/// A submission queue and its completion queue; see the
+/// [module docs](self).
+#[derive(Debug)]
+pub struct QueuePair {
+    id: u16,
+    /// Entries per queue.
+    len: u16,
+    sq: DmaBuffer,
+    cq: DmaBuffer,
+    sq_tail: u16,
+    cq_head: u16,
+    /// Phase tag of new completions.
+    phase: bool,
+    next_cid: u16,
+    /// Identifier of the command in flight.
+    in_flight: Option<u16>,
+    /// Distance between doorbell registers, from `CAP.DSTRD`.
+    doorbell_stride: u64,
+}
+
+impl QueuePair {
+    /// Allocate the queues of pair `id` with `len` entries each.
+    pub fn new(id: u16, len: u16, doorbell_stride: u64) -> Result<Self, DmaError> {
+        debug_assert!(len >= 2, "a queue needs two entries");
+        let sq = DmaBuffer::alloc(usize::from(len) * SQ_ENTRY_SIZE, QUEUE_CONSTRAINTS)?;
+        let cq = DmaBuffer::alloc(usize::from(len) * CQ_ENTRY_SIZE, QUEUE_CONSTRAINTS)?;
+        Ok(Self {
+            id,
+            len,
+            sq,
+            cq,
+            sq_tail: 0,
+            cq_head: 0,
+            phase: true,
+            next_cid: 0,
+            in_flight: None,
+            doorbell_stride,
+        })
+    }
+
+    pub const fn id(&self) -> u16 {
+        self.id
+    }
+
+    /// Entries per queue.
+    pub const fn len(&self) -> u16 {
+        self.len
+    }
+
+    /// Physical address of the submission queue.
+    pub const fn sq_phys(&self) -> PhysicalAddress {
+        self.sq.phys()
+    }
+
+    /// Physical address of the completion queue.
+    pub const fn cq_phys(&self) -> PhysicalAddress {
+        self.cq.phys()
+    }
+
+    /// Queue `command` and ring the doorbell.
+    ///
+    /// # Panics
+    /// If a command is in flight already.
+    pub fn submit(&mut self, regs: &MmioRegion, command: Command) {
+        assert!(
+            self.in_flight.is_none(),
+            "NVMe queue {} busy; opcode {:#04x} submitted",
+            self.id,
+            command.opcode
+        );
+        let cid = self.next_cid;
+        self.next_cid = self.next_cid.wrapping_add(1);
+
+        let slot = usize::from(self.sq_tail) * SQ_ENTRY_SIZE;
+        for (i, value) in command.dwords(cid).into_iter().enumerate() {
+            // Safety: the slot is in bounds, and the controller does not
+            // read it until the doorbell says so.
+            unsafe { dword(&self.sq, slot + 4 * i).write_volatile(value) };
+        }
+        self.sq_tail = (self.sq_tail + 1) % self.len;
+        self.in_flight = Some(cid);
+
+        fence(Ordering::SeqCst);
+        regs.write32(self.doorbell(false), u32::from(self.sq_tail));
+    }
+
+    /// Whether the controller posted a completion not yet reaped.
+    pub fn has_completion(&self) -> bool {
+        (self.head(CQE_STATUS) >> 16) & 1 == u32::from(self.phase)
+    }
+
+    /// Take the completion of the command in flight, if it arrived: its
+    /// result double word, or the status it failed with.
+    pub fn reap(&mut self, regs: &MmioRegion) -> Option<Result<u32, CommandStatus>> {
+        let mut reaped = None;
+        while self.has_completion() {
+            fence(Ordering::Acquire);
+            let status = self.head(CQE_STATUS);
+            let result = self.head(CQE_RESULT);
+            self.cq_head += 1;
+            if self.cq_head == self.len {
+                self.cq_head = 0;
+                self.phase = !self.phase;
+            }
+            #[allow(clippy::cast_possible_truncation)]
+            let (cid, status) = (status as u16, (status >> 17) as u16);
+            if self.in_flight == Some(cid) {
+                self.in_flight = None;
+                reaped = Some(if status == 0 {
+                    Ok(result)
+                } else {
+                    Err(CommandStatus(status))
+                });
+            }
+        }
+        if reaped.is_some() {
+            regs.write32(self.doorbell(true), u32::from(self.cq_head));
+        }
+        reaped
+    }
+
+    /// Double word `index` of the completion queue entry at the head.
+    fn head(&self, index: usize) -> u32 {
+        let offset = usize::from(self.cq_head) * CQ_ENTRY_SIZE + 4 * index;
+        // Safety: the head is below `len`; the controller writes entries
+        // whole before flipping their phase.
+        unsafe { dword(&self.cq, offset).read_volatile() }
+    }
+
+    /// Offset of the submission or completion queue doorbell register.
+    fn doorbell(&self, completion: bool) -> u64 {
+        let index = 2 * u64::from(self.id) + u64::from(completion);
+        DOORBELLS + index * self.doorbell_stride
+    }
+}
+
+/// The double word at `offset` of `queue`, through the HHDM.
+fn dword(queue: &DmaBuffer, offset: usize) -> *mut u32 {
+    debug_assert!(offset < queue.len(), "queue offset out of range");
+    (queue.virt().as_u64() + offset as u64) as *mut u32
+}
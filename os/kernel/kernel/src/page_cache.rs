@@ -0,0 +1,513 @@
+//! # Page Cache
+//!
+//! Keeps recently read contents of block devices and files in 4 KiB frames,
+//! so reading them again does not go to the device. Each page is identified
+//! by a [`PageKey`]: a page of a block device by the device and page index,
+//! a page of a file by its inode and page index. A [`PageSource`] says how
+//! to key and fill the pages of one device or file; [`BlockPages`] is the
+//! source of a [block device](crate::block).
+//!
+//! ## Pages and frames
+//!
+//! Cached frames are tagged [`FrameOwner::PageCache`] and reference counted
+//! in the [`frame_table`], like the frames of [shared memory](crate::shm):
+//! the cache holds one reference, and every user another. [`get`] returns a
+//! [`CachedPage`] holding a reference while the caller copies from it;
+//! [`CachedPage::into_frame`] hands the reference to the caller instead, so
+//! `mmap` can map the frame into a user address space and teardown drops
+//! the reference with the mapping. A frame is freed once the cache and all
+//! users let go of it.
+//!
+//! The first reader of a missing page reserves its entry and fills the frame
+//! without the cache locked, since sources may sleep; readers of the same
+//! page wait on [`FILLED`] meanwhile. Page faults cannot sleep and use
+//! [`try_get`], which fails with [`CacheError::Busy`] instead.
+//!
+//! ## Eviction
+//!
+//! Entries are stamped on every use. When all [`MAX_CACHED_PAGES`] entries
+//! are taken, a miss evicts the least recently used page that nobody else
+//! holds. When free frames run low, the [low-memory callback](on_low_memory)
+//! schedules [`shrink`] on the [workqueue](crate::workqueue), which evicts
+//! [`SHRINK_BATCH`] such pages.
+//!
+//! ## Read-ahead
+//!
+//! A [`read`] that missed goes on to fill up to [`READ_AHEAD_PAGES`] pages
+//! after the ones it read, stopping at the first one cached already, so
+//! sequential reads find their next pages in the cache.
+//!
+//! ## Limitations
+//!
+//! * The cache is read-only: nothing writes pages back, and writers of a
+//!   source [`invalidate`] its pages instead.
+//! * Read-ahead is synchronous, on the time of the reader that missed.
+
+use crate::alloc::{self, frame_table, with_kernel_frame_alloc};
+use crate::block::{BlockDevice, BlockError};
+use crate::sched::WaitQueue;
+use crate::workqueue;
+use core::fmt;
+use kernel_alloc::frame_alloc::FrameStats;
+use kernel_alloc::frame_info::FrameOwner;
+use kernel_alloc::phys_mapper::HhdmPhysMapper;
+use kernel_memory_addresses::{PageSize, PhysicalPage, Size4K};
+use kernel_sync::{IrqGuard, SpinMutex};
+use kernel_vmem::{PhysFrameAlloc, PhysMapper};
+use log::{debug, warn};
+
+/// Size of a cached page.
+#[allow(clippy::cast_possible_truncation)]
+pub const PAGE_LEN: usize = Size4K::SIZE as usize;
+
+/// Most pages cached at a time.
+pub const MAX_CACHED_PAGES: usize = 512;
+
+/// Pages filled after a read that missed.
+pub const READ_AHEAD_PAGES: u64 = 4;
+
+/// Pages evicted per [`shrink`] when memory runs low.
+pub const SHRINK_BATCH: usize = 64;
+
+/// Evict when fewer than `1 / LOW_MEMORY_DIVISOR` of all frames are free.
+const LOW_MEMORY_DIVISOR: usize = 8;
+
+/// A page of a block device or file.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PageKey {
+    /// Page `page` of the block device named `device`.
+    #[allow(dead_code)]
+    Block { device: &'static str, page: u64 },
+    /// Page `page` of the file with inode number `inode`.
+    File { inode: u64, page: u64 },
+}
+
+/// Why a page could not be read.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum CacheError {
+    /// The page lies beyond the end of the source.
+    OutOfRange,
+    /// No frame for the page.
+    OutOfMemory,
+    /// Every entry holds a page in use.
+    Full,
+    /// Another reader is filling the page.
+    Busy,
+    /// The block device failed to read the page.
+    Block(BlockError),
+}
+
+impl fmt::Display for CacheError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OutOfRange => f.write_str("page out of range"),
+            Self::OutOfMemory => f.write_str("out of memory"),
+            Self::Full => f.write_str("page cache full"),
+            Self::Busy => f.write_str("page being filled"),
+            Self::Block(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl From<BlockError> for CacheError {
+    fn from(e: BlockError) -> Self {
+        Self::Block(e)
+    }
+}
+
+/// A device or file whose pages are cached.
+pub trait PageSource {
+    /// The key of page `page`.
+    fn key(&self, page: u64) -> PageKey;
+
+    /// Size of the contents in bytes.
+    fn len(&self) -> u64;
+
+    /// Fill `frame` with page `page`; bytes past the end of the contents
+    /// are zero.
+    fn fill(&self, page: u64, frame: &mut [u8; PAGE_LEN]) -> Result<(), CacheError>;
+
+    /// Number of pages, the last one possibly partial.
+    fn pages(&self) -> u64 {
+        self.len().div_ceil(PAGE_LEN as u64)
+    }
+}
+
+/// The pages of a block device.
+#[allow(dead_code)]
+#[derive(Copy, Clone)]
+pub struct BlockPages(pub &'static dyn BlockDevice);
+
+impl PageSource for BlockPages {
+    fn key(&self, page: u64) -> PageKey {
+        PageKey::Block {
+            device: self.0.name(),
+            page,
+        }
+    }
+
+    fn len(&self) -> u64 {
+        self.0.blocks() * self.0.block_size() as u64
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn fill(&self, page: u64, frame: &mut [u8; PAGE_LEN]) -> Result<(), CacheError> {
+        let block_size = self.0.block_size();
+        if !PAGE_LEN.is_multiple_of(block_size) {
+            return Err(CacheError::Block(BlockError::Misaligned));
+        }
+        let offset = page * PAGE_LEN as u64;
+        let len = self.len().saturating_sub(offset).min(PAGE_LEN as u64) as usize;
+        self.0.read(offset / block_size as u64, &mut frame[..len])?;
+        frame[len..].fill(0);
+        Ok(())
+    }
+}
+
+/// A cached page, held while in use; see the [module docs](self).
+#[derive(Debug)]
+pub struct CachedPage {
+    frame: PhysicalPage<Size4K>,
+}
+
+impl CachedPage {
+    /// The page's contents.
+    pub fn bytes(&self) -> &[u8; PAGE_LEN] {
+        // Safety: cached frames are only written while being filled, before
+        // anyone gets to hold them.
+        unsafe { HhdmPhysMapper.phys_to_mut::<[u8; PAGE_LEN]>(self.frame.base()) }
+    }
+
+    /// The frame, with the reference this page held; the caller drops it
+    /// with [`FrameTable::release`](kernel_alloc::frame_info::FrameTable::release)
+    /// and frees the frame if that was the last one.
+    #[allow(clippy::missing_const_for_fn)]
+    pub fn into_frame(self) -> PhysicalPage<Size4K> {
+        let frame = self.frame;
+        core::mem::forget(self);
+        frame
+    }
+}
+
+impl Drop for CachedPage {
+    fn drop(&mut self) {
+        if frame_table().release(self.frame) {
+            free_frame(self.frame);
+        }
+    }
+}
+
+/// Counters of the cache, e.g. for `/proc/meminfo`.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct CacheStats {
+    /// Pages cached now.
+    pub pages: usize,
+    /// Lookups that found their page.
+    pub hits: u64,
+    /// Lookups that had to fill their page.
+    pub misses: u64,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Entry {
+    key: PageKey,
+    /// `None` while the page is being filled.
+    frame: Option<PhysicalPage<Size4K>>,
+    /// Value of [`Cache::clock`] at the last use.
+    last_used: u64,
+}
+
+impl Entry {
+    /// The frame, if nobody but the cache holds it.
+    fn evictable(&self) -> Option<PhysicalPage<Size4K>> {
+        self.frame.filter(|&frame| !frame_table().is_shared(frame))
+    }
+}
+
+/// What a lookup found.
+enum Lookup {
+    /// The page, with a reference for the caller.
+    Hit(PhysicalPage<Size4K>),
+    /// Another reader is filling the page.
+    Filling,
+    /// The page is missing; the caller fills entry `slot`.
+    Miss(usize),
+    /// The page is missing, and no entry is free.
+    Full,
+}
+
+struct Cache {
+    entries: [Option<Entry>; MAX_CACHED_PAGES],
+    /// Counts uses, for least recently used eviction.
+    clock: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl Cache {
+    // Only evaluated at compile time to initialize `CACHE`.
+    #[allow(clippy::large_stack_arrays)]
+    const fn new() -> Self {
+        Self {
+            entries: [None; MAX_CACHED_PAGES],
+            clock: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    const fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    /// Look `key` up, reserving an entry on a miss. Also returns the frame
+    /// of a page evicted for the entry, for the caller to free.
+    fn lookup(&mut self, key: PageKey) -> (Lookup, Option<PhysicalPage<Size4K>>) {
+        let now = self.tick();
+        if let Some(entry) = self.entries.iter_mut().flatten().find(|e| e.key == key) {
+            let Some(frame) = entry.frame else {
+                return (Lookup::Filling, None);
+            };
+            entry.last_used = now;
+            frame_table().share(frame);
+            self.hits += 1;
+            return (Lookup::Hit(frame), None);
+        }
+
+        let mut victim = None;
+        let slot = match self.entries.iter().position(Option::is_none) {
+            Some(slot) => slot,
+            None => match self.least_recently_used() {
+                Some((slot, frame)) => {
+                    victim = Some(frame);
+                    slot
+                }
+                None => return (Lookup::Full, None),
+            },
+        };
+        self.misses += 1;
+        self.entries[slot] = Some(Entry {
+            key,
+            frame: None,
+            last_used: now,
+        });
+        (Lookup::Miss(slot), victim)
+    }
+
+    /// The evictable entry used least recently, and its frame.
+    fn least_recently_used(&self) -> Option<(usize, PhysicalPage<Size4K>)> {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter_map(|(slot, entry)| {
+                let entry = entry.as_ref()?;
+                Some((entry.last_used, slot, entry.evictable()?))
+            })
+            .min_by_key(|&(last_used, ..)| last_used)
+            .map(|(_, slot, frame)| (slot, frame))
+    }
+
+    /// Whether the page `key` is being filled.
+    fn is_filling(&self, key: PageKey) -> bool {
+        self.entries
+            .iter()
+            .flatten()
+            .any(|e| e.key == key && e.frame.is_none())
+    }
+}
+
+static CACHE: SpinMutex<Cache> = SpinMutex::new(Cache::new());
+
+/// Readers waiting for a page another reader fills.
+static FILLED: WaitQueue = WaitQueue::new();
+
+fn with_cache<R>(f: impl FnOnce(&mut Cache) -> R) -> R {
+    let _irq = IrqGuard::new();
+    f(&mut CACHE.lock())
+}
+
+/// Have the cache shrink when memory runs low.
+pub fn init() {
+    let threshold = alloc::frame_stats().total / LOW_MEMORY_DIVISOR;
+    if let Err(e) = alloc::on_low_memory(threshold, on_low_memory) {
+        warn!("Page cache will not shrink on low memory: {e}");
+    }
+}
+
+/// Page `page` of `source`, from the cache or filled from the source.
+pub fn get(source: &dyn PageSource, page: u64) -> Result<CachedPage, CacheError> {
+    lookup_or_fill(source, page, true).map(|(page, _)| page)
+}
+
+/// Like [`get`], but fails with [`CacheError::Busy`] rather than wait for
+/// another reader to fill the page.
+pub fn try_get(source: &dyn PageSource, page: u64) -> Result<CachedPage, CacheError> {
+    lookup_or_fill(source, page, false).map(|(page, _)| page)
+}
+
+/// Copy the contents of `source` from `offset` on into `buf`; returns the
+/// number of bytes copied, short at the end of the contents.
+#[allow(dead_code, clippy::cast_possible_truncation)]
+pub fn read(source: &dyn PageSource, offset: u64, buf: &mut [u8]) -> Result<usize, CacheError> {
+    let len = source.len().saturating_sub(offset).min(buf.len() as u64) as usize;
+    let mut missed = false;
+    let mut done = 0;
+    while done < len {
+        let at = offset + done as u64;
+        let (page, miss) = lookup_or_fill(source, at / PAGE_LEN as u64, true)?;
+        missed |= miss;
+        let start = (at % PAGE_LEN as u64) as usize;
+        let n = (PAGE_LEN - start).min(len - done);
+        buf[done..done + n].copy_from_slice(&page.bytes()[start..start + n]);
+        done += n;
+    }
+
+    if missed {
+        let next = (offset + len as u64).div_ceil(PAGE_LEN as u64);
+        let end = (next + READ_AHEAD_PAGES).min(source.pages());
+        for page in next..end {
+            if !matches!(lookup_or_fill(source, page, true), Ok((_, true))) {
+                break;
+            }
+        }
+    }
+    Ok(len)
+}
+
+/// Drop the cached pages `first..first + count` of `source`, e.g. after
+/// writing to it. Pages held elsewhere live on with their holders.
+#[allow(dead_code)]
+pub fn invalidate(source: &dyn PageSource, first: u64, count: u64) {
+    for page in first..first.saturating_add(count).min(source.pages()) {
+        let key = source.key(page);
+        let frame = with_cache(|cache| {
+            let entry = cache
+                .entries
+                .iter_mut()
+                .find(|e| e.is_some_and(|e| e.key == key && e.frame.is_some()))?;
+            entry.take()?.frame
+        });
+        if let Some(frame) = frame.filter(|&frame| frame_table().release(frame)) {
+            free_frame(frame);
+        }
+    }
+}
+
+/// Evict up to `count` of the least recently used pages nobody else
+/// holds; returns how many were evicted.
+pub fn shrink(count: usize) -> usize {
+    let mut evicted = 0;
+    while evicted < count {
+        let victim = with_cache(|cache| {
+            let (slot, frame) = cache.least_recently_used()?;
+            cache.entries[slot] = None;
+            Some(frame)
+        });
+        let Some(frame) = victim else {
+            break;
+        };
+        free_frame(frame);
+        evicted += 1;
+    }
+    evicted
+}
+
+/// The cache's counters.
+pub fn stats() -> CacheStats {
+    with_cache(|cache| CacheStats {
+        pages: cache.entries.iter().flatten().count(),
+        hits: cache.hits,
+        misses: cache.misses,
+    })
+}
+
+/// Page `page` of `source`, and whether it had to be filled. Waits for
+/// another reader filling it if `wait` is set.
+fn lookup_or_fill(
+    source: &dyn PageSource,
+    page: u64,
+    wait: bool,
+) -> Result<(CachedPage, bool), CacheError> {
+    if page >= source.pages() {
+        return Err(CacheError::OutOfRange);
+    }
+    let key = source.key(page);
+    loop {
+        let (lookup, victim) = with_cache(|cache| cache.lookup(key));
+        if let Some(frame) = victim {
+            free_frame(frame);
+        }
+        match lookup {
+            Lookup::Hit(frame) => return Ok((CachedPage { frame }, false)),
+            Lookup::Filling if wait => {
+                FILLED.wait_until(|| !with_cache(|cache| cache.is_filling(key)));
+            }
+            Lookup::Filling => return Err(CacheError::Busy),
+            Lookup::Miss(slot) => return fill(source, page, slot).map(|page| (page, true)),
+            Lookup::Full => return Err(CacheError::Full),
+        }
+    }
+}
+
+/// Fill the entry `slot` reserved for page `page` of `source`.
+fn fill(source: &dyn PageSource, page: u64, slot: usize) -> Result<CachedPage, CacheError> {
+    let filled = alloc_frame()
+        .or_else(|| {
+            // Make room among our own pages first.
+            shrink(1);
+            alloc_frame()
+        })
+        .ok_or(CacheError::OutOfMemory)
+        .and_then(|frame| {
+            // Safety: the frame is fresh; nobody else knows it yet.
+            let bytes = unsafe { HhdmPhysMapper.phys_to_mut::<[u8; PAGE_LEN]>(frame.base()) };
+            match source.fill(page, bytes) {
+                Ok(()) => Ok(frame),
+                Err(e) => {
+                    free_frame(frame);
+                    Err(e)
+                }
+            }
+        });
+
+    with_cache(|cache| match filled {
+        Ok(frame) => {
+            let entry = cache.entries[slot]
+                .as_mut()
+                .expect("reserved entry vanished");
+            entry.frame = Some(frame);
+            // One reference for the cache, one for the caller.
+            frame_table().share(frame);
+        }
+        Err(_) => cache.entries[slot] = None,
+    });
+    FILLED.wake_all();
+    filled.map(|frame| CachedPage { frame })
+}
+
+fn alloc_frame() -> Option<PhysicalPage<Size4K>> {
+    let frame = with_kernel_frame_alloc(PhysFrameAlloc::alloc_4k)?;
+    frame_table().set_owner(frame, FrameOwner::PageCache);
+    Some(frame)
+}
+
+fn free_frame(frame: PhysicalPage<Size4K>) {
+    with_kernel_frame_alloc(|alloc| alloc.free_4k(frame));
+}
+
+/// Low-memory callback: shrink the cache soon. Runs with the frame
+/// allocator locked, so the work is deferred.
+fn on_low_memory(stats: &FrameStats) {
+    if workqueue::schedule_work(shrink_work, SHRINK_BATCH).is_err() {
+        debug!(
+            "Page cache not shrunk: work queue full ({} frames free)",
+            stats.free
+        );
+    }
+}
+
+fn shrink_work(count: usize) {
+    let evicted = shrink(count);
+    debug!("Page cache: evicted {evicted} pages on low memory");
+}
@@ -16,10 +16,17 @@
 //!
 //! When a panic occurs, the handler performs the following sequence:
 //!
-//! 1. **Visual Indication**: Displays ASCII art panic message for immediate recognition
-//! 2. **Error Logging**: Outputs detailed panic information via the logging system
-//! 3. **System Halt**: Enters an infinite loop to prevent further execution
-//! 4. **CPU Relaxation**: Uses `spin_loop()` to reduce CPU usage during halt
+//! 1. **Test Recovery**: With the `ktest` feature, a panic inside an
+//!    [in-kernel test](crate::ktest) is reported and the test runner resumes
+//! 2. **Visual Indication**: Displays ASCII art panic message for immediate recognition
+//! 3. **Error Logging**: Outputs detailed panic information via the logging system,
+//!    followed by a symbolized backtrace (see [`ksyms`](crate::ksyms)) and, if
+//!    tracing is enabled, a dump of the [tracepoint](crate::tracepoint) ring;
+//!    the report and the log ring are then kept in the [pstore](crate::pstore)
+//!    for the next boot; with the `ktest` feature, QEMU then exits with a
+//!    failure status
+//! 4. **System Halt**: Enters an infinite loop to prevent further execution
+//! 5. **CPU Relaxation**: Uses `spin_loop()` to reduce CPU usage during halt
 //!
 //! ## Implementation Details
 //!
@@ -37,7 +44,9 @@
 //! - Memorable indication for debugging and support
 //!
 //! ### Logging Integration
-//! Panic information is logged through the kernel's standard logging infrastructure,
+//! Panic information is logged through the
+//! [emergency path](crate::klog#emergency-path) of the kernel log, which
+//! neither allocates nor locks and survives a fault inside the logger,
 //! ensuring panic details are:
 //! - Preserved for post-mortem analysis
 //! - Transmitted to debugging interfaces (QEMU, serial, etc.)
@@ -75,12 +84,16 @@
 //! - **Infinite Loop**: Ensures system never continues after panic
 //! - **Interrupt Safe**: Functions correctly regardless of interrupt state
 
+use crate::klog::emergency;
+use crate::{ksyms, pstore, tracepoint};
 use core::hint::spin_loop;
-use log::info;
 
 #[panic_handler]
 fn panic(info: &core::panic::PanicInfo) -> ! {
-    info!(
+    #[cfg(feature = "ktest")]
+    crate::ktest::on_panic(info);
+
+    emergency!(
         "panik panik panik
         ⠀⠀⠀⠀⠀⠀⠀⠙⣿⣷⣄⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀
         ⠀⠀⠀⠀⠀⠀⠀⠀⢺⣿⣿⡆⠀⠀⠀⠀⠀⠀⡀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀
@@ -108,7 +121,14 @@ fn panic(info: &core::panic::PanicInfo) -> ! {
         ⠀⢨⠀⠉⠉⠀⠀⠀⠀⠀⠀⠀⠀⠙⣿⣿⡿⡿⠿⠛⠙⠁⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠹⠏⠉⠻⠿⠟⠁\n"
     );
 
-    info!("{info}");
+    emergency!("{info}");
+    ksyms::log_backtrace();
+    if tracepoint::any_enabled() {
+        tracepoint::dump();
+    }
+    pstore::record_panic(info);
+    #[cfg(feature = "ktest")]
+    crate::ktest::abort();
     loop {
         spin_loop();
     }
@@ -0,0 +1,80 @@
+//! # Page Attribute Table
+//!
+//! Programs `IA32_PAT` with the layout that [`CacheMode`] encodes, most
+//! importantly a **write-combining** entry. Without it, the closest a mapping
+//! can get is uncached, which makes every framebuffer store a separate bus
+//! transaction.
+//!
+//! Entries 0–3 keep their power-on values, so mappings created by the
+//! loader stay valid; see [`CacheMode`] for the full table.
+//!
+//! ## Reprogramming
+//!
+//! Changing the PAT while caches or TLBs hold lines of the old memory types
+//! is undefined. [`init`] follows the SDM sequence (Vol. 3A, 12.12.4): with
+//! caching disabled, write back and invalidate the caches, flush the TLB,
+//! write the MSR, then flush both again.
+
+use crate::cpuid::{CpuidRanges, Leaf01h};
+use kernel_registers::cr0::Cr0;
+use kernel_registers::cr4::Cr4;
+use kernel_registers::msr::{Ia32Pat, PatMemoryType};
+use kernel_registers::{LoadRegisterUnsafe, StoreRegisterUnsafe};
+use kernel_sync::IrqGuard;
+use kernel_vmem::CacheMode;
+
+/// The PAT layout matching [`CacheMode::pat_index`].
+pub const KERNEL_PAT: Ia32Pat = Ia32Pat::POWER_ON
+    .with_entry(CacheMode::WriteBack.pat_index(), PatMemoryType::WriteBack)
+    .with_entry(
+        CacheMode::WriteThrough.pat_index(),
+        PatMemoryType::WriteThrough,
+    )
+    .with_entry(CacheMode::Uncached.pat_index(), PatMemoryType::Uncacheable)
+    .with_entry(
+        CacheMode::WriteCombining.pat_index(),
+        PatMemoryType::WriteCombining,
+    )
+    .with_entry(7, PatMemoryType::Uncacheable);
+
+/// Program the PAT on this CPU. Returns `false` if the CPU has no PAT.
+///
+/// # Safety
+/// Must run at CPL0, before any mapping relies on [`CacheMode::WriteCombining`].
+pub unsafe fn init() -> bool {
+    let ranges = unsafe { CpuidRanges::read() };
+    if !unsafe { Leaf01h::read(&ranges) }.is_some_and(|l| l.has_pat()) {
+        return false;
+    }
+
+    let _irq = IrqGuard::new();
+    unsafe {
+        let cr0 = Cr0::load_unsafe();
+        cr0.with_cd_cache_disable(true)
+            .with_nw_not_write_through(false)
+            .store_unsafe();
+        wbinvd();
+        flush_tlb();
+
+        KERNEL_PAT.store_unsafe();
+
+        wbinvd();
+        flush_tlb();
+        cr0.store_unsafe();
+    }
+    true
+}
+
+/// Write back and invalidate all caches.
+unsafe fn wbinvd() {
+    unsafe { core::arch::asm!("wbinvd", options(nostack, preserves_flags)) };
+}
+
+/// Flush all TLB entries, including global ones, by toggling `CR4.PGE`.
+unsafe fn flush_tlb() {
+    unsafe {
+        let cr4 = Cr4::load_unsafe();
+        cr4.with_pge(!cr4.pge()).store_unsafe();
+        cr4.store_unsafe();
+    }
+}
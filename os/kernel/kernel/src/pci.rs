@@ -0,0 +1,252 @@
+//! # PCI Configuration Space
+//!
+//! Access to the configuration space of PCI functions through the legacy
+//! port mechanism: the address of a register goes to [`CONFIG_ADDRESS`], its
+//! value is transferred through [`CONFIG_DATA`]. QEMU's `q35` and `pc`
+//! machines decode it, as do most real chipsets. Only the first 256 bytes of
+//! each function are reachable this way; the extended configuration space
+//! of PCI Express would need the ACPI `MCFG` table.
+//!
+//! [`find`] scans every bus for a function by vendor and device ID, or
+//! [`find_class`] by what kind of device it is. The scan is brute force, a
+//! few thousand port reads; drivers do it once at boot.
+//!
+//! Optional features of a function are described by the entries of its
+//! capability list, found with [`PciFunction::capability`]; [`msix`] sets up
+//! message signaled interrupts through one.
+//!
+//! ## Limitations
+//!
+//! * Interrupt lines are not routed; drivers poll or use [`msix`].
+//! * BARs are used as the firmware assigned them, never moved or resized.
+
+pub mod msix;
+
+use core::fmt;
+use kernel_memory_addresses::PhysicalAddress;
+use kernel_ports::{Port, PortWriteOnly};
+use kernel_sync::{IrqGuard, SpinMutex};
+
+/// Port taking the address of the configuration register to access.
+const CONFIG_ADDRESS: PortWriteOnly<u32> = PortWriteOnly::new(0xCF8);
+
+/// Port transferring the addressed configuration register.
+const CONFIG_DATA: Port<u32> = Port::new(0xCFC);
+
+/// Address bit enabling the configuration cycle.
+const CONFIG_ENABLE: u32 = 1 << 31;
+
+/// Vendor ID read from a slot without a function.
+const NO_VENDOR: u16 = 0xFFFF;
+
+/// Offset of the vendor ID; the device ID follows.
+const VENDOR_ID: u8 = 0x00;
+
+/// Offset of the command register.
+const COMMAND: u8 = 0x04;
+
+/// Offset of the status register.
+const STATUS: u8 = 0x06;
+
+/// Offset of the programming interface; the subclass and class follow.
+const CLASS_CODE: u8 = 0x08;
+
+/// Offset of the header type.
+const HEADER_TYPE: u8 = 0x0E;
+
+/// Offset of the first base address register.
+const BAR0: u8 = 0x10;
+
+/// Offset of the pointer to the first capability.
+const CAPABILITIES_POINTER: u8 = 0x34;
+
+/// Status bit: the function has a capability list.
+const STATUS_CAPABILITIES: u16 = 1 << 4;
+
+/// Upper bound on the length of a capability list; 48 four-byte entries
+/// fill the device-specific part of the configuration space.
+const MAX_CAPABILITIES: usize = 48;
+
+/// Header type bit: the device implements more than function 0.
+const MULTI_FUNCTION: u8 = 1 << 7;
+
+/// Command bit: respond to I/O space accesses.
+pub const COMMAND_IO_SPACE: u16 = 1 << 0;
+
+/// Command bit: respond to memory space accesses.
+pub const COMMAND_MEMORY_SPACE: u16 = 1 << 1;
+
+/// Command bit: allow the device to master the bus, i.e. to do DMA.
+pub const COMMAND_BUS_MASTER: u16 = 1 << 2;
+
+/// Serializes the two-step address/data accesses.
+static CONFIG: SpinMutex<()> = SpinMutex::new(());
+
+/// A function of a device on a PCI bus.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct PciFunction {
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+}
+
+/// Where a base address register points.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Bar {
+    /// A range of I/O ports.
+    Io(u16),
+    /// A range of physical memory.
+    Memory(PhysicalAddress),
+}
+
+impl PciFunction {
+    /// The configuration register address of `offset`.
+    fn address(self, offset: u8) -> u32 {
+        CONFIG_ENABLE
+            | u32::from(self.bus) << 16
+            | u32::from(self.device) << 11
+            | u32::from(self.function) << 8
+            | u32::from(offset & !0b11)
+    }
+
+    /// Read the aligned double word at `offset`.
+    pub fn read_u32(self, offset: u8) -> u32 {
+        let _irq = IrqGuard::new();
+        let _config = CONFIG.lock();
+        // Safety: the configuration ports are always decoded and only ever
+        // accessed under the lock.
+        unsafe {
+            CONFIG_ADDRESS.write(self.address(offset));
+            CONFIG_DATA.read()
+        }
+    }
+
+    /// Write the aligned double word at `offset`.
+    pub fn write_u32(self, offset: u8, value: u32) {
+        let _irq = IrqGuard::new();
+        let _config = CONFIG.lock();
+        // Safety: see `read_u32`.
+        unsafe {
+            CONFIG_ADDRESS.write(self.address(offset));
+            CONFIG_DATA.write(value);
+        }
+    }
+
+    /// Read the word at `offset`, which must be 2-byte aligned.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn read_u16(self, offset: u8) -> u16 {
+        (self.read_u32(offset) >> ((offset & 0b10) * 8)) as u16
+    }
+
+    /// Read the byte at `offset`.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn read_u8(self, offset: u8) -> u8 {
+        (self.read_u32(offset) >> ((offset & 0b11) * 8)) as u8
+    }
+
+    pub fn vendor_id(self) -> u16 {
+        self.read_u16(VENDOR_ID)
+    }
+
+    pub fn device_id(self) -> u16 {
+        self.read_u16(VENDOR_ID + 2)
+    }
+
+    /// Base address register `index` (`0..6`), or `None` if it is unused.
+    ///
+    /// 64-bit memory BARs take the next register as their upper half.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn bar(self, index: u8) -> Option<Bar> {
+        debug_assert!(index < 6, "BAR index out of range");
+        let offset = BAR0 + index * 4;
+        let low = self.read_u32(offset);
+        if low & 1 != 0 {
+            let port = (low & !0b11) as u16;
+            return (port != 0).then_some(Bar::Io(port));
+        }
+
+        let is_64bit = (low >> 1) & 0b11 == 0b10;
+        let high = if is_64bit {
+            self.read_u32(offset + 4)
+        } else {
+            0
+        };
+        let base = u64::from(high) << 32 | u64::from(low & !0xF);
+        (base != 0).then(|| Bar::Memory(PhysicalAddress::new(base)))
+    }
+
+    /// Class, subclass and programming interface, e.g. `(0x01, 0x08, 0x02)`
+    /// for an NVMe controller.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn class(self) -> (u8, u8, u8) {
+        let code = self.read_u32(CLASS_CODE);
+        ((code >> 24) as u8, (code >> 16) as u8, (code >> 8) as u8)
+    }
+
+    /// Offset of the first capability with ID `id`, if the function has one.
+    pub fn capability(self, id: u8) -> Option<u8> {
+        if self.read_u16(STATUS) & STATUS_CAPABILITIES == 0 {
+            return None;
+        }
+        let mut offset = self.read_u8(CAPABILITIES_POINTER) & !0b11;
+        for _ in 0..MAX_CAPABILITIES {
+            if offset == 0 {
+                return None;
+            }
+            if self.read_u8(offset) == id {
+                return Some(offset);
+            }
+            offset = self.read_u8(offset + 1) & !0b11;
+        }
+        None
+    }
+
+    /// Set `bits` in the command register, e.g. [`COMMAND_BUS_MASTER`].
+    pub fn enable(self, bits: u16) {
+        // The upper half is the status register, whose bits clear on write.
+        let value = self.read_u32(COMMAND) & 0xFFFF;
+        self.write_u32(COMMAND, value | u32::from(bits));
+    }
+}
+
+/// `bus:device.function` in hex, as `lspci` prints it.
+impl fmt::Display for PciFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02x}:{:02x}.{}", self.bus, self.device, self.function)
+    }
+}
+
+/// Every function present on any bus.
+pub fn functions() -> impl Iterator<Item = PciFunction> {
+    (0..=u8::MAX)
+        .flat_map(|bus| (0..32).map(move |device| (bus, device)))
+        .flat_map(|(bus, device)| {
+            let first = PciFunction {
+                bus,
+                device,
+                function: 0,
+            };
+            let count = match first.vendor_id() {
+                NO_VENDOR => 0,
+                _ if first.read_u8(HEADER_TYPE) & MULTI_FUNCTION != 0 => 8,
+                _ => 1,
+            };
+            (0..count).map(move |function| PciFunction {
+                bus,
+                device,
+                function,
+            })
+        })
+        .filter(|f| f.vendor_id() != NO_VENDOR)
+}
+
+/// The first function with the given vendor and device ID.
+pub fn find(vendor: u16, device: u16) -> Option<PciFunction> {
+    functions().find(|f| f.vendor_id() == vendor && f.device_id() == device)
+}
+
+/// The first function of class `class` and subclass `subclass` with
+/// programming interface `interface`.
+pub fn find_class(class: u8, subclass: u8, interface: u8) -> Option<PciFunction> {
+    functions().find(|f| f.class() == (class, subclass, interface))
+}
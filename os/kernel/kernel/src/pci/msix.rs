@@ -0,0 +1,145 @@
+//! # MSI-X
+//!
+//! With message signaled interrupts, a device raises an interrupt by writing
+//! a message to the local APIC instead of asserting an interrupt line: the
+//! address selects the CPU, the data the vector. MSI-X gives each interrupt
+//! of a function an entry of its own in a table, which lives in one of the
+//! function's memory BARs and is found through the MSI-X capability.
+//!
+//! [`MsiX::enable`] maps the table, masks every entry and turns MSI-X on;
+//! from then on, the function no longer uses its interrupt line. Drivers
+//! [`route`](MsiX::route) the entries they use to a vector, usually the
+//! [shared device vector](crate::interrupts::msi).
+//!
+//! Without interrupt remapping, messages carry an 8-bit destination, so
+//! only CPUs with an APIC ID below 256 can be targeted.
+
+use crate::alloc::mmio::{Mmio, map_mmio};
+use crate::pci::{Bar, PciFunction};
+use core::fmt;
+use kernel_alloc::vmm::VmmError;
+
+/// Capability ID of MSI-X.
+const CAP_MSIX: u8 = 0x11;
+
+/// Offset of the table offset and BAR indicator within the capability.
+const CAP_TABLE: u8 = 0x04;
+
+/// Message control bit, in the capability's first double word: MSI-X on.
+const CONTROL_ENABLE: u32 = 1 << 31;
+
+/// Message control bit, in the capability's first double word: all entries
+/// masked.
+const CONTROL_FUNCTION_MASK: u32 = 1 << 30;
+
+/// Size of a table entry: address (64 bits), data and vector control.
+const ENTRY_SIZE: u64 = 16;
+
+/// Entry offset: message address, low half.
+const ENTRY_ADDRESS_LOW: u64 = 0x0;
+/// Entry offset: message address, high half.
+const ENTRY_ADDRESS_HIGH: u64 = 0x4;
+/// Entry offset: message data.
+const ENTRY_DATA: u64 = 0x8;
+/// Entry offset: vector control.
+const ENTRY_CONTROL: u64 = 0xC;
+
+/// Vector control bit: the entry is masked.
+const ENTRY_MASKED: u32 = 1;
+
+/// Message address of the local APIC; the destination goes into bits 19:12.
+const MESSAGE_ADDRESS: u32 = 0xFEE0_0000;
+
+/// Why MSI-X could not be set up.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum MsiXError {
+    /// The function has no MSI-X capability.
+    NotSupported,
+    /// The table lies in a BAR that is not a memory range.
+    NoTableBar(u8),
+    /// The table could not be mapped.
+    Map(VmmError),
+    /// The table has no such entry.
+    NoSuchEntry(u16),
+    /// The CPU can not be addressed by a message.
+    UnreachableCpu(u32),
+}
+
+impl fmt::Display for MsiXError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotSupported => f.write_str("no MSI-X capability"),
+            Self::NoTableBar(bar) => write!(f, "MSI-X table in BAR {bar}, not a memory range"),
+            Self::Map(e) => write!(f, "failed to map the MSI-X table: {e}"),
+            Self::NoSuchEntry(entry) => write!(f, "no MSI-X table entry {entry}"),
+            Self::UnreachableCpu(apic_id) => {
+                write!(f, "APIC ID {apic_id} is out of reach of MSI-X")
+            }
+        }
+    }
+}
+
+/// The enabled MSI-X table of a function.
+#[derive(Debug)]
+pub struct MsiX {
+    table: Mmio,
+    entries: u16,
+}
+
+impl MsiX {
+    /// Map the MSI-X table of `function`, mask all entries and enable MSI-X.
+    ///
+    /// The function must respond to memory accesses, see
+    /// [`COMMAND_MEMORY_SPACE`](crate::pci::COMMAND_MEMORY_SPACE).
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn enable(function: PciFunction) -> Result<Self, MsiXError> {
+        let cap = function
+            .capability(CAP_MSIX)
+            .ok_or(MsiXError::NotSupported)?;
+        let control = function.read_u32(cap);
+        let entries = ((control >> 16) & 0x7FF) as u16 + 1;
+
+        let table = function.read_u32(cap + CAP_TABLE);
+        let bar = (table & 0b111) as u8;
+        let Some(Bar::Memory(base)) = function.bar(bar) else {
+            return Err(MsiXError::NoTableBar(bar));
+        };
+        let base = base + u64::from(table & !0b111);
+        let table = map_mmio(base, u64::from(entries) * ENTRY_SIZE).map_err(MsiXError::Map)?;
+
+        // Mask the whole function while the entries are masked one by one.
+        function.write_u32(cap, control | CONTROL_ENABLE | CONTROL_FUNCTION_MASK);
+        let msix = Self { table, entries };
+        for entry in 0..entries {
+            msix.write_control(entry, ENTRY_MASKED);
+        }
+        let control = function.read_u32(cap);
+        function.write_u32(cap, control & !CONTROL_FUNCTION_MASK);
+        Ok(msix)
+    }
+
+    /// Have `entry` raise `vector` on the CPU with APIC ID `apic_id`, and
+    /// unmask it.
+    pub fn route(&self, entry: u16, vector: u8, apic_id: u32) -> Result<(), MsiXError> {
+        if entry >= self.entries {
+            return Err(MsiXError::NoSuchEntry(entry));
+        }
+        if apic_id > 0xFF {
+            return Err(MsiXError::UnreachableCpu(apic_id));
+        }
+        let base = u64::from(entry) * ENTRY_SIZE;
+        self.write_control(entry, ENTRY_MASKED);
+        self.table
+            .write32(base + ENTRY_ADDRESS_LOW, MESSAGE_ADDRESS | apic_id << 12);
+        self.table.write32(base + ENTRY_ADDRESS_HIGH, 0);
+        // Fixed delivery, edge triggered.
+        self.table.write32(base + ENTRY_DATA, u32::from(vector));
+        self.write_control(entry, 0);
+        Ok(())
+    }
+
+    fn write_control(&self, entry: u16, value: u32) {
+        self.table
+            .write32(u64::from(entry) * ENTRY_SIZE + ENTRY_CONTROL, value);
+    }
+}
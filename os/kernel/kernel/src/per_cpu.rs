@@ -22,7 +22,13 @@
 //! * **Task Management**: Current task pointer for future scheduling support
 //! * **Hardware Descriptors**: TSS (Task State Segment), GDT, and selector storage
 //! * **Stack Management**: Kernel stack and IST (Interrupt Stack Table) pointers
-//! * **Accounting**: Tick counter and scratch space for CPU-specific data
+//! * **Accounting**: Tick counter, run-queue load and idle time, scratch space
+//! * **Watchdog**: Heartbeat and last interrupted context (see [`watchdog`](crate::watchdog))
+//! * **Profiling**: Ring of sampled instruction pointers (see [`profiler`](crate::profiler))
+//! * **Tracing**: Ring of tracepoint records (see [`tracepoint`](crate::tracepoint))
+//! * **Deferred work**: Queue of work items (see [`workqueue`](crate::workqueue))
+//! * **Hotplug**: Whether the CPU is online or parked (see [`hotplug`](crate::hotplug))
+//! * **Interrupt statistics**: Interrupts counted by vector (see [`irq_stats`](crate::irq_stats))
 //!
 //! ### Stack Management Subsystem
 //! * [`kernel_stacks`]: Virtual memory layout for per-CPU kernel stacks
@@ -43,9 +49,9 @@
 //! ### IST Stacks (`0xffff_ff10_0000_0000` base)
 //! ```text
 //! CPU 0:
-//!   IST1: [ Guard 4K ][ Stack 128K ]
-//!   IST2: [ Guard 4K ][ Stack 128K ]  ← +128K stride per IST
-//!   ...
+//!   IST1: [ Guard 4K ][ #DF stack 16K ]
+//!   IST2: [ Guard 4K ][ #PF stack 16K ]      ← +128K stride per IST
+//!   IST3: [ Guard 4K ][ NMI/#MC stack 16K ]
 //! CPU 1: (same layout at +1MB offset)
 //! ```
 //!
@@ -55,6 +61,7 @@
 //! * **ABI Alignment**: All stack tops maintain 16-byte alignment for x86-64 System V ABI
 //! * **Isolation**: Separate virtual regions prevent stack type confusion
 //! * **Size Limits**: Configurable maximum sizes prevent excessive memory usage
+//! * **Canaries**: Optional canary words and high-water marks (see [`stack`])
 //!
 //! ## Fast Access Pattern
 //!
@@ -79,6 +86,13 @@
 //! * **Independent State**: Each CPU maintains completely separate data structures
 //! * **Atomic Operations**: Tick counters and task pointers use atomic primitives
 //!
+//! Every CPU [`register`]s its [`PerCpu`] once it is set up, so that other
+//! CPUs can find it through [`cpus`] and [`by_id`], e.g. to check it for
+//! lockups or to park it.
+//!
+//! Booting with `nosmp` on the [kernel command line](crate::cmdline) keeps the
+//! kernel on the BSP once AP startup exists; see [`smp_enabled`].
+//!
 //! ## Safety and Concurrency
 //!
 //! * **Single Owner**: Each [`PerCpu`] instance belongs to exactly one CPU
@@ -90,11 +104,74 @@ pub mod ist_stacks;
 pub mod kernel_stacks;
 pub mod stack;
 
+use crate::cmdline::{self, Param, ParamKind};
 use crate::gdt::{Gdt, Selectors};
+use crate::hotplug::CpuHotplug;
+use crate::irq_stats::CpuIrqStats;
 use crate::msr::Ia32GsBaseMsrExt;
-use crate::tss::Tss64;
+use crate::preempt::CpuPreempt;
+use crate::profiler::CpuProfile;
+use crate::tracepoint::CpuTrace;
+use crate::tss::{Tss64, set_rsp0};
+use crate::watchdog::CpuWatchdog;
+use crate::workqueue::CpuWorkqueue;
+use core::sync::atomic::{AtomicPtr, Ordering};
 use kernel_memory_addresses::VirtualAddress;
 use kernel_registers::msr::Ia32GsBaseMsr;
+use kernel_sync::SpinMutex;
+use kernel_vmem::pcid::PcidAllocator;
+
+pub static NOSMP_PARAM: Param = Param {
+    name: "nosmp",
+    kind: ParamKind::Flag,
+    help: "run on the bootstrap processor only",
+};
+
+/// Whether application processors may be started; `false` when booted with
+/// `nosmp`.
+#[must_use]
+pub fn smp_enabled() -> bool {
+    !cmdline::flag(NOSMP_PARAM.name)
+}
+
+/// Most CPUs that can be [`register`]ed.
+pub const MAX_CPUS: usize = 64;
+
+static CPUS: [AtomicPtr<PerCpu>; MAX_CPUS] =
+    [const { AtomicPtr::new(core::ptr::null_mut()) }; MAX_CPUS];
+
+/// Make `cpu` known to the other CPUs; registering it again does nothing.
+///
+/// # Panics
+/// If more than [`MAX_CPUS`] CPUs are registered.
+pub fn register(cpu: &'static PerCpu) {
+    let ptr = core::ptr::from_ref(cpu).cast_mut();
+    let registered = CPUS.iter().any(|slot| {
+        match slot.compare_exchange(
+            core::ptr::null_mut(),
+            ptr,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        ) {
+            Ok(_) => true,
+            Err(existing) => existing == ptr,
+        }
+    });
+    assert!(registered, "too many CPUs");
+}
+
+/// All registered CPUs, in the order they registered.
+pub fn cpus() -> impl Iterator<Item = &'static PerCpu> {
+    CPUS.iter()
+        .map(|slot| slot.load(Ordering::Acquire))
+        .take_while(|ptr| !ptr.is_null())
+        .map(|ptr| unsafe { &*ptr })
+}
+
+/// The registered CPU with the logical index `cpu_id`.
+pub fn by_id(cpu_id: u32) -> Option<&'static PerCpu> {
+    cpus().find(|cpu| cpu.cpu_id == cpu_id)
+}
 
 #[repr(C, align(64))] // avoid false sharing; nice for future SMP
 pub struct PerCpu {
@@ -107,6 +184,13 @@ pub struct PerCpu {
     /// Pointer to the current running task (kernel struct). Optional for now.
     pub current_task: core::sync::atomic::AtomicPtr<Task>,
 
+    /// PID of the process currently running on this CPU; `0` while idle.
+    pub current_pid: core::sync::atomic::AtomicU32,
+
+    /// Number of the system call being handled on this CPU, or
+    /// [`NO_SYSCALL`](crate::syscall::NO_SYSCALL).
+    pub syscall: core::sync::atomic::AtomicU64,
+
     /// 64-bit TSS required in long mode (rsp0, `ISTx`, iopb).
     pub tss: Tss64,
 
@@ -115,7 +199,8 @@ pub struct PerCpu {
 
     /// Interrupt Stack Table entries (alternate hard stacks, e.g., NMI/#DF).
     ///
-    /// **Slot 0:** [`IST1`](super::interrupts::Ist::Ist1)
+    /// Slot `n - 1` holds the top of IST`n`; see
+    /// [`CPU_IST_STACKS`](ist_stacks::CPU_IST_STACKS) for what runs on them.
     pub ist_stacks: [VirtualAddress; 7],
 
     /// GDT storage
@@ -129,6 +214,46 @@ pub struct PerCpu {
 
     /// Accounting / stats you might grow.
     pub ticks: core::sync::atomic::AtomicU64,
+
+    /// Ready or running processes in this CPU's run queue, as of the last
+    /// scheduling decision.
+    pub nr_runnable: core::sync::atomic::AtomicU32,
+
+    /// Context switches performed on this CPU.
+    pub ctx_switches: core::sync::atomic::AtomicU64,
+
+    /// TSC cycles spent sleeping in the idle loop.
+    pub idle_tsc: core::sync::atomic::AtomicU64,
+
+    /// Number of times the idle loop went to sleep.
+    pub idle_entries: core::sync::atomic::AtomicU64,
+
+    /// TSC cycles spent loading CR3 on context switches.
+    pub mm_switch_tsc: core::sync::atomic::AtomicU64,
+
+    /// PCIDs of the address spaces that ran on this CPU.
+    pub pcids: SpinMutex<PcidAllocator>,
+
+    /// Heartbeat and last known context for the [`watchdog`](crate::watchdog).
+    pub watchdog: CpuWatchdog,
+
+    /// Instruction pointer samples for the [`profiler`](crate::profiler).
+    pub profile: CpuProfile,
+
+    /// Records of the [`tracepoint`](crate::tracepoint)s fired on this CPU.
+    pub trace: CpuTrace,
+
+    /// Preemption counter and time slice accounting, see [`preempt`](crate::preempt).
+    pub preempt: CpuPreempt,
+
+    /// Work deferred from interrupt handlers, see [`workqueue`](crate::workqueue).
+    pub workqueue: CpuWorkqueue,
+
+    /// Online state, see [`hotplug`](crate::hotplug).
+    pub hotplug: CpuHotplug,
+
+    /// Interrupt counters, see [`irq_stats`](crate::irq_stats).
+    pub irq_stats: CpuIrqStats,
 }
 
 pub struct Task;
@@ -140,6 +265,8 @@ impl PerCpu {
             cpu_id: 0,
             apic_id: 0,
             current_task: core::sync::atomic::AtomicPtr::new(core::ptr::null_mut()),
+            current_pid: core::sync::atomic::AtomicU32::new(0),
+            syscall: core::sync::atomic::AtomicU64::new(crate::syscall::NO_SYSCALL),
             tss: Tss64::new(),
             kstack_top: VirtualAddress::zero(),
             ist_stacks: [VirtualAddress::zero(); 7],
@@ -147,6 +274,19 @@ impl PerCpu {
             selectors: Selectors::new(),
             scratch: PerCpuScratch,
             ticks: core::sync::atomic::AtomicU64::new(0),
+            nr_runnable: core::sync::atomic::AtomicU32::new(0),
+            ctx_switches: core::sync::atomic::AtomicU64::new(0),
+            idle_tsc: core::sync::atomic::AtomicU64::new(0),
+            idle_entries: core::sync::atomic::AtomicU64::new(0),
+            mm_switch_tsc: core::sync::atomic::AtomicU64::new(0),
+            pcids: SpinMutex::new(PcidAllocator::new()),
+            watchdog: CpuWatchdog::new(),
+            profile: CpuProfile::new(),
+            trace: CpuTrace::new(),
+            preempt: CpuPreempt::new(),
+            workqueue: CpuWorkqueue::new(),
+            hotplug: CpuHotplug::new(),
+            irq_stats: CpuIrqStats::new(),
         }
     }
 
@@ -163,4 +303,20 @@ impl PerCpu {
     pub unsafe fn current() -> &'static Self {
         unsafe { <Ia32GsBaseMsr as Ia32GsBaseMsrExt>::current() }
     }
+
+    /// Switch the kernel stack used on CPL3→CPL0 transitions of the current CPU.
+    ///
+    /// Updates both [`kstack_top`](Self::kstack_top) (used by the `syscall` entry stub)
+    /// and the TSS `rsp0` (used by interrupt gates).
+    ///
+    /// # Safety
+    /// - Interrupts must be disabled.
+    /// - `top` must be the 16-byte aligned top of a mapped kernel stack.
+    pub unsafe fn set_current_kstack_top(top: VirtualAddress) {
+        let ptr = unsafe { <Ia32GsBaseMsr as Ia32GsBaseMsrExt>::read_ptr() }.cast_mut();
+        debug_assert!(!ptr.is_null(), "Per-CPU instance pointer is unset");
+        let cpu = unsafe { &mut *ptr };
+        cpu.kstack_top = top;
+        set_rsp0(cpu, top);
+    }
 }
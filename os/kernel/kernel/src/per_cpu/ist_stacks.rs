@@ -1,5 +1,15 @@
 //! Virtual layout for IST stacks: per-CPU × per-IST slots with a guard page.
 //!
+//! Every CPU gets the stacks in [`CPU_IST_STACKS`], one per kind of critical
+//! exception, so that a fault in one handler cannot overwrite the frames of
+//! another:
+//!
+//! | IST                  | Used by                 |
+//! |----------------------|-------------------------|
+//! | [`DOUBLE_FAULT_IST`] | `#DF`                   |
+//! | [`PAGE_FAULT_IST`]   | `#PF`                   |
+//! | [`NMI_MCE_IST`]      | NMI and `#MC`           |
+//!
 //! Layout (virtual):
 //!   `IST_BASE`
 //!     + `cpu_id` * `CPU_STRIDE`
@@ -9,7 +19,10 @@
 //! Notes
 //! - We keep IST stacks in a separate region from kernel stacks to simplify
 //!   debugging and avoid tight packing constraints.
-//! - One unmapped 4 KiB guard below each IST stack catches overflows.
+//! - One unmapped 4 KiB guard below each IST stack catches overflows; the
+//!   double fault handler names the stack whose guard was hit (see
+//!   [`guard_owner`]). With the `stack-canaries` feature, the stacks are
+//!   [watched](super::stack::watch) as well.
 //! - `IST_SLOT_STRIDE` must be >= guard + max IST size you’ll map.
 
 use crate::interrupts::Ist;
@@ -19,7 +32,6 @@ use kernel_memory_addresses::{PageSize, Size4K, VirtualAddress, VirtualPage};
 pub const IST_SLOTS_PER_CPU: u64 = 7;
 
 /// 4 KiB guard below each IST stack.
-#[allow(dead_code)]
 pub const IST_GUARD: u64 = Size4K::SIZE;
 
 /// Virtual base for all IST stacks (choose a disjoint, canonical kernel range).
@@ -33,10 +45,45 @@ pub const IST_CPU_STRIDE: u64 = 0x10_0000; // 1 MiB per CPU in the IST region
 /// With 7 ISTs × 128 KiB < 1 MiB, this fits comfortably.
 pub const IST_SLOT_STRIDE: u64 = 0x02_0000; // 128 KiB per IST “slot”
 
-/// 16 KiB is enough for #PF/#DF handlers.
-///
-/// [`IST1`](Ist::Ist1) is used for critical handlers such as double fault.
-pub const IST1_SIZE: u64 = 16 * 1024;
+/// The IST double faults run on; the stack they interrupted may be unusable.
+pub const DOUBLE_FAULT_IST: Ist = Ist::Ist1;
+
+/// The IST page faults run on, so that a kernel stack overflow into its
+/// guard page can still be handled.
+pub const PAGE_FAULT_IST: Ist = Ist::Ist2;
+
+/// The IST NMIs and machine checks run on; both may interrupt anything,
+/// including the handlers on the other ISTs.
+pub const NMI_MCE_IST: Ist = Ist::Ist3;
+
+/// An IST stack every CPU gets.
+#[derive(Debug, Copy, Clone)]
+pub struct IstStack {
+    pub ist: Ist,
+    /// Usable bytes above the guard page; a multiple of 4 KiB.
+    pub size: u64,
+    /// What runs on the stack, for log messages.
+    pub purpose: &'static str,
+}
+
+/// The IST stacks of each CPU; 16 KiB is enough for each of the handlers.
+pub const CPU_IST_STACKS: [IstStack; 3] = [
+    IstStack {
+        ist: DOUBLE_FAULT_IST,
+        size: 16 * 1024,
+        purpose: "double fault",
+    },
+    IstStack {
+        ist: PAGE_FAULT_IST,
+        size: 16 * 1024,
+        purpose: "page fault",
+    },
+    IstStack {
+        ist: NMI_MCE_IST,
+        size: 16 * 1024,
+        purpose: "NMI and machine check",
+    },
+];
 
 const _: () = {
     // Sanity: 7 IST slots must fit inside one CPU stride
@@ -44,11 +91,17 @@ const _: () = {
     // Page-aligned strides
     assert!(IST_CPU_STRIDE.is_multiple_of(Size4K::SIZE));
     assert!(IST_SLOT_STRIDE.is_multiple_of(Size4K::SIZE));
+    // Every stack fits into its slot
+    let mut i = 0;
+    while i < CPU_IST_STACKS.len() {
+        assert!(CPU_IST_STACKS[i].size <= max_ist_bytes());
+        assert!(CPU_IST_STACKS[i].size.is_multiple_of(Size4K::SIZE));
+        i += 1;
+    }
 };
 
 /// Maximum usable IST bytes per slot (excludes the 4 KiB guard).
 #[inline]
-#[allow(dead_code)]
 pub const fn max_ist_bytes() -> u64 {
     IST_SLOT_STRIDE - IST_GUARD
 }
@@ -78,3 +131,17 @@ pub const fn ist_top_for(len_bytes_mapped: u64, guard_base: VirtualAddress) -> V
     // align down to 16 for ABI-correct entry on the stack
     VirtualAddress::new((base.as_u64() + len_bytes_mapped) & !0xFu64)
 }
+
+/// The CPU and [`CPU_IST_STACKS`] entry whose guard page contains `va`, if any.
+#[allow(clippy::cast_possible_truncation)]
+pub fn guard_owner(va: VirtualAddress) -> Option<(u32, IstStack)> {
+    let offset = va.as_u64().checked_sub(IST_BASE)?;
+    let cpu = offset / IST_CPU_STRIDE;
+    let in_cpu = offset % IST_CPU_STRIDE;
+    if in_cpu % IST_SLOT_STRIDE >= IST_GUARD {
+        return None;
+    }
+    let ist = Ist::from_bits((in_cpu / IST_SLOT_STRIDE + 1) as u8);
+    let stack = CPU_IST_STACKS.iter().find(|s| s.ist == ist)?;
+    Some((u32::try_from(cpu).ok()?, *stack))
+}
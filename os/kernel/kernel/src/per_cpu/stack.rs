@@ -1,7 +1,30 @@
+//! Mapping of kernel and IST stacks, plus optional stack canaries.
+//!
+//! ## Canaries and watermarks (`stack-canaries` feature)
+//!
+//! Stacks registered with [`watch`] are painted when they are set up: the
+//! lowest [`CANARY_WORDS`] words (right above the guard page) hold a canary
+//! value, everything above a poison pattern.
+//!
+//! * [`check_canaries`] runs on every context switch and panics with the
+//!   stack's identity, the corrupted address and value if a canary changed,
+//!   i.e. the stack ran into its last bytes without hitting the guard page
+//!   (or something else wrote over it).
+//! * [`report_usage`] scans for the lowest overwritten poison word to find
+//!   each stack's high-water mark and logs "stack N% used" whenever a stack
+//!   reaches a new 10% step.
+//!
+//! Without the feature, all of these do nothing.
+
 use crate::alloc::KernelVmm;
+use crate::per_cpu::ist_stacks::CPU_IST_STACKS;
+use crate::process::MAX_PROCESSES;
+use core::fmt;
 use kernel_alloc::vmm::{AllocationTarget, VmmError};
 use kernel_memory_addresses::{PageSize, Size4K, VirtualAddress, VirtualPage};
+use kernel_sync::SpinMutex;
 use kernel_vmem::VirtualMemoryPageBits;
+use log::{info, warn};
 
 /// Result of creating a kernel stack.
 pub struct CpuStack {
@@ -121,3 +144,151 @@ pub fn map_ist_stack(
     let top = VirtualAddress::new((base.as_u64() + ist_bytes) & !0xFu64);
     Ok((base, top))
 }
+
+/// Whether stacks are painted and checked at all.
+const ENABLED: bool = cfg!(feature = "stack-canaries");
+
+/// Number of canary words at the bottom of each watched stack.
+pub const CANARY_WORDS: usize = 8;
+
+/// Value of the canary words.
+const CANARY: u64 = 0x57AC_CA4A_57AC_CA4A;
+
+/// Value of all other words of a watched stack that were never used.
+const POISON: u64 = 0xC0DE_DEAD_C0DE_DEAD;
+
+/// One BSP kernel stack, its IST stacks, and one stack per process slot.
+const MAX_WATCHED: usize = 1 + CPU_IST_STACKS.len() + MAX_PROCESSES;
+
+/// What a watched stack is used for.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum StackKind {
+    /// Kernel stack of a CPU.
+    Cpu(u32),
+    /// Interrupt stack `ist` (1..=7) of a CPU.
+    Ist { cpu: u32, ist: u8 },
+    /// Kernel stack of a process table slot.
+    Process(usize),
+}
+
+impl fmt::Display for StackKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Cpu(cpu) => write!(f, "CPU {cpu} kernel stack"),
+            Self::Ist { cpu, ist } => write!(f, "CPU {cpu} IST{ist} stack"),
+            Self::Process(slot) => write!(f, "process slot {slot} kernel stack"),
+        }
+    }
+}
+
+struct WatchedStack {
+    kind: StackKind,
+    base: VirtualAddress,
+    len: u64,
+    /// Highest 10% step of usage already logged.
+    reported_pct: u64,
+}
+
+impl WatchedStack {
+    const fn words(&self) -> *mut u64 {
+        self.base.as_u64() as *mut u64
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    const fn word_count(&self) -> usize {
+        (self.len / 8) as usize
+    }
+
+    /// Bytes between the top and the lowest word that is no longer poison.
+    fn used_bytes(&self) -> u64 {
+        let words = self.words();
+        let untouched = (CANARY_WORDS..self.word_count())
+            .take_while(|&i| unsafe { core::ptr::read_volatile(words.add(i)) } == POISON)
+            .count();
+        self.len - ((CANARY_WORDS + untouched) as u64) * 8
+    }
+
+    fn used_pct(&self) -> u64 {
+        self.used_bytes() * 100 / self.len
+    }
+}
+
+static WATCHED: SpinMutex<[Option<WatchedStack>; MAX_WATCHED]> =
+    SpinMutex::new([const { None }; MAX_WATCHED]);
+
+/// Paint the freshly mapped, unused stack `[base, base + len)` and watch it.
+///
+/// Call right after mapping the stack and before it is used.
+pub fn watch(kind: StackKind, base: VirtualAddress, len: u64) {
+    if !ENABLED {
+        return;
+    }
+
+    let stack = WatchedStack {
+        kind,
+        base,
+        len,
+        reported_pct: 0,
+    };
+    let words = stack.words();
+    for i in 0..stack.word_count() {
+        let value = if i < CANARY_WORDS { CANARY } else { POISON };
+        unsafe { core::ptr::write_volatile(words.add(i), value) };
+    }
+
+    let mut watched = WATCHED.lock();
+    match watched.iter_mut().find(|s| s.is_none()) {
+        Some(free) => *free = Some(stack),
+        None => warn!("Not watching {kind}: too many stacks"),
+    }
+}
+
+/// Verify the canaries of all watched stacks.
+///
+/// # Panics
+/// If a canary was overwritten.
+pub fn check_canaries() {
+    if !ENABLED {
+        return;
+    }
+
+    let watched = WATCHED.lock();
+    for stack in watched.iter().flatten() {
+        let words = stack.words();
+        for i in 0..CANARY_WORDS {
+            let addr = unsafe { words.add(i) };
+            let found = unsafe { core::ptr::read_volatile(addr) };
+            assert!(
+                found == CANARY,
+                "Stack canary of {} corrupted at {:#x}: expected {CANARY:#018x}, found {found:#018x} (stack {}..{:#x}, {}% used)",
+                stack.kind,
+                addr.addr(),
+                stack.base,
+                stack.base.as_u64() + stack.len,
+                stack.used_pct(),
+            );
+        }
+    }
+}
+
+/// Log the usage of every watched stack that reached a new 10% step.
+pub fn report_usage() {
+    if !ENABLED {
+        return;
+    }
+
+    let mut watched = WATCHED.lock();
+    for stack in watched.iter_mut().flatten() {
+        let pct = stack.used_pct();
+        let step = pct / 10 * 10;
+        if step > stack.reported_pct {
+            stack.reported_pct = step;
+            info!(
+                "{}: stack {pct}% used ({} of {} bytes)",
+                stack.kind,
+                stack.used_bytes(),
+                stack.len
+            );
+        }
+    }
+}
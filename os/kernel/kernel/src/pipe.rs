@@ -0,0 +1,310 @@
+//! # Pipes
+//!
+//! A pipe is a one-way byte channel: a ring buffer of [`PIPE_CAPACITY`]
+//! bytes with a read end and a write end. Processes reach the ends through
+//! handles (see [`process::handle`](crate::process::handle)); this module
+//! only knows how many handles refer to each end.
+//!
+//! ## Blocking
+//!
+//! * [`read`] blocks while the pipe is empty and a writer is left. It then
+//!   returns what is buffered (up to the size of the buffer), or `0` (end of
+//!   file) once the last writer is gone.
+//! * [`write`] blocks while the pipe is full until all bytes are written. If
+//!   the last reader goes away first, it fails with
+//!   [`BrokenPipe`](PipeError::BrokenPipe), or returns the short count if some
+//!   bytes made it in.
+//!
+//! Each pipe has one [`WaitQueue`] for readers and one for writers. Every
+//! operation that adds data, removes data or closes an end wakes the other
+//! side's queue. A [`PipeId`] is [`Pollable`]: pollers of `POLLIN` wait with
+//! the readers, those of `POLLOUT` with the writers.
+//!
+//! ## Lifetime
+//!
+//! [`create`] returns a pipe with one reference to each end; [`dup`] and
+//! [`close`] add and drop references. The slot is freed once both ends are
+//! closed. As only the kernel hands out [`PipeId`]s, and every holder keeps a
+//! reference, a [`PipeId`] stays valid until its holder closes it.
+//!
+//! ## Lock order
+//!
+//! Wait queue first, then the pipe table. Wake-ups happen after the pipe
+//! table lock is dropped.
+
+use crate::poll::Pollable;
+use crate::process::Pid;
+use crate::sched::WaitQueue;
+use core::fmt;
+use kernel_sync::{IrqGuard, SpinMutex};
+use log::debug;
+use stdlib::syscall_abi::poll::{POLLERR, POLLHUP, POLLIN, POLLOUT};
+
+/// Maximum number of pipes.
+pub const MAX_PIPES: usize = 16;
+
+/// Number of bytes a pipe buffers.
+pub const PIPE_CAPACITY: usize = 4096;
+
+/// A pipe, identified by its table slot.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct PipeId(usize);
+
+impl fmt::Display for PipeId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "pipe#{}", self.0)
+    }
+}
+
+impl PipeId {
+    /// The queues of the pipe woken for `events`.
+    fn queues(self, events: u16) -> impl Iterator<Item = &'static WaitQueue> {
+        let readable = (events & POLLIN != 0).then_some(&READABLE[self.0]);
+        let writable = (events & POLLOUT != 0).then_some(&WRITABLE[self.0]);
+        readable.into_iter().chain(writable)
+    }
+}
+
+impl Pollable for PipeId {
+    /// `POLLIN` with data buffered, `POLLHUP` without writers; `POLLOUT`
+    /// with space left, `POLLERR` without readers.
+    fn ready(&self, events: u16) -> u16 {
+        let _irq = IrqGuard::new();
+        let mut table = PIPES.lock();
+        let pipe = table.get_mut(*self);
+        let mut ready = 0;
+        if events & POLLIN != 0 {
+            if pipe.len > 0 {
+                ready |= POLLIN;
+            }
+            if pipe.writers == 0 {
+                ready |= POLLHUP;
+            }
+        }
+        if events & POLLOUT != 0 {
+            if pipe.readers == 0 {
+                ready |= POLLERR;
+            } else if pipe.len < PIPE_CAPACITY {
+                ready |= POLLOUT;
+            }
+        }
+        ready
+    }
+
+    fn register(&self, events: u16, pid: Pid) {
+        self.queues(events).for_each(|queue| queue.add_waiter(pid));
+    }
+
+    fn unregister(&self, events: u16, pid: Pid) {
+        self.queues(events)
+            .for_each(|queue| queue.remove_waiter(pid));
+    }
+}
+
+/// One of the two ends of a pipe.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PipeEnd {
+    Read,
+    Write,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PipeError {
+    /// [`MAX_PIPES`] pipes exist already.
+    TableFull,
+    /// The read end is closed.
+    BrokenPipe,
+}
+
+impl fmt::Display for PipeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TableFull => f.write_str("too many pipes"),
+            Self::BrokenPipe => f.write_str("broken pipe"),
+        }
+    }
+}
+
+struct Pipe {
+    buf: [u8; PIPE_CAPACITY],
+    /// Index of the oldest buffered byte.
+    head: usize,
+    /// Number of buffered bytes.
+    len: usize,
+    readers: usize,
+    writers: usize,
+}
+
+impl Pipe {
+    const fn new() -> Self {
+        Self {
+            buf: [0; PIPE_CAPACITY],
+            head: 0,
+            len: 0,
+            readers: 1,
+            writers: 1,
+        }
+    }
+
+    /// Move buffered bytes into `out`; returns how many.
+    fn pop(&mut self, out: &mut [u8]) -> usize {
+        let n = out.len().min(self.len);
+        for (i, dst) in out[..n].iter_mut().enumerate() {
+            *dst = self.buf[(self.head + i) % PIPE_CAPACITY];
+        }
+        self.head = (self.head + n) % PIPE_CAPACITY;
+        self.len -= n;
+        n
+    }
+
+    /// Buffer as much of `bytes` as fits; returns how many.
+    fn push(&mut self, bytes: &[u8]) -> usize {
+        let n = bytes.len().min(PIPE_CAPACITY - self.len);
+        let tail = self.head + self.len;
+        for (i, &b) in bytes[..n].iter().enumerate() {
+            self.buf[(tail + i) % PIPE_CAPACITY] = b;
+        }
+        self.len += n;
+        n
+    }
+}
+
+struct PipeTable {
+    slots: [Option<Pipe>; MAX_PIPES],
+}
+
+impl PipeTable {
+    // Only evaluated at compile time to initialize `PIPES`.
+    #[allow(clippy::large_stack_arrays)]
+    const fn new() -> Self {
+        Self {
+            slots: [const { None }; MAX_PIPES],
+        }
+    }
+
+    const fn get_mut(&mut self, id: PipeId) -> &mut Pipe {
+        self.slots[id.0]
+            .as_mut()
+            .expect("pipe id outlived its pipe")
+    }
+}
+
+static PIPES: SpinMutex<PipeTable> = SpinMutex::new(PipeTable::new());
+
+/// Readers of each pipe wait here for data or the last writer to leave.
+static READABLE: [WaitQueue; MAX_PIPES] = [const { WaitQueue::new() }; MAX_PIPES];
+
+/// Writers of each pipe wait here for space or the last reader to leave.
+static WRITABLE: [WaitQueue; MAX_PIPES] = [const { WaitQueue::new() }; MAX_PIPES];
+
+/// Create an empty pipe with one reference to each end.
+pub fn create() -> Result<PipeId, PipeError> {
+    let _irq = IrqGuard::new();
+    let mut table = PIPES.lock();
+    let slot = table
+        .slots
+        .iter()
+        .position(Option::is_none)
+        .ok_or(PipeError::TableFull)?;
+    table.slots[slot] = Some(Pipe::new());
+
+    let id = PipeId(slot);
+    debug!("Created {id}");
+    Ok(id)
+}
+
+/// Add a reference to `end` of `id`, e.g. for a forked child.
+pub fn dup(id: PipeId, end: PipeEnd) {
+    let _irq = IrqGuard::new();
+    let mut table = PIPES.lock();
+    let pipe = table.get_mut(id);
+    match end {
+        PipeEnd::Read => pipe.readers += 1,
+        PipeEnd::Write => pipe.writers += 1,
+    }
+}
+
+/// Drop a reference to `end` of `id`.
+///
+/// Closing the last reference to an end wakes the processes blocked on the
+/// other one; closing both ends frees the pipe.
+pub fn close(id: PipeId, end: PipeEnd) {
+    let last = {
+        let _irq = IrqGuard::new();
+        let mut table = PIPES.lock();
+        let pipe = table.get_mut(id);
+        let refs = match end {
+            PipeEnd::Read => &mut pipe.readers,
+            PipeEnd::Write => &mut pipe.writers,
+        };
+        *refs -= 1;
+        let last = *refs == 0;
+
+        if pipe.readers == 0 && pipe.writers == 0 {
+            table.slots[id.0] = None;
+            debug!("Removed {id}");
+        }
+        last
+    };
+
+    if last {
+        match end {
+            PipeEnd::Read => WRITABLE[id.0].wake_all(),
+            PipeEnd::Write => READABLE[id.0].wake_all(),
+        };
+    }
+}
+
+/// Read up to `buf.len()` bytes from `id`, blocking until data is available.
+///
+/// Returns `0` if `buf` is empty, or if the pipe is empty and has no writers.
+pub fn read(id: PipeId, buf: &mut [u8]) -> usize {
+    if buf.is_empty() {
+        return 0;
+    }
+
+    let mut read = 0;
+    READABLE[id.0].wait_until(|| {
+        let mut table = PIPES.lock();
+        let pipe = table.get_mut(id);
+        read = pipe.pop(buf);
+        read > 0 || pipe.writers == 0
+    });
+
+    if read > 0 {
+        WRITABLE[id.0].wake_all();
+    }
+    read
+}
+
+/// Write all of `bytes` to `id`, blocking while the pipe is full.
+///
+/// Returns the number of bytes written, which is less than `bytes.len()`
+/// only if the read end was closed part way through.
+pub fn write(id: PipeId, bytes: &[u8]) -> Result<usize, PipeError> {
+    let mut written = 0;
+    while written < bytes.len() {
+        let mut broken = false;
+        WRITABLE[id.0].wait_until(|| {
+            let mut table = PIPES.lock();
+            let pipe = table.get_mut(id);
+            if pipe.readers == 0 {
+                broken = true;
+                return true;
+            }
+            let n = pipe.push(&bytes[written..]);
+            written += n;
+            n > 0
+        });
+
+        if broken {
+            return if written > 0 {
+                Ok(written)
+            } else {
+                Err(PipeError::BrokenPipe)
+            };
+        }
+        READABLE[id.0].wake_all();
+    }
+    Ok(written)
+}
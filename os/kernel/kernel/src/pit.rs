@@ -0,0 +1,199 @@
+//! # Programmable Interval Timer (8254)
+//!
+//! The PIT counts down at a fixed [`PIT_INPUT_HZ`], independent of the CPU,
+//! which makes it the reference the kernel measures its other clocks
+//! against. It serves two purposes:
+//!
+//! * **Calibration**: [`pit_wait_ms`] busy-waits for a span of PIT time, and
+//!   [`measure`] counts another counter, such as the TSC or the LAPIC timer,
+//!   over such a span. Both use channel 2, whose gate and output are wired to
+//!   port `0x61`, so they need no interrupt and leave channel 0 alone.
+//! * **Fallback tick**: if the LAPIC timer does not count, or `pit_tick` is
+//!   on the [command line](crate::cmdline), [`start_tick`] runs channel 0 as
+//!   a rate generator and routes IRQ 0 through the
+//!   [I/O APIC](crate::ioapic) to the timer vector.
+//!
+//! ## Error Bounds
+//!
+//! [`measure`] does not know exactly when the countdown started and ended,
+//! only between which two reads of the counter each happened: counting
+//! starts on the first PIT clock after the count is written, and the end is
+//! seen on the next poll of the output. It reports the middle of the range
+//! this allows, and half its width as the error. Longer windows shrink the
+//! error; the tolerance of the PIT crystal itself is not included.
+//!
+//! ## Limitations
+//!
+//! * One countdown covers at most 65535 PIT clocks (about 55 ms); longer
+//!   spans are chained, and the gaps between them are not counted.
+//! * There is no lock: calibration runs on the BSP during boot, before
+//!   anything else touches the PIT.
+
+use crate::clock::{self, Calibration};
+use crate::cmdline::{self, Param, ParamKind};
+use crate::interrupts::timer::LAPIC_TIMER_VECTOR;
+use crate::ioapic::{self, IoApicError};
+use crate::per_cpu::PerCpu;
+use core::hint::spin_loop;
+use kernel_ports::{Port, PortWriteOnly};
+use log::info;
+
+pub static PIT_TICK_PARAM: Param = Param {
+    name: "pit_tick",
+    kind: ParamKind::Flag,
+    help: "Drive the timer tick from the PIT instead of the LAPIC timer",
+};
+
+/// Frequency of the PIT input clock.
+pub const PIT_INPUT_HZ: u64 = 1_193_182;
+
+/// ISA IRQ of channel 0.
+const PIT_IRQ: u8 = 0;
+
+/// Channel 0 counter.
+const CHANNEL_0: PortWriteOnly<u8> = PortWriteOnly::new(0x40);
+
+/// Channel 2 counter.
+const CHANNEL_2: PortWriteOnly<u8> = PortWriteOnly::new(0x42);
+
+/// Mode/command register.
+const COMMAND: PortWriteOnly<u8> = PortWriteOnly::new(0x43);
+
+/// NMI status and control: channel 2 gate, speaker enable and output.
+const PORT_61: Port<u8> = Port::new(0x61);
+
+/// Command: channel 0, low then high byte, mode 2 (rate generator), binary.
+const CHANNEL_0_RATE_GENERATOR: u8 = 0b0011_0100;
+
+/// Command: channel 2, low then high byte, mode 0 (interrupt on terminal
+/// count), binary.
+const CHANNEL_2_ONE_SHOT: u8 = 0b1011_0000;
+
+/// Port `0x61` bit: channel 2 counts while set.
+const GATE_2: u8 = 1 << 0;
+
+/// Port `0x61` bit: channel 2 drives the speaker.
+const SPEAKER: u8 = 1 << 1;
+
+/// Port `0x61` bit: channel 2 output; set once the countdown ended.
+const OUT_2: u8 = 1 << 5;
+
+/// Longest countdown one count register allows.
+const MAX_COUNTDOWN: u64 = 0xFFFF;
+
+/// How often a countdown polls the output before giving up on the PIT;
+/// far more than the 65535 clocks of the longest one take.
+const POLL_LIMIT: usize = 10_000_000;
+
+/// PIT clocks in `ms` milliseconds.
+const fn clocks_in_ms(ms: u64) -> u64 {
+    (PIT_INPUT_HZ * ms).div_ceil(1_000)
+}
+
+/// Run one channel 2 countdown of `clocks` PIT clocks while reading
+/// `counter`. Returns the least and the most `counter` can have advanced
+/// between the start and the end, or `None` if the output never rose.
+fn countdown(clocks: u16, counter: &impl Fn() -> u64) -> Option<(u64, u64)> {
+    // Safety: channel 2 and port 0x61 are always decoded and only used
+    // here; the speaker stays off.
+    unsafe {
+        PORT_61.write((PORT_61.read() & !SPEAKER) | GATE_2);
+        COMMAND.write(CHANNEL_2_ONE_SHOT);
+        let [low, high] = clocks.to_le_bytes();
+        CHANNEL_2.write(low);
+        let before = counter();
+        CHANNEL_2.write(high);
+        let started = counter();
+
+        // A read followed by a poll that still sees the countdown running
+        // came before the end; a read after a poll that sees it done, after.
+        let mut running = started;
+        for _ in 0..POLL_LIMIT {
+            let now = counter();
+            if PORT_61.read() & OUT_2 != 0 {
+                let ended = counter();
+                return Some((running - started, ended - before));
+            }
+            running = now;
+            spin_loop();
+        }
+    }
+    None
+}
+
+/// Run chained countdowns covering `ms` milliseconds; see [`countdown`].
+/// Also returns the PIT clocks and the number of countdowns.
+fn countdowns(ms: u64, counter: &impl Fn() -> u64) -> Option<(u64, u64, u64, u64)> {
+    let clocks = clocks_in_ms(ms);
+    let (mut least, mut most, mut chunks) = (0, 0, 0);
+    let mut left = clocks;
+    while left > 0 {
+        let chunk = left.min(MAX_COUNTDOWN);
+        #[allow(clippy::cast_possible_truncation)]
+        let (l, m) = countdown(chunk as u16, counter)?;
+        least += l;
+        most += m;
+        chunks += 1;
+        left -= chunk;
+    }
+    Some((least, most, clocks, chunks))
+}
+
+/// Busy-wait for `ms` milliseconds of PIT time; `false` if the PIT does not
+/// count.
+#[must_use]
+#[allow(dead_code)]
+pub fn pit_wait_ms(ms: u64) -> bool {
+    countdowns(ms, &|| 0).is_some()
+}
+
+/// Measure the frequency of `counter`, which must count up, over `ms`
+/// milliseconds of PIT time. `None` if the PIT does not count.
+#[allow(clippy::cast_possible_truncation)]
+pub fn measure(ms: u64, counter: impl Fn() -> u64) -> Option<Calibration> {
+    let (least, most, clocks, chunks) = countdowns(ms, &counter)?;
+    if clocks == 0 {
+        return None;
+    }
+
+    // Each countdown lasts its count, plus up to one clock until the first
+    // edge loads it.
+    let hz = |count: u64, clocks: u64| {
+        (u128::from(count) * u128::from(PIT_INPUT_HZ) / u128::from(clocks)) as u64
+    };
+    let low = hz(least, clocks + chunks);
+    let high = hz(most, clocks);
+    Some(Calibration {
+        hz: low.midpoint(high),
+        error_hz: Some((high - low).div_ceil(2)),
+    })
+}
+
+/// Whether `pit_tick` asks for the PIT to drive the timer tick.
+pub fn tick_requested() -> bool {
+    cmdline::flag(PIT_TICK_PARAM.name)
+}
+
+/// Have channel 0 raise the timer interrupt on this CPU about `hz` times a
+/// second.
+#[allow(clippy::cast_possible_truncation)]
+pub fn start_tick(hz: u64) -> Result<(), IoApicError> {
+    let apic_id = unsafe { PerCpu::current() }.apic_id;
+    let gsi = ioapic::route_isa_irq(PIT_IRQ, LAPIC_TIMER_VECTOR, apic_id)?;
+
+    // Mode 2 does not take a count of 1.
+    let count = (PIT_INPUT_HZ + hz / 2) / hz.max(1);
+    let count = count.clamp(2, MAX_COUNTDOWN) as u16;
+    // Safety: channel 0 is not used otherwise.
+    unsafe {
+        COMMAND.write(CHANNEL_0_RATE_GENERATOR);
+        let [low, high] = count.to_le_bytes();
+        CHANNEL_0.write(low);
+        CHANNEL_0.write(high);
+    }
+
+    let actual_hz = PIT_INPUT_HZ / u64::from(count);
+    clock::set_timer_hz(actual_hz);
+    info!("PIT ticks at {actual_hz} Hz on GSI {gsi}");
+    Ok(())
+}
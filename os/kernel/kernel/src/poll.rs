@@ -0,0 +1,118 @@
+//! # Polling
+//!
+//! [`wait`] blocks the current process until one of several kernel objects
+//! is ready for the events asked of it, the `poll::POLL*` bits of
+//! [`syscall_abi::poll`](stdlib::syscall_abi::poll), e.g. until one of two
+//! pipes has data to read or the terminal got a line of input.
+//!
+//! Objects take part by implementing [`Pollable`]: they tell which events
+//! are ready now, and queue a process on the [`WaitQueue`]s they wake when
+//! that may change. [`wait`] queues the process on all of them and blocks
+//! it before checking readiness, so no wake-up is lost between the check
+//! and switching away (see
+//! [waiting on several queues](crate::sched::WaitQueue#waiting-on-several-queues)).
+//! Whichever queue wakes the process first, it checks all objects again.
+//!
+//! A signal that [interrupts](crate::signal::interrupts) the process ends
+//! the wait early, like a sleep.
+
+use crate::process::Pid;
+use crate::sched::{self, WaitQueue, now_ticks};
+use crate::signal;
+use kernel_sync::IrqGuard;
+
+/// A kernel object a process can [`wait`] for.
+pub trait Pollable {
+    /// Which of `events` are ready now, plus `POLLHUP` and `POLLERR` where
+    /// they apply to `events`.
+    fn ready(&self, events: u16) -> u16;
+
+    /// Queue `pid` on the queues woken when one of `events` may become
+    /// ready.
+    fn register(&self, events: u16, pid: Pid);
+
+    /// Take `pid` off the queues [`register`](Self::register) put it on.
+    fn unregister(&self, events: u16, pid: Pid);
+}
+
+/// An empty slot, e.g. of a fixed-size array of objects, is never ready.
+impl<P: Pollable> Pollable for Option<P> {
+    fn ready(&self, events: u16) -> u16 {
+        self.as_ref().map_or(0, |object| object.ready(events))
+    }
+
+    fn register(&self, events: u16, pid: Pid) {
+        if let Some(object) = self {
+            object.register(events, pid);
+        }
+    }
+
+    fn unregister(&self, events: u16, pid: Pid) {
+        if let Some(object) = self {
+            object.unregister(events, pid);
+        }
+    }
+}
+
+/// Processes in [`wait`], so that a signal can end their wait.
+static POLLING: WaitQueue = WaitQueue::new();
+
+/// Block the current process until one of `objects` is ready for its events,
+/// timer tick `deadline` passes or a signal interrupts it. Stores what is
+/// ready of each object in `revents`; returns the number of ready objects,
+/// `0` if none is.
+///
+/// # Panics
+/// If called outside of a process.
+pub fn wait<P: Pollable>(
+    objects: &[(P, u16)],
+    revents: &mut [u16],
+    deadline: Option<u64>,
+) -> usize {
+    let me = sched::current_pid().expect("poll called outside of a process");
+    loop {
+        let irq = IrqGuard::new();
+        for (object, events) in objects {
+            object.register(*events, me);
+        }
+        POLLING.add_waiter(me);
+        sched::block(me, deadline);
+
+        let ready = scan(objects, revents);
+        if ready > 0
+            || deadline.is_some_and(|deadline| now_ticks() >= deadline)
+            || signal::interrupts(me)
+        {
+            for (object, events) in objects {
+                object.unregister(*events, me);
+            }
+            POLLING.remove_waiter(me);
+            sched::resume(me);
+            return ready;
+        }
+
+        drop(irq);
+        sched::schedule();
+    }
+}
+
+/// Store what is ready of each of `objects` in `revents`; returns the number
+/// of ready objects.
+pub fn scan<P: Pollable>(objects: &[(P, u16)], revents: &mut [u16]) -> usize {
+    let mut ready = 0;
+    for ((object, events), revents) in objects.iter().zip(revents) {
+        *revents = object.ready(*events);
+        if *revents != 0 {
+            ready += 1;
+        }
+    }
+    ready
+}
+
+/// End the [`wait`] of `pid`, e.g. for a signal; it checks its objects once
+/// more and returns if the signal interrupts it.
+///
+/// Returns `true` if the process was waiting.
+pub fn interrupt(pid: Pid) -> bool {
+    POLLING.wake(pid)
+}
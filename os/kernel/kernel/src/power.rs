@@ -0,0 +1,229 @@
+//! # Power Off, Reboot and Halt
+//!
+//! [`shutdown`] takes the machine down in order, for the `reboot` system
+//! call of the init process and the `ktest` runner:
+//!
+//! 1. **CPUs**: every other CPU is taken [offline](crate::hotplug) and
+//!    given [`PARK_SPINS`] polls to park.
+//! 2. **Drivers**: the [virtio console](crate::virtio::console) hands its
+//!    last write to the host and is reset, so no device writes to memory
+//!    any more. The [NVMe controller](crate::nvme) is told to shut down, so
+//!    it flushes its write cache.
+//! 3. **Interrupts**: disabled on this CPU for good.
+//! 4. **Action**: see below. If it does not take, the CPU halts.
+//!
+//! ## Powering Off
+//!
+//! ACPI sleep state S5 ("soft off"): the [`_S5_`](kernel_acpi::sleep) sleep
+//! types from the DSDT go into the `PM1a` and `PM1b` control registers of the
+//! FADT, together with `SLP_EN`. If the firmware still runs in legacy mode
+//! (`SCI_EN` clear), it is first switched to ACPI mode through `SMI_CMD`.
+//!
+//! ## Restarting
+//!
+//! The first of these that works:
+//!
+//! | Method               | How                                                  |
+//! |----------------------|------------------------------------------------------|
+//! | ACPI reset register  | The FADT's reset value to its register (I/O space)   |
+//! | PCI reset control    | `0x06` (full reset) to port `0xCF9`                  |
+//! | Keyboard controller  | Command `0xFE` (pulse reset line) to port `0x64`     |
+//! | Triple fault         | An exception with an empty IDT                       |
+//!
+//! ## Halting
+//!
+//! The CPU stops in a `hlt` loop with interrupts disabled; the machine
+//! stays on.
+
+use crate::acpi;
+use crate::hotplug::{self, CpuState};
+use crate::per_cpu::{self, PerCpu};
+use crate::{nvme, virtio};
+use core::fmt;
+use core::hint::spin_loop;
+use kernel_acpi::sleep::{self, SleepType};
+use kernel_ports::{Port, PortWriteOnly};
+use kernel_sync::irq::cli_stop_interrupts;
+use log::{info, warn};
+
+/// How often to poll for other CPUs to park, or for the firmware to switch
+/// to ACPI mode.
+pub const PARK_SPINS: usize = 10_000_000;
+
+/// `SCI_EN` in PM1 control: the firmware is in ACPI mode.
+const SCI_EN: u16 = 1 << 0;
+
+/// PCI reset control register.
+const RESET_CONTROL: PortWriteOnly<u8> = PortWriteOnly::new(0xCF9);
+
+/// Full reset, with the reset bit set.
+const RESET_CONTROL_FULL: u8 = 0x06;
+
+/// 8042 keyboard controller command port.
+const KBC_COMMAND: PortWriteOnly<u8> = PortWriteOnly::new(0x64);
+
+/// 8042 command: pulse the CPU reset line.
+const KBC_PULSE_RESET: u8 = 0xFE;
+
+/// What [`shutdown`] does in the end.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PowerAction {
+    PowerOff,
+    Reboot,
+    Halt,
+}
+
+impl fmt::Display for PowerAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PowerOff => f.write_str("power-off"),
+            Self::Reboot => f.write_str("reboot"),
+            Self::Halt => f.write_str("halt"),
+        }
+    }
+}
+
+/// Stop the other CPUs and the drivers, then perform `action`; see the
+/// [module docs](self).
+pub fn shutdown(action: PowerAction) -> ! {
+    info!("System {action}");
+    stop_other_cpus();
+    virtio::console::shutdown();
+    nvme::shutdown();
+    cli_stop_interrupts();
+
+    match action {
+        PowerAction::PowerOff => power_off(),
+        PowerAction::Reboot => reset(),
+        PowerAction::Halt => {}
+    }
+    info!("System halted");
+    halt()
+}
+
+/// Enter ACPI S5, without stopping anything first. Returns if there is no
+/// way to or the machine stays on.
+pub fn power_off() {
+    let Some(fadt) = acpi::fadt() else {
+        warn!("Power-off: no FADT");
+        return;
+    };
+    let Some(pm1a) = fadt.pm1a_cnt else {
+        warn!("Power-off: no PM1a control register");
+        return;
+    };
+    let Some(s5) = acpi::dsdt().and_then(sleep::find_s5) else {
+        warn!("Power-off: no _S5_ in the DSDT");
+        return;
+    };
+
+    let pm1a: Port<u16> = Port::new(pm1a);
+    // Safety: the FADT names the port as the PM1a control register.
+    unsafe {
+        if pm1a.read() & SCI_EN == 0 {
+            enable_acpi(pm1a, fadt.smi_cmd, fadt.acpi_enable);
+        }
+        write_sleep_type(pm1a, s5.pm1a_bits());
+        if let Some(pm1b) = fadt.pm1b_cnt {
+            write_sleep_type(Port::new(pm1b), s5.pm1b_bits());
+        }
+    }
+    for _ in 0..PARK_SPINS {
+        spin_loop();
+    }
+    warn!("Power-off: still running after entering S5");
+}
+
+/// Restart the machine, without stopping anything first; does not return.
+pub fn reset() -> ! {
+    if let Some((register, value)) = acpi::fadt().and_then(|fadt| fadt.reset) {
+        match register.port() {
+            // Safety: the FADT names the port as the reset register.
+            Some(port) => unsafe { PortWriteOnly::<u8>::new(port).write(value) },
+            None => warn!("Reset: ACPI reset register not in I/O space"),
+        }
+    }
+    // Safety: both are the standard chipset reset ports.
+    unsafe {
+        RESET_CONTROL.write(RESET_CONTROL_FULL);
+        KBC_COMMAND.write(KBC_PULSE_RESET);
+    }
+    warn!("Reset: chipset did not reset; triple faulting");
+    triple_fault()
+}
+
+/// Stop this CPU for good.
+pub fn halt() -> ! {
+    loop {
+        unsafe { core::arch::asm!("cli", "hlt", options(nomem, nostack)) };
+    }
+}
+
+/// Take every CPU but this one offline and wait a while for them to park.
+fn stop_other_cpus() {
+    let me = unsafe { PerCpu::current() }.cpu_id;
+    let others = || per_cpu::cpus().filter(move |cpu| cpu.cpu_id != me);
+    for cpu in others() {
+        if let Err(e) = hotplug::offline(cpu.cpu_id) {
+            warn!("CPU {}: not stopped: {e}", cpu.cpu_id);
+        }
+    }
+    let parked = || {
+        others()
+            .all(|cpu| cpu.cpu_id == hotplug::BOOT_CPU || cpu.hotplug.state() == CpuState::Offline)
+    };
+    for _ in 0..PARK_SPINS {
+        if parked() {
+            return;
+        }
+        spin_loop();
+    }
+    warn!("Not all CPUs parked; shutting down anyway");
+}
+
+/// Switch the firmware from legacy to ACPI mode.
+///
+/// # Safety
+/// `pm1a` must be the `PM1a` control register.
+unsafe fn enable_acpi(pm1a: Port<u16>, smi_cmd: Option<u16>, acpi_enable: u8) {
+    let Some(smi_cmd) = smi_cmd.filter(|_| acpi_enable != 0) else {
+        warn!("Power-off: firmware in legacy mode and no way to switch");
+        return;
+    };
+    unsafe { PortWriteOnly::<u8>::new(smi_cmd).write(acpi_enable) };
+    for _ in 0..PARK_SPINS {
+        if unsafe { pm1a.read() } & SCI_EN != 0 {
+            return;
+        }
+        spin_loop();
+    }
+    warn!("Power-off: firmware did not switch to ACPI mode");
+}
+
+/// Replace the sleep type bits of a PM1 control register with `bits`, which
+/// include `SLP_EN`.
+///
+/// # Safety
+/// `port` must be a PM1 control register.
+unsafe fn write_sleep_type(port: Port<u16>, bits: u16) {
+    let keep = !((0x7 << SleepType::SLP_TYP_SHIFT) | SleepType::SLP_EN);
+    unsafe { port.write((port.read() & keep) | bits) };
+}
+
+/// Reset the CPU by raising an exception it can not deliver.
+fn triple_fault() -> ! {
+    #[repr(C, packed)]
+    struct Idtr {
+        limit: u16,
+        base: u64,
+    }
+    let empty = Idtr { limit: 0, base: 0 };
+    unsafe {
+        core::arch::asm!(
+            "lidt [{}]",
+            "int3",
+            in(reg) &raw const empty,
+            options(noreturn, nostack)
+        );
+    }
+}
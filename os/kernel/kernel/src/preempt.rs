@@ -0,0 +1,147 @@
+//! # Preemption
+//!
+//! The timer takes the CPU away from a process whose time slice ran out.
+//! Kernel code that must not be switched away from disables preemption with
+//! [`preempt_disable`](kernel_sync::preempt::preempt_disable)/[`preempt_enable`](kernel_sync::preempt::preempt_enable)
+//! or a [`PreemptGuard`](kernel_sync::PreemptGuard); locks taken with
+//! [`lock_irq`](kernel_sync::Mutex::lock_irq) do so implicitly. The counter
+//! behind them is per CPU and installed by [`init`].
+//!
+//! ## Per-CPU state
+//!
+//! Each CPU keeps a [`CpuPreempt`]: the nesting depth of preemption-disabled
+//! sections, the ticks the running process has used of its slice, and a
+//! *need resched* flag.
+//!
+//! ## Deferred switches
+//!
+//! On every timer tick, [`on_tick`] charges the running process. Once its
+//! [slice](TIME_SLICE_MS) is used up, the scheduler found a more important
+//! process ready ([`request_resched`]), or the CPU is going
+//! [offline](crate::hotplug), the CPU needs a reschedule:
+//!
+//! * If the tick interrupted user mode, the process is switched away from
+//!   right away.
+//! * If it interrupted the kernel, only the flag is set. The switch happens
+//!   when the outermost preemption-disabled section ends with interrupts
+//!   enabled, or at the process' next return to user mode
+//!   ([`preempt_point`]), whichever comes first.
+//!
+//! Apart from [kernel threads](crate::process::spawn_kernel_thread), which
+//! run with interrupts enabled, the kernel runs with interrupts disabled, so
+//! it is only ever interrupted at explicit sleep points.
+
+use crate::clock;
+use crate::per_cpu::PerCpu;
+use crate::sched;
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use kernel_sync::irq::rflags;
+
+/// Time a process may run before it is preempted in favor of another.
+pub const TIME_SLICE_MS: u64 = 10;
+
+/// Preemption state of one CPU.
+pub struct CpuPreempt {
+    /// Nesting depth of preemption-disabled sections.
+    count: AtomicU32,
+    /// Ticks the running process has used of its time slice.
+    slice_ticks: AtomicU32,
+    /// A switch is due as soon as preemption is allowed.
+    need_resched: AtomicBool,
+}
+
+impl CpuPreempt {
+    pub const fn new() -> Self {
+        Self {
+            count: AtomicU32::new(0),
+            slice_ticks: AtomicU32::new(0),
+            need_resched: AtomicBool::new(false),
+        }
+    }
+}
+
+impl Default for CpuPreempt {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Install the per-CPU counter as the preemption hooks of `kernel_sync`.
+///
+/// Must be called once per-CPU data is reachable through GS.
+pub fn init() {
+    kernel_sync::preempt::set_preempt_hooks(disable_hook, enable_hook);
+}
+
+fn disable_hook() {
+    let cpu = unsafe { PerCpu::current() };
+    cpu.preempt.count.fetch_add(1, Ordering::Relaxed);
+}
+
+fn enable_hook() {
+    let cpu = unsafe { PerCpu::current() };
+    let prev = cpu.preempt.count.fetch_sub(1, Ordering::Relaxed);
+    debug_assert!(prev > 0, "unbalanced preempt_enable");
+
+    if prev == 1 && interrupts_enabled() {
+        preempt_point();
+    }
+}
+
+fn interrupts_enabled() -> bool {
+    rflags() & (1 << 9) != 0
+}
+
+/// Whether preemption is currently allowed on this CPU.
+pub fn preemptible() -> bool {
+    let cpu = unsafe { PerCpu::current() };
+    cpu.preempt.count.load(Ordering::Relaxed) == 0
+}
+
+/// Whether a switch was deferred on this CPU.
+pub fn need_resched() -> bool {
+    let cpu = unsafe { PerCpu::current() };
+    cpu.preempt.need_resched.load(Ordering::Relaxed)
+}
+
+/// Have the running process switched away from at the next preemption point.
+pub fn request_resched() {
+    let cpu = unsafe { PerCpu::current() };
+    cpu.preempt.need_resched.store(true, Ordering::Relaxed);
+}
+
+/// Switch away now if a switch is due and preemption is allowed.
+pub fn preempt_point() {
+    if need_resched() && preemptible() && sched::current_pid().is_some() {
+        sched::schedule();
+    }
+}
+
+/// Called by the scheduler whenever it picks the next process: the next
+/// process starts with a fresh slice.
+pub fn on_switch() {
+    let cpu = unsafe { PerCpu::current() };
+    cpu.preempt.slice_ticks.store(0, Ordering::Relaxed);
+    cpu.preempt.need_resched.store(false, Ordering::Relaxed);
+}
+
+/// Charge a timer tick to the running process and preempt it if its slice is
+/// used up or a switch was requested; see
+/// [Deferred switches](self#deferred-switches).
+pub fn on_tick(from_user: bool) {
+    if sched::current_pid().is_none() {
+        return;
+    }
+
+    let cpu = unsafe { PerCpu::current() };
+    let slice =
+        u32::try_from((clock::timer_hz() * TIME_SLICE_MS / 1000).max(1)).unwrap_or(u32::MAX);
+    let used = cpu.preempt.slice_ticks.fetch_add(1, Ordering::Relaxed) + 1;
+    if used >= slice || !cpu.hotplug.is_online() {
+        request_resched();
+    }
+
+    if from_user {
+        preempt_point();
+    }
+}
@@ -0,0 +1,1152 @@
+//! # Processes and the Process Table
+//!
+//! A *process* is a user program running in its own address space. This
+//! module owns the kernel's process table and implements the lifecycle
+//! operations behind the `spawn`, `waitpid` and `exit` system calls.
+//!
+//! The table also holds the kernel's own threads, so *task* names either
+//! kind of entry; see [`TaskKind`].
+//!
+//! ## Overview
+//!
+//! * [`spawn`] resolves a program path through the [`bundlefs`], loads the
+//!   ELF image into a **fresh address space** (sharing the kernel half and
+//!   the [clock page](crate::clock_page)), allocates a [`Pid`] and a table slot, and marks the process
+//!   [`Ready`](ProcessState::Ready) for the [scheduler](crate::sched).
+//! * [`wait`] blocks the caller until a child becomes a
+//!   [`Zombie`](ProcessState::Zombie), then reaps it, frees its address space
+//!   and returns its exit code.
+//! * [`fork`] duplicates the calling process: the child shares the parent's
+//!   pages copy-on-write and returns from the same system call with `0`.
+//! * [`exit`] turns the calling process into a zombie, wakes a waiting parent
+//!   and never returns.
+//! * [`spawn_kernel_thread`] starts a function as a kernel thread.
+//!
+//! ## Lifecycle
+//!
+//! ```text
+//!   spawn ──► Ready ◄──────► Running ──exit──► Zombie ──wait──► (slot freed)
+//!               ▲               │
+//!               └── Blocked ◄───┘  (wait queue, e.g. waitpid on a live child)
+//! ```
+//!
+//! ## Scheduling
+//!
+//! Ready processes wait in the table's [`RunQueue`] by their effective
+//! [priority](crate::sched::priority); [`ProcessTable::make_ready`] and
+//! [`ProcessTable::pick_next`] move processes in and out of it and keep the
+//! process' [`SchedInfo`] accounting. A spawned process starts at
+//! [`Priority::DEFAULT`]; a forked child inherits its parent's priority.
+//!
+//! ## Kernel stacks
+//!
+//! Each table slot owns a dedicated kernel stack (see [`kstack`]). While a
+//! process runs in user mode, the per-CPU [`kstack_top`](crate::per_cpu::PerCpu::kstack_top)
+//! and TSS `rsp0` point at the top of its stack, so system calls and
+//! interrupts land on it. A process that is not running is parked on that
+//! stack inside the scheduler (see [`context`]).
+//!
+//! ## Initial user stack
+//!
+//! Arguments, environment and an auxiliary vector are placed on the new
+//! process' user stack before it first runs, following the System V layout
+//! (see [`ustack`]).
+//!
+//! ## Thread-local storage
+//!
+//! A program with a `PT_TLS` segment gets its TLS block and thread control
+//! block mapped at spawn time; the process' [`fs_base`](Process::fs_base)
+//! starts out as the thread pointer and can be changed with `arch_prctl`.
+//! The scheduler loads it into `IA32_FS_BASE` whenever the process is
+//! switched to. A forked child inherits it, along with a copy-on-write copy
+//! of the block.
+//!
+//! ## Kernel threads
+//!
+//! A kernel thread runs a `fn(usize)` on its own kernel stack and never
+//! enters user mode. It is scheduled like any process and can block on
+//! [wait queues](WaitQueue). Unlike other kernel code, it runs with
+//! interrupts enabled, but it is still only switched away from when it
+//! blocks or leaves a [preemption-disabled](crate::preempt) section with a
+//! switch due, so it must do either regularly to let others run. Kernel
+//! threads have no parent and get an address space without user mappings,
+//! so switching to them works the same as switching to a process. Signals
+//! are only delivered on the way to user mode, so kernel threads never see
+//! them. Once the function returns, the thread exits and is reaped by the
+//! [workqueue](crate::workqueue).
+//!
+//! ## Identity and affinity
+//!
+//! Every task has a [`name`](Process::name) of up to [`NAME_LEN`] bytes and a
+//! single thread, whose TID equals the PID. Its [`affinity`](Process::affinity)
+//! masks the CPUs it may run on, which [`ProcessTable::pick_next`] honors;
+//! the scheduler records the CPU it last ran on. [`tasks`](crate::tasks)
+//! lists them all.
+//!
+//! ## Memory map
+//!
+//! Every process records its user mappings (image segments, stack and the
+//! stack guard) in a [`VmaSet`] of up to [`MAX_VMAS`] areas. The stack area
+//! is reserved in full but mapped on demand (see [`stack_growth`]), as are
+//! read-only segments of the image, which map the program file's pages in
+//! the [page cache](crate::page_cache) (see [`file_pages`]). [`find_vma`]
+//! looks up the area containing an address, e.g. to classify a page fault.
+//! The set also says which frames the process owns: those are shared
+//! copy-on-write by [`fork`] and returned to the allocator when the last
+//! address space using them is torn down.
+//!
+//! Further anonymous, [shared memory](crate::shm) or file mappings are added
+//! with [`mmap::mmap`].
+//!
+//! Where the stack, the mappings and a position-independent image lie is
+//! randomized per process (see [`layout`]).
+//!
+//! ## Handles
+//!
+//! Each process holds kernel objects, such as [pipe](crate::pipe) ends,
+//! files and [shared memory](crate::shm) objects, through a table of
+//! handles with per-handle rights (see [`handle`]). A forked or spawned
+//! child inherits a copy; [`exit`] closes the handles still open.
+//!
+//! ## Capabilities
+//!
+//! Each process holds [capabilities](caps) that privileged system calls and
+//! devices check. A spawned child gets its parent's inheritable set, a
+//! forked child a copy of both sets; processes the kernel starts hold all.
+//!
+//! ## Signals
+//!
+//! Each process carries its pending [signals](crate::signal) and their
+//! dispositions. A forked child inherits the dispositions; [`exit`] sends
+//! `SIGCHLD` to the parent.
+//!
+//! ## Limitations
+//!
+//! * The table has a fixed capacity of [`MAX_PROCESSES`] entries, kernel
+//!   threads included.
+
+mod args;
+pub mod caps;
+pub mod context;
+pub mod file_pages;
+pub mod handle;
+pub mod kstack;
+pub mod layout;
+pub mod mmap;
+pub mod stack_growth;
+mod ustack;
+
+pub use crate::process::args::ArgBuf;
+
+use crate::alloc::{
+    FlushTlb, create_address_space, destroy_address_space, fork_address_space, try_with_kernel_vmm,
+    with_address_space,
+};
+use crate::bundlefs;
+use crate::clock_page;
+use crate::elf::ElfErr;
+use crate::fpu::{self, FpuState};
+use crate::per_cpu::stack::{self, StackKind, map_kernel_stack};
+use crate::process::caps::Capabilities;
+use crate::process::context::{Context, fork_context, initial_context};
+use crate::process::handle::HandleTable;
+use crate::process::kstack::kstack_slot_for_process;
+use crate::process::layout::Layout;
+use crate::process::ustack::write_initial_stack;
+use crate::sched::priority::{Priority, SchedInfo};
+use crate::sched::{self, RunQueue, WaitQueue};
+use crate::signal::{self, SignalState};
+use crate::smap::SmapGuard;
+use crate::strace;
+use crate::syscall::entry::SyscallFrame;
+use crate::timer::{self, TimerHandle};
+use crate::tracepoint::trace_event;
+use crate::userland::{enter_user_mode, load_elf};
+use crate::workqueue;
+use core::fmt;
+use core::num::{NonZeroU32, NonZeroU64};
+use kernel_info::memory::KERNEL_STACK_SIZE;
+use kernel_memory_addresses::{PageSize, Size4K, VirtualAddress};
+use kernel_registers::StoreRegisterUnsafe;
+use kernel_registers::msr::Ia32FsBaseMsr;
+use kernel_sync::irq::sti_enable_interrupts;
+use kernel_sync::{IrqGuard, SpinMutex};
+use kernel_vmem::address_space::RootPage;
+use kernel_vmem::pcid::PcidTag;
+use kernel_vmem::vma::{Vma, VmaKind, VmaSet};
+use log::{debug, info, warn};
+use stdlib::syscall_abi::signal::SIGCHLD;
+use stdlib::syscall_abi::task::{self, ALL_CPUS};
+
+/// Maximum number of processes (including zombies) alive at the same time.
+pub const MAX_PROCESSES: usize = 16;
+
+// `ProcessTable::expire_timeouts` reports one bit per slot.
+const _: () = assert!(MAX_PROCESSES <= u32::BITS as usize);
+
+/// Maximum number of virtual memory areas per process.
+pub const MAX_VMAS: usize = 32;
+
+/// The virtual memory areas of a process.
+pub type UserVmas = VmaSet<MAX_VMAS>;
+
+/// Maximum number of bytes kept of a process name.
+pub const NAME_LEN: usize = task::NAME_LEN;
+
+/// Highest top of a user stack; each process' [`Layout`] moves its stack
+/// below it.
+pub const USER_STACK_TOP: VirtualAddress = VirtualAddress::new(0x0000_7fff_f000);
+
+/// Size of the user stack reservation of every process, in 4 KiB pages
+/// (8 MiB).
+pub const USER_STACK_PAGES: NonZeroU64 = NonZeroU64::new(2048).unwrap();
+
+/// Pages at the top of the user stack mapped at spawn (64 KiB); the rest is
+/// mapped as the stack [grows](stack_growth).
+pub const USER_STACK_INITIAL_PAGES: NonZeroU64 = NonZeroU64::new(16).unwrap();
+
+/// A process identifier.
+///
+/// PIDs start at 1 ([`Pid::INIT`]) and are never reused while the kernel runs.
+/// `0` is reserved to denote "no process" (e.g. an idle CPU).
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[repr(transparent)]
+pub struct Pid(NonZeroU32);
+
+impl Pid {
+    /// The PID of the first user process.
+    pub const INIT: Self = Self(NonZeroU32::new(1).unwrap());
+
+    /// Interpret a raw value (e.g. a syscall argument) as a PID.
+    pub fn from_raw(raw: u64) -> Option<Self> {
+        u32::try_from(raw).ok().and_then(NonZeroU32::new).map(Self)
+    }
+
+    pub const fn as_u32(self) -> u32 {
+        self.0.get()
+    }
+
+    pub const fn as_u64(self) -> u64 {
+        self.0.get() as u64
+    }
+}
+
+impl fmt::Display for Pid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+/// Scheduling state of a process.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ProcessState {
+    /// Runnable, waiting for a CPU.
+    Ready,
+    /// Currently executing on a CPU.
+    Running,
+    /// Blocked on a [`WaitQueue`](crate::sched::WaitQueue), optionally only
+    /// until the given timer tick.
+    Blocked { until: Option<u64> },
+    /// Exited with the given code; waiting to be reaped by its parent.
+    Zombie(u32),
+}
+
+/// What a task runs.
+#[derive(Debug, Copy, Clone)]
+pub enum TaskKind {
+    /// A user program.
+    User,
+    /// A kernel thread running `func(arg)`.
+    Kernel { func: fn(usize), arg: usize },
+}
+
+/// A process table entry.
+pub struct Process {
+    pub pid: Pid,
+    /// The process to collect our exit code, if any.
+    pub parent: Option<Pid>,
+    pub state: ProcessState,
+    /// User process or kernel thread.
+    pub kind: TaskKind,
+    /// Bit mask of the CPUs the task may run on.
+    pub affinity: u64,
+    /// Logical index of the CPU the task last ran on.
+    pub cpu: Option<u32>,
+    /// Arguments the process was spawned with.
+    pub args: ArgBuf,
+    /// Environment the process was spawned with.
+    pub env: ArgBuf,
+    /// PML4 of the process' address space.
+    pub root: RootPage,
+    /// PCID assignment of the address space.
+    pub pcid: PcidTag,
+    /// User mappings of the address space.
+    pub vmas: UserVmas,
+    /// Where the stack, mappings and a position-independent image lie.
+    pub layout: Layout,
+    /// User entry point.
+    pub entry: VirtualAddress,
+    /// Initial user stack pointer (pointing at `argc`).
+    pub user_stack_top: VirtualAddress,
+    /// Lowest mapped address of the user stack; see [`stack_growth`].
+    pub stack_low: VirtualAddress,
+    /// FS base of the user code: the thread pointer, or `0` without TLS.
+    pub fs_base: u64,
+    /// Top of the process' kernel stack.
+    pub kstack_top: VirtualAddress,
+    /// Saved kernel context while not running.
+    pub context: Context,
+    /// Saved FP/SIMD registers while not running.
+    pub fpu: FpuState,
+    /// Held kernel objects.
+    pub handles: HandleTable,
+    /// Pending signals and their dispositions.
+    pub signals: SignalState,
+    /// Priority and CPU time accounting.
+    pub sched: SchedInfo,
+    /// Timer ending a [`Blocked`](ProcessState::Blocked) state with a timeout.
+    pub timeout: Option<TimerHandle>,
+    /// Whether the process' system calls are [traced](crate::strace).
+    pub traced: bool,
+    /// What the process may do beyond its address space; see [`caps`].
+    pub caps: Capabilities,
+    name: [u8; NAME_LEN],
+    name_len: usize,
+}
+
+impl Process {
+    /// Short name of the process (last path component of its program).
+    pub fn name(&self) -> &str {
+        core::str::from_utf8(&self.name[..self.name_len]).unwrap_or("?")
+    }
+
+    /// Whether this is a kernel thread.
+    pub const fn is_kernel_thread(&self) -> bool {
+        matches!(self.kind, TaskKind::Kernel { .. })
+    }
+
+    /// Whether the [`affinity`](Self::affinity) allows CPU `cpu_id`.
+    pub const fn may_run_on(&self, cpu_id: u32) -> bool {
+        cpu_id < u64::BITS && self.affinity & (1 << cpu_id) != 0
+    }
+
+    fn set_name(&mut self, path: &str) {
+        let base = path.rsplit('/').next().unwrap_or(path);
+
+        // Truncate at a character boundary.
+        let mut len = base.len().min(NAME_LEN);
+        while !base.is_char_boundary(len) {
+            len -= 1;
+        }
+
+        self.name[..len].copy_from_slice(&base.as_bytes()[..len]);
+        self.name_len = len;
+    }
+}
+
+/// Fixed-capacity table of all processes.
+pub struct ProcessTable {
+    slots: [Option<Process>; MAX_PROCESSES],
+    /// Kernel stack tops of slots that already had their stack mapped.
+    kstacks: [Option<VirtualAddress>; MAX_PROCESSES],
+    /// Slots of the [`Ready`](ProcessState::Ready) processes.
+    run_queue: RunQueue,
+    next_pid: u32,
+}
+
+/// Woken whenever a process exits; parents in [`wait`] block here.
+static CHILD_EXITED: WaitQueue = WaitQueue::new();
+
+/// The global process table.
+pub static PROCESSES: SpinMutex<ProcessTable> = SpinMutex::new(ProcessTable::new());
+
+impl ProcessTable {
+    // Only evaluated at compile time to initialize `PROCESSES`.
+    #[allow(clippy::large_stack_arrays)]
+    const fn new() -> Self {
+        Self {
+            slots: [const { None }; MAX_PROCESSES],
+            kstacks: [None; MAX_PROCESSES],
+            run_queue: RunQueue::new(),
+            next_pid: 1,
+        }
+    }
+
+    /// The process in table slot `slot`, if any.
+    pub fn get(&self, slot: usize) -> Option<&Process> {
+        self.slots.get(slot)?.as_ref()
+    }
+
+    /// The process in table slot `slot`, if any.
+    pub fn get_mut(&mut self, slot: usize) -> Option<&mut Process> {
+        self.slots.get_mut(slot)?.as_mut()
+    }
+
+    /// Find the table slot of the process `pid`.
+    pub fn find(&self, pid: Pid) -> Option<usize> {
+        self.slots
+            .iter()
+            .position(|p| p.as_ref().is_some_and(|p| p.pid == pid))
+    }
+
+    /// Take the highest-priority [`Ready`](ProcessState::Ready) process that
+    /// may run on CPU `cpu_id` off the run queue and mark it
+    /// [`Running`](ProcessState::Running) as of tick `now`.
+    pub fn pick_next(&mut self, now: u64, cpu_id: u32) -> Option<usize> {
+        let slots = &self.slots;
+        let slot = self
+            .run_queue
+            .pop_front_where(|slot| slots[slot].as_ref().is_some_and(|p| p.may_run_on(cpu_id)))?;
+        if let Some(p) = self.get_mut(slot) {
+            p.state = ProcessState::Running;
+            p.sched.started(now);
+        }
+        Some(slot)
+    }
+
+    /// Make the [`Running`](ProcessState::Running) or
+    /// [`Blocked`](ProcessState::Blocked) process in `slot` ready as of tick
+    /// `now` and queue it behind the processes of its priority.
+    ///
+    /// Returns `false` if the slot holds no such process.
+    pub fn make_ready(&mut self, slot: usize, now: u64) -> bool {
+        let Some(p) = self.get_mut(slot) else {
+            return false;
+        };
+        match p.state {
+            ProcessState::Running => p.sched.stopped(now),
+            ProcessState::Blocked { .. } => {
+                p.sched.woken(now);
+                if let Some(timer) = p.timeout.take() {
+                    timer::cancel(timer);
+                }
+            }
+            ProcessState::Ready | ProcessState::Zombie(_) => return false,
+        }
+        p.state = ProcessState::Ready;
+        let priority = p.sched.priority();
+        self.run_queue.push_back(priority, slot);
+        true
+    }
+
+    /// Make the process in `slot`, which [blocked](ProcessState::Blocked)
+    /// but has not switched away yet, [`Running`](ProcessState::Running)
+    /// again as of tick `now`, taking it off the run queue if it was woken.
+    pub fn resume(&mut self, slot: usize, now: u64) {
+        let queued = match self.get(slot).map(|p| p.state) {
+            Some(ProcessState::Blocked { .. }) => false,
+            Some(ProcessState::Ready) => true,
+            _ => return,
+        };
+        if queued {
+            self.run_queue.remove(slot);
+        }
+        let Some(p) = self.get_mut(slot) else {
+            return;
+        };
+        if let Some(timer) = p.timeout.take() {
+            timer::cancel(timer);
+        }
+        p.state = ProcessState::Running;
+        p.sched.started(now);
+    }
+
+    /// Raise every ready process that waited `interval` ticks for the CPU by
+    /// one level; see [`SchedInfo::age`].
+    pub fn age(&mut self, now: u64, interval: u64) {
+        for (slot, p) in self.slots.iter_mut().enumerate() {
+            let Some(p) = p.as_mut().filter(|p| p.state == ProcessState::Ready) else {
+                continue;
+            };
+            if p.sched.age(now, interval) && self.run_queue.remove(slot) {
+                self.run_queue.push_back(p.sched.priority(), slot);
+            }
+        }
+    }
+
+    /// Set the base priority of the process in `slot` and return the previous
+    /// one, re-queueing it if it is ready.
+    pub fn set_priority(&mut self, slot: usize, priority: Priority) -> Option<Priority> {
+        let p = self.slots.get_mut(slot)?.as_mut()?;
+        let old = core::mem::replace(&mut p.sched.base, priority);
+        if p.state == ProcessState::Ready && self.run_queue.remove(slot) {
+            self.run_queue.push_back(p.sched.priority(), slot);
+        }
+        Some(old)
+    }
+
+    /// Priority of the most important ready process that may run on CPU
+    /// `cpu_id`, if any.
+    pub fn highest_ready_for(&self, cpu_id: u32) -> Option<Priority> {
+        let (priority, _) = self
+            .run_queue
+            .first_where(|slot| self.get(slot).is_some_and(|p| p.may_run_on(cpu_id)))?;
+        Some(priority)
+    }
+
+    /// Number of [`Ready`](ProcessState::Ready) or [`Running`](ProcessState::Running) processes.
+    pub fn runnable(&self) -> usize {
+        self.slots
+            .iter()
+            .flatten()
+            .filter(|p| matches!(p.state, ProcessState::Ready | ProcessState::Running))
+            .count()
+    }
+
+    /// Make every [`Blocked`](ProcessState::Blocked) process whose timeout
+    /// expired at tick `now` ready again.
+    ///
+    /// Returns the woken slots, one bit each.
+    pub fn expire_timeouts(&mut self, now: u64) -> u32 {
+        let mut woken = 0;
+        for slot in 0..MAX_PROCESSES {
+            if let Some(ProcessState::Blocked {
+                until: Some(deadline),
+            }) = self.get(slot).map(|p| p.state)
+                && deadline <= now
+                && self.make_ready(slot, now)
+            {
+                woken |= 1 << slot;
+            }
+        }
+        woken
+    }
+
+    /// Put the new, [`Ready`](ProcessState::Ready) `process` into the free
+    /// `slot` and queue it.
+    fn insert(&mut self, slot: usize, process: Process) {
+        debug_assert_eq!(process.state, ProcessState::Ready);
+        self.run_queue.push_back(process.sched.priority(), slot);
+        self.slots[slot] = Some(process);
+    }
+
+    fn free_slot(&self) -> Option<usize> {
+        self.slots.iter().position(Option::is_none)
+    }
+
+    /// A free slot and its kernel stack top.
+    fn reserve_slot(&mut self) -> Result<(usize, VirtualAddress), SpawnError> {
+        let slot = self.free_slot().ok_or(SpawnError::TableFull)?;
+        Ok((slot, self.kstack_for(slot)?))
+    }
+
+    fn alloc_pid(&mut self) -> Pid {
+        let pid = Pid::from_raw(u64::from(self.next_pid)).expect("PID space exhausted");
+        self.next_pid += 1;
+        pid
+    }
+
+    /// Return the kernel stack of `slot`, mapping it on first use.
+    fn kstack_for(&mut self, slot: usize) -> Result<VirtualAddress, SpawnError> {
+        if let Some(top) = self.kstacks[slot] {
+            return Ok(top);
+        }
+
+        let stack = try_with_kernel_vmm(FlushTlb::OnSuccess, |vmm| {
+            map_kernel_stack(vmm, kstack_slot_for_process(slot), KERNEL_STACK_SIZE as u64)
+        })
+        .map_err(|_| SpawnError::OutOfMemory)?;
+        stack::watch(StackKind::Process(slot), stack.base, stack.len);
+
+        self.kstacks[slot] = Some(stack.top);
+        Ok(stack.top)
+    }
+}
+
+#[derive(Debug)]
+pub enum SpawnError {
+    /// No program exists at the given path.
+    NotFound,
+    /// The program is not a loadable ELF image.
+    Elf(ElfErr),
+    /// The process table is full.
+    TableFull,
+    /// Memory for the address space or kernel stack ran out.
+    OutOfMemory,
+    /// Arguments and environment do not fit on the user stack.
+    StackSetup,
+}
+
+impl fmt::Display for SpawnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound => f.write_str("no such program"),
+            Self::Elf(e) => write!(f, "invalid ELF image: {e:?}"),
+            Self::TableFull => f.write_str("process table is full"),
+            Self::OutOfMemory => f.write_str("out of memory"),
+            Self::StackSetup => f.write_str("failed to set up the initial user stack"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum WaitError {
+    /// The PID does not name a child of the caller.
+    NoSuchChild,
+}
+
+/// Create a new process running the program at `path` with the arguments
+/// `args` and the environment `env`.
+///
+/// The process is marked ready and will run the next time the scheduler
+/// picks it. It starts out with a copy of the `parent`'s handles.
+pub fn spawn(
+    path: &str,
+    args: &ArgBuf,
+    env: &ArgBuf,
+    parent: Option<Pid>,
+) -> Result<Pid, SpawnError> {
+    let inode = bundlefs::find(path).ok_or(SpawnError::NotFound)?;
+    let (_name, image) = bundlefs::entry(inode).ok_or(SpawnError::NotFound)?;
+    let root = create_address_space().map_err(|_| SpawnError::OutOfMemory)?;
+
+    let layout = Layout::choose();
+    let mut vmas = UserVmas::new();
+    let loaded = unsafe {
+        with_address_space(root, || {
+            try_with_kernel_vmm(FlushTlb::OnSuccess, |vmm| {
+                let _guard = SmapGuard::enter();
+                let image = load_elf(
+                    image,
+                    inode as u64,
+                    vmm,
+                    &mut vmas,
+                    &layout,
+                    USER_STACK_PAGES,
+                    USER_STACK_INITIAL_PAGES,
+                )
+                .map_err(SpawnError::Elf)?;
+                clock_page::map(vmm, &mut vmas).map_err(|()| SpawnError::OutOfMemory)?;
+                let sp = write_initial_stack(vmm, &image, args, env)
+                    .map_err(|_| SpawnError::StackSetup)?;
+                Ok((
+                    image.entry,
+                    sp,
+                    image.thread_pointer.map_or(0, VirtualAddress::as_u64),
+                ))
+            })
+        })
+    };
+    let (entry, user_stack_top, fs_base) = match loaded {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            release_address_space(root, &vmas);
+            return Err(e);
+        }
+    };
+
+    let _irq = IrqGuard::new();
+    let mut table = PROCESSES.lock();
+    let (slot, kstack_top) = match table.reserve_slot() {
+        Ok(slot) => slot,
+        Err(e) => {
+            drop(table);
+            release_address_space(root, &vmas);
+            return Err(e);
+        }
+    };
+    let pid = table.alloc_pid();
+    let parent_process = parent
+        .and_then(|parent| table.find(parent))
+        .and_then(|slot| table.get(slot));
+    let handles = parent_process.map_or_else(HandleTable::new, |parent| parent.handles.clone());
+    let caps = parent_process.map_or(Capabilities::ALL, |parent| parent.caps.spawned());
+    for object in handles.iter() {
+        object.dup();
+    }
+
+    let mut process = Process {
+        pid,
+        parent,
+        state: ProcessState::Ready,
+        kind: TaskKind::User,
+        affinity: ALL_CPUS,
+        cpu: None,
+        args: args.clone(),
+        env: env.clone(),
+        root,
+        pcid: PcidTag::new(),
+        vmas,
+        layout,
+        entry,
+        user_stack_top,
+        stack_low: VirtualAddress::new(
+            layout.stack_top.as_u64() - USER_STACK_INITIAL_PAGES.get() * Size4K::SIZE,
+        ),
+        fs_base,
+        kstack_top,
+        context: unsafe { initial_context(kstack_top, process_start) },
+        fpu: FpuState::new(),
+        handles,
+        signals: SignalState::new(),
+        sched: SchedInfo::new(Priority::DEFAULT, sched::now_ticks()),
+        timeout: None,
+        traced: strace::traced_from_start(pid),
+        caps,
+        name: [0; NAME_LEN],
+        name_len: 0,
+    };
+    process.set_name(path);
+
+    info!(
+        "Spawned process {pid} ({name}) with {argc} argument(s) and {envc} environment variable(s), entry at {entry}",
+        name = process.name(),
+        argc = args.len(),
+        envc = env.len()
+    );
+    debug!("Process {pid}: {layout}");
+    trace_event!(process_spawn, pid, parent);
+    table.insert(slot, process);
+    Ok(pid)
+}
+
+/// Start a kernel thread named `name` that runs `func(arg)`.
+///
+/// The thread is marked ready, runs with interrupts enabled and exits once
+/// `func` returns.
+pub fn spawn_kernel_thread(name: &str, func: fn(usize), arg: usize) -> Result<Pid, SpawnError> {
+    let root = create_address_space().map_err(|_| SpawnError::OutOfMemory)?;
+    let vmas = UserVmas::new();
+
+    let _irq = IrqGuard::new();
+    let mut table = PROCESSES.lock();
+    let (slot, kstack_top) = match table.reserve_slot() {
+        Ok(slot) => slot,
+        Err(e) => {
+            drop(table);
+            release_address_space(root, &vmas);
+            return Err(e);
+        }
+    };
+    let pid = table.alloc_pid();
+
+    let mut thread = Process {
+        pid,
+        parent: None,
+        state: ProcessState::Ready,
+        kind: TaskKind::Kernel { func, arg },
+        affinity: ALL_CPUS,
+        cpu: None,
+        args: ArgBuf::new(),
+        env: ArgBuf::new(),
+        root,
+        pcid: PcidTag::new(),
+        vmas,
+        layout: Layout::FIXED,
+        entry: VirtualAddress::zero(),
+        user_stack_top: VirtualAddress::zero(),
+        stack_low: VirtualAddress::zero(),
+        fs_base: 0,
+        kstack_top,
+        context: unsafe { initial_context(kstack_top, kthread_start) },
+        fpu: FpuState::new(),
+        handles: HandleTable::new(),
+        signals: SignalState::new(),
+        sched: SchedInfo::new(Priority::DEFAULT, sched::now_ticks()),
+        timeout: None,
+        traced: false,
+        caps: Capabilities::NONE,
+        name: [0; NAME_LEN],
+        name_len: 0,
+    };
+    thread.set_name(name);
+
+    info!("Spawned kernel thread {pid} ({name})", name = thread.name());
+    trace_event!(process_spawn, pid, None);
+    table.insert(slot, thread);
+    Ok(pid)
+}
+
+/// Duplicate the current process, which is inside the system call `frame`.
+///
+/// The child gets a copy-on-write view of the parent's address space, its
+/// memory map, arguments, environment and FP/SIMD state, and resumes from the
+/// same system call with a return value of `0`. Returns the child's PID.
+///
+/// # Panics
+/// If called outside of a process.
+#[allow(clippy::too_many_lines)]
+pub fn fork(frame: &SyscallFrame) -> Result<Pid, SpawnError> {
+    let me = sched::current_pid().expect("fork called outside of a process");
+    let root = create_address_space().map_err(|_| SpawnError::OutOfMemory)?;
+
+    let (
+        vmas,
+        layout,
+        handles,
+        signals,
+        priority,
+        args,
+        env,
+        entry,
+        user_stack_top,
+        stack_low,
+        fs_base,
+        affinity,
+        traced,
+        caps,
+        name,
+        name_len,
+    ) = {
+        let _irq = IrqGuard::new();
+        let table = PROCESSES.lock();
+        let parent = table
+            .find(me)
+            .and_then(|slot| table.get(slot))
+            .expect("forking process not in table");
+        (
+            parent.vmas.clone(),
+            parent.layout,
+            parent.handles.clone(),
+            parent.signals.fork(),
+            parent.sched.base,
+            parent.args.clone(),
+            parent.env.clone(),
+            parent.entry,
+            parent.user_stack_top,
+            parent.stack_low,
+            parent.fs_base,
+            parent.affinity,
+            parent.traced,
+            parent.caps,
+            parent.name,
+            parent.name_len,
+        )
+    };
+
+    if fork_address_space(root, |va| vmas.clone_policy(va)).is_err() {
+        release_address_space(root, &vmas);
+        return Err(SpawnError::OutOfMemory);
+    }
+
+    // The kernel never touches FP/SIMD registers, so they still hold ours.
+    let mut fpu = FpuState::new();
+    unsafe { fpu::save(&mut fpu) };
+
+    let mut child_frame = frame.clone();
+    child_frame.rax = 0;
+
+    let _irq = IrqGuard::new();
+    let mut table = PROCESSES.lock();
+    let (slot, kstack_top) = match table.reserve_slot() {
+        Ok(slot) => slot,
+        Err(e) => {
+            drop(table);
+            release_address_space(root, &vmas);
+            return Err(e);
+        }
+    };
+    let pid = table.alloc_pid();
+    for object in handles.iter() {
+        object.dup();
+    }
+
+    info!(
+        "Forked process {me} ({name}) into {pid}",
+        name = core::str::from_utf8(&name[..name_len]).unwrap_or("?")
+    );
+    trace_event!(process_spawn, pid, Some(me));
+    let child = Process {
+        pid,
+        parent: Some(me),
+        state: ProcessState::Ready,
+        kind: TaskKind::User,
+        affinity,
+        cpu: None,
+        args,
+        env,
+        root,
+        pcid: PcidTag::new(),
+        vmas,
+        layout,
+        entry,
+        user_stack_top,
+        stack_low,
+        fs_base,
+        kstack_top,
+        context: unsafe { fork_context(kstack_top, &child_frame) },
+        fpu,
+        handles,
+        signals,
+        sched: SchedInfo::new(priority, sched::now_ticks()),
+        timeout: None,
+        traced,
+        caps,
+        name,
+        name_len,
+    };
+    table.insert(slot, child);
+    Ok(pid)
+}
+
+/// Block until the child `child` of `caller` exits, reap it and return its exit code.
+pub fn wait(caller: Pid, child: Pid) -> Result<u32, WaitError> {
+    let mut result = Err(WaitError::NoSuchChild);
+    let mut reaped = None;
+
+    CHILD_EXITED.wait_until(|| {
+        let mut table = PROCESSES.lock();
+        let Some(slot) = table
+            .find(child)
+            .filter(|&slot| table.get(slot).is_some_and(|p| p.parent == Some(caller)))
+        else {
+            result = Err(WaitError::NoSuchChild);
+            return true;
+        };
+
+        if let Some(ProcessState::Zombie(code)) = table.get(slot).map(|p| p.state)
+            && let Some(mut zombie) = table.slots[slot].take()
+        {
+            info!("Reaped process {child} (exit code {code})");
+            reaped = Some((zombie.root, core::mem::take(&mut zombie.vmas)));
+            result = Ok(code);
+            return true;
+        }
+
+        false
+    });
+
+    // The zombie switched away from its address space for good when it exited.
+    if let Some((root, vmas)) = reaped {
+        release_address_space(root, &vmas);
+    }
+
+    result
+}
+
+/// Free the address space `root` and the user frames recorded in `vmas`.
+fn release_address_space(root: RootPage, vmas: &UserVmas) {
+    let stats = unsafe { destroy_address_space(root, |va| vmas.owns_frame(va)) };
+    debug!(
+        "Released address space {root:?}: {pages} page(s), {tables} table(s)",
+        root = root.base(),
+        pages = stats.pages,
+        tables = stats.tables
+    );
+}
+
+/// Terminate the current process with `code` and switch away for good.
+pub fn exit(code: u32) -> ! {
+    let me = sched::current_pid().expect("exit called outside of a process");
+    let mut handles = HandleTable::new();
+    let parent;
+    let mut kernel_thread = false;
+    let mut accounting = None;
+
+    {
+        let _irq = IrqGuard::new();
+        let mut table = PROCESSES.lock();
+        let slot = table.find(me).expect("exiting process not in table");
+        parent = table.get(slot).and_then(|p| p.parent);
+        if let Some(p) = table.get_mut(slot) {
+            kernel_thread = p.is_kernel_thread();
+            p.state = ProcessState::Zombie(code);
+            p.sched.stopped(sched::now_ticks());
+            accounting = Some(p.sched);
+            handles = core::mem::take(&mut p.handles);
+        }
+        trace_event!(process_exit, me, code);
+
+        // Orphans are adopted by init.
+        let adopter = Some(Pid::INIT).filter(|&init| init != me);
+        for p in table.slots.iter_mut().flatten() {
+            if p.parent == Some(me) {
+                p.parent = adopter;
+            }
+        }
+
+        if kernel_thread {
+            // Queued work runs on this CPU, after we switched away for good.
+            if workqueue::schedule_work(reap_kernel_thread, me.as_u32() as usize).is_err() {
+                warn!("Kernel thread {me} exited but could not be queued for reaping");
+            }
+        } else if parent.and_then(|pid| table.find(pid)).is_none() {
+            warn!("Process {me} exited with code {code} and has no parent to reap it");
+        }
+
+        info!("Process {me} exited with code {code}");
+        if let Some(info) = accounting {
+            debug!(
+                "Process {me} ran for {run} tick(s), waited {wait}, slept {sleep} ({wakeups} wake-up(s))",
+                run = info.run_ticks,
+                wait = info.wait_ticks,
+                sleep = info.sleep_ticks,
+                wakeups = info.wakeups
+            );
+        }
+    }
+
+    // Mapped frames stay alive until the address space is released.
+    for object in handles.iter() {
+        object.close();
+    }
+
+    if let Some(parent) = parent {
+        let _ = signal::send(parent, SIGCHLD);
+    }
+
+    // Waiters re-check their own child, so waking everyone is correct (if not cheap).
+    CHILD_EXITED.wake_all();
+
+    sched::schedule();
+    unreachable!("exited process {me} was scheduled again");
+}
+
+/// Work item: reap the exited kernel thread `pid`, which has no parent to
+/// [`wait`] for it.
+fn reap_kernel_thread(pid: usize) {
+    let Some(pid) = Pid::from_raw(pid as u64) else {
+        return;
+    };
+
+    let reaped = {
+        let _irq = IrqGuard::new();
+        let mut table = PROCESSES.lock();
+        table
+            .find(pid)
+            .filter(|&slot| {
+                table
+                    .get(slot)
+                    .is_some_and(|p| matches!(p.state, ProcessState::Zombie(_)))
+            })
+            .and_then(|slot| table.slots[slot].take())
+    };
+
+    if let Some(mut zombie) = reaped {
+        info!("Reaped kernel thread {pid} ({name})", name = zombie.name());
+        let vmas = core::mem::take(&mut zombie.vmas);
+        release_address_space(zombie.root, &vmas);
+    }
+}
+
+/// The area of `pid`'s address space containing `addr`, if any.
+///
+/// Returns `None` without waiting if the process table is locked, so this is
+/// safe to call from fault handlers.
+pub fn find_vma(pid: Pid, addr: VirtualAddress) -> Option<Vma> {
+    let table = PROCESSES.try_lock()?;
+    let slot = table.find(pid)?;
+    table.get(slot)?.vmas.find(addr).copied()
+}
+
+/// The first [guard](kernel_vmem::vma::VmaKind::Guard) area of `pid`'s
+/// address space that overlaps `[start, end)`, if any.
+pub fn guard_overlapping(pid: Pid, start: u64, end: u64) -> Option<Vma> {
+    let _irq = IrqGuard::new();
+    let table = PROCESSES.lock();
+    let slot = table.find(pid)?;
+    table
+        .get(slot)?
+        .vmas
+        .iter()
+        .find(|vma| {
+            vma.kind == VmaKind::Guard && vma.start.as_u64() < end && start < vma.end.as_u64()
+        })
+        .copied()
+}
+
+/// Set the FS base of the current process to `base` and load it.
+///
+/// # Panics
+/// If called outside of a process.
+pub fn set_fs_base(base: u64) {
+    let me = sched::current_pid().expect("set_fs_base called outside of a process");
+    let _irq = IrqGuard::new();
+    let mut table = PROCESSES.lock();
+    let slot = table.find(me).expect("calling process not in table");
+    if let Some(p) = table.get_mut(slot) {
+        p.fs_base = base;
+    }
+    unsafe { Ia32FsBaseMsr::new().with_ptr(base).store_unsafe() };
+}
+
+/// The FS base of the current process.
+///
+/// # Panics
+/// If called outside of a process.
+pub fn fs_base() -> u64 {
+    let me = sched::current_pid().expect("fs_base called outside of a process");
+    let _irq = IrqGuard::new();
+    let table = PROCESSES.lock();
+    table
+        .find(me)
+        .and_then(|slot| table.get(slot))
+        .map_or(0, |p| p.fs_base)
+}
+
+/// Set whether the system calls of `pid` are traced; returns whether they
+/// were, or `None` if there is no such process.
+pub fn set_traced(pid: Pid, on: bool) -> Option<bool> {
+    let _irq = IrqGuard::new();
+    let mut table = PROCESSES.lock();
+    let slot = table.find(pid)?;
+    let p = table.get_mut(slot)?;
+    Some(core::mem::replace(&mut p.traced, on))
+}
+
+/// Whether the system calls of `pid` are traced.
+pub fn is_traced(pid: Pid) -> bool {
+    let _irq = IrqGuard::new();
+    let table = PROCESSES.lock();
+    table
+        .find(pid)
+        .and_then(|slot| table.get(slot))
+        .is_some_and(|p| p.traced)
+}
+
+/// First code run by a new process: leave the kernel for its user entry point.
+extern "C" fn process_start() -> ! {
+    let (entry, user_sp) = {
+        let pid = sched::current_pid().expect("process start without a current process");
+        let table = PROCESSES.lock();
+        let process = table
+            .find(pid)
+            .and_then(|slot| table.get(slot))
+            .expect("started process not in table");
+
+        debug!("Starting process {pid} ({name})", name = process.name());
+        for (i, arg) in process.args.iter().enumerate() {
+            debug!(
+                "  argv[{i}] = {arg:?}",
+                arg = core::str::from_utf8(arg).unwrap_or("?")
+            );
+        }
+        for (i, var) in process.env.iter().enumerate() {
+            debug!(
+                "  envp[{i}] = {var:?}",
+                var = core::str::from_utf8(var).unwrap_or("?")
+            );
+        }
+
+        (process.entry, process.user_stack_top)
+    };
+
+    unsafe { enter_user_mode(entry, user_sp) }
+}
+
+/// First code run by a new kernel thread: run its function, then exit.
+extern "C" fn kthread_start() -> ! {
+    let (func, arg) = {
+        let pid = sched::current_pid().expect("kernel thread start without a current process");
+        let table = PROCESSES.lock();
+        let thread = table
+            .find(pid)
+            .and_then(|slot| table.get(slot))
+            .expect("started kernel thread not in table");
+
+        debug!(
+            "Starting kernel thread {pid} ({name})",
+            name = thread.name()
+        );
+        match thread.kind {
+            TaskKind::Kernel { func, arg } => (func, arg),
+            TaskKind::User => unreachable!("process {pid} started as a kernel thread"),
+        }
+    };
+
+    // The switch here happened with interrupts disabled; unlike a process
+    // entering user mode, nothing turns them back on for us.
+    sti_enable_interrupts();
+    func(arg);
+    exit(0)
+}
@@ -0,0 +1,78 @@
+//! Fixed-capacity storage for process arguments.
+
+use stdlib::syscall_abi::MAX_SPAWN_ARGS;
+
+/// Total bytes available for all arguments of one process.
+pub const ARG_BYTES: usize = 512;
+
+/// Arguments passed to a process at spawn time.
+///
+/// Strings are stored back to back in a fixed buffer; no heap is required.
+#[derive(Clone)]
+pub struct ArgBuf {
+    bytes: [u8; ARG_BYTES],
+    ends: [u16; MAX_SPAWN_ARGS],
+    argc: usize,
+}
+
+impl Default for ArgBuf {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ArgBuf {
+    /// An empty argument list.
+    pub const fn new() -> Self {
+        Self {
+            bytes: [0; ARG_BYTES],
+            ends: [0; MAX_SPAWN_ARGS],
+            argc: 0,
+        }
+    }
+
+    /// Number of arguments.
+    pub const fn len(&self) -> usize {
+        self.argc
+    }
+
+    /// Append an argument of `len` bytes and return the space to fill it in.
+    ///
+    /// Returns `None` if the argument count or byte capacity is exhausted.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn push_uninit(&mut self, len: usize) -> Option<&mut [u8]> {
+        if self.argc == MAX_SPAWN_ARGS {
+            return None;
+        }
+
+        let start = self.used();
+        let end = start.checked_add(len).filter(|&end| end <= ARG_BYTES)?;
+        self.ends[self.argc] = end as u16;
+        self.argc += 1;
+        Some(&mut self.bytes[start..end])
+    }
+
+    /// Append a copy of `arg`.
+    ///
+    /// Returns `None` if the argument count or byte capacity is exhausted.
+    pub fn push(&mut self, arg: &[u8]) -> Option<()> {
+        self.push_uninit(arg.len())?.copy_from_slice(arg);
+        Some(())
+    }
+
+    /// Iterate over the arguments in order.
+    pub fn iter(&self) -> impl Iterator<Item = &[u8]> + '_ {
+        (0..self.argc).map(|i| {
+            let start = if i == 0 { 0 } else { self.ends[i - 1] as usize };
+            &self.bytes[start..self.ends[i] as usize]
+        })
+    }
+
+    const fn used(&self) -> usize {
+        if self.argc == 0 {
+            0
+        } else {
+            self.ends[self.argc - 1] as usize
+        }
+    }
+}
@@ -0,0 +1,177 @@
+//! # Capabilities
+//!
+//! Until there are users and groups, what a process may do beyond its own
+//! address space is a set of capabilities, the `CAP_*` bits of
+//! [`syscall_abi::caps`](stdlib::syscall_abi::caps). Each process holds
+//! [`Capabilities`]: an effective set the kernel checks with [`require`],
+//! and an inheritable set handed to the processes it spawns.
+//!
+//! * Processes the kernel starts, such as init, hold
+//!   [`Capabilities::ALL`]; kernel threads hold none, as they never make
+//!   system calls.
+//! * A spawned child starts with both sets equal to its parent's
+//!   inheritable set ([`Capabilities::spawned`]); a forked child gets a
+//!   copy of both.
+//! * [`set`] replaces either set of the current process with a subset of
+//!   its effective one. A process can thus choose what its children get
+//!   and drop capabilities, but never gain any.
+//!
+//! The system call layer checks capabilities before privileged operations
+//! (`reboot`, `cpu_set_online`); [character devices](crate::chardev) name
+//! the capability needed to open them. `kill`, `setpriority`,
+//! `sched_setaffinity` and `trace` act on the caller and its children
+//! freely, and on other processes only with `CAP_PROCESS` ([`may_control`]);
+//! `kill` may also reach the caller's parent ([`may_signal`]).
+
+use crate::process::{PROCESSES, Pid};
+use crate::sched;
+use kernel_sync::IrqGuard;
+use log::warn;
+use stdlib::syscall_abi::caps::{self, EFFECTIVE, INHERITABLE};
+
+/// The capability sets of a process; see the [module docs](self).
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Capabilities {
+    effective: u64,
+    inheritable: u64,
+}
+
+impl Capabilities {
+    /// Every capability, in both sets.
+    pub const ALL: Self = Self {
+        effective: caps::ALL,
+        inheritable: caps::ALL,
+    };
+
+    /// No capabilities at all.
+    pub const NONE: Self = Self {
+        effective: 0,
+        inheritable: 0,
+    };
+
+    /// The capabilities the kernel checks.
+    #[must_use]
+    pub const fn effective(self) -> u64 {
+        self.effective
+    }
+
+    /// The capabilities spawned children get.
+    #[must_use]
+    pub const fn inheritable(self) -> u64 {
+        self.inheritable
+    }
+
+    /// Whether all of `caps` are effective.
+    #[must_use]
+    pub const fn has(self, caps: u64) -> bool {
+        self.effective & caps == caps
+    }
+
+    /// The capabilities of a child spawned by a process holding these.
+    #[must_use]
+    pub const fn spawned(self) -> Self {
+        Self {
+            effective: self.inheritable,
+            inheritable: self.inheritable,
+        }
+    }
+
+    /// These capabilities with the set `which` replaced by `caps`, or `None`
+    /// if `which` names no set or `caps` is not a subset of the effective
+    /// ones. Shrinking the effective set shrinks the inheritable one along.
+    #[must_use]
+    pub const fn with(self, which: u64, caps: u64) -> Option<Self> {
+        if !self.has(caps) {
+            return None;
+        }
+        match which {
+            EFFECTIVE => Some(Self {
+                effective: caps,
+                inheritable: self.inheritable & caps,
+            }),
+            INHERITABLE => Some(Self {
+                effective: self.effective,
+                inheritable: caps,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// The capabilities of the current process; none outside of one.
+pub fn current() -> Capabilities {
+    let Some(me) = sched::current_pid() else {
+        return Capabilities::NONE;
+    };
+    let _irq = IrqGuard::new();
+    let table = PROCESSES.lock();
+    table
+        .find(me)
+        .and_then(|slot| table.get(slot))
+        .map_or(Capabilities::NONE, |process| process.caps)
+}
+
+/// Whether the current process holds all of `caps`, to do `what`; a refusal
+/// is logged.
+pub fn require(caps: u64, what: &str) -> bool {
+    let held = current();
+    if held.has(caps) {
+        return true;
+    }
+    if let Some(pid) = sched::current_pid() {
+        warn!(
+            "Process {pid}: {what} refused; lacks capabilities {missing:#x}",
+            missing = caps & !held.effective()
+        );
+    }
+    false
+}
+
+/// Whether the current process may act on `target` to do `what`: on itself
+/// and its children always, on other processes only with `CAP_PROCESS`. A
+/// refusal is logged.
+pub fn may_control(target: Pid, what: &str) -> bool {
+    let Some(me) = sched::current_pid() else {
+        return require(caps::CAP_PROCESS, what);
+    };
+    let own_child = || parent_of(target).is_some_and(|parent| parent == me);
+    me == target || own_child() || require(caps::CAP_PROCESS, what)
+}
+
+/// Whether the current process may send a signal to `target`: as for
+/// [`may_control`], and to its own parent too, as the shell asks init to
+/// power off.
+pub fn may_signal(target: Pid) -> bool {
+    let Some(me) = sched::current_pid() else {
+        return require(caps::CAP_PROCESS, "kill");
+    };
+    parent_of(me) == Some(target) || may_control(target, "kill")
+}
+
+/// The parent of the process `pid`, if it is alive and has one.
+fn parent_of(pid: Pid) -> Option<Pid> {
+    let _irq = IrqGuard::new();
+    let table = PROCESSES.lock();
+    table
+        .find(pid)
+        .and_then(|slot| table.get(slot))
+        .and_then(|process| process.parent)
+}
+
+/// Replace the set `which` of the current process with `caps`; returns the
+/// previous set, `None` outside of a process or if [`Capabilities::with`]
+/// refuses.
+pub fn set(which: u64, caps: u64) -> Option<u64> {
+    let me = sched::current_pid()?;
+    let _irq = IrqGuard::new();
+    let mut table = PROCESSES.lock();
+    let slot = table.find(me)?;
+    let process = table.get_mut(slot)?;
+    let old = process.caps;
+    process.caps = old.with(which, caps)?;
+    Some(if which == EFFECTIVE {
+        old.effective
+    } else {
+        old.inheritable
+    })
+}
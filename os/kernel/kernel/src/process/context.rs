@@ -0,0 +1,132 @@
+//! Kernel-mode context switching.
+//!
+//! A process that is not running is parked **inside the kernel**, on its own
+//! kernel stack, at the point where it called into the scheduler. Its saved
+//! [`Context`] is just the stack pointer; the callee-saved registers live on
+//! the stack itself:
+//!
+//! ```text
+//!   higher addresses
+//!   ┌──────────────────────┐
+//!   │ return address       │ ← where switch_context() "returns" to
+//!   │ rbp                  │
+//!   │ rbx                  │
+//!   │ r12                  │
+//!   │ r13                  │
+//!   │ r14                  │
+//!   │ r15                  │ ← Context::rsp
+//!   └──────────────────────┘
+//! ```
+//!
+//! Caller-saved registers need no treatment because [`switch_context`] is an
+//! ordinary `extern "C"` call from the compiler's point of view.
+//!
+//! A freshly created process gets a synthetic frame (see [`initial_context`])
+//! whose return address points at its start routine. A forked child instead
+//! gets a copy of its parent's [`SyscallFrame`] and "returns" straight into
+//! the `syscall` exit path (see [`fork_context`]).
+
+use crate::syscall::entry::{FRAME_OFFSET, SyscallFrame, syscall_return};
+use core::arch::naked_asm;
+use kernel_memory_addresses::VirtualAddress;
+
+/// Saved kernel execution context of a process that is not running.
+#[derive(Debug, Default, Copy, Clone)]
+#[repr(C)]
+pub struct Context {
+    /// Kernel stack pointer at the time of the switch.
+    pub rsp: u64,
+}
+
+/// Number of callee-saved registers pushed by [`switch_context`].
+const SAVED_REGS: usize = 6;
+
+/// Save the current context into `*prev_rsp` and resume the one at `next_rsp`.
+///
+/// Returns when some other context switches back to the saved one.
+///
+/// # Safety
+/// - Interrupts must be disabled.
+/// - `prev_rsp` must be valid for writes until the saved context is resumed.
+/// - `next_rsp` must have been produced by this function or [`initial_context`],
+///   and the stack it points into must be mapped in the active address space.
+#[unsafe(naked)]
+pub unsafe extern "C" fn switch_context(prev_rsp: *mut u64, next_rsp: u64) {
+    naked_asm!(
+        // Park the callee-saved registers on the outgoing stack.
+        "push rbp",
+        "push rbx",
+        "push r12",
+        "push r13",
+        "push r14",
+        "push r15",
+        "mov [rdi], rsp",
+        // Switch stacks and restore the incoming context.
+        "mov rsp, rsi",
+        "pop r15",
+        "pop r14",
+        "pop r13",
+        "pop r12",
+        "pop rbx",
+        "pop rbp",
+        "ret",
+    )
+}
+
+/// Build the initial frame on an unused kernel stack so that the first
+/// [`switch_context`] into it enters `start`.
+///
+/// # Safety
+/// `kstack_top` must be the 16-byte aligned top of a mapped, unused kernel stack.
+pub unsafe fn initial_context(kstack_top: VirtualAddress, start: extern "C" fn() -> !) -> Context {
+    debug_assert!(kstack_top.as_u64().is_multiple_of(16));
+
+    // [top - 8]  : fake return address of `start` (keeps SysV alignment)
+    // [top - 16] : `start`, consumed by `ret`
+    // below      : zeroed callee-saved registers
+    let top = kstack_top.as_u64() as *mut u64;
+    unsafe {
+        let ret_slot = top.sub(2);
+        ret_slot.add(1).write(0);
+        ret_slot.write(start as usize as u64);
+        for i in 1..=SAVED_REGS {
+            ret_slot.sub(i).write(0);
+        }
+
+        Context {
+            rsp: ret_slot.sub(SAVED_REGS) as u64,
+        }
+    }
+}
+
+/// Build the initial frame on an unused kernel stack so that the first
+/// [`switch_context`] into it returns to user mode as if from the system call
+/// described by `frame`.
+///
+/// The frame is placed where the `syscall` stub would have built it, so the
+/// child looks exactly like a process inside that system call.
+///
+/// # Safety
+/// `kstack_top` must be the 16-byte aligned top of a mapped, unused kernel stack.
+pub unsafe fn fork_context(kstack_top: VirtualAddress, frame: &SyscallFrame) -> Context {
+    debug_assert!(kstack_top.as_u64().is_multiple_of(16));
+
+    // [top - FRAME_OFFSET] : the syscall frame
+    // below                : `syscall_return`, consumed by `ret`
+    // below                : zeroed callee-saved registers
+    let top = kstack_top.as_u64();
+    unsafe {
+        let frame_at = (top - FRAME_OFFSET as u64) as *mut SyscallFrame;
+        frame_at.write(frame.clone());
+
+        let ret_slot = frame_at.cast::<u64>().sub(1);
+        ret_slot.write(syscall_return as *const () as u64);
+        for i in 1..=SAVED_REGS {
+            ret_slot.sub(i).write(0);
+        }
+
+        Context {
+            rsp: ret_slot.sub(SAVED_REGS) as u64,
+        }
+    }
+}
@@ -0,0 +1,135 @@
+//! # File-Backed Pages
+//!
+//! A [`VmaKind::File`] area maps part of a file privately: a file mapped
+//! with [`mmap`](super::mmap::mmap), or a read-only segment of the program
+//! image (see [`load_elf`](crate::userland::load_elf)). Nothing is mapped up
+//! front. The first touch of a page maps the frame of the
+//! [page cache](crate::page_cache) holding it, with a reference of its own,
+//! so processes running the same program share its text. This happens
+//!
+//! * on a user page fault, through [`handle_user_fault`], and
+//! * when a system call copies to or from the area (see
+//!   [`uaccess`](crate::uaccess)).
+//!
+//! Pages of writable areas are mapped copy-on-write: the first write copies
+//! the page into a frame of the process' own (see
+//! [`resolve_cow_fault`](crate::alloc::resolve_cow_fault)), and neither the
+//! file nor other mappings see the change.
+//!
+//! ## Limitations
+//!
+//! * Files are entries of the [bundle](crate::bundlefs), the only file
+//!   system with inodes.
+//! * The page fault handler runs on its own stack and must not sleep. It
+//!   does not wait for a page another reader is filling, but returns to the
+//!   faulting instruction, which faults again.
+//! * Touching a page past the end of the file raises `SIGSEGV`; there is no
+//!   `SIGBUS`.
+
+use crate::alloc::{FlushTlb, frame_table, try_with_kernel_vmm, with_kernel_frame_alloc};
+use crate::bundlefs::EntryPages;
+use crate::page_cache::{self, CacheError, PAGE_LEN};
+use crate::process::{PROCESSES, Pid};
+use crate::sched;
+use core::fmt;
+use kernel_alloc::vmm::AllocationTarget;
+use kernel_memory_addresses::{PageSize, Size4K, VirtualAddress};
+use kernel_sync::IrqGuard;
+use kernel_vmem::vma::VmaKind;
+use kernel_vmem::{PhysFrameAlloc, VirtualMemoryPageBits};
+use log::warn;
+
+/// Why a file page could not be mapped.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum FaultError {
+    /// The address is not in a file area of the process.
+    NotFile,
+    /// The page cache could not provide the page.
+    Cache(CacheError),
+    /// Page tables ran out.
+    OutOfMemory,
+}
+
+impl fmt::Display for FaultError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFile => f.write_str("not in a file area"),
+            Self::Cache(e) => write!(f, "{e}"),
+            Self::OutOfMemory => f.write_str("out of memory"),
+        }
+    }
+}
+
+/// Map the page containing `addr` of a file area of `pid`, whose address
+/// space must be the current one. Does nothing if the page is mapped
+/// already. Unless `wait` is set, fails with [`CacheError::Busy`] rather
+/// than wait for another reader filling the page.
+///
+/// # Errors
+/// See [`FaultError`]; nothing is mapped.
+#[allow(clippy::cast_possible_truncation)]
+pub fn fault_in(pid: Pid, addr: VirtualAddress, wait: bool) -> Result<(), FaultError> {
+    let page = VirtualAddress::new(addr.as_u64() & !(Size4K::SIZE - 1));
+    let vma = {
+        let _irq = IrqGuard::new();
+        let table = PROCESSES.lock();
+        table
+            .find(pid)
+            .and_then(|slot| table.get(slot))
+            .and_then(|process| process.vmas.find(page).copied())
+    }
+    .filter(|vma| matches!(vma.kind, VmaKind::File { .. }))
+    .ok_or(FaultError::NotFile)?;
+    let (inode, offset) = vma.file_offset(page).ok_or(FaultError::NotFile)?;
+
+    // Processes are single-threaded, so the area stays while the page is
+    // looked up without the process table locked.
+    let source = EntryPages(inode as usize);
+    let index = offset / PAGE_LEN as u64;
+    let cached = if wait {
+        page_cache::get(&source, index)
+    } else {
+        page_cache::try_get(&source, index)
+    };
+    let frame = cached.map_err(FaultError::Cache)?.into_frame();
+
+    let leaf = VirtualMemoryPageBits::user_leaf_data_wb()
+        .with_writable(false)
+        .with_copy_on_write(vma.perms.write)
+        .with_no_execute(!vma.perms.execute);
+    let mapped = try_with_kernel_vmm(FlushTlb::Always, |vmm| {
+        if vmm.query(page).is_some() {
+            return Ok(false);
+        }
+        let nonleaf = VirtualMemoryPageBits::user_table_wb_exec();
+        vmm.map_one::<Size4K>(AllocationTarget::User, page, frame.base(), nonleaf, leaf)
+            .map(|()| true)
+            .map_err(|_| FaultError::OutOfMemory)
+    });
+
+    // The mapping holds the reference `into_frame` handed over, unless there
+    // is none.
+    if mapped != Ok(true) && frame_table().release(frame) {
+        with_kernel_frame_alloc(|alloc| alloc.free_4k(frame));
+    }
+    mapped.map(|_| ())
+}
+
+/// Resolve a user fault on the non-present page at `addr` by mapping it from
+/// a file area of the current process. Returns `false` if `addr` is not in
+/// one or the page cannot be mapped.
+///
+/// # Panics
+/// If called outside of a process.
+pub fn handle_user_fault(addr: VirtualAddress) -> bool {
+    let me = sched::current_pid().expect("user fault outside of a process");
+    match fault_in(me, addr, false) {
+        // Fault again once the page is filled.
+        Ok(()) | Err(FaultError::Cache(CacheError::Busy)) => true,
+        Err(FaultError::NotFile) => false,
+        Err(e) => {
+            warn!("Process {me}: cannot map the file page at {addr}: {e}");
+            false
+        }
+    }
+}
@@ -0,0 +1,504 @@
+//! # Handles
+//!
+//! Every process has a [`HandleTable`] of up to [`MAX_HANDLES`] kernel
+//! [`Object`]s it holds, indexed by small integers, the handles. New objects
+//! take the lowest free handle. File descriptors and shared memory handles
+//! are both handles into this table; each system call looks its handle up
+//! and refuses objects of the wrong kind.
+//!
+//! Objects are ends of a [pipe](crate::pipe), files of the
+//! [`procfs`](crate::procfs), [character devices](crate::chardev), entries
+//! of the [userland bundle](crate::bundlefs) and
+//! [shared memory objects](crate::shm): [`pipe`] creates a pipe and installs
+//! both ends, [`open`] opens a file by path, [`shm_open`] a shared memory
+//! object by name, [`read`] and [`write`] transfer data, [`control`] passes
+//! requests on to a device, [`lookup`] resolves a handle, e.g. to map its
+//! object, [`dup`] copies a handle, [`close`] drops one and [`poll`] waits
+//! until some of several handles are ready. There is no file system other
+//! than these to open files from yet.
+//!
+//! ## Rights
+//!
+//! Each handle carries rights, the `RIGHT_*` bits of
+//! [`syscall_abi::handle`](stdlib::syscall_abi::handle), saying what may be
+//! done with the object through it: [`read`] needs `RIGHT_READ`, [`write`]
+//! `RIGHT_WRITE` and mapping `RIGHT_MAP`, plus `RIGHT_WRITE` for writable
+//! shared mappings. A new handle gets all rights its kind of object supports
+//! ([`Object::rights`]); [`dup`] makes another handle to the same object with
+//! a subset of them, e.g. a read-only one to pass on. Devices take the
+//! [capabilities](caps) they name once, when opened; from then on the
+//! handle's rights decide.
+//!
+//! A forked or spawned child inherits a copy of its parent's table, with
+//! each object referenced once more (open `procfs` and bundle files get their
+//! own read offset); [`exit`](crate::process::exit) closes all handles still
+//! open. Data is copied between user memory and the object through a small
+//! kernel buffer, never with the process table or a pipe locked. [`read`]
+//! and [`write`] take a [`UserIoVec`], so a vectored transfer fills or drains
+//! its buffers in one pass, as if they were one.
+
+use crate::bundlefs;
+use crate::chardev::{self, CharDevId};
+use crate::pipe::{self, PipeEnd, PipeError, PipeId};
+use crate::poll::{self, Pollable};
+use crate::process::{PROCESSES, Pid, caps};
+use crate::procfs::{self, ProcFile};
+use crate::sched;
+use crate::shm::{self, ShmError, ShmId};
+use crate::uaccess::{UserAccessError, UserIoVec};
+use core::fmt;
+use kernel_sync::IrqGuard;
+use stdlib::syscall_abi::handle::{RIGHT_MAP, RIGHT_READ, RIGHT_WRITE};
+use stdlib::syscall_abi::poll::{POLLIN, POLLOUT};
+use stdlib::syscall_abi::{MAX_POLL_HANDLES, PollFd};
+
+/// Maximum number of handles per process.
+pub const MAX_HANDLES: usize = 24;
+
+/// Bytes moved between user memory and a file per step.
+const CHUNK_LEN: usize = 256;
+
+/// A kernel object held through a handle.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Object {
+    /// The read end of a pipe.
+    PipeRead(PipeId),
+    /// The write end of a pipe.
+    PipeWrite(PipeId),
+    /// A `procfs` file, read up to `offset`.
+    Proc { file: ProcFile, offset: usize },
+    /// A character device under `/dev`.
+    Char(CharDevId),
+    /// An entry of the userland bundle, read up to `offset`.
+    Bundle { entry: usize, offset: usize },
+    /// A shared memory object.
+    Shm(ShmId),
+}
+
+impl Object {
+    /// The rights this kind of object supports, and a new handle gets.
+    pub const fn rights(self) -> u64 {
+        match self {
+            Self::PipeRead(_) | Self::Proc { .. } => RIGHT_READ,
+            Self::PipeWrite(_) => RIGHT_WRITE,
+            Self::Bundle { .. } => RIGHT_READ | RIGHT_MAP,
+            Self::Char(_) | Self::Shm(_) => RIGHT_READ | RIGHT_WRITE | RIGHT_MAP,
+        }
+    }
+
+    /// Add a reference to the underlying object, for a copy of this handle.
+    pub fn dup(self) {
+        match self {
+            Self::PipeRead(id) => pipe::dup(id, PipeEnd::Read),
+            Self::PipeWrite(id) => pipe::dup(id, PipeEnd::Write),
+            Self::Shm(id) => shm::dup(id).expect("a handle keeps the object open"),
+            Self::Proc { .. } | Self::Char(_) | Self::Bundle { .. } => {}
+        }
+    }
+
+    /// Drop this handle's reference to the underlying object.
+    pub fn close(self) {
+        match self {
+            Self::PipeRead(id) => pipe::close(id, PipeEnd::Read),
+            Self::PipeWrite(id) => pipe::close(id, PipeEnd::Write),
+            Self::Shm(id) => {
+                let _ = shm::close(id);
+            }
+            Self::Proc { .. } | Self::Char(_) | Self::Bundle { .. } => {}
+        }
+    }
+}
+
+/// Only the events the object's kind supports count: `POLLIN` for the read
+/// end of a pipe and files, `POLLOUT` for the write end, both for devices.
+/// Files are always ready; shared memory never is.
+impl Pollable for Object {
+    fn ready(&self, events: u16) -> u16 {
+        match *self {
+            Self::PipeRead(id) => id.ready(events & POLLIN),
+            Self::PipeWrite(id) => id.ready(events & POLLOUT),
+            Self::Char(id) => id.ready(events),
+            Self::Proc { .. } | Self::Bundle { .. } => events & POLLIN,
+            Self::Shm(_) => 0,
+        }
+    }
+
+    fn register(&self, events: u16, pid: Pid) {
+        match *self {
+            Self::PipeRead(id) => id.register(events & POLLIN, pid),
+            Self::PipeWrite(id) => id.register(events & POLLOUT, pid),
+            Self::Char(id) => id.register(events, pid),
+            Self::Proc { .. } | Self::Bundle { .. } | Self::Shm(_) => {}
+        }
+    }
+
+    fn unregister(&self, events: u16, pid: Pid) {
+        match *self {
+            Self::PipeRead(id) => id.unregister(events & POLLIN, pid),
+            Self::PipeWrite(id) => id.unregister(events & POLLOUT, pid),
+            Self::Char(id) => id.unregister(events, pid),
+            Self::Proc { .. } | Self::Bundle { .. } | Self::Shm(_) => {}
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum HandleError {
+    /// The handle is not open.
+    BadHandle,
+    /// All [`MAX_HANDLES`] handles are in use.
+    TooManyHandles,
+    /// The handle lacks the rights for the operation.
+    MissingRights,
+    /// No file exists at the given path.
+    NotFound,
+    /// A user buffer could not be accessed.
+    Fault,
+    /// The pipe refused the operation.
+    Pipe(PipeError),
+    /// The shared memory object refused the operation.
+    Shm(ShmError),
+    /// The object does not support the request.
+    Unsupported,
+    /// The process lacks the capabilities to open the file.
+    PermissionDenied,
+}
+
+impl From<PipeError> for HandleError {
+    fn from(e: PipeError) -> Self {
+        Self::Pipe(e)
+    }
+}
+
+impl From<ShmError> for HandleError {
+    fn from(e: ShmError) -> Self {
+        Self::Shm(e)
+    }
+}
+
+impl From<UserAccessError> for HandleError {
+    fn from(_: UserAccessError) -> Self {
+        Self::Fault
+    }
+}
+
+impl fmt::Display for HandleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadHandle => f.write_str("bad handle"),
+            Self::TooManyHandles => f.write_str("too many open handles"),
+            Self::MissingRights => f.write_str("handle lacks the rights"),
+            Self::NotFound => f.write_str("no such file"),
+            Self::Fault => f.write_str("bad user buffer"),
+            Self::Pipe(e) => write!(f, "{e}"),
+            Self::Shm(e) => write!(f, "{e}"),
+            Self::Unsupported => f.write_str("request not supported"),
+            Self::PermissionDenied => f.write_str("permission denied"),
+        }
+    }
+}
+
+/// An open handle: the object and what may be done with it.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Entry {
+    pub object: Object,
+    /// The `RIGHT_*` bits granted, a subset of [`Object::rights`].
+    pub rights: u64,
+}
+
+/// The handles of one process.
+#[derive(Debug, Clone, Default)]
+pub struct HandleTable([Option<Entry>; MAX_HANDLES]);
+
+impl HandleTable {
+    pub const fn new() -> Self {
+        Self([None; MAX_HANDLES])
+    }
+
+    /// The entry open as `handle`.
+    pub fn get(&self, handle: usize) -> Option<Entry> {
+        self.0.get(handle).copied().flatten()
+    }
+
+    /// Install `object` with those of `rights` it supports at the lowest
+    /// free handle and return it.
+    pub fn insert(&mut self, object: Object, rights: u64) -> Result<usize, HandleError> {
+        let handle = self
+            .0
+            .iter()
+            .position(Option::is_none)
+            .ok_or(HandleError::TooManyHandles)?;
+        self.0[handle] = Some(Entry {
+            object,
+            rights: rights & object.rights(),
+        });
+        Ok(handle)
+    }
+
+    /// Replace the object open as `handle`, if any, with `object`, keeping
+    /// the rights.
+    fn replace(&mut self, handle: usize, object: Object) {
+        if let Some(Some(open)) = self.0.get_mut(handle) {
+            open.object = object;
+        }
+    }
+
+    /// Remove and return the object open as `handle`.
+    pub fn take(&mut self, handle: usize) -> Option<Object> {
+        self.0.get_mut(handle)?.take().map(|entry| entry.object)
+    }
+
+    /// The held objects.
+    pub fn iter(&self) -> impl Iterator<Item = Object> + '_ {
+        self.0.iter().flatten().map(|entry| entry.object)
+    }
+}
+
+/// Run `f` on the handle table of the current process.
+fn with_handles<R>(f: impl FnOnce(&mut HandleTable) -> R) -> R {
+    let me = sched::current_pid().expect("handle operation outside of a process");
+    let _irq = IrqGuard::new();
+    let mut table = PROCESSES.lock();
+    let slot = table.find(me).expect("calling process not in table");
+    let process = table.get_mut(slot).expect("calling process not in table");
+    f(&mut process.handles)
+}
+
+/// Install `object` with all its rights, or drop it if the table is full.
+fn install(object: Object) -> Result<usize, HandleError> {
+    with_handles(|handles| handles.insert(object, object.rights())).inspect_err(|_| object.close())
+}
+
+/// The object open as `handle`, if the handle grants all of `rights`.
+///
+/// # Panics
+/// If called outside of a process.
+pub fn lookup(handle: usize, rights: u64) -> Result<Object, HandleError> {
+    let entry = with_handles(|handles| handles.get(handle)).ok_or(HandleError::BadHandle)?;
+    if entry.rights & rights != rights {
+        return Err(HandleError::MissingRights);
+    }
+    Ok(entry.object)
+}
+
+/// Create a pipe and return the handles of its read and write ends.
+///
+/// # Panics
+/// If called outside of a process.
+pub fn pipe() -> Result<[usize; 2], HandleError> {
+    let id = pipe::create()?;
+    let read = Object::PipeRead(id);
+    let write = Object::PipeWrite(id);
+    let handles = with_handles(|handles| {
+        let r = handles.insert(read, read.rights())?;
+        match handles.insert(write, write.rights()) {
+            Ok(w) => Ok([r, w]),
+            Err(e) => {
+                handles.take(r);
+                Err(e)
+            }
+        }
+    });
+
+    if handles.is_err() {
+        read.close();
+        write.close();
+    }
+    handles
+}
+
+/// Open the file at `path` and return its handle. Devices take the
+/// [capabilities](caps) they name.
+///
+/// # Panics
+/// If called outside of a process.
+pub fn open(path: &str) -> Result<usize, HandleError> {
+    let object = procfs::lookup(path)
+        .map(|file| Object::Proc { file, offset: 0 })
+        .or_else(|| chardev::lookup(path).map(Object::Char))
+        .or_else(|| bundlefs::find(path).map(|entry| Object::Bundle { entry, offset: 0 }))
+        .ok_or(HandleError::NotFound)?;
+    let needed = match object {
+        Object::Char(id) => chardev::get(id).capabilities(),
+        _ => 0,
+    };
+    if !caps::require(needed, "opening a device") {
+        return Err(HandleError::PermissionDenied);
+    }
+    install(object)
+}
+
+/// Open the shared memory object `name`, creating it with `size` bytes if
+/// needed (see [`shm::open`]), and return its handle.
+///
+/// # Panics
+/// If called outside of a process.
+pub fn shm_open(name: &[u8], size: u64) -> Result<usize, HandleError> {
+    install(Object::Shm(shm::open(name, size)?))
+}
+
+/// Open another handle to the object of `handle`, granting `rights`, which
+/// `handle` must hold; returns the new handle.
+///
+/// # Panics
+/// If called outside of a process.
+pub fn dup(handle: usize, rights: u64) -> Result<usize, HandleError> {
+    let (object, copy) = with_handles(|handles| {
+        let entry = handles.get(handle).ok_or(HandleError::BadHandle)?;
+        if entry.rights & rights != rights {
+            return Err(HandleError::MissingRights);
+        }
+        entry.object.dup();
+        Ok((entry.object, handles.insert(entry.object, rights)))
+    })?;
+    copy.inspect_err(|_| object.close())
+}
+
+/// Close `handle` of the current process.
+///
+/// # Panics
+/// If called outside of a process.
+pub fn close(handle: usize) -> Result<(), HandleError> {
+    let object = with_handles(|handles| handles.take(handle)).ok_or(HandleError::BadHandle)?;
+    object.close();
+    Ok(())
+}
+
+/// Read up to `buf.len()` bytes from `handle` into the user buffers `buf`,
+/// blocking until some are available; returns how many (`0` at end of file).
+///
+/// # Panics
+/// If called outside of a process.
+pub fn read(handle: usize, buf: &UserIoVec) -> Result<usize, HandleError> {
+    let buf = buf.sub(0, CHUNK_LEN);
+    let len = buf.len();
+    let mut chunk = [0u8; CHUNK_LEN];
+    match lookup(handle, RIGHT_READ)? {
+        Object::PipeRead(id) => {
+            // Fail before consuming data that could not be handed out.
+            buf.check_writable()?;
+            let n = pipe::read(id, &mut chunk[..len]);
+            buf.write(&chunk[..n])?;
+            Ok(n)
+        }
+        Object::Proc { file, offset } => {
+            let n = procfs::read(file, offset, &mut chunk[..len]);
+            buf.write(&chunk[..n])?;
+            let offset = offset + n;
+            with_handles(|handles| handles.replace(handle, Object::Proc { file, offset }));
+            Ok(n)
+        }
+        Object::Char(id) => {
+            buf.check_writable()?;
+            let n = chardev::read(id, &mut chunk[..len]);
+            buf.write(&chunk[..n])?;
+            Ok(n)
+        }
+        Object::Bundle { entry, offset } => {
+            let bytes = bundlefs::entry(entry).map_or(&[][..], |(_name, bytes)| bytes);
+            let rest = bytes.get(offset..).unwrap_or_default();
+            let n = rest.len().min(len);
+            buf.write(&rest[..n])?;
+            let offset = offset + n;
+            with_handles(|handles| handles.replace(handle, Object::Bundle { entry, offset }));
+            Ok(n)
+        }
+        Object::PipeWrite(_) | Object::Shm(_) => Err(HandleError::Unsupported),
+    }
+}
+
+/// Write the bytes of the user buffers `buf` to `handle`, blocking while the
+/// object cannot take them; returns how many were written.
+///
+/// # Panics
+/// If called outside of a process.
+pub fn write(handle: usize, buf: &UserIoVec) -> Result<usize, HandleError> {
+    let sink = match lookup(handle, RIGHT_WRITE)? {
+        Object::PipeWrite(id) => Sink::Pipe(id),
+        Object::Char(id) => Sink::Char(id),
+        _ => return Err(HandleError::Unsupported),
+    };
+
+    let mut chunk = [0u8; CHUNK_LEN];
+    let mut written = 0;
+    while written < buf.len() {
+        let part = buf.sub(written, CHUNK_LEN);
+        let n = part.len();
+        part.read_into(&mut chunk[..n])?;
+
+        match sink.write(&chunk[..n]) {
+            Ok(done) => {
+                written += done;
+                if done < n {
+                    break;
+                }
+            }
+            Err(_) if written > 0 => break,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(written)
+}
+
+/// Apply the device-specific `request` with `arg` to `handle`; returns the
+/// result.
+///
+/// # Panics
+/// If called outside of a process.
+pub fn control(handle: usize, request: u64, arg: u64) -> Result<u64, HandleError> {
+    match lookup(handle, 0)? {
+        Object::Char(id) => chardev::control(id, request, arg).ok_or(HandleError::Unsupported),
+        _ => Err(HandleError::Unsupported),
+    }
+}
+
+/// Wait until one of the handles of `fds` is ready for its events, timer
+/// tick `deadline` passes or a signal interrupts the wait (see
+/// [`poll::wait`]); stores the ready events in each `revents` and returns
+/// how many handles are ready.
+///
+/// Waiting for `POLLIN` takes `RIGHT_READ`, for `POLLOUT` `RIGHT_WRITE`.
+///
+/// # Panics
+/// If called outside of a process, or with more than [`MAX_POLL_HANDLES`]
+/// handles.
+pub fn poll(fds: &mut [PollFd], deadline: Option<u64>) -> Result<usize, HandleError> {
+    let mut objects = [(None, 0); MAX_POLL_HANDLES];
+    for (fd, slot) in fds.iter().zip(&mut objects) {
+        let mut rights = 0;
+        if fd.events & POLLIN != 0 {
+            rights |= RIGHT_READ;
+        }
+        if fd.events & POLLOUT != 0 {
+            rights |= RIGHT_WRITE;
+        }
+        let object = lookup(fd.handle as usize, rights)?;
+        if let Object::Shm(_) = object {
+            return Err(HandleError::Unsupported);
+        }
+        *slot = (Some(object), fd.events);
+    }
+
+    let objects = &objects[..fds.len()];
+    let mut revents = [0; MAX_POLL_HANDLES];
+    let ready = poll::wait(objects, &mut revents[..fds.len()], deadline);
+    for (fd, revents) in fds.iter_mut().zip(revents) {
+        fd.revents = revents;
+    }
+    Ok(ready)
+}
+
+/// Where [`write`] puts its bytes.
+enum Sink {
+    Pipe(PipeId),
+    Char(CharDevId),
+}
+
+impl Sink {
+    fn write(&self, bytes: &[u8]) -> Result<usize, HandleError> {
+        match *self {
+            Self::Pipe(id) => Ok(pipe::write(id, bytes)?),
+            Self::Char(id) => Ok(chardev::write(id, bytes)),
+        }
+    }
+}
@@ -0,0 +1,48 @@
+//! Virtual layout for **per-process kernel stacks**.
+//!
+//! Every slot of the [process table](super::ProcessTable) owns a fixed
+//! 64 KiB window inside a dedicated high-half region. Like the
+//! [per-CPU stacks](crate::per_cpu::kernel_stacks), each window starts with an
+//! unmapped 4 KiB **guard page** followed by the mapped stack.
+//!
+//! ```text
+//!  ┌──────────────────────────────┐
+//!  │   next slot …                │
+//!  ├──────────────────────────────┤ ← BASE + (slot+1)*STRIDE
+//!  │   (unused)                   │
+//!  ├──────────────────────────────┤ ← guard + KERNEL_STACK_SIZE
+//!  │   mapped kernel stack        │
+//!  │   (RW | NX | kernel-only)    │
+//!  ├──────────────────────────────┤ ← guard + 4 KiB
+//!  │   4 KiB guard (unmapped)     │
+//!  └──────────────────────────────┘ ← slot base = BASE + slot*STRIDE
+//! ```
+//!
+//! * Stacks are mapped the first time a slot is used and then kept for reuse
+//!   by later processes occupying the same slot.
+//! * The region shares its PML4 entry with the per-CPU stacks, so mappings are
+//!   visible in every address space cloned from the kernel's.
+
+use kernel_info::memory::KERNEL_STACK_SIZE;
+use kernel_memory_addresses::{PageSize, Size4K, VirtualAddress, VirtualPage};
+
+/// Virtual base address of the **process kernel-stack region**.
+pub const PSTACK_BASE: u64 = 0xffff_ff20_0000_0000;
+
+/// Virtual span reserved per process table slot (bytes).
+pub const PSTACK_SLOT_STRIDE: u64 = 0x1_0000; // 64 KiB per slot
+
+const _: () = {
+    // Sanity: guard page + stack must fit within the slot.
+    assert!((KERNEL_STACK_SIZE as u64) + Size4K::SIZE <= PSTACK_SLOT_STRIDE);
+    assert!(PSTACK_SLOT_STRIDE.is_multiple_of(Size4K::SIZE));
+};
+
+/// Return the **guard-page base** of the kernel-stack window for table slot `slot`.
+#[inline]
+pub const fn kstack_slot_for_process(slot: usize) -> VirtualPage<Size4K> {
+    let addr = VirtualAddress::new(PSTACK_BASE + (slot as u64) * PSTACK_SLOT_STRIDE);
+    let page = addr.page();
+    assert!(page.base().as_u64() == addr.as_u64());
+    page
+}
@@ -0,0 +1,130 @@
+//! # Address Space Layout Randomization
+//!
+//! Every process gets its own [`Layout`]: where its user stack ends, where
+//! [`mmap`](super::mmap) starts looking for free ranges, and where a
+//! position-independent program is loaded. [`Layout::choose`] moves each of
+//! them by a random number of pages drawn from the [RNG](crate::random):
+//!
+//! | Part        | Fixed address      | Moved               | Entropy bits                  |
+//! |-------------|--------------------|---------------------|-------------------------------|
+//! | Stack top   | [`USER_STACK_TOP`] | down                | [`STACK_ENTROPY_BITS`] pages  |
+//! | mmap base   | [`MMAP_BASE`]      | up                  | [`MMAP_ENTROPY_BITS`] pages   |
+//! | PIE base    | [`PIE_BASE`]       | up                  | [`PIE_ENTROPY_BITS`] pages    |
+//!
+//! The bounds keep the parts apart: even the lowest stack lies above the
+//! highest PIE base, with room for the program in between, and the mmap area
+//! is far above both. Programs linked at a fixed address (`ET_EXEC`) stay
+//! where they are; only their stack, TLS block and mappings move.
+//!
+//! A forked child keeps its parent's layout, as it keeps its mappings.
+//!
+//! ## Disabling
+//!
+//! `aslr=off` on the [command line](crate::cmdline) gives every process the
+//! [`Layout::FIXED`] one, so that addresses repeat from run to run while
+//! debugging.
+
+use crate::cmdline::{self, Param, ParamKind};
+use crate::process::USER_STACK_TOP;
+use crate::process::mmap::MMAP_BASE;
+use crate::random;
+use crate::userland::PIE_BASE;
+use core::fmt;
+use kernel_memory_addresses::{PageSize, Size4K, VirtualAddress};
+
+pub static ASLR_PARAM: Param = Param {
+    name: "aslr",
+    kind: ParamKind::Bool,
+    help: "Randomize user address space layouts; `aslr=off` disables",
+};
+
+/// Random pages the user stack top is moved down by, as bits (256 MiB).
+pub const STACK_ENTROPY_BITS: u32 = 16;
+
+/// Random pages the mmap base is moved up by, as bits (1 TiB).
+pub const MMAP_ENTROPY_BITS: u32 = 28;
+
+/// Random pages position-independent programs are moved up by, as bits
+/// (256 MiB).
+pub const PIE_ENTROPY_BITS: u32 = 16;
+
+/// Room left between the highest PIE base and the lowest stack reservation.
+const MIN_PIE_ROOM: u64 = 128 << 20;
+
+const _: () = assert!(
+    PIE_BASE.as_u64() + max_offset(PIE_ENTROPY_BITS) + MIN_PIE_ROOM
+        <= USER_STACK_TOP.as_u64()
+            - max_offset(STACK_ENTROPY_BITS)
+            - (super::USER_STACK_PAGES.get() + 1) * Size4K::SIZE
+);
+
+/// Where the randomized parts of a user address space lie.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Layout {
+    /// Top of the user stack.
+    pub stack_top: VirtualAddress,
+    /// Where [`mmap`](super::mmap::mmap) and TLS blocks are placed from.
+    pub mmap_base: VirtualAddress,
+    /// Where position-independent programs are loaded.
+    pub pie_base: VirtualAddress,
+}
+
+impl Layout {
+    /// The layout without randomization.
+    pub const FIXED: Self = Self {
+        stack_top: USER_STACK_TOP,
+        mmap_base: MMAP_BASE,
+        pie_base: PIE_BASE,
+    };
+
+    /// The layout of a new process: random, unless disabled on the command
+    /// line.
+    #[must_use]
+    pub fn choose() -> Self {
+        if enabled() {
+            Self::random()
+        } else {
+            Self::FIXED
+        }
+    }
+
+    /// A random layout within the entropy bounds.
+    #[must_use]
+    pub fn random() -> Self {
+        Self {
+            stack_top: VirtualAddress::new(
+                USER_STACK_TOP.as_u64() - random_offset(STACK_ENTROPY_BITS),
+            ),
+            mmap_base: MMAP_BASE + random_offset(MMAP_ENTROPY_BITS),
+            pie_base: PIE_BASE + random_offset(PIE_ENTROPY_BITS),
+        }
+    }
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "stack top {}, mmap base {}, PIE base {}",
+            self.stack_top, self.mmap_base, self.pie_base
+        )
+    }
+}
+
+/// Whether layouts are randomized; see the [module docs](self).
+#[must_use]
+pub fn enabled() -> bool {
+    cmdline::get_bool(ASLR_PARAM.name).unwrap_or(true)
+}
+
+/// The largest offset with `bits` bits of page-granular entropy.
+const fn max_offset(bits: u32) -> u64 {
+    ((1 << bits) - 1) * Size4K::SIZE
+}
+
+/// A random page-aligned offset with `bits` bits of entropy.
+fn random_offset(bits: u32) -> u64 {
+    let mut bytes = [0u8; 8];
+    random::rand_bytes(&mut bytes);
+    (u64::from_le_bytes(bytes) & ((1 << bits) - 1)) * Size4K::SIZE
+}
@@ -0,0 +1,303 @@
+//! # User Memory Mappings
+//!
+//! [`mmap`] adds a mapping to the address space of the current process. New
+//! mappings are placed at the lowest free range between the process'
+//! randomized [`mmap_base`](super::layout::Layout::mmap_base) and
+//! [`MMAP_END`], or else the lowest one above [`MMAP_BASE`] (see
+//! [`VmaSet::find_gap_from`](kernel_vmem::vma::VmaSet::find_gap_from)), and
+//! recorded in the process' memory map.
+//!
+//! ## Backing
+//!
+//! * [`Backing::Anonymous`] maps fresh, zero-filled frames that belong to the
+//!   process alone ([`VmaKind::Anonymous`]); a fork shares them copy-on-write.
+//!   They are allocated up front, so a mapping may not exceed the free
+//!   frames.
+//! * [`Backing::Shared`] maps the frames of a [shared memory object](crate::shm)
+//!   the process holds a handle to ([`VmaKind::Shared`]). Every mapped frame
+//!   holds a reference; a fork shares them as they are.
+//! * [`Backing::File`] maps the pages of a file privately
+//!   ([`VmaKind::File`]). Nothing is mapped up front; each page is mapped
+//!   from the page cache when first touched (see [`file_pages`](super::file_pages)),
+//!   copy-on-write if the mapping is writable.
+//! * [`Backing::Device`] maps the memory of a [character device](crate::chardev),
+//!   such as the framebuffer's back buffer ([`VmaKind::Device`]). The frames
+//!   stay with the device; a fork maps them as they are.
+//!
+//! Frames of the other kinds are released when the address space is torn
+//! down.
+
+use crate::alloc::{
+    FlushTlb, frame_stats, frame_table, try_with_kernel_vmm, with_kernel_frame_alloc,
+};
+use crate::chardev::{self, CharDevId};
+use crate::process::PROCESSES;
+use crate::sched;
+use crate::shm::{self, MAX_SHM_PAGES, ShmError, ShmId};
+use core::fmt;
+use kernel_alloc::phys_mapper::HhdmPhysMapper;
+use kernel_alloc::vmm::AllocationTarget;
+use kernel_memory_addresses::{PageSize, PhysicalAddress, PhysicalPage, Size4K, VirtualAddress};
+use kernel_sync::IrqGuard;
+use kernel_vmem::vma::{Vma, VmaKind, VmaPerms};
+use kernel_vmem::{PhysFrameAlloc, PhysMapper, VirtualMemoryPageBits};
+use log::debug;
+
+/// Lowest address handed out by [`mmap`], and its base without
+/// randomization.
+pub const MMAP_BASE: VirtualAddress = VirtualAddress::new(0x0000_1000_0000_0000);
+
+/// End of the range used by [`mmap`].
+pub const MMAP_END: VirtualAddress = VirtualAddress::new(0x0000_7000_0000_0000);
+
+/// What a new mapping is backed by.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Backing {
+    /// Fresh, zero-filled frames.
+    Anonymous,
+    /// The pages of the shared memory object `id`, from page `first_page` on;
+    /// the process must hold a [handle](super::handle) to it.
+    Shared { id: ShmId, first_page: usize },
+    /// The pages of the file `inode`, from the page aligned `offset` on.
+    File { inode: u64, offset: u64 },
+    /// The memory of the device `id`, from the page aligned `offset` on.
+    Device { id: CharDevId, offset: u64 },
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum MmapError {
+    /// The length is zero or too large.
+    InvalidLength,
+    /// No free range of the requested size is left.
+    NoSpace,
+    /// The memory map of the process is full.
+    TooManyAreas,
+    /// Frames or page tables ran out.
+    OutOfMemory,
+    /// The shared memory object refused the mapping.
+    Shm(ShmError),
+    /// The device has no memory to map in the requested range.
+    NoDeviceMemory,
+}
+
+impl From<ShmError> for MmapError {
+    fn from(e: ShmError) -> Self {
+        Self::Shm(e)
+    }
+}
+
+impl fmt::Display for MmapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidLength => f.write_str("invalid mapping length"),
+            Self::NoSpace => f.write_str("no free address range"),
+            Self::TooManyAreas => f.write_str("too many mappings"),
+            Self::OutOfMemory => f.write_str("out of memory"),
+            Self::Shm(e) => write!(f, "{e}"),
+            Self::NoDeviceMemory => f.write_str("no device memory to map"),
+        }
+    }
+}
+
+/// Map `len` bytes (rounded up to pages) backed by `backing` into the current
+/// process with `perms`, and return the address of the mapping.
+///
+/// The area is reserved in the memory map with the process table locked;
+/// its pages are mapped afterwards, as processes are single-threaded and
+/// nothing else changes the map meanwhile. An anonymous mapping may not ask
+/// for more frames than are free.
+///
+/// # Panics
+/// If called outside of a process.
+pub fn mmap(len: u64, perms: VmaPerms, backing: Backing) -> Result<VirtualAddress, MmapError> {
+    let me = sched::current_pid().expect("mmap called outside of a process");
+    let len = len
+        .checked_next_multiple_of(Size4K::SIZE)
+        .filter(|&len| len > 0)
+        .ok_or(MmapError::InvalidLength)?;
+    if backing == Backing::Anonymous && len / Size4K::SIZE > frame_stats().free as u64 {
+        return Err(MmapError::OutOfMemory);
+    }
+
+    let kind = match backing {
+        Backing::Anonymous => VmaKind::Anonymous,
+        Backing::Shared { .. } => VmaKind::Shared,
+        Backing::File { inode, offset } => VmaKind::File { inode, offset },
+        Backing::Device { .. } => VmaKind::Device,
+    };
+    let start = {
+        let _irq = IrqGuard::new();
+        let mut table = PROCESSES.lock();
+        let slot = table.find(me).expect("mapping process not in table");
+        let process = table.get_mut(slot).expect("mapping process not in table");
+        let start = process
+            .vmas
+            .find_gap_from(len, process.layout.mmap_base, MMAP_BASE, MMAP_END)
+            .ok_or(MmapError::NoSpace)?;
+        process
+            .vmas
+            .insert(Vma::new(start, start + len, kind, perms))
+            .map_err(|_| MmapError::TooManyAreas)?;
+        start
+    };
+    let end = start + len;
+
+    let leaf = VirtualMemoryPageBits::user_leaf_data_wb()
+        .with_writable(perms.write)
+        .with_no_execute(!perms.execute);
+    let mapped = match backing {
+        Backing::Anonymous => map_anonymous(start, len, leaf),
+        Backing::Shared { id, first_page } => map_shared(start, len, leaf, id, first_page),
+        // Mapped page by page as they are touched.
+        Backing::File { .. } => Ok(()),
+        Backing::Device { id, offset } => map_device(start, len, leaf, id, offset),
+    };
+    if let Err(e) = mapped {
+        let _irq = IrqGuard::new();
+        let mut table = PROCESSES.lock();
+        let slot = table.find(me).expect("mapping process not in table");
+        let process = table.get_mut(slot).expect("mapping process not in table");
+        process
+            .vmas
+            .remove(start, end)
+            .expect("removing a whole area never splits");
+        return Err(e);
+    }
+
+    debug!(
+        "Process {me}: mapped {len:#x} bytes ({kind}, {perms}) at {start}",
+        kind = kind.name()
+    );
+    Ok(start)
+}
+
+/// Pages [`map_anonymous`] maps and zeroes per lock of the kernel's address
+/// space, so a large mapping does not hold it for long.
+const ANON_CHUNK_PAGES: u64 = 64;
+
+/// Map fresh, zeroed frames at `[start, start + len)`, [`ANON_CHUNK_PAGES`]
+/// at a time.
+#[allow(clippy::cast_possible_truncation)]
+pub fn map_anonymous(
+    start: VirtualAddress,
+    len: u64,
+    leaf: VirtualMemoryPageBits,
+) -> Result<(), MmapError> {
+    let mut done = 0;
+    while done < len {
+        let chunk = (len - done).min(ANON_CHUNK_PAGES * Size4K::SIZE);
+        let mapped = try_with_kernel_vmm(FlushTlb::Always, |vmm| {
+            vmm.map_anon_4k_pages(
+                AllocationTarget::User,
+                start + done,
+                0,
+                chunk,
+                VirtualMemoryPageBits::user_table_wb_exec(),
+                leaf,
+            )
+            .map_err(|_| MmapError::OutOfMemory)?;
+            for i in 0..chunk / Size4K::SIZE {
+                let pa = vmm
+                    .query(start + done + i * Size4K::SIZE)
+                    .expect("page just mapped");
+                unsafe {
+                    HhdmPhysMapper
+                        .phys_to_mut::<[u8; Size4K::SIZE as usize]>(pa)
+                        .fill(0);
+                }
+            }
+            Ok(())
+        });
+        if let Err(e) = mapped {
+            let _ = try_with_kernel_vmm(FlushTlb::Always, |vmm| {
+                vmm.unmap_4k_pages_release(start, done + chunk, |_| true);
+                Ok::<_, ()>(())
+            });
+            return Err(e);
+        }
+        done += chunk;
+    }
+    Ok(())
+}
+
+/// Map the pages of `id` from `first_page` on at `[start, start + len)`.
+#[allow(clippy::cast_possible_truncation)]
+fn map_shared(
+    start: VirtualAddress,
+    len: u64,
+    leaf: VirtualMemoryPageBits,
+    id: ShmId,
+    first_page: usize,
+) -> Result<(), MmapError> {
+    let pages = (len / Size4K::SIZE) as usize;
+    if pages > MAX_SHM_PAGES {
+        return Err(MmapError::Shm(ShmError::OutOfRange));
+    }
+
+    let mut frames = [PhysicalPage::from_addr(PhysicalAddress::new(0)); MAX_SHM_PAGES];
+    let frames = &mut frames[..pages];
+    shm::share_frames(id, first_page, frames)?;
+
+    let mapped = try_with_kernel_vmm(FlushTlb::Always, |vmm| {
+        for (i, frame) in frames.iter().enumerate() {
+            let va = start + i as u64 * Size4K::SIZE;
+            let nonleaf = VirtualMemoryPageBits::user_table_wb_exec();
+            if vmm
+                .map_one::<Size4K>(AllocationTarget::User, va, frame.base(), nonleaf, leaf)
+                .is_err()
+            {
+                vmm.unmap_4k_pages_release(start, len, |frame| frame_table().release(frame));
+                return Err(i);
+            }
+        }
+        Ok(())
+    });
+
+    // Drop the references of the frames that did not get mapped.
+    if let Err(failed) = mapped {
+        with_kernel_frame_alloc(|alloc| {
+            for &frame in &frames[failed..] {
+                if frame_table().release(frame) {
+                    alloc.free_4k(frame);
+                }
+            }
+        });
+        return Err(MmapError::OutOfMemory);
+    }
+    Ok(())
+}
+
+/// Map the memory of the device `id` from `offset` on at `[start, start + len)`,
+/// page by page: the device looks up its frames with the kernel's address
+/// space locked.
+fn map_device(
+    start: VirtualAddress,
+    len: u64,
+    leaf: VirtualMemoryPageBits,
+    id: CharDevId,
+    offset: u64,
+) -> Result<(), MmapError> {
+    let unmap = |mapped| {
+        try_with_kernel_vmm(FlushTlb::Always, |vmm| {
+            vmm.unmap_4k_pages_release(start, mapped, |_| false);
+            Ok::<_, ()>(())
+        })
+    };
+    for i in 0..len / Size4K::SIZE {
+        let page = i * Size4K::SIZE;
+        let Some(pa) = chardev::memory_page(id, offset + page) else {
+            let _ = unmap(page);
+            return Err(MmapError::NoDeviceMemory);
+        };
+        // The range was free, so no stale translations need flushing.
+        let mapped = try_with_kernel_vmm(FlushTlb::Never, |vmm| {
+            let nonleaf = VirtualMemoryPageBits::user_table_wb_exec();
+            vmm.map_one::<Size4K>(AllocationTarget::User, start + page, pa, nonleaf, leaf)
+        });
+        if mapped.is_err() {
+            let _ = unmap(page);
+            return Err(MmapError::OutOfMemory);
+        }
+    }
+    Ok(())
+}
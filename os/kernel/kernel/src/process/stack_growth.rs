@@ -0,0 +1,132 @@
+//! # User Stack Growth
+//!
+//! A process' user stack is a [`VmaKind::Stack`] area reserving
+//! [`USER_STACK_PAGES`] pages below the randomized
+//! [`stack_top`](super::layout::Layout::stack_top) of the process, with a
+//! guard page below it. Only the top [`USER_STACK_INITIAL_PAGES`] are mapped
+//! at spawn; the process records the lowest mapped address as its
+//! [`stack_low`](super::Process::stack_low).
+//!
+//! Touching a page of the area below `stack_low` maps fresh, zeroed pages
+//! from that page up to `stack_low`, so the mapped part of the stack stays
+//! contiguous. This happens
+//!
+//! * on a user page fault, through [`handle_user_fault`], and
+//! * when a system call copies to or from the area (see
+//!   [`uaccess`](crate::uaccess)).
+//!
+//! ## Limit
+//!
+//! The stack may grow to `stack_limit` KiB (see the
+//! [command line](crate::cmdline)), by default and at most the whole
+//! reservation. A fault that would grow it further kills the process with
+//! `SIGSEGV`, and a system call touching such a page fails.
+
+use crate::cmdline::{self, Param, ParamKind};
+use crate::process::mmap::map_anonymous;
+use crate::process::{PROCESSES, Pid, USER_STACK_INITIAL_PAGES, USER_STACK_PAGES};
+use crate::{sched, signal};
+use core::fmt;
+use kernel_memory_addresses::{PageSize, Size4K, VirtualAddress};
+use kernel_sync::IrqGuard;
+use kernel_vmem::VirtualMemoryPageBits;
+use kernel_vmem::vma::VmaKind;
+use log::{debug, error};
+use stdlib::syscall_abi::signal::SIGSEGV;
+
+pub static STACK_LIMIT_PARAM: Param = Param {
+    name: "stack_limit",
+    kind: ParamKind::U64,
+    help: "Largest user stack in KiB; at most the 8 MiB reserved",
+};
+
+/// Why the stack could not grow to an address.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum GrowError {
+    /// The address is not in the process' stack area.
+    NotStack,
+    /// The stack would grow to `size` bytes, more than its `limit`.
+    Limit { size: u64, limit: u64 },
+    /// Frames or page tables ran out.
+    OutOfMemory,
+}
+
+impl fmt::Display for GrowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotStack => f.write_str("not in the stack area"),
+            Self::Limit { size, limit } => write!(
+                f,
+                "the stack would grow to {} KiB, beyond its limit of {} KiB",
+                size / 1024,
+                limit / 1024
+            ),
+            Self::OutOfMemory => f.write_str("out of memory"),
+        }
+    }
+}
+
+/// Largest size of a user stack, in bytes.
+pub fn limit() -> u64 {
+    let reserved = USER_STACK_PAGES.get() * Size4K::SIZE;
+    let initial = USER_STACK_INITIAL_PAGES.get() * Size4K::SIZE;
+    cmdline::get_u64(STACK_LIMIT_PARAM.name)
+        .map_or(reserved, |kib| kib.saturating_mul(1024))
+        .clamp(initial, reserved)
+}
+
+/// Grow the stack of `pid`, whose address space must be the current one,
+/// down to the page containing `addr`. Does nothing if that page is mapped
+/// already.
+///
+/// # Errors
+/// See [`GrowError`]; the stack is left as it was.
+pub fn grow(pid: Pid, addr: VirtualAddress) -> Result<(), GrowError> {
+    let page = VirtualAddress::new(addr.as_u64() & !(Size4K::SIZE - 1));
+
+    let _irq = IrqGuard::new();
+    let mut table = PROCESSES.lock();
+    let process = table
+        .find(pid)
+        .and_then(|slot| table.get_mut(slot))
+        .ok_or(GrowError::NotStack)?;
+    process
+        .vmas
+        .find(page)
+        .filter(|vma| vma.kind == VmaKind::Stack)
+        .ok_or(GrowError::NotStack)?;
+    let low = process.stack_low;
+    if page >= low {
+        return Ok(());
+    }
+
+    let size = process.layout.stack_top.as_u64() - page.as_u64();
+    let limit = limit();
+    if size > limit {
+        return Err(GrowError::Limit { size, limit });
+    }
+
+    let leaf = VirtualMemoryPageBits::user_leaf_data_wb();
+    map_anonymous(page, low.as_u64() - page.as_u64(), leaf).map_err(|_| GrowError::OutOfMemory)?;
+    process.stack_low = page;
+    debug!("Process {pid}: stack grew to {} KiB", size / 1024);
+    Ok(())
+}
+
+/// Resolve a user fault on the non-present page at `addr` by growing the
+/// current process' stack. Returns `false` if `addr` is not in its stack
+/// area or memory ran out; kills the process if the stack hit its limit.
+///
+/// # Panics
+/// If called outside of a process.
+pub fn handle_user_fault(addr: VirtualAddress) -> bool {
+    let me = sched::current_pid().expect("user fault outside of a process");
+    match grow(me, addr) {
+        Ok(()) => true,
+        Err(e @ GrowError::Limit { .. }) => {
+            error!("Process {me}: stack overflow at {addr}: {e}");
+            signal::kill_current(SIGSEGV)
+        }
+        Err(_) => false,
+    }
+}
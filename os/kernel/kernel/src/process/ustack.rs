@@ -0,0 +1,148 @@
+//! # Initial User Stack
+//!
+//! Builds the System V style initial stack a new process finds at `rsp` when
+//! it enters user mode:
+//!
+//! ```text
+//!   USER_STACK_TOP ─► ┌──────────────────────────────┐
+//!                     │ argv / envp strings (NUL-    │
+//!                     │ terminated, back to back)    │
+//!                     │ 16 random bytes (AT_RANDOM)  │
+//!                     ├──────────────────────────────┤ ◄─ 16-byte aligned
+//!                     │ (padding)                    │
+//!                     │ auxv: (key, value) pairs,    │
+//!                     │       terminated by AT_NULL  │
+//!                     │ NULL                         │
+//!                     │ envp[envc - 1] … envp[0]     │
+//!                     │ NULL                         │
+//!                     │ argv[argc - 1] … argv[0]     │
+//!   initial rsp ────► │ argc                         │ ◄─ 16-byte aligned
+//!                     └──────────────────────────────┘
+//! ```
+//!
+//! The auxiliary vector describes the loaded program: its entry point and
+//! program headers (`AT_ENTRY`, `AT_PHDR`, `AT_PHENT`, `AT_PHNUM`), the page
+//! size and the `AT_RANDOM` bytes.
+//!
+//! The stack is written through the kernel VMM while the process' address
+//! space is active; see [`stdlib::startup`] for the user side of the ABI.
+
+use crate::alloc::KernelVmm;
+use crate::elf::PHENT_SIZE;
+use crate::process::ArgBuf;
+use crate::random;
+use crate::userland::LoadedImage;
+use kernel_alloc::vmm::VmmError;
+use kernel_memory_addresses::{PageSize, Size4K, VirtualAddress};
+use stdlib::syscall_abi::{MAX_SPAWN_ARGS, auxv};
+
+/// Downward-growing writer over a mapped user stack.
+struct StackWriter<'a, 'alloc> {
+    vmm: &'a mut KernelVmm<'alloc>,
+    sp: u64,
+}
+
+impl StackWriter<'_, '_> {
+    /// Push `bytes` and return the user address they were written to.
+    fn push_bytes(&mut self, bytes: &[u8]) -> Result<u64, VmmError> {
+        self.sp = self
+            .sp
+            .checked_sub(bytes.len() as u64)
+            .ok_or(VmmError::InvalidRange)?;
+        unsafe {
+            self.vmm
+                .copy_to_mapped_user(VirtualAddress::new(self.sp), bytes)?;
+        }
+        Ok(self.sp)
+    }
+
+    /// Push `s` followed by a NUL terminator; returns the address of `s`.
+    fn push_c_str(&mut self, s: &[u8]) -> Result<u64, VmmError> {
+        self.push_bytes(&[0])?;
+        self.push_bytes(s)
+    }
+
+    fn push_u64(&mut self, value: u64) -> Result<(), VmmError> {
+        self.push_bytes(&value.to_ne_bytes()).map(|_| ())
+    }
+
+    const fn align_down(&mut self, align: u64) {
+        self.sp &= !(align - 1);
+    }
+}
+
+/// Write `args`, `env` and the auxiliary vector describing `image` below its
+/// stack top and return the initial user stack pointer.
+///
+/// # Safety
+/// The target address space must be active, the stack below `stack_top` must
+/// be mapped writable and SMAP must be lifted (see [`SmapGuard`](crate::smap::SmapGuard)).
+pub unsafe fn write_initial_stack(
+    vmm: &mut KernelVmm,
+    image: &LoadedImage,
+    args: &ArgBuf,
+    env: &ArgBuf,
+) -> Result<VirtualAddress, VmmError> {
+    let mut w = StackWriter {
+        vmm,
+        sp: image.stack_top.as_u64(),
+    };
+
+    let mut arg_ptrs = [0u64; MAX_SPAWN_ARGS];
+    for (dst, arg) in arg_ptrs.iter_mut().zip(args.iter()) {
+        *dst = w.push_c_str(arg)?;
+    }
+
+    let mut env_ptrs = [0u64; MAX_SPAWN_ARGS];
+    for (dst, var) in env_ptrs.iter_mut().zip(env.iter()) {
+        *dst = w.push_c_str(var)?;
+    }
+
+    let random = w.push_bytes(&random_bytes())?;
+    w.align_down(16);
+
+    // `AT_PHDR` is left out if the program headers are not loaded.
+    let aux = [
+        image.phdr.map(|phdr| (auxv::AT_PHDR, phdr.as_u64())),
+        Some((auxv::AT_PHENT, PHENT_SIZE)),
+        Some((auxv::AT_PHNUM, u64::from(image.phnum))),
+        Some((auxv::AT_PAGESZ, Size4K::SIZE)),
+        Some((auxv::AT_ENTRY, image.entry.as_u64())),
+        Some((auxv::AT_RANDOM, random)),
+        Some((auxv::AT_NULL, 0)),
+    ];
+    let aux_len = aux.iter().flatten().count();
+
+    // argc + argv + NULL + envp + NULL + auxv pairs
+    let words = 1 + (args.len() + 1) + (env.len() + 1) + 2 * aux_len;
+    if !words.is_multiple_of(2) {
+        w.push_u64(0)?;
+    }
+
+    for &(key, value) in aux.iter().rev().flatten() {
+        w.push_u64(value)?;
+        w.push_u64(key)?;
+    }
+
+    w.push_u64(0)?;
+    for &ptr in env_ptrs[..env.len()].iter().rev() {
+        w.push_u64(ptr)?;
+    }
+
+    w.push_u64(0)?;
+    for &ptr in arg_ptrs[..args.len()].iter().rev() {
+        w.push_u64(ptr)?;
+    }
+
+    w.push_u64(args.len() as u64)?;
+
+    debug_assert_eq!(w.sp % 16, 0, "initial user stack must be 16-byte aligned");
+    Ok(VirtualAddress::new(w.sp))
+}
+
+/// Bytes for `AT_RANDOM`.
+fn random_bytes() -> [u8; 16] {
+    let mut out = [0u8; 16];
+    random::rand_bytes(&mut out);
+    out
+}
@@ -0,0 +1,262 @@
+//! # Process File System
+//!
+//! A synthetic file system mounted at [`MOUNT_POINT`] that exposes kernel
+//! state as text files, for observability without a debugger. Its files are
+//! not stored anywhere: their contents are generated from live kernel state
+//! on every [`read`].
+//!
+//! ## Files
+//!
+//! * `/proc/meminfo`: physical frame allocator statistics, the size of the
+//!   [page cache](crate::page_cache) and the use of the
+//!   [kernel heap](crate::alloc::heap)
+//! * `/proc/cpuinfo`: vendor, model and CPUID feature flags of each CPU, the
+//!   TSC frequency and its source, and the hypervisor
+//! * `/proc/uptime`: seconds since the timer started, and seconds idle
+//! * `/proc/interrupts`: interrupt counts per vector and CPU
+//! * `/proc/<pid>/maps`: the memory map of a process; `self` names the caller
+//!
+//! Files are opened through the [handle table](crate::process::handle)
+//! and read like any other file.
+//!
+//! ## Reading
+//!
+//! A read at offset `n` generates the whole file but keeps only the bytes
+//! from `n` on that fit into the caller's buffer. Files are small, so this
+//! costs little and needs no buffer of its own. Files that change between two
+//! reads of a sequence may come out torn; reading a whole file in one go
+//! gives a consistent snapshot of most of them.
+//!
+//! ## Limitations
+//!
+//! * [Listings](crate::dir) name the files above and `self`, but not the
+//!   directories of the other processes.
+
+use crate::alloc::frame_stats;
+use crate::alloc::heap::HEAP;
+use crate::clock;
+use crate::cpuid::{CpuidRanges, Hypervisor, Leaf01h, Leaf07h};
+use crate::irq_stats;
+use crate::page_cache;
+use crate::per_cpu;
+use crate::process::{PROCESSES, Pid, UserVmas};
+use crate::sched;
+use core::fmt::{self, Write};
+use core::sync::atomic::Ordering;
+use kernel_sync::IrqGuard;
+
+/// Where the file system is mounted.
+pub const MOUNT_POINT: &str = "/proc";
+
+/// A file of the process file system.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ProcFile {
+    MemInfo,
+    CpuInfo,
+    Uptime,
+    Interrupts,
+    /// The memory map of a process.
+    Maps(Pid),
+}
+
+/// Names of the files directly under [`MOUNT_POINT`].
+pub const FILES: [&str; 4] = ["meminfo", "cpuinfo", "uptime", "interrupts"];
+
+/// Names of the files in the directory of a process.
+pub const PROCESS_FILES: [&str; 1] = ["maps"];
+
+/// The file at `path`, if it is one of ours.
+///
+/// `/proc/self` stands for the calling process.
+pub fn lookup(path: &str) -> Option<ProcFile> {
+    let name = path.strip_prefix(MOUNT_POINT)?.strip_prefix('/')?;
+    match name {
+        "meminfo" => Some(ProcFile::MemInfo),
+        "cpuinfo" => Some(ProcFile::CpuInfo),
+        "uptime" => Some(ProcFile::Uptime),
+        "interrupts" => Some(ProcFile::Interrupts),
+        _ => {
+            let pid = name.strip_suffix("/maps")?;
+            let pid = if pid == "self" {
+                sched::current_pid()?
+            } else {
+                Pid::from_raw(pid.parse().ok()?)?
+            };
+            Some(ProcFile::Maps(pid))
+        }
+    }
+}
+
+/// Generate `file` and copy its bytes from `offset` on into `buf`; returns
+/// how many were copied (`0` past the end).
+pub fn read(file: ProcFile, offset: usize, buf: &mut [u8]) -> usize {
+    let mut window = Window {
+        skip: offset,
+        buf,
+        len: 0,
+    };
+    // Only fails once the window is full.
+    let _ = generate(file, &mut window);
+    window.len
+}
+
+/// A [`fmt::Write`] sink keeping the part of the output after the first
+/// `skip` bytes that fits into `buf`.
+struct Window<'a> {
+    skip: usize,
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl Write for Window<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let bytes = s.as_bytes();
+        let skipped = bytes.len().min(self.skip);
+        self.skip -= skipped;
+
+        let bytes = &bytes[skipped..];
+        let n = bytes.len().min(self.buf.len() - self.len);
+        self.buf[self.len..self.len + n].copy_from_slice(&bytes[..n]);
+        self.len += n;
+
+        // Stop generating once there is no more room.
+        if self.len == self.buf.len() {
+            Err(fmt::Error)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Write the whole contents of `file` to `out`.
+pub fn generate(file: ProcFile, out: &mut impl Write) -> fmt::Result {
+    match file {
+        ProcFile::MemInfo => meminfo(out),
+        ProcFile::CpuInfo => cpuinfo(out),
+        ProcFile::Uptime => uptime(out),
+        ProcFile::Interrupts => interrupts(out),
+        ProcFile::Maps(pid) => maps(pid, out),
+    }
+}
+
+fn meminfo(out: &mut impl Write) -> fmt::Result {
+    const KIB_PER_FRAME: usize = 4;
+
+    let stats = frame_stats();
+    writeln!(out, "MemTotal:     {:>10} kB", stats.total * KIB_PER_FRAME)?;
+    writeln!(out, "MemFree:      {:>10} kB", stats.free * KIB_PER_FRAME)?;
+    writeln!(out, "MemUsed:      {:>10} kB", stats.used * KIB_PER_FRAME)?;
+    writeln!(
+        out,
+        "MemMinFree:   {:>10} kB",
+        stats.min_free * KIB_PER_FRAME
+    )?;
+    writeln!(out, "FailedAllocs: {:>10}", stats.failed_allocs)?;
+    let cache = page_cache::stats();
+    writeln!(out, "Cached:       {:>10} kB", cache.pages * KIB_PER_FRAME)?;
+    writeln!(out, "CacheHits:    {:>10}", cache.hits)?;
+    writeln!(out, "CacheMisses:  {:>10}", cache.misses)?;
+    let heap = HEAP.stats();
+    writeln!(out, "HeapSlabs:    {:>10} kB", heap.slab_bytes / 1024)?;
+    writeln!(out, "HeapSmall:    {:>10} kB", heap.small_bytes / 1024)?;
+    writeln!(out, "HeapLarge:    {:>10} kB", heap.large_bytes / 1024)
+}
+
+fn cpuinfo(out: &mut impl Write) -> fmt::Result {
+    // All CPUs are alike; describe the one we run on.
+    let ranges = unsafe { CpuidRanges::read() };
+    let leaf1 = unsafe { Leaf01h::read(&ranges) };
+    let leaf7 = unsafe { Leaf07h::read(&ranges) };
+    let hypervisor = leaf1.and_then(|leaf1| unsafe { Hypervisor::read(&leaf1) });
+
+    for cpu in per_cpu::cpus() {
+        writeln!(out, "processor : {}", cpu.cpu_id)?;
+        writeln!(out, "apicid    : {}", cpu.apic_id)?;
+        writeln!(out, "vendor    : {}", ranges.vendor.as_str())?;
+        if let Some(leaf1) = leaf1 {
+            writeln!(out, "family    : {}", leaf1.family())?;
+            writeln!(out, "model     : {}", leaf1.model())?;
+            writeln!(out, "stepping  : {}", leaf1.stepping())?;
+
+            out.write_str("flags     :")?;
+            let flags = [
+                ("fxsr", leaf1.has_fxsr()),
+                ("pat", leaf1.has_pat()),
+                ("monitor", leaf1.has_monitor()),
+                ("pcid", leaf1.has_pcid()),
+                ("x2apic", leaf1.has_x2apic()),
+                ("xsave", leaf1.has_xsave()),
+                ("avx", leaf1.has_avx()),
+                ("invpcid", leaf7.is_some_and(|leaf7| leaf7.has_invpcid())),
+            ];
+            for (name, _) in flags.iter().filter(|(_, set)| *set) {
+                write!(out, " {name}")?;
+            }
+            out.write_char('\n')?;
+        }
+        if let Some((source, tsc)) = clock::tsc_calibration() {
+            let confidence = source.confidence();
+            writeln!(out, "tsc       : {tsc}, {source} ({confidence})")?;
+        }
+        if let Some(hypervisor) = hypervisor {
+            writeln!(out, "hypervisor: {}", hypervisor.vendor.as_str())?;
+        }
+        writeln!(out, "online    : {}", cpu.hotplug.is_online())?;
+        out.write_char('\n')?;
+    }
+    Ok(())
+}
+
+fn uptime(out: &mut impl Write) -> fmt::Result {
+    let timer_hz = clock::timer_hz().max(1);
+    let ticks = sched::now_ticks();
+    let idle_tsc: u64 = per_cpu::cpus()
+        .map(|cpu| cpu.idle_tsc.load(Ordering::Relaxed))
+        .sum();
+    let idle_centis = idle_tsc / (clock::tsc_hz() / 100).max(1);
+
+    writeln!(
+        out,
+        "{}.{:02} {}.{:02}",
+        ticks / timer_hz,
+        ticks % timer_hz * 100 / timer_hz,
+        idle_centis / 100,
+        idle_centis % 100
+    )
+}
+
+fn interrupts(out: &mut impl Write) -> fmt::Result {
+    out.write_str("    ")?;
+    for cpu in per_cpu::cpus() {
+        // Right-align `CPU<n>` with the counts below.
+        let digits = cpu.cpu_id.checked_ilog10().unwrap_or(0) as usize + 1;
+        write!(out, " {:>width$}{}", "CPU", cpu.cpu_id, width = 10 - digits)?;
+    }
+    out.write_char('\n')?;
+
+    for vector in (0..irq_stats::VECTORS).filter(|&vector| irq_stats::seen(vector)) {
+        write!(out, "{vector:>3}:")?;
+        for cpu in per_cpu::cpus() {
+            write!(out, " {:>10}", cpu.irq_stats.get(vector))?;
+        }
+        writeln!(out, "  {}", irq_stats::vector_name(vector))?;
+    }
+    Ok(())
+}
+
+fn maps(pid: Pid, out: &mut impl Write) -> fmt::Result {
+    // Format outside of the table lock.
+    let vmas: Option<UserVmas> = {
+        let _irq = IrqGuard::new();
+        let table = PROCESSES.lock();
+        table
+            .find(pid)
+            .and_then(|slot| table.get(slot))
+            .map(|p| p.vmas.clone())
+    };
+
+    for vma in vmas.iter().flat_map(UserVmas::iter) {
+        writeln!(out, "{vma}")?;
+    }
+    Ok(())
+}
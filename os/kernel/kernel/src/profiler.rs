@@ -0,0 +1,319 @@
+//! # Sampling Profiler
+//!
+//! Periodically records where each CPU is executing, so hot paths in the
+//! kernel show up as a histogram of functions.
+//!
+//! ## Sample sources
+//!
+//! * **Performance counter NMIs** (preferred). If CPUID leaf 0AH reports an
+//!   architectural PMU that can count unhalted core cycles, general-purpose
+//!   counter 0 is preloaded to overflow after `tsc_hz / rate` cycles and the
+//!   LAPIC delivers the overflow as an NMI (see [`nmi`](crate::interrupts::nmi)).
+//!   NMIs also hit code running with interrupts disabled. KVM exposes a
+//!   virtual PMU; plain QEMU (TCG) usually does not.
+//! * **LAPIC timer** otherwise: every n-th timer interrupt takes a sample.
+//!   Code running with interrupts disabled is invisible to this source.
+//!
+//! A sample is the interrupted instruction pointer, stored in a per-CPU ring
+//! of [`SAMPLE_RING_LEN`] entries; samples taken in user mode are only
+//! counted. Recording never allocates or locks, so it is safe in NMI context.
+//!
+//! ## Reports
+//!
+//! [`Histogram::collect`] aggregates the samples taken on the current CPU
+//! since the previous report by [symbol](crate::ksyms); it implements
+//! [`Display`](core::fmt::Display) so it can be logged or written to a file.
+//! A [delayed work](crate::workqueue) item logs a report every
+//! [`REPORT_INTERVAL_SECS`] seconds.
+//!
+//! ## Configuration
+//!
+//! `profile=<rate>` on the [kernel command line](crate::cmdline) enables the
+//! profiler with `rate` samples per second; it is off by default.
+
+use crate::apic;
+use crate::clock;
+use crate::cmdline::{self, Param, ParamKind};
+use crate::cpuid::{CpuidRanges, Leaf0Ah};
+use crate::ksyms;
+use crate::per_cpu::PerCpu;
+use core::fmt;
+use core::sync::atomic::{AtomicU8, AtomicU64, AtomicUsize, Ordering};
+use kernel_memory_addresses::VirtualAddress;
+use kernel_registers::msr::Msr;
+use log::{info, warn};
+
+pub static PROFILE_PARAM: Param = Param {
+    name: "profile",
+    kind: ParamKind::U64,
+    help: "sample the kernel this many times per second; 0 disables",
+};
+
+/// Samples kept per CPU between two reports.
+pub const SAMPLE_RING_LEN: usize = 1024;
+
+/// Seconds between two logged reports.
+pub const REPORT_INTERVAL_SECS: u64 = 10;
+
+/// Most distinct symbols in a [`Histogram`]; the rest is counted as "other".
+pub const HISTOGRAM_SLOTS: usize = 64;
+
+/// Lines printed by the [`Display`](fmt::Display) implementation of [`Histogram`].
+pub const REPORT_TOP: usize = 16;
+
+/// Marker stored in the ring for samples taken in user mode.
+const USER_SAMPLE: u64 = 1;
+
+const IA32_PMC0: Msr = Msr(0xC1);
+const IA32_PERFEVTSEL0: Msr = Msr(0x186);
+const IA32_PERF_GLOBAL_STATUS: Msr = Msr(0x38E);
+const IA32_PERF_GLOBAL_CTRL: Msr = Msr(0x38F);
+const IA32_PERF_GLOBAL_OVF_CTRL: Msr = Msr(0x390);
+
+/// `IA32_PERFEVTSEL`: "unhalted core cycles" (event 3CH, umask 0), counted in
+/// user and kernel mode, with an interrupt on overflow.
+const EVTSEL_CORE_CYCLES: u64 = 0x3C | (1 << 16) | (1 << 17) | (1 << 20) | (1 << 22);
+
+/// Largest preload period; writes to `IA32_PMC0` only take 32 sign-extended bits.
+const MAX_PERIOD: u64 = 0x7FFF_FFFF;
+
+const SOURCE_OFF: u8 = 0;
+const SOURCE_PMC: u8 = 1;
+const SOURCE_TIMER: u8 = 2;
+
+static SOURCE: AtomicU8 = AtomicU8::new(SOURCE_OFF);
+
+/// Counter preload in cycles, or timer ticks per sample.
+static PERIOD: AtomicU64 = AtomicU64::new(0);
+
+/// Architectural performance monitoring version of the PMU in use.
+static PMU_VERSION: AtomicU8 = AtomicU8::new(0);
+
+/// Per-CPU sample ring, embedded in [`PerCpu`].
+pub struct CpuProfile {
+    /// Samples recorded so far; the ring index is this modulo the ring size.
+    head: AtomicUsize,
+    /// Value of `head` at the last report.
+    reported: AtomicUsize,
+    samples: [AtomicU64; SAMPLE_RING_LEN],
+}
+
+impl CpuProfile {
+    pub const fn new() -> Self {
+        Self {
+            head: AtomicUsize::new(0),
+            reported: AtomicUsize::new(0),
+            samples: [const { AtomicU64::new(0) }; SAMPLE_RING_LEN],
+        }
+    }
+
+    fn record(&self, rip: VirtualAddress, user: bool) {
+        let sample = if user { USER_SAMPLE } else { rip.as_u64() };
+        let i = self.head.fetch_add(1, Ordering::Relaxed) % SAMPLE_RING_LEN;
+        self.samples[i].store(sample, Ordering::Relaxed);
+    }
+}
+
+/// Start sampling on the current CPU if `profile` is set on the command line.
+///
+/// Requires the TSC frequency to be known and the local APIC timer running.
+pub fn init() {
+    let rate = cmdline::get_u64(PROFILE_PARAM.name).unwrap_or(0);
+    if rate == 0 {
+        return;
+    }
+
+    let ranges = unsafe { CpuidRanges::read() };
+    let pmu = unsafe { Leaf0Ah::read(&ranges) }
+        .filter(|pmu| pmu.version() > 0 && pmu.counters() > 0 && pmu.has_core_cycles());
+
+    if let Some(pmu) = pmu {
+        let period = (clock::tsc_hz() / rate).clamp(1, MAX_PERIOD);
+        PERIOD.store(period, Ordering::Relaxed);
+        PMU_VERSION.store(pmu.version(), Ordering::Relaxed);
+        SOURCE.store(SOURCE_PMC, Ordering::Release);
+        unsafe {
+            IA32_PERFEVTSEL0.store_raw(0);
+            arm_counter(period);
+            IA32_PERFEVTSEL0.store_raw(EVTSEL_CORE_CYCLES);
+            if pmu.version() >= 2 {
+                let ctrl = IA32_PERF_GLOBAL_CTRL.load_raw();
+                IA32_PERF_GLOBAL_CTRL.store_raw(ctrl | 1);
+            }
+            apic::program_pmi_nmi_x2apic();
+        }
+        info!(
+            "Profiling at {rate} Hz via performance counter NMIs (PMU v{}, every {period} cycles)",
+            pmu.version()
+        );
+    } else {
+        let divisor = (apic::tick_hz() / rate).max(1);
+        PERIOD.store(divisor, Ordering::Relaxed);
+        SOURCE.store(SOURCE_TIMER, Ordering::Release);
+        warn!("No usable performance counters; profiling every {divisor} timer tick(s) instead");
+    }
+}
+
+/// Sample from the timer interrupt, if the timer is the sample source.
+pub fn on_tick(cpu: &PerCpu, rip: VirtualAddress, user: bool) {
+    if SOURCE.load(Ordering::Relaxed) != SOURCE_TIMER {
+        return;
+    }
+    let divisor = PERIOD.load(Ordering::Relaxed).max(1);
+    if cpu.ticks.load(Ordering::Relaxed).is_multiple_of(divisor) {
+        cpu.profile.record(rip, user);
+    }
+}
+
+/// Handle a counter overflow NMI. Returns `false` if the NMI was not caused by
+/// the profiler's counter.
+pub fn on_nmi(rip: VirtualAddress, user: bool) -> bool {
+    if SOURCE.load(Ordering::Relaxed) != SOURCE_PMC {
+        return false;
+    }
+
+    let period = PERIOD.load(Ordering::Relaxed);
+    let version = PMU_VERSION.load(Ordering::Relaxed);
+    let overflowed = unsafe {
+        if version >= 2 {
+            IA32_PERF_GLOBAL_STATUS.load_raw() & 1 != 0
+        } else {
+            // The preload is negative; after wrapping, the counter is small.
+            IA32_PMC0.load_raw() < period
+        }
+    };
+    if !overflowed {
+        return false;
+    }
+
+    let cpu = unsafe { PerCpu::current() };
+    cpu.profile.record(rip, user);
+
+    unsafe {
+        arm_counter(period);
+        if version >= 2 {
+            IA32_PERF_GLOBAL_OVF_CTRL.store_raw(1);
+        }
+        apic::program_pmi_nmi_x2apic();
+    }
+    true
+}
+
+/// Preload counter 0 to overflow after `period` events.
+unsafe fn arm_counter(period: u64) {
+    unsafe { IA32_PMC0.store_raw(period.wrapping_neg()) };
+}
+
+/// Whether samples are being taken.
+#[must_use]
+pub fn enabled() -> bool {
+    SOURCE.load(Ordering::Relaxed) != SOURCE_OFF
+}
+
+/// Log a report of the current CPU, if the profiler is enabled.
+pub fn log_report() {
+    if enabled() {
+        info!("{}", Histogram::collect());
+    }
+}
+
+/// Samples of one CPU, counted by symbol.
+pub struct Histogram {
+    cpu_id: u32,
+    total: u64,
+    /// Samples lost because the ring wrapped before the report.
+    dropped: u64,
+    user: u64,
+    unknown: u64,
+    other: u64,
+    len: usize,
+    /// `(symbol, samples)`, sorted by descending sample count.
+    slots: [(&'static str, u64); HISTOGRAM_SLOTS],
+}
+
+impl Histogram {
+    /// Aggregate the samples the current CPU took since the previous call.
+    #[must_use]
+    pub fn collect() -> Self {
+        let cpu = unsafe { PerCpu::current() };
+        let profile = &cpu.profile;
+        let head = profile.head.load(Ordering::Relaxed);
+        let from = profile.reported.swap(head, Ordering::Relaxed);
+        let taken = head.wrapping_sub(from);
+        let kept = taken.min(SAMPLE_RING_LEN);
+
+        let mut hist = Self {
+            cpu_id: cpu.cpu_id,
+            total: taken as u64,
+            dropped: (taken - kept) as u64,
+            user: 0,
+            unknown: 0,
+            other: 0,
+            len: 0,
+            slots: [("", 0); HISTOGRAM_SLOTS],
+        };
+        for i in head - kept..head {
+            let sample = profile.samples[i % SAMPLE_RING_LEN].load(Ordering::Relaxed);
+            hist.add(sample);
+        }
+        hist.slots[..hist.len].sort_unstable_by_key(|s| core::cmp::Reverse(s.1));
+        hist
+    }
+
+    fn add(&mut self, sample: u64) {
+        if sample == USER_SAMPLE {
+            self.user += 1;
+            return;
+        }
+        let Some((name, _)) = ksyms::resolve(VirtualAddress::new(sample)) else {
+            self.unknown += 1;
+            return;
+        };
+
+        // Symbol names are unique slices into the symbol table.
+        let slots = &mut self.slots[..self.len];
+        if let Some(slot) = slots.iter_mut().find(|(n, _)| core::ptr::eq(*n, name)) {
+            slot.1 += 1;
+        } else if self.len < HISTOGRAM_SLOTS {
+            self.slots[self.len] = (name, 1);
+            self.len += 1;
+        } else {
+            self.other += 1;
+        }
+    }
+
+    /// `(symbol, samples)` pairs, most frequent first.
+    pub fn entries(&self) -> &[(&'static str, u64)] {
+        &self.slots[..self.len]
+    }
+}
+
+impl fmt::Display for Histogram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Profile of CPU {}: {} samples ({} dropped)",
+            self.cpu_id, self.total, self.dropped
+        )?;
+        let counted = (self.total - self.dropped).max(1);
+        let line = |f: &mut fmt::Formatter<'_>, name: &str, n: u64| {
+            if n == 0 {
+                return Ok(());
+            }
+            let permille = n * 1000 / counted;
+            write!(
+                f,
+                "\n  {:>3}.{}% {n:>6}  {name}",
+                permille / 10,
+                permille % 10
+            )
+        };
+        for &(name, n) in self.entries().iter().take(REPORT_TOP) {
+            line(f, name, n)?;
+        }
+        let rest: u64 = self.entries().iter().skip(REPORT_TOP).map(|e| e.1).sum();
+        line(f, "[other kernel symbols]", rest + self.other)?;
+        line(f, "[unknown]", self.unknown)?;
+        line(f, "[user]", self.user)
+    }
+}
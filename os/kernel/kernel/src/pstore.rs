@@ -0,0 +1,245 @@
+//! # Persistent Store
+//!
+//! The UEFI loader reserves a small region of RAM at a fixed physical address
+//! on every boot and never clears it (see [`PstoreInfo`]). RAM keeps its
+//! contents across a warm reboot, so when the kernel panics it leaves a
+//! record there for the next boot to find; this makes crashes that end in a
+//! triple fault or a reset visible on machines without a serial console.
+//!
+//! * [`record_panic`] runs on the panic path. It writes the panic report,
+//!   followed by the lines of the [log ring](crate::klog), into the region.
+//! * [`init`] runs early during boot. If the region holds a valid record, it
+//!   logs the record as errors and then invalidates it, so it is reported
+//!   only once.
+//!
+//! ## Record format
+//!
+//! A record is a 24-byte header followed by UTF-8 text, one log line per
+//! text line:
+//!
+//! | Offset | Size | Field                                   |
+//! |--------|------|-----------------------------------------|
+//! | 0      | 8    | magic, `OSPSTORE` in ASCII              |
+//! | 8      | 4    | format version, currently 1             |
+//! | 12     | 4    | length of the text in bytes             |
+//! | 16     | 4    | CRC-32 (IEEE 802.3) of the text         |
+//! | 20     | 4    | reserved, zero                          |
+//!
+//! All fields are little endian. The text is written before the header, so a
+//! record cut short by a second fault never looks valid; firmware that
+//! scribbles over the region is caught by the checksum.
+//!
+//! ## Limitations
+//!
+//! * Only the first CPU to panic writes a record.
+//! * Log lines are kept up to their first line break; the log ring has
+//!   already truncated them to [`LINE_LEN`](crate::klog::LINE_LEN) bytes.
+//! * A cold boot, or firmware that clears memory on reset, loses the record.
+
+use crate::klog;
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering, compiler_fence};
+use kernel_info::boot::{KernelBootInfo, PstoreInfo};
+use kernel_info::memory::{HHDM_BASE, HHDM_SIZE};
+use log::{error, info, warn};
+
+/// Marks a valid record.
+const MAGIC: u64 = u64::from_le_bytes(*b"OSPSTORE");
+
+/// Format version of the record.
+const VERSION: u32 = 1;
+
+/// Size of the record header; the text follows it.
+pub const HEADER_LEN: usize = 24;
+
+const MAGIC_OFFSET: usize = 0;
+const VERSION_OFFSET: usize = 8;
+const LEN_OFFSET: usize = 12;
+const CHECKSUM_OFFSET: usize = 16;
+
+/// HHDM address of the region, or 0 before [`init`] found one.
+static REGION: AtomicU64 = AtomicU64::new(0);
+
+/// Length of the region in bytes.
+static REGION_LEN: AtomicUsize = AtomicUsize::new(0);
+
+/// Set by the first CPU to enter [`record_panic`].
+static RECORDING: AtomicBool = AtomicBool::new(false);
+
+/// Report the record the previous boot left behind, if any, and make the
+/// region available to [`record_panic`].
+///
+/// Call once, early during boot and after [`klog::configure`].
+pub fn init(bi: &KernelBootInfo) {
+    let Some(info) = bi.pstore() else {
+        info!("No pstore region; panics will not be kept across reboots");
+        return;
+    };
+    let Some((addr, len)) = locate(info) else {
+        warn!(
+            "Ignoring pstore region at {:#x} ({} bytes): outside the HHDM or too small",
+            info.phys_start, info.length
+        );
+        return;
+    };
+
+    // SAFETY: The loader reserved the region for the kernel, and it lies in
+    // the part of the HHDM the loader maps; nothing else refers to it.
+    let region = unsafe { core::slice::from_raw_parts_mut(addr as *mut u8, len) };
+    match read(region) {
+        Some(text) => {
+            error!("The previous boot panicked; its pstore record follows");
+            for line in text.lines() {
+                error!("pstore: {line}");
+            }
+            error!("End of pstore record");
+        }
+        None => info!("Pstore region at {:#x} holds no record", info.phys_start),
+    }
+    clear(region);
+
+    REGION_LEN.store(len, Ordering::Relaxed);
+    REGION.store(addr, Ordering::Release);
+}
+
+/// Check that the region is usable and return its HHDM address and length.
+fn locate(info: &PstoreInfo) -> Option<(u64, usize)> {
+    let end = info.phys_start.checked_add(info.length)?;
+    let len = usize::try_from(info.length).ok()?;
+    if info.phys_start == 0 || end > HHDM_SIZE || len <= HEADER_LEN {
+        return None;
+    }
+    Some(((HHDM_BASE + info.phys_start).as_u64(), len))
+}
+
+/// Write the panic report and the log ring into the region.
+///
+/// Call from the panic handler, after the report and backtrace were logged.
+/// Does not allocate or take locks; only the first call does anything.
+pub fn record_panic(info: &PanicInfo) {
+    if RECORDING.swap(true, Ordering::AcqRel) {
+        return;
+    }
+    let addr = REGION.load(Ordering::Acquire);
+    if addr == 0 {
+        return;
+    }
+    let len = REGION_LEN.load(Ordering::Relaxed);
+    // SAFETY: Set by `init` from a checked region; `RECORDING` keeps other
+    // CPUs out.
+    let region = unsafe { core::slice::from_raw_parts_mut(addr as *mut u8, len) };
+    let Some(mut record) = RecordWriter::new(region) else {
+        return;
+    };
+
+    let _ = writeln!(record, "{info}");
+    klog::drain(|line| {
+        let text = line.text().lines().next().unwrap_or_default();
+        let _ = writeln!(record, "{:<5} {text}", line.level);
+    });
+    record.commit();
+
+    // A reset does not write back dirty cache lines.
+    // SAFETY: Writing back and invalidating the caches has no other effect.
+    unsafe {
+        core::arch::asm!("wbinvd", options(nostack, preserves_flags));
+    }
+}
+
+/// Writes the text of a record into a region, then its header on
+/// [`commit`](Self::commit).
+///
+/// Text that does not fit is dropped, always at a character boundary.
+pub struct RecordWriter<'a> {
+    region: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> RecordWriter<'a> {
+    /// Start a record in `region`, or `None` if it cannot hold any text.
+    ///
+    /// Invalidates the record the region held so far.
+    pub fn new(region: &'a mut [u8]) -> Option<Self> {
+        if region.len() <= HEADER_LEN {
+            return None;
+        }
+        clear(region);
+        Some(Self { region, len: 0 })
+    }
+
+    /// Finish the record by writing its header.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn commit(self) {
+        let text = &self.region[HEADER_LEN..HEADER_LEN + self.len];
+        let checksum = crc32(text);
+        // The text fits in the region, whose length fits in a `u32`.
+        let len = self.len as u32;
+        self.region[VERSION_OFFSET..LEN_OFFSET].copy_from_slice(&VERSION.to_le_bytes());
+        self.region[LEN_OFFSET..CHECKSUM_OFFSET].copy_from_slice(&len.to_le_bytes());
+        self.region[CHECKSUM_OFFSET..CHECKSUM_OFFSET + 4].copy_from_slice(&checksum.to_le_bytes());
+        self.region[CHECKSUM_OFFSET + 4..HEADER_LEN].fill(0);
+        // The magic goes last: until it is there, the record is invalid.
+        compiler_fence(Ordering::Release);
+        self.region[MAGIC_OFFSET..VERSION_OFFSET].copy_from_slice(&MAGIC.to_le_bytes());
+    }
+}
+
+impl Write for RecordWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let capacity = (self.region.len() - HEADER_LEN).min(u32::MAX as usize);
+        let mut n = s.len().min(capacity - self.len);
+        while !s.is_char_boundary(n) {
+            n -= 1;
+        }
+        let start = HEADER_LEN + self.len;
+        self.region[start..start + n].copy_from_slice(&s.as_bytes()[..n]);
+        self.len += n;
+        if n < s.len() { Err(fmt::Error) } else { Ok(()) }
+    }
+}
+
+/// The text of the record in `region`, or `None` if it holds no valid one.
+pub fn read(region: &[u8]) -> Option<&str> {
+    let header = region.get(..HEADER_LEN)?;
+    let field = |offset: usize| {
+        u32::from_le_bytes([
+            header[offset],
+            header[offset + 1],
+            header[offset + 2],
+            header[offset + 3],
+        ])
+    };
+    let mut magic = [0; 8];
+    magic.copy_from_slice(&header[MAGIC_OFFSET..VERSION_OFFSET]);
+    if u64::from_le_bytes(magic) != MAGIC || field(VERSION_OFFSET) != VERSION {
+        return None;
+    }
+
+    let len = usize::try_from(field(LEN_OFFSET)).ok()?;
+    let text = region.get(HEADER_LEN..HEADER_LEN.checked_add(len)?)?;
+    if crc32(text) != field(CHECKSUM_OFFSET) {
+        return None;
+    }
+    core::str::from_utf8(text).ok()
+}
+
+/// Invalidate the record in `region`.
+pub fn clear(region: &mut [u8]) {
+    let end = region.len().min(HEADER_LEN);
+    region[..end].fill(0);
+}
+
+/// CRC-32 as used by Ethernet and zlib, computed bit by bit; records are
+/// written once per boot at most, so a table is not worth its space.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
@@ -0,0 +1,235 @@
+//! # Random Numbers
+//!
+//! A cryptographically secure random number generator built on `ChaCha20`,
+//! for everything that must not be guessed: the `AT_RANDOM` bytes of new
+//! processes, the `getrandom` system call, and later address space layout
+//! randomization and stack canaries. [`rand_bytes`] is the interface.
+//!
+//! ## Seeding
+//!
+//! The generator's whole state is one 256-bit `ChaCha20` key, the pool.
+//! [`init`] mixes into it whatever sources there are:
+//!
+//! | Source      | Where from                                          |
+//! |-------------|-----------------------------------------------------|
+//! | Boot seed   | The firmware's `EFI_RNG_PROTOCOL`, via the loader   |
+//! | `RDSEED`    | The CPU's entropy source, if it has one             |
+//! | `RDRAND`    | The CPU's DRBG, if it has no `RDSEED`               |
+//! | TSC jitter  | How long a short busy loop takes, many times over   |
+//!
+//! Input is mixed in 32 bytes at a time: XOR-ed into the key, which is then
+//! replaced by the first half of the `ChaCha20` block it produces, so every
+//! input bit affects every key bit. No source is trusted alone; the jitter
+//! is always added, and is all there is on CPUs without `RDRAND` when the
+//! firmware has no RNG protocol either.
+//!
+//! ## Output
+//!
+//! Every request runs `ChaCha20` under the pool key: the first block becomes
+//! the next key ("fast key erasure"), the following blocks are the output.
+//! Whoever reads the state afterwards learns nothing about output already
+//! handed out.
+//!
+//! Until [`init`] ran, the pool is all zeros and the output predictable.
+
+use crate::cpuid::{CpuidRanges, Leaf01h, Leaf07h};
+use crate::tsc::rdtsc;
+use kernel_info::boot::KernelBootInfo;
+use kernel_sync::{IrqGuard, SpinMutex};
+use log::{info, warn};
+
+/// Size of a `ChaCha20` block in bytes.
+pub const BLOCK_LEN: usize = 64;
+
+/// Nonce of the blocks handed out.
+const OUTPUT_NONCE: [u32; 3] = [0, 0, 0];
+/// Nonce of the blocks that absorb input, to keep them apart from output.
+const MIX_NONCE: [u32; 3] = [0, 0, 1];
+
+/// `RDRAND`/`RDSEED` may run dry for a moment; how often to ask.
+const HW_RETRIES: usize = 32;
+
+/// Busy-loop timings taken per byte of TSC jitter.
+const JITTER_SAMPLES_PER_BYTE: usize = 8;
+
+static POOL: SpinMutex<[u32; 8]> = SpinMutex::new([0; 8]);
+
+/// Seed the generator from the boot seed in `bi`, the CPU and TSC jitter.
+pub fn init(bi: &KernelBootInfo) {
+    let seed = bi.seed();
+    if let Some(seed) = seed {
+        mix(seed);
+    }
+
+    let ranges = unsafe { CpuidRanges::read() };
+    let (name, source): (_, fn() -> Option<u64>) =
+        if unsafe { Leaf07h::read(&ranges) }.is_some_and(|l| l.has_rdseed()) {
+            ("RDSEED", rdseed)
+        } else if unsafe { Leaf01h::read(&ranges) }.is_some_and(|l| l.has_rdrand()) {
+            ("RDRAND", rdrand)
+        } else {
+            ("", || None)
+        };
+    let hw = hw_bytes(source);
+    match hw {
+        Some(bytes) => mix(&bytes),
+        None if !name.is_empty() => warn!("{name} keeps failing; not using it"),
+        None => {}
+    }
+
+    mix(&jitter());
+    info!(
+        "Random number generator seeded (boot seed: {}, CPU: {}, TSC jitter)",
+        if seed.is_some() { "yes" } else { "no" },
+        if hw.is_some() { name } else { "none" },
+    );
+    if seed.is_none() && hw.is_none() {
+        warn!("TSC jitter is the only source of randomness");
+    }
+}
+
+/// Fill `buf` with random bytes.
+pub fn rand_bytes(buf: &mut [u8]) {
+    let _irq = IrqGuard::new();
+    let mut pool = POOL.lock();
+    let key = *pool;
+    *pool = next_key(&key, 0, &OUTPUT_NONCE);
+    for (i, chunk) in buf.chunks_mut(BLOCK_LEN).enumerate() {
+        // Block 0 is the next key, so output starts at 1.
+        let counter = u32::try_from(i + 1).expect("request too large");
+        let block = chacha20_block(&key, counter, &OUTPUT_NONCE);
+        chunk.copy_from_slice(&block[..chunk.len()]);
+    }
+}
+
+/// Mix `input` into the pool.
+fn mix(input: &[u8]) {
+    let _irq = IrqGuard::new();
+    let mut key = POOL.lock();
+    for chunk in input.chunks(32) {
+        for (i, byte) in chunk.iter().enumerate() {
+            key[i / 4] ^= u32::from(*byte) << (8 * (i % 4));
+        }
+        *key = next_key(&key, 0, &MIX_NONCE);
+    }
+}
+
+/// The key made of the first half of block `counter` under `key`.
+fn next_key(key: &[u32; 8], counter: u32, nonce: &[u32; 3]) -> [u32; 8] {
+    let block = chacha20_block(key, counter, nonce);
+    let mut next = [0u32; 8];
+    for (word, bytes) in next.iter_mut().zip(block.chunks_exact(4)) {
+        *word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    }
+    next
+}
+
+/// The `ChaCha20` block function of RFC 8439, section 2.3.
+pub fn chacha20_block(key: &[u32; 8], counter: u32, nonce: &[u32; 3]) -> [u8; BLOCK_LEN] {
+    let mut state = [0u32; 16];
+    state[..4].copy_from_slice(&[0x6170_7865, 0x3320_646E, 0x7962_2D32, 0x6B20_6574]);
+    state[4..12].copy_from_slice(key);
+    state[12] = counter;
+    state[13..].copy_from_slice(nonce);
+
+    let mut x = state;
+    for _ in 0..10 {
+        quarter_round(&mut x, 0, 4, 8, 12);
+        quarter_round(&mut x, 1, 5, 9, 13);
+        quarter_round(&mut x, 2, 6, 10, 14);
+        quarter_round(&mut x, 3, 7, 11, 15);
+        quarter_round(&mut x, 0, 5, 10, 15);
+        quarter_round(&mut x, 1, 6, 11, 12);
+        quarter_round(&mut x, 2, 7, 8, 13);
+        quarter_round(&mut x, 3, 4, 9, 14);
+    }
+
+    let mut out = [0u8; BLOCK_LEN];
+    for ((bytes, word), initial) in out.chunks_exact_mut(4).zip(x).zip(state) {
+        bytes.copy_from_slice(&word.wrapping_add(initial).to_le_bytes());
+    }
+    out
+}
+
+#[inline]
+#[allow(clippy::many_single_char_names)]
+const fn quarter_round(x: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    x[a] = x[a].wrapping_add(x[b]);
+    x[d] = (x[d] ^ x[a]).rotate_left(16);
+    x[c] = x[c].wrapping_add(x[d]);
+    x[b] = (x[b] ^ x[c]).rotate_left(12);
+    x[a] = x[a].wrapping_add(x[b]);
+    x[d] = (x[d] ^ x[a]).rotate_left(8);
+    x[c] = x[c].wrapping_add(x[d]);
+    x[b] = (x[b] ^ x[c]).rotate_left(7);
+}
+
+/// 32 bytes from `source`, or `None` if it keeps failing.
+fn hw_bytes(source: fn() -> Option<u64>) -> Option<[u8; 32]> {
+    let mut out = [0u8; 32];
+    for chunk in out.chunks_exact_mut(8) {
+        chunk.copy_from_slice(&source()?.to_le_bytes());
+    }
+    Some(out)
+}
+
+/// One `RDSEED` result, retrying while the CPU has none ready.
+fn rdseed() -> Option<u64> {
+    for _ in 0..HW_RETRIES {
+        let value: u64;
+        let ok: u8;
+        unsafe {
+            core::arch::asm!(
+                "rdseed {value}",
+                "setc {ok}",
+                value = out(reg) value,
+                ok = out(reg_byte) ok,
+                options(nomem, nostack),
+            );
+        }
+        if ok != 0 {
+            return Some(value);
+        }
+        core::hint::spin_loop();
+    }
+    None
+}
+
+/// One `RDRAND` result, retrying while the CPU has none ready.
+fn rdrand() -> Option<u64> {
+    for _ in 0..HW_RETRIES {
+        let value: u64;
+        let ok: u8;
+        unsafe {
+            core::arch::asm!(
+                "rdrand {value}",
+                "setc {ok}",
+                value = out(reg) value,
+                ok = out(reg_byte) ok,
+                options(nomem, nostack),
+            );
+        }
+        if ok != 0 {
+            return Some(value);
+        }
+        core::hint::spin_loop();
+    }
+    None
+}
+
+/// 32 bytes of TSC jitter: the low bits of how long a short busy loop
+/// takes, which vary with caches, pipelines and interrupts.
+#[allow(clippy::cast_possible_truncation)]
+fn jitter() -> [u8; 32] {
+    let mut out = [0u8; 32];
+    for byte in &mut out {
+        for _ in 0..JITTER_SAMPLES_PER_BYTE {
+            let start = rdtsc();
+            for i in 0..64u32 {
+                core::hint::black_box(i);
+            }
+            *byte = byte.rotate_left(1) ^ rdtsc().wrapping_sub(start) as u8;
+        }
+    }
+    out
+}
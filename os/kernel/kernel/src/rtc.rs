@@ -0,0 +1,343 @@
+//! # CMOS Real-Time Clock
+//!
+//! The battery-backed clock of the PC, reached through the CMOS index and
+//! data ports. [`init`] reads the date and time once at boot and sets the
+//! [wall clock](crate::clock::unix_time) from it; from then on, the wall
+//! clock advances with the TSC.
+//!
+//! ## Reading
+//!
+//! The RTC keeps its registers in BCD or binary and the hour in 12- or
+//! 24-hour format, as status register B says; [`RawTime::decode`] handles
+//! all four. Whether the RTC has a century register, and where, comes from
+//! the [FADT](crate::acpi::fadt); without one, years are taken to be 20xx.
+//! The time is assumed to be UTC, as QEMU keeps it by default.
+//!
+//! The registers change once a second. [`read`] waits for an update in
+//! progress to finish and reads until two reads in a row agree.
+//!
+//! ## Periodic Interrupt
+//!
+//! If neither the LAPIC timer nor the [PIT](crate::pit) can drive the tick,
+//! or `rtc_tick` is on the [command line](crate::cmdline), [`start_tick`] has
+//! the RTC raise IRQ 8 at
+//! [`TICK_HZ`] and routes it through the [I/O APIC](crate::ioapic) to the
+//! timer vector, as a coarse tick that keeps the scheduler, timers and
+//! sleepers going. The timer interrupt calls [`acknowledge_tick`], without
+//! which the RTC would not raise the next one.
+
+use crate::cmdline::{self, Param, ParamKind};
+use crate::interrupts::timer::LAPIC_TIMER_VECTOR;
+use crate::ioapic::{self, IoApicError};
+use crate::per_cpu::PerCpu;
+use crate::tsc::rdtsc;
+use crate::{acpi, clock};
+use core::fmt;
+use core::hint::spin_loop;
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use kernel_ports::PortRange;
+use kernel_sync::{IrqGuard, SpinMutex};
+use log::{info, warn};
+
+pub static RTC_TICK_PARAM: Param = Param {
+    name: "rtc_tick",
+    kind: ParamKind::Flag,
+    help: "Drive the timer tick from the RTC instead of the LAPIC timer",
+};
+
+/// Periodic interrupts per second once [`start_tick`] ran.
+pub const TICK_HZ: u64 = 1024;
+
+/// ISA IRQ of the RTC.
+const RTC_IRQ: u8 = 8;
+
+/// The CMOS index port, then the data port.
+const CMOS: PortRange = PortRange::new(0x70, 2);
+
+const SECONDS: u8 = 0x00;
+const MINUTES: u8 = 0x02;
+const HOURS: u8 = 0x04;
+const DAY: u8 = 0x07;
+const MONTH: u8 = 0x08;
+const YEAR: u8 = 0x09;
+const STATUS_A: u8 = 0x0A;
+const STATUS_B: u8 = 0x0B;
+const STATUS_C: u8 = 0x0C;
+
+/// Status A bit: the registers are being updated.
+const UPDATE_IN_PROGRESS: u8 = 1 << 7;
+
+/// Status A bits: the periodic interrupt rate.
+const RATE_MASK: u8 = 0x0F;
+
+/// Rate selecting [`TICK_HZ`]: 32768 Hz >> (rate - 1).
+const RATE_1024_HZ: u8 = 6;
+
+/// Status B bit: hours are 0-23 rather than 1-12 with a PM flag.
+pub const HOURS_24: u8 = 1 << 1;
+
+/// Status B bit: registers are binary rather than BCD.
+pub const BINARY: u8 = 1 << 2;
+
+/// Status B bit: raise the periodic interrupt.
+const PERIODIC_INTERRUPT: u8 = 1 << 6;
+
+/// Hours register bit in 12-hour format: PM.
+const HOUR_PM: u8 = 1 << 7;
+
+/// How often [`read`] tries to get two matching reads.
+const READ_ATTEMPTS: usize = 5;
+
+/// How often [`read`] polls for an update to finish; an update takes less
+/// than 2 ms.
+const UPDATE_SPINS: usize = 100_000;
+
+/// Serializes the two-step index/data accesses.
+static CMOS_LOCK: SpinMutex<()> = SpinMutex::new(());
+
+/// CMOS index of the century register; `0` if there is none.
+static CENTURY_REGISTER: AtomicU8 = AtomicU8::new(0);
+
+/// Set once the RTC drives the timer tick.
+static TICKING: AtomicBool = AtomicBool::new(false);
+
+/// The RTC registers as read, before decoding.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct RawTime {
+    pub second: u8,
+    pub minute: u8,
+    pub hour: u8,
+    pub day: u8,
+    pub month: u8,
+    pub year: u8,
+    pub century: Option<u8>,
+}
+
+/// A date and time of the proleptic Gregorian calendar.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct DateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl RawTime {
+    /// The date and time the registers hold in the format status register
+    /// B names, or `None` if they hold none.
+    pub fn decode(self, status_b: u8) -> Option<DateTime> {
+        let value = |raw: u8| {
+            if status_b & BINARY != 0 {
+                Some(raw)
+            } else {
+                from_bcd(raw)
+            }
+        };
+
+        let hour = if status_b & HOURS_24 != 0 {
+            value(self.hour)?
+        } else {
+            let hour = value(self.hour & !HOUR_PM).filter(|hour| (1..=12).contains(hour))?;
+            // 12 AM is midnight, 12 PM noon.
+            hour % 12 + if self.hour & HOUR_PM != 0 { 12 } else { 0 }
+        };
+        let century = self.century.map_or(Some(20), value)?;
+
+        let time = DateTime {
+            year: u16::from(century) * 100 + u16::from(value(self.year)?),
+            month: value(self.month)?,
+            day: value(self.day)?,
+            hour,
+            minute: value(self.minute)?,
+            second: value(self.second)?,
+        };
+        time.is_valid().then_some(time)
+    }
+}
+
+impl DateTime {
+    fn is_valid(self) -> bool {
+        (1..=12).contains(&self.month)
+            && (1..=days_in_month(self.year, self.month)).contains(&self.day)
+            && self.hour < 24
+            && self.minute < 60
+            && self.second < 60
+    }
+
+    /// Seconds since 1970-01-01 00:00:00, which must not be later.
+    pub fn unix_seconds(self) -> u64 {
+        // Days from civil, counting years from March so that the leap day
+        // comes last.
+        let (year, month) = if self.month <= 2 {
+            (u64::from(self.year) - 1, u64::from(self.month) + 9)
+        } else {
+            (u64::from(self.year), u64::from(self.month) - 3)
+        };
+        let era = year / 400;
+        let year_of_era = year % 400;
+        let day_of_year = (153 * month + 2) / 5 + u64::from(self.day) - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = era * 146_097 + day_of_era - 719_468;
+
+        days * 86_400
+            + u64::from(self.hour) * 3_600
+            + u64::from(self.minute) * 60
+            + u64::from(self.second)
+    }
+}
+
+/// ISO 8601, without a time zone.
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+const fn from_bcd(bcd: u8) -> Option<u8> {
+    let (tens, ones) = (bcd >> 4, bcd & 0x0F);
+    if tens > 9 || ones > 9 {
+        None
+    } else {
+        Some(tens * 10 + ones)
+    }
+}
+
+const fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400)) => {
+            29
+        }
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Read CMOS register `index`. The caller holds [`CMOS_LOCK`].
+fn read_register(index: u8) -> u8 {
+    // Safety: the CMOS ports are always decoded and only ever accessed under
+    // the lock. Leaving bit 7 of the index clear keeps NMIs enabled.
+    unsafe {
+        CMOS.write_only(0).write(index);
+        CMOS.read_only(1).read()
+    }
+}
+
+/// Write CMOS register `index`. The caller holds [`CMOS_LOCK`].
+fn write_register(index: u8, value: u8) {
+    // Safety: see `read_register`.
+    unsafe {
+        CMOS.write_only(0).write(index);
+        CMOS.port(1).write(value);
+    }
+}
+
+/// Wait for an update in progress to finish; `false` if it does not.
+fn wait_for_update() -> bool {
+    for _ in 0..UPDATE_SPINS {
+        if read_register(STATUS_A) & UPDATE_IN_PROGRESS == 0 {
+            return true;
+        }
+        spin_loop();
+    }
+    false
+}
+
+fn read_raw() -> RawTime {
+    let century = CENTURY_REGISTER.load(Ordering::Relaxed);
+    RawTime {
+        second: read_register(SECONDS),
+        minute: read_register(MINUTES),
+        hour: read_register(HOURS),
+        day: read_register(DAY),
+        month: read_register(MONTH),
+        year: read_register(YEAR),
+        century: (century != 0).then(|| read_register(century)),
+    }
+}
+
+/// The current date and time, or `None` if the RTC does not give a valid
+/// one.
+pub fn read() -> Option<DateTime> {
+    let _irq = IrqGuard::new();
+    let _cmos = CMOS_LOCK.lock();
+    for _ in 0..READ_ATTEMPTS {
+        if !wait_for_update() {
+            return None;
+        }
+        let first = read_raw();
+        if !wait_for_update() {
+            return None;
+        }
+        if read_raw() == first {
+            return first.decode(read_register(STATUS_B));
+        }
+    }
+    None
+}
+
+/// Read the RTC and set the wall clock.
+pub fn init() {
+    match acpi::fadt() {
+        Some(fadt) if !fadt.cmos_rtc_present => {
+            info!("The FADT says there is no CMOS RTC");
+            return;
+        }
+        Some(fadt) => {
+            CENTURY_REGISTER.store(fadt.century_register.unwrap_or(0), Ordering::Relaxed);
+        }
+        None => {}
+    }
+
+    let Some(now) = read() else {
+        warn!("The RTC does not hold a valid date; the wall clock is not set");
+        return;
+    };
+    clock::set_wall_clock(now.unix_seconds(), rdtsc());
+    info!("RTC: {now} UTC");
+}
+
+/// Whether `rtc_tick` asks for the RTC to drive the timer tick.
+pub fn tick_requested() -> bool {
+    cmdline::flag(RTC_TICK_PARAM.name)
+}
+
+/// Have the RTC raise the timer interrupt on this CPU [`TICK_HZ`] times a
+/// second.
+pub fn start_tick() -> Result<(), IoApicError> {
+    let apic_id = unsafe { PerCpu::current() }.apic_id;
+    let gsi = ioapic::route_isa_irq(RTC_IRQ, LAPIC_TIMER_VECTOR, apic_id)?;
+
+    {
+        let _irq = IrqGuard::new();
+        let _cmos = CMOS_LOCK.lock();
+        let a = read_register(STATUS_A);
+        write_register(STATUS_A, (a & !RATE_MASK) | RATE_1024_HZ);
+        let b = read_register(STATUS_B);
+        write_register(STATUS_B, b | PERIODIC_INTERRUPT);
+        TICKING.store(true, Ordering::Release);
+        // Clear a pending interrupt so that the next one is raised.
+        read_register(STATUS_C);
+    }
+
+    clock::set_timer_hz(TICK_HZ);
+    info!("RTC ticks at {TICK_HZ} Hz on GSI {gsi}");
+    Ok(())
+}
+
+/// Let the RTC raise its next periodic interrupt, if it drives the tick.
+///
+/// Called from the timer interrupt.
+pub fn acknowledge_tick() {
+    if TICKING.load(Ordering::Acquire) {
+        let _irq = IrqGuard::new();
+        let _cmos = CMOS_LOCK.lock();
+        read_register(STATUS_C);
+    }
+}
@@ -0,0 +1,560 @@
+//! # Scheduler
+//!
+//! A priority scheduler over the [process table](crate::process::PROCESSES),
+//! which holds user processes and [kernel threads](crate::process::spawn_kernel_thread)
+//! alike.
+//!
+//! ## Model
+//!
+//! * The CPU goes to the ready process of the highest effective
+//!   [priority](priority); processes of the same priority take turns in
+//!   round-robin order (see [`RunQueue`]). Waking processes are boosted and
+//!   waiting ones age, so no ready process starves.
+//! * Waking up or re-prioritizing a process that outranks the running one
+//!   requests a switch, which happens at the next
+//!   [preemption point](preempt::preempt_point).
+//! * Context switches happen when kernel code calls [`schedule`], e.g. when a
+//!   process blocks in `waitpid` or exits, and when the timer
+//!   [preempts](crate::preempt) a process whose time slice ran out. Each
+//!   switch starts a fresh slice.
+//! * The context that enters [`run_idle`] (the boot path) becomes the CPU's
+//!   **idle context**. It runs whenever no process is ready and has no entry
+//!   in the process table, so it ranks below every priority.
+//! * Switching to a process activates its address space and points the
+//!   per-CPU kernel stack (syscall stack and TSS `rsp0`) at its kernel stack.
+//!   Each address space, including the idle context's, carries a PCID tag, so
+//!   with PCIDs enabled the switch keeps the TLB warm; the cycles spent on CR3
+//!   loads are accounted in [`CpuLoad::mm_switch_tsc`].
+//! * FP/SIMD registers are saved into the outgoing process' [`FpuState`](fpu::FpuState) and
+//!   loaded from the incoming one's (see [`fpu`]); the idle context has none.
+//!   The incoming process' FS base (its thread pointer) is loaded as well.
+//! * Kernel code blocks on a [`WaitQueue`] until a condition holds; processes
+//!   blocked with a timeout are made ready again by a [timer](crate::timer)
+//!   once the deadline (in timer ticks) has passed. [`sleep`] blocks for a
+//!   fixed time; [`sleep_until_tsc`] until a TSC deadline, see below.
+//! * The switch records the CPU in the incoming process' table entry.
+//!
+//! ## Affinity and remote wakeups
+//!
+//! All CPUs share the table's run queue, and each takes the most important
+//! process whose [affinity](crate::process::Process::affinity) includes it;
+//! [`set_affinity`] changes the mask. A CPU that runs out of work thus pulls
+//! from the same queue the busy ones push preempted processes back to.
+//!
+//! Whenever a process becomes ready — woken, timed out, re-prioritized,
+//! re-bound, or preempted on a CPU it may no longer use or that has
+//! something better to do — a CPU is chosen to run it, in this order:
+//!
+//! 1. the CPU it last ran on, if idle (its caches may still be warm);
+//! 2. any other idle CPU it may run on;
+//! 3. the allowed CPU running the least important process, if the ready one
+//!    outranks it.
+//!
+//! The chosen CPU is asked to reschedule: the current one directly, any
+//! other with a [reschedule IPI](crate::interrupts::resched), which ends an
+//! idle CPU's sleep or preempts a process in user mode right away instead
+//! of at the next tick. Without a choice, the process waits for the next
+//! allowed CPU to switch. Offline CPUs are never chosen; a process bound
+//! only to those waits until one comes back.
+//!
+//! ## Sleeping until a deadline
+//!
+//! [`sleep_until_tsc`] backs the `nanosleep` system calls. The timer wheel
+//! only wakes on tick boundaries, so the process blocks for as many whole
+//! ticks as still fit before the TSC deadline, re-checking the TSC each time
+//! it wakes up; the last stretch below [`SLEEP_SPIN_NS`] is waited out on
+//! the TSC. The sleep therefore never ends before the deadline and at most
+//! one tick after it. The local APIC timer stays periodic, as it drives the
+//! scheduler, so its TSC-deadline mode is not used.
+//!
+//! A signal that would be acted upon cuts the sleep short:
+//! [`signal::send`](crate::signal::send) wakes the sleeper through
+//! [`interrupt_sleep`].
+//!
+//! ## Idle and load
+//!
+//! With nothing to run, the idle context sleeps via [`idle::enter`] until the
+//! next interrupt. A CPU going [offline](hotplug) picks no more processes and
+//! parks in the idle loop instead. Each CPU tracks its run-queue length,
+//! context switches and time spent idle; [`load`] returns a snapshot.
+//!
+//! ## Safety
+//!
+//! All scheduling decisions are made with interrupts disabled and the process
+//! table locked. The lock is released *before* the actual stack switch, so the
+//! resumed context never inherits a held lock.
+
+pub mod priority;
+mod run_queue;
+mod wait_queue;
+
+pub use crate::sched::run_queue::RunQueue;
+pub use crate::sched::wait_queue::WaitQueue;
+
+use crate::alloc::switch_address_space;
+use crate::apic;
+use crate::clock;
+use crate::fpu;
+use crate::idle;
+use crate::interrupts::resched::RESCHED_IPI_VECTOR;
+use crate::per_cpu::stack;
+use crate::per_cpu::{self, PerCpu};
+use crate::preempt;
+use crate::process::context::{Context, switch_context};
+use crate::process::{MAX_PROCESSES, PROCESSES, Pid, ProcessState, ProcessTable};
+use crate::profiler;
+use crate::sched::priority::{AGING_MS, Priority};
+use crate::tracepoint::trace_event;
+use crate::tsc::rdtsc;
+use crate::{hotplug, poll, signal, timer, watchdog, workqueue};
+use core::sync::atomic::Ordering;
+use kernel_registers::StoreRegisterUnsafe;
+use kernel_registers::msr::Ia32FsBaseMsr;
+use kernel_sync::{IrqGuard, SyncOnceCell};
+use kernel_vmem::address_space::RootPage;
+use kernel_vmem::pcid::PcidTag;
+use kernel_vmem::read_cr3_phys;
+use log::debug;
+
+/// Saved context of the idle loop while a process runs.
+static mut IDLE_CONTEXT: Context = Context { rsp: 0 };
+
+/// PCID assignment of [`KERNEL_ROOT`].
+static mut IDLE_PCID: PcidTag = PcidTag::new();
+
+/// Address space used while idling (the kernel's own).
+static KERNEL_ROOT: SyncOnceCell<RootPage> = SyncOnceCell::new();
+
+/// Processes in [`sleep`] or [`sleep_until_tsc`]; timed out, or woken by
+/// [`interrupt_sleep`].
+static SLEEPING: WaitQueue = WaitQueue::new();
+
+/// A sleep with less than this left is finished by spinning on the TSC
+/// rather than waiting for a tick.
+pub const SLEEP_SPIN_NS: u64 = 20_000;
+
+/// TSC value when the idle loop started; the reference for load metrics.
+static IDLE_SINCE: SyncOnceCell<u64> = SyncOnceCell::new();
+
+/// Load metrics of the current CPU; see [`load`].
+#[derive(Debug, Copy, Clone)]
+#[allow(dead_code)]
+pub struct CpuLoad {
+    /// Ready or running processes as of the last scheduling decision.
+    pub runnable: u32,
+    /// Context switches so far.
+    pub ctx_switches: u64,
+    /// Times the CPU went to sleep in the idle loop.
+    pub idle_entries: u64,
+    /// TSC cycles spent sleeping.
+    pub idle_tsc: u64,
+    /// TSC cycles since the idle loop started.
+    pub elapsed_tsc: u64,
+    /// TSC cycles spent switching address spaces.
+    pub mm_switch_tsc: u64,
+}
+
+#[allow(dead_code)]
+impl CpuLoad {
+    /// Share of the elapsed time spent idle, in per mille.
+    pub fn idle_permille(&self) -> u64 {
+        if self.elapsed_tsc == 0 {
+            return 0;
+        }
+        let permille = u128::from(self.idle_tsc) * 1000 / u128::from(self.elapsed_tsc);
+        u64::try_from(permille.min(1000)).unwrap_or(1000)
+    }
+}
+
+/// PID of the process running on this CPU, or `None` while idle.
+pub fn current_pid() -> Option<Pid> {
+    let cpu = unsafe { PerCpu::current() };
+    Pid::from_raw(u64::from(cpu.current_pid.load(Ordering::Acquire)))
+}
+
+/// Timer ticks elapsed on this CPU since the local APIC timer was started.
+pub fn now_ticks() -> u64 {
+    let cpu = unsafe { PerCpu::current() };
+    cpu.ticks.load(Ordering::Acquire)
+}
+
+/// Give up the CPU and switch to the next ready process (or the idle loop).
+///
+/// A running caller stays runnable and is queued behind the ready processes
+/// of its priority; a caller that marked itself as blocked before calling
+/// this only returns once it was made ready again.
+pub fn schedule() {
+    let _irq = IrqGuard::new();
+    let cpu = unsafe { PerCpu::current() };
+
+    let now = now_ticks();
+    let mut table = PROCESSES.lock();
+    let mut left = table.expire_timeouts(now);
+    table.age(now, (clock::timer_hz() * AGING_MS / 1000).max(1));
+    watchdog::feed();
+
+    let current = current_pid().and_then(|pid| table.find(pid));
+    if let Some(slot) = current
+        && table
+            .get(slot)
+            .is_some_and(|p| p.state == ProcessState::Running)
+    {
+        table.make_ready(slot, now);
+        left |= 1 << slot;
+    }
+
+    let next = if cpu.hotplug.is_online() {
+        table.pick_next(now, cpu.cpu_id)
+    } else {
+        None
+    };
+    // Whatever became ready but stays queued may fit another CPU.
+    for slot in (0..MAX_PROCESSES).filter(|&slot| left & (1 << slot) != 0) {
+        if Some(slot) != next {
+            place(&table, slot);
+        }
+    }
+    preempt::on_switch();
+    cpu.nr_runnable.store(
+        u32::try_from(table.runnable()).unwrap_or(u32::MAX),
+        Ordering::Relaxed,
+    );
+    if next == current {
+        return;
+    }
+
+    let prev_rsp: *mut u64 = match current.and_then(|slot| table.get_mut(slot)) {
+        Some(p) => {
+            unsafe { fpu::save(&mut p.fpu) };
+            &raw mut p.context.rsp
+        }
+        None => unsafe { &raw mut IDLE_CONTEXT.rsp },
+    };
+
+    let switch_start = rdtsc();
+    let next_rsp = if let Some(p) = next.and_then(|slot| table.get_mut(slot)) {
+        unsafe {
+            PerCpu::set_current_kstack_top(p.kstack_top);
+            switch_address_space(p.root, &mut p.pcid);
+            fpu::restore(&p.fpu);
+            Ia32FsBaseMsr::new().with_ptr(p.fs_base).store_unsafe();
+        }
+        cpu.current_pid.store(p.pid.as_u32(), Ordering::Release);
+        p.cpu = Some(cpu.cpu_id);
+        p.context.rsp
+    } else {
+        let root = KERNEL_ROOT.get().expect("scheduler idle loop not running");
+        let tag = &raw mut IDLE_PCID;
+        unsafe { switch_address_space(*root, &mut *tag) };
+        cpu.current_pid.store(0, Ordering::Release);
+        unsafe { IDLE_CONTEXT.rsp }
+    };
+    cpu.mm_switch_tsc
+        .fetch_add(rdtsc().wrapping_sub(switch_start), Ordering::Relaxed);
+
+    let from = current.and_then(|slot| table.get(slot)).map(|p| p.pid);
+    let to = next.and_then(|slot| table.get(slot)).map(|p| p.pid);
+    trace_event!(sched_switch, from, to);
+
+    cpu.ctx_switches.fetch_add(1, Ordering::Relaxed);
+    drop(table);
+    stack::check_canaries();
+    unsafe { switch_context(prev_rsp, next_rsp) };
+}
+
+/// Mark the process `pid` as blocked, optionally until timer tick `until`.
+///
+/// The caller still runs until it calls [`schedule`].
+pub fn block(pid: Pid, until: Option<u64>) {
+    let _irq = IrqGuard::new();
+    let mut table = PROCESSES.lock();
+    if let Some(p) = table.find(pid).and_then(|slot| table.get_mut(slot)) {
+        p.sched.stopped(now_ticks());
+        p.state = ProcessState::Blocked { until };
+        // Without a timer, the next `schedule` notices the timeout instead.
+        p.timeout = until.and_then(|deadline| timer::schedule(deadline, expire_timeouts, 0));
+        trace_event!(sched_block, pid, until.unwrap_or(0));
+    }
+}
+
+/// Undo [`block`] for the current process `pid`, which found what it waits
+/// for before calling [`schedule`]; it keeps running, whether or not it was
+/// woken in between.
+pub fn resume(pid: Pid) {
+    let _irq = IrqGuard::new();
+    let mut table = PROCESSES.lock();
+    if let Some(slot) = table.find(pid) {
+        table.resume(slot, now_ticks());
+    }
+}
+
+/// Block the current process for `ticks` timer ticks.
+pub fn sleep(ticks: u64) {
+    SLEEPING.wait_until_timeout(|| false, ticks);
+}
+
+/// Block the current process until the TSC reaches `deadline`; see the
+/// [module docs](self#sleeping-until-a-deadline).
+///
+/// Returns `false` if a pending signal ended the sleep early. Before the
+/// clocks are calibrated, spins all the way.
+pub fn sleep_until_tsc(deadline: u64) -> bool {
+    let me = current_pid();
+    let interrupted = || me.is_some_and(signal::interrupts);
+    let params = clock::params();
+    let tsc_per_tick = params.tsc_hz.checked_div(params.timer_hz).unwrap_or(0);
+    let spin = params.tsc_hz.saturating_mul(SLEEP_SPIN_NS) / 1_000_000_000;
+
+    loop {
+        let now = rdtsc();
+        if now >= deadline {
+            return true;
+        }
+        if interrupted() {
+            return false;
+        }
+
+        let left = deadline - now;
+        if tsc_per_tick == 0 || left <= spin {
+            core::hint::spin_loop();
+            continue;
+        }
+        // The current tick may be about to end, so a whole number of ticks
+        // can end early; the loop makes up for it.
+        SLEEPING.wait_until_timeout(interrupted, left.div_ceil(tsc_per_tick));
+    }
+}
+
+/// End the sleep of `pid` in [`sleep_until_tsc`] or [`poll::wait`] if a
+/// signal interrupts it.
+///
+/// Returns `true` if the process was sleeping.
+pub fn interrupt_sleep(pid: Pid) -> bool {
+    SLEEPING.wake(pid) || poll::interrupt(pid)
+}
+
+/// Make the blocked process `pid` ready again.
+///
+/// Returns `false` if the process does not exist or was not blocked.
+pub fn wake(pid: Pid) -> bool {
+    let _irq = IrqGuard::new();
+    wake_in(&mut PROCESSES.lock(), pid)
+}
+
+/// Like [`wake`], but gives up rather than wait for the process table, which
+/// the caller may be holding.
+///
+/// Returns `true` if the process was woken.
+pub fn try_wake(pid: Pid) -> bool {
+    let _irq = IrqGuard::new();
+    PROCESSES
+        .try_lock()
+        .is_some_and(|mut table| wake_in(&mut table, pid))
+}
+
+fn wake_in(table: &mut ProcessTable, pid: Pid) -> bool {
+    let Some(slot) = table.find(pid).filter(|&slot| {
+        table
+            .get(slot)
+            .is_some_and(|p| matches!(p.state, ProcessState::Blocked { .. }))
+    }) else {
+        return false;
+    };
+
+    table.make_ready(slot, now_ticks());
+    trace_event!(sched_wakeup, pid);
+    place(table, slot);
+    true
+}
+
+/// Set the base priority of the process `pid` and return the previous one,
+/// or `None` if there is no such process.
+pub fn set_priority(pid: Pid, priority: Priority) -> Option<Priority> {
+    let _irq = IrqGuard::new();
+    let mut table = PROCESSES.lock();
+    let slot = table.find(pid)?;
+    let old = table.set_priority(slot, priority)?;
+    debug!(
+        "Priority of process {pid} set to {level}",
+        level = priority.level()
+    );
+    place(&table, slot);
+    resched_if_outranked(&table);
+    Some(old)
+}
+
+/// Restrict the process `pid` to the CPUs in `mask` (bit `n` for CPU `n`)
+/// and return the previous mask.
+///
+/// Returns `None` if there is no such process or `mask` names no online
+/// CPU. A process running on a CPU it may no longer use is moved at that
+/// CPU's next [preemption point](preempt::preempt_point).
+pub fn set_affinity(pid: Pid, mask: u64) -> Option<u64> {
+    if mask & online_cpus() == 0 {
+        return None;
+    }
+
+    let _irq = IrqGuard::new();
+    let mut table = PROCESSES.lock();
+    let slot = table.find(pid)?;
+    let p = table.get_mut(slot)?;
+    let old = core::mem::replace(&mut p.affinity, mask);
+    debug!("Affinity of process {pid} set to {mask:#x}");
+
+    match (p.state, p.cpu.and_then(per_cpu::by_id)) {
+        (ProcessState::Running, Some(cpu)) if !p.may_run_on(cpu.cpu_id) => kick(cpu),
+        (ProcessState::Ready, _) => place(&table, slot),
+        _ => {}
+    }
+    Some(old)
+}
+
+/// Timer callback: wake the processes whose timeout expired.
+fn expire_timeouts(_: usize) {
+    let _irq = IrqGuard::new();
+    let mut table = PROCESSES.lock();
+    let woken = table.expire_timeouts(now_ticks());
+    for slot in (0..MAX_PROCESSES).filter(|&slot| woken & (1 << slot) != 0) {
+        place(&table, slot);
+    }
+}
+
+/// Request a switch if a ready process that may run here outranks the
+/// running one.
+fn resched_if_outranked(table: &ProcessTable) {
+    let cpu = unsafe { PerCpu::current() };
+    if let (Some(running), Some(ready)) = (
+        running_priority(table, cpu),
+        table.highest_ready_for(cpu.cpu_id),
+    ) && ready > running
+    {
+        preempt::request_resched();
+    }
+}
+
+/// Have a CPU switch to the ready process in `slot` if one should; see
+/// [remote wakeups](self#affinity-and-remote-wakeups).
+fn place(table: &ProcessTable, slot: usize) {
+    let Some(p) = table.get(slot).filter(|p| p.state == ProcessState::Ready) else {
+        return;
+    };
+    let allowed = |cpu: &PerCpu| cpu.hotplug.is_online() && p.may_run_on(cpu.cpu_id);
+    let idle = |cpu: &PerCpu| running_priority(table, cpu).is_none();
+
+    let last = p
+        .cpu
+        .and_then(per_cpu::by_id)
+        .filter(|&cpu| allowed(cpu) && idle(cpu));
+    let target = last
+        .or_else(|| per_cpu::cpus().find(|&cpu| allowed(cpu) && idle(cpu)))
+        .or_else(|| {
+            per_cpu::cpus()
+                .filter(|&cpu| allowed(cpu))
+                .filter_map(|cpu| Some((running_priority(table, cpu)?, cpu)))
+                .filter(|&(running, _)| running < p.sched.priority())
+                .min_by_key(|&(running, _)| running)
+                .map(|(_, cpu)| cpu)
+        });
+    if let Some(cpu) = target {
+        trace_event!(sched_place, p.pid, cpu.cpu_id);
+        kick(cpu);
+    }
+}
+
+/// Priority of the process running on `cpu`, or `None` if it has none (it
+/// is idle or just switching away from a blocked process).
+fn running_priority(table: &ProcessTable, cpu: &PerCpu) -> Option<Priority> {
+    Pid::from_raw(u64::from(cpu.current_pid.load(Ordering::Acquire)))
+        .and_then(|pid| table.find(pid))
+        .and_then(|slot| table.get(slot))
+        .filter(|p| p.state == ProcessState::Running)
+        .map(|p| p.sched.priority())
+}
+
+/// Ask `cpu` to reschedule: this one at its next preemption point, any
+/// other through a [reschedule IPI](RESCHED_IPI_VECTOR).
+fn kick(cpu: &PerCpu) {
+    let me = unsafe { PerCpu::current() };
+    if core::ptr::eq(cpu, me) {
+        preempt::request_resched();
+    } else {
+        unsafe { apic::send_ipi_x2apic(cpu.apic_id, RESCHED_IPI_VECTOR) };
+    }
+}
+
+/// The online CPUs, one bit each.
+pub fn online_cpus() -> u64 {
+    per_cpu::cpus()
+        .filter(|cpu| cpu.hotplug.is_online() && cpu.cpu_id < u64::BITS)
+        .fold(0, |mask, cpu| mask | 1 << cpu.cpu_id)
+}
+
+/// A snapshot of the current CPU's load metrics.
+#[allow(dead_code)]
+pub fn load() -> CpuLoad {
+    let cpu = unsafe { PerCpu::current() };
+    let since = IDLE_SINCE.get().copied().unwrap_or_else(rdtsc);
+    CpuLoad {
+        runnable: cpu.nr_runnable.load(Ordering::Relaxed),
+        ctx_switches: cpu.ctx_switches.load(Ordering::Relaxed),
+        idle_entries: cpu.idle_entries.load(Ordering::Relaxed),
+        idle_tsc: cpu.idle_tsc.load(Ordering::Relaxed),
+        elapsed_tsc: rdtsc().wrapping_sub(since),
+        mm_switch_tsc: cpu.mm_switch_tsc.load(Ordering::Relaxed),
+    }
+}
+
+/// Turn the calling context into this CPU's idle loop and start scheduling.
+pub fn run_idle() -> ! {
+    KERNEL_ROOT.get_or_init(|| unsafe { read_cr3_phys() }.page());
+    IDLE_SINCE.get_or_init(rdtsc);
+
+    let cpu = unsafe { PerCpu::current() };
+    let mut streak = 0u32;
+    let mut switches = cpu.ctx_switches.load(Ordering::Relaxed);
+    report_stack_usage(0);
+
+    loop {
+        schedule();
+        hotplug::park_if_requested();
+
+        // Any context switch since the last sleep means the CPU was busy.
+        let now = cpu.ctx_switches.load(Ordering::Relaxed);
+        streak = if now == switches {
+            streak.saturating_add(1)
+        } else {
+            0
+        };
+        switches = now;
+
+        idle::enter(streak);
+    }
+}
+
+/// Run ready processes from the calling context until `done` holds.
+///
+/// Like [`run_idle`], the caller becomes the idle context meanwhile; lets the
+/// in-kernel tests run kernel threads before the boot path got that far.
+#[cfg(feature = "ktest")]
+pub fn run_until(done: impl Fn() -> bool) {
+    KERNEL_ROOT.get_or_init(|| unsafe { read_cr3_phys() }.page());
+    while !done() {
+        schedule();
+        core::hint::spin_loop();
+    }
+}
+
+/// Work item: log stack usage, then again every second.
+fn report_stack_usage(_: usize) {
+    stack::report_usage();
+    let _ = workqueue::schedule_delayed_work(report_stack_usage, 0, 1000);
+}
+
+/// Kernel thread: log a profiler report every
+/// [`REPORT_INTERVAL_SECS`](profiler::REPORT_INTERVAL_SECS).
+pub fn report_profile(_: usize) {
+    loop {
+        sleep(timer::ms_to_ticks(profiler::REPORT_INTERVAL_SECS * 1000));
+        profiler::log_report();
+    }
+}
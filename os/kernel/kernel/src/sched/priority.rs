@@ -0,0 +1,138 @@
+//! # Priorities and Accounting
+//!
+//! Every process has a *base* [`Priority`], set with `setpriority`, and a
+//! temporary *boost* on top of it. The scheduler picks by the sum, the
+//! process' [effective priority](SchedInfo::priority):
+//!
+//! * A process woken up from sleeping gets [`WAKE_BOOST`] levels, so
+//!   processes that mostly wait respond quickly.
+//! * A ready process gains another level every [`AGING_MS`] it waits for the
+//!   CPU. Given time, it catches up with any busy process, so nothing starves.
+//! * The boost is dropped as soon as the process gets the CPU.
+//!
+//! [`SchedInfo`] also accounts the timer ticks a process spent running,
+//! waiting for the CPU and sleeping.
+
+use stdlib::syscall_abi::priority;
+
+/// Number of priority levels.
+pub const PRIORITY_LEVELS: usize = priority::MAX as usize + 1;
+
+/// Levels a process gains when it is woken up.
+pub const WAKE_BOOST: u8 = 1;
+
+/// Time a ready process waits before it gains a level.
+pub const AGING_MS: u64 = 50;
+
+/// A scheduling priority; higher values run first.
+///
+/// The idle loop is not a process and runs below the lowest priority.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub struct Priority(u8);
+
+impl Priority {
+    pub const DEFAULT: Self = Self(priority::DEFAULT);
+    pub const MAX: Self = Self(priority::MAX);
+
+    /// The priority `level`, if in range.
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn new(level: u64) -> Option<Self> {
+        if level <= priority::MAX as u64 {
+            Some(Self(level as u8))
+        } else {
+            None
+        }
+    }
+
+    pub const fn level(self) -> u8 {
+        self.0
+    }
+
+    /// `levels` above this priority, at most [`Priority::MAX`].
+    #[must_use]
+    pub const fn raised(self, levels: u8) -> Self {
+        let level = self.0.saturating_add(levels);
+        if level > priority::MAX {
+            Self::MAX
+        } else {
+            Self(level)
+        }
+    }
+}
+
+/// Scheduling parameters and accounting of a process, in timer ticks.
+#[derive(Debug, Copy, Clone)]
+pub struct SchedInfo {
+    /// Priority set for the process.
+    pub base: Priority,
+    /// Levels currently granted on top of `base`.
+    boost: u8,
+    /// Tick of the last state change.
+    since: u64,
+    /// Tick the process last gained a level while waiting for the CPU.
+    aged_at: u64,
+    /// Ticks spent running.
+    pub run_ticks: u64,
+    /// Ticks spent ready, waiting for the CPU.
+    pub wait_ticks: u64,
+    /// Ticks spent blocked.
+    pub sleep_ticks: u64,
+    /// Times the process was woken up.
+    pub wakeups: u64,
+}
+
+impl SchedInfo {
+    /// A process that becomes ready at tick `now`.
+    pub const fn new(base: Priority, now: u64) -> Self {
+        Self {
+            base,
+            boost: 0,
+            since: now,
+            aged_at: now,
+            run_ticks: 0,
+            wait_ticks: 0,
+            sleep_ticks: 0,
+            wakeups: 0,
+        }
+    }
+
+    /// The priority the scheduler picks the process by.
+    pub const fn priority(&self) -> Priority {
+        self.base.raised(self.boost)
+    }
+
+    /// The process got the CPU at tick `now`.
+    pub const fn started(&mut self, now: u64) {
+        self.wait_ticks += now.saturating_sub(self.since);
+        self.since = now;
+        self.boost = 0;
+    }
+
+    /// The running process stopped running (to become ready or blocked) at
+    /// tick `now`.
+    pub const fn stopped(&mut self, now: u64) {
+        self.run_ticks += now.saturating_sub(self.since);
+        self.since = now;
+        self.aged_at = now;
+    }
+
+    /// The blocked process was woken up at tick `now`.
+    pub const fn woken(&mut self, now: u64) {
+        self.sleep_ticks += now.saturating_sub(self.since);
+        self.wakeups += 1;
+        self.since = now;
+        self.aged_at = now;
+        self.boost = self.boost.saturating_add(WAKE_BOOST);
+    }
+
+    /// Raise the ready process by one level if it waited `interval` ticks
+    /// since it was last raised. Returns whether its priority changed.
+    pub const fn age(&mut self, now: u64, interval: u64) -> bool {
+        if now.saturating_sub(self.aged_at) < interval || self.priority().0 == priority::MAX {
+            return false;
+        }
+        self.aged_at = now;
+        self.boost += 1;
+        true
+    }
+}
@@ -0,0 +1,138 @@
+//! # Run Queues
+//!
+//! A [`RunQueue`] holds the table slots of all ready processes in one FIFO
+//! per [priority](Priority) level. A bitmap of non-empty levels finds the
+//! highest one without scanning; within a level, processes take turns.
+//!
+//! The `_where` variants skip processes a predicate rejects, e.g. those whose
+//! [affinity](crate::process::Process::affinity) excludes the picking CPU.
+
+use crate::process::MAX_PROCESSES;
+use crate::sched::priority::{PRIORITY_LEVELS, Priority};
+
+// One bit per level.
+const _: () = assert!(PRIORITY_LEVELS <= u32::BITS as usize);
+
+/// Ready processes (as table slots) by priority.
+pub struct RunQueue {
+    levels: [Level; PRIORITY_LEVELS],
+    /// Bit `n` is set if level `n` is not empty.
+    nonempty: u32,
+}
+
+impl Default for RunQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RunQueue {
+    pub const fn new() -> Self {
+        Self {
+            levels: [const { Level::new() }; PRIORITY_LEVELS],
+            nonempty: 0,
+        }
+    }
+
+    /// Queue `slot` behind the other processes of `priority`.
+    pub fn push_back(&mut self, priority: Priority, slot: usize) {
+        let level = priority.level();
+        self.levels[usize::from(level)].push_back(slot);
+        self.nonempty |= 1 << level;
+    }
+
+    /// Take the first process of the highest non-empty level.
+    #[allow(dead_code)]
+    pub fn pop_front(&mut self) -> Option<usize> {
+        self.pop_front_where(|_| true)
+    }
+
+    /// Take the first process `eligible` accepts, from the highest level
+    /// that has one.
+    pub fn pop_front_where(&mut self, eligible: impl Fn(usize) -> bool) -> Option<usize> {
+        let (priority, slot) = self.first_where(eligible)?;
+        let level = priority.level();
+        let queue = &mut self.levels[usize::from(level)];
+        queue.remove(slot);
+        if queue.len == 0 {
+            self.nonempty &= !(1 << level);
+        }
+        Some(slot)
+    }
+
+    /// Level and slot of the process [`pop_front_where`](Self::pop_front_where)
+    /// would take, leaving it queued.
+    pub fn first_where(&self, eligible: impl Fn(usize) -> bool) -> Option<(Priority, usize)> {
+        let mut bits = self.nonempty;
+        while let Some(level) = bits.checked_ilog2() {
+            bits &= !(1 << level);
+            let queue = &self.levels[level as usize];
+            if let Some(&slot) = queue.slots[..queue.len].iter().find(|&&s| eligible(s)) {
+                return Some((Priority::new(u64::from(level))?, slot));
+            }
+        }
+        None
+    }
+
+    /// Take `slot` out of whichever level it is queued on.
+    ///
+    /// Returns `false` if it was not queued.
+    pub fn remove(&mut self, slot: usize) -> bool {
+        let mut bits = self.nonempty;
+        while bits != 0 {
+            let level = bits.trailing_zeros();
+            bits &= bits - 1;
+
+            let queue = &mut self.levels[level as usize];
+            if queue.remove(slot) {
+                if queue.len == 0 {
+                    self.nonempty &= !(1 << level);
+                }
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Priority of the highest non-empty level.
+    #[allow(dead_code)]
+    pub fn highest(&self) -> Option<Priority> {
+        Priority::new(u64::from(self.nonempty.checked_ilog2()?))
+    }
+}
+
+/// Fixed-capacity FIFO of table slots (each slot is queued at most once).
+struct Level {
+    slots: [usize; MAX_PROCESSES],
+    len: usize,
+}
+
+impl Level {
+    const fn new() -> Self {
+        Self {
+            slots: [0; MAX_PROCESSES],
+            len: 0,
+        }
+    }
+
+    fn push_back(&mut self, slot: usize) {
+        debug_assert!(
+            !self.slots[..self.len].contains(&slot),
+            "slot {slot} queued twice"
+        );
+        debug_assert!(self.len < MAX_PROCESSES, "run queue overflow");
+        if let Some(entry) = self.slots.get_mut(self.len) {
+            *entry = slot;
+            self.len += 1;
+        }
+    }
+
+    fn remove(&mut self, slot: usize) -> bool {
+        let Some(i) = self.slots[..self.len].iter().position(|&s| s == slot) else {
+            return false;
+        };
+        self.slots.copy_within(i + 1..self.len, i);
+        self.len -= 1;
+        true
+    }
+}
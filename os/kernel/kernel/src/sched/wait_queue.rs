@@ -0,0 +1,207 @@
+//! # Wait Queues
+//!
+//! A [`WaitQueue`] lets kernel code sleep until a condition becomes true
+//! instead of spinning on it.
+//!
+//! ## Protocol
+//!
+//! * The waiter calls [`WaitQueue::wait_until`] with a condition closure.
+//! * Whoever makes the condition true calls [`WaitQueue::wake_one`] or
+//!   [`WaitQueue::wake_all`] *afterwards*.
+//!
+//! The condition is evaluated with the queue locked and interrupts disabled,
+//! and wakers take the same lock, so a wake-up can never fall between the
+//! check and going to sleep.
+//!
+//! ## Waiting on several queues
+//!
+//! [`poll`](crate::poll) waits for the first of several conditions, each
+//! with a queue of its own. It [adds](WaitQueue::add_waiter) the process to
+//! every queue and blocks it *before* checking the conditions, so a wake-up
+//! through any of them after the check makes it ready again.
+//!
+//! ## Waiting without a process
+//!
+//! Outside of a process (early boot, the idle loop) there is nothing to
+//! schedule away from. The caller then halts the CPU until the next interrupt
+//! and re-checks the condition, which at least stops burning cycles.
+//!
+//! ## Lock order
+//!
+//! Queue lock first, then the [process table](crate::process::PROCESSES).
+
+use crate::process::{MAX_PROCESSES, Pid};
+use crate::sched::{self, current_pid, now_ticks};
+use core::hint::spin_loop;
+use kernel_sync::irq::wait_for_interrupt;
+use kernel_sync::{IrqGuard, SpinMutex};
+
+/// A FIFO of processes blocked until some condition holds.
+pub struct WaitQueue {
+    waiters: SpinMutex<Waiters>,
+}
+
+impl Default for WaitQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WaitQueue {
+    /// An empty wait queue.
+    pub const fn new() -> Self {
+        Self {
+            waiters: SpinMutex::new(Waiters::new()),
+        }
+    }
+
+    /// Block until `cond` returns `true`.
+    pub fn wait_until(&self, cond: impl FnMut() -> bool) {
+        self.wait(cond, None);
+    }
+
+    /// Block until `cond` returns `true` or `timeout_ticks` timer ticks passed.
+    ///
+    /// Returns `true` if the condition was met, `false` on timeout.
+    pub fn wait_until_timeout(&self, cond: impl FnMut() -> bool, timeout_ticks: u64) -> bool {
+        self.wait(cond, Some(now_ticks().saturating_add(timeout_ticks)))
+    }
+
+    /// Queue `pid` to be woken like a waiter, without blocking it; see
+    /// [waiting on several queues](self#waiting-on-several-queues).
+    pub fn add_waiter(&self, pid: Pid) {
+        let _irq = IrqGuard::new();
+        self.waiters.lock().push_back(pid);
+    }
+
+    /// Take `pid` off the queue, if it is queued.
+    pub fn remove_waiter(&self, pid: Pid) {
+        let _irq = IrqGuard::new();
+        self.waiters.lock().remove(pid);
+    }
+
+    /// Wake the longest-waiting process, if any.
+    ///
+    /// Returns `true` if a process was woken.
+    pub fn wake_one(&self) -> bool {
+        let _irq = IrqGuard::new();
+        let mut waiters = self.waiters.lock();
+        while let Some(pid) = waiters.pop_front() {
+            if sched::wake(pid) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Wake `pid` if it waits here; it re-checks its condition.
+    ///
+    /// Returns `true` if the process was woken.
+    pub fn wake(&self, pid: Pid) -> bool {
+        let _irq = IrqGuard::new();
+        let mut waiters = self.waiters.lock();
+        waiters.contains(pid) && {
+            waiters.remove(pid);
+            sched::wake(pid)
+        }
+    }
+
+    /// Wake all waiting processes and return how many were woken.
+    pub fn wake_all(&self) -> usize {
+        let _irq = IrqGuard::new();
+        let mut waiters = self.waiters.lock();
+        let mut woken = 0;
+        while let Some(pid) = waiters.pop_front() {
+            if sched::wake(pid) {
+                woken += 1;
+            }
+        }
+        woken
+    }
+
+    fn wait(&self, mut cond: impl FnMut() -> bool, deadline: Option<u64>) -> bool {
+        let me = current_pid();
+
+        loop {
+            let irq = IrqGuard::new();
+            {
+                let mut waiters = self.waiters.lock();
+                let done = if cond() {
+                    Some(true)
+                } else if deadline.is_some_and(|d| now_ticks() >= d) {
+                    Some(false)
+                } else {
+                    None
+                };
+
+                if let Some(met) = done {
+                    if let Some(pid) = me {
+                        waiters.remove(pid);
+                    }
+                    return met;
+                }
+
+                if let Some(pid) = me {
+                    waiters.push_back(pid);
+                    sched::block(pid, deadline);
+                }
+            }
+
+            if me.is_some() {
+                sched::schedule();
+            } else if irq.were_enabled() {
+                wait_for_interrupt();
+            } else {
+                // Nothing can wake us up; poll.
+                spin_loop();
+            }
+        }
+    }
+}
+
+/// Fixed-capacity FIFO of waiting PIDs (each process waits at most once).
+struct Waiters {
+    pids: [Option<Pid>; MAX_PROCESSES],
+    len: usize,
+}
+
+impl Waiters {
+    const fn new() -> Self {
+        Self {
+            pids: [None; MAX_PROCESSES],
+            len: 0,
+        }
+    }
+
+    fn push_back(&mut self, pid: Pid) {
+        if self.contains(pid) {
+            return;
+        }
+
+        // Each process is queued at most once, so at most `MAX_PROCESSES`
+        // entries are ever needed.
+        debug_assert!(self.len < MAX_PROCESSES, "wait queue overflow");
+        if let Some(slot) = self.pids.get_mut(self.len) {
+            *slot = Some(pid);
+            self.len += 1;
+        }
+    }
+
+    fn contains(&self, pid: Pid) -> bool {
+        self.pids[..self.len].contains(&Some(pid))
+    }
+
+    fn pop_front(&mut self) -> Option<Pid> {
+        let pid = self.pids[..self.len].first().copied().flatten()?;
+        self.remove(pid);
+        Some(pid)
+    }
+
+    fn remove(&mut self, pid: Pid) {
+        if let Some(i) = self.pids[..self.len].iter().position(|&p| p == Some(pid)) {
+            self.pids.copy_within(i + 1..self.len, i);
+            self.len -= 1;
+            self.pids[self.len] = None;
+        }
+    }
+}
@@ -0,0 +1,297 @@
+//! # Shared Memory Objects
+//!
+//! A shared memory object is a named, fixed-size set of physical frames that
+//! several processes can map into their address spaces (`mmap` with
+//! `MAP_SHARED`, see [`process::mmap`](crate::process::mmap)). Writes through
+//! one mapping are immediately visible through all others.
+//!
+//! ## Handles
+//!
+//! [`open`] looks an object up by name, creating it (zero-filled) if it does
+//! not exist yet, and returns its [`ShmId`]. Processes hold objects through
+//! [handles](crate::process::handle), which [`dup`] and [`close`] count; a
+//! fork duplicates them, and exiting closes them. An object stays findable
+//! by name for as long as at least one handle to it is open.
+//!
+//! ## Frame lifetime
+//!
+//! Frames are reference counted in the [`frame_table`]: the object itself
+//! holds one reference, and every mapping of a frame adds one (see
+//! [`share_frames`]). Closing the last handle drops the object's reference;
+//! tearing down an address space drops those of its mappings. A frame is
+//! freed when its last reference goes, i.e. once the last handle **and** the
+//! last mapping are gone, in whichever order that happens.
+//!
+//! ## Limits
+//!
+//! * At most [`MAX_SHM_OBJECTS`] objects exist at a time.
+//! * An object spans at most [`MAX_SHM_PAGES`] pages.
+//! * Names are at most [`MAX_SHM_NAME_LEN`] bytes long.
+
+use crate::alloc::{frame_table, with_kernel_frame_alloc};
+use core::fmt;
+use core::num::NonZeroU32;
+use kernel_alloc::frame_info::FrameOwner;
+use kernel_alloc::phys_mapper::HhdmPhysMapper;
+use kernel_memory_addresses::{PageSize, PhysicalPage, Size4K};
+use kernel_sync::{IrqGuard, SpinMutex};
+use kernel_vmem::{PhysFrameAlloc, PhysMapper};
+use log::debug;
+pub use stdlib::syscall_abi::MAX_SHM_NAME_LEN;
+
+/// Maximum number of shared memory objects.
+pub const MAX_SHM_OBJECTS: usize = 16;
+
+/// Maximum size of a shared memory object, in pages.
+pub const MAX_SHM_PAGES: usize = 64;
+
+/// Identifies a shared memory object.
+///
+/// Encodes the table slot and its generation, so the id of a destroyed
+/// object never reaches an object created later in the same slot.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct ShmId(NonZeroU32);
+
+impl ShmId {
+    const SLOT_BITS: u32 = 8;
+
+    #[allow(clippy::cast_possible_truncation)]
+    const fn new(slot: usize, generation: NonZeroU32) -> Self {
+        let raw = generation.get() << Self::SLOT_BITS | slot as u32;
+        Self(NonZeroU32::new(raw).expect("generation is non-zero"))
+    }
+
+    const fn slot(self) -> usize {
+        (self.0.get() & ((1 << Self::SLOT_BITS) - 1)) as usize
+    }
+
+    const fn generation(self) -> u32 {
+        self.0.get() >> Self::SLOT_BITS
+    }
+}
+
+impl fmt::Display for ShmId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "shm#{}", self.0)
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ShmError {
+    /// The name is empty or longer than [`MAX_SHM_NAME_LEN`].
+    InvalidName,
+    /// The size is zero or larger than [`MAX_SHM_PAGES`] pages.
+    InvalidSize,
+    /// The object exists with a smaller size than requested.
+    SizeMismatch,
+    /// [`MAX_SHM_OBJECTS`] objects exist already.
+    TableFull,
+    /// Frames for the object ran out.
+    OutOfMemory,
+    /// The handle does not name an open object.
+    BadHandle,
+    /// The requested range lies outside the object.
+    OutOfRange,
+}
+
+impl fmt::Display for ShmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidName => f.write_str("invalid shared memory name"),
+            Self::InvalidSize => f.write_str("invalid shared memory size"),
+            Self::SizeMismatch => f.write_str("shared memory object is smaller than requested"),
+            Self::TableFull => f.write_str("too many shared memory objects"),
+            Self::OutOfMemory => f.write_str("out of memory"),
+            Self::BadHandle => f.write_str("bad shared memory handle"),
+            Self::OutOfRange => f.write_str("range outside of the shared memory object"),
+        }
+    }
+}
+
+struct ShmObject {
+    name: [u8; MAX_SHM_NAME_LEN],
+    name_len: usize,
+    generation: NonZeroU32,
+    frames: [Option<PhysicalPage<Size4K>>; MAX_SHM_PAGES],
+    pages: usize,
+    handles: usize,
+}
+
+impl ShmObject {
+    fn name(&self) -> &[u8] {
+        &self.name[..self.name_len]
+    }
+
+    fn frames(&self) -> impl Iterator<Item = PhysicalPage<Size4K>> + '_ {
+        self.frames[..self.pages].iter().flatten().copied()
+    }
+}
+
+struct ShmTable {
+    slots: [Option<ShmObject>; MAX_SHM_OBJECTS],
+    /// Generation of the next object created in each slot.
+    generations: [NonZeroU32; MAX_SHM_OBJECTS],
+}
+
+impl ShmTable {
+    // Only evaluated at compile time to initialize `OBJECTS`.
+    #[allow(clippy::large_stack_arrays)]
+    const fn new() -> Self {
+        Self {
+            slots: [const { None }; MAX_SHM_OBJECTS],
+            generations: [NonZeroU32::MIN; MAX_SHM_OBJECTS],
+        }
+    }
+
+    fn get_mut(&mut self, id: ShmId) -> Option<&mut ShmObject> {
+        self.slots
+            .get_mut(id.slot())?
+            .as_mut()
+            .filter(|obj| obj.generation.get() == id.generation())
+    }
+}
+
+static OBJECTS: SpinMutex<ShmTable> = SpinMutex::new(ShmTable::new());
+
+/// Open the object `name`, creating it with `size` bytes if it does not exist.
+///
+/// Opening an existing object needs `size` to be at most its size; a `size`
+/// of `0` opens an existing object of any size.
+pub fn open(name: &[u8], size: u64) -> Result<ShmId, ShmError> {
+    if name.is_empty() || name.len() > MAX_SHM_NAME_LEN {
+        return Err(ShmError::InvalidName);
+    }
+    let pages = usize::try_from(size.div_ceil(Size4K::SIZE)).unwrap_or(usize::MAX);
+
+    let _irq = IrqGuard::new();
+    let mut table = OBJECTS.lock();
+    if let Some((slot, obj)) = table
+        .slots
+        .iter_mut()
+        .enumerate()
+        .find_map(|(slot, obj)| Some((slot, obj.as_mut()?)).filter(|(_, o)| o.name() == name))
+    {
+        if pages > obj.pages {
+            return Err(ShmError::SizeMismatch);
+        }
+        obj.handles += 1;
+        return Ok(ShmId::new(slot, obj.generation));
+    }
+
+    if pages == 0 || pages > MAX_SHM_PAGES {
+        return Err(ShmError::InvalidSize);
+    }
+    let slot = table
+        .slots
+        .iter()
+        .position(Option::is_none)
+        .ok_or(ShmError::TableFull)?;
+
+    let mut frames = [None; MAX_SHM_PAGES];
+    if !allocate_frames(&mut frames[..pages]) {
+        free_frames(frames.iter().flatten().copied());
+        return Err(ShmError::OutOfMemory);
+    }
+
+    let generation = table.generations[slot];
+    table.generations[slot] = generation.checked_add(1).unwrap_or(NonZeroU32::MIN);
+    let mut obj = ShmObject {
+        name: [0; MAX_SHM_NAME_LEN],
+        name_len: name.len(),
+        generation,
+        frames,
+        pages,
+        handles: 1,
+    };
+    obj.name[..name.len()].copy_from_slice(name);
+    table.slots[slot] = Some(obj);
+
+    let id = ShmId::new(slot, generation);
+    debug!(
+        "Created shared memory object {id} ({name:?}, {pages} page(s))",
+        name = core::str::from_utf8(name).unwrap_or("?")
+    );
+    Ok(id)
+}
+
+/// Open another handle to the object `id`, e.g. for a forked child.
+pub fn dup(id: ShmId) -> Result<(), ShmError> {
+    let _irq = IrqGuard::new();
+    let mut table = OBJECTS.lock();
+    let obj = table.get_mut(id).ok_or(ShmError::BadHandle)?;
+    obj.handles += 1;
+    Ok(())
+}
+
+/// Close a handle to the object `id`.
+///
+/// Closing the last handle removes the object's name and drops its frame
+/// references; frames still mapped somewhere live on until they are unmapped.
+pub fn close(id: ShmId) -> Result<(), ShmError> {
+    let _irq = IrqGuard::new();
+    let mut table = OBJECTS.lock();
+    let obj = table.get_mut(id).ok_or(ShmError::BadHandle)?;
+    obj.handles -= 1;
+    if obj.handles > 0 {
+        return Ok(());
+    }
+
+    let obj = table.slots[id.slot()].take().expect("object vanished");
+    debug!("Removed shared memory object {id}");
+    free_frames(obj.frames().filter(|&frame| frame_table().release(frame)));
+    Ok(())
+}
+
+/// Add a reference to the `out.len()` frames of `id` starting at page
+/// `first`, for a new mapping of them, and write them to `out`.
+///
+/// The caller owns the references and must drop each with
+/// [`FrameTable::release`](kernel_alloc::frame_info::FrameTable::release)
+/// when it is unmapped (address space teardown does that).
+pub fn share_frames(
+    id: ShmId,
+    first: usize,
+    out: &mut [PhysicalPage<Size4K>],
+) -> Result<(), ShmError> {
+    let _irq = IrqGuard::new();
+    let mut table = OBJECTS.lock();
+    let obj = table.get_mut(id).ok_or(ShmError::BadHandle)?;
+    let end = first
+        .checked_add(out.len())
+        .filter(|&end| end <= obj.pages)
+        .ok_or(ShmError::OutOfRange)?;
+
+    for (dst, &frame) in out.iter_mut().zip(obj.frames[first..end].iter().flatten()) {
+        frame_table().share(frame);
+        *dst = frame;
+    }
+    Ok(())
+}
+
+/// Fill `frames` with zeroed frames. Returns `false` if memory ran out.
+#[allow(clippy::cast_possible_truncation)]
+fn allocate_frames(frames: &mut [Option<PhysicalPage<Size4K>>]) -> bool {
+    with_kernel_frame_alloc(|alloc| {
+        for slot in frames.iter_mut() {
+            let Some(frame) = alloc.alloc_4k() else {
+                return false;
+            };
+            unsafe {
+                HhdmPhysMapper
+                    .phys_to_mut::<[u8; Size4K::SIZE as usize]>(frame.base())
+                    .fill(0);
+            }
+            frame_table().set_owner(frame, FrameOwner::User);
+            *slot = Some(frame);
+        }
+        true
+    })
+}
+
+fn free_frames(frames: impl Iterator<Item = PhysicalPage<Size4K>>) {
+    with_kernel_frame_alloc(|alloc| {
+        for frame in frames {
+            alloc.free_4k(frame);
+        }
+    });
+}
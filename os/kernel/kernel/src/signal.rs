@@ -0,0 +1,572 @@
+//! # Signals
+//!
+//! Signals notify a process asynchronously: another process sends one with
+//! [`send`] (the `kill` system call), and the kernel raises them for faults
+//! ([`raise_fault`]), broken pipes and exiting children.
+//!
+//! ## Pending signals and dispositions
+//!
+//! Every process keeps a [`SignalState`]: a bitmap of pending signals and,
+//! per signal, what to do about it ([`set_action`], the `sigaction` system
+//! call):
+//!
+//! * **Default**: terminate the process with exit code `128 + signo`, except
+//!   for `SIGCHLD`, which is ignored.
+//! * **Ignore**: discard the signal.
+//! * **Handler**: run a user function, see below.
+//!
+//! `SIGKILL` always terminates. Init discards signals it has no handler for.
+//! Sending a signal that is already pending has no further effect.
+//!
+//! ## Delivery
+//!
+//! Pending signals are acted upon on the way back to user mode: when a
+//! system call returns and when the timer interrupts user code. A process
+//! blocked in the kernel notices them once its system call completes, except
+//! for one [sleeping](sched::sleep_until_tsc): [`send`] wakes it, and the
+//! sleep ends early if the signal [`interrupts`] it.
+//!
+//! Faults in user mode (`#PF` on a bad address, `#GP`) raise `SIGSEGV`
+//! synchronously: the handler runs right away, and without one the process
+//! is terminated, even if the signal is ignored.
+//!
+//! ## Handler frames
+//!
+//! To run a handler, the kernel saves the interrupted user context on the
+//! user stack, below the red zone:
+//!
+//! ```text
+//!   old rsp - 128 ─► ┌───────────────────────┐ (red zone above)
+//!                    │ FP/SIMD save area     │ 64-byte aligned
+//!                    ├───────────────────────┤
+//!                    │ SignalContext         │ 16-byte aligned
+//!   handler rsp ───► │ restorer address      │
+//!                    └───────────────────────┘
+//! ```
+//!
+//! and enters `handler(signo, &mut context)` as if called from the restorer
+//! registered with the handler. The restorer, a stub in the C library, issues
+//! `sigreturn`, which reads the (possibly modified) context back, validates
+//! it and resumes it with `iretq` ([`sigreturn`]). If the frame cannot be
+//! written, the process is terminated with `SIGSEGV`.
+//!
+//! A fault handler terminating a process switches away on the fault's stack;
+//! that is fine, as the process never runs again.
+
+use crate::fpu::{self, AREA_SIZE, FpuState};
+use crate::gdt::{USER_CS, USER_DS};
+use crate::interrupts::{ExceptionFrame, InterruptFrame};
+use crate::process::{self, PROCESSES, Pid, ProcessState};
+use crate::sched;
+use crate::syscall::entry::SyscallFrame;
+use crate::uaccess::{UserPtr, UserSlice, copy_to_user_nofault};
+use core::fmt;
+use core::mem::offset_of;
+use kernel_info::memory::LAST_USERSPACE_ADDRESS;
+use kernel_registers::rflags::Rflags;
+use kernel_sync::IrqGuard;
+use log::{debug, info};
+use stdlib::syscall_abi::SignalContext;
+use stdlib::syscall_abi::signal::{NSIG, SIG_DFL, SIG_IGN, SIGCHLD, SIGKILL, SIGSEGV};
+
+/// Bytes below the user stack pointer that leaf functions may use (SysV).
+const RED_ZONE: u64 = 128;
+
+/// `RFLAGS` bits user mode may set: the arithmetic flags and `DF`.
+const USER_RFLAGS: u64 = 0x0CD5;
+
+/// `RFLAGS.IF` and the always-one bit 1.
+const RFLAGS_FIXED: u64 = 0x0202;
+
+/// `RFLAGS.TF` and `RFLAGS.DF`, cleared on handler entry.
+const RFLAGS_TF_DF: u64 = 0x0500;
+
+/// What to do when a signal is delivered.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Disposition {
+    /// The signal's default action.
+    Default,
+    /// Discard the signal.
+    Ignore,
+    /// Run `entry`, returning through `restorer`.
+    Handler { entry: u64, restorer: u64 },
+}
+
+impl Disposition {
+    /// The raw handler value (`SIG_DFL`, `SIG_IGN` or an address).
+    const fn as_raw(self) -> u64 {
+        match self {
+            Self::Default => SIG_DFL,
+            Self::Ignore => SIG_IGN,
+            Self::Handler { entry, .. } => entry,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SignalError {
+    /// The signal number is out of range.
+    InvalidSignal,
+    /// No such process.
+    NoSuchProcess,
+    /// The signal's disposition cannot be changed.
+    Uncatchable,
+    /// The handler or restorer address is not in user space.
+    BadHandler,
+}
+
+impl fmt::Display for SignalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidSignal => f.write_str("invalid signal"),
+            Self::NoSuchProcess => f.write_str("no such process"),
+            Self::Uncatchable => f.write_str("signal cannot be caught or ignored"),
+            Self::BadHandler => f.write_str("bad signal handler address"),
+        }
+    }
+}
+
+/// Pending signals and dispositions of one process.
+#[derive(Debug, Clone)]
+pub struct SignalState {
+    /// Bit `n` is set while signal `n` is pending.
+    pending: u32,
+    actions: [Disposition; NSIG as usize],
+}
+
+impl SignalState {
+    pub const fn new() -> Self {
+        Self {
+            pending: 0,
+            actions: [Disposition::Default; NSIG as usize],
+        }
+    }
+
+    /// The state of a forked child: same dispositions, nothing pending.
+    pub const fn fork(&self) -> Self {
+        Self {
+            pending: 0,
+            actions: self.actions,
+        }
+    }
+
+    /// Mark `signo` pending.
+    pub const fn raise(&mut self, signo: u32) {
+        self.pending |= 1 << signo;
+    }
+
+    /// Whether a pending signal would be acted upon, rather than discarded,
+    /// on delivery; `init` is whether this is the init process.
+    pub fn has_actionable(&self, init: bool) -> bool {
+        (1..NSIG)
+            .filter(|&signo| self.pending & (1 << signo) != 0)
+            .any(|signo| match self.actions[signo as usize] {
+                Disposition::Ignore => false,
+                Disposition::Default => signo != SIGCHLD && (!init || signo == SIGKILL),
+                Disposition::Handler { .. } => true,
+            })
+    }
+
+    /// Take the lowest pending signal and its disposition.
+    pub const fn take_pending(&mut self) -> Option<(u32, Disposition)> {
+        if self.pending == 0 {
+            return None;
+        }
+        let signo = self.pending.trailing_zeros();
+        self.pending &= !(1 << signo);
+        Some((signo, self.actions[signo as usize]))
+    }
+}
+
+impl Default for SignalState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+const fn is_valid(signo: u32) -> bool {
+    signo > 0 && signo < NSIG
+}
+
+/// Make `signo` pending for `pid`. Signal `0` only checks that `pid` exists.
+pub fn send(pid: Pid, signo: u32) -> Result<(), SignalError> {
+    if signo != 0 && !is_valid(signo) {
+        return Err(SignalError::InvalidSignal);
+    }
+
+    let _irq = IrqGuard::new();
+    let mut table = PROCESSES.lock();
+    let process = table
+        .find(pid)
+        .and_then(|slot| table.get_mut(slot))
+        .ok_or(SignalError::NoSuchProcess)?;
+    if signo != 0 && !matches!(process.state, ProcessState::Zombie(_)) {
+        process.signals.raise(signo);
+        debug!("Signal {signo} pending for process {pid}");
+        drop(table);
+        sched::interrupt_sleep(pid);
+    }
+    Ok(())
+}
+
+/// Whether `pid` has a pending signal that delivery would act upon, which
+/// ends a sleep early.
+pub fn interrupts(pid: Pid) -> bool {
+    let _irq = IrqGuard::new();
+    let table = PROCESSES.lock();
+    table
+        .find(pid)
+        .and_then(|slot| table.get(slot))
+        .is_some_and(|process| process.signals.has_actionable(pid == Pid::INIT))
+}
+
+/// Set the disposition of `signo` for the current process and return the
+/// previous handler value.
+///
+/// `handler` is `SIG_DFL`, `SIG_IGN` or the address of a handler, which
+/// returns to `restorer`.
+///
+/// # Panics
+/// If called outside of a process.
+pub fn set_action(signo: u32, handler: u64, restorer: u64) -> Result<u64, SignalError> {
+    if !is_valid(signo) {
+        return Err(SignalError::InvalidSignal);
+    }
+    if signo == SIGKILL {
+        return Err(SignalError::Uncatchable);
+    }
+
+    let action = match handler {
+        SIG_DFL => Disposition::Default,
+        SIG_IGN => Disposition::Ignore,
+        _ if is_user_address(handler) && is_user_address(restorer) => Disposition::Handler {
+            entry: handler,
+            restorer,
+        },
+        _ => return Err(SignalError::BadHandler),
+    };
+
+    let me = sched::current_pid().expect("sigaction called outside of a process");
+    let _irq = IrqGuard::new();
+    let mut table = PROCESSES.lock();
+    let slot = table.find(me).expect("calling process not in table");
+    let signals = &mut table
+        .get_mut(slot)
+        .expect("calling process not in table")
+        .signals;
+
+    let old = core::mem::replace(&mut signals.actions[signo as usize], action);
+    if action == Disposition::Ignore {
+        signals.pending &= !(1 << signo);
+    }
+    Ok(old.as_raw())
+}
+
+const fn is_user_address(addr: u64) -> bool {
+    addr != 0 && addr <= LAST_USERSPACE_ADDRESS.as_u64()
+}
+
+/// A saved user context that signal delivery can redirect into a handler.
+pub trait UserFrame {
+    /// The user registers, as they are to be restored after the handler.
+    fn context(&self) -> SignalContext;
+
+    /// Continue in user mode at `entry` with stack pointer `rsp`, passing
+    /// `signo` and `context` as the first two arguments.
+    fn enter_handler(&mut self, entry: u64, rsp: u64, signo: u32, context: u64);
+}
+
+/// Act on the pending signals of the current process before `frame` returns
+/// to user mode.
+///
+/// Runs at most one handler; further signals stay pending until the next
+/// return to user mode. Does not return if a signal terminates the process.
+pub fn deliver(frame: &mut impl UserFrame) {
+    let Some(me) = sched::current_pid() else {
+        return;
+    };
+
+    while let Some((signo, action)) = take_pending(me) {
+        match action {
+            Disposition::Ignore => {}
+            Disposition::Default if signo == SIGCHLD => {}
+            Disposition::Default if me == Pid::INIT && signo != SIGKILL => {
+                debug!("Init discards signal {signo}");
+            }
+            Disposition::Default => terminate(me, signo),
+            Disposition::Handler { entry, restorer } => {
+                if push_frame(frame, signo, entry, restorer).is_none() {
+                    terminate(me, SIGSEGV);
+                }
+                return;
+            }
+        }
+    }
+}
+
+/// Raise `signo` for a fault of the current process at `frame`.
+///
+/// Enters the process' handler, or terminates the process if it has none.
+///
+/// # Panics
+/// If called outside of a process.
+pub fn raise_fault(frame: &mut impl UserFrame, signo: u32) {
+    let me = sched::current_pid().expect("user fault outside of a process");
+    let action = {
+        let _irq = IrqGuard::new();
+        let table = PROCESSES.lock();
+        let slot = table.find(me).expect("faulting process not in table");
+        table
+            .get(slot)
+            .expect("faulting process not in table")
+            .signals
+            .actions[signo as usize]
+    };
+
+    match action {
+        Disposition::Handler { entry, restorer } => {
+            if push_frame(frame, signo, entry, restorer).is_none() {
+                terminate(me, signo);
+            }
+        }
+        Disposition::Default | Disposition::Ignore => terminate(me, signo),
+    }
+}
+
+fn take_pending(me: Pid) -> Option<(u32, Disposition)> {
+    let _irq = IrqGuard::new();
+    let mut table = PROCESSES.lock();
+    let slot = table.find(me)?;
+    table.get_mut(slot)?.signals.take_pending()
+}
+
+/// Terminate the current process as if by `signo`, without entering its
+/// handler.
+///
+/// # Panics
+/// If called outside of a process.
+pub fn kill_current(signo: u32) -> ! {
+    let me = sched::current_pid().expect("kill_current called outside of a process");
+    terminate(me, signo)
+}
+
+fn terminate(me: Pid, signo: u32) -> ! {
+    info!("Process {me} terminated by signal {signo}");
+    process::exit(128 + signo);
+}
+
+/// Save the context of `frame` and the FP/SIMD registers on the user stack
+/// and redirect `frame` into `entry`. Returns `None` if the stack is unusable.
+fn push_frame(frame: &mut impl UserFrame, signo: u32, entry: u64, restorer: u64) -> Option<()> {
+    let mut context = frame.context();
+    let fpu_addr = context.rsp.checked_sub(RED_ZONE + AREA_SIZE as u64)?
+        & !(align_of::<FpuState>() as u64 - 1);
+    let context_addr = fpu_addr.checked_sub(size_of::<SignalContext>() as u64)? & !15;
+    let rsp = context_addr.checked_sub(8)?;
+    context.signo = u64::from(signo);
+    context.fpu = fpu_addr;
+
+    let mut fpu_state = FpuState::new();
+    unsafe { fpu::save(&mut fpu_state) };
+
+    copy_to_user_nofault(fpu_addr, fpu_state.as_bytes()).ok()?;
+    copy_to_user_nofault(context_addr, context_bytes(&context)).ok()?;
+    copy_to_user_nofault(rsp, &restorer.to_ne_bytes()).ok()?;
+
+    frame.enter_handler(entry, rsp, signo, context_addr);
+    Some(())
+}
+
+const fn context_bytes(context: &SignalContext) -> &[u8] {
+    // SAFETY: `SignalContext` is `repr(C)` and consists of `u64`s only.
+    unsafe {
+        core::slice::from_raw_parts(
+            (&raw const *context).cast::<u8>(),
+            size_of::<SignalContext>(),
+        )
+    }
+}
+
+/// Return from a signal handler: resume the context saved at `rsp`.
+///
+/// Terminates the process with `SIGSEGV` if the context cannot be read or
+/// does not describe a valid user context.
+///
+/// # Panics
+/// If called outside of a process.
+pub fn sigreturn(rsp: u64) -> ! {
+    let me = sched::current_pid().expect("sigreturn called outside of a process");
+    let Some((mut context, fpu_state)) = read_frame(rsp) else {
+        terminate(me, SIGSEGV);
+    };
+
+    unsafe { fpu::restore(&fpu_state) };
+    deliver(&mut context);
+    unsafe { resume(&context) }
+}
+
+fn read_frame(rsp: u64) -> Option<(SignalContext, FpuState)> {
+    let mut context = UserPtr::<SignalContext>::new(rsp).ok()?.read().ok()?;
+    if !is_user_address(context.rip) || !is_user_address(context.rsp) {
+        return None;
+    }
+    context.rflags = context.rflags & USER_RFLAGS | RFLAGS_FIXED;
+
+    let mut area = [0; AREA_SIZE];
+    UserSlice::new(context.fpu, area.len())
+        .ok()?
+        .read_into(&mut area)
+        .ok()?;
+    Some((context, FpuState::from_untrusted(&area)?))
+}
+
+/// Load all registers from `context` and return to user mode.
+///
+/// # Safety
+/// `context` must describe a valid user context with interrupts enabled in
+/// its `rflags`; the current address space must be the process'.
+unsafe fn resume(context: &SignalContext) -> ! {
+    let cs = u64::from(USER_CS) | 3;
+    let ss = u64::from(USER_DS) | 3;
+
+    unsafe {
+        core::arch::asm!(
+            "cli",
+            "push {ss}",
+            "push qword ptr [rax + {rsp}]",
+            "push qword ptr [rax + {rflags}]",
+            "push {cs}",
+            "push qword ptr [rax + {rip}]",
+            "mov rbx, [rax + {rbx}]",
+            "mov rcx, [rax + {rcx}]",
+            "mov rdx, [rax + {rdx}]",
+            "mov rsi, [rax + {rsi}]",
+            "mov rdi, [rax + {rdi}]",
+            "mov rbp, [rax + {rbp}]",
+            "mov r8, [rax + {r8}]",
+            "mov r9, [rax + {r9}]",
+            "mov r10, [rax + {r10}]",
+            "mov r11, [rax + {r11}]",
+            "mov r12, [rax + {r12}]",
+            "mov r13, [rax + {r13}]",
+            "mov r14, [rax + {r14}]",
+            "mov r15, [rax + {r15}]",
+            "mov rax, [rax + {rax}]",
+            "swapgs",
+            "iretq",
+            in("rax") context,
+            ss = in(reg) ss,
+            cs = in(reg) cs,
+            rsp = const offset_of!(SignalContext, rsp),
+            rflags = const offset_of!(SignalContext, rflags),
+            rip = const offset_of!(SignalContext, rip),
+            rax = const offset_of!(SignalContext, rax),
+            rbx = const offset_of!(SignalContext, rbx),
+            rcx = const offset_of!(SignalContext, rcx),
+            rdx = const offset_of!(SignalContext, rdx),
+            rsi = const offset_of!(SignalContext, rsi),
+            rdi = const offset_of!(SignalContext, rdi),
+            rbp = const offset_of!(SignalContext, rbp),
+            r8 = const offset_of!(SignalContext, r8),
+            r9 = const offset_of!(SignalContext, r9),
+            r10 = const offset_of!(SignalContext, r10),
+            r11 = const offset_of!(SignalContext, r11),
+            r12 = const offset_of!(SignalContext, r12),
+            r13 = const offset_of!(SignalContext, r13),
+            r14 = const offset_of!(SignalContext, r14),
+            r15 = const offset_of!(SignalContext, r15),
+            options(noreturn)
+        );
+    }
+}
+
+impl UserFrame for SignalContext {
+    fn context(&self) -> SignalContext {
+        *self
+    }
+
+    fn enter_handler(&mut self, entry: u64, rsp: u64, signo: u32, context: u64) {
+        self.rip = entry;
+        self.rsp = rsp;
+        self.rdi = u64::from(signo);
+        self.rsi = context;
+        self.rflags &= !RFLAGS_TF_DF;
+    }
+}
+
+/// `sysretq` clobbers `rcx` and `r11`, and the entry stub `r12`; the saved
+/// context holds the values they have after the system call.
+impl UserFrame for SyscallFrame {
+    fn context(&self) -> SignalContext {
+        SignalContext {
+            rax: self.rax,
+            rbx: self.rbx,
+            rcx: self.rip,
+            rdx: self.rdx,
+            rsi: self.rsi,
+            rdi: self.rdi,
+            rbp: self.rbp,
+            rsp: self.rsp,
+            r8: self.r8,
+            r9: self.r9,
+            r10: self.r10,
+            r11: self.rflags.into_bits(),
+            r12: self.rsp,
+            r13: self.r13,
+            r14: self.r14,
+            r15: self.r15,
+            rip: self.rip,
+            rflags: self.rflags.into_bits(),
+            ..SignalContext::default()
+        }
+    }
+
+    fn enter_handler(&mut self, entry: u64, rsp: u64, signo: u32, context: u64) {
+        self.rip = entry;
+        self.rsp = rsp;
+        self.rdi = u64::from(signo);
+        self.rsi = context;
+        self.rflags = Rflags::from_bits(self.rflags.into_bits() & !RFLAGS_TF_DF);
+    }
+}
+
+macro_rules! impl_user_frame_for_trap_frame {
+    ($frame:ty) => {
+        impl UserFrame for $frame {
+            fn context(&self) -> SignalContext {
+                SignalContext {
+                    rax: self.rax,
+                    rbx: self.rbx,
+                    rcx: self.rcx,
+                    rdx: self.rdx,
+                    rsi: self.rsi,
+                    rdi: self.rdi,
+                    rbp: self.rbp,
+                    rsp: self.rsp,
+                    r8: self.r8,
+                    r9: self.r9,
+                    r10: self.r10,
+                    r11: self.r11,
+                    r12: self.r12,
+                    r13: self.r13,
+                    r14: self.r14,
+                    r15: self.r15,
+                    rip: self.rip,
+                    rflags: self.rflags,
+                    ..SignalContext::default()
+                }
+            }
+
+            fn enter_handler(&mut self, entry: u64, rsp: u64, signo: u32, context: u64) {
+                self.rip = entry;
+                self.rsp = rsp;
+                self.rdi = u64::from(signo);
+                self.rsi = context;
+                self.rflags &= !RFLAGS_TF_DF;
+            }
+        }
+    };
+}
+
+impl_user_frame_for_trap_frame!(InterruptFrame);
+impl_user_frame_for_trap_frame!(ExceptionFrame);
@@ -0,0 +1,220 @@
+//! # System Call Tracing
+//!
+//! Every process carries a [`traced`](crate::process::Process::traced) flag.
+//! While it is set, each system call the process makes is
+//!
+//! * logged under the `strace` target once it returns, with its decoded name,
+//!   arguments and return value:
+//!
+//!   ```text
+//!   pid 1: open(0x4010a0, 0xa) = 0x3
+//!   pid 1: exit(0x0) = ?
+//!   ```
+//!
+//!   Calls that do not return, `exit` and `sigreturn`, are logged on entry;
+//!   a failed call shows `= error`.
+//! * recorded as `strace_enter`/`strace_exit` [tracepoints](crate::tracepoint)
+//!   of the `strace` class, which tracing a process enables.
+//!
+//! The flag is switched with the `trace` system call ([`set_traced`]), is
+//! inherited by forked children, and is set for init when `strace_init` is on
+//! the [command line](crate::cmdline).
+//!
+//! ## Rate limiting
+//!
+//! At most [`LINES_PER_SECOND`] lines are logged per second, across all
+//! processes; the number of lines dropped is logged once the next second
+//! begins. Tracepoint records are not limited: the rings keep the latest
+//! records anyway.
+//!
+//! ## Cost
+//!
+//! Until the first process is traced, a system call pays a single relaxed
+//! load. From then on, every call looks up the caller's flag in the process
+//! table.
+
+use crate::clock;
+use crate::cmdline::{self, Param, ParamKind};
+use crate::process::{self, Pid};
+use crate::sched;
+use crate::tracepoint::{self, EventClass, trace_event};
+use crate::tsc::rdtsc;
+use core::fmt;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use log::info;
+use stdlib::syscall_abi::{SYSCALL_ERROR, Sysno};
+
+pub static STRACE_INIT_PARAM: Param = Param {
+    name: "strace_init",
+    kind: ParamKind::Flag,
+    help: "Trace the system calls of init",
+};
+
+/// Most lines logged per second.
+pub const LINES_PER_SECOND: u64 = 100;
+
+/// Set once any process was traced.
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// TSC at which the current rate limiting window began.
+static WINDOW_START: AtomicU64 = AtomicU64::new(0);
+
+/// Lines logged in the current window.
+static LINES: AtomicU64 = AtomicU64::new(0);
+
+/// Lines dropped in the current window.
+static SUPPRESSED: AtomicU64 = AtomicU64::new(0);
+
+/// Whether the process `pid`, about to be spawned, starts out traced.
+pub fn traced_from_start(pid: Pid) -> bool {
+    let traced = pid == Pid::INIT && cmdline::flag(STRACE_INIT_PARAM.name);
+    if traced {
+        activate();
+    }
+    traced
+}
+
+/// Switch tracing of `pid` on or off; returns whether it was on, or `None`
+/// if there is no such process.
+pub fn set_traced(pid: Pid, on: bool) -> Option<bool> {
+    if on {
+        activate();
+    }
+    process::set_traced(pid, on)
+}
+
+fn activate() {
+    tracepoint::enable(EventClass::Strace);
+    ACTIVE.store(true, Ordering::Relaxed);
+}
+
+/// The calling process, if its system calls are traced.
+fn traced_caller() -> Option<Pid> {
+    if !ACTIVE.load(Ordering::Relaxed) {
+        return None;
+    }
+    sched::current_pid().filter(|&pid| process::is_traced(pid))
+}
+
+/// Trace the entry into system call `sysno`; returns the caller if it is
+/// traced, to be passed to [`exit`].
+pub fn enter(sysno: u64, args: &[u64; 6]) -> Option<Pid> {
+    let pid = traced_caller()?;
+    trace_event!(strace_enter, pid, sysno, args[0], args[1]);
+    let call = Call { sysno, args };
+    if matches!(call.sysno(), Some(Sysno::Exit | Sysno::SigReturn)) && admit() {
+        info!(target: "strace", "pid {pid}: {call} = ?");
+    }
+    Some(pid)
+}
+
+/// Trace the return of system call `sysno` with `ret` to the traced `pid`.
+pub fn exit(pid: Pid, sysno: u64, args: &[u64; 6], ret: u64) {
+    trace_event!(strace_exit, pid, sysno, ret);
+    if !admit() {
+        return;
+    }
+    let call = Call { sysno, args };
+    if ret == SYSCALL_ERROR {
+        info!(target: "strace", "pid {pid}: {call} = error");
+    } else {
+        info!(target: "strace", "pid {pid}: {call} = {ret:#x}");
+    }
+}
+
+/// Whether another line may be logged in this second.
+fn admit() -> bool {
+    let now = rdtsc();
+    let start = WINDOW_START.load(Ordering::Relaxed);
+    if now.wrapping_sub(start) >= clock::tsc_hz()
+        && WINDOW_START
+            .compare_exchange(start, now, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+    {
+        LINES.store(0, Ordering::Relaxed);
+        let suppressed = SUPPRESSED.swap(0, Ordering::Relaxed);
+        if suppressed > 0 {
+            info!(target: "strace", "{suppressed} line(s) suppressed");
+        }
+    }
+
+    if LINES.fetch_add(1, Ordering::Relaxed) < LINES_PER_SECOND {
+        true
+    } else {
+        SUPPRESSED.fetch_add(1, Ordering::Relaxed);
+        false
+    }
+}
+
+/// A system call with its arguments, formatted as `name(arg, ...)`.
+struct Call<'a> {
+    sysno: u64,
+    args: &'a [u64; 6],
+}
+
+impl Call<'_> {
+    const fn sysno(&self) -> Option<Sysno> {
+        Sysno::from_raw(self.sysno)
+    }
+}
+
+impl fmt::Display for Call<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let argc = if let Some(sysno) = self.sysno() {
+            f.write_str(sysno.name())?;
+            arg_count(sysno)
+        } else {
+            write!(f, "syscall_{}", self.sysno)?;
+            self.args.len()
+        };
+        f.write_str("(")?;
+        for (i, arg) in self.args[..argc].iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{arg:#x}")?;
+        }
+        f.write_str(")")
+    }
+}
+
+/// Number of arguments `sysno` takes.
+const fn arg_count(sysno: Sysno) -> usize {
+    match sysno {
+        Sysno::Bogus | Sysno::Fork | Sysno::SigReturn => 0,
+        Sysno::DebugWriteByte
+        | Sysno::WaitPid
+        | Sysno::Exit
+        | Sysno::ShmClose
+        | Sysno::Pipe
+        | Sysno::Close
+        | Sysno::Reboot
+        | Sysno::NanoSleep
+        | Sysno::ClockNanoSleep
+        | Sysno::CapGet
+        | Sysno::HandleClose => 1,
+        Sysno::LogRead
+        | Sysno::Kill
+        | Sysno::SetPriority
+        | Sysno::CpuSetOnline
+        | Sysno::ArchPrctl
+        | Sysno::TaskInfo
+        | Sysno::Open
+        | Sysno::Trace
+        | Sysno::GetRandom
+        | Sysno::SchedSetAffinity
+        | Sysno::CapSet
+        | Sysno::HandleDup => 2,
+        Sysno::Log
+        | Sysno::ShmOpen
+        | Sysno::Read
+        | Sysno::Write
+        | Sysno::SigAction
+        | Sysno::Ioctl
+        | Sysno::Poll
+        | Sysno::ReadV
+        | Sysno::WriteV => 3,
+        Sysno::ReadDir => 4,
+        Sysno::Spawn | Sysno::Mmap => 6,
+    }
+}
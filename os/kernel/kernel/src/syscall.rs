@@ -1,8 +1,33 @@
+mod cpu;
 pub mod entry;
+mod file;
+mod log;
+mod memory;
+mod power;
+mod process;
+mod random;
+mod signal;
+mod time;
 
-use crate::ports::outb;
+use crate::per_cpu::PerCpu;
+use crate::strace;
+use crate::tracepoint::trace_event;
+use core::sync::atomic::Ordering;
+use kernel_ports::PortWriteOnly;
 use stdlib::syscall_abi::Sysno;
 
+/// QEMU's debug console, target of [`Sysno::DebugWriteByte`].
+const QEMU_DEBUG_PORT: PortWriteOnly<u8> = PortWriteOnly::new(0x402);
+
+/// Value of [`PerCpu::syscall`] outside of system calls.
+pub const NO_SYSCALL: u64 = u64::MAX;
+
+/// Number of the system call the current CPU is handling, if any.
+pub fn current_sysno() -> Option<u64> {
+    let cpu = unsafe { PerCpu::current() };
+    Some(cpu.syscall.load(Ordering::Relaxed)).filter(|&sysno| sysno != NO_SYSCALL)
+}
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum SyscallSource {
     Syscall,
@@ -13,26 +38,74 @@ pub enum SyscallSource {
 pub fn syscall(
     sysno: u64,
     arg0: u64,
-    _arg1: u64,
-    _arg2: u64,
-    _arg3: u64,
-    _arg4: u64,
-    _arg5: u64,
+    arg1: u64,
+    arg2: u64,
+    arg3: u64,
+    arg4: u64,
+    arg5: u64,
     source: SyscallSource,
 ) -> u64 {
-    match sysno {
+    trace_event!(syscall_enter, sysno, arg0, arg1, arg2);
+    unsafe { PerCpu::current() }
+        .syscall
+        .store(sysno, Ordering::Relaxed);
+    let all_args = [arg0, arg1, arg2, arg3, arg4, arg5];
+    let traced = strace::enter(sysno, &all_args);
+    let ret = match sysno {
         x if x == Sysno::DebugWriteByte as u64 => {
-            unsafe {
-                let byte = (arg0 & 0xFF) as u8;
-                outb(0x402, byte);
-            }
+            unsafe { QEMU_DEBUG_PORT.write((arg0 & 0xFF) as u8) };
             0
         }
         x if x == Sysno::Bogus as u64 => match source {
             SyscallSource::Int80h => 0xd34d_c0d3,
             SyscallSource::Syscall => 0xb007_c4fe,
         },
+        x if x == Sysno::Spawn as u64 => process::sys_spawn(arg0, arg1, arg2, arg3, arg4, arg5),
+        x if x == Sysno::Fork as u64 => process::sys_fork(source),
+        x if x == Sysno::WaitPid as u64 => process::sys_waitpid(arg0),
+        x if x == Sysno::Exit as u64 => process::sys_exit(arg0),
+        x if x == Sysno::Log as u64 => log::sys_log(arg0, arg1, arg2),
+        x if x == Sysno::LogRead as u64 => log::sys_log_read(arg0, arg1),
+        x if x == Sysno::ShmOpen as u64 => memory::sys_shm_open(arg0, arg1, arg2),
+        x if x == Sysno::ShmClose as u64 => memory::sys_shm_close(arg0),
+        x if x == Sysno::Mmap as u64 => memory::sys_mmap(arg0, arg1, arg2, arg3, arg4, arg5),
+        x if x == Sysno::Pipe as u64 => file::sys_pipe(arg0),
+        x if x == Sysno::Read as u64 => file::sys_read(arg0, arg1, arg2),
+        x if x == Sysno::Write as u64 => file::sys_write(arg0, arg1, arg2),
+        x if x == Sysno::ReadV as u64 => file::sys_readv(arg0, arg1, arg2),
+        x if x == Sysno::WriteV as u64 => file::sys_writev(arg0, arg1, arg2),
+        x if x == Sysno::Close as u64 => file::sys_close(arg0),
+        x if x == Sysno::Kill as u64 => signal::sys_kill(arg0, arg1),
+        x if x == Sysno::SigAction as u64 => signal::sys_sigaction(arg0, arg1, arg2),
+        x if x == Sysno::SigReturn as u64 => signal::sys_sigreturn(source),
+        x if x == Sysno::SetPriority as u64 => process::sys_setpriority(arg0, arg1),
+        x if x == Sysno::CpuSetOnline as u64 => cpu::sys_cpu_set_online(arg0, arg1),
+        x if x == Sysno::ArchPrctl as u64 => process::sys_arch_prctl(arg0, arg1),
+        x if x == Sysno::TaskInfo as u64 => process::sys_task_info(arg0, arg1),
+        x if x == Sysno::Open as u64 => file::sys_open(arg0, arg1),
+        x if x == Sysno::Trace as u64 => process::sys_trace(arg0, arg1),
+        x if x == Sysno::Ioctl as u64 => file::sys_ioctl(arg0, arg1, arg2),
+        x if x == Sysno::ReadDir as u64 => file::sys_readdir(arg0, arg1, arg2, arg3),
+        x if x == Sysno::GetRandom as u64 => random::sys_getrandom(arg0, arg1),
+        x if x == Sysno::Reboot as u64 => power::sys_reboot(arg0),
+        x if x == Sysno::NanoSleep as u64 => time::sys_nanosleep(arg0),
+        x if x == Sysno::ClockNanoSleep as u64 => time::sys_clock_nanosleep(arg0),
+        x if x == Sysno::SchedSetAffinity as u64 => process::sys_sched_setaffinity(arg0, arg1),
+        x if x == Sysno::CapGet as u64 => process::sys_cap_get(arg0),
+        x if x == Sysno::CapSet as u64 => process::sys_cap_set(arg0, arg1),
+        x if x == Sysno::HandleDup as u64 => file::sys_handle_dup(arg0, arg1),
+        x if x == Sysno::HandleClose as u64 => file::sys_handle_close(arg0),
+        x if x == Sysno::Poll as u64 => file::sys_poll(arg0, arg1, arg2),
 
         _ => u64::MAX,
+    };
+    if let Some(pid) = traced {
+        strace::exit(pid, sysno, &all_args, ret);
     }
+    // A call that blocked may end on another CPU than it started on.
+    unsafe { PerCpu::current() }
+        .syscall
+        .store(NO_SYSCALL, Ordering::Relaxed);
+    trace_event!(syscall_exit, sysno, ret);
+    ret
 }
@@ -0,0 +1,31 @@
+//! CPU control syscalls: `cpu_set_online`.
+
+use crate::hotplug;
+use crate::process::caps;
+use log::debug;
+use stdlib::syscall_abi::SYSCALL_ERROR;
+use stdlib::syscall_abi::caps::CAP_RAWIO;
+
+/// `cpu_set_online(cpu, online)`: take a CPU offline (`online == 0`) or bring
+/// it back; returns `0`. Needs `CAP_RAWIO`. See [`hotplug`].
+pub fn sys_cpu_set_online(cpu: u64, online: u64) -> u64 {
+    let Ok(cpu) = u32::try_from(cpu) else {
+        return SYSCALL_ERROR;
+    };
+    if !caps::require(CAP_RAWIO, "cpu_set_online") {
+        return SYSCALL_ERROR;
+    }
+
+    let result = if online == 0 {
+        hotplug::offline(cpu)
+    } else {
+        hotplug::online(cpu)
+    };
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            debug!("cpu_set_online({cpu}, {online}) failed: {e}");
+            SYSCALL_ERROR
+        }
+    }
+}
@@ -1,26 +1,35 @@
 use crate::per_cpu::PerCpu;
 use crate::syscall::{SyscallSource, syscall};
+use crate::{preempt, signal};
 use core::mem::offset_of;
 use kernel_registers::rflags::Rflags;
 
 /// Minimal state for SYSCALL/SYSRET-based syscalls.
 ///
-/// Layout must match the push order in the naked stub:
+/// Besides the arguments and the `sysret` state, the frame keeps the user's
+/// callee-saved registers (except `r12`, which the stub clobbers), so that a
+/// copy of it fully describes the user context, e.g. for a forked child.
 ///
-///   push rsp   → rsp
-///   push r11   → rflags
-///   push rcx   → rip
-///   push rdi   → arg0
-///   push rax   → sysno/ret
+/// Layout must match the push order in the naked stub (last pushed at the
+/// lowest address). With `#[repr(C)]`, memory from top-of-stack (RSP) looks
+/// like:
 ///
-/// With `#[repr(C)]`, memory from top-of-stack (RSP) looks like:
-///
-///   +0  rax
-///   +8  rdi
-///   +16 rip
-///   +24 rflags
-///   +32 rsp
-#[derive(Debug)]
+///   +0   rax     (sysno / ret)
+///   +8   rdi     (a0)
+///   +16  rsi     (a1)
+///   +24  rdx     (a2)
+///   +32  r10     (a3)
+///   +40  r8      (a4)
+///   +48  r9      (a5)
+///   +56  rip     (user, from RCX)
+///   +64  rflags  (user, from R11)
+///   +72  rsp     (user)
+///   +80  rbx
+///   +88  rbp
+///   +96  r13
+///   +104 r14
+///   +112 r15
+#[derive(Debug, Clone)]
 #[repr(C)]
 pub struct SyscallFrame {
     pub rax: u64, // syscall number on entry, return value on exit
@@ -33,6 +42,29 @@ pub struct SyscallFrame {
     pub rip: u64, // user return RIP (from RCX)
     pub rflags: Rflags,
     pub rsp: u64, // user stack pointer on entry
+    pub rbx: u64,
+    pub rbp: u64,
+    pub r13: u64,
+    pub r14: u64,
+    pub r15: u64,
+}
+
+/// Distance from the kernel stack top to the [`SyscallFrame`] built by the stub.
+///
+/// The stub leaves one padding slot below the top so that the frame (15 words)
+/// ends 16-byte aligned, as the SysV ABI requires at the `call`.
+pub const FRAME_OFFSET: usize = 8 + size_of::<SyscallFrame>();
+
+const _: () = assert!(FRAME_OFFSET.is_multiple_of(16));
+
+/// The [`SyscallFrame`] of the system call the current process is in.
+///
+/// # Safety
+/// Must be called on the kernel stack of a process that entered the kernel
+/// through `syscall` (not `int 0x80`), before that system call returns.
+pub unsafe fn current_frame() -> &'static SyscallFrame {
+    let top = unsafe { PerCpu::current() }.kstack_top.as_u64();
+    unsafe { &*((top - FRAME_OFFSET as u64) as *const SyscallFrame) }
 }
 
 #[unsafe(naked)]
@@ -63,34 +95,16 @@ pub extern "C" fn syscall_entry_stub() {
         // Switch to kernel syscall stack: rsp = PerCpu.kstack_top
         "mov rsp, qword ptr gs:[{kstack_top}]",
 
-        // ensure pre-call %rsp % 16 == 8 (SysV). kstack_top is 16-aligned.
+        // Padding slot; with the 15 pushes below, pre-call %rsp % 16 == 0 (SysV).
+        // kstack_top is 16-aligned.
         "sub rsp, 8",
 
-        // Build SyscallFrame on kernel stack.
-        //
-        // push order (last pushed at lowest address):
-        //   rsp   (user)
-        //   rflags (user)
-        //   rip   (user)
-        //   r9    (arg5)
-        //   r8    (arg4, sic!)
-        //   r10   (arg3)
-        //   rdx   (arg2)
-        //   rsi   (arg1)
-        //   rdi   (arg0)
-        //   rax   (sysno)
-        //
-        // resulting layout at [rsp]:
-        //   +0  rax   (ret / sysno)
-        //   +8  rdi   (a0)
-        //   +16 rsi   (a1)
-        //   +24 rdx   (a2)
-        //   +32 r10   (a3)
-        //   +40 r8    (a4)
-        //   +48 r9    (a5)
-        //   +56 rip
-        //   +64 rflags
-        //   +72 rsp
+        // Build SyscallFrame on kernel stack (see its docs for the layout).
+        "push r15",   // +112
+        "push r14",   // +104
+        "push r13",   // +96
+        "push rbp",   // +88
+        "push rbx",   // +80
         "push r12",   // +72: user RSP
         "push r11",   // +64: user RFLAGS
         "push rcx",   // +56: user RIP
@@ -110,30 +124,48 @@ pub extern "C" fn syscall_entry_stub() {
 
         // On return, Rust may have updated tf.rax. Everything else we
         // restore exactly as the user had it.
+        "jmp {ret}",
 
-        // Load fields back into registers:
-        "mov rax, [rsp + 0]",   // return value
-        "mov rdi, [rsp + 8]",   // arg0 (restore)
-        "mov rsi, [rsp + 16]",  // arg1 (restore)
-        "mov rdx, [rsp + 24]",  // arg2 (restore)
-        "mov r10, [rsp + 32]",  // arg3 (restore)
-        "mov r8,  [rsp + 40]",  // arg4 (restore)
-        "mov r9,  [rsp + 48]",  // arg5 (restore)
-        "mov rcx, [rsp + 56]",  // user RIP
-        "mov r11, [rsp + 64]",  // user RFLAGS
-        "mov r12, [rsp + 72]",  // user RSP
+        kstack_top = const PERCPU_KSTACK_TOP_OFFSET,
+        rust = sym syscall_fast_rust,
+        ret = sym syscall_return,
+    );
+}
 
+/// Return to user mode from the [`SyscallFrame`] at `rsp`.
+///
+/// Shared by the entry stub and by forked children, whose first kernel
+/// context "returns" here with a copy of the parent's frame (see
+/// [`fork_context`](crate::process::context::fork_context)).
+///
+/// # Safety
+/// Jump (or `ret`) here with `rsp` pointing at a valid frame, interrupts
+/// disabled or about to be restored from the frame, and the kernel GS active.
+#[unsafe(naked)]
+pub unsafe extern "C" fn syscall_return() -> ! {
+    core::arch::naked_asm!(
+        // Load fields back into registers:
+        "mov rax, [rsp + 0]",  // return value
+        "mov rdi, [rsp + 8]",  // arg0 (restore)
+        "mov rsi, [rsp + 16]", // arg1 (restore)
+        "mov rdx, [rsp + 24]", // arg2 (restore)
+        "mov r10, [rsp + 32]", // arg3 (restore)
+        "mov r8,  [rsp + 40]", // arg4 (restore)
+        "mov r9,  [rsp + 48]", // arg5 (restore)
+        "mov rcx, [rsp + 56]", // user RIP
+        "mov r11, [rsp + 64]", // user RFLAGS
+        "mov r12, [rsp + 72]", // user RSP
+        "mov rbx, [rsp + 80]",
+        "mov rbp, [rsp + 88]",
+        "mov r13, [rsp + 96]",
+        "mov r14, [rsp + 104]",
+        "mov r15, [rsp + 112]",
         // Switch to user stack
         "mov rsp, r12",
-
         // Back to user GS
         "swapgs",
-
         // Return to user
         "sysretq",
-
-        kstack_top = const PERCPU_KSTACK_TOP_OFFSET,
-        rust = sym syscall_fast_rust,
     );
 }
 
@@ -148,4 +180,6 @@ extern "C" fn syscall_fast_rust(tf: &mut SyscallFrame) {
     let a5 = tf.r9;
 
     tf.rax = syscall(sysno, a0, a1, a2, a3, a4, a5, SyscallSource::Syscall);
+    preempt::preempt_point();
+    signal::deliver(tf);
 }
@@ -0,0 +1,243 @@
+//! File and handle syscalls: `pipe`, `open`, `read`, `write`, `readv`,
+//! `writev`, `close`, `ioctl`, `readdir`, `handle_dup`, `handle_close` and
+//! `poll`.
+
+use crate::dir;
+use crate::pipe::PipeError;
+use crate::process::handle::{self, HandleError};
+use crate::uaccess::{UserIoVec, UserSlice};
+use crate::{sched, signal, timer};
+use core::mem::offset_of;
+use log::debug;
+use stdlib::syscall_abi::poll::FOREVER;
+use stdlib::syscall_abi::signal::SIGPIPE;
+use stdlib::syscall_abi::{DirEntry, MAX_PATH_LEN, MAX_POLL_HANDLES, PollFd, SYSCALL_ERROR};
+
+/// `pipe(fds_ptr)`: create a pipe and store the descriptors of its read and
+/// write end as two `u32`s at `fds_ptr`; returns `0`.
+#[allow(clippy::cast_possible_truncation)]
+pub fn sys_pipe(fds_ptr: u64) -> u64 {
+    let fds = match handle::pipe() {
+        Ok(fds) => fds,
+        Err(e) => return fail("pipe", e),
+    };
+
+    let mut bytes = [0u8; 8];
+    for (dst, fd) in bytes.chunks_exact_mut(4).zip(fds) {
+        dst.copy_from_slice(&(fd as u32).to_ne_bytes());
+    }
+    if UserSlice::new(fds_ptr, bytes.len())
+        .and_then(|fds| fds.write(&bytes))
+        .is_err()
+    {
+        for fd in fds {
+            let _ = handle::close(fd);
+        }
+        return SYSCALL_ERROR;
+    }
+    0
+}
+
+/// `open(path_ptr, path_len)`: open a file for reading; returns its
+/// descriptor.
+#[allow(clippy::cast_possible_truncation)]
+pub fn sys_open(path_ptr: u64, path_len: u64) -> u64 {
+    let Ok(path) =
+        UserSlice::new(path_ptr, path_len as usize).and_then(UserSlice::read_vec::<MAX_PATH_LEN>)
+    else {
+        return SYSCALL_ERROR;
+    };
+    let Ok(path) = core::str::from_utf8(&path) else {
+        return SYSCALL_ERROR;
+    };
+
+    match handle::open(path) {
+        Ok(fd) => fd as u64,
+        Err(e) => fail("open", e),
+    }
+}
+
+/// `read(fd, buf_ptr, len)`: read up to `len` bytes; returns how many, `0`
+/// at end of file.
+#[allow(clippy::cast_possible_truncation)]
+pub fn sys_read(fd: u64, buf_ptr: u64, len: u64) -> u64 {
+    let Ok(buf) = UserSlice::new(buf_ptr, len as usize) else {
+        return SYSCALL_ERROR;
+    };
+    match handle::read(fd as usize, &UserIoVec::from(buf)) {
+        Ok(n) => n as u64,
+        Err(e) => fail("read", e),
+    }
+}
+
+/// `readv(fd, iov_ptr, count)`: read into the `count` buffers of the
+/// [`IoVec`](stdlib::syscall_abi::IoVec) array at `iov_ptr`, in order, like
+/// `read` into one buffer; returns how many bytes, `0` at end of file.
+#[allow(clippy::cast_possible_truncation)]
+pub fn sys_readv(fd: u64, iov_ptr: u64, count: u64) -> u64 {
+    let Ok(iov) = UserIoVec::new(iov_ptr, count as usize) else {
+        return SYSCALL_ERROR;
+    };
+    match handle::read(fd as usize, &iov) {
+        Ok(n) => n as u64,
+        Err(e) => fail("readv", e),
+    }
+}
+
+/// `write(fd, buf_ptr, len)`: write `len` bytes; returns how many.
+///
+/// Writing to a pipe without readers also raises `SIGPIPE`.
+#[allow(clippy::cast_possible_truncation)]
+pub fn sys_write(fd: u64, buf_ptr: u64, len: u64) -> u64 {
+    let Ok(buf) = UserSlice::new(buf_ptr, len as usize) else {
+        return SYSCALL_ERROR;
+    };
+    write(fd as usize, &UserIoVec::from(buf), "write")
+}
+
+/// `writev(fd, iov_ptr, count)`: write the `count` buffers of the
+/// [`IoVec`](stdlib::syscall_abi::IoVec) array at `iov_ptr`, in order, like
+/// `write`; returns how many bytes.
+#[allow(clippy::cast_possible_truncation)]
+pub fn sys_writev(fd: u64, iov_ptr: u64, count: u64) -> u64 {
+    let Ok(iov) = UserIoVec::new(iov_ptr, count as usize) else {
+        return SYSCALL_ERROR;
+    };
+    write(fd as usize, &iov, "writev")
+}
+
+/// Write `buf` to `fd` for the syscall `op`, raising `SIGPIPE` for a pipe
+/// without readers.
+fn write(fd: usize, buf: &UserIoVec, op: &str) -> u64 {
+    match handle::write(fd, buf) {
+        Ok(n) => n as u64,
+        Err(e) => {
+            if e == HandleError::Pipe(PipeError::BrokenPipe)
+                && let Some(me) = sched::current_pid()
+            {
+                let _ = signal::send(me, SIGPIPE);
+            }
+            fail(op, e)
+        }
+    }
+}
+
+/// `close(fd)`: close a file descriptor; returns `0`.
+#[allow(clippy::cast_possible_truncation)]
+pub fn sys_close(fd: u64) -> u64 {
+    match handle::close(fd as usize) {
+        Ok(()) => 0,
+        Err(e) => fail("close", e),
+    }
+}
+
+/// `handle_dup(handle, rights)`: open another handle to the object of
+/// `handle` with `rights`, a subset of its own; returns the new handle.
+#[allow(clippy::cast_possible_truncation)]
+pub fn sys_handle_dup(handle: u64, rights: u64) -> u64 {
+    match handle::dup(handle as usize, rights) {
+        Ok(copy) => copy as u64,
+        Err(e) => fail("handle_dup", e),
+    }
+}
+
+/// `handle_close(handle)`: close a handle of any kind; returns `0`.
+#[allow(clippy::cast_possible_truncation)]
+pub fn sys_handle_close(handle: u64) -> u64 {
+    match handle::close(handle as usize) {
+        Ok(()) => 0,
+        Err(e) => fail("handle_close", e),
+    }
+}
+
+/// `poll(fds_ptr, count, timeout_ms)`: wait up to `timeout_ms`
+/// milliseconds ([`FOREVER`] for no limit) until one of the `count`
+/// [`PollFd`]s at `fds_ptr` is ready, and store the ready events in their
+/// `revents`; returns how many are ready, `0` if the timeout passed or a
+/// signal interrupted the wait.
+#[allow(clippy::cast_possible_truncation)]
+pub fn sys_poll(fds_ptr: u64, count: u64, timeout_ms: u64) -> u64 {
+    const LEN: usize = size_of::<PollFd>();
+    let count = count as usize;
+    if count > MAX_POLL_HANDLES {
+        return SYSCALL_ERROR;
+    }
+    let Ok(buf) = UserSlice::new(fds_ptr, count * LEN) else {
+        return SYSCALL_ERROR;
+    };
+    let mut bytes = [0u8; MAX_POLL_HANDLES * LEN];
+    let bytes = &mut bytes[..count * LEN];
+    if buf.read_into(bytes).is_err() || buf.check_writable().is_err() {
+        return SYSCALL_ERROR;
+    }
+
+    let mut fds = [PollFd::default(); MAX_POLL_HANDLES];
+    let fds = &mut fds[..count];
+    for (fd, raw) in fds.iter_mut().zip(bytes.chunks_exact(LEN)) {
+        // SAFETY: `PollFd` is `repr(C)` without padding, valid for any bytes.
+        *fd = unsafe { raw.as_ptr().cast::<PollFd>().read_unaligned() };
+    }
+
+    let deadline = (timeout_ms != FOREVER)
+        .then(|| sched::now_ticks().saturating_add(timer::ms_to_ticks(timeout_ms)));
+    let ready = match handle::poll(fds, deadline) {
+        Ok(ready) => ready,
+        Err(e) => return fail("poll", e),
+    };
+
+    for (fd, raw) in fds.iter().zip(bytes.chunks_exact_mut(LEN)) {
+        raw[offset_of!(PollFd, revents)..].copy_from_slice(&fd.revents.to_ne_bytes());
+    }
+    if buf.write(bytes).is_err() {
+        return SYSCALL_ERROR;
+    }
+    ready as u64
+}
+
+/// `ioctl(fd, request, arg)`: apply a device-specific request; returns its
+/// result.
+#[allow(clippy::cast_possible_truncation)]
+pub fn sys_ioctl(fd: u64, request: u64, arg: u64) -> u64 {
+    match handle::control(fd as usize, request, arg) {
+        Ok(ret) => ret,
+        Err(e) => fail("ioctl", e),
+    }
+}
+
+/// `readdir(path_ptr, path_len, cursor, entry_ptr)`: describe the entry at
+/// `cursor` of a directory in the [`DirEntry`] at `entry_ptr`; returns the
+/// cursor of the next entry.
+#[allow(clippy::cast_possible_truncation)]
+pub fn sys_readdir(path_ptr: u64, path_len: u64, cursor: u64, entry_ptr: u64) -> u64 {
+    let Ok(path) =
+        UserSlice::new(path_ptr, path_len as usize).and_then(UserSlice::read_vec::<MAX_PATH_LEN>)
+    else {
+        return SYSCALL_ERROR;
+    };
+    let Ok(path) = core::str::from_utf8(&path) else {
+        return SYSCALL_ERROR;
+    };
+    let Some((entry, next)) = usize::try_from(cursor)
+        .ok()
+        .and_then(|cursor| dir::read(path, cursor))
+    else {
+        return SYSCALL_ERROR;
+    };
+
+    // SAFETY: `DirEntry` is `repr(C)` without padding.
+    let bytes = unsafe {
+        core::slice::from_raw_parts((&raw const entry).cast::<u8>(), size_of::<DirEntry>())
+    };
+    if UserSlice::new(entry_ptr, bytes.len())
+        .and_then(|dst| dst.write(bytes))
+        .is_err()
+    {
+        return SYSCALL_ERROR;
+    }
+    next as u64
+}
+
+fn fail(op: &str, e: HandleError) -> u64 {
+    debug!("{op} failed: {e}");
+    SYSCALL_ERROR
+}
@@ -0,0 +1,80 @@
+//! Logging syscalls: `log` and `log_read`.
+//!
+//! `log` forwards a user message into the kernel's [`log`] facade under the
+//! `user` target, prefixed with the caller's PID, so it reaches the same
+//! sinks as kernel messages. `log_read` hands lines from the
+//! [log ring](crate::klog) back to userland, e.g. to a future `syslogd`.
+
+use crate::klog::{self, LINE_LEN};
+use crate::sched;
+use crate::uaccess::UserSlice;
+use log::Level;
+use stdlib::syscall_abi::{LOG_RECORD_LEN, LogLevel, MAX_LOG_LEN, SYSCALL_ERROR};
+
+// Level digit, space, text and newline must fit into one record.
+const _: () = assert!(LINE_LEN + 3 <= LOG_RECORD_LEN);
+
+/// `log(level, ptr, len)`: log a UTF-8 message; returns its length.
+#[allow(clippy::cast_possible_truncation)]
+pub fn sys_log(level: u64, ptr: u64, len: u64) -> u64 {
+    let Some(level) = LogLevel::from_raw(level) else {
+        return SYSCALL_ERROR;
+    };
+    let Ok(buf) = UserSlice::new(ptr, len as usize).and_then(UserSlice::read_vec::<MAX_LOG_LEN>)
+    else {
+        return SYSCALL_ERROR;
+    };
+    let Ok(msg) = core::str::from_utf8(&buf) else {
+        return SYSCALL_ERROR;
+    };
+
+    let level = match level {
+        LogLevel::Error => Level::Error,
+        LogLevel::Warn => Level::Warn,
+        LogLevel::Info => Level::Info,
+        LogLevel::Debug => Level::Debug,
+        LogLevel::Trace => Level::Trace,
+    };
+    match sched::current_pid() {
+        Some(pid) => log::log!(target: "user", level, "pid {pid}: {msg}"),
+        None => log::log!(target: "user", level, "{msg}"),
+    }
+    len
+}
+
+/// `log_read(buf, len)`: move as many log ring lines as fit into `buf`, one
+/// [`LOG_RECORD_LEN`] record each; returns the number of bytes written.
+#[allow(clippy::cast_possible_truncation)]
+pub fn sys_log_read(buf: u64, len: u64) -> u64 {
+    let records = len as usize / LOG_RECORD_LEN;
+    if records == 0 {
+        return SYSCALL_ERROR;
+    }
+    let Ok(buf) = UserSlice::new(buf, records * LOG_RECORD_LEN) else {
+        return SYSCALL_ERROR;
+    };
+    // Check up front so that no line is taken from the ring and then lost.
+    if buf.check_writable().is_err() {
+        return SYSCALL_ERROR;
+    }
+
+    let mut written = 0;
+    while written < buf.len() {
+        let Some(line) = klog::pop() else {
+            break;
+        };
+
+        let mut record = [0u8; LOG_RECORD_LEN];
+        let text = line.text().as_bytes();
+        record[0] = b'0' + line.level as u8;
+        record[1] = b' ';
+        record[2..2 + text.len()].copy_from_slice(text);
+        record[2 + text.len()] = b'\n';
+
+        if buf.sub(written, LOG_RECORD_LEN).write(&record).is_err() {
+            return SYSCALL_ERROR;
+        }
+        written += LOG_RECORD_LEN;
+    }
+    written as u64
+}
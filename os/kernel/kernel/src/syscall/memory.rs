@@ -0,0 +1,104 @@
+//! Memory syscalls: `shm_open`, `shm_close` and `mmap`.
+
+use crate::process::handle::{self, Object};
+use crate::process::mmap::{self, Backing};
+use crate::shm::MAX_SHM_NAME_LEN;
+use crate::uaccess::UserSlice;
+use kernel_memory_addresses::{PageSize, Size4K};
+use kernel_vmem::vma::VmaPerms;
+use log::warn;
+use stdlib::syscall_abi::handle::{RIGHT_MAP, RIGHT_WRITE};
+use stdlib::syscall_abi::{
+    MAP_ANONYMOUS, MAP_DEVICE, MAP_PRIVATE, MAP_SHARED, PROT_EXEC, PROT_READ, PROT_WRITE,
+    SYSCALL_ERROR,
+};
+
+/// `shm_open(name_ptr, name_len, size)`: open the shared memory object
+/// `name`, creating it with `size` bytes if needed; returns a handle.
+#[allow(clippy::cast_possible_truncation)]
+pub fn sys_shm_open(name_ptr: u64, name_len: u64, size: u64) -> u64 {
+    let Ok(name) = UserSlice::new(name_ptr, name_len as usize)
+        .and_then(UserSlice::read_vec::<MAX_SHM_NAME_LEN>)
+    else {
+        return SYSCALL_ERROR;
+    };
+
+    match handle::shm_open(&name, size) {
+        Ok(handle) => handle as u64,
+        Err(e) => {
+            warn!("shm_open failed: {e}");
+            SYSCALL_ERROR
+        }
+    }
+}
+
+/// `shm_close(handle)`: close a shared memory handle; returns `0`.
+#[allow(clippy::cast_possible_truncation)]
+pub fn sys_shm_close(handle: u64) -> u64 {
+    let handle = handle as usize;
+    match handle::lookup(handle, 0) {
+        Ok(Object::Shm(_)) if handle::close(handle).is_ok() => 0,
+        _ => SYSCALL_ERROR,
+    }
+}
+
+/// `mmap(addr, len, prot, flags, handle, offset)`: map memory into the
+/// calling process; returns the address of the mapping.
+///
+/// Supports private anonymous mappings, shared mappings of a shared memory
+/// object (`handle`, from the page aligned `offset` on) and private mappings
+/// of a file (`handle` is its descriptor, again from the page aligned
+/// `offset` on). With `MAP_SHARED | MAP_DEVICE`, `handle` is the descriptor
+/// of a character device whose memory to map. `handle` needs `RIGHT_MAP`,
+/// and `RIGHT_WRITE` too for a writable shared mapping. `addr` is only a
+/// hint and currently ignored.
+#[allow(clippy::cast_possible_truncation)]
+pub fn sys_mmap(_addr: u64, len: u64, prot: u64, flags: u64, handle: u64, offset: u64) -> u64 {
+    if prot & !(PROT_READ | PROT_WRITE | PROT_EXEC) != 0 || prot & PROT_READ == 0 {
+        return SYSCALL_ERROR;
+    }
+    let perms = VmaPerms::new(true, prot & PROT_WRITE != 0, prot & PROT_EXEC != 0);
+
+    let object = if flags == MAP_PRIVATE | MAP_ANONYMOUS {
+        None
+    } else {
+        // Shared mappings write through to the object; private ones copy.
+        let rights = if flags & MAP_SHARED != 0 && perms.write {
+            RIGHT_MAP | RIGHT_WRITE
+        } else {
+            RIGHT_MAP
+        };
+        match handle::lookup(handle as usize, rights) {
+            Ok(object) => Some(object),
+            Err(e) => {
+                warn!("mmap of handle {handle} failed: {e}");
+                return SYSCALL_ERROR;
+            }
+        }
+    };
+
+    let aligned = offset.is_multiple_of(Size4K::SIZE);
+    let backing = match object {
+        None => Backing::Anonymous,
+        Some(Object::Shm(id)) if flags == MAP_SHARED && aligned => Backing::Shared {
+            id,
+            first_page: (offset / Size4K::SIZE) as usize,
+        },
+        Some(Object::Char(id)) if flags == MAP_SHARED | MAP_DEVICE && aligned => {
+            Backing::Device { id, offset }
+        }
+        Some(Object::Bundle { entry, .. }) if flags == MAP_PRIVATE && aligned => Backing::File {
+            inode: entry as u64,
+            offset,
+        },
+        Some(_) => return SYSCALL_ERROR,
+    };
+
+    match mmap::mmap(len, perms, backing) {
+        Ok(va) => va.as_u64(),
+        Err(e) => {
+            warn!("mmap failed: {e}");
+            SYSCALL_ERROR
+        }
+    }
+}
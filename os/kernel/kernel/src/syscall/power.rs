@@ -0,0 +1,21 @@
+//! Power syscalls: `reboot`.
+
+use crate::power::{self, PowerAction};
+use crate::process::caps;
+use stdlib::syscall_abi::caps::CAP_REBOOT;
+use stdlib::syscall_abi::{SYSCALL_ERROR, reboot};
+
+/// `reboot(cmd)`: power off, restart or halt the machine; needs
+/// `CAP_REBOOT`. Does not return on success. See [`power`].
+pub fn sys_reboot(cmd: u64) -> u64 {
+    let action = match cmd {
+        reboot::POWER_OFF => PowerAction::PowerOff,
+        reboot::RESTART => PowerAction::Reboot,
+        reboot::HALT => PowerAction::Halt,
+        _ => return SYSCALL_ERROR,
+    };
+    if !caps::require(CAP_REBOOT, "reboot") {
+        return SYSCALL_ERROR;
+    }
+    power::shutdown(action)
+}
@@ -0,0 +1,225 @@
+//! Process management syscalls: `spawn`, `fork`, `waitpid`, `exit`,
+//! `setpriority`, `sched_setaffinity`, `arch_prctl`, `task_info`, `trace`,
+//! `cap_get` and `cap_set`.
+
+use crate::process::{self, ArgBuf, Pid, caps};
+use crate::sched;
+use crate::sched::priority::Priority;
+use crate::strace;
+use crate::syscall::SyscallSource;
+use crate::syscall::entry;
+use crate::tasks;
+use crate::uaccess::{UserPtr, UserSlice};
+use log::warn;
+use stdlib::syscall_abi::arch_prctl::{ARCH_GET_FS, ARCH_SET_FS};
+use stdlib::syscall_abi::caps::{EFFECTIVE, INHERITABLE};
+use stdlib::syscall_abi::{MAX_PATH_LEN, MAX_SPAWN_ARGS, SYSCALL_ERROR, TaskInfo, UserStr};
+
+/// `spawn(path_ptr, path_len, argv_ptr, argc, envp_ptr, envc)`: start a
+/// program; returns its PID.
+///
+/// `argv_ptr` and `envp_ptr` point at `argc` and `envc` [`UserStr`] records.
+#[allow(clippy::cast_possible_truncation)]
+pub fn sys_spawn(
+    path_ptr: u64,
+    path_len: u64,
+    argv_ptr: u64,
+    argc: u64,
+    envp_ptr: u64,
+    envc: u64,
+) -> u64 {
+    let Ok(path) =
+        UserSlice::new(path_ptr, path_len as usize).and_then(UserSlice::read_vec::<MAX_PATH_LEN>)
+    else {
+        return SYSCALL_ERROR;
+    };
+    let Ok(path) = core::str::from_utf8(&path) else {
+        return SYSCALL_ERROR;
+    };
+
+    let (Some(arguments), Some(environment)) =
+        (copy_strings(argv_ptr, argc), copy_strings(envp_ptr, envc))
+    else {
+        return SYSCALL_ERROR;
+    };
+
+    match process::spawn(path, &arguments, &environment, sched::current_pid()) {
+        Ok(pid) => pid.as_u64(),
+        Err(e) => {
+            warn!("spawn of {path} failed: {e}");
+            SYSCALL_ERROR
+        }
+    }
+}
+
+/// Copy `count` user strings described by the [`UserStr`] array at `ptr`.
+#[allow(clippy::cast_possible_truncation)]
+fn copy_strings(ptr: u64, count: u64) -> Option<ArgBuf> {
+    if count as usize > MAX_SPAWN_ARGS {
+        return None;
+    }
+
+    let records = UserSlice::new(ptr, count as usize * size_of::<UserStr>()).ok()?;
+    let mut strings = ArgBuf::new();
+    for i in 0..count as usize {
+        let record = records.sub(i * size_of::<UserStr>(), size_of::<UserStr>());
+        let s = UserPtr::<UserStr>::new(record.addr()).ok()?.read().ok()?;
+        let dst = strings.push_uninit(s.len as usize)?;
+        UserSlice::new(s.ptr, dst.len()).ok()?.read_into(dst).ok()?;
+    }
+
+    Some(strings)
+}
+
+/// `fork()`: duplicate the calling process; returns the child's PID in the
+/// parent and `0` in the child.
+///
+/// Only supported through `syscall`: the child resumes via the `syscall`
+/// stub's saved frame, which `int 0x80` does not build.
+pub fn sys_fork(source: SyscallSource) -> u64 {
+    if source != SyscallSource::Syscall {
+        return SYSCALL_ERROR;
+    }
+
+    let frame = unsafe { entry::current_frame() };
+    match process::fork(frame) {
+        Ok(pid) => pid.as_u64(),
+        Err(e) => {
+            warn!("fork failed: {e}");
+            SYSCALL_ERROR
+        }
+    }
+}
+
+/// `waitpid(pid)`: wait for a child to exit; returns its exit code.
+pub fn sys_waitpid(pid: u64) -> u64 {
+    let (Some(caller), Some(child)) = (sched::current_pid(), Pid::from_raw(pid)) else {
+        return SYSCALL_ERROR;
+    };
+
+    process::wait(caller, child).map_or(SYSCALL_ERROR, u64::from)
+}
+
+/// `exit(code)`: terminate the calling process. Does not return.
+#[allow(clippy::cast_possible_truncation)]
+pub fn sys_exit(code: u64) -> ! {
+    process::exit(code as u32)
+}
+
+/// `setpriority(pid, priority)`: set the scheduling priority of a process
+/// (`0` for the caller); returns the previous priority. See
+/// [`caps::may_control`] for which processes the caller may reach.
+pub fn sys_setpriority(pid: u64, priority: u64) -> u64 {
+    let pid = if pid == 0 {
+        sched::current_pid()
+    } else {
+        Pid::from_raw(pid)
+    };
+    let (Some(pid), Some(priority)) = (pid, Priority::new(priority)) else {
+        return SYSCALL_ERROR;
+    };
+    if !caps::may_control(pid, "setpriority") {
+        return SYSCALL_ERROR;
+    }
+
+    sched::set_priority(pid, priority).map_or(SYSCALL_ERROR, |old| u64::from(old.level()))
+}
+
+/// `sched_setaffinity(pid, mask)`: let the process `pid` (`0` for the
+/// caller) run only on the CPUs in `mask`; returns `0`. Fails unless the
+/// mask names an online CPU. See [`sched::set_affinity`], and
+/// [`caps::may_control`] for which processes the caller may reach.
+pub fn sys_sched_setaffinity(pid: u64, mask: u64) -> u64 {
+    let pid = if pid == 0 {
+        sched::current_pid()
+    } else {
+        Pid::from_raw(pid)
+    };
+    pid.filter(|&pid| caps::may_control(pid, "sched_setaffinity"))
+        .and_then(|pid| sched::set_affinity(pid, mask))
+        .map_or(SYSCALL_ERROR, |_| 0)
+}
+
+/// First address past the lower canonical half; FS bases must lie below.
+const USER_ADDRESS_END: u64 = 1 << 47;
+
+/// `arch_prctl(code, addr)`: set the caller's FS base to `addr`
+/// (`ARCH_SET_FS`) or store it at `addr` (`ARCH_GET_FS`); returns `0`.
+pub fn sys_arch_prctl(code: u64, addr: u64) -> u64 {
+    match code {
+        ARCH_SET_FS if addr < USER_ADDRESS_END => {
+            process::set_fs_base(addr);
+            0
+        }
+        ARCH_GET_FS => {
+            let base = process::fs_base();
+            let base = base.to_ne_bytes();
+            if UserSlice::new(addr, base.len())
+                .and_then(|dst| dst.write(&base))
+                .is_err()
+            {
+                return SYSCALL_ERROR;
+            }
+            0
+        }
+        _ => SYSCALL_ERROR,
+    }
+}
+
+/// `task_info(cursor, info_ptr)`: store the [`TaskInfo`] of the first task at
+/// or after `cursor` at `info_ptr`; returns the cursor of the next task. See
+/// [`tasks::info`].
+pub fn sys_task_info(cursor: u64, info_ptr: u64) -> u64 {
+    let Some((info, next)) = usize::try_from(cursor).ok().and_then(tasks::info) else {
+        return SYSCALL_ERROR;
+    };
+
+    // SAFETY: `TaskInfo` is `repr(C)` without padding.
+    let bytes = unsafe {
+        core::slice::from_raw_parts((&raw const info).cast::<u8>(), size_of::<TaskInfo>())
+    };
+    if UserSlice::new(info_ptr, bytes.len())
+        .and_then(|dst| dst.write(bytes))
+        .is_err()
+    {
+        return SYSCALL_ERROR;
+    }
+    next as u64
+}
+
+/// `trace(pid, on)`: switch [syscall tracing](strace) of a process (`0` for
+/// the caller) on (`1`) or off (`0`); returns whether it was on. See
+/// [`caps::may_control`] for which processes the caller may reach.
+pub fn sys_trace(pid: u64, on: u64) -> u64 {
+    let pid = if pid == 0 {
+        sched::current_pid()
+    } else {
+        Pid::from_raw(pid)
+    };
+    let (Some(pid), Some(on)) = (pid, matches!(on, 0 | 1).then_some(on == 1)) else {
+        return SYSCALL_ERROR;
+    };
+    if !caps::may_control(pid, "trace") {
+        return SYSCALL_ERROR;
+    }
+
+    strace::set_traced(pid, on).map_or(SYSCALL_ERROR, u64::from)
+}
+
+/// `cap_get(which)`: return the caller's effective or inheritable
+/// [capabilities](caps).
+pub fn sys_cap_get(which: u64) -> u64 {
+    let held = caps::current();
+    match which {
+        EFFECTIVE => held.effective(),
+        INHERITABLE => held.inheritable(),
+        _ => SYSCALL_ERROR,
+    }
+}
+
+/// `cap_set(which, caps)`: replace the caller's effective or inheritable
+/// capabilities with a subset of the effective ones; returns the previous
+/// set. See [`caps::set`].
+pub fn sys_cap_set(which: u64, mask: u64) -> u64 {
+    caps::set(which, mask).unwrap_or(SYSCALL_ERROR)
+}
@@ -0,0 +1,18 @@
+//! Randomness syscalls: `getrandom`.
+
+use crate::random;
+use crate::uaccess::UserSlice;
+use stdlib::syscall_abi::{MAX_GETRANDOM_LEN, SYSCALL_ERROR};
+
+/// `getrandom(buf_ptr, len)`: fill the buffer with random bytes; returns how
+/// many, at most [`MAX_GETRANDOM_LEN`].
+#[allow(clippy::cast_possible_truncation)]
+pub fn sys_getrandom(buf_ptr: u64, len: u64) -> u64 {
+    let len = (len as usize).min(MAX_GETRANDOM_LEN);
+    let mut bytes = [0u8; MAX_GETRANDOM_LEN];
+    random::rand_bytes(&mut bytes[..len]);
+    match UserSlice::new(buf_ptr, len).and_then(|buf| buf.write(&bytes[..len])) {
+        Ok(()) => len as u64,
+        Err(_) => SYSCALL_ERROR,
+    }
+}
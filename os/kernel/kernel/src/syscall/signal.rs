@@ -0,0 +1,60 @@
+//! Signal syscalls: `kill`, `sigaction` and `sigreturn`.
+
+use crate::process::{Pid, caps};
+use crate::signal;
+use crate::syscall::SyscallSource;
+use crate::syscall::entry;
+use log::debug;
+use stdlib::syscall_abi::SYSCALL_ERROR;
+
+/// `kill(pid, signo)`: send a signal to a process; returns `0`.
+///
+/// Signal `0` only checks that `pid` exists. Processes other than the
+/// caller, its parent and its children need `CAP_PROCESS`; see
+/// [`caps::may_signal`].
+pub fn sys_kill(pid: u64, signo: u64) -> u64 {
+    let (Some(pid), Ok(signo)) = (Pid::from_raw(pid), u32::try_from(signo)) else {
+        return SYSCALL_ERROR;
+    };
+    if !caps::may_signal(pid) {
+        return SYSCALL_ERROR;
+    }
+
+    match signal::send(pid, signo) {
+        Ok(()) => 0,
+        Err(e) => {
+            debug!("kill({pid}, {signo}) failed: {e}");
+            SYSCALL_ERROR
+        }
+    }
+}
+
+/// `sigaction(signo, handler, restorer)`: set how a signal is handled;
+/// returns the previous handler value.
+pub fn sys_sigaction(signo: u64, handler: u64, restorer: u64) -> u64 {
+    let Ok(signo) = u32::try_from(signo) else {
+        return SYSCALL_ERROR;
+    };
+
+    match signal::set_action(signo, handler, restorer) {
+        Ok(old) => old,
+        Err(e) => {
+            debug!("sigaction({signo}) failed: {e}");
+            SYSCALL_ERROR
+        }
+    }
+}
+
+/// `sigreturn()`: resume the context saved by signal delivery at the user
+/// stack pointer. Does not return to the caller.
+///
+/// Only supported through `syscall`, whose saved frame holds the user stack
+/// pointer.
+pub fn sys_sigreturn(source: SyscallSource) -> u64 {
+    if source != SyscallSource::Syscall {
+        return SYSCALL_ERROR;
+    }
+
+    let rsp = unsafe { entry::current_frame() }.rsp;
+    signal::sigreturn(rsp)
+}
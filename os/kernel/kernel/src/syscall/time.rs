@@ -0,0 +1,54 @@
+//! Sleep syscalls: `nanosleep`, `clock_nanosleep`.
+//!
+//! Both sleep until a TSC deadline with [`sched::sleep_until_tsc`]; the
+//! monotonic clock of `clock_nanosleep` is the one userland reads from the
+//! [clock page](crate::clock_page): the TSC converted to nanoseconds.
+
+use crate::clock;
+use crate::sched;
+use crate::tsc::rdtsc;
+use stdlib::syscall_abi::SYSCALL_ERROR;
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+/// `nanosleep(ns)`: sleep for `ns` nanoseconds; returns the nanoseconds
+/// left if a signal cut the sleep short, `0` otherwise.
+pub fn sys_nanosleep(ns: u64) -> u64 {
+    let hz = clock::tsc_hz();
+    if hz == 0 {
+        return SYSCALL_ERROR;
+    }
+    sleep_until(rdtsc().saturating_add(ns_to_tsc(ns, hz)), hz)
+}
+
+/// `clock_nanosleep(deadline)`: sleep until the monotonic clock reads
+/// `deadline` nanoseconds; returns the nanoseconds left if a signal cut the
+/// sleep short, `0` otherwise.
+pub fn sys_clock_nanosleep(deadline: u64) -> u64 {
+    let hz = clock::tsc_hz();
+    if hz == 0 {
+        return SYSCALL_ERROR;
+    }
+    sleep_until(ns_to_tsc(deadline, hz), hz)
+}
+
+fn sleep_until(deadline: u64, hz: u64) -> u64 {
+    if sched::sleep_until_tsc(deadline) {
+        0
+    } else {
+        // At least 1, so the caller can tell the sleep was cut short.
+        tsc_to_ns(deadline.saturating_sub(rdtsc()), hz).max(1)
+    }
+}
+
+/// TSC cycles for `ns` nanoseconds at `hz`, rounded up so that a deadline
+/// is never early.
+fn ns_to_tsc(ns: u64, hz: u64) -> u64 {
+    let cycles = (u128::from(ns) * u128::from(hz)).div_ceil(NANOS_PER_SEC);
+    u64::try_from(cycles).unwrap_or(u64::MAX)
+}
+
+/// Nanoseconds for `cycles` TSC cycles at `hz`, rounded down.
+fn tsc_to_ns(cycles: u64, hz: u64) -> u64 {
+    u64::try_from(u128::from(cycles) * NANOS_PER_SEC / u128::from(hz)).unwrap_or(u64::MAX)
+}
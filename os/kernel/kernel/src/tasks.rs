@@ -0,0 +1,155 @@
+//! # Task Listing
+//!
+//! Snapshots of the [process table](crate::process::PROCESSES), user processes
+//! and kernel threads alike, for debugging and for `ps`-like tools.
+//!
+//! * [`info`] describes one task as a [`TaskInfo`], the record the
+//!   `task_info` system call hands to user space. Tasks are addressed by a
+//!   *cursor*: the table slot to start searching at, so a listing starts at
+//!   `0` and continues with the cursor returned for the previous task.
+//! * [`dump`] logs a table of all tasks. Pressing F12 runs it from the
+//!   [keyboard](crate::keyboard) handler. [`write_table`] writes the same
+//!   table anywhere else, e.g. to the [debugger](crate::kdb).
+//!
+//! Tasks come and go between two calls of [`info`]; a listing is consistent
+//! per task, not as a whole.
+
+use crate::process::{MAX_PROCESSES, PROCESSES, Pid, Process, ProcessState};
+use core::fmt;
+use kernel_sync::IrqGuard;
+use log::info;
+use stdlib::syscall_abi::TaskInfo;
+use stdlib::syscall_abi::task::{
+    KIND_KERNEL, KIND_USER, NAME_LEN, NO_CPU, STATE_BLOCKED, STATE_READY, STATE_RUNNING,
+    STATE_ZOMBIE,
+};
+
+/// The first task in a table slot at or after `cursor` and the cursor of the
+/// task after it, or `None` if there is none.
+pub fn info(cursor: usize) -> Option<(TaskInfo, usize)> {
+    let _irq = IrqGuard::new();
+    let table = PROCESSES.lock();
+    (cursor..MAX_PROCESSES).find_map(|slot| table.get(slot).map(|p| (snapshot(p), slot + 1)))
+}
+
+/// Column headers of the task table.
+const HEADER: &str =
+    "  PID  PPID NAME             KIND   STATE    CPU PRIO AFFINITY              TICKS";
+
+/// Log a table of all tasks.
+pub fn dump() {
+    // Logging is slow; copy everything out of the table first.
+    let tasks = collect();
+    info!("{HEADER}");
+    for task in tasks.iter().flatten() {
+        info!("{}", Row(task));
+    }
+}
+
+/// Write a table of all tasks to `out`, one line per task.
+pub fn write_table(out: &mut impl fmt::Write) -> fmt::Result {
+    let tasks = collect();
+    writeln!(out, "{HEADER}")?;
+    for task in tasks.iter().flatten() {
+        writeln!(out, "{}", Row(task))?;
+    }
+    Ok(())
+}
+
+/// Snapshots of all tasks, in table order.
+fn collect() -> [Option<TaskInfo>; MAX_PROCESSES] {
+    let mut tasks = [None; MAX_PROCESSES];
+    let mut cursor = 0;
+    for task in &mut tasks {
+        let Some((info, next)) = info(cursor) else {
+            break;
+        };
+        *task = Some(info);
+        cursor = next;
+    }
+    tasks
+}
+
+/// One line of the task table.
+struct Row<'a>(&'a TaskInfo);
+
+impl fmt::Display for Row<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let task = self.0;
+        write!(
+            f,
+            "{pid:>5} {ppid:>5} {name:<16} {kind:<6} {state:<8} {cpu:>3} {prio:>4} {affinity:#018x} {ticks:>8}",
+            pid = task.pid,
+            ppid = task.ppid,
+            name = task.name(),
+            kind = kind_name(task.kind),
+            state = state_name(task.state),
+            cpu = CpuColumn(task.cpu),
+            prio = task.priority,
+            affinity = task.affinity,
+            ticks = task.run_ticks
+        )
+    }
+}
+
+/// The [`TaskInfo`] of `p`.
+fn snapshot(p: &Process) -> TaskInfo {
+    let (state, exit_code) = match p.state {
+        ProcessState::Ready => (STATE_READY, 0),
+        ProcessState::Running => (STATE_RUNNING, 0),
+        ProcessState::Blocked { .. } => (STATE_BLOCKED, 0),
+        ProcessState::Zombie(code) => (STATE_ZOMBIE, code),
+    };
+
+    let mut name = [0; NAME_LEN];
+    name[..p.name().len()].copy_from_slice(p.name().as_bytes());
+
+    TaskInfo {
+        pid: p.pid.as_u32(),
+        tid: p.pid.as_u32(),
+        ppid: p.parent.map_or(0, Pid::as_u32),
+        state,
+        kind: if p.is_kernel_thread() {
+            KIND_KERNEL
+        } else {
+            KIND_USER
+        },
+        cpu: p.cpu.unwrap_or(NO_CPU),
+        priority: u32::from(p.sched.priority().level()),
+        exit_code,
+        affinity: p.affinity,
+        run_ticks: p.sched.run_ticks,
+        name,
+    }
+}
+
+/// The CPU column of [`dump`]: the CPU index, or `-` if the task never ran.
+struct CpuColumn(u32);
+
+impl fmt::Display for CpuColumn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0 == NO_CPU {
+            f.pad("-")
+        } else {
+            fmt::Display::fmt(&self.0, f)
+        }
+    }
+}
+
+const fn kind_name(kind: u32) -> &'static str {
+    match kind {
+        KIND_USER => "user",
+        KIND_KERNEL => "kernel",
+        _ => "?",
+    }
+}
+
+const fn state_name(state: u32) -> &'static str {
+    match state {
+        STATE_READY => "ready",
+        STATE_RUNNING => "running",
+        STATE_BLOCKED => "blocked",
+        STATE_ZOMBIE => "zombie",
+        _ => "?",
+    }
+}
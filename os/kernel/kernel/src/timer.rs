@@ -0,0 +1,350 @@
+//! # Software Timers
+//!
+//! One-shot and periodic timers that call a function at a given timer tick,
+//! for everything in the kernel that needs to act at a point in time rather
+//! than poll for it.
+//!
+//! ## Interface
+//!
+//! * [`schedule`] arms a one-shot timer that calls `callback(arg)` once the
+//!   deadline has passed; [`schedule_periodic`] re-arms it every `period`
+//!   ticks after that.
+//! * Both return a [`TimerHandle`] for [`cancel`]. Handles of timers that
+//!   already fired (or were cancelled) are stale and harmless: cancelling
+//!   them does nothing, even if the slot was reused meanwhile.
+//!
+//! Deadlines are absolute timer ticks ([`sched::now_ticks`](crate::sched::now_ticks));
+//! [`ms_to_ticks`] converts durations.
+//!
+//! ## Timer wheel
+//!
+//! Armed timers live in a hierarchical timing wheel of [`LEVELS`] levels
+//! with 64 buckets each. A bucket of level `n` spans `64^n` ticks, so a
+//! timer lands in level 0 if it is due within 64 ticks, in level 1 if due
+//! within 4096, and so on. Whenever the lower level wraps around, the next
+//! bucket of the level above is *cascaded*: its timers are sorted into the
+//! finer levels below. Arming, cancelling and expiring are O(1); timers
+//! further out than the wheel spans are parked in the top level and
+//! re-cascaded until they are due.
+//!
+//! ## Expiry
+//!
+//! The local APIC timer interrupt calls [`on_tick`], which
+//! advances the wheel and runs the callbacks of expired timers. Callbacks run
+//! in interrupt context with the wheel unlocked, so they may arm or cancel
+//! timers, but they must be short; anything heavy belongs on the
+//! [workqueue](crate::workqueue).
+//!
+//! ## Limitations
+//!
+//! * At most [`MAX_TIMERS`] timers can be armed at the same time.
+//! * There is one wheel, driven by the ticks of the one CPU running the
+//!   timer interrupt.
+
+use crate::clock;
+use kernel_sync::{IrqGuard, SpinMutex};
+
+/// Most timers armed at the same time.
+pub const MAX_TIMERS: usize = 64;
+
+/// Levels of the timer wheel.
+pub const LEVELS: usize = 4;
+
+/// log2 of the buckets per level.
+const LEVEL_BITS: u32 = 6;
+
+/// Buckets per level.
+const LEVEL_SIZE: usize = 1 << LEVEL_BITS;
+
+/// End of a list of entries.
+const NIL: u16 = u16::MAX;
+
+/// Handle of an armed timer, for [`cancel`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct TimerHandle {
+    index: u16,
+    generation: u32,
+}
+
+/// A timer slot, linked into a bucket while armed and into the free list
+/// otherwise.
+#[derive(Copy, Clone)]
+struct Entry {
+    /// Tick at which the timer is due.
+    deadline: u64,
+    /// Ticks between two expiries; `0` for one-shot timers.
+    period: u64,
+    /// `None` while the slot is free.
+    callback: Option<fn(usize)>,
+    arg: usize,
+    /// Bumped whenever the slot is freed, invalidating its handles.
+    generation: u32,
+    next: u16,
+    prev: u16,
+    /// Bucket the entry is linked into.
+    bucket: u16,
+}
+
+impl Entry {
+    const EMPTY: Self = Self {
+        deadline: 0,
+        period: 0,
+        callback: None,
+        arg: 0,
+        generation: 0,
+        next: NIL,
+        prev: NIL,
+        bucket: 0,
+    };
+}
+
+/// The timer wheel; see the [module docs](self).
+struct Wheel {
+    entries: [Entry; MAX_TIMERS],
+    /// First entry of each bucket, level by level.
+    buckets: [u16; LEVELS * LEVEL_SIZE],
+    /// First free entry.
+    free: u16,
+    /// Number of armed timers.
+    armed: usize,
+    /// Next tick to expire timers for.
+    now: u64,
+}
+
+static WHEEL: SpinMutex<Wheel> = SpinMutex::new(Wheel::new());
+
+impl Wheel {
+    #[allow(clippy::cast_possible_truncation)]
+    const fn new() -> Self {
+        let mut entries = [Entry::EMPTY; MAX_TIMERS];
+        let mut i = 0;
+        while i + 1 < MAX_TIMERS {
+            entries[i].next = (i + 1) as u16;
+            i += 1;
+        }
+
+        Self {
+            entries,
+            buckets: [NIL; LEVELS * LEVEL_SIZE],
+            free: 0,
+            armed: 0,
+            now: 0,
+        }
+    }
+
+    fn arm(
+        &mut self,
+        deadline: u64,
+        period: u64,
+        callback: fn(usize),
+        arg: usize,
+    ) -> Option<TimerHandle> {
+        let index = self.free;
+        let entry = self.entries.get_mut(usize::from(index))?;
+        self.free = entry.next;
+        entry.deadline = deadline;
+        entry.period = period;
+        entry.callback = Some(callback);
+        entry.arg = arg;
+        let generation = entry.generation;
+
+        self.armed += 1;
+        self.insert(index);
+        Some(TimerHandle { index, generation })
+    }
+
+    fn cancel(&mut self, handle: TimerHandle) -> bool {
+        let Some(entry) = self.entries.get(usize::from(handle.index)) else {
+            return false;
+        };
+        if entry.callback.is_none() || entry.generation != handle.generation {
+            return false;
+        }
+
+        self.unlink(handle.index);
+        self.release(handle.index);
+        true
+    }
+
+    /// The bucket a timer due at `deadline` belongs to.
+    #[allow(clippy::cast_possible_truncation)]
+    fn bucket_for(&self, deadline: u64) -> u16 {
+        // Overdue timers go into the bucket expired next.
+        let deadline = deadline.max(self.now);
+        let delta = deadline - self.now;
+
+        let (level, deadline) = (0..LEVELS)
+            .find(|&level| delta >> (LEVEL_BITS * (level as u32 + 1)) == 0)
+            .map_or_else(
+                // Beyond the wheel: park in the farthest bucket.
+                || {
+                    (
+                        LEVELS - 1,
+                        self.now + (1 << (LEVEL_BITS * LEVELS as u32)) - 1,
+                    )
+                },
+                |level| (level, deadline),
+            );
+        let index = (deadline >> (LEVEL_BITS * level as u32)) as usize & (LEVEL_SIZE - 1);
+        (level * LEVEL_SIZE + index) as u16
+    }
+
+    /// Link the entry `index` into the bucket of its deadline.
+    fn insert(&mut self, index: u16) {
+        let bucket = self.bucket_for(self.entries[usize::from(index)].deadline);
+        let head = self.buckets[usize::from(bucket)];
+        if head != NIL {
+            self.entries[usize::from(head)].prev = index;
+        }
+
+        let entry = &mut self.entries[usize::from(index)];
+        entry.bucket = bucket;
+        entry.prev = NIL;
+        entry.next = head;
+        self.buckets[usize::from(bucket)] = index;
+    }
+
+    /// Unlink the entry `index` from its bucket.
+    fn unlink(&mut self, index: u16) {
+        let Entry {
+            prev, next, bucket, ..
+        } = self.entries[usize::from(index)];
+        if prev == NIL {
+            self.buckets[usize::from(bucket)] = next;
+        } else {
+            self.entries[usize::from(prev)].next = next;
+        }
+        if next != NIL {
+            self.entries[usize::from(next)].prev = prev;
+        }
+    }
+
+    /// Return the unlinked entry `index` to the free list.
+    fn release(&mut self, index: u16) {
+        let entry = &mut self.entries[usize::from(index)];
+        entry.callback = None;
+        entry.generation = entry.generation.wrapping_add(1);
+        entry.next = self.free;
+        self.free = index;
+        self.armed -= 1;
+    }
+
+    /// Sort the timers of `bucket` into their buckets as of now.
+    fn rehash(&mut self, bucket: usize) {
+        let mut index = core::mem::replace(&mut self.buckets[bucket], NIL);
+        while index != NIL {
+            let next = self.entries[usize::from(index)].next;
+            self.insert(index);
+            index = next;
+        }
+    }
+
+    /// Move the timers of the next upper-level buckets down; called whenever
+    /// level 0 wraps around.
+    #[allow(clippy::cast_possible_truncation)]
+    fn cascade(&mut self) {
+        for level in 1..LEVELS {
+            let index = (self.now >> (LEVEL_BITS * level as u32)) as usize & (LEVEL_SIZE - 1);
+            self.rehash(level * LEVEL_SIZE + index);
+            if index != 0 {
+                break;
+            }
+        }
+    }
+
+    /// Take one timer due by tick `now`, re-arming it if periodic, and return
+    /// its callback.
+    #[allow(clippy::cast_possible_truncation)]
+    fn next_expired(&mut self, now: u64) -> Option<(fn(usize), usize)> {
+        while self.now <= now {
+            if self.armed == 0 {
+                // Nothing to cascade; skip ahead.
+                self.now = now + 1;
+                break;
+            }
+
+            let bucket = self.now as usize & (LEVEL_SIZE - 1);
+            let index = self.buckets[bucket];
+            if index == NIL {
+                self.now += 1;
+                if self.now as usize & (LEVEL_SIZE - 1) == 0 {
+                    self.cascade();
+                }
+                continue;
+            }
+
+            self.unlink(index);
+            let entry = &mut self.entries[usize::from(index)];
+            let fired = (entry.callback?, entry.arg);
+            if entry.period == 0 {
+                self.release(index);
+            } else {
+                // Skip missed periods rather than firing them back to back.
+                entry.deadline = entry
+                    .deadline
+                    .saturating_add(entry.period)
+                    .max(self.now + 1);
+                self.insert(index);
+            }
+            return Some(fired);
+        }
+        None
+    }
+}
+
+/// Call `callback(arg)` once timer tick `deadline` has passed.
+///
+/// Returns `None` if [`MAX_TIMERS`] timers are armed already.
+pub fn schedule(deadline: u64, callback: fn(usize), arg: usize) -> Option<TimerHandle> {
+    let _irq = IrqGuard::new();
+    WHEEL.lock().arm(deadline, 0, callback, arg)
+}
+
+/// Call `callback(arg)` once timer tick `deadline` has passed, and then every
+/// `period` ticks until cancelled.
+///
+/// Returns `None` if `period` is `0` or [`MAX_TIMERS`] timers are armed
+/// already.
+#[allow(dead_code)]
+pub fn schedule_periodic(
+    deadline: u64,
+    period: u64,
+    callback: fn(usize),
+    arg: usize,
+) -> Option<TimerHandle> {
+    if period == 0 {
+        return None;
+    }
+
+    let _irq = IrqGuard::new();
+    WHEEL.lock().arm(deadline, period, callback, arg)
+}
+
+/// Disarm the timer `handle`.
+///
+/// Returns `false` if the timer already fired (one-shot) or was cancelled.
+pub fn cancel(handle: TimerHandle) -> bool {
+    let _irq = IrqGuard::new();
+    WHEEL.lock().cancel(handle)
+}
+
+/// Timer ticks that span at least `ms` milliseconds.
+pub fn ms_to_ticks(ms: u64) -> u64 {
+    ms.saturating_mul(clock::timer_hz().max(1)).div_ceil(1000)
+}
+
+/// Run the callbacks of all timers due by tick `now`.
+///
+/// Called from the timer interrupt.
+pub fn on_tick(now: u64) {
+    loop {
+        let fired = {
+            let _irq = IrqGuard::new();
+            WHEEL.lock().next_expired(now)
+        };
+        let Some((callback, arg)) = fired else {
+            break;
+        };
+        callback(arg);
+    }
+}
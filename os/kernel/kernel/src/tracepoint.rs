@@ -0,0 +1,343 @@
+//! # Tracepoints
+//!
+//! Static instrumentation points for performance analysis, in the spirit of
+//! Linux' ftrace events.
+//!
+//! ## Events
+//!
+//! Every event is declared once in [`events`] with a class and the names of
+//! its arguments, and fired with [`trace_event!`]:
+//!
+//! ```ignore
+//! trace_event!(sched_switch, from_pid, to_pid);
+//! ```
+//!
+//! While the event's [`EventClass`] is disabled, a tracepoint costs a single
+//! relaxed load. Otherwise it writes a fixed-size record with the TSC, the
+//! event and up to [`MAX_ARGS`] arguments into the current CPU's
+//! [`CpuTrace`] ring, overwriting the oldest record once the ring is full.
+//! Recording never allocates or locks, so tracepoints may fire in interrupt
+//! and NMI context.
+//!
+//! ## Dumps
+//!
+//! [`dump`] writes the current CPU's ring to the QEMU debug console, bypassing
+//! the logger, as one line per record:
+//!
+//! ```text
+//! TRACE-BEGIN cpu=0 tsc_hz=2999997000 records=3 lost=0
+//! TRACE cpu=0 tsc=1234567890 event=sched_switch from=0 to=1
+//! ...
+//! TRACE-END cpu=0
+//! ```
+//!
+//! The panic handler dumps the ring if any class is enabled.
+//!
+//! ## Configuration
+//!
+//! `trace=<class>,<class>,...` on the [kernel command line](crate::cmdline)
+//! enables event classes at boot (`trace=all` enables every class); at run
+//! time, [`enable`] and [`disable`] switch them individually.
+
+pub mod events;
+
+use crate::clock;
+use crate::cmdline::{self, Param, ParamKind};
+use crate::per_cpu::PerCpu;
+use crate::process::Pid;
+use crate::tsc::rdtsc;
+use core::sync::atomic::{AtomicPtr, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use kernel_memory_addresses::VirtualAddress;
+use kernel_qemu::qemu_trace;
+use log::{info, warn};
+
+pub static TRACE_PARAM: Param = Param {
+    name: "trace",
+    kind: ParamKind::Str,
+    help: "comma-separated tracepoint classes to enable, or `all`",
+};
+
+/// Records kept per CPU.
+pub const TRACE_RING_LEN: usize = 1024;
+
+/// Most arguments a single event can carry.
+pub const MAX_ARGS: usize = 4;
+
+/// Bit `n` set: events of the class with discriminant `n` are recorded.
+static ENABLED: AtomicU32 = AtomicU32::new(0);
+
+/// A group of events switched on and off together.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(u8)]
+pub enum EventClass {
+    /// Context switches, blocking and wake-ups.
+    Sched = 0,
+    /// Interrupts and NMIs.
+    Irq = 1,
+    /// System call entry and exit.
+    Syscall = 2,
+    /// Process creation and termination.
+    Process = 3,
+    /// System calls of [traced](crate::strace) processes.
+    Strace = 4,
+}
+
+impl EventClass {
+    pub const ALL: [Self; 5] = [
+        Self::Sched,
+        Self::Irq,
+        Self::Syscall,
+        Self::Process,
+        Self::Strace,
+    ];
+
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Sched => "sched",
+            Self::Irq => "irq",
+            Self::Syscall => "syscall",
+            Self::Process => "process",
+            Self::Strace => "strace",
+        }
+    }
+
+    const fn bit(self) -> u32 {
+        1 << self as u8
+    }
+}
+
+/// A static event description; see [`events`].
+#[derive(Debug)]
+pub struct TraceEvent {
+    pub name: &'static str,
+    pub class: EventClass,
+    /// Argument names, in the order the tracepoint passes them.
+    pub fields: &'static [&'static str],
+}
+
+/// Values that can be passed as tracepoint arguments.
+pub trait TraceArg {
+    fn to_trace_arg(self) -> u64;
+}
+
+impl TraceArg for u64 {
+    fn to_trace_arg(self) -> u64 {
+        self
+    }
+}
+
+impl TraceArg for u32 {
+    fn to_trace_arg(self) -> u64 {
+        self.into()
+    }
+}
+
+impl TraceArg for usize {
+    fn to_trace_arg(self) -> u64 {
+        self as u64
+    }
+}
+
+impl TraceArg for bool {
+    fn to_trace_arg(self) -> u64 {
+        self.into()
+    }
+}
+
+impl TraceArg for Pid {
+    fn to_trace_arg(self) -> u64 {
+        self.as_u64()
+    }
+}
+
+/// `0` stands for "no process", e.g. the idle loop.
+impl TraceArg for Option<Pid> {
+    fn to_trace_arg(self) -> u64 {
+        self.map_or(0, Pid::as_u64)
+    }
+}
+
+impl TraceArg for VirtualAddress {
+    fn to_trace_arg(self) -> u64 {
+        self.as_u64()
+    }
+}
+
+/// Fire the tracepoint `$event` from [`events`] with the given arguments.
+macro_rules! trace_event {
+    ($event:ident $(, $arg:expr)* $(,)?) => {{
+        let event = &$crate::tracepoint::events::$event;
+        if $crate::tracepoint::is_enabled(event.class) {
+            $crate::tracepoint::record(
+                event,
+                &[$($crate::tracepoint::TraceArg::to_trace_arg($arg)),*],
+            );
+        }
+    }};
+}
+
+pub(crate) use trace_event;
+
+/// One record of a [`CpuTrace`] ring.
+struct TraceSlot {
+    /// Sequence number of the record plus one; `0` while it is being written.
+    seq: AtomicU64,
+    tsc: AtomicU64,
+    event: AtomicPtr<TraceEvent>,
+    args: [AtomicU64; MAX_ARGS],
+}
+
+impl TraceSlot {
+    const fn new() -> Self {
+        Self {
+            seq: AtomicU64::new(0),
+            tsc: AtomicU64::new(0),
+            event: AtomicPtr::new(core::ptr::null_mut()),
+            args: [const { AtomicU64::new(0) }; MAX_ARGS],
+        }
+    }
+}
+
+/// Per-CPU trace ring, embedded in [`PerCpu`].
+pub struct CpuTrace {
+    /// Records written so far; the ring index is this modulo the ring size.
+    head: AtomicUsize,
+    slots: [TraceSlot; TRACE_RING_LEN],
+}
+
+impl CpuTrace {
+    // Only ever evaluated at compile time, into the static per-CPU data.
+    #[allow(clippy::large_stack_arrays)]
+    pub const fn new() -> Self {
+        Self {
+            head: AtomicUsize::new(0),
+            slots: [const { TraceSlot::new() }; TRACE_RING_LEN],
+        }
+    }
+
+    fn push(&self, event: &'static TraceEvent, args: &[u64]) {
+        let seq = self.head.fetch_add(1, Ordering::Relaxed);
+        let slot = &self.slots[seq % TRACE_RING_LEN];
+        slot.seq.store(0, Ordering::Relaxed);
+        core::sync::atomic::fence(Ordering::Release);
+        slot.tsc.store(rdtsc(), Ordering::Relaxed);
+        slot.event
+            .store(core::ptr::from_ref(event).cast_mut(), Ordering::Relaxed);
+        for (i, arg) in slot.args.iter().enumerate() {
+            arg.store(args.get(i).copied().unwrap_or(0), Ordering::Relaxed);
+        }
+        slot.seq.store(seq as u64 + 1, Ordering::Release);
+    }
+
+    /// The record with sequence number `seq`, unless it was overwritten or is
+    /// still being written.
+    fn read(&self, seq: usize) -> Option<(u64, &'static TraceEvent, [u64; MAX_ARGS])> {
+        let slot = &self.slots[seq % TRACE_RING_LEN];
+        let expected = seq as u64 + 1;
+        if slot.seq.load(Ordering::Acquire) != expected {
+            return None;
+        }
+        let tsc = slot.tsc.load(Ordering::Relaxed);
+        let event = slot.event.load(Ordering::Relaxed);
+        let args = core::array::from_fn(|i| slot.args[i].load(Ordering::Relaxed));
+        core::sync::atomic::fence(Ordering::Acquire);
+        if slot.seq.load(Ordering::Relaxed) != expected {
+            return None;
+        }
+        // Only pointers to declared events are ever stored.
+        let event = events::ALL
+            .iter()
+            .copied()
+            .find(|e| core::ptr::eq(*e, event))?;
+        Some((tsc, event, args))
+    }
+}
+
+/// Enable the event classes listed in `trace` on the command line.
+///
+/// Tracepoints fire on the current CPU's [`PerCpu`], so this must run after
+/// the per-CPU data is set up.
+pub fn init() {
+    let Some(list) = cmdline::get_str(TRACE_PARAM.name) else {
+        return;
+    };
+    for name in list.split(',').filter(|name| !name.is_empty()) {
+        if name == "all" {
+            EventClass::ALL.into_iter().for_each(enable);
+        } else if let Some(class) = EventClass::ALL.into_iter().find(|c| c.name() == name) {
+            enable(class);
+        } else {
+            warn!("Unknown tracepoint class {name:?}");
+        }
+    }
+
+    let enabled = ENABLED.load(Ordering::Relaxed);
+    for class in EventClass::ALL {
+        if enabled & class.bit() != 0 {
+            info!("Tracing {} events", class.name());
+        }
+    }
+}
+
+/// Start recording events of `class`.
+pub fn enable(class: EventClass) {
+    ENABLED.fetch_or(class.bit(), Ordering::Relaxed);
+}
+
+/// Stop recording events of `class`.
+#[allow(dead_code)]
+pub fn disable(class: EventClass) {
+    ENABLED.fetch_and(!class.bit(), Ordering::Relaxed);
+}
+
+/// Whether events of `class` are recorded.
+#[must_use]
+pub fn is_enabled(class: EventClass) -> bool {
+    ENABLED.load(Ordering::Relaxed) & class.bit() != 0
+}
+
+/// Whether any event class is recorded.
+#[must_use]
+pub fn any_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed) != 0
+}
+
+/// Append a record to the current CPU's ring; use [`trace_event!`] instead.
+pub fn record(event: &'static TraceEvent, args: &[u64]) {
+    debug_assert_eq!(
+        args.len(),
+        event.fields.len(),
+        "wrong argument count for tracepoint {}",
+        event.name
+    );
+    let cpu = unsafe { PerCpu::current() };
+    cpu.trace.push(event, args);
+}
+
+/// Write the records of the current CPU to the debug console; see the
+/// [module docs](self) for the format.
+pub fn dump() {
+    let cpu = unsafe { PerCpu::current() };
+    let trace = &cpu.trace;
+    let head = trace.head.load(Ordering::Acquire);
+    let kept = head.min(TRACE_RING_LEN);
+
+    qemu_trace!(
+        "TRACE-BEGIN cpu={} tsc_hz={} records={kept} lost={}\n",
+        cpu.cpu_id,
+        clock::tsc_hz(),
+        head - kept
+    );
+    for seq in head - kept..head {
+        let Some((tsc, event, args)) = trace.read(seq) else {
+            continue;
+        };
+        qemu_trace!("TRACE cpu={} tsc={tsc} event={}", cpu.cpu_id, event.name);
+        for (field, value) in event.fields.iter().zip(args) {
+            qemu_trace!(" {field}={value}");
+        }
+        qemu_trace!("\n");
+    }
+    qemu_trace!("TRACE-END cpu={}\n", cpu.cpu_id);
+}
@@ -0,0 +1,38 @@
+//! Declarations of all tracepoint events.
+//!
+//! Each line `name: Class(field, ...);` declares a static [`TraceEvent`]
+//! called `name`, which [`trace_event!`](super::trace_event) refers to.
+
+#![allow(non_upper_case_globals)]
+
+use super::{EventClass, TraceEvent};
+
+macro_rules! events {
+    ($($name:ident: $class:ident($($field:ident),*);)*) => {
+        $(
+            pub static $name: TraceEvent = TraceEvent {
+                name: stringify!($name),
+                class: EventClass::$class,
+                fields: &[$(stringify!($field)),*],
+            };
+        )*
+
+        /// Every declared event.
+        pub static ALL: &[&TraceEvent] = &[$(&$name),*];
+    };
+}
+
+events! {
+    sched_switch: Sched(from, to);
+    sched_block: Sched(pid, until);
+    sched_wakeup: Sched(pid);
+    sched_place: Sched(pid, cpu);
+    irq_timer: Irq(tick, rip);
+    irq_nmi: Irq(rip);
+    syscall_enter: Syscall(nr, arg0, arg1, arg2);
+    syscall_exit: Syscall(nr, ret);
+    process_spawn: Process(pid, parent);
+    process_exit: Process(pid, code);
+    strace_enter: Strace(pid, nr, arg0, arg1);
+    strace_exit: Strace(pid, nr, ret);
+}
@@ -1,5 +1,6 @@
 //! # Kernel Tracing helpers
 
+use crate::framebuffer::pixel::PixelFormat;
 use kernel_info::boot::{BootPixelFormat, KernelBootInfo};
 use log::info;
 
@@ -7,16 +8,21 @@ pub fn trace_boot_info(boot_info: &KernelBootInfo) {
     info!(
         concat!(
             "Boot Info in Kernel:\n",
-            "  BI ptr   = {bi:#018x}\n",
+            "  BI ptr   = {bi:#018x}, version = {bi_version}, size = {bi_size}, caps = {bi_caps}\n",
             "  MMAP ptr = {mmap_ptr:#018x}, len = {mmap_len}, desc size = {mmap_desc_size}, desc ver = {mmap_desc_ver}, rsdp addr = {rsdp_addr}\n",
-            "  FB ptr   = {fb_ptr:#018x}, size = {fb_size}, width = {fb_width}, height = {fb_height}, stride = {fb_stride}, format = {fb_fmt}"
+            "  FB ptr   = {fb_ptr:#018x}, size = {fb_size}, width = {fb_width}, height = {fb_height}, stride = {fb_stride}, format = {fb_fmt}, mode = {fb_selection:?} of {fb_modes}\n",
+            "  Cmdline  = {cmdline_ptr:#018x}, len = {cmdline_len}\n",
+            "  Arena    = {arena_ptr:#018x}, len = {arena_len}"
         ),
         bi = core::ptr::from_ref(boot_info) as usize,
+        bi_version = boot_info.header.version,
+        bi_size = boot_info.header.size,
+        bi_caps = boot_info.header.capabilities,
         mmap_ptr = boot_info.mmap.mmap_ptr,
         mmap_len = boot_info.mmap.mmap_len,
         mmap_desc_size = boot_info.mmap.mmap_desc_size,
         mmap_desc_ver = usize::try_from(boot_info.mmap.mmap_desc_version).unwrap_or_default(),
-        rsdp_addr = usize::try_from(boot_info.rsdp_addr).unwrap_or_default(),
+        rsdp_addr = boot_info.rsdp().unwrap_or_default(),
         fb_ptr = boot_info.fb.framebuffer_ptr,
         fb_size = boot_info.fb.framebuffer_size,
         fb_width = boot_info.fb.framebuffer_width,
@@ -28,7 +34,26 @@ pub fn trace_boot_info(boot_info: &KernelBootInfo) {
             BootPixelFormat::Bitmask => "Bitmask",
             BootPixelFormat::BltOnly => "BltOnly",
         },
+        fb_selection = boot_info.fb.framebuffer_selection,
+        fb_modes = boot_info.fb.framebuffer_mode_count,
+        cmdline_ptr = boot_info.cmdline_ptr,
+        cmdline_len = boot_info.cmdline_len,
+        arena_ptr = boot_info.arena.phys_start,
+        arena_len = boot_info.arena.length,
     );
+
+    if matches!(boot_info.fb.framebuffer_format, BootPixelFormat::Bitmask) {
+        let masks = &boot_info.fb.framebuffer_masks;
+        info!(
+            "  FB masks = R {r:#010x}, G {g:#010x}, B {b:#010x}, A {a:#010x}, {bpp} bytes per pixel",
+            r = masks.red_mask,
+            g = masks.green_mask,
+            b = masks.blue_mask,
+            a = masks.alpha_mask,
+            bpp = PixelFormat::from_boot(BootPixelFormat::Bitmask, masks)
+                .map_or(0, PixelFormat::bytes_per_pixel),
+        );
+    }
 }
 
 pub fn log_ctrl_bits() {
@@ -15,28 +15,51 @@
 //! ## TSC Frequency Detection Strategy
 //!
 //! The module employs a fallback hierarchy to determine TSC frequency with maximum
-//! accuracy and compatibility:
+//! accuracy and compatibility. The deterministic sources come first; each result
+//! carries its [`TscSource`] and, through it, a [`Confidence`]:
 //!
 //! ### 1. CPUID Leaf 15H (Primary Method)
 //! - **Source**: Architectural frequency information from processor
 //! - **Accuracy**: Exact crystal oscillator frequency and ratio
-//! - **Requirements**: TSC/CORE crystal ratio and crystal frequency both non-zero
+//! - **Requirements**: Intel; TSC/CORE crystal ratio and crystal frequency both non-zero
 //! - **Formula**: `TSC_Hz = crystal_hz × (numerator / denominator)`
-//! - **Availability**: Modern Intel processors, some AMD processors
+//! - **Availability**: Modern Intel processors
 //!
-//! ### 2. CPUID Leaf 16H (Secondary Method)
+//! ### 2. Hypervisor Timing Leaf 40000010H
+//! - **Source**: The TSC frequency the host measured, in kHz
+//! - **Accuracy**: Reported; within 1 kHz
+//! - **Requirements**: Hypervisor bit set and leaf 40000010H present
+//! - **Availability**: VMware, KVM/QEMU with `vmware-cpuid-freq` and an invariant TSC
+//!
+//! ### 3. Hyper-V TSC Frequency MSR
+//! - **Source**: MSR `0x40000022`, in Hz
+//! - **Accuracy**: Exact as far as the host knows
+//! - **Requirements**: Hyper-V signature and the frequency MSR feature bits
+//! - **Availability**: Hyper-V, and KVM with Hyper-V enlightenments
+//!
+//! ### 4. CPUID Leaf 16H with a Leaf 15H Ratio
 //! - **Source**: Processor base frequency information
-//! - **Accuracy**: Good approximation assuming TSC runs at base frequency
-//! - **Requirements**: Base frequency must be reported (non-zero)
+//! - **Accuracy**: Nominal; no error bound
+//! - **Requirements**: Intel; a leaf 15H ratio (so the TSC runs at the base
+//!   frequency) and a non-zero base frequency
 //! - **Formula**: `TSC_Hz = base_mhz × 1,000,000`
+//! - **Availability**: Intel processors that report the ratio but not the crystal
+//!
+//! ### 5. PIT Calibration
+//! - **Source**: Measurement against the Programmable Interval Timer (PIT)
+//! - **Accuracy**: Within the error bound [`pit::measure`] reports, typically
+//!   a few parts per million over the measurement window
+//! - **Requirements**: Functional PIT channel 2 (gated through port `0x61`)
+//! - **Method**: Count TSC cycles over [`CALIBRATION_MS`] of PIT countdown
+//! - **Availability**: Nearly universal (all PC-compatible systems have a PIT)
+//!
+//! ### 6. CPUID Leaf 16H (Last Resort)
+//! - **Source**: Processor base frequency information
+//! - **Accuracy**: Nominal only; no error bound
+//! - **Requirements**: Base frequency must be reported (non-zero)
 //! - **Availability**: Intel processors with frequency reporting
 //!
-//! ### 3. PIT Calibration (Fallback Method)
-//! - **Source**: Measurement against Programmable Interval Timer (PIT)
-//! - **Accuracy**: Limited by measurement window and system noise
-//! - **Requirements**: Functional PIT hardware and stable timing
-//! - **Method**: Measure TSC delta over known PIT countdown period
-//! - **Availability**: Universal (all x86 systems have PIT)
+//! There is no HPET driver yet, so the PIT is the only measurement reference.
 //!
 //! ## Key Functions
 //!
@@ -45,27 +68,10 @@
 //!
 //! ### Frequency Detection
 //! * [`estimate_tsc_hz`] - Multi-method TSC frequency detection
-//! * [`cpuid_leaf_15_tsc_hz`] - CPUID.15H crystal-based frequency
+//! * [`Leaf15h::tsc_hz`] - CPUID.15H crystal-based frequency
+//! * [`hypervisor_tsc_hz`] - Hypervisor timing leaf and Hyper-V MSR
+//! * [`pit::measure`] - PIT-based measurement with an error bound
 //! * [`cpuid_leaf_16_base_mhz_hz`] - CPUID.16H base frequency estimation
-//! * [`pit_measure_tsc_hz`] - PIT-based measurement calibration
-//!
-//! ## PIT Calibration Details
-//!
-//! When CPUID methods fail, the module falls back to PIT-based measurement:
-//!
-//! ### Calibration Process
-//! 1. **PIT Setup**: Configure Channel 0 in Mode 2 (rate generator)
-//! 2. **Window Selection**: Use configurable measurement window (typically 50ms)
-//! 3. **Synchronization**: Read TSC before and after PIT countdown
-//! 4. **Calculation**: Convert TSC delta to frequency using known time window
-//!
-//! ### PIT Configuration
-//! ```text
-//! Channel 0: Rate generator mode (Mode 2)
-//! Input Frequency: 1,193,182 Hz (standard PIT frequency)
-//! Reload Value: Calculated for desired measurement window
-//! Access Mode: Low byte, then high byte
-//! ```
 //!
 //! ## Timing Accuracy Considerations
 //!
@@ -85,8 +91,8 @@
 //!
 //! ### Basic Frequency Detection
 //! ```rust
-//! let tsc_hz = unsafe { estimate_tsc_hz() };
-//! println!("TSC frequency: {} Hz", tsc_hz);
+//! let tsc = unsafe { estimate_tsc_hz() }.expect("TSC frequency");
+//! println!("TSC frequency: {tsc}");
 //! ```
 //!
 //! ### High-Resolution Timing
@@ -95,7 +101,7 @@
 //! // ... timed operation ...
 //! let end = rdtsc();
 //! let cycles = end - start;
-//! let nanoseconds = (cycles * 1_000_000_000) / tsc_hz;
+//! let nanoseconds = (cycles * 1_000_000_000) / tsc.hz;
 //! ```
 //!
 //! ## Safety Considerations
@@ -118,150 +124,157 @@
 //! * **Intel**: Full support for all detection methods
 //! * **AMD**: Partial CPUID support, PIT fallback available
 //! * **Virtual Machines**: Variable support, often requires PIT calibration
-//! * **Legacy Systems**: PIT calibration provides universal compatibility
+//! * **Legacy Systems**: PIT calibration provides near-universal compatibility
 
-#![allow(dead_code)]
+use crate::clock::Calibration;
+use crate::cpuid::{
+    CpuVendor, CpuidRanges, Hypervisor, HypervisorTiming, Leaf01h, Leaf15h, Leaf16,
+};
+use crate::pit;
+use core::fmt;
+use kernel_registers::msr::{Msr, try_rdmsr};
 
-use crate::cpuid::{CpuidRanges, Leaf15h, Leaf16};
-use crate::ports::{inb, outb};
+/// PIT time the TSC is counted over.
+const CALIBRATION_MS: u64 = 50;
 
-/// Best-effort TSC frequency estimate in Hz.
-/// Order: CPUID.15H → CPUID.16H → PIT measurement.
-/// Call with interrupts masked to reduce jitter during PIT timing.
-pub unsafe fn estimate_tsc_hz() -> u64 {
-    unsafe {
-        if let Some(hz) = cpuid_leaf_15_tsc_hz() {
-            return hz;
-        }
-        if let Some(hz) = cpuid_leaf_16_base_mhz_hz() {
-            return hz;
-        }
-        pit_measure_tsc_hz(50_000) // 50 ms window for decent precision
-    }
+/// Hyper-V: TSC frequency in Hz.
+const HV_X64_MSR_TSC_FREQUENCY: Msr = Msr(0x4000_0022);
+
+/// Where the TSC frequency came from.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TscSource {
+    /// CPUID leaf 15H: crystal frequency and TSC ratio.
+    Cpuid15h,
+    /// Hypervisor timing leaf 40000010H.
+    HypervisorLeaf,
+    /// Hyper-V TSC frequency MSR.
+    HyperVMsr,
+    /// Measured against the PIT.
+    Pit,
+    /// CPUID leaf 16H base frequency.
+    Cpuid16h,
 }
 
-/// Try CPUID.15H (TSC/CORE crystal + ratio).
-/// EAX = denom, EBX = numer, ECX = `crystal_hz` (may be 0).
-#[inline]
-unsafe fn cpuid_leaf_15_tsc_hz() -> Option<u64> {
-    // Check max leaf first.
-    let ranges = unsafe { CpuidRanges::read() };
-    let r = unsafe { Leaf15h::read(&ranges)? };
+/// How far a [`TscSource`] can be trusted.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Confidence {
+    /// A nominal value the TSC may not actually run at.
+    Nominal,
+    /// Measured; the calibration carries the error bound.
+    Measured,
+    /// Reported by the hypervisor with limited resolution.
+    Reported,
+    /// Architecturally defined.
+    Exact,
+}
 
-    if r.denom == 0 || r.numer == 0 {
-        return None;
-    }
-    // If ECX==0, many VMs/older CPUs don’t report the crystal. Bail out here;
-    // avoiding guessing (19.2/24/25 MHz) keeps this robust.
-    if r.crystal_hz == 0 {
-        return None;
+impl TscSource {
+    pub const fn confidence(self) -> Confidence {
+        match self {
+            Self::Cpuid15h | Self::HyperVMsr => Confidence::Exact,
+            Self::HypervisorLeaf => Confidence::Reported,
+            Self::Pit => Confidence::Measured,
+            Self::Cpuid16h => Confidence::Nominal,
+        }
     }
-    // TSC Hz = crystal_hz * (num / den)
-    Some(
-        u64::from(r.crystal_hz)
-            .saturating_mul(u64::from(r.numer))
-            .saturating_div(u64::from(r.denom)),
-    )
 }
 
-/// Try CPUID.16H (processor base frequency in MHz).
-/// EAX = base MHz (may be 0 on many VMs).
-#[inline]
-unsafe fn cpuid_leaf_16_base_mhz_hz() -> Option<u64> {
-    let ranges = unsafe { CpuidRanges::read() };
-    let r = unsafe { Leaf16::read(&ranges)? };
-    if r.base_mhz == 0 {
-        return None;
+impl fmt::Display for TscSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Cpuid15h => "CPUID leaf 15H",
+            Self::HypervisorLeaf => "hypervisor leaf 40000010H",
+            Self::HyperVMsr => "Hyper-V frequency MSR",
+            Self::Pit => "PIT",
+            Self::Cpuid16h => "CPUID leaf 16H",
+        })
     }
-
-    // Treat base MHz as TSC MHz. Often true under KVM/QEMU, good enough for a first pass.
-    Some(u64::from(r.base_mhz) * 1_000_000u64)
 }
 
-/// Calibrate TSC Hz by measuring rdtsc delta over a PIT window.
-/// Uses PIT channel 0 in mode 2 (rate generator).
-/// `window_us` typically `10_000–100_000`; larger → better precision.
-unsafe fn pit_measure_tsc_hz(window_us: u64) -> u64 {
-    const PIT_CH0_DATA: u16 = 0x40;
-    const PIT_CMD: u16 = 0x43;
-    const PIT_INPUT_HZ: u64 = 1_193_182;
-
-    // Compute PIT ticks for requested window (mode 2 expects 16-bit reload; clamp to >=1).
-    let desired_ticks = (PIT_INPUT_HZ * window_us).div_ceil(1_000_000);
-    let reload = desired_ticks.clamp(1, 0xFFFF) as u16;
-
-    // Program PIT: Channel 0, Access lobyte/hibyte, Mode 2 (rate gen), Binary
-    unsafe {
-        outb(PIT_CMD, 0b0011_0100);
-        outb(PIT_CH0_DATA, (reload & 0x00FF) as u8);
-        outb(PIT_CH0_DATA, (reload >> 8) as u8);
+impl fmt::Display for Confidence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Nominal => "nominal",
+            Self::Measured => "measured",
+            Self::Reported => "reported",
+            Self::Exact => "exact",
+        })
     }
+}
 
-    // Latch TSC, then busy-wait roughly window_us using a software delay
-    // driven by the PIT countdown. We poll the PIT output by reading back the
-    // counter to approximate the elapsed time without needing IRQ0.
-    let t0 = rdtsc();
-    unsafe {
-        busy_wait_pit_window(reload);
-    }
-    let t1 = rdtsc();
+/// Best-effort TSC frequency estimate, and where it came from.
+/// Order: CPUID.15H → hypervisor → CPUID.16H with a 15H ratio → PIT
+/// measurement → CPUID.16H.
+/// Call with interrupts masked to reduce jitter during PIT timing.
+pub unsafe fn estimate_tsc_hz() -> Option<(TscSource, Calibration)> {
+    let exact = |hz| Calibration {
+        hz,
+        error_hz: Some(0),
+    };
+    let nominal = |hz| Calibration { hz, error_hz: None };
 
-    // Convert TSC delta to Hz using window_us
-    let delta = t1.saturating_sub(t0);
-    if window_us == 0 {
-        return 0;
+    unsafe {
+        let ranges = CpuidRanges::read();
+        let leaf15 = Leaf15h::read(&ranges).filter(|_| ranges.vendor == CpuVendor::Intel);
+        if let Some(hz) = leaf15.and_then(|leaf15| leaf15.tsc_hz()) {
+            return Some((TscSource::Cpuid15h, exact(hz)));
+        }
+        if let Some(tsc) = hypervisor_tsc_hz(&ranges) {
+            return Some(tsc);
+        }
+        let base_hz = cpuid_leaf_16_base_mhz_hz();
+        if leaf15.is_some_and(|leaf15| leaf15.denom != 0 && leaf15.numer != 0)
+            && let Some(hz) = base_hz
+        {
+            return Some((TscSource::Cpuid16h, nominal(hz)));
+        }
+        if let Some(tsc) = pit::measure(CALIBRATION_MS, rdtsc) {
+            return Some((TscSource::Pit, tsc));
+        }
+        base_hz.map(|hz| (TscSource::Cpuid16h, nominal(hz)))
     }
-    (delta.saturating_mul(1_000_000)) / window_us
 }
 
-/// Spin until the PIT has counted down approximately one reload period in mode 2.
-/// We read back the counter by issuing a latch command; this is coarse but stable
-/// enough for a one-shot window.
-#[allow(clippy::missing_transmute_annotations)]
-unsafe fn busy_wait_pit_window(reload: u16) {
-    // Latch the count repeatedly and exit once it wraps near zero.
-    // In mode 2 the counter reloads on terminal count; we wait for a wrap.
-    let mut last = unsafe { read_pit_counter() };
-    loop {
-        let cur = unsafe { read_pit_counter() };
-        // Detect wrap: counter increases (since it reloads), or falls below small threshold.
-        if cur > last || cur <= 2 || cur >= reload - 2 {
-            break;
-        }
-        last = cur;
-        cpu_relax();
+/// Ask the hypervisor, if there is one: the timing leaf first, then the
+/// Hyper-V frequency MSR.
+unsafe fn hypervisor_tsc_hz(ranges: &CpuidRanges) -> Option<(TscSource, Calibration)> {
+    let leaf1 = unsafe { Leaf01h::read(ranges)? };
+    let hypervisor = unsafe { Hypervisor::read(&leaf1)? };
+
+    if let Some(hz) = unsafe { hypervisor.timing() }.and_then(HypervisorTiming::tsc_hz) {
+        // The leaf truncates to kHz.
+        let tsc = Calibration {
+            hz: hz + 500,
+            error_hz: Some(500),
+        };
+        return Some((TscSource::HypervisorLeaf, tsc));
     }
 
-    // Small extra settle to reduce sampling jitter.
-    for _ in 0..1024 {
-        unsafe {
-            core::arch::asm!("pause", options(nomem, nostack, preserves_flags));
+    if unsafe { hypervisor.has_hyperv_frequency_msrs() } {
+        let hz = unsafe { try_rdmsr(HV_X64_MSR_TSC_FREQUENCY) }.unwrap_or(0);
+        if hz != 0 {
+            let tsc = Calibration {
+                hz,
+                error_hz: Some(0),
+            };
+            return Some((TscSource::HyperVMsr, tsc));
         }
     }
+    None
 }
 
+/// Try CPUID.16H (processor base frequency in MHz).
+/// EAX = base MHz (may be 0 on many VMs).
 #[inline]
-unsafe fn read_pit_counter() -> u16 {
-    const PIT_CH0_DATA: u16 = 0x40;
-    const PIT_CMD: u16 = 0x43;
-
-    // Latch channel 0 count
-    unsafe {
-        outb(PIT_CMD, 0b0000_0000);
-        let lo = u16::from(inb(PIT_CH0_DATA));
-        let hi = u16::from(inb(PIT_CH0_DATA));
-        (hi << 8) | lo
+unsafe fn cpuid_leaf_16_base_mhz_hz() -> Option<u64> {
+    let ranges = unsafe { CpuidRanges::read() };
+    let r = unsafe { Leaf16::read(&ranges)? };
+    if r.base_mhz == 0 {
+        return None;
     }
-}
 
-/// Allows the CPU to save some power. Functionally equivalent to [`spin_loop`](core::hint::spin_loop).
-#[inline(always)]
-#[allow(clippy::inline_always)]
-fn cpu_relax() {
-    // Okay to call without unsafe
-    unsafe {
-        core::arch::asm!("pause", options(nomem, nostack, preserves_flags));
-    }
+    // Treat base MHz as TSC MHz. Often true under KVM/QEMU, good enough for a first pass.
+    Some(u64::from(r.base_mhz) * 1_000_000u64)
 }
 
 #[inline(always)]
@@ -14,13 +14,16 @@
 //!   **end of the TSS** disables the bitmap (typical for kernels).
 //!
 //! ## How we use it
-//! - `init_tss(rsp0, ist1)` fills a global TSS with a kernel stack and
-//!   optional IST1. You will reference this TSS from the GDT's **TSS
-//!   descriptor**, then load it into the **Task Register** via `ltr`.
+//! - `init_tss(rsp0)` fills the CPU's TSS with its kernel stack. You will
+//!   reference this TSS from the GDT's **TSS descriptor**, then load it into
+//!   the **Task Register** via `ltr`.
+//! - `set_ist()` points an IST entry at one of the CPU's
+//!   [IST stacks](crate::per_cpu::ist_stacks).
 //! - Later, you can change `rsp0` via `set_rsp0()`.
 //!
 //! For SMP, create one TSS per CPU and load the CPU-local TSS on AP startup.
 
+use crate::interrupts::Ist;
 use crate::per_cpu::PerCpu;
 use core::mem::size_of;
 use kernel_memory_addresses::VirtualAddress;
@@ -170,22 +173,36 @@ impl Tss64 {
     }
 }
 
-/// Initialize the TSS with a kernel RSP0 and optional IST1.
+/// Initialize the TSS with a kernel RSP0; IST entries are set with [`set_ist`].
 ///
 /// * `kernel_stack_top` — top (highest address) of a valid kernel stack.
 ///   The CPU switches to this when entering Ring-0 from Ring-3 via an
 ///   interrupt/exception gate (e.g., `int 0x80`).
-/// * `ist1_top` — top of an IST1 stack for critical handlers (you
-///   later bind an IDT entry to IST1).
-pub const fn init_tss(p: &mut PerCpu, kernel_stack_top: VirtualAddress, ist1_top: VirtualAddress) {
+pub const fn init_tss(p: &mut PerCpu, kernel_stack_top: VirtualAddress) {
     let tss = &mut p.tss;
 
     tss.rsp0 = kernel_stack_top;
-    tss.ist1 = ist1_top;
+}
+
+/// Set the stack top the CPU switches to for gates using `ist`.
+pub const fn set_ist(p: &mut PerCpu, ist: Ist, top: VirtualAddress) {
+    let tss = &mut p.tss;
+    match ist {
+        Ist::None => {}
+        Ist::Ist1 => tss.ist1 = top,
+        Ist::Ist2 => tss.ist2 = top,
+        Ist::Ist3 => tss.ist3 = top,
+        Ist::Ist4 => tss.ist4 = top,
+        Ist::Ist5 => tss.ist5 = top,
+        Ist::Ist6 => tss.ist6 = top,
+        Ist::Ist7 => tss.ist7 = top,
+    }
+    if let Some(idx) = ist.tss_index() {
+        p.ist_stacks[idx] = top;
+    }
 }
 
 /// Update the Ring-0 stack pointer used on user→kernel transitions.
-#[allow(dead_code)]
 pub const fn set_rsp0(p: &mut PerCpu, new_top: VirtualAddress) {
     p.tss.rsp0 = new_top;
 }
@@ -0,0 +1,269 @@
+//! # Terminal
+//!
+//! `/dev/console`: the [keyboard](crate::keyboard) and the
+//! [framebuffer console](crate::framebuffer::console) joined into one
+//! [character device](crate::chardev), [`TTY`]. Writes go to the screen;
+//! reads return what was typed, after the line discipline had its say.
+//!
+//! ## Modes
+//!
+//! The mode is a set of the `MODE_*` flags of
+//! [`syscall_abi::tty`](stdlib::syscall_abi::tty), read and switched with
+//! the `ioctl` system call. The terminal starts out canonical and echoing.
+//!
+//! * **Canonical**: typed characters are collected into a line, which
+//!   readers only get once Enter ends it; a read returns at most one line.
+//!   While a line is being typed, it can be edited:
+//!
+//!   | Key                  | Effect                                    |
+//!   |----------------------|-------------------------------------------|
+//!   | Backspace            | Erase the last character                  |
+//!   | `Ctrl-U`             | Erase the whole line                      |
+//!   | Enter                | End the line with `\n` and hand it out    |
+//!   | `Ctrl-D`             | Hand out the line without a `\n`          |
+//!
+//!   Other control characters are dropped, as are characters beyond
+//!   [`LINE_LEN`] bytes.
+//! * **Raw**: every typed character is handed out as UTF-8 right away,
+//!   control characters included. Switching to raw mode hands out the line
+//!   typed so far.
+//! * **Echo**: typed characters, and the effect of editing, are shown on the
+//!   screen.
+//!
+//! Input not read yet is kept up to [`INPUT_LEN`] bytes; further input is
+//! dropped until readers catch up.
+
+use crate::chardev::{self, CharDevice};
+use crate::framebuffer::console;
+use crate::sched::WaitQueue;
+use kernel_sync::{IrqGuard, SpinMutex};
+use log::{info, warn};
+use stdlib::syscall_abi::caps::CAP_DEVICE_CONSOLE;
+use stdlib::syscall_abi::tty::{
+    MODE_CANONICAL, MODE_DEFAULT, MODE_ECHO, TTY_GET_MODE, TTY_SET_MODE,
+};
+
+/// Longest line in canonical mode, in bytes, `\n` included.
+pub const LINE_LEN: usize = 256;
+
+/// Most bytes of input kept for readers.
+pub const INPUT_LEN: usize = 512;
+
+const BACKSPACE: char = '\u{8}';
+const DELETE: char = '\u{7F}';
+const KILL_LINE: char = '\u{15}';
+const END_OF_FILE: char = '\u{4}';
+
+/// Erases the character before the cursor on the screen.
+const ERASE: &[u8] = b"\x08 \x08";
+
+/// The terminal; see the [module docs](self).
+pub static TTY: Tty = Tty {
+    state: SpinMutex::new(State::new()),
+    readable: WaitQueue::new(),
+};
+
+pub struct Tty {
+    state: SpinMutex<State>,
+    readable: WaitQueue,
+}
+
+struct State {
+    mode: u64,
+    /// The line being typed in canonical mode.
+    line: [u8; LINE_LEN],
+    line_len: usize,
+    /// Input ready for readers.
+    input: [u8; INPUT_LEN],
+    input_len: usize,
+}
+
+/// Register `/dev/console`.
+pub fn init() {
+    match chardev::register(&TTY) {
+        Ok(_) => info!("Terminal ready"),
+        Err(e) => warn!("Terminal not registered: {e}"),
+    }
+}
+
+impl Tty {
+    /// Feed typed `text` through the line discipline.
+    pub fn receive(&self, text: impl IntoIterator<Item = char>) {
+        let mut ready = false;
+        {
+            let _irq = IrqGuard::new();
+            let mut state = self.state.lock();
+            for c in text {
+                ready |= state.receive(c);
+            }
+        }
+        if ready {
+            self.readable.wake_all();
+        }
+    }
+}
+
+impl CharDevice for Tty {
+    fn name(&self) -> &'static str {
+        "console"
+    }
+
+    fn read(&self, buf: &mut [u8]) -> usize {
+        let _irq = IrqGuard::new();
+        self.state.lock().take_input(buf)
+    }
+
+    fn write(&self, bytes: &[u8]) -> usize {
+        console::write(bytes);
+        bytes.len()
+    }
+
+    fn has_input(&self) -> bool {
+        let _irq = IrqGuard::new();
+        self.state.lock().input_len > 0
+    }
+
+    fn readable(&self) -> &WaitQueue {
+        &self.readable
+    }
+
+    fn capabilities(&self) -> u64 {
+        CAP_DEVICE_CONSOLE
+    }
+
+    fn control(&self, request: u64, arg: u64) -> Option<u64> {
+        let (old, ready) = {
+            let _irq = IrqGuard::new();
+            let mut state = self.state.lock();
+            match request {
+                TTY_GET_MODE => return Some(state.mode),
+                TTY_SET_MODE if arg & !(MODE_CANONICAL | MODE_ECHO) == 0 => state.set_mode(arg),
+                _ => return None,
+            }
+        };
+        if ready {
+            self.readable.wake_all();
+        }
+        Some(old)
+    }
+}
+
+impl State {
+    const fn new() -> Self {
+        Self {
+            mode: MODE_DEFAULT,
+            line: [0; LINE_LEN],
+            line_len: 0,
+            input: [0; INPUT_LEN],
+            input_len: 0,
+        }
+    }
+
+    const fn canonical(&self) -> bool {
+        self.mode & MODE_CANONICAL != 0
+    }
+
+    /// Switch to `mode`; returns the previous mode and whether readers have
+    /// input now.
+    fn set_mode(&mut self, mode: u64) -> (u64, bool) {
+        let old = core::mem::replace(&mut self.mode, mode);
+        // Raw readers get the line typed so far.
+        let ready = !self.canonical() && self.line_len > 0 && self.commit_line();
+        (old, ready)
+    }
+
+    fn echo(&self, bytes: &[u8]) {
+        if self.mode & MODE_ECHO != 0 {
+            console::write(bytes);
+        }
+    }
+
+    /// Handle the typed `c`; returns whether readers have input now.
+    fn receive(&mut self, c: char) -> bool {
+        let mut utf8 = [0; 4];
+        let bytes = c.encode_utf8(&mut utf8).as_bytes();
+        if !self.canonical() {
+            self.echo(bytes);
+            return self.push_input(bytes);
+        }
+
+        match c {
+            '\n' | '\r' => {
+                self.line[self.line_len] = b'\n';
+                self.line_len += 1;
+                self.echo(b"\n");
+                self.commit_line()
+            }
+            END_OF_FILE => self.line_len > 0 && self.commit_line(),
+            BACKSPACE | DELETE => {
+                if self.erase_char() {
+                    self.echo(ERASE);
+                }
+                false
+            }
+            KILL_LINE => {
+                while self.erase_char() {
+                    self.echo(ERASE);
+                }
+                false
+            }
+            _ if c.is_control() => false,
+            // One byte stays free for the `\n`.
+            _ if self.line_len + bytes.len() < LINE_LEN => {
+                self.line[self.line_len..self.line_len + bytes.len()].copy_from_slice(bytes);
+                self.line_len += bytes.len();
+                self.echo(bytes);
+                false
+            }
+            _ => false,
+        }
+    }
+
+    /// Drop the last character of the line; returns whether there was one.
+    const fn erase_char(&mut self) -> bool {
+        if self.line_len == 0 {
+            return false;
+        }
+        // Step back over UTF-8 continuation bytes to the start of the
+        // character.
+        self.line_len -= 1;
+        while self.line_len > 0 && self.line[self.line_len] & 0xC0 == 0x80 {
+            self.line_len -= 1;
+        }
+        true
+    }
+
+    /// Move the line to the input; returns whether it fit.
+    fn commit_line(&mut self) -> bool {
+        let len = core::mem::take(&mut self.line_len);
+        let line = self.line;
+        self.push_input(&line[..len])
+    }
+
+    /// Append `bytes` to the input, unless they do not fit; returns whether
+    /// they did.
+    fn push_input(&mut self, bytes: &[u8]) -> bool {
+        let end = self.input_len + bytes.len();
+        if end > INPUT_LEN {
+            return false;
+        }
+        self.input[self.input_len..end].copy_from_slice(bytes);
+        self.input_len = end;
+        true
+    }
+
+    /// Move input into `buf`, at most one line in canonical mode; returns
+    /// how many bytes.
+    fn take_input(&mut self, buf: &mut [u8]) -> usize {
+        let mut len = buf.len().min(self.input_len);
+        if self.canonical()
+            && let Some(newline) = self.input[..len].iter().position(|&b| b == b'\n')
+        {
+            len = newline + 1;
+        }
+        buf[..len].copy_from_slice(&self.input[..len]);
+        self.input.copy_within(len..self.input_len, 0);
+        self.input_len -= len;
+        len
+    }
+}
@@ -0,0 +1,580 @@
+//! # User Memory Access
+//!
+//! Helpers for reading syscall arguments that point into user memory, and
+//! for writing results back.
+//!
+//! Before touching user memory the whole range is checked to lie in the
+//! lower half and to be mapped in the current address space, growing the
+//! caller's [stack](stack_growth) if the range reaches below it and mapping
+//! pages of [file areas](file_pages) not touched yet; ranges written
+//! by [`copy_to_user`] must additionally be mapped user-accessible and
+//! writable. The copy itself runs inside a [`SmapGuard`] so SMAP does not
+//! trap it, and is listed in the [exception table](crate::extable): should
+//! another thread unmap the range between the check and the copy, the copy
+//! fails with [`Fault`](UserAccessError::Fault) instead of taking the kernel
+//! down.
+//!
+//! Writes to copy-on-write pages normally fault and are resolved by the page
+//! fault handler. Code that must not fault, such as the fault handler itself,
+//! uses [`copy_to_user_nofault`], which resolves them up front and does not
+//! sleep for file pages being filled.
+//!
+//! ## Syscall arguments
+//!
+//! System calls take their pointer arguments as a [`UserPtr`], a
+//! [`UserSlice`] or, for vectored transfers, a [`UserIoVec`] of slices
+//! before touching them. Creating one refuses ranges that
+//!
+//! * reach past [`LAST_USERSPACE_ADDRESS`] or wrap around,
+//! * touch the [null page](NULL_PAGE_END), or
+//! * overlap a [guard area](kernel_vmem::vma::VmaKind::Guard) of the caller.
+//!
+//! Every refusal is logged with the caller's PID and the number of the
+//! system call it made, and [counted](rejected_accesses). Only the copy
+//! itself goes through the functions below, which check the page tables
+//! again, so an area unmapped in the meantime still fails cleanly.
+
+use crate::alloc::{resolve_cow_fault, with_kernel_vmm};
+use crate::process::file_pages::{self, FaultError};
+use crate::process::stack_growth::{self, GrowError};
+use crate::smap::SmapGuard;
+use crate::{process, sched, syscall};
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::Deref;
+use core::sync::atomic::{AtomicU64, Ordering};
+use kernel_info::memory::LAST_USERSPACE_ADDRESS;
+use kernel_memory_addresses::{PageSize, Size4K, VirtualAddress};
+use kernel_registers::extable_entry;
+use log::warn;
+use stdlib::syscall_abi::{IoVec, MAX_IOV};
+
+/// End of the page at address `0`, which is never mapped.
+pub const NULL_PAGE_END: u64 = Size4K::SIZE;
+
+/// Syscall arguments refused so far.
+static REJECTED: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum UserAccessError {
+    /// The range is not entirely in user space.
+    NotUserMemory,
+    /// Some page of the range is not mapped.
+    Unmapped,
+    /// Some page of the range is not mapped user-writable.
+    ReadOnly,
+    /// The copy faulted although the range checked out.
+    Fault,
+    /// The range touches the null page or a guard area.
+    Guard,
+    /// The range is longer than the kernel takes for this argument.
+    TooLong,
+}
+
+impl fmt::Display for UserAccessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::NotUserMemory => "not user memory",
+            Self::Unmapped => "not mapped",
+            Self::ReadOnly => "not writable",
+            Self::Fault => "faulted",
+            Self::Guard => "in a guard area",
+            Self::TooLong => "too long",
+        })
+    }
+}
+
+/// Verify that `[addr, addr + len)` is user memory and mapped.
+pub fn check_user_range(addr: u64, len: usize) -> Result<(), UserAccessError> {
+    check_pages(addr, len, false, true)
+}
+
+/// Verify that `[addr, addr + len)` is user memory, mapped user-accessible
+/// and writable.
+pub fn check_user_range_writable(addr: u64, len: usize) -> Result<(), UserAccessError> {
+    check_pages(addr, len, true, true)
+}
+
+/// Check the pages of `[addr, addr + len)`; file pages are only waited for
+/// if `may_sleep` is set.
+fn check_pages(
+    addr: u64,
+    len: usize,
+    writable: bool,
+    may_sleep: bool,
+) -> Result<(), UserAccessError> {
+    if len == 0 {
+        return Ok(());
+    }
+
+    let end = addr
+        .checked_add(len as u64 - 1)
+        .filter(|&last| last <= LAST_USERSPACE_ADDRESS.as_u64())
+        .ok_or(UserAccessError::NotUserMemory)?;
+
+    let mut probe = addr & !(Size4K::SIZE - 1);
+    while probe <= end {
+        let va = VirtualAddress::new(probe);
+        let mut flags = None;
+        with_kernel_vmm(|vmm| flags = vmm.query_flags(va));
+        // Pages below the mapped part of the stack and file pages are mapped
+        // on first use.
+        if flags.is_none() && (grow_stack(va) || map_file_page(va, may_sleep)) {
+            with_kernel_vmm(|vmm| flags = vmm.query_flags(va));
+        }
+        match flags {
+            None => return Err(UserAccessError::Unmapped),
+            // Copy-on-write pages are made writable by the fault handler.
+            Some(flags)
+                if writable && !(flags.user && (flags.writable || flags.copy_on_write())) =>
+            {
+                return Err(UserAccessError::ReadOnly);
+            }
+            Some(_) => {}
+        }
+        probe += Size4K::SIZE;
+    }
+    Ok(())
+}
+
+/// Grow the current process' stack down to `va`; whether it did.
+fn grow_stack(va: VirtualAddress) -> bool {
+    let Some(pid) = sched::current_pid() else {
+        return false;
+    };
+    match stack_growth::grow(pid, va) {
+        Ok(()) => true,
+        Err(GrowError::NotStack) => false,
+        Err(e) => {
+            warn!("pid {pid}: cannot grow the stack to {va}: {e}");
+            false
+        }
+    }
+}
+
+/// Map the page of a file area of the current process at `va`; whether it
+/// did.
+fn map_file_page(va: VirtualAddress, may_sleep: bool) -> bool {
+    let Some(pid) = sched::current_pid() else {
+        return false;
+    };
+    match file_pages::fault_in(pid, va, may_sleep) {
+        Ok(()) => true,
+        Err(FaultError::NotFile) => false,
+        Err(e) => {
+            warn!("pid {pid}: cannot map the file page at {va}: {e}");
+            false
+        }
+    }
+}
+
+/// Copy `len` bytes from `src` to `dst`, stopping at the first fault.
+///
+/// # Safety
+/// The kernel side of the copy must be valid, and SMAP must be lifted.
+unsafe fn copy_bytes(dst: *mut u8, src: *const u8, len: usize) -> Result<(), UserAccessError> {
+    let fault: u64;
+    unsafe {
+        core::arch::asm!(
+            "2:",
+            "rep movsb",
+            "xor eax, eax",
+            "3:",
+            extable_entry!("2b", "3b"),
+            inout("rdi") dst => _,
+            inout("rsi") src => _,
+            inout("rcx") len => _,
+            out("rax") fault,
+            options(nostack)
+        );
+    }
+    if fault == 0 {
+        Ok(())
+    } else {
+        Err(UserAccessError::Fault)
+    }
+}
+
+/// Copy `dst.len()` bytes from user address `src` into `dst`.
+pub fn copy_from_user(dst: &mut [u8], src: u64) -> Result<(), UserAccessError> {
+    check_user_range(src, dst.len())?;
+
+    let _guard = SmapGuard::enter();
+    unsafe { copy_bytes(dst.as_mut_ptr(), src as *const u8, dst.len()) }
+}
+
+/// Copy `src` to user address `dst`.
+pub fn copy_to_user(dst: u64, src: &[u8]) -> Result<(), UserAccessError> {
+    check_user_range_writable(dst, src.len())?;
+
+    let _guard = SmapGuard::enter();
+    unsafe { copy_bytes(dst as *mut u8, src.as_ptr(), src.len()) }
+}
+
+/// Copy `src` to user address `dst` without taking a page fault.
+///
+/// Copy-on-write pages of the range are made writable before the copy; fails
+/// with [`ReadOnly`](UserAccessError::ReadOnly) if one cannot be resolved,
+/// and with [`Unmapped`](UserAccessError::Unmapped) if a file page of the
+/// range is still being filled.
+pub fn copy_to_user_nofault(dst: u64, src: &[u8]) -> Result<(), UserAccessError> {
+    check_pages(dst, src.len(), true, false)?;
+
+    let end = dst + src.len() as u64;
+    let mut page = dst & !(Size4K::SIZE - 1);
+    while page < end {
+        let va = VirtualAddress::new(page);
+        let mut writable = false;
+        with_kernel_vmm(|vmm| writable = vmm.query_flags(va).is_some_and(|f| f.writable));
+        if !writable && !resolve_cow_fault(va) {
+            return Err(UserAccessError::ReadOnly);
+        }
+        page += Size4K::SIZE;
+    }
+
+    let _guard = SmapGuard::enter();
+    unsafe { copy_bytes(dst as *mut u8, src.as_ptr(), src.len()) }
+}
+
+/// Read one value of type `T` from user address `src`.
+///
+/// `T` must be valid for any bit pattern (plain `#[repr(C)]` integers/structs).
+pub fn read_from_user<T: Copy>(src: u64) -> Result<T, UserAccessError> {
+    check_user_range(src, size_of::<T>())?;
+
+    let mut value = core::mem::MaybeUninit::<T>::uninit();
+    let _guard = SmapGuard::enter();
+    unsafe {
+        copy_bytes(value.as_mut_ptr().cast(), src as *const u8, size_of::<T>())?;
+        Ok(value.assume_init())
+    }
+}
+
+/// Number of syscall arguments refused so far; see [`UserSlice::new`].
+#[allow(dead_code)]
+pub fn rejected_accesses() -> u64 {
+    REJECTED.load(Ordering::Relaxed)
+}
+
+/// Check that `[addr, addr + len)` may be a syscall argument, logging the
+/// refusal if not.
+fn validate(addr: u64, len: usize) -> Result<(), UserAccessError> {
+    let result = classify(addr, len);
+    if let Err(e) = result {
+        REJECTED.fetch_add(1, Ordering::Relaxed);
+        let pid = sched::current_pid().map_or(0, process::Pid::as_u64);
+        let sysno = syscall::current_sysno().unwrap_or(syscall::NO_SYSCALL);
+        warn!("pid {pid}: syscall {sysno} passed {addr:#x}+{len:#x}, which is {e}");
+    }
+    result
+}
+
+fn classify(addr: u64, len: usize) -> Result<(), UserAccessError> {
+    if len == 0 {
+        return Ok(());
+    }
+    let end = addr
+        .checked_add(len as u64)
+        .filter(|&end| end - 1 <= LAST_USERSPACE_ADDRESS.as_u64())
+        .ok_or(UserAccessError::NotUserMemory)?;
+    if addr < NULL_PAGE_END {
+        return Err(UserAccessError::Guard);
+    }
+    let guarded = sched::current_pid().and_then(|pid| process::guard_overlapping(pid, addr, end));
+    if guarded.is_some() {
+        return Err(UserAccessError::Guard);
+    }
+    Ok(())
+}
+
+/// A syscall argument pointing at one `T` in user memory.
+///
+/// `T` must be valid for any bit pattern (plain `#[repr(C)]` integers/structs).
+#[derive(Debug)]
+pub struct UserPtr<T> {
+    addr: u64,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for UserPtr<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for UserPtr<T> {}
+
+impl<T: Copy> UserPtr<T> {
+    /// Take `addr` as a pointer to a `T`.
+    ///
+    /// # Errors
+    /// See the [module documentation](self); the refusal is logged.
+    pub fn new(addr: u64) -> Result<Self, UserAccessError> {
+        validate(addr, size_of::<T>())?;
+        Ok(Self {
+            addr,
+            _marker: PhantomData,
+        })
+    }
+
+    /// The user address.
+    #[allow(dead_code)]
+    pub const fn addr(self) -> u64 {
+        self.addr
+    }
+
+    /// Read the value.
+    ///
+    /// # Errors
+    /// If the memory is not mapped or the copy faults.
+    pub fn read(self) -> Result<T, UserAccessError> {
+        read_from_user(self.addr)
+    }
+}
+
+/// A syscall argument pointing at `len` bytes of user memory.
+#[derive(Debug, Copy, Clone)]
+pub struct UserSlice {
+    addr: u64,
+    len: usize,
+}
+
+impl UserSlice {
+    /// The empty buffer.
+    const EMPTY: Self = Self { addr: 0, len: 0 };
+
+    /// Take `[addr, addr + len)` as a user buffer.
+    ///
+    /// # Errors
+    /// See the [module documentation](self); the refusal is logged.
+    pub fn new(addr: u64, len: usize) -> Result<Self, UserAccessError> {
+        validate(addr, len)?;
+        Ok(Self { addr, len })
+    }
+
+    /// The user address.
+    pub const fn addr(self) -> u64 {
+        self.addr
+    }
+
+    /// Length in bytes.
+    pub const fn len(self) -> usize {
+        self.len
+    }
+
+    /// Whether the buffer is empty.
+    #[allow(dead_code)]
+    pub const fn is_empty(self) -> bool {
+        self.len == 0
+    }
+
+    /// The `len` bytes at `offset`, clamped to the buffer.
+    pub fn sub(self, offset: usize, len: usize) -> Self {
+        let offset = offset.min(self.len);
+        Self {
+            addr: self.addr + offset as u64,
+            len: len.min(self.len - offset),
+        }
+    }
+
+    /// Fill `dst` from the start of the buffer.
+    ///
+    /// # Errors
+    /// [`TooLong`](UserAccessError::TooLong) if `dst` is longer than the
+    /// buffer; otherwise if the memory is not mapped or the copy faults.
+    pub fn read_into(self, dst: &mut [u8]) -> Result<(), UserAccessError> {
+        if dst.len() > self.len {
+            return Err(UserAccessError::TooLong);
+        }
+        copy_from_user(dst, self.addr)
+    }
+
+    /// Copy the whole buffer into kernel memory of up to `N` bytes.
+    ///
+    /// # Errors
+    /// [`TooLong`](UserAccessError::TooLong) if the buffer holds more than
+    /// `N` bytes; otherwise if the memory is not mapped or the copy faults.
+    pub fn read_vec<const N: usize>(self) -> Result<UserBytes<N>, UserAccessError> {
+        if self.len > N {
+            return Err(UserAccessError::TooLong);
+        }
+        let mut bytes = UserBytes {
+            bytes: [0; N],
+            len: self.len,
+        };
+        copy_from_user(&mut bytes.bytes[..self.len], self.addr)?;
+        Ok(bytes)
+    }
+
+    /// Copy `src` to the start of the buffer.
+    ///
+    /// # Errors
+    /// [`TooLong`](UserAccessError::TooLong) if `src` is longer than the
+    /// buffer; otherwise if the memory is not mapped writable or the copy
+    /// faults.
+    pub fn write(self, src: &[u8]) -> Result<(), UserAccessError> {
+        if src.len() > self.len {
+            return Err(UserAccessError::TooLong);
+        }
+        copy_to_user(self.addr, src)
+    }
+
+    /// Check that the whole buffer is mapped writable, e.g. before taking
+    /// data that could not be handed back.
+    ///
+    /// # Errors
+    /// If it is not.
+    pub fn check_writable(self) -> Result<(), UserAccessError> {
+        check_user_range_writable(self.addr, self.len)
+    }
+}
+
+/// A syscall argument pointing at up to [`MAX_IOV`] [`IoVec`]s, the
+/// buffers of a vectored transfer, taken as one buffer of their combined
+/// length.
+///
+/// It offers what transfers use of a [`UserSlice`], and a single slice
+/// converts into one; copies split at the buffer boundaries.
+#[derive(Debug, Copy, Clone)]
+pub struct UserIoVec {
+    slices: [UserSlice; MAX_IOV],
+    count: usize,
+    len: usize,
+}
+
+impl From<UserSlice> for UserIoVec {
+    fn from(slice: UserSlice) -> Self {
+        let mut slices = [UserSlice::EMPTY; MAX_IOV];
+        slices[0] = slice;
+        Self {
+            slices,
+            count: 1,
+            len: slice.len,
+        }
+    }
+}
+
+impl UserIoVec {
+    /// Take the `count` [`IoVec`]s at `addr` and the buffers they describe.
+    ///
+    /// # Errors
+    /// [`TooLong`](UserAccessError::TooLong) for more than [`MAX_IOV`]
+    /// buffers or more bytes than fit a `usize`; otherwise if the array
+    /// cannot be read or a buffer is refused like a [`UserSlice`].
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn new(addr: u64, count: usize) -> Result<Self, UserAccessError> {
+        if count > MAX_IOV {
+            return Err(UserAccessError::TooLong);
+        }
+        let records = UserSlice::new(addr, count * size_of::<IoVec>())?;
+        let mut iov = Self {
+            slices: [UserSlice::EMPTY; MAX_IOV],
+            count,
+            len: 0,
+        };
+        for (i, slice) in iov.slices[..count].iter_mut().enumerate() {
+            let record = records.sub(i * size_of::<IoVec>(), size_of::<IoVec>());
+            let vec = UserPtr::<IoVec>::new(record.addr())?.read()?;
+            *slice = UserSlice::new(vec.base, vec.len as usize)?;
+            iov.len = iov
+                .len
+                .checked_add(slice.len)
+                .ok_or(UserAccessError::TooLong)?;
+        }
+        Ok(iov)
+    }
+
+    /// Combined length in bytes.
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    /// The `len` bytes at `offset`, clamped to the buffers.
+    pub fn sub(&self, mut offset: usize, mut len: usize) -> Self {
+        let mut sub = Self {
+            slices: [UserSlice::EMPTY; MAX_IOV],
+            count: 0,
+            len: 0,
+        };
+        for slice in self.slices() {
+            if len == 0 {
+                break;
+            }
+            if offset >= slice.len {
+                offset -= slice.len;
+                continue;
+            }
+            let part = slice.sub(offset, len);
+            offset = 0;
+            len -= part.len;
+            sub.slices[sub.count] = part;
+            sub.count += 1;
+            sub.len += part.len;
+        }
+        sub
+    }
+
+    /// Fill `dst` from the start of the buffers.
+    ///
+    /// # Errors
+    /// [`TooLong`](UserAccessError::TooLong) if `dst` is longer than the
+    /// buffers; otherwise if the memory is not mapped or a copy faults.
+    pub fn read_into(&self, dst: &mut [u8]) -> Result<(), UserAccessError> {
+        if dst.len() > self.len {
+            return Err(UserAccessError::TooLong);
+        }
+        let mut done = 0;
+        for slice in self.slices() {
+            let n = slice.len.min(dst.len() - done);
+            slice.read_into(&mut dst[done..done + n])?;
+            done += n;
+        }
+        Ok(())
+    }
+
+    /// Copy `src` to the start of the buffers.
+    ///
+    /// # Errors
+    /// [`TooLong`](UserAccessError::TooLong) if `src` is longer than the
+    /// buffers; otherwise if the memory is not mapped writable or a copy
+    /// faults.
+    pub fn write(&self, src: &[u8]) -> Result<(), UserAccessError> {
+        if src.len() > self.len {
+            return Err(UserAccessError::TooLong);
+        }
+        let mut done = 0;
+        for slice in self.slices() {
+            let n = slice.len.min(src.len() - done);
+            slice.write(&src[done..done + n])?;
+            done += n;
+        }
+        Ok(())
+    }
+
+    /// Check that all buffers are mapped writable, e.g. before taking data
+    /// that could not be handed back.
+    ///
+    /// # Errors
+    /// If one is not.
+    pub fn check_writable(&self) -> Result<(), UserAccessError> {
+        self.slices().try_for_each(UserSlice::check_writable)
+    }
+
+    fn slices(&self) -> impl Iterator<Item = UserSlice> + '_ {
+        self.slices[..self.count].iter().copied()
+    }
+}
+
+/// Bytes copied from a [`UserSlice`], up to `N` of them.
+pub struct UserBytes<const N: usize> {
+    bytes: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> Deref for UserBytes<N> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+}
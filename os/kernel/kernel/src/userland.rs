@@ -1,14 +1,18 @@
 use crate::alloc::KernelVmm;
 use crate::elf::helpers::{pie_bias, segment_file_bytes};
-use crate::elf::{ElfErr, PFlags, elf64_view};
+use crate::elf::{
+    ElfErr, ElfView, PFlags, Ph64, R_X86_64_NONE, R_X86_64_RELATIVE, Rela, elf64_view,
+};
 use crate::gdt::{USER_CS, USER_DS};
+use crate::process::layout::Layout;
+use crate::process::mmap::{MMAP_BASE, MMAP_END};
 use core::num::NonZeroU64;
 use kernel_alloc::vmm::AllocationTarget;
-use kernel_info::boot::UserBundleInfo;
 use kernel_memory_addresses::{PageSize, Size4K, VirtualAddress};
 use kernel_vmem::VirtualMemoryPageBits;
+use kernel_vmem::vma::{Vma, VmaKind, VmaPerms, VmaSet};
 use log::{debug, info, trace};
-use packer_abi::unbundle::Bundle;
+use stdlib::syscall_abi::tls::TCB_SIZE;
 
 pub unsafe fn enter_user_mode(entry: VirtualAddress, user_sp: VirtualAddress) -> ! {
     let rip = entry.as_u64();
@@ -37,42 +41,59 @@ pub unsafe fn enter_user_mode(entry: VirtualAddress, user_sp: VirtualAddress) ->
 
 pub type UserStackTop = VirtualAddress;
 pub type UserCode = VirtualAddress;
+pub type ThreadPointer = VirtualAddress;
 
-#[allow(clippy::cast_possible_truncation)]
-pub fn parse_userland_bundle(
-    bundle: &UserBundleInfo,
-    vmm: &mut KernelVmm,
-    user_stack_top: VirtualAddress,
-    stack_pages_4k: NonZeroU64,
-) -> Result<(UserCode, UserStackTop), ElfErr> {
-    let slice: &[u8] = unsafe {
-        core::slice::from_raw_parts(bundle.bytes_ptr as *const u8, bundle.length as usize)
-    };
-
-    let bundle = Bundle::parse(slice).expect("failed to parse userland bundle");
-    info!("Userland bundle has {num} entries", num = bundle.len());
+/// Where position-independent (`ET_DYN`) programs are loaded without
+/// randomization: above the `0x40_0000` fixed programs are linked at, below
+/// the user stack.
+pub const PIE_BASE: VirtualAddress = VirtualAddress::new(0x5555_0000);
 
-    let init_bytes = bundle
-        .entries()
-        .filter_map(Result::ok)
-        .find(|(name, _bytes)| "init".eq(*name))
-        .map(|(_name, bytes)| bytes)
-        .expect("userland bundle has no init binary");
-    info!("Init binary is {len} bytes", len = init_bytes.len());
-
-    parse_elf_bytes(init_bytes, vmm, user_stack_top, stack_pages_4k)
+/// A program mapped by [`load_elf`].
+#[derive(Debug, Copy, Clone)]
+pub struct LoadedImage {
+    /// The (biased) entry point.
+    pub entry: UserCode,
+    /// The top of the user stack.
+    pub stack_top: UserStackTop,
+    /// The thread pointer, if the program has thread-local storage.
+    pub thread_pointer: Option<ThreadPointer>,
+    /// Where the program headers are mapped, if they are.
+    pub phdr: Option<VirtualAddress>,
+    /// Number of program headers.
+    pub phnum: u16,
 }
 
-fn parse_elf_bytes(
+/// Load the ELF program `bytes`, the contents of the file `inode`, into the
+/// **currently active** address space and reserve a user stack of `stack_pages_4k` pages right below the
+/// `layout`'s stack top, of which the top `mapped_pages_4k` are mapped.
+///
+/// Position-independent programs (`ET_DYN`) are loaded at the `layout`'s PIE
+/// base (rounded up to their alignment), their entry point moved along, and
+/// get their relative relocations applied; programs that ask for a dynamic
+/// loader or shared objects are refused. Segments are mapped with their
+/// final W^X protections once relocated. Read-only segments are not copied
+/// but mapped from the page cache on demand (see [`file_backed`]). A
+/// `PT_TLS` segment gets its TLS block and thread control block (see
+/// [`map_tls`]). Every mapping, and the guard page below the stack, is
+/// recorded in `vmas`.
+pub fn load_elf<const N: usize>(
     bytes: &[u8],
+    inode: u64,
     vmm: &mut KernelVmm,
-    user_stack_top: VirtualAddress,
+    vmas: &mut VmaSet<N>,
+    layout: &Layout,
     stack_pages_4k: NonZeroU64,
-) -> Result<(UserCode, UserStackTop), ElfErr> {
-    let view = elf64_view(bytes).expect("failed to parse init binary ELF");
+    mapped_pages_4k: NonZeroU64,
+) -> Result<LoadedImage, ElfErr> {
+    let view = elf64_view(bytes)?;
+    if view.interp().is_some() {
+        return Err(ElfErr::NeedsInterp);
+    }
+    // Check before mapping anything.
+    let relocations = view.relocations()?;
 
-    // Optional bias for ET_DYN (0 for ET_EXEC with your linker script)
-    let bias = pie_bias(&view).unwrap_or(0);
+    // Zero for ET_EXEC, which is linked at its final address.
+    let bias = pie_bias(&view, layout.pie_base.as_u64()).ok_or(ElfErr::BadPh)?;
 
     // Common non-leaf flags for user traversal (US=1, WB, Present)
     let nonleaf = VirtualMemoryPageBits::user_table_wb_exec();
@@ -88,24 +109,26 @@ fn parse_elf_bytes(
         if ph.p_memsz < ph.p_filesz {
             return Err(ElfErr::BadPh);
         }
+        if file_backed(&view, &ph) {
+            map_file_segment(vmas, bytes, inode, &ph, bias)?;
+            continue;
+        }
 
-        // Align the segment mapping range
-        let align = core::cmp::max(ph.p_align, Size4K::SIZE);
-        let seg_va = ph.p_vaddr.as_u64();
-        trace!("Mapping segment to VA {seg_va} ...", seg_va = ph.p_vaddr);
+        let (map_at, map_end) = segment_range(&ph, bias)?;
+        let write_at = VirtualAddress::new(ph.p_vaddr.as_u64() + bias);
+        trace!("Mapping segment to VA {write_at} ...");
 
-        let seg_start = round_down(seg_va, align);
-        let seg_end = round_up_4k(seg_va + ph.p_memsz);
-        let seg_len = seg_end.checked_sub(seg_start).ok_or(ElfErr::BadPh)?;
-        let map_at = VirtualAddress::new(seg_start + bias);
-        let write_at = VirtualAddress::new(seg_va + bias);
+        // Record the area first, so that a partial mapping is released with the
+        // address space.
+        vmas.insert(Vma::new(map_at, map_end, VmaKind::Image, VmaPerms::RW))
+            .map_err(|_| ElfErr::MapFail)?;
 
         // Anonymous, zeroed user pages → temp RW,NX
         vmm.map_anon_4k_pages(
             AllocationTarget::User,
             map_at,
             0,
-            seg_len,
+            map_end.as_u64() - map_at.as_u64(),
             nonleaf,
             temp_leaf_nx,
         )
@@ -117,66 +140,283 @@ fn parse_elf_bytes(
             vmm.copy_to_mapped_user(write_at, file_bytes)
                 .map_err(|_| ElfErr::MapFail)?;
         }
+    }
 
-        // ---- Final protections (W^X) ----
-        let final_perm = want_perm(ph.p_flags);
-
-        match final_perm {
-            // Executable: flip ONLY the file-backed pages to RX.
-            FinalPerm::Rx => {
-                let file_base = write_at.as_u64();
-                let file_end = file_base + ph.p_filesz;
-                let rx_start = file_base & !(Size4K::SIZE - 1);
-                let rx_end = (file_end + Size4K::SIZE - 1) & !(Size4K::SIZE - 1);
-
-                // Per-page re-protect to work around helpers that don't clear NX
-                let leaf_rx = VirtualMemoryPageBits::user_leaf_code_wb()
-                    .with_writable(false)
-                    .with_no_execute(false); // <- ensure NX=0
-
-                let mut addr = rx_start;
-                while addr < rx_end {
-                    vmm.make_region_rx(VirtualAddress::new(addr), Size4K::SIZE, nonleaf, leaf_rx)
-                        .map_err(|_| ElfErr::MapFail)?;
-                    addr += Size4K::SIZE;
-                }
-            }
+    if view.is_pie() {
+        debug!("Applying relocations ...");
+        for rela in relocations {
+            relocate(vmm, &view, bias, &rela)?;
+        }
+    }
 
-            // Writable, not executable: keep as RW,NX (already correct).
-            FinalPerm::Rw => { /* no-op */ }
+    for ph in view.iter_pt_load().filter(|ph| !file_backed(&view, ph)) {
+        protect_segment(vmm, vmas, &ph, bias)?;
+    }
+
+    let thread_pointer = match view.tls() {
+        Some(ph) => Some(map_tls(vmm, vmas, layout, bytes, &ph)?),
+        None => None,
+    };
+    let stack_top = map_user_stack(vmm, vmas, layout.stack_top, stack_pages_4k, mapped_pages_4k)?;
+
+    Ok(LoadedImage {
+        entry: VirtualAddress::new(view.entry().as_u64() + bias),
+        stack_top,
+        thread_pointer,
+        phdr: view
+            .phdr_vaddr()
+            .map(|vaddr| VirtualAddress::new(vaddr + bias)),
+        phnum: view.phnum(),
+    })
+}
 
-            // Read-only, not executable: flip whole segment to RO,NX.
-            FinalPerm::Ro => {
-                vmm.make_region_ro(
-                    map_at,
-                    seg_len,
-                    nonleaf,
-                    VirtualMemoryPageBits::user_leaf_data_wb().with_writable(false),
+/// The page range the `PT_LOAD` segment `ph` is mapped to.
+fn segment_range(ph: &Ph64, bias: u64) -> Result<(VirtualAddress, VirtualAddress), ElfErr> {
+    let align = core::cmp::max(ph.p_align, Size4K::SIZE);
+    if !align.is_power_of_two() {
+        return Err(ElfErr::BadPh);
+    }
+    let seg_va = ph.p_vaddr.as_u64();
+    let seg_start = round_down(seg_va, align);
+    let seg_end = seg_va
+        .checked_add(ph.p_memsz)
+        .map(round_up_4k)
+        .ok_or(ElfErr::BadPh)?;
+    Ok((
+        VirtualAddress::new(seg_start + bias),
+        VirtualAddress::new(seg_end + bias),
+    ))
+}
+
+/// Whether the `PT_LOAD` segment `ph` is mapped from the file rather than
+/// copied: it is read-only, has no zero-filled tail, starts at the same
+/// offset into a page in memory and in the file, and no relocation writes to
+/// it. Like the segment, the rest of its last page is the file's.
+fn file_backed(view: &ElfView, ph: &Ph64) -> bool {
+    let start = ph.p_vaddr.as_u64();
+    let end = start.saturating_add(ph.p_memsz);
+    let relocated = |rela: Rela| rela.r_offset < end && rela.r_offset.saturating_add(8) > start;
+    !ph.p_flags.write()
+        && ph.p_memsz == ph.p_filesz
+        && ph.p_offset % Size4K::SIZE == start % Size4K::SIZE
+        && view
+            .relocations()
+            .is_ok_and(|mut relocations| !relocations.any(relocated))
+}
+
+/// Record the [`file_backed`] segment `ph` of the file `inode` as a file
+/// area with its final protection; its pages are mapped when first touched
+/// (see [`file_pages`](crate::process::file_pages)).
+fn map_file_segment<const N: usize>(
+    vmas: &mut VmaSet<N>,
+    bytes: &[u8],
+    inode: u64,
+    ph: &Ph64,
+    bias: u64,
+) -> Result<(), ElfErr> {
+    segment_file_bytes(bytes, ph)?;
+    let start = round_down(ph.p_vaddr.as_u64() + bias, Size4K::SIZE);
+    let end = round_up_4k(ph.p_vaddr.as_u64() + bias + ph.p_memsz);
+    let kind = VmaKind::File {
+        inode,
+        offset: round_down(ph.p_offset, Size4K::SIZE),
+    };
+    let perms = match want_perm(ph.p_flags) {
+        FinalPerm::Rx => VmaPerms::RX,
+        FinalPerm::Rw | FinalPerm::Ro => VmaPerms::RO,
+    };
+    trace!("Mapping segment to VA {start} from the file ...");
+    vmas.insert(Vma::new(
+        VirtualAddress::new(start),
+        VirtualAddress::new(end),
+        kind,
+        perms,
+    ))
+    .map_err(|_| ElfErr::MapFail)
+}
+
+/// Apply the relocation `rela` of the program `view` loaded at `bias`.
+fn relocate(vmm: &mut KernelVmm, view: &ElfView, bias: u64, rela: &Rela) -> Result<(), ElfErr> {
+    match rela.r_type {
+        R_X86_64_NONE => Ok(()),
+        R_X86_64_RELATIVE if rela.r_sym == 0 => {
+            // Only ever write into the program's own memory.
+            if !view.is_loaded(rela.r_offset, size_of::<u64>() as u64) {
+                return Err(ElfErr::BadReloc);
+            }
+            let value = bias.wrapping_add_signed(rela.r_addend);
+            unsafe {
+                vmm.copy_to_mapped_user(
+                    VirtualAddress::new(rela.r_offset + bias),
+                    &value.to_ne_bytes(),
                 )
-                .map_err(|_| ElfErr::MapFail)?;
+                .map_err(|_| ElfErr::MapFail)
+            }
+        }
+        _ => Err(ElfErr::BadReloc),
+    }
+}
+
+/// Give the loaded `PT_LOAD` segment `ph` its final protection (W^X).
+fn protect_segment<const N: usize>(
+    vmm: &mut KernelVmm,
+    vmas: &mut VmaSet<N>,
+    ph: &Ph64,
+    bias: u64,
+) -> Result<(), ElfErr> {
+    let nonleaf = VirtualMemoryPageBits::user_table_wb_exec();
+    let (map_at, map_end) = segment_range(ph, bias)?;
+
+    match want_perm(ph.p_flags) {
+        // Executable: flip ONLY the file-backed pages to RX.
+        FinalPerm::Rx => {
+            let file_base = ph.p_vaddr.as_u64() + bias;
+            let file_end = file_base + ph.p_filesz;
+            let rx_start = file_base & !(Size4K::SIZE - 1);
+            let rx_end = (file_end + Size4K::SIZE - 1) & !(Size4K::SIZE - 1);
+
+            // Per-page re-protect to work around helpers that don't clear NX
+            let leaf_rx = VirtualMemoryPageBits::user_leaf_code_wb()
+                .with_writable(false)
+                .with_no_execute(false); // <- ensure NX=0
+
+            let mut addr = rx_start;
+            while addr < rx_end {
+                vmm.make_region_rx(VirtualAddress::new(addr), Size4K::SIZE, nonleaf, leaf_rx)
+                    .map_err(|_| ElfErr::MapFail)?;
+                addr += Size4K::SIZE;
             }
+            vmas.protect(
+                VirtualAddress::new(rx_start),
+                VirtualAddress::new(rx_end),
+                VmaPerms::RX,
+            )
+            .map_err(|_| ElfErr::MapFail)?;
+        }
+
+        // Writable, not executable: keep as RW,NX (already correct).
+        FinalPerm::Rw => { /* no-op */ }
+
+        // Read-only, not executable: flip whole segment to RO,NX.
+        FinalPerm::Ro => {
+            vmm.make_region_ro(
+                map_at,
+                map_end.as_u64() - map_at.as_u64(),
+                nonleaf,
+                VirtualMemoryPageBits::user_leaf_data_wb().with_writable(false),
+            )
+            .map_err(|_| ElfErr::MapFail)?;
+            vmas.protect(map_at, map_end, VmaPerms::RO)
+                .map_err(|_| ElfErr::MapFail)?;
         }
     }
+    Ok(())
+}
 
-    // Map user stack with guard page
+/// Map the static TLS block described by the `PT_TLS` header `ph`, followed
+/// by the thread control block, into a free range of the
+/// [`mmap`](crate::process::mmap) area, preferably above the `layout`'s mmap
+/// base, and record it in `vmas`.
+///
+/// Uses the x86-64 variant II layout: the block ends at the thread pointer,
+/// whose first word points at itself (see `stdlib::tls`). Returns the thread
+/// pointer.
+fn map_tls<const N: usize>(
+    vmm: &mut KernelVmm,
+    vmas: &mut VmaSet<N>,
+    layout: &Layout,
+    bytes: &[u8],
+    ph: &Ph64,
+) -> Result<ThreadPointer, ElfErr> {
+    debug!("Mapping thread-local storage ...");
+    trace!("{ph:#?}");
+
+    // The block starts page-aligned, so larger alignments can not be met.
+    let align = ph.p_align.max(8);
+    if ph.p_memsz < ph.p_filesz || !align.is_power_of_two() || align > Size4K::SIZE {
+        return Err(ElfErr::BadPh);
+    }
+
+    let block_len = ph
+        .p_memsz
+        .checked_next_multiple_of(align)
+        .ok_or(ElfErr::BadPh)?;
+    let map_len = round_up_4k(block_len.checked_add(TCB_SIZE).ok_or(ElfErr::BadPh)?);
+    let base = vmas
+        .find_gap_from(map_len, layout.mmap_base, MMAP_BASE, MMAP_END)
+        .ok_or(ElfErr::MapFail)?;
+    let end = VirtualAddress::new(base.as_u64() + map_len);
+    vmas.insert(Vma::new(base, end, VmaKind::Tls, VmaPerms::RW))
+        .map_err(|_| ElfErr::MapFail)?;
+
+    vmm.map_anon_4k_pages(
+        AllocationTarget::User,
+        base,
+        0,
+        map_len,
+        VirtualMemoryPageBits::user_table_wb_exec(),
+        VirtualMemoryPageBits::user_leaf_data_wb(), // RW, NX
+    )
+    .map_err(|_| ElfErr::MapFail)?;
+
+    // .tdata from the file; .tbss is already zero.
+    let thread_pointer = VirtualAddress::new(base.as_u64() + block_len);
+    let tdata = segment_file_bytes(bytes, ph)?;
+    unsafe {
+        vmm.copy_to_mapped_user(base, tdata)
+            .map_err(|_| ElfErr::MapFail)?;
+        vmm.copy_to_mapped_user(thread_pointer, &thread_pointer.as_u64().to_ne_bytes())
+            .map_err(|_| ElfErr::MapFail)?;
+    }
+
+    Ok(thread_pointer)
+}
+
+/// Reserve a user stack of `stack_pages_4k` pages right below
+/// `user_stack_top`, with an unmapped guard page below it, and record both in
+/// `vmas`. Only the top `mapped_pages_4k` pages are mapped; the rest is
+/// mapped as the stack grows (see [`stack_growth`](crate::process::stack_growth)).
+fn map_user_stack<const N: usize>(
+    vmm: &mut KernelVmm,
+    vmas: &mut VmaSet<N>,
+    user_stack_top: VirtualAddress,
+    stack_pages_4k: NonZeroU64,
+    mapped_pages_4k: NonZeroU64,
+) -> Result<UserStackTop, ElfErr> {
     debug!("Mapping user binary stack ...");
     let guard = Size4K::SIZE;
     let stack_size = stack_pages_4k.get() * Size4K::SIZE;
+    let mapped_size = mapped_pages_4k.get().min(stack_pages_4k.get()) * Size4K::SIZE;
     let stack_base = VirtualAddress::new(user_stack_top.as_u64() - guard - stack_size);
 
+    let stack_bottom = VirtualAddress::new(stack_base.as_u64() + guard);
+    vmas.insert(Vma::new(
+        stack_base,
+        stack_bottom,
+        VmaKind::Guard,
+        VmaPerms::NONE,
+    ))
+    .and_then(|()| {
+        vmas.insert(Vma::new(
+            stack_bottom,
+            user_stack_top,
+            VmaKind::Stack,
+            VmaPerms::RW,
+        ))
+    })
+    .map_err(|_| ElfErr::MapFail)?;
+
     vmm.map_anon_4k_pages(
         AllocationTarget::User,
-        stack_base,
-        guard,
-        stack_size,
-        nonleaf.with_no_execute(true),
+        VirtualAddress::new(user_stack_top.as_u64() - mapped_size),
+        0,
+        mapped_size,
+        VirtualMemoryPageBits::user_table_wb_exec().with_no_execute(true),
         VirtualMemoryPageBits::user_leaf_data_wb(), // RW, NX
     )
     .map_err(|_| ElfErr::MapFail)?;
 
-    // Entrypoint
-    let entry = VirtualAddress::new(view.entry().as_u64() + bias);
-    Ok((entry, user_stack_top))
+    Ok(user_stack_top)
 }
 
 #[inline]
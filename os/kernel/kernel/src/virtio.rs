@@ -0,0 +1,365 @@
+//! # Virtio Devices
+//!
+//! Paravirtualized devices of QEMU and other hypervisors, driven through the
+//! *legacy* PCI transport of virtio 0.9.5: the registers sit in I/O BAR 0,
+//! and each split virtqueue is handed to the device by its page frame number.
+//! QEMU offers this transport on transitional devices, the default for
+//! devices on the root bus, so no capability parsing or MMIO mapping is
+//! needed.
+//!
+//! * [`LegacyDevice`]: the register block of a device
+//! * [`Virtqueue`]: a split virtqueue in physically contiguous frames
+//! * [`console`]: the console device, `/dev/hvc0`
+//!
+//! ## Interrupts
+//!
+//! Device interrupts are not routed yet, so queues are set up with
+//! interrupts suppressed and drivers poll their used rings.
+
+pub mod console;
+
+use crate::alloc::dma::{DmaBuffer, DmaConstraints, DmaError};
+use crate::pci::{self, Bar, COMMAND_BUS_MASTER, COMMAND_IO_SPACE, PciFunction};
+use core::fmt;
+use core::sync::atomic::{Ordering, fence};
+use kernel_memory_addresses::{PageSize, PhysicalAddress, Size4K};
+use kernel_ports::PortRange;
+use log::debug;
+
+/// PCI vendor ID of all virtio devices.
+pub const VENDOR_ID: u16 = 0x1AF4;
+
+/// Register: features the device offers (32 bits).
+const DEVICE_FEATURES: u16 = 0x00;
+/// Register: features the driver accepted (32 bits).
+const GUEST_FEATURES: u16 = 0x04;
+/// Register: page frame number of the selected queue (32 bits).
+const QUEUE_PFN: u16 = 0x08;
+/// Register: size of the selected queue (16 bits).
+const QUEUE_SIZE: u16 = 0x0C;
+/// Register: the queue the queue registers refer to (16 bits).
+const QUEUE_SELECT: u16 = 0x0E;
+/// Register: write a queue index to tell the device it has new buffers.
+const QUEUE_NOTIFY: u16 = 0x10;
+/// Register: device status (8 bits).
+const DEVICE_STATUS: u16 = 0x12;
+/// Start of the device-specific configuration, without MSI-X.
+const DEVICE_CONFIG: u16 = 0x14;
+/// Ports of the register block the drivers touch: the registers above and
+/// up to 44 bytes of device configuration.
+const REGISTERS_LEN: u16 = 0x40;
+
+/// Status bit: the guest has noticed the device.
+const STATUS_ACKNOWLEDGE: u8 = 1;
+/// Status bit: the guest has a driver for the device.
+const STATUS_DRIVER: u8 = 2;
+/// Status bit: the driver is ready.
+const STATUS_DRIVER_OK: u8 = 4;
+/// Status bit: the driver gave up on the device.
+const STATUS_FAILED: u8 = 128;
+
+/// Descriptor flag: the device writes the buffer.
+pub const DESC_WRITE: u16 = 2;
+
+/// Available ring flag: do not interrupt when buffers are used.
+const AVAIL_NO_INTERRUPT: u16 = 1;
+
+/// Alignment of the used ring in the legacy layout.
+const USED_ALIGN: u64 = 4096;
+
+/// Where queues go: the device takes their page frame number.
+const QUEUE_CONSTRAINTS: DmaConstraints = DmaConstraints::ANY.with_align(Size4K::SIZE);
+
+/// Why a virtio device could not be set up.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum VirtioError {
+    /// No PCI function with the device ID.
+    NotFound,
+    /// BAR 0 is not an I/O range; the device is modern-only.
+    NoLegacyTransport(PciFunction),
+    /// The device does not implement the queue.
+    NoQueue(u16),
+    /// No contiguous frames for a queue or buffers.
+    OutOfMemory,
+}
+
+impl fmt::Display for VirtioError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound => f.write_str("no such device"),
+            Self::NoLegacyTransport(function) => {
+                write!(f, "{function} has no legacy I/O BAR")
+            }
+            Self::NoQueue(index) => write!(f, "device has no queue {index}"),
+            Self::OutOfMemory => f.write_str("out of memory"),
+        }
+    }
+}
+
+impl From<DmaError> for VirtioError {
+    fn from(_: DmaError) -> Self {
+        Self::OutOfMemory
+    }
+}
+
+/// The legacy register block of a virtio device.
+#[derive(Debug)]
+pub struct LegacyDevice {
+    function: PciFunction,
+    io: PortRange,
+}
+
+impl LegacyDevice {
+    /// Find the device with PCI device ID `device_id`, reset it and
+    /// acknowledge it.
+    pub fn probe(device_id: u16) -> Result<Self, VirtioError> {
+        let function = pci::find(VENDOR_ID, device_id).ok_or(VirtioError::NotFound)?;
+        let Some(Bar::Io(io)) = function.bar(0) else {
+            return Err(VirtioError::NoLegacyTransport(function));
+        };
+        function.enable(COMMAND_IO_SPACE | COMMAND_BUS_MASTER);
+        debug!("virtio device {device_id:#06x} at {function}, I/O base {io:#06x}");
+
+        let device = Self {
+            function,
+            io: PortRange::new(io, REGISTERS_LEN),
+        };
+        device.reset();
+        device.set_status(STATUS_ACKNOWLEDGE | STATUS_DRIVER);
+        Ok(device)
+    }
+
+    /// Where the device sits on the PCI bus.
+    pub const fn function(&self) -> PciFunction {
+        self.function
+    }
+
+    pub fn device_features(&self) -> u32 {
+        // Safety: `io` is the device's legacy register block.
+        unsafe { self.io.read_only::<u32>(DEVICE_FEATURES).read() }
+    }
+
+    /// Accept `features`, a subset of [`Self::device_features`].
+    pub fn set_guest_features(&self, features: u32) {
+        // Safety: see `device_features`.
+        unsafe { self.io.write_only(GUEST_FEATURES).write(features) };
+    }
+
+    /// Read the 16-bit field at `offset` of the device configuration.
+    #[allow(dead_code)]
+    pub fn config_u16(&self, offset: u16) -> u16 {
+        // Safety: see `device_features`.
+        unsafe { self.io.read_only::<u16>(DEVICE_CONFIG + offset).read() }
+    }
+
+    /// Allocate queue `index` at the size the device dictates and hand it to
+    /// the device.
+    pub fn setup_queue(&self, index: u16) -> Result<Virtqueue, VirtioError> {
+        // Safety: see `device_features`.
+        let size = unsafe {
+            self.io.port(QUEUE_SELECT).write(index);
+            self.io.read_only::<u16>(QUEUE_SIZE).read()
+        };
+        if size == 0 {
+            return Err(VirtioError::NoQueue(index));
+        }
+
+        let queue = Virtqueue::new(size)?;
+        let pfn = queue.memory.phys().as_u64() / Size4K::SIZE;
+        // Safety: see `device_features`.
+        unsafe {
+            self.io.port(QUEUE_SELECT).write(index);
+            self.io
+                .port(QUEUE_PFN)
+                .write(u32::try_from(pfn).expect("queue above 16 TiB"));
+        }
+        debug!(
+            "virtio queue {index}: {size} entries at {}",
+            queue.memory.phys()
+        );
+        Ok(queue)
+    }
+
+    /// Tell the device that queue `index` has new available buffers.
+    pub fn notify(&self, index: u16) {
+        // Make the available ring visible before the device looks at it.
+        fence(Ordering::SeqCst);
+        // Safety: see `device_features`.
+        unsafe { self.io.write_only(QUEUE_NOTIFY).write(index) };
+    }
+
+    /// Set up is complete; the device may start using the queues.
+    pub fn driver_ok(&self) {
+        self.set_status(self.status() | STATUS_DRIVER_OK);
+    }
+
+    /// Give up on the device.
+    pub fn fail(&self) {
+        self.set_status(self.status() | STATUS_FAILED);
+    }
+
+    /// Stop the device: it forgets its queues and no longer touches their
+    /// memory.
+    pub fn reset(&self) {
+        self.set_status(0);
+    }
+
+    fn status(&self) -> u8 {
+        // Safety: see `device_features`.
+        unsafe { self.io.port::<u8>(DEVICE_STATUS).read() }
+    }
+
+    fn set_status(&self, status: u8) {
+        // Safety: see `device_features`.
+        unsafe { self.io.port(DEVICE_STATUS).write(status) };
+    }
+}
+
+/// One entry of the descriptor table.
+#[repr(C)]
+struct Descriptor {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+/// A split virtqueue in the legacy layout: descriptor table and available
+/// ring, then the used ring on the next page.
+///
+/// The driver keeps its own copy of the available index and the position in
+/// the used ring; the device's copies live in the rings.
+///
+/// The queue's memory is freed when it is dropped; the device must be
+/// [reset](LegacyDevice::reset) before, unless it never got to use it.
+#[derive(Debug)]
+pub struct Virtqueue {
+    memory: DmaBuffer,
+    size: u16,
+    next_avail: u16,
+    last_used: u16,
+}
+
+impl Virtqueue {
+    /// Allocate zeroed memory for a queue of `size` entries.
+    #[allow(clippy::cast_possible_truncation)]
+    fn new(size: u16) -> Result<Self, VirtioError> {
+        let bytes = usize::try_from(Self::bytes(size)).expect("queue size fits usize");
+        let memory = DmaBuffer::alloc(bytes, QUEUE_CONSTRAINTS)?;
+
+        let queue = Self {
+            memory,
+            size,
+            next_avail: 0,
+            last_used: 0,
+        };
+        // Safety: in bounds of the available ring, which the device does
+        // not know about yet.
+        unsafe {
+            queue
+                .ptr::<u16>(queue.avail_offset())
+                .write_volatile(AVAIL_NO_INTERRUPT);
+        }
+        Ok(queue)
+    }
+
+    /// Number of entries.
+    pub const fn size(&self) -> u16 {
+        self.size
+    }
+
+    /// Bytes taken by a queue of `size` entries.
+    const fn bytes(size: u16) -> u64 {
+        let size = size as u64;
+        Self::used_offset_for(size) + (6 + 8 * size).next_multiple_of(USED_ALIGN)
+    }
+
+    const fn used_offset_for(size: u64) -> u64 {
+        (16 * size + 6 + 2 * size).next_multiple_of(USED_ALIGN)
+    }
+
+    const fn avail_offset(&self) -> u64 {
+        16 * self.size as u64
+    }
+
+    const fn used_offset(&self) -> u64 {
+        Self::used_offset_for(self.size as u64)
+    }
+
+    /// The queue memory at `offset`, through the HHDM.
+    fn ptr<T>(&self, offset: u64) -> *mut T {
+        (self.memory.virt().as_u64() + offset) as *mut T
+    }
+
+    /// Point descriptor `index` at `len` bytes at `addr`.
+    pub fn set_descriptor(&mut self, index: u16, addr: PhysicalAddress, len: u32, flags: u16) {
+        assert!(index < self.size, "descriptor index out of range");
+        // Safety: in bounds of the descriptor table, which only we write.
+        unsafe {
+            let descriptor = self.ptr::<Descriptor>(u64::from(index) * 16);
+            (&raw mut (*descriptor).addr).write_volatile(addr.as_u64());
+            (&raw mut (*descriptor).len).write_volatile(len);
+            (&raw mut (*descriptor).flags).write_volatile(flags);
+            (&raw mut (*descriptor).next).write_volatile(0);
+        }
+    }
+
+    /// Make descriptor `index` available to the device; it learns about it
+    /// from [`LegacyDevice::notify`].
+    pub fn push_avail(&mut self, index: u16) {
+        let slot = u64::from(self.next_avail % self.size);
+        self.next_avail = self.next_avail.wrapping_add(1);
+        // Safety: in bounds of the available ring, which only we write.
+        unsafe {
+            self.ptr::<u16>(self.avail_offset() + 4 + 2 * slot)
+                .write_volatile(index);
+            fence(Ordering::Release);
+            self.ptr::<u16>(self.avail_offset() + 2)
+                .write_volatile(self.next_avail);
+        }
+    }
+
+    /// Whether the device returned buffers not yet taken by [`Self::pop_used`].
+    pub fn has_used(&self) -> bool {
+        self.used_idx() != self.last_used
+    }
+
+    /// Take the next buffer the device returned: its descriptor index and
+    /// the number of bytes the device wrote into it.
+    pub fn pop_used(&mut self) -> Option<(u16, u32)> {
+        if !self.has_used() {
+            return None;
+        }
+        let used = self.used(self.last_used);
+        self.last_used = self.last_used.wrapping_add(1);
+        Some(used)
+    }
+
+    /// The buffers [`Self::pop_used`] would return, without taking them.
+    pub fn peek_used(&self) -> impl Iterator<Item = (u16, u32)> + '_ {
+        let pending = self.used_idx().wrapping_sub(self.last_used);
+        (0..pending).map(|n| self.used(self.last_used.wrapping_add(n)))
+    }
+
+    /// Entry `position` of the used ring, which the device has written.
+    #[allow(clippy::cast_possible_truncation)]
+    fn used(&self, position: u16) -> (u16, u32) {
+        fence(Ordering::Acquire);
+        let elem = self.used_offset() + 4 + 8 * u64::from(position % self.size);
+        // Safety: in bounds of the used ring.
+        let (id, len) = unsafe {
+            (
+                self.ptr::<u32>(elem).read_volatile(),
+                self.ptr::<u32>(elem + 4).read_volatile(),
+            )
+        };
+        (id as u16, len)
+    }
+
+    /// The device's index into the used ring; it moves on with every
+    /// buffer returned.
+    pub fn used_idx(&self) -> u16 {
+        // Safety: in bounds of the used ring.
+        unsafe { self.ptr::<u16>(self.used_offset() + 2).read_volatile() }
+    }
+}
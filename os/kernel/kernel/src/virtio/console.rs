@@ -0,0 +1,388 @@
+//! # Virtio Console
+//!
+//! Driver of the virtio console, a bidirectional byte channel between the
+//! host and the kernel. Under QEMU, add one with
+//!
+//! ```text
+//! -device virtio-serial-pci,disable-legacy=off
+//! -chardev socket,id=hvc0,path=...,server=on,wait=off
+//! -device virtconsole,chardev=hvc0
+//! ```
+//!
+//! `task qemu` does, on the Unix socket `qemu/hvc0.sock`; connect with e.g.
+//! `socat - UNIX-CONNECT:qemu/hvc0.sock`. The device is registered as the
+//! [character device](crate::chardev) `hvc0`: userland opens `/dev/hvc0`,
+//! kernel code such as the [debugger](crate::kdb) uses [`CONSOLE`] directly.
+//!
+//! ## Queues
+//!
+//! Multiport is not negotiated, so only port 0 exists, with the receive
+//! queue 0 and the transmit queue 1. [`RX_BUFFERS`] buffers of
+//! [`BUFFER_LEN`] bytes stay posted on the receive queue; a buffer goes back
+//! to the device once it is read to the end. Until then, the device holds
+//! further input, so nothing is dropped.
+//!
+//! Writes are synchronous: a write copies up to [`BUFFER_LEN`] bytes into the
+//! single transmit buffer and waits for the device to return it. If the host
+//! does not take the data within [`TX_SPINS`] polls, the buffer stays in
+//! flight and writes return `0` until the device hands it back.
+//!
+//! ## Polling
+//!
+//! Device interrupts are not routed, so [`poll`] checks the receive queue
+//! from the LAPIC timer interrupt. It is the top half of the [threaded
+//! interrupt](crate::irq_thread) [`IRQ`]: it only notes whether the device
+//! returned buffers since the last look. The bottom half, in the `irq-hvc0`
+//! kernel thread, then wakes the readers.
+//!
+//! ## Shutdown
+//!
+//! Before the machine powers off or restarts, [`shutdown`] waits for the
+//! transmit buffer still in flight and resets the device, so the host sees
+//! the last write and the device stops using the queues, whose memory is
+//! freed then. The console is down afterwards: reads and writes return `0`.
+
+use crate::alloc::dma::{DmaBuffer, DmaConstraints};
+use crate::chardev::{self, CharDevice};
+use crate::irq_thread::{self, IrqReturn, ThreadedIrq};
+use crate::sched::WaitQueue;
+use crate::virtio::{DESC_WRITE, LegacyDevice, VirtioError, Virtqueue};
+use core::hint::spin_loop;
+use core::ops::Range;
+use kernel_memory_addresses::PhysicalAddress;
+use kernel_sync::{IrqGuard, SpinMutex};
+use log::{debug, info, warn};
+use stdlib::syscall_abi::caps::CAP_DEVICE_CONSOLE;
+
+/// PCI device ID of the transitional virtio console.
+const DEVICE_ID: u16 = 0x1003;
+
+/// Queue of port 0 carrying host input.
+const RX_QUEUE: u16 = 0;
+
+/// Queue of port 0 carrying kernel output.
+const TX_QUEUE: u16 = 1;
+
+/// Bytes per buffer.
+pub const BUFFER_LEN: usize = 128;
+
+/// Receive buffers kept posted; the transmit buffer follows them.
+pub const RX_BUFFERS: u16 = 16;
+
+/// How often a write polls for the transmit buffer to come back.
+pub const TX_SPINS: usize = 100_000;
+
+/// The virtio console, once [`init`] found it.
+pub static CONSOLE: VirtioConsole = VirtioConsole {
+    state: SpinMutex::new(None),
+    readable: WaitQueue::new(),
+};
+
+/// Notices input in the interrupt, wakes readers in a thread.
+pub static IRQ: ThreadedIrq = ThreadedIrq::new("irq-hvc0", check_input, wake_readers);
+
+/// The console device; see the [module docs](self).
+pub struct VirtioConsole {
+    state: SpinMutex<Option<Console>>,
+    readable: WaitQueue,
+}
+
+/// A receive buffer the device filled, partly read.
+#[derive(Debug, Copy, Clone)]
+struct Pending {
+    descriptor: u16,
+    len: usize,
+    read: usize,
+}
+
+struct Console {
+    device: LegacyDevice,
+    rx: Virtqueue,
+    tx: Virtqueue,
+    /// All buffers, the transmit buffer last.
+    buffers: DmaBuffer,
+    /// The receive buffer being read.
+    pending: Option<Pending>,
+    /// The transmit buffer is with the device.
+    tx_in_flight: bool,
+    /// The device's used index as of the last [`poll`].
+    rx_seen: u16,
+}
+
+impl Console {
+    /// Set up the device's queues and post the receive buffers; marks the
+    /// device failed if that does not work out.
+    #[allow(clippy::cast_possible_truncation)]
+    fn new(device: LegacyDevice) -> Result<Self, VirtioError> {
+        debug!("Virtio console features: {:#x}", device.device_features());
+        // No size, multiport or emergency write.
+        device.set_guest_features(0);
+
+        let (mut rx, tx, buffers) = Self::allocate(&device).inspect_err(|_| device.fail())?;
+        for descriptor in 0..RX_BUFFERS.min(rx.size()) {
+            rx.set_descriptor(
+                descriptor,
+                Self::buffer(&buffers, descriptor),
+                BUFFER_LEN as u32,
+                DESC_WRITE,
+            );
+            rx.push_avail(descriptor);
+        }
+
+        device.driver_ok();
+        device.notify(RX_QUEUE);
+        Ok(Self {
+            device,
+            rx,
+            tx,
+            buffers,
+            pending: None,
+            tx_in_flight: false,
+            rx_seen: 0,
+        })
+    }
+
+    /// The receive and transmit queues and the buffers.
+    fn allocate(device: &LegacyDevice) -> Result<(Virtqueue, Virtqueue, DmaBuffer), VirtioError> {
+        let rx = device.setup_queue(RX_QUEUE)?;
+        let tx = device.setup_queue(TX_QUEUE)?;
+        let len = (usize::from(RX_BUFFERS) + 1) * BUFFER_LEN;
+        let buffers = DmaBuffer::alloc(len, DmaConstraints::ANY)?;
+        Ok((rx, tx, buffers))
+    }
+
+    /// Buffer `index`; the transmit buffer is index [`RX_BUFFERS`].
+    fn buffer(buffers: &DmaBuffer, index: u16) -> PhysicalAddress {
+        buffers.phys_at(usize::from(index) * BUFFER_LEN)
+    }
+
+    /// Buffer `index`, through the HHDM.
+    fn buffer_ptr(&self, index: u16) -> *mut u8 {
+        debug_assert!(index <= RX_BUFFERS);
+        // Safety: in bounds of `buffers`.
+        unsafe { self.buffers.as_ptr().add(usize::from(index) * BUFFER_LEN) }
+    }
+
+    /// Bytes `range` of receive buffer `descriptor`, which the device
+    /// returned.
+    fn received(&self, descriptor: u16, range: Range<usize>) -> &[u8] {
+        debug_assert!(descriptor < RX_BUFFERS && range.end <= BUFFER_LEN);
+        // Safety: the device does not touch the buffer until it is posted
+        // again, which takes `&mut self`.
+        unsafe {
+            core::slice::from_raw_parts(self.buffer_ptr(descriptor).add(range.start), range.len())
+        }
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> usize {
+        let mut read = 0;
+        let mut returned = false;
+        while read < buf.len() {
+            let mut pending = match self.pending.take() {
+                Some(pending) => pending,
+                None => match self.rx.pop_used() {
+                    Some((descriptor, len)) => Pending {
+                        descriptor,
+                        len: (len as usize).min(BUFFER_LEN),
+                        read: 0,
+                    },
+                    None => break,
+                },
+            };
+
+            let n = (pending.len - pending.read).min(buf.len() - read);
+            let bytes = self.received(pending.descriptor, pending.read..pending.read + n);
+            buf[read..read + n].copy_from_slice(bytes);
+            read += n;
+            pending.read += n;
+
+            if pending.read == pending.len {
+                self.rx.push_avail(pending.descriptor);
+                returned = true;
+            } else {
+                self.pending = Some(pending);
+            }
+        }
+
+        if returned {
+            self.device.notify(RX_QUEUE);
+        }
+        read
+    }
+
+    /// Whether received bytes are not read yet.
+    fn has_input(&self) -> bool {
+        self.pending.is_some() || self.rx.peek_used().next().is_some()
+    }
+
+    /// Whether `byte` is among the received bytes not yet read.
+    fn contains(&self, byte: u8) -> bool {
+        let pending = self.pending.is_some_and(|pending| {
+            self.received(pending.descriptor, pending.read..pending.len)
+                .contains(&byte)
+        });
+        pending
+            || self.rx.peek_used().any(|(descriptor, len)| {
+                self.received(descriptor, 0..(len as usize).min(BUFFER_LEN))
+                    .contains(&byte)
+            })
+    }
+
+    /// Wait up to [`TX_SPINS`] polls for the transmit buffer to come back.
+    fn flush(&mut self) -> bool {
+        for _ in 0..TX_SPINS {
+            if !self.tx_in_flight || self.tx.pop_used().is_some() {
+                self.tx_in_flight = false;
+                return true;
+            }
+            spin_loop();
+        }
+        false
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn write(&mut self, bytes: &[u8]) -> usize {
+        if self.tx_in_flight {
+            if self.tx.pop_used().is_none() {
+                return 0;
+            }
+            self.tx_in_flight = false;
+        }
+
+        let n = bytes.len().min(BUFFER_LEN);
+        // Safety: the transmit buffer is not with the device.
+        unsafe {
+            core::ptr::copy_nonoverlapping(bytes.as_ptr(), self.buffer_ptr(RX_BUFFERS), n);
+        }
+        self.tx
+            .set_descriptor(0, Self::buffer(&self.buffers, RX_BUFFERS), n as u32, 0);
+        self.tx.push_avail(0);
+        self.device.notify(TX_QUEUE);
+        self.tx_in_flight = true;
+        self.flush();
+        n
+    }
+}
+
+impl VirtioConsole {
+    /// Whether [`init`] set the device up.
+    pub fn is_up(&self) -> bool {
+        self.with_state(|_| ()).is_some()
+    }
+
+    /// Whether `byte` was received and not read yet; it stays unread.
+    pub fn contains(&self, byte: u8) -> bool {
+        self.with_state(|console| console.contains(byte))
+            .unwrap_or(false)
+    }
+
+    fn with_state<R>(&self, f: impl FnOnce(&mut Console) -> R) -> Option<R> {
+        let _irq = IrqGuard::new();
+        self.state.lock().as_mut().map(f)
+    }
+}
+
+impl CharDevice for VirtioConsole {
+    fn name(&self) -> &'static str {
+        "hvc0"
+    }
+
+    fn read(&self, buf: &mut [u8]) -> usize {
+        self.with_state(|console| console.read(buf)).unwrap_or(0)
+    }
+
+    fn write(&self, bytes: &[u8]) -> usize {
+        self.with_state(|console| console.write(bytes)).unwrap_or(0)
+    }
+
+    fn has_input(&self) -> bool {
+        self.with_state(|console| console.has_input())
+            .unwrap_or(false)
+    }
+
+    fn readable(&self) -> &WaitQueue {
+        &self.readable
+    }
+
+    fn capabilities(&self) -> u64 {
+        CAP_DEVICE_CONSOLE
+    }
+}
+
+/// Find and set up the console, and register it as `hvc0`.
+pub fn init() {
+    let device = match LegacyDevice::probe(DEVICE_ID) {
+        Ok(device) => device,
+        Err(VirtioError::NotFound) => {
+            debug!("No virtio console");
+            return;
+        }
+        Err(e) => {
+            warn!("Virtio console not usable: {e}");
+            return;
+        }
+    };
+
+    let function = device.function();
+    let console = match Console::new(device) {
+        Ok(console) => console,
+        Err(e) => {
+            warn!("Failed to set up the virtio console at {function}: {e}");
+            return;
+        }
+    };
+    {
+        let _irq = IrqGuard::new();
+        *CONSOLE.state.lock() = Some(console);
+    }
+
+    match chardev::register(&CONSOLE) {
+        Ok(_) => info!("Virtio console at {function}"),
+        Err(e) => warn!("Virtio console at {function} not registered: {e}"),
+    }
+    if let Err(e) = irq_thread::register(&IRQ) {
+        warn!("Virtio console readers will not be woken: {e}");
+    }
+}
+
+/// Flush pending output and reset the device; see the
+/// [module docs](self#shutdown).
+pub fn shutdown() {
+    let console = {
+        let _irq = IrqGuard::new();
+        CONSOLE.state.lock().take()
+    };
+    if let Some(mut console) = console {
+        if !console.flush() {
+            warn!("Virtio console: host did not take the last write");
+        }
+        console.device.reset();
+        debug!("Virtio console at {} reset", console.device.function());
+    }
+}
+
+/// Have readers of the console woken if input arrived.
+///
+/// Called from interrupt context.
+pub fn poll() {
+    IRQ.handle();
+}
+
+/// Top half: whether the device returned receive buffers since the last
+/// look.
+fn check_input() -> IrqReturn {
+    let arrived = CONSOLE.with_state(|console| {
+        let used = console.rx.used_idx();
+        core::mem::replace(&mut console.rx_seen, used) != used
+    });
+    if arrived == Some(true) {
+        IrqReturn::WakeThread
+    } else {
+        IrqReturn::None
+    }
+}
+
+/// Bottom half: wake the readers of the console.
+fn wake_readers() {
+    CONSOLE.readable.wake_all();
+}
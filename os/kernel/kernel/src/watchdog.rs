@@ -0,0 +1,205 @@
+//! # Watchdog
+//!
+//! Detects CPUs that stopped making progress and reports where they got stuck.
+//!
+//! ## Heartbeats and progress
+//!
+//! Every local APIC timer interrupt calls [`on_tick`], which stamps the CPU's
+//! [`CpuWatchdog`] with the current TSC (the *heartbeat*) and records the
+//! interrupted instruction and frame pointer as the CPU's last known context.
+//!
+//! Progress is tracked separately: the scheduler, the idle loop and ticks
+//! that interrupt user mode mark the CPU as *fed*. Code that legitimately
+//! keeps a CPU busy in the kernel for a long time, such as slow boot phases,
+//! calls [`feed`] from time to time.
+//!
+//! ## Detection
+//!
+//! After each heartbeat, the CPU checks
+//!
+//! * **itself** for a *soft lockup*: timer interrupts still arrive, but the
+//!   CPU was not fed for longer than the threshold, e.g. because it spins in
+//!   a kernel loop. The report includes a backtrace of the interrupted code.
+//! * **every other CPU** for a *hard lockup*: its heartbeat is older than the
+//!   threshold, so it no longer takes timer interrupts, e.g. because it is
+//!   stuck with interrupts disabled or in an interrupt handler that never
+//!   returns. The report shows that CPU's last recorded context.
+//!
+//! Each stall is reported once; a CPU that makes progress again is rearmed.
+//! [Parked](crate::hotplug) CPUs are not checked.
+//! A CPU can not detect its own hard lockup from its timer interrupt; the
+//! [NMI handler](crate::interrupts::nmi) calls [`check_all`] instead, which
+//! covers every CPU as long as NMIs arrive, e.g. from the
+//! [profiler](crate::profiler).
+//!
+//! ## Configuration
+//!
+//! The threshold is set in seconds with `watchdog_thresh` on the
+//! [kernel command line](crate::cmdline); `watchdog_thresh=0` disables the
+//! watchdog. Nothing is checked before [`enable`] was called.
+
+use crate::cmdline::{self, Param, ParamKind};
+use crate::hotplug::CpuState;
+use crate::klog::emergency;
+use crate::ksyms::{self, Symbolized};
+use crate::per_cpu::{self, PerCpu};
+use crate::tsc::rdtsc;
+use crate::{clock, kimage};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use kernel_memory_addresses::VirtualAddress;
+use log::info;
+
+pub static WATCHDOG_THRESH_PARAM: Param = Param {
+    name: "watchdog_thresh",
+    kind: ParamKind::U64,
+    help: "seconds without progress before a CPU is reported as hung; 0 disables",
+};
+
+/// Threshold used when `watchdog_thresh` is not given.
+pub const DEFAULT_THRESHOLD_SECS: u64 = 10;
+
+/// Threshold in TSC cycles; `0` while the watchdog is disabled.
+static THRESHOLD_TSC: AtomicU64 = AtomicU64::new(0);
+
+/// Per-CPU watchdog state, embedded in [`PerCpu`].
+pub struct CpuWatchdog {
+    /// TSC of the last timer interrupt.
+    heartbeat_tsc: AtomicU64,
+    /// TSC of the last sign of progress.
+    fed_tsc: AtomicU64,
+    /// Instruction pointer interrupted by the last timer interrupt.
+    last_rip: AtomicU64,
+    /// Frame pointer interrupted by the last timer interrupt.
+    last_rbp: AtomicU64,
+    /// Whether the current soft lockup was reported.
+    soft_reported: AtomicBool,
+    /// Whether the current hard lockup was reported.
+    hard_reported: AtomicBool,
+}
+
+impl CpuWatchdog {
+    pub const fn new() -> Self {
+        Self {
+            heartbeat_tsc: AtomicU64::new(0),
+            fed_tsc: AtomicU64::new(0),
+            last_rip: AtomicU64::new(0),
+            last_rbp: AtomicU64::new(0),
+            soft_reported: AtomicBool::new(false),
+            hard_reported: AtomicBool::new(false),
+        }
+    }
+
+    fn feed(&self, now: u64) {
+        self.fed_tsc.store(now, Ordering::Relaxed);
+        self.soft_reported.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Start checking registered CPUs, using the threshold from the command line.
+///
+/// Requires the TSC frequency to be known.
+pub fn enable() {
+    let secs = cmdline::get_u64(WATCHDOG_THRESH_PARAM.name).unwrap_or(DEFAULT_THRESHOLD_SECS);
+    if secs == 0 {
+        info!("Watchdog disabled on the command line");
+        return;
+    }
+
+    let now = rdtsc();
+    for cpu in per_cpu::cpus() {
+        cpu.watchdog.heartbeat_tsc.store(now, Ordering::Relaxed);
+        cpu.watchdog.feed(now);
+    }
+    THRESHOLD_TSC.store(
+        secs.saturating_mul(clock::tsc_hz().max(1)),
+        Ordering::Release,
+    );
+    info!("Watchdog enabled; threshold {secs} s");
+}
+
+/// Mark the current CPU as making progress.
+pub fn feed() {
+    let cpu = unsafe { PerCpu::current() };
+    cpu.watchdog.feed(rdtsc());
+}
+
+/// Start over the checks of the current CPU, which was parked and therefore
+/// neither took timer interrupts nor made progress.
+pub fn resume() {
+    let cpu = unsafe { PerCpu::current() };
+    let now = rdtsc();
+    cpu.watchdog.heartbeat_tsc.store(now, Ordering::Relaxed);
+    cpu.watchdog.feed(now);
+}
+
+/// Heartbeat from the timer interrupt of the current CPU; see the
+/// [module docs](self).
+///
+/// `user` tells whether the interrupt arrived in user mode.
+pub fn on_tick(rip: VirtualAddress, rbp: u64, user: bool) {
+    let cpu = unsafe { PerCpu::current() };
+    let wd = &cpu.watchdog;
+    let now = rdtsc();
+    wd.heartbeat_tsc.store(now, Ordering::Relaxed);
+    wd.last_rip.store(rip.as_u64(), Ordering::Relaxed);
+    wd.last_rbp.store(rbp, Ordering::Relaxed);
+    wd.hard_reported.store(false, Ordering::Relaxed);
+    if user {
+        wd.feed(now);
+    }
+
+    let threshold = THRESHOLD_TSC.load(Ordering::Acquire);
+    if threshold == 0 {
+        return;
+    }
+
+    let stalled = now.wrapping_sub(wd.fed_tsc.load(Ordering::Relaxed));
+    if stalled > threshold && !wd.soft_reported.swap(true, Ordering::Relaxed) {
+        emergency!(
+            "Watchdog: soft lockup on CPU {} for {} ms at {}",
+            cpu.cpu_id,
+            tsc_to_ms(stalled),
+            Symbolized(rip)
+        );
+        if kimage::is_kernel_text(rip) {
+            ksyms::log_backtrace_from(rbp);
+        }
+    }
+
+    check_others(cpu, now, threshold);
+}
+
+/// Check every registered CPU, including the current one, for a hard lockup.
+pub fn check_all() {
+    let threshold = THRESHOLD_TSC.load(Ordering::Acquire);
+    if threshold != 0 {
+        check_others(core::ptr::null(), rdtsc(), threshold);
+    }
+}
+
+/// Report CPUs other than `this` whose heartbeat is older than `threshold`.
+fn check_others(this: *const PerCpu, now: u64, threshold: u64) {
+    for cpu in per_cpu::cpus()
+        .filter(|&cpu| !core::ptr::eq(cpu, this) && cpu.hotplug.state() != CpuState::Offline)
+    {
+        let wd = &cpu.watchdog;
+        let silent = now.wrapping_sub(wd.heartbeat_tsc.load(Ordering::Relaxed));
+        // Heartbeats taken on another CPU may be slightly ahead of `now`.
+        if silent > threshold
+            && silent < u64::MAX / 2
+            && !wd.hard_reported.swap(true, Ordering::Relaxed)
+        {
+            emergency!(
+                "Watchdog: hard lockup on CPU {}: no timer interrupt for {} ms; last at {} (rbp={:#x})",
+                cpu.cpu_id,
+                tsc_to_ms(silent),
+                Symbolized(VirtualAddress::new(wd.last_rip.load(Ordering::Relaxed))),
+                wd.last_rbp.load(Ordering::Relaxed)
+            );
+        }
+    }
+}
+
+fn tsc_to_ms(cycles: u64) -> u64 {
+    cycles / (clock::tsc_hz() / 1000).max(1)
+}
@@ -0,0 +1,249 @@
+//! # Workqueue
+//!
+//! Interrupt handlers should do as little as possible. Anything that can wait,
+//! such as logging, is packaged as a [`Work`] item, a function and an
+//! argument, and run later outside of interrupt context.
+//!
+//! ## Queueing
+//!
+//! * [`schedule_work`] pushes an item onto the current CPU's queue, a
+//!   lock-free [`MpscRing`], and wakes the CPU's worker. It never waits for a
+//!   lock, so it is safe from any context, including interrupt handlers.
+//! * [`schedule_delayed_work`] parks the item in one of [`MAX_DELAYED`]
+//!   delayed slots and arms a [timer](crate::timer) that queues it once the
+//!   delay has passed, on the CPU running the timer.
+//!
+//! Items queued on a CPU run on that CPU, in order.
+//!
+//! ## Worker
+//!
+//! Each CPU drains its queue in a kernel thread of its own, `kworker`, which
+//! [`start_worker`] spawns and pins to the CPU. The worker runs with
+//! interrupts enabled and sleeps while the queue is empty; until it is
+//! started, queued items wait.
+//!
+//! Waking the worker takes the process table's lock, which whoever queues
+//! the item may be holding, e.g. while it maps a new process' kernel stack
+//! and the frame allocator runs low. Queueing therefore only tries the lock;
+//! if that fails, the next timer tick wakes the worker ([`wake_worker`]).
+//!
+//! Work items may take locks and wake processes, but should not block for
+//! long, as the items behind them wait. [`run_pending`] runs at most
+//! [`MAX_ITEMS_PER_RUN`] items at a time; a CPU going
+//! [offline](crate::hotplug) calls it until the queue is empty.
+//!
+//! ## Overflow
+//!
+//! A full queue rejects new items with [`WorkError::QueueFull`]; rejected
+//! items are counted in the queue's [`RingStats`]. Delayed items that find
+//! the queue full are retried on the next tick.
+
+use crate::per_cpu::PerCpu;
+use crate::process::{self, Pid, SpawnError};
+use crate::{sched, timer};
+use core::fmt;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use kernel_sync::IrqGuard;
+use kernel_sync::ring::{MpscRing, RingStats};
+
+/// Capacity of each CPU's work queue.
+pub const QUEUE_LEN: usize = 64;
+
+/// Delayed items armed at the same time.
+pub const MAX_DELAYED: usize = 16;
+
+/// Most items run per call of [`run_pending`].
+pub const MAX_ITEMS_PER_RUN: usize = 16;
+
+/// A function to run later, with its argument.
+#[derive(Debug, Copy, Clone)]
+pub struct Work {
+    func: fn(usize),
+    arg: usize,
+}
+
+impl Work {
+    pub const fn new(func: fn(usize), arg: usize) -> Self {
+        Self { func, arg }
+    }
+
+    fn run(self) {
+        (self.func)(self.arg);
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum WorkError {
+    /// The CPU's work queue is full.
+    QueueFull,
+    /// All delayed slots are in use.
+    NoDelayedSlot,
+    /// No timer could be armed for a delayed item.
+    NoTimer,
+}
+
+impl fmt::Display for WorkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::QueueFull => f.write_str("work queue full"),
+            Self::NoDelayedSlot => f.write_str("no free delayed work slot"),
+            Self::NoTimer => f.write_str("no free timer"),
+        }
+    }
+}
+
+/// A work item waiting for its timer.
+struct DelayedSlot {
+    in_use: AtomicBool,
+    /// The item's `fn(usize)`.
+    func: AtomicUsize,
+    arg: AtomicUsize,
+}
+
+impl DelayedSlot {
+    const fn new() -> Self {
+        Self {
+            in_use: AtomicBool::new(false),
+            func: AtomicUsize::new(0),
+            arg: AtomicUsize::new(0),
+        }
+    }
+}
+
+static DELAYED: [DelayedSlot; MAX_DELAYED] = [const { DelayedSlot::new() }; MAX_DELAYED];
+
+/// Work queue of one CPU.
+pub struct CpuWorkqueue {
+    /// Items ready to run.
+    ready: MpscRing<Work, QUEUE_LEN>,
+    /// PID of the worker; `0` until it is started.
+    worker: AtomicU32,
+}
+
+impl CpuWorkqueue {
+    pub const fn new() -> Self {
+        Self {
+            ready: MpscRing::new(),
+            worker: AtomicU32::new(0),
+        }
+    }
+
+    /// Wake the worker, unless the process table is locked.
+    fn wake(&self) {
+        if let Some(pid) = Pid::from_raw(u64::from(self.worker.load(Ordering::Acquire))) {
+            sched::try_wake(pid);
+        }
+    }
+}
+
+impl Default for CpuWorkqueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Queue `func(arg)` to run on this CPU outside of interrupt context.
+pub fn schedule_work(func: fn(usize), arg: usize) -> Result<(), WorkError> {
+    let cpu = unsafe { PerCpu::current() };
+    cpu.workqueue
+        .ready
+        .push(Work::new(func, arg))
+        .map_err(|_| WorkError::QueueFull)?;
+    cpu.workqueue.wake();
+    Ok(())
+}
+
+/// Queue `func(arg)` once `delay_ms` have passed.
+///
+/// The delay is rounded up to whole timer ticks.
+pub fn schedule_delayed_work(func: fn(usize), arg: usize, delay_ms: u64) -> Result<(), WorkError> {
+    let (index, slot) = DELAYED
+        .iter()
+        .enumerate()
+        .find(|(_, slot)| !slot.in_use.swap(true, Ordering::Acquire))
+        .ok_or(WorkError::NoDelayedSlot)?;
+
+    slot.func.store(func as usize, Ordering::Relaxed);
+    slot.arg.store(arg, Ordering::Relaxed);
+    let deadline = sched::now_ticks().saturating_add(timer::ms_to_ticks(delay_ms));
+    if timer::schedule(deadline, queue_delayed, index).is_none() {
+        slot.in_use.store(false, Ordering::Release);
+        return Err(WorkError::NoTimer);
+    }
+    Ok(())
+}
+
+/// Timer callback: queue the delayed item `index` on this CPU.
+fn queue_delayed(index: usize) {
+    let slot = &DELAYED[index];
+    // Safety: only ever set from a valid `fn(usize)` above.
+    let func: fn(usize) = unsafe { core::mem::transmute(slot.func.load(Ordering::Relaxed)) };
+    let work = Work::new(func, slot.arg.load(Ordering::Relaxed));
+
+    let cpu = unsafe { PerCpu::current() };
+    let queued = cpu.workqueue.ready.push(work).is_ok();
+    if queued || timer::schedule(sched::now_ticks() + 1, queue_delayed, index).is_none() {
+        slot.in_use.store(false, Ordering::Release);
+    }
+    if queued {
+        cpu.workqueue.wake();
+    }
+}
+
+/// Wake this CPU's worker if items are queued; called on every timer tick,
+/// in case queueing could not.
+pub fn wake_worker() {
+    let cpu = unsafe { PerCpu::current() };
+    if !cpu.workqueue.ready.is_empty() {
+        cpu.workqueue.wake();
+    }
+}
+
+/// Spawn the worker of this CPU.
+pub fn start_worker() -> Result<Pid, SpawnError> {
+    let cpu = unsafe { PerCpu::current() };
+    let pid = process::spawn_kernel_thread("kworker", worker, 0)?;
+    sched::set_affinity(pid, 1 << cpu.cpu_id);
+    cpu.workqueue.worker.store(pid.as_u32(), Ordering::Release);
+    Ok(pid)
+}
+
+/// Kernel thread: run the items queued on this CPU, sleeping while there
+/// are none.
+fn worker(_: usize) {
+    let me = sched::current_pid().expect("work queue worker without a current process");
+    let cpu = unsafe { PerCpu::current() };
+    loop {
+        while run_pending() > 0 {}
+
+        // Only this CPU queues here, so nothing arrives before we block.
+        let _irq = IrqGuard::new();
+        if cpu.workqueue.ready.is_empty() {
+            sched::block(me, None);
+            sched::schedule();
+        }
+    }
+}
+
+/// Run up to [`MAX_ITEMS_PER_RUN`] queued items of this CPU.
+///
+/// Returns the number of items run.
+pub fn run_pending() -> usize {
+    let cpu = unsafe { PerCpu::current() };
+    let mut ran = 0;
+    while ran < MAX_ITEMS_PER_RUN {
+        let Some(work) = cpu.workqueue.ready.pop() else {
+            break;
+        };
+        work.run();
+        ran += 1;
+    }
+    ran
+}
+
+/// Traffic through this CPU's work queue; `popped` counts the items run.
+#[allow(dead_code)]
+pub fn stats() -> RingStats {
+    let cpu = unsafe { PerCpu::current() };
+    cpu.workqueue.ready.stats()
+}
@@ -0,0 +1,653 @@
+//! # xHCI
+//!
+//! Driver of USB host controllers following the eXtensible Host Controller
+//! Interface, which serves USB devices of every speed on current machines;
+//! QEMU emulates one with `-device qemu-xhci`. Its purpose is a keyboard
+//! for machines without PS/2: it sets up the first HID boot keyboard it
+//! finds and nothing else.
+//!
+//! ## Bring-up
+//!
+//! [`init`] takes the first function with the xHCI class code and maps its
+//! registers from BAR 0. If the firmware still drives the controller for
+//! its USB legacy support, the driver asks for ownership through the
+//! legacy support capability and turns the firmware's SMIs off. The
+//! controller is reset and gets its device context base address array
+//! (DCBAA), the scratchpad buffers it asks for, the command [ring](ring)
+//! and an event ring; then it runs.
+//!
+//! ## Enumeration
+//!
+//! Each root hub port with a device connected is reset, which enables it.
+//! The device gets a slot and an address, and its descriptors are read
+//! over the default control endpoint; see [`device`]. The first device with
+//! a boot keyboard interface is configured: its interrupt IN endpoint gets a
+//! transfer ring, the interface is switched to the boot protocol, and a few
+//! reads are queued. Other devices get their slot disabled again.
+//!
+//! ## Reports
+//!
+//! Commands and transfers complete with events. While enumerating, the
+//! driver polls the event ring for them. Afterwards, the interrupter stays
+//! disabled and the timer tick runs the top half of the [threaded
+//! interrupt](crate::irq_thread) [`IRQ`], as for the
+//! [PS/2 keyboard](crate::keyboard): it copies each report off the event
+//! ring into a queue and queues the read again. The bottom half turns the
+//! reports into [scancodes](crate::keyboard::hid) for the keyboard decoder.
+//!
+//! ## Limitations
+//!
+//! * One controller and one keyboard, attached to a root hub port: no
+//!   hubs, no hot-plug, no other device classes.
+//! * A failed transfer stops the keyboard; endpoints are never reset.
+//! * Keyboard LEDs are not lit.
+
+mod device;
+mod ring;
+
+use crate::alloc::dma::{DmaBuffer, DmaConstraints, DmaError};
+use crate::alloc::mmio::{Mmio, map_mmio};
+use crate::irq_thread::{self, IrqReturn, ThreadedIrq};
+use crate::keyboard::{self, hid};
+use crate::pci::{self, Bar, COMMAND_BUS_MASTER, COMMAND_MEMORY_SPACE, PciFunction};
+use crate::sched::{self, now_ticks};
+use crate::timer;
+use core::fmt;
+use core::sync::atomic::{AtomicU8, Ordering};
+use device::Keyboard;
+use kernel_alloc::vmm::VmmError;
+use kernel_memory_addresses::{PageSize, Size4K};
+use kernel_sync::ring::MpscRing;
+use kernel_sync::{SpinMutex, SyncOnceCell};
+use log::{debug, info, trace, warn};
+use ring::{EventRing, Ring, TRB_COMMAND_COMPLETION, Trb};
+
+/// PCI class, subclass and programming interface of xHCI controllers.
+const CLASS: (u8, u8, u8) = (0x0C, 0x03, 0x30);
+
+/// Registers the driver maps. Common controllers keep all of them, and the
+/// extended capabilities, in the first 64 KiB; QEMU's BAR is 16 KiB.
+const REGISTERS_LEN: u64 = 0x1_0000;
+
+/// Capability register: length of the capability registers (8 bits).
+const CAPLENGTH: u64 = 0x00;
+/// Capability register: interface version (16 bits).
+const HCIVERSION: u64 = 0x02;
+/// Capability register: slots, interrupters and ports.
+const HCSPARAMS1: u64 = 0x04;
+/// Capability register: scratchpad buffers, among others.
+const HCSPARAMS2: u64 = 0x08;
+/// Capability register: addressing, context size, extended capabilities.
+const HCCPARAMS1: u64 = 0x10;
+/// Capability register: offset of the doorbells.
+const DBOFF: u64 = 0x14;
+/// Capability register: offset of the runtime registers.
+const RTSOFF: u64 = 0x18;
+
+/// `HCCPARAMS1`: 64-bit addressing.
+const HCC_AC64: u32 = 1 << 0;
+/// `HCCPARAMS1`: contexts are 64 bytes, not 32.
+const HCC_CSZ: u32 = 1 << 2;
+/// `HCCPARAMS1`: ports have power switches.
+const HCC_PPC: u32 = 1 << 3;
+
+/// Operational register: command.
+const USBCMD: u64 = 0x00;
+/// Operational register: status.
+const USBSTS: u64 = 0x04;
+/// Operational register: supported page sizes.
+const PAGESIZE: u64 = 0x08;
+/// Operational register: command ring control (64 bits).
+const CRCR: u64 = 0x18;
+/// Operational register: DCBAA pointer (64 bits).
+const DCBAAP: u64 = 0x30;
+/// Operational register: configure, with the number of enabled slots.
+const CONFIG: u64 = 0x38;
+/// Operational register: status and control of port 1; the others follow.
+const PORTSC: u64 = 0x400;
+/// Distance between the register sets of two ports.
+const PORT_STRIDE: u64 = 0x10;
+
+/// `USBCMD`: run.
+const USBCMD_RUN: u32 = 1 << 0;
+/// `USBCMD`: reset the controller.
+const USBCMD_HCRST: u32 = 1 << 1;
+
+/// `USBSTS`: halted.
+const USBSTS_HCH: u32 = 1 << 0;
+/// `USBSTS`: controller not ready.
+const USBSTS_CNR: u32 = 1 << 11;
+
+/// `PORTSC`: a device is connected.
+const PORTSC_CCS: u32 = 1 << 0;
+/// `PORTSC`: enabled; written 1, disables the port.
+const PORTSC_PED: u32 = 1 << 1;
+/// `PORTSC`: reset.
+const PORTSC_PR: u32 = 1 << 4;
+/// `PORTSC`: powered.
+const PORTSC_PP: u32 = 1 << 9;
+/// `PORTSC`: reset change.
+const PORTSC_PRC: u32 = 1 << 21;
+/// `PORTSC`: the change bits, cleared by writing 1.
+const PORTSC_CHANGES: u32 = 0x7F << 17;
+
+/// Runtime register: interrupter 0 management.
+const IMAN: u64 = 0x20;
+/// Runtime register: interrupter 0 event ring segment table size.
+const ERSTSZ: u64 = 0x28;
+/// Runtime register: interrupter 0 event ring segment table base (64 bits).
+const ERSTBA: u64 = 0x30;
+/// Runtime register: interrupter 0 event ring dequeue pointer (64 bits).
+const ERDP: u64 = 0x38;
+
+/// `IMAN`: interrupt pending, cleared by writing 1.
+const IMAN_IP: u32 = 1 << 0;
+/// `ERDP`: event handler busy, cleared by writing 1.
+const ERDP_EHB: u64 = 1 << 3;
+
+/// Extended capability: USB legacy support.
+const EXT_CAP_LEGACY: u32 = 1;
+/// USB legacy support byte: the firmware owns the controller.
+const LEGACY_BIOS_OWNED: u8 = 2;
+/// USB legacy support byte: the OS owns the controller.
+const LEGACY_OS_OWNED: u8 = 3;
+/// USB legacy control and status: the reserved bits, kept when disabling
+/// the SMIs.
+const LEGACY_SMI_RESERVED: u32 = 0x7 << 1 | 0xFF << 5 | 0x7 << 17;
+/// USB legacy control and status: SMI events, cleared by writing 1.
+const LEGACY_SMI_EVENTS: u32 = 0x7 << 29;
+/// Upper bound of extended capabilities walked.
+const MAX_EXT_CAPS: usize = 64;
+
+/// Time the firmware gets to hand the controller over.
+const HANDOFF_TIMEOUT_MS: u64 = 1000;
+/// Time the controller gets to halt or reset.
+const RESET_TIMEOUT_MS: u64 = 1000;
+/// Time a command or control transfer gets to complete.
+const COMMAND_TIMEOUT_MS: u64 = 1000;
+/// Time a port gets to finish its reset.
+const PORT_RESET_TIMEOUT_MS: u64 = 500;
+/// Time devices get to connect once their ports have power.
+const POWER_ON_MS: u64 = 20;
+/// Time a device gets to recover from a port reset before it is addressed.
+const RESET_RECOVERY_MS: u64 = 10;
+
+static CONTROLLER: SyncOnceCell<SpinMutex<Controller>> = SyncOnceCell::new();
+
+/// Reports taken off the event ring, for the bottom half.
+static REPORTS: MpscRing<[u8; hid::REPORT_LEN], 32> = MpscRing::new();
+
+/// Completion code of the transfer that stopped the keyboard; `0` if none
+/// did, or the bottom half reported it already.
+static TRANSFER_ERROR: AtomicU8 = AtomicU8::new(0);
+
+static BOOT_REPORTS: SpinMutex<hid::BootReports> = SpinMutex::new(hid::BootReports::new());
+
+/// Takes reports off the event ring in the timer tick, translates them in a
+/// thread.
+pub static IRQ: ThreadedIrq = ThreadedIrq::new("irq-xhci", drain_events, decode_reports);
+
+/// Why the controller or a device could not be set up.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum XhciError {
+    /// BAR 0 is not a memory range.
+    NoRegisters(PciFunction),
+    /// The registers could not be mapped.
+    Map(VmmError),
+    /// The controller lacks something the driver relies on, such as 4 KiB
+    /// pages.
+    Unsupported,
+    /// No memory for rings or contexts.
+    OutOfMemory,
+    /// The controller or a device did not answer in time.
+    Timeout,
+    /// A command failed with this completion code.
+    Command(u8),
+    /// A transfer failed with this completion code.
+    Transfer(u8),
+    /// The port did not come up enabled after its reset.
+    PortDisabled,
+    /// The device has no boot keyboard interface.
+    NotAKeyboard,
+}
+
+impl fmt::Display for XhciError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoRegisters(function) => write!(f, "{function}: BAR 0 is not a memory range"),
+            Self::Map(e) => write!(f, "failed to map the registers: {e}"),
+            Self::Unsupported => f.write_str("unsupported controller"),
+            Self::OutOfMemory => f.write_str("out of DMA memory"),
+            Self::Timeout => f.write_str("timed out"),
+            Self::Command(code) => write!(f, "command failed with completion code {code}"),
+            Self::Transfer(code) => write!(f, "transfer failed with completion code {code}"),
+            Self::PortDisabled => f.write_str("port not enabled after reset"),
+            Self::NotAKeyboard => f.write_str("not a boot keyboard"),
+        }
+    }
+}
+
+impl From<DmaError> for XhciError {
+    fn from(_: DmaError) -> Self {
+        Self::OutOfMemory
+    }
+}
+
+/// A running controller.
+struct Controller {
+    function: PciFunction,
+    regs: Mmio,
+    /// Offset of the operational registers.
+    operational: u64,
+    /// Offset of the runtime registers.
+    runtime: u64,
+    /// Offset of the doorbell of slot 0, the command ring's.
+    doorbells: u64,
+    ports: u8,
+    /// Size of a context: 32 or 64 bytes.
+    context_size: usize,
+    /// Where the controller can reach DMA buffers.
+    constraints: DmaConstraints,
+    dcbaa: DmaBuffer,
+    /// The scratchpad buffer array and the pages it lists.
+    _scratchpads: Option<(DmaBuffer, DmaBuffer)>,
+    commands: Ring,
+    events: EventRing,
+    keyboard: Option<Keyboard>,
+}
+
+impl Controller {
+    /// Take the controller from the firmware, reset it and run it.
+    #[allow(clippy::cast_possible_truncation)]
+    fn bring_up(function: PciFunction) -> Result<Self, XhciError> {
+        let Some(Bar::Memory(base)) = function.bar(0) else {
+            return Err(XhciError::NoRegisters(function));
+        };
+        function.enable(COMMAND_MEMORY_SPACE | COMMAND_BUS_MASTER);
+        let regs = map_mmio(base, REGISTERS_LEN).map_err(XhciError::Map)?;
+
+        let operational = u64::from(regs.read8(CAPLENGTH));
+        let params1 = regs.read32(HCSPARAMS1);
+        let params2 = regs.read32(HCSPARAMS2);
+        let capabilities = regs.read32(HCCPARAMS1);
+        let doorbells = u64::from(regs.read32(DBOFF) & !0b11);
+        let runtime = u64::from(regs.read32(RTSOFF) & !0x1F);
+        let slots = (params1 & 0xFF) as u8;
+        let ports = (params1 >> 24) as u8;
+        let in_range = operational + PORTSC + PORT_STRIDE * u64::from(ports) <= REGISTERS_LEN
+            && runtime + ERDP + 8 <= REGISTERS_LEN
+            && doorbells + 4 * (u64::from(slots) + 1) <= REGISTERS_LEN;
+        if !in_range || slots == 0 {
+            return Err(XhciError::Unsupported);
+        }
+
+        take_ownership(function, &regs, capabilities);
+
+        let regs = Self::reset(function, regs, operational)?;
+        // Bit 0: 4 KiB pages.
+        if regs.read32(operational + PAGESIZE) & 1 == 0 {
+            return Err(XhciError::Unsupported);
+        }
+
+        let constraints = if capabilities & HCC_AC64 == 0 {
+            DmaConstraints::BELOW_4G
+        } else {
+            DmaConstraints::ANY
+        };
+        let page = constraints.with_align(Size4K::SIZE);
+        let mut dcbaa = DmaBuffer::alloc((usize::from(slots) + 1) * 8, page)?;
+        let scratchpads = (params2 >> 27 & 0x1F) | (params2 >> 21 & 0x1F) << 5;
+        let scratchpads = if scratchpads == 0 {
+            None
+        } else {
+            let count = scratchpads as usize;
+            let mut array = DmaBuffer::alloc(count * 8, page)?;
+            let pages = DmaBuffer::alloc(count * Size4K::SIZE as usize, page)?;
+            for i in 0..count {
+                set_entry(
+                    &mut array,
+                    i,
+                    pages.phys_at(i * Size4K::SIZE as usize).as_u64(),
+                );
+            }
+            set_entry(&mut dcbaa, 0, array.phys().as_u64());
+            Some((array, pages))
+        };
+
+        let controller = Self {
+            function,
+            regs,
+            operational,
+            runtime,
+            doorbells,
+            ports,
+            context_size: if capabilities & HCC_CSZ == 0 { 32 } else { 64 },
+            constraints,
+            dcbaa,
+            _scratchpads: scratchpads,
+            commands: Ring::new(constraints)?,
+            events: EventRing::new(constraints)?,
+            keyboard: None,
+        };
+        if let Err(e) = controller.start(slots, capabilities) {
+            controller.halt();
+            return Err(e);
+        }
+        Ok(controller)
+    }
+
+    /// Halt and reset the controller; returns its registers.
+    fn reset(function: PciFunction, regs: Mmio, operational: u64) -> Result<Mmio, XhciError> {
+        let command = regs.read32(operational + USBCMD);
+        regs.write32(operational + USBCMD, command & !USBCMD_RUN);
+        wait_until(RESET_TIMEOUT_MS, || {
+            regs.read32(operational + USBSTS) & USBSTS_HCH != 0
+        })?;
+        regs.write32(operational + USBCMD, USBCMD_HCRST);
+        wait_until(RESET_TIMEOUT_MS, || {
+            regs.read32(operational + USBCMD) & USBCMD_HCRST == 0
+                && regs.read32(operational + USBSTS) & USBSTS_CNR == 0
+        })?;
+        debug!("xHCI controller at {function} reset");
+        Ok(regs)
+    }
+
+    /// Hand the controller its structures, run it and power the ports.
+    fn start(&self, slots: u8, capabilities: u32) -> Result<(), XhciError> {
+        let config = self.read_op(CONFIG);
+        self.write_op(CONFIG, (config & !0xFF) | u32::from(slots));
+        self.regs
+            .write64(self.operational + DCBAAP, self.dcbaa.phys().as_u64());
+        self.regs
+            .write64(self.operational + CRCR, self.commands.dequeue_pointer());
+
+        self.regs.write32(self.runtime + ERSTSZ, 1);
+        self.update_dequeue();
+        self.regs
+            .write64(self.runtime + ERSTBA, self.events.table_phys().as_u64());
+
+        self.write_op(USBCMD, self.read_op(USBCMD) | USBCMD_RUN);
+        wait_until(RESET_TIMEOUT_MS, || self.read_op(USBSTS) & USBSTS_HCH == 0)?;
+
+        if capabilities & HCC_PPC != 0 {
+            for port in 1..=self.ports {
+                let status = self.read_port(port) & !PORTSC_CHANGES;
+                self.write_port(port, status | PORTSC_PP);
+            }
+        }
+        sched::sleep(timer::ms_to_ticks(POWER_ON_MS));
+        Ok(())
+    }
+
+    /// Halt the controller, so it no longer touches its structures.
+    fn halt(&self) {
+        self.write_op(USBCMD, self.read_op(USBCMD) & !USBCMD_RUN);
+        if wait_until(RESET_TIMEOUT_MS, || self.read_op(USBSTS) & USBSTS_HCH != 0).is_err() {
+            warn!("xHCI controller at {} did not halt", self.function);
+        }
+    }
+
+    /// Set up the first boot keyboard connected to a root hub port.
+    fn find_keyboard(&mut self) -> Option<Keyboard> {
+        for port in 1..=self.ports {
+            if self.read_port(port) & PORTSC_CCS == 0 {
+                continue;
+            }
+            let keyboard = self
+                .reset_port(port)
+                .and_then(|speed| Keyboard::attach(self, port, speed));
+            match keyboard {
+                Ok(keyboard) => return Some(keyboard),
+                Err(e) => debug!("xHCI controller at {}, port {port}: {e}", self.function),
+            }
+        }
+        None
+    }
+
+    /// Reset `port`, which enables it; returns the speed of its device.
+    #[allow(clippy::cast_possible_truncation)]
+    fn reset_port(&self, port: u8) -> Result<u8, XhciError> {
+        // USB 3 ports enable themselves once the link is up.
+        if self.read_port(port) & PORTSC_PED == 0 {
+            let status = self.read_port(port) & !PORTSC_CHANGES;
+            self.write_port(port, status | PORTSC_PR);
+            wait_until(PORT_RESET_TIMEOUT_MS, || {
+                self.read_port(port) & PORTSC_PRC != 0
+            })?;
+            sched::sleep(timer::ms_to_ticks(RESET_RECOVERY_MS));
+        }
+        let status = self.read_port(port);
+        self.write_port(port, status | PORTSC_CHANGES);
+        if status & PORTSC_PED == 0 {
+            return Err(XhciError::PortDisabled);
+        }
+        Ok((status >> 10 & 0xF) as u8)
+    }
+
+    /// Run `command` and wait for its completion event.
+    fn command(&mut self, command: Trb) -> Result<Trb, XhciError> {
+        let address = self.commands.push(command).as_u64();
+        self.ring_doorbell(0, 0);
+        let event = self.wait_event(|event| {
+            event.kind() == TRB_COMMAND_COMPLETION && event.parameter == address
+        })?;
+        match event.completion_code() {
+            ring::COMPLETION_SUCCESS => Ok(event),
+            code => Err(XhciError::Command(code)),
+        }
+    }
+
+    /// Poll the event ring until an event `matches`, skipping others.
+    fn wait_event(&mut self, matches: impl Fn(&Trb) -> bool) -> Result<Trb, XhciError> {
+        let deadline = now_ticks() + timer::ms_to_ticks(COMMAND_TIMEOUT_MS);
+        loop {
+            while let Some(event) = self.events.pop() {
+                self.update_dequeue();
+                if matches(&event) {
+                    return Ok(event);
+                }
+                trace!("xHCI event of type {} skipped", event.kind());
+            }
+            if now_ticks() >= deadline {
+                return Err(XhciError::Timeout);
+            }
+            sched::sleep(1);
+        }
+    }
+
+    /// Tell the controller how far the event ring was read.
+    fn update_dequeue(&self) {
+        let dequeue = self.events.dequeue_phys().as_u64();
+        self.regs.write64(self.runtime + ERDP, dequeue | ERDP_EHB);
+        self.regs.write32(self.runtime + IMAN, IMAN_IP);
+    }
+
+    /// Have the controller look at the ring of `target` on `slot`: the
+    /// command ring for slot 0, else an endpoint's transfer ring.
+    fn ring_doorbell(&self, slot: u8, target: u8) {
+        self.regs
+            .write32(self.doorbells + 4 * u64::from(slot), u32::from(target));
+    }
+
+    /// Point the DCBAA entry of `slot` at its device context, or clear it.
+    fn set_device_context(&mut self, slot: u8, context: u64) {
+        set_entry(&mut self.dcbaa, usize::from(slot), context);
+    }
+
+    /// Top half: take the keyboard's reports off the event ring.
+    fn drain(&mut self) -> IrqReturn {
+        let mut ret = IrqReturn::None;
+        while let Some(event) = self.events.pop() {
+            ret = IrqReturn::Handled;
+            let Some(keyboard) = self.keyboard.as_mut() else {
+                continue;
+            };
+            match keyboard.complete(&event) {
+                Ok(Some(report)) => {
+                    // Dropped reports are accounted for in the ring
+                    // statistics.
+                    REPORTS.push(report).ok();
+                    let (slot, endpoint) = keyboard.doorbell();
+                    self.ring_doorbell(slot, endpoint);
+                }
+                Ok(None) => {}
+                Err(code) => {
+                    TRANSFER_ERROR.store(code, Ordering::Relaxed);
+                    ret = IrqReturn::WakeThread;
+                }
+            }
+        }
+        if ret == IrqReturn::None {
+            return ret;
+        }
+        self.update_dequeue();
+        if REPORTS.is_empty() {
+            ret
+        } else {
+            IrqReturn::WakeThread
+        }
+    }
+
+    fn read_op(&self, register: u64) -> u32 {
+        self.regs.read32(self.operational + register)
+    }
+
+    fn write_op(&self, register: u64, value: u32) {
+        self.regs.write32(self.operational + register, value);
+    }
+
+    fn read_port(&self, port: u8) -> u32 {
+        self.read_op(PORTSC + PORT_STRIDE * u64::from(port - 1))
+    }
+
+    /// Write `value` to the `PORTSC` of `port`. The enable bit is masked, as
+    /// writing it disables the port; change bits set in `value` are cleared.
+    fn write_port(&self, port: u8, value: u32) {
+        self.write_op(
+            PORTSC + PORT_STRIDE * u64::from(port - 1),
+            value & !PORTSC_PED,
+        );
+    }
+}
+
+/// Find and set up the first controller and its keyboard.
+pub fn init() {
+    let Some(function) = pci::find_class(CLASS.0, CLASS.1, CLASS.2) else {
+        debug!("No xHCI controller");
+        return;
+    };
+    let mut controller = match Controller::bring_up(function) {
+        Ok(controller) => controller,
+        Err(e) => {
+            warn!("xHCI controller at {function} not usable: {e}");
+            return;
+        }
+    };
+    let version = controller.regs.read16(HCIVERSION);
+    info!(
+        "xHCI controller at {function}: version {:x}.{:02x}, {} ports",
+        version >> 8,
+        version & 0xFF,
+        controller.ports
+    );
+
+    let Some(keyboard) = controller.find_keyboard() else {
+        info!("xHCI controller at {function}: no keyboard");
+        controller.halt();
+        return;
+    };
+    controller.keyboard = Some(keyboard);
+    CONTROLLER.get_or_init(|| SpinMutex::new(controller));
+    if let Err(e) = irq_thread::register(&IRQ) {
+        warn!("USB keyboard input will not be decoded: {e}");
+    }
+}
+
+/// Take pending reports off the event ring, and have them decoded.
+///
+/// Called from interrupt context.
+pub fn poll() {
+    IRQ.handle();
+}
+
+/// Top half: take reports off the event ring and queue the reads again.
+fn drain_events() -> IrqReturn {
+    let Some(controller) = CONTROLLER.get() else {
+        return IrqReturn::None;
+    };
+    // The tick of another CPU is at it.
+    let Some(mut controller) = controller.try_lock() else {
+        return IrqReturn::None;
+    };
+    controller.drain()
+}
+
+/// Bottom half: turn the reports into scancodes.
+fn decode_reports() {
+    match TRANSFER_ERROR.swap(0, Ordering::Relaxed) {
+        0 => {}
+        code => warn!("USB keyboard stopped: transfer failed with completion code {code}"),
+    }
+    let mut reports = BOOT_REPORTS.lock();
+    while let Some(report) = REPORTS.pop() {
+        reports.update(report, keyboard::queue_scancode);
+    }
+}
+
+/// Ask the firmware to hand over the controller, and stop its SMIs.
+fn take_ownership(function: PciFunction, regs: &Mmio, capabilities: u32) {
+    let Some(legacy) = extended_capability(regs, capabilities, EXT_CAP_LEGACY) else {
+        return;
+    };
+    if regs.read8(legacy + u64::from(LEGACY_BIOS_OWNED)) & 1 != 0 {
+        regs.write8(legacy + u64::from(LEGACY_OS_OWNED), 1);
+        let released = wait_until(HANDOFF_TIMEOUT_MS, || {
+            regs.read8(legacy + u64::from(LEGACY_BIOS_OWNED)) & 1 == 0
+        });
+        match released {
+            Ok(()) => debug!("xHCI controller at {function} taken over from the firmware"),
+            Err(_) => warn!("xHCI controller at {function}: the firmware did not let go"),
+        }
+    }
+    let control = regs.read32(legacy + 4);
+    regs.write32(
+        legacy + 4,
+        (control & LEGACY_SMI_RESERVED) | LEGACY_SMI_EVENTS,
+    );
+}
+
+/// Offset of the first extended capability with ID `id`, if there is one.
+fn extended_capability(regs: &Mmio, capabilities: u32, id: u32) -> Option<u64> {
+    let mut offset = u64::from(capabilities >> 16) * 4;
+    for _ in 0..MAX_EXT_CAPS {
+        if offset == 0 || offset + 8 > REGISTERS_LEN {
+            return None;
+        }
+        let header = regs.read32(offset);
+        if header & 0xFF == id {
+            return Some(offset);
+        }
+        match (header >> 8) & 0xFF {
+            0 => return None,
+            next => offset += u64::from(next) * 4,
+        }
+    }
+    None
+}
+
+/// Wait until `done` holds, sleeping a tick at a time.
+fn wait_until(timeout_ms: u64, mut done: impl FnMut() -> bool) -> Result<(), XhciError> {
+    let deadline = now_ticks() + timer::ms_to_ticks(timeout_ms);
+    while !done() {
+        if now_ticks() >= deadline {
+            return Err(XhciError::Timeout);
+        }
+        sched::sleep(1);
+    }
+    Ok(())
+}
+
+/// Set 64-bit entry `index` of a DCBAA or scratchpad buffer array.
+fn set_entry(array: &mut DmaBuffer, index: usize, value: u64) {
+    array[index * 8..][..8].copy_from_slice(&value.to_le_bytes());
+}
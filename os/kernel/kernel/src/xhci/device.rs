@@ -0,0 +1,466 @@
+//! # USB Devices
+//!
+//! A device on a freshly reset root hub port answers on address 0 until it
+//! is addressed, and only the 8 bytes of its first control packet are known
+//! to fit. [`Keyboard::attach`] walks it through the usual steps:
+//!
+//! 1. *Enable Slot* gives it a slot, and its device context goes into the
+//!    DCBAA. An input context describes the slot and the default control
+//!    endpoint; *Address Device* assigns the address.
+//! 2. The first 8 bytes of the device descriptor tell the real packet size
+//!    of the control endpoint; *Evaluate Context* corrects it if needed.
+//! 3. The configuration descriptor, with its interface and endpoint
+//!    descriptors, is searched for a boot keyboard interface and its
+//!    interrupt IN endpoint.
+//! 4. *Configure Endpoint* adds that endpoint, with a transfer ring of its
+//!    own. `SET_CONFIGURATION` activates the configuration and
+//!    `SET_PROTOCOL` switches the interface to boot reports.
+//!
+//! Then [`READS`] reads are queued, each into a buffer of its own; as the
+//! endpoint completes them in order, each completion is for the buffer after
+//! the previous one.
+
+use super::ring::{
+    COMPLETION_SHORT_PACKET, COMPLETION_SUCCESS, IMMEDIATE_DATA, INTERRUPT_ON_COMPLETION,
+    INTERRUPT_ON_SHORT_PACKET, Ring, TRB_ADDRESS_DEVICE, TRB_CONFIGURE_ENDPOINT, TRB_DATA,
+    TRB_DISABLE_SLOT, TRB_ENABLE_SLOT, TRB_EVALUATE_CONTEXT, TRB_NORMAL, TRB_SETUP, TRB_STATUS,
+    TRB_TRANSFER_EVENT, Trb,
+};
+use super::{Controller, XhciError};
+use crate::alloc::dma::DmaBuffer;
+use crate::keyboard::hid::REPORT_LEN;
+use kernel_memory_addresses::{PageSize, Size4K};
+use log::info;
+
+/// Port speed, as `PORTSC` and the slot context encode it: full speed.
+const SPEED_FULL: u8 = 1;
+/// Port speed: low speed.
+const SPEED_LOW: u8 = 2;
+/// Port speed: high speed.
+const SPEED_HIGH: u8 = 3;
+
+/// Endpoint context type: control.
+const EP_TYPE_CONTROL: u32 = 4;
+/// Endpoint context type: interrupt IN.
+const EP_TYPE_INTERRUPT_IN: u32 = 7;
+/// Endpoint context: errors tolerated before the endpoint halts.
+const EP_ERROR_COUNT: u32 = 3;
+
+/// Device context index of the default control endpoint.
+const CONTROL_ENDPOINT: u8 = 1;
+
+/// Setup packet request type: standard, to the device.
+const TO_DEVICE: u8 = 0x00;
+/// Setup packet request type: standard, from the device.
+const FROM_DEVICE: u8 = 0x80;
+/// Setup packet request type: class specific, to an interface.
+const TO_CLASS_INTERFACE: u8 = 0x21;
+
+/// Standard request: get descriptor.
+const GET_DESCRIPTOR: u8 = 0x06;
+/// Standard request: set configuration.
+const SET_CONFIGURATION: u8 = 0x09;
+/// HID class request: set protocol.
+const SET_PROTOCOL: u8 = 0x0B;
+/// `SET_PROTOCOL` value: boot protocol.
+const BOOT_PROTOCOL: u16 = 0;
+
+/// Descriptor type: device.
+const DESCRIPTOR_DEVICE: u8 = 1;
+/// Descriptor type: configuration.
+const DESCRIPTOR_CONFIGURATION: u8 = 2;
+/// Descriptor type: interface.
+const DESCRIPTOR_INTERFACE: u8 = 4;
+/// Descriptor type: endpoint.
+const DESCRIPTOR_ENDPOINT: u8 = 5;
+
+/// Length of a device descriptor.
+const DEVICE_DESCRIPTOR_LEN: u16 = 18;
+/// Length of a configuration descriptor, before the descriptors it
+/// includes.
+const CONFIGURATION_DESCRIPTOR_LEN: u16 = 9;
+
+/// Interface class, subclass and protocol of a HID boot keyboard.
+const BOOT_KEYBOARD: (u8, u8, u8) = (0x03, 0x01, 0x01);
+
+/// Reads kept queued on the interrupt endpoint.
+const READS: usize = 4;
+
+/// Bytes per read buffer: the largest packet of a low or full speed
+/// interrupt endpoint.
+const READ_LEN: usize = 64;
+
+/// Size of the read buffers, which hold the descriptors during set-up.
+const BUFFERS_LEN: usize = READS * READ_LEN;
+
+/// A USB request, as the setup stage of a control transfer sends it.
+#[derive(Debug, Copy, Clone)]
+struct Setup {
+    request_type: u8,
+    request: u8,
+    value: u16,
+    index: u16,
+    length: u16,
+}
+
+impl Setup {
+    /// Get descriptor `kind`, up to `length` bytes.
+    const fn descriptor(kind: u8, length: u16) -> Self {
+        Self {
+            request_type: FROM_DEVICE,
+            request: GET_DESCRIPTOR,
+            value: (kind as u16) << 8,
+            index: 0,
+            length,
+        }
+    }
+
+    /// The 8-byte setup packet.
+    const fn packet(self) -> u64 {
+        self.request_type as u64
+            | (self.request as u64) << 8
+            | (self.value as u64) << 16
+            | (self.index as u64) << 32
+            | (self.length as u64) << 48
+    }
+}
+
+/// What the configuration descriptor says about a boot keyboard.
+#[derive(Debug, Copy, Clone)]
+struct BootInterface {
+    configuration: u8,
+    interface: u8,
+    /// Number of the interrupt IN endpoint.
+    endpoint: u8,
+    max_packet: u16,
+    /// Polling interval, as the endpoint descriptor encodes it.
+    interval: u8,
+}
+
+/// The input and output device contexts of a slot.
+#[derive(Debug)]
+struct Contexts {
+    input: DmaBuffer,
+    output: DmaBuffer,
+    /// Size of a context: 32 or 64 bytes.
+    size: usize,
+}
+
+impl Contexts {
+    /// Set double word `dword` of input context `index`: `0` for the input
+    /// control context, `1` for the slot context, then the endpoints by
+    /// their device context index, plus one.
+    fn set(&mut self, index: usize, dword: usize, value: u32) {
+        let offset = index * self.size + dword * 4;
+        self.input[offset..][..4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    /// Set the transfer ring of endpoint context `index`, and its average
+    /// TRB length.
+    #[allow(clippy::cast_possible_truncation)]
+    fn set_ring(&mut self, index: usize, ring: &Ring, average_len: u32) {
+        let dequeue = ring.dequeue_pointer();
+        self.set(index, 2, dequeue as u32);
+        self.set(index, 3, (dequeue >> 32) as u32);
+        self.set(index, 4, average_len);
+    }
+}
+
+/// A configured boot keyboard; see the [module docs](self).
+#[derive(Debug)]
+pub struct Keyboard {
+    slot: u8,
+    /// Device context index of the interrupt IN endpoint.
+    endpoint: u8,
+    contexts: Contexts,
+    control: Ring,
+    reads: Ring,
+    /// The buffers of the reads, one after the other.
+    buffers: DmaBuffer,
+    /// Bytes per read.
+    read_len: u32,
+    /// The buffer of the read to complete next.
+    next: usize,
+}
+
+impl Keyboard {
+    /// Set up the device on `port`, reset at `speed`, if it is a boot
+    /// keyboard; its slot is disabled again if not.
+    pub fn attach(controller: &mut Controller, port: u8, speed: u8) -> Result<Self, XhciError> {
+        let page = controller.constraints.with_align(Size4K::SIZE);
+        #[allow(clippy::cast_possible_truncation)]
+        let context_len = Size4K::SIZE as usize;
+        let mut keyboard = Self {
+            slot: 0,
+            endpoint: 0,
+            contexts: Contexts {
+                input: DmaBuffer::alloc(context_len, page)?,
+                output: DmaBuffer::alloc(context_len, page)?,
+                size: controller.context_size,
+            },
+            control: Ring::new(controller.constraints)?,
+            reads: Ring::new(controller.constraints)?,
+            buffers: DmaBuffer::alloc(BUFFERS_LEN, page)?,
+            read_len: 0,
+            next: 0,
+        };
+        keyboard.slot = controller.command(Trb::new(TRB_ENABLE_SLOT))?.slot_id();
+        if let Err(e) = keyboard.set_up(controller, port, speed) {
+            // Stop the slot before its contexts and rings are freed.
+            controller
+                .command(Trb::new(TRB_DISABLE_SLOT).slot(keyboard.slot))
+                .ok();
+            controller.set_device_context(keyboard.slot, 0);
+            return Err(e);
+        }
+        Ok(keyboard)
+    }
+
+    fn set_up(
+        &mut self,
+        controller: &mut Controller,
+        port: u8,
+        speed: u8,
+    ) -> Result<(), XhciError> {
+        let slot = self.slot;
+        controller.set_device_context(slot, self.contexts.output.phys().as_u64());
+
+        // Slot and control endpoint, at the smallest packet size the speed
+        // allows.
+        let mut max_packet = match speed {
+            SPEED_FULL | SPEED_LOW => 8,
+            SPEED_HIGH => 64,
+            _ => 512,
+        };
+        self.contexts.set(0, 1, 0b11);
+        self.contexts.set(
+            1,
+            0,
+            u32::from(speed) << 20 | u32::from(CONTROL_ENDPOINT) << 27,
+        );
+        self.contexts.set(1, 1, u32::from(port) << 16);
+        self.contexts.set(2, 1, control_endpoint(max_packet));
+        self.contexts.set_ring(2, &self.control, 8);
+        let input = self.contexts.input.phys().as_u64();
+        controller.command(Trb::new(TRB_ADDRESS_DEVICE).parameter(input).slot(slot))?;
+
+        self.control(controller, Setup::descriptor(DESCRIPTOR_DEVICE, 8))?;
+        let size = u32::from(self.buffers[7]);
+        let size = if speed > SPEED_HIGH { 1 << size } else { size };
+        if size != max_packet {
+            max_packet = size;
+            self.contexts.set(0, 1, 0b10);
+            self.contexts.set(2, 1, control_endpoint(max_packet));
+            controller.command(Trb::new(TRB_EVALUATE_CONTEXT).parameter(input).slot(slot))?;
+        }
+
+        self.control(
+            controller,
+            Setup::descriptor(DESCRIPTOR_DEVICE, DEVICE_DESCRIPTOR_LEN),
+        )?;
+        let vendor = u16::from_le_bytes([self.buffers[8], self.buffers[9]]);
+        let product = u16::from_le_bytes([self.buffers[10], self.buffers[11]]);
+
+        self.control(
+            controller,
+            Setup::descriptor(DESCRIPTOR_CONFIGURATION, CONFIGURATION_DESCRIPTOR_LEN),
+        )?;
+        let total = u16::from_le_bytes([self.buffers[2], self.buffers[3]]);
+        #[allow(clippy::cast_possible_truncation)]
+        let total = total.min(BUFFERS_LEN as u16);
+        self.control(
+            controller,
+            Setup::descriptor(DESCRIPTOR_CONFIGURATION, total),
+        )?;
+        let boot = find_boot_interface(&self.buffers[..usize::from(total)])
+            .ok_or(XhciError::NotAKeyboard)?;
+
+        // The interrupt IN endpoint, the last one the slot uses.
+        self.endpoint = boot.endpoint * 2 + 1;
+        let index = usize::from(self.endpoint) + 1;
+        let max_packet = u32::from(boot.max_packet);
+        self.contexts.set(0, 0, 0);
+        self.contexts.set(0, 1, 1 | 1 << self.endpoint);
+        self.contexts.set(
+            1,
+            0,
+            u32::from(speed) << 20 | u32::from(self.endpoint) << 27,
+        );
+        self.contexts
+            .set(index, 0, interval(speed, boot.interval) << 16);
+        self.contexts.set(
+            index,
+            1,
+            EP_ERROR_COUNT << 1 | EP_TYPE_INTERRUPT_IN << 3 | max_packet << 16,
+        );
+        self.contexts
+            .set_ring(index, &self.reads, max_packet << 16 | max_packet);
+        controller.command(Trb::new(TRB_CONFIGURE_ENDPOINT).parameter(input).slot(slot))?;
+
+        self.control(
+            controller,
+            Setup {
+                request_type: TO_DEVICE,
+                request: SET_CONFIGURATION,
+                value: u16::from(boot.configuration),
+                index: 0,
+                length: 0,
+            },
+        )?;
+        self.control(
+            controller,
+            Setup {
+                request_type: TO_CLASS_INTERFACE,
+                request: SET_PROTOCOL,
+                value: BOOT_PROTOCOL,
+                index: u16::from(boot.interface),
+                length: 0,
+            },
+        )?;
+
+        #[allow(clippy::cast_possible_truncation)]
+        let read_len = (READ_LEN as u32).min(max_packet);
+        self.read_len = read_len;
+        for read in 0..READS {
+            self.queue_read(read);
+        }
+        controller.ring_doorbell(slot, self.endpoint);
+        info!(
+            "USB keyboard {vendor:04x}:{product:04x} on port {port} of the xHCI controller at {}",
+            controller.function
+        );
+        Ok(())
+    }
+
+    /// Run `setup` on the default control endpoint; data the device
+    /// sends lands at the start of the read buffers.
+    fn control(&mut self, controller: &mut Controller, setup: Setup) -> Result<(), XhciError> {
+        let data = setup.length > 0;
+        // Transfer type: IN data stage, or none.
+        let transfer_type = if data { 3 << 16 } else { 0 };
+        self.control.push(
+            Trb::new(TRB_SETUP)
+                .parameter(setup.packet())
+                .status(8)
+                .flags(IMMEDIATE_DATA | transfer_type),
+        );
+        if data {
+            self.control.push(
+                Trb::new(TRB_DATA)
+                    .parameter(self.buffers.phys().as_u64())
+                    .status(u32::from(setup.length))
+                    .flags(1 << 16),
+            );
+        }
+        // The status stage goes the other way: out after data in.
+        let direction = if data { 0 } else { 1 << 16 };
+        self.control
+            .push(Trb::new(TRB_STATUS).flags(INTERRUPT_ON_COMPLETION | direction));
+        controller.ring_doorbell(self.slot, CONTROL_ENDPOINT);
+
+        let slot = self.slot;
+        let event = controller.wait_event(|event| {
+            event.kind() == TRB_TRANSFER_EVENT
+                && event.slot_id() == slot
+                && event.endpoint_id() == CONTROL_ENDPOINT
+        })?;
+        match event.completion_code() {
+            COMPLETION_SUCCESS => Ok(()),
+            code => Err(XhciError::Transfer(code)),
+        }
+    }
+
+    /// Queue a read into buffer `read`, cleared first so a short report
+    /// reads as zeros.
+    fn queue_read(&mut self, read: usize) {
+        let offset = read * READ_LEN;
+        self.buffers[offset..][..READ_LEN].fill(0);
+        self.reads.push(
+            Trb::new(TRB_NORMAL)
+                .parameter(self.buffers.phys_at(offset).as_u64())
+                .status(self.read_len)
+                .flags(INTERRUPT_ON_COMPLETION | INTERRUPT_ON_SHORT_PACKET),
+        );
+    }
+
+    /// Take the report of a completed read, and queue the read again; the
+    /// doorbell is up to the caller, see [`doorbell`](Self::doorbell).
+    ///
+    /// Returns `None` for events of other endpoints, and the completion
+    /// code if the read failed, which halts the endpoint.
+    pub fn complete(&mut self, event: &Trb) -> Result<Option<[u8; REPORT_LEN]>, u8> {
+        if event.kind() != TRB_TRANSFER_EVENT
+            || event.slot_id() != self.slot
+            || event.endpoint_id() != self.endpoint
+        {
+            return Ok(None);
+        }
+        match event.completion_code() {
+            COMPLETION_SUCCESS | COMPLETION_SHORT_PACKET => {}
+            code => return Err(code),
+        }
+        let read = self.next;
+        let mut report = [0; REPORT_LEN];
+        report.copy_from_slice(&self.buffers[read * READ_LEN..][..REPORT_LEN]);
+        self.queue_read(read);
+        self.next = (read + 1) % READS;
+        Ok(Some(report))
+    }
+
+    /// Slot and target of the doorbell for the interrupt endpoint.
+    pub const fn doorbell(&self) -> (u8, u8) {
+        (self.slot, self.endpoint)
+    }
+}
+
+/// Endpoint context double word 1 of a control endpoint.
+const fn control_endpoint(max_packet: u32) -> u32 {
+    EP_ERROR_COUNT << 1 | EP_TYPE_CONTROL << 3 | max_packet << 16
+}
+
+/// The endpoint context interval, in 125 µs frames as a power of two, for
+/// the `interval` of an endpoint descriptor.
+fn interval(speed: u8, interval: u8) -> u32 {
+    match speed {
+        // Milliseconds.
+        SPEED_FULL | SPEED_LOW => (u32::from(interval.max(1)) * 8).ilog2().clamp(3, 10),
+        // Already a power of two, in frames, plus one.
+        _ => u32::from(interval.clamp(1, 16)) - 1,
+    }
+}
+
+/// The first boot keyboard interface in `config`, a configuration
+/// descriptor with the descriptors it includes, and its interrupt IN
+/// endpoint.
+fn find_boot_interface(config: &[u8]) -> Option<BootInterface> {
+    let configuration = *config.get(5)?;
+    let mut interface = None;
+    let mut offset = 0;
+    while let Some(&len) = config.get(offset) {
+        let len = usize::from(len);
+        let Some(descriptor) = config.get(offset..offset + len).filter(|_| len >= 2) else {
+            break;
+        };
+        match (descriptor[1], interface) {
+            (DESCRIPTOR_INTERFACE, _) if len >= 9 => {
+                let class = (descriptor[5], descriptor[6], descriptor[7]);
+                interface = (class == BOOT_KEYBOARD).then_some(descriptor[2]);
+            }
+            // Direction IN, transfer type interrupt.
+            (DESCRIPTOR_ENDPOINT, Some(interface))
+                if len >= 7 && descriptor[2] & 0x80 != 0 && descriptor[3] & 0b11 == 0b11 =>
+            {
+                return Some(BootInterface {
+                    configuration,
+                    interface,
+                    endpoint: descriptor[2] & 0xF,
+                    max_packet: u16::from_le_bytes([descriptor[4], descriptor[5]]) & 0x7FF,
+                    interval: descriptor[6],
+                });
+            }
+            _ => {}
+        }
+        offset += len;
+    }
+    None
+}
@@ -0,0 +1,299 @@
+//! # TRB Rings
+//!
+//! The controller and the driver talk through rings of 16-byte transfer
+//! request blocks (TRBs), each in a [`DmaBuffer`] of one page. Every TRB
+//! carries a cycle bit; whoever produces TRBs writes the ring's current
+//! cycle state into it last, so the consumer can tell new TRBs from old ones
+//! without a register read.
+//!
+//! * A [`Ring`] is produced by the driver and consumed by the controller:
+//!   the command ring and the transfer ring of each endpoint. Its last TRB
+//!   links back to the first and toggles the cycle state.
+//! * The [`EventRing`] is produced by the controller. It has no link TRB;
+//!   its single segment is listed in an event ring segment table, and the
+//!   driver flips its cycle state on every wrap itself.
+
+use crate::alloc::dma::{DmaBuffer, DmaConstraints, DmaError};
+use core::sync::atomic::{Ordering, fence};
+use kernel_memory_addresses::{PageSize, PhysicalAddress, Size4K};
+
+/// Size of a TRB.
+const TRB_SIZE: usize = 16;
+
+/// TRBs per ring: one page.
+#[allow(clippy::cast_possible_truncation)]
+const RING_LEN: usize = Size4K::SIZE as usize / TRB_SIZE;
+
+/// TRB type: normal, a transfer without a setup stage.
+pub const TRB_NORMAL: u32 = 1;
+/// TRB type: setup stage of a control transfer.
+pub const TRB_SETUP: u32 = 2;
+/// TRB type: data stage of a control transfer.
+pub const TRB_DATA: u32 = 3;
+/// TRB type: status stage of a control transfer.
+pub const TRB_STATUS: u32 = 4;
+/// TRB type: link to the next segment.
+const TRB_LINK: u32 = 6;
+/// TRB type: command to enable a device slot.
+pub const TRB_ENABLE_SLOT: u32 = 9;
+/// TRB type: command to disable a device slot.
+pub const TRB_DISABLE_SLOT: u32 = 10;
+/// TRB type: command to address a device.
+pub const TRB_ADDRESS_DEVICE: u32 = 11;
+/// TRB type: command to configure endpoints.
+pub const TRB_CONFIGURE_ENDPOINT: u32 = 12;
+/// TRB type: command to update the contexts of a slot.
+pub const TRB_EVALUATE_CONTEXT: u32 = 13;
+/// TRB type: transfer event.
+pub const TRB_TRANSFER_EVENT: u32 = 32;
+/// TRB type: command completion event.
+pub const TRB_COMMAND_COMPLETION: u32 = 33;
+
+/// TRB control bit: cycle.
+const CYCLE: u32 = 1 << 0;
+/// Link TRB control bit: toggle the cycle state when following the link.
+const TOGGLE_CYCLE: u32 = 1 << 1;
+/// Transfer TRB control bit: raise an event on a short packet.
+pub const INTERRUPT_ON_SHORT_PACKET: u32 = 1 << 2;
+/// Transfer TRB control bit: raise an event on completion.
+pub const INTERRUPT_ON_COMPLETION: u32 = 1 << 5;
+/// Transfer TRB control bit: the parameter holds the data itself.
+pub const IMMEDIATE_DATA: u32 = 1 << 6;
+
+/// Completion code: success.
+pub const COMPLETION_SUCCESS: u8 = 1;
+/// Completion code: the device sent less than asked for.
+pub const COMPLETION_SHORT_PACKET: u8 = 13;
+
+/// A transfer request block.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Trb {
+    pub parameter: u64,
+    pub status: u32,
+    /// Type, flags and, depending on the type, slot and endpoint; without
+    /// the cycle bit, which the ring owns.
+    pub control: u32,
+}
+
+impl Trb {
+    /// A TRB of type `kind`.
+    pub const fn new(kind: u32) -> Self {
+        Self {
+            parameter: 0,
+            status: 0,
+            control: kind << 10,
+        }
+    }
+
+    pub const fn parameter(mut self, parameter: u64) -> Self {
+        self.parameter = parameter;
+        self
+    }
+
+    pub const fn status(mut self, status: u32) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// Set `flags` in the control field, e.g. [`INTERRUPT_ON_COMPLETION`].
+    pub const fn flags(mut self, flags: u32) -> Self {
+        self.control |= flags;
+        self
+    }
+
+    /// Address the command or transfer to device slot `slot`.
+    pub const fn slot(self, slot: u8) -> Self {
+        self.flags((slot as u32) << 24)
+    }
+
+    pub const fn kind(&self) -> u32 {
+        (self.control >> 10) & 0x3F
+    }
+
+    /// Completion code of an event.
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn completion_code(&self) -> u8 {
+        (self.status >> 24) as u8
+    }
+
+    /// Slot of an event.
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn slot_id(&self) -> u8 {
+        (self.control >> 24) as u8
+    }
+
+    /// Endpoint (device context index) of a transfer event.
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn endpoint_id(&self) -> u8 {
+        ((self.control >> 16) & 0x1F) as u8
+    }
+}
+
+/// A ring the driver produces; see the [module docs](self).
+#[derive(Debug)]
+pub struct Ring {
+    buf: DmaBuffer,
+    /// Index of the next TRB to write.
+    enqueue: usize,
+    /// Cycle state: the cycle bit of TRBs owned by the controller.
+    cycle: bool,
+}
+
+impl Ring {
+    pub fn new(constraints: DmaConstraints) -> Result<Self, DmaError> {
+        let buf = DmaBuffer::alloc(RING_LEN * TRB_SIZE, constraints.with_align(Size4K::SIZE))?;
+        let ring = Self {
+            buf,
+            enqueue: 0,
+            cycle: true,
+        };
+        let link = Trb::new(TRB_LINK)
+            .parameter(ring.buf.phys().as_u64())
+            .flags(TOGGLE_CYCLE);
+        // Safety: the controller does not know the ring yet.
+        unsafe { write_trb(&ring.buf, RING_LEN - 1, link, false) };
+        Ok(ring)
+    }
+
+    /// The dequeue pointer to hand the controller: the start of the ring,
+    /// with the initial cycle state in bit 0.
+    pub const fn dequeue_pointer(&self) -> u64 {
+        self.buf.phys().as_u64() | 1
+    }
+
+    /// Append `trb`, handing it to the controller; returns its physical
+    /// address, as events refer to it.
+    ///
+    /// The controller only looks once its doorbell is rung.
+    pub fn push(&mut self, trb: Trb) -> PhysicalAddress {
+        let index = self.enqueue;
+        // Safety: the TRB at the enqueue index is owned by the driver.
+        unsafe { write_trb(&self.buf, index, trb, self.cycle) };
+        self.enqueue += 1;
+        if self.enqueue == RING_LEN - 1 {
+            // Hand over the link TRB too, and continue on the other side
+            // of the toggle.
+            let link = read_trb(&self.buf, RING_LEN - 1);
+            // Safety: as above; the link TRB is the next one in the ring.
+            unsafe { write_trb(&self.buf, RING_LEN - 1, link, self.cycle) };
+            self.enqueue = 0;
+            self.cycle = !self.cycle;
+        }
+        self.buf.phys_at(index * TRB_SIZE)
+    }
+}
+
+/// The ring the controller posts events to; see the [module docs](self).
+#[derive(Debug)]
+pub struct EventRing {
+    segment: DmaBuffer,
+    /// The event ring segment table, with the one entry for `segment`.
+    table: DmaBuffer,
+    /// Index of the next event to read.
+    dequeue: usize,
+    /// Cycle bit of new events.
+    cycle: bool,
+}
+
+impl EventRing {
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn new(constraints: DmaConstraints) -> Result<Self, DmaError> {
+        let page = constraints.with_align(Size4K::SIZE);
+        let segment = DmaBuffer::alloc(RING_LEN * TRB_SIZE, page)?;
+        let table = DmaBuffer::alloc(TRB_SIZE, page)?;
+        let entry = Trb {
+            parameter: segment.phys().as_u64(),
+            status: RING_LEN as u32,
+            control: 0,
+        };
+        // Safety: the controller does not know the table yet; entries have
+        // the layout of a TRB, and no cycle bit.
+        unsafe { write_trb(&table, 0, entry, false) };
+        Ok(Self {
+            segment,
+            table,
+            dequeue: 0,
+            cycle: true,
+        })
+    }
+
+    /// Physical address of the segment table, for `ERSTBA`.
+    pub const fn table_phys(&self) -> PhysicalAddress {
+        self.table.phys()
+    }
+
+    /// Physical address of the next event, for `ERDP`.
+    pub fn dequeue_phys(&self) -> PhysicalAddress {
+        self.segment.phys_at(self.dequeue * TRB_SIZE)
+    }
+
+    /// Take the next event, if the controller posted one.
+    ///
+    /// The controller learns about taken events when the driver writes
+    /// [`dequeue_phys`](Self::dequeue_phys) to `ERDP`.
+    pub fn pop(&mut self) -> Option<Trb> {
+        let event = read_trb(&self.segment, self.dequeue);
+        if (event.control & CYCLE != 0) != self.cycle {
+            return None;
+        }
+        fence(Ordering::Acquire);
+        // Read again: the controller writes the cycle bit last.
+        let event = read_trb(&self.segment, self.dequeue);
+        self.dequeue += 1;
+        if self.dequeue == RING_LEN {
+            self.dequeue = 0;
+            self.cycle = !self.cycle;
+        }
+        Some(Trb {
+            control: event.control & !CYCLE,
+            ..event
+        })
+    }
+}
+
+/// Write `trb` to slot `index` of `ring`, with the cycle bit `cycle`,
+/// publishing the control double word last.
+///
+/// # Safety
+/// The slot must be owned by the driver, i.e. not be one the controller
+/// may read.
+unsafe fn write_trb(ring: &DmaBuffer, index: usize, trb: Trb, cycle: bool) {
+    let dwords = dwords(ring, index);
+    #[allow(clippy::cast_possible_truncation)]
+    let values = [
+        trb.parameter as u32,
+        (trb.parameter >> 32) as u32,
+        trb.status,
+    ];
+    for (i, value) in values.into_iter().enumerate() {
+        // Safety: in bounds; the caller owns the slot.
+        unsafe { dwords.add(i).write_volatile(value) };
+    }
+    fence(Ordering::SeqCst);
+    let control = (trb.control & !CYCLE) | u32::from(cycle);
+    // Safety: as above.
+    unsafe { dwords.add(3).write_volatile(control) };
+}
+
+/// The TRB in slot `index` of `ring`, with its cycle bit.
+fn read_trb(ring: &DmaBuffer, index: usize) -> Trb {
+    let dwords = dwords(ring, index);
+    // Safety: in bounds; TRBs are read whole only after their cycle bit
+    // said they are complete.
+    let [low, high, status, control] =
+        [0, 1, 2, 3].map(|i| unsafe { dwords.add(i).read_volatile() });
+    Trb {
+        parameter: u64::from(high) << 32 | u64::from(low),
+        status,
+        control,
+    }
+}
+
+/// The double words of the TRB in slot `index`, through the HHDM.
+fn dwords(ring: &DmaBuffer, index: usize) -> *mut u32 {
+    debug_assert!(
+        (index + 1) * TRB_SIZE <= ring.len(),
+        "TRB index out of range"
+    );
+    (ring.virt().as_u64() + (index * TRB_SIZE) as u64) as *mut u32
+}
@@ -1,6 +1,14 @@
 #[doc(hidden)]
 #[macro_use]
 pub mod fmt;
+pub mod fb;
+pub mod io;
+pub mod poll;
+pub mod shm;
+pub mod signal;
+pub mod startup;
+pub mod time;
+pub mod tls;
 
 use crate::syscall::debug_byte;
 
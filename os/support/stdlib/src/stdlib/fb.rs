@@ -0,0 +1,91 @@
+//! Drawing to the screen through `/dev/fb0`.
+//!
+//! [`Framebuffer::open`] maps the kernel's back buffer into the process.
+//! Drawing into [`pixels`](Framebuffer::pixels) changes nothing on screen
+//! until the changed area is [presented](Framebuffer::present):
+//!
+//! ```ignore
+//! let mut fb = Framebuffer::open().unwrap();
+//! let red = fb.info().pack(0xFF, 0, 0);
+//! fb.pixels()[..100].fill(red);
+//! fb.present(Some(FbRect { x: 0, y: 0, width: 100, height: 1 }));
+//! ```
+//!
+//! The back buffer is shared with the kernel's console and every other
+//! process drawing to the screen. Dropping a [`Framebuffer`] closes the
+//! device; the mapping goes away with the process.
+
+use crate::syscall::{sys_close, sys_ioctl, sys_mmap, sys_open};
+use crate::syscall_abi::fb::{FB_GET_INFO, FB_PRESENT};
+use crate::syscall_abi::{FbInfo, FbRect, MAP_DEVICE, MAP_SHARED, PROT_READ, PROT_WRITE};
+
+/// Path of the framebuffer device.
+pub const PATH: &str = "/dev/fb0";
+
+/// The mapped back buffer of the screen.
+pub struct Framebuffer {
+    fd: u32,
+    info: FbInfo,
+    base: *mut u32,
+}
+
+impl Framebuffer {
+    /// Open [`PATH`] and map its back buffer readable and writable.
+    ///
+    /// Returns `None` if there is no framebuffer or it cannot be mapped.
+    #[must_use]
+    pub fn open() -> Option<Self> {
+        let fd = sys_open(PATH)?;
+        let mut info = FbInfo::default();
+        let base = sys_ioctl(fd, FB_GET_INFO, (&raw mut info) as u64).and_then(|_| {
+            sys_mmap(
+                info.len,
+                PROT_READ | PROT_WRITE,
+                MAP_SHARED | MAP_DEVICE,
+                u64::from(fd),
+                0,
+            )
+        });
+        let Some(base) = base else {
+            let _ = sys_close(fd);
+            return None;
+        };
+
+        Some(Self {
+            fd,
+            info,
+            base: base as *mut u32,
+        })
+    }
+
+    /// The size and pixel layout of the back buffer.
+    #[must_use]
+    pub const fn info(&self) -> &FbInfo {
+        &self.info
+    }
+
+    /// The pixels, row after row, [`stride`](FbInfo::stride) apart.
+    #[must_use]
+    pub const fn pixels(&mut self) -> &mut [u32] {
+        let len = self.info.stride as usize * self.info.height as usize;
+        // The mapping holds at least `len` pixels and lives as long as the
+        // process.
+        unsafe { core::slice::from_raw_parts_mut(self.base, len) }
+    }
+
+    /// Copy `rect` of the back buffer, or all of it for `None`, to the
+    /// screen; returns the number of pixels copied.
+    #[must_use]
+    pub fn present(&self, rect: Option<FbRect>) -> Option<u64> {
+        let arg = rect
+            .as_ref()
+            .map_or(0, |rect| core::ptr::from_ref(rect) as u64);
+        sys_ioctl(self.fd, FB_PRESENT, arg)
+    }
+}
+
+impl Drop for Framebuffer {
+    fn drop(&mut self) {
+        let _ = sys_close(self.fd);
+    }
+}
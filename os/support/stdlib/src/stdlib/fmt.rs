@@ -1,23 +1,70 @@
-use crate::syscall::debug_byte;
+use crate::syscall::sys_log;
+use crate::syscall_abi::{LogLevel, MAX_LOG_LEN};
 use core::fmt::{self, Write};
 
-pub struct SyscallSink;
+/// Collects formatted output and sends it to the kernel log one line at a
+/// time via [`sys_log`].
+///
+/// Lines longer than [`MAX_LOG_LEN`] bytes are split (at a character
+/// boundary) into several messages. Whatever is left without a trailing
+/// newline is sent by [`LogWriter::flush`].
+pub struct LogWriter {
+    level: LogLevel,
+    len: usize,
+    buf: [u8; MAX_LOG_LEN],
+}
 
-impl Write for SyscallSink {
-    #[inline]
-    fn write_str(&mut self, s: &str) -> fmt::Result {
-        for b in s.bytes() {
-            debug_byte(b);
+impl LogWriter {
+    #[must_use]
+    pub const fn new(level: LogLevel) -> Self {
+        Self {
+            level,
+            len: 0,
+            buf: [0; MAX_LOG_LEN],
         }
-        Ok(())
     }
 
-    #[inline]
-    fn write_char(&mut self, c: char) -> fmt::Result {
-        // UTF-8 encode without allocation.
-        let mut buf = [0u8; 4];
-        let s = c.encode_utf8(&mut buf);
-        self.write_str(s)
+    /// Send the buffered text, if any, as one message.
+    pub fn flush(&mut self) {
+        if self.len == 0 {
+            return;
+        }
+        // The buffer only ever receives whole characters.
+        if let Ok(msg) = core::str::from_utf8(&self.buf[..self.len]) {
+            let _ = sys_log(self.level, msg);
+        }
+        self.len = 0;
+    }
+
+    fn push(&mut self, mut s: &str) {
+        while !s.is_empty() {
+            let room = MAX_LOG_LEN - self.len;
+            let mut n = s.len().min(room);
+            while !s.is_char_boundary(n) {
+                n -= 1;
+            }
+            if n == 0 {
+                self.flush();
+                continue;
+            }
+            self.buf[self.len..self.len + n].copy_from_slice(&s.as_bytes()[..n]);
+            self.len += n;
+            s = &s[n..];
+        }
+    }
+}
+
+impl Write for LogWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut lines = s.split('\n');
+        if let Some(first) = lines.next() {
+            self.push(first);
+        }
+        for line in lines {
+            self.flush();
+            self.push(line);
+        }
+        Ok(())
     }
 }
 
@@ -25,10 +72,16 @@ impl Write for SyscallSink {
 #[inline(always)]
 #[allow(clippy::inline_always)]
 pub fn syscall_write(args: fmt::Arguments) {
-    // Ignore errors; this is best-effort debug output.
-    fmt::write(&mut SyscallSink, args).ok();
+    let mut writer = LogWriter::new(LogLevel::Info);
+    // Ignore errors; this is best-effort output.
+    fmt::write(&mut writer, args).ok();
+    writer.flush();
 }
 
+/// Print to the kernel log.
+///
+/// Every call ends the current log message, so text printed without a
+/// newline still shows up as a line of its own.
 #[macro_export]
 macro_rules! print {
     ($($arg:tt)*) => {{
@@ -36,10 +89,10 @@ macro_rules! print {
     }};
 }
 
+/// Print a line to the kernel log.
 #[macro_export]
 macro_rules! println {
     ($($arg:tt)*) => {{
         $crate::stdlib::fmt::syscall_write(core::format_args!($($arg)*));
-        $crate::syscall::debug_byte(b'\n');
     }};
 }
@@ -0,0 +1,71 @@
+//! Standard streams.
+//!
+//! A spawned process inherits the open files of its parent, so by
+//! convention descriptors [`STDIN`], [`STDOUT`] and [`STDERR`] are already
+//! open when `main` runs; `init` opens `/dev/console` as all three for the
+//! shell. Unlike [`println!`](crate::println), which goes to the kernel log,
+//! [`Output`] writes to such a stream:
+//!
+//! ```ignore
+//! use core::fmt::Write;
+//!
+//! let _ = writeln!(io::stdout(), "Hello, console!");
+//! ```
+
+use crate::syscall::{sys_read, sys_write};
+use core::fmt;
+
+/// Descriptor of the standard input.
+pub const STDIN: u32 = 0;
+/// Descriptor of the standard output.
+pub const STDOUT: u32 = 1;
+/// Descriptor of the standard error output.
+pub const STDERR: u32 = 2;
+
+/// A [`fmt::Write`] sink writing to a file descriptor.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Output(pub u32);
+
+impl Output {
+    /// Write all of `bytes`, blocking while the file cannot take them.
+    ///
+    /// # Errors
+    /// If the descriptor is not open for writing or stops taking bytes.
+    pub fn write_all(self, mut bytes: &[u8]) -> fmt::Result {
+        while !bytes.is_empty() {
+            match sys_write(self.0, bytes) {
+                Some(n @ 1..) => bytes = &bytes[n..],
+                _ => return Err(fmt::Error),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Write for Output {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_all(s.as_bytes())
+    }
+}
+
+/// The standard output.
+#[must_use]
+pub const fn stdout() -> Output {
+    Output(STDOUT)
+}
+
+/// The standard error output.
+#[must_use]
+pub const fn stderr() -> Output {
+    Output(STDERR)
+}
+
+/// Read from the standard input into `buf`, blocking until data is
+/// available; from a terminal in canonical mode, this is at most one line.
+///
+/// Returns the number of bytes read, `Some(0)` at end of file, or `None` if
+/// the standard input is not open.
+#[must_use]
+pub fn read_stdin(buf: &mut [u8]) -> Option<usize> {
+    sys_read(STDIN, buf)
+}
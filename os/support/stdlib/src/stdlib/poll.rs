@@ -0,0 +1,48 @@
+//! Waiting for several handles at once.
+//!
+//! [`poll`] blocks until one of a set of handles is ready, e.g. until either
+//! the standard input or a pipe has data to read, or a timeout passes:
+//!
+//! ```ignore
+//! let mut fds = [PollFd::new(io::STDIN, POLLIN), PollFd::new(pipe, POLLIN)];
+//! if poll(&mut fds, Some(Duration::from_secs(1))) == Some(0) {
+//!     // timed out
+//! }
+//! if fds[0].revents & POLLIN != 0 {
+//!     // a read from stdin does not block
+//! }
+//! ```
+
+use crate::syscall::sys_poll;
+use core::time::Duration;
+
+pub use crate::syscall_abi::PollFd;
+pub use crate::syscall_abi::poll::{FOREVER, POLLERR, POLLHUP, POLLIN, POLLOUT};
+
+impl PollFd {
+    /// Wait for `events` on `handle`.
+    #[must_use]
+    pub const fn new(handle: u32, events: u16) -> Self {
+        Self {
+            handle,
+            events,
+            revents: 0,
+        }
+    }
+}
+
+/// Wait until one of `fds` is ready for its `events`, at most `timeout`
+/// (rounded up to milliseconds) or forever for `None`; each `revents` then
+/// holds the ready events.
+///
+/// Returns how many handles are ready, `Some(0)` if the timeout passed or a
+/// signal interrupted the wait, or `None` if a handle is not open or lacks
+/// the rights for its events.
+#[must_use]
+pub fn poll(fds: &mut [PollFd], timeout: Option<Duration>) -> Option<usize> {
+    let timeout_ms = timeout.map_or(FOREVER, |timeout| {
+        let ms = timeout.as_nanos().div_ceil(1_000_000);
+        u64::try_from(ms).unwrap_or(FOREVER - 1).min(FOREVER - 1)
+    });
+    sys_poll(fds, timeout_ms)
+}
@@ -0,0 +1,163 @@
+//! Shared memory between processes.
+//!
+//! [`SharedMemory::open`] opens (or creates) a named kernel shared memory
+//! object and maps all of it into the process. Every process opening the
+//! same name sees the same physical pages, so writes by one are immediately
+//! visible to the others:
+//!
+//! ```ignore
+//! let shm = SharedMemory::open("/greetings", 4096).unwrap();
+//! let ring = ShmRing::new(&shm);
+//! ring.push(b"hello\n");
+//! ```
+//!
+//! The object lives as long as a process holds a handle to it or has it
+//! mapped; dropping a [`SharedMemory`] closes the handle, and the mapping goes
+//! away with the process.
+//!
+//! ## Ring buffer
+//!
+//! [`ShmRing`] lays a single-producer, single-consumer byte queue over a
+//! region: a header with the read and write positions followed by the data.
+//! The positions are atomics, so the producer and consumer may run in
+//! different processes at the same time. A new object is zero-filled, which
+//! is a valid empty ring.
+
+use crate::syscall::{sys_mmap, sys_shm_close, sys_shm_open};
+use crate::syscall_abi::{MAP_SHARED, PROT_READ, PROT_WRITE};
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// A mapped shared memory object.
+pub struct SharedMemory {
+    handle: u64,
+    base: *mut u8,
+    len: usize,
+}
+
+impl SharedMemory {
+    /// Open the object `name`, creating it with `len` bytes if it does not
+    /// exist yet, and map it readable and writable.
+    ///
+    /// Returns `None` if the object cannot be opened or mapped.
+    #[must_use]
+    pub fn open(name: &str, len: usize) -> Option<Self> {
+        let handle = sys_shm_open(name, len as u64)?;
+        let Some(base) = sys_mmap(len as u64, PROT_READ | PROT_WRITE, MAP_SHARED, handle, 0) else {
+            let _ = sys_shm_close(handle);
+            return None;
+        };
+
+        Some(Self {
+            handle,
+            base: base as *mut u8,
+            len,
+        })
+    }
+
+    /// Address of the mapping.
+    #[must_use]
+    pub const fn as_ptr(&self) -> *mut u8 {
+        self.base
+    }
+
+    /// Size of the mapping in bytes.
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether the mapping is empty (never true for an open object).
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Drop for SharedMemory {
+    fn drop(&mut self) {
+        let _ = sys_shm_close(self.handle);
+    }
+}
+
+/// Read and write positions at the start of a [`ShmRing`] region.
+#[repr(C, align(64))]
+struct RingHeader {
+    /// Next byte to read, in `0..capacity`.
+    head: AtomicU32,
+    /// Next byte to write, in `0..capacity`.
+    tail: AtomicU32,
+}
+
+/// A single-producer, single-consumer byte queue in shared memory.
+///
+/// One slot is kept free to tell a full ring from an empty one, so the ring
+/// holds up to `capacity - 1` bytes.
+pub struct ShmRing<'a> {
+    header: &'a RingHeader,
+    data: *mut u8,
+    capacity: u32,
+}
+
+impl<'a> ShmRing<'a> {
+    /// View the region of `shm` as a ring.
+    ///
+    /// # Panics
+    /// If the region is too small to hold the header and any data.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation, clippy::cast_ptr_alignment)]
+    pub fn new(shm: &'a SharedMemory) -> Self {
+        let header_len = size_of::<RingHeader>();
+        assert!(shm.len() > header_len + 1, "region too small for a ring");
+        let capacity = (shm.len() - header_len).min(u32::MAX as usize) as u32;
+
+        // The mapping is page aligned and lives as long as `shm`.
+        let header = unsafe { &*shm.as_ptr().cast::<RingHeader>() };
+        let data = unsafe { shm.as_ptr().add(header_len) };
+        Self {
+            header,
+            data,
+            capacity,
+        }
+    }
+
+    /// Append as much of `bytes` as fits; returns the number of bytes queued.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn push(&self, bytes: &[u8]) -> usize {
+        let head = self.header.head.load(Ordering::Acquire);
+        let mut tail = self.header.tail.load(Ordering::Relaxed);
+
+        let mut written = 0;
+        for &b in bytes {
+            let next = (tail + 1) % self.capacity;
+            if next == head {
+                break;
+            }
+            unsafe { self.data.add(tail as usize).write_volatile(b) };
+            tail = next;
+            written += 1;
+        }
+
+        self.header.tail.store(tail, Ordering::Release);
+        written
+    }
+
+    /// Move queued bytes into `buf`; returns the number of bytes read.
+    pub fn pop(&self, buf: &mut [u8]) -> usize {
+        let tail = self.header.tail.load(Ordering::Acquire);
+        let mut head = self.header.head.load(Ordering::Relaxed);
+
+        let mut read = 0;
+        for slot in buf {
+            if head == tail {
+                break;
+            }
+            *slot = unsafe { self.data.add(head as usize).read_volatile() };
+            head = (head + 1) % self.capacity;
+            read += 1;
+        }
+
+        self.header.head.store(head, Ordering::Release);
+        read
+    }
+}
@@ -0,0 +1,62 @@
+//! Signal handlers.
+//!
+//! [`set_handler`] makes the kernel run a function when a signal arrives:
+//!
+//! ```ignore
+//! extern "C" fn on_usr1(signo: u32, _context: &mut SignalContext) {
+//!     println!("Got signal {signo}");
+//! }
+//!
+//! signal::set_handler(SIGUSR1, on_usr1);
+//! ```
+//!
+//! The handler runs on the interrupted stack and returns through [`restore`],
+//! which resumes the interrupted code with the (possibly modified) context.
+//! Signals arriving while a handler runs stay pending until it returns.
+
+use crate::syscall::sys_sigaction;
+use crate::syscall_abi::signal::{SIG_DFL, SIG_IGN};
+use crate::syscall_abi::{SignalContext, Sysno};
+
+/// A signal handler, called with the signal number and the interrupted
+/// context.
+pub type Handler = extern "C" fn(signo: u32, context: &mut SignalContext);
+
+/// Run `handler` when `signo` arrives.
+///
+/// Returns the previous handler value, or `None` if `signo` cannot be caught.
+#[must_use]
+pub fn set_handler(signo: u32, handler: Handler) -> Option<u64> {
+    let restorer: extern "C" fn() -> ! = restore;
+    sys_sigaction(signo, handler as usize as u64, restorer as usize as u64)
+}
+
+/// Discard `signo` from now on.
+///
+/// Returns the previous handler value, or `None` if `signo` cannot be ignored.
+#[must_use]
+pub fn ignore(signo: u32) -> Option<u64> {
+    sys_sigaction(signo, SIG_IGN, 0)
+}
+
+/// Take the default action for `signo` again.
+///
+/// Returns the previous handler value, or `None` if `signo` is out of range.
+#[must_use]
+pub fn reset(signo: u32) -> Option<u64> {
+    sys_sigaction(signo, SIG_DFL, 0)
+}
+
+/// Return from a signal handler: the handler's return address.
+///
+/// The stack pointer points at the saved [`SignalContext`], which the
+/// `sigreturn` system call resumes.
+#[unsafe(naked)]
+extern "C" fn restore() -> ! {
+    core::arch::naked_asm!(
+        "mov eax, {sigreturn}",
+        "syscall",
+        "ud2",
+        sigreturn = const Sysno::SigReturn as u32,
+    )
+}
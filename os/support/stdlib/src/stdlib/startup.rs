@@ -0,0 +1,170 @@
+//! Program startup: arguments, environment and auxiliary vector.
+//!
+//! The kernel starts every process with a System V style initial stack:
+//!
+//! ```text
+//!   rsp ─► argc
+//!          argv[0] … argv[argc - 1], NULL
+//!          envp[0] … envp[n - 1],    NULL
+//!          (key, value) auxv pairs,  (AT_NULL, 0)
+//!          … strings and AT_RANDOM bytes …
+//! ```
+//!
+//! Use [`entry!`](crate::entry) to define `_start` and receive a parsed
+//! [`Startup`] in `main`:
+//!
+//! ```ignore
+//! stdlib::entry!(main);
+//!
+//! fn main(startup: &stdlib::startup::Startup) -> u32 {
+//!     for arg in startup.args() {
+//!         stdlib::println!("{arg}");
+//!     }
+//!     0
+//! }
+//! ```
+
+use crate::syscall_abi::auxv;
+use core::ffi::{CStr, c_char};
+
+/// View of the initial process stack.
+#[derive(Copy, Clone)]
+pub struct Startup {
+    argc: usize,
+    argv: *const *const c_char,
+    envp: *const *const c_char,
+    auxv: *const [u64; 2],
+}
+
+impl Startup {
+    /// Parse the initial stack whose `argc` slot is at `sp`.
+    ///
+    /// # Safety
+    /// `sp` must be the stack pointer the process was entered with, and the
+    /// stack must stay untouched below it for as long as this value is used.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub const unsafe fn from_stack(sp: *const u64) -> Self {
+        unsafe {
+            let count = *sp as usize;
+            let args = sp.add(1).cast::<*const c_char>();
+            let envp = args.add(count + 1);
+
+            let mut end = envp;
+            while !(*end).is_null() {
+                end = end.add(1);
+            }
+            let auxv = end.add(1).cast::<[u64; 2]>();
+
+            Self {
+                argc: count,
+                argv: args,
+                envp,
+                auxv,
+            }
+        }
+    }
+
+    /// Number of arguments, including the program path.
+    #[must_use]
+    pub const fn argc(&self) -> usize {
+        self.argc
+    }
+
+    /// The arguments, starting with the program path.
+    #[must_use]
+    pub const fn args(&self) -> StrVec {
+        StrVec { next: self.argv }
+    }
+
+    /// The environment as `KEY=value` strings.
+    #[must_use]
+    pub const fn env(&self) -> StrVec {
+        StrVec { next: self.envp }
+    }
+
+    /// Value of the environment variable `key`, if set.
+    #[must_use]
+    pub fn env_var(&self, key: &str) -> Option<&'static str> {
+        self.env().find_map(|var| {
+            var.strip_prefix(key)
+                .and_then(|rest| rest.strip_prefix('='))
+        })
+    }
+
+    /// Value of the auxiliary vector entry `key` (see [`auxv`]).
+    #[must_use]
+    pub const fn aux(&self, key: u64) -> Option<u64> {
+        let mut entry = self.auxv;
+        loop {
+            let [k, v] = unsafe { *entry };
+            if k == auxv::AT_NULL {
+                return None;
+            }
+            if k == key {
+                return Some(v);
+            }
+            entry = unsafe { entry.add(1) };
+        }
+    }
+
+    /// The system page size.
+    #[must_use]
+    pub const fn page_size(&self) -> Option<u64> {
+        self.aux(auxv::AT_PAGESZ)
+    }
+
+    /// Sixteen random bytes provided by the kernel.
+    #[must_use]
+    pub fn random_bytes(&self) -> Option<&'static [u8; 16]> {
+        self.aux(auxv::AT_RANDOM)
+            .map(|addr| unsafe { &*(addr as *const [u8; 16]) })
+    }
+}
+
+/// Iterator over a NULL-terminated array of C strings.
+///
+/// Strings that are not valid UTF-8 are yielded as `""`.
+pub struct StrVec {
+    next: *const *const c_char,
+}
+
+impl Iterator for StrVec {
+    type Item = &'static str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let ptr = unsafe { *self.next };
+        if ptr.is_null() {
+            return None;
+        }
+
+        self.next = unsafe { self.next.add(1) };
+        Some(unsafe { CStr::from_ptr(ptr) }.to_str().unwrap_or_default())
+    }
+}
+
+/// Define the program entry point `_start`, which parses the initial stack,
+/// calls `$main(&Startup) -> u32` and exits with its return value.
+#[macro_export]
+macro_rules! entry {
+    ($main:path) => {
+        extern "C" fn __stdlib_start(sp: *const u64) -> ! {
+            let startup = unsafe { $crate::startup::Startup::from_stack(sp) };
+            let code: u32 = $main(&startup);
+            $crate::syscall::sys_exit(code)
+        }
+
+        #[unsafe(no_mangle)]
+        #[unsafe(naked)]
+        extern "C" fn _start() -> ! {
+            core::arch::naked_asm!(
+                "xor ebp, ebp",
+                "mov rdi, rsp",
+                "and rsp, -16",
+                "call {start}",
+                "ud2",
+                start = sym __stdlib_start,
+            )
+        }
+    };
+}
@@ -0,0 +1,159 @@
+//! Clocks, read without a system call.
+//!
+//! The kernel maps its clock parameters into every process as the
+//! [`ClockPage`] at [`CLOCK_PAGE_ADDR`]. [`clock_gettime`] reads the TSC and
+//! converts it with them, retrying while the kernel updates the page:
+//!
+//! ```ignore
+//! if let Some(now) = time::clock_gettime(ClockId::Realtime) {
+//!     let _ = writeln!(io::stdout(), "{} s since the epoch", now.secs);
+//! }
+//! ```
+//!
+//! The clocks are as good as the kernel's TSC calibration; on CPUs without an
+//! invariant TSC they may drift.
+//!
+//! [`sleep`] and [`sleep_until`] block in the kernel instead; they never end
+//! early, and at most a timer tick late.
+
+use crate::syscall::{sys_clock_nanosleep, sys_nanosleep};
+use crate::syscall_abi::{CLOCK_PAGE_ADDR, ClockPage};
+use core::sync::atomic::{Ordering, fence};
+use core::time::Duration;
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+/// A clock to read with [`clock_gettime`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ClockId {
+    /// Wall clock time since the Unix epoch.
+    Realtime,
+    /// Time since an arbitrary point before boot; never goes backwards.
+    Monotonic,
+}
+
+/// A point in time, as seconds and nanoseconds.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, Ord, PartialOrd)]
+pub struct Timespec {
+    pub secs: u64,
+    /// Nanoseconds within the second, below `1_000_000_000`.
+    pub nanos: u32,
+}
+
+impl Timespec {
+    #[allow(clippy::cast_possible_truncation)]
+    const fn from_ticks(ticks: u64, hz: u64) -> Self {
+        Self {
+            secs: ticks / hz,
+            nanos: ((ticks % hz) as u128 * NANOS_PER_SEC as u128 / hz as u128) as u32,
+        }
+    }
+}
+
+/// The current time of `clock`.
+///
+/// Returns `None` before the kernel calibrated the TSC, and for
+/// [`ClockId::Realtime`] before it set the wall clock.
+#[must_use]
+pub fn clock_gettime(clock: ClockId) -> Option<Timespec> {
+    let snapshot = Snapshot::read();
+    if snapshot.tsc_hz == 0 {
+        return None;
+    }
+    match clock {
+        ClockId::Monotonic => Some(Timespec::from_ticks(snapshot.tsc, snapshot.tsc_hz)),
+        ClockId::Realtime if snapshot.wall_secs == 0 => None,
+        ClockId::Realtime => {
+            let since = Timespec::from_ticks(
+                snapshot.tsc.saturating_sub(snapshot.wall_tsc),
+                snapshot.tsc_hz,
+            );
+            Some(Timespec {
+                secs: snapshot.wall_secs + since.secs,
+                nanos: since.nanos,
+            })
+        }
+    }
+}
+
+/// Sleep for at least `duration`, resuming after signal handlers.
+///
+/// Returns `false` if the kernel cannot sleep yet because its clock is not
+/// calibrated.
+#[must_use]
+pub fn sleep(duration: Duration) -> bool {
+    let mut ns = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+    while ns > 0 {
+        match sys_nanosleep(ns) {
+            Some(left) => ns = left,
+            None => return false,
+        }
+    }
+    true
+}
+
+/// Sleep until the [`ClockId::Monotonic`] clock reads at least `deadline`,
+/// resuming after signal handlers.
+///
+/// Returns `false` if the kernel cannot sleep yet because its clock is not
+/// calibrated.
+#[must_use]
+pub fn sleep_until(deadline: Timespec) -> bool {
+    let ns = deadline
+        .secs
+        .saturating_mul(NANOS_PER_SEC)
+        .saturating_add(u64::from(deadline.nanos));
+    loop {
+        match sys_clock_nanosleep(ns) {
+            Some(0) => return true,
+            Some(_) => {}
+            None => return false,
+        }
+    }
+}
+
+/// The clock page fields and the TSC, read consistently.
+struct Snapshot {
+    tsc: u64,
+    tsc_hz: u64,
+    wall_secs: u64,
+    wall_tsc: u64,
+}
+
+impl Snapshot {
+    fn read() -> Self {
+        // The kernel maps the page into every process before it starts.
+        let page = unsafe { &*(CLOCK_PAGE_ADDR as *const ClockPage) };
+        loop {
+            let sequence = page.sequence.load(Ordering::Acquire);
+            if sequence % 2 == 0 {
+                let snapshot = Self {
+                    tsc: rdtsc(),
+                    tsc_hz: page.tsc_hz.load(Ordering::Relaxed),
+                    wall_secs: page.wall_secs.load(Ordering::Relaxed),
+                    wall_tsc: page.wall_tsc.load(Ordering::Relaxed),
+                };
+                fence(Ordering::Acquire);
+                if page.sequence.load(Ordering::Relaxed) == sequence {
+                    return snapshot;
+                }
+            }
+            core::hint::spin_loop();
+        }
+    }
+}
+
+fn rdtsc() -> u64 {
+    let lo: u32;
+    let hi: u32;
+    unsafe {
+        core::arch::asm!(
+            "lfence",
+            "rdtsc",
+            out("eax") lo,
+            out("edx") hi,
+            options(nomem, nostack, preserves_flags),
+        );
+    }
+    (u64::from(hi) << 32) | u64::from(lo)
+}
@@ -0,0 +1,82 @@
+//! Thread-local storage.
+//!
+//! The kernel sets up the static TLS block of a program (its `PT_TLS`
+//! segment) before the program starts, using the x86-64 *variant II* layout
+//! of the initial-exec model:
+//!
+//! ```text
+//!   ┌─────────────────────────────┬──────────────────────────────┐
+//!   │ TLS block                   │ TCB (TCB_SIZE bytes)         │
+//!   │ .tdata image, .tbss zeroed  │ [0]: the thread pointer      │
+//!   └─────────────────────────────┴──────────────────────────────┘
+//!                                 ▲
+//!                       thread pointer = FS base
+//! ```
+//!
+//! * The block ends at the thread pointer, rounded up to the segment's
+//!   alignment; variables sit at fixed negative offsets from it, which the
+//!   linker resolves at link time.
+//! * The first word of the thread control block points at itself, so
+//!   `mov rax, fs:0` yields the thread pointer; the rest of the
+//!   [`TCB_SIZE`] bytes is zeroed and reserved.
+//!
+//! Programs without a `PT_TLS` segment start with an FS base of `0`.
+//!
+//! ## Thread-local variables
+//!
+//! With the (nightly) `thread_local` feature, the compiler emits the
+//! accesses itself:
+//!
+//! ```ignore
+//! #![feature(thread_local)]
+//!
+//! #[thread_local]
+//! static mut COUNTER: u64 = 42;
+//!
+//! fn bump() -> u64 {
+//!     unsafe {
+//!         COUNTER += 1;
+//!         COUNTER
+//!     }
+//! }
+//! ```
+//!
+//! The linker script has to keep `.tdata` and `.tbss` together in a `PT_TLS`
+//! segment (see `userland/init/linker.ld`). Runtimes that manage their own
+//! blocks switch the FS base with [`set_thread_pointer`].
+
+use crate::syscall::sys_arch_prctl;
+use crate::syscall_abi::arch_prctl::{ARCH_GET_FS, ARCH_SET_FS};
+pub use crate::syscall_abi::tls::TCB_SIZE;
+
+/// The thread pointer, read from the first word of the TCB.
+///
+/// Only meaningful if the program has a TLS block, or the FS base was set to
+/// a TCB with [`set_thread_pointer`].
+#[inline]
+#[must_use]
+pub fn thread_pointer() -> u64 {
+    let tp: u64;
+    unsafe {
+        core::arch::asm!("mov {}, fs:0", out(reg) tp, options(nostack, readonly, preserves_flags));
+    }
+    tp
+}
+
+/// The FS base as recorded by the kernel.
+#[must_use]
+pub fn fs_base() -> Option<u64> {
+    let mut base = 0u64;
+    sys_arch_prctl(ARCH_GET_FS, core::ptr::from_mut(&mut base) as u64).then_some(base)
+}
+
+/// Point the FS base at `tcb`.
+///
+/// # Safety
+/// `tcb` must point at a thread control block that stays valid while it is
+/// in use, with its first word pointing at itself; thread-local variables
+/// are accessed relative to it from now on.
+#[must_use]
+pub unsafe fn set_thread_pointer(tcb: u64) -> bool {
+    sys_arch_prctl(ARCH_SET_FS, tcb)
+}
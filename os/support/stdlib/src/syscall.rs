@@ -3,7 +3,10 @@
 #[deprecated(since = "0.0.0", note = "Use the syscall variants instead")]
 pub mod int80;
 
-use crate::syscall_abi::Sysno;
+use crate::syscall_abi::{
+    DirEntry, IoVec, LogLevel, MAX_LOG_LEN, MAX_PATH_LEN, MAX_SHM_NAME_LEN, MAX_SPAWN_ARGS, PollFd,
+    SYSCALL_ERROR, Sysno, TaskInfo, UserStr,
+};
 
 #[inline(always)]
 pub fn debug_byte(b: u8) {
@@ -36,3 +39,1042 @@ pub fn sys_bogus() -> u64 {
     }
     ret
 }
+
+/// Start the program at `path` as a new process, passing `argv`.
+///
+/// Returns the PID of the new process, or `None` if the program could not be
+/// started (unknown path, too many arguments, out of memory, ...).
+#[inline(always)]
+#[must_use]
+pub fn sys_spawn(path: &str, argv: &[&str]) -> Option<u64> {
+    sys_spawn_env(path, argv, &[])
+}
+
+/// Start the program at `path` as a new process, passing `argv` and the
+/// environment `envp` (conventionally `KEY=value` strings).
+///
+/// Returns the PID of the new process, or `None` if the program could not be
+/// started (unknown path, too many arguments, out of memory, ...).
+#[inline(always)]
+#[must_use]
+pub fn sys_spawn_env(path: &str, argv: &[&str], envp: &[&str]) -> Option<u64> {
+    if argv.len() > MAX_SPAWN_ARGS || envp.len() > MAX_SPAWN_ARGS {
+        return None;
+    }
+
+    let mut raw_argv = [UserStr::EMPTY; MAX_SPAWN_ARGS];
+    for (dst, arg) in raw_argv.iter_mut().zip(argv) {
+        *dst = UserStr::new(arg);
+    }
+
+    let mut raw_envp = [UserStr::EMPTY; MAX_SPAWN_ARGS];
+    for (dst, var) in raw_envp.iter_mut().zip(envp) {
+        *dst = UserStr::new(var);
+    }
+
+    let mut ret: u64;
+    unsafe {
+        core::arch::asm!(
+            "syscall",
+            inlateout("rax") Sysno::Spawn as u64 => ret,
+            in("rdi") path.as_ptr() as u64,
+            in("rsi") path.len() as u64,
+            in("rdx") raw_argv.as_ptr() as u64,
+            in("r10") argv.len() as u64,
+            in("r8") raw_envp.as_ptr() as u64,
+            in("r9") envp.len() as u64,
+            out("rcx") _, // syscall clobbers
+            out("r11") _, // syscall clobbers
+            out("r12") _, // syscall stub clobbers
+            options(nostack)
+        );
+    }
+
+    if ret == SYSCALL_ERROR {
+        None
+    } else {
+        Some(ret)
+    }
+}
+
+/// Wait for the child process `pid` to exit and return its exit code.
+///
+/// Returns `None` if `pid` is not a child of the calling process.
+#[inline(always)]
+#[must_use]
+#[allow(clippy::cast_possible_truncation)]
+pub fn sys_waitpid(pid: u64) -> Option<u32> {
+    let mut ret: u64;
+    unsafe {
+        core::arch::asm!(
+            "syscall",
+            inlateout("rax") Sysno::WaitPid as u64 => ret,
+            in("rdi") pid,
+            out("rcx") _, // syscall clobbers
+            out("r11") _, // syscall clobbers
+            out("r12") _, // syscall stub clobbers
+            options(nostack)
+        );
+    }
+
+    if ret == SYSCALL_ERROR {
+        None
+    } else {
+        Some(ret as u32)
+    }
+}
+
+/// Write `msg` to the kernel log at `level`.
+///
+/// The kernel tags the message with the caller's PID. Returns the number of
+/// bytes logged, or `None` if `msg` is longer than [`MAX_LOG_LEN`].
+#[inline(always)]
+#[must_use]
+#[allow(clippy::cast_possible_truncation)]
+pub fn sys_log(level: LogLevel, msg: &str) -> Option<usize> {
+    if msg.len() > MAX_LOG_LEN {
+        return None;
+    }
+
+    let mut ret: u64;
+    unsafe {
+        core::arch::asm!(
+            "syscall",
+            inlateout("rax") Sysno::Log as u64 => ret,
+            in("rdi") level as u64,
+            in("rsi") msg.as_ptr() as u64,
+            in("rdx") msg.len() as u64,
+            out("rcx") _, // syscall clobbers
+            out("r11") _, // syscall clobbers
+            out("r12") _, // syscall stub clobbers
+            options(nostack)
+        );
+    }
+
+    if ret == SYSCALL_ERROR {
+        None
+    } else {
+        Some(ret as usize)
+    }
+}
+
+/// Move lines from the kernel log ring into `buf`, oldest first.
+///
+/// Each line fills one [`LOG_RECORD_LEN`](crate::syscall_abi::LOG_RECORD_LEN)
+/// sized record. Returns the number of bytes written (zero if the ring is
+/// empty), or `None` if `buf` cannot hold a single record.
+#[inline(always)]
+#[must_use]
+#[allow(clippy::cast_possible_truncation)]
+pub fn sys_log_read(buf: &mut [u8]) -> Option<usize> {
+    let mut ret: u64;
+    unsafe {
+        core::arch::asm!(
+            "syscall",
+            inlateout("rax") Sysno::LogRead as u64 => ret,
+            in("rdi") buf.as_mut_ptr() as u64,
+            in("rsi") buf.len() as u64,
+            out("rcx") _, // syscall clobbers
+            out("r11") _, // syscall clobbers
+            out("r12") _, // syscall stub clobbers
+            options(nostack)
+        );
+    }
+
+    if ret == SYSCALL_ERROR {
+        None
+    } else {
+        Some(ret as usize)
+    }
+}
+
+/// Duplicate the calling process.
+///
+/// Returns the PID of the child in the parent and `0` in the child, or `None`
+/// if the process could not be forked (process table full, out of memory).
+#[inline(always)]
+#[must_use]
+pub fn sys_fork() -> Option<u64> {
+    let mut ret: u64;
+    unsafe {
+        core::arch::asm!(
+            "syscall",
+            inlateout("rax") Sysno::Fork as u64 => ret,
+            out("rcx") _, // syscall clobbers
+            out("r11") _, // syscall clobbers
+            out("r12") _, // syscall stub clobbers
+            options(nostack)
+        );
+    }
+
+    if ret == SYSCALL_ERROR {
+        None
+    } else {
+        Some(ret)
+    }
+}
+
+/// Open the shared memory object `name`, creating it with `size` bytes if it
+/// does not exist yet.
+///
+/// Returns a handle to pass to [`sys_mmap`] and [`sys_shm_close`], or `None`
+/// if the name is empty or longer than [`MAX_SHM_NAME_LEN`], `size` does not
+/// match an existing object, or the kernel ran out of objects or memory.
+#[inline(always)]
+#[must_use]
+pub fn sys_shm_open(name: &str, size: u64) -> Option<u64> {
+    if name.is_empty() || name.len() > MAX_SHM_NAME_LEN {
+        return None;
+    }
+
+    let mut ret: u64;
+    unsafe {
+        core::arch::asm!(
+            "syscall",
+            inlateout("rax") Sysno::ShmOpen as u64 => ret,
+            in("rdi") name.as_ptr() as u64,
+            in("rsi") name.len() as u64,
+            in("rdx") size,
+            out("rcx") _, // syscall clobbers
+            out("r11") _, // syscall clobbers
+            out("r12") _, // syscall stub clobbers
+            options(nostack)
+        );
+    }
+
+    if ret == SYSCALL_ERROR {
+        None
+    } else {
+        Some(ret)
+    }
+}
+
+/// Close a handle returned by [`sys_shm_open`].
+///
+/// Existing mappings stay valid. Returns `false` if `handle` is not open.
+#[inline(always)]
+#[must_use]
+pub fn sys_shm_close(handle: u64) -> bool {
+    let mut ret: u64;
+    unsafe {
+        core::arch::asm!(
+            "syscall",
+            inlateout("rax") Sysno::ShmClose as u64 => ret,
+            in("rdi") handle,
+            out("rcx") _, // syscall clobbers
+            out("r11") _, // syscall clobbers
+            out("r12") _, // syscall stub clobbers
+            options(nostack)
+        );
+    }
+    ret != SYSCALL_ERROR
+}
+
+/// Map `len` bytes into the calling process.
+///
+/// `prot` is a combination of the `PROT_*` and `flags` of the `MAP_*`
+/// constants in [`syscall_abi`](crate::syscall_abi). With
+/// [`MAP_SHARED`](crate::syscall_abi::MAP_SHARED), `handle` is a shared
+/// memory handle and `offset` the page aligned offset into the object. With
+/// [`MAP_PRIVATE`](crate::syscall_abi::MAP_PRIVATE) alone, `handle` is the
+/// descriptor of a file to map privately, from the page aligned `offset` on.
+/// Anonymous mappings ignore both. The kernel picks the address.
+///
+/// Returns the address of the mapping, or `None` on failure.
+#[inline(always)]
+#[must_use]
+pub fn sys_mmap(len: u64, prot: u64, flags: u64, handle: u64, offset: u64) -> Option<u64> {
+    let mut ret: u64;
+    unsafe {
+        core::arch::asm!(
+            "syscall",
+            inlateout("rax") Sysno::Mmap as u64 => ret,
+            in("rdi") 0u64,
+            in("rsi") len,
+            in("rdx") prot,
+            in("r10") flags,
+            in("r8") handle,
+            in("r9") offset,
+            out("rcx") _, // syscall clobbers
+            out("r11") _, // syscall clobbers
+            out("r12") _, // syscall stub clobbers
+            options(nostack)
+        );
+    }
+
+    if ret == SYSCALL_ERROR {
+        None
+    } else {
+        Some(ret)
+    }
+}
+
+/// Create a pipe.
+///
+/// Returns the file descriptors of the read and the write end, or `None` if
+/// the kernel ran out of pipes or the process out of descriptors.
+#[inline(always)]
+#[must_use]
+pub fn sys_pipe() -> Option<(u32, u32)> {
+    let mut fds = [0u32; 2];
+    let mut ret: u64;
+    unsafe {
+        core::arch::asm!(
+            "syscall",
+            inlateout("rax") Sysno::Pipe as u64 => ret,
+            in("rdi") fds.as_mut_ptr() as u64,
+            out("rcx") _, // syscall clobbers
+            out("r11") _, // syscall clobbers
+            out("r12") _, // syscall stub clobbers
+            options(nostack)
+        );
+    }
+
+    if ret == SYSCALL_ERROR {
+        None
+    } else {
+        Some(fds.into())
+    }
+}
+
+/// Open the file at `path` for reading.
+///
+/// Returns its file descriptor, or `None` if there is no such file, `path` is
+/// longer than [`MAX_PATH_LEN`] or the process ran out of descriptors. So far
+/// only the files below `/proc`, the devices below `/dev` and the programs of
+/// the userland bundle can be opened.
+#[inline(always)]
+#[must_use]
+#[allow(clippy::cast_possible_truncation)]
+pub fn sys_open(path: &str) -> Option<u32> {
+    if path.len() > MAX_PATH_LEN {
+        return None;
+    }
+
+    let mut ret: u64;
+    unsafe {
+        core::arch::asm!(
+            "syscall",
+            inlateout("rax") Sysno::Open as u64 => ret,
+            in("rdi") path.as_ptr() as u64,
+            in("rsi") path.len() as u64,
+            out("rcx") _, // syscall clobbers
+            out("r11") _, // syscall clobbers
+            out("r12") _, // syscall stub clobbers
+            options(nostack)
+        );
+    }
+
+    if ret == SYSCALL_ERROR {
+        None
+    } else {
+        Some(ret as u32)
+    }
+}
+
+/// Read from `fd` into `buf`, blocking until data is available.
+///
+/// Returns the number of bytes read, `Some(0)` at end of file, or `None` if
+/// `fd` is not open for reading.
+#[inline(always)]
+#[must_use]
+#[allow(clippy::cast_possible_truncation)]
+pub fn sys_read(fd: u32, buf: &mut [u8]) -> Option<usize> {
+    let mut ret: u64;
+    unsafe {
+        core::arch::asm!(
+            "syscall",
+            inlateout("rax") Sysno::Read as u64 => ret,
+            in("rdi") u64::from(fd),
+            in("rsi") buf.as_mut_ptr() as u64,
+            in("rdx") buf.len() as u64,
+            out("rcx") _, // syscall clobbers
+            out("r11") _, // syscall clobbers
+            out("r12") _, // syscall stub clobbers
+            options(nostack)
+        );
+    }
+
+    if ret == SYSCALL_ERROR {
+        None
+    } else {
+        Some(ret as usize)
+    }
+}
+
+/// Read from `fd` into the buffers of `iov` in order, blocking until data is
+/// available; takes as much as one [`sys_read`] of their combined length.
+///
+/// Returns the number of bytes read, `Some(0)` at end of file, or `None` if
+/// `fd` is not open for reading, a buffer is not writable or there are more
+/// than [`MAX_IOV`](crate::syscall_abi::MAX_IOV) buffers.
+#[inline(always)]
+#[must_use]
+#[allow(clippy::cast_possible_truncation)]
+pub fn sys_readv(fd: u32, iov: &[IoVec]) -> Option<usize> {
+    let mut ret: u64;
+    unsafe {
+        core::arch::asm!(
+            "syscall",
+            inlateout("rax") Sysno::ReadV as u64 => ret,
+            in("rdi") u64::from(fd),
+            in("rsi") iov.as_ptr() as u64,
+            in("rdx") iov.len() as u64,
+            out("rcx") _, // syscall clobbers
+            out("r11") _, // syscall clobbers
+            out("r12") _, // syscall stub clobbers
+            options(nostack)
+        );
+    }
+
+    if ret == SYSCALL_ERROR {
+        None
+    } else {
+        Some(ret as usize)
+    }
+}
+
+/// Fill `buf` with random bytes from the kernel's generator.
+///
+/// Returns the number of bytes filled, at most
+/// [`MAX_GETRANDOM_LEN`](crate::syscall_abi::MAX_GETRANDOM_LEN), or
+/// `None` if `buf` is not writable.
+#[inline(always)]
+#[must_use]
+#[allow(clippy::cast_possible_truncation)]
+pub fn sys_getrandom(buf: &mut [u8]) -> Option<usize> {
+    let mut ret: u64;
+    unsafe {
+        core::arch::asm!(
+            "syscall",
+            inlateout("rax") Sysno::GetRandom as u64 => ret,
+            in("rdi") buf.as_mut_ptr() as u64,
+            in("rsi") buf.len() as u64,
+            out("rcx") _, // syscall clobbers
+            out("r11") _, // syscall clobbers
+            out("r12") _, // syscall stub clobbers
+            options(nostack)
+        );
+    }
+
+    if ret == SYSCALL_ERROR {
+        None
+    } else {
+        Some(ret as usize)
+    }
+}
+
+/// Write `buf` to `fd`, blocking until all of it is written.
+///
+/// Returns the number of bytes written, which is short only if the reader
+/// went away, or `None` if `fd` is not open for writing or has no reader.
+#[inline(always)]
+#[must_use]
+#[allow(clippy::cast_possible_truncation)]
+pub fn sys_write(fd: u32, buf: &[u8]) -> Option<usize> {
+    let mut ret: u64;
+    unsafe {
+        core::arch::asm!(
+            "syscall",
+            inlateout("rax") Sysno::Write as u64 => ret,
+            in("rdi") u64::from(fd),
+            in("rsi") buf.as_ptr() as u64,
+            in("rdx") buf.len() as u64,
+            out("rcx") _, // syscall clobbers
+            out("r11") _, // syscall clobbers
+            out("r12") _, // syscall stub clobbers
+            options(nostack)
+        );
+    }
+
+    if ret == SYSCALL_ERROR {
+        None
+    } else {
+        Some(ret as usize)
+    }
+}
+
+/// Write the buffers of `iov` to `fd` in order, blocking until all of them
+/// are written.
+///
+/// Returns the number of bytes written, which is short only if the reader
+/// went away, or `None` if `fd` is not open for writing, has no reader, a
+/// buffer is not readable or there are more than
+/// [`MAX_IOV`](crate::syscall_abi::MAX_IOV) buffers.
+#[inline(always)]
+#[must_use]
+#[allow(clippy::cast_possible_truncation)]
+pub fn sys_writev(fd: u32, iov: &[IoVec]) -> Option<usize> {
+    let mut ret: u64;
+    unsafe {
+        core::arch::asm!(
+            "syscall",
+            inlateout("rax") Sysno::WriteV as u64 => ret,
+            in("rdi") u64::from(fd),
+            in("rsi") iov.as_ptr() as u64,
+            in("rdx") iov.len() as u64,
+            out("rcx") _, // syscall clobbers
+            out("r11") _, // syscall clobbers
+            out("r12") _, // syscall stub clobbers
+            options(nostack)
+        );
+    }
+
+    if ret == SYSCALL_ERROR {
+        None
+    } else {
+        Some(ret as usize)
+    }
+}
+
+/// Close the file descriptor `fd`.
+///
+/// Returns `false` if `fd` is not open.
+#[inline(always)]
+#[must_use]
+pub fn sys_close(fd: u32) -> bool {
+    let mut ret: u64;
+    unsafe {
+        core::arch::asm!(
+            "syscall",
+            inlateout("rax") Sysno::Close as u64 => ret,
+            in("rdi") u64::from(fd),
+            out("rcx") _, // syscall clobbers
+            out("r11") _, // syscall clobbers
+            out("r12") _, // syscall stub clobbers
+            options(nostack)
+        );
+    }
+    ret != SYSCALL_ERROR
+}
+
+/// Open another handle to the object of `handle` (a file descriptor or a
+/// shared memory handle), granting `rights`, a mask of the `RIGHT_*` values
+/// in [`handle`](crate::syscall_abi::handle).
+///
+/// Returns the new handle, or `None` if `handle` is not open, lacks some of
+/// `rights` or the process is out of handles.
+#[inline(always)]
+#[must_use]
+#[allow(clippy::cast_possible_truncation)]
+pub fn sys_handle_dup(handle: u32, rights: u64) -> Option<u32> {
+    let mut ret: u64;
+    unsafe {
+        core::arch::asm!(
+            "syscall",
+            inlateout("rax") Sysno::HandleDup as u64 => ret,
+            in("rdi") u64::from(handle),
+            in("rsi") rights,
+            out("rcx") _, // syscall clobbers
+            out("r11") _, // syscall clobbers
+            out("r12") _, // syscall stub clobbers
+            options(nostack)
+        );
+    }
+    if ret == SYSCALL_ERROR {
+        None
+    } else {
+        Some(ret as u32)
+    }
+}
+
+/// Close `handle`, whatever object it holds.
+///
+/// Returns `false` if `handle` is not open.
+#[inline(always)]
+#[must_use]
+pub fn sys_handle_close(handle: u32) -> bool {
+    let mut ret: u64;
+    unsafe {
+        core::arch::asm!(
+            "syscall",
+            inlateout("rax") Sysno::HandleClose as u64 => ret,
+            in("rdi") u64::from(handle),
+            out("rcx") _, // syscall clobbers
+            out("r11") _, // syscall clobbers
+            out("r12") _, // syscall stub clobbers
+            options(nostack)
+        );
+    }
+    ret != SYSCALL_ERROR
+}
+
+/// Wait up to `timeout_ms` milliseconds
+/// ([`FOREVER`](crate::syscall_abi::poll::FOREVER) for no limit) until one
+/// of `fds` is ready for its `events`; the kernel stores the ready events in
+/// each `revents`.
+///
+/// Returns how many handles are ready, `Some(0)` if the timeout passed or a
+/// signal interrupted the wait, or `None` if a handle is not open, lacks the
+/// rights for its events or there are more than
+/// [`MAX_POLL_HANDLES`](crate::syscall_abi::MAX_POLL_HANDLES).
+#[inline(always)]
+#[must_use]
+#[allow(clippy::cast_possible_truncation)]
+pub fn sys_poll(fds: &mut [PollFd], timeout_ms: u64) -> Option<usize> {
+    let mut ret: u64;
+    unsafe {
+        core::arch::asm!(
+            "syscall",
+            inlateout("rax") Sysno::Poll as u64 => ret,
+            in("rdi") fds.as_mut_ptr() as u64,
+            in("rsi") fds.len() as u64,
+            in("rdx") timeout_ms,
+            out("rcx") _, // syscall clobbers
+            out("r11") _, // syscall clobbers
+            out("r12") _, // syscall stub clobbers
+            options(nostack)
+        );
+    }
+    if ret == SYSCALL_ERROR {
+        None
+    } else {
+        Some(ret as usize)
+    }
+}
+
+/// Apply the device-specific `request` with `arg` to the file `fd`, e.g.
+/// [`TTY_SET_MODE`](crate::syscall_abi::tty::TTY_SET_MODE) on
+/// `/dev/console`.
+///
+/// Returns the request's result, or `None` if `fd` is not open or does not
+/// support the request.
+#[inline(always)]
+#[must_use]
+pub fn sys_ioctl(fd: u32, request: u64, arg: u64) -> Option<u64> {
+    let mut ret: u64;
+    unsafe {
+        core::arch::asm!(
+            "syscall",
+            inlateout("rax") Sysno::Ioctl as u64 => ret,
+            in("rdi") u64::from(fd),
+            in("rsi") request,
+            in("rdx") arg,
+            out("rcx") _, // syscall clobbers
+            out("r11") _, // syscall clobbers
+            out("r12") _, // syscall stub clobbers
+            options(nostack)
+        );
+    }
+
+    match ret {
+        SYSCALL_ERROR => None,
+        ret => Some(ret),
+    }
+}
+
+/// Describe the entry at `cursor` of the directory `path` in `entry`;
+/// start with cursor `0`.
+///
+/// Returns the cursor of the next entry, or `None` once all entries were
+/// listed, if there is no such directory or `path` is longer than
+/// [`MAX_PATH_LEN`]:
+///
+/// ```ignore
+/// let mut entry = DirEntry::default();
+/// let mut cursor = 0;
+/// while let Some(next) = sys_readdir("/", cursor, &mut entry) {
+///     // use `entry`
+///     cursor = next;
+/// }
+/// ```
+#[inline(always)]
+#[must_use]
+pub fn sys_readdir(path: &str, cursor: u64, entry: &mut DirEntry) -> Option<u64> {
+    if path.len() > MAX_PATH_LEN {
+        return None;
+    }
+
+    let mut ret: u64;
+    unsafe {
+        core::arch::asm!(
+            "syscall",
+            inlateout("rax") Sysno::ReadDir as u64 => ret,
+            in("rdi") path.as_ptr() as u64,
+            in("rsi") path.len() as u64,
+            in("rdx") cursor,
+            in("r10") core::ptr::from_mut(entry) as u64,
+            out("rcx") _, // syscall clobbers
+            out("r11") _, // syscall clobbers
+            out("r12") _, // syscall stub clobbers
+            options(nostack)
+        );
+    }
+
+    if ret == SYSCALL_ERROR {
+        None
+    } else {
+        Some(ret)
+    }
+}
+
+/// Send the signal `signo` to the process `pid`.
+///
+/// Signal `0` only checks that `pid` exists. Returns `false` if `signo` is
+/// out of range or there is no such process.
+#[inline(always)]
+#[must_use]
+pub fn sys_kill(pid: u64, signo: u32) -> bool {
+    let mut ret: u64;
+    unsafe {
+        core::arch::asm!(
+            "syscall",
+            inlateout("rax") Sysno::Kill as u64 => ret,
+            in("rdi") pid,
+            in("rsi") u64::from(signo),
+            out("rcx") _, // syscall clobbers
+            out("r11") _, // syscall clobbers
+            out("r12") _, // syscall stub clobbers
+            options(nostack)
+        );
+    }
+    ret != SYSCALL_ERROR
+}
+
+/// Set how the signal `signo` is handled.
+///
+/// `handler` is [`SIG_DFL`](crate::syscall_abi::signal::SIG_DFL),
+/// [`SIG_IGN`](crate::syscall_abi::signal::SIG_IGN) or the address of a
+/// handler, which returns to `restorer`; see
+/// [`SignalContext`](crate::syscall_abi::SignalContext). Most programs use
+/// [`signal::set_handler`](crate::signal::set_handler) instead.
+///
+/// Returns the previous handler value, or `None` if `signo` cannot be caught
+/// or an address is invalid.
+#[inline(always)]
+#[must_use]
+pub fn sys_sigaction(signo: u32, handler: u64, restorer: u64) -> Option<u64> {
+    let mut ret: u64;
+    unsafe {
+        core::arch::asm!(
+            "syscall",
+            inlateout("rax") Sysno::SigAction as u64 => ret,
+            in("rdi") u64::from(signo),
+            in("rsi") handler,
+            in("rdx") restorer,
+            out("rcx") _, // syscall clobbers
+            out("r11") _, // syscall clobbers
+            out("r12") _, // syscall stub clobbers
+            options(nostack)
+        );
+    }
+
+    if ret == SYSCALL_ERROR {
+        None
+    } else {
+        Some(ret)
+    }
+}
+
+/// Set the scheduling priority of the process `pid` (`0` for the caller).
+///
+/// `priority` ranges from [`priority::MIN`](crate::syscall_abi::priority::MIN)
+/// to [`priority::MAX`](crate::syscall_abi::priority::MAX).
+///
+/// Returns the previous priority, or `None` if `priority` is out of range or
+/// there is no such process.
+#[inline(always)]
+#[must_use]
+pub fn sys_setpriority(pid: u64, priority: u8) -> Option<u8> {
+    let mut ret: u64;
+    unsafe {
+        core::arch::asm!(
+            "syscall",
+            inlateout("rax") Sysno::SetPriority as u64 => ret,
+            in("rdi") pid,
+            in("rsi") u64::from(priority),
+            out("rcx") _, // syscall clobbers
+            out("r11") _, // syscall clobbers
+            out("r12") _, // syscall stub clobbers
+            options(nostack)
+        );
+    }
+
+    u8::try_from(ret).ok()
+}
+
+/// Let the process `pid` (`0` for the caller) run only on the CPUs in
+/// `mask`, bit `n` standing for CPU `n`.
+///
+/// Returns `false` if there is no such process or `mask` names no online
+/// CPU.
+#[inline(always)]
+#[must_use]
+pub fn sys_sched_setaffinity(pid: u64, mask: u64) -> bool {
+    let mut ret: u64;
+    unsafe {
+        core::arch::asm!(
+            "syscall",
+            inlateout("rax") Sysno::SchedSetAffinity as u64 => ret,
+            in("rdi") pid,
+            in("rsi") mask,
+            out("rcx") _, // syscall clobbers
+            out("r11") _, // syscall clobbers
+            out("r12") _, // syscall stub clobbers
+            options(nostack)
+        );
+    }
+
+    ret != SYSCALL_ERROR
+}
+
+/// The caller's capability set `which`, [`EFFECTIVE`](crate::syscall_abi::caps::EFFECTIVE)
+/// or [`INHERITABLE`](crate::syscall_abi::caps::INHERITABLE).
+///
+/// Returns `None` for any other selector.
+#[inline(always)]
+#[must_use]
+pub fn sys_cap_get(which: u64) -> Option<u64> {
+    let mut ret: u64;
+    unsafe {
+        core::arch::asm!(
+            "syscall",
+            inlateout("rax") Sysno::CapGet as u64 => ret,
+            in("rdi") which,
+            out("rcx") _, // syscall clobbers
+            out("r11") _, // syscall clobbers
+            out("r12") _, // syscall stub clobbers
+            options(nostack)
+        );
+    }
+
+    match ret {
+        SYSCALL_ERROR => None,
+        ret => Some(ret),
+    }
+}
+
+/// Replace the caller's capability set `which` with `caps`, e.g. to drop
+/// capabilities or to choose those of the processes it spawns; see
+/// [`caps`](crate::syscall_abi::caps).
+///
+/// Returns the previous set, or `None` if `caps` holds a capability the
+/// caller lacks.
+#[inline(always)]
+#[must_use]
+pub fn sys_cap_set(which: u64, caps: u64) -> Option<u64> {
+    let mut ret: u64;
+    unsafe {
+        core::arch::asm!(
+            "syscall",
+            inlateout("rax") Sysno::CapSet as u64 => ret,
+            in("rdi") which,
+            in("rsi") caps,
+            out("rcx") _, // syscall clobbers
+            out("r11") _, // syscall clobbers
+            out("r12") _, // syscall stub clobbers
+            options(nostack)
+        );
+    }
+
+    match ret {
+        SYSCALL_ERROR => None,
+        ret => Some(ret),
+    }
+}
+
+/// Take the CPU with the logical index `cpu` offline or bring it back online.
+///
+/// Meant for debugging; needs [`CAP_RAWIO`](crate::syscall_abi::caps::CAP_RAWIO).
+/// Returns `false` without it, if there is no such CPU, it is in that state
+/// already or, for the boot CPU, can not be taken offline.
+#[inline(always)]
+#[must_use]
+pub fn sys_cpu_set_online(cpu: u32, online: bool) -> bool {
+    let mut ret: u64;
+    unsafe {
+        core::arch::asm!(
+            "syscall",
+            inlateout("rax") Sysno::CpuSetOnline as u64 => ret,
+            in("rdi") u64::from(cpu),
+            in("rsi") u64::from(online),
+            out("rcx") _, // syscall clobbers
+            out("r11") _, // syscall clobbers
+            out("r12") _, // syscall stub clobbers
+            options(nostack)
+        );
+    }
+
+    ret != SYSCALL_ERROR
+}
+
+/// Set or read the FS base (thread pointer) of the caller.
+///
+/// With [`ARCH_SET_FS`](crate::syscall_abi::arch_prctl::ARCH_SET_FS), `addr`
+/// is the new base; with [`ARCH_GET_FS`](crate::syscall_abi::arch_prctl::ARCH_GET_FS),
+/// the base is stored at `addr`.
+///
+/// Returns `false` for unknown operations or addresses outside user space.
+#[inline(always)]
+#[must_use]
+pub fn sys_arch_prctl(code: u64, addr: u64) -> bool {
+    let mut ret: u64;
+    unsafe {
+        core::arch::asm!(
+            "syscall",
+            inlateout("rax") Sysno::ArchPrctl as u64 => ret,
+            in("rdi") code,
+            in("rsi") addr,
+            out("rcx") _, // syscall clobbers
+            out("r11") _, // syscall clobbers
+            out("r12") _, // syscall stub clobbers
+            options(nostack)
+        );
+    }
+
+    ret != SYSCALL_ERROR
+}
+
+/// Describe the task at `cursor` in `info`; start with a cursor of `0`.
+///
+/// Returns the cursor of the next task, or `None` once all tasks were
+/// listed:
+///
+/// ```ignore
+/// let mut info = TaskInfo::default();
+/// let mut cursor = 0;
+/// while let Some(next) = sys_task_info(cursor, &mut info) {
+///     // use `info`
+///     cursor = next;
+/// }
+/// ```
+#[inline(always)]
+#[must_use]
+pub fn sys_task_info(cursor: u64, info: &mut TaskInfo) -> Option<u64> {
+    let mut ret: u64;
+    unsafe {
+        core::arch::asm!(
+            "syscall",
+            inlateout("rax") Sysno::TaskInfo as u64 => ret,
+            in("rdi") cursor,
+            in("rsi") core::ptr::from_mut(info) as u64,
+            out("rcx") _, // syscall clobbers
+            out("r11") _, // syscall clobbers
+            out("r12") _, // syscall stub clobbers
+            options(nostack)
+        );
+    }
+
+    if ret == SYSCALL_ERROR {
+        None
+    } else {
+        Some(ret)
+    }
+}
+
+/// Switch syscall tracing of the process `pid` (`0` for the caller) on or
+/// off. A traced process has every system call logged by the kernel.
+///
+/// Returns whether tracing was on before, or `None` if there is no such
+/// process.
+#[inline(always)]
+#[must_use]
+pub fn sys_trace(pid: u64, on: bool) -> Option<bool> {
+    let mut ret: u64;
+    unsafe {
+        core::arch::asm!(
+            "syscall",
+            inlateout("rax") Sysno::Trace as u64 => ret,
+            in("rdi") pid,
+            in("rsi") u64::from(on),
+            out("rcx") _, // syscall clobbers
+            out("r11") _, // syscall clobbers
+            out("r12") _, // syscall stub clobbers
+            options(nostack)
+        );
+    }
+
+    match ret {
+        SYSCALL_ERROR => None,
+        ret => Some(ret != 0),
+    }
+}
+
+/// Sleep for `ns` nanoseconds.
+///
+/// Returns the nanoseconds left, `Some(0)` unless a signal ended the sleep
+/// early, or `None` before the kernel calibrated its clock.
+#[inline(always)]
+#[must_use]
+pub fn sys_nanosleep(ns: u64) -> Option<u64> {
+    let mut ret: u64;
+    unsafe {
+        core::arch::asm!(
+            "syscall",
+            inlateout("rax") Sysno::NanoSleep as u64 => ret,
+            in("rdi") ns,
+            out("rcx") _, // syscall clobbers
+            out("r11") _, // syscall clobbers
+            out("r12") _, // syscall stub clobbers
+            options(nostack)
+        );
+    }
+
+    if ret == SYSCALL_ERROR {
+        None
+    } else {
+        Some(ret)
+    }
+}
+
+/// Sleep until the monotonic clock (see `time::clock_gettime`) reads
+/// `deadline` nanoseconds.
+///
+/// Returns the nanoseconds left, `Some(0)` unless a signal ended the sleep
+/// early, or `None` before the kernel calibrated its clock.
+#[inline(always)]
+#[must_use]
+pub fn sys_clock_nanosleep(deadline: u64) -> Option<u64> {
+    let mut ret: u64;
+    unsafe {
+        core::arch::asm!(
+            "syscall",
+            inlateout("rax") Sysno::ClockNanoSleep as u64 => ret,
+            in("rdi") deadline,
+            out("rcx") _, // syscall clobbers
+            out("r11") _, // syscall clobbers
+            out("r12") _, // syscall stub clobbers
+            options(nostack)
+        );
+    }
+
+    if ret == SYSCALL_ERROR {
+        None
+    } else {
+        Some(ret)
+    }
+}
+
+/// Power the machine off, restart or halt it, by one of the
+/// [`reboot`](crate::syscall_abi::reboot) commands.
+///
+/// Needs [`CAP_REBOOT`](crate::syscall_abi::caps::CAP_REBOOT); without
+/// it, and for unknown commands, the call fails and returns. On success it
+/// does not return.
+#[inline(always)]
+pub fn sys_reboot(cmd: u64) {
+    unsafe {
+        core::arch::asm!(
+            "syscall",
+            inlateout("rax") Sysno::Reboot as u64 => _,
+            in("rdi") cmd,
+            out("rcx") _, // syscall clobbers
+            out("r11") _, // syscall clobbers
+            out("r12") _, // syscall stub clobbers
+            options(nostack)
+        );
+    }
+}
+
+/// Terminate the calling process with the given exit code.
+#[inline(always)]
+pub fn sys_exit(code: u32) -> ! {
+    unsafe {
+        core::arch::asm!(
+            "syscall",
+            in("rax") Sysno::Exit as u64,
+            in("rdi") u64::from(code),
+            options(noreturn, nostack)
+        );
+    }
+}
@@ -1,7 +1,806 @@
+use core::sync::atomic::AtomicU64;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
 #[repr(u64)]
 pub enum Sysno {
     /// Write a single byte to a kernel-chosen “debug” sink.
     DebugWriteByte = 1,
     /// Just return a made-up number to prove plumbing.
     Bogus = 2,
+    /// Start a new process from a program path; returns its PID.
+    Spawn = 3,
+    /// Block until a child process exits; returns its exit code.
+    WaitPid = 4,
+    /// Terminate the calling process with an exit code.
+    Exit = 5,
+    /// Write a message to the kernel log, tagged with the caller's PID.
+    Log = 6,
+    /// Drain lines from the kernel log ring into a user buffer.
+    LogRead = 7,
+    /// Duplicate the calling process; returns the child's PID, or `0` in the
+    /// child.
+    Fork = 8,
+    /// Open (or create) a named shared memory object; returns a handle.
+    ShmOpen = 9,
+    /// Close a shared memory handle; [`Sysno::HandleClose`] restricted to
+    /// shared memory objects.
+    ShmClose = 10,
+    /// Map memory into the calling process; returns the mapping's address.
+    Mmap = 11,
+    /// Create a pipe; stores the read and write file descriptors.
+    Pipe = 12,
+    /// Read from a file descriptor; returns the number of bytes read.
+    Read = 13,
+    /// Write to a file descriptor; returns the number of bytes written.
+    Write = 14,
+    /// Close a file descriptor, like [`Sysno::HandleClose`].
+    Close = 15,
+    /// Send a signal to a process. Signalling a process other than the
+    /// caller, its parent or its children needs [`caps::CAP_PROCESS`].
+    Kill = 16,
+    /// Set how a signal is handled; returns the previous handler.
+    SigAction = 17,
+    /// Return from a signal handler to the interrupted context.
+    SigReturn = 18,
+    /// Set the scheduling priority of a process; returns the previous one.
+    /// Other processes than the caller and its children need
+    /// [`caps::CAP_PROCESS`].
+    SetPriority = 19,
+    /// Take a CPU offline or bring it back online (for debugging).
+    CpuSetOnline = 20,
+    /// Set or read the thread pointer (FS base) of the caller.
+    ArchPrctl = 21,
+    /// Describe one task (process or kernel thread); returns the cursor of
+    /// the next one.
+    TaskInfo = 22,
+    /// Open a file by path for reading; returns a file descriptor.
+    Open = 23,
+    /// Switch syscall tracing of a process on or off; returns whether it was
+    /// on. Other processes than the caller and its children need
+    /// [`caps::CAP_PROCESS`].
+    Trace = 24,
+    /// Apply a device-specific request to an open file, such as switching
+    /// the terminal mode (see [`tty`]).
+    Ioctl = 25,
+    /// Describe one entry of a directory; returns the cursor of the next one.
+    ReadDir = 26,
+    /// Fill a buffer with random bytes; returns how many, at most
+    /// [`MAX_GETRANDOM_LEN`].
+    GetRandom = 27,
+    /// Power the machine off, restart or halt it (see [`reboot`]); needs
+    /// [`caps::CAP_REBOOT`]. Does not return on success.
+    Reboot = 28,
+    /// Sleep for a number of nanoseconds; returns the nanoseconds left,
+    /// `0` unless a signal cut the sleep short.
+    NanoSleep = 29,
+    /// Sleep until a deadline in nanoseconds of the monotonic clock (see
+    /// [`ClockPage`]); returns the nanoseconds left, `0` unless a signal cut
+    /// the sleep short.
+    ClockNanoSleep = 30,
+    /// Restrict a process to a set of CPUs, one bit per CPU (see
+    /// [`task::ALL_CPUS`]). Other processes than the caller and its children
+    /// need [`caps::CAP_PROCESS`].
+    SchedSetAffinity = 31,
+    /// Return one of the caller's capability sets (see [`caps`]).
+    CapGet = 32,
+    /// Replace one of the caller's capability sets with a subset of its
+    /// effective capabilities; returns the previous one.
+    CapSet = 33,
+    /// Open another handle to the object of a handle, with a subset of its
+    /// rights (see [`handle`]); returns the new handle.
+    HandleDup = 34,
+    /// Close a handle of any kind.
+    HandleClose = 35,
+    /// Wait until one of several handles is ready (see [`poll`]); returns
+    /// how many are.
+    Poll = 36,
+    /// Read from a file descriptor into several buffers (see [`IoVec`]);
+    /// returns how many bytes.
+    ReadV = 37,
+    /// Write several buffers to a file descriptor (see [`IoVec`]); returns
+    /// how many bytes.
+    WriteV = 38,
+}
+
+impl Sysno {
+    /// Every system call, in numeric order.
+    pub const ALL: [Self; 38] = [
+        Self::DebugWriteByte,
+        Self::Bogus,
+        Self::Spawn,
+        Self::WaitPid,
+        Self::Exit,
+        Self::Log,
+        Self::LogRead,
+        Self::Fork,
+        Self::ShmOpen,
+        Self::ShmClose,
+        Self::Mmap,
+        Self::Pipe,
+        Self::Read,
+        Self::Write,
+        Self::Close,
+        Self::Kill,
+        Self::SigAction,
+        Self::SigReturn,
+        Self::SetPriority,
+        Self::CpuSetOnline,
+        Self::ArchPrctl,
+        Self::TaskInfo,
+        Self::Open,
+        Self::Trace,
+        Self::Ioctl,
+        Self::ReadDir,
+        Self::GetRandom,
+        Self::Reboot,
+        Self::NanoSleep,
+        Self::ClockNanoSleep,
+        Self::SchedSetAffinity,
+        Self::CapGet,
+        Self::CapSet,
+        Self::HandleDup,
+        Self::HandleClose,
+        Self::Poll,
+        Self::ReadV,
+        Self::WriteV,
+    ];
+
+    /// The system call with the given number.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn from_raw(raw: u64) -> Option<Self> {
+        match raw {
+            1..=38 => Some(Self::ALL[raw as usize - 1]),
+            _ => None,
+        }
+    }
+
+    /// Lower-case name, as in the kernel's documentation.
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::DebugWriteByte => "debug_write_byte",
+            Self::Bogus => "bogus",
+            Self::Spawn => "spawn",
+            Self::WaitPid => "waitpid",
+            Self::Exit => "exit",
+            Self::Log => "log",
+            Self::LogRead => "log_read",
+            Self::Fork => "fork",
+            Self::ShmOpen => "shm_open",
+            Self::ShmClose => "shm_close",
+            Self::Mmap => "mmap",
+            Self::Pipe => "pipe",
+            Self::Read => "read",
+            Self::Write => "write",
+            Self::Close => "close",
+            Self::Kill => "kill",
+            Self::SigAction => "sigaction",
+            Self::SigReturn => "sigreturn",
+            Self::SetPriority => "setpriority",
+            Self::CpuSetOnline => "cpu_set_online",
+            Self::ArchPrctl => "arch_prctl",
+            Self::TaskInfo => "task_info",
+            Self::Open => "open",
+            Self::Trace => "trace",
+            Self::Ioctl => "ioctl",
+            Self::ReadDir => "readdir",
+            Self::GetRandom => "getrandom",
+            Self::Reboot => "reboot",
+            Self::NanoSleep => "nanosleep",
+            Self::ClockNanoSleep => "clock_nanosleep",
+            Self::SchedSetAffinity => "sched_setaffinity",
+            Self::CapGet => "cap_get",
+            Self::CapSet => "cap_set",
+            Self::HandleDup => "handle_dup",
+            Self::HandleClose => "handle_close",
+            Self::Poll => "poll",
+            Self::ReadV => "readv",
+            Self::WriteV => "writev",
+        }
+    }
+}
+
+/// Return value used by the kernel to signal a failed syscall.
+pub const SYSCALL_ERROR: u64 = u64::MAX;
+
+/// Maximum number of arguments (and, separately, environment entries)
+/// accepted by [`Sysno::Spawn`].
+pub const MAX_SPAWN_ARGS: usize = 16;
+
+/// Maximum length of a path accepted by [`Sysno::Spawn`], [`Sysno::Open`]
+/// and [`Sysno::ReadDir`].
+pub const MAX_PATH_LEN: usize = 64;
+
+/// Most bytes filled by one [`Sysno::GetRandom`] call.
+pub const MAX_GETRANDOM_LEN: usize = 256;
+
+/// Most handles one [`Sysno::Poll`] call waits for.
+pub const MAX_POLL_HANDLES: usize = 16;
+
+/// Most buffers one [`Sysno::ReadV`] or [`Sysno::WriteV`] call takes.
+pub const MAX_IOV: usize = 16;
+
+/// Maximum length of a message accepted by [`Sysno::Log`].
+pub const MAX_LOG_LEN: usize = 256;
+
+/// Maximum length of a shared memory object name accepted by
+/// [`Sysno::ShmOpen`].
+pub const MAX_SHM_NAME_LEN: usize = 32;
+
+/// [`Sysno::Mmap`] protection: the pages can be read. Required.
+pub const PROT_READ: u64 = 0x1;
+/// [`Sysno::Mmap`] protection: the pages can be written.
+pub const PROT_WRITE: u64 = 0x2;
+/// [`Sysno::Mmap`] protection: the pages can be executed.
+pub const PROT_EXEC: u64 = 0x4;
+
+/// [`Sysno::Mmap`] flag: map a shared memory object (or, with
+/// [`MAP_DEVICE`], device memory); writes are visible to every process
+/// mapping it.
+pub const MAP_SHARED: u64 = 0x01;
+/// [`Sysno::Mmap`] flag: the mapping is private to the process; without
+/// [`MAP_ANONYMOUS`], it maps a file, and writes stay with the process.
+pub const MAP_PRIVATE: u64 = 0x02;
+/// [`Sysno::Mmap`] flag: the mapping is not backed by an object; combine with
+/// [`MAP_PRIVATE`].
+pub const MAP_ANONYMOUS: u64 = 0x20;
+/// [`Sysno::Mmap`] flag: map the memory of the character device whose
+/// descriptor is the handle, such as the back buffer of `/dev/fb0`; combine
+/// with [`MAP_SHARED`].
+pub const MAP_DEVICE: u64 = 0x40;
+
+/// Size of one record returned by [`Sysno::LogRead`].
+///
+/// Each record is a level digit (see [`LogLevel`]), a space, the line text
+/// and a newline, padded with zero bytes to this size so that records can be
+/// located without parsing.
+pub const LOG_RECORD_LEN: usize = 128;
+
+/// Severity of a [`Sysno::Log`] message.
+///
+/// The values match the kernel's log levels, most severe first.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+#[repr(u64)]
+pub enum LogLevel {
+    Error = 1,
+    Warn = 2,
+    Info = 3,
+    Debug = 4,
+    Trace = 5,
+}
+
+impl LogLevel {
+    /// The level with the given raw value.
+    #[must_use]
+    pub const fn from_raw(raw: u64) -> Option<Self> {
+        match raw {
+            1 => Some(Self::Error),
+            2 => Some(Self::Warn),
+            3 => Some(Self::Info),
+            4 => Some(Self::Debug),
+            5 => Some(Self::Trace),
+            _ => None,
+        }
+    }
+}
+
+/// A borrowed byte string in user memory, as passed to the kernel.
+///
+/// `&str` is a fat pointer without a stable layout; this type
+/// spells out the pointer/length pair explicitly.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(C)]
+pub struct UserStr {
+    /// User-space address of the first byte.
+    pub ptr: u64,
+    /// Length in bytes.
+    pub len: u64,
+}
+
+impl UserStr {
+    /// An empty string.
+    pub const EMPTY: Self = Self { ptr: 0, len: 0 };
+
+    /// Describe a string slice.
+    #[must_use]
+    pub fn new(s: &str) -> Self {
+        Self {
+            ptr: s.as_ptr() as u64,
+            len: s.len() as u64,
+        }
+    }
+}
+
+/// One buffer of a [`Sysno::ReadV`] or [`Sysno::WriteV`] call; the calls
+/// fill or drain the buffers in order, like one buffer of their combined
+/// length.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(C)]
+pub struct IoVec {
+    /// User-space address of the first byte.
+    pub base: u64,
+    /// Length in bytes.
+    pub len: u64,
+}
+
+impl IoVec {
+    /// An empty buffer.
+    pub const EMPTY: Self = Self { base: 0, len: 0 };
+
+    /// Describe a buffer to write from.
+    #[must_use]
+    pub fn new(buf: &[u8]) -> Self {
+        Self {
+            base: buf.as_ptr() as u64,
+            len: buf.len() as u64,
+        }
+    }
+
+    /// Describe a buffer to read into.
+    #[must_use]
+    pub fn new_mut(buf: &mut [u8]) -> Self {
+        Self {
+            base: buf.as_mut_ptr() as u64,
+            len: buf.len() as u64,
+        }
+    }
+}
+
+/// Auxiliary vector entry types placed on the initial user stack.
+///
+/// The values match the System V ABI so that familiar tooling can make sense of them.
+pub mod auxv {
+    /// Terminates the auxiliary vector.
+    pub const AT_NULL: u64 = 0;
+    /// Address of the program headers, if they are loaded.
+    pub const AT_PHDR: u64 = 3;
+    /// Size of one program header in bytes.
+    pub const AT_PHENT: u64 = 4;
+    /// Number of program headers.
+    pub const AT_PHNUM: u64 = 5;
+    /// System page size in bytes.
+    pub const AT_PAGESZ: u64 = 6;
+    /// Entry point of the program.
+    pub const AT_ENTRY: u64 = 9;
+    /// Address of 16 random bytes.
+    pub const AT_RANDOM: u64 = 25;
+}
+
+/// Signal numbers and dispositions for [`Sysno::Kill`] and
+/// [`Sysno::SigAction`].
+///
+/// The numbers match Linux on x86-64.
+pub mod signal {
+    /// Interrupt from the terminal.
+    pub const SIGINT: u32 = 2;
+    /// Illegal instruction.
+    pub const SIGILL: u32 = 4;
+    /// Arithmetic error.
+    pub const SIGFPE: u32 = 8;
+    /// Terminate; cannot be caught or ignored.
+    pub const SIGKILL: u32 = 9;
+    /// User-defined signal 1.
+    pub const SIGUSR1: u32 = 10;
+    /// Invalid memory access.
+    pub const SIGSEGV: u32 = 11;
+    /// User-defined signal 2.
+    pub const SIGUSR2: u32 = 12;
+    /// Write to a pipe without readers.
+    pub const SIGPIPE: u32 = 13;
+    /// Timer expired.
+    pub const SIGALRM: u32 = 14;
+    /// Polite request to terminate.
+    pub const SIGTERM: u32 = 15;
+    /// A child process exited. Ignored by default.
+    pub const SIGCHLD: u32 = 17;
+
+    /// Number of signals; valid signals are `1..NSIG`.
+    pub const NSIG: u32 = 32;
+
+    /// Handler value: take the default action.
+    pub const SIG_DFL: u64 = 0;
+    /// Handler value: discard the signal.
+    pub const SIG_IGN: u64 = 1;
+}
+
+/// Scheduling priorities for [`Sysno::SetPriority`].
+///
+/// Ready processes with a higher priority run first; processes of equal
+/// priority take turns.
+pub mod priority {
+    /// Lowest priority; still above the kernel's idle loop.
+    pub const MIN: u8 = 0;
+    /// Priority of spawned processes.
+    pub const DEFAULT: u8 = 16;
+    /// Highest priority.
+    pub const MAX: u8 = 31;
+}
+
+/// Capabilities, the privileges of a process, for [`Sysno::CapGet`] and
+/// [`Sysno::CapSet`].
+///
+/// A process holds two sets, as bit masks of the `CAP_*` values: the
+/// kernel checks the *effective* set, and a spawned child starts out with
+/// both sets equal to its parent's *inheritable* set. A forked child gets
+/// copies of both. Either set can only be changed to a subset of the
+/// effective one, so a capability once dropped is gone for good. Processes
+/// the kernel starts, such as init, hold [`ALL`].
+pub mod caps {
+    /// Selects the effective set.
+    pub const EFFECTIVE: u64 = 0;
+    /// Selects the inheritable set.
+    pub const INHERITABLE: u64 = 1;
+
+    /// Control the hardware directly: take CPUs on- and offline, and port
+    /// and MMIO access once there are system calls for it.
+    pub const CAP_RAWIO: u64 = 1 << 0;
+    /// Power off, restart or halt the machine.
+    pub const CAP_REBOOT: u64 = 1 << 1;
+    /// Open the framebuffer, `/dev/fb0`.
+    pub const CAP_DEVICE_FB: u64 = 1 << 2;
+    /// Open consoles: the terminal, `/dev/console`, and the virtio console.
+    pub const CAP_DEVICE_CONSOLE: u64 = 1 << 3;
+    /// Use the network; reserved, there is no network stack yet.
+    pub const CAP_NET: u64 = 1 << 4;
+    /// Signal, reprioritize, pin or trace processes other than the caller
+    /// and its children.
+    pub const CAP_PROCESS: u64 = 1 << 5;
+
+    /// Every capability.
+    pub const ALL: u64 =
+        CAP_RAWIO | CAP_REBOOT | CAP_DEVICE_FB | CAP_DEVICE_CONSOLE | CAP_NET | CAP_PROCESS;
+}
+
+/// Rights of a handle, for [`Sysno::HandleDup`].
+///
+/// File descriptors and shared memory handles are both handles: small
+/// integers naming a kernel object the process holds. Each handle carries a
+/// bit mask of the `RIGHT_*` values saying what may be done with the object
+/// through it. A new handle gets every right its object supports; pipe ends
+/// only read or only write, and `procfs` files cannot be mapped.
+/// [`Sysno::HandleDup`] copies a handle with fewer rights, e.g. a read-only
+/// one to pass to a child.
+pub mod handle {
+    /// Read from the object.
+    pub const RIGHT_READ: u64 = 1 << 0;
+    /// Write to the object, or map it writable and shared.
+    pub const RIGHT_WRITE: u64 = 1 << 1;
+    /// Map the object's memory.
+    pub const RIGHT_MAP: u64 = 1 << 2;
+
+    /// Every right.
+    pub const ALL_RIGHTS: u64 = RIGHT_READ | RIGHT_WRITE | RIGHT_MAP;
+}
+
+/// Events of [`PollFd`], for [`Sysno::Poll`].
+///
+/// A handle is ready for `POLLIN` if a read would return data without
+/// blocking, and for `POLLOUT` if a write would not block; files are always
+/// ready. `POLLHUP` (the writers of a pipe are gone, so reads end at end of
+/// file) and `POLLERR` (its readers are gone) come along with `POLLIN` and
+/// `POLLOUT` interest respectively. The values match Linux.
+pub mod poll {
+    /// Reading would not block.
+    pub const POLLIN: u16 = 0x1;
+    /// Writing would not block.
+    pub const POLLOUT: u16 = 0x4;
+    /// Writing fails; only in `revents`.
+    pub const POLLERR: u16 = 0x8;
+    /// The other side hung up; only in `revents`.
+    pub const POLLHUP: u16 = 0x10;
+
+    /// Timeout of [`Sysno::Poll`](super::Sysno::Poll) that waits forever.
+    pub const FOREVER: u64 = u64::MAX;
+}
+
+/// One handle to wait for with [`Sysno::Poll`].
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+#[repr(C)]
+pub struct PollFd {
+    /// The handle; it needs `RIGHT_READ` to wait for `POLLIN` and
+    /// `RIGHT_WRITE` for `POLLOUT` (see [`handle`]).
+    pub handle: u32,
+    /// The `poll::POLL*` events to wait for.
+    pub events: u16,
+    /// The events that are ready, stored by the kernel.
+    pub revents: u16,
+}
+
+// The kernel copies the record byte by byte; there must be no padding.
+const _: () = assert!(size_of::<PollFd>() == 4 + 2 * 2);
+
+/// Commands of [`Sysno::Reboot`].
+pub mod reboot {
+    /// Power the machine off (ACPI S5).
+    pub const POWER_OFF: u64 = 1;
+    /// Reset the machine.
+    pub const RESTART: u64 = 2;
+    /// Stop the CPUs and leave the machine on.
+    pub const HALT: u64 = 3;
+}
+
+/// Operations of [`Sysno::ArchPrctl`].
+///
+/// The values match Linux on x86-64.
+pub mod arch_prctl {
+    /// Set the FS base to the address given.
+    pub const ARCH_SET_FS: u64 = 0x1002;
+    /// Store the FS base at the address given.
+    pub const ARCH_GET_FS: u64 = 0x1003;
+}
+
+/// Requests of [`Sysno::Ioctl`] on the terminal, `/dev/console`.
+pub mod tty {
+    /// Return the mode flags.
+    pub const TTY_GET_MODE: u64 = 1;
+    /// Set the mode flags to the argument; returns the previous ones.
+    pub const TTY_SET_MODE: u64 = 2;
+
+    /// Mode flag: input is edited and handed out line by line. Without it,
+    /// the terminal is in raw mode and every character is handed out as
+    /// typed.
+    pub const MODE_CANONICAL: u64 = 1 << 0;
+    /// Mode flag: typed characters are shown on the screen.
+    pub const MODE_ECHO: u64 = 1 << 1;
+    /// The mode the terminal starts out in.
+    pub const MODE_DEFAULT: u64 = MODE_CANONICAL | MODE_ECHO;
+}
+
+/// Requests of [`Sysno::Ioctl`] on the framebuffer, `/dev/fb0`.
+///
+/// Mapping the device with [`MAP_DEVICE`](super::MAP_DEVICE) gives access
+/// to its back buffer; nothing drawn there shows until presented.
+pub mod fb {
+    /// Store the screen's [`FbInfo`](super::FbInfo) at the address given.
+    pub const FB_GET_INFO: u64 = 1;
+    /// Copy the [`FbRect`](super::FbRect) at the address given (the whole
+    /// screen for `0`) from the back buffer to the screen; returns the
+    /// number of pixels copied.
+    pub const FB_PRESENT: u64 = 2;
+}
+
+/// The layout of the framebuffer's back buffer, as stored by
+/// [`fb::FB_GET_INFO`].
+///
+/// Pixels are `u32`s, row after row, holding the color channels at their
+/// masks; bits outside the masks must be set as in [`opaque`](Self::opaque).
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+#[repr(C)]
+pub struct FbInfo {
+    /// Visible pixels per row.
+    pub width: u32,
+    /// Rows.
+    pub height: u32,
+    /// Pixels from the start of one row to the next.
+    pub stride: u32,
+    /// Bits of the red channel.
+    pub red_mask: u32,
+    /// Bits of the green channel.
+    pub green_mask: u32,
+    /// Bits of the blue channel.
+    pub blue_mask: u32,
+    /// Bits set in every pixel.
+    pub opaque: u32,
+    pub reserved: u32,
+    /// Bytes of the back buffer to map, a multiple of the page size.
+    pub len: u64,
+}
+
+// The kernel copies the record byte by byte; there must be no padding.
+const _: () = assert!(size_of::<FbInfo>() == 8 * 4 + 8);
+
+impl FbInfo {
+    /// Pack the 8-bit components `r`, `g` and `b` into a pixel.
+    #[must_use]
+    pub const fn pack(&self, r: u8, g: u8, b: u8) -> u32 {
+        const fn channel(mask: u32, value: u8) -> u32 {
+            if mask == 0 {
+                return 0;
+            }
+            let shift = mask.trailing_zeros();
+            let bits = (mask >> shift).trailing_ones();
+            let value = value as u32;
+            let value = if bits >= 8 {
+                value << (bits - 8)
+            } else {
+                value >> (8 - bits)
+            };
+            (value << shift) & mask
+        }
+        channel(self.red_mask, r)
+            | channel(self.green_mask, g)
+            | channel(self.blue_mask, b)
+            | self.opaque
+    }
+}
+
+/// A rectangle of the screen, as taken by [`fb::FB_PRESENT`].
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+#[repr(C)]
+pub struct FbRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Thread-local storage set up by the kernel for every new process.
+///
+/// The layout is described in the `tls` module of the standard library.
+pub mod tls {
+    /// Size of the thread control block at the thread pointer.
+    pub const TCB_SIZE: u64 = 64;
+}
+
+/// Values of the [`TaskInfo`] fields returned by [`Sysno::TaskInfo`].
+pub mod task {
+    /// Bytes kept of a task name.
+    pub const NAME_LEN: usize = 16;
+    /// [`cpu`](super::TaskInfo::cpu) of a task that never ran.
+    pub const NO_CPU: u32 = u32::MAX;
+    /// Affinity of a task that may run on every CPU.
+    pub const ALL_CPUS: u64 = u64::MAX;
+
+    /// The task is a user process.
+    pub const KIND_USER: u32 = 0;
+    /// The task is a kernel thread.
+    pub const KIND_KERNEL: u32 = 1;
+
+    /// Waiting for a CPU.
+    pub const STATE_READY: u32 = 0;
+    /// Running on a CPU.
+    pub const STATE_RUNNING: u32 = 1;
+    /// Blocked, e.g. in `waitpid` or on a pipe.
+    pub const STATE_BLOCKED: u32 = 2;
+    /// Exited, waiting to be reaped.
+    pub const STATE_ZOMBIE: u32 = 3;
+}
+
+/// A snapshot of one task, as stored by [`Sysno::TaskInfo`].
+///
+/// Every task has a single thread, so [`tid`](Self::tid) equals
+/// [`pid`](Self::pid) for now.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+#[repr(C)]
+pub struct TaskInfo {
+    pub pid: u32,
+    pub tid: u32,
+    /// PID of the parent, or `0` if there is none.
+    pub ppid: u32,
+    /// One of the `task::STATE_*` values.
+    pub state: u32,
+    /// One of the `task::KIND_*` values.
+    pub kind: u32,
+    /// CPU the task last ran on, or [`task::NO_CPU`].
+    pub cpu: u32,
+    /// Effective scheduling priority (see [`priority`]).
+    pub priority: u32,
+    /// Exit code of a zombie; `0` otherwise.
+    pub exit_code: u32,
+    /// Bit mask of the CPUs the task may run on.
+    pub affinity: u64,
+    /// Timer ticks spent running.
+    pub run_ticks: u64,
+    /// Name of the task, padded with zero bytes.
+    pub name: [u8; task::NAME_LEN],
+}
+
+// The kernel copies the record byte by byte; there must be no padding.
+const _: () = assert!(size_of::<TaskInfo>() == 8 * 4 + 2 * 8 + task::NAME_LEN);
+
+impl TaskInfo {
+    /// The task's name.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        let len = self
+            .name
+            .iter()
+            .position(|&b| b == 0)
+            .unwrap_or(self.name.len());
+        core::str::from_utf8(&self.name[..len]).unwrap_or("?")
+    }
+}
+
+/// Values of the [`DirEntry`] fields returned by [`Sysno::ReadDir`].
+pub mod dirent {
+    /// Bytes kept of an entry name.
+    pub const NAME_LEN: usize = 32;
+
+    /// The entry is a file that can be read.
+    pub const KIND_FILE: u32 = 0;
+    /// The entry is a directory.
+    pub const KIND_DIR: u32 = 1;
+    /// The entry is a character device.
+    pub const KIND_DEVICE: u32 = 2;
+}
+
+/// One entry of a directory, as stored by [`Sysno::ReadDir`].
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+#[repr(C)]
+pub struct DirEntry {
+    /// One of the `dirent::KIND_*` values.
+    pub kind: u32,
+    pub reserved: u32,
+    /// Size of a file in bytes; `0` for files generated on every read and
+    /// for anything else.
+    pub size: u64,
+    /// Name of the entry, padded with zero bytes.
+    pub name: [u8; dirent::NAME_LEN],
+}
+
+// The kernel copies the record byte by byte; there must be no padding.
+const _: () = assert!(size_of::<DirEntry>() == 2 * 4 + 8 + dirent::NAME_LEN);
+
+impl DirEntry {
+    /// The entry's name.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        let len = self
+            .name
+            .iter()
+            .position(|&b| b == 0)
+            .unwrap_or(self.name.len());
+        core::str::from_utf8(&self.name[..len]).unwrap_or("?")
+    }
+}
+
+/// The clock page: clock parameters the kernel maps read-only into every
+/// process at [`CLOCK_PAGE_ADDR`], so that reading a clock takes no system
+/// call.
+///
+/// The kernel updates the page like a sequence lock: [`sequence`](Self::sequence)
+/// is odd while an update is in progress and advances with every update. A
+/// reader loads it (`Acquire`), reads the fields, and tries again if the
+/// sequence was odd or has changed since.
+#[derive(Debug, Default)]
+#[repr(C)]
+pub struct ClockPage {
+    /// Odd while the kernel updates the page.
+    pub sequence: AtomicU64,
+    /// TSC frequency in Hz; `0` until calibrated.
+    pub tsc_hz: AtomicU64,
+    /// Seconds since the Unix epoch at [`wall_tsc`](Self::wall_tsc); `0`
+    /// until the wall clock is set.
+    pub wall_secs: AtomicU64,
+    /// TSC value at [`wall_secs`](Self::wall_secs).
+    pub wall_tsc: AtomicU64,
+}
+
+/// Where the [`ClockPage`] is mapped in every process.
+pub const CLOCK_PAGE_ADDR: u64 = 0x0000_7FFF_0000_0000;
+
+/// User context saved on the user stack while a signal handler runs.
+///
+/// The kernel enters a handler as `handler(signo, &mut context)` with the
+/// return address pointing at the restorer registered through
+/// [`Sysno::SigAction`]. The restorer issues [`Sysno::SigReturn`] with the
+/// stack pointer at this structure, which resumes the (possibly modified)
+/// context.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+#[repr(C)]
+pub struct SignalContext {
+    /// The signal being handled.
+    pub signo: u64,
+    pub rax: u64,
+    pub rbx: u64,
+    pub rcx: u64,
+    pub rdx: u64,
+    pub rsi: u64,
+    pub rdi: u64,
+    pub rbp: u64,
+    pub rsp: u64,
+    pub r8: u64,
+    pub r9: u64,
+    pub r10: u64,
+    pub r11: u64,
+    pub r12: u64,
+    pub r13: u64,
+    pub r14: u64,
+    pub r15: u64,
+    pub rip: u64,
+    pub rflags: u64,
+    /// Address of the saved FP/SIMD registers (`XSAVE` or `FXSAVE` layout,
+    /// 64-byte aligned), which are restored along with the context.
+    pub fpu: u64,
 }
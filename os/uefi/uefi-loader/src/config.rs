@@ -0,0 +1,205 @@
+//! # Loader Configuration
+//!
+//! Boot options are read from the optional text file `\EFI\Boot\boot.cfg` on
+//! the ESP. Each line holds one `key = value` pair; blank lines and lines
+//! starting with `#` are ignored. Unknown keys and malformed values are
+//! reported and otherwise ignored, so a broken file never prevents booting.
+//!
+//! ## Options
+//!
+//! | Key                  | Value                                            | Default                |
+//! |----------------------|--------------------------------------------------|------------------------|
+//! | `kernel`             | Path of the kernel ELF on the ESP                | `\EFI\Boot\kernel.elf` |
+//! | `log_level`          | `off`, `error`, `warn`, `info`, `debug`, `trace` | `debug`                |
+//! | `kaslr`              | `on`/`off` (also `true`/`false`, `1`/`0`)        | `off`                  |
+//! | `resolution`         | `WIDTHxHEIGHT` or `auto`                         | `1920x1080`            |
+//! | `cmdline`            | Kernel command line, passed on verbatim          | empty                  |
+//! | `module`             | `NAME:PATH`, may be repeated                     | none                   |
+//! | `verify_page_tables` | `on`/`off`                                       | `off`                  |
+//!
+//! Each `module` is loaded into memory and handed to the kernel as a
+//! [`BootModule`](kernel_info::boot::BootModule) under its name, up to
+//! [`MAX_BOOT_MODULES`] of them. A module named `userland` replaces the
+//! default userland bundle `\EFI\Boot\user.bundle`; one named `ksyms` carries
+//! the kernel symbol table written by `packer symbols`.
+//!
+//! `verify_page_tables = on` walks the new page tables before the switch and
+//! refuses to boot if anything the switch depends on is missing; see
+//! [`verify`](crate::verify).
+//!
+//! The kernel is linked to a fixed address, so `kaslr = on` is accepted but
+//! only reported as unsupported for now.
+
+use crate::file_system::try_load_file;
+use alloc::string::String;
+use alloc::vec::Vec;
+use kernel_info::boot::{BOOT_MODULE_NAME_LEN, MAX_BOOT_MODULES};
+use log::{LevelFilter, info, warn};
+use uefi::{CString16, cstr16};
+
+/// A display resolution in pixels.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Resolution {
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Resolution {
+    /// Parse `WIDTHxHEIGHT`.
+    fn parse(value: &str) -> Option<Self> {
+        let (width, height) = value.split_once(['x', 'X'])?;
+        let width = width.trim().parse().ok()?;
+        let height = height.trim().parse().ok()?;
+        (width > 0 && height > 0).then_some(Self { width, height })
+    }
+}
+
+/// A file to load for the kernel.
+#[derive(Debug, Clone)]
+pub struct ModuleSpec {
+    pub name: String,
+    pub path: CString16,
+}
+
+impl ModuleSpec {
+    /// Parse `NAME:PATH`.
+    fn parse(value: &str) -> Option<Self> {
+        let (name, path) = value.split_once(':')?;
+        let (name, path) = (name.trim(), path.trim());
+        if name.is_empty() || name.len() > BOOT_MODULE_NAME_LEN || path.is_empty() {
+            return None;
+        }
+        Some(Self {
+            name: String::from(name),
+            path: CString16::try_from(path).ok()?,
+        })
+    }
+}
+
+/// Options read from `boot.cfg`; see the [module docs](self).
+#[derive(Debug, Clone)]
+pub struct LoaderConfig {
+    /// Path of the kernel image on the ESP.
+    pub kernel: CString16,
+    /// Most verbose log level the loader prints.
+    pub log_level: LevelFilter,
+    /// Whether to randomize the kernel's load address.
+    pub kaslr: bool,
+    /// Resolution to switch to if the firmware offers it; `None` picks the
+    /// largest mode.
+    pub resolution: Option<Resolution>,
+    /// Command line handed to the kernel.
+    pub cmdline: String,
+    /// Additional files to load.
+    pub modules: Vec<ModuleSpec>,
+    /// Whether to check the kernel page tables before switching to them.
+    pub verify_page_tables: bool,
+}
+
+impl Default for LoaderConfig {
+    fn default() -> Self {
+        Self {
+            kernel: CString16::from(cstr16!("\\EFI\\Boot\\kernel.elf")),
+            log_level: LevelFilter::Debug,
+            kaslr: false,
+            resolution: Some(Resolution {
+                width: 1920,
+                height: 1080,
+            }),
+            cmdline: String::new(),
+            modules: Vec::new(),
+            verify_page_tables: false,
+        }
+    }
+}
+
+impl LoaderConfig {
+    /// The module with the given name, if configured.
+    pub fn module(&self, name: &str) -> Option<&ModuleSpec> {
+        self.modules.iter().find(|m| m.name == name)
+    }
+
+    /// Read `\EFI\Boot\boot.cfg`, falling back to the defaults if it is
+    /// missing or unreadable.
+    pub fn load() -> Self {
+        match try_load_file(cstr16!("\\EFI\\Boot\\boot.cfg")) {
+            Ok(Some(bytes)) => {
+                info!("Loaded {size} bytes of boot.cfg", size = bytes.len());
+                match core::str::from_utf8(&bytes) {
+                    Ok(text) => Self::parse(text),
+                    Err(e) => {
+                        warn!("boot.cfg is not valid UTF-8 ({e}); using defaults");
+                        Self::default()
+                    }
+                }
+            }
+            Ok(None) => {
+                info!("No boot.cfg found; using defaults");
+                Self::default()
+            }
+            Err(status) => {
+                warn!("Failed to read boot.cfg ({status:?}); using defaults");
+                Self::default()
+            }
+        }
+    }
+
+    /// Parse the contents of a configuration file.
+    pub fn parse(text: &str) -> Self {
+        let mut config = Self::default();
+        for (number, line) in text.lines().enumerate().map(|(i, l)| (i + 1, l.trim())) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                warn!("boot.cfg:{number}: expected `key = value`, ignoring");
+                continue;
+            };
+            let (key, value) = (key.trim(), value.trim());
+
+            match key {
+                "kernel" => match CString16::try_from(value) {
+                    Ok(path) if !value.is_empty() => config.kernel = path,
+                    _ => warn!("boot.cfg:{number}: invalid kernel path `{value}`, ignoring"),
+                },
+                "log_level" => match value.parse() {
+                    Ok(level) => config.log_level = level,
+                    Err(_) => warn!("boot.cfg:{number}: invalid log level `{value}`, ignoring"),
+                },
+                "kaslr" => match parse_bool(value) {
+                    Some(kaslr) => config.kaslr = kaslr,
+                    None => warn!("boot.cfg:{number}: invalid switch `{value}`, ignoring"),
+                },
+                "resolution" if value.eq_ignore_ascii_case("auto") => config.resolution = None,
+                "resolution" => match Resolution::parse(value) {
+                    Some(resolution) => config.resolution = Some(resolution),
+                    None => warn!("boot.cfg:{number}: invalid resolution `{value}`, ignoring"),
+                },
+                "cmdline" => config.cmdline = String::from(value),
+                "verify_page_tables" => match parse_bool(value) {
+                    Some(verify) => config.verify_page_tables = verify,
+                    None => warn!("boot.cfg:{number}: invalid switch `{value}`, ignoring"),
+                },
+                "module" if config.modules.len() == MAX_BOOT_MODULES => {
+                    warn!("boot.cfg:{number}: more than {MAX_BOOT_MODULES} modules, ignoring");
+                }
+                "module" => match ModuleSpec::parse(value) {
+                    Some(module) => config.modules.push(module),
+                    None => warn!("boot.cfg:{number}: invalid module `{value}`, ignoring"),
+                },
+                _ => warn!("boot.cfg:{number}: unknown option `{key}`, ignoring"),
+            }
+        }
+        config
+    }
+}
+
+/// Parse an on/off switch.
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "on" | "true" | "yes" | "1" => Some(true),
+        "off" | "false" | "no" | "0" => Some(false),
+        _ => None,
+    }
+}
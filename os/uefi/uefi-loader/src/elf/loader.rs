@@ -7,30 +7,61 @@ use alloc::vec::Vec;
 use core::ptr;
 use kernel_info::memory::{KERNEL_BASE, PHYS_LOAD};
 use kernel_memory_addresses::{
-    MemoryAddress, PageSize, PhysicalAddress, PhysicalPage, Size4K, VirtualPage,
+    MemoryAddress, PageSize, PhysicalAddress, PhysicalPage, Size4K, VirtualAddress, VirtualPage,
 };
 use uefi::Status;
 use uefi::boot::{self, AllocateType, MemoryType};
 
+/// Why a `PT_LOAD` segment could not be loaded; each error names the
+/// segment's virtual address.
 #[derive(Debug, thiserror::Error)]
 pub enum ElfLoaderError {
     #[error("A pointer arithmetic operation failed due to an underflow or overflow")]
-    PointerArithmetic,
+    PointerArithmetic { vaddr: VirtualAddress },
     #[error("A provided memory address is out of bounds for the architecture")]
-    AddressOutOfBounds,
-    #[error("An physical memory allocation failed")]
-    PhysicalAllocationFailed(#[source] uefi::Error),
-    #[error("The ELF segment size does not match the program size")]
-    ElfSizeMismatch,
+    AddressOutOfBounds { vaddr: VirtualAddress },
+    #[error("Allocating {pages} pages at {phys} failed")]
+    PhysicalAllocationFailed {
+        vaddr: VirtualAddress,
+        phys: PhysicalAddress,
+        pages: usize,
+        #[source]
+        source: uefi::Error,
+    },
+    #[error("The segment's file range {offset:#x}+{len:#x} exceeds the {file_len}-byte file")]
+    ElfSizeMismatch {
+        vaddr: VirtualAddress,
+        offset: u64,
+        len: u64,
+        file_len: usize,
+    },
+}
+
+impl ElfLoaderError {
+    /// Virtual address of the segment that failed to load.
+    pub const fn vaddr(&self) -> VirtualAddress {
+        match self {
+            Self::PointerArithmetic { vaddr }
+            | Self::AddressOutOfBounds { vaddr }
+            | Self::PhysicalAllocationFailed { vaddr, .. }
+            | Self::ElfSizeMismatch { vaddr, .. } => *vaddr,
+        }
+    }
 }
 
 impl From<ElfLoaderError> for Status {
     fn from(value: ElfLoaderError) -> Self {
+        Self::from(&value)
+    }
+}
+
+impl From<&ElfLoaderError> for Status {
+    fn from(value: &ElfLoaderError) -> Self {
         match value {
-            ElfLoaderError::PhysicalAllocationFailed(_) => Self::BUFFER_TOO_SMALL,
-            ElfLoaderError::PointerArithmetic
-            | ElfLoaderError::AddressOutOfBounds
-            | ElfLoaderError::ElfSizeMismatch => Self::BAD_BUFFER_SIZE,
+            ElfLoaderError::PhysicalAllocationFailed { .. } => Self::BUFFER_TOO_SMALL,
+            ElfLoaderError::PointerArithmetic { .. }
+            | ElfLoaderError::AddressOutOfBounds { .. }
+            | ElfLoaderError::ElfSizeMismatch { .. } => Self::BAD_BUFFER_SIZE,
         }
     }
 }
@@ -50,18 +81,15 @@ pub fn load_pt_load_segments_hi(
 
         // LMA math
         let seg_vaddr = seg.vaddr;
+        let overflow = || ElfLoaderError::PointerArithmetic { vaddr: seg_vaddr };
+        let out_of_bounds = || ElfLoaderError::AddressOutOfBounds { vaddr: seg_vaddr };
         let lma = seg_vaddr
             .as_u64()
             .checked_sub(KERNEL_BASE.as_u64())
-            .ok_or(ElfLoaderError::PointerArithmetic)?;
+            .ok_or_else(overflow)?;
 
-        let phys_start = PHYS_LOAD
-            .as_u64()
-            .checked_add(lma)
-            .ok_or(ElfLoaderError::PointerArithmetic)?;
-        let phys_end = phys_start
-            .checked_add(seg.memsz)
-            .ok_or(ElfLoaderError::PointerArithmetic)?;
+        let phys_start = PHYS_LOAD.as_u64().checked_add(lma).ok_or_else(overflow)?;
+        let phys_end = phys_start.checked_add(seg.memsz).ok_or_else(overflow)?;
 
         // Page-rounded allocation window (physical)
         let alloc_start = MemoryAddress::new(phys_start)
@@ -78,12 +106,17 @@ pub fn load_pt_load_segments_hi(
 
         // Reserve at the *physical address* we computed (UEFI AllocatePages at address)
         let ptr = boot::allocate_pages(AllocateType::Address(alloc_start), mem_type, pages)
-            .map_err(ElfLoaderError::PhysicalAllocationFailed)?;
+            .map_err(|source| ElfLoaderError::PhysicalAllocationFailed {
+                vaddr: seg_vaddr,
+                phys: PhysicalAddress::new(alloc_start),
+                pages,
+                source,
+            })?;
         // This is a physical address returned by UEFI:
         let phys_base = PhysicalAddress::from_nonnull(ptr);
 
         // Zero full in-memory size (BSS tail)
-        let mem_len = usize::try_from(seg.memsz).map_err(|_| ElfLoaderError::AddressOutOfBounds)?;
+        let mem_len = usize::try_from(seg.memsz).map_err(|_| out_of_bounds())?;
         let in_seg_off = phys_start - alloc_start; // offset *within first page* to seg start
         let dst = (phys_base.as_u64() + in_seg_off) as *mut u8;
         unsafe {
@@ -92,15 +125,16 @@ pub fn load_pt_load_segments_hi(
 
         // Copy file payload (if any)
         if seg.filesz != 0 {
-            let src_off =
-                usize::try_from(seg.offset).map_err(|_| ElfLoaderError::AddressOutOfBounds)?;
-            let file_len =
-                usize::try_from(seg.filesz).map_err(|_| ElfLoaderError::AddressOutOfBounds)?;
-            let src_end = src_off
-                .checked_add(file_len)
-                .ok_or(ElfLoaderError::PointerArithmetic)?;
+            let src_off = usize::try_from(seg.offset).map_err(|_| out_of_bounds())?;
+            let file_len = usize::try_from(seg.filesz).map_err(|_| out_of_bounds())?;
+            let src_end = src_off.checked_add(file_len).ok_or_else(overflow)?;
             if src_end > elf_bytes.len() {
-                return Err(ElfLoaderError::ElfSizeMismatch);
+                return Err(ElfLoaderError::ElfSizeMismatch {
+                    vaddr: seg_vaddr,
+                    offset: seg.offset,
+                    len: seg.filesz,
+                    file_len: elf_bytes.len(),
+                });
             }
             unsafe {
                 ptr::copy_nonoverlapping(elf_bytes.as_ptr().add(src_off), dst, file_len);
@@ -112,7 +146,7 @@ pub fn load_pt_load_segments_hi(
         let vaddr_end_u64 = seg_vaddr
             .as_u64()
             .checked_add(seg.memsz)
-            .ok_or(ElfLoaderError::PointerArithmetic)?;
+            .ok_or_else(overflow)?;
         let vaddr_end_aligned = align_up_u64(vaddr_end_u64, Size4K::SIZE);
         let map_len = vaddr_end_aligned - vaddr_page.base().as_u64();
 
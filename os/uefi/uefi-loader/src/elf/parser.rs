@@ -6,7 +6,6 @@ use alloc::vec::Vec;
 use core::mem::size_of;
 use core::ptr::read_unaligned;
 use kernel_memory_addresses::{PhysicalAddress, VirtualAddress};
-use uefi::Status;
 
 // Minimal ELF64 definitions
 #[repr(C)]
@@ -57,6 +56,25 @@ pub struct LoadSegment {
     pub align: u64,
 }
 
+/// Why a kernel image is not a loadable x86-64 ELF64 file.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, thiserror::Error)]
+pub enum ElfParseError {
+    #[error("file is {len} bytes, too short for an ELF header")]
+    TooShort { len: usize },
+    #[error("no ELF magic")]
+    BadMagic,
+    #[error(
+        "not a little-endian ELF64 version 1 file (class {class}, data {data}, version {version})"
+    )]
+    UnsupportedIdent { class: u8, data: u8, version: u8 },
+    #[error("machine {0} is not x86-64")]
+    WrongMachine(u16),
+    #[error("program header entries are {found} bytes, expected {expected}")]
+    PhdrSizeMismatch { expected: usize, found: usize },
+    #[error("program header table at {offset:#x} ({size} bytes) exceeds the {len}-byte file")]
+    PhdrTableOutOfBounds { offset: u64, size: u64, len: usize },
+}
+
 #[derive(Debug)]
 pub struct ElfHeader {
     pub entry: VirtualAddress,
@@ -67,11 +85,13 @@ impl ElfHeader {
     const EI_MAGIC_BYTES: [u8; 4] = [0x7F, b'E', b'L', b'F'];
 
     /// Parse a 64-bit little-endian x86-64 ELF image and collect `PT_LOAD` segments.
-    /// Returns `Status::UNSUPPORTED` for any validation or bounds failure.
-    pub fn parse_elf64(bytes: &[u8]) -> Result<Self, Status> {
+    ///
+    /// # Errors
+    /// An [`ElfParseError`] for any validation or bounds failure.
+    pub fn parse_elf64(bytes: &[u8]) -> Result<Self, ElfParseError> {
         // Bounds for header
         if bytes.len() < size_of::<Elf64Ehdr>() {
-            return Err(Status::UNSUPPORTED);
+            return Err(ElfParseError::TooShort { len: bytes.len() });
         }
 
         // SAFETY: We just checked bounds; using read_unaligned to avoid alignment assumptions.
@@ -79,31 +99,43 @@ impl ElfHeader {
 
         // Validate magic 0x7F 'E''L''F'
         if ehdr.e_ident[0..4] != Self::EI_MAGIC_BYTES {
-            return Err(Status::UNSUPPORTED);
+            return Err(ElfParseError::BadMagic);
         }
         // Class = 2 (ELF64), Data = 1 (little-endian), Version = 1
         if ehdr.e_ident[4] != 2 || ehdr.e_ident[5] != 1 || ehdr.e_ident[6] != 1 {
-            return Err(Status::UNSUPPORTED);
+            return Err(ElfParseError::UnsupportedIdent {
+                class: ehdr.e_ident[4],
+                data: ehdr.e_ident[5],
+                version: ehdr.e_ident[6],
+            });
         }
 
         if ehdr.e_machine != EM_X86_64 {
-            return Err(Status::UNSUPPORTED);
+            return Err(ElfParseError::WrongMachine(ehdr.e_machine));
         }
 
         if ehdr.e_phentsize as usize != size_of::<Elf64Phdr>() {
-            return Err(Status::UNSUPPORTED);
+            return Err(ElfParseError::PhdrSizeMismatch {
+                expected: size_of::<Elf64Phdr>(),
+                found: ehdr.e_phentsize as usize,
+            });
         }
 
         // Program header table bounds
-        let phoff = usize::try_from(ehdr.e_phoff).map_err(|_| Status::UNSUPPORTED)?;
         let phentsize = ehdr.e_phentsize as usize;
         let phnum = ehdr.e_phnum as usize;
+        let out_of_bounds = ElfParseError::PhdrTableOutOfBounds {
+            offset: ehdr.e_phoff,
+            size: (phentsize * phnum) as u64,
+            len: bytes.len(),
+        };
+        let phoff = usize::try_from(ehdr.e_phoff).map_err(|_| out_of_bounds)?;
 
         // Compute end of the table and check overflow/bounds
-        let table_size = phentsize.checked_mul(phnum).ok_or(Status::UNSUPPORTED)?;
-        let end = phoff.checked_add(table_size).ok_or(Status::UNSUPPORTED)?;
+        let table_size = phentsize.checked_mul(phnum).ok_or(out_of_bounds)?;
+        let end = phoff.checked_add(table_size).ok_or(out_of_bounds)?;
         if end > bytes.len() {
-            return Err(Status::UNSUPPORTED);
+            return Err(out_of_bounds);
         }
 
         let mut segments = Vec::new();
@@ -0,0 +1,18 @@
+//! # Boot Entropy
+//!
+//! Random bytes from the firmware's `EFI_RNG_PROTOCOL`, passed to the kernel
+//! to seed its random number generator. Firmware without the protocol (e.g.
+//! OVMF without a `virtio-rng` device) passes nothing, and the kernel makes
+//! do with the CPU's sources.
+
+use uefi::boot;
+use uefi::proto::rng::Rng;
+
+/// 32 random bytes from the firmware, if it can provide them.
+pub fn boot_seed() -> Option<[u8; 32]> {
+    let handle = boot::get_handle_for_protocol::<Rng>().ok()?;
+    let mut rng = boot::open_protocol_exclusive::<Rng>(handle).ok()?;
+    let mut seed = [0u8; 32];
+    rng.get_rng(None, &mut seed).ok()?;
+    Some(seed)
+}
@@ -0,0 +1,129 @@
+//! # Boot Errors
+//!
+//! Every step of [`efi_main`](crate::efi_main) that can fail reports a
+//! [`BootError`]: the [`BootStage`] that failed, the address involved (if
+//! any), and the cause, i.e. the stage's own error type or a plain UEFI
+//! [`Status`]. [`BootError::log`] writes all of it, including the chain of
+//! underlying errors, before the loader returns [`BootError::status`] to the
+//! firmware.
+
+use crate::elf::loader::ElfLoaderError;
+use crate::elf::parser::ElfParseError;
+use crate::verify::PageTableCheckError;
+use crate::vmem::KernelPageTableError;
+use core::error::Error;
+use core::fmt;
+use log::error;
+use uefi::Status;
+
+/// A step of the boot sequence.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum BootStage {
+    LoadKernel,
+    ParseKernel,
+    LoadSegments,
+    LoadModules,
+    LoadUserBundle,
+    Framebuffer,
+    PageTables,
+    VerifyPageTables,
+    ExitBootServices,
+}
+
+impl BootStage {
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::LoadKernel => "loading the kernel image",
+            Self::ParseKernel => "parsing the kernel ELF",
+            Self::LoadSegments => "loading kernel segments",
+            Self::LoadModules => "loading boot modules",
+            Self::LoadUserBundle => "loading the userland bundle",
+            Self::Framebuffer => "setting up the framebuffer",
+            Self::PageTables => "building kernel page tables",
+            Self::VerifyPageTables => "verifying kernel page tables",
+            Self::ExitBootServices => "exiting boot services",
+        }
+    }
+}
+
+impl fmt::Display for BootStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// What went wrong in a [`BootStage`].
+#[derive(Debug, thiserror::Error)]
+pub enum BootErrorCause {
+    #[error("UEFI error {0:?}")]
+    Status(Status),
+    #[error(transparent)]
+    Elf(#[from] ElfParseError),
+    #[error(transparent)]
+    Loader(#[from] ElfLoaderError),
+    #[error(transparent)]
+    PageTable(#[from] KernelPageTableError),
+    #[error(transparent)]
+    PageTableCheck(#[from] PageTableCheckError),
+}
+
+impl From<Status> for BootErrorCause {
+    fn from(status: Status) -> Self {
+        Self::Status(status)
+    }
+}
+
+/// A failed [`BootStage`]; see the [module docs](self).
+#[derive(Debug, thiserror::Error)]
+#[error("{stage} failed: {cause}")]
+pub struct BootError {
+    pub stage: BootStage,
+    /// The address the failure is about, if any.
+    pub address: Option<u64>,
+    #[source]
+    pub cause: BootErrorCause,
+}
+
+impl BootError {
+    /// A failure of `stage`; takes the address from `cause` where it has one.
+    pub fn new(stage: BootStage, cause: impl Into<BootErrorCause>) -> Self {
+        let cause = cause.into();
+        let address = match &cause {
+            BootErrorCause::Loader(e) => Some(e.vaddr().as_u64()),
+            BootErrorCause::PageTable(e) => e.address(),
+            BootErrorCause::PageTableCheck(e) => Some(e.address().as_u64()),
+            BootErrorCause::Status(_) | BootErrorCause::Elf(_) => None,
+        };
+        Self {
+            stage,
+            address,
+            cause,
+        }
+    }
+
+    /// The status returned to the firmware.
+    pub fn status(&self) -> Status {
+        match &self.cause {
+            BootErrorCause::Status(status) => *status,
+            BootErrorCause::Elf(_) => Status::UNSUPPORTED,
+            BootErrorCause::Loader(e) => e.into(),
+            BootErrorCause::PageTable(_) => Status::OUT_OF_RESOURCES,
+            BootErrorCause::PageTableCheck(_) => Status::LOAD_ERROR,
+        }
+    }
+
+    /// Log the stage, address and cause, followed by the underlying errors.
+    pub fn log(&self) {
+        match self.address {
+            Some(address) => error!("Boot failed while {} at {address:#018x}", self.stage),
+            None => error!("Boot failed while {}", self.stage),
+        }
+        error!("  cause: {}", self.cause);
+        let mut source = self.cause.source();
+        while let Some(e) = source {
+            error!("  caused by: {e}");
+            source = e.source();
+        }
+        uefi::println!("{self}");
+    }
+}
@@ -10,6 +10,17 @@ use uefi::{CStr16, Status, boot};
 /// # Error
 /// Returns a [`Status`] in case of error.
 pub fn load_file(path: &CStr16) -> Result<Vec<u8>, Status> {
+    try_load_file(path)?.ok_or_else(|| {
+        uefi::println!("Failed to read file: {path} not found");
+        Status::NOT_FOUND
+    })
+}
+
+/// Loads a file from the EFI file system if it exists.
+///
+/// # Error
+/// Returns a [`Status`] in case of any error other than the file not existing.
+pub fn try_load_file(path: &CStr16) -> Result<Option<Vec<u8>>, Status> {
     let image_handle = boot::image_handle();
     let mut sfs = match boot::get_image_file_system(image_handle) {
         Ok(fs) => fs,
@@ -29,6 +40,7 @@ pub fn load_file(path: &CStr16) -> Result<Vec<u8>, Status> {
 
     let handle = match volume.open(path, FileMode::Read, FileAttribute::empty()) {
         Ok(handle) => handle,
+        Err(e) if e.status() == Status::NOT_FOUND => return Ok(None),
         Err(e) => {
             uefi::println!("Failed to read file: {e:?}");
             return Err(Status::UNSUPPORTED);
@@ -79,5 +91,5 @@ pub fn load_file(path: &CStr16) -> Result<Vec<u8>, Status> {
         return Err(Status::UNSUPPORTED);
     }
 
-    Ok(buf)
+    Ok(Some(buf))
 }
@@ -1,13 +1,30 @@
 //! # GOP for the Kernel
+//!
+//! ## Mode selection
+//!
+//! The firmware's active mode is often a small default (e.g. 800x600 or
+//! 1024x768). [`get_framebuffer`] enumerates all GOP modes and switches to
+//! 1. the preferred resolution, if a qualifying mode offers it, or else
+//! 2. the qualifying mode with the largest area.
+//!
+//! A mode qualifies if it has a linear framebuffer with 32-bit pixels; `BltOnly`
+//! and 16/24-bit bitmask modes are skipped. If no mode qualifies or switching
+//! fails, the active mode is kept. The choice is recorded in the
+//! [`FramebufferInfo`] handed to the kernel.
 
-use kernel_info::boot::{BootPixelFormat, BootPixelMasks, FramebufferInfo};
-use log::{error, info};
+extern crate alloc;
+
+use crate::config::Resolution;
+use alloc::vec::Vec;
+use kernel_info::boot::{BootModeSelection, BootPixelFormat, BootPixelMasks, FramebufferInfo};
+use log::{debug, error, info, warn};
 use uefi::boot::ScopedProtocol;
-use uefi::proto::console::gop::{GraphicsOutput, PixelFormat};
+use uefi::proto::console::gop::{GraphicsOutput, Mode, ModeInfo, PixelFormat};
 use uefi::{Status, boot};
 
-/// Fetch an optimal framebuffer for the Kernel.
-pub fn get_framebuffer() -> Result<FramebufferInfo, Status> {
+/// Fetch an optimal framebuffer for the Kernel, preferring `preferred` if set;
+/// see the [module docs](self).
+pub fn get_framebuffer(preferred: Option<Resolution>) -> Result<FramebufferInfo, Status> {
     info!("Obtaining Graphics Output Protocol (GOP)");
     let mut gop = match get_gop() {
         Ok(gop) => gop,
@@ -17,6 +34,24 @@ pub fn get_framebuffer() -> Result<FramebufferInfo, Status> {
         }
     };
 
+    let modes: Vec<Mode> = gop.modes().collect();
+    let mut selection = BootModeSelection::Firmware;
+    if let Some((mode, chosen)) = select_mode(&modes, preferred) {
+        let (width, height) = mode.info().resolution();
+        if *mode.info() == gop.current_mode_info() {
+            info!("Keeping active GOP mode {width}x{height} ({chosen:?})");
+            selection = chosen;
+        } else {
+            info!("Switching to GOP mode {width}x{height} ({chosen:?})");
+            match gop.set_mode(mode) {
+                Ok(()) => selection = chosen,
+                Err(e) => warn!("Failed to set GOP mode, keeping the active one: {e:?}"),
+            }
+        }
+    } else {
+        warn!("No 32-bit linear GOP mode found, keeping the active one");
+    }
+
     let mode = gop.current_mode_info();
     let (framebuffer_width, framebuffer_height) = mode.resolution();
 
@@ -70,11 +105,63 @@ pub fn get_framebuffer() -> Result<FramebufferInfo, Status> {
         framebuffer_stride: framebuffer_stride as u64,
         framebuffer_format,
         framebuffer_masks,
+        framebuffer_mode_count: u32::try_from(modes.len()).unwrap_or(u32::MAX),
+        framebuffer_selection: selection,
     };
 
     Ok(fb)
 }
 
+/// Pick the mode to use from `modes`; see the [module docs](self).
+fn select_mode(
+    modes: &[Mode],
+    preferred: Option<Resolution>,
+) -> Option<(&Mode, BootModeSelection)> {
+    for mode in modes {
+        let info = mode.info();
+        let (width, height) = info.resolution();
+        debug!(
+            "GOP mode {width}x{height}, stride {stride}, {format:?}{usable}",
+            stride = info.stride(),
+            format = info.pixel_format(),
+            usable = if is_usable(info) { "" } else { " (unusable)" },
+        );
+    }
+
+    let mut usable = modes.iter().filter(|m| is_usable(m.info()));
+    if let Some(preferred) = preferred {
+        let wanted = (preferred.width, preferred.height);
+        if let Some(mode) = usable.clone().find(|m| m.info().resolution() == wanted) {
+            return Some((mode, BootModeSelection::Preferred));
+        }
+        info!(
+            "Preferred resolution {w}x{h} is not available",
+            w = preferred.width,
+            h = preferred.height
+        );
+    }
+
+    usable
+        .by_ref()
+        .max_by_key(|m| {
+            let (width, height) = m.info().resolution();
+            width.saturating_mul(height)
+        })
+        .map(|mode| (mode, BootModeSelection::Largest))
+}
+
+/// Whether the kernel can draw into `info`'s framebuffer: linear, 32 bits per pixel.
+fn is_usable(info: &ModeInfo) -> bool {
+    match info.pixel_format() {
+        PixelFormat::Rgb | PixelFormat::Bgr => true,
+        PixelFormat::Bitmask => info.pixel_bitmask().is_some_and(|m| {
+            let all = m.red | m.green | m.blue | m.reserved;
+            all.leading_zeros() == 0
+        }),
+        PixelFormat::BltOnly => false,
+    }
+}
+
 /// Fetch the Graphics Output Protocol (GOP).
 fn get_gop() -> Result<ScopedProtocol<GraphicsOutput>, uefi::Error> {
     let handle = boot::get_handle_for_protocol::<GraphicsOutput>().map_err(|e| {
@@ -36,6 +36,11 @@ impl UefiLogger {
         unsafe { Ok(LOGGER.as_mut().expect("initialized")) }
     }
 
+    /// Only log records up to `max_level` from now on.
+    pub const fn set_max_level(&mut self, max_level: LevelFilter) {
+        self.max_level = max_level;
+    }
+
     pub const fn exit_boot_services(&mut self) {
         self.boot_services_available = false;
     }
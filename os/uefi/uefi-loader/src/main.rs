@@ -73,7 +73,7 @@
 //!
 //! ### System Information Gathering
 //! * **UEFI Memory Map**: Capture complete physical memory layout
-//! * **Graphics Configuration**: Obtain GOP framebuffer details
+//! * **Graphics Configuration**: Select the best GOP mode and obtain its framebuffer details
 //! * **ACPI Discovery**: Locate RSDP for hardware enumeration
 //! * **Boot Information**: Package data for kernel consumption
 //!
@@ -182,6 +182,10 @@
 //! * **Resource Cleanup**: Ensure proper cleanup on failure paths
 //! * **Status Reporting**: Provide meaningful error codes to firmware
 //!
+//! Each failing step yields a [`BootError`](error::BootError) naming the boot
+//! stage, the address involved and the cause; it is logged before the loader
+//! returns its status to the firmware.
+//!
 //! ## Security Considerations
 //!
 //! ### Memory Protection
@@ -200,6 +204,8 @@
 //! * **Debug Logging**: Comprehensive trace output for development
 //! * **Memory Layout Tracing**: Detailed memory allocation information
 //! * **Boot Information**: Complete system state at kernel handoff
+//! * **Page Table Verification**: Optionally walk the new page tables before
+//!   switching to them; see [`verify`]
 //! * **Error Diagnostics**: Detailed error reporting for troubleshooting
 //!
 //! ## Standards Compliance
@@ -214,7 +220,10 @@
 #![allow(unsafe_code, dead_code)]
 extern crate alloc;
 
+mod config;
 mod elf;
+mod entropy;
+mod error;
 mod file_system;
 mod framebuffer;
 mod logger;
@@ -222,23 +231,33 @@ mod memory;
 mod rsdp;
 mod tracing;
 mod uefi_mmap;
+mod verify;
 mod vmem;
 
+use crate::config::LoaderConfig;
+use crate::elf::loader::LoadedSegMap;
 use crate::elf::parser::ElfHeader;
+use crate::entropy::boot_seed;
+use crate::error::{BootError, BootStage};
 use crate::file_system::load_file;
 use crate::framebuffer::get_framebuffer;
 use crate::logger::UefiLogger;
-use crate::memory::alloc_trampoline_stack;
+use crate::memory::{alloc_boot_arena, alloc_hhdm_copy, alloc_pstore, alloc_trampoline_stack};
 use crate::rsdp::find_rsdp_addr;
 use crate::tracing::trace_boot_info;
 use crate::uefi_mmap::exit_boot_services;
+use crate::verify::{critical_ranges, verify_page_tables};
 use crate::vmem::create_kernel_pagetables;
 use alloc::boxed::Box;
-use kernel_info::boot::{KernelBootInfo, UefiMemoryMapInfo, UserBundleInfo};
+use core::convert::Infallible;
+use kernel_info::boot::{
+    BOOT_ARENA_SIZE, BootCapabilities, BootInfoHeader, BootModules, KernelBootInfo, KernelSegment,
+    KernelSegments, PSTORE_PHYS, PstoreInfo, UefiMemoryMapInfo, UserBundleInfo,
+};
 use kernel_memory_addresses::{PhysicalAddress, VirtualAddress};
 use kernel_registers::cr0::Cr0;
 use kernel_registers::{LoadRegisterUnsafe, StoreRegisterUnsafe, cr4::Cr4, efer::Efer};
-use log::{LevelFilter, debug, info};
+use log::{LevelFilter, debug, info, warn};
 use uefi::boot::PAGE_SIZE;
 use uefi::cstr16;
 use uefi::prelude::*;
@@ -246,8 +265,28 @@ use uefi::prelude::*;
 // TODO: Add proper documentation.
 const TRAMPOLINE_STACK_SIZE_BYTES: usize = 64 * 1024;
 
+/// Describe the loaded `PT_LOAD` segments for the kernel.
+fn kernel_segment_table(maps: &[LoadedSegMap]) -> KernelSegments {
+    let mut segments = KernelSegments::new();
+    for m in maps {
+        let segment = KernelSegment {
+            virt_start: m.vaddr_page.base().as_u64(),
+            phys_start: m.phys_page.base().as_u64(),
+            length: m.map_len,
+            flags: m.flags.into_bits(),
+        };
+        if !segments.push(segment) {
+            warn!(
+                "More than {} kernel segments; not passing on the rest",
+                segments.as_slice().len()
+            );
+            break;
+        }
+    }
+    segments
+}
+
 #[entry]
-#[allow(clippy::too_many_lines)]
 fn efi_main() -> Status {
     // Initialize logging and allocator helpers
     if uefi::helpers::init().is_err() {
@@ -258,43 +297,74 @@ fn efi_main() -> Status {
     let logger = logger.init().expect("logger init");
 
     info!("UEFI Loader reporting to QEMU");
-    info!("Attempting to load kernel.elf ...");
+    let config = LoaderConfig::load();
+    logger.set_max_level(config.log_level);
+    if config.kaslr {
+        warn!("KASLR requested, but the kernel is linked to a fixed address; ignoring");
+    }
 
-    let elf_bytes = match load_file(cstr16!("\\EFI\\Boot\\kernel.elf")) {
-        Ok(bytes) => {
-            info!("Loaded {size} bytes of kernel.elf", size = bytes.len());
-            bytes
-        }
-        Err(status) => {
-            info!("Failed to load kernel.elf. Exiting.");
-            return status;
+    match boot(&config, logger) {
+        Ok(never) => match never {},
+        Err(e) => {
+            e.log();
+            e.status()
         }
-    };
+    }
+}
+
+/// Load the kernel and everything it needs, then jump into it.
+#[allow(clippy::too_many_lines)]
+fn boot(config: &LoaderConfig, logger: &mut UefiLogger) -> Result<Infallible, BootError> {
+    info!("Attempting to load {} ...", config.kernel);
+
+    let elf_bytes =
+        load_file(&config.kernel).map_err(|s| BootError::new(BootStage::LoadKernel, s))?;
+    info!(
+        "Loaded {size} bytes of {}",
+        config.kernel,
+        size = elf_bytes.len()
+    );
 
     // Parse ELF64, collect PT_LOAD segments and entry address
-    let Ok(parsed) = ElfHeader::parse_elf64(&elf_bytes) else {
-        info!("kernel.elf is not a valid x86_64 ELF64");
-        return Status::UNSUPPORTED;
-    };
+    let parsed = ElfHeader::parse_elf64(&elf_bytes)
+        .map_err(|e| BootError::new(BootStage::ParseKernel, e))?;
 
     info!("Loading kernel segments into memory ...");
-    let kernel_segments = match elf::loader::load_pt_load_segments_hi(&elf_bytes, &parsed) {
-        Ok(segments) => segments,
-        Err(e) => {
-            info!("Failed to load PT_LOAD segments: {e:?}");
-            return e.into();
-        }
-    };
+    let kernel_segments = elf::loader::load_pt_load_segments_hi(&elf_bytes, &parsed)
+        .map_err(|e| BootError::new(BootStage::LoadSegments, e))?;
 
-    info!("Load userland bundle into memory ...");
-    let bun_bytes = match load_file(cstr16!("\\EFI\\Boot\\user.bundle")) {
-        Ok(bytes) => {
-            info!("Loaded {size} bytes of user.bundle", size = bytes.len());
-            bytes
+    info!("Loading boot modules into memory ...");
+    let mut modules = BootModules::new();
+    for module in &config.modules {
+        let bytes = load_file(&module.path).map_err(|s| {
+            info!("Failed to load module {} from {}", module.name, module.path);
+            BootError::new(BootStage::LoadModules, s)
+        })?;
+        info!(
+            "Loaded {size} bytes of module {} from {}",
+            module.name,
+            module.path,
+            size = bytes.len()
+        );
+        let bytes = bytes.leak();
+        modules.push(&module.name, bytes.as_ptr() as u64, bytes.len() as u64);
+    }
+
+    let userland = if config.module("userland").is_some() {
+        info!("Using the userland module instead of user.bundle");
+        UserBundleInfo {
+            bytes_ptr: 0,
+            length: 0,
         }
-        Err(status) => {
-            info!("Failed to load user.bundle. Exiting.");
-            return status;
+    } else {
+        info!("Load userland bundle into memory ...");
+        let bun_bytes = load_file(cstr16!("\\EFI\\Boot\\user.bundle"))
+            .map_err(|s| BootError::new(BootStage::LoadUserBundle, s))?;
+        info!("Loaded {size} bytes of user.bundle", size = bun_bytes.len());
+        let bun_bytes = bun_bytes.leak();
+        UserBundleInfo {
+            bytes_ptr: bun_bytes.as_ptr() as u64,
+            length: bun_bytes.len() as u64,
         }
     };
 
@@ -304,17 +374,41 @@ fn efi_main() -> Status {
         parsed.segments.len()
     );
 
-    let fb = match get_framebuffer() {
-        Ok(fb) => fb,
-        Err(status) => {
-            return status;
-        }
-    };
+    let fb = get_framebuffer(config.resolution)
+        .map_err(|s| BootError::new(BootStage::Framebuffer, s))?;
 
     // Locate RSDP before exiting boot services; if not found, set 0.
     let rsdp_addr: u64 = find_rsdp_addr();
 
+    // Place the command line where the kernel can reach it through the HHDM.
+    let cmdline_ptr = alloc_hhdm_copy(config.cmdline.as_bytes());
+
+    // Memory the kernel allocates from before its frame allocator is up.
+    let arena = alloc_boot_arena(BOOT_ARENA_SIZE);
+
+    // Where the kernel finds the report of its last panic after a warm reboot.
+    let pstore = alloc_pstore();
+    if pstore.is_none() {
+        warn!("Memory at {PSTORE_PHYS:#x} is in use; no pstore for panic reports");
+    }
+
+    // Seed for the kernel's random number generator, if the firmware has one.
+    let seed = boot_seed();
+    if seed.is_none() {
+        info!("No EFI_RNG_PROTOCOL; the kernel seeds its RNG by itself");
+    }
+
+    // Tell the kernel which of the optional sections are filled in.
+    let capabilities = BootCapabilities::ARENA
+        .union_if(BootCapabilities::RSDP, rsdp_addr != 0)
+        .union_if(BootCapabilities::CMDLINE, cmdline_ptr != 0)
+        .union_if(BootCapabilities::MODULES, !modules.as_slice().is_empty())
+        .union_if(BootCapabilities::USERLAND, userland.length != 0)
+        .union_if(BootCapabilities::SEED, seed.is_some())
+        .union_if(BootCapabilities::PSTORE, pstore.is_some());
+
     let boot_info = KernelBootInfo {
+        header: BootInfoHeader::new(capabilities),
         // Memory map fields are filled right after exit_boot_services returns the owned map:
         mmap: UefiMemoryMapInfo {
             mmap_ptr: 0,
@@ -324,10 +418,19 @@ fn efi_main() -> Status {
         },
         rsdp_addr,
         fb,
-        userland: UserBundleInfo {
-            bytes_ptr: bun_bytes.as_ptr() as u64,
-            length: bun_bytes.len() as u64,
-        },
+        userland,
+        cmdline_ptr,
+        cmdline_len: config.cmdline.len() as u64,
+        modules,
+        kernel_segments: kernel_segment_table(&kernel_segments),
+        arena,
+        // The kernel is loaded at its link address.
+        kaslr_slide: 0,
+        seed: seed.unwrap_or_default(),
+        pstore: pstore.unwrap_or(PstoreInfo {
+            phys_start: 0,
+            length: 0,
+        }),
     };
 
     // Heap-allocate and leak the boot info.
@@ -351,23 +454,34 @@ fn efi_main() -> Status {
 
     // Build page tables
     info!("Creating initial kernel page tables ...");
-    let Ok(pml4_phys) = create_kernel_pagetables(
+    let pml4_phys = create_kernel_pagetables(
         &kernel_segments,
         tramp_code_va,
         tramp_code_len,
         tramp_stack_base_phys,
         TRAMPOLINE_STACK_SIZE_BYTES,
         bi_ptr_va,
-    ) else {
-        uefi::println!("Failed to create kernel page tables");
-        return Status::OUT_OF_RESOURCES;
-    };
+    )
+    .map_err(|e| BootError::new(BootStage::PageTables, e))?;
+
+    if config.verify_page_tables {
+        let ranges = critical_ranges(
+            &kernel_segments,
+            parsed.entry,
+            tramp_code_va,
+            tramp_code_len,
+            tramp_stack_base_phys,
+            TRAMPOLINE_STACK_SIZE_BYTES,
+            boot_info,
+            bi_ptr_va,
+        );
+        verify_page_tables(pml4_phys, ranges)
+            .map_err(|e| BootError::new(BootStage::VerifyPageTables, e))?;
+    }
 
     logger.exit_boot_services();
-    boot_info.mmap = match exit_boot_services() {
-        Ok(value) => value,
-        Err(value) => return value,
-    };
+    boot_info.mmap =
+        exit_boot_services().map_err(|s| BootError::new(BootStage::ExitBootServices, s))?;
 
     // Off we pop.
     unsafe {
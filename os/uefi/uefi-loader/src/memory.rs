@@ -5,6 +5,8 @@ use core::alloc::{GlobalAlloc, Layout};
 use core::ptr;
 use core::ptr::NonNull;
 use core::ptr::null_mut;
+use kernel_info::boot::{BootArenaInfo, PSTORE_PHYS, PSTORE_SIZE, PstoreInfo};
+use kernel_info::memory::HHDM_SIZE;
 use kernel_memory_addresses::{PhysicalAddress, VirtualAddress};
 use uefi::boot;
 use uefi::boot::{AllocateType, MemoryType};
@@ -116,3 +118,72 @@ pub fn alloc_trampoline_stack(
         VirtualAddress::new(top),
     )
 }
+
+/// Copy `bytes` into freshly allocated pages the kernel can reach through its HHDM.
+///
+/// Returns the physical address of the copy, or 0 if `bytes` is empty.
+///
+/// # Panics
+/// If no memory below [`HHDM_SIZE`] is available.
+pub fn alloc_hhdm_copy(bytes: &[u8]) -> u64 {
+    if bytes.is_empty() {
+        return 0;
+    }
+
+    let page_size = usize::try_from(PAGE_SIZE).expect("PAGE_SIZE is too large");
+    let pages = bytes.len().div_ceil(page_size);
+    let base = boot::allocate_pages(
+        AllocateType::MaxAddress(HHDM_SIZE - 1),
+        MemoryType::LOADER_DATA,
+        pages,
+    )
+    .expect("failed to allocate pages below the HHDM limit");
+
+    // Boot services identity-map all memory.
+    unsafe {
+        ptr::copy_nonoverlapping(bytes.as_ptr(), base.as_ptr(), bytes.len());
+    }
+    base.as_ptr() as u64
+}
+
+/// Reserve `size` bytes of free RAM below [`HHDM_SIZE`] for the kernel's
+/// early boot allocations.
+///
+/// The pages are loader data, so the kernel's memory map leaves them alone
+/// until the kernel hands them to its frame allocator itself.
+///
+/// # Panics
+/// If no memory below [`HHDM_SIZE`] is available.
+pub fn alloc_boot_arena(size: u64) -> BootArenaInfo {
+    let pages = size.div_ceil(PAGE_SIZE);
+    let base = boot::allocate_pages(
+        AllocateType::MaxAddress(HHDM_SIZE - 1),
+        MemoryType::LOADER_DATA,
+        usize::try_from(pages).expect("boot arena is too large"),
+    )
+    .expect("failed to allocate the boot arena below the HHDM limit");
+
+    BootArenaInfo {
+        phys_start: base.as_ptr() as u64,
+        length: pages * PAGE_SIZE,
+    }
+}
+
+/// Reserve the persistent store at its fixed address, leaving its contents
+/// as the previous boot left them.
+///
+/// Returns `None` if the firmware already uses the pages.
+pub fn alloc_pstore() -> Option<PstoreInfo> {
+    let pages = PSTORE_SIZE.div_ceil(PAGE_SIZE);
+    let base = boot::allocate_pages(
+        AllocateType::Address(PSTORE_PHYS),
+        MemoryType::LOADER_DATA,
+        usize::try_from(pages).expect("pstore is too large"),
+    )
+    .ok()?;
+
+    Some(PstoreInfo {
+        phys_start: base.as_ptr() as u64,
+        length: pages * PAGE_SIZE,
+    })
+}
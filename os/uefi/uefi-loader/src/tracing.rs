@@ -19,18 +19,26 @@ pub fn trace_boot_info(
             "  Trampol. = {trampoline_stack_va:?}\n",
             "  BI ptr   = {bi_ptr:#018x} (@{bi_mib} MiB)\n",
             "       VA  = {bi_ptr_va:?}\n",
+            "  BI ver.  = {bi_version}, size = {bi_size}, caps = {bi_caps}\n",
             "  MMAP ptr = {mmap_ptr:#018x} (@{mmap_mib} MiB), ",
             "len = {mmap_len}, desc size = {mmap_desc_size}, ",
             "desc ver = {mmap_desc_ver}, rsdp addr = {rsdp_addr}\n",
             "  FB ptr   = {fb_ptr:#018x} (@{fb_mib} MiB), ",
             "size = {fb_size}, width = {fb_width}, height = {fb_height}, ",
-            "stride = {fb_stride}, format = {fb_fmt}"
+            "stride = {fb_stride}, format = {fb_fmt}, ",
+            "mode = {fb_selection:?} of {fb_modes}\n",
+            "  Cmdline  = {cmdline_ptr:#018x}, len = {cmdline_len}\n",
+            "  Arena    = {arena_ptr:#018x}, len = {arena_len}\n",
+            "  Pstore   = {pstore_ptr:#018x}, len = {pstore_len}"
         ),
         kernel_va = kernel_va,
         trampoline_stack_va = trampoline_stack_va,
         bi_ptr = core::ptr::from_ref(boot_info) as usize,
         bi_mib = (core::ptr::from_ref(boot_info) as usize) / 1024 / 1024,
         bi_ptr_va = bi_ptr_va,
+        bi_version = boot_info.header.version,
+        bi_size = boot_info.header.size,
+        bi_caps = boot_info.header.capabilities,
         mmap_ptr = boot_info.mmap.mmap_ptr,
         mmap_mib = boot_info.mmap.mmap_ptr / 1024 / 1024,
         mmap_len = boot_info.mmap.mmap_len,
@@ -49,5 +57,32 @@ pub fn trace_boot_info(
             kernel_info::boot::BootPixelFormat::Bitmask => "Bitmask",
             kernel_info::boot::BootPixelFormat::BltOnly => "BltOnly",
         },
+        fb_selection = boot_info.fb.framebuffer_selection,
+        fb_modes = boot_info.fb.framebuffer_mode_count,
+        cmdline_ptr = boot_info.cmdline_ptr,
+        cmdline_len = boot_info.cmdline_len,
+        arena_ptr = boot_info.arena.phys_start,
+        arena_len = boot_info.arena.length,
+        pstore_ptr = boot_info.pstore.phys_start,
+        pstore_len = boot_info.pstore.length,
     );
+
+    for segment in boot_info.kernel_segments.as_slice() {
+        info!(
+            "  Segment  = {start:#018x}..{end:#018x} -> {phys:#018x}, flags = {flags:#x}",
+            start = segment.virt_start,
+            end = segment.virt_end(),
+            phys = segment.phys_start,
+            flags = segment.flags
+        );
+    }
+
+    for module in boot_info.modules.as_slice() {
+        info!(
+            "  Module   = {ptr:#018x}, len = {len}, name = {name}",
+            ptr = module.bytes_ptr,
+            len = module.length,
+            name = module.name()
+        );
+    }
 }
@@ -0,0 +1,338 @@
+//! # Page Table Verification
+//!
+//! A mistake in the tables built by
+//! [`create_kernel_pagetables`](crate::vmem::create_kernel_pagetables) only
+//! shows after `mov cr3`, usually as a triple fault without a single line of
+//! output. With `verify_page_tables = on` in [`boot.cfg`](crate::config), the
+//! loader walks the new tables in software before it exits boot services and
+//! checks every page of the ranges the switch and the kernel's first
+//! instructions depend on:
+//!
+//! * the trampoline code and stack, identity mapped,
+//! * the kernel's `PT_LOAD` segments and its entry point,
+//! * the boot info, identity mapped,
+//! * the HHDM and the boot arena in it.
+//!
+//! Each range must be mapped, translate to where it is expected to, and allow
+//! the [`Access`] it needs. Every range is logged with the translation and
+//! flags of its first page; a range that fails is logged with its first
+//! failing page and the number of pages that fail. If any check fails, the
+//! loader refuses to switch and reports a [`PageTableCheckError`] instead.
+//!
+//! The framebuffer is logged too but not required: the loader does not map
+//! it, the kernel does once it runs.
+//!
+//! Only leaf entries are inspected. Links are created present, writable and
+//! executable, so they never take away what a leaf allows.
+
+use crate::elf::loader::LoadedSegMap;
+use crate::vmem::LoaderPhysMapper;
+use core::fmt;
+use kernel_info::boot::KernelBootInfo;
+use kernel_info::memory::{HHDM_BASE, HHDM_SIZE};
+use kernel_memory_addresses::{PhysicalAddress, PhysicalPage, VirtualAddress};
+use kernel_vmem::{AddressSpace, VirtualMemoryPageBits};
+use log::{error, info, warn};
+
+const PAGE_SIZE: u64 = 4096;
+
+/// What a range must allow beyond being mapped.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+    Execute,
+}
+
+impl Access {
+    const fn allowed_by(self, flags: VirtualMemoryPageBits) -> bool {
+        match self {
+            Self::Read => true,
+            Self::Write => flags.writable,
+            Self::Execute => !flags.no_execute,
+        }
+    }
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Read => "readable",
+            Self::Write => "writable",
+            Self::Execute => "executable",
+        })
+    }
+}
+
+/// A range of the new address space the switch to the kernel depends on.
+#[derive(Debug, Copy, Clone)]
+pub struct CriticalRange {
+    pub name: &'static str,
+    pub start: VirtualAddress,
+    pub len: u64,
+    /// Where `start` must translate to, if known.
+    pub phys: Option<PhysicalAddress>,
+    pub access: Access,
+    /// Whether a failed check refuses the switch or is only reported.
+    pub required: bool,
+}
+
+impl CriticalRange {
+    const fn new(name: &'static str, start: VirtualAddress, len: u64, access: Access) -> Self {
+        Self {
+            name,
+            start,
+            len,
+            phys: None,
+            access,
+            required: true,
+        }
+    }
+
+    /// A range mapped to the same physical address.
+    const fn identity(name: &'static str, start: VirtualAddress, len: u64, access: Access) -> Self {
+        Self::new(name, start, len, access).at(PhysicalAddress::new(start.as_u64()))
+    }
+
+    const fn at(mut self, phys: PhysicalAddress) -> Self {
+        self.phys = Some(phys);
+        self
+    }
+
+    const fn optional(mut self) -> Self {
+        self.required = false;
+        self
+    }
+}
+
+/// Why the new page tables are unfit to switch to.
+#[derive(Debug, Copy, Clone, thiserror::Error)]
+pub enum PageTableCheckError {
+    #[error("{name} is not mapped at {va}")]
+    Unmapped {
+        name: &'static str,
+        va: VirtualAddress,
+    },
+    #[error("{name} maps {va} to {actual}, expected {expected}")]
+    WrongTarget {
+        name: &'static str,
+        va: VirtualAddress,
+        actual: PhysicalAddress,
+        expected: PhysicalAddress,
+    },
+    #[error("{name} is not {access} at {va}")]
+    Permission {
+        name: &'static str,
+        va: VirtualAddress,
+        access: Access,
+    },
+}
+
+impl PageTableCheckError {
+    /// The address that failed the check.
+    #[must_use]
+    pub const fn address(&self) -> VirtualAddress {
+        match self {
+            Self::Unmapped { va, .. }
+            | Self::WrongTarget { va, .. }
+            | Self::Permission { va, .. } => *va,
+        }
+    }
+}
+
+/// The ranges to check for a kernel loaded from `kernel_maps` and entered at
+/// `entry`; see the [module docs](self).
+#[allow(clippy::too_many_arguments)]
+pub fn critical_ranges(
+    kernel_maps: &[LoadedSegMap],
+    entry: VirtualAddress,
+    tramp_code_va: VirtualAddress,
+    tramp_code_len: usize,
+    tramp_stack_base_phys: PhysicalAddress,
+    tramp_stack_size_bytes: usize,
+    boot_info: &KernelBootInfo,
+    bi_ptr_va: VirtualAddress,
+) -> impl Iterator<Item = CriticalRange> {
+    let fixed = [
+        CriticalRange::identity(
+            "trampoline code",
+            tramp_code_va,
+            tramp_code_len as u64,
+            Access::Execute,
+        ),
+        CriticalRange::identity(
+            "trampoline stack",
+            VirtualAddress::new(tramp_stack_base_phys.as_u64()),
+            tramp_stack_size_bytes as u64,
+            Access::Write,
+        ),
+        CriticalRange::new("kernel entry", entry, 1, Access::Execute),
+        CriticalRange::identity(
+            "boot info",
+            bi_ptr_va,
+            size_of::<KernelBootInfo>() as u64,
+            Access::Read,
+        ),
+        CriticalRange::new("HHDM", HHDM_BASE, HHDM_SIZE, Access::Write).at(PhysicalAddress::zero()),
+        CriticalRange::new(
+            "boot arena",
+            HHDM_BASE + boot_info.arena.phys_start,
+            boot_info.arena.length,
+            Access::Write,
+        )
+        .at(PhysicalAddress::new(boot_info.arena.phys_start)),
+        CriticalRange::new(
+            "pstore",
+            HHDM_BASE + boot_info.pstore.phys_start,
+            boot_info.pstore.length,
+            Access::Write,
+        )
+        .at(PhysicalAddress::new(boot_info.pstore.phys_start))
+        .optional(),
+        CriticalRange::new(
+            "framebuffer",
+            HHDM_BASE + boot_info.fb.framebuffer_ptr,
+            boot_info.fb.framebuffer_size,
+            Access::Write,
+        )
+        .at(PhysicalAddress::new(boot_info.fb.framebuffer_ptr))
+        .optional(),
+    ];
+
+    let segments = kernel_maps.iter().map(|m| {
+        let access = if m.flags.execute() {
+            Access::Execute
+        } else if m.flags.write() {
+            Access::Write
+        } else {
+            Access::Read
+        };
+        CriticalRange::new("kernel segment", m.vaddr_page.base(), m.map_len, access)
+            .at(m.phys_page.base())
+    });
+
+    fixed.into_iter().chain(segments)
+}
+
+/// Walk the tables rooted at `pml4_phys` and check every page of `ranges`.
+///
+/// # Errors
+/// The first failed check of a required range; all failures are logged.
+pub fn verify_page_tables(
+    pml4_phys: PhysicalAddress,
+    ranges: impl IntoIterator<Item = CriticalRange>,
+) -> Result<(), PageTableCheckError> {
+    info!("Verifying kernel page tables at {pml4_phys} ...");
+    let aspace = AddressSpace::from_root(&LoaderPhysMapper, PhysicalPage::from_addr(pml4_phys));
+
+    let mut first_error = None;
+    for range in ranges {
+        if let Err(e) = check_range(&aspace, &range)
+            && range.required
+        {
+            first_error.get_or_insert(e);
+        }
+    }
+
+    first_error.map_or_else(
+        || {
+            info!("Kernel page tables verified");
+            Ok(())
+        },
+        Err,
+    )
+}
+
+/// Check every page of `range` and log the outcome; returns the first failure.
+fn check_range(
+    aspace: &AddressSpace<LoaderPhysMapper>,
+    range: &CriticalRange,
+) -> Result<(), PageTableCheckError> {
+    let end = range.start.as_u64().saturating_add(range.len.max(1));
+    let first_page = range.start.as_u64() & !(PAGE_SIZE - 1);
+
+    match (aspace.query(range.start), aspace.query_flags(range.start)) {
+        (Some(pa), Some(flags)) => info!(
+            "  {:<16} {}..{end:#018x} -> {pa} [{}]",
+            range.name,
+            range.start,
+            LeafFlags(flags)
+        ),
+        _ => info!(
+            "  {:<16} {}..{end:#018x} -> not mapped",
+            range.name, range.start
+        ),
+    }
+
+    // Log the first failure of each range; a range that is missing
+    // altogether would otherwise take thousands of lines.
+    let mut first_error = None;
+    let mut failed = 0u64;
+    for page in 0..(end - first_page).div_ceil(PAGE_SIZE) {
+        // Check the range's own first byte, not the start of its page.
+        let va = VirtualAddress::new((first_page + page * PAGE_SIZE).max(range.start.as_u64()));
+        if let Err(e) = check_page(aspace, range, va) {
+            failed += 1;
+            first_error.get_or_insert(e);
+        }
+    }
+
+    if let Some(e) = first_error {
+        if range.required {
+            error!("    {e} ({failed} pages fail)");
+        } else {
+            warn!("    {e} (not required; the kernel maps it itself)");
+        }
+    }
+    first_error.map_or(Ok(()), Err)
+}
+
+fn check_page(
+    aspace: &AddressSpace<LoaderPhysMapper>,
+    range: &CriticalRange,
+    va: VirtualAddress,
+) -> Result<(), PageTableCheckError> {
+    let name = range.name;
+    let (Some(actual), Some(flags)) = (aspace.query(va), aspace.query_flags(va)) else {
+        return Err(PageTableCheckError::Unmapped { name, va });
+    };
+
+    if let Some(phys) = range.phys {
+        let expected = PhysicalAddress::new(phys.as_u64() + (va.as_u64() - range.start.as_u64()));
+        if actual != expected {
+            return Err(PageTableCheckError::WrongTarget {
+                name,
+                va,
+                actual,
+                expected,
+            });
+        }
+    }
+
+    if !range.access.allowed_by(flags) {
+        return Err(PageTableCheckError::Permission {
+            name,
+            va,
+            access: range.access,
+        });
+    }
+    Ok(())
+}
+
+/// Leaf flags in the form `rwx U G WB`.
+struct LeafFlags(VirtualMemoryPageBits);
+
+impl fmt::Display for LeafFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let flags = self.0;
+        write!(
+            f,
+            "r{}{} {} {} {:?}",
+            if flags.writable { 'w' } else { '-' },
+            if flags.no_execute { '-' } else { 'x' },
+            if flags.user { 'U' } else { 'S' },
+            if flags.global { 'G' } else { '-' },
+            flags.cache_mode()
+        )
+    }
+}
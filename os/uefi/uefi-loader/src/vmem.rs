@@ -1,6 +1,7 @@
 //! # Virtual Memory Setup for Kernel loading (new typed API)
 
 use crate::elf::loader::LoadedSegMap;
+use kernel_info::boot::KernelBootInfo;
 use kernel_info::memory::{HHDM_BASE /*KERNEL_BASE,*/ /*PHYS_LOAD*/};
 use log::info;
 
@@ -45,7 +46,7 @@ impl PhysFrameAlloc for BsFrameAlloc {
 ///
 /// # Safety
 /// Valid only in the UEFI loader context where those frames are mapped.
-struct LoaderPhysMapper;
+pub struct LoaderPhysMapper;
 
 impl PhysMapper for LoaderPhysMapper {
     unsafe fn phys_to_mut<T>(&self, at: PhysicalAddress) -> &mut T {
@@ -88,12 +89,11 @@ pub fn create_kernel_pagetables(
     info!("Mapping kernel ELF PT_LOAD segments ...");
     for m in kernel_maps {
         let mut cur_va = m.vaddr_page.base(); // VirtualAddress (page-aligned)
-        let end_u64 = m
-            .vaddr_page
-            .base()
+        let vaddr = m.vaddr_page.base();
+        let end_u64 = vaddr
             .as_u64()
             .checked_add(m.map_len)
-            .ok_or(KernelPageTableError::SegmentLengthOverflow)?;
+            .ok_or(KernelPageTableError::SegmentLengthOverflow { vaddr })?;
 
         while cur_va.as_u64() < end_u64 {
             // Compute PA = phys_page.base + (cur_va - vaddr_page.base)
@@ -115,22 +115,14 @@ pub fn create_kernel_pagetables(
                 && remaining >= Size2M::SIZE;
 
             if can_2m {
-                aspace.map_one::<_, Size2M>(
-                    &mut alloc,
-                    cur_va,
-                    cur_pa,
-                    nonleaf_flags,
-                    leaf_flags,
-                )?;
+                aspace
+                    .map_one::<_, Size2M>(&mut alloc, cur_va, cur_pa, nonleaf_flags, leaf_flags)
+                    .map_err(|source| KernelPageTableError::Map { va: cur_va, source })?;
                 cur_va = VirtualAddress::new(cur_va.as_u64() + Size2M::SIZE);
             } else {
-                aspace.map_one::<_, Size4K>(
-                    &mut alloc,
-                    cur_va,
-                    cur_pa,
-                    nonleaf_flags,
-                    leaf_flags,
-                )?;
+                aspace
+                    .map_one::<_, Size4K>(&mut alloc, cur_va, cur_pa, nonleaf_flags, leaf_flags)
+                    .map_err(|source| KernelPageTableError::Map { va: cur_va, source })?;
                 cur_va = VirtualAddress::new(cur_va.as_u64() + Size4K::SIZE);
             }
         }
@@ -146,7 +138,12 @@ pub fn create_kernel_pagetables(
             .with_writable(true)
             .with_global(true)
             .with_no_execute(true);
-        aspace.map_one::<_, Size1G>(&mut alloc, hhdm_va, zero_pa, nonleaf_flags, leaf)?;
+        aspace
+            .map_one::<_, Size1G>(&mut alloc, hhdm_va, zero_pa, nonleaf_flags, leaf)
+            .map_err(|source| KernelPageTableError::Map {
+                va: hhdm_va,
+                source,
+            })?;
     }
 
     // Identity map the trampoline stack (4 KiB, NX)
@@ -157,7 +154,9 @@ pub fn create_kernel_pagetables(
             tramp_stack_base_phys
                 .as_u64()
                 .checked_add(tramp_stack_size_bytes as u64)
-                .ok_or(KernelPageTableError::TrampolineStackRangeOverflow)?,
+                .ok_or(KernelPageTableError::TrampolineStackRangeOverflow {
+                    base: tramp_stack_base_phys,
+                })?,
             Size4K::SIZE,
         );
         let leaf = VirtualMemoryPageBits::default()
@@ -170,7 +169,9 @@ pub fn create_kernel_pagetables(
         while pa < end {
             let va = VirtualAddress::new(pa); // identity
             let phys = PhysicalAddress::new(pa);
-            aspace.map_one::<_, Size4K>(&mut alloc, va, phys, nonleaf_flags, leaf)?;
+            aspace
+                .map_one::<_, Size4K>(&mut alloc, va, phys, nonleaf_flags, leaf)
+                .map_err(|source| KernelPageTableError::Map { va, source })?;
             pa += Size4K::SIZE;
         }
     }
@@ -183,7 +184,7 @@ pub fn create_kernel_pagetables(
             tramp_code_va
                 .as_u64()
                 .checked_add(tramp_code_len as u64)
-                .ok_or(KernelPageTableError::TrampolineCodeRangeOverflow)?,
+                .ok_or(KernelPageTableError::TrampolineCodeRangeOverflow { va: tramp_code_va })?,
             Size4K::SIZE,
         );
         let leaf = VirtualMemoryPageBits::default()
@@ -196,44 +197,72 @@ pub fn create_kernel_pagetables(
         while addr < end {
             let va = VirtualAddress::new(addr);
             let pa = PhysicalAddress::new(addr); // identity
-            aspace.map_one::<_, Size4K>(&mut alloc, va, pa, nonleaf_flags, leaf)?;
+            aspace
+                .map_one::<_, Size4K>(&mut alloc, va, pa, nonleaf_flags, leaf)
+                .map_err(|source| KernelPageTableError::Map { va, source })?;
             addr += Size4K::SIZE;
         }
     }
 
-    // Identity map just the BootInfo pointer page (4 KiB, NX)
-    info!("Identity map bootinfo pointer ...");
+    // Identity map the pages of the BootInfo (4 KiB, NX)
+    info!("Identity map bootinfo ...");
     {
-        let bi_page = boot_info_ptr_va.page::<Size4K>().base();
+        let start = boot_info_ptr_va.page::<Size4K>().base().as_u64();
+        let end = align_up_u64(
+            boot_info_ptr_va.as_u64() + size_of::<KernelBootInfo>() as u64,
+            Size4K::SIZE,
+        );
         let leaf = VirtualMemoryPageBits::default()
             .with_present(true)
             .with_writable(true)
             .with_global(true)
             .with_no_execute(true);
-        aspace.map_one::<_, Size4K>(
-            &mut alloc,
-            bi_page,
-            PhysicalAddress::new(bi_page.as_u64()),
-            nonleaf_flags,
-            leaf,
-        )?;
+
+        let mut addr = start;
+        while addr < end {
+            let va = VirtualAddress::new(addr);
+            let pa = PhysicalAddress::new(addr); // identity
+            aspace
+                .map_one::<_, Size4K>(&mut alloc, va, pa, nonleaf_flags, leaf)
+                .map_err(|source| KernelPageTableError::Map { va, source })?;
+            addr += Size4K::SIZE;
+        }
     }
 
     Ok(pml4_phys)
 }
 
+/// Why the kernel page tables could not be built.
 #[derive(Debug, thiserror::Error)]
 pub enum KernelPageTableError {
     #[error("out of memory in PML4")]
     OutOfMemoryPml4,
     #[error("PT_LOAD segment length overflow")]
-    SegmentLengthOverflow,
+    SegmentLengthOverflow { vaddr: VirtualAddress },
     /// Address arithmetic overflow while mapping trampoline stack memory range.
     #[error("stack range overflow")]
-    TrampolineStackRangeOverflow,
+    TrampolineStackRangeOverflow { base: PhysicalAddress },
     /// Address arithmetic overflow while mapping trampoline code memory range.
     #[error("trampoline code overflow")]
-    TrampolineCodeRangeOverflow,
-    #[error(transparent)]
-    OutOfMemoryPageTable(#[from] AddressSpaceMapOneError),
+    TrampolineCodeRangeOverflow { va: VirtualAddress },
+    /// Mapping the page at `va` failed.
+    #[error("cannot map {va}")]
+    Map {
+        va: VirtualAddress,
+        #[source]
+        source: AddressSpaceMapOneError,
+    },
+}
+
+impl KernelPageTableError {
+    /// The address the error is about, if any.
+    pub const fn address(&self) -> Option<u64> {
+        match self {
+            Self::OutOfMemoryPml4 => None,
+            Self::SegmentLengthOverflow { vaddr: va }
+            | Self::TrampolineCodeRangeOverflow { va }
+            | Self::Map { va, .. } => Some(va.as_u64()),
+            Self::TrampolineStackRangeOverflow { base } => Some(base.as_u64()),
+        }
+    }
 }
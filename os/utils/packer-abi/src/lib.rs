@@ -1,5 +1,6 @@
 #![no_std]
 
+pub mod symbols;
 #[cfg(feature = "unbundle")]
 pub mod unbundle;
 
@@ -0,0 +1,197 @@
+//! Kernel symbol table.
+//!
+//! A flat, address-sorted table of the kernel's function and data symbols,
+//! written by `packer symbols` and handed to the kernel as a boot module.
+//!
+//! Layout (all integers little-endian, all sections 8-byte aligned):
+//! 1. A [`SymbolHeader`]
+//! 2. `count` [`SymbolEntry`] records, sorted by ascending address
+//! 3. A blob of UTF-8 names, referenced by offset and length (not NUL-terminated)
+
+/// Magic signature identifying a symbol table: the ASCII bytes `"KSYMTAB\0"`.
+pub const SYMBOLS_MAGIC: u64 = 0x0042_4154_4D59_534B;
+
+/// Fixed-size header of a symbol table.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SymbolHeader {
+    /// Constant [`SYMBOLS_MAGIC`] value.
+    pub magic: u64,
+
+    /// Format version; currently `0`.
+    pub version: u32,
+
+    /// Number of [`SymbolEntry`] records.
+    pub count: u32,
+
+    /// Absolute offset, in bytes, to the first [`SymbolEntry`].
+    pub entries_off: u64,
+
+    /// Absolute offset, in bytes, to the name blob.
+    pub names_off: u64,
+}
+
+/// One symbol.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SymbolEntry {
+    /// Virtual address of the symbol's first byte.
+    pub addr: u64,
+
+    /// Size in bytes; `0` if unknown.
+    pub size: u64,
+
+    /// Offset, relative to [`SymbolHeader::names_off`], of the name.
+    pub name_off: u32,
+
+    /// Length of the name in bytes.
+    pub name_len: u32,
+}
+
+impl Default for SymbolHeader {
+    fn default() -> Self {
+        Self {
+            magic: SYMBOLS_MAGIC,
+            version: 0,
+            count: 0,
+            entries_off: 0,
+            names_off: 0,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SymbolsError {
+    TooShort,
+    BadMagic,
+    BadVersion,
+    BadAlignment,
+    OutOfBounds,
+}
+
+/// Parsed symbol table view over an in-memory blob.
+pub struct SymbolTable<'a> {
+    blob: &'a [u8],
+    count: usize,
+    entries_off: usize,
+    names_off: usize,
+}
+
+#[inline]
+fn read_u32_le(buf: &[u8], off: usize) -> Result<u32, SymbolsError> {
+    let end = off.checked_add(4).ok_or(SymbolsError::OutOfBounds)?;
+    let s = buf.get(off..end).ok_or(SymbolsError::OutOfBounds)?;
+    Ok(u32::from_le_bytes([s[0], s[1], s[2], s[3]]))
+}
+
+#[inline]
+fn read_u64_le(buf: &[u8], off: usize) -> Result<u64, SymbolsError> {
+    let end = off.checked_add(8).ok_or(SymbolsError::OutOfBounds)?;
+    let s = buf.get(off..end).ok_or(SymbolsError::OutOfBounds)?;
+    Ok(u64::from_le_bytes([
+        s[0], s[1], s[2], s[3], s[4], s[5], s[6], s[7],
+    ]))
+}
+
+impl<'a> SymbolTable<'a> {
+    /// Parse and validate a symbol table blob.
+    #[allow(clippy::missing_errors_doc, clippy::cast_possible_truncation)]
+    pub fn parse(blob: &'a [u8]) -> Result<Self, SymbolsError> {
+        use SymbolsError::{BadAlignment, BadMagic, BadVersion, OutOfBounds, TooShort};
+        if blob.len() < size_of::<SymbolHeader>() {
+            return Err(TooShort);
+        }
+        if read_u64_le(blob, 0)? != SYMBOLS_MAGIC {
+            return Err(BadMagic);
+        }
+        if read_u32_le(blob, 8)? != 0 {
+            return Err(BadVersion);
+        }
+
+        let count = read_u32_le(blob, 12)? as usize;
+        let entries_off = read_u64_le(blob, 16)? as usize;
+        let names_off = read_u64_le(blob, 24)? as usize;
+        if !entries_off.is_multiple_of(8) || !names_off.is_multiple_of(8) {
+            return Err(BadAlignment);
+        }
+
+        let entries_end = count
+            .checked_mul(size_of::<SymbolEntry>())
+            .and_then(|len| entries_off.checked_add(len))
+            .ok_or(OutOfBounds)?;
+        if entries_end > blob.len() || names_off > blob.len() {
+            return Err(OutOfBounds);
+        }
+
+        Ok(Self {
+            blob,
+            count,
+            entries_off,
+            names_off,
+        })
+    }
+
+    /// Number of symbols.
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.count
+    }
+
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// The address of symbol `i`; `u64::MAX` if out of bounds.
+    fn addr(&self, i: usize) -> u64 {
+        read_u64_le(self.blob, self.entries_off + i * size_of::<SymbolEntry>()).unwrap_or(u64::MAX)
+    }
+
+    /// Fetch `(name, address, size)` of symbol `i`.
+    #[allow(clippy::missing_errors_doc)]
+    pub fn get(&self, i: usize) -> Result<(&'a str, u64, u64), SymbolsError> {
+        if i >= self.count {
+            return Err(SymbolsError::OutOfBounds);
+        }
+        let off = self.entries_off + i * size_of::<SymbolEntry>();
+        let addr = read_u64_le(self.blob, off)?;
+        let size = read_u64_le(self.blob, off + 8)?;
+        let name_off = read_u32_le(self.blob, off + 16)? as usize;
+        let name_len = read_u32_le(self.blob, off + 20)? as usize;
+
+        let start = self
+            .names_off
+            .checked_add(name_off)
+            .ok_or(SymbolsError::OutOfBounds)?;
+        let end = start
+            .checked_add(name_len)
+            .ok_or(SymbolsError::OutOfBounds)?;
+        let name = self
+            .blob
+            .get(start..end)
+            .and_then(|bytes| core::str::from_utf8(bytes).ok())
+            .unwrap_or("?");
+        Ok((name, addr, size))
+    }
+
+    /// The symbol containing `addr`, as `(name, offset into the symbol)`.
+    ///
+    /// Symbols of unknown size extend up to the next symbol.
+    #[must_use]
+    pub fn resolve(&self, addr: u64) -> Option<(&'a str, u64)> {
+        // Index of the first symbol starting after `addr`.
+        let (mut lo, mut hi) = (0, self.count);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            if self.addr(mid) <= addr {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+
+        let (name, start, size) = self.get(lo.checked_sub(1)?).ok()?;
+        let offset = addr - start;
+        (size == 0 || offset < size).then_some((name, offset))
+    }
+}
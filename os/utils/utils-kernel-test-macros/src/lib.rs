@@ -0,0 +1,105 @@
+//! # Kernel Test Attribute
+//!
+//! This crate provides the `#[kernel_test]` attribute, which registers a
+//! function as a test the kernel runs at boot. Use it through the
+//! `kernel-test` crate, which defines the descriptor the attribute emits.
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::punctuated::Punctuated;
+use syn::{ItemFn, Meta, ReturnType, Token, parse::Parser, parse_macro_input, spanned::Spanned};
+
+/// Register a function as an in-kernel test.
+///
+/// The function takes no arguments and returns `()`; it fails by panicking.
+/// `#[kernel_test(should_panic)]` inverts this: the test passes only if it
+/// panics.
+///
+/// The attribute keeps the function as is and places a
+/// `kernel_test::KernelTest` descriptor with its name and module path into
+/// the `.ktests` linker section, where the kernel's test runner finds it.
+///
+/// ```ignore
+/// use kernel_test::kernel_test;
+///
+/// #[kernel_test]
+/// fn cr3_is_page_aligned() {
+///     assert_eq!(unsafe { read_cr3_phys() }.as_u64() % 4096, 0);
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn kernel_test(args: TokenStream, input: TokenStream) -> TokenStream {
+    let should_panic = match parse_should_panic(args) {
+        Ok(should_panic) => should_panic,
+        Err(e) => return e.to_compile_error().into(),
+    };
+
+    let func = parse_macro_input!(input as ItemFn);
+    if let Err(e) = check_signature(&func) {
+        return e.to_compile_error().into();
+    }
+
+    let ident = &func.sig.ident;
+    let name = ident.to_string();
+    quote! {
+        // Tests are only ever called through the descriptor.
+        #[allow(clippy::missing_const_for_fn)]
+        #func
+
+        const _: () = {
+            #[used]
+            #[unsafe(link_section = ".ktests")]
+            static KERNEL_TEST: ::kernel_test::KernelTest = ::kernel_test::KernelTest {
+                name: #name,
+                module: ::core::module_path!(),
+                run: #ident,
+                should_panic: #should_panic,
+            };
+        };
+    }
+    .into()
+}
+
+/// Accepts no arguments or `should_panic`.
+fn parse_should_panic(args: TokenStream) -> syn::Result<bool> {
+    let metas = Punctuated::<Meta, Token![,]>::parse_terminated.parse(args)?;
+    let mut should_panic = false;
+    for meta in metas {
+        match meta {
+            Meta::Path(path) if path.is_ident("should_panic") => should_panic = true,
+            other => {
+                return Err(syn::Error::new(
+                    other.span(),
+                    "expected `should_panic` or no arguments",
+                ));
+            }
+        }
+    }
+    Ok(should_panic)
+}
+
+fn check_signature(func: &ItemFn) -> syn::Result<()> {
+    let sig = &func.sig;
+    if !sig.inputs.is_empty() {
+        return Err(syn::Error::new(
+            sig.inputs.span(),
+            "kernel tests take no arguments",
+        ));
+    }
+    if !sig.generics.params.is_empty() {
+        return Err(syn::Error::new(
+            sig.generics.span(),
+            "kernel tests cannot be generic",
+        ));
+    }
+    if let Some(asyncness) = &sig.asyncness {
+        return Err(syn::Error::new(
+            asyncness.span(),
+            "kernel tests cannot be async",
+        ));
+    }
+    if let ReturnType::Type(_, ty) = &sig.output {
+        return Err(syn::Error::new(ty.span(), "kernel tests return `()`"));
+    }
+    Ok(())
+}
@@ -1,10 +1,18 @@
+mod symbols;
+
 use packer_abi::{Entry, Header};
 use std::{env, fs};
 
 fn main() -> std::io::Result<()> {
     // args: <input_dir> <out_bundle>
+    //   or: symbols <kernel_elf> <out_symbols>
     let mut args = env::args().skip(1);
     let dir = args.next().expect("input dir");
+    if dir == "symbols" {
+        let elf = args.next().expect("kernel elf");
+        let out = args.next().expect("out symbols");
+        return symbols::write_symbols(&elf, &out);
+    }
     let out = args.next().expect("out bundle");
 
     // Collect files (regular files only)
@@ -0,0 +1,231 @@
+//! `packer symbols`: extract the kernel's symbol table.
+//!
+//! Reads the `.symtab` of the kernel ELF, keeps function and data symbols,
+//! demangles their names and writes them as a
+//! [`packer_abi::symbols`] table, sorted by address.
+
+use crate::align8;
+use packer_abi::symbols::{SymbolEntry, SymbolHeader};
+use std::io::{Error, ErrorKind};
+use std::{fs, io};
+
+const SHT_SYMTAB: u32 = 2;
+const STT_OBJECT: u8 = 1;
+const STT_FUNC: u8 = 2;
+
+/// Section header size in ELF64.
+const SHDR_SIZE: usize = 64;
+
+/// Symbol size in ELF64.
+const SYM_SIZE: usize = 24;
+
+pub fn write_symbols(elf: &str, out: &str) -> io::Result<()> {
+    let bytes = fs::read(elf)?;
+    let mut symbols = read_symtab(&bytes)?;
+
+    // One symbol per address; prefer the one that knows its size.
+    symbols.sort_by(|a, b| a.1.cmp(&b.1).then(b.2.cmp(&a.2)));
+    symbols.dedup_by_key(|s| s.1);
+    let count = symbols.len();
+
+    let mut names = Vec::new();
+    let mut entries = Vec::with_capacity(count);
+    for (name, addr, size) in &symbols {
+        entries.push(SymbolEntry {
+            addr: *addr,
+            size: *size,
+            name_off: u32::try_from(names.len()).expect("names blob too large"),
+            name_len: u32::try_from(name.len()).expect("symbol name too long"),
+        });
+        names.extend_from_slice(name.as_bytes());
+    }
+
+    let entries_off = align8(size_of::<SymbolHeader>());
+    let names_off = align8(entries_off + count * size_of::<SymbolEntry>());
+
+    let hdr = SymbolHeader {
+        count: u32::try_from(count).expect("invalid count"),
+        entries_off: entries_off as u64,
+        names_off: names_off as u64,
+        ..SymbolHeader::default()
+    };
+
+    let mut out_bytes = vec![0; names_off];
+    out_bytes[..size_of::<SymbolHeader>()].copy_from_slice(unsafe {
+        std::slice::from_raw_parts((&raw const hdr).cast::<u8>(), size_of::<SymbolHeader>())
+    });
+    for (i, e) in entries.iter().enumerate() {
+        let p = entries_off + i * size_of::<SymbolEntry>();
+        out_bytes[p..p + size_of::<SymbolEntry>()].copy_from_slice(unsafe {
+            std::slice::from_raw_parts(std::ptr::from_ref(e).cast::<u8>(), size_of::<SymbolEntry>())
+        });
+    }
+    out_bytes.extend_from_slice(&names);
+
+    fs::write(out, &out_bytes)?;
+    eprintln!("wrote {count} symbols into {out}");
+    Ok(())
+}
+
+/// The `(name, address, size)` of every function and data symbol.
+fn read_symtab(elf: &[u8]) -> io::Result<Vec<(String, u64, u64)>> {
+    if elf.get(..4) != Some(b"\x7fELF") || elf.get(4) != Some(&2) || elf.get(5) != Some(&1) {
+        return Err(invalid("not a little-endian ELF64 file"));
+    }
+
+    let shoff = to_usize(read_u64(elf, 0x28)?)?;
+    let shnum = usize::from(read_u16(elf, 0x3C)?);
+    let section = |i: usize| -> io::Result<Section> {
+        let p = shoff + i * SHDR_SIZE;
+        Ok(Section {
+            kind: read_u32(elf, p + 4)?,
+            offset: to_usize(read_u64(elf, p + 24)?)?,
+            size: to_usize(read_u64(elf, p + 32)?)?,
+            link: read_u32(elf, p + 40)? as usize,
+        })
+    };
+
+    let symtab = (0..shnum)
+        .map(section)
+        .find(|s| s.as_ref().is_ok_and(|s| s.kind == SHT_SYMTAB))
+        .ok_or_else(|| invalid("no .symtab; is the kernel stripped?"))??;
+    let strtab = section(symtab.link)?;
+    let strings = elf
+        .get(strtab.offset..strtab.offset + strtab.size)
+        .ok_or_else(|| invalid(".strtab out of bounds"))?;
+
+    let mut symbols = Vec::new();
+    for i in 0..symtab.size / SYM_SIZE {
+        let p = symtab.offset + i * SYM_SIZE;
+        let name_off = read_u32(elf, p)? as usize;
+        let kind = elf
+            .get(p + 4)
+            .ok_or_else(|| invalid("symbol out of bounds"))?
+            & 0xF;
+        let addr = read_u64(elf, p + 8)?;
+        let size = read_u64(elf, p + 16)?;
+        if addr == 0 || (kind != STT_FUNC && kind != STT_OBJECT) {
+            continue;
+        }
+
+        let raw = strings
+            .get(name_off..)
+            .and_then(|s| s.split(|&b| b == 0).next())
+            .ok_or_else(|| invalid("symbol name out of bounds"))?;
+        let raw = String::from_utf8_lossy(raw);
+        symbols.push((demangle(&raw), addr, size));
+    }
+    Ok(symbols)
+}
+
+struct Section {
+    kind: u32,
+    offset: usize,
+    size: usize,
+    link: usize,
+}
+
+/// Demangle a legacy Rust symbol (`_ZN3foo3bar17h0123456789abcdefE` becomes
+/// `foo::bar`). Anything else is returned as is.
+fn demangle(raw: &str) -> String {
+    let Some(mut rest) = raw.strip_prefix("_ZN") else {
+        return raw.to_string();
+    };
+
+    let mut parts = Vec::new();
+    while !rest.starts_with('E') {
+        let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+        let Ok(len) = rest[..digits].parse::<usize>() else {
+            return raw.to_string();
+        };
+        let Some(part) = rest.get(digits..digits + len) else {
+            return raw.to_string();
+        };
+        parts.push(part);
+        rest = &rest[digits + len..];
+    }
+
+    // Drop the trailing hash.
+    if parts
+        .last()
+        .and_then(|p| p.strip_prefix('h'))
+        .is_some_and(|h| h.len() == 16 && h.bytes().all(|b| b.is_ascii_hexdigit()))
+    {
+        parts.pop();
+    }
+
+    parts
+        .iter()
+        // Parts starting with an escape are prefixed with an underscore.
+        .map(|p| {
+            unescape(
+                p.strip_prefix('_')
+                    .filter(|q| q.starts_with('$'))
+                    .unwrap_or(p),
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("::")
+}
+
+/// Undo the `$LT$`-style escapes of legacy mangling.
+fn unescape(mut part: &str) -> String {
+    const ESCAPES: &[(&str, &str)] = &[
+        ("$LT$", "<"),
+        ("$GT$", ">"),
+        ("$RF$", "&"),
+        ("$BP$", "*"),
+        ("$LP$", "("),
+        ("$RP$", ")"),
+        ("$SP$", "@"),
+        ("$C$", ","),
+        ("$u20$", " "),
+        ("$u27$", "'"),
+        ("$u5b$", "["),
+        ("$u5d$", "]"),
+        ("$u7b$", "{"),
+        ("$u7d$", "}"),
+        ("$u7e$", "~"),
+        ("..", "::"),
+    ];
+
+    let mut out = String::with_capacity(part.len());
+    'outer: while let Some(c) = part.chars().next() {
+        for (from, to) in ESCAPES {
+            if let Some(rest) = part.strip_prefix(from) {
+                out.push_str(to);
+                part = rest;
+                continue 'outer;
+            }
+        }
+        out.push(c);
+        part = &part[c.len_utf8()..];
+    }
+    out
+}
+
+fn read_u16(buf: &[u8], off: usize) -> io::Result<u16> {
+    read_array(buf, off).map(u16::from_le_bytes)
+}
+
+fn read_u32(buf: &[u8], off: usize) -> io::Result<u32> {
+    read_array(buf, off).map(u32::from_le_bytes)
+}
+
+fn read_u64(buf: &[u8], off: usize) -> io::Result<u64> {
+    read_array(buf, off).map(u64::from_le_bytes)
+}
+
+fn read_array<const N: usize>(buf: &[u8], off: usize) -> io::Result<[u8; N]> {
+    buf.get(off..off + N)
+        .and_then(|s| s.try_into().ok())
+        .ok_or_else(|| invalid("ELF field out of bounds"))
+}
+
+fn to_usize(value: u64) -> io::Result<usize> {
+    usize::try_from(value).map_err(|_| invalid("ELF offset out of range"))
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
@@ -0,0 +1,50 @@
+//! `cat [file ...]`: copy the files to the standard output, one after the
+//! other, or the standard input if there are none.
+
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use stdlib::io::{STDIN, stderr, stdout};
+use stdlib::startup::Startup;
+use stdlib::syscall;
+
+stdlib::entry!(main);
+
+fn main(startup: &Startup) -> u32 {
+    if startup.argc() < 2 {
+        return u32::from(!copy(STDIN, "-"));
+    }
+
+    let mut failed = false;
+    for path in startup.args().skip(1) {
+        let Some(fd) = syscall::sys_open(path) else {
+            let _ = writeln!(stderr(), "cat: {path}: no such file");
+            failed = true;
+            continue;
+        };
+        failed |= !copy(fd, path);
+        let _ = syscall::sys_close(fd);
+    }
+    u32::from(failed)
+}
+
+/// Copy `fd`, opened as `path`, to the standard output until its end;
+/// returns whether that worked.
+fn copy(fd: u32, path: &str) -> bool {
+    let mut buf = [0u8; 256];
+    loop {
+        match syscall::sys_read(fd, &mut buf) {
+            Some(0) => return true,
+            Some(n) => {
+                if stdout().write_all(&buf[..n]).is_err() {
+                    return false;
+                }
+            }
+            None => {
+                let _ = writeln!(stderr(), "cat: {path}: cannot read");
+                return false;
+            }
+        }
+    }
+}
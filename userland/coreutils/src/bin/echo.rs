@@ -0,0 +1,45 @@
+//! `echo [-n] [word ...]`: print the words, separated by spaces; `-n` leaves
+//! out the newline at the end. The words go out with one `writev` per
+//! [`MAX_IOV`] pieces, so a short line is never torn apart by other writers.
+
+#![no_std]
+#![no_main]
+
+use stdlib::io::STDOUT;
+use stdlib::startup::Startup;
+use stdlib::syscall::sys_writev;
+use stdlib::syscall_abi::{IoVec, MAX_IOV};
+
+stdlib::entry!(main);
+
+fn main(startup: &Startup) -> u32 {
+    let mut args = startup.args().skip(1).peekable();
+    let newline = args.next_if_eq(&"-n").is_none();
+
+    let pieces = args
+        .enumerate()
+        .flat_map(|(i, word)| [if i == 0 { "" } else { " " }, word])
+        .chain(newline.then_some("\n"));
+
+    let mut iov = [IoVec::EMPTY; MAX_IOV];
+    let mut len = 0;
+    let mut ok = true;
+    for piece in pieces {
+        if len == iov.len() {
+            ok &= write(&iov);
+            len = 0;
+        }
+        iov[len] = IoVec::new(piece.as_bytes());
+        len += 1;
+    }
+    ok &= write(&iov[..len]);
+    u32::from(!ok)
+}
+
+/// Write the buffers of `iov` to the standard output; returns whether all of
+/// them went out.
+#[allow(clippy::cast_possible_truncation)]
+fn write(iov: &[IoVec]) -> bool {
+    let total: u64 = iov.iter().map(|vec| vec.len).sum();
+    sys_writev(STDOUT, iov) == Some(total as usize)
+}
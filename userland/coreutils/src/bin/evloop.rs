@@ -0,0 +1,115 @@
+//! `evloop [seconds]`: an event loop over several sources at once. It echoes
+//! lines typed on the standard input, prints the messages a forked child
+//! sends through a pipe and reports every second without events, for the
+//! given number of seconds (10 by default) or until `quit` is typed.
+
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use core::time::Duration;
+use stdlib::io::{self, STDIN, stderr, stdout};
+use stdlib::poll::{POLLHUP, POLLIN, PollFd, poll};
+use stdlib::startup::Startup;
+use stdlib::syscall::{sys_close, sys_exit, sys_fork, sys_pipe, sys_read, sys_waitpid, sys_write};
+use stdlib::time::{self, ClockId};
+
+stdlib::entry!(main);
+
+/// Messages the child sends.
+const PINGS: u32 = 5;
+
+/// Time between the child's messages.
+const PING_INTERVAL: Duration = Duration::from_millis(1500);
+
+/// Longest wait for an event.
+const IDLE: Duration = Duration::from_secs(1);
+
+fn main(startup: &Startup) -> u32 {
+    let mut args = startup.args().skip(1);
+    let seconds = match (args.next(), args.next()) {
+        (None, None) => 10,
+        (Some(arg), None) => {
+            let Ok(seconds) = arg.parse::<u64>() else {
+                let _ = writeln!(stderr(), "evloop: invalid time: {arg}");
+                return 1;
+            };
+            seconds
+        }
+        _ => {
+            let _ = writeln!(stderr(), "usage: evloop [seconds]");
+            return 1;
+        }
+    };
+    let Some(start) = time::clock_gettime(ClockId::Monotonic) else {
+        let _ = writeln!(stderr(), "evloop: the kernel clock is not ready");
+        return 1;
+    };
+    let Some((rx, tx)) = sys_pipe() else {
+        let _ = writeln!(stderr(), "evloop: cannot create a pipe");
+        return 1;
+    };
+    let Some(child) = sys_fork() else {
+        let _ = writeln!(stderr(), "evloop: cannot fork");
+        return 1;
+    };
+    if child == 0 {
+        let _ = sys_close(rx);
+        ping(tx);
+    }
+    let _ = sys_close(tx);
+
+    let _ = writeln!(stdout(), "evloop: type a line, or `quit`");
+    let mut fds = [PollFd::new(STDIN, POLLIN), PollFd::new(rx, POLLIN)];
+    let mut watched = fds.len();
+    let mut buf = [0u8; 128];
+    loop {
+        let now = time::clock_gettime(ClockId::Monotonic).unwrap_or(start);
+        let elapsed = now.secs - start.secs;
+        if elapsed >= seconds {
+            break;
+        }
+
+        let Some(ready) = poll(&mut fds[..watched], Some(IDLE)) else {
+            let _ = writeln!(stderr(), "evloop: poll failed");
+            break;
+        };
+        if ready == 0 {
+            let _ = writeln!(stdout(), "[{elapsed:>3} s] idle");
+            continue;
+        }
+
+        if fds[0].revents & POLLIN != 0 {
+            let n = io::read_stdin(&mut buf).unwrap_or(0);
+            let line = core::str::from_utf8(&buf[..n]).unwrap_or("<invalid UTF-8>");
+            if line.trim_end() == "quit" {
+                break;
+            }
+            let _ = writeln!(stdout(), "[{elapsed:>3} s] stdin: {}", line.trim_end());
+        }
+        if watched > 1 && fds[1].revents & (POLLIN | POLLHUP) != 0 {
+            if let Some(n @ 1..) = sys_read(rx, &mut buf) {
+                let message = core::str::from_utf8(&buf[..n]).unwrap_or("<invalid UTF-8>");
+                let _ = writeln!(stdout(), "[{elapsed:>3} s] pipe: {message}");
+            } else {
+                // End of file: the child is done; stop watching the pipe.
+                let _ = writeln!(stdout(), "[{elapsed:>3} s] pipe closed");
+                watched = 1;
+            }
+        }
+    }
+
+    let _ = sys_close(rx);
+    let _ = sys_waitpid(child);
+    0
+}
+
+/// The child: send `ping` through `tx` [`PINGS`] times, then exit.
+fn ping(tx: u32) -> ! {
+    for _ in 0..PINGS {
+        if !time::sleep(PING_INTERVAL) || sys_write(tx, b"ping").is_none() {
+            break;
+        }
+    }
+    sys_exit(0);
+}
@@ -0,0 +1,134 @@
+//! `fbdemo [frames]`: bounce a square across a color gradient on the
+//! screen, through `/dev/fb0`, for the given number of frames (300 by
+//! default, about five seconds).
+
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use core::time::Duration;
+use stdlib::fb::Framebuffer;
+use stdlib::io::stderr;
+use stdlib::startup::Startup;
+use stdlib::syscall_abi::{FbInfo, FbRect};
+use stdlib::time;
+
+stdlib::entry!(main);
+
+/// Edge of the square, in pixels.
+const SIZE: u32 = 64;
+
+/// Pixels the square moves per frame, along each axis.
+const SPEED: u32 = 6;
+
+/// Time between frames.
+const FRAME: Duration = Duration::from_millis(16);
+
+fn main(startup: &Startup) -> u32 {
+    let mut args = startup.args().skip(1);
+    let frames = match (args.next(), args.next()) {
+        (None, None) => 300,
+        (Some(arg), None) => {
+            let Ok(frames) = arg.parse::<u32>() else {
+                let _ = writeln!(stderr(), "fbdemo: invalid frame count: {arg}");
+                return 1;
+            };
+            frames
+        }
+        _ => {
+            let _ = writeln!(stderr(), "usage: fbdemo [frames]");
+            return 1;
+        }
+    };
+    let Some(mut fb) = Framebuffer::open() else {
+        let _ = writeln!(stderr(), "fbdemo: no framebuffer");
+        return 1;
+    };
+    let info = *fb.info();
+    if info.width < SIZE || info.height < SIZE {
+        let _ = writeln!(stderr(), "fbdemo: screen too small");
+        return 1;
+    }
+
+    let screen = FbRect {
+        x: 0,
+        y: 0,
+        width: info.width,
+        height: info.height,
+    };
+    background(&info, fb.pixels(), screen);
+    let _ = fb.present(None);
+
+    let mut square = FbRect {
+        x: 0,
+        y: 0,
+        width: SIZE,
+        height: SIZE,
+    };
+    let (mut right, mut down) = (true, true);
+    for frame in 0..frames {
+        let old = square;
+        (square.x, right) = step(square.x, right, info.width - SIZE);
+        (square.y, down) = step(square.y, down, info.height - SIZE);
+
+        background(&info, fb.pixels(), old);
+        #[allow(clippy::cast_possible_truncation)]
+        let shade = (frame * 4) as u8;
+        let color = info.pack(0xFF, shade, 0xFF - shade);
+        fill(&info, fb.pixels(), square, color);
+        let _ = fb.present(Some(union(old, square)));
+        let _ = time::sleep(FRAME);
+    }
+
+    background(&info, fb.pixels(), square);
+    let _ = fb.present(Some(square));
+    0
+}
+
+/// Move `pos` one step towards `0` or `max`, turning at either end.
+const fn step(pos: u32, forward: bool, max: u32) -> (u32, bool) {
+    if forward {
+        if pos + SPEED >= max {
+            (max, false)
+        } else {
+            (pos + SPEED, true)
+        }
+    } else if pos <= SPEED {
+        (0, true)
+    } else {
+        (pos - SPEED, false)
+    }
+}
+
+/// Paint `rect` with the gradient behind the square.
+#[allow(clippy::cast_possible_truncation)]
+fn background(info: &FbInfo, pixels: &mut [u32], rect: FbRect) {
+    for y in rect.y..rect.y + rect.height {
+        let row = y as usize * info.stride as usize;
+        let blue = (y * 255 / info.height) as u8;
+        for x in rect.x..rect.x + rect.width {
+            let red = (x * 255 / info.width) as u8;
+            pixels[row + x as usize] = info.pack(red / 2, 0x20, blue / 2);
+        }
+    }
+}
+
+/// Fill `rect` with `color`.
+fn fill(info: &FbInfo, pixels: &mut [u32], rect: FbRect, color: u32) {
+    for y in rect.y..rect.y + rect.height {
+        let row = y as usize * info.stride as usize + rect.x as usize;
+        pixels[row..row + rect.width as usize].fill(color);
+    }
+}
+
+/// The smallest rectangle covering `a` and `b`.
+fn union(a: FbRect, b: FbRect) -> FbRect {
+    let x = a.x.min(b.x);
+    let y = a.y.min(b.y);
+    FbRect {
+        x,
+        y,
+        width: (a.x + a.width).max(b.x + b.width) - x,
+        height: (a.y + a.height).max(b.y + b.height) - y,
+    }
+}
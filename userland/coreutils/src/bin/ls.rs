@@ -0,0 +1,67 @@
+//! `ls [-l] [dir ...]`: list the entries of the directories, `/` if there
+//! are none. Directories are marked with a trailing `/`; `-l` also shows the
+//! kind and size of every entry.
+
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use stdlib::io::{stderr, stdout};
+use stdlib::startup::Startup;
+use stdlib::syscall;
+use stdlib::syscall_abi::DirEntry;
+use stdlib::syscall_abi::dirent::{KIND_DEVICE, KIND_DIR};
+
+stdlib::entry!(main);
+
+fn main(startup: &Startup) -> u32 {
+    let mut args = startup.args().skip(1).peekable();
+    let long = args.next_if_eq(&"-l").is_some();
+    let dirs = startup.argc() - 1 - usize::from(long);
+
+    let mut failed = false;
+    if dirs == 0 {
+        failed |= !list("/", long);
+    }
+    for (i, dir) in args.enumerate() {
+        if dirs > 1 {
+            let sep = if i == 0 { "" } else { "\n" };
+            let _ = writeln!(stdout(), "{sep}{dir}:");
+        }
+        failed |= !list(dir, long);
+    }
+    u32::from(failed)
+}
+
+/// Print the entries of `dir`; returns whether it exists.
+fn list(dir: &str, long: bool) -> bool {
+    let mut out = stdout();
+    let mut entry = DirEntry::default();
+    let Some(mut cursor) = syscall::sys_readdir(dir, 0, &mut entry) else {
+        let _ = writeln!(stderr(), "ls: {dir}: no such directory");
+        return false;
+    };
+
+    loop {
+        let (kind, suffix) = match entry.kind {
+            KIND_DIR => ('d', "/"),
+            KIND_DEVICE => ('c', ""),
+            _ => ('-', ""),
+        };
+        let _ = if long {
+            writeln!(
+                out,
+                "{kind} {size:>8} {name}{suffix}",
+                size = entry.size,
+                name = entry.name()
+            )
+        } else {
+            writeln!(out, "{}{suffix}", entry.name())
+        };
+
+        match syscall::sys_readdir(dir, cursor, &mut entry) {
+            Some(next) => cursor = next,
+            None => return true,
+        }
+    }
+}
@@ -0,0 +1,63 @@
+//! `meminfo`: summarize `/proc/meminfo` as total, used and free physical
+//! memory in MiB.
+
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use stdlib::io::{stderr, stdout};
+use stdlib::startup::Startup;
+use stdlib::syscall;
+
+stdlib::entry!(main);
+
+const PATH: &str = "/proc/meminfo";
+
+fn main(_startup: &Startup) -> u32 {
+    let mut buf = [0u8; 512];
+    let Some(len) = read_all(PATH, &mut buf) else {
+        let _ = writeln!(stderr(), "meminfo: cannot read {PATH}");
+        return 1;
+    };
+    let text = core::str::from_utf8(&buf[..len]).unwrap_or_default();
+    let [Some(total), Some(used), Some(free)] =
+        ["MemTotal", "MemUsed", "MemFree"].map(|key| field_kib(text, key))
+    else {
+        let _ = writeln!(stderr(), "meminfo: unexpected contents of {PATH}");
+        return 1;
+    };
+
+    let percent = used * 100 / total.max(1);
+    let mib = |kib: u64| kib / 1024;
+    let result =
+        writeln!(stdout(), "{:>10} {:>10} {:>10}", "total", "used", "free").and_then(|()| {
+            writeln!(
+                stdout(),
+                "{:>6} MiB {:>6} MiB {:>6} MiB  ({percent}% used)",
+                mib(total),
+                mib(used),
+                mib(free)
+            )
+        });
+    u32::from(result.is_err())
+}
+
+/// Read the file at `path` into `buf`, up to its length; returns how many
+/// bytes.
+fn read_all(path: &str, buf: &mut [u8]) -> Option<usize> {
+    let fd = syscall::sys_open(path)?;
+    let mut len = 0;
+    while let Some(n @ 1..) = syscall::sys_read(fd, &mut buf[len..]) {
+        len += n;
+    }
+    let _ = syscall::sys_close(fd);
+    Some(len)
+}
+
+/// The value of the `key: <n> kB` line of `text`.
+fn field_kib(text: &str, key: &str) -> Option<u64> {
+    text.lines().find_map(|line| {
+        let value = line.strip_prefix(key)?.strip_prefix(':')?;
+        value.trim().strip_suffix("kB")?.trim().parse().ok()
+    })
+}
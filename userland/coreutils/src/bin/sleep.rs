@@ -0,0 +1,51 @@
+//! `sleep seconds`: wait for the given time, in seconds with an optional
+//! fraction such as `0.25`.
+
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use core::time::Duration;
+use stdlib::io::stderr;
+use stdlib::startup::Startup;
+use stdlib::time;
+
+stdlib::entry!(main);
+
+fn main(startup: &Startup) -> u32 {
+    let mut args = startup.args().skip(1);
+    let (Some(arg), None) = (args.next(), args.next()) else {
+        let _ = writeln!(stderr(), "usage: sleep seconds");
+        return 1;
+    };
+    let Some(duration) = parse(arg) else {
+        let _ = writeln!(stderr(), "sleep: invalid time: {arg}");
+        return 1;
+    };
+    if !time::sleep(duration) {
+        let _ = writeln!(stderr(), "sleep: the kernel clock is not ready");
+        return 1;
+    }
+    0
+}
+
+/// `secs[.fraction]` as a duration; digits beyond nanoseconds are ignored.
+fn parse(arg: &str) -> Option<Duration> {
+    let (secs, fraction) = arg.split_once('.').unwrap_or((arg, ""));
+    let digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+    if (secs.is_empty() && fraction.is_empty()) || !digits(secs) || !digits(fraction) {
+        return None;
+    }
+
+    let secs = if secs.is_empty() {
+        0
+    } else {
+        secs.parse().ok()?
+    };
+    let mut nanos = 0u32;
+    for i in 0..9 {
+        let digit = fraction.as_bytes().get(i).map_or(0, |b| b - b'0');
+        nanos = nanos * 10 + u32::from(digit);
+    }
+    Some(Duration::new(secs, nanos))
+}
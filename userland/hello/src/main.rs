@@ -0,0 +1,36 @@
+#![no_std]
+#![no_main]
+
+use stdlib::println;
+use stdlib::shm::{SharedMemory, ShmRing};
+use stdlib::startup::Startup;
+
+stdlib::entry!(main);
+
+fn main(startup: &Startup) -> u32 {
+    println!("Hello from a spawned process!");
+
+    for (i, arg) in startup.args().enumerate() {
+        println!("argv[{i}] = {arg}");
+    }
+    for var in startup.env() {
+        println!("env: {var}");
+    }
+    if let Some(greeting) = startup.env_var("GREETING") {
+        println!("GREETING is {greeting}");
+    }
+
+    if let Some(shm) = SharedMemory::open("/greetings", 4096) {
+        let ring = ShmRing::new(&shm);
+        let mut message = [0u8; 128];
+        let len = ring.pop(&mut message);
+        let message = core::str::from_utf8(&message[..len]).unwrap_or("<invalid UTF-8>");
+        println!("Message in /greetings: {}", message.trim_end());
+        let reply = b"Hello back from /hello!\n";
+        if ring.push(reply) < reply.len() {
+            println!("Reply did not fit into /greetings");
+        }
+    }
+
+    42
+}
@@ -1,11 +1,27 @@
 #![no_std]
 #![no_main]
 
-use stdlib::{println, syscall};
+use core::fmt::Write;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use stdlib::fmt::LogWriter;
+use stdlib::shm::{SharedMemory, ShmRing};
+use stdlib::startup::Startup;
+use stdlib::syscall_abi::signal::{SIGTERM, SIGUSR1, SIGUSR2};
+use stdlib::syscall_abi::task::{ALL_CPUS, KIND_KERNEL};
+use stdlib::syscall_abi::{LogLevel, SignalContext, TaskInfo};
+use stdlib::syscall_abi::{caps, priority, reboot};
+use stdlib::{println, signal, syscall, tls};
 
-#[unsafe(no_mangle)]
-pub extern "C" fn _start() -> ! {
+stdlib::entry!(main);
+
+fn main(startup: &Startup) -> u32 {
     println!("Init process started successfully!");
+    println!(
+        "Started as {name} with {argc} argument(s), page size {page_size:?}",
+        name = startup.args().next().unwrap_or("?"),
+        argc = startup.argc(),
+        page_size = startup.page_size()
+    );
 
     #[allow(deprecated)]
     {
@@ -20,7 +36,300 @@ pub extern "C" fn _start() -> ! {
         println!("Returned value: 0x{v2:04X}");
     }
 
+    // Leave a message for /hello in shared memory; it answers in the same
+    // ring before it exits.
+    let shm = SharedMemory::open("/greetings", 4096);
+    if let Some(shm) = &shm {
+        let queued = ShmRing::new(shm).push(b"Greetings from init via shared memory!\n");
+        println!("Queued {queued} bytes in /greetings");
+    } else {
+        println!("Failed to open shared memory /greetings");
+    }
+
+    // /hello needs no privileges at all.
+    if syscall::sys_cap_set(caps::INHERITABLE, 0).is_none() {
+        println!("Failed to clear the inheritable capabilities");
+    }
+    println!("Spawning /hello ...");
+    if let Some(pid) = syscall::sys_spawn_env("/hello", &["/hello", "world"], &["GREETING=hi"]) {
+        println!("Spawned process {pid}, waiting for it to exit ...");
+        if let Some(code) = syscall::sys_waitpid(pid) {
+            println!("Process {pid} exited with code {code}");
+            if let Some(shm) = &shm {
+                let mut reply = [0u8; 128];
+                let len = ShmRing::new(shm).pop(&mut reply);
+                let reply = core::str::from_utf8(&reply[..len]).unwrap_or("<invalid UTF-8>");
+                println!("Reply in /greetings: {}", reply.trim_end());
+            }
+        } else {
+            println!("Failed to wait for process {pid}");
+        }
+    } else {
+        println!("Failed to spawn /hello");
+    }
+
+    pipe_demo();
+    signal_demo();
+    priority_demo();
+    hotplug_demo();
+    affinity_demo();
+    tls_demo();
+    ps_demo();
+    procfs_demo();
+
+    shell()
+}
+
+/// Capabilities of the shell and its children.
+const SHELL_CAPS: u64 = caps::CAP_DEVICE_CONSOLE | caps::CAP_DEVICE_FB;
+
+/// The [`reboot`] command requested by a signal; `0` for none.
+static POWER_REQUEST: AtomicU64 = AtomicU64::new(0);
+
+/// Record the power request of `signo`, mapped as by `BusyBox` init:
+/// `SIGUSR1` halts, `SIGUSR2` powers off and `SIGTERM` reboots.
+extern "C" fn on_power_signal(signo: u32, _context: &mut SignalContext) {
+    let cmd = match signo {
+        SIGUSR1 => reboot::HALT,
+        SIGUSR2 => reboot::POWER_OFF,
+        SIGTERM => reboot::RESTART,
+        _ => return,
+    };
+    POWER_REQUEST.store(cmd, Ordering::Relaxed);
+}
+
+/// Run `/sh` on the console, starting it over whenever it exits, until a
+/// signal asks to power off, reboot or halt.
+fn shell() -> ! {
+    for signo in [SIGUSR1, SIGUSR2, SIGTERM] {
+        if signal::set_handler(signo, on_power_signal).is_none() {
+            println!("Failed to set a handler for signal {signo}");
+        }
+    }
+
+    // The shell and everything it runs may open the consoles and draw to the
+    // screen; power requests go through us.
+    if syscall::sys_cap_set(caps::INHERITABLE, SHELL_CAPS).is_none() {
+        println!("Failed to set the capabilities of the shell");
+    }
+
+    // Descriptors 0, 1 and 2 of the shell and of everything it runs.
+    let console = [0, 1, 2].map(|fd| syscall::sys_open("/dev/console") == Some(fd));
+    if console.contains(&false) {
+        println!("Failed to open /dev/console as the standard streams");
+    } else {
+        // Signals arrive when `waitpid` returns, so a shell asking for a
+        // power-off exits right after sending the signal.
+        while let Some(pid) = syscall::sys_spawn("/sh", &["/sh"]) {
+            let code = syscall::sys_waitpid(pid);
+            let request = POWER_REQUEST.swap(0, Ordering::Relaxed);
+            if request != 0 {
+                println!("Shell {pid} exited; shutting down");
+                syscall::sys_reboot(request);
+                println!("Failed to shut down");
+            }
+            match code {
+                Some(code) => println!("Shell {pid} exited with code {code}, restarting"),
+                None => break,
+            }
+        }
+        println!("Failed to run /sh");
+    }
+
     loop {
         core::hint::spin_loop();
     }
 }
+
+/// Fork a child that sends a message through a pipe, and read it.
+fn pipe_demo() {
+    let Some((rx, tx)) = syscall::sys_pipe() else {
+        println!("Failed to create a pipe");
+        return;
+    };
+
+    match syscall::sys_fork() {
+        Some(0) => {
+            let _ = syscall::sys_close(rx);
+            let sent = syscall::sys_write(tx, b"Hello through a pipe!");
+            syscall::sys_exit(u32::from(sent.is_none()));
+        }
+        Some(pid) => {
+            let _ = syscall::sys_close(tx);
+
+            // Read until the child's end is closed.
+            let mut buf = [0u8; 64];
+            let mut len = 0;
+            while let Some(n @ 1..) = syscall::sys_read(rx, &mut buf[len..]) {
+                len += n;
+                if len == buf.len() {
+                    break;
+                }
+            }
+            let _ = syscall::sys_close(rx);
+
+            let message = core::str::from_utf8(&buf[..len]).unwrap_or("<invalid UTF-8>");
+            println!("Read from pipe: {message}");
+            if let Some(code) = syscall::sys_waitpid(pid) {
+                println!("Pipe writer {pid} exited with code {code}");
+            }
+        }
+        None => println!("Failed to fork"),
+    }
+}
+
+/// Set by [`on_usr1`].
+static GOT_USR1: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_usr1(_signo: u32, _context: &mut SignalContext) {
+    GOT_USR1.store(true, Ordering::Relaxed);
+}
+
+/// Interrupt a child with a handler and terminate two with signals; a
+/// signal's victim exits with `128 + signo`.
+fn signal_demo() {
+    // Inherited by the child, so the signal cannot arrive before it is set.
+    if signal::set_handler(SIGUSR1, on_usr1).is_none() {
+        println!("Failed to set a SIGUSR1 handler");
+        return;
+    }
+
+    let children = [
+        ("handles SIGUSR1", Some(SIGUSR1)),
+        ("is terminated by SIGTERM", Some(SIGTERM)),
+        ("dereferences NULL", None),
+    ];
+    for (what, signo) in children {
+        match syscall::sys_fork() {
+            Some(0) => {
+                if signo.is_none() {
+                    let null = core::ptr::null::<u8>();
+                    // SAFETY: it is not; the fault raises SIGSEGV.
+                    let _ = unsafe { core::ptr::read_volatile(null) };
+                }
+                while !GOT_USR1.load(Ordering::Relaxed) {
+                    core::hint::spin_loop();
+                }
+                syscall::sys_exit(0);
+            }
+            Some(pid) => {
+                if let Some(signo) = signo
+                    && !syscall::sys_kill(pid, signo)
+                {
+                    println!("Failed to send signal {signo} to {pid}");
+                }
+                if let Some(code) = syscall::sys_waitpid(pid) {
+                    println!("Child {pid} that {what} exited with code {code}");
+                }
+            }
+            None => println!("Failed to fork"),
+        }
+    }
+
+    let _ = signal::reset(SIGUSR1);
+}
+
+/// Run a busy child at the lowest priority; aging still lets it finish.
+fn priority_demo() {
+    match syscall::sys_fork() {
+        Some(0) => {
+            let mut sum = 0u64;
+            for i in 0..1_000_000u64 {
+                sum = core::hint::black_box(sum.wrapping_add(i));
+            }
+            syscall::sys_exit(u32::from(sum == 0));
+        }
+        Some(pid) => {
+            match syscall::sys_setpriority(pid, priority::MIN) {
+                Some(old) => println!("Lowered priority of {pid} from {old} to {}", priority::MIN),
+                None => println!("Failed to set the priority of {pid}"),
+            }
+            if let Some(code) = syscall::sys_waitpid(pid) {
+                println!("Low-priority child {pid} exited with code {code}");
+            }
+        }
+        None => println!("Failed to fork"),
+    }
+}
+
+/// Try to take the boot CPU offline, which the kernel must refuse.
+fn hotplug_demo() {
+    if syscall::sys_cpu_set_online(0, false) {
+        println!("Unexpectedly took the boot CPU offline");
+        let _ = syscall::sys_cpu_set_online(0, true);
+    } else {
+        println!("Boot CPU refused to go offline, as it should");
+    }
+}
+
+/// Bind ourselves to the boot CPU and back; a mask without an online CPU
+/// must be refused.
+fn affinity_demo() {
+    if syscall::sys_sched_setaffinity(0, 0) {
+        println!("Unexpectedly bound to no CPU at all");
+    }
+    let bound = syscall::sys_sched_setaffinity(0, 1);
+    let restored = syscall::sys_sched_setaffinity(0, ALL_CPUS);
+    println!("Bound to the boot CPU: {bound}, unbound again: {restored}");
+}
+
+/// Check the thread pointer the kernel set up, then switch to a thread
+/// control block of our own and back.
+fn tls_demo() {
+    let tp = tls::thread_pointer();
+    match tls::fs_base() {
+        Some(base) if base == tp && base != 0 => println!("Thread pointer at {tp:#x}"),
+        base => println!("Thread pointer {tp:#x} does not match the FS base {base:?}"),
+    }
+
+    let mut tcb = [0u64; 8];
+    tcb[0] = tcb.as_ptr() as u64;
+    if unsafe { tls::set_thread_pointer(tcb[0]) } {
+        let switched = tls::thread_pointer() == tcb[0];
+        let restored = unsafe { tls::set_thread_pointer(tp) };
+        println!("Switched thread pointer: {switched}, restored: {restored}");
+    } else {
+        println!("Failed to set the thread pointer");
+    }
+}
+
+/// List all tasks, like a tiny `ps`.
+fn ps_demo() {
+    println!("  PID  PPID NAME");
+    let mut info = TaskInfo::default();
+    let mut cursor = 0;
+    while let Some(next) = syscall::sys_task_info(cursor, &mut info) {
+        let (open, close) = if info.kind == KIND_KERNEL {
+            ("[", "]")
+        } else {
+            ("", "")
+        };
+        println!(
+            "{pid:>5} {ppid:>5} {open}{name}{close}",
+            pid = info.pid,
+            ppid = info.ppid,
+            name = info.name()
+        );
+        cursor = next;
+    }
+}
+
+/// Print a few files of the kernel's `/proc`.
+fn procfs_demo() {
+    for path in ["/proc/uptime", "/proc/meminfo", "/proc/self/maps"] {
+        let Some(fd) = syscall::sys_open(path) else {
+            println!("Failed to open {path}");
+            continue;
+        };
+
+        println!("{path}:");
+        // Reads end mid-line; let the writer join them into lines.
+        let mut out = LogWriter::new(LogLevel::Info);
+        let mut buf = [0u8; 128];
+        while let Some(n) = syscall::sys_read(fd, &mut buf).filter(|&n| n > 0) {
+            let _ = out.write_str(core::str::from_utf8(&buf[..n]).unwrap_or("?"));
+        }
+        out.flush();
+        let _ = syscall::sys_close(fd);
+    }
+}
@@ -0,0 +1,8 @@
+use std::{env, path::PathBuf};
+
+fn main() {
+    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let ld = manifest_dir.join("linker.ld");
+    println!("cargo:rerun-if-changed={}", ld.display());
+    println!("cargo:rustc-link-arg-bins=-T{}", ld.display());
+}
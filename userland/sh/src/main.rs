@@ -0,0 +1,168 @@
+//! `sh`: read commands from the standard input and run them, one at a time.
+//! Words are separated by whitespace; see [`split`] for quoting.
+
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use stdlib::io::{self, stderr, stdout};
+use stdlib::startup::Startup;
+use stdlib::syscall;
+use stdlib::syscall_abi::dirent::KIND_FILE;
+use stdlib::syscall_abi::signal::{SIGTERM, SIGUSR1, SIGUSR2};
+use stdlib::syscall_abi::{DirEntry, MAX_PATH_LEN, MAX_SPAWN_ARGS};
+
+stdlib::entry!(main);
+
+/// Longest command line read at once.
+const LINE_LEN: usize = 256;
+
+/// Commands the shell runs itself.
+const BUILTINS: [(&str, &str); 5] = [
+    ("exit [code]", "leave the shell"),
+    ("halt", "stop the machine"),
+    ("help", "show this help"),
+    ("poweroff", "power the machine off"),
+    ("reboot", "restart the machine"),
+];
+
+/// PID of init, which performs power requests.
+const INIT_PID: u64 = 1;
+
+fn main(startup: &Startup) -> u32 {
+    let mut env = [""; MAX_SPAWN_ARGS];
+    let envc = collect(startup.env(), &mut env);
+    let env = &env[..envc];
+
+    let _ = writeln!(stdout(), "Type `help` to list the commands.");
+    let mut line = [0u8; LINE_LEN];
+    loop {
+        let _ = write!(stdout(), "$ ");
+        let len = match io::read_stdin(&mut line) {
+            Some(0) | None => return 0,
+            Some(len) => len,
+        };
+        let Ok(line) = core::str::from_utf8(&line[..len]) else {
+            let _ = writeln!(stderr(), "sh: input is not UTF-8");
+            continue;
+        };
+
+        let mut words = [""; MAX_SPAWN_ARGS];
+        let count = match split(line, &mut words) {
+            Ok(count) => count,
+            Err(e) => {
+                let _ = writeln!(stderr(), "sh: {e}");
+                continue;
+            }
+        };
+        match words[..count] {
+            [] => {}
+            ["exit"] => return 0,
+            ["exit", code] => match code.parse() {
+                Ok(code) => return code,
+                Err(_) => {
+                    let _ = writeln!(stderr(), "sh: exit: not a number: {code}");
+                }
+            },
+            ["help", ..] => help(),
+            ["halt"] => return power(SIGUSR1),
+            ["poweroff"] => return power(SIGUSR2),
+            ["reboot"] => return power(SIGTERM),
+            _ => run(&words[..count], env),
+        }
+    }
+}
+
+/// Ask init to halt, power off or reboot by sending it `signo`, and return
+/// the shell's exit code; init acts once the shell has exited.
+fn power(signo: u32) -> u32 {
+    if syscall::sys_kill(INIT_PID, signo) {
+        0
+    } else {
+        let _ = writeln!(stderr(), "sh: failed to signal init");
+        1
+    }
+}
+
+/// Split `line` into words at whitespace; a word in single or double quotes
+/// keeps its whitespace. Returns the number of words stored in `words`.
+fn split<'a>(line: &'a str, words: &mut [&'a str]) -> Result<usize, &'static str> {
+    let mut rest = line.trim_start();
+    let mut count = 0;
+    while !rest.is_empty() {
+        let (word, tail) = match rest.chars().next() {
+            Some(quote @ ('"' | '\'')) => {
+                let inner = &rest[1..];
+                let end = inner.find(quote).ok_or("unterminated quote")?;
+                (&inner[..end], &inner[end + 1..])
+            }
+            _ => rest.split_at(rest.find(char::is_whitespace).unwrap_or(rest.len())),
+        };
+        *words.get_mut(count).ok_or("too many arguments")? = word;
+        count += 1;
+        rest = tail.trim_start();
+    }
+    Ok(count)
+}
+
+/// Store the items of `iter` in `out`, as many as fit; returns how many.
+fn collect<'a>(iter: impl Iterator<Item = &'a str>, out: &mut [&'a str]) -> usize {
+    let mut len = 0;
+    for (slot, s) in out.iter_mut().zip(iter) {
+        *slot = s;
+        len += 1;
+    }
+    len
+}
+
+/// Spawn the program `args[0]` and wait for it to exit.
+///
+/// A name without `/` is looked up in `/`, where the bundled programs live.
+fn run(args: &[&str], env: &[&str]) {
+    let name = args[0];
+    let mut buf = [0u8; MAX_PATH_LEN];
+    let path = if name.contains('/') {
+        name
+    } else if name.len() < buf.len() {
+        buf[0] = b'/';
+        buf[1..=name.len()].copy_from_slice(name.as_bytes());
+        core::str::from_utf8(&buf[..=name.len()]).unwrap_or(name)
+    } else {
+        let _ = writeln!(stderr(), "sh: {name}: name too long");
+        return;
+    };
+
+    let Some(pid) = syscall::sys_spawn_env(path, args, env) else {
+        let _ = writeln!(stderr(), "sh: {name}: command not found");
+        return;
+    };
+    match syscall::sys_waitpid(pid) {
+        Some(0) => {}
+        Some(code) => {
+            let _ = writeln!(stderr(), "sh: {name} exited with code {code}");
+        }
+        None => {
+            let _ = writeln!(stderr(), "sh: failed to wait for {name}");
+        }
+    }
+}
+
+/// List the builtins and the programs in `/`.
+fn help() {
+    let mut out = stdout();
+    let _ = writeln!(out, "Builtins:");
+    for (usage, what) in BUILTINS {
+        let _ = writeln!(out, "  {usage:<12} {what}");
+    }
+
+    let _ = write!(out, "Programs:");
+    let mut entry = DirEntry::default();
+    let mut cursor = 0;
+    while let Some(next) = syscall::sys_readdir("/", cursor, &mut entry) {
+        if entry.kind == KIND_FILE {
+            let _ = write!(out, " {}", entry.name());
+        }
+        cursor = next;
+    }
+    let _ = writeln!(out);
+}